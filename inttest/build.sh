@@ -40,4 +40,54 @@ mkdir /sbin
 write target/$TARGET/debug/init /sbin/init
 write target/$TARGET/debug/inttest /inttest
 write mod/target/$ARCH/debug/libinttest.so /mod.kmod
+mkdir /htree
+EOF
+
+# Create a large directory, then have it indexed
+touch empty
+for i in $(seq 0 999); do
+	echo "write empty /htree/file$i"
+done | debugfs -wf - disk >/dev/null
+rm empty
+e2fsck -fyD disk || [ $? -le 1 ]
+
+# Leave orphan inodes, as after a crash during a deletion and a truncation
+yes | head -c 16384 >orphan
+debugfs -wf - disk >/dev/null <<EOF
+write orphan /orphan_deleted
+write orphan /orphan_truncated
+EOF
+rm orphan
+deleted=$(debugfs -R "stat /orphan_deleted" disk | sed -n 's/^Inode: \([0-9]*\).*/\1/p')
+truncated=$(debugfs -R "stat /orphan_truncated" disk | sed -n 's/^Inode: \([0-9]*\).*/\1/p')
+debugfs -wf - disk >/dev/null <<EOF
+unlink /orphan_deleted
+sif <$deleted> links_count 0
+sif /orphan_truncated size 4096
+sif /orphan_truncated dtime $deleted
+ssv last_orphan $truncated
+EOF
+
+# Add a journal, then leave a committed transaction in it, as after a crash during a commit
+tune2fs -O has_journal disk
+echo old >journal_replayed
+debugfs -wf - disk >/dev/null <<EOF
+write journal_replayed /journal_replayed
+sif /journal_replayed size 9
+EOF
+rm journal_replayed
+blk=$(debugfs -R "bmap /journal_replayed 0" disk)
+printf 'replayed\n' >journal_block
+truncate -s 4096 journal_block
+debugfs -wf - disk >/dev/null <<EOF
+journal_open
+journal_write -b $blk journal_block
+journal_close
+EOF
+rm journal_block
+
+# Corrupt the length of the first entry of a directory
+debugfs -wf - disk >/dev/null <<EOF
+mkdir /corrupted
+zap_block -f /corrupted -o 4 -l 2 -p 0xff 0
 EOF
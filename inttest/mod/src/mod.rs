@@ -26,6 +26,13 @@ extern crate kernel;
 
 kernel::module!([]);
 
+kernel::module_params! {
+	/// A parameter set when loading the module.
+	static ANSWER: u32 = 0;
+	/// A parameter left to its default value.
+	static VERBOSE: bool = false;
+}
+
 use kernel::{
 	device::{CharDev, DeviceID},
 	file::fs::DummyOps,
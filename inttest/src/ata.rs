@@ -0,0 +1,74 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! ATA passthrough tests.
+
+use crate::{log, test_assert, test_assert_eq, util::TestResult};
+use libc::{EINVAL, ENOTTY, c_ulong, c_void};
+use std::{fs, fs::File, io, os::fd::AsRawFd};
+
+const HDIO_GET_IDENTITY: c_ulong = 0x030d;
+const HDIO_DRIVE_CMD: c_ulong = 0x031f;
+
+const ATA_CMD_IDENTIFY: u8 = 0xec;
+const ATA_CMD_WRITE_SECTORS: u8 = 0x30;
+
+fn ioctl<T>(file: &File, req: c_ulong, arg: &mut T) -> io::Result<()> {
+	let res = unsafe { libc::ioctl(file.as_raw_fd(), req as _, arg as *mut T as *mut c_void) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Queries the identity of ATA drives. Other drives do not support the requests.
+pub fn passthrough() -> TestResult {
+	for ent in fs::read_dir("/sys/block")? {
+		let name = ent?.file_name().into_string().unwrap();
+		log!("Check {name}");
+		let dev = File::open(format!("/dev/{name}"))?;
+		let mut identity = [0u16; 256];
+		let res = ioctl(&dev, HDIO_GET_IDENTITY, &mut identity);
+		if !name.starts_with("sd") {
+			test_assert_eq!(res.err().and_then(|e| e.raw_os_error()), Some(ENOTTY));
+			continue;
+		}
+		res?;
+		// The drive is an ATA device
+		test_assert_eq!(identity[0] & (1 << 15), 0);
+		// The model number is not empty
+		test_assert!(identity[27..47].iter().any(|w| *w != 0x2020 && *w != 0));
+
+		log!("Identify through passthrough");
+		let mut args = [0u8; 4 + 512];
+		args[..4].copy_from_slice(&[ATA_CMD_IDENTIFY, 0, 0, 1]);
+		ioctl(&dev, HDIO_DRIVE_CMD, &mut args)?;
+		let data: Vec<u16> = args[4..]
+			.chunks_exact(2)
+			.map(|w| u16::from_le_bytes([w[0], w[1]]))
+			.collect();
+		test_assert!(data == identity);
+
+		log!("Forbidden command");
+		let mut args = [ATA_CMD_WRITE_SECTORS, 0, 0, 1];
+		let res = ioctl(&dev, HDIO_DRIVE_CMD, &mut args);
+		test_assert_eq!(res.err().and_then(|e| e.raw_os_error()), Some(EINVAL));
+	}
+	Ok(())
+}
@@ -22,16 +22,53 @@ use crate::{
 	log, test_assert, test_assert_eq, util,
 	util::{TestError, TestResult, unprivileged},
 };
+use libc::{
+	AT_EMPTY_PATH, AT_FDCWD, AT_SYMLINK_FOLLOW, AT_SYMLINK_NOFOLLOW, CLONE_FS, CLONE_VM, EAGAIN,
+	EBUSY, EDEADLK, EFAULT, EINVAL, EPERM, ESPIPE, EUCLEAN, F_GETLK, F_RDLCK, F_SETLK, F_SETLKW,
+	F_UNLCK, F_WRLCK, FAN_ACCESS, FAN_ALLOW, FAN_CLASS_CONTENT, FAN_CLASS_NOTIF, FAN_CLOEXEC,
+	FAN_CLOSE_NOWRITE, FAN_DENY, FAN_MARK_ADD, FAN_OPEN_PERM, O_RDONLY, POLLIN, S_IFDIR, S_IFIFO,
+	S_IFLNK, S_IFMT, S_IFREG, SEEK_SET, SIGCHLD, SYNC_FILE_RANGE_WAIT_AFTER,
+	SYNC_FILE_RANGE_WAIT_BEFORE, SYNC_FILE_RANGE_WRITE, WEXITSTATUS, WIFEXITED, WNOHANG,
+	fanotify_event_metadata, fanotify_response, flock, iovec, timespec,
+};
 use memmap2::MmapOptions;
 use std::{
+	ffi::{CString, c_int, c_uint, c_ulong, c_void},
 	fs,
 	fs::OpenOptions,
 	io,
-	io::{Read, Seek, SeekFrom, Write},
-	os::{fd::AsRawFd, unix, unix::fs::MetadataExt},
+	io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write},
+	mem,
+	os::{
+		fd::{AsRawFd, FromRawFd},
+		unix,
+		unix::{
+			ffi::OsStrExt,
+			fs::{
+				DirBuilderExt, FileExt, FileTypeExt, MetadataExt, OpenOptionsExt, PermissionsExt,
+			},
+		},
+	},
 	path::Path,
+	ptr::null_mut,
+	thread,
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+/// `statx` flag: Synchronize the status with the storage.
+const AT_STATX_FORCE_SYNC: c_int = 0x2000;
+/// `statx` flag: Use the cached status.
+const AT_STATX_DONT_SYNC: c_int = 0x4000;
+/// `statx` mask: The fields also returned by `stat`.
+const STATX_BASIC_STATS: c_uint = 0x7ff;
+/// `statx` mask: The creation timestamp.
+const STATX_BTIME: c_uint = 0x800;
+
+/// `IOCB_CMD_PREAD` opcode for legacy asynchronous I/O requests.
+const IOCB_CMD_PREAD: u16 = 0;
+/// `IOCB_CMD_PWRITE` opcode for legacy asynchronous I/O requests.
+const IOCB_CMD_PWRITE: u16 = 1;
+
 pub fn basic(root: &Path) -> TestResult {
 	log!("File creation");
 	let path = root.join("test");
@@ -134,6 +171,332 @@ pub fn mmap(root: &Path) -> TestResult {
 	Ok(())
 }
 
+pub fn positional_io(root: &Path) -> TestResult {
+	let path = root.join("positional");
+	let mut file = OpenOptions::new()
+		.create_new(true)
+		.read(true)
+		.write(true)
+		.open(&path)?;
+	file.write_all(b"abcdef")?;
+
+	log!("Positional write");
+	test_assert_eq!(file.write_at(b"XY", 2)?, 2);
+	test_assert_eq!(file.stream_position()?, 6);
+	test_assert_eq!(file.write_at(b"end", 8)?, 3);
+	test_assert_eq!(file.metadata()?.len(), 11);
+
+	log!("Positional read");
+	let mut buf = [0xffu8; 11];
+	file.read_exact_at(&mut buf, 0)?;
+	test_assert_eq!(&buf, b"abXYef\0\0end");
+	test_assert_eq!(file.stream_position()?, 6);
+	test_assert_eq!(file.read_at(&mut buf, 11)?, 0);
+
+	log!("Negative offset");
+	let fd = file.as_raw_fd();
+	let res = unsafe { libc::pread(fd, buf.as_mut_ptr() as _, buf.len(), -1) };
+	test_assert!(res < 0 && io::Error::last_os_error().raw_os_error() == Some(EINVAL));
+
+	log!("Pipe");
+	let (rx, tx) = util::pipe()?;
+	let res = unsafe { libc::pwrite(tx.as_raw_fd(), buf.as_ptr() as _, buf.len(), 0) };
+	test_assert!(res < 0 && io::Error::last_os_error().raw_os_error() == Some(ESPIPE));
+	let res = unsafe { libc::pread(rx.as_raw_fd(), buf.as_mut_ptr() as _, buf.len(), 0) };
+	test_assert!(res < 0 && io::Error::last_os_error().raw_os_error() == Some(ESPIPE));
+
+	log!("Cleanup");
+	drop(file);
+	fs::remove_file(path)?;
+	Ok(())
+}
+
+pub fn vectored_io(root: &Path) -> TestResult {
+	let path = root.join("vectored");
+	let mut file = OpenOptions::new()
+		.create_new(true)
+		.read(true)
+		.write(true)
+		.open(&path)?;
+
+	log!("writev");
+	let bufs = [
+		IoSlice::new(b"abc"),
+		IoSlice::new(b""),
+		IoSlice::new(b"def"),
+	];
+	test_assert_eq!(file.write_vectored(&bufs)?, 6);
+	test_assert_eq!(file.metadata()?.len(), 6);
+
+	log!("readv");
+	file.rewind()?;
+	let (mut a, mut b) = ([0u8; 2], [0u8; 8]);
+	let mut bufs = [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)];
+	test_assert_eq!(file.read_vectored(&mut bufs)?, 6);
+	test_assert_eq!(&a, b"ab");
+	test_assert_eq!(&b[..4], b"cdef");
+
+	log!("Maximum number of elements");
+	let fd = file.as_raw_fd();
+	let mut iov = vec![
+		iovec {
+			iov_base: null_mut(),
+			iov_len: 0,
+		};
+		1025
+	];
+	let res = unsafe { libc::writev(fd, iov.as_ptr(), 1024) };
+	test_assert_eq!(res, 0);
+	let res = unsafe { libc::writev(fd, iov.as_ptr(), 1025) };
+	test_assert!(res < 0 && io::Error::last_os_error().raw_os_error() == Some(EINVAL));
+
+	log!("Invalid length");
+	iov[0].iov_len = usize::MAX;
+	let res = unsafe { libc::readv(fd, iov.as_ptr(), 1) };
+	test_assert!(res < 0 && io::Error::last_os_error().raw_os_error() == Some(EINVAL));
+
+	log!("Partial transfer");
+	iov[0] = iovec {
+		iov_base: a.as_mut_ptr() as _,
+		iov_len: a.len(),
+	};
+	iov[1] = iovec {
+		iov_base: 1 as _,
+		iov_len: 4,
+	};
+	let res = unsafe { libc::preadv(fd, iov.as_ptr(), 2, 0) };
+	test_assert_eq!(res, 2);
+	let res = unsafe { libc::preadv(fd, iov[1..].as_ptr(), 1, 0) };
+	test_assert!(res < 0 && io::Error::last_os_error().raw_os_error() == Some(EFAULT));
+
+	log!("Cleanup");
+	drop(file);
+	fs::remove_file(path)?;
+	Ok(())
+}
+
+pub fn sync_file_range(root: &Path) -> TestResult {
+	log!("Create file");
+	let path = root.join("file");
+	let mut file = OpenOptions::new()
+		.create(true)
+		.truncate(true)
+		.read(true)
+		.write(true)
+		.open(&path)?;
+	file.write_all(&vec![1; 3 * 4096])?;
+	let fd = file.as_raw_fd();
+
+	log!("Write back a range");
+	util::sync_file_range(
+		fd,
+		4096,
+		4096,
+		SYNC_FILE_RANGE_WAIT_BEFORE | SYNC_FILE_RANGE_WRITE | SYNC_FILE_RANGE_WAIT_AFTER,
+	)?;
+
+	log!("Write back until the end of the file");
+	util::sync_file_range(fd, 0, 0, SYNC_FILE_RANGE_WRITE)?;
+
+	log!("Invalid arguments");
+	let res = util::sync_file_range(fd, -1, 0, SYNC_FILE_RANGE_WRITE);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+	let res = util::sync_file_range(fd, 0, 0, 0x8);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	log!("Write back a pipe");
+	let (rx, _tx) = util::pipe()?;
+	let res = util::sync_file_range(rx.as_raw_fd(), 0, 0, SYNC_FILE_RANGE_WRITE);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(ESPIPE)));
+
+	log!("Cleanup");
+	fs::remove_file(path)?;
+
+	Ok(())
+}
+
+pub fn aio(root: &Path) -> TestResult {
+	log!("Create file");
+	let path = root.join("aio");
+	let file = OpenOptions::new()
+		.create(true)
+		.truncate(true)
+		.read(true)
+		.write(true)
+		.open(&path)?;
+	let fd = file.as_raw_fd() as u32;
+
+	log!("Create context");
+	let ctx = util::io_setup(8)?;
+
+	log!("Submit requests");
+	let data = b"asynchronous";
+	let write = util::IoCb {
+		aio_data: 1,
+		aio_lio_opcode: IOCB_CMD_PWRITE,
+		aio_fildes: fd,
+		aio_buf: data.as_ptr() as _,
+		aio_nbytes: data.len() as _,
+		aio_offset: 4,
+		..Default::default()
+	};
+	let mut buf = [0u8; 12];
+	let read = util::IoCb {
+		aio_data: 2,
+		aio_lio_opcode: IOCB_CMD_PREAD,
+		aio_fildes: fd,
+		aio_buf: buf.as_mut_ptr() as _,
+		aio_nbytes: buf.len() as _,
+		aio_offset: 4,
+		..Default::default()
+	};
+	test_assert_eq!(util::io_submit(ctx, &[&write, &read])?, 2);
+
+	log!("Reap events");
+	let mut events = [util::IoEvent::default(); 4];
+	let n = util::io_getevents(ctx, 2, &mut events, None)?;
+	test_assert_eq!(n, 2);
+	test_assert_eq!((events[0].data, events[0].res), (1, data.len() as i64));
+	test_assert_eq!((events[1].data, events[1].res), (2, buf.len() as i64));
+	test_assert_eq!(&buf, data);
+
+	log!("Time out without events");
+	let timeout = timespec {
+		tv_sec: 0,
+		tv_nsec: 1_000_000,
+	};
+	test_assert_eq!(util::io_getevents(ctx, 1, &mut events, Some(&timeout))?, 0);
+
+	log!("Invalid request");
+	let invalid = util::IoCb {
+		aio_lio_opcode: 0xff,
+		aio_fildes: fd,
+		..Default::default()
+	};
+	let res = util::io_submit(ctx, &[&invalid]);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	log!("Destroy context");
+	util::io_destroy(ctx)?;
+	let res = util::io_destroy(ctx);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	log!("Cleanup");
+	fs::remove_file(path)?;
+
+	Ok(())
+}
+
+extern "C" fn set_umask(_: *mut c_void) -> c_int {
+	util::umask(0o077);
+	0
+}
+
+pub fn umask(root: &Path) -> TestResult {
+	log!("Set umask");
+	let prev = util::umask(0o027);
+	test_assert_eq!(util::umask(0o027), 0o027);
+	let dir = root.join("umask");
+	fs::create_dir(&dir)?;
+	util::chmod(&dir, 0o777)?;
+
+	log!("Create a regular file");
+	let path = dir.join("file");
+	let file = OpenOptions::new()
+		.create_new(true)
+		.write(true)
+		.mode(0o666)
+		.open(&path)?;
+	test_assert_eq!(file.metadata()?.mode() & 0o7777, 0o640);
+	drop(file);
+	fs::remove_file(&path)?;
+
+	log!("Open a created file regardless of its mode");
+	unprivileged(|| -> TestResult {
+		let file = OpenOptions::new()
+			.create_new(true)
+			.read(true)
+			.write(true)
+			.mode(0o444)
+			.open(&path)?;
+		test_assert_eq!(file.metadata()?.mode() & 0o7777, 0o440);
+		let res = OpenOptions::new().write(true).open(&path);
+		test_assert!(matches!(res, Err(e) if e.kind() == io::ErrorKind::PermissionDenied));
+		Ok(())
+	})??;
+	fs::remove_file(&path)?;
+
+	log!("Create a directory");
+	let path = dir.join("dir");
+	fs::DirBuilder::new().mode(0o777).create(&path)?;
+	test_assert_eq!(util::stat(&path)?.st_mode & 0o7777, 0o750);
+	fs::remove_dir(&path)?;
+
+	log!("Create a directory inheriting SGID");
+	util::chmod(&dir, 0o2777)?;
+	fs::create_dir(&path)?;
+	test_assert_eq!(util::stat(&path)?.st_mode & 0o7777, 0o2750);
+	fs::remove_dir(&path)?;
+	log!("Drop SGID of a file created outside of the inherited group");
+	let path = dir.join("file");
+	unprivileged(|| -> TestResult {
+		let file = OpenOptions::new()
+			.create_new(true)
+			.write(true)
+			.mode(0o2777)
+			.open(&path)?;
+		test_assert_eq!(file.metadata()?.mode() & 0o7777, 0o750);
+		Ok(())
+	})??;
+	fs::remove_file(&path)?;
+	util::chmod(&dir, 0o777)?;
+
+	log!("Create a regular file with mknod");
+	let c_path = CString::new(path.as_os_str().as_bytes())?;
+	util::mknodat(AT_FDCWD, &c_path, 0o666, 0)?;
+	test_assert_eq!(util::stat(&path)?.st_mode, S_IFREG | 0o640);
+	fs::remove_file(&path)?;
+
+	log!("Create a FIFO");
+	let path = dir.join("fifo");
+	util::mkfifo(&path, 0o666)?;
+	test_assert_eq!(util::stat(&path)?.st_mode & 0o7777, 0o640);
+	fs::remove_file(&path)?;
+
+	log!("Create a symbolic link");
+	let path = dir.join("link");
+	unix::fs::symlink("target", &path)?;
+	test_assert_eq!(fs::symlink_metadata(&path)?.mode() & 0o7777, 0o777);
+	fs::remove_file(&path)?;
+
+	log!("Inherit umask on fork");
+	let pid = util::fork()?;
+	if pid == 0 {
+		let code = if util::umask(0) == 0o027 { 0 } else { 1 };
+		unsafe { libc::_exit(code) };
+	}
+	let status = util::waitpid(pid)?;
+	test_assert!(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	test_assert_eq!(util::umask(0o027), 0o027);
+
+	log!("Share umask with CLONE_FS");
+	let mut stack = vec![0u8; 64 * 1024];
+	let pid = util::clone(
+		set_umask,
+		&mut stack,
+		CLONE_VM | CLONE_FS | SIGCHLD,
+		null_mut(),
+	)?;
+	util::waitpid(pid)?;
+	test_assert_eq!(util::umask(0o027), 0o077);
+
+	log!("Cleanup");
+	fs::remove_dir(&dir)?;
+	util::umask(prev);
+
+	Ok(())
+}
+
 pub fn directories(root: &Path) -> TestResult {
 	log!("Create directory at non-existent location (invalid)");
 	let path = root.join("abc/def");
@@ -225,6 +588,104 @@ pub fn dir_perms(root: &Path) -> TestResult {
 	Ok(())
 }
 
+pub fn ownership(root: &Path) -> TestResult {
+	let path = root.join("owned");
+	let link = root.join("owned_link");
+	fs::write(&path, b"")?;
+	unix::fs::symlink(&path, &link)?;
+
+	log!("Privileged chown");
+	util::chmod(&path, 0o6755)?;
+	unix::fs::chown(&path, Some(1000), Some(1000))?;
+	let metadata = fs::metadata(&path)?;
+	test_assert_eq!((metadata.uid(), metadata.gid()), (1000, 1000));
+	test_assert_eq!(metadata.mode() & 0o7777, 0o755);
+
+	log!("Setgid without group execution");
+	util::chmod(&path, 0o2744)?;
+	unix::fs::chown(&path, Some(1000), None)?;
+	test_assert_eq!(fs::metadata(&path)?.mode() & 0o7777, 0o2744);
+
+	log!("lchown");
+	unix::fs::lchown(&link, Some(1000), Some(1000))?;
+	let metadata = fs::symlink_metadata(&link)?;
+	test_assert_eq!((metadata.uid(), metadata.gid()), (1000, 1000));
+	test_assert_eq!(fs::metadata(&link)?.mode() & 0o7777, 0o2744);
+
+	util::chmod(&path, 0o6755)?;
+	unprivileged(|| {
+		log!("Unprivileged fchown");
+		let file = fs::File::open(&path)?;
+		unix::fs::fchown(&file, None, Some(1000))?;
+		test_assert_eq!(fs::metadata(&path)?.mode() & 0o7777, 0o755);
+
+		log!("Unprivileged chown to another user");
+		let res = unix::fs::chown(&path, Some(0), None);
+		test_assert!(matches!(res, Err(e) if e.kind() == io::ErrorKind::PermissionDenied));
+		log!("Unprivileged chown to a foreign group");
+		let res = unix::fs::chown(&path, None, Some(1234));
+		test_assert!(matches!(res, Err(e) if e.kind() == io::ErrorKind::PermissionDenied));
+
+		Ok(())
+	})??;
+
+	log!("Unprivileged chown of a foreign file");
+	unix::fs::chown(&path, Some(0), Some(0))?;
+	unprivileged(|| {
+		let res = unix::fs::chown(&path, None, Some(1000));
+		test_assert!(matches!(res, Err(e) if e.kind() == io::ErrorKind::PermissionDenied));
+		Ok(())
+	})??;
+
+	log!("Cleanup");
+	fs::remove_file(link)?;
+	fs::remove_file(path)?;
+	Ok(())
+}
+
+pub fn fsuid(root: &Path) -> TestResult {
+	let private = root.join("private");
+	let created = root.join("created");
+	fs::write(&private, b"")?;
+	util::chmod(&private, 0o600)?;
+
+	log!("Set filesystem IDs");
+	test_assert_eq!(util::setfsuid(1000), 0);
+	test_assert_eq!(util::setfsgid(1000), 0);
+	test_assert_eq!(util::setfsuid(u32::MAX), 1000);
+	test_assert_eq!(unsafe { (libc::getuid(), libc::geteuid()) }, (0, 0));
+
+	log!("Access files with filesystem IDs");
+	let res = fs::File::open(&private);
+	test_assert!(matches!(res, Err(e) if e.kind() == io::ErrorKind::PermissionDenied));
+	fs::write(&created, b"")?;
+	let metadata = fs::metadata(&created)?;
+	test_assert_eq!((metadata.uid(), metadata.gid()), (1000, 1000));
+
+	log!("Restore filesystem IDs");
+	test_assert_eq!(util::setfsuid(0), 1000);
+	test_assert_eq!(util::setfsgid(0), 1000);
+	fs::File::open(&private)?;
+
+	unprivileged(|| {
+		log!("Filesystem IDs follow effective IDs");
+		test_assert_eq!(util::setfsuid(u32::MAX), 1000);
+		test_assert_eq!(util::setfsgid(u32::MAX), 1000);
+
+		log!("Unprivileged setfsuid to a foreign user");
+		util::setfsuid(1234);
+		test_assert_eq!(util::setfsuid(u32::MAX), 1000);
+
+		Ok(())
+	})??;
+	test_assert_eq!(util::setfsuid(u32::MAX), 0);
+
+	log!("Cleanup");
+	fs::remove_file(created)?;
+	fs::remove_file(private)?;
+	Ok(())
+}
+
 pub fn hardlinks(root: &Path) -> TestResult {
 	let test_dir = root.join("test_dir");
 	let file = root.join("file");
@@ -340,6 +801,258 @@ pub fn rename(root: &Path) -> TestResult {
 	Ok(())
 }
 
+/// Sets the access and modification timestamps of the file at `path`.
+///
+/// `UTIME_NOW` and `UTIME_OMIT` can be used in the `tv_nsec` fields of `times`.
+fn set_times(path: &Path, times: [timespec; 2]) -> io::Result<()> {
+	let path = CString::new(path.as_os_str().as_bytes())?;
+	let res = unsafe { libc::utimensat(libc::AT_FDCWD, path.as_ptr(), times.as_ptr(), 0) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn at_functions(root: &Path) -> TestResult {
+	let path = root.join("at");
+	fs::create_dir(&path)?;
+	let dir = fs::File::open(&path)?;
+	let dirfd = dir.as_raw_fd();
+
+	log!("mkdirat");
+	util::mkdirat(dirfd, c"dir", 0o755)?;
+	test_assert!(fs::metadata(path.join("dir"))?.is_dir());
+	let res = util::mkdirat(dirfd, c"dir", 0o755);
+	test_assert!(matches!(res, Err(e) if e.kind() == io::ErrorKind::AlreadyExists));
+
+	log!("mknodat");
+	util::mknodat(dirfd, c"dir/fifo", S_IFIFO | 0o644, 0)?;
+	test_assert!(fs::metadata(path.join("dir/fifo"))?.file_type().is_fifo());
+
+	log!("readlinkat");
+	unix::fs::symlink("fifo", path.join("dir/link"))?;
+	test_assert_eq!(util::readlinkat(dirfd, c"dir/link")?, b"fifo");
+	let res = util::readlinkat(dirfd, c"dir/fifo");
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	log!("fstatat");
+	let stat = util::fstatat(dirfd, c"dir/link", 0)?;
+	test_assert_eq!(stat.st_mode & S_IFMT, S_IFIFO);
+	let stat = util::fstatat(dirfd, c"dir/link", AT_SYMLINK_NOFOLLOW)?;
+	test_assert_eq!(stat.st_mode & S_IFMT, S_IFLNK);
+	let stat = util::fstatat(dirfd, c"", AT_EMPTY_PATH)?;
+	test_assert_eq!(stat.st_mode & S_IFMT, S_IFDIR);
+	let res = util::fstatat(dirfd, c"dir/link", AT_SYMLINK_FOLLOW);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	log!("fchownat");
+	util::fchownat(dirfd, c"dir/link", 1000, 1000, AT_SYMLINK_NOFOLLOW)?;
+	let stat = util::fstatat(dirfd, c"dir/link", AT_SYMLINK_NOFOLLOW)?;
+	test_assert_eq!((stat.st_uid, stat.st_gid), (1000, 1000));
+	let stat = util::fstatat(dirfd, c"dir/fifo", 0)?;
+	test_assert_eq!((stat.st_uid, stat.st_gid), (0, 0));
+	let res = util::fchownat(dirfd, c"dir/fifo", 0, 0, AT_SYMLINK_FOLLOW);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	log!("renameat");
+	let sub = fs::File::open(path.join("dir"))?;
+	util::renameat(sub.as_raw_fd(), c"fifo", dirfd, c"fifo")?;
+	test_assert!(fs::symlink_metadata(path.join("dir/fifo")).is_err());
+	test_assert!(fs::metadata(path.join("fifo"))?.file_type().is_fifo());
+
+	log!("Cleanup");
+	drop(sub);
+	drop(dir);
+	fs::remove_file(path.join("fifo"))?;
+	fs::remove_file(path.join("dir/link"))?;
+	fs::remove_dir(path.join("dir"))?;
+	fs::remove_dir(path)?;
+
+	Ok(())
+}
+
+/// Resolves `path` `iterations` times.
+fn walk(path: &Path, iterations: u32) -> io::Result<()> {
+	for _ in 0..iterations {
+		util::stat(path)?;
+	}
+	Ok(())
+}
+
+/// Resolves each path of `paths` `iterations` times, each from its own process, and returns the
+/// average duration of a resolution.
+fn walk_concurrently(paths: &[&Path], iterations: u32) -> Result<Duration, TestError> {
+	let start = Instant::now();
+	let pids = paths
+		.iter()
+		.map(|path| {
+			let pid = util::fork()?;
+			if pid == 0 {
+				let res = walk(path, iterations);
+				unsafe { libc::_exit(res.is_err() as _) };
+			}
+			Ok(pid)
+		})
+		.collect::<io::Result<Vec<_>>>()?;
+	for pid in pids {
+		let status = util::waitpid(pid)?;
+		test_assert!(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	}
+	Ok(start.elapsed() / (iterations * paths.len() as u32))
+}
+
+pub fn path_walk(root: &Path) -> TestResult {
+	const PROCS: usize = 4;
+	const ITERATIONS: u32 = 10_000;
+	let dir = root.join("walk");
+	let paths = (0..PROCS)
+		.map(|i| {
+			let path = dir.join(format!("{i}/a/b/c/d"));
+			fs::create_dir_all(&path)?;
+			let path = path.join("file");
+			fs::write(&path, b"")?;
+			Ok(path)
+		})
+		.collect::<io::Result<Vec<_>>>()?;
+
+	log!("Sequential");
+	let start = Instant::now();
+	walk(&paths[0], ITERATIONS)?;
+	log!("{:?} per resolution", start.elapsed() / ITERATIONS);
+
+	log!("Concurrent, disjoint paths");
+	let disjoint: Vec<&Path> = paths.iter().map(|p| p.as_path()).collect();
+	log!(
+		"{:?} per resolution",
+		walk_concurrently(&disjoint, ITERATIONS)?
+	);
+
+	log!("Concurrent, same path");
+	let same = [paths[0].as_path(); PROCS];
+	log!("{:?} per resolution", walk_concurrently(&same, ITERATIONS)?);
+
+	fs::remove_dir_all(&dir)?;
+	Ok(())
+}
+
+pub fn timestamps(root: &Path) -> TestResult {
+	let dir = root.join("timestamps");
+	let sub = dir.join("sub");
+	let written = dir.join("written");
+	let truncated = dir.join("truncated");
+	let chmoded = dir.join("chmod");
+	let linked = dir.join("linked");
+	let mapped = dir.join("mapped");
+	let touched = dir.join("touched");
+	let old = dir.join("old");
+	let new = dir.join("new");
+
+	log!("Create files");
+	fs::create_dir_all(&sub)?;
+	for path in [&written, &truncated, &chmoded, &linked, &touched, &old] {
+		fs::write(path, b"abc")?;
+	}
+	fs::write(&mapped, vec![0; 4096])?;
+	log!("Reset timestamps");
+	let zero = timespec {
+		tv_sec: 0,
+		tv_nsec: 0,
+	};
+	for path in [
+		&dir, &sub, &written, &truncated, &chmoded, &linked, &mapped, &touched, &old,
+	] {
+		set_times(path, [zero; 2])?;
+		let stat = util::stat(path)?;
+		test_assert_eq!((stat.st_atime, stat.st_mtime), (0, 0));
+	}
+	// Timestamps have a precision of one second, so wait for the clock to move
+	thread::sleep(Duration::from_millis(1100));
+	// Inferred as the type of the `stat` timestamps, which is 32 bits wide on some targets
+	let start = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as _;
+
+	log!("Write");
+	OpenOptions::new()
+		.append(true)
+		.open(&written)?
+		.write_all(b"def")?;
+	let stat = util::stat(&written)?;
+	test_assert!(stat.st_mtime >= start && stat.st_ctime >= start);
+
+	log!("Truncate");
+	OpenOptions::new()
+		.write(true)
+		.open(&truncated)?
+		.set_len(1)?;
+	let stat = util::stat(&truncated)?;
+	test_assert!(stat.st_mtime >= start && stat.st_ctime >= start);
+
+	log!("Change mode");
+	fs::set_permissions(&chmoded, fs::Permissions::from_mode(0o600))?;
+	let stat = util::stat(&chmoded)?;
+	test_assert_eq!(stat.st_mtime, 0);
+	test_assert!(stat.st_ctime >= start);
+
+	log!("Hard link");
+	fs::hard_link(&linked, sub.join("link"))?;
+	let stat = util::stat(&linked)?;
+	test_assert_eq!(stat.st_mtime, 0);
+	test_assert!(stat.st_ctime >= start);
+	let stat = util::stat(&sub)?;
+	test_assert!(stat.st_mtime >= start && stat.st_ctime >= start);
+
+	log!("Write through a shared mapping");
+	{
+		let file = OpenOptions::new().read(true).write(true).open(&mapped)?;
+		let mut mmap = unsafe { MmapOptions::new().len(4096).map_mut(&file)? };
+		mmap.fill(1);
+		mmap.flush()?;
+	}
+	let stat = util::stat(&mapped)?;
+	test_assert!(stat.st_mtime >= start && stat.st_ctime >= start);
+
+	log!("Set the access time only");
+	let now = timespec {
+		tv_sec: 0,
+		tv_nsec: libc::UTIME_NOW,
+	};
+	let omit = timespec {
+		tv_sec: 0,
+		tv_nsec: libc::UTIME_OMIT,
+	};
+	set_times(&touched, [now, omit])?;
+	let stat = util::stat(&touched)?;
+	test_assert!(stat.st_atime >= start && stat.st_ctime >= start);
+	test_assert_eq!(stat.st_mtime, 0);
+
+	log!("Rename");
+	fs::rename(&old, &new)?;
+	let stat = util::stat(&new)?;
+	test_assert_eq!(stat.st_mtime, 0);
+	test_assert!(stat.st_ctime >= start);
+	let stat = util::stat(&dir)?;
+	test_assert!(stat.st_mtime >= start && stat.st_ctime >= start);
+
+	log!("Unlink");
+	set_times(&sub, [zero; 2])?;
+	fs::remove_file(sub.join("link"))?;
+	test_assert!(util::stat(&sub)?.st_mtime >= start);
+	test_assert!(util::stat(&linked)?.st_ctime >= start);
+
+	log!("Invalid time");
+	let invalid = timespec {
+		tv_sec: 0,
+		tv_nsec: 1_000_000_000,
+	};
+	let res = set_times(&touched, [invalid, omit]);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	log!("Cleanup");
+	fs::remove_dir_all(&dir)?;
+
+	Ok(())
+}
+
 pub fn fifo(root: &Path) -> TestResult {
 	log!("Create fifo");
 	let path = root.join("fifo");
@@ -353,7 +1066,594 @@ pub fn fifo(root: &Path) -> TestResult {
 	Ok(())
 }
 
+/// Performs the record lock command `cmd` on `fd`, for a lock of type `ty` on `len` bytes from
+/// `start`.
+///
+/// On success, the function returns the resulting lock description.
+fn record_lock(fd: c_int, cmd: c_int, ty: c_int, start: i64, len: i64) -> io::Result<flock> {
+	let mut lock: flock = unsafe { mem::zeroed() };
+	lock.l_type = ty as _;
+	lock.l_whence = SEEK_SET as _;
+	lock.l_start = start as _;
+	lock.l_len = len as _;
+	let res = unsafe { libc::fcntl(fd, cmd, &mut lock) };
+	if res >= 0 {
+		Ok(lock)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn record_locks(root: &Path) -> TestResult {
+	let path = root.join("locks");
+	let file = OpenOptions::new()
+		.create_new(true)
+		.read(true)
+		.write(true)
+		.open(&path)?;
+	let fd = file.as_raw_fd();
+
+	log!("Lock ranges");
+	record_lock(fd, F_SETLK, F_WRLCK, 0, 10)?;
+	record_lock(fd, F_SETLK, F_RDLCK, 20, 0)?;
+	// The process's own locks never conflict
+	let lock = record_lock(fd, F_GETLK, F_WRLCK, 0, 100)?;
+	test_assert_eq!(lock.l_type as c_int, F_UNLCK);
+
+	log!("Conflicts with another process");
+	let parent = unsafe { libc::getpid() };
+	let pid = util::fork()?;
+	if pid == 0 {
+		let res = (|| -> io::Result<bool> {
+			let lock = record_lock(fd, F_GETLK, F_RDLCK, 5, 1)?;
+			let found = lock.l_type as c_int == F_WRLCK
+				&& lock.l_start == 0
+				&& lock.l_len == 10
+				&& lock.l_pid == parent;
+			let res = record_lock(fd, F_SETLK, F_RDLCK, 9, 1);
+			let busy = matches!(res, Err(e) if e.raw_os_error() == Some(EAGAIN));
+			// Shared locks do not conflict
+			record_lock(fd, F_SETLK, F_RDLCK, 30, 10)?;
+			record_lock(fd, F_SETLK, F_WRLCK, 10, 10)?;
+			Ok(found && busy)
+		})();
+		unsafe { libc::_exit(!matches!(res, Ok(true)) as _) };
+	}
+	let status = util::waitpid(pid)?;
+	test_assert!(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	// The locks of the child are released on exit
+	record_lock(fd, F_SETLK, F_WRLCK, 10, 10)?;
+
+	log!("Deadlock detection");
+	let pid = util::fork()?;
+	if pid == 0 {
+		let res = record_lock(fd, F_SETLK, F_WRLCK, 50, 10)
+			.and_then(|_| record_lock(fd, F_SETLKW, F_WRLCK, 0, 10));
+		unsafe { libc::_exit(res.is_err() as _) };
+	}
+	// Wait for the child to block
+	thread::sleep(Duration::from_millis(100));
+	let res = record_lock(fd, F_SETLKW, F_WRLCK, 50, 10);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EDEADLK)));
+	// Closing any descriptor of the file releases the locks, unblocking the child
+	drop(fs::File::open(&path)?);
+	let status = util::waitpid(pid)?;
+	test_assert!(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	log!("Invalid ranges");
+	let res = record_lock(fd, F_SETLK, F_WRLCK, -1, 10);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+	let res = record_lock(fd, F_SETLK, F_WRLCK, 5, -10);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	log!("Cleanup");
+	drop(file);
+	fs::remove_file(path)?;
+
+	Ok(())
+}
+
+/// Reads an event from the fanotify group `fd`.
+fn fanotify_event(fd: c_int) -> io::Result<fanotify_event_metadata> {
+	let mut event: fanotify_event_metadata = unsafe { mem::zeroed() };
+	let len = unsafe {
+		libc::read(
+			fd,
+			&mut event as *mut _ as *mut c_void,
+			mem::size_of_val(&event),
+		)
+	};
+	if len >= 0 {
+		Ok(event)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Writes the response `response` for the permission event `event` to the fanotify group `fd`.
+fn fanotify_respond(fd: c_int, event: &fanotify_event_metadata, response: u32) -> io::Result<()> {
+	let response = fanotify_response {
+		fd: event.fd,
+		response,
+	};
+	let len = unsafe {
+		libc::write(
+			fd,
+			&response as *const _ as *const c_void,
+			mem::size_of_val(&response),
+		)
+	};
+	if len >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn fanotify(root: &Path) -> TestResult {
+	let path = root.join("fanotify");
+	fs::write(&path, b"content")?;
+	let cpath = CString::new(path.as_os_str().as_bytes())?;
+
+	log!("Permission events require a content class");
+	let group = util::fanotify_init(FAN_CLASS_NOTIF, O_RDONLY as _)?;
+	let res = util::fanotify_mark(group.as_raw_fd(), FAN_MARK_ADD, FAN_OPEN_PERM, &cpath);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+	drop(group);
+
+	log!("Mark file");
+	let group = util::fanotify_init(FAN_CLASS_CONTENT | FAN_CLOEXEC, O_RDONLY as _)?;
+	let group_fd = group.as_raw_fd();
+	let mask = FAN_OPEN_PERM | FAN_ACCESS | FAN_CLOSE_NOWRITE;
+	util::fanotify_mark(group_fd, FAN_MARK_ADD, mask, &cpath)?;
+
+	for (response, expected) in [(FAN_ALLOW, 0), (FAN_DENY, EPERM)] {
+		log!("Respond {response} to a permission event");
+		let pid = util::fork()?;
+		if pid == 0 {
+			let errno = match fs::read(&path) {
+				Ok(_) => 0,
+				Err(e) => e.raw_os_error().unwrap_or(-1),
+			};
+			unsafe { libc::_exit(errno) };
+		}
+		let event = fanotify_event(group_fd)?;
+		test_assert_eq!(event.mask, FAN_OPEN_PERM);
+		test_assert_eq!(event.pid, pid);
+		// The reported file descriptor refers to the accessed file. Reading it does not generate
+		// events
+		let mut file = unsafe { fs::File::from_raw_fd(event.fd) };
+		let mut buf = String::new();
+		file.read_to_string(&mut buf)?;
+		test_assert_eq!(buf, "content");
+		fanotify_respond(group_fd, &event, response)?;
+		drop(file);
+		let status = util::waitpid(pid)?;
+		test_assert!(WIFEXITED(status) && WEXITSTATUS(status) == expected);
+		if response == FAN_ALLOW {
+			let mut mask = 0;
+			while mask != FAN_ACCESS | FAN_CLOSE_NOWRITE {
+				let event = fanotify_event(group_fd)?;
+				test_assert_eq!(event.pid, pid);
+				unsafe {
+					libc::close(event.fd);
+				}
+				mask |= event.mask;
+			}
+		}
+		test_assert_eq!(util::poll(group_fd, POLLIN)?, 0);
+	}
+
+	log!("Closing the group allows pending accesses");
+	let pid = util::fork()?;
+	if pid == 0 {
+		// Do not keep the group open
+		unsafe {
+			libc::close(group_fd);
+		}
+		let res = fs::read(&path);
+		unsafe { libc::_exit(res.is_err() as _) };
+	}
+	// Wait for the child to block
+	thread::sleep(Duration::from_millis(100));
+	drop(group);
+	let status = util::waitpid(pid)?;
+	test_assert!(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	log!("Cleanup");
+	fs::remove_file(path)?;
+
+	Ok(())
+}
+
+pub fn statx(root: &Path) -> TestResult {
+	let path = root.join("statx");
+	let mut file = OpenOptions::new()
+		.create_new(true)
+		.read(true)
+		.write(true)
+		.open(&path)?;
+	let fd = file.as_raw_fd();
+	let empty = c"";
+
+	log!("Empty file");
+	let stx = util::statx(fd, empty, AT_EMPTY_PATH, STATX_BASIC_STATS | STATX_BTIME)?;
+	test_assert_eq!(stx.stx_mask & STATX_BASIC_STATS, STATX_BASIC_STATS);
+	test_assert_eq!(stx.stx_size, 0);
+	test_assert_eq!(stx.stx_blocks, 0);
+	if stx.stx_mask & STATX_BTIME != 0 {
+		test_assert!(stx.stx_btime.tv_sec <= stx.stx_mtime.tv_sec);
+	}
+
+	log!("Allocated blocks");
+	file.write_all(&[1; 4096])?;
+	let stx = util::statx(
+		fd,
+		empty,
+		AT_EMPTY_PATH | AT_STATX_FORCE_SYNC,
+		STATX_BASIC_STATS,
+	)?;
+	test_assert_eq!(stx.stx_size, 4096);
+	test_assert!(stx.stx_blocks >= 8);
+	test_assert_eq!(stx.stx_blocks, util::fstat(fd)?.st_blocks as u64);
+	file.set_len(0)?;
+	let stx = util::statx(
+		fd,
+		empty,
+		AT_EMPTY_PATH | AT_STATX_DONT_SYNC,
+		STATX_BASIC_STATS,
+	)?;
+	test_assert_eq!(stx.stx_blocks, 0);
+
+	log!("Attributes");
+	// No attribute is set on a new file
+	test_assert_eq!(stx.stx_attributes, 0);
+
+	log!("Invalid flags");
+	let res = util::statx(
+		fd,
+		empty,
+		AT_EMPTY_PATH | AT_STATX_FORCE_SYNC | AT_STATX_DONT_SYNC,
+		STATX_BASIC_STATS,
+	);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	log!("Cleanup");
+	drop(file);
+	fs::remove_file(path)?;
+
+	Ok(())
+}
+
+pub fn large_file(root: &Path) -> TestResult {
+	const GIB: u64 = 1024 * 1024 * 1024;
+
+	log!("Create sparse file");
+	let path = root.join("large");
+	let mut file = OpenOptions::new()
+		.create_new(true)
+		.read(true)
+		.write(true)
+		.open(&path)?;
+	let off = file.seek(SeekFrom::Start(5 * GIB))?;
+	test_assert_eq!(off, 5 * GIB);
+	file.write_all(b"end")?;
+	let stat = util::fstat(file.as_raw_fd())?;
+	test_assert_eq!(stat.st_size as u64, 5 * GIB + 3);
+	test_assert_eq!(fs::metadata(&path)?.len(), 5 * GIB + 3);
+
+	log!("Read after the 4 GiB boundary");
+	let mut buf = [0xffu8; 3];
+	file.seek(SeekFrom::Start(5 * GIB))?;
+	file.read_exact(&mut buf)?;
+	test_assert_eq!(&buf, b"end");
+
+	log!("Read hole");
+	let mut buf = [0xffu8; 16];
+	file.seek(SeekFrom::Start(GIB))?;
+	file.read_exact(&mut buf)?;
+	test_assert!(buf.iter().all(|b| *b == 0));
+
+	log!("Seek relative to end");
+	let off = file.seek(SeekFrom::End(-3))?;
+	test_assert_eq!(off, 5 * GIB);
+
+	#[cfg(target_arch = "x86")]
+	{
+		use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+		log!("32-bit offsets overflow");
+		let fd = file.as_raw_fd();
+		let res = unsafe { libc::syscall(libc::SYS_lseek, fd, 0, libc::SEEK_END) };
+		test_assert!(
+			res < 0 && io::Error::last_os_error().raw_os_error() == Some(libc::EOVERFLOW)
+		);
+		let c_path = CString::new(path.as_os_str().as_bytes())?;
+		let mut stat = [0u8; 64];
+		let res = unsafe { libc::syscall(libc::SYS_stat, c_path.as_ptr(), stat.as_mut_ptr()) };
+		test_assert!(
+			res < 0 && io::Error::last_os_error().raw_os_error() == Some(libc::EOVERFLOW)
+		);
+		let res = unsafe { libc::syscall(libc::SYS_open, c_path.as_ptr(), libc::O_RDONLY) };
+		test_assert!(
+			res < 0 && io::Error::last_os_error().raw_os_error() == Some(libc::EOVERFLOW)
+		);
+	}
+
+	log!("Truncate");
+	file.set_len(4 * GIB + 1)?;
+	test_assert_eq!(util::fstat(file.as_raw_fd())?.st_size as u64, 4 * GIB + 1);
+	util::truncate(&path, 6 * GIB)?;
+	test_assert_eq!(fs::metadata(&path)?.len(), 6 * GIB);
+	file.set_len(5 * GIB + 3)?;
+	let mut buf = [0xffu8; 3];
+	file.seek(SeekFrom::Start(5 * GIB))?;
+	file.read_exact(&mut buf)?;
+	test_assert_eq!(&buf, &[0; 3]);
+
+	log!("Cleanup");
+	fs::remove_file(&path)?;
+
+	Ok(())
+}
+
 pub fn persistence(root: &Path) -> TestResult {
 	fs::write(root.join("persistent"), "persistence OK")?;
 	Ok(())
 }
+
+/// Looks up entries in the hash-indexed directory created along with the disk, then modifies
+/// it.
+pub fn htree() -> TestResult {
+	const DIR: &str = "/htree";
+	const COUNT: usize = 1000;
+
+	log!("Lookup");
+	for i in 0..COUNT {
+		fs::metadata(format!("{DIR}/file{i}"))?;
+	}
+	let res = fs::metadata(format!("{DIR}/file{COUNT}"));
+	test_assert!(matches!(res, Err(e) if e.kind() == io::ErrorKind::NotFound));
+
+	log!("Modify");
+	fs::write(format!("{DIR}/new"), b"")?;
+	fs::remove_file(format!("{DIR}/file0"))?;
+
+	log!("Lookup after modification");
+	fs::metadata(format!("{DIR}/new"))?;
+	let res = fs::metadata(format!("{DIR}/file0"));
+	test_assert!(matches!(res, Err(e) if e.kind() == io::ErrorKind::NotFound));
+	for i in 1..COUNT {
+		fs::metadata(format!("{DIR}/file{i}"))?;
+	}
+
+	log!("Cleanup");
+	fs::remove_dir_all(DIR)?;
+	Ok(())
+}
+
+/// Checks the orphan inodes left on the disk when it was created have been processed when
+/// mounting the root filesystem.
+pub fn orphans() -> TestResult {
+	const PATH: &str = "/orphan_truncated";
+
+	log!("Check truncated file");
+	let metadata = fs::metadata(PATH)?;
+	test_assert_eq!(metadata.len(), 4096);
+	// Blocks after the size have been freed
+	test_assert_eq!(metadata.blocks(), 8);
+	let content = fs::read(PATH)?;
+	test_assert!(content.chunks(2).all(|c| c == b"y\n"));
+
+	log!("Cleanup");
+	fs::remove_file(PATH)?;
+	Ok(())
+}
+
+/// Checks the transaction left in the journal when the disk was created has been replayed when
+/// mounting the root filesystem, then performs operations committed to the journal.
+pub fn journal() -> TestResult {
+	const PATH: &str = "/journal_replayed";
+	const DIR: &str = "/journal_dir";
+
+	log!("Check replayed file");
+	test_assert_eq!(fs::read(PATH)?, b"replayed\n");
+	fs::remove_file(PATH)?;
+
+	log!("Journaled operations");
+	fs::create_dir(DIR)?;
+	for i in 0..64 {
+		fs::write(format!("{DIR}/file{i}"), format!("content {i}"))?;
+	}
+	fs::rename(format!("{DIR}/file0"), format!("{DIR}/renamed"))?;
+	unsafe {
+		libc::sync();
+	}
+	for i in 1..64 {
+		test_assert_eq!(
+			fs::read(format!("{DIR}/file{i}"))?,
+			format!("content {i}").into_bytes()
+		);
+	}
+	test_assert_eq!(fs::read(format!("{DIR}/renamed"))?, b"content 0");
+
+	log!("Cleanup");
+	fs::remove_dir_all(DIR)?;
+	unsafe {
+		libc::sync();
+	}
+	Ok(())
+}
+
+/// ioctl request: freeze the filesystem.
+const FIFREEZE: c_ulong = 0xc0045877;
+/// ioctl request: thaw the filesystem.
+const FITHAW: c_ulong = 0xc0045878;
+
+fn fs_ioctl(file: &fs::File, req: c_ulong) -> io::Result<()> {
+	let mut arg: c_int = 0;
+	let res = unsafe { libc::ioctl(file.as_raw_fd(), req as _, &mut arg) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+pub fn freeze() -> TestResult {
+	const PATH: &str = "/frozen";
+	let root = fs::File::open("/")?;
+
+	log!("Freeze");
+	fs_ioctl(&root, FIFREEZE)?;
+	// Make sure the filesystem is thawed, even on failure
+	let res = (|| -> Result<libc::pid_t, TestError> {
+		let res = fs_ioctl(&root, FIFREEZE);
+		test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EBUSY)));
+		log!("Write while frozen");
+		let pid = util::fork()?;
+		if pid == 0 {
+			let res = fs::write(PATH, b"thawed");
+			unsafe { libc::_exit(res.is_err() as _) };
+		}
+		thread::sleep(Duration::from_millis(100));
+		// The child is blocked and reading is still possible
+		let res = unsafe { libc::waitpid(pid, null_mut(), WNOHANG) };
+		test_assert_eq!(res, 0);
+		test_assert!(!fs::exists(PATH)?);
+		Ok(pid)
+	})();
+	log!("Thaw");
+	fs_ioctl(&root, FITHAW)?;
+	let pid = res?;
+	let status = util::waitpid(pid)?;
+	test_assert!(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	test_assert_eq!(fs::read(PATH)?, b"thawed");
+	fs::remove_file(PATH)?;
+	let res = fs_ioctl(&root, FITHAW);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+	Ok(())
+}
+
+/// ioctl request: get the location of a block of a file on the device.
+const FIBMAP: c_ulong = 0x1;
+/// ioctl request: get the block size of the filesystem.
+const FIGETBSZ: c_ulong = 0x2;
+/// ioctl request: get the location of the extents of a file on the device.
+const FS_IOC_FIEMAP: c_ulong = 0xc020660b;
+
+/// `fe_flags`: The extent is the last of the file.
+const FIEMAP_EXTENT_LAST: u32 = 0x1;
+
+#[repr(C)]
+#[derive(Default)]
+struct Fiemap {
+	fm_start: u64,
+	fm_length: u64,
+	fm_flags: u32,
+	fm_mapped_extents: u32,
+	fm_extent_count: u32,
+	fm_reserved: u32,
+	fm_extents: [FiemapExtent; 8],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct FiemapExtent {
+	fe_logical: u64,
+	fe_physical: u64,
+	fe_length: u64,
+	fe_reserved64: [u64; 2],
+	fe_flags: u32,
+	fe_reserved: [u32; 3],
+}
+
+fn fibmap(file: &fs::File, blk: c_int) -> io::Result<c_int> {
+	let mut arg = blk;
+	let res = unsafe { libc::ioctl(file.as_raw_fd(), FIBMAP as _, &mut arg) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(arg)
+}
+
+fn fiemap(file: &fs::File, extent_count: u32) -> io::Result<Fiemap> {
+	let mut fiemap = Fiemap {
+		fm_length: u64::MAX,
+		fm_extent_count: extent_count,
+		..Default::default()
+	};
+	let res = unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as _, &mut fiemap) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(fiemap)
+}
+
+pub fn bmap() -> TestResult {
+	const PATH: &str = "/bmap";
+
+	log!("Create file with a hole");
+	let mut file = OpenOptions::new()
+		.create_new(true)
+		.read(true)
+		.write(true)
+		.open(PATH)?;
+	let mut blk_size: c_int = 0;
+	let res = unsafe { libc::ioctl(file.as_raw_fd(), FIGETBSZ as _, &mut blk_size) };
+	test_assert_eq!(res, 0);
+	test_assert_eq!(blk_size, 4096);
+	let blk_size = blk_size as u64;
+	file.write_all(&[1; 8192])?;
+	file.seek(SeekFrom::Start(4 * blk_size))?;
+	file.write_all(&[2; 4096])?;
+	file.sync_all()?;
+
+	log!("FIBMAP");
+	let first = fibmap(&file, 0)?;
+	test_assert!(first > 0);
+	test_assert!(fibmap(&file, 1)? > 0);
+	test_assert_eq!(fibmap(&file, 2)?, 0);
+	test_assert!(fibmap(&file, 4)? > 0);
+	test_assert_eq!(fibmap(&file, 5)?, 0);
+
+	log!("FIEMAP");
+	let count = fiemap(&file, 0)?.fm_mapped_extents;
+	test_assert!((2..=3).contains(&count));
+	let res = fiemap(&file, 8)?;
+	test_assert_eq!(res.fm_mapped_extents, count);
+	let extents = &res.fm_extents[..count as usize];
+	test_assert_eq!(extents[0].fe_logical, 0);
+	test_assert_eq!(extents[0].fe_physical, first as u64 * blk_size);
+	let total: u64 = extents.iter().map(|e| e.fe_length).sum();
+	test_assert_eq!(total, 3 * blk_size);
+	let last = extents.last().unwrap();
+	test_assert_eq!(last.fe_logical, 4 * blk_size);
+	test_assert!(last.fe_flags & FIEMAP_EXTENT_LAST != 0);
+	test_assert!(
+		extents[..extents.len() - 1]
+			.iter()
+			.all(|e| e.fe_flags & FIEMAP_EXTENT_LAST == 0)
+	);
+	// Fewer extents than the file has
+	let res = fiemap(&file, 1)?;
+	test_assert_eq!(res.fm_mapped_extents, 1);
+
+	log!("Cleanup");
+	drop(file);
+	fs::remove_file(PATH)?;
+	Ok(())
+}
+
+pub fn errors() -> TestResult {
+	const PATH: &str = "/errors_continue";
+
+	log!("Read corrupted directory");
+	let res = fs::read_dir("/corrupted")?.next();
+	test_assert!(matches!(res, Some(Err(e)) if e.raw_os_error() == Some(EUCLEAN)));
+
+	log!("Check the filesystem is still writable");
+	fs::write(PATH, b"ok")?;
+	test_assert_eq!(fs::read(PATH)?, b"ok");
+	fs::remove_file(PATH)?;
+	Ok(())
+}
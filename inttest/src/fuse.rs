@@ -0,0 +1,264 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Tests a FUSE filesystem served by a minimal daemon.
+
+use crate::{log, test_assert_eq, util, util::TestResult};
+use libc::{ENODEV, ENOENT, ENOSYS, c_int};
+use std::{
+	cmp::min,
+	ffi::CString,
+	fs,
+	fs::{File, OpenOptions},
+	io,
+	io::{Read, Write},
+	mem,
+	os::{fd::AsRawFd, unix::fs::MetadataExt},
+	process,
+};
+
+const LOOKUP: u32 = 1;
+const FORGET: u32 = 2;
+const SETATTR: u32 = 4;
+const OPEN: u32 = 14;
+const READ: u32 = 15;
+const WRITE: u32 = 16;
+const STATFS: u32 = 17;
+const RELEASE: u32 = 18;
+const INIT: u32 = 26;
+const OPENDIR: u32 = 27;
+const READDIR: u32 = 28;
+const RELEASEDIR: u32 = 29;
+
+const FATTR_SIZE: u32 = 1 << 3;
+
+/// The size of a request's header.
+const IN_HEADER_SIZE: usize = 40;
+/// The node ID of the only file of the filesystem.
+const FILE_ID: u64 = 2;
+
+fn u32_at(buf: &[u8], off: usize) -> u32 {
+	u32::from_ne_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], off: usize) -> u64 {
+	u64::from_ne_bytes(buf[off..off + 8].try_into().unwrap())
+}
+
+/// Returns the attributes of the node `nodeid`, `size` being the size of the file.
+fn attr(nodeid: u64, size: usize) -> Vec<u8> {
+	let (mode, nlink): (u32, u32) = if nodeid == FILE_ID {
+		(0o100644, 1)
+	} else {
+		(0o40755, 2)
+	};
+	let mut out = Vec::new();
+	// ino, size, blocks, atime, mtime, ctime
+	for n in [nodeid, size as u64, size.div_ceil(512) as u64, 0, 0, 0] {
+		out.extend_from_slice(&n.to_ne_bytes());
+	}
+	// nsecs, mode, nlink, uid, gid, rdev, blksize, flags
+	for n in [0, 0, 0, mode, nlink, 0, 0, 0, 4096, 0] {
+		out.extend_from_slice(&n.to_ne_bytes());
+	}
+	out
+}
+
+/// Serves requests from `dev` until the filesystem is unmounted.
+///
+/// The filesystem contains a single file, `hello`.
+fn serve(mut dev: &File) -> io::Result<()> {
+	let mut content = b"hello world\n".to_vec();
+	let mut buf = vec![0; 65536 + 4096];
+	loop {
+		let len = match dev.read(&mut buf) {
+			Ok(len) => len,
+			Err(e) if e.raw_os_error() == Some(ENODEV) => return Ok(()),
+			Err(e) => return Err(e),
+		};
+		let req = &buf[..len];
+		let opcode = u32_at(req, 4);
+		let unique = u64_at(req, 8);
+		let nodeid = u64_at(req, 16);
+		let arg = &req[IN_HEADER_SIZE..];
+		let res: Result<Vec<u8>, c_int> = match opcode {
+			INIT => {
+				let mut out = Vec::new();
+				// major, minor, max_readahead, flags
+				for n in [7u32, 26, 0, 0] {
+					out.extend_from_slice(&n.to_ne_bytes());
+				}
+				// max_background, congestion_threshold, max_write
+				out.extend_from_slice(&[0; 4]);
+				out.extend_from_slice(&4096u32.to_ne_bytes());
+				Ok(out)
+			}
+			LOOKUP => {
+				let name = arg.split(|b| *b == 0).next().unwrap();
+				if nodeid == 1 && name == b"hello" {
+					let mut out = Vec::new();
+					out.extend_from_slice(&FILE_ID.to_ne_bytes());
+					out.extend_from_slice(&[0; 32]);
+					out.extend(attr(FILE_ID, content.len()));
+					Ok(out)
+				} else {
+					Err(ENOENT)
+				}
+			}
+			FORGET => continue,
+			SETATTR => {
+				if u32_at(arg, 0) & FATTR_SIZE != 0 {
+					content.resize(u64_at(arg, 16) as usize, 0);
+				}
+				let mut out = vec![0; 16];
+				out.extend(attr(nodeid, content.len()));
+				Ok(out)
+			}
+			OPEN | OPENDIR => Ok([0; 16].to_vec()),
+			READ => {
+				let off = min(u64_at(arg, 8) as usize, content.len());
+				let end = min(off + u32_at(arg, 16) as usize, content.len());
+				Ok(content[off..end].to_vec())
+			}
+			WRITE => {
+				let off = u64_at(arg, 8) as usize;
+				let size = u32_at(arg, 16) as usize;
+				let data = &arg[40..40 + size];
+				if content.len() < off + size {
+					content.resize(off + size, 0);
+				}
+				content[off..off + size].copy_from_slice(data);
+				Ok([size as u32, 0]
+					.iter()
+					.flat_map(|n| n.to_ne_bytes())
+					.collect())
+			}
+			STATFS => {
+				let mut out = vec![0; 40];
+				// bsize, namelen, frsize, padding, spare
+				for n in [4096u32, 255, 4096, 0, 0, 0, 0, 0, 0, 0] {
+					out.extend_from_slice(&n.to_ne_bytes());
+				}
+				Ok(out)
+			}
+			RELEASE | RELEASEDIR => Ok(vec![]),
+			READDIR => {
+				let entries: [(u64, &[u8], u32); 3] = [
+					(1, b".", libc::DT_DIR as _),
+					(1, b"..", libc::DT_DIR as _),
+					(FILE_ID, b"hello", libc::DT_REG as _),
+				];
+				let mut out = Vec::new();
+				for (i, (ino, name, typ)) in entries.iter().enumerate().skip(u64_at(arg, 8) as _) {
+					out.extend_from_slice(&ino.to_ne_bytes());
+					out.extend_from_slice(&(i as u64 + 1).to_ne_bytes());
+					out.extend_from_slice(&(name.len() as u32).to_ne_bytes());
+					out.extend_from_slice(&typ.to_ne_bytes());
+					out.extend_from_slice(name);
+					out.resize(out.len().next_multiple_of(8), 0);
+				}
+				Ok(out)
+			}
+			_ => Err(ENOSYS),
+		};
+		// Reply
+		let (error, data) = match res {
+			Ok(data) => (0, data),
+			Err(errno) => (-errno, vec![]),
+		};
+		let mut reply = Vec::new();
+		reply.extend_from_slice(&(16 + data.len() as u32).to_ne_bytes());
+		reply.extend_from_slice(&error.to_ne_bytes());
+		reply.extend_from_slice(&unique.to_ne_bytes());
+		reply.extend(data);
+		let len = dev.write(&reply)?;
+		if len != reply.len() {
+			return Err(io::Error::from_raw_os_error(libc::EIO));
+		}
+	}
+}
+
+/// Mounts a FUSE filesystem and accesses it while a child process serves it.
+pub fn daemon() -> TestResult {
+	const TARGET: &str = "/mnt/fuse";
+
+	log!("Mount");
+	let dev = OpenOptions::new()
+		.read(true)
+		.write(true)
+		.open("/dev/fuse")?;
+	fs::create_dir_all(TARGET)?;
+	let target = CString::new(TARGET)?;
+	let opts = CString::new(format!(
+		"fd={},rootmode=40000,user_id=0,group_id=0",
+		dev.as_raw_fd()
+	))?;
+	util::mount(c"fuse", target.as_c_str(), c"fuse", 0, opts.as_ptr() as _)?;
+	let child = util::fork()?;
+	if child == 0 {
+		let code = match serve(&dev) {
+			Ok(()) => 0,
+			Err(_) => 1,
+		};
+		process::exit(code);
+	}
+	let res = (|| {
+		log!("List root");
+		let names = fs::read_dir(TARGET)?
+			.map(|ent| Ok(ent?.file_name().into_string().unwrap()))
+			.collect::<io::Result<Vec<_>>>()?;
+		test_assert_eq!(names, ["hello"]);
+
+		log!("Read");
+		let path = format!("{TARGET}/hello");
+		let meta = fs::metadata(&path)?;
+		test_assert_eq!(meta.len(), 12);
+		test_assert_eq!(meta.mode(), 0o100644);
+		test_assert_eq!(fs::read(&path)?, b"hello world\n");
+		let res = fs::metadata(format!("{TARGET}/missing"));
+		test_assert_eq!(res.err().and_then(|e| e.raw_os_error()), Some(ENOENT));
+
+		log!("Write");
+		OpenOptions::new()
+			.write(true)
+			.open(&path)?
+			.write_all(b"HELLO")?;
+		test_assert_eq!(fs::read(&path)?, b"HELLO world\n");
+		fs::write(&path, b"bye\n")?;
+		test_assert_eq!(fs::read(&path)?, b"bye\n");
+		test_assert_eq!(fs::metadata(&path)?.len(), 4);
+
+		log!("Unsupported operation");
+		let res = fs::create_dir(format!("{TARGET}/dir"));
+		test_assert_eq!(res.err().and_then(|e| e.raw_os_error()), Some(ENOSYS));
+
+		log!("Statfs");
+		let mut stat: libc::statfs = unsafe { mem::zeroed() };
+		let res = unsafe { libc::statfs(target.as_ptr(), &mut stat) };
+		test_assert_eq!(res, 0);
+		test_assert_eq!(stat.f_type, 0x65735546);
+		test_assert_eq!(stat.f_namelen, 255);
+		Ok(())
+	})();
+
+	log!("Cleanup");
+	util::umount(target.as_c_str())?;
+	util::kill(child, libc::SIGKILL)?;
+	util::waitpid(child)?;
+	res
+}
@@ -0,0 +1,152 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Loop devices tests.
+
+use crate::{log, test_assert, test_assert_eq, util::TestResult};
+use libc::{EBUSY, EINVAL, ENXIO, EPERM, c_int, c_ulong, c_void};
+use std::{
+	fs,
+	fs::{File, OpenOptions},
+	io,
+	io::{Read, Seek, SeekFrom, Write},
+	mem,
+	os::{fd::AsRawFd, unix::fs::MetadataExt},
+};
+
+const LOOP_SET_FD: c_ulong = 0x4c00;
+const LOOP_CLR_FD: c_ulong = 0x4c01;
+const LOOP_GET_STATUS64: c_ulong = 0x4c05;
+const LOOP_CTL_GET_FREE: c_ulong = 0x4c82;
+const BLKGETSIZE64: c_ulong = 0x80081272;
+
+const LO_FLAGS_READ_ONLY: u32 = 1;
+
+#[repr(C)]
+struct LoopInfo64 {
+	lo_device: u64,
+	lo_inode: u64,
+	lo_rdevice: u64,
+	lo_offset: u64,
+	lo_sizelimit: u64,
+	lo_number: u32,
+	lo_encrypt_type: u32,
+	lo_encrypt_key_size: u32,
+	lo_flags: u32,
+	lo_file_name: [u8; 64],
+	lo_crypt_name: [u8; 64],
+	lo_encrypt_key: [u8; 32],
+	lo_init: [u64; 2],
+}
+
+fn ioctl(file: &File, req: c_ulong, arg: usize) -> io::Result<c_int> {
+	let res = unsafe { libc::ioctl(file.as_raw_fd(), req as _, arg as *mut c_void) };
+	if res >= 0 {
+		Ok(res)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+fn status(dev: &File) -> io::Result<LoopInfo64> {
+	let mut info: LoopInfo64 = unsafe { mem::zeroed() };
+	ioctl(dev, LOOP_GET_STATUS64, &mut info as *mut _ as usize)?;
+	Ok(info)
+}
+
+fn errno<T>(res: io::Result<T>) -> Option<i32> {
+	res.err().and_then(|e| e.raw_os_error())
+}
+
+/// Attaches a file to a loop device, then accesses it through the device.
+pub fn attach() -> TestResult {
+	const PATH: &str = "/loop_image";
+	const SIZE: usize = 2 * 4096;
+
+	log!("Create backing file");
+	let data: Vec<u8> = (0..SIZE).map(|i| (i % 251) as u8).collect();
+	fs::write(PATH, &data)?;
+
+	log!("Get free loop device");
+	let ctl = File::open("/dev/loop-control")?;
+	let index = ioctl(&ctl, LOOP_CTL_GET_FREE, 0)?;
+	let dev_path = format!("/dev/loop{index}");
+	let mut dev = OpenOptions::new().read(true).write(true).open(&dev_path)?;
+	test_assert_eq!(errno(status(&dev)), Some(ENXIO));
+
+	log!("Attach");
+	let backing = OpenOptions::new().read(true).write(true).open(PATH)?;
+	ioctl(&dev, LOOP_SET_FD, backing.as_raw_fd() as _)?;
+	test_assert_eq!(
+		errno(ioctl(&dev, LOOP_SET_FD, backing.as_raw_fd() as _)),
+		Some(EBUSY)
+	);
+	drop(backing);
+	let mut size = 0u64;
+	ioctl(&dev, BLKGETSIZE64, &mut size as *mut _ as usize)?;
+	test_assert_eq!(size, SIZE as u64);
+	let info = status(&dev)?;
+	test_assert_eq!(info.lo_number, index as u32);
+	test_assert_eq!(info.lo_flags & LO_FLAGS_READ_ONLY, 0);
+	test_assert!(info.lo_file_name.starts_with(PATH.as_bytes()));
+	test_assert_eq!(info.lo_inode, fs::metadata(PATH)?.ino());
+
+	log!("Read through device");
+	let mut buf = vec![0; SIZE];
+	dev.read_exact(&mut buf)?;
+	test_assert!(buf == data);
+
+	log!("Write through device");
+	dev.seek(SeekFrom::Start(4096 + 10))?;
+	dev.write_all(b"loop")?;
+	let content = fs::read(PATH)?;
+	test_assert_eq!(&content[4096 + 10..4096 + 14], b"loop");
+
+	log!("Detach");
+	ioctl(&dev, LOOP_CLR_FD, 0)?;
+	test_assert_eq!(errno(ioctl(&dev, LOOP_CLR_FD, 0)), Some(ENXIO));
+	test_assert_eq!(errno(status(&dev)), Some(ENXIO));
+	// The device is free again
+	test_assert_eq!(ioctl(&ctl, LOOP_CTL_GET_FREE, 0)?, index);
+
+	log!("Attach read-only");
+	let backing = File::open(PATH)?;
+	ioctl(&dev, LOOP_SET_FD, backing.as_raw_fd() as _)?;
+	test_assert_eq!(
+		status(&dev)?.lo_flags & LO_FLAGS_READ_ONLY,
+		LO_FLAGS_READ_ONLY
+	);
+	dev.seek(SeekFrom::Start(0))?;
+	test_assert_eq!(errno(dev.write(b"a")), Some(EPERM));
+	ioctl(&dev, LOOP_CLR_FD, 0)?;
+
+	log!("Attach sparse file");
+	let backing = OpenOptions::new().read(true).write(true).open(PATH)?;
+	backing.set_len(2 * SIZE as u64)?;
+	// Writes to the holes would be lost
+	test_assert_eq!(
+		errno(ioctl(&dev, LOOP_SET_FD, backing.as_raw_fd() as _)),
+		Some(EINVAL)
+	);
+	let backing = File::open(PATH)?;
+	ioctl(&dev, LOOP_SET_FD, backing.as_raw_fd() as _)?;
+	ioctl(&dev, LOOP_CLR_FD, 0)?;
+
+	fs::remove_file(PATH)?;
+	Ok(())
+}
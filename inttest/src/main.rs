@@ -21,16 +21,30 @@
 #![feature(io_error_more)]
 
 use crate::{
-	mount::{mount, umount},
+	mount::{
+		bind, debugfs, flags, fs_stats, mount, propagation, remount, sysfs_block, umount,
+		unknown_type,
+	},
 	util::TestResult,
 };
 use std::{path::Path, process::exit};
 
+mod ata;
 mod filesystem;
+mod fuse;
+mod loop_dev;
 mod module;
 mod mount;
+mod netlink;
+mod poll;
 mod procfs;
+mod quota;
 mod signal;
+mod socket;
+mod spawn;
+mod squashfs;
+mod time;
+mod udp;
 mod util;
 
 /*
@@ -71,8 +85,32 @@ macro_rules! fs_suite {
 					desc: "Map a file",
 					start: || filesystem::mmap(Path::new($root)),
 				},
+				Test {
+					name: "positional_io",
+					desc: "Read and write at a given offset",
+					start: || filesystem::positional_io(Path::new($root)),
+				},
+				Test {
+					name: "vectored_io",
+					desc: "Read and write with IO vectors",
+					start: || filesystem::vectored_io(Path::new($root)),
+				},
+				Test {
+					name: "sync_file_range",
+					desc: "Write back a range of a file",
+					start: || filesystem::sync_file_range(Path::new($root)),
+				},
+				Test {
+					name: "aio",
+					desc: "Read and write a file with legacy asynchronous I/O",
+					start: || filesystem::aio(Path::new($root)),
+				},
 				// TODO private mapped file
-				// TODO umask
+				Test {
+					name: "umask",
+					desc: "Apply the file mode creation mask and share it between threads",
+					start: || filesystem::umask(Path::new($root)),
+				},
 				Test {
 					name: "directories",
 					desc: "Create, remove and modify the properties directories",
@@ -83,6 +121,16 @@ macro_rules! fs_suite {
 					desc: "Test directory permissions",
 					start: || filesystem::dir_perms(Path::new($root)),
 				},
+				Test {
+					name: "ownership",
+					desc: "Change the owner and group of files",
+					start: || filesystem::ownership(Path::new($root)),
+				},
+				Test {
+					name: "fsuid",
+					desc: "Access files with the filesystem user and group IDs",
+					start: || filesystem::fsuid(Path::new($root)),
+				},
 				Test {
 					name: "hardlinks",
 					desc: "Test hard links",
@@ -102,11 +150,41 @@ macro_rules! fs_suite {
 					desc: "Test renaming files",
 					start: || filesystem::rename(Path::new($root)),
 				},
+				Test {
+					name: "at_functions",
+					desc: "Use directory-relative path resolution",
+					start: || filesystem::at_functions(Path::new($root)),
+				},
+				Test {
+					name: "path_walk",
+					desc: "Resolve paths concurrently from several processes and report timings",
+					start: || filesystem::path_walk(Path::new($root)),
+				},
+				Test {
+					name: "timestamps",
+					desc: "Check timestamps are updated by operations modifying files",
+					start: || filesystem::timestamps(Path::new($root)),
+				},
 				Test {
 					name: "fifo",
 					desc: "Test FIFO files",
 					start: || filesystem::fifo(Path::new($root)),
 				},
+				Test {
+					name: "record_locks",
+					desc: "Take POSIX record locks on ranges of a file",
+					start: || filesystem::record_locks(Path::new($root)),
+				},
+				Test {
+					name: "statx",
+					desc: "Check the extended status of files",
+					start: || filesystem::statx(Path::new($root)),
+				},
+				Test {
+					name: "fanotify",
+					desc: "Gate accesses to a file with fanotify permission events",
+					start: || filesystem::fanotify(Path::new($root)),
+				},
 				// TODO file socket
 				// TODO check /dev/* contents
 			],
@@ -126,11 +204,81 @@ const TESTS: &[TestSuite] = &[
 				desc: "Mount procfs",
 				start: || mount("procfs", "/proc", "procfs"),
 			},
+			Test {
+				name: "sysfs",
+				desc: "Mount sysfs",
+				start: || mount("sysfs", "/sys", "sysfs"),
+			},
+			Test {
+				name: "sysfs_block",
+				desc: "List storage devices and partitions in sysfs",
+				start: sysfs_block,
+			},
+			Test {
+				name: "debugfs",
+				desc: "Mount debugfs and read its files",
+				start: debugfs,
+			},
 			Test {
 				name: "tmpfs",
 				desc: "Mount tmpfs",
 				start: || mount("tmpfs", "/tmp", "tmpfs"),
 			},
+			Test {
+				name: "unknown",
+				desc: "Mount an unknown filesystem type",
+				start: unknown_type,
+			},
+			Test {
+				name: "bind",
+				desc: "Bind mount a directory",
+				start: bind,
+			},
+			Test {
+				name: "propagation",
+				desc: "Propagate mounts between shared mountpoints",
+				start: propagation,
+			},
+			Test {
+				name: "flags",
+				desc: "Enforce the read-only, nodev and noexec mount flags",
+				start: flags,
+			},
+			Test {
+				name: "remount",
+				desc: "Change the flags of a mountpoint",
+				start: remount,
+			},
+			Test {
+				name: "statfs",
+				desc: "Get the statistics of filesystems",
+				start: fs_stats,
+			},
+			Test {
+				name: "quota",
+				desc: "Enforce disk quotas",
+				start: quota::quota,
+			},
+			Test {
+				name: "loop",
+				desc: "Access a file through a loop device",
+				start: loop_dev::attach,
+			},
+			Test {
+				name: "ata_passthrough",
+				desc: "Query the identity of ATA drives",
+				start: ata::passthrough,
+			},
+			Test {
+				name: "squashfs",
+				desc: "Mount and read a squashfs image",
+				start: squashfs::read,
+			},
+			Test {
+				name: "fuse",
+				desc: "Serve a FUSE filesystem from userspace",
+				start: fuse::daemon,
+			},
 			// TODO other filesystem types
 		],
 	},
@@ -138,6 +286,51 @@ const TESTS: &[TestSuite] = &[
 	// TODO anonymous map (both shared and private)
 	fs_suite!("/"),
 	fs_suite!("/tmp"),
+	TestSuite {
+		name: "largefile",
+		desc: "Large files handling",
+		tests: &[Test {
+			name: "sparse",
+			desc: "Create, read and truncate a sparse 5 GiB file on ext2",
+			start: || filesystem::large_file(Path::new("/")),
+		}],
+	},
+	TestSuite {
+		name: "ext2",
+		desc: "ext2 filesystem features",
+		tests: &[
+			Test {
+				name: "htree",
+				desc: "Look up and modify entries of a hash-indexed directory",
+				start: filesystem::htree,
+			},
+			Test {
+				name: "orphans",
+				desc: "Check orphan inodes left on the disk have been processed at mount",
+				start: filesystem::orphans,
+			},
+			Test {
+				name: "journal",
+				desc: "Check the journal has been replayed at mount, then use it",
+				start: filesystem::journal,
+			},
+			Test {
+				name: "freeze",
+				desc: "Freeze and thaw the filesystem",
+				start: filesystem::freeze,
+			},
+			Test {
+				name: "bmap",
+				desc: "Map the blocks of a file to their location on the device",
+				start: filesystem::bmap,
+			},
+			Test {
+				name: "errors",
+				desc: "Read a corrupted directory with the `continue` errors behaviour",
+				start: filesystem::errors,
+			},
+		],
+	},
 	TestSuite {
 		name: "signal",
 		desc: "Test signals",
@@ -146,13 +339,210 @@ const TESTS: &[TestSuite] = &[
 				name: "handler",
 				desc: "Register and use a signal handler",
 				start: signal::handler,
-			}, /* TODO signal masking
-			    * TODO pause */
+			},
+			Test {
+				name: "core_dump",
+				desc: "Dump the core of a process killed by a signal",
+				start: signal::core_dump,
+			},
+			Test {
+				name: "suspend",
+				desc: "Wait for a signal with pause and sigsuspend",
+				start: signal::suspend,
+			},
+			Test {
+				name: "altstack",
+				desc: "Run signal handlers on an alternate stack",
+				start: signal::altstack,
+			},
+			Test {
+				name: "siginfo",
+				desc: "Pass the information of signals to SA_SIGINFO handlers",
+				start: signal::siginfo,
+			},
+			Test {
+				name: "queue",
+				desc: "Queue signals with a value and wait for them synchronously",
+				start: signal::queue,
+			},
+			Test {
+				name: "realtime",
+				desc: "Queue several instances of a realtime signal",
+				start: signal::realtime,
+			},
+			Test {
+				name: "waitid",
+				desc: "Wait for children to exit, stop and continue",
+				start: signal::waitid,
+			}, /* TODO signal masking */
+		],
+	},
+	TestSuite {
+		name: "poll",
+		desc: "Test events reported by poll and select",
+		tests: &[
+			Test {
+				name: "pipe",
+				desc: "Poll pipes, including closed ends",
+				start: poll::pipe,
+			},
+			Test {
+				name: "select",
+				desc: "Select on a pipe whose write end is closed",
+				start: poll::select,
+			},
+			Test {
+				name: "timeout",
+				desc: "Wait for a timeout and check the remaining time",
+				start: poll::timeout,
+			},
+			Test {
+				name: "sigmask",
+				desc: "Replace the signal mask while polling",
+				start: poll::sigmask,
+			},
+			Test {
+				name: "tty_hangup",
+				desc: "Poll a terminal before and after a hangup",
+				start: poll::tty_hangup,
+			},
+			Test {
+				name: "socket",
+				desc: "Poll a socket after shutting it down",
+				start: poll::socket,
+			},
+		],
+	},
+	TestSuite {
+		name: "socket",
+		desc: "Transfer data and control messages on Unix sockets",
+		tests: &[
+			Test {
+				name: "rights",
+				desc: "Pass file descriptors with SCM_RIGHTS",
+				start: socket::rights,
+			},
+			Test {
+				name: "credentials",
+				desc: "Receive the credentials of the sender with SCM_CREDENTIALS",
+				start: socket::credentials,
+			},
+			Test {
+				name: "datagram",
+				desc: "Peek and truncate datagrams",
+				start: socket::datagram,
+			},
+			Test {
+				name: "listen",
+				desc: "Accept connections on a listening socket",
+				start: socket::listen,
+			},
+			Test {
+				name: "options",
+				desc: "Read and write socket options, and get the name of the peer",
+				start: socket::options,
+			},
+			Test {
+				name: "netlink_route",
+				desc: "List and configure interfaces, addresses and routes with netlink",
+				start: netlink::route,
+			},
+			Test {
+				name: "udp_broadcast",
+				desc: "Broadcast UDP datagrams to a socket bound to the DHCP client port",
+				start: udp::broadcast,
+			},
+		],
+	},
+	TestSuite {
+		name: "spawn",
+		desc: "Test vfork semantics and posix_spawn",
+		tests: &[
+			Test {
+				name: "vfork_exit",
+				desc: "Suspend the parent until the child exits",
+				start: spawn::vfork_exit,
+			},
+			Test {
+				name: "vfork_exec_failure",
+				desc: "Keep the parent suspended after a failed execve",
+				start: spawn::vfork_exec_failure,
+			},
+			Test {
+				name: "vfork_signal",
+				desc: "Send a signal to the parent while it is suspended",
+				start: spawn::vfork_signal,
+			},
+			Test {
+				name: "posix_spawn",
+				desc: "Spawn programs with posix_spawn",
+				start: spawn::posix_spawn,
+			},
+			Test {
+				name: "auxv",
+				desc: "Check the auxiliary vector passed by execve",
+				start: spawn::auxv,
+			},
+			Test {
+				name: "interpreter",
+				desc: "Execute programs through an ELF interpreter",
+				start: spawn::interpreter,
+			},
+			Test {
+				name: "script",
+				desc: "Execute scripts through their interpreter line",
+				start: spawn::script,
+			},
+			Test {
+				name: "cloexec",
+				desc: "Close file descriptors on execution",
+				start: spawn::cloexec,
+			},
 		],
 	},
 	// TODO ELF files (execve)
 	// TODO user/group file accesses (including SUID/SGID)
-	// TODO time ((non-)monotonic clock, sleep and timer_*)
+	TestSuite {
+		name: "time",
+		desc: "Test clocks and sleeps",
+		tests: &[
+			Test {
+				name: "resolution",
+				desc: "Get the resolution of clocks",
+				start: time::resolution,
+			},
+			Test {
+				name: "clock_nanosleep",
+				desc: "Sleep for a relative and absolute time",
+				start: time::nanosleep,
+			},
+			Test {
+				name: "settime",
+				desc: "Get and set the real time clock",
+				start: time::settime,
+			},
+			Test {
+				name: "timer",
+				desc: "Arm, read and disarm a POSIX timer",
+				start: time::timer,
+			},
+			Test {
+				name: "sched_rr_get_interval",
+				desc: "Get the round-robin time slice",
+				start: time::rr_interval,
+			},
+			Test {
+				name: "times",
+				desc: "Account the CPU time of processes and their children",
+				start: time::cputime,
+			},
+			Test {
+				name: "itimer",
+				desc: "Deliver signals with alarm and interval timers",
+				start: time::itimer,
+			},
+		],
+	},
 	// TODO termcaps
 	// TODO SSE/MMX/AVX states consistency
 	TestSuite {
@@ -179,6 +569,46 @@ const TESTS: &[TestSuite] = &[
 				desc: "/proc/self/environ",
 				start: procfs::environ,
 			},
+			Test {
+				name: "prctl(PR_SET_MM)",
+				desc: "Move the command line with prctl(PR_SET_MM) and read /proc/self/cmdline",
+				start: procfs::set_cmdline,
+			},
+			Test {
+				name: "/proc/bus/pci/devices",
+				desc: "/proc/bus/pci/devices",
+				start: procfs::pci_devices,
+			},
+			Test {
+				name: "/proc/diskstats",
+				desc: "/proc/diskstats",
+				start: procfs::diskstats,
+			},
+			Test {
+				name: "/proc/config.gz",
+				desc: "/proc/config.gz",
+				start: procfs::config_gz,
+			},
+			Test {
+				name: "/proc/version",
+				desc: "/proc/version",
+				start: procfs::version,
+			},
+			Test {
+				name: "/proc/loadavg",
+				desc: "Compute the load average",
+				start: procfs::loadavg,
+			},
+			Test {
+				name: "/proc/sys/fs/dentry-state",
+				desc: "Cache failed lookups and bound the directory entries cache",
+				start: procfs::dcache,
+			},
+			Test {
+				name: "hidepid",
+				desc: "Mount procfs with hidepid=2",
+				start: procfs::hidepid,
+			},
 			// TODO /proc/self/stat
 		],
 	},
@@ -224,6 +654,16 @@ const TESTS: &[TestSuite] = &[
 				desc: "Unmount procfs",
 				start: || umount("/proc"),
 			},
+			Test {
+				name: "debugfs",
+				desc: "Unmount debugfs",
+				start: || umount("/sys/kernel/debug"),
+			},
+			Test {
+				name: "sysfs",
+				desc: "Unmount sysfs",
+				start: || umount("/sys"),
+			},
 			Test {
 				name: "tmpfs",
 				desc: "Unmount tmpfs",
@@ -35,7 +35,9 @@ use std::{
 pub fn dummy() -> TestResult {
 	log!("Load the module");
 	let file = File::open("/mod.kmod")?;
-	finit_module(file.as_raw_fd())?;
+	let res = finit_module(file.as_raw_fd(), c"answer=abc");
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(libc::EINVAL)));
+	finit_module(file.as_raw_fd(), c"answer=42")?;
 	drop(file);
 
 	log!("Check presence of the device file");
@@ -43,8 +45,30 @@ pub fn dummy() -> TestResult {
 	test_assert!(stat.file_type().is_char_device());
 	test_assert_eq!(stat.rdev(), libc::makedev(255, 255));
 
+	log!("Check the module is listed");
+	let modules = fs::read_to_string("/proc/modules")?;
+	let line = modules.lines().find(|line| line.starts_with("inttest "));
+	let fields: Vec<_> = line.unwrap_or_default().split(' ').collect();
+	test_assert_eq!(fields.len(), 6);
+	test_assert!(fields[1].parse::<u64>().is_ok());
+	test_assert_eq!(&fields[2..5], ["0", "-", "Live"]);
+
+	log!("Check the module's parameters");
+	let params = "/sys/module/inttest/parameters";
+	let mut names = fs::read_dir(params)?
+		.map(|ent| Ok(ent?.file_name()))
+		.collect::<io::Result<Vec<_>>>()?;
+	names.sort();
+	test_assert_eq!(names, ["answer", "verbose"]);
+	test_assert_eq!(fs::read_to_string(format!("{params}/answer"))?, "42\n");
+	test_assert_eq!(fs::read_to_string(format!("{params}/verbose"))?, "N\n");
+
 	log!("Unload the module");
 	delete_module(c"inttest")?;
+	let modules = fs::read_to_string("/proc/modules")?;
+	test_assert!(!modules.lines().any(|line| line.starts_with("inttest ")));
+	let res = fs::metadata("/sys/module/inttest");
+	test_assert!(matches!(res, Err(e) if e.kind() == io::ErrorKind::NotFound));
 
 	log!("Check the device file is gone");
 	let res = fs::metadata("/dev/test");
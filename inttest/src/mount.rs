@@ -18,8 +18,18 @@
 
 //! Filesystem mounting tests.
 
-use crate::{log, util, util::TestResult};
-use std::{ffi::CString, fs, ptr::null};
+use crate::{
+	log, test_assert, test_assert_eq, util,
+	util::{TestError, TestResult},
+};
+use std::{
+	env,
+	ffi::{CStr, CString},
+	fs, io, mem,
+	path::Path,
+	process::Command,
+	ptr::null,
+};
 
 pub fn mount(src: &str, target: &str, fstype: &str) -> TestResult {
 	log!("Create directory");
@@ -38,8 +48,402 @@ pub fn mount(src: &str, target: &str, fstype: &str) -> TestResult {
 	Ok(())
 }
 
+/// Checks mounting an unknown filesystem type fails once the module request is done.
+pub fn unknown_type() -> TestResult {
+	let modprobe = fs::read_to_string("/proc/sys/kernel/modprobe")?;
+	test_assert!(modprobe.starts_with('/'));
+	let res = util::mount(c"none", c"/tmp", c"maestro-unknown-fs", 0, null());
+	test_assert_eq!(res.map_err(|e| e.raw_os_error()), Err(Some(libc::ENODEV)));
+	Ok(())
+}
+
+/// Mounts the debugfs and checks the files registered by the kernel.
+pub fn debugfs() -> TestResult {
+	mount("debugfs", "/sys/kernel/debug", "debugfs")?;
+	log!("List root");
+	let names = fs::read_dir("/sys/kernel/debug")?
+		.map(|ent| Ok(ent?.file_name().into_string().unwrap()))
+		.collect::<io::Result<Vec<_>>>()?;
+	for name in ["buddy", "cache", "sched"] {
+		test_assert!(names.iter().any(|n| n == name));
+	}
+	log!("Read files");
+	let run_queues = fs::read_to_string("/sys/kernel/debug/sched/run_queues")?;
+	test_assert!(run_queues.starts_with("cpu0 current "));
+	let free_lists = fs::read_to_string("/sys/kernel/debug/buddy/free_lists")?;
+	test_assert!(free_lists.lines().any(|l| l.starts_with("kernel")));
+	let stats = fs::read_to_string("/sys/kernel/debug/cache/stats")?;
+	test_assert!(stats.starts_with("hits "));
+	log!("Write read-only file");
+	let res = fs::write("/sys/kernel/debug/cache/stats", "0");
+	test_assert!(res.is_err());
+	Ok(())
+}
+
+/// Reads the attribute `name` of the block device whose sysfs directory is `dir`.
+fn block_attr(dir: &Path, name: &str) -> Result<String, TestError> {
+	let content = fs::read_to_string(dir.join(name))?;
+	let Some(content) = content.strip_suffix('\n') else {
+		return Err(TestError(format!("missing newline in {name}")));
+	};
+	Ok(content.to_owned())
+}
+
+/// Checks the `dev` attribute of the block device whose sysfs directory is `dir` against its
+/// device file.
+fn check_block_dev(dir: &Path) -> TestResult {
+	let name = dir.file_name().unwrap();
+	let rdev = util::stat(Path::new("/dev").join(name))?.st_rdev;
+	let dev = block_attr(dir, "dev")?;
+	test_assert_eq!(dev, format!("{}:{}", libc::major(rdev), libc::minor(rdev)));
+	Ok(())
+}
+
+/// Checks the storage devices and partitions listed in `/sys/block`.
+pub fn sysfs_block() -> TestResult {
+	log!("List devices");
+	let disks = fs::read_dir("/sys/block")?
+		.map(|ent| Ok(ent?.path()))
+		.collect::<io::Result<Vec<_>>>()?;
+	test_assert!(!disks.is_empty());
+	for disk in disks {
+		log!("Check {}", disk.display());
+		check_block_dev(&disk)?;
+		let size: u64 = block_attr(&disk, "size")?.parse()?;
+		test_assert!(matches!(block_attr(&disk, "ro")?.as_str(), "0" | "1"));
+		// Partitions are subdirectories
+		for ent in fs::read_dir(&disk)? {
+			let ent = ent?;
+			if !ent.file_type()?.is_dir() {
+				continue;
+			}
+			let part = ent.path();
+			check_block_dev(&part)?;
+			let number: u32 = block_attr(&part, "partition")?.parse()?;
+			test_assert!(number > 0);
+			let start: u64 = block_attr(&part, "start")?.parse()?;
+			let part_size: u64 = block_attr(&part, "size")?.parse()?;
+			test_assert!(start + part_size <= size);
+		}
+	}
+	test_assert!(fs::metadata("/sys/block/nonexistent").is_err());
+	Ok(())
+}
+
 pub fn umount(target: &str) -> TestResult {
 	let target = CString::new(target)?;
 	util::umount(target.as_c_str())?;
 	Ok(())
 }
+
+/// Checks the error number of the result of a `mount` call.
+fn check_errno(res: io::Result<()>, errno: i32) -> TestResult {
+	test_assert_eq!(res.map_err(|e| e.raw_os_error()), Err(Some(errno)));
+	Ok(())
+}
+
+/// Bind mounts a directory, with and without the mountpoints beneath it.
+pub fn bind() -> TestResult {
+	log!("Setup");
+	fs::create_dir_all("/mnt/bind/src/sub")?;
+	fs::create_dir_all("/mnt/bind/dst")?;
+	fs::write("/mnt/bind/src/file", "hello")?;
+	mount("tmpfs", "/mnt/bind/src/sub", "tmpfs")?;
+	fs::write("/mnt/bind/src/sub/inner", "inner")?;
+
+	log!("Bind");
+	util::mount(
+		c"/mnt/bind/src",
+		c"/mnt/bind/dst",
+		c"none",
+		libc::MS_BIND,
+		null(),
+	)?;
+	test_assert_eq!(fs::read_to_string("/mnt/bind/dst/file")?, "hello");
+	fs::write("/mnt/bind/dst/new", "new")?;
+	test_assert_eq!(fs::read_to_string("/mnt/bind/src/new")?, "new");
+	// Mountpoints beneath the source are not bound
+	test_assert!(!Path::new("/mnt/bind/dst/sub/inner").exists());
+	umount("/mnt/bind/dst")?;
+	test_assert!(!Path::new("/mnt/bind/dst/file").exists());
+
+	log!("Recursive bind");
+	util::mount(
+		c"/mnt/bind/src",
+		c"/mnt/bind/dst",
+		c"none",
+		libc::MS_BIND | libc::MS_REC,
+		null(),
+	)?;
+	test_assert_eq!(fs::read_to_string("/mnt/bind/dst/sub/inner")?, "inner");
+	umount("/mnt/bind/dst/sub")?;
+	umount("/mnt/bind/dst")?;
+
+	log!("Bind file on directory");
+	let res = util::mount(
+		c"/mnt/bind/src/file",
+		c"/mnt/bind/dst",
+		c"none",
+		libc::MS_BIND,
+		null(),
+	);
+	check_errno(res, libc::ENOTDIR)?;
+
+	log!("Cleanup");
+	umount("/mnt/bind/src/sub")?;
+	fs::remove_dir_all("/mnt/bind")?;
+	Ok(())
+}
+
+/// Checks mounts are propagated between shared mountpoints, and not to private ones.
+pub fn propagation() -> TestResult {
+	log!("Setup");
+	fs::create_dir_all("/mnt/prop/a")?;
+	fs::create_dir_all("/mnt/prop/b")?;
+	mount("tmpfs", "/mnt/prop/a", "tmpfs")?;
+	fs::create_dir("/mnt/prop/a/sub")?;
+
+	log!("Invalid propagation flags");
+	let res = util::mount(
+		c"none",
+		c"/mnt/prop/a",
+		c"none",
+		libc::MS_SHARED | libc::MS_PRIVATE,
+		null(),
+	);
+	check_errno(res, libc::EINVAL)?;
+	let res = util::mount(
+		c"none",
+		c"/mnt/prop/a/sub",
+		c"none",
+		libc::MS_SHARED,
+		null(),
+	);
+	check_errno(res, libc::EINVAL)?;
+
+	log!("Shared");
+	util::mount(c"none", c"/mnt/prop/a", c"none", libc::MS_SHARED, null())?;
+	util::mount(
+		c"/mnt/prop/a",
+		c"/mnt/prop/b",
+		c"none",
+		libc::MS_BIND,
+		null(),
+	)?;
+	mount("tmpfs", "/mnt/prop/a/sub", "tmpfs")?;
+	fs::write("/mnt/prop/a/sub/file", "shared")?;
+	test_assert_eq!(fs::read_to_string("/mnt/prop/b/sub/file")?, "shared");
+	umount("/mnt/prop/a/sub")?;
+	test_assert!(!Path::new("/mnt/prop/b/sub/file").exists());
+
+	log!("Private");
+	util::mount(c"none", c"/mnt/prop/b", c"none", libc::MS_PRIVATE, null())?;
+	mount("tmpfs", "/mnt/prop/a/sub", "tmpfs")?;
+	fs::write("/mnt/prop/a/sub/file", "private")?;
+	test_assert!(!Path::new("/mnt/prop/b/sub/file").exists());
+
+	log!("Unbindable");
+	util::mount(
+		c"none",
+		c"/mnt/prop/a",
+		c"none",
+		libc::MS_UNBINDABLE,
+		null(),
+	)?;
+	let res = util::mount(
+		c"/mnt/prop/a",
+		c"/mnt/prop/a/sub",
+		c"none",
+		libc::MS_BIND,
+		null(),
+	);
+	check_errno(res, libc::EINVAL)?;
+
+	log!("Cleanup");
+	umount("/mnt/prop/a/sub")?;
+	umount("/mnt/prop/b")?;
+	umount("/mnt/prop/a")?;
+	fs::remove_dir_all("/mnt/prop")?;
+	Ok(())
+}
+
+/// Checks the `MS_RDONLY`, `MS_NODEV` and `MS_NOEXEC` mount flags are enforced.
+pub fn flags() -> TestResult {
+	fs::create_dir_all("/mnt/flags")?;
+
+	log!("Read-only");
+	util::mount(c"tmpfs", c"/mnt/flags", c"tmpfs", libc::MS_RDONLY, null())?;
+	check_errno(fs::write("/mnt/flags/file", "a"), libc::EROFS)?;
+	check_errno(fs::create_dir("/mnt/flags/dir"), libc::EROFS)?;
+	check_errno(util::chmod("/mnt/flags", 0o700), libc::EROFS)?;
+	umount("/mnt/flags")?;
+
+	log!("No device");
+	util::mount(c"tmpfs", c"/mnt/flags", c"tmpfs", libc::MS_NODEV, null())?;
+	util::mknodat(
+		libc::AT_FDCWD,
+		c"/mnt/flags/null",
+		libc::S_IFCHR | 0o666,
+		libc::makedev(1, 3),
+	)?;
+	check_errno(fs::File::open("/mnt/flags/null").map(drop), libc::EACCES)?;
+	umount("/mnt/flags")?;
+
+	log!("No exec");
+	util::mount(c"tmpfs", c"/mnt/flags", c"tmpfs", libc::MS_NOEXEC, null())?;
+	fs::copy(env::current_exe()?, "/mnt/flags/exe")?;
+	util::chmod("/mnt/flags/exe", 0o755)?;
+	let res = Command::new("/mnt/flags/exe").status().map(drop);
+	check_errno(res, libc::EACCES)?;
+	umount("/mnt/flags")?;
+
+	log!("Cleanup");
+	fs::remove_dir("/mnt/flags")?;
+	Ok(())
+}
+
+/// Returns the options of the mountpoint at `target`, as listed in `/proc/self/mounts`.
+fn mount_options(target: &str) -> Result<String, TestError> {
+	fs::read_to_string("/proc/self/mounts")?
+		.lines()
+		.find_map(|line| {
+			let mut fields = line.split(' ').skip(1);
+			(fields.next() == Some(target)).then(|| fields.nth(1).unwrap_or_default().to_owned())
+		})
+		.ok_or_else(|| TestError(format!("{target} is not mounted")))
+}
+
+/// Changes the flags of a mountpoint with `MS_REMOUNT` and `mount_setattr`.
+pub fn remount() -> TestResult {
+	log!("Setup");
+	mount("tmpfs", "/mnt/remount", "tmpfs")?;
+	fs::create_dir("/mnt/remount/sub")?;
+	fs::create_dir("/mnt/remount/dir")?;
+	mount("tmpfs", "/mnt/remount/sub", "tmpfs")?;
+	fs::write("/mnt/remount/file", "a")?;
+	test_assert!(mount_options("/mnt/remount")?.starts_with("rw"));
+
+	log!("Remount read-only");
+	util::mount(
+		c"none",
+		c"/mnt/remount",
+		c"none",
+		libc::MS_REMOUNT | libc::MS_RDONLY,
+		null(),
+	)?;
+	check_errno(fs::write("/mnt/remount/file", "b"), libc::EROFS)?;
+	test_assert_eq!(fs::read_to_string("/mnt/remount/file")?, "a");
+	test_assert!(mount_options("/mnt/remount")?.starts_with("ro"));
+	// Mountpoints beneath are not affected
+	fs::write("/mnt/remount/sub/file", "a")?;
+
+	log!("Remount read-write");
+	util::mount(c"none", c"/mnt/remount", c"none", libc::MS_REMOUNT, null())?;
+	fs::write("/mnt/remount/file", "b")?;
+	test_assert!(mount_options("/mnt/remount")?.starts_with("rw"));
+
+	log!("Remount read-only with a file open for writing");
+	let file = fs::OpenOptions::new()
+		.write(true)
+		.open("/mnt/remount/file")?;
+	let res = util::mount(
+		c"none",
+		c"/mnt/remount",
+		c"none",
+		libc::MS_REMOUNT | libc::MS_RDONLY,
+		null(),
+	);
+	check_errno(res, libc::EBUSY)?;
+	let mut attr: libc::mount_attr = unsafe { mem::zeroed() };
+	attr.attr_set = libc::MOUNT_ATTR_RDONLY;
+	check_errno(util::mount_setattr(c"/mnt/remount", 0, &attr), libc::EBUSY)?;
+	test_assert!(mount_options("/mnt/remount")?.starts_with("rw"));
+	drop(file);
+
+	log!("Not a mountpoint");
+	let res = util::mount(
+		c"none",
+		c"/mnt/remount/dir",
+		c"none",
+		libc::MS_REMOUNT,
+		null(),
+	);
+	check_errno(res, libc::EINVAL)?;
+
+	log!("mount_setattr");
+	let mut attr: libc::mount_attr = unsafe { mem::zeroed() };
+	attr.attr_set = libc::MOUNT_ATTR_RDONLY | libc::MOUNT_ATTR_NOEXEC;
+	util::mount_setattr(c"/mnt/remount", libc::AT_RECURSIVE as _, &attr)?;
+	check_errno(fs::write("/mnt/remount/file", "c"), libc::EROFS)?;
+	check_errno(fs::write("/mnt/remount/sub/file", "c"), libc::EROFS)?;
+	test_assert_eq!(mount_options("/mnt/remount/sub")?, "ro,noexec");
+	attr.attr_set = 0;
+	attr.attr_clr = libc::MOUNT_ATTR_RDONLY;
+	util::mount_setattr(c"/mnt/remount/sub", 0, &attr)?;
+	fs::write("/mnt/remount/sub/file", "c")?;
+	check_errno(fs::write("/mnt/remount/file", "c"), libc::EROFS)?;
+	test_assert_eq!(mount_options("/mnt/remount/sub")?, "rw,noexec");
+	attr.attr_clr = libc::MOUNT_ATTR_NOATIME;
+	let res = util::mount_setattr(c"/mnt/remount", 0, &attr);
+	check_errno(res, libc::EINVAL)?;
+
+	log!("Cleanup");
+	umount("/mnt/remount/sub")?;
+	umount("/mnt/remount")?;
+	fs::remove_dir("/mnt/remount")?;
+	Ok(())
+}
+
+/// Returns the statistics of the filesystem at `path`.
+fn statfs(path: &CStr) -> io::Result<libc::statfs> {
+	let mut stat: libc::statfs = unsafe { mem::zeroed() };
+	let res = unsafe { libc::statfs(path.as_ptr(), &mut stat) };
+	if res >= 0 {
+		Ok(stat)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Checks the statistics returned by `statfs` and `statvfs`.
+pub fn fs_stats() -> TestResult {
+	log!("Setup");
+	fs::create_dir_all("/mnt/statfs")?;
+	util::mount(
+		c"tmpfs",
+		c"/mnt/statfs",
+		c"tmpfs",
+		libc::MS_NOSUID | libc::MS_NODEV,
+		c"size=1M".as_ptr() as _,
+	)?;
+
+	log!("tmpfs");
+	let stat = statfs(c"/mnt/statfs")?;
+	test_assert_eq!(stat.f_type, 0x01021994);
+	test_assert_eq!(stat.f_bsize, 4096);
+	test_assert_eq!(stat.f_blocks, 256);
+	test_assert_eq!(stat.f_bfree, 256);
+	let files = stat.f_files - stat.f_ffree;
+	fs::write("/mnt/statfs/file", [0u8; 8192])?;
+	let stat = statfs(c"/mnt/statfs")?;
+	test_assert_eq!(stat.f_bfree, 254);
+	test_assert_eq!(stat.f_files - stat.f_ffree, files + 1);
+
+	log!("Mount flags");
+	let mut stat: libc::statvfs = unsafe { mem::zeroed() };
+	let res = unsafe { libc::statvfs(c"/mnt/statfs".as_ptr(), &mut stat) };
+	test_assert_eq!(res, 0);
+	test_assert!(stat.f_flag & libc::ST_NOSUID != 0);
+	test_assert!(stat.f_flag & libc::ST_NODEV != 0);
+	test_assert!(stat.f_flag & (libc::ST_RDONLY | libc::ST_NOEXEC) == 0);
+
+	log!("procfs");
+	let stat = statfs(c"/proc")?;
+	test_assert_eq!(stat.f_type, 0x9fa0);
+	test_assert_eq!(stat.f_namelen, 255);
+
+	log!("Cleanup");
+	umount("/mnt/statfs")?;
+	fs::remove_dir("/mnt/statfs")?;
+	Ok(())
+}
@@ -0,0 +1,296 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Tests of the netlink interface used to configure network interfaces and routes.
+
+use crate::{log, test_assert, test_assert_eq, util::TestResult};
+use libc::{
+	AF_INET, AF_NETLINK, EADDRNOTAVAIL, IFA_LOCAL, IFF_LOOPBACK, IFLA_IFNAME, NETLINK_ROUTE,
+	NLM_F_ACK, NLM_F_DUMP, NLM_F_REQUEST, NLMSG_DONE, NLMSG_ERROR, RT_SCOPE_UNIVERSE,
+	RT_TABLE_MAIN, RTA_GATEWAY, RTM_DELADDR, RTM_DELROUTE, RTM_GETADDR, RTM_GETLINK, RTM_GETROUTE,
+	RTM_NEWADDR, RTM_NEWLINK, RTM_NEWROUTE, RTN_UNICAST, RTPROT_BOOT, SOCK_RAW, c_int, nlmsghdr,
+	sockaddr_nl, socklen_t,
+};
+use std::{
+	io, mem,
+	os::fd::{AsRawFd, FromRawFd, OwnedFd},
+	ptr, slice,
+};
+
+/// Route attribute: index of the output interface.
+const RTA_OIF: u16 = 4;
+
+/// Description of a network interface.
+#[repr(C)]
+struct IfInfoMsg {
+	ifi_family: u8,
+	ifi_pad: u8,
+	ifi_type: u16,
+	ifi_index: i32,
+	ifi_flags: u32,
+	ifi_change: u32,
+}
+
+/// Description of an address.
+#[repr(C)]
+struct IfAddrMsg {
+	ifa_family: u8,
+	ifa_prefixlen: u8,
+	ifa_flags: u8,
+	ifa_scope: u8,
+	ifa_index: u32,
+}
+
+/// Description of a route.
+#[repr(C)]
+struct RtMsg {
+	rtm_family: u8,
+	rtm_dst_len: u8,
+	rtm_src_len: u8,
+	rtm_tos: u8,
+	rtm_table: u8,
+	rtm_protocol: u8,
+	rtm_scope: u8,
+	rtm_type: u8,
+	rtm_flags: u32,
+}
+
+/// Aligns `len` to the alignment of netlink messages and attributes.
+fn align(len: usize) -> usize {
+	len.next_multiple_of(4)
+}
+
+/// Returns the bytes of `val`.
+fn bytes<T>(val: &T) -> &[u8] {
+	unsafe { slice::from_raw_parts(val as *const T as *const u8, mem::size_of::<T>()) }
+}
+
+/// Reads a value of type `T` at the beginning of `buf`.
+fn read<T>(buf: &[u8]) -> T {
+	assert!(buf.len() >= mem::size_of::<T>());
+	unsafe { ptr::read_unaligned(buf.as_ptr() as *const T) }
+}
+
+/// Returns the payload of the attribute of type `ty` in `attrs`.
+fn find_attr(mut attrs: &[u8], ty: u16) -> Option<&[u8]> {
+	while attrs.len() >= 4 {
+		let len = u16::from_ne_bytes([attrs[0], attrs[1]]) as usize;
+		let attr_ty = u16::from_ne_bytes([attrs[2], attrs[3]]);
+		if len < 4 || len > attrs.len() {
+			break;
+		}
+		if attr_ty == ty {
+			return Some(&attrs[4..len]);
+		}
+		attrs = &attrs[align(len).min(attrs.len())..];
+	}
+	None
+}
+
+/// A netlink socket.
+struct Netlink {
+	/// The socket.
+	sock: OwnedFd,
+	/// The sequence number of the next request.
+	seq: u32,
+}
+
+impl Netlink {
+	/// Opens and binds a `NETLINK_ROUTE` socket, returning it along with its port ID.
+	fn open() -> io::Result<(Self, u32)> {
+		let res = unsafe { libc::socket(AF_NETLINK, SOCK_RAW, NETLINK_ROUTE) };
+		if res < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let sock = unsafe { OwnedFd::from_raw_fd(res) };
+		let mut addr: sockaddr_nl = unsafe { mem::zeroed() };
+		addr.nl_family = AF_NETLINK as _;
+		let mut len = mem::size_of_val(&addr) as socklen_t;
+		unsafe {
+			if libc::bind(sock.as_raw_fd(), &addr as *const _ as _, len) < 0
+				|| libc::getsockname(sock.as_raw_fd(), &mut addr as *mut _ as _, &mut len) < 0
+			{
+				return Err(io::Error::last_os_error());
+			}
+		}
+		Ok((
+			Self {
+				sock,
+				seq: 1,
+			},
+			addr.nl_pid,
+		))
+	}
+
+	/// Sends a request of type `ty` with flags `flags`, with the payload `payload` followed by
+	/// the attributes `attrs`.
+	///
+	/// The function returns the replies, as type and payload, until the end of a dump or an
+	/// error message.
+	fn request<T>(
+		&mut self,
+		ty: u16,
+		flags: c_int,
+		payload: &T,
+		attrs: &[(u16, &[u8])],
+	) -> io::Result<Vec<(u16, Vec<u8>)>> {
+		let mut msg = vec![0u8; mem::size_of::<nlmsghdr>()];
+		msg.extend_from_slice(bytes(payload));
+		msg.resize(align(msg.len()), 0);
+		for (attr_ty, data) in attrs {
+			msg.extend_from_slice(&((4 + data.len()) as u16).to_ne_bytes());
+			msg.extend_from_slice(&attr_ty.to_ne_bytes());
+			msg.extend_from_slice(data);
+			msg.resize(align(msg.len()), 0);
+		}
+		let hdr = nlmsghdr {
+			nlmsg_len: msg.len() as _,
+			nlmsg_type: ty,
+			nlmsg_flags: (NLM_F_REQUEST | flags) as _,
+			nlmsg_seq: self.seq,
+			nlmsg_pid: 0,
+		};
+		self.seq += 1;
+		msg[..mem::size_of::<nlmsghdr>()].copy_from_slice(bytes(&hdr));
+		let res = unsafe { libc::send(self.sock.as_raw_fd(), msg.as_ptr() as _, msg.len(), 0) };
+		if res < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let mut replies = vec![];
+		let mut buf = vec![0u8; 8192];
+		loop {
+			let len =
+				unsafe { libc::recv(self.sock.as_raw_fd(), buf.as_mut_ptr() as _, buf.len(), 0) };
+			if len < 0 {
+				return Err(io::Error::last_os_error());
+			}
+			let mut data = &buf[..len as usize];
+			while data.len() >= mem::size_of::<nlmsghdr>() {
+				let reply: nlmsghdr = read(data);
+				let len = reply.nlmsg_len as usize;
+				assert_eq!(reply.nlmsg_seq, hdr.nlmsg_seq);
+				let payload = data[mem::size_of::<nlmsghdr>()..len].to_vec();
+				replies.push((reply.nlmsg_type, payload));
+				if reply.nlmsg_type as c_int == NLMSG_DONE
+					|| reply.nlmsg_type as c_int == NLMSG_ERROR
+				{
+					return Ok(replies);
+				}
+				data = &data[align(len).min(data.len())..];
+			}
+		}
+	}
+
+	/// Sends a request with an acknowledgement, and returns the error code of the reply.
+	fn request_ack<T>(
+		&mut self,
+		ty: u16,
+		payload: &T,
+		attrs: &[(u16, &[u8])],
+	) -> io::Result<c_int> {
+		let replies = self.request(ty, NLM_F_ACK, payload, attrs)?;
+		let (reply_ty, payload) = replies.last().unwrap();
+		assert_eq!(*reply_ty as c_int, NLMSG_ERROR);
+		Ok(read(payload))
+	}
+}
+
+pub fn route() -> TestResult {
+	log!("Open socket");
+	let (mut nl, port) = Netlink::open()?;
+	test_assert_eq!(port, unsafe { libc::getpid() } as u32);
+
+	log!("List interfaces");
+	let info: IfInfoMsg = unsafe { mem::zeroed() };
+	let replies = nl.request(RTM_GETLINK, NLM_F_DUMP, &info, &[])?;
+	test_assert_eq!(replies.last().unwrap().0 as c_int, NLMSG_DONE);
+	let lo = replies.iter().find(|(ty, payload)| {
+		let attrs = &payload[mem::size_of::<IfInfoMsg>()..];
+		*ty == RTM_NEWLINK && find_attr(attrs, IFLA_IFNAME) == Some(b"lo\0")
+	});
+	test_assert!(lo.is_some());
+	let lo: IfInfoMsg = read(&lo.unwrap().1);
+	test_assert!(lo.ifi_flags & IFF_LOOPBACK as u32 != 0);
+	let index = lo.ifi_index as u32;
+
+	log!("Add an address");
+	let addr = IfAddrMsg {
+		ifa_family: AF_INET as _,
+		ifa_prefixlen: 24,
+		ifa_flags: 0,
+		ifa_scope: 0,
+		ifa_index: index,
+	};
+	let local = [10, 1, 2, 3];
+	test_assert_eq!(
+		nl.request_ack(RTM_NEWADDR, &addr, &[(IFA_LOCAL, &local)])?,
+		0
+	);
+
+	log!("List addresses");
+	let replies = nl.request(RTM_GETADDR, NLM_F_DUMP, &addr, &[])?;
+	let addrs: Vec<_> = replies
+		.iter()
+		.filter(|(ty, _)| *ty == RTM_NEWADDR)
+		.filter_map(|(_, payload)| {
+			let attrs = &payload[mem::size_of::<IfAddrMsg>()..];
+			find_attr(attrs, IFA_LOCAL).map(<[u8]>::to_vec)
+		})
+		.collect();
+	test_assert!(addrs.iter().any(|a| a == &[127, 0, 0, 1]));
+	test_assert!(addrs.iter().any(|a| a == &local));
+
+	log!("Add a route through a gateway");
+	let route = RtMsg {
+		rtm_family: AF_INET as _,
+		rtm_dst_len: 0,
+		rtm_src_len: 0,
+		rtm_tos: 0,
+		rtm_table: RT_TABLE_MAIN,
+		rtm_protocol: RTPROT_BOOT,
+		rtm_scope: RT_SCOPE_UNIVERSE,
+		rtm_type: RTN_UNICAST,
+		rtm_flags: 0,
+	};
+	let gateway = [10, 1, 2, 254];
+	let attrs: &[(u16, &[u8])] = &[(RTA_GATEWAY, &gateway)];
+	test_assert_eq!(nl.request_ack(RTM_NEWROUTE, &route, attrs)?, 0);
+
+	log!("List routes");
+	let replies = nl.request(RTM_GETROUTE, NLM_F_DUMP, &route, &[])?;
+	let found = replies.iter().any(|(ty, payload)| {
+		let attrs = &payload[mem::size_of::<RtMsg>()..];
+		*ty == RTM_NEWROUTE
+			&& find_attr(attrs, RTA_GATEWAY) == Some(&gateway)
+			&& find_attr(attrs, RTA_OIF) == Some(&index.to_ne_bytes())
+	});
+	test_assert!(found);
+
+	log!("Remove the route and the address");
+	test_assert_eq!(nl.request_ack(RTM_DELROUTE, &route, attrs)?, 0);
+	test_assert_eq!(
+		nl.request_ack(RTM_DELADDR, &addr, &[(IFA_LOCAL, &local)])?,
+		0
+	);
+	test_assert_eq!(
+		nl.request_ack(RTM_DELADDR, &addr, &[(IFA_LOCAL, &local)])?,
+		-EADDRNOTAVAIL
+	);
+
+	Ok(())
+}
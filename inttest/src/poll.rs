@@ -0,0 +1,295 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Tests of the events reported by `poll` and `select`, including exceptional conditions.
+
+use crate::{log, test_assert, test_assert_eq, util, util::TestResult};
+use libc::{
+	AF_UNIX, CLOCK_MONOTONIC, EINTR, EINVAL, EIO, FD_ISSET, FD_SET, FD_ZERO, O_NOCTTY, O_NONBLOCK,
+	POLLERR, POLLHUP, POLLIN, POLLNVAL, POLLOUT, POLLPRI, POLLRDHUP, SHUT_RD, SHUT_RDWR,
+	SIG_BLOCK, SIG_DFL, SIG_SETMASK, SIGUSR1, SOCK_STREAM, TIOCVHANGUP, fd_set, sigset_t,
+	timespec, timeval,
+};
+use std::{
+	ffi::{c_int, c_void},
+	fs::{File, OpenOptions},
+	io,
+	io::{Read, Write},
+	mem,
+	os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+	ptr::null_mut,
+	sync::atomic::{
+		AtomicBool,
+		Ordering::{Acquire, Release},
+	},
+};
+
+pub fn pipe() -> TestResult {
+	log!("Empty pipe");
+	let (rd, wr) = util::pipe()?;
+	test_assert_eq!(util::poll(rd.as_raw_fd(), POLLIN | POLLPRI)?, 0);
+	test_assert_eq!(util::poll(wr.as_raw_fd(), POLLOUT)?, POLLOUT);
+
+	log!("Readable pipe");
+	let mut wr = File::from(wr);
+	wr.write_all(b"a")?;
+	test_assert_eq!(util::poll(rd.as_raw_fd(), POLLIN | POLLPRI)?, POLLIN);
+
+	log!("Close write end");
+	drop(wr);
+	test_assert_eq!(util::poll(rd.as_raw_fd(), POLLIN)?, POLLIN | POLLHUP);
+	let mut rd = File::from(rd);
+	let mut buf = [0; 1];
+	test_assert_eq!(rd.read(&mut buf)?, 1);
+	test_assert_eq!(util::poll(rd.as_raw_fd(), POLLIN)?, POLLHUP);
+	// Hangup is reported even if not requested
+	test_assert_eq!(util::poll(rd.as_raw_fd(), 0)?, POLLHUP);
+
+	log!("Close read end");
+	let (rd, wr) = util::pipe()?;
+	drop(rd);
+	test_assert_eq!(util::poll(wr.as_raw_fd(), POLLOUT)?, POLLOUT | POLLERR);
+	// Errors are reported even if not requested
+	test_assert_eq!(util::poll(wr.as_raw_fd(), 0)?, POLLERR);
+
+	log!("Invalid file descriptor");
+	let fd = wr.as_raw_fd();
+	drop(wr);
+	test_assert_eq!(util::poll(fd, POLLIN)?, POLLNVAL);
+
+	Ok(())
+}
+
+pub fn select() -> TestResult {
+	log!("Close write end");
+	let (rd, wr) = util::pipe()?;
+	drop(wr);
+	let fd = rd.as_raw_fd();
+	let (ready, readable, except) = unsafe {
+		let mut readfds: fd_set = mem::zeroed();
+		let mut exceptfds: fd_set = mem::zeroed();
+		FD_ZERO(&mut readfds);
+		FD_ZERO(&mut exceptfds);
+		FD_SET(fd, &mut readfds);
+		FD_SET(fd, &mut exceptfds);
+		let mut timeout = timeval {
+			tv_sec: 0,
+			tv_usec: 0,
+		};
+		let ready = libc::select(
+			fd + 1,
+			&mut readfds,
+			null_mut(),
+			&mut exceptfds,
+			&mut timeout,
+		);
+		(ready, FD_ISSET(fd, &readfds), FD_ISSET(fd, &exceptfds))
+	};
+	if ready < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	// Hangup makes the file descriptor readable, but is not an exceptional condition
+	test_assert_eq!(ready, 1);
+	test_assert!(readable);
+	test_assert!(!except);
+
+	Ok(())
+}
+
+pub fn timeout() -> TestResult {
+	log!("Sleep with select");
+	let start = util::clock_gettime(CLOCK_MONOTONIC)?;
+	let mut timeout = timeval {
+		tv_sec: 0,
+		tv_usec: 10_000,
+	};
+	let ready = unsafe { libc::select(0, null_mut(), null_mut(), null_mut(), &mut timeout) };
+	if ready < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	let end = util::clock_gettime(CLOCK_MONOTONIC)?;
+	let elapsed = (end.tv_sec - start.tv_sec) * 1_000_000_000 + (end.tv_nsec - start.tv_nsec);
+	test_assert_eq!(ready, 0);
+	test_assert!(elapsed >= 10_000_000);
+	// The remaining time is written back
+	test_assert_eq!((timeout.tv_sec, timeout.tv_usec), (0, 0));
+
+	log!("Invalid timeout");
+	let (rd, _wr) = util::pipe()?;
+	let mut fds = [libc::pollfd {
+		fd: rd.as_raw_fd(),
+		events: POLLIN,
+		revents: 0,
+	}];
+	let timeout = timespec {
+		tv_sec: 0,
+		tv_nsec: 1_000_000_000,
+	};
+	let res = unsafe { libc::ppoll(fds.as_mut_ptr(), 1, &timeout, null_mut()) };
+	test_assert_eq!(res, -1);
+	test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(EINVAL));
+
+	Ok(())
+}
+
+static HIT: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn signal_handler(_: c_int) {
+	HIT.store(true, Release);
+}
+
+/// Returns a signal set containing only `sig`, or no signal if `None`.
+fn sigset(sig: Option<c_int>) -> sigset_t {
+	unsafe {
+		let mut set: sigset_t = mem::zeroed();
+		libc::sigemptyset(&mut set);
+		if let Some(sig) = sig {
+			libc::sigaddset(&mut set, sig);
+		}
+		set
+	}
+}
+
+pub fn sigmask() -> TestResult {
+	util::signal(SIGUSR1, signal_handler as *const () as usize)?;
+	let blocked = sigset(Some(SIGUSR1));
+	let prev = util::sigprocmask(SIG_BLOCK, Some(&blocked))?;
+	unsafe {
+		util::kill(libc::getpid(), SIGUSR1)?;
+	}
+	test_assert!(!HIT.load(Acquire));
+
+	log!("Keep the signal blocked");
+	let timeout = timespec {
+		tv_sec: 0,
+		tv_nsec: 0,
+	};
+	let res = unsafe { libc::ppoll(null_mut(), 0, &timeout, &blocked) };
+	test_assert_eq!(res, 0);
+	test_assert!(!HIT.load(Acquire));
+
+	log!("Unblock the signal while polling");
+	let timeout = timespec {
+		tv_sec: 1,
+		tv_nsec: 0,
+	};
+	let empty = sigset(None);
+	let res = unsafe { libc::ppoll(null_mut(), 0, &timeout, &empty) };
+	test_assert_eq!(res, -1);
+	test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(EINTR));
+	test_assert!(HIT.load(Acquire));
+	// The previous mask is restored after the handler
+	let mask = util::sigprocmask(SIG_BLOCK, None)?;
+	test_assert!(unsafe { libc::sigismember(&mask, SIGUSR1) } == 1);
+	HIT.store(false, Release);
+
+	log!("Unblock the signal while selecting");
+	unsafe {
+		util::kill(libc::getpid(), SIGUSR1)?;
+	}
+	let res = unsafe { libc::pselect(0, null_mut(), null_mut(), null_mut(), &timeout, &empty) };
+	test_assert_eq!(res, -1);
+	test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(EINTR));
+	test_assert!(HIT.load(Acquire));
+	let mask = util::sigprocmask(SIG_BLOCK, None)?;
+	test_assert!(unsafe { libc::sigismember(&mask, SIGUSR1) } == 1);
+
+	log!("Invalid mask size");
+	let res = unsafe {
+		libc::syscall(
+			libc::SYS_ppoll,
+			null_mut::<c_void>(),
+			0,
+			&timeout,
+			&empty,
+			1,
+		)
+	};
+	test_assert_eq!(res, -1);
+	test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(EINVAL));
+
+	log!("Cleanup");
+	HIT.store(false, Release);
+	util::sigprocmask(SIG_SETMASK, Some(&prev))?;
+	util::signal(SIGUSR1, SIG_DFL)?;
+
+	Ok(())
+}
+
+pub fn tty_hangup() -> TestResult {
+	let open = || {
+		OpenOptions::new()
+			.read(true)
+			.write(true)
+			.custom_flags(O_NOCTTY | O_NONBLOCK)
+			.open("/dev/tty2")
+	};
+
+	log!("Open terminal");
+	let mut tty = open()?;
+	test_assert_eq!(util::poll(tty.as_raw_fd(), POLLIN | POLLOUT)?, POLLOUT);
+
+	log!("Hang up");
+	let res = unsafe { libc::ioctl(tty.as_raw_fd(), TIOCVHANGUP) };
+	if res < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	test_assert_eq!(
+		util::poll(tty.as_raw_fd(), POLLIN | POLLOUT)?,
+		POLLIN | POLLOUT | POLLERR | POLLHUP
+	);
+	let mut buf = [0; 1];
+	test_assert_eq!(tty.read(&mut buf)?, 0);
+	let err = tty.write(b"a").unwrap_err();
+	test_assert_eq!(err.raw_os_error(), Some(EIO));
+
+	log!("Reopen terminal");
+	let tty = open()?;
+	test_assert_eq!(util::poll(tty.as_raw_fd(), POLLIN | POLLOUT)?, POLLOUT);
+
+	Ok(())
+}
+
+pub fn socket() -> TestResult {
+	log!("Create socket pair");
+	let (sock, _peer) = util::socketpair(AF_UNIX, SOCK_STREAM)?;
+	let fd = sock.as_raw_fd();
+	test_assert_eq!(util::poll(fd, POLLIN | POLLOUT | POLLRDHUP)?, POLLOUT);
+
+	log!("Shutdown reception");
+	if unsafe { libc::shutdown(fd, SHUT_RD) } < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	test_assert_eq!(
+		util::poll(fd, POLLIN | POLLOUT | POLLRDHUP)?,
+		POLLIN | POLLOUT | POLLRDHUP
+	);
+
+	log!("Shutdown both sides");
+	if unsafe { libc::shutdown(fd, SHUT_RDWR) } < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	test_assert_eq!(
+		util::poll(fd, POLLIN | POLLOUT | POLLRDHUP)?,
+		POLLIN | POLLOUT | POLLRDHUP | POLLHUP
+	);
+
+	// TODO test out-of-band data and connection reset (need a TCP stack)
+
+	Ok(())
+}
@@ -19,10 +19,14 @@
 //! procfs filesystem testing.
 
 use crate::{
-	test_assert_eq,
-	util::{TestError, TestResult},
+	log, test_assert, test_assert_eq, util,
+	util::{TestError, TestResult, unprivileged},
+};
+use libc::{PR_SET_MM, PR_SET_MM_ARG_END, PR_SET_MM_ARG_START};
+use std::{
+	collections::HashMap, env, env::current_dir, ffi::c_ulong, fs, hint, io, mem,
+	os::unix::ffi::OsStrExt, process, thread, time::Duration,
 };
-use std::{collections::HashMap, env, env::current_dir, fs, os::unix::ffi::OsStrExt};
 
 pub fn cwd() -> TestResult {
 	let cwd = fs::read_link("/proc/self/cwd")?;
@@ -45,6 +49,37 @@ pub fn cmdline() -> TestResult {
 	Ok(())
 }
 
+pub fn set_cmdline() -> TestResult {
+	log!("Move the command line");
+	// Use a child process since the previous location of the command line cannot be restored
+	let child = util::fork()?;
+	if child == 0 {
+		let cmdline = b"renamed\0arg\0";
+		let start = cmdline.as_ptr() as c_ulong;
+		let res = (|| -> TestResult {
+			util::prctl(PR_SET_MM, PR_SET_MM_ARG_START as _, start, 0, 0)?;
+			util::prctl(
+				PR_SET_MM,
+				PR_SET_MM_ARG_END as _,
+				start + cmdline.len() as c_ulong,
+				0,
+				0,
+			)?;
+			test_assert_eq!(fs::read("/proc/self/cmdline")?, cmdline);
+			// The end cannot be before the start
+			let res = util::prctl(PR_SET_MM, PR_SET_MM_ARG_END as _, start - 1, 0, 0);
+			test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(libc::EINVAL)));
+			Ok(())
+		})();
+		unsafe {
+			libc::_exit(res.is_err() as _);
+		}
+	}
+	let status = util::waitpid(child)?;
+	test_assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+	Ok(())
+}
+
 pub fn environ() -> TestResult {
 	let environ = fs::read("/proc/self/environ")?;
 	let args0 = environ
@@ -69,3 +104,175 @@ pub fn environ() -> TestResult {
 	test_assert_eq!(args0, args1);
 	Ok(())
 }
+
+pub fn pci_devices() -> TestResult {
+	let devices = fs::read_to_string("/proc/bus/pci/devices")?;
+	// There is at least a host bridge
+	test_assert!(!devices.is_empty());
+	for line in devices.lines() {
+		let fields: Vec<_> = line.split('\t').map(str::trim).collect();
+		test_assert!((17..=18).contains(&fields.len()));
+		for field in &fields[..17] {
+			test_assert!(u64::from_str_radix(field, 16).is_ok());
+		}
+	}
+	Ok(())
+}
+
+pub fn diskstats() -> TestResult {
+	let stats = fs::read_to_string("/proc/diskstats")?;
+	for line in stats.lines() {
+		let fields: Vec<_> = line.split_whitespace().collect();
+		test_assert_eq!(fields.len(), 14);
+		test_assert!(!fields[2].is_empty());
+		for (i, field) in fields.iter().enumerate() {
+			if i != 2 {
+				test_assert!(field.parse::<u64>().is_ok());
+			}
+		}
+	}
+	Ok(())
+}
+
+pub fn config_gz() -> TestResult {
+	let config = fs::read("/proc/config.gz")?;
+	// Check the gzip header
+	test_assert!(config.starts_with(&[0x1f, 0x8b, 0x08]));
+	test_assert_eq!(fs::metadata("/proc/config.gz")?.len(), config.len() as u64);
+	Ok(())
+}
+
+pub fn version() -> TestResult {
+	let version = fs::read_to_string("/proc/version")?;
+	let release = fs::read_to_string("/proc/sys/kernel/osrelease")?;
+	let build = fs::read_to_string("/proc/sys/kernel/version")?;
+	test_assert!(version.contains(release.trim()));
+	test_assert!(version.trim_end().ends_with(build.trim()));
+	Ok(())
+}
+
+/// Returns the fields of `/proc/sys/fs/dentry-state`.
+fn dentry_state() -> Result<Vec<usize>, TestError> {
+	let state = fs::read_to_string("/proc/sys/fs/dentry-state")?;
+	let fields = state
+		.split_whitespace()
+		.map(str::parse)
+		.collect::<Result<Vec<usize>, _>>()?;
+	test_assert_eq!(fields.len(), 6);
+	Ok(fields)
+}
+
+/// Checks failed lookups are cached, and the size of the directory entries cache is bounded.
+pub fn dcache() -> TestResult {
+	const MAX: &str = "/proc/sys/fs/dentry-max";
+
+	log!("Negative entries");
+	let before = dentry_state()?;
+	for i in 0..16 {
+		test_assert!(!fs::exists(format!("/dcache_missing{i}"))?);
+	}
+	let after = dentry_state()?;
+	test_assert!(after[4] >= before[4] + 16);
+	test_assert!(after[0] >= after[4]);
+
+	log!("Bounded cache");
+	let res = fs::write(MAX, "1");
+	test_assert!(matches!(res, Err(e) if e.kind() == io::ErrorKind::InvalidInput));
+	let old = fs::read_to_string(MAX)?;
+	fs::write(MAX, "64")?;
+	let res = (|| {
+		for i in 0..256 {
+			test_assert!(!fs::exists(format!("/dcache_missing{i}"))?);
+		}
+		// Negative entries are never in use, so they are evicted first
+		test_assert!(dentry_state()?[4] <= 64);
+		Ok(())
+	})();
+	fs::write(MAX, old.trim())?;
+	res
+}
+
+/// Checks the load average accounts for a busy process.
+pub fn loadavg() -> TestResult {
+	// Spawn a process that never sleeps
+	let child = util::fork()?;
+	if child == 0 {
+		loop {
+			hint::spin_loop();
+		}
+	}
+	log!("Wait for the load average to be sampled");
+	thread::sleep(Duration::from_secs(6));
+	let res = (|| {
+		log!("Read /proc/loadavg");
+		let content = fs::read_to_string("/proc/loadavg")?;
+		let fields: Vec<&str> = content.split_whitespace().collect();
+		test_assert_eq!(fields.len(), 5);
+		let loads = fields[..3]
+			.iter()
+			.map(|load| load.parse::<f32>())
+			.collect::<Result<Vec<_>, _>>()?;
+		test_assert!(loads[0] > 0.0);
+		test_assert!(fields[3].contains('/'));
+
+		log!("Get the load average with sysinfo");
+		let mut info: libc::sysinfo = unsafe { mem::zeroed() };
+		let res = unsafe { libc::sysinfo(&mut info) };
+		test_assert_eq!(res, 0);
+		test_assert!(info.loads[0] > 0);
+		Ok(())
+	})();
+	util::kill(child, libc::SIGKILL)?;
+	util::waitpid(child)?;
+	res
+}
+
+/// Checks the processes of other users are hidden with `hidepid=2`, and their private files
+/// cannot be read.
+pub fn hidepid() -> TestResult {
+	// Spawn a process owned by root
+	let child = util::fork()?;
+	if child == 0 {
+		loop {
+			unsafe {
+				libc::pause();
+			}
+		}
+	}
+	fs::create_dir_all("/tmp/proc")?;
+	util::mount(
+		c"procfs",
+		c"/tmp/proc",
+		c"procfs",
+		0,
+		c"hidepid=2".as_ptr() as _,
+	)?;
+	let res = (|| {
+		let own = format!("/tmp/proc/{}", process::id());
+		let other = format!("/tmp/proc/{child}");
+		log!("Privileged access");
+		fs::metadata(&other)?;
+		unprivileged(|| {
+			log!("Own directory");
+			fs::metadata(&own)?;
+			fs::read(format!("{own}/environ"))?;
+			log!("Hidden directory");
+			let res = fs::metadata(&other);
+			test_assert!(matches!(res, Err(e) if e.kind() == io::ErrorKind::NotFound));
+			let listed = fs::read_dir("/tmp/proc")?
+				.filter_map(Result::ok)
+				.any(|ent| ent.file_name().as_bytes() == child.to_string().as_bytes());
+			test_assert!(!listed);
+			log!("Private files");
+			let res = fs::read(format!("/proc/{child}/environ"));
+			test_assert!(matches!(res, Err(e) if e.kind() == io::ErrorKind::PermissionDenied));
+			let res = fs::read(format!("/proc/{child}/maps"));
+			test_assert!(matches!(res, Err(e) if e.kind() == io::ErrorKind::PermissionDenied));
+			Ok(())
+		})?
+	})();
+	util::umount(c"/tmp/proc")?;
+	util::kill(child, libc::SIGKILL)?;
+	util::waitpid(child)?;
+	res
+}
@@ -0,0 +1,159 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Disk quotas tests.
+
+use crate::{
+	log, test_assert, test_assert_eq,
+	util::{TestError, TestResult, unprivileged},
+};
+use libc::{
+	EDQUOT, EPERM, ESRCH, Q_GETFMT, Q_GETQUOTA, Q_QUOTAOFF, Q_QUOTAON, Q_SETQUOTA, Q_SYNC, QCMD,
+	QFMT_VFS_V1, QIF_ALL, QIF_LIMITS, c_char, c_int, dqblk,
+};
+use std::{
+	ffi::{CStr, CString},
+	fs,
+	fs::File,
+	io,
+	io::Write,
+	mem,
+	os::unix::{
+		ffi::OsStrExt,
+		fs::{FileTypeExt, MetadataExt, PermissionsExt},
+	},
+	path::PathBuf,
+	ptr::null_mut,
+};
+
+const USRQUOTA: c_int = 0;
+
+/// The path of the quota file.
+const QUOTA_FILE: &CStr = c"/aquota.user";
+/// The path of the directory in which files are created.
+const DIR: &str = "/quota";
+
+fn quotactl(cmd: c_int, special: &CStr, id: c_int, addr: *mut c_char) -> io::Result<()> {
+	let res = unsafe { libc::quotactl(QCMD(cmd, USRQUOTA), special.as_ptr(), id, addr) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+fn get_quota(dev: &CStr, id: c_int) -> io::Result<dqblk> {
+	let mut dq: dqblk = unsafe { mem::zeroed() };
+	quotactl(Q_GETQUOTA, dev, id, &mut dq as *mut _ as _)?;
+	Ok(dq)
+}
+
+fn errno<T>(res: io::Result<T>) -> Option<i32> {
+	res.err().and_then(|e| e.raw_os_error())
+}
+
+/// Returns the path to the block device holding the root filesystem.
+fn root_device() -> Result<CString, TestError> {
+	let dev = fs::metadata("/")?.dev();
+	for ent in fs::read_dir("/dev")? {
+		let ent = ent?;
+		let metadata = ent.metadata()?;
+		if metadata.file_type().is_block_device() && metadata.rdev() == dev {
+			return Ok(CString::new(ent.path().as_os_str().as_bytes())?);
+		}
+	}
+	Err(TestError("cannot find the root device".to_owned()))
+}
+
+/// Enables user quotas on the root filesystem and checks they are enforced.
+pub fn quota() -> TestResult {
+	log!("Create quota file");
+	let dev = root_device()?;
+	// Empty `vfsv1` file: the header, then the root of the tree
+	let mut data = vec![0u8; 2048];
+	data[0..4].copy_from_slice(&0xd9c01f11u32.to_le_bytes());
+	data[4..8].copy_from_slice(&1u32.to_le_bytes());
+	data[20..24].copy_from_slice(&2u32.to_le_bytes());
+	fs::write(QUOTA_FILE.to_str().unwrap(), data)?;
+	fs::create_dir_all(DIR)?;
+	fs::set_permissions(DIR, fs::Permissions::from_mode(0o777))?;
+
+	log!("Enable quotas");
+	quotactl(Q_QUOTAON, &dev, QFMT_VFS_V1, QUOTA_FILE.as_ptr() as _)?;
+	test_assert!(quotactl(Q_QUOTAON, &dev, QFMT_VFS_V1, QUOTA_FILE.as_ptr() as _).is_err());
+	let mut fmt: u32 = 0;
+	quotactl(Q_GETFMT, &dev, 0, &mut fmt as *mut _ as _)?;
+	test_assert_eq!(fmt, QFMT_VFS_V1 as u32);
+
+	log!("Set limits");
+	let mut dq: dqblk = unsafe { mem::zeroed() };
+	dq.dqb_bhardlimit = 16;
+	dq.dqb_ihardlimit = 2;
+	dq.dqb_valid = QIF_LIMITS;
+	quotactl(Q_SETQUOTA, &dev, 1000, &mut dq as *mut _ as _)?;
+	let dq = get_quota(&dev, 1000)?;
+	test_assert_eq!(dq.dqb_valid, QIF_ALL);
+	test_assert_eq!(dq.dqb_bhardlimit, 16);
+	test_assert_eq!(dq.dqb_ihardlimit, 2);
+	test_assert_eq!(dq.dqb_curinodes, 0);
+
+	let paths: Vec<PathBuf> = (0..3).map(|i| format!("{DIR}/{i}").into()).collect();
+	unprivileged(|| {
+		log!("Inodes limit");
+		File::create(&paths[0])?;
+		File::create(&paths[1])?;
+		test_assert_eq!(errno(File::create(&paths[2])), Some(EDQUOT));
+
+		log!("Space limit");
+		let mut file = File::options().write(true).open(&paths[0])?;
+		test_assert_eq!(errno(file.write_all(&[0; 32 * 1024])), Some(EDQUOT));
+
+		log!("Get own quotas");
+		let dq = get_quota(&dev, 1000)?;
+		test_assert_eq!(dq.dqb_curinodes, 2);
+		test_assert!(dq.dqb_curspace > 0 && dq.dqb_curspace <= 16 * 1024);
+		test_assert_eq!(errno(get_quota(&dev, 0)), Some(EPERM));
+		let mut dq: dqblk = unsafe { mem::zeroed() };
+		test_assert_eq!(
+			errno(quotactl(Q_SETQUOTA, &dev, 1000, &mut dq as *mut _ as _)),
+			Some(EPERM)
+		);
+		Ok(())
+	})??;
+
+	log!("Release usage");
+	fs::remove_file(&paths[0])?;
+	fs::remove_file(&paths[1])?;
+	let dq = get_quota(&dev, 1000)?;
+	test_assert_eq!(dq.dqb_curinodes, 0);
+	test_assert_eq!(dq.dqb_curspace, 0);
+
+	log!("Sync and disable quotas");
+	quotactl(Q_SYNC, &dev, 0, null_mut())?;
+	test_assert!(fs::metadata(QUOTA_FILE.to_str().unwrap())?.len() > 2048);
+	quotactl(Q_QUOTAOFF, &dev, 0, null_mut())?;
+	test_assert_eq!(
+		errno(quotactl(Q_GETFMT, &dev, 0, &mut fmt as *mut _ as _)),
+		Some(ESRCH)
+	);
+
+	log!("Cleanup");
+	fs::remove_dir(DIR)?;
+	fs::remove_file(QUOTA_FILE.to_str().unwrap())?;
+	Ok(())
+}
@@ -19,14 +19,24 @@
 //! Signals testing.
 
 use crate::{
-	log,
+	log, test_assert, test_assert_eq, util,
 	util::{TestResult, kill, signal},
 };
-use libc::{SIG_DFL, SIGINT, getpid};
+use libc::{
+	CLD_CONTINUED, CLD_EXITED, CLD_KILLED, CLD_STOPPED, EAGAIN, ECHILD, EINTR, EINVAL, ENOMEM,
+	EPERM, MINSIGSTKSZ, P_ALL, P_PID, RLIM_INFINITY, SA_ONSTACK, SA_SIGINFO, SI_QUEUE, SI_USER,
+	SIG_BLOCK, SIG_DFL, SIG_SETMASK, SIGABRT, SIGALRM, SIGCHLD, SIGCONT, SIGINT, SIGKILL, SIGSEGV,
+	SIGSTOP, SIGUSR1, SS_DISABLE, SS_ONSTACK, WCONTINUED, WCOREDUMP, WEXITED, WEXITSTATUS,
+	WIFEXITED, WIFSIGNALED, WNOHANG, WNOWAIT, WSTOPPED, WTERMSIG, getpid, rlimit, siginfo_t,
+	sigset_t, stack_t, timespec,
+};
 use std::{
-	ffi::c_int,
+	ffi::{c_int, c_void},
+	fs, hint, io, mem,
+	path::Path,
+	ptr::null_mut,
 	sync::atomic::{
-		AtomicBool,
+		AtomicBool, AtomicI32, AtomicUsize,
 		Ordering::{Acquire, Release},
 	},
 };
@@ -54,3 +64,497 @@ pub fn handler() -> TestResult {
 
 	Ok(())
 }
+
+/// Checks a process killed by `SIGABRT` dumps its core to its working directory, within the limit
+/// set by `RLIMIT_CORE`.
+pub fn core_dump() -> TestResult {
+	let dir = Path::new("/tmp/coredump");
+	let core_path = dir.join("core");
+	fs::create_dir_all(dir)?;
+	for limit in [0, 4096, RLIM_INFINITY] {
+		log!("Abort with a core size limit of {limit}");
+		let _ = fs::remove_file(&core_path);
+		let pid = util::fork()?;
+		if pid == 0 {
+			unsafe {
+				let lim = rlimit {
+					rlim_cur: limit,
+					rlim_max: RLIM_INFINITY,
+				};
+				libc::setrlimit(libc::RLIMIT_CORE, &lim);
+				libc::chdir(c"/tmp/coredump".as_ptr());
+				libc::abort();
+			}
+		}
+		let status = util::waitpid(pid)?;
+		test_assert!(WIFSIGNALED(status));
+		test_assert_eq!(WTERMSIG(status), SIGABRT);
+		test_assert_eq!(WCOREDUMP(status), limit != 0);
+		if limit == 0 {
+			test_assert!(!core_path.exists());
+			continue;
+		}
+		log!("Check the core file");
+		let core = fs::read(&core_path)?;
+		test_assert!(core.starts_with(b"\x7fELF"));
+		// ET_CORE
+		test_assert_eq!(u16::from_le_bytes([core[16], core[17]]), 4);
+		if limit == RLIM_INFINITY {
+			test_assert!(core.len() > 4096);
+		} else {
+			test_assert_eq!(core.len() as u64, limit);
+		}
+	}
+	log!("Abort while not dumpable");
+	let _ = fs::remove_file(&core_path);
+	test_assert_eq!(unsafe { libc::prctl(libc::PR_GET_DUMPABLE) }, 1);
+	let pid = util::fork()?;
+	if pid == 0 {
+		unsafe {
+			let lim = rlimit {
+				rlim_cur: RLIM_INFINITY,
+				rlim_max: RLIM_INFINITY,
+			};
+			libc::setrlimit(libc::RLIMIT_CORE, &lim);
+			libc::chdir(c"/tmp/coredump".as_ptr());
+			libc::prctl(libc::PR_SET_DUMPABLE, 0);
+			libc::abort();
+		}
+	}
+	let status = util::waitpid(pid)?;
+	test_assert!(WIFSIGNALED(status));
+	test_assert!(!WCOREDUMP(status));
+	test_assert!(!core_path.exists());
+	fs::remove_dir_all(dir)?;
+	Ok(())
+}
+
+/// Checks `pause` and `sigsuspend` sleep until a signal is handled.
+pub fn suspend() -> TestResult {
+	log!("Pause until an alarm");
+	signal(SIGALRM, signal_handler as *const () as usize)?;
+	unsafe {
+		libc::alarm(1);
+	}
+	let res = unsafe { libc::pause() };
+	test_assert_eq!(res, -1);
+	test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(EINTR));
+	test_assert!(HIT.load(Acquire));
+	HIT.store(false, Release);
+	signal(SIGALRM, SIG_DFL)?;
+
+	log!("Suspend with a signal already pending");
+	signal(SIGUSR1, signal_handler as *const () as usize)?;
+	let (blocked, empty) = unsafe {
+		let mut blocked: sigset_t = mem::zeroed();
+		libc::sigemptyset(&mut blocked);
+		libc::sigaddset(&mut blocked, SIGUSR1);
+		let mut empty: sigset_t = mem::zeroed();
+		libc::sigemptyset(&mut empty);
+		(blocked, empty)
+	};
+	let prev = util::sigprocmask(SIG_BLOCK, Some(&blocked))?;
+	unsafe {
+		kill(getpid(), SIGUSR1)?;
+	}
+	test_assert!(!HIT.load(Acquire));
+	let res = unsafe { libc::sigsuspend(&empty) };
+	test_assert_eq!(res, -1);
+	test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(EINTR));
+	test_assert!(HIT.load(Acquire));
+	// The previous mask is restored after the handler
+	let mask = util::sigprocmask(SIG_BLOCK, None)?;
+	test_assert!(unsafe { libc::sigismember(&mask, SIGUSR1) } == 1);
+	HIT.store(false, Release);
+
+	log!("Suspend until another process sends a signal");
+	let pid = util::fork()?;
+	if pid == 0 {
+		unsafe {
+			libc::usleep(100_000);
+			libc::kill(libc::getppid(), SIGUSR1);
+			libc::_exit(0);
+		}
+	}
+	let res = unsafe { libc::sigsuspend(&empty) };
+	test_assert_eq!(res, -1);
+	test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(EINTR));
+	test_assert!(HIT.load(Acquire));
+	util::waitpid(pid)?;
+
+	log!("Invalid mask size");
+	let res = unsafe { libc::syscall(libc::SYS_rt_sigsuspend, &empty, 1) };
+	test_assert_eq!(res, -1);
+	test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(EINVAL));
+
+	log!("Cleanup");
+	HIT.store(false, Release);
+	util::sigprocmask(SIG_SETMASK, Some(&prev))?;
+	signal(SIGUSR1, SIG_DFL)?;
+
+	Ok(())
+}
+
+/// The size of the alternate signal stacks used in tests.
+const ALTSTACK_SIZE: usize = 16384;
+
+static HANDLER_SP: AtomicUsize = AtomicUsize::new(0);
+static HANDLER_SS_FLAGS: AtomicI32 = AtomicI32::new(0);
+static HANDLER_SS_ERRNO: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn altstack_handler(_: c_int) {
+	let local = 0u8;
+	HANDLER_SP.store(hint::black_box(&local) as *const _ as usize, Release);
+	if let Ok(ss) = util::sigaltstack(None) {
+		HANDLER_SS_FLAGS.store(ss.ss_flags, Release);
+	}
+	// Changing the stack while running on it is forbidden
+	let ss = stack_t {
+		ss_sp: null_mut(),
+		ss_flags: SS_DISABLE,
+		ss_size: 0,
+	};
+	let errno = util::sigaltstack(Some(&ss))
+		.err()
+		.and_then(|e| e.raw_os_error())
+		.unwrap_or(0);
+	HANDLER_SS_ERRNO.store(errno, Release);
+}
+
+extern "C" fn overflow_handler(_: c_int) {
+	unsafe {
+		libc::_exit(0);
+	}
+}
+
+fn recurse(depth: usize) -> usize {
+	let buf = hint::black_box([depth as u8; 1024]);
+	if depth == usize::MAX {
+		return 0;
+	}
+	recurse(depth + 1) + buf[depth % buf.len()] as usize
+}
+
+/// Checks handlers registered with `SA_ONSTACK` run on the alternate signal stack.
+pub fn altstack() -> TestResult {
+	let mut stack = vec![0u8; ALTSTACK_SIZE];
+	let start = stack.as_mut_ptr() as usize;
+	let ss = stack_t {
+		ss_sp: stack.as_mut_ptr() as _,
+		ss_flags: 0,
+		ss_size: ALTSTACK_SIZE,
+	};
+
+	log!("Invalid alternate stacks");
+	let small = stack_t {
+		ss_size: MINSIGSTKSZ - 1,
+		..ss
+	};
+	let res = util::sigaltstack(Some(&small));
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(ENOMEM)));
+	let invalid = stack_t {
+		ss_flags: 0x100,
+		..ss
+	};
+	let res = util::sigaltstack(Some(&invalid));
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	log!("Set the alternate stack");
+	let prev = util::sigaltstack(Some(&ss))?;
+	let curr = util::sigaltstack(None)?;
+	test_assert_eq!(curr.ss_sp, ss.ss_sp);
+	test_assert_eq!(curr.ss_size, ALTSTACK_SIZE);
+	test_assert_eq!(curr.ss_flags, 0);
+
+	log!("Run a handler on the alternate stack");
+	util::sigaction(SIGUSR1, altstack_handler as *const () as usize, SA_ONSTACK)?;
+	unsafe {
+		kill(getpid(), SIGUSR1)?;
+	}
+	let sp = HANDLER_SP.load(Acquire);
+	test_assert!((start..start + ALTSTACK_SIZE).contains(&sp));
+	test_assert_eq!(HANDLER_SS_FLAGS.load(Acquire), SS_ONSTACK);
+	test_assert_eq!(HANDLER_SS_ERRNO.load(Acquire), EPERM);
+	// The stack is left unchanged and is not in use anymore
+	let curr = util::sigaltstack(None)?;
+	test_assert_eq!(curr.ss_sp, ss.ss_sp);
+	test_assert_eq!(curr.ss_flags, 0);
+
+	log!("Run a handler without SA_ONSTACK");
+	util::sigaction(SIGUSR1, altstack_handler as *const () as usize, 0)?;
+	unsafe {
+		kill(getpid(), SIGUSR1)?;
+	}
+	let sp = HANDLER_SP.load(Acquire);
+	test_assert!(!(start..start + ALTSTACK_SIZE).contains(&sp));
+	test_assert_eq!(HANDLER_SS_FLAGS.load(Acquire), 0);
+	test_assert_eq!(HANDLER_SS_ERRNO.load(Acquire), 0);
+	util::sigaltstack(Some(&ss))?;
+
+	log!("Handle a stack overflow");
+	let pid = util::fork()?;
+	if pid == 0 {
+		unsafe {
+			if util::sigaction(SIGSEGV, overflow_handler as *const () as usize, SA_ONSTACK)
+				.is_err()
+			{
+				libc::_exit(1);
+			}
+			recurse(0);
+			libc::_exit(1);
+		}
+	}
+	let status = util::waitpid(pid)?;
+	test_assert!(WIFEXITED(status));
+	test_assert_eq!(WEXITSTATUS(status), 0);
+
+	log!("Cleanup");
+	util::sigaction(SIGUSR1, SIG_DFL, 0)?;
+	util::sigaltstack(Some(&prev))?;
+
+	Ok(())
+}
+
+static INFO_CODE: AtomicI32 = AtomicI32::new(0);
+static INFO_PID: AtomicI32 = AtomicI32::new(0);
+static INFO_VALUE: AtomicUsize = AtomicUsize::new(0);
+static INFO_CTX: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn siginfo_handler(sig: c_int, info: *mut siginfo_t, ctx: *mut c_void) {
+	let info = unsafe { &*info };
+	if info.si_signo != sig {
+		return;
+	}
+	INFO_CODE.store(info.si_code, Release);
+	INFO_PID.store(unsafe { info.si_pid() }, Release);
+	INFO_VALUE.store(unsafe { info.si_value().sival_ptr } as _, Release);
+	INFO_CTX.store(!ctx.is_null(), Release);
+}
+
+/// Checks a handler registered with `SA_SIGINFO` receives the information of the signal.
+pub fn siginfo() -> TestResult {
+	log!("Register signal handler");
+	util::sigaction(SIGUSR1, siginfo_handler as *const () as usize, SA_SIGINFO)?;
+
+	log!("Queue a signal with a value");
+	let pid = unsafe { getpid() };
+	util::sigqueue(pid, SIGUSR1, 42)?;
+	test_assert_eq!(INFO_CODE.load(Acquire), SI_QUEUE);
+	test_assert_eq!(INFO_PID.load(Acquire), pid);
+	test_assert_eq!(INFO_VALUE.load(Acquire), 42);
+	test_assert!(INFO_CTX.load(Acquire));
+
+	log!("Kill self");
+	kill(pid, SIGUSR1)?;
+	test_assert_eq!(INFO_CODE.load(Acquire), SI_USER);
+	test_assert_eq!(INFO_PID.load(Acquire), pid);
+
+	log!("Cleanup");
+	util::sigaction(SIGUSR1, SIG_DFL, 0)?;
+
+	Ok(())
+}
+
+/// Checks signals sent with `sigqueue` carry their value, and synchronous waiting with
+/// `sigtimedwait`.
+pub fn queue() -> TestResult {
+	let blocked = unsafe {
+		let mut blocked: sigset_t = mem::zeroed();
+		libc::sigemptyset(&mut blocked);
+		libc::sigaddset(&mut blocked, SIGUSR1);
+		blocked
+	};
+	let prev = util::sigprocmask(SIG_BLOCK, Some(&blocked))?;
+	let pid = unsafe { getpid() };
+	let uid = unsafe { libc::getuid() };
+	let no_wait = timespec {
+		tv_sec: 0,
+		tv_nsec: 0,
+	};
+
+	log!("Queue a signal with a value");
+	util::sigqueue(pid, SIGUSR1, 42)?;
+	let pending = util::sigpending()?;
+	test_assert!(unsafe { libc::sigismember(&pending, SIGUSR1) } == 1);
+	let info = util::sigtimedwait(&blocked, &no_wait)?;
+	test_assert_eq!(info.si_signo, SIGUSR1);
+	test_assert_eq!(info.si_code, SI_QUEUE);
+	unsafe {
+		test_assert_eq!(info.si_pid(), pid);
+		test_assert_eq!(info.si_uid(), uid);
+		test_assert_eq!(info.si_value().sival_ptr as usize, 42);
+	}
+	let pending = util::sigpending()?;
+	test_assert!(unsafe { libc::sigismember(&pending, SIGUSR1) } == 0);
+
+	log!("Wait for a signal sent by kill");
+	kill(pid, SIGUSR1)?;
+	let info = util::sigtimedwait(&blocked, &no_wait)?;
+	test_assert_eq!(info.si_signo, SIGUSR1);
+	test_assert_eq!(info.si_code, SI_USER);
+	test_assert_eq!(unsafe { info.si_pid() }, pid);
+
+	log!("Wait for a signal sent by another process");
+	let child = util::fork()?;
+	if child == 0 {
+		unsafe {
+			libc::usleep(100_000);
+			libc::kill(libc::getppid(), SIGUSR1);
+			libc::_exit(0);
+		}
+	}
+	let timeout = timespec {
+		tv_sec: 5,
+		tv_nsec: 0,
+	};
+	let info = util::sigtimedwait(&blocked, &timeout)?;
+	test_assert_eq!(info.si_signo, SIGUSR1);
+	test_assert_eq!(unsafe { info.si_pid() }, child);
+	util::waitpid(child)?;
+
+	log!("Time out");
+	let timeout = timespec {
+		tv_sec: 0,
+		tv_nsec: 10_000_000,
+	};
+	let res = util::sigtimedwait(&blocked, &timeout);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EAGAIN)));
+
+	log!("Impersonate kill");
+	let child = util::fork()?;
+	if child == 0 {
+		unsafe {
+			libc::pause();
+			libc::_exit(0);
+		}
+	}
+	let res = unsafe {
+		let mut info: siginfo_t = mem::zeroed();
+		info.si_signo = SIGUSR1;
+		info.si_code = SI_USER;
+		libc::syscall(libc::SYS_rt_sigqueueinfo, child, SIGUSR1, &info)
+	};
+	test_assert_eq!(res, -1);
+	test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(EPERM));
+	kill(child, SIGKILL)?;
+	util::waitpid(child)?;
+
+	log!("Cleanup");
+	util::sigprocmask(SIG_SETMASK, Some(&prev))?;
+
+	Ok(())
+}
+
+/// Checks instances of a realtime signal are queued in order, within `RLIMIT_SIGPENDING`.
+pub fn realtime() -> TestResult {
+	let sig = libc::SIGRTMIN();
+	let blocked = unsafe {
+		let mut blocked: sigset_t = mem::zeroed();
+		libc::sigemptyset(&mut blocked);
+		libc::sigaddset(&mut blocked, sig);
+		blocked
+	};
+	let prev = util::sigprocmask(SIG_BLOCK, Some(&blocked))?;
+	let pid = unsafe { getpid() };
+	let no_wait = timespec {
+		tv_sec: 0,
+		tv_nsec: 0,
+	};
+
+	log!("Queue several instances");
+	for value in 0..3 {
+		util::sigqueue(pid, sig, value)?;
+	}
+	for value in 0..3 {
+		let pending = util::sigpending()?;
+		test_assert!(unsafe { libc::sigismember(&pending, sig) } == 1);
+		let info = util::sigtimedwait(&blocked, &no_wait)?;
+		test_assert_eq!(info.si_signo, sig);
+		test_assert_eq!(unsafe { info.si_value().sival_ptr } as usize, value);
+	}
+	let pending = util::sigpending()?;
+	test_assert!(unsafe { libc::sigismember(&pending, sig) } == 0);
+
+	log!("Exceed the queue limit");
+	let mut lim: rlimit = unsafe { mem::zeroed() };
+	test_assert_eq!(
+		unsafe { libc::getrlimit(libc::RLIMIT_SIGPENDING, &mut lim) },
+		0
+	);
+	test_assert!(lim.rlim_cur != RLIM_INFINITY);
+	for value in 0..lim.rlim_cur {
+		util::sigqueue(pid, sig, value as _)?;
+	}
+	let res = util::sigqueue(pid, sig, 0);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EAGAIN)));
+	// `tkill` is subject to the limit too
+	let res = unsafe { libc::syscall(libc::SYS_tkill, pid, sig) };
+	test_assert_eq!(res, -1);
+	test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(EAGAIN));
+	// `kill` does not fail, but the instance is not queued beyond the limit
+	kill(pid, sig)?;
+	for value in 0..lim.rlim_cur {
+		let info = util::sigtimedwait(&blocked, &no_wait)?;
+		test_assert_eq!(unsafe { info.si_value().sival_ptr } as u64, value);
+	}
+	let res = util::sigtimedwait(&blocked, &no_wait);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EAGAIN)));
+
+	log!("Cleanup");
+	util::sigprocmask(SIG_SETMASK, Some(&prev))?;
+
+	Ok(())
+}
+
+pub fn waitid() -> TestResult {
+	log!("Wait without consuming the exit");
+	let pid = util::fork()?;
+	if pid == 0 {
+		unsafe {
+			libc::_exit(42);
+		}
+	}
+	for options in [WEXITED | WNOWAIT, WEXITED] {
+		let info = util::waitid(P_PID, pid as _, options)?;
+		test_assert_eq!(info.si_signo, SIGCHLD);
+		test_assert_eq!(info.si_code, CLD_EXITED);
+		test_assert_eq!(unsafe { info.si_pid() }, pid);
+		test_assert_eq!(unsafe { info.si_status() }, 42);
+	}
+	let res = util::waitid(P_PID, pid as _, WEXITED);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(ECHILD)));
+
+	log!("Poll a running child");
+	let pid = util::fork()?;
+	if pid == 0 {
+		loop {
+			unsafe {
+				libc::pause();
+			}
+		}
+	}
+	let info = util::waitid(P_ALL, 0, WEXITED | WNOHANG)?;
+	test_assert_eq!(unsafe { info.si_pid() }, 0);
+
+	log!("Wait for a stop and a continue");
+	kill(pid, SIGSTOP)?;
+	let info = util::waitid(P_PID, pid as _, WSTOPPED)?;
+	test_assert_eq!(info.si_code, CLD_STOPPED);
+	test_assert_eq!(unsafe { info.si_status() }, SIGSTOP);
+	kill(pid, SIGCONT)?;
+	let info = util::waitid(P_PID, pid as _, WCONTINUED)?;
+	test_assert_eq!(info.si_code, CLD_CONTINUED);
+	test_assert_eq!(unsafe { info.si_status() }, SIGCONT);
+
+	log!("Wait for a kill");
+	kill(pid, SIGKILL)?;
+	let info = util::waitid(P_PID, pid as _, WEXITED)?;
+	test_assert_eq!(info.si_code, CLD_KILLED);
+	test_assert_eq!(unsafe { info.si_status() }, SIGKILL);
+
+	log!("Invalid options");
+	let res = util::waitid(P_ALL, 0, WNOHANG);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	Ok(())
+}
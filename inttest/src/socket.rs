@@ -0,0 +1,458 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Tests of data transfer on Unix sockets, including control messages.
+
+use crate::{log, test_assert, test_assert_eq, util, util::TestResult};
+use libc::{
+	AF_UNIX, CMSG_DATA, CMSG_FIRSTHDR, CMSG_LEN, CMSG_NXTHDR, CMSG_SPACE, EADDRINUSE, EAGAIN,
+	ECONNREFUSED, ENOPROTOOPT, ENOTCONN, ETOOMANYREFS, F_GETFD, F_GETFL, F_SETFL, FD_CLOEXEC,
+	MSG_CTRUNC, MSG_DONTWAIT, MSG_PEEK, MSG_TRUNC, O_NONBLOCK, POLLIN, POLLOUT, SCM_CREDENTIALS,
+	SCM_RIGHTS, SO_ERROR, SO_PASSCRED, SO_PEERCRED, SO_RCVBUF, SO_REUSEADDR, SO_SNDBUF, SO_TYPE,
+	SOCK_CLOEXEC, SOCK_DGRAM, SOCK_NONBLOCK, SOCK_STREAM, SOL_SOCKET, WEXITSTATUS, WIFEXITED,
+	c_int, iovec, msghdr, sa_family_t, sockaddr_un, socklen_t, ucred,
+};
+use std::{
+	fs::File,
+	io,
+	io::{Read, Write},
+	mem,
+	os::fd::{AsRawFd, FromRawFd, OwnedFd},
+	ptr, thread,
+	time::Duration,
+};
+
+/// Returns the Unix socket address with the abstract name `name`.
+fn abstract_addr(name: &[u8]) -> (sockaddr_un, socklen_t) {
+	let mut addr: sockaddr_un = unsafe { mem::zeroed() };
+	addr.sun_family = AF_UNIX as _;
+	for (dst, src) in addr.sun_path[1..].iter_mut().zip(name) {
+		*dst = *src as _;
+	}
+	let len = mem::offset_of!(sockaddr_un, sun_path) + 1 + name.len();
+	(addr, len as _)
+}
+
+/// Creates a Unix socket of type `ty`.
+fn socket(ty: c_int) -> io::Result<OwnedFd> {
+	let res = unsafe { libc::socket(AF_UNIX, ty, 0) };
+	if res >= 0 {
+		unsafe { Ok(OwnedFd::from_raw_fd(res)) }
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Binds `sock` to the abstract name `name`.
+fn bind(sock: c_int, name: &[u8]) -> io::Result<()> {
+	let (addr, len) = abstract_addr(name);
+	let res = unsafe { libc::bind(sock, &addr as *const _ as _, len) };
+	if res == 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Connects `sock` to the socket bound to the abstract name `name`.
+fn connect(sock: c_int, name: &[u8]) -> io::Result<()> {
+	let (addr, len) = abstract_addr(name);
+	let res = unsafe { libc::connect(sock, &addr as *const _ as _, len) };
+	if res == 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Accepts a connection on `sock` with `flags`, returning the new socket and the length of the
+/// address of the peer.
+fn accept4(sock: c_int, flags: c_int) -> io::Result<(OwnedFd, socklen_t)> {
+	let mut addr: sockaddr_un = unsafe { mem::zeroed() };
+	let mut len = mem::size_of_val(&addr) as socklen_t;
+	let res = unsafe { libc::accept4(sock, &mut addr as *mut _ as _, &mut len, flags) };
+	if res >= 0 {
+		unsafe { Ok((OwnedFd::from_raw_fd(res), len)) }
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Returns the value of the socket option `name` on `sock`.
+pub fn getsockopt<T>(sock: c_int, name: c_int) -> io::Result<T> {
+	let mut val: T = unsafe { mem::zeroed() };
+	let mut len = mem::size_of::<T>() as socklen_t;
+	let res =
+		unsafe { libc::getsockopt(sock, SOL_SOCKET, name, &mut val as *mut _ as _, &mut len) };
+	if res == 0 {
+		Ok(val)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Sets the integer socket option `name` on `sock` to `val`.
+pub fn setsockopt(sock: c_int, name: c_int, val: c_int) -> io::Result<()> {
+	let res = unsafe {
+		libc::setsockopt(
+			sock,
+			SOL_SOCKET,
+			name,
+			&val as *const _ as _,
+			mem::size_of_val(&val) as _,
+		)
+	};
+	if res == 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Sends `data` on `sock`.
+fn send(sock: c_int, data: &[u8]) -> io::Result<usize> {
+	let res = unsafe { libc::send(sock, data.as_ptr() as _, data.len(), 0) };
+	if res >= 0 {
+		Ok(res as _)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Sends `data` on `sock`, along with the control message of type `ty` with payload `payload`.
+fn send_cmsg<T>(sock: c_int, data: &[u8], ty: c_int, payload: &[T]) -> io::Result<usize> {
+	let len = mem::size_of_val(payload) as u32;
+	let mut control = vec![0u8; unsafe { CMSG_SPACE(len) } as usize];
+	let mut iov = iovec {
+		iov_base: data.as_ptr() as _,
+		iov_len: data.len(),
+	};
+	let mut msg: msghdr = unsafe { mem::zeroed() };
+	msg.msg_iov = &mut iov;
+	msg.msg_iovlen = 1;
+	msg.msg_control = control.as_mut_ptr() as _;
+	msg.msg_controllen = control.len() as _;
+	unsafe {
+		let cmsg = CMSG_FIRSTHDR(&msg);
+		(*cmsg).cmsg_level = SOL_SOCKET;
+		(*cmsg).cmsg_type = ty;
+		(*cmsg).cmsg_len = CMSG_LEN(len) as _;
+		ptr::copy_nonoverlapping(payload.as_ptr() as *const u8, CMSG_DATA(cmsg), len as usize);
+	}
+	let res = unsafe { libc::sendmsg(sock, &msg, 0) };
+	if res >= 0 {
+		Ok(res as _)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// The result of [`recv_cmsg`].
+struct Received {
+	/// The number of bytes received.
+	len: usize,
+	/// The flags of the message.
+	flags: c_int,
+	/// The control messages, as level, type and payload.
+	cmsgs: Vec<(c_int, c_int, Vec<u8>)>,
+}
+
+/// Receives data on `sock` into `buf`, with a control buffer of `control_len` bytes.
+fn recv_cmsg(
+	sock: c_int,
+	buf: &mut [u8],
+	control_len: usize,
+	flags: c_int,
+) -> io::Result<Received> {
+	let mut control = vec![0u8; control_len];
+	let mut iov = iovec {
+		iov_base: buf.as_mut_ptr() as _,
+		iov_len: buf.len(),
+	};
+	let mut msg: msghdr = unsafe { mem::zeroed() };
+	msg.msg_iov = &mut iov;
+	msg.msg_iovlen = 1;
+	msg.msg_control = control.as_mut_ptr() as _;
+	msg.msg_controllen = control.len() as _;
+	let res = unsafe { libc::recvmsg(sock, &mut msg, flags) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	let mut cmsgs = vec![];
+	unsafe {
+		let mut cmsg = CMSG_FIRSTHDR(&msg);
+		while !cmsg.is_null() {
+			let len = (*cmsg).cmsg_len as usize - CMSG_LEN(0) as usize;
+			let data = std::slice::from_raw_parts(CMSG_DATA(cmsg), len);
+			cmsgs.push(((*cmsg).cmsg_level, (*cmsg).cmsg_type, data.to_vec()));
+			cmsg = CMSG_NXTHDR(&msg, cmsg);
+		}
+	}
+	Ok(Received {
+		len: res as _,
+		flags: msg.msg_flags,
+		cmsgs,
+	})
+}
+
+pub fn rights() -> TestResult {
+	let (sock, peer) = util::socketpair(AF_UNIX, SOCK_STREAM)?;
+	let (rx, tx) = util::pipe()?;
+
+	log!("Send the read end of a pipe");
+	test_assert_eq!(
+		send_cmsg(sock.as_raw_fd(), b"a", SCM_RIGHTS, &[rx.as_raw_fd()])?,
+		1
+	);
+	drop(rx);
+
+	log!("Receive it");
+	let mut buf = [0; 16];
+	let received = recv_cmsg(peer.as_raw_fd(), &mut buf, 64, 0)?;
+	test_assert_eq!(received.len, 1);
+	test_assert_eq!(received.flags, 0);
+	test_assert_eq!(received.cmsgs.len(), 1);
+	let (level, ty, data) = &received.cmsgs[0];
+	test_assert_eq!((*level, *ty), (SOL_SOCKET, SCM_RIGHTS));
+	test_assert_eq!(data.len(), mem::size_of::<c_int>());
+	let fd = c_int::from_ne_bytes(data[..].try_into().unwrap());
+	let mut rx = unsafe { File::from_raw_fd(fd) };
+
+	log!("Use the received file descriptor");
+	File::from(tx).write_all(b"hello")?;
+	let mut buf = [0; 5];
+	rx.read_exact(&mut buf)?;
+	test_assert_eq!(&buf, b"hello");
+
+	log!("Receive without enough control space");
+	let (rx, _tx) = util::pipe()?;
+	send_cmsg(sock.as_raw_fd(), b"b", SCM_RIGHTS, &[rx.as_raw_fd()])?;
+	let received = recv_cmsg(peer.as_raw_fd(), &mut buf, 0, 0)?;
+	test_assert_eq!(received.len, 1);
+	test_assert!(received.flags & MSG_CTRUNC != 0);
+	test_assert!(received.cmsgs.is_empty());
+
+	log!("Send a socket into its own queue");
+	let err = send_cmsg(sock.as_raw_fd(), b"c", SCM_RIGHTS, &[peer.as_raw_fd()]).unwrap_err();
+	test_assert_eq!(err.raw_os_error(), Some(ETOOMANYREFS));
+
+	log!("Fill a queue with empty datagrams carrying files");
+	let (sock, peer) = util::socketpair(AF_UNIX, SOCK_DGRAM | SOCK_NONBLOCK)?;
+	let (rx, _tx) = util::pipe()?;
+	let err = loop {
+		match send_cmsg(sock.as_raw_fd(), b"", SCM_RIGHTS, &[rx.as_raw_fd()]) {
+			Ok(_) => {}
+			Err(e) => break e,
+		}
+	};
+	test_assert_eq!(err.raw_os_error(), Some(EAGAIN));
+	drop(peer);
+
+	Ok(())
+}
+
+pub fn credentials() -> TestResult {
+	let (sock, peer) = util::socketpair(AF_UNIX, SOCK_STREAM)?;
+	let on: c_int = 1;
+	let res = unsafe {
+		libc::setsockopt(
+			peer.as_raw_fd(),
+			SOL_SOCKET,
+			SO_PASSCRED,
+			&on as *const _ as _,
+			mem::size_of_val(&on) as _,
+		)
+	};
+	if res < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	let cred = unsafe {
+		ucred {
+			pid: libc::getpid(),
+			uid: libc::geteuid(),
+			gid: libc::getegid(),
+		}
+	};
+
+	log!("Send explicit credentials");
+	send_cmsg(sock.as_raw_fd(), b"a", SCM_CREDENTIALS, &[cred])?;
+	log!("Send without credentials");
+	send(sock.as_raw_fd(), b"b")?;
+
+	log!("Receive credentials");
+	for _ in 0..2 {
+		let mut buf = [0; 1];
+		let received = recv_cmsg(peer.as_raw_fd(), &mut buf, 64, 0)?;
+		test_assert_eq!(received.len, 1);
+		test_assert_eq!(received.cmsgs.len(), 1);
+		let (level, ty, data) = &received.cmsgs[0];
+		test_assert_eq!((*level, *ty), (SOL_SOCKET, SCM_CREDENTIALS));
+		test_assert_eq!(data.len(), mem::size_of::<ucred>());
+		let recv_cred: ucred = unsafe { ptr::read_unaligned(data.as_ptr() as *const _) };
+		test_assert_eq!(recv_cred.pid, cred.pid);
+		test_assert_eq!(recv_cred.uid, cred.uid);
+		test_assert_eq!(recv_cred.gid, cred.gid);
+	}
+
+	Ok(())
+}
+
+pub fn datagram() -> TestResult {
+	let (sock, peer) = util::socketpair(AF_UNIX, SOCK_DGRAM)?;
+	send(sock.as_raw_fd(), b"hello")?;
+	send(sock.as_raw_fd(), b"world")?;
+
+	log!("Peek");
+	let mut buf = [0; 16];
+	let received = recv_cmsg(peer.as_raw_fd(), &mut buf, 0, MSG_PEEK)?;
+	test_assert_eq!(received.len, 5);
+	test_assert_eq!(&buf[..5], b"hello");
+
+	log!("Receive truncated");
+	let received = recv_cmsg(peer.as_raw_fd(), &mut buf[..2], 0, 0)?;
+	test_assert_eq!(received.len, 2);
+	test_assert_eq!(received.flags, MSG_TRUNC);
+	test_assert_eq!(&buf[..2], b"he");
+
+	log!("Receive next datagram");
+	let received = recv_cmsg(peer.as_raw_fd(), &mut buf, 0, 0)?;
+	test_assert_eq!(received.len, 5);
+	test_assert_eq!(&buf[..5], b"world");
+
+	log!("Receive on empty queue");
+	let err = recv_cmsg(peer.as_raw_fd(), &mut buf, 0, MSG_DONTWAIT).err();
+	test_assert_eq!(err.and_then(|e| e.raw_os_error()), Some(EAGAIN));
+
+	Ok(())
+}
+
+pub fn listen() -> TestResult {
+	const NAME: &[u8] = b"inttest-listen";
+	let listener = socket(SOCK_STREAM | SOCK_NONBLOCK)?;
+	let fd = listener.as_raw_fd();
+
+	log!("Bind and listen");
+	bind(fd, NAME)?;
+	let other = socket(SOCK_STREAM)?;
+	let err = bind(other.as_raw_fd(), NAME).unwrap_err();
+	test_assert_eq!(err.raw_os_error(), Some(EADDRINUSE));
+	if unsafe { libc::listen(fd, 1) } < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	test_assert_eq!(util::poll(fd, POLLIN | POLLOUT)?, 0);
+	let err = accept4(fd, 0).err();
+	test_assert_eq!(err.and_then(|e| e.raw_os_error()), Some(EAGAIN));
+
+	log!("Connect");
+	let client = socket(SOCK_STREAM)?;
+	connect(client.as_raw_fd(), NAME)?;
+	test_assert_eq!(util::poll(fd, POLLIN | POLLOUT)?, POLLIN);
+
+	log!("Accept with flags");
+	let (server, len) = accept4(fd, SOCK_NONBLOCK | SOCK_CLOEXEC)?;
+	// The client is not bound
+	test_assert_eq!(len as usize, mem::size_of::<sa_family_t>());
+	let fd_flags = unsafe { libc::fcntl(server.as_raw_fd(), F_GETFD) };
+	test_assert!(fd_flags & FD_CLOEXEC != 0);
+	let fl_flags = unsafe { libc::fcntl(server.as_raw_fd(), F_GETFL) };
+	test_assert!(fl_flags & O_NONBLOCK != 0);
+	test_assert_eq!(util::poll(fd, POLLIN | POLLOUT)?, 0);
+
+	log!("Transfer data");
+	test_assert_eq!(send(client.as_raw_fd(), b"ping")?, 4);
+	let mut buf = [0; 4];
+	test_assert_eq!(recv_cmsg(server.as_raw_fd(), &mut buf, 0, 0)?.len, 4);
+	test_assert_eq!(&buf, b"ping");
+	test_assert_eq!(send(server.as_raw_fd(), b"pong")?, 4);
+	test_assert_eq!(recv_cmsg(client.as_raw_fd(), &mut buf, 0, 0)?.len, 4);
+	test_assert_eq!(&buf, b"pong");
+	let err = recv_cmsg(server.as_raw_fd(), &mut buf, 0, 0).err();
+	test_assert_eq!(err.and_then(|e| e.raw_os_error()), Some(EAGAIN));
+
+	log!("Blocking accept");
+	if unsafe { libc::fcntl(fd, F_SETFL, 0) } < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	let pid = util::fork()?;
+	if pid == 0 {
+		thread::sleep(Duration::from_millis(100));
+		let res = socket(SOCK_STREAM).and_then(|sock| {
+			connect(sock.as_raw_fd(), NAME)?;
+			send(sock.as_raw_fd(), b"a")
+		});
+		unsafe { libc::_exit(res.is_err() as _) };
+	}
+	let (server, _) = accept4(fd, 0)?;
+	let mut buf = [0; 1];
+	test_assert_eq!(recv_cmsg(server.as_raw_fd(), &mut buf, 0, 0)?.len, 1);
+	let status = util::waitpid(pid)?;
+	test_assert!(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	// The client has exited
+	test_assert_eq!(recv_cmsg(server.as_raw_fd(), &mut buf, 0, 0)?.len, 0);
+
+	log!("Close the listening socket");
+	drop(listener);
+	let client = socket(SOCK_STREAM)?;
+	let err = connect(client.as_raw_fd(), NAME).unwrap_err();
+	test_assert_eq!(err.raw_os_error(), Some(ECONNREFUSED));
+	bind(other.as_raw_fd(), NAME)?;
+
+	Ok(())
+}
+
+pub fn options() -> TestResult {
+	let (sock, peer) = util::socketpair(AF_UNIX, SOCK_STREAM)?;
+	let fd = sock.as_raw_fd();
+
+	log!("Read-only options");
+	test_assert_eq!(getsockopt::<c_int>(fd, SO_TYPE)?, SOCK_STREAM);
+	test_assert_eq!(getsockopt::<c_int>(fd, SO_ERROR)?, 0);
+	let cred: ucred = getsockopt(fd, SO_PEERCRED)?;
+	test_assert_eq!(cred.pid, unsafe { libc::getpid() });
+	test_assert_eq!(cred.uid, unsafe { libc::geteuid() });
+	let err = setsockopt(fd, SO_TYPE, SOCK_DGRAM).unwrap_err();
+	test_assert_eq!(err.raw_os_error(), Some(ENOPROTOOPT));
+
+	log!("Writable options");
+	setsockopt(fd, SO_REUSEADDR, 1)?;
+	test_assert_eq!(getsockopt::<c_int>(fd, SO_REUSEADDR)?, 1);
+	setsockopt(fd, SO_RCVBUF, 10000)?;
+	test_assert_eq!(getsockopt::<c_int>(fd, SO_RCVBUF)?, 20000);
+	setsockopt(fd, SO_SNDBUF, 0)?;
+	test_assert!(getsockopt::<c_int>(fd, SO_SNDBUF)? > 0);
+
+	log!("Unknown option");
+	let err = getsockopt::<c_int>(fd, -1).unwrap_err();
+	test_assert_eq!(err.raw_os_error(), Some(ENOPROTOOPT));
+
+	log!("Peer names");
+	let mut addr: sockaddr_un = unsafe { mem::zeroed() };
+	let mut len = mem::size_of_val(&addr) as socklen_t;
+	let res = unsafe { libc::getpeername(peer.as_raw_fd(), &mut addr as *mut _ as _, &mut len) };
+	test_assert_eq!(res, 0);
+	// The peer is not bound
+	test_assert_eq!(len as usize, mem::size_of::<sa_family_t>());
+	test_assert_eq!(addr.sun_family, AF_UNIX as sa_family_t);
+	let unconnected = socket(SOCK_STREAM)?;
+	let res =
+		unsafe { libc::getpeername(unconnected.as_raw_fd(), &mut addr as *mut _ as _, &mut len) };
+	test_assert_eq!(res, -1);
+	test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(ENOTCONN));
+
+	Ok(())
+}
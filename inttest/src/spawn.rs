@@ -0,0 +1,532 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Tests of program execution, including `vfork` semantics as used by the `posix_spawn`
+//! implementations of C libraries.
+
+use crate::{
+	log, test_assert, test_assert_eq, util,
+	util::{TestResult, kill, signal, waitpid},
+};
+use libc::{
+	_exit, CLONE_VFORK, CLONE_VM, CLOSE_RANGE_CLOEXEC, EBADF, EINVAL, ELOOP, ENOENT, ENOEXEC,
+	F_DUPFD_CLOEXEC, FD_CLOEXEC, O_CLOEXEC, SIG_DFL, SIGCHLD, SIGUSR1, WEXITSTATUS, WIFEXITED,
+	execve, getppid, pid_t, posix_spawn_file_actions_addopen, posix_spawn_file_actions_destroy,
+	posix_spawn_file_actions_init, posix_spawn_file_actions_t, posix_spawnp, timespec,
+};
+use std::{
+	ffi::{CStr, CString, c_char, c_int, c_uint, c_void},
+	fs,
+	fs::File,
+	io, mem,
+	os::{fd::AsRawFd, unix::ffi::OsStringExt},
+	path::Path,
+	ptr::{null, null_mut},
+	sync::atomic::{
+		AtomicBool, AtomicI32,
+		Ordering::{Acquire, Release},
+	},
+	thread,
+	time::Duration,
+};
+
+/// The size of the stack of children.
+const STACK_SIZE: usize = 64 * 1024;
+
+/// Set by the child, which shares the memory of the parent.
+static CHILD_DONE: AtomicBool = AtomicBool::new(false);
+/// The `errno` reported by the child after a failed `execve`.
+static CHILD_ERRNO: AtomicI32 = AtomicI32::new(0);
+/// Set by the signal handler of the parent.
+static HIT: AtomicBool = AtomicBool::new(false);
+/// The value of [`HIT`] observed by the child before exiting.
+static CHILD_SAW_HIT: AtomicBool = AtomicBool::new(false);
+
+/// Sleeps for a short time, so that the parent would have the opportunity to run if it was not
+/// suspended.
+fn delay() {
+	let ts = timespec {
+		tv_sec: 0,
+		tv_nsec: 10_000_000,
+	};
+	unsafe {
+		libc::nanosleep(&ts, null_mut());
+	}
+}
+
+/// Creates a child with `CLONE_VM | CLONE_VFORK` running `f`, then returns its exit status.
+fn vfork_run(f: extern "C" fn(*mut c_void) -> c_int) -> Result<c_int, util::TestError> {
+	let mut stack = vec![0u8; STACK_SIZE];
+	let pid = util::clone(f, &mut stack, CLONE_VM | CLONE_VFORK | SIGCHLD, null_mut())?;
+	let status = waitpid(pid)?;
+	test_assert!(WIFEXITED(status));
+	Ok(WEXITSTATUS(status))
+}
+
+extern "C" fn child_exit(_: *mut c_void) -> c_int {
+	delay();
+	CHILD_DONE.store(true, Release);
+	unsafe { _exit(42) }
+}
+
+extern "C" fn child_exec_fail(_: *mut c_void) -> c_int {
+	delay();
+	let argv = [c"/nonexistent".as_ptr(), null()];
+	let envp = [null()];
+	unsafe {
+		execve(c"/nonexistent".as_ptr(), argv.as_ptr(), envp.as_ptr());
+	}
+	// Report the error through shared memory, then exit without returning into the parent's frames
+	CHILD_ERRNO.store(
+		std::io::Error::last_os_error().raw_os_error().unwrap_or(0),
+		Release,
+	);
+	unsafe { _exit(127) }
+}
+
+extern "C" fn child_signal(_: *mut c_void) -> c_int {
+	let _ = kill(unsafe { getppid() }, SIGUSR1);
+	delay();
+	CHILD_SAW_HIT.store(HIT.load(Acquire), Release);
+	unsafe { _exit(0) }
+}
+
+extern "C" fn signal_handler(_: c_int) {
+	HIT.store(true, Release);
+}
+
+pub fn vfork_exit() -> TestResult {
+	log!("Run child until exit");
+	CHILD_DONE.store(false, Release);
+	let status = vfork_run(child_exit)?;
+	// The parent must not have resumed before the child exited
+	test_assert!(CHILD_DONE.load(Acquire));
+	test_assert_eq!(status, 42);
+	Ok(())
+}
+
+pub fn vfork_exec_failure() -> TestResult {
+	log!("Run child with a failing execve");
+	CHILD_ERRNO.store(0, Release);
+	let status = vfork_run(child_exec_fail)?;
+	// A failed execve must not resume the parent
+	test_assert_eq!(CHILD_ERRNO.load(Acquire), ENOENT);
+	test_assert_eq!(status, 127);
+	Ok(())
+}
+
+pub fn vfork_signal() -> TestResult {
+	log!("Register signal handler");
+	HIT.store(false, Release);
+	signal(SIGUSR1, signal_handler as *const () as usize)?;
+
+	log!("Send a signal to the suspended parent");
+	let status = vfork_run(child_signal)?;
+	test_assert_eq!(status, 0);
+	// The signal must be handled only once the parent has resumed
+	test_assert!(!CHILD_SAW_HIT.load(Acquire));
+	// Let the signal be delivered, if it has not been already
+	thread::sleep(Duration::from_millis(10));
+	test_assert!(HIT.load(Acquire));
+
+	log!("Cleanup");
+	signal(SIGUSR1, SIG_DFL)?;
+	Ok(())
+}
+
+pub fn posix_spawn() -> TestResult {
+	let mut actions: posix_spawn_file_actions_t = unsafe { mem::zeroed() };
+	unsafe {
+		posix_spawn_file_actions_init(&mut actions);
+		posix_spawn_file_actions_addopen(
+			&mut actions,
+			1,
+			c"/dev/null".as_ptr(),
+			libc::O_WRONLY,
+			0,
+		);
+	}
+	let spawn = |file: &std::ffi::CStr| -> (c_int, pid_t) {
+		let argv = [file.as_ptr() as *mut _, c"/".as_ptr() as *mut _, null_mut()];
+		let envp = [null_mut()];
+		let mut pid = 0;
+		let res = unsafe {
+			posix_spawnp(
+				&mut pid,
+				file.as_ptr(),
+				&actions,
+				null(),
+				argv.as_ptr(),
+				envp.as_ptr(),
+			)
+		};
+		(res, pid)
+	};
+	let res = (|| {
+		log!("Spawn a program");
+		let (res, pid) = spawn(c"ls");
+		test_assert_eq!(res, 0);
+		let status = waitpid(pid)?;
+		test_assert!(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+		log!("Spawn a missing program");
+		let (res, _) = spawn(c"/nonexistent");
+		test_assert_eq!(res, ENOENT);
+		Ok(())
+	})();
+	unsafe {
+		posix_spawn_file_actions_destroy(&mut actions);
+	}
+	res
+}
+
+pub fn auxv() -> TestResult {
+	let aux = |ty| unsafe { libc::getauxval(ty) };
+
+	log!("Check program headers");
+	test_assert!(aux(libc::AT_PHDR) != 0);
+	let phent = if cfg!(target_pointer_width = "64") {
+		mem::size_of::<libc::Elf64_Phdr>()
+	} else {
+		mem::size_of::<libc::Elf32_Phdr>()
+	};
+	test_assert_eq!(aux(libc::AT_PHENT) as usize, phent);
+	test_assert!(aux(libc::AT_PHNUM) > 0);
+	test_assert!(aux(libc::AT_ENTRY) != 0);
+
+	log!("Check system information");
+	test_assert_eq!(aux(libc::AT_PAGESZ), 4096);
+	test_assert_eq!(aux(libc::AT_CLKTCK), 100);
+	test_assert_eq!(aux(libc::AT_SECURE), 0);
+	let platform = unsafe { CStr::from_ptr(aux(libc::AT_PLATFORM) as *const c_char) };
+	let expected: &CStr = if cfg!(target_arch = "x86_64") {
+		c"x86_64"
+	} else {
+		c"i686"
+	};
+	test_assert_eq!(platform, expected);
+
+	log!("Check random bytes");
+	let random = aux(libc::AT_RANDOM) as *const [u8; 16];
+	test_assert!(!random.is_null());
+	test_assert!(unsafe { *random } != [0; 16]);
+
+	log!("Check vDSO");
+	let vdso = aux(libc::AT_SYSINFO_EHDR) as *const [u8; 4];
+	test_assert!(!vdso.is_null());
+	test_assert_eq!(unsafe { *vdso }, *b"\x7fELF");
+
+	Ok(())
+}
+
+/// Machine code making the `exit` system call with the given status.
+#[cfg(target_arch = "x86_64")]
+fn exit_code(status: u8) -> [u8; 12] {
+	// mov edi, status; mov eax, 60; syscall
+	[0xbf, status, 0, 0, 0, 0xb8, 60, 0, 0, 0, 0x0f, 0x05]
+}
+
+/// Machine code making the `exit` system call with the given status.
+#[cfg(target_arch = "x86")]
+fn exit_code(status: u8) -> [u8; 12] {
+	// mov ebx, status; mov eax, 1; int 0x80
+	[0xbb, status, 0, 0, 0, 0xb8, 1, 0, 0, 0, 0xcd, 0x80]
+}
+
+/// Machine code making the `exit` system call with the number of arguments as status.
+#[cfg(target_arch = "x86_64")]
+const EXIT_ARGC_CODE: &[u8] = &[
+	// mov rdi, [rsp]; mov eax, 60; syscall
+	0x48, 0x8b, 0x3c, 0x24, 0xb8, 60, 0, 0, 0, 0x0f, 0x05,
+];
+
+/// Machine code making the `exit` system call with the number of arguments as status.
+#[cfg(target_arch = "x86")]
+const EXIT_ARGC_CODE: &[u8] = &[
+	// mov ebx, [esp]; mov eax, 1; int 0x80
+	0x8b, 0x1c, 0x24, 0xb8, 1, 0, 0, 0, 0xcd, 0x80,
+];
+
+/// Machine code making the `exit` system call with the flags of the file descriptor `fd` as
+/// status, or the `errno` if the descriptor is not open.
+#[cfg(target_arch = "x86_64")]
+fn exit_fd_flags_code(fd: u8) -> [u8; 28] {
+	// mov edi, fd; mov esi, F_GETFD; mov eax, 72; syscall
+	// mov edi, eax; neg edi; mov eax, 60; syscall
+	[
+		0xbf, fd, 0, 0, 0, 0xbe, 1, 0, 0, 0, 0xb8, 72, 0, 0, 0, 0x0f, 0x05, 0x89, 0xc7, 0xf7,
+		0xdf, 0xb8, 60, 0, 0, 0, 0x0f, 0x05,
+	]
+}
+
+/// Machine code making the `exit` system call with the flags of the file descriptor `fd` as
+/// status, or the `errno` if the descriptor is not open.
+#[cfg(target_arch = "x86")]
+fn exit_fd_flags_code(fd: u8) -> [u8; 28] {
+	// mov ebx, fd; mov ecx, F_GETFD; mov eax, 55; int 0x80
+	// mov ebx, eax; neg ebx; mov eax, 1; int 0x80
+	[
+		0xbb, fd, 0, 0, 0, 0xb9, 1, 0, 0, 0, 0xb8, 55, 0, 0, 0, 0xcd, 0x80, 0x89, 0xc3, 0xf7,
+		0xdb, 0xb8, 1, 0, 0, 0, 0xcd, 0x80,
+	]
+}
+
+/// Builds a minimal ELF image for the current architecture, running `code`.
+///
+/// Arguments:
+/// - `e_type` is the type of the image (`ET_EXEC` or `ET_DYN`)
+/// - `base` is the address at which the image is loaded
+/// - `interp` is the path to the interpreter, if any
+fn elf_image(e_type: u16, base: usize, interp: Option<&[u8]>, code: &[u8]) -> Vec<u8> {
+	const WORD: usize = mem::size_of::<usize>();
+	let ehsize = 40 + 3 * WORD;
+	let phentsize = 8 + 6 * WORD;
+	let phnum = 1 + interp.is_some() as usize;
+	let interp_off = ehsize + phnum * phentsize;
+	let interp_len = interp.map(|i| i.len() + 1).unwrap_or(0);
+	let code_off = interp_off + interp_len;
+	let len = code_off + code.len();
+	let mut buf = Vec::new();
+	let word = |buf: &mut Vec<u8>, val: usize| buf.extend_from_slice(&val.to_le_bytes());
+	// Header
+	buf.extend_from_slice(b"\x7fELF");
+	buf.extend_from_slice(&[if WORD == 8 { 2 } else { 1 }, 1, 1]);
+	buf.resize(16, 0);
+	buf.extend_from_slice(&e_type.to_le_bytes());
+	let machine: u16 = if cfg!(target_arch = "x86_64") { 62 } else { 3 };
+	buf.extend_from_slice(&machine.to_le_bytes());
+	buf.extend_from_slice(&1u32.to_le_bytes());
+	word(&mut buf, base + code_off);
+	word(&mut buf, ehsize);
+	word(&mut buf, 0);
+	buf.extend_from_slice(&0u32.to_le_bytes());
+	for val in [ehsize, phentsize, phnum, 0, 0, 0] {
+		buf.extend_from_slice(&(val as u16).to_le_bytes());
+	}
+	// Program headers
+	let mut phdr = |p_type: u32, flags: u32, off: usize, vaddr: usize, size: usize, align| {
+		buf.extend_from_slice(&p_type.to_le_bytes());
+		if WORD == 8 {
+			buf.extend_from_slice(&flags.to_le_bytes());
+		}
+		for val in [off, vaddr, vaddr, size, size] {
+			word(&mut buf, val);
+		}
+		if WORD == 4 {
+			buf.extend_from_slice(&flags.to_le_bytes());
+		}
+		word(&mut buf, align);
+	};
+	if interp.is_some() {
+		// PT_INTERP
+		phdr(3, 4, interp_off, base + interp_off, interp_len, 1);
+	}
+	// PT_LOAD, readable and executable
+	phdr(1, 5, 0, base, len, 4096);
+	// Content
+	if let Some(interp) = interp {
+		buf.extend_from_slice(interp);
+		buf.push(0);
+	}
+	buf.extend_from_slice(code);
+	buf
+}
+
+/// Executes the program at `path` in a child process, returning its exit status.
+///
+/// If `execve` fails, the child exits with the `errno` as status.
+fn run(path: &CStr) -> io::Result<c_int> {
+	let pid = util::fork()?;
+	if pid == 0 {
+		unsafe {
+			let argv = [path.as_ptr(), c"arg".as_ptr(), null()];
+			let envp = [null()];
+			execve(path.as_ptr(), argv.as_ptr(), envp.as_ptr());
+			_exit(*libc::__errno_location());
+		}
+	}
+	let status = waitpid(pid)?;
+	if !WIFEXITED(status) {
+		return Err(io::Error::other("the child did not exit"));
+	}
+	Ok(WEXITSTATUS(status))
+}
+
+pub fn interpreter() -> TestResult {
+	let dir = Path::new("/tmp/interp");
+	fs::create_dir_all(dir)?;
+	let write = |name: &str, content: &[u8]| -> io::Result<()> {
+		let path = dir.join(name);
+		fs::write(&path, content)?;
+		util::chmod(&path, 0o755)
+	};
+
+	log!("Run a program through an interpreter");
+	write("ld.so", &elf_image(3, 0, None, &exit_code(42)))?;
+	write(
+		"prog",
+		&elf_image(2, 0x400000, Some(b"/tmp/interp/ld.so"), &exit_code(1)),
+	)?;
+	test_assert_eq!(run(c"/tmp/interp/prog")?, 42);
+
+	log!("Run the interpreter directly");
+	test_assert_eq!(run(c"/tmp/interp/ld.so")?, 42);
+
+	log!("Missing interpreter");
+	write(
+		"missing",
+		&elf_image(2, 0x400000, Some(b"/tmp/interp/none"), &exit_code(1)),
+	)?;
+	test_assert_eq!(run(c"/tmp/interp/missing")?, ENOENT);
+
+	log!("Invalid interpreter");
+	write("garbage", b"not an ELF")?;
+	write(
+		"invalid",
+		&elf_image(2, 0x400000, Some(b"/tmp/interp/garbage"), &exit_code(1)),
+	)?;
+	test_assert_eq!(run(c"/tmp/interp/invalid")?, ENOEXEC);
+
+	fs::remove_dir_all(dir)?;
+	Ok(())
+}
+
+pub fn script() -> TestResult {
+	let dir = Path::new("/tmp/script");
+	fs::create_dir_all(dir)?;
+	let write = |name: &str, content: &[u8]| -> io::Result<()> {
+		let path = dir.join(name);
+		fs::write(&path, content)?;
+		util::chmod(&path, 0o755)
+	};
+	// The interpreter exits with the number of arguments it received
+	write("argc", &elf_image(2, 0x400000, None, EXIT_ARGC_CODE))?;
+
+	log!("Script without argument");
+	write("plain", b"#!/tmp/script/argc\nexit\n")?;
+	// `argc /tmp/script/plain arg`
+	test_assert_eq!(run(c"/tmp/script/plain")?, 3);
+
+	log!("Script with argument");
+	write("arg", b"#!  /tmp/script/argc  -a -b \n")?;
+	// `argc "-a -b" /tmp/script/arg arg`
+	test_assert_eq!(run(c"/tmp/script/arg")?, 4);
+
+	log!("Nested scripts");
+	write("nested", b"#!/tmp/script/arg -c")?;
+	// `argc "-a -b" /tmp/script/arg -c /tmp/script/nested arg`
+	test_assert_eq!(run(c"/tmp/script/nested")?, 6);
+
+	log!("Missing interpreter");
+	write("missing", b"#!/tmp/script/none\n")?;
+	test_assert_eq!(run(c"/tmp/script/missing")?, ENOENT);
+
+	log!("Empty interpreter line");
+	write("empty", b"#!\n/tmp/script/argc\n")?;
+	test_assert_eq!(run(c"/tmp/script/empty")?, ENOEXEC);
+
+	log!("Recursive script");
+	write("loop", b"#!/tmp/script/loop\n")?;
+	test_assert_eq!(run(c"/tmp/script/loop")?, ELOOP);
+
+	fs::remove_dir_all(dir)?;
+	Ok(())
+}
+
+pub fn cloexec() -> TestResult {
+	let dir = Path::new("/tmp/cloexec");
+	fs::create_dir_all(dir)?;
+	// Writes a program exiting with the flags of `fd` as seen after `execve`
+	let check_prog = |fd: c_int| -> io::Result<CString> {
+		let path = dir.join(format!("fd{fd}"));
+		let image = elf_image(2, 0x400000, None, &exit_fd_flags_code(fd as _));
+		fs::write(&path, image)?;
+		util::chmod(&path, 0o755)?;
+		Ok(CString::new(path.into_os_string().into_vec())?)
+	};
+
+	log!("Open with O_CLOEXEC");
+	fs::write("/tmp/cloexec/file", b"")?;
+	// The standard library opens files with `O_CLOEXEC`
+	let file = File::open("/tmp/cloexec/file")?;
+	let cloexec_fd = file.as_raw_fd();
+	test_assert_eq!(util::fd_flags(cloexec_fd)?, FD_CLOEXEC);
+	// `dup` clears the flag
+	let keep_fd = unsafe { libc::dup(cloexec_fd) };
+	test_assert!(keep_fd >= 0);
+	test_assert_eq!(util::fd_flags(keep_fd)?, 0);
+
+	log!("Close on exec");
+	test_assert_eq!(run(&check_prog(cloexec_fd)?)?, EBADF);
+	test_assert_eq!(run(&check_prog(keep_fd)?)?, 0);
+
+	log!("pipe2 with O_CLOEXEC");
+	let mut pipefd = [0; 2];
+	let res = unsafe { libc::pipe2(pipefd.as_mut_ptr(), O_CLOEXEC) };
+	test_assert_eq!(res, 0);
+	test_assert_eq!(util::fd_flags(pipefd[0])?, FD_CLOEXEC);
+	test_assert_eq!(util::fd_flags(pipefd[1])?, FD_CLOEXEC);
+	test_assert_eq!(run(&check_prog(pipefd[0])?)?, EBADF);
+
+	log!("dup2 and dup3");
+	let res = unsafe { libc::dup2(cloexec_fd, cloexec_fd) };
+	test_assert_eq!(res, cloexec_fd);
+	test_assert_eq!(util::fd_flags(cloexec_fd)?, FD_CLOEXEC);
+	let res = unsafe { libc::dup3(keep_fd, keep_fd, 0) };
+	test_assert!(res < 0 && io::Error::last_os_error().raw_os_error() == Some(EINVAL));
+	let res = unsafe { libc::dup3(keep_fd, 100, O_CLOEXEC) };
+	test_assert_eq!(res, 100);
+	test_assert_eq!(util::fd_flags(100)?, FD_CLOEXEC);
+
+	log!("F_DUPFD_CLOEXEC");
+	let res = unsafe { libc::fcntl(keep_fd, F_DUPFD_CLOEXEC, 50) };
+	test_assert!(res >= 50);
+	test_assert_eq!(util::fd_flags(res)?, FD_CLOEXEC);
+	test_assert_eq!(run(&check_prog(res)?)?, EBADF);
+	unsafe {
+		libc::close(res);
+	}
+	let res = unsafe { libc::fcntl(keep_fd, F_DUPFD_CLOEXEC, -1) };
+	test_assert!(res < 0 && io::Error::last_os_error().raw_os_error() == Some(EINVAL));
+
+	log!("close_range with CLOSE_RANGE_CLOEXEC");
+	util::close_range(keep_fd as _, keep_fd as _, CLOSE_RANGE_CLOEXEC)?;
+	test_assert_eq!(util::fd_flags(keep_fd)?, FD_CLOEXEC);
+	test_assert_eq!(run(&check_prog(keep_fd)?)?, EBADF);
+
+	log!("close_range");
+	util::close_range(pipefd[0] as _, pipefd[1] as _, 0)?;
+	let res = util::fd_flags(pipefd[0]);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EBADF)));
+	let res = util::fd_flags(pipefd[1]);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EBADF)));
+	util::close_range(100, c_uint::MAX, 0)?;
+	let res = util::fd_flags(100);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EBADF)));
+	test_assert_eq!(util::fd_flags(cloexec_fd)?, FD_CLOEXEC);
+	let res = util::close_range(2, 1, 0);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+	let res = util::close_range(0, 1, 1 << 8);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	unsafe {
+		libc::close(keep_fd);
+	}
+	fs::remove_dir_all(dir)?;
+	Ok(())
+}
@@ -0,0 +1,383 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! squashfs tests.
+//!
+//! Since no squashfs tool is available on the system, the image is built by the test.
+
+use crate::{log, test_assert, test_assert_eq, util, util::TestResult};
+use libc::{EROFS, c_int, c_ulong, c_void};
+use std::{
+	cmp::min,
+	ffi::CString,
+	fs,
+	fs::{File, OpenOptions},
+	io,
+	os::{fd::AsRawFd, unix::fs::MetadataExt},
+	path::Path,
+	ptr::null,
+};
+
+const LOOP_SET_FD: c_ulong = 0x4c00;
+const LOOP_CLR_FD: c_ulong = 0x4c01;
+const LOOP_CTL_GET_FREE: c_ulong = 0x4c82;
+
+const BLOCK_SIZE: usize = 4096;
+const BLOCK_UNCOMPRESSED: u32 = 1 << 24;
+const INVALID_FRAG: u32 = 0xffffffff;
+
+const TYPE_DIR: u16 = 1;
+const TYPE_REG: u16 = 2;
+const TYPE_SYMLINK: u16 = 3;
+const TYPE_LREG: u16 = 9;
+
+/// Writes bits in the order of a DEFLATE stream.
+#[derive(Default)]
+struct BitWriter {
+	out: Vec<u8>,
+	bit: u32,
+}
+
+impl BitWriter {
+	/// Writes the `n` lowest bits of `val`, least significant first.
+	fn write(&mut self, val: u32, n: u32) {
+		for i in 0..n {
+			if self.bit == 0 {
+				self.out.push(0);
+			}
+			*self.out.last_mut().unwrap() |= (((val >> i) & 1) as u8) << self.bit;
+			self.bit = (self.bit + 1) % 8;
+		}
+	}
+
+	/// Writes a Huffman code of `n` bits, most significant first.
+	fn write_code(&mut self, code: u32, n: u32) {
+		self.write(code.reverse_bits() >> (32 - n), n);
+	}
+
+	/// Writes a symbol of the fixed literal/length alphabet.
+	fn write_sym(&mut self, sym: u32) {
+		match sym {
+			0..=143 => self.write_code(0x30 + sym, 8),
+			144..=255 => self.write_code(0x190 + sym - 144, 9),
+			256..=279 => self.write_code(sym - 256, 7),
+			_ => self.write_code(0xc0 + sym - 280, 8),
+		}
+	}
+}
+
+/// Wraps `data` in a zlib stream, compressed with fixed Huffman codes.
+fn zlib(data: &[u8]) -> Vec<u8> {
+	const LEN_BASE: [u32; 29] = [
+		3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+		131, 163, 195, 227, 258,
+	];
+	const LEN_EXTRA: [u32; 29] = [
+		0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+	];
+	const DIST_BASE: [u32; 30] = [
+		1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+		2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+	];
+	const DIST_EXTRA: [u32; 30] = [
+		0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12,
+		13, 13,
+	];
+	const WINDOW: usize = 1024;
+
+	let mut w = BitWriter::default();
+	// Final block with fixed codes
+	w.write(0b011, 3);
+	let mut i = 0;
+	while i < data.len() {
+		// Naive search of the longest match in the window
+		let (len, dist) = (i.saturating_sub(WINDOW)..i)
+			.map(|start| {
+				let len = (0..min(258, data.len() - i))
+					.take_while(|j| data[start + j] == data[i + j])
+					.count();
+				(len, i - start)
+			})
+			.max_by_key(|(len, _)| *len)
+			.unwrap_or_default();
+		if len < 3 {
+			w.write_sym(data[i] as _);
+			i += 1;
+			continue;
+		}
+		let code = LEN_BASE.iter().rposition(|b| *b <= len as u32).unwrap();
+		w.write_sym(257 + code as u32);
+		w.write(len as u32 - LEN_BASE[code], LEN_EXTRA[code]);
+		let code = DIST_BASE.iter().rposition(|b| *b <= dist as u32).unwrap();
+		w.write_code(code as _, 5);
+		w.write(dist as u32 - DIST_BASE[code], DIST_EXTRA[code]);
+		i += len;
+	}
+	w.write_sym(256);
+	let mut out = vec![0x78, 0x01];
+	out.extend_from_slice(&w.out);
+	let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), c| {
+		let a = (a + *c as u32) % 65521;
+		(a, (b + a) % 65521)
+	});
+	out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+	out
+}
+
+/// Appends a compressed metadata block containing `data` to `img`, returning its offset.
+fn metadata(img: &mut Vec<u8>, data: &[u8]) -> u64 {
+	let off = img.len() as u64;
+	let data = zlib(data);
+	img.extend_from_slice(&(data.len() as u16).to_le_bytes());
+	img.extend_from_slice(&data);
+	off
+}
+
+/// Appends an inode header to `buf`.
+fn inode_hdr(buf: &mut Vec<u8>, inode_type: u16, mode: u16, uid: u16, inode: u32) {
+	for v in [inode_type, mode, uid, 0] {
+		buf.extend_from_slice(&v.to_le_bytes());
+	}
+	buf.extend_from_slice(&1000000000u32.to_le_bytes());
+	buf.extend_from_slice(&inode.to_le_bytes());
+}
+
+fn put32(buf: &mut Vec<u8>, vals: &[u32]) {
+	for v in vals {
+		buf.extend_from_slice(&v.to_le_bytes());
+	}
+}
+
+/// Appends a directory listing made of one header to `buf`.
+///
+/// Each entry is a tuple with the offset of the inode, the inode number, the type and the name.
+fn listing(buf: &mut Vec<u8>, entries: &[(u16, u32, u16, &str)]) {
+	let base = entries[0].1;
+	put32(buf, &[entries.len() as u32 - 1, 0, base]);
+	for (off, inode, inode_type, name) in entries {
+		buf.extend_from_slice(&off.to_le_bytes());
+		buf.extend_from_slice(&((*inode as i32 - base as i32) as i16).to_le_bytes());
+		buf.extend_from_slice(&inode_type.to_le_bytes());
+		buf.extend_from_slice(&(name.len() as u16 - 1).to_le_bytes());
+		buf.extend_from_slice(name.as_bytes());
+	}
+}
+
+fn file_data() -> Vec<u8> {
+	(0..10000).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+/// Builds a squashfs image with the following hierarchy:
+/// - `/file`: regular file of two blocks, the first one compressed, and a tail in a fragment
+/// - `/link`: symbolic link to `file`
+/// - `/sparse`: sparse file of two blocks, using an extended inode
+/// - `/dir/small`: regular file stored entirely in a fragment
+fn build_image() -> Vec<u8> {
+	let file = file_data();
+	let small = b"hello world\n";
+	let mut img = vec![0; 96];
+	// Data blocks
+	let blocks_start = img.len() as u32;
+	let blk0 = zlib(&file[..BLOCK_SIZE]);
+	img.extend_from_slice(&blk0);
+	img.extend_from_slice(&file[BLOCK_SIZE..(2 * BLOCK_SIZE)]);
+	let tail = &file[(2 * BLOCK_SIZE)..];
+	let frag = zlib(&[tail, small].concat());
+	let frag_start = img.len() as u64;
+	img.extend_from_slice(&frag);
+	// Inodes. The root is inode 1, and its parent is one past the number of inodes
+	let mut inodes = Vec::new();
+	let file_off = inodes.len() as u16;
+	inode_hdr(&mut inodes, TYPE_REG, 0o644, 1, 2);
+	put32(
+		&mut inodes,
+		&[blocks_start, 0, 0, file.len() as u32, blk0.len() as u32],
+	);
+	put32(&mut inodes, &[BLOCK_SIZE as u32 | BLOCK_UNCOMPRESSED]);
+	let link_off = inodes.len() as u16;
+	inode_hdr(&mut inodes, TYPE_SYMLINK, 0o777, 0, 3);
+	put32(&mut inodes, &[1, 4]);
+	inodes.extend_from_slice(b"file");
+	let sparse_off = inodes.len() as u16;
+	inode_hdr(&mut inodes, TYPE_LREG, 0o600, 0, 4);
+	inodes.extend_from_slice(&0u64.to_le_bytes());
+	inodes.extend_from_slice(&(2 * BLOCK_SIZE as u64).to_le_bytes());
+	inodes.extend_from_slice(&(2 * BLOCK_SIZE as u64).to_le_bytes());
+	put32(&mut inodes, &[1, INVALID_FRAG, 0, !0, 0, 0]);
+	let small_off = inodes.len() as u16;
+	inode_hdr(&mut inodes, TYPE_REG, 0o644, 0, 6);
+	put32(&mut inodes, &[0, 0, tail.len() as u32, small.len() as u32]);
+	// Directories
+	let mut dirs = Vec::new();
+	listing(&mut dirs, &[(small_off, 6, TYPE_REG, "small")]);
+	let dir_size = dirs.len();
+	let dir_inode_off = inodes.len() as u16;
+	inode_hdr(&mut inodes, TYPE_DIR, 0o755, 0, 5);
+	put32(&mut inodes, &[0, 2]);
+	inodes.extend_from_slice(&(dir_size as u16 + 3).to_le_bytes());
+	inodes.extend_from_slice(&0u16.to_le_bytes());
+	put32(&mut inodes, &[1]);
+	let root_listing = dirs.len();
+	listing(
+		&mut dirs,
+		&[
+			(dir_inode_off, 5, TYPE_DIR, "dir"),
+			(file_off, 2, TYPE_REG, "file"),
+			(link_off, 3, TYPE_SYMLINK, "link"),
+			(sparse_off, 4, TYPE_REG, "sparse"),
+		],
+	);
+	let root_off = inodes.len() as u16;
+	inode_hdr(&mut inodes, TYPE_DIR, 0o755, 0, 1);
+	put32(&mut inodes, &[0, 3]);
+	inodes.extend_from_slice(&((dirs.len() - root_listing) as u16 + 3).to_le_bytes());
+	inodes.extend_from_slice(&(root_listing as u16).to_le_bytes());
+	put32(&mut inodes, &[7]);
+	// Tables
+	let inode_table = metadata(&mut img, &inodes);
+	let dir_table = metadata(&mut img, &dirs);
+	let mut frags = Vec::new();
+	frags.extend_from_slice(&frag_start.to_le_bytes());
+	put32(&mut frags, &[frag.len() as u32, 0]);
+	let frag_blk = metadata(&mut img, &frags);
+	let frag_table = img.len() as u64;
+	img.extend_from_slice(&frag_blk.to_le_bytes());
+	let ids_blk = metadata(
+		&mut img,
+		&[0u32.to_le_bytes(), 1000u32.to_le_bytes()].concat(),
+	);
+	let id_table = img.len() as u64;
+	img.extend_from_slice(&ids_blk.to_le_bytes());
+	let bytes_used = img.len() as u64;
+	// Superblock
+	let mut sp = Vec::new();
+	put32(&mut sp, &[0x73717368, 6, 0, BLOCK_SIZE as u32, 1]);
+	// Compression, block log, flags, ID count, version
+	for v in [1u16, 12, 0, 2, 4, 0] {
+		sp.extend_from_slice(&v.to_le_bytes());
+	}
+	for v in [
+		root_off as u64,
+		bytes_used,
+		id_table,
+		!0,
+		inode_table,
+		dir_table,
+		frag_table,
+		!0,
+	] {
+		sp.extend_from_slice(&v.to_le_bytes());
+	}
+	img[..96].copy_from_slice(&sp);
+	img.resize(bytes_used.next_multiple_of(BLOCK_SIZE as u64) as usize, 0);
+	img
+}
+
+fn ioctl(file: &File, req: c_ulong, arg: usize) -> io::Result<c_int> {
+	let res = unsafe { libc::ioctl(file.as_raw_fd(), req as _, arg as *mut c_void) };
+	if res >= 0 {
+		Ok(res)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Mounts a squashfs image through a loop device and reads its content.
+pub fn read() -> TestResult {
+	const IMAGE: &str = "/squashfs_image";
+	const TARGET: &str = "/mnt/squashfs";
+
+	log!("Build image");
+	fs::write(IMAGE, build_image())?;
+	let ctl = File::open("/dev/loop-control")?;
+	let index = ioctl(&ctl, LOOP_CTL_GET_FREE, 0)?;
+	let dev_path = format!("/dev/loop{index}");
+	let dev = OpenOptions::new().read(true).write(true).open(&dev_path)?;
+	let backing = File::open(IMAGE)?;
+	ioctl(&dev, LOOP_SET_FD, backing.as_raw_fd() as _)?;
+	drop(backing);
+
+	log!("Mount");
+	fs::create_dir_all(TARGET)?;
+	let src = CString::new(dev_path)?;
+	let target = CString::new(TARGET)?;
+	util::mount(
+		src.as_c_str(),
+		target.as_c_str(),
+		c"squashfs",
+		libc::MS_RDONLY,
+		null(),
+	)?;
+
+	log!("List root");
+	let mut names = fs::read_dir(TARGET)?
+		.map(|ent| Ok(ent?.file_name().into_string().unwrap()))
+		.collect::<io::Result<Vec<_>>>()?;
+	names.sort();
+	test_assert_eq!(names, ["dir", "file", "link", "sparse"]);
+
+	log!("Read files");
+	let meta = fs::metadata(format!("{TARGET}/file"))?;
+	test_assert_eq!(meta.len(), 10000);
+	test_assert_eq!(meta.mode(), 0o100644);
+	test_assert_eq!(meta.uid(), 1000);
+	test_assert_eq!(meta.ino(), 2);
+	test_assert!(fs::read(format!("{TARGET}/file"))? == file_data());
+	test_assert!(fs::read(format!("{TARGET}/sparse"))? == [0; 2 * BLOCK_SIZE]);
+	test_assert_eq!(fs::read(format!("{TARGET}/dir/small"))?, b"hello world\n");
+	test_assert!(fs::metadata(format!("{TARGET}/dir"))?.is_dir());
+
+	log!("Follow link");
+	test_assert!(fs::read_link(format!("{TARGET}/link"))? == Path::new("file"));
+	test_assert!(fs::read(format!("{TARGET}/link"))? == file_data());
+
+	log!("Write");
+	let res = fs::write(format!("{TARGET}/new"), b"a");
+	test_assert_eq!(res.err().and_then(|e| e.raw_os_error()), Some(EROFS));
+
+	util::umount(target.as_c_str())?;
+
+	log!("Mount with type detection");
+	for fstype in [c"ext2,squashfs".as_ptr(), c"auto".as_ptr(), null()] {
+		let res = unsafe {
+			libc::mount(
+				src.as_ptr(),
+				target.as_ptr(),
+				fstype,
+				libc::MS_RDONLY,
+				null(),
+			)
+		};
+		test_assert_eq!(res, 0);
+		test_assert_eq!(fs::read(format!("{TARGET}/dir/small"))?, b"hello world\n");
+		util::umount(target.as_c_str())?;
+	}
+	let res = util::mount(
+		src.as_c_str(),
+		target.as_c_str(),
+		c"nonexistent",
+		libc::MS_RDONLY,
+		null(),
+	);
+	test_assert_eq!(res.err().and_then(|e| e.raw_os_error()), Some(libc::ENODEV));
+
+	log!("Cleanup");
+	ioctl(&dev, LOOP_CLR_FD, 0)?;
+	fs::remove_file(IMAGE)?;
+	Ok(())
+}
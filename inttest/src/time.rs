@@ -0,0 +1,301 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Clocks and sleep testing.
+
+use crate::{
+	log, test_assert, test_assert_eq, util,
+	util::{
+		TestResult, clock_getres, clock_gettime, clock_nanosleep, clock_settime, gettimeofday,
+		sched_rr_get_interval, signal, timer_create, timer_delete, timer_gettime, timer_settime,
+	},
+};
+use libc::{
+	CLOCK_MONOTONIC, CLOCK_REALTIME, CLOCK_THREAD_CPUTIME_ID, EINVAL, ESRCH, ITIMER_PROF,
+	ITIMER_REAL, RUSAGE_CHILDREN, RUSAGE_SELF, SIG_DFL, SIGALRM, SIGPROF, TIMER_ABSTIME,
+	WEXITSTATUS, WIFEXITED, itimerspec, itimerval, timespec, timeval,
+};
+use std::{
+	ffi::c_int,
+	sync::atomic::{
+		AtomicBool,
+		Ordering::{Acquire, Release},
+	},
+};
+
+/// Set when `SIGALRM` is received.
+static ALARM: AtomicBool = AtomicBool::new(false);
+/// Set when `SIGPROF` is received.
+static PROF: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn alarm_handler(_: c_int) {
+	ALARM.store(true, Release);
+}
+
+extern "C" fn prof_handler(_: c_int) {
+	PROF.store(true, Release);
+}
+
+/// The duration of sleeps, in nanoseconds.
+const SLEEP_DURATION: u64 = 10_000_000;
+
+fn to_nano(ts: &timespec) -> u64 {
+	ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+fn timeval_to_nano(tv: &timeval) -> u64 {
+	tv.tv_sec as u64 * 1_000_000_000 + tv.tv_usec as u64 * 1000
+}
+
+/// Spins on the CPU for `ns` nanoseconds.
+fn burn(ns: u64) -> TestResult {
+	let start = to_nano(&clock_gettime(CLOCK_MONOTONIC)?);
+	while to_nano(&clock_gettime(CLOCK_MONOTONIC)?) - start < ns {}
+	Ok(())
+}
+
+fn from_micro(us: u64) -> timeval {
+	timeval {
+		tv_sec: (us / 1_000_000) as _,
+		tv_usec: (us % 1_000_000) as _,
+	}
+}
+
+fn from_nano(ns: u64) -> timespec {
+	timespec {
+		tv_sec: (ns / 1_000_000_000) as _,
+		tv_nsec: (ns % 1_000_000_000) as _,
+	}
+}
+
+pub fn resolution() -> TestResult {
+	for clock in [CLOCK_REALTIME, CLOCK_MONOTONIC] {
+		log!("Get resolution of clock {clock}");
+		let res = to_nano(&clock_getres(clock)?);
+		test_assert!(res > 0 && res <= 1_000_000);
+	}
+
+	log!("Get resolution of an invalid clock");
+	let res = clock_getres(-1);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	Ok(())
+}
+
+pub fn nanosleep() -> TestResult {
+	log!("Relative sleep");
+	let start = to_nano(&clock_gettime(CLOCK_MONOTONIC)?);
+	clock_nanosleep(CLOCK_MONOTONIC, 0, &from_nano(SLEEP_DURATION))?;
+	let end = to_nano(&clock_gettime(CLOCK_MONOTONIC)?);
+	test_assert!(end - start >= SLEEP_DURATION);
+
+	log!("Absolute sleep");
+	let target = end + SLEEP_DURATION;
+	clock_nanosleep(CLOCK_MONOTONIC, TIMER_ABSTIME, &from_nano(target))?;
+	test_assert!(to_nano(&clock_gettime(CLOCK_MONOTONIC)?) >= target);
+
+	log!("Absolute sleep in the past");
+	clock_nanosleep(CLOCK_MONOTONIC, TIMER_ABSTIME, &from_nano(start))?;
+
+	log!("Sleep for an invalid time");
+	let req = timespec {
+		tv_sec: 0,
+		tv_nsec: 1_000_000_000,
+	};
+	let res = clock_nanosleep(CLOCK_MONOTONIC, 0, &req);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	log!("Sleep on a CPU-time clock");
+	let res = clock_nanosleep(CLOCK_THREAD_CPUTIME_ID, 0, &from_nano(SLEEP_DURATION));
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	Ok(())
+}
+
+pub fn settime() -> TestResult {
+	log!("Compare gettimeofday with the real time clock");
+	let tv = gettimeofday()?;
+	let now = to_nano(&clock_gettime(CLOCK_REALTIME)?);
+	test_assert!(now / 1_000_000_000 - tv.tv_sec as u64 <= 1);
+
+	log!("Set the real time clock forward");
+	let monotonic = to_nano(&clock_gettime(CLOCK_MONOTONIC)?);
+	let target = now + 3600 * 1_000_000_000;
+	clock_settime(CLOCK_REALTIME, &from_nano(target))?;
+	test_assert!(gettimeofday()?.tv_sec as u64 >= target / 1_000_000_000);
+
+	log!("Set the real time clock back");
+	clock_settime(CLOCK_REALTIME, &from_nano(now))?;
+	let realtime = to_nano(&clock_gettime(CLOCK_REALTIME)?);
+	test_assert!(realtime >= now && realtime < target);
+	test_assert!(to_nano(&clock_gettime(CLOCK_MONOTONIC)?) >= monotonic);
+
+	log!("Set the monotonic clock");
+	let res = clock_settime(CLOCK_MONOTONIC, &from_nano(now));
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	Ok(())
+}
+
+pub fn timer() -> TestResult {
+	log!("Create a timer");
+	let timer = timer_create(CLOCK_MONOTONIC)?;
+	let curr = timer_gettime(timer)?;
+	test_assert!(to_nano(&curr.it_value) == 0);
+
+	log!("Arm the timer");
+	let interval = 1_000_000_000;
+	let value = 10_000_000_000;
+	let old = timer_settime(
+		timer,
+		0,
+		&itimerspec {
+			it_interval: from_nano(interval),
+			it_value: from_nano(value),
+		},
+	)?;
+	test_assert!(to_nano(&old.it_value) == 0);
+	let curr = timer_gettime(timer)?;
+	test_assert!(to_nano(&curr.it_interval) == interval);
+	let remaining = to_nano(&curr.it_value);
+	test_assert!(remaining > 0 && remaining <= value);
+
+	log!("Arm the timer with an invalid value");
+	let res = timer_settime(
+		timer,
+		0,
+		&itimerspec {
+			it_interval: from_nano(0),
+			it_value: timespec {
+				tv_sec: 0,
+				tv_nsec: 1_000_000_000,
+			},
+		},
+	);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	log!("Disarm the timer");
+	let old = timer_settime(
+		timer,
+		0,
+		&itimerspec {
+			it_interval: from_nano(0),
+			it_value: from_nano(0),
+		},
+	)?;
+	test_assert!(to_nano(&old.it_interval) == interval);
+	test_assert!(to_nano(&timer_gettime(timer)?.it_value) == 0);
+
+	timer_delete(timer)?;
+	Ok(())
+}
+
+pub fn rr_interval() -> TestResult {
+	log!("Get the round-robin interval of the current process");
+	let ts = sched_rr_get_interval(0)?;
+	test_assert!(to_nano(&ts) > 0);
+
+	log!("Get the round-robin interval of a nonexistent process");
+	let res = sched_rr_get_interval(i32::MAX);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(ESRCH)));
+
+	Ok(())
+}
+
+pub fn cputime() -> TestResult {
+	log!("CPU time of the current process");
+	let (start, before) = util::times()?;
+	burn(SLEEP_DURATION * 10)?;
+	let (end, after) = util::times()?;
+	test_assert!(end > start);
+	let ticks = (after.tms_utime + after.tms_stime) - (before.tms_utime + before.tms_stime);
+	test_assert!(ticks > 0);
+	let usage = util::getrusage(RUSAGE_SELF)?;
+	test_assert!(timeval_to_nano(&usage.ru_utime) + timeval_to_nano(&usage.ru_stime) > 0);
+
+	log!("CPU time of children");
+	let pid = util::fork()?;
+	if pid == 0 {
+		let code = if burn(SLEEP_DURATION * 10).is_ok() {
+			0
+		} else {
+			1
+		};
+		unsafe { libc::_exit(code) };
+	}
+	let status = util::waitpid(pid)?;
+	test_assert!(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	let (_, after) = util::times()?;
+	test_assert!(after.tms_cutime + after.tms_cstime > 0);
+	let usage = util::getrusage(RUSAGE_CHILDREN)?;
+	test_assert!(timeval_to_nano(&usage.ru_utime) + timeval_to_nano(&usage.ru_stime) > 0);
+
+	Ok(())
+}
+
+pub fn itimer() -> TestResult {
+	log!("Arm and disarm an alarm");
+	test_assert_eq!(unsafe { libc::alarm(10) }, 0);
+	let remaining = unsafe { libc::alarm(0) };
+	test_assert!((9..=10).contains(&remaining));
+
+	log!("Real time interval timer");
+	signal(SIGALRM, alarm_handler as *const () as usize)?;
+	let new = itimerval {
+		it_interval: from_micro(0),
+		it_value: from_micro(SLEEP_DURATION / 1000),
+	};
+	util::setitimer(ITIMER_REAL, &new)?;
+	let curr = util::getitimer(ITIMER_REAL)?;
+	test_assert!(curr.it_value.tv_sec > 0 || curr.it_value.tv_usec > 0);
+	let start = to_nano(&clock_gettime(CLOCK_MONOTONIC)?);
+	while !ALARM.load(Acquire) {
+		test_assert!(to_nano(&clock_gettime(CLOCK_MONOTONIC)?) - start < 1_000_000_000);
+	}
+	let curr = util::getitimer(ITIMER_REAL)?;
+	test_assert!(curr.it_value.tv_sec == 0 && curr.it_value.tv_usec == 0);
+	signal(SIGALRM, SIG_DFL)?;
+
+	log!("Profiling interval timer");
+	signal(SIGPROF, prof_handler as *const () as usize)?;
+	let new = itimerval {
+		it_interval: from_micro(0),
+		it_value: from_micro(SLEEP_DURATION / 1000),
+	};
+	util::setitimer(ITIMER_PROF, &new)?;
+	let start = to_nano(&clock_gettime(CLOCK_MONOTONIC)?);
+	while !PROF.load(Acquire) {
+		test_assert!(to_nano(&clock_gettime(CLOCK_MONOTONIC)?) - start < 5_000_000_000);
+	}
+	signal(SIGPROF, SIG_DFL)?;
+
+	log!("Invalid timer");
+	let res = util::getitimer(-1);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+	let new = itimerval {
+		it_interval: from_micro(0),
+		it_value: timeval {
+			tv_sec: 0,
+			tv_usec: 1_000_000,
+		},
+	};
+	let res = util::setitimer(ITIMER_REAL, &new);
+	test_assert!(matches!(res, Err(e) if e.raw_os_error() == Some(EINVAL)));
+
+	Ok(())
+}
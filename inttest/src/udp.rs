@@ -0,0 +1,158 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Tests of UDP sockets, as used by DHCP clients before the network is configured.
+
+use crate::{
+	log,
+	socket::{getsockopt, setsockopt},
+	test_assert, test_assert_eq,
+	util::TestResult,
+};
+use libc::{
+	AF_INET, EACCES, EADDRINUSE, INADDR_ANY, INADDR_BROADCAST, INADDR_LOOPBACK, MSG_DONTWAIT,
+	SO_BROADCAST, SO_REUSEADDR, SOCK_DGRAM, c_int, in_addr, sockaddr_in, socklen_t,
+};
+use std::{
+	io, mem,
+	os::fd::{AsRawFd, FromRawFd, OwnedFd},
+};
+
+/// The port of DHCP clients.
+const DHCP_CLIENT_PORT: u16 = 68;
+
+/// Returns the IPv4 socket address for `addr` and `port`, in host byte order.
+fn inet_addr(addr: u32, port: u16) -> sockaddr_in {
+	let mut sockaddr: sockaddr_in = unsafe { mem::zeroed() };
+	sockaddr.sin_family = AF_INET as _;
+	sockaddr.sin_port = port.to_be();
+	sockaddr.sin_addr = in_addr {
+		s_addr: addr.to_be(),
+	};
+	sockaddr
+}
+
+/// Creates a UDP socket.
+fn socket() -> io::Result<OwnedFd> {
+	let res = unsafe { libc::socket(AF_INET, SOCK_DGRAM, 0) };
+	if res >= 0 {
+		unsafe { Ok(OwnedFd::from_raw_fd(res)) }
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Binds `sock` to `addr`.
+fn bind(sock: c_int, addr: &sockaddr_in) -> io::Result<()> {
+	let res = unsafe { libc::bind(sock, addr as *const _ as _, mem::size_of_val(addr) as _) };
+	if res == 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Sends `data` on `sock` to `addr`.
+fn sendto(sock: c_int, data: &[u8], addr: &sockaddr_in) -> io::Result<usize> {
+	let res = unsafe {
+		libc::sendto(
+			sock,
+			data.as_ptr() as _,
+			data.len(),
+			0,
+			addr as *const _ as _,
+			mem::size_of_val(addr) as _,
+		)
+	};
+	if res >= 0 {
+		Ok(res as _)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Receives a datagram on `sock` without blocking, returning its data and the address of the
+/// sender.
+fn recvfrom(sock: c_int) -> io::Result<(Vec<u8>, sockaddr_in)> {
+	let mut buf = vec![0u8; 1024];
+	let mut addr: sockaddr_in = unsafe { mem::zeroed() };
+	let mut len = mem::size_of_val(&addr) as socklen_t;
+	let res = unsafe {
+		libc::recvfrom(
+			sock,
+			buf.as_mut_ptr() as _,
+			buf.len(),
+			MSG_DONTWAIT,
+			&mut addr as *mut _ as _,
+			&mut len,
+		)
+	};
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	assert_eq!(len as usize, mem::size_of_val(&addr));
+	buf.truncate(res as _);
+	Ok((buf, addr))
+}
+
+pub fn broadcast() -> TestResult {
+	log!("Bind the client port");
+	let client = socket()?;
+	setsockopt(client.as_raw_fd(), SO_REUSEADDR, 1)?;
+	bind(client.as_raw_fd(), &inet_addr(INADDR_ANY, DHCP_CLIENT_PORT))?;
+	let other = socket()?;
+	let err = bind(other.as_raw_fd(), &inet_addr(INADDR_ANY, DHCP_CLIENT_PORT)).unwrap_err();
+	test_assert_eq!(err.raw_os_error(), Some(EADDRINUSE));
+	let err = bind(
+		other.as_raw_fd(),
+		&inet_addr(INADDR_LOOPBACK, DHCP_CLIENT_PORT),
+	)
+	.unwrap_err();
+	test_assert_eq!(err.raw_os_error(), Some(EADDRINUSE));
+
+	log!("Broadcast without permission");
+	let sender = socket()?;
+	let dest = inet_addr(INADDR_BROADCAST, DHCP_CLIENT_PORT);
+	let err = sendto(sender.as_raw_fd(), b"discover", &dest).unwrap_err();
+	test_assert_eq!(err.raw_os_error(), Some(EACCES));
+
+	log!("Broadcast");
+	test_assert_eq!(getsockopt::<c_int>(sender.as_raw_fd(), SO_BROADCAST)?, 0);
+	setsockopt(sender.as_raw_fd(), SO_BROADCAST, 1)?;
+	test_assert_eq!(getsockopt::<c_int>(sender.as_raw_fd(), SO_BROADCAST)?, 1);
+	test_assert_eq!(sendto(sender.as_raw_fd(), b"discover", &dest)?, 8);
+	let (data, src) = recvfrom(client.as_raw_fd())?;
+	test_assert_eq!(data, b"discover");
+	// The sender has been bound to an ephemeral port
+	let port = u16::from_be(src.sin_port);
+	test_assert!(port >= 32768);
+	test_assert_eq!(src.sin_addr.s_addr, INADDR_ANY);
+
+	log!("Unicast reply");
+	let dest = inet_addr(INADDR_LOOPBACK, port);
+	test_assert_eq!(sendto(client.as_raw_fd(), b"offer", &dest)?, 5);
+	let (data, src) = recvfrom(sender.as_raw_fd())?;
+	test_assert_eq!(data, b"offer");
+	test_assert_eq!(u16::from_be(src.sin_port), DHCP_CLIENT_PORT);
+	test_assert_eq!(u32::from_be(src.sin_addr.s_addr), INADDR_LOOPBACK);
+	// Nothing else has been received
+	let res = recvfrom(client.as_raw_fd()).map(|(data, _)| data);
+	test_assert_eq!(res.unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+	Ok(())
+}
@@ -18,15 +18,21 @@
 
 //! Utility features.
 
-use libc::{gid_t, mode_t, pid_t, sighandler_t, uid_t};
+use libc::{
+	clock_t, clockid_t, dev_t, gid_t, itimerspec, itimerval, mode_t, pid_t, pollfd, rusage,
+	sighandler_t, siginfo_t, sigset_t, sigval, stack_t, timer_t, timespec, timeval, tms, uid_t,
+};
 use std::{
 	error::Error,
-	ffi::{CStr, CString, c_int, c_ulong, c_void},
+	ffi::{CStr, CString, c_int, c_short, c_uint, c_ulong, c_void},
 	io, mem,
-	os::unix::ffi::OsStrExt,
+	os::{
+		fd::{FromRawFd, OwnedFd},
+		unix::ffi::OsStrExt,
+	},
 	path::Path,
 	process::{Command, Stdio},
-	ptr::null,
+	ptr::{null, null_mut},
 };
 
 pub struct TestError(pub String);
@@ -87,6 +93,25 @@ pub fn chmod<P: AsRef<Path>>(path: P, mode: mode_t) -> io::Result<()> {
 	}
 }
 
+/// Returns the flags of the file descriptor `fd`.
+pub fn fd_flags(fd: c_int) -> io::Result<c_int> {
+	let res = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+	if res >= 0 {
+		Ok(res)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn close_range(first: c_uint, last: c_uint, flags: c_uint) -> io::Result<()> {
+	let res = unsafe { libc::syscall(libc::SYS_close_range, first, last, flags) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
 pub fn fchmod(fd: c_int, mode: mode_t) -> io::Result<()> {
 	let res = unsafe { libc::fchmod(fd, mode) };
 	if res >= 0 {
@@ -131,6 +156,90 @@ pub fn fstat(fd: c_int) -> io::Result<libc::stat> {
 	}
 }
 
+pub fn truncate<P: AsRef<Path>>(path: P, length: u64) -> io::Result<()> {
+	let path = CString::new(path.as_ref().as_os_str().as_bytes())?;
+	let res = unsafe { libc::truncate(path.as_ptr(), length as _) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn fstatat(dirfd: c_int, path: &CStr, flags: c_int) -> io::Result<libc::stat> {
+	unsafe {
+		let mut stat: libc::stat = mem::zeroed();
+		let res = libc::fstatat(dirfd, path.as_ptr(), &mut stat, flags);
+		if res >= 0 {
+			Ok(stat)
+		} else {
+			Err(io::Error::last_os_error())
+		}
+	}
+}
+
+pub fn mkdirat(dirfd: c_int, path: &CStr, mode: mode_t) -> io::Result<()> {
+	let res = unsafe { libc::mkdirat(dirfd, path.as_ptr(), mode) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn mknodat(dirfd: c_int, path: &CStr, mode: mode_t, dev: dev_t) -> io::Result<()> {
+	let res = unsafe { libc::mknodat(dirfd, path.as_ptr(), mode, dev) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn fchownat(
+	dirfd: c_int,
+	path: &CStr,
+	uid: uid_t,
+	gid: gid_t,
+	flags: c_int,
+) -> io::Result<()> {
+	let res = unsafe { libc::fchownat(dirfd, path.as_ptr(), uid, gid, flags) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn readlinkat(dirfd: c_int, path: &CStr) -> io::Result<Vec<u8>> {
+	let mut buf = vec![0u8; 4096];
+	let res = unsafe { libc::readlinkat(dirfd, path.as_ptr(), buf.as_mut_ptr() as _, buf.len()) };
+	if res >= 0 {
+		buf.truncate(res as _);
+		Ok(buf)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn renameat(
+	olddirfd: c_int,
+	oldpath: &CStr,
+	newdirfd: c_int,
+	newpath: &CStr,
+) -> io::Result<()> {
+	let res = unsafe { libc::renameat(olddirfd, oldpath.as_ptr(), newdirfd, newpath.as_ptr()) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn umask(mask: mode_t) -> mode_t {
+	unsafe { libc::umask(mask) }
+}
+
 pub fn mkfifo<P: AsRef<Path>>(path: P, mode: mode_t) -> io::Result<()> {
 	let path = CString::new(path.as_ref().as_os_str().as_bytes())?;
 	let res = unsafe { libc::mkfifo(path.as_ptr(), mode) };
@@ -156,6 +265,24 @@ pub fn mount(
 	}
 }
 
+pub fn mount_setattr(path: &CStr, flags: c_uint, attr: &libc::mount_attr) -> io::Result<()> {
+	let res = unsafe {
+		libc::syscall(
+			libc::SYS_mount_setattr,
+			libc::AT_FDCWD,
+			path.as_ptr(),
+			flags,
+			attr as *const libc::mount_attr,
+			size_of::<libc::mount_attr>(),
+		)
+	};
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
 pub fn umount(src: &CStr) -> io::Result<()> {
 	let res = unsafe { libc::umount(src.as_ptr()) };
 	if res >= 0 {
@@ -183,6 +310,16 @@ pub fn setegid(gid: gid_t) -> io::Result<()> {
 	}
 }
 
+/// Sets the filesystem user ID, returning the previous one.
+pub fn setfsuid(uid: uid_t) -> uid_t {
+	unsafe { libc::setfsuid(uid) as _ }
+}
+
+/// Sets the filesystem group ID, returning the previous one.
+pub fn setfsgid(gid: gid_t) -> gid_t {
+	unsafe { libc::setfsgid(gid) as _ }
+}
+
 /// Executes the given code while unprivileged
 pub fn unprivileged<F: FnOnce() -> R, R>(f: F) -> io::Result<R> {
 	seteuid(1000)?;
@@ -218,6 +355,106 @@ pub fn signal(signum: c_int, handler: sighandler_t) -> io::Result<()> {
 	}
 }
 
+/// Changes the signal mask with `how` and `set`, returning the previous mask.
+pub fn sigprocmask(how: c_int, set: Option<&sigset_t>) -> io::Result<sigset_t> {
+	let set = set.map(|set| set as *const _).unwrap_or(null());
+	unsafe {
+		let mut old: sigset_t = mem::zeroed();
+		let res = libc::sigprocmask(how, set, &mut old);
+		if res >= 0 {
+			Ok(old)
+		} else {
+			Err(io::Error::last_os_error())
+		}
+	}
+}
+
+/// Replaces the alternate signal stack with `ss`, returning the previous one.
+pub fn sigaltstack(ss: Option<&stack_t>) -> io::Result<stack_t> {
+	let ss = ss.map(|ss| ss as *const _).unwrap_or(null());
+	unsafe {
+		let mut old: stack_t = mem::zeroed();
+		let res = libc::sigaltstack(ss, &mut old);
+		if res >= 0 {
+			Ok(old)
+		} else {
+			Err(io::Error::last_os_error())
+		}
+	}
+}
+
+/// Sets `handler` for `signum`, with the given `flags`.
+pub fn sigaction(signum: c_int, handler: sighandler_t, flags: c_int) -> io::Result<()> {
+	unsafe {
+		let mut act: libc::sigaction = mem::zeroed();
+		act.sa_sigaction = handler;
+		act.sa_flags = flags;
+		let res = libc::sigaction(signum, &act, null_mut());
+		if res >= 0 {
+			Ok(())
+		} else {
+			Err(io::Error::last_os_error())
+		}
+	}
+}
+
+unsafe extern "C" {
+	#[link_name = "sigqueue"]
+	fn libc_sigqueue(pid: pid_t, sig: c_int, value: sigval) -> c_int;
+}
+
+pub fn sigqueue(pid: pid_t, sig: c_int, value: usize) -> io::Result<()> {
+	let value = sigval {
+		sival_ptr: value as _,
+	};
+	let res = unsafe { libc_sigqueue(pid, sig, value) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Returns the set of pending signals.
+pub fn sigpending() -> io::Result<sigset_t> {
+	unsafe {
+		let mut set: sigset_t = mem::zeroed();
+		let res = libc::sigpending(&mut set);
+		if res >= 0 {
+			Ok(set)
+		} else {
+			Err(io::Error::last_os_error())
+		}
+	}
+}
+
+/// Waits for a signal in `set` for at most `timeout`, returning its information.
+pub fn sigtimedwait(set: &sigset_t, timeout: &timespec) -> io::Result<siginfo_t> {
+	unsafe {
+		let mut info: siginfo_t = mem::zeroed();
+		let res = libc::sigtimedwait(set, &mut info, timeout);
+		if res >= 0 {
+			Ok(info)
+		} else {
+			Err(io::Error::last_os_error())
+		}
+	}
+}
+
+/// Waits for a state change of the children designated by `idtype` and `id`, returning its
+/// information.
+pub fn waitid(idtype: libc::idtype_t, id: libc::id_t, options: c_int) -> io::Result<siginfo_t> {
+	unsafe {
+		let mut info: siginfo_t = mem::zeroed();
+		let res = libc::waitid(idtype, id, &mut info, options);
+		if res >= 0 {
+			Ok(info)
+		} else {
+			Err(io::Error::last_os_error())
+		}
+	}
+}
+
 pub fn kill(pid: pid_t, sig: c_int) -> io::Result<()> {
 	let res = unsafe { libc::kill(pid, sig) };
 	if res >= 0 {
@@ -227,8 +464,27 @@ pub fn kill(pid: pid_t, sig: c_int) -> io::Result<()> {
 	}
 }
 
-pub fn finit_module(fd: c_int) -> io::Result<()> {
-	let res = unsafe { libc::syscall(libc::SYS_finit_module, fd, null::<()>(), 0) };
+pub fn fork() -> io::Result<pid_t> {
+	let res = unsafe { libc::fork() };
+	if res >= 0 {
+		Ok(res)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn waitpid(pid: pid_t) -> io::Result<c_int> {
+	let mut status = 0;
+	let res = unsafe { libc::waitpid(pid, &mut status, 0) };
+	if res >= 0 {
+		Ok(status)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn finit_module(fd: c_int, params: &CStr) -> io::Result<()> {
+	let res = unsafe { libc::syscall(libc::SYS_finit_module, fd, params.as_ptr(), 0) };
 	if res == 0 {
 		Ok(())
 	} else {
@@ -244,3 +500,361 @@ pub fn delete_module(name: &CStr) -> io::Result<()> {
 		Err(io::Error::last_os_error())
 	}
 }
+
+pub fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+	let mut fds = [0; 2];
+	let res = unsafe { libc::pipe(fds.as_mut_ptr()) };
+	if res >= 0 {
+		unsafe { Ok((OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))) }
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn socketpair(domain: c_int, ty: c_int) -> io::Result<(OwnedFd, OwnedFd)> {
+	let mut fds = [0; 2];
+	let res = unsafe { libc::socketpair(domain, ty, 0, fds.as_mut_ptr()) };
+	if res >= 0 {
+		unsafe { Ok((OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))) }
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn fanotify_init(flags: c_uint, event_f_flags: c_uint) -> io::Result<OwnedFd> {
+	let res = unsafe { libc::fanotify_init(flags, event_f_flags) };
+	if res >= 0 {
+		unsafe { Ok(OwnedFd::from_raw_fd(res)) }
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn fanotify_mark(fd: c_int, flags: c_uint, mask: u64, path: &CStr) -> io::Result<()> {
+	let res = unsafe { libc::fanotify_mark(fd, flags, mask, libc::AT_FDCWD, path.as_ptr()) };
+	if res == 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// Polls the file descriptor `fd` for `events` without blocking, and returns the events that
+/// occurred.
+pub fn poll(fd: c_int, events: c_short) -> io::Result<c_short> {
+	let mut fds = [pollfd {
+		fd,
+		events,
+		revents: 0,
+	}];
+	let res = unsafe { libc::poll(fds.as_mut_ptr(), 1, 0) };
+	if res >= 0 {
+		Ok(fds[0].revents)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn clock_getres(clock: clockid_t) -> io::Result<timespec> {
+	let mut res: timespec = unsafe { mem::zeroed() };
+	let ret = unsafe { libc::clock_getres(clock, &mut res) };
+	if ret >= 0 {
+		Ok(res)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn clock_gettime(clock: clockid_t) -> io::Result<timespec> {
+	let mut ts: timespec = unsafe { mem::zeroed() };
+	let res = unsafe { libc::clock_gettime(clock, &mut ts) };
+	if res >= 0 {
+		Ok(ts)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn clock_nanosleep(clock: clockid_t, flags: c_int, req: &timespec) -> io::Result<()> {
+	// The error is returned directly instead of through `errno`
+	let res = unsafe { libc::clock_nanosleep(clock, flags, req, null_mut()) };
+	if res == 0 {
+		Ok(())
+	} else {
+		Err(io::Error::from_raw_os_error(res))
+	}
+}
+
+pub fn timer_create(clock: clockid_t) -> io::Result<timer_t> {
+	let mut timer: timer_t = null_mut();
+	let res = unsafe { libc::timer_create(clock, null_mut(), &mut timer) };
+	if res >= 0 {
+		Ok(timer)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn timer_settime(timer: timer_t, flags: c_int, new: &itimerspec) -> io::Result<itimerspec> {
+	let mut old: itimerspec = unsafe { mem::zeroed() };
+	let res = unsafe { libc::timer_settime(timer, flags, new, &mut old) };
+	if res >= 0 {
+		Ok(old)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn timer_gettime(timer: timer_t) -> io::Result<itimerspec> {
+	let mut curr: itimerspec = unsafe { mem::zeroed() };
+	let res = unsafe { libc::timer_gettime(timer, &mut curr) };
+	if res >= 0 {
+		Ok(curr)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn timer_delete(timer: timer_t) -> io::Result<()> {
+	let res = unsafe { libc::timer_delete(timer) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn sched_rr_get_interval(pid: pid_t) -> io::Result<timespec> {
+	let mut ts: timespec = unsafe { mem::zeroed() };
+	let res = unsafe { libc::sched_rr_get_interval(pid, &mut ts) };
+	if res >= 0 {
+		Ok(ts)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn times() -> io::Result<(clock_t, tms)> {
+	let mut buf: tms = unsafe { mem::zeroed() };
+	let res = unsafe { libc::times(&mut buf) };
+	if res != -1 {
+		Ok((res, buf))
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn getrusage(who: c_int) -> io::Result<rusage> {
+	let mut usage: rusage = unsafe { mem::zeroed() };
+	let res = unsafe { libc::getrusage(who, &mut usage) };
+	if res >= 0 {
+		Ok(usage)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn getitimer(which: c_int) -> io::Result<itimerval> {
+	let mut curr: itimerval = unsafe { mem::zeroed() };
+	let res = unsafe { libc::syscall(libc::SYS_getitimer, which, &mut curr) };
+	if res >= 0 {
+		Ok(curr)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn setitimer(which: c_int, new: &itimerval) -> io::Result<itimerval> {
+	let mut old: itimerval = unsafe { mem::zeroed() };
+	let res = unsafe { libc::syscall(libc::SYS_setitimer, which, new, &mut old) };
+	if res >= 0 {
+		Ok(old)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn sync_file_range(fd: c_int, offset: i64, nbytes: i64, flags: c_uint) -> io::Result<()> {
+	let res = unsafe { libc::sync_file_range(fd, offset, nbytes, flags) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn clock_settime(clock: clockid_t, ts: &timespec) -> io::Result<()> {
+	let res = unsafe { libc::clock_settime(clock, ts) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn gettimeofday() -> io::Result<timeval> {
+	let mut tv: timeval = unsafe { mem::zeroed() };
+	let res = unsafe { libc::gettimeofday(&mut tv, null_mut()) };
+	if res >= 0 {
+		Ok(tv)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn clone(
+	f: extern "C" fn(*mut c_void) -> c_int,
+	stack: &mut [u8],
+	flags: c_int,
+	arg: *mut c_void,
+) -> io::Result<pid_t> {
+	// The stack grows downwards
+	let stack_top = stack.as_mut_ptr_range().end as usize & !0xf;
+	let res = unsafe { libc::clone(f, stack_top as _, flags, arg) };
+	if res >= 0 {
+		Ok(res)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn prctl(
+	op: c_int,
+	arg2: c_ulong,
+	arg3: c_ulong,
+	arg4: c_ulong,
+	arg5: c_ulong,
+) -> io::Result<()> {
+	let res = unsafe { libc::prctl(op, arg2, arg3, arg4, arg5) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// A legacy asynchronous I/O request.
+#[repr(C)]
+#[derive(Default)]
+pub struct IoCb {
+	pub aio_data: u64,
+	pub aio_key: u32,
+	pub aio_rw_flags: i32,
+	pub aio_lio_opcode: u16,
+	pub aio_reqprio: i16,
+	pub aio_fildes: u32,
+	pub aio_buf: u64,
+	pub aio_nbytes: u64,
+	pub aio_offset: i64,
+	pub aio_reserved2: u64,
+	pub aio_flags: u32,
+	pub aio_resfd: u32,
+}
+
+/// The completion event of a legacy asynchronous I/O request.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct IoEvent {
+	pub data: u64,
+	pub obj: u64,
+	pub res: i64,
+	pub res2: i64,
+}
+
+pub fn io_setup(nr_events: c_uint) -> io::Result<c_ulong> {
+	let mut ctx: c_ulong = 0;
+	let res = unsafe { libc::syscall(libc::SYS_io_setup, nr_events, &mut ctx) };
+	if res >= 0 {
+		Ok(ctx)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn io_destroy(ctx: c_ulong) -> io::Result<()> {
+	let res = unsafe { libc::syscall(libc::SYS_io_destroy, ctx) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn io_submit(ctx: c_ulong, iocbs: &[&IoCb]) -> io::Result<usize> {
+	let res = unsafe { libc::syscall(libc::SYS_io_submit, ctx, iocbs.len(), iocbs.as_ptr()) };
+	if res >= 0 {
+		Ok(res as _)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn io_getevents(
+	ctx: c_ulong,
+	min_nr: usize,
+	events: &mut [IoEvent],
+	timeout: Option<&timespec>,
+) -> io::Result<usize> {
+	let timeout = timeout.map(|ts| ts as *const timespec).unwrap_or(null());
+	let res = unsafe {
+		libc::syscall(
+			libc::SYS_io_getevents,
+			ctx,
+			min_nr,
+			events.len(),
+			events.as_mut_ptr(),
+			timeout,
+		)
+	};
+	if res >= 0 {
+		Ok(res as _)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// A timestamp in the extended status of a file.
+#[repr(C)]
+#[derive(Default)]
+pub struct StatxTimestamp {
+	pub tv_sec: i64,
+	pub tv_nsec: u32,
+	__reserved: i32,
+}
+
+/// The extended status of a file.
+#[repr(C)]
+#[derive(Default)]
+pub struct Statx {
+	pub stx_mask: u32,
+	pub stx_blksize: u32,
+	pub stx_attributes: u64,
+	pub stx_nlink: u32,
+	pub stx_uid: u32,
+	pub stx_gid: u32,
+	pub stx_mode: u16,
+	__spare0: u16,
+	pub stx_ino: u64,
+	pub stx_size: u64,
+	pub stx_blocks: u64,
+	pub stx_attributes_mask: u64,
+	pub stx_atime: StatxTimestamp,
+	pub stx_btime: StatxTimestamp,
+	pub stx_ctime: StatxTimestamp,
+	pub stx_mtime: StatxTimestamp,
+	pub stx_rdev_major: u32,
+	pub stx_rdev_minor: u32,
+	pub stx_dev_major: u32,
+	pub stx_dev_minor: u32,
+	__spare2: [u64; 14],
+}
+
+pub fn statx(fd: c_int, path: &CStr, flags: c_int, mask: c_uint) -> io::Result<Statx> {
+	let mut buf = Statx::default();
+	let res = unsafe { libc::syscall(libc::SYS_statx, fd, path.as_ptr(), flags, mask, &mut buf) };
+	if res >= 0 {
+		Ok(buf)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
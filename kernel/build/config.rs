@@ -18,11 +18,13 @@
 
 //! This file implements the configuration file for compilation.
 
+use flate2::{Compression, write::GzEncoder};
 use serde::Deserialize;
 use std::{
 	any::type_name,
-	fs,
+	env, fs,
 	io::{self, Write},
+	path::PathBuf,
 	process::exit,
 };
 
@@ -107,6 +109,10 @@ struct ConfigDebug {
 struct ConfigMemory {
 	/// The timeout, in milliseconds, after which a dirty page may be written back to disk.
 	writeback_timeout: u64,
+	/// If enabled, kernel memory is filled with zeros on allocation by default.
+	init_on_alloc: bool,
+	/// If enabled, kernel memory is filled with zeros when freed by default.
+	init_on_free: bool,
 }
 
 /// Kernel panic section of the configuration file
@@ -136,6 +142,10 @@ pub struct Config {
 	panic: ConfigPanic,
 	/// TTY configuration
 	pub tty: TTYConfig,
+
+	/// The content of the configuration file, as read.
+	#[serde(skip)]
+	raw: String,
 }
 
 impl Config {
@@ -153,7 +163,21 @@ impl Config {
 			Err(e) if e.kind() == io::ErrorKind::NotFound => fs::read_to_string(FILE_DEFAULT)?,
 			Err(e) => return Err(e),
 		};
-		toml::from_str(&config_str).map_err(|e| io::Error::other(e.to_string()))
+		let mut config: Self =
+			toml::from_str(&config_str).map_err(|e| io::Error::other(e.to_string()))?;
+		config.raw = config_str;
+		Ok(config)
+	}
+
+	/// Writes the configuration file, compressed with gzip, to `config.gz` in the output
+	/// directory, so that it can be embedded in the kernel image.
+	pub fn embed(&self) -> io::Result<()> {
+		let out_dir = env::var_os("OUT_DIR").expect("OUT_DIR environment variable not set");
+		let file = fs::File::create(PathBuf::from(out_dir).join("config.gz"))?;
+		let mut encoder = GzEncoder::new(file, Compression::best());
+		encoder.write_all(self.raw.as_bytes())?;
+		encoder.finish()?;
+		Ok(())
 	}
 
 	/// Sets the crate's cfg flags and generates the const files according to the configuration.
@@ -165,6 +189,8 @@ impl Config {
 		}
 
 		generate_const_file!(self.memory.writeback_timeout);
+		generate_const_file!(self.memory.init_on_alloc);
+		generate_const_file!(self.memory.init_on_free);
 		generate_const_file!(self.panic.callstack_depth);
 
 		generate_cfg_flag!(self.tty.enabled);
@@ -24,6 +24,7 @@ pub mod config;
 pub mod font;
 pub mod target;
 pub mod util;
+pub mod version;
 
 use crate::{config::Config, target::Target};
 use std::{env, path::PathBuf};
@@ -72,6 +73,8 @@ fn main() {
 	let target = Target::from_env(&env).expect("cannot retrieve target");
 	let config = Config::read().expect("failed to read build configuration file");
 	config.set_cfg(env.is_debug());
+	config.embed().expect("failed to embed build configuration");
+	version::set_env();
 	// Build TTY font, if enabled
 	if config.tty.enabled {
 		font::build(&config.tty.font).expect("failed to build font");
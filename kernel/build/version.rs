@@ -0,0 +1,111 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Identification of the kernel build.
+//!
+//! The build script passes the following environment variables to the compiler:
+//! - `KERNEL_RELEASE`: the crate's version, followed by the abbreviated hash of the git commit the
+//!   kernel is built from, and a `-dirty` suffix if the working tree has uncommitted changes
+//! - `KERNEL_BUILD_VERSION`: the build number and the build time, in UTC
+//!
+//! For reproducible builds, the build time can be overridden with the `SOURCE_DATE_EPOCH`
+//! environment variable.
+
+use std::{
+	env,
+	path::Path,
+	process::Command,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Runs `git` with the given arguments and returns its trimmed output.
+///
+/// If the command fails (for example if the sources are not in a git repository), the function
+/// returns `None`.
+fn git(args: &[&str]) -> Option<String> {
+	let output = Command::new("git").args(args).output().ok()?;
+	if !output.status.success() {
+		return None;
+	}
+	let out = String::from_utf8(output.stdout).ok()?;
+	Some(out.trim().to_owned())
+}
+
+/// Returns the abbreviated hash of the current git commit, with a `-dirty` suffix if the working
+/// tree has been modified.
+fn commit() -> Option<String> {
+	let hash = git(&["rev-parse", "--short=12", "HEAD"])?;
+	let dirty = git(&["status", "--porcelain", "--untracked-files=no"])
+		.map(|s| !s.is_empty())
+		.unwrap_or(false);
+	Some(if dirty { format!("{hash}-dirty") } else { hash })
+}
+
+/// Returns the build timestamp, in seconds since the Unix epoch.
+fn timestamp() -> u64 {
+	println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+	env::var("SOURCE_DATE_EPOCH")
+		.ok()
+		.and_then(|s| s.parse().ok())
+		.unwrap_or_else(|| {
+			SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map(|d| d.as_secs())
+				.unwrap_or(0)
+		})
+}
+
+/// Formats `ts`, in seconds since the Unix epoch, as a `YYYY-MM-DD hh:mm:ss UTC` date.
+fn format_date(ts: u64) -> String {
+	let days = (ts / 86400) as i64;
+	let secs = ts % 86400;
+	// Conversion from days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+	let z = days + 719468;
+	let era = z.div_euclid(146097);
+	let doe = z.rem_euclid(146097);
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = yoe + era * 400 + (month <= 2) as i64;
+	format!(
+		"{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+		secs / 3600,
+		(secs / 60) % 60,
+		secs % 60
+	)
+}
+
+/// Passes the build identification to the compiler.
+pub fn set_env() {
+	// Rebuild when the current commit or the working tree changes
+	for path in ["../.git/HEAD", "../.git/index"] {
+		if Path::new(path).exists() {
+			println!("cargo:rerun-if-changed={path}");
+		}
+	}
+	let version = env::var("CARGO_PKG_VERSION").unwrap();
+	let release = match commit() {
+		Some(commit) => format!("{version}-{commit}"),
+		None => version,
+	};
+	let date = format_date(timestamp());
+	println!("cargo:rustc-env=KERNEL_RELEASE={release}");
+	println!("cargo:rustc-env=KERNEL_BUILD_VERSION=#1 {date}");
+}
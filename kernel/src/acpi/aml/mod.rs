@@ -283,3 +283,121 @@ pub struct AMLCode {
 pub fn parse(_aml: &[u8]) -> Result<AMLCode, String> {
 	todo!();
 }
+
+/// Parses a `PkgLength` at the beginning of `b`.
+///
+/// On success, the function returns the encoded length and the number of bytes used by the
+/// encoding.
+fn parse_pkg_length(b: &[u8]) -> Option<(usize, usize)> {
+	let lead = *b.first()?;
+	let follow = (lead >> 6) as usize;
+	if follow == 0 {
+		return Some(((lead & 0x3f) as usize, 1));
+	}
+	let bytes = b.get(1..=follow)?;
+	let len = bytes
+		.iter()
+		.enumerate()
+		.fold((lead & 0xf) as usize, |len, (i, b)| {
+			len | ((*b as usize) << (4 + i * 8))
+		});
+	Some((len, follow + 1))
+}
+
+/// Parses a constant integer at the beginning of `b`.
+///
+/// On success, the function returns the value and the number of bytes used by the encoding.
+fn parse_const_integer(b: &[u8]) -> Option<(u64, usize)> {
+	match *b.first()? {
+		ZERO_OP => Some((0, 1)),
+		ONE_OP => Some((1, 1)),
+		BYTE_PREFIX => Some((*b.get(1)? as u64, 2)),
+		WORD_PREFIX => Some((u16::from_le_bytes(b.get(1..3)?.try_into().ok()?) as u64, 3)),
+		DWORD_PREFIX => Some((u32::from_le_bytes(b.get(1..5)?.try_into().ok()?) as u64, 5)),
+		QWORD_PREFIX => Some((u64::from_le_bytes(b.get(1..9)?.try_into().ok()?), 9)),
+		_ => None,
+	}
+}
+
+/// Looks up the `\_Sx` object for the sleep state `state` in `aml`, and returns the values to
+/// write in the `SLP_TYP` fields of the PM1a and PM1b control registers to enter this state.
+///
+/// Since there is no AML interpreter yet, the function only supports the common case where the
+/// object is defined with a `Name` at the top level, holding a package of constant integers.
+///
+/// If the object cannot be found, the function returns `None`.
+pub fn find_sleep_type(aml: &[u8], state: u8) -> Option<(u8, u8)> {
+	let name = [b'_', b'S', b'0' + state, b'_'];
+	(0..aml.len()).find_map(|i| {
+		let rest = aml[i..].strip_prefix(&name)?;
+		// Check the name is defined by a `Name` operator
+		if !matches!(aml[..i], [.., NAME_OP, b'\\'] | [.., NAME_OP]) {
+			return None;
+		}
+		let rest = rest.strip_prefix(&[PACKAGE_OP])?;
+		let (_, len) = parse_pkg_length(rest)?;
+		// Skip `NumElements`
+		let rest = rest.get((len + 1)..)?;
+		let (slp_typ_a, len) = parse_const_integer(rest)?;
+		let (slp_typ_b, _) = parse_const_integer(&rest[len..]).unwrap_or((0, 0));
+		Some((slp_typ_a as _, slp_typ_b as _))
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn aml_pkg_length() {
+		assert_eq!(parse_pkg_length(&[0x0a]), Some((0x0a, 1)));
+		assert_eq!(parse_pkg_length(&[0x4a, 0x12]), Some((0x12a, 2)));
+		assert_eq!(parse_pkg_length(&[0x81, 0x34, 0x12]), Some((0x12341, 3)));
+		assert_eq!(parse_pkg_length(&[0x81, 0x34]), None);
+	}
+
+	#[test_case]
+	fn aml_sleep_type() {
+		// Name (\_S5, Package (0x04) { 0x05, Zero, Zero, Zero }), preceded by unrelated code
+		let aml = [
+			0x14,
+			0x05,
+			b'_',
+			b'S',
+			b'5',
+			b'_',
+			0x00,
+			0x08,
+			b'\\',
+			b'_',
+			b'S',
+			b'5',
+			b'_',
+			PACKAGE_OP,
+			0x07,
+			0x04,
+			BYTE_PREFIX,
+			0x05,
+			ZERO_OP,
+			ZERO_OP,
+			ZERO_OP,
+		];
+		assert_eq!(find_sleep_type(&aml, 5), Some((5, 0)));
+		assert_eq!(find_sleep_type(&aml, 3), None);
+		// Name (_S5_, Package (0x02) { One, 0x07 })
+		let aml = [
+			NAME_OP,
+			b'_',
+			b'S',
+			b'5',
+			b'_',
+			PACKAGE_OP,
+			0x05,
+			0x02,
+			ONE_OP,
+			BYTE_PREFIX,
+			0x07,
+		];
+		assert_eq!(find_sleep_type(&aml, 5), Some((1, 7)));
+	}
+}
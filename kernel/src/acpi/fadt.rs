@@ -20,7 +20,10 @@
 
 use super::{GenericAddr, Table, TableHdr, dsdt::Dsdt};
 use crate::memory::PhysAddr;
-use core::slice;
+use core::{mem::offset_of, slice};
+
+/// FADT flag: the reset register is supported.
+const RESET_REG_SUP: u32 = 1 << 10;
 
 /// The Fixed ACPI Description Table.
 ///
@@ -93,9 +96,26 @@ pub struct Fadt {
 }
 
 impl Fadt {
+	/// Tells whether the table is large enough to contain the fields up to the offset `end`.
+	///
+	/// Tables from older revisions of ACPI are shorter.
+	fn contains(&self, end: usize) -> bool {
+		self.header.length as usize >= end
+	}
+
+	/// Returns the reset register and the value to write to it to reset the system.
+	///
+	/// If the reset register is not supported, the function returns `None`.
+	pub fn get_reset_reg(&self) -> Option<(&GenericAddr, u8)> {
+		if !self.contains(offset_of!(Self, reserved3)) || self.flags & RESET_REG_SUP == 0 {
+			return None;
+		}
+		Some((&self.reset_reg, self.reset_value))
+	}
+
 	/// Returns a reference to the DSDT if it exists.
 	pub fn get_dsdt(&self) -> Option<&Dsdt> {
-		let dsdt = if self.x_dsdt != 0 {
+		let dsdt = if self.contains(offset_of!(Self, x_pm1a_event_block)) && self.x_dsdt != 0 {
 			self.x_dsdt
 		} else {
 			self.dsdt as _
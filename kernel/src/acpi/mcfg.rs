@@ -0,0 +1,61 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! ACPI's PCI Express Memory-mapped Configuration (MCFG) table handling.
+
+use super::{Table, TableHdr};
+use core::{mem::size_of, slice};
+
+/// The PCI Express Memory-mapped Configuration table.
+#[repr(C, packed)]
+pub struct Mcfg {
+	/// The table's header.
+	pub header: TableHdr,
+	reserved: u64,
+}
+
+impl Mcfg {
+	/// Returns the list of configuration space allocations.
+	pub fn entries(&self) -> &[ConfigSpaceAlloc] {
+		let len = (self.header.length as usize).saturating_sub(size_of::<Self>())
+			/ size_of::<ConfigSpaceAlloc>();
+		unsafe {
+			let start = (self as *const Self).add(1) as *const ConfigSpaceAlloc;
+			slice::from_raw_parts(start, len)
+		}
+	}
+}
+
+impl Table for Mcfg {
+	const SIGNATURE: &'static [u8; 4] = b"MCFG";
+}
+
+/// Location of the Enhanced Configuration Access Mechanism (ECAM) space for a range of buses.
+#[derive(Debug)]
+#[repr(C, packed)]
+pub struct ConfigSpaceAlloc {
+	/// Physical address of the configuration space
+	pub base_addr: u64,
+	/// PCI segment group number
+	pub segment: u16,
+	/// First bus number decoded by the host bridge
+	pub start_bus: u8,
+	/// Last bus number decoded by the host bridge
+	pub end_bus: u8,
+	reserved: u32,
+}
@@ -22,6 +22,8 @@
 use crate::{
 	acpi::rsdt::Sdt,
 	memory::{KERNEL_BEGIN, PhysAddr},
+	multiboot::BOOT_INFO,
+	sync::once::OnceInit,
 };
 use core::{
 	hint::{likely, unlikely},
@@ -29,12 +31,15 @@ use core::{
 	slice,
 };
 use fadt::Fadt;
+use power::PowerInfo;
 use utils::errno::AllocResult;
 
 mod aml;
 pub mod dsdt;
 pub mod fadt;
 pub mod madt;
+pub mod mcfg;
+pub mod power;
 pub mod rsdt;
 
 /// The beginning physical address of scan for the RSDP
@@ -45,6 +50,9 @@ pub const RSDP_SCAN_END: usize = 0xfffff;
 /// The signature of the RSDP.
 const RSDP_SIGNATURE: &[u8] = b"RSD PTR ";
 
+/// Power management information.
+static POWER_INFO: OnceInit<PowerInfo> = unsafe { OnceInit::new() };
+
 /// Checks the checksum for `obj`.
 ///
 /// `len` is the size of the object in bytes.
@@ -111,11 +119,10 @@ impl Rsdp {
 			return false;
 		}
 		// Check XSDT
-		if self.revision >= 2 {
-			if let Some(v2) = self.as_v2() {
-				if v2.xsdt_address == 0 {
-					return false;
-				}
+		if let Some(v2) = self.as_v2() {
+			let checksum_valid = unsafe { check_checksum(v2, size_of::<Rsdp2>()) };
+			if !checksum_valid || v2.xsdt_address == 0 {
+				return false;
 			}
 		}
 		true
@@ -222,7 +229,13 @@ pub struct GenericAddr {
 }
 
 /// Finds the [`Rsdp`] and returns a reference to it.
+///
+/// The copy given by the bootloader is used if present. Otherwise, the BIOS memory area is
+/// scanned.
 unsafe fn find_rsdp() -> Option<&'static Rsdp> {
+	if let Some(rsdp) = BOOT_INFO.rsdp {
+		return rsdp.kernel_to_virtual().map(|ptr| &*ptr.as_ptr());
+	}
 	let begin = (KERNEL_BEGIN + RSDP_SCAN_BEGIN).as_ptr();
 	let end = (KERNEL_BEGIN + RSDP_SCAN_END).as_ptr();
 	let mut ptr = begin;
@@ -253,24 +266,26 @@ pub fn get_table<T: Table>() -> Option<&'static T> {
 /// This function must be called only once, at boot.
 pub(crate) fn init() -> AllocResult<()> {
 	let rsdp = unsafe { find_rsdp() };
-	let Some(rsdp) = rsdp else {
-		return Ok(());
-	};
-	if unlikely(!rsdp.check()) {
-		panic!("ACPI: invalid RSDP");
+	let fadt = rsdp.and_then(|rsdp| {
+		if unlikely(!rsdp.check()) {
+			panic!("ACPI: invalid RSDP");
+		}
+		get_table::<Fadt>()
+	});
+	let power_info = fadt.map(PowerInfo::new).unwrap_or_default();
+	unsafe {
+		OnceInit::init(&POWER_INFO, power_info);
 	}
-	// FIXME: pointer issue (bad alignment?)
-	let Some(_fadt) = get_table::<Fadt>() else {
-		return Ok(());
-	};
-	/*if let Some(dsdt) = fadt.get_dsdt() {
-		// Parse AML code
-		let _aml = dsdt.get_aml();
-		// TODO let _ast = aml::parse(aml);
-	}*/
 	Ok(())
 }
 
+/// Returns power management information.
+///
+/// If ACPI is not present, the returned structure does not support any operation.
+pub fn power_info() -> &'static PowerInfo {
+	&POWER_INFO
+}
+
 /// Returns the address of the RTC's century register.
 ///
 /// If not present, the function returns `0`.
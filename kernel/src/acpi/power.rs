@@ -0,0 +1,133 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! ACPI power management, allowing to power off the system by entering the `S5` sleep state, and
+//! to reset it through the reset register.
+//!
+//! Entering a sleep state should be preceded by the execution of the `\_PTS` AML method. Since
+//! there is no AML interpreter yet, this step is skipped, which is sufficient on most systems.
+
+use super::{aml, fadt::Fadt};
+use crate::arch::x86::io::{inw, outb, outw};
+
+/// Address space of a [`super::GenericAddr`]: I/O ports.
+const ADDR_SPACE_IO: u8 = 1;
+
+/// PM1 control register: ACPI mode is enabled.
+const PM1_SCI_EN: u16 = 1 << 0;
+/// PM1 control register: offset of the sleep type field.
+const PM1_SLP_TYP_SHIFT: u16 = 10;
+/// PM1 control register: mask of the sleep type field.
+const PM1_SLP_TYP_MASK: u16 = 0b111 << PM1_SLP_TYP_SHIFT;
+/// PM1 control register: enter the sleep state.
+const PM1_SLP_EN: u16 = 1 << 13;
+
+/// The number of times `SCI_EN` is polled when enabling ACPI mode, before giving up.
+const ACPI_ENABLE_POLL: usize = 1_000_000;
+
+/// Information retrieved from the ACPI tables, required for power management.
+#[derive(Debug, Default)]
+pub struct PowerInfo {
+	/// The I/O port of the System Management Interrupt command register.
+	smi_cmd: u16,
+	/// The value to write to `smi_cmd` to enable ACPI mode.
+	acpi_enable: u8,
+	/// The I/O port of the PM1a control register.
+	pm1a_cnt: u16,
+	/// The I/O port of the PM1b control register, if any.
+	pm1b_cnt: Option<u16>,
+
+	/// The values of the `SLP_TYP` fields for the PM1a and PM1b control registers, to enter the
+	/// `S5` sleep state.
+	s5: Option<(u8, u8)>,
+	/// The I/O port of the reset register, along with the value to write to it.
+	reset: Option<(u16, u8)>,
+}
+
+impl PowerInfo {
+	/// Retrieves power management information from the given FADT.
+	pub fn new(fadt: &Fadt) -> Self {
+		let s5 = fadt
+			.get_dsdt()
+			.and_then(|dsdt| aml::find_sleep_type(dsdt.get_aml(), 5));
+		let reset = fadt.get_reset_reg().and_then(|(reg, val)| {
+			let addr = reg.address;
+			// TODO support other address spaces
+			(reg.addr_space == ADDR_SPACE_IO).then_some((addr as u16, val))
+		});
+		Self {
+			smi_cmd: fadt.smi_commandport as _,
+			acpi_enable: fadt.acpi_enable,
+			pm1a_cnt: fadt.pm1a_control_block as _,
+			pm1b_cnt: (fadt.pm1b_control_block != 0).then_some(fadt.pm1b_control_block as _),
+
+			s5,
+			reset,
+		}
+	}
+
+	/// Switches the system to ACPI mode if it is not already.
+	///
+	/// If the switch failed, the function returns `false`.
+	fn enable(&self) -> bool {
+		let enabled = || unsafe { inw(self.pm1a_cnt) } & PM1_SCI_EN != 0;
+		if enabled() {
+			return true;
+		}
+		// If no SMI command port is present, ACPI mode is always enabled
+		if self.smi_cmd == 0 || self.acpi_enable == 0 {
+			return false;
+		}
+		unsafe {
+			outb(self.smi_cmd, self.acpi_enable);
+		}
+		(0..ACPI_ENABLE_POLL).any(|_| enabled())
+	}
+
+	/// Enters the `S5` sleep state, powering off the system.
+	///
+	/// If not supported, or if the operation failed, the function returns.
+	pub fn shutdown(&self) {
+		let Some((slp_typ_a, slp_typ_b)) = self.s5 else {
+			return;
+		};
+		if self.pm1a_cnt == 0 || !self.enable() {
+			return;
+		}
+		let sleep = |port: u16, slp_typ: u8| unsafe {
+			let val = inw(port) & !(PM1_SLP_TYP_MASK | PM1_SLP_EN);
+			let val = val | (((slp_typ as u16) << PM1_SLP_TYP_SHIFT) & PM1_SLP_TYP_MASK);
+			outw(port, val | PM1_SLP_EN);
+		};
+		sleep(self.pm1a_cnt, slp_typ_a);
+		if let Some(pm1b_cnt) = self.pm1b_cnt {
+			sleep(pm1b_cnt, slp_typ_b);
+		}
+	}
+
+	/// Resets the system using the reset register.
+	///
+	/// If not supported, or if the operation failed, the function returns.
+	pub fn reset(&self) {
+		if let Some((port, val)) = self.reset {
+			unsafe {
+				outb(port, val);
+			}
+		}
+	}
+}
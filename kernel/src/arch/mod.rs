@@ -21,7 +21,10 @@
 use crate::{
 	arch::x86::cpu::{enumerate_cpus, topology_add},
 	println, process,
-	process::scheduler::cpu::{per_cpu, store_per_cpu},
+	process::scheduler::{
+		self,
+		cpu::{per_cpu, store_per_cpu},
+	},
 	sync::once::OnceInit,
 };
 use utils::errno::AllocResult;
@@ -110,7 +113,7 @@ pub(crate) fn init2(first: bool) -> AllocResult<()> {
 		// Setup timer
 		timer::init(first)?;
 		if apic::is_present() {
-			timer::apic::periodic(100_000_000);
+			timer::apic::periodic(scheduler::timeslice());
 		} else {
 			todo!() // fallback to PIT
 		}
@@ -132,7 +135,7 @@ pub fn enable_irq(irq: u8) {
 	{
 		use x86::*;
 		if apic::is_present() {
-			// TODO enable in I/O APIC
+			apic::set_masked(apic::irq_to_gsi(irq), false);
 		} else {
 			pic::enable_irq(irq);
 		}
@@ -145,7 +148,7 @@ pub fn disable_irq(irq: u8) {
 	{
 		use x86::*;
 		if apic::is_present() {
-			// TODO disable in I/O APIC
+			apic::set_masked(apic::irq_to_gsi(irq), true);
 		} else {
 			pic::disable_irq(irq);
 		}
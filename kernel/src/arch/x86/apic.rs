@@ -28,7 +28,7 @@ use crate::{
 	memory::{PhysAddr, mmio::Mmio},
 	sync::once::OnceInit,
 };
-use core::{hint, hint::likely, num::NonZeroUsize};
+use core::{array, hint, hint::likely, num::NonZeroUsize};
 use utils::{
 	collections::vec::Vec,
 	errno::{AllocResult, CollectResult},
@@ -65,6 +65,16 @@ pub const LVT_TSC_DEADLINE: u32 = 0b10 << 17;
 /// I/O APIC: redirection entries registers offset
 pub const IO_APIC_REDIRECTIONS_OFF: u8 = 0x10;
 
+/// I/O APIC redirection flag: the interrupt pin is active low
+pub const REDIRECT_ACTIVE_LOW: u64 = 1 << 13;
+/// I/O APIC redirection flag: the interrupt is level-triggered
+pub const REDIRECT_LEVEL: u64 = 1 << 15;
+/// I/O APIC redirection flag: mask interrupt
+pub const REDIRECT_MASKED: u64 = 1 << 16;
+
+/// The number of legacy ISA IRQs.
+pub const ISA_IRQ_COUNT: usize = 16;
+
 /// Tells whether the APIC is present or not.
 #[inline]
 pub fn is_present() -> bool {
@@ -104,10 +114,31 @@ struct IoApic {
 	gsi: u32,
 }
 
+/// The routing of a legacy ISA IRQ to the I/O APIC.
+#[derive(Clone, Copy)]
+struct IsaIrq {
+	/// The Global System Interrupt the IRQ is connected to
+	gsi: u32,
+	/// Polarity and trigger mode flags for the redirection entry
+	flags: u64,
+}
+
+impl IsaIrq {
+	/// Returns the default routing of `irq`: identity mapped, edge-triggered and active high.
+	fn identity(irq: usize) -> Self {
+		Self {
+			gsi: irq as _,
+			flags: 0,
+		}
+	}
+}
+
 /// Local APIC's registers map, this initialized in [`init`]
 static LAPIC_MMIO: OnceInit<Mmio> = unsafe { OnceInit::new() };
 /// The list of I/O APIC on the system, this initialized in [`enumerate_ioapic`]
 static IO_APIC: OnceInit<Vec<IoApic>> = unsafe { OnceInit::new() };
+/// The routing of legacy ISA IRQs, this initialized in [`enumerate_ioapic`]
+static ISA_IRQ: OnceInit<[IsaIrq; ISA_IRQ_COUNT]> = unsafe { OnceInit::new() };
 
 /// Reads a register of the local APIC.
 ///
@@ -206,6 +237,10 @@ pub(crate) fn init(first: bool) -> AllocResult<()> {
 /// This function must be called only once, at boot.
 pub(crate) fn enumerate_ioapic() -> AllocResult<()> {
 	let Some(madt) = acpi::get_table::<Madt>() else {
+		unsafe {
+			OnceInit::init(&IO_APIC, Vec::new());
+			OnceInit::init(&ISA_IRQ, array::from_fn(IsaIrq::identity));
+		}
 		return Ok(());
 	};
 	let ioapics = madt
@@ -225,47 +260,138 @@ pub(crate) fn enumerate_ioapic() -> AllocResult<()> {
 	unsafe {
 		OnceInit::init(&IO_APIC, ioapics);
 	}
-	// TODO: this is necessary to use the PIT
-	/*// Remap legacy interrupts
-	let lapic_id = lapic_id();
+	// Apply interrupt source overrides
+	let mut isa_irq: [IsaIrq; ISA_IRQ_COUNT] = array::from_fn(IsaIrq::identity);
 	madt.entries()
 		.filter(|e| e.entry_type == 2)
-		.map(|e| unsafe { e.body::<InterruptSourceOverride>() })
+		.map(|e| unsafe { e.body::<madt::InterruptSourceOverride>() })
+		// Only the ISA bus is defined
+		.filter(|e| e.bus_souce == 0 && (e.irq_source as usize) < ISA_IRQ_COUNT)
 		.for_each(|e| {
-			redirect_int(e.gsi, lapic_id, 0x20 + e.irq_source);
-		});*/
+			let mut flags = 0;
+			// `0b00` means conforming to the bus's specification, which is the default for ISA
+			if e.flags & 0b11 == 0b11 {
+				flags |= REDIRECT_ACTIVE_LOW;
+			}
+			if (e.flags >> 2) & 0b11 == 0b11 {
+				flags |= REDIRECT_LEVEL;
+			}
+			isa_irq[e.irq_source as usize] = IsaIrq {
+				gsi: e.gsi,
+				flags,
+			};
+		});
+	unsafe {
+		OnceInit::init(&ISA_IRQ, isa_irq);
+	}
+	// Mask everything, then route legacy IRQs to the current CPU, at the same vectors as the PIC
+	for ioapic in IO_APIC.iter() {
+		let count = unsafe { ioapic_redirect_count(ioapic.mmio.as_ptr()) } as u32;
+		for gsi in ioapic.gsi..ioapic.gsi + count {
+			redirect(gsi, 0, 0, REDIRECT_MASKED);
+		}
+	}
+	let lapic_id = lapic_id();
+	for (irq, isa) in ISA_IRQ.iter().enumerate() {
+		// Skip identity mapped IRQs whose line is used by another IRQ through an override
+		let taken = ISA_IRQ
+			.iter()
+			.enumerate()
+			.any(|(i, other)| i != irq && other.gsi == isa.gsi && other.gsi != i as u32);
+		if taken {
+			continue;
+		}
+		redirect(
+			isa.gsi,
+			lapic_id,
+			0x20 + irq as u8,
+			isa.flags | REDIRECT_MASKED,
+		);
+	}
 	Ok(())
 }
 
+/// Returns the I/O APIC handling `gsi`, along with the index of its redirection entry.
+fn find_ioapic(gsi: u32) -> Option<(&'static IoApic, u8)> {
+	IO_APIC
+		.iter()
+		.find(|ioapic| {
+			let max_entries = unsafe { ioapic_redirect_count(ioapic.mmio.as_ptr()) } as u32;
+			(ioapic.gsi..ioapic.gsi + max_entries).contains(&gsi)
+		})
+		.map(|ioapic| (ioapic, (gsi - ioapic.gsi) as u8))
+}
+
+/// Writes the redirection entry for `gsi`, with the given `flags`.
+///
+/// If no I/O APIC is available for `gsi`, the function does nothing and returns `false`.
+fn redirect(gsi: u32, lapic: u32, int: u8, flags: u64) -> bool {
+	let Some((ioapic, i)) = find_ioapic(gsi) else {
+		return false;
+	};
+	let val = (int as u64) | flags | ((lapic as u64) << 56);
+	let reg = IO_APIC_REDIRECTIONS_OFF + i * 2;
+	unsafe {
+		// Mask the entry while it is being modified, since it is written in two steps
+		ioapic_write(ioapic.mmio.as_ptr(), reg, REDIRECT_MASKED as u32);
+		ioapic_write(ioapic.mmio.as_ptr(), reg + 1, (val >> 32) as u32);
+		ioapic_write(ioapic.mmio.as_ptr(), reg, val as u32);
+	}
+	true
+}
+
 /// Configures an I/O APIC to redirect `gsi` (Global System Interrupt) to the CPU with the given
 /// local APIC ID `lapic`, at the interrupt vector `int`.
 ///
+/// The interrupt is edge-triggered and active high.
+///
 /// If no I/O APIC is available for `gsi`, the function does nothing and returns `false`. On
 /// success, it returns `true`.
 pub fn redirect_int(gsi: u32, lapic: u32, int: u8) -> bool {
-	// Find the associated I/O APIC
-	let ioapic = IO_APIC.iter().find(|ioapic| {
-		let max_entries = unsafe { ioapic_redirect_count(ioapic.mmio.as_ptr()) } as u32;
-		(ioapic.gsi..ioapic.gsi + max_entries).contains(&gsi)
-	});
-	let Some(ioapic) = ioapic else {
+	redirect(gsi, lapic, int, 0)
+}
+
+/// Returns the Global System Interrupt the legacy ISA IRQ `irq` is connected to.
+pub fn irq_to_gsi(irq: u8) -> u32 {
+	ISA_IRQ
+		.get(irq as usize)
+		.map(|isa| isa.gsi)
+		.unwrap_or(irq as _)
+}
+
+/// Configures an I/O APIC to redirect the legacy ISA IRQ `irq` to the CPU with the given local
+/// APIC ID `lapic`, at the interrupt vector `int`.
+///
+/// Contrary to [`redirect_int`], this function takes into account the interrupt source overrides
+/// described by ACPI.
+///
+/// If no I/O APIC is available for `irq`, the function does nothing and returns `false`. On
+/// success, it returns `true`.
+pub fn redirect_irq(irq: u8, lapic: u32, int: u8) -> bool {
+	let Some(isa) = ISA_IRQ.get(irq as usize) else {
+		return redirect_int(irq as _, lapic, int);
+	};
+	redirect(isa.gsi, lapic, int, isa.flags)
+}
+
+/// Masks or unmasks `gsi` (Global System Interrupt) on its I/O APIC, leaving the rest of the
+/// redirection entry unchanged.
+///
+/// If no I/O APIC is available for `gsi`, the function does nothing and returns `false`. On
+/// success, it returns `true`.
+pub fn set_masked(gsi: u32, masked: bool) -> bool {
+	let Some((ioapic, i)) = find_ioapic(gsi) else {
 		return false;
 	};
-	// Configure redirection
-	let i = (gsi - ioapic.gsi) as u8;
-	// TODO flags
-	let val = (int as u64) | ((lapic as u64) << 56);
+	let reg = IO_APIC_REDIRECTIONS_OFF + i * 2;
 	unsafe {
-		ioapic_write(
-			ioapic.mmio.as_ptr(),
-			IO_APIC_REDIRECTIONS_OFF + i * 2,
-			val as u32,
-		);
-		ioapic_write(
-			ioapic.mmio.as_ptr(),
-			IO_APIC_REDIRECTIONS_OFF + i * 2 + 1,
-			val as u32,
-		);
+		let mut val = ioapic_read(ioapic.mmio.as_ptr(), reg);
+		if masked {
+			val |= REDIRECT_MASKED as u32;
+		} else {
+			val &= !(REDIRECT_MASKED as u32);
+		}
+		ioapic_write(ioapic.mmio.as_ptr(), reg, val);
 	}
 	true
 }
@@ -102,3 +102,20 @@ pub fn has_package_bits() -> bool {
 	let edx = cpuid(1, 0).3;
 	edx & (1 << 28) != 0
 }
+
+/// Returns the processor's brand string, if available
+pub fn brand() -> Option<[u8; 48]> {
+	if extended_max_leaf() < 0x80000004 {
+		return None;
+	}
+	let mut brand = [0; 48];
+	for (i, leaf) in (0x80000002..=0x80000004).enumerate() {
+		let (eax, ebx, ecx, edx) = cpuid(leaf, 0);
+		let off = i * 16;
+		brand[off..(off + 4)].copy_from_slice(&eax.to_ne_bytes());
+		brand[(off + 4)..(off + 8)].copy_from_slice(&ebx.to_ne_bytes());
+		brand[(off + 8)..(off + 12)].copy_from_slice(&ecx.to_ne_bytes());
+		brand[(off + 12)..(off + 16)].copy_from_slice(&edx.to_ne_bytes());
+	}
+	Some(brand)
+}
@@ -157,6 +157,56 @@ pub fn wrmsr(msr: u32, val: u64) {
 	}
 }
 
+/// Returns the value of the Time-Stamp Counter.
+#[inline]
+pub fn rdtsc() -> u64 {
+	let mut edx: u32;
+	let mut eax: u32;
+	unsafe {
+		asm!(
+			"rdtsc",
+			out("edx") edx,
+			out("eax") eax,
+			options(nomem, nostack)
+		);
+	}
+	((edx as u64) << 32) | eax as u64
+}
+
+/// Tells whether the CPU supports the `rdrand` instruction.
+#[inline]
+pub fn has_rdrand() -> bool {
+	cpuid(1, 0).2 & (1 << 30) != 0
+}
+
+/// Returns a random value from the CPU's hardware random number generator.
+///
+/// If the instruction is not supported, or if the generator could not provide a value, the
+/// function returns `None`.
+pub fn rdrand() -> Option<usize> {
+	if !has_rdrand() {
+		return None;
+	}
+	// Retry a few times since the generator may temporarily run out of entropy
+	for _ in 0..10 {
+		let val: usize;
+		let ok: u8;
+		unsafe {
+			asm!(
+				"rdrand {val}",
+				"setc {ok}",
+				val = out(reg) val,
+				ok = out(reg_byte) ok,
+				options(nomem, nostack)
+			);
+		}
+		if ok != 0 {
+			return Some(val);
+		}
+	}
+	None
+}
+
 /// Returns HWCAP bitmask for ELF.
 #[inline]
 pub fn get_hwcap() -> u32 {
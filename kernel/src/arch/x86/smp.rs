@@ -33,13 +33,12 @@ use crate::{
 	},
 	println,
 	process::scheduler::{
-		cpu::{CPU, per_cpu},
+		cpu::{CPU, IDLE_CPUS, per_cpu},
 		switch::idle_task,
 	},
 };
 use core::{
 	arch::global_asm,
-	hint,
 	num::NonZeroUsize,
 	ptr,
 	ptr::null_mut,
@@ -222,6 +221,13 @@ unsafe extern "C" {
 	fn smp_trampoline_end();
 }
 
+/// Processor Local APIC flag: the processor is enabled.
+const LAPIC_ENABLED: u32 = 1 << 0;
+/// The maximum time to wait for application processors to start, in microseconds.
+const BOOT_TIMEOUT: u32 = 1_000_000;
+/// The interval at which the boot of application processors is checked, in microseconds.
+const BOOT_POLL_INTERVAL: u32 = 100;
+
 /// The number of running CPU cores.
 static BOOTED_CORES: AtomicUsize = AtomicUsize::new(1);
 
@@ -229,6 +235,7 @@ static BOOTED_CORES: AtomicUsize = AtomicUsize::new(1);
 /// Initializes the SMP.
 pub fn init() -> AllocResult<()> {
 	let lapic_id = lapic_id();
+	per_cpu().online.store(true, Release);
 	// Allocate stacks list
 	let max_apic_id = CPU
 		.iter()
@@ -253,11 +260,17 @@ pub fn init() -> AllocResult<()> {
 		});
 	}
 	// Boot cores
+	let mut expected = 1;
 	for cpu in CPU.iter() {
 		// Do no attempt to boot the current core
 		if cpu.apic_id == lapic_id {
 			continue;
 		}
+		// Cores which are only online capable are reserved for hotplug
+		if cpu.apic_flags & LAPIC_ENABLED == 0 {
+			continue;
+		}
+		expected += 1;
 		// Allocate stack
 		unsafe {
 			let pages = NonZeroUsize::new(BOOT_STACK_SIZE / PAGE_SIZE).unwrap();
@@ -305,8 +318,17 @@ pub fn init() -> AllocResult<()> {
 		}
 	}
 	// Wait for all cores to be up before returning
-	while BOOTED_CORES.load(Acquire) < CPU.len() {
-		hint::spin_loop();
+	let mut elapsed = 0;
+	while BOOTED_CORES.load(Acquire) < expected && elapsed < BOOT_TIMEOUT {
+		udelay(BOOT_POLL_INTERVAL);
+		elapsed += BOOT_POLL_INTERVAL;
+	}
+	// Do not schedule anything on cores that did not start
+	for cpu in CPU.iter().filter(|cpu| !cpu.online.load(Acquire)) {
+		if cpu.apic_flags & LAPIC_ENABLED != 0 {
+			println!("Core {} failed to start", cpu.apic_id);
+		}
+		IDLE_CPUS.clear_bit(cpu.apic_id as _);
 	}
 	Ok(())
 }
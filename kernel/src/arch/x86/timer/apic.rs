@@ -47,7 +47,6 @@ pub(crate) fn calibrate_hpet() -> AllocResult<()> {
 	let period = unsafe {
 		// Use divider `16`
 		apic::write_reg(REG_TIMER_DIVIDE, 3);
-		hpet::set_enabled(true);
 		let hpet_before = hpet::read_counter();
 		apic::write_reg(REG_TIMER_INIT_COUNT, APIC_TICKS);
 		apic::write_reg(REG_LVT_TIMER, LVT_ONESHOT | LVT_MASKED);
@@ -56,8 +55,7 @@ pub(crate) fn calibrate_hpet() -> AllocResult<()> {
 			hint::spin_loop();
 		}
 		// Compute elapsed time
-		let hpet_delta = hpet::read_counter() - hpet_before;
-		hpet::set_enabled(false);
+		let hpet_delta = hpet::read_counter().wrapping_sub(hpet_before);
 		let period = hpet_delta * hpet::INFO.tick_period as u64;
 		period / APIC_TICKS as u64
 	};
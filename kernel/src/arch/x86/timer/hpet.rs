@@ -23,10 +23,17 @@
 use crate::{
 	acpi,
 	acpi::{GenericAddr, TableHdr},
+	arch::x86::apic,
 	memory::{PhysAddr, mmio::Mmio},
 	sync::once::OnceInit,
 };
-use core::num::NonZeroUsize;
+use core::{
+	num::NonZeroUsize,
+	sync::atomic::{
+		AtomicBool,
+		Ordering::{Acquire, Release},
+	},
+};
 use utils::errno::AllocResult;
 
 /// HPET register: General Capability and ID
@@ -43,6 +50,21 @@ const TIMER_CONFIG_OFF: usize = 0x0;
 /// Offset to the comparator value register of a timer
 const TIMER_COMPARATOR_OFF: usize = 0x8;
 
+/// Capability flag: the main counter is 64 bits wide
+const CAP_COUNT_SIZE: u64 = 1 << 13;
+
+/// Timer configuration flag: enable interrupts
+const TIMER_INT_ENABLE: u64 = 1 << 2;
+/// Timer capability flag: the timer is 64 bits wide
+const TIMER_SIZE_CAP: u64 = 1 << 5;
+/// Shift of the I/O APIC routing in the timer configuration register
+const TIMER_INT_ROUTE_SHIFT: u64 = 9;
+
+/// The interrupt vector of the HPET's event timer.
+///
+/// This is the vector of the legacy PIC's cascade line, which never fires on its own.
+pub const INTERRUPT_VECTOR: u8 = 0x22;
+
 /// ACPI HPET table
 #[repr(C, packed)]
 pub struct AcpiHpet {
@@ -83,10 +105,16 @@ pub struct Hpet {
 	pub mmio: Mmio,
 	/// The period of a tick in nanoseconds
 	pub tick_period: u32,
+	/// The period of a tick in femtoseconds
+	pub period: u64,
+	/// Tells whether the main counter is 64 bits wide
+	pub wide: bool,
 }
 
 /// The HPET's information.
 pub static INFO: OnceInit<Hpet> = unsafe { OnceInit::new() };
+/// Tells whether the first timer of the HPET is used to fire events.
+static EVENT: AtomicBool = AtomicBool::new(false);
 
 /// Initializes the HPET.
 pub(crate) fn init(acpi_info: &'static AcpiHpet) -> AllocResult<()> {
@@ -94,18 +122,88 @@ pub(crate) fn init(acpi_info: &'static AcpiHpet) -> AllocResult<()> {
 	let physaddr = PhysAddr(acpi_info.base_address.address as _);
 	let mmio = Mmio::new(physaddr, NonZeroUsize::new(1).unwrap(), false)?;
 	// Read period
-	let tick_period =
-		unsafe { (reg_read(mmio.as_ptr(), REG_CAP_ID) >> 32) as u32 }.div_ceil(1_000_000);
+	let cap = unsafe { reg_read(mmio.as_ptr(), REG_CAP_ID) };
+	let period = cap >> 32;
 	let info = Hpet {
 		mmio,
-		tick_period,
+		tick_period: period.div_ceil(1_000_000) as _,
+		period,
+		wide: cap & CAP_COUNT_SIZE != 0,
 	};
 	unsafe {
 		OnceInit::init(&INFO, info);
 	}
+	// The main counter runs from now on, since it is used for timekeeping
+	set_enabled(true);
 	Ok(())
 }
 
+/// Sets up the first timer of the HPET to fire events on the CPU with the local APIC ID `lapic`,
+/// at [`INTERRUPT_VECTOR`].
+///
+/// If the timer cannot be used, the function returns `false`.
+pub(crate) fn init_event(lapic: u32) -> bool {
+	if !INFO.wide {
+		return false;
+	}
+	let config_reg = TIMER_BASE + TIMER_CONFIG_OFF;
+	let config = unsafe { reg_read(INFO.mmio.as_ptr(), config_reg) };
+	if config & TIMER_SIZE_CAP == 0 {
+		return false;
+	}
+	// Use a line of the I/O APIC above legacy IRQs
+	let route_cap = (config >> 32) & !0xffff;
+	if route_cap == 0 {
+		return false;
+	}
+	let gsi = route_cap.trailing_zeros();
+	if !apic::redirect_int(gsi, lapic, INTERRUPT_VECTOR) {
+		return false;
+	}
+	// Oneshot mode, edge-triggered
+	unsafe {
+		reg_write(
+			INFO.mmio.as_ptr(),
+			TIMER_BASE + TIMER_COMPARATOR_OFF,
+			u64::MAX,
+		);
+		reg_write(
+			INFO.mmio.as_ptr(),
+			config_reg,
+			((gsi as u64) << TIMER_INT_ROUTE_SHIFT) | TIMER_INT_ENABLE,
+		);
+	}
+	EVENT.store(true, Release);
+	true
+}
+
+/// Programs the event timer to fire an interrupt in `delay` nanoseconds.
+///
+/// If the event timer is not in use, the function does nothing and returns `false`.
+pub fn set_oneshot(delay: u64) -> bool {
+	if !EVENT.load(Acquire) {
+		return false;
+	}
+	// Convert to ticks, rounding up
+	let mut ticks = ((delay as u128 * 1_000_000).div_ceil(INFO.period as u128) as u64).max(1);
+	loop {
+		let target = read_counter().wrapping_add(ticks);
+		unsafe {
+			reg_write(
+				INFO.mmio.as_ptr(),
+				TIMER_BASE + TIMER_COMPARATOR_OFF,
+				target,
+			);
+		}
+		// If the counter went past the comparator while writing it, the interrupt would not fire
+		if read_counter() < target {
+			break;
+		}
+		ticks *= 2;
+	}
+	true
+}
+
 /// Enables or disables the HPET.
 pub fn set_enabled(enabled: bool) {
 	unsafe {
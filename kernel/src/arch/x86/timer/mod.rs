@@ -23,6 +23,7 @@
 //! - RTC (legacy)
 //! - APIC
 //! - HPET
+//! - TSC
 //!
 //! If the APIC is present, its timer shall be used for scheduling. If not, the kernel fallbacks on
 //! the PIT.
@@ -33,17 +34,14 @@
 //! The kernel will attempt to detect the presence of an HPET.
 //!
 //! TODO: if the HPET is net present, fallback on the PIT
-
-// TODO calibrate the TSC if present and use it for timekeeping.
-// If the TSC is unavailable, fallback in this order:
-// - HPET
-// - APIC
-// - RTC
-// - PIT
+//!
+//! For timekeeping, the invariant TSC is preferred over the HPET's main counter. The first timer
+//! of the HPET is used to fire timers at their exact expiration time.
 
 use crate::{
 	acpi,
 	arch::{x86, x86::timer::hpet::AcpiHpet},
+	time::clock::ClockSource,
 };
 use utils::errno::AllocResult;
 
@@ -51,6 +49,7 @@ pub mod apic;
 pub mod hpet;
 pub mod pit;
 pub mod rtc;
+pub mod tsc;
 
 /// Makes the current CPU cores wait for at least `ms` milliseconds.
 #[inline]
@@ -86,6 +85,9 @@ pub(crate) fn init(first: bool) -> AllocResult<()> {
 	if let Some(hpet) = acpi::get_table::<AcpiHpet>() {
 		if first {
 			hpet::init(hpet)?;
+			if tsc::is_invariant() {
+				tsc::calibrate_hpet();
+			}
 		}
 		apic::calibrate_hpet()?;
 	} else {
@@ -97,3 +99,24 @@ pub(crate) fn init(first: bool) -> AllocResult<()> {
 	}
 	Ok(())
 }
+
+/// Returns the best clocksource available for timekeeping, if any.
+pub fn clocksource() -> Option<ClockSource> {
+	if let Some(frequency) = tsc::frequency() {
+		return Some(ClockSource {
+			name: "tsc",
+			read: tsc::read,
+			frequency,
+			tsc: true,
+		});
+	}
+	if acpi::get_table::<AcpiHpet>().is_some() && hpet::INFO.wide {
+		return Some(ClockSource {
+			name: "hpet",
+			read: hpet::read_counter,
+			frequency: 1_000_000_000_000_000 / hpet::INFO.period,
+			tsc: false,
+		});
+	}
+	None
+}
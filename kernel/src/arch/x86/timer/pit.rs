@@ -64,9 +64,9 @@ pub fn init(freq: u32) {
 /// Enables or disables the PIT.
 pub fn set_enabled(enable: bool) {
 	if enable {
-		enable_irq(0x20);
+		enable_irq(0);
 	} else {
-		disable_irq(0x20);
+		disable_irq(0);
 	}
 }
 
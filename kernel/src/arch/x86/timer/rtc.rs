@@ -51,6 +51,14 @@ fn read_reg(reg: u8) -> u8 {
 	}
 }
 
+/// Writes the value of a register
+fn write_reg(reg: u8, val: u8) {
+	unsafe {
+		outb(SELECT_PORT, reg);
+		outb(VALUE_PORT, val);
+	}
+}
+
 /// Enables or disables the RTC.
 pub fn set_enabled(enable: bool) {
 	disable_int(|| unsafe {
@@ -120,8 +128,10 @@ fn secs_through_year(year: u64, leap: &mut bool) -> u64 {
 			*leap = rem % 4 == 0;
 		}
 	}
-	leaps += 97 * cycles + 24 * centuries - if *leap { 1 } else { 0 };
-	(year - 2000) * 31536000 + leaps * 86400 + 946684800 + 86400
+	leaps += 97 * cycles + 24 * centuries;
+	let t = (year - 2000) * 31536000 + leaps * 86400 + 946684800 + 86400;
+	// Do not count the leap day of the current year
+	if *leap { t - 86400 } else { t }
 }
 
 fn secs_through_month(month: u8, leap: bool) -> u64 {
@@ -140,7 +150,7 @@ fn secs_through_month(month: u8, leap: bool) -> u64 {
 		334 * 86400,
 	];
 	let mut t = SECS_THROUGH_MONTH[month as usize - 1];
-	if leap && month >= 2 {
+	if leap && month > 2 {
 		t += 86400;
 	}
 	t
@@ -162,6 +172,34 @@ fn date_to_ts(time: &[u8; 7]) -> Timestamp {
 	t
 }
 
+// The code of this function is based on the `civil_from_days` algorithm from Howard Hinnant
+/// Computes the date from the given Unix timestamp `ts`, in seconds.
+///
+/// The layout of the returned date is the same as the one taken by [`date_to_ts`].
+fn ts_to_date(ts: u64) -> [u8; 7] {
+	let secs = ts % 86400;
+	// Count days from 0000-03-01, so that leap days are at the end of years
+	let days = ts / 86400 + 719468;
+	let era = days / 146097;
+	let day_of_era = days % 146097;
+	let year_of_era =
+		(day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let month = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * month + 2) / 5 + 1;
+	let month = if month < 10 { month + 3 } else { month - 9 };
+	let year = era * 400 + year_of_era + (month <= 2) as u64;
+	[
+		(secs % 60) as _,
+		(secs / 60 % 60) as _,
+		(secs / 3600) as _,
+		day as _,
+		month as _,
+		(year % 100) as _,
+		(year / 100) as _,
+	]
+}
+
 /// Reads the current time from the RTC.
 pub fn read_time() -> Timestamp {
 	let century_reg = acpi::rtc_century_register();
@@ -200,3 +238,57 @@ pub fn read_time() -> Timestamp {
 	}
 	date_to_ts(&time) * 1_000_000_000
 }
+
+/// Writes the time `ts`, in nanoseconds since the Unix epoch, to the RTC.
+pub fn write_time(ts: Timestamp) {
+	let century_reg = acpi::rtc_century_register();
+	let mut time = ts_to_date(ts / 1_000_000_000);
+	disable_int(|| {
+		let reg_b = read_reg(STATUS_B_REGISTER);
+		// Convert 24-hour clock to 12-hour clock if necessary
+		let pm = reg_b & 0x2 == 0 && time[2] >= 12;
+		if reg_b & 0x2 == 0 {
+			time[2] = match time[2] % 12 {
+				0 => 12,
+				hour => hour,
+			};
+		}
+		// Convert binary to BCD if necessary
+		if reg_b & 0x04 == 0 {
+			for val in &mut time {
+				*val = ((*val / 10) << 4) | (*val % 10);
+			}
+		}
+		if pm {
+			time[2] |= 0x80;
+		}
+		// Inhibit updates of the clock while writing
+		write_reg(STATUS_B_REGISTER, reg_b | 0x80);
+		write_reg(0x0, time[0]);
+		write_reg(0x2, time[1]);
+		write_reg(0x4, time[2]);
+		write_reg(0x7, time[3]);
+		write_reg(0x8, time[4]);
+		write_reg(0x9, time[5]);
+		if century_reg != 0 {
+			write_reg(century_reg, time[6]);
+		}
+		write_reg(STATUS_B_REGISTER, reg_b);
+	});
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn rtc_date_conversion() {
+		// 2000-02-29 00:00:00
+		assert_eq!(ts_to_date(951782400), [0, 0, 0, 29, 2, 0, 20]);
+		// 2023-11-14 22:13:20
+		assert_eq!(ts_to_date(1700000000), [20, 13, 22, 14, 11, 23, 20]);
+		for ts in [951782400, 951868800, 1708992000, 1700000000, 4102444799] {
+			assert_eq!(date_to_ts(&ts_to_date(ts)), ts);
+		}
+	}
+}
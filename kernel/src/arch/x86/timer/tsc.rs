@@ -0,0 +1,85 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Time-Stamp Counter (TSC) implementation.
+//!
+//! The TSC is a 64-bit counter incremented at each CPU cycle. It can only be used to measure time
+//! if it is **invariant**, meaning its frequency does not depend on the power state of the CPU.
+
+use crate::{
+	arch::x86::{cpuid::cpuid, rdtsc, timer::hpet},
+	sync::atomic::AtomicU64,
+};
+use core::{hint, sync::atomic::Ordering::Relaxed};
+
+/// The duration of the calibration, in femtoseconds (10 milliseconds).
+const CALIBRATION_DURATION: u64 = 10_000_000_000_000;
+
+/// The frequency of the TSC in hertz, or zero if unknown.
+static FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
+/// Tells whether the TSC is invariant.
+pub fn is_invariant() -> bool {
+	let max_leaf = cpuid(0x80000000, 0).0;
+	max_leaf >= 0x80000007 && cpuid(0x80000007, 0).3 & (1 << 8) != 0
+}
+
+/// Returns the frequency of the TSC as reported by CPUID, if available.
+fn cpuid_frequency() -> Option<u64> {
+	let max_leaf = cpuid(0, 0).0;
+	if max_leaf < 0x15 {
+		return None;
+	}
+	// Ratio of the TSC frequency to the core crystal clock frequency
+	let (denominator, numerator, crystal, _) = cpuid(0x15, 0);
+	if denominator == 0 || numerator == 0 || crystal == 0 {
+		return None;
+	}
+	Some(crystal as u64 * numerator as u64 / denominator as u64)
+}
+
+/// Returns the frequency of the TSC in hertz, if known.
+pub fn frequency() -> Option<u64> {
+	Some(FREQUENCY.load(Relaxed)).filter(|f| *f != 0)
+}
+
+/// Returns the current value of the TSC.
+pub fn read() -> u64 {
+	rdtsc()
+}
+
+/// Measures and stores the frequency of the TSC, using the HPET.
+///
+/// If CPUID reports the frequency, it is used instead.
+pub(crate) fn calibrate_hpet() {
+	let freq = cpuid_frequency().unwrap_or_else(|| {
+		let period = hpet::INFO.period;
+		let hpet_ticks = CALIBRATION_DURATION / period;
+		let hpet_before = hpet::read_counter();
+		let tsc_before = rdtsc();
+		while hpet::read_counter().wrapping_sub(hpet_before) < hpet_ticks {
+			hint::spin_loop();
+		}
+		let tsc_delta = rdtsc() - tsc_before;
+		let hpet_delta = hpet::read_counter().wrapping_sub(hpet_before);
+		// Elapsed time in femtoseconds
+		let elapsed = hpet_delta as u128 * period as u128;
+		(tsc_delta as u128 * 1_000_000_000_000_000 / elapsed) as u64
+	});
+	FREQUENCY.store(freq, Relaxed);
+}
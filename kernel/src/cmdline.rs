@@ -18,7 +18,7 @@
 
 //! Boot-time kernel command line arguments parsing.
 
-use crate::tty::vga;
+use crate::{crash, tty::vga};
 use core::{cmp::min, fmt, str};
 use utils::DisplayableStr;
 
@@ -131,6 +131,16 @@ pub struct ArgsParser<'s> {
 	init: Option<&'s [u8]>,
 	/// Whether the kernel boots silently.
 	silent: bool,
+	/// Whether kernel memory is zeroed on allocation.
+	init_on_alloc: bool,
+	/// Whether kernel memory is zeroed when freed.
+	init_on_free: bool,
+	/// The destination of crash dumps, if any.
+	crashdump: Option<crash::Target>,
+	/// Whether crash dumps include memory.
+	crashdump_mem: bool,
+	/// The size of the RAM disk to create, in KiB, if any.
+	ramdisk: Option<u32>,
 }
 
 impl<'s> ArgsParser<'s> {
@@ -140,6 +150,11 @@ impl<'s> ArgsParser<'s> {
 			root: None,
 			init: None,
 			silent: false,
+			init_on_alloc: false,
+			init_on_free: false,
+			crashdump: None,
+			crashdump_mem: false,
+			ramdisk: None,
 		};
 
 		let mut iter = TokenIterator {
@@ -190,7 +205,57 @@ impl<'s> ArgsParser<'s> {
 					s.init = Some(init.s);
 				}
 
+				b"-crashdump" => {
+					let Some((_, target)) = iter.next() else {
+						return Err(ParseError {
+							cmdline,
+							err: "not enough arguments for `-crashdump`",
+							token: Some((token.begin, token.s.len())),
+						});
+					};
+					if target.s == b"serial" {
+						s.crashdump = Some(crash::Target::Serial);
+						continue;
+					}
+					let Some(major) = parse_nbr(target.s) else {
+						return Err(ParseError {
+							cmdline,
+							err: "invalid major number",
+							token: Some((i + 1, 1)),
+						});
+					};
+					let Some(minor) = iter.next().and_then(|(_, minor)| parse_nbr(minor.s)) else {
+						return Err(ParseError {
+							cmdline,
+							err: "invalid minor number",
+							token: Some((i + 2, 1)),
+						});
+					};
+					s.crashdump = Some(crash::Target::Disk(major, minor));
+				}
+				b"-crashdump_mem" => s.crashdump_mem = true,
+
+				b"-ramdisk" => {
+					let Some((_, size)) = iter.next() else {
+						return Err(ParseError {
+							cmdline,
+							err: "not enough arguments for `-ramdisk`",
+							token: Some((token.begin, token.s.len())),
+						});
+					};
+					let Some(size) = parse_nbr(size.s).filter(|size| *size > 0) else {
+						return Err(ParseError {
+							cmdline,
+							err: "invalid RAM disk size",
+							token: Some((size.begin, size.s.len())),
+						});
+					};
+					s.ramdisk = Some(size);
+				}
+
 				b"-silent" => s.silent = true,
+				b"-init_on_alloc" => s.init_on_alloc = true,
+				b"-init_on_free" => s.init_on_free = true,
 
 				_ => {
 					return Err(ParseError {
@@ -219,6 +284,31 @@ impl<'s> ArgsParser<'s> {
 	pub fn is_silent(&self) -> bool {
 		self.silent
 	}
+
+	/// Returns the destination of crash dumps, if any.
+	pub fn get_crashdump(&self) -> Option<crash::Target> {
+		self.crashdump
+	}
+
+	/// If `true`, crash dumps include memory.
+	pub fn is_crashdump_mem(&self) -> bool {
+		self.crashdump_mem
+	}
+
+	/// Returns the size of the RAM disk to create, in KiB, if any.
+	pub fn get_ramdisk_size(&self) -> Option<u32> {
+		self.ramdisk
+	}
+
+	/// If `true`, kernel memory is zeroed on allocation.
+	pub fn is_init_on_alloc(&self) -> bool {
+		self.init_on_alloc
+	}
+
+	/// If `true`, kernel memory is zeroed when freed.
+	pub fn is_init_on_free(&self) -> bool {
+		self.init_on_free
+	}
 }
 
 #[cfg(test)]
@@ -264,4 +354,37 @@ mod test {
 	fn cmdline7() {
 		assert!(ArgsParser::parse(b"-root 1 0 -init bleh -silent").is_ok());
 	}
+
+	#[test_case]
+	fn cmdline8() {
+		let args = ArgsParser::parse(b"-root 1 0 -init_on_alloc -init_on_free").unwrap();
+		assert!(args.is_init_on_alloc());
+		assert!(args.is_init_on_free());
+	}
+
+	#[test_case]
+	fn cmdline9() {
+		let args = ArgsParser::parse(b"-crashdump serial -crashdump_mem").unwrap();
+		assert_eq!(args.get_crashdump(), Some(crash::Target::Serial));
+		assert!(args.is_crashdump_mem());
+		let args = ArgsParser::parse(b"-crashdump 8 2").unwrap();
+		assert_eq!(args.get_crashdump(), Some(crash::Target::Disk(8, 2)));
+		assert!(!args.is_crashdump_mem());
+		assert!(ArgsParser::parse(b"-crashdump").is_err());
+		assert!(ArgsParser::parse(b"-crashdump 8").is_err());
+		assert!(ArgsParser::parse(b"-crashdump disk 2").is_err());
+	}
+
+	#[test_case]
+	fn cmdline10() {
+		let args = ArgsParser::parse(b"-root 1 0 -ramdisk 4096").unwrap();
+		assert_eq!(args.get_ramdisk_size(), Some(4096));
+		assert_eq!(
+			ArgsParser::parse(b"-silent").unwrap().get_ramdisk_size(),
+			None
+		);
+		assert!(ArgsParser::parse(b"-ramdisk").is_err());
+		assert!(ArgsParser::parse(b"-ramdisk 0").is_err());
+		assert!(ArgsParser::parse(b"-ramdisk 4M").is_err());
+	}
 }
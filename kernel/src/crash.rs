@@ -0,0 +1,344 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Crash dumps, written when the kernel panics to collect its state.
+//!
+//! The destination of crash dumps is selected on the command line:
+//! - `-crashdump serial` streams the dump on the **COM1** serial port
+//! - `-crashdump <major> <minor>` writes the dump at the beginning of the given block device,
+//!   typically a partition reserved for this purpose
+//!
+//! With `-crashdump_mem`, the dump also includes the stack of the panicking context, from the
+//! stack pointer to the end of its page.
+//!
+//! # Format
+//!
+//! The dump is text, with one record per line. Each record starts with a keyword, followed by
+//! space-separated fields. Addresses and values are in hexadecimal:
+//!
+//! ```text
+//! maestro-crashdump 1
+//! release <kernel release>
+//! cpu <core id>
+//! reason <message>
+//! location <file>:<line>:<column>
+//! reg <name> <value>
+//! frame <address> <symbol>
+//! mem <address> <bytes>
+//! end
+//! ```
+//!
+//! `location`, `reg`, `frame` and `mem` records are present only if the information is
+//! available. On a block device, the dump is padded with nul bytes up to the end of its last page.
+
+use crate::{
+	RELEASE,
+	arch::{core_id, x86::idt::IntFrame},
+	device,
+	device::{BlkDev, DeviceID, serial},
+	elf,
+	memory::{PROCESS_END, VirtAddr, vmem::KERNEL_VMEM},
+	println, register_get,
+	sync::spin::IntSpin,
+};
+use core::{
+	cmp::min,
+	fmt,
+	fmt::Write,
+	panic::Location,
+	slice,
+	sync::atomic::{
+		AtomicBool,
+		Ordering::{Acquire, Release},
+	},
+};
+use utils::{DisplayableStr, errno, errno::EResult, limits::PAGE_SIZE, ptr::arc::Arc};
+
+/// The number of bytes per `mem` record.
+const MEM_LINE: usize = 32;
+
+/// The destination of crash dumps, as given on the command line.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Target {
+	/// The first serial port.
+	Serial,
+	/// The block device with the given major and minor numbers.
+	Disk(u32, u32),
+}
+
+/// The destination of crash dumps, once resolved.
+enum Output {
+	/// The first serial port.
+	Serial,
+	/// A block device.
+	Disk(Arc<BlkDev>),
+}
+
+/// The destination of crash dumps. If `None`, crash dumps are disabled.
+static OUTPUT: IntSpin<Option<Output>> = IntSpin::new(None);
+/// Whether crash dumps include memory.
+static WITH_MEM: AtomicBool = AtomicBool::new(false);
+/// Set once a crash dump is being written, so that concurrent or nested panics do not write
+/// another one.
+static DUMPING: AtomicBool = AtomicBool::new(false);
+/// The buffer of the page being written to a block device.
+static PAGE: IntSpin<[u8; PAGE_SIZE]> = IntSpin::new([0; PAGE_SIZE]);
+
+/// Enables crash dumps to `target`.
+///
+/// If `mem` is `true`, crash dumps include memory.
+///
+/// If the block device to write to does not exist, the function returns [`errno::ENODEV`].
+pub(crate) fn init(target: Target, mem: bool) -> EResult<()> {
+	let output = match target {
+		Target::Serial => Output::Serial,
+		Target::Disk(major, minor) => {
+			let dev = device::request_blk(&DeviceID {
+				major,
+				minor,
+			})
+			.ok_or_else(|| errno!(ENODEV))?;
+			Output::Disk(dev)
+		}
+	};
+	*OUTPUT.lock() = Some(output);
+	WITH_MEM.store(mem, Release);
+	Ok(())
+}
+
+/// Writer for a crash dump.
+struct Writer<'o> {
+	/// The destination of the dump.
+	output: &'o Output,
+	/// The buffer of the page being written to a block device.
+	page: &'o mut [u8; PAGE_SIZE],
+	/// The offset of the end of the data in `page`.
+	cursor: usize,
+	/// The offset of `page` on the block device, in pages.
+	page_off: u64,
+}
+
+impl Writer<'_> {
+	/// Writes the buffered page to the block device, padding it with zeros, then moves to the
+	/// next page.
+	fn flush(&mut self) -> fmt::Result {
+		let Output::Disk(dev) = self.output else {
+			return Ok(());
+		};
+		self.page[self.cursor..].fill(0);
+		dev.ops
+			.dump_page(dev, self.page_off, self.page)
+			.map_err(|_| fmt::Error)?;
+		self.cursor = 0;
+		self.page_off += 1;
+		Ok(())
+	}
+}
+
+impl Write for Writer<'_> {
+	fn write_str(&mut self, s: &str) -> fmt::Result {
+		if matches!(self.output, Output::Serial) {
+			serial::PORTS[0].lock().write(s.as_bytes());
+			return Ok(());
+		}
+		let mut buf = s.as_bytes();
+		while !buf.is_empty() {
+			let len = min(buf.len(), PAGE_SIZE - self.cursor);
+			self.page[self.cursor..(self.cursor + len)].copy_from_slice(&buf[..len]);
+			self.cursor += len;
+			buf = &buf[len..];
+			if self.cursor == PAGE_SIZE {
+				self.flush()?;
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Adapter replacing newlines with spaces, to keep a field on a single line.
+struct OneLine<'w, W: Write>(&'w mut W);
+
+impl<W: Write> Write for OneLine<'_, W> {
+	fn write_str(&mut self, s: &str) -> fmt::Result {
+		for (i, part) in s.split('\n').enumerate() {
+			if i > 0 {
+				self.0.write_char(' ')?;
+			}
+			self.0.write_str(part)?;
+		}
+		Ok(())
+	}
+}
+
+/// Returns the names and values of the registers saved in `frame`.
+#[cfg(target_arch = "x86")]
+fn registers(frame: &IntFrame) -> [(&'static str, usize); 16] {
+	[
+		("eax", frame.rax as _),
+		("ebx", frame.rbx as _),
+		("ecx", frame.rcx as _),
+		("edx", frame.rdx as _),
+		("esi", frame.rsi as _),
+		("edi", frame.rdi as _),
+		("ebp", frame.rbp as _),
+		("gs", frame.gs as _),
+		("fs", frame.fs as _),
+		("int", frame.int as _),
+		("err", frame.code as _),
+		("eip", frame.rip as _),
+		("cs", frame.cs as _),
+		("eflags", frame.rflags as _),
+		("esp", frame.rsp as _),
+		("ss", frame.ss as _),
+	]
+}
+
+/// Returns the names and values of the registers saved in `frame`.
+#[cfg(target_arch = "x86_64")]
+fn registers(frame: &IntFrame) -> [(&'static str, usize); 24] {
+	[
+		("rax", frame.rax as _),
+		("rbx", frame.rbx as _),
+		("rcx", frame.rcx as _),
+		("rdx", frame.rdx as _),
+		("rsi", frame.rsi as _),
+		("rdi", frame.rdi as _),
+		("rbp", frame.rbp as _),
+		("r8", frame.r8 as _),
+		("r9", frame.r9 as _),
+		("r10", frame.r10 as _),
+		("r11", frame.r11 as _),
+		("r12", frame.r12 as _),
+		("r13", frame.r13 as _),
+		("r14", frame.r14 as _),
+		("r15", frame.r15 as _),
+		("gs", frame.gs as _),
+		("fs", frame.fs as _),
+		("int", frame.int as _),
+		("err", frame.code as _),
+		("rip", frame.rip as _),
+		("cs", frame.cs as _),
+		("rflags", frame.rflags as _),
+		("rsp", frame.rsp as _),
+		("ss", frame.ss as _),
+	]
+}
+
+/// Writes the stack of the panicking context, from `sp` to the end of its page.
+///
+/// If the stack is not in kernel space or is not mapped, the function writes nothing.
+fn write_stack(w: &mut Writer, sp: usize) -> fmt::Result {
+	let begin = sp & !(MEM_LINE - 1);
+	let end = (sp & !(PAGE_SIZE - 1)).wrapping_add(PAGE_SIZE);
+	if VirtAddr(begin) < PROCESS_END || KERNEL_VMEM.translate(VirtAddr(begin)).is_none() {
+		return Ok(());
+	}
+	for addr in (begin..end).step_by(MEM_LINE) {
+		let line = unsafe { slice::from_raw_parts(VirtAddr(addr).as_ptr::<u8>(), MEM_LINE) };
+		write!(w, "mem {addr:x} ")?;
+		for b in line {
+			write!(w, "{b:02x}")?;
+		}
+		writeln!(w)?;
+	}
+	Ok(())
+}
+
+/// Writes the records of a crash dump to `w`.
+fn write_dump(
+	w: &mut Writer,
+	reason: fmt::Arguments,
+	loc: Option<&Location>,
+	frame: Option<&IntFrame>,
+	callstack: &[VirtAddr],
+) -> fmt::Result {
+	writeln!(w, "maestro-crashdump 1")?;
+	writeln!(w, "release {RELEASE}")?;
+	writeln!(w, "cpu {}", core_id())?;
+	write!(w, "reason ")?;
+	OneLine(w).write_fmt(reason)?;
+	writeln!(w)?;
+	if let Some(loc) = loc {
+		writeln!(w, "location {loc}")?;
+	}
+	if let Some(frame) = frame {
+		for (name, val) in registers(frame) {
+			writeln!(w, "reg {name} {val:x}")?;
+		}
+		writeln!(w, "reg cr2 {:x}", register_get!("cr2"))?;
+		writeln!(w, "reg cr3 {:x}", register_get!("cr3"))?;
+	}
+	for pc in callstack.iter().take_while(|pc| !pc.is_null()) {
+		let name = elf::kernel::get_function_name(*pc).unwrap_or(b"???");
+		write!(w, "frame {:x} ", pc.0)?;
+		match str::from_utf8(name) {
+			Ok(name) => write!(OneLine(w), "{:#}", rustc_demangle::demangle(name))?,
+			Err(_) => write!(OneLine(w), "{}", DisplayableStr(name))?,
+		}
+		writeln!(w)?;
+	}
+	if WITH_MEM.load(Acquire) {
+		#[cfg(target_arch = "x86")]
+		let sp = frame
+			.map(|f| f.rsp as usize)
+			.unwrap_or(register_get!("esp"));
+		#[cfg(target_arch = "x86_64")]
+		let sp = frame
+			.map(|f| f.rsp as usize)
+			.unwrap_or(register_get!("rsp"));
+		write_stack(w, sp)?;
+	}
+	writeln!(w, "end")?;
+	if w.cursor > 0 {
+		w.flush()?;
+	}
+	Ok(())
+}
+
+/// Writes a crash dump, if enabled.
+///
+/// Arguments:
+/// - `reason` is the reason for the panic
+/// - `loc` is the location of the panic in the source code, if any
+/// - `frame` is the interrupt frame of the faulting context, if any
+/// - `callstack` is the callstack of the panic
+pub(crate) fn dump(
+	reason: fmt::Arguments,
+	loc: Option<&Location>,
+	frame: Option<&IntFrame>,
+	callstack: &[VirtAddr],
+) {
+	if DUMPING.swap(true, Acquire) {
+		return;
+	}
+	let output = OUTPUT.lock();
+	let Some(output) = &*output else {
+		return;
+	};
+	let mut page = PAGE.lock();
+	let mut w = Writer {
+		output,
+		page: &mut page,
+		cursor: 0,
+		page_off: 0,
+	};
+	match write_dump(&mut w, reason, loc, frame, callstack) {
+		Ok(()) => println!("Crash dump written"),
+		Err(_) => println!("Crash dump failed"),
+	}
+}
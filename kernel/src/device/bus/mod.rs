@@ -19,16 +19,18 @@
 //! Internal buses implementation
 
 pub mod pci;
+pub mod usb;
 
 use crate::device::manager;
 use utils::errno::EResult;
 
 /// Detects internal buses and registers them.
 pub fn detect() -> EResult<()> {
+	// USB host controllers are detected on the PCI bus
+	usb::init()?;
 	// PCI
 	let mut pci_manager = pci::PciManager::new();
 	pci_manager.scan()?;
 	manager::register(pci_manager)?;
-	// TODO USB
 	Ok(())
 }
@@ -27,8 +27,17 @@
 //! A PCI device can specify one or several BARs (Base Address Registers). They
 //! specify the address of the device's registers in memory, allowing
 //! communications through DMA (Direct Memory Access).
+//!
+//! Devices are enumerated through the legacy configuration mechanism (I/O ports). If the ACPI
+//! MCFG table is present, the Enhanced Configuration Access Mechanism (ECAM) is used to access
+//! the extended configuration space of PCI Express devices.
+//!
+//! Drivers register themselves with [`register_driver`], and are bound to the devices matching
+//! their [`DeviceId`] table.
 
 use crate::{
+	acpi,
+	acpi::mcfg::Mcfg,
 	arch::{
 		x86,
 		x86::io::{inl, outl},
@@ -40,9 +49,14 @@ use crate::{
 		manager::PhysicalDevice,
 	},
 	memory::{PhysAddr, mmio::Mmio},
+	println,
+	sync::spin::Spin,
 };
 use core::{
+	any::Any,
 	cmp::min,
+	fmt,
+	fmt::Formatter,
 	hint::unlikely,
 	iter,
 	mem::size_of,
@@ -51,7 +65,7 @@ use core::{
 use utils::{
 	collections::vec::Vec,
 	errno,
-	errno::{CollectResult, EResult},
+	errno::{AllocResult, CollectResult, ENOMEM, EResult},
 	limits::PAGE_SIZE,
 };
 
@@ -110,6 +124,11 @@ pub static CAP_MSI: u8 = 0x5;
 /// Device capability ID: Message Signaled Interrupt X
 pub static CAP_MSI_X: u8 = 0x11;
 
+/// The size of the configuration space of a function, with the legacy mechanism.
+const CONFIG_SIZE: usize = 0x100;
+/// The size of the configuration space of a function, with ECAM.
+const EXT_CONFIG_SIZE: usize = 0x1000;
+
 /// Returns the address of a PCI register
 fn reg_addr(bus: u8, device: u8, func: u8, reg_off: u8) -> u32 {
 	((bus as u32) << 16)
@@ -169,6 +188,27 @@ fn write_data(bus: u8, device: u8, func: u8, off: usize, buf: &[u32]) {
 	}
 }
 
+/// Maps the ECAM configuration space of the given function.
+///
+/// If the function is not covered by the ACPI MCFG table, the function returns `None`.
+fn map_ecam(bus: u8, device: u8, func: u8) -> AllocResult<Option<Mmio>> {
+	let Some(mcfg) = acpi::get_table::<Mcfg>() else {
+		return Ok(None);
+	};
+	// Only the first segment group is supported
+	let alloc = mcfg.entries().iter().find(|alloc| {
+		let segment = alloc.segment;
+		segment == 0 && (alloc.start_bus..=alloc.end_bus).contains(&bus)
+	});
+	let Some(alloc) = alloc else {
+		return Ok(None);
+	};
+	let off = ((bus - alloc.start_bus) as u64) << 20 | (device as u64) << 15 | (func as u64) << 12;
+	let addr = alloc.base_addr + off;
+	let mmio = Mmio::new(PhysAddr(addr as _), NonZeroUsize::new(1).unwrap(), false)?;
+	Ok(Some(mmio))
+}
+
 /// PCI device capability
 pub struct PciDevCap<'d> {
 	dev: &'d PciDev,
@@ -197,6 +237,12 @@ pub struct MsiX<'d> {
 }
 
 impl MsiX<'_> {
+	/// Returns the number of entries in the message table.
+	#[inline]
+	pub fn entries(&self) -> u16 {
+		self.entries.get()
+	}
+
 	/// Sets the `n`'s entry of the message table.
 	///
 	/// Arguments:
@@ -278,8 +324,15 @@ pub struct PciDev {
 
 	/// The list of BARs for the device.
 	bars: Vec<Option<Bar>>,
+	/// The size of each BAR, indexed by register
+	bar_sizes: [usize; 6],
 	/// The list of MMIOs associated with the device's BARs.
 	mmios: Vec<Mmio>,
+
+	/// The mapping of the configuration space, if ECAM is available
+	ecam: Option<Mmio>,
+	/// The driver bound to the device
+	driver: Spin<Option<&'static dyn Driver>>,
 }
 
 impl PciDev {
@@ -400,6 +453,7 @@ impl PciDev {
 	/// - `function` is the function number on the device.
 	/// - `data` is the data returned by the PCI.
 	fn new(bus: u8, device: u8, function: u8, data: &[u32; 16]) -> EResult<Self> {
+		let ecam = map_ecam(bus, device, function)?;
 		let mut dev = Self {
 			bus,
 			device,
@@ -424,12 +478,17 @@ impl PciDev {
 			],
 
 			bars: Vec::new(),
+			bar_sizes: [0; 6],
 			mmios: Vec::new(),
+
+			ecam,
+			driver: Spin::new(None),
 		};
 		// Load BARs
 		let mut i = 0;
 		while i < dev.get_max_bars_count() {
 			let bar = if let Some((bar, mmio)) = dev.load_bar(i)? {
+				dev.bar_sizes[i as usize] = bar.get_size();
 				if let Bar::MemorySpace {
 					type_: BarType::Bit64,
 					..
@@ -481,6 +540,77 @@ impl PciDev {
 		write_long(self.bus, self.device, self.function, 1, val);
 	}
 
+	/// Returns the size of the device's configuration space in bytes.
+	///
+	/// The extended configuration space is accessible only through ECAM.
+	#[inline]
+	pub fn config_size(&self) -> usize {
+		if self.ecam.is_some() {
+			EXT_CONFIG_SIZE
+		} else {
+			CONFIG_SIZE
+		}
+	}
+
+	/// Reads the 32-bit register at the offset `off` in bytes of the configuration space.
+	///
+	/// If `off` is not aligned or out of bounds, the function returns [`errno::EINVAL`].
+	pub fn read_config(&self, off: usize) -> EResult<u32> {
+		if unlikely(off % size_of::<u32>() != 0 || off >= self.config_size()) {
+			return Err(errno!(EINVAL));
+		}
+		let val = match &self.ecam {
+			Some(ecam) => unsafe { ecam.as_ptr::<u8>().add(off).cast::<u32>().read_volatile() },
+			None => read_long(
+				self.bus,
+				self.device,
+				self.function,
+				(off / size_of::<u32>()) as _,
+			),
+		};
+		Ok(val)
+	}
+
+	/// Writes the 32-bit register at the offset `off` in bytes of the configuration space.
+	///
+	/// If `off` is not aligned or out of bounds, the function returns [`errno::EINVAL`].
+	pub fn write_config(&self, off: usize, val: u32) -> EResult<()> {
+		if unlikely(off % size_of::<u32>() != 0 || off >= self.config_size()) {
+			return Err(errno!(EINVAL));
+		}
+		match &self.ecam {
+			Some(ecam) => unsafe {
+				ecam.as_ptr::<u8>()
+					.add(off)
+					.cast::<u32>()
+					.write_volatile(val)
+			},
+			None => write_long(
+				self.bus,
+				self.device,
+				self.function,
+				(off / size_of::<u32>()) as _,
+				val,
+			),
+		}
+		Ok(())
+	}
+
+	/// Returns the raw value of the register of the `n`th BAR, along with the size of its
+	/// address space.
+	///
+	/// If the BAR does not exist, the function returns `None`.
+	pub fn get_bar_raw(&self, n: u8) -> Option<(u32, usize)> {
+		let reg_off = self.get_bar_reg_off(n)?;
+		let val = read_long(self.bus, self.device, self.function, reg_off as _);
+		Some((val, self.bar_sizes[n as usize]))
+	}
+
+	/// Returns the name of the driver bound to the device, if any.
+	pub fn driver_name(&self) -> Option<&'static str> {
+		self.driver.lock().map(|drv| drv.name())
+	}
+
 	/// Returns the header type of the device.
 	#[inline(always)]
 	pub fn get_header_type(&self) -> u8 {
@@ -616,6 +746,16 @@ impl PciDev {
 	}
 }
 
+impl fmt::Display for PciDev {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"0000:{:02x}:{:02x}.{}",
+			self.bus, self.device, self.function
+		)
+	}
+}
+
 impl PhysicalDevice for PciDev {
 	fn get_device_id(&self) -> u16 {
 		self.device_id
@@ -656,6 +796,124 @@ impl PhysicalDevice for PciDev {
 	}
 }
 
+/// Identifies the devices handled by a [`Driver`].
+///
+/// Fields set to `None` match any value.
+#[derive(Clone, Copy, Debug)]
+pub struct DeviceId {
+	/// Vendor ID
+	pub vendor: Option<u16>,
+	/// Device ID
+	pub device: Option<u16>,
+	/// Class
+	pub class: Option<u16>,
+	/// Subclass
+	pub subclass: Option<u16>,
+	/// Programming interface
+	pub prog_if: Option<u8>,
+}
+
+impl DeviceId {
+	/// Matches the devices with the given vendor and device IDs.
+	pub const fn device(vendor: u16, device: u16) -> Self {
+		Self {
+			vendor: Some(vendor),
+			device: Some(device),
+			class: None,
+			subclass: None,
+			prog_if: None,
+		}
+	}
+
+	/// Matches the devices with the given class and subclass.
+	///
+	/// If `prog_if` is `None`, any programming interface matches.
+	pub const fn class(class: u16, subclass: u16, prog_if: Option<u8>) -> Self {
+		Self {
+			vendor: None,
+			device: None,
+			class: Some(class),
+			subclass: Some(subclass),
+			prog_if,
+		}
+	}
+
+	/// Tells whether `dev` matches.
+	pub fn matches(&self, dev: &PciDev) -> bool {
+		fn check<T: PartialEq>(expected: Option<T>, val: T) -> bool {
+			expected.is_none_or(|e| e == val)
+		}
+		check(self.vendor, dev.get_vendor_id())
+			&& check(self.device, dev.get_device_id())
+			&& check(self.class, dev.get_class())
+			&& check(self.subclass, dev.get_subclass())
+			&& check(self.prog_if, dev.get_prog_if())
+	}
+}
+
+/// A driver for PCI devices.
+pub trait Driver: Sync {
+	/// Returns the name of the driver.
+	fn name(&self) -> &'static str;
+
+	/// Returns the list of devices the driver can be bound to.
+	fn id_table(&self) -> &'static [DeviceId];
+
+	/// Initializes the device `dev`, which matches an entry of [`Self::id_table`].
+	///
+	/// On error, the device is left unbound.
+	fn probe(&self, dev: &PciDev) -> EResult<()>;
+}
+
+/// The list of registered drivers.
+static DRIVERS: Spin<Vec<&'static dyn Driver>> = Spin::new(Vec::new());
+
+/// Tries to bind `drv` to `dev`.
+///
+/// The function returns `true` if the driver has been bound. Errors other than
+/// [`errno::ENOMEM`] are reported in the kernel logs and not returned.
+fn bind(dev: &PciDev, drv: &'static dyn Driver) -> EResult<bool> {
+	if dev.driver.lock().is_some() || !drv.id_table().iter().any(|id| id.matches(dev)) {
+		return Ok(false);
+	}
+	match drv.probe(dev) {
+		Ok(()) => {
+			*dev.driver.lock() = Some(drv);
+			Ok(true)
+		}
+		Err(e) if e.as_int() == ENOMEM => Err(e),
+		Err(e) => {
+			println!("pci: {}: cannot bind device {dev}: {e}", drv.name());
+			Ok(false)
+		}
+	}
+}
+
+/// Registers a driver and binds it to the devices it matches that have no driver yet.
+pub fn register_driver(drv: &'static dyn Driver) -> EResult<()> {
+	DRIVERS.lock().push(drv)?;
+	with_devices(|devices| {
+		for dev in devices {
+			bind(dev, drv)?;
+		}
+		Ok(())
+	})
+}
+
+/// Executes `f` with the list of PCI devices.
+///
+/// If the PCI has not been scanned yet, the list is empty.
+pub fn with_devices<F: FnOnce(&[PciDev]) -> R, R>(f: F) -> R {
+	let Some(manager) = manager::get::<PciManager>() else {
+		return f(&[]);
+	};
+	let manager = manager.lock();
+	let manager = (&*manager as &dyn Any)
+		.downcast_ref::<PciManager>()
+		.unwrap();
+	f(&manager.devices)
+}
+
 /// This manager handles every device connected to the PCI bus.
 ///
 /// Since the PCI bus is not a hotplug bus, calling `on_unplug` on this structure has no effect.
@@ -728,6 +986,13 @@ impl PciManager {
 				// Register the device
 				let dev = PciDev::new(bus, device, func, &data)?;
 				manager::on_plug(&dev)?;
+				// Bind a driver
+				let drivers = DRIVERS.lock();
+				for drv in drivers.iter() {
+					if bind(&dev, *drv)? {
+						break;
+					}
+				}
 				Ok(dev)
 			})
 			.collect::<EResult<CollectResult<_>>>()?
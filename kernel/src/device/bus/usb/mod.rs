@@ -0,0 +1,598 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Universal Serial Bus (USB) implementation.
+//!
+//! Host controller drivers detect devices plugged on their ports and assign them an address.
+//! Then, [`attach`] reads the device's descriptors, selects its first configuration and binds each
+//! interface to the first registered class [`Driver`] accepting it.
+
+pub mod xhci;
+
+use crate::{
+	device::bus::{
+		pci,
+		pci::{DeviceId, PciDev},
+	},
+	memory::{PhysAddr, VirtAddr, buddy, buddy::FrameOrder},
+	println,
+	sync::spin::Spin,
+};
+use core::{
+	fmt,
+	hint::unlikely,
+	mem::size_of,
+	ptr::{NonNull, read_unaligned},
+};
+use utils::{
+	collections::vec::Vec,
+	errno::{AllocResult, ENOMEM, EResult},
+	limits::PAGE_SIZE,
+	ptr::arc::Arc,
+};
+
+/// Descriptor type: Device
+pub const DESC_DEVICE: u8 = 1;
+/// Descriptor type: Configuration
+pub const DESC_CONFIGURATION: u8 = 2;
+/// Descriptor type: Interface
+pub const DESC_INTERFACE: u8 = 4;
+/// Descriptor type: Endpoint
+pub const DESC_ENDPOINT: u8 = 5;
+
+/// Request type flag: Device-to-host transfer
+pub const REQ_TYPE_IN: u8 = 0x80;
+/// Request type flag: Class-specific request
+pub const REQ_TYPE_CLASS: u8 = 0x20;
+/// Request type flag: The recipient is an interface
+pub const REQ_TYPE_INTERFACE: u8 = 0x01;
+/// Request type flag: The recipient is an endpoint
+pub const REQ_TYPE_ENDPOINT: u8 = 0x02;
+
+/// Standard request: Clear Feature
+pub const REQ_CLEAR_FEATURE: u8 = 1;
+/// Standard request: Get Descriptor
+pub const REQ_GET_DESCRIPTOR: u8 = 6;
+/// Standard request: Set Configuration
+pub const REQ_SET_CONFIGURATION: u8 = 9;
+
+/// Feature selector: Endpoint halt
+pub const FEATURE_ENDPOINT_HALT: u16 = 0;
+
+/// Endpoint address flag: Device-to-host endpoint
+pub const ENDPOINT_IN: u8 = 0x80;
+
+/// Interface class: Human Interface Device
+pub const CLASS_HID: u8 = 0x03;
+/// Interface class: Mass Storage
+pub const CLASS_MASS_STORAGE: u8 = 0x08;
+/// Device class: Hub
+pub const CLASS_HUB: u8 = 0x09;
+
+/// The speed of a USB device.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Speed {
+	/// Low speed (1.5 Mb/s)
+	Low,
+	/// Full speed (12 Mb/s)
+	Full,
+	/// High speed (480 Mb/s)
+	High,
+	/// SuperSpeed (5 Gb/s and above)
+	Super,
+}
+
+impl Speed {
+	/// Returns the default maximum packet size of the control endpoint, to be used until the
+	/// device descriptor has been read.
+	pub fn default_max_packet_size(self) -> u16 {
+		match self {
+			Self::Low | Self::Full => 8,
+			Self::High => 64,
+			Self::Super => 512,
+		}
+	}
+}
+
+/// A request on a control endpoint.
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct SetupPacket {
+	/// Characteristics of the request
+	pub request_type: u8,
+	/// The request
+	pub request: u8,
+	/// Request-specific value
+	pub value: u16,
+	/// Request-specific index or offset
+	pub index: u16,
+	/// The number of bytes to transfer in the data stage
+	pub length: u16,
+}
+
+/// Device descriptor.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct DeviceDescriptor {
+	/// The size of the descriptor in bytes
+	pub length: u8,
+	/// The type of the descriptor
+	pub descriptor_type: u8,
+	/// USB specification release number in binary-coded decimal
+	pub bcd_usb: u16,
+	/// Class code
+	pub device_class: u8,
+	/// Subclass code
+	pub device_subclass: u8,
+	/// Protocol code
+	pub device_protocol: u8,
+	/// Maximum packet size of the control endpoint. For SuperSpeed devices, this is an exponent
+	/// of two
+	pub max_packet_size0: u8,
+	/// Vendor ID
+	pub vendor_id: u16,
+	/// Product ID
+	pub product_id: u16,
+	/// Device release number in binary-coded decimal
+	pub bcd_device: u16,
+	/// Index of the manufacturer string descriptor
+	pub manufacturer: u8,
+	/// Index of the product string descriptor
+	pub product: u8,
+	/// Index of the serial number string descriptor
+	pub serial_number: u8,
+	/// The number of possible configurations
+	pub num_configurations: u8,
+}
+
+/// Configuration descriptor.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct ConfigurationDescriptor {
+	/// The size of the descriptor in bytes
+	pub length: u8,
+	/// The type of the descriptor
+	pub descriptor_type: u8,
+	/// The total size of the configuration, including interfaces and endpoints descriptors
+	pub total_length: u16,
+	/// The number of interfaces
+	pub num_interfaces: u8,
+	/// The value to use with `SET_CONFIGURATION` to select the configuration
+	pub configuration_value: u8,
+	/// Index of the string descriptor of the configuration
+	pub configuration: u8,
+	/// Configuration characteristics
+	pub attributes: u8,
+	/// Maximum power consumption, in units of 2 mA
+	pub max_power: u8,
+}
+
+/// Interface descriptor.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct InterfaceDescriptor {
+	/// The size of the descriptor in bytes
+	pub length: u8,
+	/// The type of the descriptor
+	pub descriptor_type: u8,
+	/// The number of the interface
+	pub interface_number: u8,
+	/// The alternate setting of the interface
+	pub alternate_setting: u8,
+	/// The number of endpoints, excluding the control endpoint
+	pub num_endpoints: u8,
+	/// Class code
+	pub interface_class: u8,
+	/// Subclass code
+	pub interface_subclass: u8,
+	/// Protocol code
+	pub interface_protocol: u8,
+	/// Index of the string descriptor of the interface
+	pub interface: u8,
+}
+
+/// The type of transfers an endpoint performs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TransferType {
+	/// Control transfers
+	Control,
+	/// Isochronous transfers
+	Isochronous,
+	/// Bulk transfers
+	Bulk,
+	/// Interrupt transfers
+	Interrupt,
+}
+
+/// Endpoint descriptor.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct EndpointDescriptor {
+	/// The size of the descriptor in bytes
+	pub length: u8,
+	/// The type of the descriptor
+	pub descriptor_type: u8,
+	/// The address of the endpoint, with its direction
+	pub endpoint_address: u8,
+	/// Endpoint characteristics
+	pub attributes: u8,
+	/// Maximum packet size
+	pub max_packet_size: u16,
+	/// Polling interval
+	pub interval: u8,
+}
+
+impl EndpointDescriptor {
+	/// Tells whether the endpoint transfers data from the device to the host.
+	#[inline]
+	pub fn is_in(&self) -> bool {
+		self.endpoint_address & ENDPOINT_IN != 0
+	}
+
+	/// Returns the type of transfers performed by the endpoint.
+	#[inline]
+	pub fn transfer_type(&self) -> TransferType {
+		match self.attributes & 0b11 {
+			0 => TransferType::Control,
+			1 => TransferType::Isochronous,
+			2 => TransferType::Bulk,
+			_ => TransferType::Interrupt,
+		}
+	}
+
+	/// Returns the maximum packet size of the endpoint.
+	#[inline]
+	pub fn max_packet_size(&self) -> u16 {
+		self.max_packet_size & 0x7ff
+	}
+}
+
+/// An interface of a USB device, with its endpoints.
+#[derive(Debug)]
+pub struct Interface {
+	/// The interface's descriptor
+	pub desc: InterfaceDescriptor,
+	/// The descriptors of the interface's endpoints
+	pub endpoints: Vec<EndpointDescriptor>,
+}
+
+/// Physically contiguous memory, suitable for DMA.
+///
+/// The memory is zeroed on allocation.
+pub struct DmaBuf {
+	/// Pointer to the memory
+	ptr: NonNull<u8>,
+	/// The order of the allocation
+	order: FrameOrder,
+}
+
+impl DmaBuf {
+	/// Allocates a buffer of `2^order` pages.
+	pub fn new(order: FrameOrder) -> AllocResult<Self> {
+		let ptr = buddy::alloc_kernel(order, 0)?;
+		unsafe {
+			NonNull::slice_from_raw_parts(ptr, buddy::get_frame_size(order))
+				.as_mut()
+				.fill(0);
+		}
+		Ok(Self {
+			ptr,
+			order,
+		})
+	}
+
+	/// Returns the physical address of the buffer.
+	#[inline]
+	pub fn phys_addr(&self) -> PhysAddr {
+		VirtAddr::from(self.ptr).kernel_to_physical().unwrap()
+	}
+
+	/// Returns a pointer to the object of type `T` at offset `off` in bytes in the buffer.
+	#[inline]
+	pub fn ptr<T>(&self, off: usize) -> *mut T {
+		debug_assert!(off + size_of::<T>() <= buddy::get_frame_size(self.order));
+		unsafe { self.ptr.add(off).cast().as_ptr() }
+	}
+
+	/// Returns the buffer as a slice.
+	#[inline]
+	pub fn as_slice(&self) -> &[u8] {
+		unsafe {
+			NonNull::slice_from_raw_parts(self.ptr, buddy::get_frame_size(self.order)).as_ref()
+		}
+	}
+}
+
+impl Drop for DmaBuf {
+	fn drop(&mut self) {
+		unsafe {
+			buddy::free_kernel(self.ptr.as_ptr(), self.order);
+		}
+	}
+}
+
+unsafe impl Send for DmaBuf {}
+
+unsafe impl Sync for DmaBuf {}
+
+/// Interface to a USB host controller, allowing to communicate with its devices.
+pub trait HostController {
+	/// Performs a transfer on the control endpoint of `dev`.
+	///
+	/// `data` is the physical address of the buffer for the data stage, if any. Its length and
+	/// the direction of the transfer are given by `setup`.
+	fn control_transfer(
+		&self,
+		dev: &UsbDevice,
+		setup: SetupPacket,
+		data: Option<PhysAddr>,
+	) -> EResult<()>;
+
+	/// Performs a bulk transfer of `len` bytes on the endpoint with address `endpoint` of `dev`.
+	///
+	/// `data` is the physical address of the buffer. It must not cross a 64 KiB boundary.
+	///
+	/// On success, the function returns the number of bytes transferred. If the endpoint stalls,
+	/// the function returns [`EPIPE`](utils::errno::EPIPE) and the endpoint has to be cleared with
+	/// [`HostController::clear_halt`].
+	fn bulk_transfer(
+		&self,
+		dev: &UsbDevice,
+		endpoint: u8,
+		data: PhysAddr,
+		len: usize,
+	) -> EResult<usize>;
+
+	/// Enables the given endpoints of `dev`, so that transfers can be performed on them.
+	fn configure_endpoints(
+		&self,
+		dev: &UsbDevice,
+		endpoints: &[EndpointDescriptor],
+	) -> EResult<()>;
+
+	/// Clears the halt condition of the endpoint with address `endpoint` of `dev`, on both the
+	/// host controller and the device.
+	fn clear_halt(&self, dev: &UsbDevice, endpoint: u8) -> EResult<()>;
+}
+
+/// A device attached to a USB bus.
+pub struct UsbDevice {
+	/// The host controller the device is attached to
+	pub hc: Arc<dyn HostController>,
+	/// The controller-specific identifier of the device
+	pub id: u8,
+	/// The root hub port the device is plugged on
+	pub port: u8,
+	/// The speed of the device
+	pub speed: Speed,
+
+	/// The device's descriptor
+	pub desc: DeviceDescriptor,
+	/// The interfaces of the selected configuration
+	pub interfaces: Vec<Interface>,
+}
+
+impl UsbDevice {
+	/// Performs a transfer on the control endpoint. See [`HostController::control_transfer`].
+	#[inline]
+	pub fn control_transfer(&self, setup: SetupPacket, data: Option<PhysAddr>) -> EResult<()> {
+		self.hc.control_transfer(self, setup, data)
+	}
+
+	/// Performs a bulk transfer. See [`HostController::bulk_transfer`].
+	#[inline]
+	pub fn bulk_transfer(&self, endpoint: u8, data: PhysAddr, len: usize) -> EResult<usize> {
+		self.hc.bulk_transfer(self, endpoint, data, len)
+	}
+
+	/// Clears the halt condition of an endpoint. See [`HostController::clear_halt`].
+	#[inline]
+	pub fn clear_halt(&self, endpoint: u8) -> EResult<()> {
+		self.hc.clear_halt(self, endpoint)
+	}
+
+	/// Reads the descriptor of type `desc_type` and index `index` into `buf`.
+	///
+	/// `len` is the number of bytes to read.
+	fn get_descriptor(&self, desc_type: u8, index: u8, buf: &DmaBuf, len: u16) -> EResult<()> {
+		self.control_transfer(
+			SetupPacket {
+				request_type: REQ_TYPE_IN,
+				request: REQ_GET_DESCRIPTOR,
+				value: ((desc_type as u16) << 8) | index as u16,
+				index: 0,
+				length: len,
+			},
+			Some(buf.phys_addr()),
+		)
+	}
+}
+
+impl fmt::Debug for UsbDevice {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("UsbDevice")
+			.field("id", &self.id)
+			.field("port", &self.port)
+			.field("speed", &self.speed)
+			.finish()
+	}
+}
+
+/// A class driver, handling interfaces of USB devices.
+pub trait Driver: Sync {
+	/// Returns the name of the driver.
+	fn name(&self) -> &'static str;
+
+	/// Tries to bind the driver to the interface `iface` of the device `dev`.
+	///
+	/// If the driver does not handle the interface, the function returns `false`.
+	fn probe(&self, dev: &Arc<UsbDevice>, iface: &Interface) -> EResult<bool>;
+}
+
+/// The list of registered class drivers.
+static DRIVERS: Spin<Vec<&'static dyn Driver>> = Spin::new(Vec::new());
+
+/// Registers a class driver.
+///
+/// The driver is used for devices attached after the call.
+pub fn register_driver(driver: &'static dyn Driver) -> AllocResult<()> {
+	DRIVERS.lock().push(driver)
+}
+
+/// Parses the interfaces and endpoints from the configuration descriptor in `buf`.
+///
+/// `len` is the total length of the configuration.
+fn parse_interfaces(buf: &[u8], len: usize) -> AllocResult<Vec<Interface>> {
+	let mut interfaces: Vec<Interface> = Vec::new();
+	let mut off = 0;
+	while off + 2 <= len {
+		let desc_len = buf[off] as usize;
+		if unlikely(desc_len < 2 || off + desc_len > len) {
+			break;
+		}
+		match buf[off + 1] {
+			DESC_INTERFACE if desc_len >= size_of::<InterfaceDescriptor>() => {
+				let desc = unsafe { read_unaligned(buf[off..].as_ptr().cast()) };
+				interfaces.push(Interface {
+					desc,
+					endpoints: Vec::new(),
+				})?;
+			}
+			DESC_ENDPOINT if desc_len >= size_of::<EndpointDescriptor>() => {
+				let desc = unsafe { read_unaligned(buf[off..].as_ptr().cast()) };
+				if let Some(iface) = interfaces.last_mut() {
+					iface.endpoints.push(desc)?;
+				}
+			}
+			// Ignore class-specific descriptors
+			_ => {}
+		}
+		off += desc_len;
+	}
+	Ok(interfaces)
+}
+
+/// Attaches the device with the controller-specific identifier `id` to the USB subsystem.
+///
+/// The device must have been assigned an address by its host controller.
+///
+/// Arguments:
+/// - `hc` is the host controller the device is attached to
+/// - `port` is the root hub port the device is plugged on
+/// - `speed` is the speed of the device
+pub fn attach(
+	hc: Arc<dyn HostController>,
+	id: u8,
+	port: u8,
+	speed: Speed,
+) -> EResult<Arc<UsbDevice>> {
+	let mut dev = UsbDevice {
+		hc,
+		id,
+		port,
+		speed,
+
+		desc: Default::default(),
+		interfaces: Vec::new(),
+	};
+	let buf = DmaBuf::new(0)?;
+	// Read device descriptor
+	dev.get_descriptor(DESC_DEVICE, 0, &buf, size_of::<DeviceDescriptor>() as _)?;
+	dev.desc = unsafe { read_unaligned(buf.ptr(0)) };
+	let vendor_id = dev.desc.vendor_id;
+	let product_id = dev.desc.product_id;
+	println!("usb: new device {vendor_id:04x}:{product_id:04x} on port {port} ({speed:?} speed)");
+	if dev.desc.device_class == CLASS_HUB {
+		// TODO support hubs
+		println!("usb: hubs are not supported");
+		return Ok(Arc::new(dev)?);
+	}
+	if unlikely(dev.desc.num_configurations == 0) {
+		return Ok(Arc::new(dev)?);
+	}
+	// Read the first configuration
+	let len = size_of::<ConfigurationDescriptor>();
+	dev.get_descriptor(DESC_CONFIGURATION, 0, &buf, len as _)?;
+	let config: ConfigurationDescriptor = unsafe { read_unaligned(buf.ptr(0)) };
+	let total_len = (config.total_length as usize).min(PAGE_SIZE);
+	dev.get_descriptor(DESC_CONFIGURATION, 0, &buf, total_len as _)?;
+	dev.interfaces = parse_interfaces(buf.as_slice(), total_len)?;
+	// Select configuration
+	dev.control_transfer(
+		SetupPacket {
+			request_type: 0,
+			request: REQ_SET_CONFIGURATION,
+			value: config.configuration_value as _,
+			index: 0,
+			length: 0,
+		},
+		None,
+	)?;
+	// Bind drivers
+	let dev = Arc::new(dev)?;
+	let drivers = DRIVERS.lock();
+	for iface in &dev.interfaces {
+		// Only the default setting is used
+		if iface.desc.alternate_setting != 0 {
+			continue;
+		}
+		for drv in drivers.iter() {
+			match drv.probe(&dev, iface) {
+				Ok(true) => break,
+				Ok(false) => {}
+				Err(e) if e.as_int() == ENOMEM => return Err(e),
+				Err(e) => {
+					println!("usb: {}: cannot bind interface: {e}", drv.name());
+				}
+			}
+		}
+	}
+	Ok(dev)
+}
+
+/// The list of xHCI controllers
+static XHCI_CONTROLLERS: Spin<Vec<xhci::Controller>> = Spin::new(Vec::new());
+
+/// PCI driver for xHCI controllers.
+struct XhciDriver;
+
+impl pci::Driver for XhciDriver {
+	fn name(&self) -> &'static str {
+		"xhci"
+	}
+
+	fn id_table(&self) -> &'static [DeviceId] {
+		const IDS: &[DeviceId] = &[DeviceId::class(
+			pci::CLASS_SERIAL_BUS_CONTROLLER,
+			0x03,
+			Some(0x30),
+		)];
+		IDS
+	}
+
+	fn probe(&self, dev: &PciDev) -> EResult<()> {
+		let ctrlr = xhci::Controller::new(dev)?;
+		XHCI_CONTROLLERS.lock().push(ctrlr)?;
+		Ok(())
+	}
+}
+
+/// Registers the drivers for USB host controllers.
+pub fn init() -> EResult<()> {
+	pci::register_driver(&XhciDriver)
+}
@@ -0,0 +1,1081 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! eXtensible Host Controller Interface (xHCI) driver
+//!
+//! Only devices plugged directly on the root hub are supported.
+//!
+//! [xHCI specification](https://www.intel.com/content/dam/www/public/us/en/documents/technical-specifications/extensible-host-controler-interface-usb-xhci.pdf)
+
+use super::{
+	DmaBuf, EndpointDescriptor, FEATURE_ENDPOINT_HALT, HostController, REQ_CLEAR_FEATURE,
+	REQ_TYPE_ENDPOINT, REQ_TYPE_IN, SetupPacket, Speed, TransferType, UsbDevice, attach,
+};
+use crate::{
+	arch::{
+		core_id,
+		x86::{idt::disable_int, timer::mdelay},
+	},
+	device::{bar::Bar, bus::pci::PciDev, manager::PhysicalDevice},
+	int,
+	int::CallbackHandle,
+	memory::PhysAddr,
+	println, process,
+	process::{Process, State, scheduler::schedule},
+	sync::{
+		mutex::Mutex,
+		spin::{IntSpin, Spin},
+	},
+};
+use core::{any::Any, array, hint::unlikely, mem::size_of, ptr, sync::atomic};
+use utils::{
+	collections::vec::Vec,
+	errno,
+	errno::{AllocResult, EResult},
+	limits::PAGE_SIZE,
+	ptr::arc::Arc,
+};
+
+/// Capability register: Capability Register Length
+const REG_CAPLENGTH: usize = 0x00;
+/// Capability register: Structural Parameters 1
+const REG_HCSPARAMS1: usize = 0x04;
+/// Capability register: Structural Parameters 2
+const REG_HCSPARAMS2: usize = 0x08;
+/// Capability register: Capability Parameters 1
+const REG_HCCPARAMS1: usize = 0x10;
+/// Capability register: Doorbell Offset
+const REG_DBOFF: usize = 0x14;
+/// Capability register: Runtime Register Space Offset
+const REG_RTSOFF: usize = 0x18;
+
+/// Operational register: USB Command
+const REG_USBCMD: usize = 0x00;
+/// Operational register: USB Status
+const REG_USBSTS: usize = 0x04;
+/// Operational register: Command Ring Control
+const REG_CRCR: usize = 0x18;
+/// Operational register: Device Context Base Address Array Pointer
+const REG_DCBAAP: usize = 0x30;
+/// Operational register: Configure
+const REG_CONFIG: usize = 0x38;
+/// Operational register: Port Status and Control of the first port
+const REG_PORTSC: usize = 0x400;
+
+/// Runtime register: Interrupter Management of the first interrupter
+const REG_IMAN: usize = 0x20;
+/// Runtime register: Interrupter Moderation of the first interrupter
+const REG_IMOD: usize = 0x24;
+/// Runtime register: Event Ring Segment Table Size of the first interrupter
+const REG_ERSTSZ: usize = 0x28;
+/// Runtime register: Event Ring Segment Table Base Address of the first interrupter
+const REG_ERSTBA: usize = 0x30;
+/// Runtime register: Event Ring Dequeue Pointer of the first interrupter
+const REG_ERDP: usize = 0x38;
+
+/// Flag (USBCMD): Run/Stop
+const FLAG_USBCMD_RS: u32 = 1 << 0;
+/// Flag (USBCMD): Host Controller Reset
+const FLAG_USBCMD_HCRST: u32 = 1 << 1;
+/// Flag (USBCMD): Interrupter Enable
+const FLAG_USBCMD_INTE: u32 = 1 << 2;
+/// Flag (USBSTS): HC Halted
+const FLAG_USBSTS_HCH: u32 = 1 << 0;
+/// Flag (USBSTS): Event Interrupt
+const FLAG_USBSTS_EINT: u32 = 1 << 3;
+/// Flag (USBSTS): Controller Not Ready
+const FLAG_USBSTS_CNR: u32 = 1 << 11;
+/// Flag (HCCPARAMS1): Context Size
+const FLAG_HCCPARAMS1_CSZ: u32 = 1 << 2;
+/// Flag (IMAN): Interrupt Pending
+const FLAG_IMAN_IP: u32 = 1 << 0;
+/// Flag (IMAN): Interrupt Enable
+const FLAG_IMAN_IE: u32 = 1 << 1;
+/// Flag (ERDP): Event Handler Busy
+const FLAG_ERDP_EHB: u64 = 1 << 3;
+
+/// Flag (PORTSC): Current Connect Status
+const FLAG_PORTSC_CCS: u32 = 1 << 0;
+/// Flag (PORTSC): Port Enabled
+const FLAG_PORTSC_PED: u32 = 1 << 1;
+/// Flag (PORTSC): Port Reset
+const FLAG_PORTSC_PR: u32 = 1 << 4;
+/// Flag (PORTSC): Connect Status Change
+const FLAG_PORTSC_CSC: u32 = 1 << 17;
+/// Flag (PORTSC): Port Reset Change
+const FLAG_PORTSC_PRC: u32 = 1 << 21;
+/// Mask of the bits of PORTSC that are cleared by writing `1`, including the Port Enabled bit
+const PORTSC_RW1C_MASK: u32 = FLAG_PORTSC_PED | (0x7f << 17);
+
+/// Extended capability ID: USB Legacy Support
+const EXT_CAP_LEGACY: u32 = 1;
+
+/// TRB type: Normal
+const TRB_NORMAL: u32 = 1;
+/// TRB type: Setup Stage
+const TRB_SETUP: u32 = 2;
+/// TRB type: Data Stage
+const TRB_DATA: u32 = 3;
+/// TRB type: Status Stage
+const TRB_STATUS: u32 = 4;
+/// TRB type: Link
+const TRB_LINK: u32 = 6;
+/// TRB type: Enable Slot Command
+const TRB_ENABLE_SLOT: u32 = 9;
+/// TRB type: Disable Slot Command
+const TRB_DISABLE_SLOT: u32 = 10;
+/// TRB type: Address Device Command
+const TRB_ADDRESS_DEVICE: u32 = 11;
+/// TRB type: Configure Endpoint Command
+const TRB_CONFIGURE_ENDPOINT: u32 = 12;
+/// TRB type: Evaluate Context Command
+const TRB_EVALUATE_CONTEXT: u32 = 13;
+/// TRB type: Reset Endpoint Command
+const TRB_RESET_ENDPOINT: u32 = 14;
+/// TRB type: Set TR Dequeue Pointer Command
+const TRB_SET_TR_DEQUEUE: u32 = 16;
+/// TRB type: Transfer Event
+const TRB_TRANSFER_EVENT: u32 = 32;
+/// TRB type: Command Completion Event
+const TRB_COMMAND_COMPLETION: u32 = 33;
+/// TRB type: Port Status Change Event
+const TRB_PORT_STATUS_CHANGE: u32 = 34;
+
+/// TRB flag: Cycle bit
+const FLAG_TRB_CYCLE: u32 = 1 << 0;
+/// TRB flag (Link): Toggle Cycle
+const FLAG_TRB_TC: u32 = 1 << 1;
+/// TRB flag: Interrupt-on Short Packet
+const FLAG_TRB_ISP: u32 = 1 << 2;
+/// TRB flag: Interrupt On Completion
+const FLAG_TRB_IOC: u32 = 1 << 5;
+/// TRB flag: Immediate Data
+const FLAG_TRB_IDT: u32 = 1 << 6;
+/// TRB flag (Data and Status stages): the direction is device-to-host
+const FLAG_TRB_DIR_IN: u32 = 1 << 16;
+
+/// Completion code: Success
+const COMPLETION_SUCCESS: u32 = 1;
+/// Completion code: Stall Error
+const COMPLETION_STALL: u32 = 6;
+/// Completion code: Short Packet
+const COMPLETION_SHORT_PACKET: u32 = 13;
+
+/// Endpoint type: Bulk OUT
+const EP_TYPE_BULK_OUT: u32 = 2;
+/// Endpoint type: Interrupt OUT
+const EP_TYPE_INTERRUPT_OUT: u32 = 3;
+/// Endpoint type: Control
+const EP_TYPE_CONTROL: u32 = 4;
+/// Endpoint type: Bulk IN
+const EP_TYPE_BULK_IN: u32 = 6;
+/// Endpoint type: Interrupt IN
+const EP_TYPE_INTERRUPT_IN: u32 = 7;
+
+/// The number of TRBs in a ring, including the Link TRB for transfer and command rings.
+const RING_LEN: usize = PAGE_SIZE / size_of::<Trb>();
+/// The number of Device Context Indexes (DCI) of a device slot.
+const DCI_COUNT: usize = 32;
+/// The timeout for the controller's state changes, in milliseconds.
+const TIMEOUT: u32 = 1000;
+
+/// Writes a 64 bit register at offset `off`, in two accesses.
+unsafe fn write_u64(bar: &Bar, off: usize, val: u64) {
+	unsafe {
+		bar.write::<u32>(off, val as u32);
+		bar.write::<u32>(off + 4, (val >> 32) as u32);
+	}
+}
+
+/// Waits until `f` returns `true`, for at most [`TIMEOUT`] milliseconds.
+///
+/// If the timeout is reached, the function returns [`errno::ETIMEDOUT`].
+fn wait_for<F: FnMut() -> bool>(mut f: F) -> EResult<()> {
+	for _ in 0..TIMEOUT {
+		if f() {
+			return Ok(());
+		}
+		mdelay(1);
+	}
+	Err(errno!(ETIMEDOUT))
+}
+
+/// Transfer Request Block, the unit of command, transfer and event rings.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, align(16))]
+struct Trb {
+	/// Parameter, usually a pointer
+	param: u64,
+	/// Status
+	status: u32,
+	/// Control, including the TRB type and the cycle bit
+	control: u32,
+}
+
+impl Trb {
+	/// Creates a TRB of type `ty`.
+	fn new(ty: u32, param: u64, status: u32, control: u32) -> Self {
+		Self {
+			param,
+			status,
+			control: (ty << 10) | control,
+		}
+	}
+
+	/// Returns the type of the TRB.
+	#[inline]
+	fn ty(&self) -> u32 {
+		(self.control >> 10) & 0x3f
+	}
+
+	/// Returns the completion code of an event TRB.
+	#[inline]
+	fn completion_code(&self) -> u32 {
+		self.status >> 24
+	}
+
+	/// Returns the slot ID of a command or event TRB.
+	#[inline]
+	fn slot_id(&self) -> u8 {
+		(self.control >> 24) as u8
+	}
+}
+
+/// A command or transfer ring, on which the host produces TRBs for the controller.
+struct Ring {
+	/// The ring's TRBs
+	buf: DmaBuf,
+	/// The index of the next TRB to be written
+	enqueue: usize,
+	/// The Producer Cycle State
+	cycle: bool,
+}
+
+impl Ring {
+	/// Creates a new ring.
+	fn new() -> AllocResult<Self> {
+		let buf = DmaBuf::new(0)?;
+		// Make the last TRB point back to the beginning of the ring
+		let link = Trb::new(TRB_LINK, buf.phys_addr().0 as _, 0, FLAG_TRB_TC);
+		unsafe {
+			buf.ptr::<Trb>((RING_LEN - 1) * size_of::<Trb>())
+				.write_volatile(link);
+		}
+		Ok(Self {
+			buf,
+			enqueue: 0,
+			cycle: true,
+		})
+	}
+
+	/// Returns the dequeue pointer to give to the controller for the next TRB to be written,
+	/// including the cycle state.
+	#[inline]
+	fn dequeue_ptr(&self) -> u64 {
+		(self.buf.phys_addr().0 + self.enqueue * size_of::<Trb>()) as u64 | self.cycle as u64
+	}
+
+	/// Writes `trb` on the ring, giving its ownership to the controller.
+	fn push(&mut self, mut trb: Trb) {
+		trb.control = (trb.control & !FLAG_TRB_CYCLE) | self.cycle as u32;
+		let ptr = self.buf.ptr::<Trb>(self.enqueue * size_of::<Trb>());
+		unsafe {
+			// Write the control field last, so that the controller does not see the TRB before
+			// it is complete
+			(&raw mut (*ptr).param).write_volatile(trb.param);
+			(&raw mut (*ptr).status).write_volatile(trb.status);
+			atomic::fence(atomic::Ordering::Release);
+			(&raw mut (*ptr).control).write_volatile(trb.control);
+		}
+		self.enqueue += 1;
+		if self.enqueue == RING_LEN - 1 {
+			// Give the Link TRB to the controller
+			let link = self.buf.ptr::<Trb>(self.enqueue * size_of::<Trb>());
+			unsafe {
+				let control = (&raw const (*link).control).read_volatile();
+				(&raw mut (*link).control)
+					.write_volatile((control & !FLAG_TRB_CYCLE) | self.cycle as u32);
+			}
+			self.enqueue = 0;
+			self.cycle = !self.cycle;
+		}
+	}
+}
+
+/// Entry of the Event Ring Segment Table.
+#[repr(C)]
+struct ErstEntry {
+	/// The address of the segment
+	addr: u64,
+	/// The number of TRBs in the segment
+	size: u32,
+	_reserved: u32,
+}
+
+/// The ring on which the controller produces events for the host.
+struct EventRing {
+	/// The ring's TRBs
+	buf: DmaBuf,
+	/// The Event Ring Segment Table
+	erst: DmaBuf,
+	/// The index of the next TRB to be read
+	dequeue: usize,
+	/// The Consumer Cycle State
+	cycle: bool,
+}
+
+impl EventRing {
+	/// Creates a new event ring, made of a single segment.
+	fn new() -> AllocResult<Self> {
+		let buf = DmaBuf::new(0)?;
+		let erst = DmaBuf::new(0)?;
+		unsafe {
+			erst.ptr::<ErstEntry>(0).write_volatile(ErstEntry {
+				addr: buf.phys_addr().0 as _,
+				size: RING_LEN as _,
+				_reserved: 0,
+			});
+		}
+		Ok(Self {
+			buf,
+			erst,
+			dequeue: 0,
+			cycle: true,
+		})
+	}
+
+	/// Returns the physical address of the next TRB to be read.
+	#[inline]
+	fn dequeue_ptr(&self) -> u64 {
+		(self.buf.phys_addr().0 + self.dequeue * size_of::<Trb>()) as u64
+	}
+
+	/// Returns the next event, if any.
+	fn pop(&mut self) -> Option<Trb> {
+		let trb = unsafe {
+			self.buf
+				.ptr::<Trb>(self.dequeue * size_of::<Trb>())
+				.read_volatile()
+		};
+		if (trb.control & FLAG_TRB_CYCLE != 0) != self.cycle {
+			return None;
+		}
+		self.dequeue += 1;
+		if self.dequeue == RING_LEN {
+			self.dequeue = 0;
+			self.cycle = !self.cycle;
+		}
+		Some(trb)
+	}
+}
+
+/// A process waiting for an event.
+#[derive(Clone, Default)]
+struct Waiter {
+	/// The waiting process
+	proc: Option<Arc<Process>>,
+	/// The received event
+	event: Option<Trb>,
+}
+
+/// State modified by the interrupt handler.
+struct EventState {
+	/// The event ring
+	ring: EventRing,
+	/// Waiters, indexed by `slot * DCI_COUNT + dci`. Index `0` is used by commands
+	waiters: Vec<Waiter>,
+}
+
+/// A device slot.
+struct Slot {
+	/// The slot ID
+	id: u8,
+	/// The output device context, written by the controller
+	ctx: DmaBuf,
+	/// The input context, used to pass parameters to commands
+	input: Mutex<DmaBuf, false>,
+	/// Transfer rings, by DCI
+	rings: [Mutex<Option<Ring>, false>; DCI_COUNT],
+}
+
+struct ControllerInner {
+	/// Base Address Register
+	bar: Bar,
+	/// Offset of the operational registers
+	op_off: usize,
+	/// Offset of the runtime registers
+	rt_off: usize,
+	/// Offset of the doorbell registers
+	db_off: usize,
+	/// The size of a context structure in bytes
+	ctx_size: usize,
+
+	/// Device Context Base Address Array
+	dcbaa: DmaBuf,
+	/// Scratchpad buffers array, followed by the buffers themselves
+	_scratchpad: Option<(DmaBuf, Vec<DmaBuf>)>,
+	/// Command ring
+	cmd_ring: Mutex<Ring, false>,
+	/// Event ring and waiters
+	events: IntSpin<EventState>,
+	/// Device slots, by slot ID
+	slots: Spin<Vec<Option<Arc<Slot>>>>,
+}
+
+impl ControllerInner {
+	/// Reads the operational register at `off`.
+	#[inline]
+	fn read_op(&self, off: usize) -> u32 {
+		unsafe { self.bar.read(self.op_off + off) }
+	}
+
+	/// Writes the operational register at `off`.
+	#[inline]
+	fn write_op(&self, off: usize, val: u32) {
+		unsafe { self.bar.write(self.op_off + off, val) }
+	}
+
+	/// Rings the doorbell `slot` with value `target`.
+	#[inline]
+	fn ring_doorbell(&self, slot: u8, target: u8) {
+		unsafe {
+			self.bar
+				.write::<u32>(self.db_off + slot as usize * 4, target as u32);
+		}
+	}
+
+	/// Handles pending events.
+	fn handle_int(&self) {
+		let mut events = self.events.lock();
+		// Acknowledge interrupt
+		unsafe {
+			let iman = self.bar.read::<u32>(self.rt_off + REG_IMAN);
+			self.bar
+				.write::<u32>(self.rt_off + REG_IMAN, iman | FLAG_IMAN_IP);
+		}
+		self.write_op(REG_USBSTS, FLAG_USBSTS_EINT);
+		while let Some(trb) = events.ring.pop() {
+			let index = match trb.ty() {
+				TRB_COMMAND_COMPLETION => 0,
+				TRB_TRANSFER_EVENT => {
+					let dci = (trb.control >> 16) & 0x1f;
+					trb.slot_id() as usize * DCI_COUNT + dci as usize
+				}
+				// TODO handle hotplug
+				TRB_PORT_STATUS_CHANGE => continue,
+				_ => continue,
+			};
+			let Some(waiter) = events.waiters.get_mut(index) else {
+				continue;
+			};
+			waiter.event = Some(trb);
+			if let Some(proc) = &waiter.proc {
+				Process::wake_from(proc, State::Sleeping as _);
+			}
+		}
+		// Update dequeue pointer and clear the busy flag
+		unsafe {
+			write_u64(
+				&self.bar,
+				self.rt_off + REG_ERDP,
+				events.ring.dequeue_ptr() | FLAG_ERDP_EHB,
+			);
+		}
+	}
+
+	/// Calls `submit` to submit a TRB, then waits for the event corresponding to `index` in
+	/// the waiters list.
+	fn wait_event<F: FnOnce()>(&self, index: usize, submit: F) -> Trb {
+		// Disable interrupts to prevent the event from being handled before the process is put
+		// to sleep
+		disable_int(|| {
+			{
+				let mut events = self.events.lock();
+				let waiter = &mut events.waiters[index];
+				waiter.proc = Some(Process::current());
+				waiter.event = None;
+				submit();
+				process::set_state(State::Sleeping);
+			}
+			loop {
+				schedule();
+				let mut events = self.events.lock();
+				let waiter = &mut events.waiters[index];
+				if let Some(event) = waiter.event.take() {
+					waiter.proc = None;
+					break event;
+				}
+				process::set_state(State::Sleeping);
+			}
+		})
+	}
+
+	/// Executes the command `trb`, returning the completion event.
+	fn command(&self, trb: Trb) -> EResult<Trb> {
+		let mut ring = self.cmd_ring.lock();
+		let event = self.wait_event(0, || {
+			ring.push(trb);
+			self.ring_doorbell(0, 0);
+		});
+		if unlikely(event.completion_code() != COMPLETION_SUCCESS) {
+			println!(
+				"xhci: command {} failed (completion code: {})",
+				trb.ty(),
+				event.completion_code()
+			);
+			return Err(errno!(EIO));
+		}
+		Ok(event)
+	}
+
+	/// Performs a transfer made of `trbs` on the endpoint `dci` of `slot`.
+	///
+	/// Only the last TRB generates an event. On success, the function returns the number of
+	/// bytes that have not been transferred.
+	fn transfer(&self, slot: &Slot, dci: u8, trbs: &[Trb]) -> EResult<usize> {
+		let mut ring = slot.rings[dci as usize].lock();
+		let ring = ring.as_mut().ok_or_else(|| errno!(EINVAL))?;
+		let index = slot.id as usize * DCI_COUNT + dci as usize;
+		let event = self.wait_event(index, || {
+			for trb in trbs {
+				ring.push(*trb);
+			}
+			self.ring_doorbell(slot.id, dci);
+		});
+		match event.completion_code() {
+			COMPLETION_SUCCESS | COMPLETION_SHORT_PACKET => Ok((event.status & 0xffffff) as usize),
+			COMPLETION_STALL => Err(errno!(EPIPE)),
+			code => {
+				println!(
+					"xhci: transfer failed on slot {} endpoint {dci} (completion code: {code})",
+					slot.id
+				);
+				Err(errno!(EIO))
+			}
+		}
+	}
+
+	/// Returns the slot associated with `dev`.
+	fn get_slot(&self, dev: &UsbDevice) -> EResult<Arc<Slot>> {
+		self.slots
+			.lock()
+			.get(dev.id as usize)
+			.cloned()
+			.flatten()
+			.ok_or_else(|| errno!(ENODEV))
+	}
+
+	/// Writes the context of the control endpoint in the input context `input`.
+	fn write_ep0_ctx(&self, input: &DmaBuf, ring: &Ring, max_packet_size: u16) {
+		let ep0 = input.ptr::<[u32; 5]>(2 * self.ctx_size);
+		let dequeue = ring.dequeue_ptr();
+		unsafe {
+			ep0.write_volatile([
+				0,
+				(3 << 1) | (EP_TYPE_CONTROL << 3) | ((max_packet_size as u32) << 16),
+				dequeue as u32,
+				(dequeue >> 32) as u32,
+				8,
+			]);
+		}
+	}
+
+	/// Enables a slot for the device on `port`, and assigns it an address.
+	fn address_device(&self, port: u8, speed: u32) -> EResult<Arc<Slot>> {
+		let event = self.command(Trb::new(TRB_ENABLE_SLOT, 0, 0, 0))?;
+		let id = event.slot_id();
+		let res = self.init_slot(id, port, speed);
+		if res.is_err() {
+			let _ = self.command(Trb::new(TRB_DISABLE_SLOT, 0, 0, (id as u32) << 24));
+			unsafe {
+				self.dcbaa.ptr::<u64>(id as usize * 8).write_volatile(0);
+			}
+		}
+		res
+	}
+
+	/// Initializes the slot `id` for the device on `port`, and assigns it an address.
+	fn init_slot(&self, id: u8, port: u8, speed: u32) -> EResult<Arc<Slot>> {
+		let max_packet_size = Self::speed(speed)
+			.ok_or_else(|| errno!(EINVAL))?
+			.default_max_packet_size();
+		let slot = Arc::new(Slot {
+			id,
+			ctx: DmaBuf::new(0)?,
+			input: Mutex::new(DmaBuf::new(0)?),
+			rings: array::from_fn(|_| Mutex::new(None)),
+		})?;
+		unsafe {
+			self.dcbaa
+				.ptr::<u64>(id as usize * 8)
+				.write_volatile(slot.ctx.phys_addr().0 as _);
+		}
+		let ring = Ring::new()?;
+		{
+			let input = slot.input.lock();
+			// Input control context: add slot and control endpoint contexts
+			unsafe {
+				input.ptr::<u32>(4).write_volatile(0b11);
+				// Slot context
+				input
+					.ptr::<[u32; 2]>(self.ctx_size)
+					.write_volatile([(speed << 20) | (1 << 27), (port as u32) << 16]);
+			}
+			self.write_ep0_ctx(&input, &ring, max_packet_size);
+			*slot.rings[1].lock() = Some(ring);
+			self.command(Trb::new(
+				TRB_ADDRESS_DEVICE,
+				input.phys_addr().0 as _,
+				0,
+				(id as u32) << 24,
+			))?;
+		}
+		self.slots.lock()[id as usize] = Some(slot.clone());
+		Ok(slot)
+	}
+
+	/// Returns the speed corresponding to the Port Speed ID `speed`, using the default mapping.
+	fn speed(speed: u32) -> Option<Speed> {
+		match speed {
+			1 => Some(Speed::Full),
+			2 => Some(Speed::Low),
+			3 => Some(Speed::High),
+			4 | 5 => Some(Speed::Super),
+			_ => None,
+		}
+	}
+
+	/// Reads the Port Status and Control register of `port`.
+	#[inline]
+	fn read_portsc(&self, port: u8) -> u32 {
+		self.read_op(REG_PORTSC + (port as usize - 1) * 0x10)
+	}
+
+	/// Writes the Port Status and Control register of `port`.
+	#[inline]
+	fn write_portsc(&self, port: u8, val: u32) {
+		self.write_op(REG_PORTSC + (port as usize - 1) * 0x10, val);
+	}
+}
+
+/// Handle to the controller, implementing [`HostController`].
+struct Handle(Arc<ControllerInner>);
+
+impl Handle {
+	/// Initializes the device plugged on `port`, if any.
+	fn init_port(this: &Arc<Self>, port: u8) -> EResult<()> {
+		let inner = &this.0;
+		let portsc = inner.read_portsc(port);
+		if portsc & FLAG_PORTSC_CCS == 0 {
+			return Ok(());
+		}
+		// USB 2 ports need a reset to be enabled, while USB 3 ports are enabled automatically
+		if portsc & FLAG_PORTSC_PED == 0 {
+			inner.write_portsc(port, (portsc & !PORTSC_RW1C_MASK) | FLAG_PORTSC_PR);
+			wait_for(|| inner.read_portsc(port) & FLAG_PORTSC_PRC != 0)?;
+		}
+		// Clear changes
+		let portsc = inner.read_portsc(port);
+		inner.write_portsc(
+			port,
+			(portsc & !PORTSC_RW1C_MASK) | FLAG_PORTSC_CSC | FLAG_PORTSC_PRC,
+		);
+		if unlikely(portsc & FLAG_PORTSC_PED == 0) {
+			println!("xhci: cannot enable port {port}");
+			return Err(errno!(EIO));
+		}
+		let speed_id = (portsc >> 10) & 0xf;
+		let Some(speed) = ControllerInner::speed(speed_id) else {
+			println!("xhci: unknown speed on port {port}");
+			return Err(errno!(EINVAL));
+		};
+		let slot = inner.address_device(port, speed_id)?;
+		// Read the actual maximum packet size of the control endpoint
+		let buf = DmaBuf::new(0)?;
+		let dev = UsbDevice {
+			hc: this.clone(),
+			id: slot.id,
+			port,
+			speed,
+
+			desc: Default::default(),
+			interfaces: Vec::new(),
+		};
+		dev.get_descriptor(super::DESC_DEVICE, 0, &buf, 8)?;
+		let max_packet_size = match (speed, buf.as_slice()[7]) {
+			(Speed::Super, exp) => 1u16 << exp.min(15),
+			(_, size) => size as u16,
+		};
+		if max_packet_size != speed.default_max_packet_size() {
+			let input = slot.input.lock();
+			unsafe {
+				// Only evaluate the control endpoint context
+				input.ptr::<[u32; 2]>(0).write_volatile([0, 0b10]);
+			}
+			let ring = slot.rings[1].lock();
+			inner.write_ep0_ctx(&input, ring.as_ref().unwrap(), max_packet_size);
+			inner.command(Trb::new(
+				TRB_EVALUATE_CONTEXT,
+				input.phys_addr().0 as _,
+				0,
+				(slot.id as u32) << 24,
+			))?;
+		}
+		drop(dev);
+		attach(this.clone(), slot.id, port, speed)?;
+		Ok(())
+	}
+}
+
+/// Returns the Device Context Index of the endpoint with address `addr`.
+#[inline]
+fn endpoint_dci(addr: u8) -> u8 {
+	(addr & 0xf) * 2 + (addr >> 7)
+}
+
+impl HostController for Handle {
+	fn control_transfer(
+		&self,
+		dev: &UsbDevice,
+		setup: SetupPacket,
+		data: Option<PhysAddr>,
+	) -> EResult<()> {
+		let slot = self.0.get_slot(dev)?;
+		let is_in = setup.request_type & REQ_TYPE_IN != 0;
+		let len = setup.length as u32;
+		let data = data.filter(|_| len > 0);
+		// Transfer type
+		let trt = match (data, is_in) {
+			(None, _) => 0,
+			(Some(_), false) => 2,
+			(Some(_), true) => 3,
+		};
+		let setup_param = unsafe { ptr::read_unaligned(&setup as *const _ as *const u64) };
+		let mut trbs = [Trb::default(); 3];
+		let mut n = 0;
+		trbs[n] = Trb::new(TRB_SETUP, setup_param, 8, FLAG_TRB_IDT | (trt << 16));
+		n += 1;
+		if let Some(data) = data {
+			let dir = if is_in { FLAG_TRB_DIR_IN } else { 0 };
+			trbs[n] = Trb::new(TRB_DATA, data.0 as _, len, dir);
+			n += 1;
+		}
+		// The status stage goes in the opposite direction of the data stage
+		let dir = if data.is_none() || !is_in {
+			FLAG_TRB_DIR_IN
+		} else {
+			0
+		};
+		trbs[n] = Trb::new(TRB_STATUS, 0, 0, FLAG_TRB_IOC | dir);
+		n += 1;
+		self.0.transfer(&slot, 1, &trbs[..n])?;
+		Ok(())
+	}
+
+	fn bulk_transfer(
+		&self,
+		dev: &UsbDevice,
+		endpoint: u8,
+		data: PhysAddr,
+		len: usize,
+	) -> EResult<usize> {
+		if unlikely(len > 0x10000) {
+			return Err(errno!(EINVAL));
+		}
+		let slot = self.0.get_slot(dev)?;
+		let trb = Trb::new(
+			TRB_NORMAL,
+			data.0 as _,
+			len as _,
+			FLAG_TRB_IOC | FLAG_TRB_ISP,
+		);
+		let remaining = self.0.transfer(&slot, endpoint_dci(endpoint), &[trb])?;
+		Ok(len.saturating_sub(remaining))
+	}
+
+	fn configure_endpoints(
+		&self,
+		dev: &UsbDevice,
+		endpoints: &[EndpointDescriptor],
+	) -> EResult<()> {
+		let inner = &self.0;
+		let slot = inner.get_slot(dev)?;
+		let input = slot.input.lock();
+		unsafe {
+			ptr::write_bytes(input.ptr::<u8>(0), 0, PAGE_SIZE);
+		}
+		let mut add = 1;
+		let mut max_dci = 1;
+		for ep in endpoints {
+			let dci = endpoint_dci(ep.endpoint_address);
+			let ep_type = match (ep.transfer_type(), ep.is_in()) {
+				(TransferType::Bulk, false) => EP_TYPE_BULK_OUT,
+				(TransferType::Bulk, true) => EP_TYPE_BULK_IN,
+				(TransferType::Interrupt, false) => EP_TYPE_INTERRUPT_OUT,
+				(TransferType::Interrupt, true) => EP_TYPE_INTERRUPT_IN,
+				_ => return Err(errno!(EINVAL)),
+			};
+			// Convert the interval to an exponent of 125 µs units
+			let interval = match (ep.transfer_type(), dev.speed) {
+				(TransferType::Interrupt, Speed::Low | Speed::Full) => {
+					(ep.interval.max(1) as u32 * 8).ilog2()
+				}
+				(TransferType::Interrupt, _) => ep.interval.clamp(1, 16) as u32 - 1,
+				_ => 0,
+			};
+			let max_packet_size = ep.max_packet_size() as u32;
+			let ring = Ring::new()?;
+			let dequeue = ring.dequeue_ptr();
+			unsafe {
+				input
+					.ptr::<[u32; 5]>((dci as usize + 1) * inner.ctx_size)
+					.write_volatile([
+						interval << 16,
+						(3 << 1) | (ep_type << 3) | (max_packet_size << 16),
+						dequeue as u32,
+						(dequeue >> 32) as u32,
+						max_packet_size | (max_packet_size << 16),
+					]);
+			}
+			*slot.rings[dci as usize].lock() = Some(ring);
+			add |= 1 << dci;
+			max_dci = max_dci.max(dci as u32);
+		}
+		unsafe {
+			// Input control context
+			input.ptr::<[u32; 2]>(0).write_volatile([0, add]);
+			// Copy the slot context from the output context, updating the number of entries
+			let slot_ctx = slot.ctx.ptr::<[u32; 4]>(0).read_volatile();
+			input.ptr::<[u32; 4]>(inner.ctx_size).write_volatile([
+				(slot_ctx[0] & !(0x1f << 27)) | (max_dci << 27),
+				slot_ctx[1],
+				slot_ctx[2],
+				0,
+			]);
+		}
+		inner.command(Trb::new(
+			TRB_CONFIGURE_ENDPOINT,
+			input.phys_addr().0 as _,
+			0,
+			(slot.id as u32) << 24,
+		))?;
+		Ok(())
+	}
+
+	fn clear_halt(&self, dev: &UsbDevice, endpoint: u8) -> EResult<()> {
+		let inner = &self.0;
+		let slot = inner.get_slot(dev)?;
+		let dci = endpoint_dci(endpoint);
+		let target = ((slot.id as u32) << 24) | ((dci as u32) << 16);
+		inner.command(Trb::new(TRB_RESET_ENDPOINT, 0, 0, target))?;
+		// Skip the TRBs of the failed transfer
+		{
+			let ring = slot.rings[dci as usize].lock();
+			let ring = ring.as_ref().ok_or_else(|| errno!(EINVAL))?;
+			inner.command(Trb::new(TRB_SET_TR_DEQUEUE, ring.dequeue_ptr(), 0, target))?;
+		}
+		dev.control_transfer(
+			SetupPacket {
+				request_type: REQ_TYPE_ENDPOINT,
+				request: REQ_CLEAR_FEATURE,
+				value: FEATURE_ENDPOINT_HALT,
+				index: endpoint as _,
+				length: 0,
+			},
+			None,
+		)
+	}
+}
+
+/// A xHCI controller.
+pub struct Controller {
+	/// The handle to the controller
+	_handle: Arc<Handle>,
+	/// The interrupt callback
+	_int: CallbackHandle,
+}
+
+impl Controller {
+	/// Creates a new instance, initializing the controller and the devices plugged on it.
+	pub fn new(dev: &dyn PhysicalDevice) -> EResult<Self> {
+		// A xHCI can only be connected to a PCI bus
+		let dev: &PciDev = (dev as &dyn Any).downcast_ref().unwrap();
+		let bar = dev.get_bars().first().cloned().flatten();
+		let Some(bar) = bar else {
+			println!("xhci: BAR not found");
+			return Err(errno!(EINVAL));
+		};
+		// Enable interrupts, bus mastering and memory access
+		dev.write_status_command((dev.read_status_command() & !(1 << 10)) | 0b110);
+		let (op_off, hcsparams1, hcsparams2, hccparams1, db_off, rt_off) = unsafe {
+			(
+				bar.read::<u8>(REG_CAPLENGTH) as usize,
+				bar.read::<u32>(REG_HCSPARAMS1),
+				bar.read::<u32>(REG_HCSPARAMS2),
+				bar.read::<u32>(REG_HCCPARAMS1),
+				bar.read::<u32>(REG_DBOFF) as usize & !0b11,
+				bar.read::<u32>(REG_RTSOFF) as usize & !0x1f,
+			)
+		};
+		let max_slots = (hcsparams1 & 0xff) as u8;
+		let max_ports = (hcsparams1 >> 24) as u8;
+		let ctx_size = if hccparams1 & FLAG_HCCPARAMS1_CSZ != 0 {
+			64
+		} else {
+			32
+		};
+		// Take ownership from the BIOS
+		let mut ext_off = ((hccparams1 >> 16) as usize) << 2;
+		while ext_off != 0 {
+			let cap: u32 = unsafe { bar.read(ext_off) };
+			if cap & 0xff == EXT_CAP_LEGACY {
+				unsafe {
+					bar.write::<u32>(ext_off, cap | (1 << 24));
+				}
+				let res = wait_for(|| unsafe { bar.read::<u32>(ext_off) } & (1 << 16) == 0);
+				if res.is_err() {
+					println!("xhci: BIOS did not release the controller");
+				}
+				break;
+			}
+			let next = ((cap >> 8) & 0xff) as usize;
+			ext_off = if next != 0 { ext_off + (next << 2) } else { 0 };
+		}
+		// Stop and reset the controller
+		let usbcmd: u32 = unsafe { bar.read(op_off + REG_USBCMD) };
+		unsafe {
+			bar.write::<u32>(op_off + REG_USBCMD, usbcmd & !FLAG_USBCMD_RS);
+		}
+		wait_for(|| unsafe { bar.read::<u32>(op_off + REG_USBSTS) } & FLAG_USBSTS_HCH != 0)?;
+		unsafe {
+			bar.write::<u32>(op_off + REG_USBCMD, FLAG_USBCMD_HCRST);
+		}
+		wait_for(|| unsafe {
+			bar.read::<u32>(op_off + REG_USBCMD) & FLAG_USBCMD_HCRST == 0
+				&& bar.read::<u32>(op_off + REG_USBSTS) & FLAG_USBSTS_CNR == 0
+		})
+		.inspect_err(|_| println!("xhci: controller reset timeout"))?;
+		// Allocate structures
+		let dcbaa = DmaBuf::new(0)?;
+		let scratchpad_count = (((hcsparams2 >> 21) & 0x1f) << 5) | (hcsparams2 >> 27);
+		let scratchpad = if scratchpad_count > 0 {
+			let array = DmaBuf::new(0)?;
+			let mut bufs = Vec::with_capacity(scratchpad_count as _)?;
+			for i in 0..scratchpad_count as usize {
+				let buf = DmaBuf::new(0)?;
+				unsafe {
+					array
+						.ptr::<u64>(i * 8)
+						.write_volatile(buf.phys_addr().0 as _);
+				}
+				bufs.push(buf)?;
+			}
+			unsafe {
+				dcbaa.ptr::<u64>(0).write_volatile(array.phys_addr().0 as _);
+			}
+			Some((array, bufs))
+		} else {
+			None
+		};
+		let mut waiters = Vec::new();
+		waiters.resize((max_slots as usize + 1) * DCI_COUNT, Waiter::default())?;
+		let mut slots = Vec::new();
+		slots.resize(max_slots as usize + 1, None)?;
+		let inner = Arc::new(ControllerInner {
+			bar,
+			op_off,
+			rt_off,
+			db_off,
+			ctx_size,
+
+			dcbaa,
+			_scratchpad: scratchpad,
+			cmd_ring: Mutex::new(Ring::new()?),
+			events: IntSpin::new(EventState {
+				ring: EventRing::new()?,
+				waiters,
+			}),
+			slots: Spin::new(slots),
+		})?;
+		// Setup interrupt handler
+		let int = unsafe {
+			let inner_ = inner.clone();
+			int::alloc_callback(move |_, _, _, _| inner_.handle_int())?
+		};
+		if let Some(msi_x) = dev.enable_msi_x() {
+			msi_x
+				.set(0, core_id() as _, true, false, int.id())
+				.inspect_err(|_| println!("xhci: failed to initialize MSI-X"))?;
+		} else if !dev.enable_msi(core_id() as _, true, false, int.id()) {
+			println!("xhci: no MSI or MSI-X, driver does not support legacy interrupts");
+			unsafe {
+				int.unregister();
+			}
+			return Err(errno!(EINVAL));
+		}
+		// Setup the controller
+		inner.write_op(REG_CONFIG, max_slots as u32);
+		unsafe {
+			write_u64(
+				&inner.bar,
+				op_off + REG_DCBAAP,
+				inner.dcbaa.phys_addr().0 as _,
+			);
+			write_u64(
+				&inner.bar,
+				op_off + REG_CRCR,
+				inner.cmd_ring.lock().dequeue_ptr(),
+			);
+			let events = inner.events.lock();
+			inner.bar.write::<u32>(rt_off + REG_ERSTSZ, 1);
+			write_u64(&inner.bar, rt_off + REG_ERDP, events.ring.dequeue_ptr());
+			write_u64(
+				&inner.bar,
+				rt_off + REG_ERSTBA,
+				events.ring.erst.phys_addr().0 as _,
+			);
+			// Moderate interrupts to at most one every millisecond
+			inner.bar.write::<u32>(rt_off + REG_IMOD, 4000);
+			inner
+				.bar
+				.write::<u32>(rt_off + REG_IMAN, FLAG_IMAN_IP | FLAG_IMAN_IE);
+		}
+		// Start the controller
+		inner.write_op(REG_USBCMD, FLAG_USBCMD_RS | FLAG_USBCMD_INTE);
+		wait_for(|| inner.read_op(REG_USBSTS) & FLAG_USBSTS_HCH == 0)
+			.inspect_err(|_| println!("xhci: controller start timeout"))?;
+		let handle = Arc::new(Handle(inner))?;
+		println!("xhci: controller started ({max_ports} ports, {max_slots} slots)");
+		// Let ports settle after the reset
+		mdelay(100);
+		for port in 1..=max_ports {
+			if let Err(e) = Handle::init_port(&handle, port) {
+				println!("xhci: cannot initialize device on port {port}: {e}");
+			}
+		}
+		// Check whether the controller is still running
+		let sts = handle.0.read_op(REG_USBSTS);
+		if unlikely(sts & FLAG_USBSTS_HCH != 0) {
+			println!("xhci: controller halted");
+		}
+		Ok(Self {
+			_handle: handle,
+			_int: int,
+		})
+	}
+}
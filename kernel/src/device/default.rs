@@ -20,15 +20,28 @@
 
 use super::{CharDev, DeviceType, register_char};
 use crate::{
-	device::{DeviceID, id::MajorBlock, tty::TTYDeviceHandle},
-	file::{File, fs::FileOps},
+	device::{
+		DeviceID,
+		id::MajorBlock,
+		tty::{CTTY_ID, CttyDeviceHandle, TTYDeviceHandle},
+	},
+	file::{File, O_NONBLOCK, SEEK_DATA, SEEK_END, SEEK_SET, fs::FileOps},
 	logger,
+	logger::{BUF, DEFAULT_LEVEL, LOG_USER, Level, RecordCursor},
 	memory::user::UserSlice,
 	rand,
 	rand::{GRND_RANDOM, getrandom},
+	sync::spin::Spin,
+	tty::VT_COUNT,
 };
 use core::mem::ManuallyDrop;
-use utils::{collections::path::PathBuf, errno, errno::EResult};
+use utils::{
+	DisplayableStr,
+	collections::{hashmap::HashMap, path::PathBuf},
+	errno,
+	errno::EResult,
+	format,
+};
 
 /// Device which does nothing.
 #[derive(Debug)]
@@ -102,21 +115,88 @@ impl FileOps for URandomDeviceHandle {
 	}
 }
 
-/// Device allowing to read or write kernel logs.
-#[derive(Debug)]
-pub struct KMsgDeviceHandle;
+/// Device allowing to read kernel logs record by record, and to write to them.
+///
+/// Each open file has its own position in the logs.
+#[derive(Debug, Default)]
+pub struct KMsgDeviceHandle {
+	/// The cursor of each open file, by file identifier.
+	readers: Spin<HashMap<usize, RecordCursor>>,
+}
+
+impl KMsgDeviceHandle {
+	/// Returns the key identifying `file` in the readers list.
+	fn reader_id(file: &File) -> usize {
+		file.id()
+	}
+}
 
 impl FileOps for KMsgDeviceHandle {
-	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
-		let off = off.try_into().map_err(|_| errno!(EINVAL))?;
-		let logger = logger::BUF.lock();
-		let content = logger.get_content();
-		let l = buf.copy_to_user(0, &content[off..])?;
-		Ok(l)
+	fn acquire(&self, file: &File) {
+		// On failure, the cursor is created when the file is first read
+		let cursor = BUF.lock().first_record();
+		let _ = self.readers.lock().insert(Self::reader_id(file), cursor);
+	}
+
+	fn release(&self, file: &File) {
+		self.readers.lock().remove(&Self::reader_id(file));
 	}
 
-	fn write(&self, _file: &File, _off: u64, _buf: UserSlice<u8>) -> EResult<usize> {
-		todo!();
+	fn read(&self, file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let id = Self::reader_id(file);
+		let mut cursor = self
+			.readers
+			.lock()
+			.get(&id)
+			.cloned()
+			.unwrap_or_else(|| BUF.lock().first_record());
+		let nonblock = file.get_flags() & O_NONBLOCK != 0;
+		// The cursor is saved even on error, since it is reset when records are overwritten
+		let res = logger::read_record(&mut cursor, buf, nonblock);
+		self.readers.lock().insert(id, cursor)?;
+		res
+	}
+
+	fn write(&self, _file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let msg = buf.copy_from_user_vec(0)?.ok_or_else(|| errno!(EFAULT))?;
+		// Parse the optional `<priority>` prefix. The kernel facility is reserved
+		let (facility, level, content) = msg
+			.strip_prefix(b"<")
+			.and_then(|s| {
+				let end = s.iter().position(|c| !c.is_ascii_digit())?;
+				let (prio, rest) = s.split_at(end);
+				let content = rest.strip_prefix(b">")?;
+				if prio.is_empty() {
+					return None;
+				}
+				let prio = prio
+					.iter()
+					.try_fold(0u8, |n, c| n.checked_mul(10)?.checked_add(c - b'0'))?;
+				let facility = (prio >> 3).max(LOG_USER);
+				let level = Level::try_from(prio & 7).ok()?;
+				Some((facility, level, content))
+			})
+			.unwrap_or((LOG_USER, DEFAULT_LEVEL, msg.as_slice()));
+		let content = content.strip_suffix(b"\n").unwrap_or(content);
+		BUF.lock().write_record(
+			facility,
+			level,
+			format_args!("{}\n", DisplayableStr(content)),
+		);
+		Ok(msg.len())
+	}
+
+	fn seek(&self, file: &File, off: i64, whence: u32) -> EResult<Option<u64>> {
+		if off != 0 {
+			return Err(errno!(ESPIPE));
+		}
+		let cursor = match whence {
+			SEEK_SET | SEEK_DATA => BUF.lock().first_record(),
+			SEEK_END => BUF.lock().end_record(),
+			_ => return Err(errno!(EINVAL)),
+		};
+		self.readers.lock().insert(Self::reader_id(file), cursor)?;
+		Ok(Some(0))
 	}
 }
 
@@ -166,18 +246,29 @@ pub(super) fn create() -> EResult<()> {
 		},
 		PathBuf::try_from(b"/dev/kmsg")?,
 		0o600,
-		KMsgDeviceHandle,
+		KMsgDeviceHandle::default(),
 	)?)?;
 
+	let _fourth_major = ManuallyDrop::new(MajorBlock::new_fixed(DeviceType::Char, 4)?);
+	// `tty0` refers to the active virtual terminal
+	for vt in 0..=VT_COUNT {
+		register_char(CharDev::new(
+			DeviceID {
+				major: 4,
+				minor: vt as _,
+			},
+			PathBuf::try_from(format!("/dev/tty{vt}")?)?,
+			0o620,
+			TTYDeviceHandle::new(vt),
+		)?)?;
+	}
+
 	let _fifth_major = ManuallyDrop::new(MajorBlock::new_fixed(DeviceType::Char, 5)?);
 	register_char(CharDev::new(
-		DeviceID {
-			major: 5,
-			minor: 0,
-		},
+		CTTY_ID,
 		PathBuf::try_from(b"/dev/tty")?,
 		0o666,
-		TTYDeviceHandle,
+		CttyDeviceHandle,
 	)?)?;
 
 	Ok(())
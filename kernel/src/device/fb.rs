@@ -22,10 +22,20 @@ use crate::{
 	arch::x86::paging::{FLAG_CACHE_DISABLE, FLAG_GLOBAL, FLAG_WRITE, FLAG_WRITE_THROUGH},
 	device::{CharDev, DeviceID, DeviceType, id::MajorBlock, register_char},
 	file::{File, fs::FileOps},
-	memory::{PhysAddr, VirtAddr, user::UserSlice, vmem::KERNEL_VMEM},
+	memory::{
+		PhysAddr, VirtAddr,
+		user::{UserPtr, UserSlice},
+		vmem::KERNEL_VMEM,
+	},
 	multiboot::FramebufferInfo,
+	syscall::{FromSyscallArg, ioctl},
+};
+use core::{
+	cmp::min,
+	ffi::{c_ulong, c_void},
+	hint::unlikely,
+	mem::ManuallyDrop,
 };
-use core::{hint::unlikely, mem::ManuallyDrop};
 use utils::{
 	collections::path::PathBuf,
 	errno,
@@ -37,6 +47,130 @@ use utils::{
 /// Flags used to map a framebuffer
 pub const MAP_FLAGS: usize = FLAG_CACHE_DISABLE | FLAG_WRITE_THROUGH | FLAG_WRITE | FLAG_GLOBAL;
 
+/// ioctl request: get variable screen information.
+const FBIOGET_VSCREENINFO: c_ulong = 0x4600;
+/// ioctl request: set variable screen information.
+const FBIOPUT_VSCREENINFO: c_ulong = 0x4601;
+/// ioctl request: get fixed screen information.
+const FBIOGET_FSCREENINFO: c_ulong = 0x4602;
+
+/// Framebuffer type: packed pixels.
+const FB_TYPE_PACKED_PIXELS: u32 = 0;
+/// Framebuffer visual: true color.
+const FB_VISUAL_TRUECOLOR: u32 = 2;
+
+/// Description of the position of a color component in a pixel.
+#[repr(C)]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FbBitfield {
+	/// Offset of the component, in bits
+	pub offset: u32,
+	/// Length of the component, in bits
+	pub length: u32,
+	/// If non-zero, the most significant bit is on the right
+	pub msb_right: u32,
+}
+
+/// Variable screen information, which can be changed by userspace.
+#[repr(C)]
+#[derive(Clone, Debug, Default)]
+pub struct FbVarScreenInfo {
+	/// Visible horizontal resolution
+	pub xres: u32,
+	/// Visible vertical resolution
+	pub yres: u32,
+	/// Virtual horizontal resolution
+	pub xres_virtual: u32,
+	/// Virtual vertical resolution
+	pub yres_virtual: u32,
+	/// Horizontal offset from virtual to visible resolution
+	pub xoffset: u32,
+	/// Vertical offset from virtual to visible resolution
+	pub yoffset: u32,
+	/// Number of bits per pixel
+	pub bits_per_pixel: u32,
+	/// If non-zero, the framebuffer is in grayscale
+	pub grayscale: u32,
+	/// Red component
+	pub red: FbBitfield,
+	/// Green component
+	pub green: FbBitfield,
+	/// Blue component
+	pub blue: FbBitfield,
+	/// Transparency component
+	pub transp: FbBitfield,
+	/// If non-zero, non-standard pixel format
+	pub nonstd: u32,
+	/// Activation flags
+	pub activate: u32,
+	/// Height of the picture, in millimeters
+	pub height: u32,
+	/// Width of the picture, in millimeters
+	pub width: u32,
+	/// Acceleration flags (obsolete)
+	pub accel_flags: u32,
+	/// Pixel clock, in picoseconds
+	pub pixclock: u32,
+	/// Time from sync to picture
+	pub left_margin: u32,
+	/// Time from picture to sync
+	pub right_margin: u32,
+	/// Time from sync to picture
+	pub upper_margin: u32,
+	/// Time from picture to sync
+	pub lower_margin: u32,
+	/// Length of horizontal sync
+	pub hsync_len: u32,
+	/// Length of vertical sync
+	pub vsync_len: u32,
+	/// Sync flags
+	pub sync: u32,
+	/// Video mode flags
+	pub vmode: u32,
+	/// Angle of rotation counter-clockwise
+	pub rotate: u32,
+	/// Colorspace for FOURCC-based modes
+	pub colorspace: u32,
+	/// Reserved for future use
+	pub reserved: [u32; 4],
+}
+
+/// Fixed screen information, which cannot be changed by userspace.
+#[repr(C)]
+#[derive(Clone, Debug, Default)]
+pub struct FbFixScreenInfo {
+	/// Identification string
+	pub id: [u8; 16],
+	/// Physical address of the framebuffer
+	pub smem_start: c_ulong,
+	/// Length of the framebuffer, in bytes
+	pub smem_len: u32,
+	/// Framebuffer type
+	pub type_: u32,
+	/// Interleave for interleaved planes
+	pub type_aux: u32,
+	/// Visual type
+	pub visual: u32,
+	/// Horizontal panning step, zero if not supported
+	pub xpanstep: u16,
+	/// Vertical panning step, zero if not supported
+	pub ypanstep: u16,
+	/// Vertical wrapping step, zero if not supported
+	pub ywrapstep: u16,
+	/// Length of a line, in bytes
+	pub line_length: u32,
+	/// Physical address of memory-mapped I/O
+	pub mmio_start: c_ulong,
+	/// Length of memory-mapped I/O
+	pub mmio_len: u32,
+	/// Acceleration type
+	pub accel: u32,
+	/// Capabilities flags
+	pub capabilities: u16,
+	/// Reserved for future use
+	pub reserved: [u16; 2],
+}
+
 /// A framebuffer
 #[derive(Debug)]
 pub struct Framebuffer(FramebufferInfo);
@@ -83,6 +217,54 @@ impl Framebuffer {
 	pub fn len(&self) -> usize {
 		self.0.framebuffer_pitch as usize * self.0.framebuffer_height as usize
 	}
+
+	/// Returns the variable screen information of the framebuffer.
+	pub fn var_screen_info(&self) -> FbVarScreenInfo {
+		let rgb = &self.0.framebuffer_rgb;
+		let bitfield = |offset: u8, length: u8| FbBitfield {
+			offset: offset as _,
+			length: length as _,
+			msb_right: 0,
+		};
+		FbVarScreenInfo {
+			xres: self.0.framebuffer_width,
+			yres: self.0.framebuffer_height,
+			xres_virtual: self.0.framebuffer_width,
+			yres_virtual: self.0.framebuffer_height,
+			bits_per_pixel: self.0.framebuffer_bpp as _,
+			red: bitfield(
+				rgb.framebuffer_red_field_position,
+				rgb.framebuffer_red_mask_size,
+			),
+			green: bitfield(
+				rgb.framebuffer_green_field_position,
+				rgb.framebuffer_green_mask_size,
+			),
+			blue: bitfield(
+				rgb.framebuffer_blue_field_position,
+				rgb.framebuffer_blue_mask_size,
+			),
+			// Unknown physical size
+			height: u32::MAX,
+			width: u32::MAX,
+			..Default::default()
+		}
+	}
+
+	/// Returns the fixed screen information of the framebuffer.
+	pub fn fix_screen_info(&self) -> FbFixScreenInfo {
+		let mut id = [0; 16];
+		id[..4].copy_from_slice(b"VESA");
+		FbFixScreenInfo {
+			id,
+			smem_start: self.0.framebuffer_addr as _,
+			smem_len: self.len() as _,
+			type_: FB_TYPE_PACKED_PIXELS,
+			visual: FB_VISUAL_TRUECOLOR,
+			line_length: self.0.framebuffer_pitch,
+			..Default::default()
+		}
+	}
 }
 
 // TODO undo memory remap on fb drop? (determine if this is useful)
@@ -93,29 +275,72 @@ pub struct FramebufferDev(Arc<Framebuffer>);
 
 impl FileOps for FramebufferDev {
 	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
-		let off: usize = off.try_into().map_err(|_| errno!(EINVAL))?;
-		let fb_len = self.0.len();
-		let oob = off.checked_add(buf.len()).is_none_or(|l| l > fb_len);
-		if unlikely(oob) {
-			return Err(errno!(EINVAL));
+		// Transfers stop at the end of the framebuffer
+		let off = usize::try_from(off).unwrap_or(usize::MAX);
+		let len = min(buf.len(), self.0.len().saturating_sub(off));
+		if len == 0 {
+			return Ok(0);
 		}
 		unsafe {
 			let ptr = self.0.addr().as_ptr::<u8>().add(off);
-			buf.copy_to_user_raw(0, ptr, buf.len())
+			buf.copy_to_user_raw(0, ptr, len)
 		}
 	}
 
 	fn write(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
-		let off: usize = off.try_into().map_err(|_| errno!(EINVAL))?;
-		let fb_len = self.0.len();
-		let oob = off.checked_add(buf.len()).is_none_or(|l| l > fb_len);
-		if unlikely(oob) {
-			return Err(errno!(EINVAL));
+		// Transfers stop at the end of the framebuffer
+		let off = usize::try_from(off).unwrap_or(usize::MAX);
+		let len = min(buf.len(), self.0.len().saturating_sub(off));
+		if len == 0 {
+			return Ok(0);
 		}
 		unsafe {
 			let ptr = self.0.addr().as_ptr::<u8>().add(off);
-			buf.copy_from_user_raw(0, ptr, buf.len())
+			buf.copy_from_user_raw(0, ptr, len)
+		}
+	}
+
+	fn ioctl(&self, _file: &File, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
+		match request.get_old_format() {
+			FBIOGET_VSCREENINFO => {
+				let info = UserPtr::<FbVarScreenInfo>::from_ptr(argp as usize);
+				info.copy_to_user(&self.0.var_screen_info())?;
+				Ok(0)
+			}
+			FBIOPUT_VSCREENINFO => {
+				let info_ptr = UserPtr::<FbVarScreenInfo>::from_ptr(argp as usize);
+				let info = info_ptr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+				// The mode is set by the bootloader and cannot be changed
+				let cur = self.0.var_screen_info();
+				let same_mode = info.xres == cur.xres
+					&& info.yres == cur.yres
+					&& info.xres_virtual == cur.xres_virtual
+					&& info.yres_virtual == cur.yres_virtual
+					&& info.xoffset == 0
+					&& info.yoffset == 0
+					&& info.bits_per_pixel == cur.bits_per_pixel;
+				if unlikely(!same_mode) {
+					return Err(errno!(EINVAL));
+				}
+				info_ptr.copy_to_user(&cur)?;
+				Ok(0)
+			}
+			FBIOGET_FSCREENINFO => {
+				let info = UserPtr::<FbFixScreenInfo>::from_ptr(argp as usize);
+				info.copy_to_user(&self.0.fix_screen_info())?;
+				Ok(0)
+			}
+			_ => Err(errno!(EINVAL)),
+		}
+	}
+
+	fn map_page(&self, _file: &File, off: u64) -> EResult<Option<PhysAddr>> {
+		let off: usize = off.try_into().map_err(|_| errno!(EINVAL))?;
+		if unlikely(off >= self.0.len().div_ceil(PAGE_SIZE)) {
+			return Err(errno!(EINVAL));
 		}
+		let addr = PhysAddr(self.0.info().framebuffer_addr as _) + off * PAGE_SIZE;
+		Ok(Some(addr))
 	}
 }
 
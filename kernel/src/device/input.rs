@@ -0,0 +1,582 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The input core collects events from input drivers (keyboards, mouses, ...) and exposes each
+//! input device to userspace through an evdev character device (`/dev/input/eventN`).
+//!
+//! Events are reported by drivers with [`InputDevice::report`], then committed with
+//! [`InputDevice::sync`]. Each open file description on the device file has its own queue of
+//! events.
+
+use crate::{
+	device::{CHAR_DEVICES, CharDev, DeviceID, register_char},
+	file::{File, O_NONBLOCK, fs::FileOps},
+	memory::user::{UserPtr, UserSlice},
+	sync::{spin::IntSpin, wait_queue::WaitQueue},
+	syscall::{
+		FromSyscallArg, ioctl,
+		select::{POLLERR, POLLHUP, POLLIN, POLLRDNORM},
+	},
+	time::{
+		clock::{Clock, current_time_ns},
+		unit::{TimeUnit, Timeval},
+	},
+};
+use core::{
+	cmp::min,
+	ffi::{c_int, c_long, c_void},
+	hint::unlikely,
+	mem::size_of,
+	slice,
+};
+use utils::{
+	collections::{id_allocator::IDAllocator, path::PathBuf, vec::Vec},
+	errno,
+	errno::EResult,
+	format,
+	ptr::arc::Arc,
+	vec,
+};
+
+/// Event type: synchronization.
+pub const EV_SYN: u16 = 0x00;
+/// Event type: key or button state change.
+pub const EV_KEY: u16 = 0x01;
+/// Event type: relative axis change.
+pub const EV_REL: u16 = 0x02;
+/// Event type: miscellaneous.
+pub const EV_MSC: u16 = 0x04;
+/// The number of event types.
+pub const EV_CNT: usize = 0x20;
+
+/// Synchronization event: end of a packet of events.
+pub const SYN_REPORT: u16 = 0;
+/// Synchronization event: events have been lost because the client's buffer overflowed.
+pub const SYN_DROPPED: u16 = 3;
+
+/// Relative axis: horizontal.
+pub const REL_X: u16 = 0x00;
+/// Relative axis: vertical.
+pub const REL_Y: u16 = 0x01;
+/// Relative axis: vertical wheel.
+pub const REL_WHEEL: u16 = 0x08;
+/// The number of relative axes.
+pub const REL_CNT: usize = 0x10;
+
+/// Mouse button: left.
+pub const BTN_LEFT: u16 = 0x110;
+/// Mouse button: right.
+pub const BTN_RIGHT: u16 = 0x111;
+/// Mouse button: middle.
+pub const BTN_MIDDLE: u16 = 0x112;
+/// The number of key and button codes.
+pub const KEY_CNT: usize = 0x300;
+
+/// Bus type: i8042 (PS/2) controller.
+pub const BUS_I8042: u16 = 0x11;
+/// Bus type: USB.
+pub const BUS_USB: u16 = 0x03;
+
+/// The major number of input devices.
+const INPUT_MAJOR: u32 = 13;
+/// The first minor number of evdev devices.
+const EVDEV_MINOR_BASE: u32 = 64;
+/// The maximum number of evdev devices.
+const EVDEV_MINORS: usize = 32;
+/// The number of events each client can buffer.
+const EVDEV_BUFFER_SIZE: usize = 64;
+
+/// The version of the evdev protocol.
+const EV_VERSION: c_int = 0x010001;
+
+/// ioctl major number for evdev requests.
+const EVDEV_IOCTL_MAJOR: u8 = b'E';
+/// ioctl request number: get the driver version.
+const EVIOCGVERSION: u8 = 0x01;
+/// ioctl request number: get the device's ID.
+const EVIOCGID: u8 = 0x02;
+/// ioctl request number: get the device's name.
+const EVIOCGNAME: u8 = 0x06;
+/// ioctl request number: get the device's physical location.
+const EVIOCGPHYS: u8 = 0x07;
+/// ioctl request number: get the device's unique identifier.
+const EVIOCGUNIQ: u8 = 0x08;
+/// ioctl request number: get the device's properties.
+const EVIOCGPROP: u8 = 0x09;
+/// ioctl request number: get the global key state.
+const EVIOCGKEY: u8 = 0x18;
+/// ioctl request number: get the LEDs state.
+const EVIOCGLED: u8 = 0x19;
+/// ioctl request number: get the switches state.
+const EVIOCGSW: u8 = 0x1b;
+/// ioctl request number: get the event bits. The event type is added to this value.
+const EVIOCGBIT: u8 = 0x20;
+/// ioctl request number: grab or release the device.
+const EVIOCGRAB: u8 = 0x90;
+
+/// Allocator for evdev device numbers.
+static EVDEV_ALLOCATOR: IntSpin<IDAllocator<[u8; EVDEV_MINORS / 8]>> =
+	IntSpin::new(IDAllocator::new_inplace());
+
+/// An input event, as read from an evdev device file.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InputEvent {
+	/// Timestamp of the event, seconds
+	pub sec: c_long,
+	/// Timestamp of the event, microseconds
+	pub usec: c_long,
+	/// The event's type
+	pub type_: u16,
+	/// The event's code
+	pub code: u16,
+	/// The event's value
+	pub value: i32,
+}
+
+/// Identifier of an input device.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InputId {
+	/// The bus type
+	pub bustype: u16,
+	/// The vendor ID
+	pub vendor: u16,
+	/// The product ID
+	pub product: u16,
+	/// The version
+	pub version: u16,
+}
+
+/// Sets the bit `n` in `bits`.
+#[inline]
+fn set_bit(bits: &mut [u8], n: usize) {
+	bits[n / 8] |= 1 << (n % 8);
+}
+
+/// Tells whether the bit `n` is set in `bits`.
+#[inline]
+fn test_bit(bits: &[u8], n: usize) -> bool {
+	bits.get(n / 8).is_some_and(|b| b & (1 << (n % 8)) != 0)
+}
+
+/// The queue of events of an open file description on an evdev device.
+#[derive(Debug)]
+struct Client {
+	/// The identifier of the open file description
+	file: usize,
+	/// Circular buffer of events
+	events: Vec<InputEvent>,
+	/// The index of the oldest event in the buffer
+	head: usize,
+	/// The number of events in the buffer
+	len: usize,
+}
+
+impl Client {
+	/// Pushes an event on the queue.
+	///
+	/// If the buffer is full, pending events are discarded and replaced by a [`SYN_DROPPED`]
+	/// event so that the reader knows it must resynchronize its state.
+	fn push(&mut self, ev: InputEvent) {
+		if self.len >= self.events.len() {
+			self.head = 0;
+			self.events[0] = InputEvent {
+				type_: EV_SYN,
+				code: SYN_DROPPED,
+				value: 0,
+				..ev
+			};
+			self.len = 1;
+		}
+		let i = (self.head + self.len) % self.events.len();
+		self.events[i] = ev;
+		self.len += 1;
+	}
+
+	/// Pops events from the queue into `buf`. The function returns the number of events.
+	fn pop(&mut self, buf: &mut [InputEvent]) -> usize {
+		let count = min(buf.len(), self.len);
+		for ev in &mut buf[..count] {
+			*ev = self.events[self.head];
+			self.head = (self.head + 1) % self.events.len();
+		}
+		self.len -= count;
+		count
+	}
+}
+
+/// The mutable state of an input device.
+#[derive(Debug)]
+struct InputDeviceState {
+	/// The state of each key (pressed or not)
+	key_state: [u8; KEY_CNT / 8],
+	/// The list of clients
+	clients: Vec<Client>,
+	/// The identifier of the open file description which grabbed the device, if any
+	grab: Option<usize>,
+	/// Tells whether the device has been unregistered
+	removed: bool,
+}
+
+/// An input device, fed by a driver.
+#[derive(Debug)]
+pub struct InputDevice {
+	/// The device's name
+	name: &'static [u8],
+	/// The device's identifier
+	id: InputId,
+	/// The set of supported event types
+	ev_bits: [u8; EV_CNT / 8],
+	/// The set of supported keys
+	key_bits: [u8; KEY_CNT / 8],
+	/// The set of supported relative axes
+	rel_bits: [u8; REL_CNT / 8],
+	/// The device's evdev number, set at registration
+	num: u32,
+
+	/// The mutable state
+	state: IntSpin<InputDeviceState>,
+	/// The queue of processes waiting for events
+	queue: WaitQueue,
+}
+
+impl InputDevice {
+	/// Creates a new device with no capability.
+	///
+	/// Arguments:
+	/// - `name` is the name of the device
+	/// - `id` is the identifier of the device
+	pub fn new(name: &'static [u8], id: InputId) -> Self {
+		let mut ev_bits = [0; EV_CNT / 8];
+		set_bit(&mut ev_bits, EV_SYN as _);
+		Self {
+			name,
+			id,
+			ev_bits,
+			key_bits: [0; KEY_CNT / 8],
+			rel_bits: [0; REL_CNT / 8],
+			num: 0,
+
+			state: IntSpin::new(InputDeviceState {
+				key_state: [0; KEY_CNT / 8],
+				clients: Vec::new(),
+				grab: None,
+				removed: false,
+			}),
+			queue: WaitQueue::new(),
+		}
+	}
+
+	/// Declares that the device can emit events with type `type_` and code `code`.
+	///
+	/// This function must be called before registering the device.
+	pub fn set_capability(&mut self, type_: u16, code: u16) {
+		set_bit(&mut self.ev_bits, type_ as _);
+		match type_ {
+			EV_KEY => set_bit(&mut self.key_bits, code as _),
+			EV_REL => set_bit(&mut self.rel_bits, code as _),
+			_ => {}
+		}
+	}
+
+	/// Returns the bitmap of supported codes for the given event type, if known.
+	fn capabilities(&self, type_: u16) -> &[u8] {
+		match type_ {
+			0 => &self.ev_bits,
+			EV_KEY => &self.key_bits,
+			EV_REL => &self.rel_bits,
+			_ => &[],
+		}
+	}
+
+	/// Tells whether the device is grabbed by an open file description.
+	///
+	/// When grabbed, events must not be delivered to other consumers (such as the TTY).
+	pub fn is_grabbed(&self) -> bool {
+		self.state.lock().grab.is_some()
+	}
+
+	/// Tells whether the key or button `code` is currently pressed.
+	pub fn is_pressed(&self, code: u16) -> bool {
+		test_bit(&self.state.lock().key_state, code as _)
+	}
+
+	/// Reports an event.
+	///
+	/// Events are not made visible to readers until [`Self::sync`] is called.
+	///
+	/// Events that the device did not declare with [`Self::set_capability`] are ignored. For
+	/// [`EV_KEY`] events, events that do not change the state of the key are ignored, except
+	/// repeats (value `2`) of a pressed key.
+	///
+	/// This function does not allocate memory and can be called from an interrupt handler.
+	pub fn report(&self, type_: u16, code: u16, value: i32) {
+		if unlikely(!test_bit(self.capabilities(type_), code as _)) {
+			return;
+		}
+		let mut state = self.state.lock();
+		if type_ == EV_KEY {
+			let pressed = test_bit(&state.key_state, code as _);
+			let i = code as usize;
+			match value {
+				0 if pressed => state.key_state[i / 8] &= !(1 << (i % 8)),
+				1 if !pressed => set_bit(&mut state.key_state, i),
+				2 if pressed => {}
+				_ => return,
+			}
+		}
+		Self::push(&mut state, type_, code, value);
+	}
+
+	/// Ends the current packet of events and wakes up readers.
+	pub fn sync(&self) {
+		Self::push(&mut self.state.lock(), EV_SYN, SYN_REPORT, 0);
+		self.queue.wake_all();
+	}
+
+	/// Pushes an event to the clients.
+	fn push(state: &mut InputDeviceState, type_: u16, code: u16, value: i32) {
+		let time = Timeval::from_nano(current_time_ns(Clock::Realtime));
+		let ev = InputEvent {
+			sec: time.tv_sec as _,
+			usec: time.tv_usec as _,
+			type_,
+			code,
+			value,
+		};
+		let grab = state.grab;
+		state
+			.clients
+			.iter_mut()
+			.filter(|c| grab.is_none_or(|g| g == c.file))
+			.for_each(|c| c.push(ev));
+	}
+
+	/// Returns the device ID of the device's file.
+	fn device_id(&self) -> DeviceID {
+		DeviceID {
+			major: INPUT_MAJOR,
+			minor: EVDEV_MINOR_BASE + self.num,
+		}
+	}
+}
+
+/// Registers the input device `dev` and creates its device file.
+pub fn register(mut dev: InputDevice) -> EResult<Arc<InputDevice>> {
+	let num = EVDEV_ALLOCATOR.lock().alloc(None)?;
+	dev.num = num;
+	let res = (|| {
+		let path = PathBuf::new_unchecked(format!("/dev/input/event{}", dev.num)?);
+		let id = dev.device_id();
+		let dev = Arc::new(dev)?;
+		register_char(CharDev::new(id, path, 0o660, EvdevHandle(dev.clone()))?)?;
+		Ok(dev)
+	})();
+	if res.is_err() {
+		EVDEV_ALLOCATOR.lock().free(num);
+	}
+	res
+}
+
+/// Unregisters the input device `dev` and removes its device file.
+///
+/// Files that are still open on the device can only be closed.
+pub fn unregister(dev: &InputDevice) {
+	CHAR_DEVICES.lock().remove(&dev.device_id());
+	EVDEV_ALLOCATOR.lock().free(dev.num);
+	dev.state.lock().removed = true;
+	dev.queue.wake_all();
+}
+
+/// Handle of an evdev device file.
+#[derive(Debug)]
+struct EvdevHandle(Arc<InputDevice>);
+
+impl EvdevHandle {
+	/// Returns the client ID associated with `file`.
+	#[inline]
+	fn client_id(file: &File) -> usize {
+		file.id()
+	}
+
+	/// Executes `f` on the client associated with `file`.
+	///
+	/// If the client could not be created when the file was opened, the function tries to create
+	/// it again.
+	fn with_client<F: FnOnce(&mut Client) -> T, T>(&self, file: &File, f: F) -> EResult<T> {
+		let id = Self::client_id(file);
+		{
+			let mut state = self.0.state.lock();
+			if let Some(c) = state.clients.iter_mut().find(|c| c.file == id) {
+				return Ok(f(c));
+			}
+		}
+		// Allocate outside of the critical section, since it disables interruptions
+		let client = Client {
+			file: id,
+			events: vec![InputEvent::default(); EVDEV_BUFFER_SIZE]?,
+			head: 0,
+			len: 0,
+		};
+		let mut state = self.0.state.lock();
+		state.clients.push(client)?;
+		let i = state.clients.len() - 1;
+		Ok(f(&mut state.clients[i]))
+	}
+}
+
+/// Copies the bitmap `bits` to userspace at `argp`, truncated to `len` bytes.
+///
+/// The function returns the number of bytes copied.
+fn copy_bits(argp: *const c_void, len: usize, bits: &[u8]) -> EResult<u32> {
+	let len = min(len, bits.len());
+	let buf = UserSlice::from_user(argp as *mut u8, len)?;
+	buf.copy_to_user(0, &bits[..len])?;
+	Ok(len as _)
+}
+
+impl FileOps for EvdevHandle {
+	fn acquire(&self, file: &File) {
+		// On failure, creation is retried when the client is used
+		let _ = self.with_client(file, |_| ());
+	}
+
+	fn release(&self, file: &File) {
+		let id = Self::client_id(file);
+		let mut state = self.0.state.lock();
+		state.clients.retain(|c| c.file != id);
+		if state.grab == Some(id) {
+			state.grab = None;
+		}
+	}
+
+	fn poll(&self, file: &File, mask: u32) -> EResult<u32> {
+		if self.0.state.lock().removed {
+			return Ok((POLLHUP | POLLERR) & mask);
+		}
+		let available = self.with_client(file, |c| c.len > 0)?;
+		Ok(if available { POLLIN | POLLRDNORM } else { 0 } & mask)
+	}
+
+	fn ioctl(&self, file: &File, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
+		if request.major != EVDEV_IOCTL_MAJOR {
+			return Err(errno!(ENOTTY));
+		}
+		if unlikely(self.0.state.lock().removed) {
+			return Err(errno!(ENODEV));
+		}
+		let dev = &self.0;
+		match request.minor {
+			EVIOCGVERSION => {
+				let ptr = UserPtr::<c_int>::from_ptr(argp as usize);
+				ptr.copy_to_user(&EV_VERSION)?;
+				Ok(0)
+			}
+			EVIOCGID => {
+				let ptr = UserPtr::<InputId>::from_ptr(argp as usize);
+				ptr.copy_to_user(&dev.id)?;
+				Ok(0)
+			}
+			EVIOCGNAME => {
+				if request.size == 0 {
+					return Ok(0);
+				}
+				// Copy the name with the terminating nul byte, truncated to the buffer's size
+				let len = min(dev.name.len(), request.size - 1);
+				let buf = UserSlice::from_user(argp as *mut u8, len + 1)?;
+				buf.copy_to_user(0, &dev.name[..len])?;
+				buf.copy_to_user(len, b"\0")?;
+				Ok((len + 1) as _)
+			}
+			EVIOCGPHYS | EVIOCGUNIQ => Err(errno!(ENOENT)),
+			EVIOCGPROP | EVIOCGLED | EVIOCGSW => {
+				// No property, LED or switch is reported
+				let buf = UserSlice::from_user(argp as *mut u8, request.size)?;
+				buf.zero(0, request.size)?;
+				Ok(request.size as _)
+			}
+			EVIOCGKEY => {
+				let state = dev.state.lock().key_state;
+				copy_bits(argp, request.size, &state)
+			}
+			EVIOCGRAB => {
+				let id = Self::client_id(file);
+				let mut state = dev.state.lock();
+				if !argp.is_null() {
+					if state.grab.is_some() {
+						return Err(errno!(EBUSY));
+					}
+					state.grab = Some(id);
+				} else if state.grab == Some(id) {
+					state.grab = None;
+				} else {
+					return Err(errno!(EINVAL));
+				}
+				Ok(0)
+			}
+			n if (EVIOCGBIT..EVIOCGBIT + EV_CNT as u8).contains(&n) => {
+				let bits = dev.capabilities((n - EVIOCGBIT) as _);
+				copy_bits(argp, request.size, bits)
+			}
+			_ => Err(errno!(EINVAL)),
+		}
+	}
+
+	fn read(&self, file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		const EV_SIZE: usize = size_of::<InputEvent>();
+		if unlikely(buf.len() < EV_SIZE) {
+			return Err(errno!(EINVAL));
+		}
+		let max = buf.len() / EV_SIZE;
+		let mut off = 0;
+		// Block only until the first events are available
+		let mut events = [InputEvent::default(); 16];
+		let mut count = self.0.queue.wait_until(|| {
+			if self.0.state.lock().removed {
+				return Some(Err(errno!(ENODEV)));
+			}
+			let count = match self.with_client(file, |c| c.pop(&mut events[..min(16, max)])) {
+				Ok(c) => c,
+				Err(e) => return Some(Err(e)),
+			};
+			if count > 0 {
+				Some(Ok(count))
+			} else if file.get_flags() & O_NONBLOCK != 0 {
+				Some(Err(errno!(EAGAIN)))
+			} else {
+				None
+			}
+		})??;
+		loop {
+			let bytes =
+				unsafe { slice::from_raw_parts(events.as_ptr() as *const u8, count * EV_SIZE) };
+			buf.copy_to_user(off, bytes)?;
+			off += bytes.len();
+			let remaining = max - off / EV_SIZE;
+			if remaining == 0 {
+				break;
+			}
+			count = self.with_client(file, |c| c.pop(&mut events[..min(16, remaining)]))?;
+			if count == 0 {
+				break;
+			}
+		}
+		Ok(off)
+	}
+}
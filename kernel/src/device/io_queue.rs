@@ -0,0 +1,277 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Block device I/O request queue.
+//!
+//! Accesses to a block device are dispatched one at a time, in an order depending on the I/O
+//! priority of the issuing processes. Three scheduling classes are supported:
+//! - Real-time ([`IOPRIO_CLASS_RT`]): always served first
+//! - Best-effort ([`IOPRIO_CLASS_BE`]): the default class, served when no real-time request is
+//!   pending
+//! - Idle ([`IOPRIO_CLASS_IDLE`]): only served when the device has nothing else to do
+//!
+//! Real-time and best-effort classes have eight levels each, `0` being the highest priority.
+//!
+//! Each request is given a deadline depending on its class and level. Within a class, requests
+//! are served by earliest deadline. Once a request's deadline has expired, it is served before
+//! any non-expired request regardless of its class, which prevents starvation.
+
+use crate::{
+	process,
+	process::{Process, State, scheduler::schedule},
+	sync::spin::IntSpin,
+	time::{
+		clock::{Clock, current_time_ns},
+		unit::Timestamp,
+	},
+};
+use core::{fmt, fmt::Formatter, ptr, sync::atomic::Ordering::Acquire};
+use utils::{collections::vec::Vec, errno::AllocResult, ptr::arc::Arc};
+
+/// Shift of the class in an I/O priority value.
+pub const IOPRIO_CLASS_SHIFT: u16 = 13;
+/// Mask of the data (level) in an I/O priority value.
+pub const IOPRIO_PRIO_MASK: u16 = (1 << IOPRIO_CLASS_SHIFT) - 1;
+
+/// I/O priority class: No class set, derived from the process's niceness.
+pub const IOPRIO_CLASS_NONE: u16 = 0;
+/// I/O priority class: Real-time.
+pub const IOPRIO_CLASS_RT: u16 = 1;
+/// I/O priority class: Best-effort.
+pub const IOPRIO_CLASS_BE: u16 = 2;
+/// I/O priority class: Idle.
+pub const IOPRIO_CLASS_IDLE: u16 = 3;
+
+/// The number of levels in the real-time and best-effort classes.
+pub const IOPRIO_NR_LEVELS: u16 = 8;
+
+/// Base deadline for real-time requests, in nanoseconds.
+const RT_EXPIRE: Timestamp = 10_000_000;
+/// Base deadline for best-effort requests, in nanoseconds.
+const BE_EXPIRE: Timestamp = 100_000_000;
+/// Deadline for idle requests, in nanoseconds.
+const IDLE_EXPIRE: Timestamp = 5_000_000_000;
+
+/// Returns the class of the I/O priority value `prio`.
+#[inline]
+pub fn ioprio_class(prio: u16) -> u16 {
+	prio >> IOPRIO_CLASS_SHIFT
+}
+
+/// Returns the data (level) of the I/O priority value `prio`.
+#[inline]
+pub fn ioprio_data(prio: u16) -> u16 {
+	prio & IOPRIO_PRIO_MASK
+}
+
+/// Returns an I/O priority value from the given `class` and `data`.
+#[inline]
+pub fn ioprio_value(class: u16, data: u16) -> u16 {
+	(class << IOPRIO_CLASS_SHIFT) | (data & IOPRIO_PRIO_MASK)
+}
+
+/// Returns the effective I/O priority value of a process with the given I/O priority `prio` and
+/// niceness `nice`.
+///
+/// If no class is set, the priority is best-effort with a level derived from the niceness.
+pub fn effective_ioprio(prio: u16, nice: i8) -> u16 {
+	if ioprio_class(prio) == IOPRIO_CLASS_NONE {
+		let level = (nice.clamp(-20, 19) + 20) / 5;
+		ioprio_value(IOPRIO_CLASS_BE, level as _)
+	} else {
+		prio
+	}
+}
+
+/// Returns the deadline of a request with the effective I/O priority `prio`, submitted at `now`.
+fn deadline(prio: u16, now: Timestamp) -> Timestamp {
+	let level = ioprio_data(prio).min(IOPRIO_NR_LEVELS - 1) as Timestamp;
+	let expire = match ioprio_class(prio) {
+		IOPRIO_CLASS_RT => RT_EXPIRE * (level + 1),
+		IOPRIO_CLASS_IDLE => IDLE_EXPIRE,
+		_ => BE_EXPIRE * (level + 1),
+	};
+	now.saturating_add(expire)
+}
+
+/// Returns the key used to sort pending requests. The request with the lowest key is dispatched
+/// first.
+///
+/// Arguments:
+/// - `prio` is the effective I/O priority of the request
+/// - `deadline` is the request's deadline
+/// - `now` is the current timestamp
+fn dispatch_key(prio: u16, deadline: Timestamp, now: Timestamp) -> (u16, Timestamp) {
+	// Expired requests take precedence over everything else
+	let rank = if deadline <= now {
+		0
+	} else {
+		ioprio_class(prio)
+	};
+	(rank, deadline)
+}
+
+/// A request waiting for the device.
+struct Request {
+	/// The process issuing the request
+	proc: Arc<Process>,
+	/// The request's effective I/O priority
+	prio: u16,
+	/// The request's deadline
+	deadline: Timestamp,
+}
+
+struct Inner {
+	/// Tells whether a request is being processed by the device
+	busy: bool,
+	/// Requests waiting for the device, in submission order
+	pending: Vec<Request>,
+}
+
+/// Queue of I/O requests on a block device.
+pub struct IoQueue(IntSpin<Inner>);
+
+impl Default for IoQueue {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl IoQueue {
+	/// Creates a new empty queue.
+	pub const fn new() -> Self {
+		Self(IntSpin::new(Inner {
+			busy: false,
+			pending: Vec::new(),
+		}))
+	}
+
+	/// Waits until the current process is allowed to access the device.
+	///
+	/// The function returns a guard. When dropped, the device is handed to the next request.
+	///
+	/// Waiting cannot be interrupted by signals.
+	pub fn acquire(&self) -> AllocResult<IoQueueGuard<'_>> {
+		let proc = {
+			let mut inner = self.0.lock();
+			if !inner.busy {
+				inner.busy = true;
+				return Ok(IoQueueGuard {
+					queue: self,
+				});
+			}
+			let proc = Process::current();
+			let prio = effective_ioprio(proc.ioprio.load(Acquire), proc.nice.load(Acquire));
+			inner.pending.push(Request {
+				proc: proc.clone(),
+				prio,
+				deadline: deadline(prio, current_time_ns(Clock::Monotonic)),
+			})?;
+			// Put to sleep before releasing the spinlock to make sure the process releasing the
+			// device does not try to wake us up before we sleep
+			process::set_state(State::Sleeping);
+			proc
+		};
+		loop {
+			schedule();
+			let inner = self.0.lock();
+			// If the request has been dispatched, the device has been handed to us
+			if !inner.pending.iter().any(|r| ptr::eq(&*r.proc, &*proc)) {
+				break;
+			}
+			// Spurious wakeup
+			process::set_state(State::Sleeping);
+		}
+		Ok(IoQueueGuard {
+			queue: self,
+		})
+	}
+
+	/// Hands the device to the next pending request, if any.
+	fn release(&self) {
+		let next = {
+			let mut inner = self.0.lock();
+			let now = current_time_ns(Clock::Monotonic);
+			let next = inner
+				.pending
+				.iter()
+				.enumerate()
+				// `min_by_key` returns the first minimum, preserving submission order on equality
+				.min_by_key(|(_, r)| dispatch_key(r.prio, r.deadline, now))
+				.map(|(i, _)| i);
+			match next {
+				// Keep the device busy, ownership is transferred to the next request
+				Some(i) => Some(inner.pending.remove(i)),
+				None => {
+					inner.busy = false;
+					None
+				}
+			}
+		};
+		if let Some(req) = next {
+			Process::wake_from(&req.proc, State::Sleeping as u8);
+		}
+	}
+}
+
+impl fmt::Debug for IoQueue {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.write_str("IoQueue")
+	}
+}
+
+/// Gives access to the device until dropped.
+pub struct IoQueueGuard<'q> {
+	queue: &'q IoQueue,
+}
+
+impl Drop for IoQueueGuard<'_> {
+	fn drop(&mut self) {
+		self.queue.release();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn ioprio_dispatch_order() {
+		let now = 1_000_000_000;
+		let rt = ioprio_value(IOPRIO_CLASS_RT, 7);
+		let be = ioprio_value(IOPRIO_CLASS_BE, 0);
+		let idle = ioprio_value(IOPRIO_CLASS_IDLE, 0);
+		let rt_key = dispatch_key(rt, deadline(rt, now), now);
+		let be_key = dispatch_key(be, deadline(be, now), now);
+		let idle_key = dispatch_key(idle, deadline(idle, now), now);
+		assert!(rt_key < be_key);
+		assert!(be_key < idle_key);
+		// Expired idle request
+		let idle_key = dispatch_key(idle, deadline(idle, 0), now);
+		assert!(idle_key < rt_key);
+	}
+
+	#[test_case]
+	fn ioprio_from_nice() {
+		assert_eq!(effective_ioprio(0, -20), ioprio_value(IOPRIO_CLASS_BE, 0));
+		assert_eq!(effective_ioprio(0, 0), ioprio_value(IOPRIO_CLASS_BE, 4));
+		assert_eq!(effective_ioprio(0, 19), ioprio_value(IOPRIO_CLASS_BE, 7));
+		let idle = ioprio_value(IOPRIO_CLASS_IDLE, 0);
+		assert_eq!(effective_ioprio(idle, -20), idle);
+	}
+}
@@ -0,0 +1,213 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Block device I/O statistics.
+//!
+//! Each block device keeps counters of the requests it served, which are exposed to userspace
+//! through `/proc/diskstats`.
+//!
+//! A request is accounted for from the moment it is submitted, which includes the time spent
+//! waiting in the device's [`IoQueue`](super::io_queue::IoQueue), until its completion. As such,
+//! the number of requests in flight is the depth of the queue.
+
+use crate::{
+	device::io_queue::IoQueueGuard,
+	sync::spin::IntSpin,
+	time::{
+		clock::{Clock, current_time_ns},
+		unit::Timestamp,
+	},
+};
+use core::{fmt, fmt::Formatter};
+use utils::{limits::PAGE_SIZE, ptr::arc::Arc};
+
+/// The size of a sector in statistics, in bytes. This is independent of the device's block size.
+pub const STAT_SECTOR_SIZE: u64 = 512;
+
+/// The direction of an I/O request.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IoDir {
+	/// Reading from the device
+	Read,
+	/// Writing to the device
+	Write,
+}
+
+/// A snapshot of the I/O statistics of a block device.
+///
+/// Durations are in nanoseconds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IoCounters {
+	/// The number of completed read requests
+	pub read_ios: u64,
+	/// The number of sectors read
+	pub read_sectors: u64,
+	/// The total time spent by read requests
+	pub read_ticks: Timestamp,
+	/// The number of completed write requests
+	pub write_ios: u64,
+	/// The number of sectors written
+	pub write_sectors: u64,
+	/// The total time spent by write requests
+	pub write_ticks: Timestamp,
+	/// The number of requests currently in flight
+	pub in_flight: u32,
+	/// The time during which at least one request was in flight
+	pub io_ticks: Timestamp,
+	/// The time spent by requests in flight, weighted by their number
+	pub time_in_queue: Timestamp,
+}
+
+#[derive(Default)]
+struct Inner {
+	/// The counters
+	counters: IoCounters,
+	/// The timestamp of the last update of time counters
+	stamp: Timestamp,
+}
+
+impl Inner {
+	/// Accounts the time elapsed since the last update.
+	fn update_time(&mut self, now: Timestamp) {
+		let delta = now.saturating_sub(self.stamp);
+		let in_flight = self.counters.in_flight as Timestamp;
+		if in_flight > 0 {
+			self.counters.io_ticks += delta;
+			self.counters.time_in_queue += delta * in_flight;
+		}
+		self.stamp = now;
+	}
+}
+
+/// The I/O statistics of a block device.
+#[derive(Default)]
+pub struct IoStats(IntSpin<Inner>);
+
+impl fmt::Debug for IoStats {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.write_str("IoStats")
+	}
+}
+
+impl IoStats {
+	/// Returns a snapshot of the statistics.
+	pub fn get(&self) -> IoCounters {
+		let mut inner = self.0.lock();
+		inner.update_time(current_time_ns(Clock::Monotonic));
+		inner.counters
+	}
+
+	/// Accounts for the submission of a request at `now`.
+	fn start(&self, now: Timestamp) {
+		let mut inner = self.0.lock();
+		inner.update_time(now);
+		inner.counters.in_flight += 1;
+	}
+
+	/// Accounts for the end of a request submitted at `start`, at `now`.
+	///
+	/// If `completed` is `false`, the request was aborted before reaching the device and is only
+	/// removed from the requests in flight.
+	fn end(&self, dir: IoDir, start: Timestamp, now: Timestamp, completed: bool) {
+		let mut inner = self.0.lock();
+		inner.update_time(now);
+		inner.counters.in_flight -= 1;
+		if !completed {
+			return;
+		}
+		let sectors = PAGE_SIZE as u64 / STAT_SECTOR_SIZE;
+		let ticks = now.saturating_sub(start);
+		let counters = &mut inner.counters;
+		match dir {
+			IoDir::Read => {
+				counters.read_ios += 1;
+				counters.read_sectors += sectors;
+				counters.read_ticks += ticks;
+			}
+			IoDir::Write => {
+				counters.write_ios += 1;
+				counters.write_sectors += sectors;
+				counters.write_ticks += ticks;
+			}
+		}
+	}
+}
+
+/// An I/O request on a page of a block device, accounted for until dropped.
+///
+/// The request is returned by [`BlkDev::start_io`](super::BlkDev::start_io).
+pub struct IoRequest<'d> {
+	/// The statistics of the device
+	pub(super) dev: &'d IoStats,
+	/// The statistics of the partition containing the page, if any
+	pub(super) part: Option<Arc<IoStats>>,
+	/// The direction of the request
+	pub(super) dir: IoDir,
+	/// The timestamp at which the request has been submitted
+	pub(super) start: Timestamp,
+	/// Exclusive access to the device. If `None`, the request has not reached the device
+	pub(super) queue: Option<IoQueueGuard<'d>>,
+}
+
+impl<'d> IoRequest<'d> {
+	/// Starts accounting for a request.
+	pub(super) fn new(dev: &'d IoStats, part: Option<Arc<IoStats>>, dir: IoDir) -> Self {
+		let start = current_time_ns(Clock::Monotonic);
+		dev.start(start);
+		if let Some(part) = &part {
+			part.start(start);
+		}
+		Self {
+			dev,
+			part,
+			dir,
+			start,
+			queue: None,
+		}
+	}
+}
+
+impl Drop for IoRequest<'_> {
+	fn drop(&mut self) {
+		let now = current_time_ns(Clock::Monotonic);
+		let completed = self.queue.is_some();
+		self.dev.end(self.dir, self.start, now, completed);
+		if let Some(part) = &self.part {
+			part.end(self.dir, self.start, now, completed);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn io_stats_time() {
+		let mut inner = Inner::default();
+		inner.update_time(10);
+		assert_eq!(inner.counters.io_ticks, 0);
+		inner.counters.in_flight = 2;
+		inner.update_time(15);
+		assert_eq!(inner.counters.io_ticks, 5);
+		assert_eq!(inner.counters.time_in_queue, 10);
+		inner.counters.in_flight = 0;
+		inner.update_time(100);
+		assert_eq!(inner.counters.io_ticks, 5);
+	}
+}
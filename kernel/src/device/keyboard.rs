@@ -20,7 +20,7 @@
 
 use crate::{
 	device::manager::{DeviceManager, PhysicalDevice},
-	tty::TTY,
+	tty,
 };
 use utils::errno::EResult;
 
@@ -158,6 +158,151 @@ pub enum KeyboardKey {
 }
 
 impl KeyboardKey {
+	/// Returns the key's code, as reported by input devices.
+	pub fn code(&self) -> u16 {
+		match self {
+			Self::KeyEsc => 1,
+			Self::Key1 => 2,
+			Self::Key2 => 3,
+			Self::Key3 => 4,
+			Self::Key4 => 5,
+			Self::Key5 => 6,
+			Self::Key6 => 7,
+			Self::Key7 => 8,
+			Self::Key8 => 9,
+			Self::Key9 => 10,
+			Self::Key0 => 11,
+			Self::KeyMinus => 12,
+			Self::KeyEqual => 13,
+			Self::KeyBackspace => 14,
+			Self::KeyTab => 15,
+			Self::KeyQ => 16,
+			Self::KeyW => 17,
+			Self::KeyE => 18,
+			Self::KeyR => 19,
+			Self::KeyT => 20,
+			Self::KeyY => 21,
+			Self::KeyU => 22,
+			Self::KeyI => 23,
+			Self::KeyO => 24,
+			Self::KeyP => 25,
+			Self::KeyOpenBrace => 26,
+			Self::KeyCloseBrace => 27,
+			Self::KeyEnter => 28,
+			Self::KeyLeftControl => 29,
+			Self::KeyA => 30,
+			Self::KeyS => 31,
+			Self::KeyD => 32,
+			Self::KeyF => 33,
+			Self::KeyG => 34,
+			Self::KeyH => 35,
+			Self::KeyJ => 36,
+			Self::KeyK => 37,
+			Self::KeyL => 38,
+			Self::KeySemiColon => 39,
+			Self::KeySingleQuote => 40,
+			Self::KeyBackTick => 41,
+			Self::KeyLeftShift => 42,
+			Self::KeyBackslash => 43,
+			Self::KeyZ => 44,
+			Self::KeyX => 45,
+			Self::KeyC => 46,
+			Self::KeyV => 47,
+			Self::KeyB => 48,
+			Self::KeyN => 49,
+			Self::KeyM => 50,
+			Self::KeyComma => 51,
+			Self::KeyDot => 52,
+			Self::KeySlash => 53,
+			Self::KeyRightShift => 54,
+			Self::KeyKeypadStar => 55,
+			Self::KeyLeftAlt => 56,
+			Self::KeySpace => 57,
+			Self::KeyCapsLock => 58,
+			Self::KeyF1 => 59,
+			Self::KeyF2 => 60,
+			Self::KeyF3 => 61,
+			Self::KeyF4 => 62,
+			Self::KeyF5 => 63,
+			Self::KeyF6 => 64,
+			Self::KeyF7 => 65,
+			Self::KeyF8 => 66,
+			Self::KeyF9 => 67,
+			Self::KeyF10 => 68,
+			Self::KeyNumberLock => 69,
+			Self::KeyScrollLock => 70,
+			Self::KeyKeypad7 => 71,
+			Self::KeyKeypad8 => 72,
+			Self::KeyKeypad9 => 73,
+			Self::KeyKeypadMinus => 74,
+			Self::KeyKeypad4 => 75,
+			Self::KeyKeypad5 => 76,
+			Self::KeyKeypad6 => 77,
+			Self::KeyKeypadPlus => 78,
+			Self::KeyKeypad1 => 79,
+			Self::KeyKeypad2 => 80,
+			Self::KeyKeypad3 => 81,
+			Self::KeyKeypad0 => 82,
+			Self::KeyKeypadDot => 83,
+			Self::KeyF11 => 87,
+			Self::KeyF12 => 88,
+			Self::KeyKeypadEnter => 96,
+			Self::KeyRightControl => 97,
+			Self::KeyKeypadSlash => 98,
+			Self::KeyPrintScreen => 99,
+			Self::KeyRightAlt => 100,
+			Self::KeyHome => 102,
+			Self::KeyCursorUp => 103,
+			Self::KeyPageUp => 104,
+			Self::KeyCursorLeft => 105,
+			Self::KeyCursorRight => 106,
+			Self::KeyEnd => 107,
+			Self::KeyCursorDown => 108,
+			Self::KeyPageDown => 109,
+			Self::KeyInsert => 110,
+			Self::KeyDelete => 111,
+			Self::KeyMute => 113,
+			Self::KeyVolumeDown => 114,
+			Self::KeyVolumeUp => 115,
+			Self::KeyACPIPower => 116,
+			Self::KeyPause => 119,
+			Self::KeyLeftGUI => 125,
+			Self::KeyRightGUI => 126,
+			Self::KeyApps => 127,
+			Self::KeyWWWStop => 128,
+			Self::KeyCalculator => 140,
+			Self::KeyACPISleep => 142,
+			Self::KeyACPIWake => 143,
+			Self::KeyEmail => 155,
+			Self::KeyWWWFavorites => 156,
+			Self::KeyMyComputer => 157,
+			Self::KeyWWWBack => 158,
+			Self::KeyWWWForward => 159,
+			Self::KeyNextTrack => 163,
+			Self::KeyPlay => 164,
+			Self::KeyPreviousTrack => 165,
+			Self::KeyStop => 166,
+			Self::KeyWWWHome => 172,
+			Self::KeyWWWRefresh => 173,
+			Self::KeyWWWSearch => 217,
+			Self::KeyMediaSelect => 226,
+		}
+	}
+
+	/// Returns the number of the virtual terminal the key switches to when pressed with alt, if
+	/// any.
+	pub fn get_vt(&self) -> Option<usize> {
+		match self {
+			Self::KeyF1 => Some(1),
+			Self::KeyF2 => Some(2),
+			Self::KeyF3 => Some(3),
+			Self::KeyF4 => Some(4),
+			Self::KeyF5 => Some(5),
+			Self::KeyF6 => Some(6),
+			_ => None,
+		}
+	}
+
 	// TODO Implement correctly with modifiers
 	/// Returns the TTY characters for the given current.
 	///
@@ -511,7 +656,7 @@ impl KeyboardManager {
 	/// Creates a new instance.
 	#[allow(clippy::new_without_default)]
 	pub fn new() -> Self {
-		let s = Self {
+		Self {
 			ctrl: false,
 			left_shift: false,
 			right_shift: false,
@@ -522,25 +667,11 @@ impl KeyboardManager {
 			number_lock: EnableKey::default(),
 			caps_lock: EnableKey::default(),
 			scroll_lock: EnableKey::default(),
-		};
-		s.init_device_files();
-		s
-	}
-
-	/// Initializes devices files.
-	fn init_device_files(&self) {
-		// TODO Create /dev/input/event* files
-	}
-
-	/// Destroys devices files.
-	fn fini_device_files(&self) {
-		// TODO Remove /dev/input/event* files
+		}
 	}
 
 	/// Handles a keyboard input.
 	pub fn input(&mut self, key: KeyboardKey, action: KeyboardAction) {
-		// TODO Write on /dev/input/event* files
-
 		// TODO Handle several keyboards at a time
 		match key {
 			KeyboardKey::KeyLeftControl => self.ctrl = action == KeyboardAction::Pressed,
@@ -570,9 +701,23 @@ impl KeyboardManager {
 			// TODO
 			let meta = false;
 
+			// Virtual terminal switching
+			if alt && let Some(vt) = key.get_vt() {
+				let _ = tty::switch(vt);
+				return;
+			}
+			// Scrollback
+			let tty = tty::current();
+			let shift_pressed = self.left_shift || self.right_shift;
+			if shift_pressed && matches!(key, KeyboardKey::KeyPageUp | KeyboardKey::KeyPageDown) {
+				let height = tty.get_winsize().ws_row as usize;
+				tty.scroll(height / 2, key == KeyboardKey::KeyPageUp);
+				return;
+			}
+
 			// Write on TTY
 			if let Some(tty_chars) = key.get_tty_chars(shift, alt, ctrl, meta) {
-				TTY.input(tty_chars);
+				tty.input(tty_chars);
 			}
 		}
 	}
@@ -598,9 +743,3 @@ impl DeviceManager for KeyboardManager {
 		Ok(())
 	}
 }
-
-impl Drop for KeyboardManager {
-	fn drop(&mut self) {
-		self.fini_device_files();
-	}
-}
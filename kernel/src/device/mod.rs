@@ -36,6 +36,9 @@ pub mod bus;
 pub mod default;
 pub mod fb;
 pub mod id;
+pub mod input;
+pub mod io_queue;
+pub mod io_stats;
 pub mod keyboard;
 pub mod manager;
 pub mod serial;
@@ -45,6 +48,8 @@ pub mod tty;
 use crate::{
 	device::{
 		fb::Framebuffer,
+		io_queue::IoQueue,
+		io_stats::{IoDir, IoRequest, IoStats},
 		manager::DeviceManager,
 		storage::{PartitionOps, partition::Partition},
 	},
@@ -58,12 +63,16 @@ use crate::{
 	memory::{
 		buddy::ZONE_KERNEL,
 		cache::{MappedNode, RcPage},
-		user::UserSlice,
+		user::{UserPtr, UserSlice},
 	},
-	sync::{mutex::Mutex, spin::Spin},
-	syscall::ioctl,
+	module::kmod,
+	sync::{
+		mutex::Mutex,
+		spin::{IntSpin, Spin},
+	},
+	syscall::{FromSyscallArg, ioctl},
 };
-use core::{ffi::c_void, fmt, hint::likely, num::NonZeroU64};
+use core::{ffi::c_void, fmt, hint::likely, num::NonZeroU64, ops::Range};
 use keyboard::KeyboardManager;
 use storage::StorageManager;
 use utils::{
@@ -75,6 +84,7 @@ use utils::{
 	},
 	errno,
 	errno::{AllocResult, ENOENT, EResult},
+	format,
 	limits::PAGE_SIZE,
 	ptr::arc::Arc,
 };
@@ -174,6 +184,16 @@ pub trait BlockDeviceOps: fmt::Debug {
 		let _ = dev;
 	}
 
+	/// Tells whether the device is read-only.
+	fn is_read_only(&self) -> bool {
+		false
+	}
+
+	/// Returns the bounds of the partition, if the device is a partition.
+	fn partition(&self) -> Option<&Partition> {
+		None
+	}
+
 	/// Reads a page of data from the device.
 	///
 	/// `off` is the offset of the page, in pages
@@ -184,6 +204,18 @@ pub trait BlockDeviceOps: fmt::Debug {
 	/// `off` is the offset of the page, in pages
 	fn writeback(&self, dev: &BlkDev, off: u64, page: &RcPage) -> EResult<()>;
 
+	/// Writes a page of data `buf` to the device synchronously, without sleeping or relying on
+	/// interrupts and locks.
+	///
+	/// This is used to write crash dumps when the kernel panics. Devices that cannot be driven in
+	/// such a context return [`errno::EOPNOTSUPP`].
+	///
+	/// `off` is the offset of the page, in pages
+	fn dump_page(&self, dev: &BlkDev, off: u64, buf: &[u8; PAGE_SIZE]) -> EResult<()> {
+		let _ = (dev, off, buf);
+		Err(errno!(EOPNOTSUPP))
+	}
+
 	/// Polls the device with the given mask.
 	fn poll(&self, dev: &BlkDev, mask: u32) -> EResult<u32> {
 		let _ = (dev, mask);
@@ -195,9 +227,12 @@ pub trait BlockDeviceOps: fmt::Debug {
 	/// Arguments:
 	/// - `request` is the ID of the request to perform
 	/// - `argp` is a pointer to the argument
+	///
+	/// If the request is not supported, the function returns [`errno::ENOTTY`], in which case
+	/// requests common to all block devices are handled by the caller.
 	fn ioctl(&self, dev: &BlkDev, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
 		let _ = (dev, request, argp);
-		Err(errno!(EINVAL))
+		Err(errno!(ENOTTY))
 	}
 }
 
@@ -220,6 +255,13 @@ pub struct BlkDev {
 	pub is_partition: bool,
 	/// The list of associated partition devices
 	pub(crate) partitions: Mutex<Vec<Arc<BlkDev>>, false>,
+	/// The queue of I/O requests on the device. Partitions use the queue of the device containing
+	/// them
+	pub io_queue: IoQueue,
+	/// I/O statistics of the device
+	pub stats: Arc<IoStats>,
+	/// The range of pages covered by each partition, along with their I/O statistics
+	pub(crate) part_stats: IntSpin<Vec<(Range<u64>, Arc<IoStats>)>>,
 
 	/// The device I/O interface
 	pub ops: Box<dyn BlockDeviceOps>,
@@ -263,6 +305,9 @@ impl BlkDev {
 
 			is_partition: false,
 			partitions: Mutex::new(Vec::new()),
+			io_queue: IoQueue::new(),
+			stats: Arc::new(IoStats::default())?,
+			part_stats: IntSpin::new(Vec::new()),
 
 			ops,
 			mapped: Default::default(),
@@ -294,6 +339,9 @@ impl BlkDev {
 
 			is_partition: true,
 			partitions: Mutex::new(Vec::new()),
+			io_queue: IoQueue::new(),
+			stats: Arc::new(IoStats::default())?,
+			part_stats: IntSpin::new(Vec::new()),
 
 			ops: Box::new(PartitionOps {
 				dev,
@@ -310,6 +358,25 @@ impl BlkDev {
 		RcPage::new(ZONE_KERNEL, Some(this.clone()), off)
 	}
 
+	/// Waits until the device is available for an I/O request in direction `dir`, on the page at
+	/// offset `off`.
+	///
+	/// The request is accounted for in the statistics of the device and of the partition
+	/// containing the page, until the returned value is dropped.
+	///
+	/// This function is meant to be used by the implementations of [`BlockDeviceOps`].
+	pub fn start_io(&self, dir: IoDir, off: u64) -> AllocResult<IoRequest<'_>> {
+		let part = self
+			.part_stats
+			.lock()
+			.iter()
+			.find(|(range, _)| range.contains(&off))
+			.map(|(_, stats)| stats.clone());
+		let mut req = IoRequest::new(&self.stats, part, dir);
+		req.queue = Some(self.io_queue.acquire()?);
+		Ok(req)
+	}
+
 	/// Removes the device file from the filesystem
 	#[inline]
 	pub fn remove_file(&self) -> EResult<()> {
@@ -391,6 +458,58 @@ pub fn register_char(dev: Arc<CharDev>) -> AllocResult<()> {
 	Ok(())
 }
 
+/// Returns the device with type `dev_type` and ID `id`, using `get` to look it up.
+///
+/// If the device is not registered, the function requests the module providing it, then tries
+/// again.
+fn request_device<T, F: Fn() -> Option<T>>(
+	dev_type: DeviceType,
+	id: &DeviceID,
+	get: F,
+) -> Option<T> {
+	if let Some(dev) = get() {
+		return Some(dev);
+	}
+	let prefix = match dev_type {
+		DeviceType::Block => "block",
+		DeviceType::Char => "char",
+	};
+	// First look for a module handling the specific device, then for the whole major number
+	for with_minor in [true, false] {
+		let alias = if with_minor {
+			format!("{prefix}-major-{}-{}", id.major, id.minor)
+		} else {
+			format!("{prefix}-major-{}", id.major)
+		};
+		kmod::request_module(alias.ok()?).ok()?;
+		let dev = get();
+		if dev.is_some() {
+			return dev;
+		}
+	}
+	None
+}
+
+/// Returns the block device with ID `id`.
+///
+/// If the device is not registered, the function requests the module providing it, then tries
+/// again.
+pub fn request_blk(id: &DeviceID) -> Option<Arc<BlkDev>> {
+	request_device(DeviceType::Block, id, || {
+		BLK_DEVICES.lock().get(id).cloned()
+	})
+}
+
+/// Returns the character device with ID `id`.
+///
+/// If the device is not registered, the function requests the module providing it, then tries
+/// again.
+pub fn request_char(id: &DeviceID) -> Option<Arc<CharDev>> {
+	request_device(DeviceType::Char, id, || {
+		CHAR_DEVICES.lock().get(id).cloned()
+	})
+}
+
 /// Block device file operations.
 #[derive(Debug)]
 pub struct BlkDevFileOps;
@@ -419,6 +538,9 @@ impl FileOps for BlkDevFileOps {
 
 	fn write(&self, file: &File, mut off: u64, buf: UserSlice<u8>) -> EResult<usize> {
 		let dev = file.as_block_device().ok_or_else(|| errno!(ENODEV))?;
+		if dev.ops.is_read_only() {
+			return Err(errno!(EPERM));
+		}
 		let start = off / PAGE_SIZE as u64;
 		let end = off
 			.checked_add(buf.len() as u64)
@@ -446,7 +568,27 @@ impl FileOps for BlkDevFileOps {
 
 	fn ioctl(&self, file: &File, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
 		let dev = file.as_block_device().ok_or_else(|| errno!(ENODEV))?;
-		dev.ops.ioctl(&dev, request, argp)
+		let req = request.get_old_format();
+		match dev.ops.ioctl(&dev, request, argp) {
+			Err(e) if e == errno!(ENOTTY) => {}
+			res => return res,
+		}
+		// Requests common to all block devices
+		match req {
+			ioctl::BLKSSZGET => {
+				let blk_size = dev.blk_size.get();
+				let size_ptr = UserPtr::<u32>::from_ptr(argp as usize);
+				size_ptr.copy_to_user(&(blk_size as _))?;
+				Ok(0)
+			}
+			ioctl::BLKGETSIZE64 => {
+				let size = dev.blk_size.get() * dev.blk_count;
+				let size_ptr = UserPtr::<u64>::from_ptr(argp as usize);
+				size_ptr.copy_to_user(&size)?;
+				Ok(0)
+			}
+			_ => Err(errno!(ENOTTY)),
+		}
 	}
 }
 
@@ -0,0 +1,841 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Advanced Host Controller Interface (AHCI) SATA driver.
+//!
+//! Commands are issued one at a time on each port, without Native Command Queuing.
+//!
+//! [AHCI specification](https://www.intel.com/content/dam/www/public/us/en/documents/technical-specifications/serial-ata-ahci-spec-rev1-3-1.pdf)
+
+use crate::{
+	arch::{
+		core_id,
+		x86::{idt::disable_int, timer::mdelay},
+	},
+	device::{
+		BlkDev, BlockDeviceOps, DeviceID,
+		bar::Bar,
+		bus::pci::PciDev,
+		id::{BLOCK_EXTENDED_MAJOR, BLOCK_EXTENDED_MAJOR_HANDLE},
+		io_stats::IoDir,
+		manager::PhysicalDevice,
+		storage::{
+			SCSI_MAJOR, STORAGE_MODE, alloc_scsi_id, ata,
+			ata::{AtaDrive, TaskFile},
+			register_disk, scsi_partition,
+		},
+	},
+	int,
+	int::CallbackHandle,
+	memory::{PhysAddr, VirtAddr, buddy, cache::RcPage},
+	println, process,
+	process::{Process, State, scheduler::schedule},
+	sync::{mutex::Mutex, spin::IntSpin},
+	syscall::ioctl,
+};
+use core::{
+	any::Any, ffi::c_void, fmt, fmt::Formatter, hint::unlikely, num::NonZeroU64, ptr::NonNull,
+};
+use utils::{
+	boxed::Box,
+	collections::{path::PathBuf, vec::Vec},
+	errno,
+	errno::{AllocResult, ENOMEM, EResult},
+	format,
+	limits::PAGE_SIZE,
+	ptr::arc::Arc,
+};
+
+/// Register: Host Capabilities
+const REG_CAP: usize = 0x00;
+/// Register: Global Host Control
+const REG_GHC: usize = 0x04;
+/// Register: Interrupt Status
+const REG_IS: usize = 0x08;
+/// Register: Ports Implemented
+const REG_PI: usize = 0x0c;
+/// Register: Version
+const REG_VS: usize = 0x10;
+/// Register: Host Capabilities Extended
+const REG_CAP2: usize = 0x24;
+/// Register: BIOS/OS Handoff Control and Status
+const REG_BOHC: usize = 0x28;
+
+/// Flag (CAP): Supports 64-bit Addressing
+const FLAG_CAP_S64A: u32 = 1 << 31;
+/// Flag (GHC): HBA Reset
+const FLAG_GHC_HR: u32 = 1 << 0;
+/// Flag (GHC): Interrupt Enable
+const FLAG_GHC_IE: u32 = 1 << 1;
+/// Flag (GHC): AHCI Enable
+const FLAG_GHC_AE: u32 = 1 << 31;
+/// Flag (CAP2): BIOS/OS Handoff
+const FLAG_CAP2_BOH: u32 = 1 << 0;
+/// Flag (BOHC): BIOS Owned Semaphore
+const FLAG_BOHC_BOS: u32 = 1 << 0;
+/// Flag (BOHC): OS Owned Semaphore
+const FLAG_BOHC_OOS: u32 = 1 << 1;
+/// Flag (BOHC): BIOS Busy
+const FLAG_BOHC_BB: u32 = 1 << 4;
+
+/// Offset of the first port's registers
+const PORTS_OFF: usize = 0x100;
+/// The size of each port's registers
+const PORT_REGS_SIZE: usize = 0x80;
+
+/// Port register: Command List Base Address
+const PORT_CLB: usize = 0x00;
+/// Port register: Command List Base Address Upper 32-bits
+const PORT_CLBU: usize = 0x04;
+/// Port register: FIS Base Address
+const PORT_FB: usize = 0x08;
+/// Port register: FIS Base Address Upper 32-bits
+const PORT_FBU: usize = 0x0c;
+/// Port register: Interrupt Status
+const PORT_IS: usize = 0x10;
+/// Port register: Interrupt Enable
+const PORT_IE: usize = 0x14;
+/// Port register: Command and Status
+const PORT_CMD: usize = 0x18;
+/// Port register: Task File Data
+const PORT_TFD: usize = 0x20;
+/// Port register: Signature
+const PORT_SIG: usize = 0x24;
+/// Port register: SATA Status
+const PORT_SSTS: usize = 0x28;
+/// Port register: SATA Error
+const PORT_SERR: usize = 0x30;
+/// Port register: Command Issue
+const PORT_CI: usize = 0x38;
+
+/// Flag (PxCMD): Start
+const FLAG_CMD_ST: u32 = 1 << 0;
+/// Flag (PxCMD): Spin-Up Device
+const FLAG_CMD_SUD: u32 = 1 << 1;
+/// Flag (PxCMD): Power On Device
+const FLAG_CMD_POD: u32 = 1 << 2;
+/// Flag (PxCMD): FIS Receive Enable
+const FLAG_CMD_FRE: u32 = 1 << 4;
+/// Flag (PxCMD): FIS Receive Running
+const FLAG_CMD_FR: u32 = 1 << 14;
+/// Flag (PxCMD): Command List Running
+const FLAG_CMD_CR: u32 = 1 << 15;
+
+/// Flag (PxIS): Device to Host Register FIS Interrupt
+const FLAG_IS_DHRS: u32 = 1 << 0;
+/// Flag (PxIS): PIO Setup FIS Interrupt
+const FLAG_IS_PSS: u32 = 1 << 1;
+/// Flag (PxIS): DMA Setup FIS Interrupt
+const FLAG_IS_DSS: u32 = 1 << 2;
+/// Flag (PxIS): Set Device Bits Interrupt
+const FLAG_IS_SDBS: u32 = 1 << 3;
+/// Flag (PxIS): Interface Fatal Error
+const FLAG_IS_IFS: u32 = 1 << 27;
+/// Flag (PxIS): Host Bus Data Error
+const FLAG_IS_HBDS: u32 = 1 << 28;
+/// Flag (PxIS): Host Bus Fatal Error
+const FLAG_IS_HBFS: u32 = 1 << 29;
+/// Flag (PxIS): Task File Error
+const FLAG_IS_TFES: u32 = 1 << 30;
+/// Interrupts signaling an error on a port
+const IS_ERROR: u32 = FLAG_IS_IFS | FLAG_IS_HBDS | FLAG_IS_HBFS | FLAG_IS_TFES;
+
+/// Flag (PxTFD): Error
+const FLAG_TFD_ERR: u32 = 1 << 0;
+/// Flag (PxTFD): Data Request
+const FLAG_TFD_DRQ: u32 = 1 << 3;
+/// Flag (PxTFD): Busy
+const FLAG_TFD_BSY: u32 = 1 << 7;
+
+/// SSTS device detection: device present and communication established
+const SSTS_DET_PRESENT: u32 = 3;
+/// SSTS interface power management: active
+const SSTS_IPM_ACTIVE: u32 = 1;
+
+/// Device signature: SATA drive
+const SIG_ATA: u32 = 0x00000101;
+/// Device signature: SATAPI drive
+const SIG_ATAPI: u32 = 0xeb140101;
+
+/// FIS type: Register, host to device
+const FIS_TYPE_REG_H2D: u8 = 0x27;
+
+/// ATA command: Read DMA (LBA28)
+const ATA_CMD_READ_DMA: u8 = 0xc8;
+/// ATA command: Read DMA (LBA48)
+const ATA_CMD_READ_DMA_EXT: u8 = 0x25;
+/// ATA command: Write DMA (LBA28)
+const ATA_CMD_WRITE_DMA: u8 = 0xca;
+/// ATA command: Write DMA (LBA48)
+const ATA_CMD_WRITE_DMA_EXT: u8 = 0x35;
+/// ATA command: Identify Device
+const ATA_CMD_IDENTIFY: u8 = 0xec;
+
+/// Offset of the command list in a port's memory
+const CL_OFF: usize = 0;
+/// Offset of the received FIS area in a port's memory
+const FIS_OFF: usize = 0x400;
+/// Offset of the last D2H register FIS received, in the port's memory
+const D2H_OFF: usize = FIS_OFF + 0x40;
+/// Offset of the command table in a port's memory
+const CT_OFF: usize = 0x500;
+/// Offset of the PRDT in a command table
+const PRDT_OFF: usize = 0x80;
+/// Offset of the buffer for small transfers in a port's memory
+const DATA_OFF: usize = 0x800;
+
+/// The default size of a sector, in bytes.
+const SECTOR_SIZE: u64 = 512;
+
+/// The maximum time to wait for the controller, in milliseconds.
+const TIMEOUT: u32 = 1000;
+
+/// Waits until `f` returns `true`, or until [`TIMEOUT`] is reached.
+fn wait_for<F: FnMut() -> bool>(mut f: F) -> EResult<()> {
+	for _ in 0..TIMEOUT {
+		if f() {
+			return Ok(());
+		}
+		mdelay(1);
+	}
+	Err(errno!(ETIMEDOUT))
+}
+
+/// Command header, in the command list.
+#[repr(C)]
+struct CommandHeader {
+	/// Flags, including the command FIS length and the direction
+	flags: u16,
+	/// Physical Region Descriptor Table Length, in entries
+	prdtl: u16,
+	/// Physical Region Descriptor Byte Count, the number of bytes transferred
+	prdbc: u32,
+	/// Command Table Base Address
+	ctba: u64,
+	_reserved: [u32; 4],
+}
+
+/// Physical Region Descriptor, describing a memory region for a transfer.
+#[repr(C)]
+struct Prd {
+	/// Data Base Address
+	dba: u64,
+	_reserved: u32,
+	/// Data Byte Count (minus one), with the Interrupt on Completion flag
+	dbc: u32,
+}
+
+/// Register FIS, host to device.
+#[repr(C)]
+#[derive(Default)]
+struct RegH2D {
+	/// FIS type, always [`FIS_TYPE_REG_H2D`]
+	fis_type: u8,
+	/// Flags. Bit 7 indicates the FIS is a command
+	flags: u8,
+	/// The command
+	command: u8,
+	/// Features register (low)
+	featurel: u8,
+	/// LBA, bits 0 to 23
+	lba_low: [u8; 3],
+	/// Device register
+	device: u8,
+	/// LBA, bits 24 to 47
+	lba_high: [u8; 3],
+	/// Features register (high)
+	featureh: u8,
+	/// Sectors count
+	count: u16,
+	/// Isochronous Command Completion
+	icc: u8,
+	/// Control register
+	control: u8,
+	_reserved: u32,
+}
+
+impl RegH2D {
+	/// Creates a FIS for the ATA command `command`, applying to `count` sectors at `lba`.
+	fn new(command: u8, lba: u64, count: u16) -> Self {
+		let lba = lba.to_le_bytes();
+		Self {
+			fis_type: FIS_TYPE_REG_H2D,
+			flags: 0x80,
+			command,
+			lba_low: [lba[0], lba[1], lba[2]],
+			// LBA mode. The low bits hold bits 24 to 27 of the LBA for 28-bit commands
+			device: (1 << 6) | (lba[3] & 0xf),
+			lba_high: [lba[3], lba[4], lba[5]],
+			count,
+			..Default::default()
+		}
+	}
+}
+
+/// Register FIS, device to host.
+#[repr(C)]
+struct RegD2H {
+	/// FIS type
+	fis_type: u8,
+	/// Flags
+	flags: u8,
+	/// Status register
+	status: u8,
+	/// Error register
+	error: u8,
+	/// LBA, bits 0 to 23
+	lba_low: [u8; 3],
+	/// Device register
+	device: u8,
+	/// LBA, bits 24 to 47
+	lba_high: [u8; 3],
+	_reserved0: u8,
+	/// Sectors count
+	count: u16,
+	_reserved1: [u8; 6],
+}
+
+/// DMA memory of a port.
+///
+/// It contains the command list (with a single command slot used), the received FIS area, the
+/// command table and a buffer for small transfers.
+struct PortMem(NonNull<u8>);
+
+impl PortMem {
+	/// Allocates zeroed memory.
+	fn new() -> AllocResult<Self> {
+		let ptr = buddy::alloc_kernel(0, 0)?;
+		unsafe {
+			NonNull::slice_from_raw_parts(ptr, PAGE_SIZE)
+				.as_mut()
+				.fill(0);
+		}
+		Ok(Self(ptr))
+	}
+
+	/// Returns the physical address of the data at offset `off`.
+	fn phys_addr(&self, off: usize) -> PhysAddr {
+		VirtAddr::from(self.0).kernel_to_physical().unwrap() + off
+	}
+
+	/// Returns a pointer to the data at offset `off`.
+	fn ptr<T>(&self, off: usize) -> *mut T {
+		unsafe { self.0.add(off).cast().as_ptr() }
+	}
+}
+
+impl Drop for PortMem {
+	fn drop(&mut self) {
+		unsafe {
+			buddy::free_kernel(self.0.as_ptr(), 0);
+		}
+	}
+}
+
+unsafe impl Send for PortMem {}
+
+unsafe impl Sync for PortMem {}
+
+/// The state of the command being executed on a port.
+#[derive(Default)]
+struct Completion {
+	/// The process waiting for the command to complete
+	waiter: Option<Arc<Process>>,
+	/// The interrupt status of the port when the command completed, if it did
+	status: Option<u32>,
+}
+
+/// A port of the controller, with a SATA drive attached.
+struct Port {
+	/// The port's number
+	num: usize,
+	/// The port's DMA memory
+	mem: PortMem,
+
+	/// Serializes commands on the port
+	lock: Mutex<(), false>,
+	/// The state of the command being executed, modified by the interrupt handler
+	completion: IntSpin<Completion>,
+}
+
+struct ControllerInner {
+	/// AHCI Base Address Register
+	bar: Bar,
+	/// Tells whether the controller supports 64-bit addresses
+	s64a: bool,
+	/// The ports with a drive attached
+	ports: Vec<Port>,
+}
+
+impl ControllerInner {
+	/// Reads the register `reg` of the port `port`.
+	#[inline]
+	fn read_port(&self, port: usize, reg: usize) -> u32 {
+		unsafe { self.bar.read(PORTS_OFF + port * PORT_REGS_SIZE + reg) }
+	}
+
+	/// Writes the register `reg` of the port `port`.
+	#[inline]
+	fn write_port(&self, port: usize, reg: usize, val: u32) {
+		unsafe {
+			self.bar.write(PORTS_OFF + port * PORT_REGS_SIZE + reg, val);
+		}
+	}
+
+	/// Stops the command engine of the port `port`.
+	fn stop_port(&self, port: usize) -> EResult<()> {
+		let cmd = self.read_port(port, PORT_CMD);
+		self.write_port(port, PORT_CMD, cmd & !(FLAG_CMD_ST | FLAG_CMD_FRE));
+		wait_for(|| self.read_port(port, PORT_CMD) & (FLAG_CMD_CR | FLAG_CMD_FR) == 0)
+	}
+
+	/// Starts the command engine of the port `port`.
+	fn start_port(&self, port: usize) -> EResult<()> {
+		let cmd = self.read_port(port, PORT_CMD);
+		self.write_port(port, PORT_CMD, cmd | FLAG_CMD_FRE);
+		// The drive must be idle before starting
+		wait_for(|| self.read_port(port, PORT_TFD) & (FLAG_TFD_BSY | FLAG_TFD_DRQ) == 0)?;
+		let cmd = self.read_port(port, PORT_CMD);
+		self.write_port(port, PORT_CMD, cmd | FLAG_CMD_ST);
+		Ok(())
+	}
+
+	/// Initializes the port `num`, returning `None` if no supported drive is attached to it.
+	fn init_port(&self, num: usize) -> EResult<Option<PortMem>> {
+		let ssts = self.read_port(num, PORT_SSTS);
+		if ssts & 0xf != SSTS_DET_PRESENT || (ssts >> 8) & 0xf != SSTS_IPM_ACTIVE {
+			return Ok(None);
+		}
+		match self.read_port(num, PORT_SIG) {
+			SIG_ATA => {}
+			SIG_ATAPI => {
+				println!("ahci: port {num}: ATAPI drives are not supported");
+				return Ok(None);
+			}
+			sig => {
+				println!("ahci: port {num}: unsupported device (signature: {sig:08x})");
+				return Ok(None);
+			}
+		}
+		self.stop_port(num)?;
+		let mem = PortMem::new()?;
+		let cl = mem.phys_addr(CL_OFF).0 as u64;
+		let fis = mem.phys_addr(FIS_OFF).0 as u64;
+		if unlikely(!self.s64a && (cl >> 32) != 0) {
+			println!("ahci: port {num}: memory out of the controller's addressing range");
+			return Err(errno!(EIO));
+		}
+		self.write_port(num, PORT_CLB, cl as u32);
+		self.write_port(num, PORT_CLBU, (cl >> 32) as u32);
+		self.write_port(num, PORT_FB, fis as u32);
+		self.write_port(num, PORT_FBU, (fis >> 32) as u32);
+		// Clear errors and pending interrupts
+		self.write_port(num, PORT_SERR, !0);
+		self.write_port(num, PORT_IS, !0);
+		self.write_port(
+			num,
+			PORT_IE,
+			FLAG_IS_DHRS | FLAG_IS_PSS | FLAG_IS_DSS | FLAG_IS_SDBS | IS_ERROR,
+		);
+		let cmd = self.read_port(num, PORT_CMD);
+		self.write_port(num, PORT_CMD, cmd | FLAG_CMD_SUD | FLAG_CMD_POD);
+		self.start_port(num)?;
+		Ok(Some(mem))
+	}
+
+	/// Recovers the port `port` after an error.
+	fn recover_port(&self, port: usize) -> EResult<()> {
+		self.stop_port(port)?;
+		self.write_port(port, PORT_SERR, !0);
+		self.write_port(port, PORT_IS, !0);
+		self.start_port(port)
+	}
+
+	/// Executes the command `fis` on the drive attached to `port`, transferring `len` bytes from
+	/// or to the buffer at `buf`. If `len` is zero, no data is transferred.
+	///
+	/// `write` tells whether data is written to the drive.
+	///
+	/// On success, the function returns the registers of the drive after the command.
+	fn command(
+		&self,
+		port: &Port,
+		fis: RegH2D,
+		buf: PhysAddr,
+		len: usize,
+		write: bool,
+	) -> EResult<TaskFile> {
+		let command = fis.command;
+		let _guard = port.lock.lock();
+		// Build the command table
+		unsafe {
+			port.mem.ptr::<RegH2D>(CT_OFF).write_volatile(fis);
+			if len > 0 {
+				port.mem.ptr::<Prd>(CT_OFF + PRDT_OFF).write_volatile(Prd {
+					dba: buf.0 as _,
+					_reserved: 0,
+					dbc: (len as u32 - 1) | (1 << 31),
+				});
+			}
+			// Command FIS length in dwords
+			let cfl = (size_of::<RegH2D>() / 4) as u16;
+			port.mem
+				.ptr::<CommandHeader>(CL_OFF)
+				.write_volatile(CommandHeader {
+					flags: cfl | ((write as u16) << 6),
+					prdtl: (len > 0) as u16,
+					prdbc: 0,
+					ctba: port.mem.phys_addr(CT_OFF).0 as _,
+					_reserved: [0; 4],
+				});
+		}
+		// Disable interrupts to prevent the completion interrupt from being handled before the
+		// process is put to sleep
+		let status = disable_int(|| {
+			*port.completion.lock() = Completion {
+				waiter: Some(Process::current()),
+				status: None,
+			};
+			self.write_port(port.num, PORT_CI, 1);
+			loop {
+				{
+					let mut completion = port.completion.lock();
+					if let Some(status) = completion.status {
+						completion.waiter = None;
+						break status;
+					}
+					process::set_state(State::Sleeping);
+				}
+				schedule();
+			}
+		});
+		let tfd = self.read_port(port.num, PORT_TFD);
+		if unlikely(status & IS_ERROR != 0 || tfd & FLAG_TFD_ERR != 0) {
+			println!(
+				"ahci: port {}: command {command:#x} failed (status: {status:#x}, task file: {tfd:#x})",
+				port.num
+			);
+			self.recover_port(port.num)?;
+			return Err(errno!(EIO));
+		}
+		// The status and error are up to date in the task file, even if the command ended
+		// without a D2H FIS
+		let d2h = unsafe { port.mem.ptr::<RegD2H>(D2H_OFF).read_volatile() };
+		Ok(TaskFile {
+			command: tfd as u8,
+			feature: (tfd >> 8) as u8,
+			count: d2h.count as u8,
+			lba: d2h.lba_low,
+			device: d2h.device,
+		})
+	}
+
+	/// Handles an interrupt from the controller.
+	fn handle_int(&self) {
+		let is: u32 = unsafe { self.bar.read(REG_IS) };
+		for port in &self.ports {
+			if is & (1 << port.num) == 0 {
+				continue;
+			}
+			let port_is = self.read_port(port.num, PORT_IS);
+			self.write_port(port.num, PORT_IS, port_is);
+			// The command is completed when its slot is cleared
+			let done = self.read_port(port.num, PORT_CI) & 1 == 0;
+			if !done && port_is & IS_ERROR == 0 {
+				continue;
+			}
+			let mut completion = port.completion.lock();
+			if let Some(proc) = &completion.waiter {
+				Process::wake_from(proc, State::Sleeping as _);
+				completion.status = Some(port_is);
+			}
+		}
+		unsafe {
+			self.bar.write(REG_IS, is);
+		}
+	}
+}
+
+/// Handle of a drive attached to a port.
+struct Disk {
+	/// The controller
+	ctrlr: Arc<ControllerInner>,
+	/// The index of the port in the controller's list
+	port: usize,
+	/// Tells whether the drive supports 48-bit LBA
+	lba48: bool,
+	/// The ID of the disk
+	scsi_id: u32,
+	/// The data returned by the IDENTIFY DEVICE command
+	identity: [u16; 256],
+}
+
+impl Disk {
+	/// Identifies the drive attached to the port `port` of `ctrlr`, and registers it.
+	fn init(ctrlr: &Arc<ControllerInner>, port: usize) -> EResult<()> {
+		let p = &ctrlr.ports[port];
+		let fis = RegH2D::new(ATA_CMD_IDENTIFY, 0, 0);
+		ctrlr.command(p, fis, p.mem.phys_addr(DATA_OFF), 512, false)?;
+		let data = unsafe { p.mem.ptr::<[u16; 256]>(DATA_OFF).read() };
+		let lba48 = data[83] & (1 << 10) != 0;
+		let sectors_count = if lba48 {
+			(data[100] as u64)
+				| ((data[101] as u64) << 16)
+				| ((data[102] as u64) << 32)
+				| ((data[103] as u64) << 48)
+		} else {
+			(data[60] as u64) | ((data[61] as u64) << 16)
+		};
+		// Logical sector size, if reported
+		let blk_size = if data[106] & 0xc000 == 0x4000 && data[106] & (1 << 12) != 0 {
+			((data[117] as u64) | ((data[118] as u64) << 16)) * 2
+		} else {
+			SECTOR_SIZE
+		};
+		if unlikely(!blk_size.is_power_of_two() || blk_size > PAGE_SIZE as u64) {
+			println!("ahci: port {}: unsupported sector size {blk_size}", p.num);
+			return Ok(());
+		}
+		// Register device
+		let scsi_id = alloc_scsi_id();
+		// TODO Handle if out of the alphabet
+		let letter = (b'a' + scsi_id as u8) as char;
+		let path = PathBuf::new_unchecked(format!("/dev/sd{letter}")?);
+		println!("ahci: port {}: detected drive ({path})", p.num);
+		let dev = BlkDev::new(
+			DeviceID {
+				major: SCSI_MAJOR,
+				minor: scsi_id * 16,
+			},
+			path,
+			STORAGE_MODE,
+			NonZeroU64::new(blk_size).unwrap(),
+			sectors_count,
+			Box::new(Disk {
+				ctrlr: ctrlr.clone(),
+				port,
+				lba48,
+				scsi_id,
+				identity: data,
+			})?,
+		)?;
+		register_disk(dev)?;
+		Ok(())
+	}
+
+	/// Reads or writes the page at offset `off` of `dev`, from or to the buffer at `addr`.
+	fn io(&self, dev: &BlkDev, write: bool, off: u64, addr: PhysAddr) -> EResult<()> {
+		let blocks = PAGE_SIZE as u64 / dev.blk_size.get();
+		let lba = off.checked_mul(blocks).ok_or_else(|| errno!(EOVERFLOW))?;
+		// Bound check
+		let end_lba = lba.checked_add(blocks).ok_or_else(|| errno!(EOVERFLOW))?;
+		if unlikely(end_lba > dev.blk_count) {
+			return Err(errno!(EOVERFLOW));
+		}
+		let command = match (self.lba48, write) {
+			(true, false) => ATA_CMD_READ_DMA_EXT,
+			(true, true) => ATA_CMD_WRITE_DMA_EXT,
+			(false, false) => ATA_CMD_READ_DMA,
+			(false, true) => ATA_CMD_WRITE_DMA,
+		};
+		// Wait for our turn on the device
+		let dir = if write { IoDir::Write } else { IoDir::Read };
+		let _io = dev.start_io(dir, off)?;
+		let port = &self.ctrlr.ports[self.port];
+		self.ctrlr.command(
+			port,
+			RegH2D::new(command, lba, blocks as _),
+			addr,
+			PAGE_SIZE,
+			write,
+		)?;
+		Ok(())
+	}
+}
+
+impl AtaDrive for Disk {
+	fn identity(&self) -> &[u16; 256] {
+		&self.identity
+	}
+
+	fn exec(&self, tf: &TaskFile, buf: Option<&mut [u8; ata::SECTOR_SIZE]>) -> EResult<TaskFile> {
+		let fis = RegH2D {
+			fis_type: FIS_TYPE_REG_H2D,
+			flags: 0x80,
+			command: tf.command,
+			featurel: tf.feature,
+			lba_low: tf.lba,
+			device: tf.device,
+			count: tf.count as _,
+			..Default::default()
+		};
+		let port = &self.ctrlr.ports[self.port];
+		let Some(buf) = buf else {
+			return self.ctrlr.command(port, fis, PhysAddr(0), 0, false);
+		};
+		// Bounce buffer, since the command is executed by DMA
+		let mem = PortMem::new()?;
+		let res = self
+			.ctrlr
+			.command(port, fis, mem.phys_addr(0), buf.len(), false)?;
+		unsafe {
+			buf.copy_from_slice(&*mem.ptr::<[u8; ata::SECTOR_SIZE]>(0));
+		}
+		Ok(res)
+	}
+}
+
+impl BlockDeviceOps for Disk {
+	fn new_partition(&self, _dev: &BlkDev, id: u32) -> AllocResult<(DeviceID, PathBuf)> {
+		scsi_partition(self.scsi_id, id)
+	}
+
+	fn drop_partition(&self, dev: &BlkDev) {
+		if dev.id.major == BLOCK_EXTENDED_MAJOR {
+			BLOCK_EXTENDED_MAJOR_HANDLE.lock().free_minor(dev.id.minor);
+		}
+	}
+
+	fn read_page(&self, dev: &Arc<BlkDev>, off: u64) -> EResult<RcPage> {
+		dev.mapped.get_or_insert_page(off, || {
+			let blk = BlkDev::new_page(dev, off)?;
+			self.io(dev, false, off, blk.phys_addr())?;
+			Ok(blk)
+		})
+	}
+
+	fn writeback(&self, dev: &BlkDev, off: u64, blk: &RcPage) -> EResult<()> {
+		self.io(dev, true, off, blk.phys_addr())
+	}
+
+	fn ioctl(&self, _dev: &BlkDev, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
+		ata::ioctl(self, request, argp)
+	}
+}
+
+impl fmt::Debug for Disk {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Disk")
+			.field("port", &self.ctrlr.ports[self.port].num)
+			.finish()
+	}
+}
+
+/// An AHCI controller.
+pub struct Controller {
+	/// The interrupt callback
+	_int: CallbackHandle,
+}
+
+impl Controller {
+	/// Creates a new instance, initializing the controller and the drives attached to it.
+	pub fn new(dev: &dyn PhysicalDevice) -> EResult<Self> {
+		// An AHCI controller can only be connected to a PCI bus
+		let dev: &PciDev = (dev as &dyn Any).downcast_ref().unwrap();
+		let bar = dev.get_bars().get(5).cloned().flatten();
+		let Some(bar) = bar else {
+			println!("ahci: BAR not found");
+			return Err(errno!(EINVAL));
+		};
+		// Enable interrupts, bus mastering and memory access
+		dev.write_status_command((dev.read_status_command() & !(1 << 10)) | 0b110);
+		let version: u32 = unsafe { bar.read(REG_VS) };
+		println!(
+			"ahci: controller version {}.{}",
+			version >> 16,
+			(version & 0xffff) >> 8
+		);
+		// Take ownership from the BIOS
+		let cap2: u32 = unsafe { bar.read(REG_CAP2) };
+		if cap2 & FLAG_CAP2_BOH != 0 {
+			unsafe {
+				let bohc = bar.read::<u32>(REG_BOHC);
+				bar.write::<u32>(REG_BOHC, bohc | FLAG_BOHC_OOS);
+			}
+			let res = wait_for(|| {
+				let bohc = unsafe { bar.read::<u32>(REG_BOHC) };
+				bohc & (FLAG_BOHC_BOS | FLAG_BOHC_BB) == 0
+			});
+			if res.is_err() {
+				println!("ahci: BIOS did not release the controller");
+			}
+		}
+		// Reset the controller
+		unsafe {
+			bar.write::<u32>(REG_GHC, FLAG_GHC_AE);
+			bar.write::<u32>(REG_GHC, FLAG_GHC_AE | FLAG_GHC_HR);
+		}
+		wait_for(|| unsafe { bar.read::<u32>(REG_GHC) } & FLAG_GHC_HR == 0)
+			.inspect_err(|_| println!("ahci: controller reset timeout"))?;
+		unsafe {
+			bar.write::<u32>(REG_GHC, FLAG_GHC_AE);
+		}
+		let (cap, pi) = unsafe { (bar.read::<u32>(REG_CAP), bar.read::<u32>(REG_PI)) };
+		let mut inner = ControllerInner {
+			bar,
+			s64a: cap & FLAG_CAP_S64A != 0,
+			ports: Vec::new(),
+		};
+		// Let drives settle after the reset
+		mdelay(10);
+		for num in (0..32).filter(|i| pi & (1 << i) != 0) {
+			match inner.init_port(num) {
+				Ok(Some(mem)) => inner.ports.push(Port {
+					num,
+					mem,
+
+					lock: Mutex::new(()),
+					completion: IntSpin::new(Completion::default()),
+				})?,
+				Ok(None) => {}
+				Err(e) => println!("ahci: port {num}: initialization failed: {e}"),
+			}
+		}
+		let inner = Arc::new(inner)?;
+		// Setup interrupt handler
+		let int = unsafe {
+			let inner_ = inner.clone();
+			int::alloc_callback(move |_, _, _, _| inner_.handle_int())?
+		};
+		if !dev.enable_msi(core_id() as _, true, false, int.id()) {
+			println!("ahci: no MSI, driver does not support legacy interrupts");
+			unsafe {
+				int.unregister();
+			}
+			return Err(errno!(EINVAL));
+		}
+		unsafe {
+			inner.bar.write::<u32>(REG_IS, !0);
+			inner.bar.write::<u32>(REG_GHC, FLAG_GHC_AE | FLAG_GHC_IE);
+		}
+		println!("ahci: controller started ({} drives)", inner.ports.len());
+		for port in 0..inner.ports.len() {
+			match Disk::init(&inner, port) {
+				Ok(()) => {}
+				Err(e) if e.as_int() == ENOMEM => return Err(e),
+				Err(e) => println!(
+					"ahci: port {}: cannot initialize drive: {e}",
+					inner.ports[port].num
+				),
+			}
+		}
+		Ok(Self {
+			_int: int,
+		})
+	}
+}
@@ -0,0 +1,230 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Passthrough of ATA commands from userspace, to query the identity and health of drives.
+//!
+//! Only commands that do not modify the content of the drive and transfer at most one sector from
+//! it are accepted.
+
+use crate::{
+	file::perm::is_privileged,
+	memory::user::{UserPtr, UserSlice},
+	syscall::{FromSyscallArg, ioctl},
+};
+use core::{ffi::c_void, ptr};
+use utils::{errno, errno::EResult};
+
+/// The size of a sector transferred by a command, in bytes.
+pub const SECTOR_SIZE: usize = 512;
+
+/// ATA command: IDENTIFY DEVICE.
+const ATA_CMD_IDENTIFY: u8 = 0xec;
+/// ATA command: CHECK POWER MODE.
+const ATA_CMD_CHECK_POWER_MODE: u8 = 0xe5;
+/// ATA command: SMART, the subcommand being in the features register.
+const ATA_CMD_SMART: u8 = 0xb0;
+
+/// SMART subcommand: read attribute values.
+const SMART_READ_VALUES: u8 = 0xd0;
+/// SMART subcommand: read attribute thresholds.
+const SMART_READ_THRESHOLDS: u8 = 0xd1;
+/// SMART subcommand: enable operations.
+const SMART_ENABLE: u8 = 0xd8;
+/// SMART subcommand: disable operations.
+const SMART_DISABLE: u8 = 0xd9;
+/// SMART subcommand: return the health status in the LBA registers.
+const SMART_STATUS: u8 = 0xda;
+/// The value of the LBA registers (mid and high) required by SMART commands.
+const SMART_LBA: [u8; 2] = [0x4f, 0xc2];
+
+/// The registers of an ATA command.
+///
+/// When returned by a drive, the command and features registers are replaced by the status and
+/// error registers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TaskFile {
+	/// The command (status on return)
+	pub command: u8,
+	/// The features (error on return)
+	pub feature: u8,
+	/// Sectors count
+	pub count: u8,
+	/// LBA, bits 0 to 23
+	pub lba: [u8; 3],
+	/// Device register
+	pub device: u8,
+}
+
+/// A drive accepting ATA commands.
+pub trait AtaDrive {
+	/// Returns the data returned by the IDENTIFY DEVICE command when the drive was detected.
+	fn identity(&self) -> &[u16; 256];
+
+	/// Executes the command `tf` and returns the registers of the drive afterward.
+	///
+	/// If `buf` is specified, the command reads a sector of data from the drive into it.
+	/// Otherwise, no data is transferred.
+	///
+	/// If the drive reports an error, the function returns [`errno::EIO`].
+	fn exec(&self, tf: &TaskFile, buf: Option<&mut [u8; SECTOR_SIZE]>) -> EResult<TaskFile>;
+}
+
+/// Returns the number of sectors read by the command `tf`.
+///
+/// If the command is not allowed to be passed through, the function returns `None`.
+fn data_sectors(tf: &TaskFile) -> Option<u8> {
+	match (tf.command, tf.feature) {
+		(ATA_CMD_IDENTIFY, _) => Some(1),
+		(ATA_CMD_CHECK_POWER_MODE, _) => Some(0),
+		(ATA_CMD_SMART, _) if tf.lba[1..] != SMART_LBA => None,
+		(ATA_CMD_SMART, SMART_READ_VALUES | SMART_READ_THRESHOLDS) => Some(1),
+		(ATA_CMD_SMART, SMART_ENABLE | SMART_DISABLE | SMART_STATUS) => Some(0),
+		_ => None,
+	}
+}
+
+/// Executes the `HDIO_DRIVE_CMD` request.
+///
+/// The argument is an array holding the command, the sector number, the features and the number
+/// of sectors to read, followed by the buffer receiving them. On return, the first three bytes
+/// are replaced with the status, error and sectors count registers.
+fn drive_cmd(drive: &dyn AtaDrive, argp: *const c_void) -> EResult<u32> {
+	// Allow to check whether the request is supported
+	if argp.is_null() {
+		return Ok(0);
+	}
+	let args_ptr = UserPtr::<[u8; 4]>::from_ptr(argp as usize);
+	let args = args_ptr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	let [command, sector, feature, sectors] = args;
+	// SMART commands take the sector number in the LBA register
+	let tf = if command == ATA_CMD_SMART {
+		TaskFile {
+			command,
+			feature,
+			count: sectors,
+			lba: [sector, SMART_LBA[0], SMART_LBA[1]],
+			device: 0,
+		}
+	} else {
+		TaskFile {
+			command,
+			feature,
+			count: sector,
+			..Default::default()
+		}
+	};
+	if data_sectors(&tf) != Some(sectors) {
+		return Err(errno!(EINVAL));
+	}
+	let mut buf = [0; SECTOR_SIZE];
+	let res = drive.exec(&tf, (sectors > 0).then_some(&mut buf))?;
+	args_ptr.copy_to_user(&[res.command, res.feature, res.count, sectors])?;
+	if sectors > 0 {
+		let data = UserSlice::from_user(
+			ptr::with_exposed_provenance_mut::<u8>(argp as usize + 4),
+			SECTOR_SIZE,
+		)?;
+		data.copy_to_user(0, &buf)?;
+	}
+	Ok(0)
+}
+
+/// Executes the `HDIO_DRIVE_TASK` request, for commands that transfer no data.
+///
+/// The argument is an array holding the command, features, sectors count, LBA (low, mid, high)
+/// and device registers. On return, it is replaced with the registers of the drive, the command
+/// and features being replaced with the status and error.
+fn drive_task(drive: &dyn AtaDrive, argp: *const c_void) -> EResult<u32> {
+	let args_ptr = UserPtr::<[u8; 7]>::from_ptr(argp as usize);
+	let args = args_ptr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	let [command, feature, count, lba_low, lba_mid, lba_high, device] = args;
+	let tf = TaskFile {
+		command,
+		feature,
+		count,
+		lba: [lba_low, lba_mid, lba_high],
+		device,
+	};
+	if data_sectors(&tf) != Some(0) {
+		return Err(errno!(EINVAL));
+	}
+	let res = drive.exec(&tf, None)?;
+	args_ptr.copy_to_user(&[
+		res.command,
+		res.feature,
+		res.count,
+		res.lba[0],
+		res.lba[1],
+		res.lba[2],
+		res.device,
+	])?;
+	Ok(0)
+}
+
+/// Performs an ioctl request on the ATA drive `drive`.
+pub fn ioctl(drive: &dyn AtaDrive, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
+	match request.get_old_format() {
+		ioctl::HDIO_GET_IDENTITY => {
+			let id_ptr = UserPtr::<[u16; 256]>::from_ptr(argp as usize);
+			id_ptr.copy_to_user(drive.identity())?;
+			Ok(0)
+		}
+		ioctl::HDIO_DRIVE_CMD | ioctl::HDIO_DRIVE_TASK => {
+			if !is_privileged() {
+				return Err(errno!(EACCES));
+			}
+			if request.get_old_format() == ioctl::HDIO_DRIVE_CMD {
+				drive_cmd(drive, argp)
+			} else {
+				drive_task(drive, argp)
+			}
+		}
+		_ => Err(errno!(ENOTTY)),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn ata_passthrough_allowed() {
+		let smart = |feature, lba_mid| TaskFile {
+			command: ATA_CMD_SMART,
+			feature,
+			lba: [0, lba_mid, SMART_LBA[1]],
+			..Default::default()
+		};
+		assert_eq!(
+			data_sectors(&smart(SMART_READ_VALUES, SMART_LBA[0])),
+			Some(1)
+		);
+		assert_eq!(data_sectors(&smart(SMART_STATUS, SMART_LBA[0])), Some(0));
+		assert_eq!(data_sectors(&smart(SMART_STATUS, 0)), None);
+		// SMART WRITE LOG
+		assert_eq!(data_sectors(&smart(0xd6, SMART_LBA[0])), None);
+		let cmd = |command| TaskFile {
+			command,
+			..Default::default()
+		};
+		assert_eq!(data_sectors(&cmd(ATA_CMD_IDENTIFY)), Some(1));
+		assert_eq!(data_sectors(&cmd(ATA_CMD_CHECK_POWER_MODE)), Some(0));
+		// WRITE SECTORS
+		assert_eq!(data_sectors(&cmd(0x30)), None);
+	}
+}
@@ -22,15 +22,12 @@
 use crate::device::{
 	BlkDev, DeviceID,
 	bar::Bar,
-	register_blk,
 	storage::{
-		PhysicalDevice, SCSI_MAJOR, STORAGE_MODE, partition::read_partitions, pata::PATAInterface,
+		PhysicalDevice, SCSI_MAJOR, STORAGE_MODE, alloc_scsi_id, pata::PATAInterface,
+		register_disk,
 	},
 };
-use core::{
-	num::NonZeroU64,
-	sync::atomic::{AtomicU32, Ordering::Relaxed},
-};
+use core::num::NonZeroU64;
 use utils::{boxed::Box, collections::path::PathBuf, errno::EResult, format};
 
 /// The beginning of the port range for the primary ATA bus (compatibility
@@ -141,8 +138,7 @@ impl Controller {
 				Channel::new_compatibility(secondary)
 			};
 			// Assign disk ID
-			static ID: AtomicU32 = AtomicU32::new(0);
-			let scsi_id = ID.fetch_add(1, Relaxed);
+			let scsi_id = alloc_scsi_id();
 			let Some(interface) = PATAInterface::new(scsi_id, channel, slave) else {
 				continue;
 			};
@@ -162,8 +158,7 @@ impl Controller {
 				interface.sectors_count,
 				Box::new(interface)?,
 			)?;
-			register_blk(dev.clone())?;
-			read_partitions(&dev)?;
+			register_disk(dev)?;
 		}
 		Ok(ctrlr)
 	}
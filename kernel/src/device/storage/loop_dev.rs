@@ -0,0 +1,472 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Loop devices (`/dev/loopN`) expose a file as a block device, allowing to mount filesystem
+//! images.
+//!
+//! Devices are allocated through `/dev/loop-control`, then attached to a file with the
+//! `LOOP_SET_FD` ioctl on the device itself.
+//!
+//! Pages of a loop device are the pages of the backing file's page cache, so that the data is
+//! written back by the filesystem containing the file.
+
+use super::STORAGE_MODE;
+use crate::{
+	device::{
+		BLK_DEVICES, BlkDev, BlockDeviceOps, CharDev, DeviceID, register_blk, register_char,
+	},
+	file::{File, FileType, fd::fd_to_file, fs::FileOps, vfs, vfs::mountpoint::FILESYSTEMS},
+	memory::{cache::RcPage, user::UserPtr},
+	sync::{mutex::Mutex, spin::Spin},
+	syscall::{FromSyscallArg, ioctl},
+};
+use core::{
+	cmp::min,
+	ffi::{c_int, c_void},
+	num::NonZeroU64,
+	ptr,
+};
+use utils::{
+	boxed::Box,
+	collections::{btreemap::BTreeMap, path::PathBuf},
+	errno,
+	errno::{AllocResult, EResult},
+	format,
+	limits::PAGE_SIZE,
+	ptr::arc::Arc,
+};
+
+/// Major number of loop devices.
+const LOOP_MAJOR: u32 = 7;
+/// Major number of miscellaneous character devices.
+const MISC_MAJOR: u32 = 10;
+/// Minor number of `/dev/loop-control`.
+const LOOP_CTRL_MINOR: u32 = 237;
+/// The number of loop devices created at boot.
+const DEFAULT_COUNT: u32 = 8;
+/// The maximum number of loop devices, limited by the range of minor numbers.
+const MAX_COUNT: u32 = 1 << 20;
+
+/// The size of a sector of a loop device, in bytes.
+const SECTOR_SIZE: u64 = 512;
+/// The size of the name of the backing file, including the terminating null byte.
+const LO_NAME_SIZE: usize = 64;
+
+/// Loop device flag: the device is read-only.
+const LO_FLAGS_READ_ONLY: u32 = 1;
+/// Loop device flag: the device is detached when its last user closes it.
+const LO_FLAGS_AUTOCLEAR: u32 = 4;
+/// Loop device flag: the partitions of the device are scanned.
+const LO_FLAGS_PARTSCAN: u32 = 8;
+/// Loop device flag: the backing file is accessed with direct I/O.
+const LO_FLAGS_DIRECT_IO: u32 = 16;
+/// Loop device flags that can be changed with `LOOP_SET_STATUS64`.
+const LO_FLAGS_SETTABLE: u32 = LO_FLAGS_AUTOCLEAR | LO_FLAGS_PARTSCAN | LO_FLAGS_DIRECT_IO;
+
+/// Status of a loop device, as passed to `LOOP_GET_STATUS64` and `LOOP_SET_STATUS64`.
+#[repr(C)]
+#[derive(Debug)]
+struct LoopInfo64 {
+	/// The device containing the backing file
+	lo_device: u64,
+	/// The inode of the backing file
+	lo_inode: u64,
+	/// The device number of the backing file, if it is a device
+	lo_rdevice: u64,
+	/// The offset of the data in the backing file, in bytes
+	lo_offset: u64,
+	/// The maximum size of the data, in bytes. If zero, the data extends to the end of the file
+	lo_sizelimit: u64,
+	/// The index of the loop device
+	lo_number: u32,
+	/// Obsolete
+	lo_encrypt_type: u32,
+	/// Obsolete
+	lo_encrypt_key_size: u32,
+	/// `LO_FLAGS_*` flags
+	lo_flags: u32,
+	/// The name of the backing file
+	lo_file_name: [u8; LO_NAME_SIZE],
+	/// Obsolete
+	lo_crypt_name: [u8; LO_NAME_SIZE],
+	/// Obsolete
+	lo_encrypt_key: [u8; 32],
+	/// Obsolete
+	lo_init: [u64; 2],
+}
+
+/// The file a loop device is attached to.
+#[derive(Debug)]
+struct Backing {
+	/// The backing file
+	file: Arc<File>,
+	/// The offset of the data in the file, in bytes
+	offset: u64,
+	/// The maximum size of the data, in bytes. If zero, the data extends to the end of the file
+	sizelimit: u64,
+	/// `LO_FLAGS_*` flags
+	flags: u32,
+	/// The name of the backing file, as reported to userspace
+	name: [u8; LO_NAME_SIZE],
+}
+
+impl Backing {
+	/// Returns the size of the device, in bytes.
+	fn size(&self) -> u64 {
+		let size = self.file.stat().size.saturating_sub(self.offset);
+		if self.sizelimit != 0 {
+			min(size, self.sizelimit)
+		} else {
+			size
+		}
+	}
+}
+
+/// Tells whether the range of `size` bytes at `offset` in the regular file `file` contains holes.
+///
+/// Holes are not allocated when written through the page cache, so data written to them through
+/// a loop device would be lost. Filesystems which do not map blocks to a device have no holes.
+fn has_holes(file: &File, offset: u64, size: u64) -> EResult<bool> {
+	let node = file.node();
+	let blk_size = node.fs.ops.get_stat()?.block_size() as u64;
+	let start = offset / blk_size;
+	let end = offset.saturating_add(size).div_ceil(blk_size);
+	for blk in start..end {
+		match node.node_ops.bmap(node, blk) {
+			Ok(Some(_)) => {}
+			Ok(None) => return Ok(true),
+			Err(e) if e.as_int() == errno::EINVAL => return Ok(false),
+			Err(e) => return Err(e),
+		}
+	}
+	Ok(false)
+}
+
+/// A loop device.
+#[derive(Debug, Default)]
+struct LoopDevice {
+	/// The file the device is attached to, if any
+	backing: Spin<Option<Backing>>,
+}
+
+impl LoopDevice {
+	/// Returns the backing file along with the offset of the data and the size of the device, in
+	/// pages.
+	///
+	/// If the device is not attached to a file, the function returns [`errno::ENXIO`].
+	fn backing_pages(&self) -> EResult<(Arc<File>, u64, u64)> {
+		let backing = self.backing.lock();
+		let backing = backing.as_ref().ok_or_else(|| errno!(ENXIO))?;
+		Ok((
+			backing.file.clone(),
+			backing.offset / PAGE_SIZE as u64,
+			backing.size().div_ceil(PAGE_SIZE as u64),
+		))
+	}
+
+	/// Attaches the device to the file `file`.
+	///
+	/// If the device is writable and `file` is sparse, the function returns [`errno::EINVAL`].
+	fn set_fd(&self, file: Arc<File>) -> EResult<()> {
+		if file.get_type()? != FileType::Regular {
+			return Err(errno!(EINVAL));
+		}
+		if file.can_write() && has_holes(&file, 0, file.stat().size)? {
+			return Err(errno!(EINVAL));
+		}
+		let mut name = [0; LO_NAME_SIZE];
+		let path = vfs::Entry::get_path(&file.vfs_entry)?;
+		let len = min(path.len(), LO_NAME_SIZE - 1);
+		name[..len].copy_from_slice(&path.as_bytes()[..len]);
+		let flags = if file.can_write() {
+			0
+		} else {
+			LO_FLAGS_READ_ONLY
+		};
+		let mut backing = self.backing.lock();
+		if backing.is_some() {
+			return Err(errno!(EBUSY));
+		}
+		*backing = Some(Backing {
+			file,
+			offset: 0,
+			sizelimit: 0,
+			flags,
+			name,
+		});
+		Ok(())
+	}
+
+	/// Detaches the device from its file.
+	fn clr_fd(&self, dev: &BlkDev) -> EResult<()> {
+		// TODO defer the detachment until the device is closed, as with `LO_FLAGS_AUTOCLEAR`
+		if FILESYSTEMS.lock().contains_key(&dev.id) {
+			return Err(errno!(EBUSY));
+		}
+		let backing = self.backing.lock().take().ok_or_else(|| errno!(ENXIO))?;
+		if let Some(file) = Arc::into_inner(backing.file) {
+			file.close()?;
+		}
+		Ok(())
+	}
+
+	/// Returns the status of the device with ID `id`.
+	fn get_status(&self, id: &DeviceID) -> EResult<LoopInfo64> {
+		let backing = self.backing.lock();
+		let backing = backing.as_ref().ok_or_else(|| errno!(ENXIO))?;
+		let node = backing.file.node();
+		Ok(LoopInfo64 {
+			lo_device: node.fs.dev,
+			lo_inode: node.inode,
+			lo_rdevice: 0,
+			lo_offset: backing.offset,
+			lo_sizelimit: backing.sizelimit,
+			lo_number: id.minor,
+			lo_encrypt_type: 0,
+			lo_encrypt_key_size: 0,
+			lo_flags: backing.flags,
+			lo_file_name: backing.name,
+			lo_crypt_name: [0; LO_NAME_SIZE],
+			lo_encrypt_key: [0; 32],
+			lo_init: [0; 2],
+		})
+	}
+
+	/// Sets the status of the device.
+	///
+	/// If the device is writable and the new range of the backing file contains holes, the
+	/// function returns [`errno::EINVAL`].
+	fn set_status(&self, info: &LoopInfo64) -> EResult<()> {
+		// Pages of the device are the pages of the backing file
+		if !info.lo_offset.is_multiple_of(PAGE_SIZE as u64) || info.lo_encrypt_type != 0 {
+			return Err(errno!(EINVAL));
+		}
+		// Checking for holes may sleep, so it cannot be done while holding the lock
+		let (file, flags) = {
+			let backing = self.backing.lock();
+			let backing = backing.as_ref().ok_or_else(|| errno!(ENXIO))?;
+			(backing.file.clone(), backing.flags)
+		};
+		if flags & LO_FLAGS_READ_ONLY == 0 {
+			let size = file.stat().size.saturating_sub(info.lo_offset);
+			let size = if info.lo_sizelimit != 0 {
+				min(size, info.lo_sizelimit)
+			} else {
+				size
+			};
+			if has_holes(&file, info.lo_offset, size)? {
+				return Err(errno!(EINVAL));
+			}
+		}
+		let mut backing = self.backing.lock();
+		let backing = backing.as_mut().ok_or_else(|| errno!(ENXIO))?;
+		// The device has been attached to another file in the meantime
+		if !ptr::eq(Arc::as_ptr(&backing.file), Arc::as_ptr(&file)) {
+			return Err(errno!(EBUSY));
+		}
+		backing.offset = info.lo_offset;
+		backing.sizelimit = info.lo_sizelimit;
+		// TODO support `LO_FLAGS_AUTOCLEAR` and `LO_FLAGS_PARTSCAN`
+		backing.flags = (backing.flags & !LO_FLAGS_SETTABLE) | (info.lo_flags & LO_FLAGS_SETTABLE);
+		backing.name = info.lo_file_name;
+		backing.name[LO_NAME_SIZE - 1] = 0;
+		Ok(())
+	}
+}
+
+/// Block device operations of a loop device.
+///
+/// Since the size of the device changes when it is attached to a file, it is given by
+/// `BLKGETSIZE64` rather than [`BlkDev::blk_count`].
+#[derive(Debug)]
+struct LoopOps(Arc<LoopDevice>);
+
+impl BlockDeviceOps for LoopOps {
+	fn new_partition(&self, _dev: &BlkDev, _id: u32) -> AllocResult<(DeviceID, PathBuf)> {
+		panic!("trying to create a partition of a loop device");
+	}
+
+	fn is_read_only(&self) -> bool {
+		self.0
+			.backing
+			.lock()
+			.as_ref()
+			.is_some_and(|backing| backing.flags & LO_FLAGS_READ_ONLY != 0)
+	}
+
+	fn read_page(&self, _dev: &Arc<BlkDev>, off: u64) -> EResult<RcPage> {
+		let (file, start, size) = self.0.backing_pages()?;
+		if off >= size {
+			return Err(errno!(EINVAL));
+		}
+		let node = file.node();
+		// Writable devices are not attached to ranges containing holes, so written pages are
+		// backed by blocks of the file
+		node.node_ops.read_page(node, start + off)
+	}
+
+	fn writeback(&self, _dev: &BlkDev, _off: u64, _page: &RcPage) -> EResult<()> {
+		// Pages belong to the backing file, whose filesystem writes them back
+		Ok(())
+	}
+
+	fn ioctl(&self, dev: &BlkDev, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
+		match request.get_old_format() {
+			ioctl::LOOP_SET_FD => {
+				let file = fd_to_file(argp as usize as c_int)?;
+				self.0.set_fd(file)?;
+				Ok(0)
+			}
+			ioctl::LOOP_CLR_FD => {
+				self.0.clr_fd(dev)?;
+				Ok(0)
+			}
+			ioctl::LOOP_GET_STATUS64 => {
+				let info = self.0.get_status(&dev.id)?;
+				UserPtr::<LoopInfo64>::from_ptr(argp as usize).copy_to_user(&info)?;
+				Ok(0)
+			}
+			ioctl::LOOP_SET_STATUS64 => {
+				let info = UserPtr::<LoopInfo64>::from_ptr(argp as usize)
+					.copy_from_user()?
+					.ok_or_else(|| errno!(EFAULT))?;
+				self.0.set_status(&info)?;
+				Ok(0)
+			}
+			ioctl::BLKGETSIZE64 => {
+				let size = self
+					.0
+					.backing
+					.lock()
+					.as_ref()
+					.map(Backing::size)
+					.unwrap_or(0);
+				let size_ptr = UserPtr::<u64>::from_ptr(argp as usize);
+				size_ptr.copy_to_user(&size)?;
+				Ok(0)
+			}
+			_ => Err(errno!(ENOTTY)),
+		}
+	}
+}
+
+/// The list of loop devices, by index.
+static DEVICES: Mutex<BTreeMap<u32, Arc<LoopDevice>>, false> = Mutex::new(BTreeMap::new());
+
+/// Creates the loop device with index `index`.
+///
+/// If the device already exists, the function returns [`errno::EEXIST`].
+fn add(devices: &mut BTreeMap<u32, Arc<LoopDevice>>, index: u32) -> EResult<()> {
+	if index >= MAX_COUNT {
+		return Err(errno!(EINVAL));
+	}
+	if devices.contains_key(&index) {
+		return Err(errno!(EEXIST));
+	}
+	let loop_dev = Arc::new(LoopDevice::default())?;
+	let dev = BlkDev::new(
+		DeviceID {
+			major: LOOP_MAJOR,
+			minor: index,
+		},
+		PathBuf::try_from(format!("/dev/loop{index}")?)?,
+		STORAGE_MODE,
+		NonZeroU64::new(SECTOR_SIZE).unwrap(),
+		0,
+		Box::new(LoopOps(loop_dev.clone()))?,
+	)?;
+	devices.insert(index, loop_dev)?;
+	register_blk(dev)?;
+	Ok(())
+}
+
+/// Removes the loop device with index `index`.
+///
+/// If the device is attached to a file, the function returns [`errno::EBUSY`].
+fn remove(devices: &mut BTreeMap<u32, Arc<LoopDevice>>, index: u32) -> EResult<()> {
+	let loop_dev = devices.get(&index).ok_or_else(|| errno!(ENODEV))?;
+	if loop_dev.backing.lock().is_some() {
+		return Err(errno!(EBUSY));
+	}
+	devices.remove(&index);
+	BLK_DEVICES.lock().remove(&DeviceID {
+		major: LOOP_MAJOR,
+		minor: index,
+	});
+	Ok(())
+}
+
+/// Returns the lowest index that is not used by a loop device.
+fn free_index(devices: &BTreeMap<u32, Arc<LoopDevice>>) -> u32 {
+	(0..).find(|i| !devices.contains_key(i)).unwrap()
+}
+
+/// The `/dev/loop-control` device, allowing to allocate loop devices.
+#[derive(Debug)]
+struct LoopControl;
+
+impl FileOps for LoopControl {
+	fn ioctl(&self, _file: &File, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
+		let mut devices = DEVICES.lock();
+		// The argument is an integer
+		let arg = argp as usize as c_int;
+		match request.get_old_format() {
+			ioctl::LOOP_CTL_ADD => {
+				let index = u32::try_from(arg).unwrap_or_else(|_| free_index(&devices));
+				add(&mut devices, index)?;
+				Ok(index)
+			}
+			ioctl::LOOP_CTL_REMOVE => {
+				let index = u32::try_from(arg).map_err(|_| errno!(EINVAL))?;
+				remove(&mut devices, index)?;
+				Ok(index)
+			}
+			ioctl::LOOP_CTL_GET_FREE => {
+				let free = devices
+					.iter()
+					.find(|(_, loop_dev)| loop_dev.backing.lock().is_none())
+					.map(|(index, _)| *index);
+				if let Some(index) = free {
+					return Ok(index);
+				}
+				let index = free_index(&devices);
+				add(&mut devices, index)?;
+				Ok(index)
+			}
+			_ => Err(errno!(ENOTTY)),
+		}
+	}
+}
+
+/// Registers `/dev/loop-control` and the default loop devices.
+pub(super) fn init() -> EResult<()> {
+	register_char(CharDev::new(
+		DeviceID {
+			major: MISC_MAJOR,
+			minor: LOOP_CTRL_MINOR,
+		},
+		PathBuf::try_from(b"/dev/loop-control")?,
+		STORAGE_MODE,
+		LoopControl,
+	)?)?;
+	let mut devices = DEVICES.lock();
+	for index in 0..DEFAULT_COUNT {
+		add(&mut devices, index)?;
+	}
+	Ok(())
+}
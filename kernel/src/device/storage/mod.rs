@@ -18,32 +18,45 @@
 
 //! Storage management implementation.
 
+mod ahci;
+mod ata;
 mod ide;
+mod loop_dev;
 mod nvme;
 pub mod partition;
 mod pata;
+pub mod ramdisk;
+mod usb;
 
 use crate::{
 	device::{
 		BlkDev, BlockDeviceOps, DeviceID, DeviceType,
-		bus::pci,
-		id::MajorBlock,
+		bus::{
+			pci,
+			pci::{DeviceId, PciDev},
+		},
+		id::{BLOCK_EXTENDED_MAJOR, BLOCK_EXTENDED_MAJOR_HANDLE, MajorBlock},
 		manager::{DeviceManager, PhysicalDevice},
+		register_blk,
 		storage::partition::read_partitions,
 	},
 	file::Mode,
 	memory::{cache::RcPage, user::UserPtr},
+	sync::spin::Spin,
 	syscall::{FromSyscallArg, ioctl},
 };
 use core::{
 	ffi::{c_uchar, c_ulong, c_ushort, c_void},
 	hint::likely,
+	sync::atomic::{AtomicU32, Ordering::Relaxed},
 };
 use partition::Partition;
 use utils::{
 	collections::{path::PathBuf, vec::Vec},
 	errno,
-	errno::{AllocResult, ENOMEM, EResult},
+	errno::{AllocResult, EResult},
+	format,
+	limits::PAGE_SIZE,
 	ptr::arc::Arc,
 };
 
@@ -53,6 +66,31 @@ pub const STORAGE_MODE: Mode = 0o660;
 /// Major number for SCSI devices
 pub const SCSI_MAJOR: u32 = 8;
 
+/// Allocates an ID for a new SCSI disk, used to name its device file `/dev/sdX`.
+fn alloc_scsi_id() -> u32 {
+	static ID: AtomicU32 = AtomicU32::new(0);
+	ID.fetch_add(1, Relaxed)
+}
+
+/// Returns the device ID and path of the partition `id` of the SCSI disk `scsi_id`.
+fn scsi_partition(scsi_id: u32, id: u32) -> AllocResult<(DeviceID, PathBuf)> {
+	let device_id = if id < 16 {
+		DeviceID {
+			major: SCSI_MAJOR,
+			minor: scsi_id * 16 + id,
+		}
+	} else {
+		DeviceID {
+			major: BLOCK_EXTENDED_MAJOR,
+			minor: BLOCK_EXTENDED_MAJOR_HANDLE.lock().alloc_minor(None)?,
+		}
+	};
+	// TODO Handle if out of the alphabet
+	let letter = (b'a' + scsi_id as u8) as char;
+	let path = PathBuf::new_unchecked(format!("/dev/sd{letter}{id}")?);
+	Ok((device_id, path))
+}
+
 /// Hard drive geometry.
 #[derive(Debug)]
 #[repr(C)]
@@ -76,32 +114,45 @@ pub struct PartitionOps {
 	pub partition: Partition,
 }
 
+impl PartitionOps {
+	/// Translates the offset `off` of a page in the partition to its offset on the device.
+	///
+	/// If the page is outside the partition, the function returns [`errno::EINVAL`].
+	fn page_offset(&self, off: u64) -> EResult<u64> {
+		let pages = self.partition.pages(self.dev.blk_size.get());
+		if likely(off < pages.end - pages.start) {
+			Ok(pages.start + off)
+		} else {
+			Err(errno!(EINVAL))
+		}
+	}
+}
+
 impl BlockDeviceOps for PartitionOps {
 	fn new_partition(&self, _dev: &BlkDev, _id: u32) -> AllocResult<(DeviceID, PathBuf)> {
 		panic!("trying to create a partition of a partition");
 	}
 
+	fn partition(&self) -> Option<&Partition> {
+		Some(&self.partition)
+	}
+
 	fn read_page(&self, _dev: &Arc<BlkDev>, off: u64) -> EResult<RcPage> {
-		if likely(off < self.partition.size) {
-			self.dev
-				.ops
-				.read_page(&self.dev, self.partition.offset + off)
-		} else {
-			Err(errno!(EINVAL))
-		}
+		let off = self.page_offset(off)?;
+		self.dev.ops.read_page(&self.dev, off)
 	}
 
 	fn writeback(&self, _dev: &BlkDev, off: u64, blk: &RcPage) -> EResult<()> {
-		if likely(off < self.partition.size) {
-			self.dev
-				.ops
-				.writeback(&self.dev, self.partition.offset + off, blk)
-		} else {
-			Err(errno!(EINVAL))
-		}
+		let off = self.page_offset(off)?;
+		self.dev.ops.writeback(&self.dev, off, blk)
+	}
+
+	fn dump_page(&self, _dev: &BlkDev, off: u64, buf: &[u8; PAGE_SIZE]) -> EResult<()> {
+		let off = self.page_offset(off)?;
+		self.dev.ops.dump_page(&self.dev, off, buf)
 	}
 
-	fn ioctl(&self, dev: &BlkDev, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
+	fn ioctl(&self, _dev: &BlkDev, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
 		match request.get_old_format() {
 			ioctl::HDIO_GETGEO => {
 				// Translate from LBA to CHS
@@ -123,75 +174,109 @@ impl BlockDeviceOps for PartitionOps {
 				read_partitions(&self.dev)?;
 				Ok(0)
 			}
-			ioctl::BLKSSZGET => {
-				let blk_size = dev.blk_size.get();
-				let size_ptr = UserPtr::<u32>::from_ptr(argp as usize);
-				size_ptr.copy_to_user(&(blk_size as _))?;
-				Ok(0)
-			}
-			ioctl::BLKGETSIZE64 => {
-				let size = dev.blk_size.get() * self.partition.size;
-				let size_ptr = UserPtr::<u64>::from_ptr(argp as usize);
-				size_ptr.copy_to_user(&size)?;
-				Ok(0)
-			}
-			_ => Err(errno!(ENOTTY)),
+			// Handled on the partition's own bounds by the caller
+			ioctl::BLKSSZGET | ioctl::BLKGETSIZE64 => Err(errno!(ENOTTY)),
+			// Requests on the drive
+			_ => self.dev.ops.ioctl(&self.dev, request, argp),
 		}
 	}
 }
 
 enum Controller {
+	Ahci(ahci::Controller),
 	Ide(ide::Controller),
 	Nvme(nvme::Controller),
 }
 
+/// The list of detected controllers
+static CONTROLLERS: Spin<Vec<Controller>> = Spin::new(Vec::new());
+
+/// PCI driver for a type of storage controller.
+struct ControllerDriver {
+	/// The name of the driver
+	name: &'static str,
+	/// The devices handled by the driver
+	ids: &'static [DeviceId],
+	/// Initializes the controller
+	init: fn(&PciDev) -> EResult<Controller>,
+}
+
+impl pci::Driver for ControllerDriver {
+	fn name(&self) -> &'static str {
+		self.name
+	}
+
+	fn id_table(&self) -> &'static [DeviceId] {
+		self.ids
+	}
+
+	fn probe(&self, dev: &PciDev) -> EResult<()> {
+		let ctrlr = (self.init)(dev)?;
+		CONTROLLERS.lock().push(ctrlr)?;
+		Ok(())
+	}
+}
+
+/// Drivers for storage controllers.
+static DRIVERS: [ControllerDriver; 3] = [
+	ControllerDriver {
+		name: "ide",
+		ids: &[DeviceId::class(
+			pci::CLASS_MASS_STORAGE_CONTROLLER,
+			0x01,
+			None,
+		)],
+		init: |dev| Ok(Controller::Ide(ide::Controller::new(dev)?)),
+	},
+	ControllerDriver {
+		name: "ahci",
+		ids: &[DeviceId::class(
+			pci::CLASS_MASS_STORAGE_CONTROLLER,
+			0x06,
+			Some(0x01),
+		)],
+		init: |dev| Ok(Controller::Ahci(ahci::Controller::new(dev)?)),
+	},
+	ControllerDriver {
+		name: "nvme",
+		ids: &[DeviceId::class(
+			pci::CLASS_MASS_STORAGE_CONTROLLER,
+			0x08,
+			Some(0x02),
+		)],
+		init: |dev| Ok(Controller::Nvme(nvme::Controller::new(dev)?)),
+	},
+];
+
+/// Registers the storage device `dev`, then creates devices for the partitions it contains.
+pub fn register_disk(dev: Arc<BlkDev>) -> EResult<()> {
+	register_blk(dev.clone())?;
+	read_partitions(&dev)
+}
+
 /// Manages storage controllers, devices and their partitions.
 pub struct StorageManager {
 	/// Allocated device major number for NVMe controllers
 	nvme_ctrlr_major: MajorBlock,
-	/// The list of detected controllers
-	controllers: Vec<Controller>,
 }
 
 impl StorageManager {
 	/// Creates a new instance.
 	pub fn new() -> EResult<Self> {
+		usb::register()?;
+		loop_dev::init()?;
+		for drv in &DRIVERS {
+			pci::register_driver(drv)?;
+		}
 		Ok(Self {
 			nvme_ctrlr_major: MajorBlock::new_dyn(DeviceType::Char)?,
-			controllers: Vec::new(),
 		})
 	}
 }
 
 impl DeviceManager for StorageManager {
-	fn on_plug(&mut self, dev: &dyn PhysicalDevice) -> EResult<()> {
-		// Ignore non-storage devices
-		if dev.get_class() != pci::CLASS_MASS_STORAGE_CONTROLLER {
-			return Ok(());
-		}
-		match (dev.get_subclass(), dev.get_prog_if()) {
-			// IDE
-			(0x01, _) => {
-				let ctrlr = ide::Controller::new(dev);
-				let ctrlr = match ctrlr {
-					Ok(n) => n,
-					Err(e) if e.as_int() == ENOMEM => return Err(e),
-					Err(_) => return Ok(()),
-				};
-				self.controllers.push(Controller::Ide(ctrlr))?;
-			}
-			// NVMe
-			(0x08, 0x02) => {
-				let ctrlr = nvme::Controller::new(dev);
-				let ctrlr = match ctrlr {
-					Ok(n) => n,
-					Err(e) if e.as_int() == ENOMEM => return Err(e),
-					Err(_) => return Ok(()),
-				};
-				self.controllers.push(Controller::Nvme(ctrlr))?;
-			}
-			_ => {}
-		}
+	fn on_plug(&mut self, _dev: &dyn PhysicalDevice) -> EResult<()> {
+		// Controllers are detected by their PCI drivers
 		Ok(())
 	}
 
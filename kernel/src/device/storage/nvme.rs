@@ -27,15 +27,21 @@ use crate::{
 		bar::Bar,
 		bus::pci::PciDev,
 		id::{BLOCK_EXTENDED_MAJOR, BLOCK_EXTENDED_MAJOR_HANDLE},
+		io_stats::IoDir,
 		manager::PhysicalDevice,
-		register_blk,
-		storage::{STORAGE_MODE, partition::read_partitions},
+		storage::{STORAGE_MODE, register_disk},
 	},
 	int,
 	int::CallbackHandle,
 	memory::{VirtAddr, buddy, cache::RcPage},
 	println, process,
-	process::{Process, State, scheduler::schedule},
+	process::{
+		Process, State,
+		scheduler::{
+			cpu::{CPU, per_cpu},
+			schedule,
+		},
+	},
 	sync::{rwlock::RwLock, semaphore::Semaphore, spin::Spin},
 };
 use core::{
@@ -89,6 +95,11 @@ const ADMIN_CMD_CREATE_IO_SQ: u32 = 0x1;
 const ADMIN_CMD_CREATE_IO_CQ: u32 = 0x5;
 /// Admin command opcode: Identify
 const ADMIN_CMD_IDENTIFY: u32 = 0x6;
+/// Admin command opcode: Set Features
+const ADMIN_CMD_SET_FEATURES: u32 = 0x9;
+
+/// Feature identifier: Number of Queues
+const FEATURE_NUMBER_OF_QUEUES: u32 = 0x7;
 
 /// Command opcode: Write
 const CMD_WRITE: u32 = 0x1;
@@ -524,9 +535,11 @@ impl BlockDeviceOps for NamespaceOps {
 		}
 		dev.mapped.get_or_insert_page(off, || {
 			let blk = BlkDev::new_page(dev, off)?;
-			let qp = &self.ctrlr.queues.read()[0];
+			// Wait for our turn on the device
+			let _io = dev.start_io(IoDir::Read, off)?;
+			let queues = self.ctrlr.queues.read();
 			let cqe = self.ctrlr.submit_cmd_sync(
-				qp,
+				self.ctrlr.io_queue(&queues),
 				SubmissionQueueEntry {
 					cdw0: CMD_READ,
 					nsid: self.nsid,
@@ -537,7 +550,11 @@ impl BlockDeviceOps for NamespaceOps {
 				},
 			);
 			if unlikely(cqe.status() != 0) {
-				// TODO print log?
+				println!(
+					"nvme: read error on namespace {nsid} at LBA {lba} (status: {})",
+					cqe.status(),
+					nsid = self.nsid
+				);
 				return Err(errno!(EIO));
 			}
 			Ok(blk)
@@ -552,9 +569,11 @@ impl BlockDeviceOps for NamespaceOps {
 		if unlikely(end_lba > dev.blk_count) {
 			return Err(errno!(EOVERFLOW));
 		}
-		let qp = &self.ctrlr.queues.read()[0];
+		// Wait for our turn on the device
+		let _io = dev.start_io(IoDir::Write, off)?;
+		let queues = self.ctrlr.queues.read();
 		let cqe = self.ctrlr.submit_cmd_sync(
-			qp,
+			self.ctrlr.io_queue(&queues),
 			SubmissionQueueEntry {
 				cdw0: CMD_WRITE,
 				nsid: self.nsid,
@@ -565,7 +584,11 @@ impl BlockDeviceOps for NamespaceOps {
 			},
 		);
 		if unlikely(cqe.status() != 0) {
-			// TODO print log?
+			println!(
+				"nvme: write error on namespace {nsid} at LBA {lba} (status: {})",
+				cqe.status(),
+				nsid = self.nsid
+			);
 			return Err(errno!(EIO));
 		}
 		Ok(())
@@ -696,10 +719,53 @@ struct ControllerInner {
 
 	admin_qp: QueuePair,
 	/// I/O queues list
+	///
+	/// The queue at index `i` has the ID `i + 1`.
 	queues: RwLock<Vec<QueuePair>>,
 }
 
 impl ControllerInner {
+	/// Returns the I/O queue pair to be used by the current CPU core.
+	///
+	/// Each core is assigned a queue pair to limit contention. If there are less queue pairs than
+	/// cores, several cores share the same queue pair.
+	#[inline]
+	fn io_queue<'q>(&self, queues: &'q [QueuePair]) -> &'q QueuePair {
+		&queues[per_cpu().cpu_id as usize % queues.len()]
+	}
+
+	/// Requests `count` I/O queue pairs to the controller.
+	///
+	/// On success, the function returns the number of queue pairs that can be created, which may
+	/// be lower than `count`.
+	fn set_queue_count(&self, count: u16) -> EResult<u16> {
+		let n = count as u32 - 1;
+		let cqe = self.submit_cmd_sync(
+			&self.admin_qp,
+			SubmissionQueueEntry {
+				cdw0: ADMIN_CMD_SET_FEATURES,
+				nsid: 0,
+				cdw12: [0; 2],
+				mptr: [0; 2],
+				dptr: [0; 2],
+				cdw: [FEATURE_NUMBER_OF_QUEUES, n | (n << 16), 0, 0, 0, 0],
+			},
+		);
+		if unlikely(cqe.status() != 0) {
+			println!(
+				"nvme: failed to set the number of queues (status: {})",
+				cqe.status()
+			);
+			return Err(errno!(EIO));
+		}
+		// Values are zero-based
+		let nsqa = (cqe.cdw01[0] & 0xffff) as u16;
+		let ncqa = (cqe.cdw01[0] >> 16) as u16;
+		Ok(count
+			.min(nsqa.saturating_add(1))
+			.min(ncqa.saturating_add(1)))
+	}
+
 	fn init_ns(this: &Arc<Self>, nsid: u32) -> EResult<()> {
 		// Build device path
 		let path =
@@ -745,14 +811,13 @@ impl ControllerInner {
 			path,
 			STORAGE_MODE,
 			NonZeroU64::new(blk_size).unwrap(),
-			ns_id.ncap,
+			ns_id.nsze,
 			Box::new(NamespaceOps {
 				ctrlr: this.clone(),
 				nsid,
 			})?,
 		)?;
-		register_blk(blkdev.clone())?;
-		read_partitions(&blkdev)?;
+		register_disk(blkdev)?;
 		Ok(())
 	}
 
@@ -903,7 +968,8 @@ pub struct Controller {
 	inner: Arc<ControllerInner>,
 
 	admin_int: CallbackHandle,
-	io_int: CallbackHandle,
+	/// Interrupt handlers for each I/O queue pair
+	io_int: Vec<CallbackHandle>,
 }
 
 impl Controller {
@@ -945,32 +1011,26 @@ impl Controller {
 			admin_qp: QueuePair::new(0)?,
 			queues: RwLock::new(Vec::new()),
 		})?;
-		let (admin_int, io_int) = unsafe {
+		let admin_int = unsafe {
 			let inner_ = inner.clone();
-			let admin_int = int::alloc_callback(move |_, _, _, _| {
+			int::alloc_callback(move |_, _, _, _| {
 				handle_int(&inner_, &inner_.admin_qp);
-			})?;
-			let inner_ = inner.clone();
-			let io_int = int::alloc_callback(move |_, _, _, _| {
-				let queues = inner_.queues.read();
-				handle_int(&inner_, &queues[0]);
-			})?;
-			(admin_int, io_int)
+			})?
 		};
 		// Setup MSI
-		let msi_x = dev.enable_msi_x();
-		if let Some(msi_x) = msi_x {
-			msi_x
-				.set(0, core_id() as _, true, false, admin_int.id())
-				.inspect_err(|_| println!("nvme: failed to initialize MSI-x"))?;
-			msi_x
-				.set(1, core_id() as _, true, false, io_int.id())
-				.inspect_err(|_| println!("nvme: failed to initialize MSI-x"))?;
-			println!("nvme: using MSI-X");
-		} else {
+		let Some(msi_x) = dev.enable_msi_x() else {
 			println!("nvme: no MSI-X, driver does not support MSI");
 			return Err(errno!(EINVAL));
+		};
+		// The first vector is used by the admin queue, the others by I/O queues
+		if unlikely(msi_x.entries() < 2) {
+			println!("nvme: not enough MSI-X vectors");
+			return Err(errno!(EINVAL));
 		}
+		msi_x
+			.set(0, core_id() as _, true, false, admin_int.id())
+			.inspect_err(|_| println!("nvme: failed to initialize MSI-x"))?;
+		println!("nvme: using MSI-X");
 		// Disable controller
 		unsafe {
 			inner
@@ -1080,12 +1140,33 @@ impl Controller {
 		}
 		let dev_path = PathBuf::new_unchecked(format!("/dev/nvme{}", inner.id)?);
 		println!("nvme: detected controller ({dev_path})");
+		// Create one I/O queue pair per CPU core, within the limits of the controller and of the
+		// available interrupt vectors
+		let count = CPU.len().min(msi_x.entries() as usize - 1) as u16;
+		let count = inner.set_queue_count(count)?;
+		let mut io_int = Vec::with_capacity(count as usize)?;
+		for id in 1..=count {
+			let int = unsafe {
+				let inner_ = inner.clone();
+				int::alloc_callback(move |_, _, _, _| {
+					let queues = inner_.queues.read();
+					if let Some(qp) = queues.get(id as usize - 1) {
+						handle_int(&inner_, qp);
+					}
+				})?
+			};
+			msi_x
+				.set(id, core_id() as _, true, false, int.id())
+				.inspect_err(|_| println!("nvme: failed to initialize MSI-x"))?;
+			io_int.push(int)?;
+			inner.init_io_queue(id, id)?;
+		}
+		println!("nvme: using {count} I/O queue(s)");
 		let controller = Self {
 			inner,
 			admin_int,
 			io_int,
 		};
-		controller.inner.init_io_queue(1, 1)?;
 		let ns_ids = unsafe { ns_ids.assume_init() };
 		for &i in ns_ids.iter() {
 			if i == 0 {
@@ -19,7 +19,7 @@
 //! The GUID Partition Table (GPT) is a standard partitions table format. It is
 //! a successor of MBR.
 
-use super::{Partition, Table};
+use super::{Partition, Table, read_sector};
 use crate::device::BlkDev;
 use core::{hint::unlikely, mem::size_of};
 use macros::AnyRepr;
@@ -57,7 +57,7 @@ fn translate_lba(lba: i64, storage_size: u64) -> Option<u64> {
 			None
 		}
 	} else {
-		if (lba as u64) <= storage_size {
+		if (lba as u64) < storage_size {
 			Some(lba as _)
 		} else {
 			None
@@ -190,8 +190,8 @@ impl Gpt {
 		}
 		// Read the first block
 		let lba = translate_lba(lba, dev.blk_count).ok_or_else(|| errno!(EINVAL))?;
-		let page = dev.ops.read_page(dev, lba)?;
-		let gpt_hdr = &page.slice::<Self>()[0];
+		let (page, off) = read_sector(dev, lba)?;
+		let gpt_hdr = from_bytes::<Self>(&page.slice()[off..]).unwrap();
 		if unlikely(!gpt_hdr.is_valid()) {
 			return Err(errno!(EINVAL));
 		}
@@ -225,10 +225,10 @@ impl Gpt {
 		true
 	}
 
-	/// Returns the list of entries in the table.
+	/// Returns the list of used entries in the table, along with their index.
 	///
 	/// `dev` is the block device
-	fn get_entries(&self, dev: &Arc<BlkDev>) -> EResult<Vec<GPTEntry>> {
+	fn get_entries(&self, dev: &Arc<BlkDev>) -> EResult<Vec<(u32, GPTEntry)>> {
 		let block_size = dev.blk_size.get();
 		let blocks_count = dev.blk_count;
 		let entries_start =
@@ -238,24 +238,25 @@ impl Gpt {
 			.map(|i| {
 				let off = entries_start + (i as u64 * self.entry_size as u64) / block_size;
 				let inner_off = ((i as u64 * self.entry_size as u64) % block_size) as usize;
-				let page = dev.ops.read_page(dev, off)?;
-				let ent = from_bytes::<GPTEntry>(&page.slice()[inner_off..])
-					.unwrap()
+				let (page, sector_off) = read_sector(dev, off)?;
+				// An entry spanning across pages is invalid
+				let ent = from_bytes::<GPTEntry>(&page.slice()[(sector_off + inner_off)..])
+					.ok_or_else(|| errno!(EINVAL))?
 					.clone();
-				Ok(ent)
+				Ok((i, ent))
 			})
 			// Ignore empty entries
-			.filter_map(|entry: EResult<GPTEntry>| {
-				entry.map(|e| e.is_used().then_some(e)).transpose()
+			.filter_map(|entry: EResult<(u32, GPTEntry)>| {
+				entry.map(|e| e.1.is_used().then_some(e)).transpose()
 			})
 			.map(|entry| {
-				let entry = entry?;
+				let (i, entry) = entry?;
 				// Check entry correctness
 				let start =
 					translate_lba(entry.start, blocks_count).ok_or_else(|| errno!(EINVAL))?;
 				let end = translate_lba(entry.end, blocks_count).ok_or_else(|| errno!(EINVAL))?;
 				if start < end {
-					Ok(entry)
+					Ok((i, entry))
 				} else {
 					Err(errno!(EINVAL))
 				}
@@ -280,7 +281,9 @@ impl Table for Gpt {
 		let alternate_entries = alternate_hdr.get_entries(dev)?;
 		// Check entries correctness
 		let blocks_count = dev.blk_count;
-		for (main_entry, alternate_entry) in main_entries.iter().zip(alternate_entries.iter()) {
+		for ((_, main_entry), (_, alternate_entry)) in
+			main_entries.iter().zip(alternate_entries.iter())
+		{
 			if !main_entry.eq(alternate_entry, main_hdr.entry_size as _, blocks_count) {
 				return Err(errno!(EINVAL));
 			}
@@ -295,13 +298,14 @@ impl Table for Gpt {
 	fn read_partitions(&self, dev: &Arc<BlkDev>) -> EResult<Vec<Partition>> {
 		let blocks_count = dev.blk_count;
 		let mut partitions = Vec::new();
-		for e in self.get_entries(dev)? {
+		for (i, e) in self.get_entries(dev)? {
 			let start = translate_lba(e.start, blocks_count).ok_or_else(|| errno!(EINVAL))?;
 			let end = translate_lba(e.end, blocks_count).ok_or_else(|| errno!(EINVAL))?;
 			// Doesn't overflow because the condition `end >= start` has already been
 			// checked + 1 is required because the ending LBA is included
 			let size = (end - start) + 1;
 			partitions.push(Partition {
+				number: i + 1,
 				offset: start,
 				size,
 			})?;
@@ -309,3 +313,18 @@ impl Table for Gpt {
 		Ok(partitions)
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn gpt_translate_lba() {
+		assert_eq!(translate_lba(0, 100), Some(0));
+		assert_eq!(translate_lba(99, 100), Some(99));
+		assert_eq!(translate_lba(100, 100), None);
+		assert_eq!(translate_lba(-1, 100), Some(99));
+		assert_eq!(translate_lba(-100, 100), Some(0));
+		assert_eq!(translate_lba(-101, 100), None);
+	}
+}
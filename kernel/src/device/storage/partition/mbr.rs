@@ -21,19 +21,25 @@
 //!
 //! The partition table is located on the first sector of the boot disk,
 //! alongside with the boot code.
+//!
+//! One of the four primary partitions may be an extended partition, containing a chain of
+//! Extended Boot Records (EBR). Each EBR has the same layout as the MBR, its first entry
+//! describing a logical partition and its second entry pointing to the next EBR.
 
-use super::{Partition, Table};
+use super::{Partition, Table, read_sector};
 use crate::device::BlkDev;
 use core::hint::unlikely;
 use macros::AnyRepr;
-use utils::{
-	collections::vec::Vec,
-	errno::{CollectResult, EResult},
-	ptr::arc::Arc,
-};
+use utils::{bytes::from_bytes, collections::vec::Vec, errno::EResult, ptr::arc::Arc};
 
 /// The signature of the MBR partition table.
 const MBR_SIGNATURE: u16 = 0xaa55;
+/// Partition types of extended partitions.
+const EXTENDED_TYPES: [u8; 3] = [0x05, 0x0f, 0x85];
+/// The number of the first logical partition.
+const FIRST_LOGICAL: u32 = 5;
+/// The maximum number of logical partitions, preventing loops in the chain of EBRs.
+const MAX_LOGICAL: u32 = 128;
 
 /// A MBR partition.
 #[repr(C, packed)]
@@ -81,31 +87,88 @@ impl Clone for MbrTable {
 	}
 }
 
-impl Table for MbrTable {
-	fn read(dev: &Arc<BlkDev>) -> EResult<Option<Self>> {
-		let page = dev.ops.read_page(dev, 0)?;
-		let table = &page.slice::<Self>()[0];
+impl MbrPartition {
+	/// Tells whether the partition is an extended partition.
+	fn is_extended(&self) -> bool {
+		EXTENDED_TYPES.contains(&self.partition_type)
+	}
+}
+
+impl MbrTable {
+	/// Reads the table located in the sector at `lba` on the device `dev`.
+	///
+	/// If the sector does not contain a table, the function returns `None`.
+	fn read_at(dev: &Arc<BlkDev>, lba: u64) -> EResult<Option<Self>> {
+		let (page, off) = read_sector(dev, lba)?;
+		let table = from_bytes::<Self>(&page.slice()[off..]).unwrap();
 		if unlikely(table.signature != MBR_SIGNATURE) {
 			return Ok(None);
 		}
 		Ok(Some(table.clone()))
 	}
 
+	/// Reads the logical partitions of the extended partition starting at `start`, and appends
+	/// them to `partitions`.
+	fn read_logical(
+		dev: &Arc<BlkDev>,
+		start: u64,
+		partitions: &mut Vec<Partition>,
+	) -> EResult<()> {
+		let mut ebr = start;
+		for number in FIRST_LOGICAL..(FIRST_LOGICAL + MAX_LOGICAL) {
+			let Some(table) = Self::read_at(dev, ebr)? else {
+				break;
+			};
+			let [logical, next, ..] = &table.partitions;
+			// The logical partition is relative to its EBR
+			if logical.partition_type != 0 && logical.sectors_count != 0 {
+				partitions.push(Partition {
+					number,
+					offset: ebr + logical.lba_start as u64,
+					size: logical.sectors_count as _,
+				})?;
+			}
+			// The next EBR is relative to the extended partition
+			if !next.is_extended() || next.lba_start == 0 {
+				break;
+			}
+			ebr = start + next.lba_start as u64;
+		}
+		Ok(())
+	}
+}
+
+impl Table for MbrTable {
+	fn read(dev: &Arc<BlkDev>) -> EResult<Option<Self>> {
+		Self::read_at(dev, 0)
+	}
+
 	fn get_type(&self) -> &'static str {
 		"MBR"
 	}
 
-	fn read_partitions(&self, _: &Arc<BlkDev>) -> EResult<Vec<Partition>> {
-		let partitions = self
-			.partitions
-			.iter()
-			.filter(|p| p.partition_type != 0)
-			.map(|p| Partition {
+	fn read_partitions(&self, dev: &Arc<BlkDev>) -> EResult<Vec<Partition>> {
+		let mut partitions = Vec::new();
+		let mut extended = None;
+		// Primary partitions are numbered after their slot in the table
+		for (i, p) in self.partitions.iter().enumerate() {
+			if p.partition_type == 0 {
+				continue;
+			}
+			// Only one extended partition is allowed. Its EBR is not exposed as a partition
+			if p.is_extended() {
+				extended.get_or_insert(p.lba_start as u64);
+				continue;
+			}
+			partitions.push(Partition {
+				number: i as u32 + 1,
 				offset: p.lba_start as _,
 				size: p.sectors_count as _,
-			})
-			.collect::<CollectResult<_>>()
-			.0?;
+			})?;
+		}
+		if let Some(start) = extended {
+			Self::read_logical(dev, start, &mut partitions)?;
+		}
 		Ok(partitions)
 	}
 }
@@ -25,21 +25,50 @@ mod mbr;
 use crate::{
 	device,
 	device::{BLK_DEVICES, BlkDev, storage::STORAGE_MODE},
+	memory::cache::RcPage,
 	println,
 };
+use core::ops::Range;
 use gpt::Gpt;
 use mbr::MbrTable;
-use utils::{boxed::Box, collections::vec::Vec, errno::EResult, ptr::arc::Arc};
+use utils::{
+	boxed::Box, collections::vec::Vec, errno, errno::EResult, limits::PAGE_SIZE, ptr::arc::Arc,
+};
 
 /// A disk partition bounds.
 #[derive(Debug)]
 pub struct Partition {
+	/// The number of the partition, used to name its device file.
+	pub number: u32,
 	/// The offset to the first sector of the partition.
 	pub offset: u64,
 	/// The number of sectors in the partition.
 	pub size: u64,
 }
 
+impl Partition {
+	/// Returns the range of pages covered by the partition on a device with sectors of
+	/// `blk_size` bytes.
+	///
+	/// A trailing incomplete page is not included.
+	pub fn pages(&self, blk_size: u64) -> Range<u64> {
+		let start = self.offset * blk_size / PAGE_SIZE as u64;
+		let end = (self.offset + self.size) * blk_size / PAGE_SIZE as u64;
+		start..end
+	}
+}
+
+/// Reads the page containing the sector at `lba` on the device `dev`.
+///
+/// The function returns the page along with the offset of the sector in it, in bytes.
+fn read_sector(dev: &Arc<BlkDev>, lba: u64) -> EResult<(RcPage, usize)> {
+	let off = lba
+		.checked_mul(dev.blk_size.get())
+		.ok_or_else(|| errno!(EINVAL))?;
+	let page = dev.ops.read_page(dev, off / PAGE_SIZE as u64)?;
+	Ok((page, (off % PAGE_SIZE as u64) as usize))
+}
+
 /// Trait representing a partition table.
 pub trait Table {
 	/// Reads the partition table from the given storage device `dev`.
@@ -98,19 +127,66 @@ pub fn read_partitions(dev: &Arc<BlkDev>) -> EResult<()> {
 	println!("Read partitions on {}", dev.path);
 	let mut dev_parts = dev.partitions.lock();
 	clear_partitions(&mut dev_parts)?;
+	dev.part_stats.lock().clear();
 	let Some(parts) = read(dev)? else {
 		return Ok(());
 	};
 	let parts = parts.read_partitions(dev)?;
+	let blk_size = dev.blk_size.get();
 	// TODO When failing, remove previously registered devices
-	for (i, partition) in parts.into_iter().enumerate() {
-		let part_nbr = (i + 1) as u32;
-		let (id, path) = dev.ops.new_partition(dev, part_nbr)?;
+	for mut partition in parts {
+		// Clamp the partition to the end of the device
+		let Some(max_size) = dev.blk_count.checked_sub(partition.offset) else {
+			println!(
+				"Partition {} of {} starts beyond the end of the device",
+				partition.number, dev.path
+			);
+			continue;
+		};
+		partition.size = partition.size.min(max_size);
+		// Pages of the partition are pages of the device
+		if !(partition.offset * blk_size).is_multiple_of(PAGE_SIZE as u64) {
+			println!(
+				"Partition {} of {} is not aligned on a page boundary",
+				partition.number, dev.path
+			);
+			continue;
+		}
+		let range = partition.pages(blk_size);
+		if range.is_empty() {
+			continue;
+		}
+		let (id, path) = dev.ops.new_partition(dev, partition.number)?;
 		// Create the partition's device file
 		println!("Found partition {path}");
 		let part_dev = BlkDev::new_partition(id, path, STORAGE_MODE, dev.clone(), partition)?;
+		dev.part_stats
+			.lock()
+			.push((range, part_dev.stats.clone()))?;
 		dev_parts.push(part_dev.clone())?;
 		device::register_blk(part_dev)?;
 	}
 	Ok(())
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn partition_pages() {
+		let part = Partition {
+			number: 1,
+			offset: 2048,
+			size: 4096 + 7,
+		};
+		// Sectors of 512 bytes: the trailing incomplete page is excluded
+		assert_eq!(part.pages(512), 256..768);
+		let part = Partition {
+			number: 1,
+			offset: 256,
+			size: 3,
+		};
+		assert_eq!(part.pages(4096), 256..259);
+	}
+}
@@ -34,19 +34,24 @@ use crate::{
 	device::{
 		BlkDev, BlockDeviceOps, DeviceID,
 		id::{BLOCK_EXTENDED_MAJOR, BLOCK_EXTENDED_MAJOR_HANDLE},
-		storage::{SCSI_MAJOR, ide},
+		io_stats::IoDir,
+		storage::{
+			ata,
+			ata::{AtaDrive, TaskFile},
+			ide, scsi_partition,
+		},
 	},
 	memory::cache::RcPage,
 	println,
 	sync::mutex::Mutex,
+	syscall::ioctl,
 };
-use core::hint::unlikely;
+use core::{ffi::c_void, hint::unlikely};
 use utils::{
 	bytes::slice_from_bytes,
 	collections::path::PathBuf,
 	errno,
 	errno::{AllocResult, EResult},
-	format,
 	limits::PAGE_SIZE,
 	ptr::arc::Arc,
 };
@@ -132,6 +137,8 @@ pub struct PATAInterface {
 	lba48: bool,
 	/// The number of sectors on the disk.
 	pub sectors_count: u64,
+	/// The data returned by the IDENTIFY DEVICE command
+	identity: [u16; 256],
 
 	/// Spinlock preventing data race on read/write operations
 	lock: Mutex<(), false>,
@@ -155,6 +162,7 @@ impl PATAInterface {
 
 			lba48: false,
 			sectors_count: 0,
+			identity: [0; 256],
 
 			lock: Default::default(),
 		};
@@ -302,7 +310,7 @@ impl PATAInterface {
 			}
 		}
 
-		let mut data: [u16; 256] = [0; 256];
+		let data = &mut self.identity;
 		for d in data.iter_mut() {
 			unsafe {
 				*d = self.channel.ata_bar.read::<u16>(REG_DATA);
@@ -381,6 +389,48 @@ impl PATAInterface {
 		(count, lba48)
 	}
 
+	/// Returns the LBA of the first sector of the page at offset `off` of `dev`, checking the
+	/// page is in bounds.
+	fn page_lba(&self, dev: &BlkDev, off: u64) -> EResult<u64> {
+		let size = PAGE_SIZE as u64 / SECTOR_SIZE;
+		let lba = off.checked_mul(size).ok_or_else(|| errno!(EOVERFLOW))?;
+		// If the offset and size are out of bounds of the disk, return an error
+		let end = lba.checked_add(size).ok_or_else(|| errno!(EOVERFLOW))?;
+		if unlikely(end > dev.blk_count) {
+			return Err(errno!(EOVERFLOW));
+		}
+		Ok(lba)
+	}
+
+	/// Writes the page `page` at the sector `lba`, by polling the drive.
+	///
+	/// The caller is responsible for serializing accesses to the drive.
+	fn write_page(&self, lba: u64, page: &[u8]) -> EResult<()> {
+		let size = PAGE_SIZE as u64 / SECTOR_SIZE;
+		// Select disk
+		self.select(false);
+		// Write
+		let buf = slice_from_bytes::<u16>(page).unwrap();
+		let mut i = 0;
+		while i < size {
+			let off = lba + i;
+			let count = (size - i).min(u16::MAX as u64) as u16;
+			let (count, lba48) = self.prepare_io(off, count, true);
+			let start = i as usize;
+			let end = start + count as usize;
+			for j in start..end {
+				self.wait_io()?;
+				for k in 0..256 {
+					let index = j * 256 + k;
+					unsafe { self.channel.ata_bar.write::<u16>(REG_DATA, buf[index]) }
+				}
+			}
+			self.cache_flush(lba48);
+			i += count as u64;
+		}
+		Ok(())
+	}
+
 	/// Waits for the drive to be ready for IO operation.
 	///
 	/// The device is assumed to be selected.
@@ -397,22 +447,60 @@ impl PATAInterface {
 	}
 }
 
+impl AtaDrive for PATAInterface {
+	fn identity(&self) -> &[u16; 256] {
+		&self.identity
+	}
+
+	fn exec(&self, tf: &TaskFile, buf: Option<&mut [u8; ata::SECTOR_SIZE]>) -> EResult<TaskFile> {
+		// Avoid data race
+		let _guard = self.lock.lock();
+		// Select disk, keeping the other bits of the device register
+		let drive = (tf.device & !(1 << 4)) | SELECT_MASTER | ((self.slave as u8) << 4);
+		unsafe {
+			self.channel.ata_bar.write(REG_DRIVE, drive);
+		}
+		delay(420);
+		unsafe {
+			self.channel.ata_bar.write(REG_FEATURES, tf.feature);
+			self.channel.ata_bar.write(REG_SEC_CNT, tf.count);
+			self.channel.ata_bar.write(REG_LBA_LO, tf.lba[0]);
+			self.channel.ata_bar.write(REG_LBA_MID, tf.lba[1]);
+			self.channel.ata_bar.write(REG_LBA_HI, tf.lba[2]);
+		}
+		self.send_command(tf.command);
+		delay(420);
+		self.wait_busy();
+		if let Some(buf) = buf {
+			self.wait_io()?;
+			for word in buf.chunks_exact_mut(2) {
+				let val = unsafe { self.channel.ata_bar.read::<u16>(REG_DATA) };
+				word.copy_from_slice(&val.to_le_bytes());
+			}
+		}
+		let status = self.get_status();
+		if unlikely(status & (STATUS_ERR | STATUS_DF) != 0) {
+			return Err(errno!(EIO));
+		}
+		unsafe {
+			Ok(TaskFile {
+				command: status,
+				feature: self.get_error(),
+				count: self.channel.ata_bar.read(REG_SEC_CNT),
+				lba: [
+					self.channel.ata_bar.read(REG_LBA_LO),
+					self.channel.ata_bar.read(REG_LBA_MID),
+					self.channel.ata_bar.read(REG_LBA_HI),
+				],
+				device: self.channel.ata_bar.read(REG_DRIVE),
+			})
+		}
+	}
+}
+
 impl BlockDeviceOps for PATAInterface {
 	fn new_partition(&self, _dev: &BlkDev, id: u32) -> AllocResult<(DeviceID, PathBuf)> {
-		let device_id = if id < 16 {
-			DeviceID {
-				major: SCSI_MAJOR,
-				minor: self.scsi_id * 16 + id,
-			}
-		} else {
-			DeviceID {
-				major: BLOCK_EXTENDED_MAJOR,
-				minor: BLOCK_EXTENDED_MAJOR_HANDLE.lock().alloc_minor(None)?,
-			}
-		};
-		let letter = (b'a' + self.scsi_id as u8) as char;
-		let path = PathBuf::new_unchecked(format!("/dev/sd{letter}{id}")?);
-		Ok((device_id, path))
+		scsi_partition(self.scsi_id, id)
 	}
 
 	fn drop_partition(&self, dev: &BlkDev) {
@@ -425,12 +513,9 @@ impl BlockDeviceOps for PATAInterface {
 		dev.mapped.get_or_insert_page(off, || {
 			let blk = BlkDev::new_page(dev, off)?;
 			let size = PAGE_SIZE as u64 / SECTOR_SIZE;
-			let off = off.checked_mul(size).ok_or_else(|| errno!(EOVERFLOW))?;
-			// If the offset and size are out of bounds of the disk, return an error
-			let end = off.checked_add(size).ok_or_else(|| errno!(EOVERFLOW))?;
-			if unlikely(end > dev.blk_count) {
-				return Err(errno!(EOVERFLOW));
-			}
+			let lba = self.page_lba(dev, off)?;
+			// Wait for our turn on the device
+			let _io = dev.start_io(IoDir::Read, off)?;
 			// Avoid data race
 			let _guard = self.lock.lock();
 			// Select disk
@@ -439,7 +524,7 @@ impl BlockDeviceOps for PATAInterface {
 			let buf = unsafe { blk.slice_mut() };
 			let mut i = 0;
 			while i < size {
-				let off = off + i;
+				let off = lba + i;
 				let count = (size - i).min(u16::MAX as u64) as u16;
 				let (count, _) = self.prepare_io(off, count, false);
 				let start = i as usize;
@@ -460,36 +545,21 @@ impl BlockDeviceOps for PATAInterface {
 	}
 
 	fn writeback(&self, dev: &BlkDev, off: u64, blk: &RcPage) -> EResult<()> {
-		let size = PAGE_SIZE as u64 / SECTOR_SIZE;
-		let off = off.checked_mul(size).ok_or_else(|| errno!(EOVERFLOW))?;
-		// If the offset and size are out of bounds of the disk, return an error
-		let end = off.checked_add(size).ok_or_else(|| errno!(EOVERFLOW))?;
-		if unlikely(end > dev.blk_count) {
-			return Err(errno!(EOVERFLOW));
-		}
+		let lba = self.page_lba(dev, off)?;
+		// Wait for our turn on the device
+		let _io = dev.start_io(IoDir::Write, off)?;
 		// Avoid data race
 		let _guard = self.lock.lock();
-		// Select disk
-		self.select(false);
-		// Write
-		let buf = slice_from_bytes::<u16>(blk.slice()).unwrap();
-		let mut i = 0;
-		while i < size {
-			let off = off + i;
-			let count = (size - i).min(u16::MAX as u64) as u16;
-			let (count, lba48) = self.prepare_io(off, count, true);
-			let start = i as usize;
-			let end = start + count as usize;
-			for j in start..end {
-				self.wait_io()?;
-				for k in 0..256 {
-					let index = j * 256 + k;
-					unsafe { self.channel.ata_bar.write::<u16>(REG_DATA, buf[index]) }
-				}
-			}
-			self.cache_flush(lba48);
-			i += count as u64;
-		}
-		Ok(())
+		self.write_page(lba, blk.slice())
+	}
+
+	fn dump_page(&self, dev: &BlkDev, off: u64, buf: &[u8; PAGE_SIZE]) -> EResult<()> {
+		let lba = self.page_lba(dev, off)?;
+		// The kernel is panicking: the lock is bypassed since its holder will never release it
+		self.write_page(lba, buf)
+	}
+
+	fn ioctl(&self, _dev: &BlkDev, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
+		ata::ioctl(self, request, argp)
 	}
 }
@@ -0,0 +1,101 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! RAM disks (`/dev/ramN`) are block devices whose data lives in memory.
+//!
+//! Their content is lost on reboot. They are useful as a root filesystem populated at boot, or to
+//! test filesystems without a disk.
+
+use super::STORAGE_MODE;
+use crate::{
+	device::{BlkDev, BlockDeviceOps, DeviceID, register_blk},
+	memory::cache::RcPage,
+	sync::spin::Spin,
+};
+use core::num::NonZeroU64;
+use utils::{
+	boxed::Box,
+	collections::{btreemap::BTreeMap, path::PathBuf},
+	errno,
+	errno::{AllocResult, EResult},
+	limits::PAGE_SIZE,
+	ptr::arc::Arc,
+};
+
+/// Major number of RAM disks.
+const RAMDISK_MAJOR: u32 = 1;
+/// The size of a sector of a RAM disk, in bytes.
+const SECTOR_SIZE: u64 = 512;
+
+/// Block device operations of a RAM disk.
+#[derive(Debug)]
+struct RamDiskOps {
+	/// The size of the disk, in pages
+	size: u64,
+	/// The pages of the disk, by offset. Pages are allocated on first access
+	pages: Spin<BTreeMap<u64, RcPage>>,
+}
+
+impl BlockDeviceOps for RamDiskOps {
+	fn new_partition(&self, _dev: &BlkDev, _id: u32) -> AllocResult<(DeviceID, PathBuf)> {
+		panic!("trying to create a partition of a RAM disk");
+	}
+
+	fn read_page(&self, _dev: &Arc<BlkDev>, off: u64) -> EResult<RcPage> {
+		if off >= self.size {
+			return Err(errno!(EINVAL));
+		}
+		let mut pages = self.pages.lock();
+		if let Some(page) = pages.get(&off) {
+			return Ok(page.clone());
+		}
+		// Since the page is referenced by the disk, it is never evicted from the page cache
+		let page = RcPage::new_zeroed()?;
+		page.init(off);
+		pages.insert(off, page.clone())?;
+		Ok(page)
+	}
+
+	fn writeback(&self, _dev: &BlkDev, _off: u64, _page: &RcPage) -> EResult<()> {
+		// The data already lives in memory
+		Ok(())
+	}
+}
+
+/// Creates the RAM disk `/dev/ram0`.
+///
+/// `size` is the size of the disk in KiB, rounded up to the size of a page.
+pub fn create(size: u32) -> EResult<()> {
+	let size = (size as u64 * 1024).div_ceil(PAGE_SIZE as u64);
+	let dev = BlkDev::new(
+		DeviceID {
+			major: RAMDISK_MAJOR,
+			minor: 0,
+		},
+		PathBuf::try_from(b"/dev/ram0")?,
+		STORAGE_MODE,
+		NonZeroU64::new(SECTOR_SIZE).unwrap(),
+		size * (PAGE_SIZE as u64 / SECTOR_SIZE),
+		Box::new(RamDiskOps {
+			size,
+			pages: Spin::new(BTreeMap::new()),
+		})?,
+	)?;
+	register_blk(dev)?;
+	Ok(())
+}
@@ -0,0 +1,541 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! USB Mass Storage class driver.
+//!
+//! SCSI commands are sent to the device using the Bulk-Only Transport (BOT).
+
+use crate::{
+	arch::x86::timer::mdelay,
+	device::{
+		BlkDev, BlockDeviceOps, DeviceID,
+		bus::usb::{
+			CLASS_MASS_STORAGE, DmaBuf, Driver, Interface, REQ_TYPE_CLASS, REQ_TYPE_IN,
+			REQ_TYPE_INTERFACE, SetupPacket, TransferType, UsbDevice, register_driver,
+		},
+		id::{BLOCK_EXTENDED_MAJOR, BLOCK_EXTENDED_MAJOR_HANDLE},
+		io_stats::IoDir,
+		storage::{SCSI_MAJOR, STORAGE_MODE, alloc_scsi_id, register_disk, scsi_partition},
+	},
+	memory::{PhysAddr, cache::RcPage},
+	println,
+	sync::mutex::Mutex,
+};
+use core::{fmt, hint::unlikely, mem::size_of, num::NonZeroU64, ptr::read_unaligned};
+use utils::{
+	DisplayableStr,
+	boxed::Box,
+	collections::path::PathBuf,
+	errno,
+	errno::{AllocResult, ENOMEM, EPIPE, EResult},
+	format,
+	limits::PAGE_SIZE,
+	ptr::arc::Arc,
+};
+
+/// Interface subclass: SCSI transparent command set
+const SUBCLASS_SCSI: u8 = 0x06;
+/// Interface protocol: Bulk-Only Transport
+const PROTOCOL_BOT: u8 = 0x50;
+
+/// Class request: Bulk-Only Mass Storage Reset
+const REQ_BOT_RESET: u8 = 0xff;
+/// Class request: Get Max LUN
+const REQ_GET_MAX_LUN: u8 = 0xfe;
+
+/// Signature of a Command Block Wrapper
+const CBW_SIGNATURE: u32 = 0x43425355;
+/// Signature of a Command Status Wrapper
+const CSW_SIGNATURE: u32 = 0x53425355;
+/// Command status: Passed
+const CSW_STATUS_PASSED: u8 = 0;
+/// Command status: Phase Error
+const CSW_STATUS_PHASE_ERROR: u8 = 2;
+
+/// SCSI command: Test Unit Ready
+const SCSI_TEST_UNIT_READY: u8 = 0x00;
+/// SCSI command: Request Sense
+const SCSI_REQUEST_SENSE: u8 = 0x03;
+/// SCSI command: Inquiry
+const SCSI_INQUIRY: u8 = 0x12;
+/// SCSI command: Read Capacity (10)
+const SCSI_READ_CAPACITY_10: u8 = 0x25;
+/// SCSI command: Read (10)
+const SCSI_READ_10: u8 = 0x28;
+/// SCSI command: Write (10)
+const SCSI_WRITE_10: u8 = 0x2a;
+/// SCSI command: Read (16)
+const SCSI_READ_16: u8 = 0x88;
+/// SCSI command: Write (16)
+const SCSI_WRITE_16: u8 = 0x8a;
+/// SCSI command: Service Action In (16), used for Read Capacity (16)
+const SCSI_SERVICE_ACTION_IN_16: u8 = 0x9e;
+/// Service action: Read Capacity (16)
+const SA_READ_CAPACITY_16: u8 = 0x10;
+
+/// Peripheral device type: Direct access block device
+const TYPE_DIRECT_ACCESS: u8 = 0x00;
+/// Peripheral device type: Simplified direct access device
+const TYPE_SIMPLIFIED_DIRECT_ACCESS: u8 = 0x0e;
+
+/// Offset of the Command Block Wrapper in the transport buffer
+const CBW_OFF: usize = 0;
+/// Offset of the Command Status Wrapper in the transport buffer
+const CSW_OFF: usize = 64;
+/// Offset of the data of small commands in the transport buffer
+const DATA_OFF: usize = 128;
+
+/// The number of attempts to wait for a unit to become ready.
+const READY_ATTEMPTS: usize = 10;
+
+/// Command Block Wrapper, sent to the device before a command.
+#[repr(C, packed)]
+struct Cbw {
+	/// Signature, always [`CBW_SIGNATURE`]
+	signature: u32,
+	/// Command tag, echoed back in the CSW
+	tag: u32,
+	/// The number of bytes to transfer in the data stage
+	data_transfer_length: u32,
+	/// Flags. Bit 7 gives the direction of the data stage
+	flags: u8,
+	/// The Logical Unit Number the command is sent to
+	lun: u8,
+	/// The length of the command block
+	cb_length: u8,
+	/// The command block
+	cb: [u8; 16],
+}
+
+/// Command Status Wrapper, received from the device after a command.
+#[repr(C, packed)]
+struct Csw {
+	/// Signature, always [`CSW_SIGNATURE`]
+	signature: u32,
+	/// The tag of the associated command
+	tag: u32,
+	/// The number of bytes of the data stage that have not been processed
+	data_residue: u32,
+	/// The status of the command
+	status: u8,
+}
+
+/// The data stage of a command.
+enum Data {
+	/// No data stage
+	None,
+	/// Device-to-host data stage, with the physical address and length of the buffer
+	In(PhysAddr, usize),
+	/// Host-to-device data stage, with the physical address and length of the buffer
+	Out(PhysAddr, usize),
+}
+
+/// State of the transport, used by one command at a time.
+struct Transport {
+	/// Buffer for command and status wrappers, and for the data of small commands
+	buf: DmaBuf,
+	/// The tag of the last command
+	tag: u32,
+}
+
+/// A USB mass storage interface.
+struct MassStorage {
+	/// The USB device
+	dev: Arc<UsbDevice>,
+	/// The interface number
+	iface: u8,
+	/// The address of the bulk IN endpoint
+	bulk_in: u8,
+	/// The address of the bulk OUT endpoint
+	bulk_out: u8,
+
+	/// The transport, serializing commands
+	transport: Mutex<Transport, false>,
+}
+
+impl MassStorage {
+	/// Performs a class-specific request on the interface.
+	fn class_request(
+		&self,
+		request: u8,
+		is_in: bool,
+		len: u16,
+		data: Option<PhysAddr>,
+	) -> EResult<()> {
+		let dir = if is_in { REQ_TYPE_IN } else { 0 };
+		self.dev.control_transfer(
+			SetupPacket {
+				request_type: dir | REQ_TYPE_CLASS | REQ_TYPE_INTERFACE,
+				request,
+				value: 0,
+				index: self.iface as _,
+				length: len,
+			},
+			data,
+		)
+	}
+
+	/// Returns the greatest Logical Unit Number of the device.
+	fn max_lun(&self, transport: &Transport) -> u8 {
+		let res = self.class_request(
+			REQ_GET_MAX_LUN,
+			true,
+			1,
+			Some(transport.buf.phys_addr() + DATA_OFF),
+		);
+		// Devices with a single LUN may stall the request
+		match res {
+			Ok(_) => transport.buf.as_slice()[DATA_OFF].min(15),
+			Err(_) => 0,
+		}
+	}
+
+	/// Resets the device after an error in the transport.
+	fn reset_recovery(&self) -> EResult<()> {
+		self.class_request(REQ_BOT_RESET, false, 0, None)?;
+		self.dev.clear_halt(self.bulk_in)?;
+		self.dev.clear_halt(self.bulk_out)
+	}
+
+	/// Performs a bulk transfer for the data stage, clearing the endpoint if it stalls.
+	fn data_stage(&self, endpoint: u8, addr: PhysAddr, len: usize) -> EResult<usize> {
+		match self.dev.bulk_transfer(endpoint, addr, len) {
+			// The status is reported in the CSW
+			Err(e) if e.as_int() == EPIPE => {
+				self.dev.clear_halt(endpoint)?;
+				Ok(0)
+			}
+			res => res,
+		}
+	}
+
+	/// Sends the command block `cb` to the logical unit `lun`.
+	///
+	/// On success, the function returns the number of bytes transferred in the data stage. If the
+	/// command fails, the function returns [`errno::EIO`].
+	fn command(
+		&self,
+		transport: &mut Transport,
+		lun: u8,
+		cb: &[u8],
+		data: Data,
+	) -> EResult<usize> {
+		transport.tag = transport.tag.wrapping_add(1);
+		let (len, flags) = match data {
+			Data::None => (0, 0),
+			Data::In(_, len) => (len, 0x80),
+			Data::Out(_, len) => (len, 0),
+		};
+		let mut cbw = Cbw {
+			signature: CBW_SIGNATURE,
+			tag: transport.tag,
+			data_transfer_length: len as _,
+			flags,
+			lun,
+			cb_length: cb.len() as _,
+			cb: [0; 16],
+		};
+		cbw.cb[..cb.len()].copy_from_slice(cb);
+		let buf_addr = transport.buf.phys_addr();
+		unsafe {
+			transport.buf.ptr::<Cbw>(CBW_OFF).write_unaligned(cbw);
+		}
+		let res = self
+			.dev
+			.bulk_transfer(self.bulk_out, buf_addr + CBW_OFF, size_of::<Cbw>());
+		if unlikely(res.is_err()) {
+			self.reset_recovery()?;
+			return Err(errno!(EIO));
+		}
+		let transferred = match data {
+			Data::None => 0,
+			Data::In(addr, len) => self.data_stage(self.bulk_in, addr, len)?,
+			Data::Out(addr, len) => self.data_stage(self.bulk_out, addr, len)?,
+		};
+		// Read status, retrying once if the endpoint stalls
+		let csw_addr = buf_addr + CSW_OFF;
+		let res = match self
+			.dev
+			.bulk_transfer(self.bulk_in, csw_addr, size_of::<Csw>())
+		{
+			Err(e) if e.as_int() == EPIPE => {
+				self.dev.clear_halt(self.bulk_in)?;
+				self.dev
+					.bulk_transfer(self.bulk_in, csw_addr, size_of::<Csw>())
+			}
+			res => res,
+		};
+		let csw: Csw = unsafe { read_unaligned(transport.buf.ptr(CSW_OFF)) };
+		let valid = matches!(res, Ok(n) if n == size_of::<Csw>())
+			&& csw.signature == CSW_SIGNATURE
+			&& csw.tag == transport.tag;
+		if unlikely(!valid || csw.status == CSW_STATUS_PHASE_ERROR) {
+			self.reset_recovery()?;
+			return Err(errno!(EIO));
+		}
+		if unlikely(csw.status != CSW_STATUS_PASSED) {
+			return Err(errno!(EIO));
+		}
+		Ok(transferred)
+	}
+}
+
+/// Returns the command block to read or write `count` blocks at `lba`, with its length.
+fn rw_command(write: bool, lba: u64, count: u32) -> ([u8; 16], usize) {
+	let mut cb = [0; 16];
+	match u32::try_from(lba) {
+		Ok(lba) if count <= u16::MAX as u32 => {
+			cb[0] = if write { SCSI_WRITE_10 } else { SCSI_READ_10 };
+			cb[2..6].copy_from_slice(&lba.to_be_bytes());
+			cb[7..9].copy_from_slice(&(count as u16).to_be_bytes());
+			(cb, 10)
+		}
+		_ => {
+			cb[0] = if write { SCSI_WRITE_16 } else { SCSI_READ_16 };
+			cb[2..10].copy_from_slice(&lba.to_be_bytes());
+			cb[10..14].copy_from_slice(&count.to_be_bytes());
+			(cb, 16)
+		}
+	}
+}
+
+/// A logical unit of a mass storage device, exposed as a block device.
+struct Disk {
+	/// The mass storage interface
+	storage: Arc<MassStorage>,
+	/// The Logical Unit Number
+	lun: u8,
+	/// The ID of the disk
+	scsi_id: u32,
+}
+
+impl Disk {
+	/// Detects the logical unit `lun` of `storage` and registers it as a block device.
+	fn init(storage: &Arc<MassStorage>, lun: u8) -> EResult<()> {
+		let mut transport = storage.transport.lock();
+		let data_addr = transport.buf.phys_addr() + DATA_OFF;
+		// Identify the unit
+		storage.command(
+			&mut transport,
+			lun,
+			&[SCSI_INQUIRY, 0, 0, 0, 36, 0],
+			Data::In(data_addr, 36),
+		)?;
+		let inquiry = &transport.buf.as_slice()[DATA_OFF..(DATA_OFF + 36)];
+		let qualifier = inquiry[0] >> 5;
+		let dev_type = inquiry[0] & 0x1f;
+		if qualifier != 0
+			|| !matches!(dev_type, TYPE_DIRECT_ACCESS | TYPE_SIMPLIFIED_DIRECT_ACCESS)
+		{
+			return Ok(());
+		}
+		let vendor = inquiry[8..16].trim_ascii();
+		let product = inquiry[16..32].trim_ascii();
+		println!(
+			"usb-storage: detected {} {} (LUN {lun})",
+			DisplayableStr(vendor),
+			DisplayableStr(product)
+		);
+		// Wait for the unit to be ready
+		let mut ready = false;
+		for _ in 0..READY_ATTEMPTS {
+			let res = storage.command(&mut transport, lun, &[SCSI_TEST_UNIT_READY; 6], Data::None);
+			if res.is_ok() {
+				ready = true;
+				break;
+			}
+			// Clear the pending error
+			storage.command(
+				&mut transport,
+				lun,
+				&[SCSI_REQUEST_SENSE, 0, 0, 0, 18, 0],
+				Data::In(data_addr, 18),
+			)?;
+			mdelay(100);
+		}
+		if !ready {
+			println!("usb-storage: LUN {lun} is not ready");
+			return Ok(());
+		}
+		// Read capacity
+		storage.command(
+			&mut transport,
+			lun,
+			&[SCSI_READ_CAPACITY_10, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+			Data::In(data_addr, 8),
+		)?;
+		let data = &transport.buf.as_slice()[DATA_OFF..];
+		let mut last_lba = u32::from_be_bytes(data[0..4].try_into().unwrap()) as u64;
+		let mut blk_size = u32::from_be_bytes(data[4..8].try_into().unwrap());
+		if last_lba == u32::MAX as u64 {
+			// The capacity does not fit on 32 bits
+			let mut cb = [0; 16];
+			cb[0] = SCSI_SERVICE_ACTION_IN_16;
+			cb[1] = SA_READ_CAPACITY_16;
+			cb[13] = 32;
+			storage.command(&mut transport, lun, &cb, Data::In(data_addr, 32))?;
+			let data = &transport.buf.as_slice()[DATA_OFF..];
+			last_lba = u64::from_be_bytes(data[0..8].try_into().unwrap());
+			blk_size = u32::from_be_bytes(data[8..12].try_into().unwrap());
+		}
+		drop(transport);
+		if unlikely(!blk_size.is_power_of_two() || !(512..=PAGE_SIZE as u32).contains(&blk_size)) {
+			println!("usb-storage: unsupported block size {blk_size} on LUN {lun}");
+			return Ok(());
+		}
+		// Register device
+		let scsi_id = alloc_scsi_id();
+		// TODO Handle if out of the alphabet
+		let letter = (b'a' + scsi_id as u8) as char;
+		let path = PathBuf::new_unchecked(format!("/dev/sd{letter}")?);
+		let dev = BlkDev::new(
+			DeviceID {
+				major: SCSI_MAJOR,
+				minor: scsi_id * 16,
+			},
+			path,
+			STORAGE_MODE,
+			NonZeroU64::new(blk_size as _).unwrap(),
+			last_lba + 1,
+			Box::new(Disk {
+				storage: storage.clone(),
+				lun,
+				scsi_id,
+			})?,
+		)?;
+		register_disk(dev)?;
+		Ok(())
+	}
+
+	/// Reads or writes the page at offset `off` of `dev`, from or to the buffer at `addr`.
+	fn io(&self, dev: &BlkDev, write: bool, off: u64, addr: PhysAddr) -> EResult<()> {
+		let blocks = PAGE_SIZE as u64 / dev.blk_size.get();
+		let lba = off.checked_mul(blocks).ok_or_else(|| errno!(EOVERFLOW))?;
+		// Bound check
+		let end_lba = lba.checked_add(blocks).ok_or_else(|| errno!(EOVERFLOW))?;
+		if unlikely(end_lba > dev.blk_count) {
+			return Err(errno!(EOVERFLOW));
+		}
+		let (cb, len) = rw_command(write, lba, blocks as _);
+		let data = if write {
+			Data::Out(addr, PAGE_SIZE)
+		} else {
+			Data::In(addr, PAGE_SIZE)
+		};
+		// Wait for our turn on the device
+		let dir = if write { IoDir::Write } else { IoDir::Read };
+		let _io = dev.start_io(dir, off)?;
+		let mut transport = self.storage.transport.lock();
+		let transferred = self
+			.storage
+			.command(&mut transport, self.lun, &cb[..len], data)?;
+		if unlikely(transferred != PAGE_SIZE) {
+			return Err(errno!(EIO));
+		}
+		Ok(())
+	}
+}
+
+impl BlockDeviceOps for Disk {
+	fn new_partition(&self, _dev: &BlkDev, id: u32) -> AllocResult<(DeviceID, PathBuf)> {
+		scsi_partition(self.scsi_id, id)
+	}
+
+	fn drop_partition(&self, dev: &BlkDev) {
+		if dev.id.major == BLOCK_EXTENDED_MAJOR {
+			BLOCK_EXTENDED_MAJOR_HANDLE.lock().free_minor(dev.id.minor);
+		}
+	}
+
+	fn read_page(&self, dev: &Arc<BlkDev>, off: u64) -> EResult<RcPage> {
+		dev.mapped.get_or_insert_page(off, || {
+			let blk = BlkDev::new_page(dev, off)?;
+			self.io(dev, false, off, blk.phys_addr())?;
+			Ok(blk)
+		})
+	}
+
+	fn writeback(&self, dev: &BlkDev, off: u64, blk: &RcPage) -> EResult<()> {
+		self.io(dev, true, off, blk.phys_addr())
+	}
+}
+
+impl fmt::Debug for Disk {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Disk")
+			.field("dev", &self.storage.dev)
+			.field("lun", &self.lun)
+			.finish()
+	}
+}
+
+/// The USB mass storage class driver.
+struct MassStorageDriver;
+
+impl Driver for MassStorageDriver {
+	fn name(&self) -> &'static str {
+		"usb-storage"
+	}
+
+	fn probe(&self, dev: &Arc<UsbDevice>, iface: &Interface) -> EResult<bool> {
+		let desc = &iface.desc;
+		if desc.interface_class != CLASS_MASS_STORAGE {
+			return Ok(false);
+		}
+		if desc.interface_subclass != SUBCLASS_SCSI || desc.interface_protocol != PROTOCOL_BOT {
+			println!("usb-storage: unsupported command set or transport");
+			return Ok(false);
+		}
+		let bulk = |is_in| {
+			iface
+				.endpoints
+				.iter()
+				.find(|ep| ep.transfer_type() == TransferType::Bulk && ep.is_in() == is_in)
+				.copied()
+		};
+		let (Some(bulk_in), Some(bulk_out)) = (bulk(true), bulk(false)) else {
+			return Err(errno!(EINVAL));
+		};
+		dev.hc.configure_endpoints(dev, &[bulk_in, bulk_out])?;
+		let storage = Arc::new(MassStorage {
+			dev: dev.clone(),
+			iface: desc.interface_number,
+			bulk_in: bulk_in.endpoint_address,
+			bulk_out: bulk_out.endpoint_address,
+
+			transport: Mutex::new(Transport {
+				buf: DmaBuf::new(0)?,
+				tag: 0,
+			}),
+		})?;
+		let max_lun = storage.max_lun(&storage.transport.lock());
+		for lun in 0..=max_lun {
+			match Disk::init(&storage, lun) {
+				Ok(()) => {}
+				Err(e) if e.as_int() == ENOMEM => return Err(e),
+				Err(e) => println!("usb-storage: cannot initialize LUN {lun}: {e}"),
+			}
+		}
+		Ok(true)
+	}
+}
+
+/// Registers the driver.
+pub(super) fn register() -> AllocResult<()> {
+	register_driver(&MassStorageDriver)
+}
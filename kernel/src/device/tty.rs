@@ -20,27 +20,136 @@
 //! communicate with it.
 
 use crate::{
-	file::{File, fs::FileOps},
+	device::DeviceID,
+	file::{File, O_NOCTTY, O_NONBLOCK, fs::FileOps, perm::is_privileged},
 	memory::user::{UserPtr, UserSlice},
 	process::{
 		Process,
 		pid::Pid,
 		signal::{Signal, SignalHandler},
 	},
+	sync::spin::Spin,
 	syscall::{
 		FromSyscallArg, ioctl,
-		select::{POLLIN, POLLOUT},
+		select::{POLLERR, POLLHUP, POLLIN, POLLOUT, POLLRDNORM, POLLWRNORM},
 	},
-	tty::{TTY, WinSize, termios, termios::Termios},
+	tty,
+	tty::{
+		TTY, VT_COUNT, WinSize, termios,
+		termios::{
+			TCFlag, Termios,
+			consts::{TCIFLUSH, TCIOFLUSH, TCOFLUSH},
+		},
+	},
+};
+use core::{
+	ffi::{c_int, c_void},
+	sync::atomic::Ordering::{Acquire, Relaxed, Release},
+};
+use utils::{collections::hashmap::HashMap, errno, errno::EResult};
+
+/// Virtual terminals state, used by the `VT_GETSTATE` ioctl.
+#[repr(C)]
+#[derive(Debug)]
+struct VtStat {
+	/// The number of the active virtual terminal.
+	v_active: u16,
+	/// The signal to send.
+	v_signal: u16,
+	/// Bitmask of the virtual terminals in use.
+	v_state: u16,
+}
+
+/// The ID of the `/dev/tty` device, which refers to the controlling terminal of the process
+/// opening it.
+pub const CTTY_ID: DeviceID = DeviceID {
+	major: 5,
+	minor: 0,
 };
-use core::ffi::c_void;
-use utils::{errno, errno::EResult};
+
+/// Returns the ID of the device to be opened when opening the device with ID `id`.
+///
+/// If `id` is [`CTTY_ID`], the function returns the ID of the controlling terminal of the current
+/// process, or [`errno::ENXIO`] if it has none. Else, `id` is returned as is.
+pub fn resolve(id: DeviceID) -> EResult<DeviceID> {
+	if id != CTTY_ID {
+		return Ok(id);
+	}
+	let vt = Process::current().ctty.load(Acquire);
+	if vt == 0 {
+		return Err(errno!(ENXIO));
+	}
+	Ok(DeviceID {
+		major: 4,
+		minor: vt as _,
+	})
+}
+
+/// The handle of the `/dev/tty` device.
+///
+/// Opening the device opens the controlling terminal instead (see [`resolve`]), so this handle is
+/// never used.
+#[derive(Debug)]
+pub struct CttyDeviceHandle;
+
+impl FileOps for CttyDeviceHandle {}
 
 /// A TTY device's handle.
 #[derive(Debug)]
-pub struct TTYDeviceHandle;
+pub struct TTYDeviceHandle {
+	/// The number of the virtual terminal, starting at `1`. If zero, the handle refers to the
+	/// active virtual terminal.
+	vt: usize,
+	/// For each open file description, the number of the virtual terminal it was opened on and
+	/// the terminal's hangup count at that time.
+	files: Spin<HashMap<usize, (usize, u32)>>,
+}
 
 impl TTYDeviceHandle {
+	/// Creates a handle for the virtual terminal with the given number.
+	///
+	/// If `vt` is zero, the handle refers to the active virtual terminal.
+	pub fn new(vt: usize) -> Self {
+		Self {
+			vt,
+			files: Default::default(),
+		}
+	}
+
+	/// Returns the TTY the handle refers to.
+	fn tty(&self) -> &'static TTY {
+		tty::get(self.vt).unwrap_or_else(tty::current)
+	}
+
+	/// Returns the number of the virtual terminal the handle refers to.
+	fn vt_num(&self) -> usize {
+		if self.vt != 0 { self.vt } else { tty::active() }
+	}
+
+	/// Returns the key identifying `file` in the files list.
+	fn file_id(file: &File) -> usize {
+		file.id()
+	}
+
+	/// Returns the hangup count of the TTY at the time `file` was opened.
+	///
+	/// If unknown, or if `file` was opened on another virtual terminal, the function returns the
+	/// current count.
+	fn open_hangups(&self, file: &File) -> u32 {
+		let vt = self.vt_num();
+		self.files
+			.lock()
+			.get(&Self::file_id(file))
+			.filter(|(file_vt, _)| *file_vt == vt)
+			.map(|(_, hangups)| *hangups)
+			.unwrap_or_else(|| self.tty().hangup_count())
+	}
+
+	/// Tells whether the TTY has been hung up since `file` was opened.
+	fn is_hung_up(&self, file: &File) -> bool {
+		self.open_hangups(file) != self.tty().hangup_count()
+	}
+
 	/// Checks whether the current process is allowed to read from the TTY.
 	///
 	/// If not, it is killed with a `SIGTTIN` signal.
@@ -48,7 +157,7 @@ impl TTYDeviceHandle {
 	/// This function must be called before performing the read operation.
 	fn check_sigttin(&self) -> EResult<()> {
 		let proc = Process::current();
-		if proc.get_pgid() == TTY.get_pgrp() {
+		if proc.get_pgid() == self.tty().get_pgrp() {
 			return Ok(());
 		}
 		if proc.is_in_orphan_process_group() {
@@ -67,24 +176,38 @@ impl TTYDeviceHandle {
 		Ok(())
 	}
 
-	/// Checks whether the current process is allowed to write to the TTY.
+	/// Checks whether the current process is allowed to switch virtual terminals.
 	///
-	/// If not, it is killed with a `SIGTTOU` signal.
+	/// This is the case if the TTY is the controlling terminal of the process, or if the process
+	/// is privileged.
+	fn check_vt_switch(&self) -> EResult<()> {
+		let ctty = Process::current().ctty.load(Acquire);
+		if ctty != self.vt_num() && !is_privileged() {
+			return Err(errno!(EPERM));
+		}
+		Ok(())
+	}
+
+	/// Checks whether the current process is allowed to change the TTY's settings.
 	///
-	/// This function must be called before performing the write operation.
-	fn check_sigttou(&self) -> EResult<()> {
+	/// If not, its process group is killed with a `SIGTTOU` signal.
+	///
+	/// This function must be called before performing the operation.
+	fn check_change(&self) -> EResult<()> {
 		let proc = Process::current();
-		if TTY.get_termios().c_lflag & termios::consts::TOSTOP == 0 {
+		// Allow if no foreground process group is set or if the process belongs to it
+		let pgrp = self.tty().get_pgrp();
+		if pgrp == 0 || proc.get_pgid() == pgrp {
 			return Ok(());
 		}
 		if proc.signal.lock().is_signal_blocked(Signal::SIGTTOU) {
-			return Err(errno!(EIO));
+			return Ok(());
 		}
 		if matches!(
 			proc.sig_handlers.lock()[Signal::SIGTTOU.0 as usize],
 			SignalHandler::Ignore
 		) {
-			return Err(errno!(EIO));
+			return Ok(());
 		}
 		if proc.is_in_orphan_process_group() {
 			return Err(errno!(EIO));
@@ -92,47 +215,108 @@ impl TTYDeviceHandle {
 		Process::kill_group(&proc, Signal::SIGTTOU);
 		Ok(())
 	}
+
+	/// Checks whether the current process is allowed to write to the TTY.
+	///
+	/// If not, its process group is killed with a `SIGTTOU` signal.
+	///
+	/// This function must be called before performing the write operation.
+	fn check_sigttou(&self) -> EResult<()> {
+		if self.tty().get_termios().c_lflag & termios::consts::TOSTOP == 0 {
+			return Ok(());
+		}
+		self.check_change()
+	}
 }
 
 impl FileOps for TTYDeviceHandle {
-	fn poll(&self, _file: &File, mask: u32) -> EResult<u32> {
-		let input = TTY.has_input_available();
-		let res = (if input { POLLIN } else { 0 } | POLLOUT) & mask;
+	fn acquire(&self, file: &File) {
+		// The first terminal opened by a process becomes its controlling terminal
+		if file.get_flags() & O_NOCTTY == 0 {
+			let _ = Process::current()
+				.ctty
+				.compare_exchange(0, self.vt_num(), Release, Relaxed);
+		}
+		let hangups = self.tty().hangup_count();
+		// On failure, the file is never considered hung up
+		let _ = self
+			.files
+			.lock()
+			.insert(Self::file_id(file), (self.vt_num(), hangups));
+	}
+
+	fn release(&self, file: &File) {
+		self.files.lock().remove(&Self::file_id(file));
+	}
+
+	fn poll(&self, file: &File, mask: u32) -> EResult<u32> {
+		if self.is_hung_up(file) {
+			let res = POLLIN | POLLRDNORM | POLLOUT | POLLWRNORM | POLLERR | POLLHUP;
+			return Ok(res & mask);
+		}
+		let input = self.tty().has_input_available();
+		let res = (if input { POLLIN | POLLRDNORM } else { 0 } | POLLOUT | POLLWRNORM) & mask;
 		Ok(res)
 	}
 
-	fn ioctl(&self, _file: &File, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
+	fn ioctl(&self, file: &File, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
+		if self.is_hung_up(file) {
+			return Err(if request.get_old_format() == ioctl::TIOCSPGRP {
+				errno!(ENOTTY)
+			} else {
+				errno!(EIO)
+			});
+		}
+		let tty = self.tty();
 		match request.get_old_format() {
 			ioctl::TCGETS => {
 				let termios_ptr = UserPtr::<Termios>::from_ptr(argp as usize);
-				termios_ptr.copy_to_user(&TTY.get_termios())?;
+				termios_ptr.copy_to_user(&tty.get_termios())?;
 				Ok(0)
 			}
-			// TODO Implement correct behaviours for each
+			// Output is written synchronously, so there is never any pending data to drain
 			ioctl::TCSETS | ioctl::TCSETSW | ioctl::TCSETSF => {
-				self.check_sigttou()?;
+				self.check_change()?;
 				let termios_ptr = UserPtr::<Termios>::from_ptr(argp as usize);
 				let termios = termios_ptr
 					.copy_from_user()?
 					.ok_or_else(|| errno!(EFAULT))?;
-				TTY.set_termios(termios);
+				if request.get_old_format() == ioctl::TCSETSF {
+					tty.flush_input();
+				}
+				tty.set_termios(termios);
+				Ok(0)
+			}
+			ioctl::TCSBRK => Ok(0),
+			ioctl::TCFLSH => {
+				self.check_change()?;
+				match argp as usize as TCFlag {
+					TCIFLUSH | TCIOFLUSH => tty.flush_input(),
+					TCOFLUSH => {}
+					_ => return Err(errno!(EINVAL)),
+				}
+				Ok(0)
+			}
+			ioctl::FIONREAD => {
+				let count_ptr = UserPtr::<c_int>::from_ptr(argp as usize);
+				count_ptr.copy_to_user(&(tty.available_len() as _))?;
 				Ok(0)
 			}
 			ioctl::TIOCGPGRP => {
 				let pgid_ptr = UserPtr::<Pid>::from_ptr(argp as usize);
-				pgid_ptr.copy_to_user(&TTY.get_pgrp())?;
+				pgid_ptr.copy_to_user(&tty.get_pgrp())?;
 				Ok(0)
 			}
 			ioctl::TIOCSPGRP => {
-				self.check_sigttou()?;
+				self.check_change()?;
 				let pgid_ptr = UserPtr::<Pid>::from_ptr(argp as usize);
 				let pgid = pgid_ptr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
-				TTY.set_pgrp(pgid);
+				tty.set_pgrp(pgid);
 				Ok(0)
 			}
 			ioctl::TIOCGWINSZ => {
 				let winsize = UserPtr::<WinSize>::from_ptr(argp as usize);
-				winsize.copy_to_user(&TTY.get_winsize())?;
+				winsize.copy_to_user(&tty.get_winsize())?;
 				Ok(0)
 			}
 			ioctl::TIOCSWINSZ => {
@@ -140,27 +324,64 @@ impl FileOps for TTYDeviceHandle {
 				let winsize = winsize_ptr
 					.copy_from_user()?
 					.ok_or_else(|| errno!(EFAULT))?;
-				TTY.set_winsize(winsize);
+				tty.set_winsize(winsize);
+				Ok(0)
+			}
+			ioctl::TIOCVHANGUP => {
+				if !is_privileged() {
+					return Err(errno!(EPERM));
+				}
+				tty.hangup();
+				Ok(0)
+			}
+			ioctl::VT_GETSTATE => {
+				let stat_ptr = UserPtr::<VtStat>::from_ptr(argp as usize);
+				stat_ptr.copy_to_user(&VtStat {
+					v_active: tty::active() as _,
+					v_signal: 0,
+					// Bit `0` is reserved, then one bit per virtual terminal
+					v_state: ((1 << (VT_COUNT + 1)) - 1) as _,
+				})?;
+				Ok(0)
+			}
+			ioctl::VT_ACTIVATE => {
+				self.check_vt_switch()?;
+				tty::switch(argp as usize)?;
+				Ok(0)
+			}
+			ioctl::VT_WAITACTIVE => {
+				self.check_vt_switch()?;
+				tty::wait_active(argp as usize)?;
 				Ok(0)
 			}
 			_ => Err(errno!(EINVAL)),
 		}
 	}
 
-	fn read(&self, _file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+	fn read(&self, file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let hangups = self.open_hangups(file);
+		if hangups != self.tty().hangup_count() {
+			return Ok(0);
+		}
 		self.check_sigttin()?;
-		let len = TTY.read(buf)?;
+		let len = self
+			.tty()
+			.read(buf, file.get_flags() & O_NONBLOCK != 0, hangups)?;
 		Ok(len)
 	}
 
-	fn write(&self, _file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+	fn write(&self, file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		if self.is_hung_up(file) {
+			return Err(errno!(EIO));
+		}
 		self.check_sigttou()?;
 		// Write
 		let mut i = 0;
+		let tty = self.tty();
 		let mut b: [u8; 128] = [0; 128];
 		while i < buf.len() {
 			let l = buf.copy_from_user(i, &mut b)?;
-			TTY.write(&b[..l]);
+			tty.write(&b[..l]);
 			i += l;
 		}
 		Ok(buf.len())
@@ -38,6 +38,9 @@ pub const EI_DATA: usize = 5;
 /// Identification bytes offset: Version.
 pub const EI_VERSION: usize = 6;
 
+/// ELF version: Current version.
+pub const EV_CURRENT: u8 = 1;
+
 /// File's class: Invalid class.
 pub const ELFCLASSNONE: u8 = 0;
 /// File's class: 32-bit objects.
@@ -83,6 +86,8 @@ pub const EM_860: u16 = 7;
 pub const EM_MIPS: u16 = 8;
 /// Required architecture: MIPS RS4000 Big-Endian.
 pub const EM_MIPS_RS4_BE: u16 = 10;
+/// Required architecture: AMD x86-64.
+pub const EM_X86_64: u16 = 62;
 
 /// Program header type: Ignored.
 pub const PT_NULL: u32 = 0;
@@ -104,6 +109,11 @@ pub const PT_TLS: u32 = 7;
 /// Program header type (GNU): Specifies whether the stack is executable.
 pub const PT_GNU_STACK: u32 = 0x6474e551;
 
+/// Note type: Process status, including registers.
+pub const NT_PRSTATUS: u32 = 1;
+/// Note type: Process information.
+pub const NT_PRPSINFO: u32 = 3;
+
 /// Segment flag: Execute.
 pub const PF_X: u32 = 0x1;
 /// Segment flag: Write.
@@ -166,6 +176,22 @@ pub const STT_FILE: u8 = 4;
 /// Thread-Local Storage (TLS) symbol.
 pub const STT_TLS: u8 = 6;
 
+/// The symbol is not visible outside the object file containing its definition.
+pub const STB_LOCAL: u8 = 0;
+/// The symbol is visible to all object files being combined.
+pub const STB_GLOBAL: u8 = 1;
+/// Like [`STB_GLOBAL`], but with a lower precedence.
+pub const STB_WEAK: u8 = 2;
+
+/// The symbol's visibility is specified by its binding.
+pub const STV_DEFAULT: u8 = 0;
+/// Like [`STV_HIDDEN`], with processor-specific semantics.
+pub const STV_INTERNAL: u8 = 1;
+/// The symbol is not visible to other components.
+pub const STV_HIDDEN: u8 = 2;
+/// The symbol is visible to other components, but cannot be preempted.
+pub const STV_PROTECTED: u8 = 3;
+
 /// 32 bit ELF header.
 #[derive(AnyRepr, Clone, Debug)]
 #[repr(C)]
@@ -282,6 +308,21 @@ pub struct ELF64ProgramHeader {
 	pub p_align: u64,
 }
 
+/// ELF note header, followed by the note's name and descriptor, each padded to a multiple of 4
+/// bytes.
+///
+/// The layout is the same for 32 and 64 bit files.
+#[derive(AnyRepr, Clone, Debug)]
+#[repr(C)]
+pub struct ELFNoteHeader {
+	/// The size of the name, including the terminating nul byte.
+	pub n_namesz: u32,
+	/// The size of the descriptor.
+	pub n_descsz: u32,
+	/// The type of the note.
+	pub n_type: u32,
+}
+
 /// 32 bit ELF section header.
 #[derive(AnyRepr, Clone, Copy, Debug)]
 #[repr(C)]
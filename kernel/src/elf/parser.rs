@@ -26,7 +26,12 @@ use crate::{
 	process::mem_space::{PROT_EXEC, PROT_READ, PROT_WRITE},
 };
 use core::hint::unlikely;
-use utils::{bytes, collections::vec::Vec, errno::CollectResult, limits::PAGE_SIZE};
+use utils::{
+	bytes,
+	collections::vec::Vec,
+	errno::CollectResult,
+	limits::{PAGE_SIZE, PATH_MAX},
+};
 
 /// The ELF's class.
 #[derive(Clone, Copy, Eq, PartialEq)]
@@ -407,6 +412,16 @@ impl Sym {
 	pub fn is_defined(&self) -> bool {
 		self.st_shndx != 0
 	}
+
+	/// Returns the symbol's binding (`STB_*`).
+	pub fn bind(&self) -> u8 {
+		self.st_info >> 4
+	}
+
+	/// Returns the symbol's visibility (`STV_*`).
+	pub fn visibility(&self) -> u8 {
+		self.st_other & 0x3
+	}
 }
 
 /// Representation of a relocation, bit-width-agnostic.
@@ -596,6 +611,10 @@ impl ELFParser<'static> {
 			.iter()
 			.find(|seg| seg.p_type == PT_INTERP)
 			.map(|seg| -> EResult<_> {
+				// The path must fit in a path buffer, including the trailing `\0`
+				if unlikely(!(2..=PATH_MAX as u64).contains(&seg.p_filesz)) {
+					return Err(errno!(ENOEXEC));
+				}
 				// Read from file
 				let mut path = unsafe { Vec::new_uninit(seg.p_filesz as usize) }?;
 				file.ops
@@ -0,0 +1,264 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Legacy asynchronous I/O, used by the `io_setup`, `io_submit`, `io_getevents` and `io_destroy`
+//! system calls.
+//!
+//! A context is a queue of completion events, with a fixed capacity. Requests are performed
+//! synchronously on submission, their completion event being queued on the context right away,
+//! until reaped by `io_getevents`.
+
+use crate::{
+	memory::VirtAddr,
+	sync::{spin::Spin, wait_queue::WaitQueue},
+};
+use core::{
+	cmp::min,
+	hint::unlikely,
+	sync::atomic::{
+		AtomicBool, AtomicUsize,
+		Ordering::{Acquire, Relaxed, Release},
+	},
+};
+use utils::{collections::vec::Vec, errno, errno::EResult};
+
+/// Request opcode: read at an offset.
+pub const IOCB_CMD_PREAD: u16 = 0;
+/// Request opcode: write at an offset.
+pub const IOCB_CMD_PWRITE: u16 = 1;
+/// Request opcode: synchronize the file's data and metadata.
+pub const IOCB_CMD_FSYNC: u16 = 2;
+/// Request opcode: synchronize the file's data.
+pub const IOCB_CMD_FDSYNC: u16 = 3;
+/// Request opcode: vectored read at an offset.
+pub const IOCB_CMD_PREADV: u16 = 7;
+/// Request opcode: vectored write at an offset.
+pub const IOCB_CMD_PWRITEV: u16 = 8;
+
+/// Request flag: notify an eventfd on completion.
+pub const IOCB_FLAG_RESFD: u32 = 1;
+
+/// The maximum number of events that can be reserved by all contexts.
+pub const AIO_MAX_NR: usize = 65536;
+
+/// The number of events currently reserved by all contexts.
+static AIO_NR: AtomicUsize = AtomicUsize::new(0);
+
+/// An I/O control block, describing a request.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct IoCb {
+	/// Data returned as is in the completion event
+	pub aio_data: u64,
+	/// Key used by the kernel to identify the request
+	pub aio_key: u32,
+	/// Per-request flags, as for `preadv2`
+	pub aio_rw_flags: u32,
+	/// The operation to perform (`IOCB_CMD_*`)
+	pub aio_lio_opcode: u16,
+	/// Request priority
+	pub aio_reqprio: i16,
+	/// The file descriptor to perform the operation on
+	pub aio_fildes: u32,
+	/// The buffer, or I/O vector for vectored operations
+	pub aio_buf: u64,
+	/// The size of the buffer, or number of entries in the I/O vector
+	pub aio_nbytes: u64,
+	/// The offset in the file
+	pub aio_offset: i64,
+	/// Reserved, must be zero
+	pub aio_reserved2: u64,
+	/// Request flags (`IOCB_FLAG_*`)
+	pub aio_flags: u32,
+	/// The eventfd to notify if [`IOCB_FLAG_RESFD`] is set
+	pub aio_resfd: u32,
+}
+
+/// A completion event.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IoEvent {
+	/// The `aio_data` field of the request
+	pub data: u64,
+	/// The userspace address of the request
+	pub obj: u64,
+	/// The result of the operation, or the negated error number on failure
+	pub res: i64,
+	/// Secondary result
+	pub res2: i64,
+}
+
+/// Queue of completion events.
+struct EventRing {
+	/// The buffer of events, whose size is the capacity of the ring
+	buf: Vec<IoEvent>,
+	/// The index of the oldest event in `buf`
+	head: usize,
+	/// The number of events in `buf`
+	len: usize,
+}
+
+/// An asynchronous I/O context.
+pub struct AioContext {
+	/// The address of the context's ring in userspace, used as the context's ID
+	addr: VirtAddr,
+	/// The capacity of the ring
+	size: usize,
+	/// The number of pending requests or queued events, bounded by the size of the ring
+	reserved: AtomicUsize,
+	/// Completion events
+	events: Spin<EventRing>,
+	/// Tells whether the context has been destroyed
+	dead: AtomicBool,
+	/// The queue of processes waiting for events
+	queue: WaitQueue,
+}
+
+impl AioContext {
+	/// Creates a context able to hold up to `nr_events` events.
+	///
+	/// `addr` is the address of the context's ring in userspace.
+	///
+	/// If the system-wide limit of events is reached, the function returns [`errno::EAGAIN`].
+	pub fn new(addr: VirtAddr, nr_events: usize) -> EResult<Self> {
+		let mut buf = Vec::new();
+		buf.resize(nr_events, IoEvent::default())?;
+		AIO_NR
+			.fetch_update(Relaxed, Relaxed, |nr| {
+				nr.checked_add(nr_events).filter(|nr| *nr <= AIO_MAX_NR)
+			})
+			.map_err(|_| errno!(EAGAIN))?;
+		Ok(Self {
+			addr,
+			size: nr_events,
+			reserved: AtomicUsize::new(0),
+			events: Spin::new(EventRing {
+				buf,
+				head: 0,
+				len: 0,
+			}),
+			dead: AtomicBool::new(false),
+			queue: WaitQueue::new(),
+		})
+	}
+
+	/// Returns the address of the context's ring in userspace.
+	#[inline]
+	pub fn addr(&self) -> VirtAddr {
+		self.addr
+	}
+
+	/// Returns the maximum number of events the context can hold.
+	#[inline]
+	pub fn size(&self) -> usize {
+		self.size
+	}
+
+	/// Reserves room for the event of a new request.
+	///
+	/// If the ring is full, the function returns [`errno::EAGAIN`].
+	pub fn reserve(&self) -> EResult<()> {
+		self.reserved
+			.fetch_update(Acquire, Relaxed, |n| (n < self.size).then_some(n + 1))
+			.map(|_| ())
+			.map_err(|_| errno!(EAGAIN))
+	}
+
+	/// Queues the completion event of a request, for which room has been reserved with
+	/// [`Self::reserve`].
+	pub fn complete(&self, event: IoEvent) {
+		{
+			let mut ring = self.events.lock();
+			let i = (ring.head + ring.len) % self.size;
+			ring.buf[i] = event;
+			ring.len += 1;
+		}
+		self.queue.wake_all();
+	}
+
+	/// Moves up to `out.len()` events from the ring to `out`, and returns their number.
+	fn take(&self, out: &mut [IoEvent]) -> usize {
+		let mut ring = self.events.lock();
+		let count = min(out.len(), ring.len);
+		for ev in &mut out[..count] {
+			*ev = ring.buf[ring.head];
+			ring.head = (ring.head + 1) % self.size;
+		}
+		ring.len -= count;
+		self.reserved.fetch_sub(count, Release);
+		count
+	}
+
+	/// Waits for at least `min_nr` events, then moves up to `out.len()` events to `out` and
+	/// returns their number.
+	///
+	/// `expired` tells whether the timeout is reached, in which case the function returns with
+	/// the available events, if any.
+	///
+	/// If the context is destroyed while waiting, the function returns with the available events.
+	///
+	/// If the process is interrupted by a signal while waiting, the function returns
+	/// [`errno::EINTR`].
+	pub fn get_events<F: Fn() -> bool>(
+		&self,
+		min_nr: usize,
+		out: &mut [IoEvent],
+		expired: F,
+	) -> EResult<usize> {
+		self.queue.wait_until(|| {
+			let available = self.events.lock().len;
+			if available >= min_nr || self.dead.load(Acquire) || expired() {
+				Some(self.take(out))
+			} else {
+				None
+			}
+		})
+	}
+
+	/// Marks the context as destroyed, waking up processes waiting on it.
+	pub fn kill(&self) {
+		self.dead.store(true, Release);
+		self.queue.wake_all();
+	}
+}
+
+impl Drop for AioContext {
+	fn drop(&mut self) {
+		AIO_NR.fetch_sub(self.size, Relaxed);
+	}
+}
+
+/// Checks the given request is valid.
+///
+/// If not, the function returns [`errno::EINVAL`].
+pub fn check_iocb(iocb: &IoCb) -> EResult<()> {
+	// Notifying an eventfd is not supported
+	if unlikely(iocb.aio_reserved2 != 0 || iocb.aio_flags != 0) {
+		return Err(errno!(EINVAL));
+	}
+	match iocb.aio_lio_opcode {
+		IOCB_CMD_PREAD | IOCB_CMD_PWRITE | IOCB_CMD_PREADV | IOCB_CMD_PWRITEV => {
+			if unlikely(iocb.aio_offset < 0 || iocb.aio_nbytes > isize::MAX as u64) {
+				return Err(errno!(EINVAL));
+			}
+		}
+		IOCB_CMD_FSYNC | IOCB_CMD_FDSYNC => {}
+		_ => return Err(errno!(EINVAL)),
+	}
+	Ok(())
+}
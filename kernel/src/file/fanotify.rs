@@ -0,0 +1,812 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! fanotify allows userspace to watch accesses to files, and to decide whether some of them are
+//! permitted.
+//!
+//! A listener creates a notification group, then marks files or whole filesystems with the set
+//! of events it is interested in. Each event is read from the group along with a new file
+//! descriptor referring to the accessed file.
+//!
+//! Permission events block the accessing process until the listener writes a response to the
+//! group. If the group is closed before responding, the access is allowed.
+
+use crate::{
+	file::{
+		FMODE_NONOTIFY, File, FileType, INode, O_CLOEXEC, O_NONBLOCK, fd::FD_CLOEXEC, fs::FileOps,
+		vfs, vfs::node::Node,
+	},
+	memory::user::UserSlice,
+	process::{Process, pid::Pid},
+	sync::{spin::Spin, wait_queue::WaitQueue},
+	syscall::select::{POLLIN, POLLRDNORM},
+};
+use core::{
+	ffi::c_int,
+	hint::{likely, unlikely},
+	mem,
+	mem::size_of,
+	ptr,
+	sync::atomic::{
+		AtomicUsize,
+		Ordering::{Relaxed, Release},
+	},
+};
+use utils::{
+	bytes::as_bytes,
+	collections::vec::Vec,
+	errno,
+	errno::{AllocResult, EResult},
+	ptr::arc::Arc,
+};
+
+/// Event: A file has been accessed.
+pub const FAN_ACCESS: u64 = 0x1;
+/// Event: A file has been modified.
+pub const FAN_MODIFY: u64 = 0x2;
+/// Event: A file open for writing has been closed.
+pub const FAN_CLOSE_WRITE: u64 = 0x8;
+/// Event: A file open read-only has been closed.
+pub const FAN_CLOSE_NOWRITE: u64 = 0x10;
+/// Event: A file has been opened.
+pub const FAN_OPEN: u64 = 0x20;
+/// Event: The event queue overflowed.
+pub const FAN_Q_OVERFLOW: u64 = 0x4000;
+/// Event: Permission to open a file is requested.
+pub const FAN_OPEN_PERM: u64 = 0x10000;
+/// Event: Permission to read a file is requested.
+pub const FAN_ACCESS_PERM: u64 = 0x20000;
+/// Mark: Report events on the children of the marked directory.
+pub const FAN_EVENT_ON_CHILD: u64 = 0x08000000;
+/// Mark: Report events on directories.
+pub const FAN_ONDIR: u64 = 0x40000000;
+
+/// Events requiring a response from the listener.
+pub const FAN_PERM_EVENTS: u64 = FAN_OPEN_PERM | FAN_ACCESS_PERM;
+/// Events that can be marked.
+pub const FAN_ALL_EVENTS: u64 =
+	FAN_ACCESS | FAN_MODIFY | FAN_CLOSE_WRITE | FAN_CLOSE_NOWRITE | FAN_OPEN | FAN_PERM_EVENTS;
+
+/// Response: Allow the access.
+pub const FAN_ALLOW: u32 = 1;
+/// Response: Deny the access.
+pub const FAN_DENY: u32 = 2;
+
+/// The file descriptor reported along with events that do not refer to a file.
+const FAN_NOFD: c_int = -1;
+/// The version of [`EventMetadata`].
+const FANOTIFY_METADATA_VERSION: u8 = 3;
+
+/// The maximum number of events queued on a group, unless it is unlimited.
+const MAX_QUEUED_EVENTS: usize = 16384;
+/// The maximum number of marks on a group, unless it is unlimited.
+pub const MAX_MARKS: usize = 8192;
+/// The maximum number of groups on the system.
+pub const MAX_GROUPS: usize = 128;
+
+/// An event, as read from a group.
+#[repr(C)]
+#[derive(Debug)]
+struct EventMetadata {
+	/// The length of the event, including metadata
+	event_len: u32,
+	/// The version of the structure
+	vers: u8,
+	reserved: u8,
+	/// The length of the structure
+	metadata_len: u16,
+	/// The mask of events
+	mask: u64,
+	/// The file descriptor of the accessed file
+	fd: i32,
+	/// The PID of the process which caused the event
+	pid: i32,
+}
+
+/// A response to a permission event, as written to a group.
+#[repr(C)]
+#[derive(Debug)]
+struct Response {
+	/// The file descriptor of the event
+	fd: i32,
+	/// [`FAN_ALLOW`] or [`FAN_DENY`]
+	response: u32,
+}
+
+/// The object a mark is attached to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MarkObject {
+	/// A file, identified by the device of its filesystem and its inode.
+	Inode {
+		/// The device of the filesystem
+		dev: u64,
+		/// The inode of the file
+		inode: INode,
+	},
+	/// Every file on the filesystem with the given device.
+	Filesystem(u64),
+}
+
+impl MarkObject {
+	/// Returns the object for the given node.
+	pub fn inode(node: &Node) -> Self {
+		Self::Inode {
+			dev: node.fs.dev,
+			inode: node.inode,
+		}
+	}
+
+	/// Tells whether the mark applies to `node`.
+	fn matches(&self, node: &Node) -> bool {
+		match *self {
+			Self::Inode {
+				dev,
+				inode,
+			} => dev == node.fs.dev && inode == node.inode,
+			Self::Filesystem(dev) => dev == node.fs.dev,
+		}
+	}
+}
+
+/// A mark, attaching a set of events to an object.
+#[derive(Debug)]
+struct Mark {
+	/// The marked object
+	object: MarkObject,
+	/// The events to report
+	mask: u64,
+	/// The events to ignore, even if another mark reports them
+	ignored: u64,
+}
+
+/// An event waiting to be read.
+#[derive(Debug)]
+struct Event {
+	/// The identifier of the event, used to match the response of permission events
+	id: u64,
+	/// The mask of events
+	mask: u64,
+	/// The accessed file. `None` for overflow events
+	entry: Option<Arc<vfs::Entry>>,
+	/// The PID of the process which caused the event
+	pid: Pid,
+}
+
+/// A permission event waiting for a response.
+#[derive(Debug)]
+struct PendingPermission {
+	/// The identifier of the event
+	id: u64,
+	/// The file descriptor the event has been reported with. `None` if not read yet
+	fd: Option<c_int>,
+	/// The response of the listener, if any
+	response: Option<u32>,
+}
+
+/// The state of a group.
+#[derive(Debug, Default)]
+struct GroupInner {
+	/// The marks of the group
+	marks: Vec<Mark>,
+	/// Events waiting to be read
+	queue: Vec<Event>,
+	/// Permission events waiting for a response
+	pending: Vec<PendingPermission>,
+	/// The ID of the next event
+	next_id: u64,
+	/// Tells whether the group has been closed
+	closed: bool,
+}
+
+impl GroupInner {
+	/// Returns the events of `mask` to report for `node`.
+	///
+	/// `parent` is the node of the directory containing `node`, if any.
+	fn event_mask(&self, node: &Node, parent: Option<&Node>, mask: u64) -> u64 {
+		let mut marked = 0;
+		let mut ignored = 0;
+		for mark in self.marks.iter() {
+			if mark.object.matches(node) {
+				marked |= mark.mask;
+				ignored |= mark.ignored;
+			} else if let Some(parent) = parent
+				&& mark.mask & FAN_EVENT_ON_CHILD != 0
+				&& mark.object == MarkObject::inode(parent)
+			{
+				marked |= mark.mask;
+			}
+		}
+		// Events on directories are reported only if requested
+		if node.get_type() == Some(FileType::Directory) && marked & FAN_ONDIR == 0 {
+			return 0;
+		}
+		mask & marked & !ignored & FAN_ALL_EVENTS
+	}
+
+	/// Adds the events `mask` to the mark on `object`, creating it if necessary.
+	///
+	/// If `ignored` is set, events are added to the ignored mask instead.
+	///
+	/// `limit` is the maximum number of marks on the group, if any.
+	fn add_mark(
+		&mut self,
+		object: MarkObject,
+		mask: u64,
+		ignored: bool,
+		limit: Option<usize>,
+	) -> EResult<()> {
+		let mark = match self.marks.iter_mut().find(|m| m.object == object) {
+			Some(mark) => mark,
+			None => {
+				if let Some(limit) = limit
+					&& self.marks.len() >= limit
+				{
+					return Err(errno!(ENOSPC));
+				}
+				self.marks.push(Mark {
+					object,
+					mask: 0,
+					ignored: 0,
+				})?;
+				self.marks.last_mut().unwrap()
+			}
+		};
+		if ignored {
+			mark.ignored |= mask;
+		} else {
+			mark.mask |= mask;
+		}
+		Ok(())
+	}
+
+	/// Removes the events `mask` from the mark on `object`. The mark is removed when no event
+	/// remains.
+	///
+	/// If `ignored` is set, events are removed from the ignored mask instead.
+	///
+	/// If no mark exists on `object`, the function returns [`errno::ENOENT`].
+	fn remove_mark(&mut self, object: MarkObject, mask: u64, ignored: bool) -> EResult<()> {
+		let i = self
+			.marks
+			.iter()
+			.position(|m| m.object == object)
+			.ok_or_else(|| errno!(ENOENT))?;
+		let mark = &mut self.marks[i];
+		if ignored {
+			mark.ignored &= !mask;
+		} else {
+			mark.mask &= !mask;
+		}
+		if mark.mask == 0 && mark.ignored == 0 {
+			self.marks.remove(i);
+		}
+		Ok(())
+	}
+
+	/// Queues an event. If the event is a permission event, a pending response is recorded.
+	///
+	/// `limit` is the maximum number of queued events, if any.
+	///
+	/// On success, the function returns the ID of the event. If the queue is full, the event is
+	/// dropped and the function returns `None`.
+	fn push(
+		&mut self,
+		entry: &Arc<vfs::Entry>,
+		mask: u64,
+		pid: Pid,
+		limit: Option<usize>,
+	) -> AllocResult<Option<u64>> {
+		let perm = mask & FAN_PERM_EVENTS != 0;
+		// Merge with the last event if it refers to the same access
+		if !perm
+			&& let Some(last) = self.queue.last_mut()
+			&& last.mask & (FAN_PERM_EVENTS | FAN_Q_OVERFLOW) == 0
+			&& last.pid == pid
+			&& last
+				.entry
+				.as_ref()
+				.is_some_and(|e| Arc::as_ptr(e) == Arc::as_ptr(entry))
+		{
+			last.mask |= mask;
+			return Ok(Some(last.id));
+		}
+		let id = self.next_id;
+		if let Some(limit) = limit
+			&& self.queue.len() >= limit
+		{
+			// Report the overflow once
+			if self
+				.queue
+				.last()
+				.is_none_or(|e| e.mask & FAN_Q_OVERFLOW == 0)
+			{
+				self.queue.push(Event {
+					id,
+					mask: FAN_Q_OVERFLOW,
+					entry: None,
+					pid: 0,
+				})?;
+				self.next_id += 1;
+			}
+			return Ok(None);
+		}
+		if perm {
+			self.pending.push(PendingPermission {
+				id,
+				fd: None,
+				response: None,
+			})?;
+		}
+		let res = self.queue.push(Event {
+			id,
+			mask,
+			entry: Some(entry.clone()),
+			pid,
+		});
+		if let Err(e) = res {
+			if perm {
+				self.pending.pop();
+			}
+			return Err(e);
+		}
+		self.next_id += 1;
+		Ok(Some(id))
+	}
+
+	/// Records `response` for the pending permission event `id`.
+	fn respond(&mut self, id: u64, response: u32) {
+		if let Some(pending) = self.pending.iter_mut().find(|p| p.id == id) {
+			pending.response = Some(response);
+		}
+	}
+}
+
+/// A fanotify group.
+#[derive(Debug)]
+pub struct Group {
+	/// Tells whether the group may receive permission events
+	pub permissions: bool,
+	/// Tells whether the number of queued events is unlimited
+	pub unlimited_queue: bool,
+	/// Tells whether the number of marks is unlimited
+	pub unlimited_marks: bool,
+	/// The flags of the open file descriptions created for events
+	pub event_f_flags: i32,
+
+	/// The state of the group
+	state: Spin<GroupInner>,
+	/// Queue of processes waiting for events
+	rd_queue: WaitQueue,
+	/// Queue of processes waiting for responses to permission events
+	perm_queue: WaitQueue,
+}
+
+/// The list of groups on the system.
+static GROUPS: Spin<Vec<Arc<Group>>> = Spin::new(Vec::new());
+/// The number of groups on the system, allowing to skip hooks when no group exist.
+static GROUPS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+impl Group {
+	/// Creates a new group and registers it.
+	///
+	/// If too many groups exist, the function returns [`errno::EMFILE`].
+	pub fn new(
+		permissions: bool,
+		unlimited_queue: bool,
+		unlimited_marks: bool,
+		event_f_flags: i32,
+	) -> EResult<Arc<Self>> {
+		let mut groups = GROUPS.lock();
+		if unlikely(groups.len() >= MAX_GROUPS) {
+			return Err(errno!(EMFILE));
+		}
+		let group = Arc::new(Self {
+			permissions,
+			unlimited_queue,
+			unlimited_marks,
+			event_f_flags,
+
+			state: Default::default(),
+			rd_queue: WaitQueue::new(),
+			perm_queue: WaitQueue::new(),
+		})?;
+		groups.push(group.clone())?;
+		GROUPS_COUNT.store(groups.len(), Release);
+		Ok(group)
+	}
+
+	/// Adds the events `mask` to the mark on `object`.
+	///
+	/// See [`GroupInner::add_mark`].
+	pub fn add_mark(&self, object: MarkObject, mask: u64, ignored: bool) -> EResult<()> {
+		let limit = (!self.unlimited_marks).then_some(MAX_MARKS);
+		self.state.lock().add_mark(object, mask, ignored, limit)
+	}
+
+	/// Removes the events `mask` from the mark on `object`.
+	///
+	/// See [`GroupInner::remove_mark`].
+	pub fn remove_mark(&self, object: MarkObject, mask: u64, ignored: bool) -> EResult<()> {
+		self.state.lock().remove_mark(object, mask, ignored)
+	}
+
+	/// Removes all the marks for which `f` returns `true`.
+	pub fn flush_marks<F: Fn(&MarkObject) -> bool>(&self, f: F) {
+		self.state.lock().marks.retain(|m| !f(&m.object));
+	}
+
+	/// Asks the listener for the permission to perform the access `mask` on `entry`, then waits
+	/// for the response.
+	///
+	/// If the access is denied, the function returns [`errno::EPERM`].
+	fn ask_permission(&self, entry: &Arc<vfs::Entry>, mask: u64, pid: Pid) -> EResult<()> {
+		let limit = (!self.unlimited_queue).then_some(MAX_QUEUED_EVENTS);
+		let id = {
+			let mut inner = self.state.lock();
+			if inner.closed {
+				return Ok(());
+			}
+			inner.push(entry, mask, pid, limit)?
+		};
+		self.rd_queue.wake_all();
+		// If the event could not be queued, allow the access
+		let Some(id) = id else {
+			return Ok(());
+		};
+		let res = self.perm_queue.wait_until(|| {
+			let mut inner = self.state.lock();
+			if inner.closed {
+				return Some(FAN_ALLOW);
+			}
+			let i = inner.pending.iter().position(|p| p.id == id)?;
+			let response = inner.pending[i].response?;
+			inner.pending.remove(i);
+			Some(response)
+		});
+		let response = match res {
+			Ok(r) => r,
+			Err(e) => {
+				// Interrupted: withdraw the event
+				let mut inner = self.state.lock();
+				inner.pending.retain(|p| p.id != id);
+				if let Some(i) = inner.queue.iter().position(|e| e.id == id) {
+					let event = inner.queue.remove(i);
+					drop(inner);
+					release_event(event);
+				}
+				return Err(e);
+			}
+		};
+		if response == FAN_ALLOW {
+			Ok(())
+		} else {
+			Err(errno!(EPERM))
+		}
+	}
+
+	/// Reports `event` to the current process, opening the accessed file on a new file
+	/// descriptor.
+	fn report(&self, event: Event) -> EResult<EventMetadata> {
+		let perm = event.mask & FAN_PERM_EVENTS != 0;
+		let fd = match event.entry {
+			Some(entry) => {
+				let res =
+					File::open(entry, self.event_f_flags | FMODE_NONOTIFY).and_then(|file| {
+						let fd_flags = if self.event_f_flags & O_CLOEXEC != 0 {
+							FD_CLOEXEC
+						} else {
+							0
+						};
+						let fd = Process::current()
+							.file_descriptors()
+							.create_fd(fd_flags, file)?;
+						Ok(fd as c_int)
+					});
+				match res {
+					Ok(fd) => fd,
+					Err(e) => {
+						// The listener cannot decide, deny the access
+						if perm {
+							self.state.lock().respond(event.id, FAN_DENY);
+							self.perm_queue.wake_all();
+						}
+						return Err(e);
+					}
+				}
+			}
+			None => FAN_NOFD,
+		};
+		if perm
+			&& let Some(pending) = self
+				.state
+				.lock()
+				.pending
+				.iter_mut()
+				.find(|p| p.id == event.id)
+		{
+			pending.fd = Some(fd);
+		}
+		Ok(EventMetadata {
+			event_len: size_of::<EventMetadata>() as _,
+			vers: FANOTIFY_METADATA_VERSION,
+			reserved: 0,
+			metadata_len: size_of::<EventMetadata>() as _,
+			mask: event.mask,
+			fd,
+			pid: event.pid as _,
+		})
+	}
+
+	/// Unregisters the group and allows all pending accesses.
+	fn close(&self) {
+		{
+			let mut groups = GROUPS.lock();
+			groups.retain(|g| !ptr::eq(Arc::as_ptr(g), self));
+			GROUPS_COUNT.store(groups.len(), Release);
+		}
+		let queue = {
+			let mut inner = self.state.lock();
+			inner.closed = true;
+			inner.marks.clear();
+			inner.pending.clear();
+			mem::take(&mut inner.queue)
+		};
+		self.perm_queue.wake_all();
+		self.rd_queue.wake_all();
+		for event in queue {
+			release_event(event);
+		}
+	}
+}
+
+/// Releases the resources held by an event that will not be read.
+fn release_event(event: Event) {
+	if let Some(entry) = event.entry {
+		let _ = vfs::Entry::release(entry);
+	}
+}
+
+/// File operations of a group, returned to userspace by `fanotify_init`.
+#[derive(Debug)]
+pub struct GroupFile(pub Arc<Group>);
+
+impl FileOps for GroupFile {
+	fn release(&self, _file: &File) {
+		self.0.close();
+	}
+
+	fn poll(&self, _file: &File, mask: u32) -> EResult<u32> {
+		let events = if !self.0.state.lock().queue.is_empty() {
+			POLLIN | POLLRDNORM
+		} else {
+			0
+		};
+		Ok(events & mask)
+	}
+
+	fn read(&self, file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		const LEN: usize = size_of::<EventMetadata>();
+		if unlikely(buf.len() < LEN) {
+			return Err(errno!(EINVAL));
+		}
+		let group = &self.0;
+		// Wait for the first event
+		let mut event = Some(group.rd_queue.wait_until(|| {
+			let mut inner = group.state.lock();
+			if !inner.queue.is_empty() {
+				return Some(Ok(inner.queue.remove(0)));
+			}
+			if file.get_flags() & O_NONBLOCK != 0 {
+				Some(Err(errno!(EAGAIN)))
+			} else {
+				None
+			}
+		})??);
+		let mut off = 0;
+		while let Some(e) = event {
+			let metadata = match group.report(e) {
+				Ok(m) => m,
+				Err(e) if off == 0 => return Err(e),
+				Err(_) => break,
+			};
+			buf.copy_to_user(off, as_bytes(&metadata))?;
+			off += LEN;
+			if buf.len() - off < LEN {
+				break;
+			}
+			let mut inner = group.state.lock();
+			event = (!inner.queue.is_empty()).then(|| inner.queue.remove(0));
+		}
+		Ok(off)
+	}
+
+	fn write(&self, _file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		const LEN: usize = size_of::<Response>();
+		let mut raw = [0u8; LEN];
+		if unlikely(buf.copy_from_user(0, &mut raw)? < LEN) {
+			return Err(errno!(EINVAL));
+		}
+		let response = Response {
+			fd: i32::from_ne_bytes([raw[0], raw[1], raw[2], raw[3]]),
+			response: u32::from_ne_bytes([raw[4], raw[5], raw[6], raw[7]]),
+		};
+		if unlikely(response.fd < 0 || !matches!(response.response, FAN_ALLOW | FAN_DENY)) {
+			return Err(errno!(EINVAL));
+		}
+		{
+			let mut inner = self.0.state.lock();
+			let pending = inner
+				.pending
+				.iter_mut()
+				.find(|p| p.fd == Some(response.fd) && p.response.is_none())
+				.ok_or_else(|| errno!(ENOENT))?;
+			pending.response = Some(response.response);
+		}
+		self.0.perm_queue.wake_all();
+		Ok(LEN)
+	}
+}
+
+/// Calls `f` for each group with the events of `mask` it has to report for `entry`.
+///
+/// The list of groups is locked while `f` runs.
+fn for_each_group<F: FnMut(&Arc<Group>, u64) -> EResult<()>>(
+	entry: &vfs::Entry,
+	mask: u64,
+	mut f: F,
+) -> EResult<()> {
+	let Some(node) = entry.node.as_ref() else {
+		return Ok(());
+	};
+	let parent = entry.parent.as_ref().and_then(|p| p.node.as_deref());
+	for group in GROUPS.lock().iter() {
+		let mask = group.state.lock().event_mask(node, parent, mask);
+		if mask != 0 {
+			f(group, mask)?;
+		}
+	}
+	Ok(())
+}
+
+/// Reports the events `mask` on `entry` to the interested groups.
+pub fn notify(entry: &Arc<vfs::Entry>, mask: u64) {
+	if likely(GROUPS_COUNT.load(Relaxed) == 0) {
+		return;
+	}
+	let pid = Process::current().get_pid();
+	let _ = for_each_group(entry, mask, |group, mask| {
+		let limit = (!group.unlimited_queue).then_some(MAX_QUEUED_EVENTS);
+		let mut inner = group.state.lock();
+		if !inner.closed {
+			// On allocation failure, the event is lost
+			let _ = inner.push(entry, mask, pid, limit);
+			drop(inner);
+			group.rd_queue.wake_all();
+		}
+		Ok(())
+	});
+}
+
+/// Asks the interested groups for the permission to perform the access `mask` on `entry`,
+/// waiting for their responses.
+///
+/// If a group denies the access, the function returns [`errno::EPERM`].
+pub fn permission(entry: &Arc<vfs::Entry>, mask: u64) -> EResult<()> {
+	if likely(GROUPS_COUNT.load(Relaxed) == 0) {
+		return Ok(());
+	}
+	// Collect groups first, since waiting for responses cannot be done with the list locked
+	let mut groups = Vec::new();
+	for_each_group(entry, mask & FAN_PERM_EVENTS, |group, mask| {
+		groups.push((group.clone(), mask))?;
+		Ok(())
+	})?;
+	let pid = Process::current().get_pid();
+	for (group, mask) in groups {
+		group.ask_permission(entry, mask, pid)?;
+	}
+	Ok(())
+}
+
+/// Like [`notify`], for an access through an open file description.
+pub fn notify_file(file: &File, mask: u64) {
+	if file.get_flags() & FMODE_NONOTIFY == 0 {
+		notify(&file.vfs_entry, mask);
+	}
+}
+
+/// Like [`permission`], for an access through an open file description.
+pub fn permission_file(file: &File, mask: u64) -> EResult<()> {
+	if file.get_flags() & FMODE_NONOTIFY == 0 {
+		permission(&file.vfs_entry, mask)
+	} else {
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::file::fs::{DummyOps, float};
+
+	#[test_case]
+	fn fanotify_marks() {
+		let mut inner = GroupInner::default();
+		let object = MarkObject::Filesystem(1);
+		inner
+			.add_mark(object, FAN_OPEN | FAN_ACCESS, false, Some(1))
+			.unwrap();
+		inner.add_mark(object, FAN_ACCESS, true, Some(1)).unwrap();
+		assert_eq!(
+			inner
+				.add_mark(MarkObject::Filesystem(2), FAN_OPEN, false, Some(1))
+				.unwrap_err()
+				.as_int(),
+			errno::ENOSPC
+		);
+		let ent = float::get_entry(DummyOps, FileType::Regular).unwrap();
+		let node = ent.node.as_ref().unwrap();
+		let object = MarkObject::Filesystem(node.fs.dev);
+		inner.marks.clear();
+		inner
+			.add_mark(object, FAN_OPEN | FAN_ACCESS, false, None)
+			.unwrap();
+		inner.add_mark(object, FAN_ACCESS, true, None).unwrap();
+		assert_eq!(
+			inner.event_mask(node, None, FAN_OPEN | FAN_ACCESS),
+			FAN_OPEN
+		);
+		inner.remove_mark(object, FAN_OPEN, false).unwrap();
+		assert_eq!(inner.event_mask(node, None, FAN_OPEN | FAN_ACCESS), 0);
+		inner.remove_mark(object, FAN_ACCESS, false).unwrap();
+		inner.remove_mark(object, FAN_ACCESS, true).unwrap();
+		assert!(inner.marks.is_empty());
+		assert_eq!(
+			inner
+				.remove_mark(object, FAN_ACCESS, false)
+				.unwrap_err()
+				.as_int(),
+			errno::ENOENT
+		);
+	}
+
+	#[test_case]
+	fn fanotify_queue() {
+		let mut inner = GroupInner::default();
+		let ent0 = float::get_entry(DummyOps, FileType::Regular).unwrap();
+		let ent1 = float::get_entry(DummyOps, FileType::Regular).unwrap();
+		// Events on the same file are merged
+		let id = inner.push(&ent0, FAN_OPEN, 1, Some(3)).unwrap();
+		assert_eq!(inner.push(&ent0, FAN_ACCESS, 1, Some(3)).unwrap(), id);
+		assert_eq!(inner.queue.len(), 1);
+		assert_eq!(inner.queue[0].mask, FAN_OPEN | FAN_ACCESS);
+		// Permission events are not
+		let perm = inner.push(&ent0, FAN_OPEN_PERM, 1, Some(3)).unwrap();
+		assert_ne!(perm, id);
+		assert_eq!(inner.pending.len(), 1);
+		inner.push(&ent1, FAN_OPEN, 1, Some(3)).unwrap();
+		// Overflow
+		assert_eq!(inner.push(&ent0, FAN_MODIFY, 2, Some(3)).unwrap(), None);
+		assert_eq!(inner.push(&ent1, FAN_MODIFY, 2, Some(3)).unwrap(), None);
+		assert_eq!(inner.queue.len(), 4);
+		assert_eq!(inner.queue[3].mask, FAN_Q_OVERFLOW);
+		// Response
+		inner.respond(perm.unwrap(), FAN_DENY);
+		assert_eq!(inner.pending[0].response, Some(FAN_DENY));
+	}
+}
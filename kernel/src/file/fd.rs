@@ -21,12 +21,31 @@
 //! A file descriptor is an ID held by a process pointing to an entry in the
 //! open file description table.
 
-use crate::{file::File, process::Process};
-use core::{cmp::max, ffi::c_int, mem};
+use crate::{
+	file::File,
+	process::{Process, pid::Pid},
+	sync::{
+		rcu,
+		rcu::ReadGuard,
+		spin::{Spin, SpinGuard},
+	},
+};
+use core::{
+	cmp::{max, min},
+	ffi::c_int,
+	hint,
+	mem::ManuallyDrop,
+	ptr::null_mut,
+	sync::atomic::{
+		AtomicI32, AtomicPtr, AtomicUsize,
+		Ordering::{Acquire, Relaxed, Release, SeqCst},
+	},
+};
 use utils::{
+	boxed::Box,
 	collections::vec::Vec,
 	errno,
-	errno::{AllocResult, CollectResult, EResult},
+	errno::{AllocResult, EResult},
 	limits::OPEN_MAX,
 	ptr::arc::Arc,
 };
@@ -76,6 +95,16 @@ impl FileDescriptor {
 		&self.file
 	}
 
+	/// Releases the POSIX record locks held by `owner` on the file.
+	///
+	/// A process loses its record locks on a file as soon as it closes any of its descriptors
+	/// for it.
+	pub fn release_record_locks(&self, owner: Pid) {
+		if let Some(node) = self.file.vfs_entry.node.as_ref() {
+			node.record_locks.release_all(owner);
+		}
+	}
+
 	/// Closes the file descriptor.
 	///
 	/// If the file descriptor is the last reference to the underlying open file description, the
@@ -92,52 +121,204 @@ impl FileDescriptor {
 	}
 }
 
-/// A table of file descriptors.
+/// The number of locks serializing modifications of a [`FileDescriptorTable`].
+const LOCK_STRIPES: usize = 16;
+
+/// A slot of a [`FileDescriptorTable`], holding at most one file descriptor.
 #[derive(Default)]
-pub struct FileDescriptorTable(Vec<Option<FileDescriptor>>);
+struct Slot {
+	/// The open file description, obtained from [`Arc::into_raw`]. If null, the slot is free.
+	file: AtomicPtr<File>,
+	/// The file descriptor's flags.
+	flags: AtomicI32,
+	/// The number of lookups currently taking a reference to `file`.
+	readers: AtomicUsize,
+}
 
-impl FileDescriptorTable {
-	/// Returns the available file descriptor with the lowest ID.
+impl Slot {
+	/// Tells whether the slot is free.
+	fn is_free(&self) -> bool {
+		self.file.load(Relaxed).is_null()
+	}
+
+	/// Returns a copy of the file descriptor in the slot, if any.
 	///
-	/// If no ID is available, the function returns an error.
+	/// Since [`Self::replace`] waits for lookups to finish, this function must be called with
+	/// preemption disabled.
+	fn get(&self) -> Option<FileDescriptor> {
+		self.readers.fetch_add(1, SeqCst);
+		let file = self.file.load(SeqCst);
+		let fd = (!file.is_null()).then(|| {
+			// Safe because the reference owned by the slot cannot be released before `readers`
+			// drops to zero
+			let file = ManuallyDrop::new(unsafe { Arc::from_raw(file) });
+			FileDescriptor {
+				flags: self.flags.load(Relaxed),
+				file: Arc::clone(&file),
+			}
+		});
+		self.readers.fetch_sub(1, Release);
+		fd
+	}
+
+	/// Replaces the file descriptor in the slot with `fd`, returning the previous one.
 	///
-	/// `min` is the minimum value for the file descriptor to be returned.
-	fn get_available_fd(&self, min: Option<u32>) -> EResult<u32> {
-		let min = min.unwrap_or(0) as usize;
-		// Find a hole in the table
-		let fd = if min < self.0.len() {
-			self.0[min..]
-				.iter()
-				.enumerate()
-				.find(|(_, fd)| fd.is_none())
-				.map(|(i, _)| (min + i) as u32)
-		} else {
-			None
-		};
-		match fd {
-			Some(fd) => Ok(fd),
-			// No hole found, place the new FD at the end
-			None => {
-				let id = max(self.0.len(), min) as u32;
-				if id < OPEN_MAX {
-					Ok(id)
-				} else {
-					Err(errno!(EMFILE))
-				}
+	/// The caller must hold the lock of the slot.
+	fn replace(&self, fd: Option<FileDescriptor>) -> Option<FileDescriptor> {
+		let flags = self.flags.load(Relaxed);
+		let new = match fd {
+			Some(fd) => {
+				self.flags.store(fd.flags, Relaxed);
+				Arc::into_raw(fd.file) as *mut _
 			}
+			None => null_mut(),
+		};
+		let old = self.file.swap(new, SeqCst);
+		if old.is_null() {
+			return None;
 		}
+		// Wait for lookups that may still be taking a reference to the previous file
+		while self.readers.load(SeqCst) != 0 {
+			hint::spin_loop();
+		}
+		Some(FileDescriptor {
+			flags,
+			file: unsafe { Arc::from_raw(old) },
+		})
+	}
+}
+
+/// A table of file descriptors, shared between the threads of a process.
+///
+/// Lookups do not take any lock: the array of slots is published with RCU, and a lookup only
+/// announces itself on the slot it reads, so that a concurrent close does not release the file
+/// under its feet. Operations creating, closing or modifying a file descriptor take the lock of
+/// its slot, among [`LOCK_STRIPES`] locks, so that operations on different file descriptors
+/// rarely contend.
+///
+/// Slots are never moved nor freed while the table is alive. Growing the table allocates a larger
+/// array pointing to the same slots, and releases the previous array after a grace period.
+#[derive(Default)]
+pub struct FileDescriptorTable {
+	/// The array of slots, obtained from [`Box::into_raw`]. If null, the table is empty.
+	slots: AtomicPtr<Vec<Arc<Slot>>>,
+	/// The locks of slots. Slot `i` is protected by lock `i % LOCK_STRIPES`.
+	locks: [Spin<()>; LOCK_STRIPES],
+	/// Lock serializing growths of the table.
+	grow_lock: Spin<()>,
+}
+
+impl FileDescriptorTable {
+	/// Returns the array of slots.
+	///
+	/// The array remains valid as long as `guard` is alive.
+	fn slots<'g>(&self, _guard: &'g ReadGuard) -> &'g [Arc<Slot>] {
+		let slots = self.slots.load(Acquire);
+		// Safe because a replaced array is reclaimed only after the end of the grace period
+		unsafe { slots.as_ref() }.map(Vec::as_slice).unwrap_or(&[])
+	}
+
+	/// Returns the number of slots in the table.
+	fn len(&self) -> usize {
+		self.slots(&rcu::read_lock()).len()
+	}
+
+	/// Returns the slot with ID `id`, if it exists.
+	fn slot(&self, id: usize) -> Option<Arc<Slot>> {
+		self.slots(&rcu::read_lock()).get(id).cloned()
+	}
+
+	/// Locks the slot with ID `id`.
+	fn lock(&self, id: usize) -> SpinGuard<'_, (), true> {
+		self.locks[id % LOCK_STRIPES].lock()
+	}
+
+	/// Returns a copy of the file descriptor with ID `id`, if it exists.
+	fn get(&self, id: usize) -> Option<FileDescriptor> {
+		let guard = rcu::read_lock();
+		self.slots(&guard).get(id)?.get()
 	}
 
 	/// Extends the file descriptor table if necessary so that it can fit the given ID.
 	///
 	/// If the table is already large enough, this function is a no-op.
-	fn extend(&mut self, id: u32) -> AllocResult<()> {
+	fn extend(&self, id: u32) -> AllocResult<()> {
 		let id = id as usize;
+		let guard = self.grow_lock.lock();
+		// Safe because the array is replaced only while holding `grow_lock`
+		let slots = unsafe { self.slots.load(Acquire).as_ref() }
+			.map(Vec::as_slice)
+			.unwrap_or(&[]);
 		// The ID fits. Do nothing
-		if id < self.0.len() {
+		if id < slots.len() {
 			return Ok(());
 		}
-		self.0.resize(id + 1, None)
+		let len = max(id + 1, slots.len() * 2).min(OPEN_MAX as usize);
+		let mut new = Vec::with_capacity(len)?;
+		for slot in slots {
+			new.push(slot.clone())?;
+		}
+		for _ in slots.len()..len {
+			new.push(Arc::new(Slot::default())?)?;
+		}
+		let new = Box::into_raw(Box::new(new)?);
+		let old = self.slots.swap(new, Release);
+		drop(guard);
+		if !old.is_null() {
+			rcu::defer_drop(unsafe { Box::from_raw(old) });
+		}
+		Ok(())
+	}
+
+	/// Replaces the file descriptor with ID `id` with `fd`, returning the previous one.
+	///
+	/// The table is extended if necessary.
+	fn replace(&self, id: u32, fd: FileDescriptor) -> AllocResult<Option<FileDescriptor>> {
+		self.extend(id)?;
+		let id = id as usize;
+		// Cannot fail since the table has been extended
+		let slot = self.slot(id).unwrap();
+		let _lock = self.lock(id);
+		Ok(slot.replace(Some(fd)))
+	}
+
+	/// Inserts `fd` in the free slot with the lowest ID, starting from `min`.
+	///
+	/// If no ID is available, the function returns an error.
+	///
+	/// The function returns the ID of the new file descriptor.
+	fn insert(&self, min: u32, fd: FileDescriptor) -> EResult<u32> {
+		let mut min = min as usize;
+		loop {
+			// Find a hole in the table
+			let (len, free) = {
+				let guard = rcu::read_lock();
+				let slots = self.slots(&guard);
+				let free = slots
+					.iter()
+					.enumerate()
+					.skip(min)
+					.find(|(_, slot)| slot.is_free())
+					.map(|(id, slot)| (id, slot.clone()));
+				(slots.len(), free)
+			};
+			let Some((id, slot)) = free else {
+				// No hole found, place the new FD at the end
+				let id = max(len, min) as u32;
+				if id >= OPEN_MAX {
+					return Err(errno!(EMFILE));
+				}
+				self.extend(id)?;
+				continue;
+			};
+			let _lock = self.lock(id);
+			if slot.is_free() {
+				slot.replace(Some(fd));
+				return Ok(id as u32);
+			}
+			// Another thread took the slot in the meantime
+			min = id + 1;
+		}
 	}
 
 	/// Creates a file descriptor.
@@ -146,59 +327,68 @@ impl FileDescriptorTable {
 	/// - `flags` are the file descriptor's flags
 	/// - `file` is the file associated with the file descriptor
 	///
-	/// The function returns the ID of the new file descriptor alongside a reference to it.
-	pub fn create_fd(&mut self, flags: i32, file: Arc<File>) -> EResult<(u32, &FileDescriptor)> {
-		let id = self.get_available_fd(None)?;
+	/// The function returns the ID of the new file descriptor.
+	pub fn create_fd(&self, flags: i32, file: Arc<File>) -> EResult<u32> {
 		let fd = FileDescriptor::new(flags, file)?;
-		// Insert the FD
-		self.extend(id)?;
-		let fd = self.0[id as usize].insert(fd);
-		Ok((id, fd))
+		self.insert(0, fd)
 	}
 
-	/// Creates a pair of file descriptors. The `flags` field is set to zero for both.
+	/// Creates a pair of file descriptors.
 	///
 	/// This function is a helper for system calls that create pipe or pipe-like objects. It allows
-	/// to ensure the first file descriptor is not created if the creation of the second fails.
+	/// to ensure the first file descriptor is not left open if the creation of the second fails.
 	///
 	/// Arguments:
+	/// - `flags` are the flags of both file descriptors
 	/// - `file0` is the file associated with the first file descriptor
 	/// - `file1` is the file associated with the second file descriptor
 	///
 	/// The function returns the IDs of the new file descriptors.
-	pub fn create_fd_pair(&mut self, file0: Arc<File>, file1: Arc<File>) -> EResult<(u32, u32)> {
-		let id0 = self.get_available_fd(None)?;
-		// Add a constraint to avoid using twice the same ID
-		let id1 = self.get_available_fd(Some(id0 + 1))?;
-		let fd0 = FileDescriptor::new(0, file0)?;
-		let fd1 = FileDescriptor::new(0, file1)?;
-		// Insert the FDs
-		self.extend(id1)?; // `id1` is always larger than `id0`
-		self.0[id0 as usize] = Some(fd0);
-		self.0[id1 as usize] = Some(fd1);
-		Ok((id0, id1))
-	}
-
-	/// Returns an immutable reference to the file descriptor with ID `id`.
+	pub fn create_fd_pair(
+		&self,
+		flags: i32,
+		file0: Arc<File>,
+		file1: Arc<File>,
+	) -> EResult<(u32, u32)> {
+		let fd0 = FileDescriptor::new(flags, file0)?;
+		let fd1 = FileDescriptor::new(flags, file1)?;
+		let id0 = self.insert(0, fd0)?;
+		match self.insert(id0 + 1, fd1) {
+			Ok(id1) => Ok((id0, id1)),
+			Err(e) => {
+				let _ = self.close_fd(id0 as _);
+				Err(e)
+			}
+		}
+	}
+
+	/// Returns a copy of the file descriptor with ID `id`.
 	///
 	/// If the file descriptor does not exist, the function returns [`errno::EBADF`].
-	pub fn get_fd(&self, id: c_int) -> EResult<&FileDescriptor> {
+	pub fn get_fd(&self, id: c_int) -> EResult<FileDescriptor> {
 		let id: usize = id.try_into().map_err(|_| errno!(EBADF))?;
-		self.0
-			.get(id)
-			.and_then(Option::as_ref)
-			.ok_or_else(|| errno!(EBADF))
+		self.get(id).ok_or_else(|| errno!(EBADF))
 	}
 
-	/// Returns a mutable reference to the file descriptor with ID `id`.
+	/// Sets the flags of the file descriptor with ID `id`.
 	///
 	/// If the file descriptor does not exist, the function returns [`errno::EBADF`].
-	pub fn get_fd_mut(&mut self, id: c_int) -> EResult<&mut FileDescriptor> {
+	pub fn set_flags(&self, id: c_int, flags: i32) -> EResult<()> {
 		let id: usize = id.try_into().map_err(|_| errno!(EBADF))?;
-		self.0
-			.get_mut(id)
-			.and_then(Option::as_mut)
-			.ok_or_else(|| errno!(EBADF))
+		let slot = self.slot(id).ok_or_else(|| errno!(EBADF))?;
+		let _lock = self.lock(id);
+		if slot.is_free() {
+			return Err(errno!(EBADF));
+		}
+		slot.flags.store(flags, Relaxed);
+		Ok(())
+	}
+
+	/// Returns an iterator over the file descriptors of the table, alongside their respective ID.
+	///
+	/// File descriptors created or closed during the iteration may or may not be returned.
+	pub fn iter(&self) -> impl Iterator<Item = (u32, FileDescriptor)> + '_ {
+		(0..self.len()).filter_map(|id| Some((id as u32, self.get(id)?)))
 	}
 
 	/// Duplicates the file descriptor with id `id`.
@@ -207,41 +397,48 @@ impl FileDescriptorTable {
 	/// - `constraint` is the constraint the new file descriptor ID will follow.
 	/// - `cloexec` tells whether the new file descriptor has the `FD_CLOEXEC` flag enabled.
 	///
-	/// The function returns the ID of the new file descriptor alongside a reference to it.
+	/// The new file descriptor is created atomically with its flags, so that another thread
+	/// executing a program cannot inherit it with the wrong flags.
+	///
+	/// If the constraint cannot be satisfied by any valid ID, the function returns
+	/// [`errno::EBADF`] for [`NewFDConstraint::Fixed`] and [`errno::EINVAL`] for
+	/// [`NewFDConstraint::Min`].
+	///
+	/// The function returns the ID of the new file descriptor.
 	pub fn duplicate_fd(
-		&mut self,
+		&self,
 		id: c_int,
 		constraint: NewFDConstraint,
 		cloexec: bool,
-	) -> EResult<(u32, &FileDescriptor)> {
-		// The ID of the new FD
-		let new_id = match constraint {
-			NewFDConstraint::None => self.get_available_fd(None)?,
+	) -> EResult<u32> {
+		// Validate the constraint before looking up the old FD
+		let fixed = match constraint {
 			NewFDConstraint::Fixed(id) => {
 				let id: u32 = id.try_into().map_err(|_| errno!(EBADF))?;
 				if id >= OPEN_MAX {
-					return Err(errno!(EMFILE));
+					return Err(errno!(EBADF));
 				}
-				id
+				Some(id)
 			}
-			NewFDConstraint::Min(min) => self.get_available_fd(Some(min))?,
+			NewFDConstraint::Min(min) if min >= OPEN_MAX => return Err(errno!(EINVAL)),
+			_ => None,
 		};
-		// The old FD
-		let old_fd = self.get_fd(id)?;
 		// Create the new FD
-		let mut new_fd = old_fd.clone();
-		let flags = if cloexec { FD_CLOEXEC } else { 0 };
-		new_fd.flags = flags;
-		// Make sure the table is large enough
-		self.extend(new_id)?;
+		let mut new_fd = self.get_fd(id)?;
+		new_fd.flags = if cloexec { FD_CLOEXEC } else { 0 };
+		let Some(new_id) = fixed else {
+			let min = match constraint {
+				NewFDConstraint::Min(min) => min,
+				_ => 0,
+			};
+			return self.insert(min, new_fd);
+		};
 		// If there was a file descriptor in the slot, close it
-		let slot = &mut self.0[new_id as usize];
-		if let Some(prev) = slot.take() {
+		if let Some(prev) = self.replace(new_id, new_fd)? {
+			prev.release_record_locks(Process::current().get_pid());
 			let _ = prev.close();
 		}
-		// Insert the FD
-		let new_fd = slot.insert(new_fd);
-		Ok((new_id, new_fd))
+		Ok(new_id)
 	}
 
 	/// Duplicates the whole file descriptors table.
@@ -249,49 +446,83 @@ impl FileDescriptorTable {
 	/// `cloexec` specifies whether the cloexec flag must be taken into account. This is the case
 	/// when executing a program.
 	pub fn duplicate(&self, cloexec: bool) -> EResult<Self> {
+		let new = Self::default();
+		// cloexec implies the FD's cloexec flag must be clear
 		let fds = self
-			.0
 			.iter()
-			.cloned()
-			.map(|fd| {
-				fd.filter(|fd| {
-					// cloexec implies the FD's cloexec flag must be clear
-					!cloexec || fd.flags & FD_CLOEXEC == 0
-				})
-			})
-			.collect::<CollectResult<Vec<_>>>()
-			.0?;
-		Ok(Self(fds))
+			.filter(|(_, fd)| !cloexec || fd.flags & FD_CLOEXEC == 0);
+		for (id, fd) in fds {
+			new.replace(id, fd)?;
+		}
+		Ok(new)
+	}
+
+	/// Releases the POSIX record locks held by `owner` on the files of the table.
+	///
+	/// `cloexec` specifies whether only the files of descriptors with the cloexec flag are
+	/// concerned. This is the case when executing a program.
+	pub fn release_record_locks(&self, owner: Pid, cloexec: bool) {
+		self.iter()
+			.filter(|(_, fd)| !cloexec || fd.flags & FD_CLOEXEC != 0)
+			.for_each(|(_, fd)| fd.release_record_locks(owner));
 	}
 
 	/// Closes the file descriptor with the ID `id`.
 	///
 	/// If the file descriptor does not exist, the function returns [`errno::EBADF`].
-	pub fn close_fd(&mut self, id: c_int) -> EResult<()> {
+	pub fn close_fd(&self, id: c_int) -> EResult<()> {
 		let id: usize = id.try_into().map_err(|_| errno!(EBADF))?;
-		let fd = self.0.get_mut(id).ok_or_else(|| errno!(EBADF))?;
+		let slot = self.slot(id).ok_or_else(|| errno!(EBADF))?;
 		// Remove FD from table
-		let Some(fd) = fd.take() else {
-			return Err(errno!(EBADF));
+		let fd = {
+			let _lock = self.lock(id);
+			slot.replace(None)
 		};
-		// Shrink the table if necessary
-		let new_len = self
-			.0
-			.iter()
-			.enumerate()
-			.rfind(|(_, fd)| fd.is_some())
-			.map(|(i, _)| i + 1)
-			.unwrap_or(0);
-		self.0.truncate(new_len);
+		let fd = fd.ok_or_else(|| errno!(EBADF))?;
 		// Close FD
+		fd.release_record_locks(Process::current().get_pid());
 		fd.close()
 	}
+
+	/// Closes the file descriptors with an ID in the range `first..=last`.
+	///
+	/// If `cloexec` is set, the file descriptors are not closed but have their `FD_CLOEXEC` flag
+	/// set instead.
+	///
+	/// Errors occurring while closing files are ignored.
+	pub fn close_range(&self, first: u32, last: u32, cloexec: bool) {
+		let end = min((last as usize).saturating_add(1), self.len());
+		let pid = Process::current().get_pid();
+		for id in first as usize..end {
+			// Cannot fail since the table never shrinks
+			let slot = self.slot(id).unwrap();
+			let fd = {
+				let _lock = self.lock(id);
+				if cloexec {
+					if !slot.is_free() {
+						slot.flags.fetch_or(FD_CLOEXEC, Relaxed);
+					}
+					None
+				} else {
+					slot.replace(None)
+				}
+			};
+			if let Some(fd) = fd {
+				fd.release_record_locks(pid);
+				let _ = fd.close();
+			}
+		}
+	}
 }
 
 impl Drop for FileDescriptorTable {
 	fn drop(&mut self) {
-		let fds = mem::take(&mut self.0);
-		for fd in fds.into_iter().flatten() {
+		let slots = *self.slots.get_mut();
+		if slots.is_null() {
+			return;
+		}
+		let slots = unsafe { Box::from_raw(slots) };
+		for fd in slots.iter().filter_map(|slot| slot.replace(None)) {
 			let _ = fd.close();
 		}
 	}
@@ -302,13 +533,8 @@ impl Drop for FileDescriptorTable {
 ///
 /// If the file descriptor does not exist, the function returns [`errno::EBADF`].
 pub fn fd_to_file(fd: c_int) -> EResult<Arc<File>> {
-	let file = Process::current()
-		.file_descriptors()
-		.lock()
-		.get_fd(fd)?
-		.get_file()
-		.clone();
-	Ok(file)
+	let fd = Process::current().file_descriptors().get_fd(fd)?;
+	Ok(fd.file)
 }
 
 #[cfg(test)]
@@ -327,35 +553,97 @@ mod test {
 
 	#[test_case]
 	fn fd_create0() {
-		let mut fds = FileDescriptorTable::default();
-		let (id, _) = fds.create_fd(0, dummy_file()).unwrap();
+		let fds = FileDescriptorTable::default();
+		let id = fds.create_fd(0, dummy_file()).unwrap();
 		assert_eq!(id, 0);
 	}
 
 	#[test_case]
 	fn fd_create1() {
-		let mut fds = FileDescriptorTable::default();
-		let (id, _) = fds.create_fd(0, dummy_file()).unwrap();
+		let fds = FileDescriptorTable::default();
+		let id = fds.create_fd(0, dummy_file()).unwrap();
 		assert_eq!(id, 0);
-		let (id, _) = fds.create_fd(0, dummy_file()).unwrap();
+		let id = fds.create_fd(0, dummy_file()).unwrap();
 		assert_eq!(id, 1);
 	}
 
 	#[test_case]
 	fn fd_dup() {
-		let mut fds = FileDescriptorTable::default();
-		let (id, _) = fds.create_fd(0, dummy_file()).unwrap();
+		let fds = FileDescriptorTable::default();
+		let id = fds.create_fd(0, dummy_file()).unwrap();
 		assert_eq!(id, 0);
-		let (id0, _) = fds.duplicate_fd(0, NewFDConstraint::None, false).unwrap();
+		let id0 = fds.duplicate_fd(0, NewFDConstraint::None, false).unwrap();
 		assert_ne!(id0, 0);
-		let (id1, _) = fds
+		let id1 = fds
 			.duplicate_fd(0, NewFDConstraint::Fixed(16), false)
 			.unwrap();
 		assert_eq!(id1, 16);
-		let (id2, _) = fds.duplicate_fd(0, NewFDConstraint::Min(8), false).unwrap();
+		let id2 = fds.duplicate_fd(0, NewFDConstraint::Min(8), false).unwrap();
 		assert!(id2 >= 8);
-		let (id3, _) = fds.duplicate_fd(0, NewFDConstraint::Min(8), false).unwrap();
+		let id3 = fds.duplicate_fd(0, NewFDConstraint::Min(8), false).unwrap();
 		assert!(id3 >= 8);
 		assert_ne!(id3, id2);
 	}
+
+	#[test_case]
+	fn fd_dup_cloexec() {
+		let fds = FileDescriptorTable::default();
+		fds.create_fd(FD_CLOEXEC, dummy_file()).unwrap();
+		let id = fds.duplicate_fd(0, NewFDConstraint::None, false).unwrap();
+		assert_eq!(fds.get_fd(id as _).unwrap().flags, 0);
+		let id = fds.duplicate_fd(1, NewFDConstraint::Min(4), true).unwrap();
+		assert_eq!(id, 4);
+		assert_eq!(fds.get_fd(4).unwrap().flags, FD_CLOEXEC);
+		let id = fds
+			.duplicate_fd(0, NewFDConstraint::Fixed(4), false)
+			.unwrap();
+		assert_eq!(id, 4);
+		assert_eq!(fds.get_fd(4).unwrap().flags, 0);
+		let res = fds.duplicate_fd(0, NewFDConstraint::Fixed(OPEN_MAX as _), true);
+		assert_eq!(res.unwrap_err().as_int(), errno::EBADF);
+		let res = fds.duplicate_fd(0, NewFDConstraint::Min(OPEN_MAX), true);
+		assert_eq!(res.unwrap_err().as_int(), errno::EINVAL);
+		let res = fds.duplicate_fd(8, NewFDConstraint::None, true);
+		assert_eq!(res.unwrap_err().as_int(), errno::EBADF);
+	}
+
+	#[test_case]
+	fn fd_close_range() {
+		let fds = FileDescriptorTable::default();
+		for _ in 0..4 {
+			fds.create_fd(0, dummy_file()).unwrap();
+		}
+		fds.close_range(1, 2, true);
+		assert_eq!(fds.get_fd(0).unwrap().flags, 0);
+		assert_eq!(fds.get_fd(1).unwrap().flags, FD_CLOEXEC);
+		assert_eq!(fds.get_fd(2).unwrap().flags, FD_CLOEXEC);
+		assert_eq!(fds.get_fd(3).unwrap().flags, 0);
+		fds.close_range(1, 2, false);
+		assert!(fds.get_fd(0).is_ok());
+		assert!(fds.get_fd(1).is_err());
+		assert!(fds.get_fd(2).is_err());
+		assert!(fds.get_fd(3).is_ok());
+		fds.close_range(3, u32::MAX, false);
+		assert_eq!(fds.iter().count(), 1);
+		// Closing no file descriptor is not an error
+		fds.close_range(8, 16, false);
+		let id = fds.create_fd(0, dummy_file()).unwrap();
+		assert_eq!(id, 1);
+	}
+
+	#[test_case]
+	fn fd_grow() {
+		let fds = FileDescriptorTable::default();
+		for i in 0..64 {
+			let id = fds.create_fd(i, dummy_file()).unwrap();
+			assert_eq!(id, i as u32);
+		}
+		for i in 0..64 {
+			assert_eq!(fds.get_fd(i).unwrap().flags, i);
+		}
+		fds.close_fd(8).unwrap();
+		assert_eq!(fds.close_fd(8).unwrap_err().as_int(), errno::EBADF);
+		let (id0, id1) = fds.create_fd_pair(0, dummy_file(), dummy_file()).unwrap();
+		assert_eq!((id0, id1), (8, 64));
+	}
 }
@@ -0,0 +1,218 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Mapping of the blocks of files to their location on the device of their filesystem, exposed
+//! to userspace through the `FIBMAP` and `FS_IOC_FIEMAP` ioctls.
+//!
+//! Tools such as `filefrag`, defragmenters or bootloader installers use it to locate files on
+//! disk.
+
+use crate::{
+	file::{File, FileType, vfs::node::Node},
+	memory::user::{UserPtr, UserSlice},
+	syscall::FromSyscallArg,
+};
+use core::{
+	ffi::{c_int, c_void},
+	hint::unlikely,
+	slice,
+};
+use utils::{errno, errno::EResult};
+
+/// `fm_flags`: Synchronizes the file before mapping it.
+const FIEMAP_FLAG_SYNC: u32 = 0x1;
+
+/// `fe_flags`: The extent is the last of the file.
+const FIEMAP_EXTENT_LAST: u32 = 0x1;
+/// `fe_flags`: The extent is made of blocks which are mapped individually by the filesystem.
+const FIEMAP_EXTENT_MERGED: u32 = 0x1000;
+
+/// The maximum number of extents that can be requested at once.
+const FIEMAP_MAX_EXTENTS: u32 = u32::MAX / size_of::<FiemapExtent>() as u32 - 1;
+
+/// Header of the argument of the `FS_IOC_FIEMAP` ioctl, followed by an array of
+/// [`FiemapExtent`].
+#[repr(C)]
+#[derive(Debug)]
+struct Fiemap {
+	/// The logical offset of the beginning of the range to map, in bytes.
+	fm_start: u64,
+	/// The length of the range to map, in bytes.
+	fm_length: u64,
+	/// Flags for the request.
+	fm_flags: u32,
+	/// The number of extents that have been mapped.
+	fm_mapped_extents: u32,
+	/// The size of the extents array. If zero, extents are only counted.
+	fm_extent_count: u32,
+	/// Reserved.
+	fm_reserved: u32,
+}
+
+/// An extent of a file, returned by the `FS_IOC_FIEMAP` ioctl.
+#[repr(C)]
+#[derive(Debug, Default)]
+struct FiemapExtent {
+	/// The logical offset of the extent in the file, in bytes.
+	fe_logical: u64,
+	/// The offset of the extent on the device, in bytes.
+	fe_physical: u64,
+	/// The length of the extent, in bytes.
+	fe_length: u64,
+	/// Reserved.
+	fe_reserved64: [u64; 2],
+	/// Flags for the extent.
+	fe_flags: u32,
+	/// Reserved.
+	fe_reserved: [u32; 3],
+}
+
+/// Returns the size of the blocks of the filesystem `file` is located on.
+fn block_size(file: &File) -> EResult<u64> {
+	Ok(file.node().fs.ops.get_stat()?.block_size() as _)
+}
+
+/// Performs the `FIGETBSZ` ioctl, writing the block size of the filesystem to `argp`.
+pub fn getbsz(file: &File, argp: *const c_void) -> EResult<u32> {
+	let blk_size: c_int = block_size(file)?
+		.try_into()
+		.map_err(|_| errno!(EOVERFLOW))?;
+	UserPtr::<c_int>::from_ptr(argp as usize).copy_to_user(&blk_size)?;
+	Ok(0)
+}
+
+/// Performs the `FIBMAP` ioctl, translating the block number in `file` pointed to by `argp` to
+/// the block number on the device.
+///
+/// Blocks which are not mapped, such as holes, are translated to zero.
+pub fn fibmap(file: &File, argp: *const c_void) -> EResult<u32> {
+	if unlikely(file.stat().get_type() != Some(FileType::Regular)) {
+		return Err(errno!(EINVAL));
+	}
+	let ptr = UserPtr::<c_int>::from_ptr(argp as usize);
+	let blk = ptr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	let blk = blk.try_into().map_err(|_| errno!(EINVAL))?;
+	let node = file.node();
+	let phys = node.node_ops.bmap(node, blk)?.unwrap_or(0);
+	let phys: c_int = phys.try_into().map_err(|_| errno!(ERANGE))?;
+	ptr.copy_to_user(&phys)?;
+	Ok(0)
+}
+
+/// Returns the extent starting at the first mapped block of `node` in the range `blk..end`,
+/// along with the block following the extent.
+///
+/// `blk_size` is the size of a block in bytes.
+///
+/// If no block is mapped in the range, the function returns `None`.
+fn next_extent(
+	node: &Node,
+	mut blk: u64,
+	end: u64,
+	blk_size: u64,
+) -> EResult<Option<(FiemapExtent, u64)>> {
+	let phys = loop {
+		if blk >= end {
+			return Ok(None);
+		}
+		if let Some(phys) = node.node_ops.bmap(node, blk)? {
+			break phys;
+		}
+		blk += 1;
+	};
+	// Extend the extent while blocks are contiguous on the device
+	let mut len = 1;
+	while blk + len < end && node.node_ops.bmap(node, blk + len)? == Some(phys + len) {
+		len += 1;
+	}
+	let ext = FiemapExtent {
+		fe_logical: blk * blk_size,
+		fe_physical: phys * blk_size,
+		fe_length: len * blk_size,
+		fe_flags: FIEMAP_EXTENT_MERGED,
+		..Default::default()
+	};
+	Ok(Some((ext, blk + len)))
+}
+
+/// Performs the `FS_IOC_FIEMAP` ioctl, writing the extents of `file` in the range requested by
+/// the structure pointed to by `argp`.
+pub fn fiemap(file: &File, argp: *const c_void) -> EResult<u32> {
+	let stat = file.stat();
+	if unlikely(!matches!(
+		stat.get_type(),
+		Some(FileType::Regular | FileType::Directory)
+	)) {
+		return Err(errno!(EOPNOTSUPP));
+	}
+	let ptr = UserPtr::<Fiemap>::from_ptr(argp as usize);
+	let mut fiemap = ptr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if unlikely(fiemap.fm_extent_count > FIEMAP_MAX_EXTENTS || fiemap.fm_length == 0) {
+		return Err(errno!(EINVAL));
+	}
+	// Report unsupported flags
+	let unsupported = fiemap.fm_flags & !FIEMAP_FLAG_SYNC;
+	if unlikely(unsupported != 0) {
+		fiemap.fm_flags = unsupported;
+		ptr.copy_to_user(&fiemap)?;
+		return Err(errno!(EBADR));
+	}
+	let node = file.node();
+	if fiemap.fm_flags & FIEMAP_FLAG_SYNC != 0 {
+		node.sync_data()?;
+	}
+	let extents = UserSlice::from_user(
+		ptr.as_ptr().wrapping_add(1) as *mut FiemapExtent,
+		fiemap.fm_extent_count as _,
+	)?;
+	let blk_size = block_size(file)?;
+	let blocks = stat.size.div_ceil(blk_size);
+	let start = fiemap.fm_start / blk_size;
+	let end = fiemap
+		.fm_start
+		.saturating_add(fiemap.fm_length)
+		.div_ceil(blk_size)
+		.min(blocks);
+	// Each extent is written once the next one is known, to tell whether it is the last
+	let mut mapped = 0;
+	let mut prev: Option<FiemapExtent> = None;
+	let mut blk = start;
+	loop {
+		let next = next_extent(node, blk, end, blk_size)?;
+		if let Some(mut ext) = prev.take() {
+			if next.is_none() && end == blocks {
+				ext.fe_flags |= FIEMAP_EXTENT_LAST;
+			}
+			if fiemap.fm_extent_count > 0 {
+				extents.copy_to_user(mapped as _, slice::from_ref(&ext))?;
+			}
+			mapped += 1;
+		}
+		let Some((ext, next_blk)) = next else {
+			break;
+		};
+		if fiemap.fm_extent_count > 0 && mapped == fiemap.fm_extent_count {
+			break;
+		}
+		prev = Some(ext);
+		blk = next_blk;
+	}
+	fiemap.fm_mapped_extents = mapped;
+	ptr.copy_to_user(&fiemap)?;
+	Ok(0)
+}
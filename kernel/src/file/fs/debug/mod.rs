@@ -0,0 +1,450 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `debugfs` is a virtual filesystem exposing kernel internals to userspace, for debugging
+//! purposes. It is meant to be mounted at `/sys/kernel/debug`.
+//!
+//! Subsystems expose their state by registering files at slash-separated paths, with
+//! [`create_file`], [`create_u32`] or [`create_bool`]. Directories are implied by the paths of
+//! the files they contain.
+//!
+//! Each [relay channel](crate::file::relay) is also a directory at the root of the filesystem,
+//! containing a `cpuN` file for the buffer of each CPU.
+
+mod relay;
+
+use super::{DummyOps, Filesystem, FilesystemOps, FilesystemType, Statfs};
+use crate::{
+	device::BlkDev,
+	file,
+	file::{
+		DirContext, DirEntry, File, FileType, Mode, Stat,
+		fs::{FileOps, NodeOps, kernfs::static_dir_stat, options::MountOptions},
+		perm::{S_IRUSR, S_IWUSR},
+		vfs,
+		vfs::node::Node,
+	},
+	format_content,
+	memory::{buddy, cache, user::UserSlice},
+	module::param::ParamType,
+	sync::spin::Spin,
+};
+use core::{
+	fmt::Debug,
+	str,
+	sync::atomic::{
+		AtomicBool, AtomicU32,
+		Ordering::{Acquire, Release},
+	},
+};
+use utils::{
+	boxed::Box,
+	collections::{btreemap::BTreeMap, path::PathBuf, string::String, vec::Vec},
+	errno,
+	errno::{AllocResult, EResult},
+	limits::{NAME_MAX, PAGE_SIZE},
+	ptr::arc::Arc,
+	try_writeln,
+};
+
+/// The magic number of the debugfs.
+const DEBUGFS_MAGIC: u32 = 0x64626720;
+
+/// Permissions for a read-only file.
+pub const MODE_RO: Mode = S_IRUSR;
+/// Permissions for a file writable by the owner (root).
+pub const MODE_RW: Mode = S_IRUSR | S_IWUSR;
+
+/// A debugfs file.
+pub trait DebugFile: Debug + Sync {
+	/// Appends the content of the file to `buf`.
+	fn read(&self, buf: &mut String) -> AllocResult<()>;
+
+	/// Sets the value of the file from its text representation `val`.
+	///
+	/// The default implementation returns [`errno::EPERM`].
+	fn write(&self, val: &[u8]) -> EResult<()> {
+		let _ = val;
+		Err(errno!(EPERM))
+	}
+}
+
+/// Parses the text representation `val` of a value written to a file.
+fn parse<T: ParamType>(val: &[u8]) -> EResult<T> {
+	let val = str::from_utf8(val).map_err(|_| errno!(EINVAL))?.trim();
+	T::parse(Some(val.as_bytes())).ok_or_else(|| errno!(EINVAL))
+}
+
+impl DebugFile for AtomicU32 {
+	fn read(&self, buf: &mut String) -> AllocResult<()> {
+		try_writeln!(buf, "{}", self.load(Acquire))
+	}
+
+	fn write(&self, val: &[u8]) -> EResult<()> {
+		self.store(parse(val)?, Release);
+		Ok(())
+	}
+}
+
+impl DebugFile for AtomicBool {
+	fn read(&self, buf: &mut String) -> AllocResult<()> {
+		try_writeln!(buf, "{}", if self.load(Acquire) { 'Y' } else { 'N' })
+	}
+
+	fn write(&self, val: &[u8]) -> EResult<()> {
+		self.store(parse(val)?, Release);
+		Ok(())
+	}
+}
+
+/// A read-only file whose content is generated by a function.
+#[derive(Debug)]
+pub struct ShowFile(pub fn(&mut String) -> AllocResult<()>);
+
+impl DebugFile for ShowFile {
+	fn read(&self, buf: &mut String) -> AllocResult<()> {
+		(self.0)(buf)
+	}
+}
+
+/// A registered file.
+#[derive(Clone, Copy, Debug)]
+struct Registered {
+	/// The file permissions.
+	mode: Mode,
+	/// The file itself.
+	file: &'static dyn DebugFile,
+}
+
+/// Registered files, by path.
+static FILES: Spin<BTreeMap<&'static [u8], Registered>> = Spin::new(BTreeMap::new());
+
+/// Registers the file `file` at the slash-separated path `path`, with the permissions `mode`.
+///
+/// If a file is already registered at the same path, it is replaced.
+pub fn create_file(
+	path: &'static [u8],
+	mode: Mode,
+	file: &'static dyn DebugFile,
+) -> AllocResult<()> {
+	FILES.lock().insert(
+		path,
+		Registered {
+			mode,
+			file,
+		},
+	)?;
+	Ok(())
+}
+
+/// Registers a file exposing the integer `val` at `path`, with the permissions `mode`.
+pub fn create_u32(path: &'static [u8], mode: Mode, val: &'static AtomicU32) -> AllocResult<()> {
+	create_file(path, mode, val)
+}
+
+/// Registers a file exposing the boolean `val` at `path`, with the permissions `mode`.
+///
+/// The file reads as `Y` or `N`.
+pub fn create_bool(path: &'static [u8], mode: Mode, val: &'static AtomicBool) -> AllocResult<()> {
+	create_file(path, mode, val)
+}
+
+/// Unregisters the file at `path`.
+pub fn remove(path: &[u8]) {
+	FILES.lock().remove(path);
+}
+
+/// Returns the path of the given file relative to `prefix`, or `None` if not in `prefix`.
+///
+/// An empty `prefix` represents the root of the filesystem.
+fn strip_prefix<'p>(path: &'p [u8], prefix: &[u8]) -> Option<&'p [u8]> {
+	if prefix.is_empty() {
+		return Some(path);
+	}
+	path.strip_prefix(prefix)?.strip_prefix(b"/")
+}
+
+/// Returns the file registered at `path`, if any.
+fn get(path: &[u8]) -> Option<Registered> {
+	FILES.lock().get(path).copied()
+}
+
+/// Tells whether at least one file is located under `prefix`.
+fn is_dir(prefix: &[u8]) -> bool {
+	FILES
+		.lock()
+		.iter()
+		.any(|(path, _)| strip_prefix(path, prefix).is_some())
+}
+
+/// Returns the direct children of the directory `prefix`, along with whether each of them is a
+/// directory.
+fn children(prefix: &[u8]) -> AllocResult<Vec<(String, bool)>> {
+	let files = FILES.lock();
+	let mut children: Vec<(String, bool)> = Vec::new();
+	for (path, _) in files.iter() {
+		let Some(rel) = strip_prefix(path, prefix) else {
+			continue;
+		};
+		let (name, dir) = match rel.iter().position(|c| *c == b'/') {
+			Some(i) => (&rel[..i], true),
+			None => (rel, false),
+		};
+		// Entries are sorted, so entries sharing a subdirectory are next to each other
+		if matches!(children.last(), Some((prev, _)) if prev.as_bytes() == name) {
+			continue;
+		}
+		children.push((String::try_from(name)?, dir))?;
+	}
+	Ok(children)
+}
+
+/// A directory of the debugfs.
+///
+/// The inner value is the slash-separated path of the directory. It is empty for the root.
+#[derive(Debug, Default)]
+struct DebugDir(String);
+
+impl NodeOps for DebugDir {
+	fn lookup_entry(&self, dir: &Node, ent: &mut vfs::Entry) -> EResult<()> {
+		// Names containing slashes would allow to escape the directory's hierarchy
+		if ent.name.contains(&b'/') {
+			return Ok(());
+		}
+		let mut path = String::try_from(self.0.as_bytes())?;
+		if !path.is_empty() {
+			path.push(b'/')?;
+		}
+		path.push_str(&ent.name)?;
+		let (stat, node_ops, file_ops): (_, Box<dyn NodeOps>, Box<dyn FileOps>) =
+			if let Some(reg) = get(&path) {
+				let stat = Stat {
+					mode: FileType::Regular.to_mode() | reg.mode,
+					..Default::default()
+				};
+				(stat, Box::new(DummyOps)?, Box::new(DebugFileOps(reg.file))?)
+			} else if is_dir(&path) {
+				(
+					static_dir_stat(),
+					Box::new(DebugDir(path))?,
+					Box::new(DummyOps)?,
+				)
+			} else if let Some(chan) = self
+				.0
+				.is_empty()
+				.then(|| file::relay::get(&ent.name))
+				.flatten()
+			{
+				let stat = Stat {
+					mode: FileType::Directory.to_mode() | 0o500,
+					..Default::default()
+				};
+				(
+					stat,
+					Box::new(relay::ChannelDir(chan))?,
+					Box::new(DummyOps)?,
+				)
+			} else {
+				return Ok(());
+			};
+		ent.node = Some(Arc::new(Node::new(
+			0,
+			dir.fs.clone(),
+			stat,
+			node_ops,
+			file_ops,
+		))?);
+		Ok(())
+	}
+
+	fn iter_entries(&self, _dir: &Node, ctx: &mut DirContext) -> EResult<()> {
+		let mut children = children(self.0.as_bytes())?;
+		if self.0.is_empty() {
+			for name in file::relay::names()? {
+				children.push((name, true))?;
+			}
+		}
+		for (name, dir) in children.iter().skip(ctx.off as usize) {
+			let ent = DirEntry {
+				inode: 0,
+				entry_type: Some(if *dir {
+					FileType::Directory
+				} else {
+					FileType::Regular
+				}),
+				name,
+			};
+			if !(ctx.write)(&ent)? {
+				break;
+			}
+			ctx.off += 1;
+		}
+		Ok(())
+	}
+}
+
+/// Operations on a registered file.
+#[derive(Debug)]
+struct DebugFileOps(&'static dyn DebugFile);
+
+impl FileOps for DebugFileOps {
+	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let mut content = String::new();
+		self.0.read(&mut content)?;
+		format_content!(off, buf, "{content}")
+	}
+
+	fn write(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		// Partial writes are not supported
+		if off != 0 {
+			return Err(errno!(EINVAL));
+		}
+		let mut val = Vec::new();
+		val.resize(buf.len(), 0)?;
+		let len = buf.copy_from_user(0, &mut val)?;
+		self.0.write(&val[..len])?;
+		Ok(len)
+	}
+
+	fn truncate(&self, _file: &File, _size: u64) -> EResult<()> {
+		// Allow to open with `O_TRUNC`, as done by shells when redirecting output
+		Ok(())
+	}
+}
+
+/// A debugfs.
+#[derive(Debug)]
+pub struct DebugFS;
+
+impl FilesystemOps for DebugFS {
+	fn get_name(&self) -> &[u8] {
+		b"debugfs"
+	}
+
+	fn cache_entries(&self) -> bool {
+		false
+	}
+
+	fn get_stat(&self) -> EResult<Statfs> {
+		Ok(Statfs {
+			f_type: DEBUGFS_MAGIC,
+			f_bsize: PAGE_SIZE as _,
+			f_blocks: 0,
+			f_bfree: 0,
+			f_bavail: 0,
+			f_files: 0,
+			f_ffree: 0,
+			f_fsid: Default::default(),
+			f_namelen: NAME_MAX as _,
+			f_frsize: 0,
+			f_flags: 0,
+		})
+	}
+
+	fn root(&self, fs: &Arc<Filesystem>) -> EResult<Arc<Node>> {
+		Ok(Arc::new(Node::new(
+			0,
+			fs.clone(),
+			static_dir_stat(),
+			Box::new(DebugDir::default())?,
+			Box::new(DummyOps)?,
+		))?)
+	}
+
+	fn create_node(&self, _fs: &Arc<Filesystem>, _stat: Stat) -> EResult<Arc<Node>> {
+		Err(errno!(EINVAL))
+	}
+
+	fn destroy_node(&self, _node: &Node) -> EResult<()> {
+		Ok(())
+	}
+}
+
+/// The debugfs filesystem type.
+pub struct DebugFsType;
+
+impl FilesystemType for DebugFsType {
+	fn get_name(&self) -> &'static [u8] {
+		b"debugfs"
+	}
+
+	fn detect(&self, _dev: &Arc<BlkDev>) -> EResult<bool> {
+		Ok(false)
+	}
+
+	fn load_filesystem(
+		&self,
+		_dev: Option<Arc<BlkDev>>,
+		_mountpath: PathBuf,
+		_readonly: bool,
+		data: &[u8],
+	) -> EResult<Arc<Filesystem>> {
+		// The debugfs takes no option
+		if MountOptions::new(data).next().is_some() {
+			return Err(errno!(EINVAL));
+		}
+		Ok(Filesystem::new(0, Box::new(DebugFS)?)?)
+	}
+}
+
+/// Registers the files of subsystems which do not have an initialization stage of their own.
+pub(crate) fn init() -> AllocResult<()> {
+	create_file(b"buddy/free_lists", MODE_RO, &buddy::FREE_LISTS)?;
+	create_file(b"cache/stats", MODE_RO, &cache::STATS)?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use utils::errno::CollectResult;
+
+	static VAL: AtomicU32 = AtomicU32::new(0);
+	static FLAG: AtomicBool = AtomicBool::new(false);
+
+	#[test_case]
+	fn debugfs_files() {
+		create_u32(b"test/dir/val", MODE_RW, &VAL).unwrap();
+		create_bool(b"test/flag", MODE_RW, &FLAG).unwrap();
+		let children = children(b"test").unwrap();
+		let names: Vec<(&[u8], bool)> = children
+			.iter()
+			.map(|(name, dir)| (name.as_bytes(), *dir))
+			.collect::<CollectResult<_>>()
+			.0
+			.unwrap();
+		assert_eq!(names.as_slice(), &[(&b"dir"[..], true), (b"flag", false)]);
+		assert!(is_dir(b"test/dir"));
+		assert!(!is_dir(b"test/flag"));
+		// Values
+		let file = get(b"test/dir/val").unwrap().file;
+		file.write(b"0x2a\n").unwrap();
+		assert!(file.write(b"abc").is_err());
+		let mut buf = String::new();
+		file.read(&mut buf).unwrap();
+		assert_eq!(buf.as_bytes(), b"42\n");
+		let file = get(b"test/flag").unwrap().file;
+		file.write(b"y").unwrap();
+		let mut buf = String::new();
+		file.read(&mut buf).unwrap();
+		assert_eq!(buf.as_bytes(), b"Y\n");
+		remove(b"test/dir/val");
+		remove(b"test/flag");
+		assert!(!is_dir(b"test"));
+	}
+}
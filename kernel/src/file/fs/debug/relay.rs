@@ -0,0 +1,76 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The directory of each [relay channel](crate::file::relay), at the root of the debugfs.
+
+use crate::file::{
+	DirContext, FileType,
+	fs::{
+		NodeOps,
+		kernfs::{box_file, box_node, iter_names},
+	},
+	relay::{BufferFile, Channel},
+	vfs,
+	vfs::node::Node,
+};
+use core::str;
+use utils::{collections::vec::Vec, errno::EResult, format, ptr::arc::Arc};
+
+/// The directory of a relay channel.
+#[derive(Debug)]
+pub struct ChannelDir(pub Arc<Channel>);
+
+impl NodeOps for ChannelDir {
+	fn lookup_entry(&self, dir: &Node, ent: &mut vfs::Entry) -> EResult<()> {
+		let cpu = ent
+			.name
+			.strip_prefix(b"cpu")
+			.and_then(|n| str::from_utf8(n).ok())
+			.and_then(|n| n.parse::<usize>().ok())
+			.filter(|cpu| *cpu < self.0.buffers().len());
+		ent.node = cpu
+			.map(|cpu| {
+				let file = BufferFile {
+					chan: self.0.clone(),
+					cpu,
+				};
+				let stat = file.stat();
+				let node_ops = box_node(BufferFile {
+					chan: self.0.clone(),
+					cpu,
+				})?;
+				Arc::new(Node::new(
+					0,
+					dir.fs.clone(),
+					stat,
+					node_ops,
+					box_file(file)?,
+				))
+			})
+			.transpose()?;
+		Ok(())
+	}
+
+	fn iter_entries(&self, _dir: &Node, ctx: &mut DirContext) -> EResult<()> {
+		let mut names = Vec::with_capacity(self.0.buffers().len())?;
+		for cpu in 0..self.0.buffers().len() {
+			names.push(format!("cpu{cpu}")?)?;
+		}
+		iter_names(&names, FileType::Regular, ctx)
+	}
+}
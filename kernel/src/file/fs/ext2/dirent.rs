@@ -24,7 +24,7 @@ use crate::{
 	file::{FileType, fs::ext2::inode::Ext2INode},
 	memory::cache::RcPage,
 };
-use core::{hint::unlikely, mem::offset_of, ptr::NonNull};
+use core::{hint::unlikely, mem::offset_of, ptr::NonNull, sync::atomic::Ordering::Relaxed};
 use macros::AnyRepr;
 use utils::{
 	errno,
@@ -119,26 +119,26 @@ impl Dirent {
 
 	/// Reinterprets a slice of bytes as a directory entry.
 	///
-	/// `superblock` is the filesystem's superblock.
+	/// `fs` is the filesystem the entry belongs to.
 	///
-	/// If the entry is invalid, the function returns [`EUCLEAN`].
-	pub fn from_slice<'b>(slice: &'b mut [u8], superblock: &Superblock) -> EResult<&'b mut Self> {
+	/// If the entry is invalid, the function returns [`errno::EUCLEAN`].
+	pub fn from_slice<'b>(slice: &'b mut [u8], fs: &Ext2Fs) -> EResult<&'b mut Self> {
 		// Validation
 		if unlikely(slice.len() < NAME_OFF) {
-			return Err(errno!(EUCLEAN));
+			return Err(fs.corrupted());
 		}
 		// Read record's length
 		const REC_LEN_OFF: usize = offset_of!(Dirent, rec_len);
 		let rec_len = u16::from_le_bytes([slice[REC_LEN_OFF], slice[REC_LEN_OFF + 1]]) as usize;
 		// Validation
 		if unlikely(rec_len > slice.len() || rec_len < NAME_OFF || rec_len % ALIGN != 0) {
-			return Err(errno!(EUCLEAN));
+			return Err(fs.corrupted());
 		}
 		// Reinterpret
 		let ent = unsafe { &mut *(&mut slice[..rec_len] as *mut _ as *mut Self) };
 		// Validation
-		if unlikely(!ent.is_free() && NAME_OFF + ent.name_len(superblock) > rec_len) {
-			return Err(errno!(EUCLEAN));
+		if unlikely(!ent.is_free() && NAME_OFF + ent.name_len(&fs.sp) > rec_len) {
+			return Err(fs.corrupted());
 		}
 		Ok(ent)
 	}
@@ -147,7 +147,9 @@ impl Dirent {
 	///
 	/// `superblock` is the filesystem's superblock.
 	pub fn name_len(&self, superblock: &Superblock) -> usize {
-		if superblock.s_feature_incompat & super::REQUIRED_FEATURE_DIRECTORY_TYPE == 0 {
+		if superblock.s_feature_incompat.load(Relaxed) & super::REQUIRED_FEATURE_DIRECTORY_TYPE
+			== 0
+		{
 			((self.file_type as usize) << 8) | (self.name_len as usize)
 		} else {
 			self.name_len as usize
@@ -166,7 +168,9 @@ impl Dirent {
 	///
 	/// If the type cannot be retrieved from the entry directly, the function returns [`None`].
 	pub fn get_type(&self, superblock: &Superblock) -> Option<FileType> {
-		if superblock.s_feature_incompat & super::REQUIRED_FEATURE_DIRECTORY_TYPE == 0 {
+		if superblock.s_feature_incompat.load(Relaxed) & super::REQUIRED_FEATURE_DIRECTORY_TYPE
+			== 0
+		{
 			return None;
 		}
 		match self.file_type {
@@ -183,7 +187,9 @@ impl Dirent {
 
 	/// Sets the file type associated with the entry (if the option is enabled).
 	pub fn set_type(&mut self, superblock: &Superblock, file_type: Option<FileType>) {
-		if superblock.s_feature_incompat & super::REQUIRED_FEATURE_DIRECTORY_TYPE != 0 {
+		if superblock.s_feature_incompat.load(Relaxed) & super::REQUIRED_FEATURE_DIRECTORY_TYPE
+			!= 0
+		{
 			self.file_type = match file_type {
 				None => TYPE_INDICATOR_UNKNOWN,
 				Some(FileType::Regular) => TYPE_INDICATOR_REGULAR,
@@ -272,7 +278,7 @@ impl<'a> DirentIterator<'a> {
 		// Safe since the node is locked
 		let blk_slice = unsafe { blk.slice_mut() };
 		// Read entry
-		let ent = Dirent::from_slice(&mut blk_slice[inner_off..], &self.fs.sp)?;
+		let ent = Dirent::from_slice(&mut blk_slice[inner_off..], self.fs)?;
 		let prev_off = self.off;
 		self.off += ent.rec_len as u64;
 		// If on the next block, ensure the offset is at the beginning
@@ -0,0 +1,430 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Hash-indexed directories (htree) allow to find an entry without scanning the whole
+//! directory.
+//!
+//! The first block of an indexed directory contains the `.` and `..` entries, the `..` entry
+//! covering the rest of the block, which holds the root of the index. The index is a tree
+//! whose nodes are sorted arrays of `(hash, block)` pairs, each pointing to the directory block
+//! that contains the entries whose name hash is greater or equal to `hash`. Internal nodes are
+//! stored in blocks which appear as a single free entry to implementations that ignore the
+//! index, making the format compatible with linear lookups.
+//!
+//! The index is only used for lookups. Modifying the directory clears the
+//! [`INODE_FLAG_HASH_INDEXED`] flag, turning it back into a linear directory.
+//!
+//! [`INODE_FLAG_HASH_INDEXED`]: super::inode::INODE_FLAG_HASH_INDEXED
+
+use super::{Ext2Fs, dirent::DirentIterator, inode::Ext2INode};
+use crate::memory::cache::RcPage;
+use core::{cmp::min, hint::unlikely};
+use utils::{collections::vec::Vec, errno, errno::EResult};
+
+/// Hash version: legacy, with signed characters
+const DX_HASH_LEGACY: u8 = 0;
+/// Hash version: half MD4, with signed characters
+const DX_HASH_HALF_MD4: u8 = 1;
+/// Hash version: TEA, with signed characters
+const DX_HASH_TEA: u8 = 2;
+/// Hash version: legacy, with unsigned characters
+const DX_HASH_LEGACY_UNSIGNED: u8 = 3;
+/// Hash version: half MD4, with unsigned characters
+const DX_HASH_HALF_MD4_UNSIGNED: u8 = 4;
+/// Hash version: TEA, with unsigned characters
+const DX_HASH_TEA_UNSIGNED: u8 = 5;
+
+/// `s_flags`: The hash of directory indexes uses unsigned characters
+const FLAG_UNSIGNED_HASH: u32 = 0x2;
+
+/// The hash marking the end of the directory, which names cannot have.
+const HTREE_EOF: u32 = 0x7fffffff;
+
+/// The offset of the index information in the root block.
+const ROOT_INFO_OFF: usize = 24;
+/// The offset of the index entries in an internal node block.
+const NODE_ENTRIES_OFF: usize = 8;
+/// The size of an index entry in bytes.
+const ENTRY_SIZE: usize = 8;
+/// The maximum depth of the index, including the root.
+const MAX_DEPTH: usize = 3;
+
+/// Reads a little-endian `u16` at offset `off` in `buf`.
+fn read_u16(buf: &[u8], off: usize) -> u16 {
+	u16::from_le_bytes([buf[off], buf[off + 1]])
+}
+
+/// Reads a little-endian `u32` at offset `off` in `buf`.
+fn read_u32(buf: &[u8], off: usize) -> u32 {
+	u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+/// Converts a character of a name to an integer, for hashing.
+fn char_value(c: u8, signed: bool) -> u32 {
+	if signed { c as i8 as u32 } else { c as u32 }
+}
+
+/// The legacy hash function.
+fn legacy_hash(name: &[u8], signed: bool) -> u32 {
+	let mut hash0: u32 = 0x12a3fe2d;
+	let mut hash1: u32 = 0x37abe8f9;
+	for c in name {
+		let c = char_value(*c, signed);
+		let mut hash = hash1.wrapping_add(hash0 ^ c.wrapping_mul(7152373));
+		if hash & 0x80000000 != 0 {
+			hash = hash.wrapping_sub(0x7fffffff);
+		}
+		hash1 = hash0;
+		hash0 = hash;
+	}
+	hash0 << 1
+}
+
+/// Fills `buf` with the words to be hashed from `msg`, padding the remaining space.
+fn str_to_hash_buf(msg: &[u8], signed: bool, buf: &mut [u32]) {
+	let len = msg.len() as u32;
+	let pad = len | (len << 8);
+	let pad = pad | (pad << 16);
+	let msg = &msg[..min(msg.len(), buf.len() * 4)];
+	let mut val = pad;
+	let mut i = 0;
+	for (j, c) in msg.iter().enumerate() {
+		val = char_value(*c, signed).wrapping_add(val << 8);
+		if j % 4 == 3 {
+			buf[i] = val;
+			i += 1;
+			val = pad;
+		}
+	}
+	if let Some(word) = buf.get_mut(i) {
+		*word = val;
+		i += 1;
+	}
+	buf[i..].fill(pad);
+}
+
+/// The transformation of the half MD4 hash function.
+fn half_md4_transform(buf: &mut [u32; 4], input: &[u32; 8]) {
+	const K1: u32 = 0;
+	const K2: u32 = 0x5a827999;
+	const K3: u32 = 0x6ed9eba1;
+	let f = |x: u32, y: u32, z: u32| z ^ (x & (y ^ z));
+	let g = |x: u32, y: u32, z: u32| (x & y).wrapping_add((x ^ y) & z);
+	let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+	let [mut a, mut b, mut c, mut d] = *buf;
+	macro_rules! round {
+		($f:ident, $a:ident, $b:ident, $c:ident, $d:ident, $x:expr, $s:expr) => {
+			$a = $a
+				.wrapping_add($f($b, $c, $d))
+				.wrapping_add($x)
+				.rotate_left($s);
+		};
+	}
+	// Round 1
+	round!(f, a, b, c, d, input[0].wrapping_add(K1), 3);
+	round!(f, d, a, b, c, input[1].wrapping_add(K1), 7);
+	round!(f, c, d, a, b, input[2].wrapping_add(K1), 11);
+	round!(f, b, c, d, a, input[3].wrapping_add(K1), 19);
+	round!(f, a, b, c, d, input[4].wrapping_add(K1), 3);
+	round!(f, d, a, b, c, input[5].wrapping_add(K1), 7);
+	round!(f, c, d, a, b, input[6].wrapping_add(K1), 11);
+	round!(f, b, c, d, a, input[7].wrapping_add(K1), 19);
+	// Round 2
+	round!(g, a, b, c, d, input[1].wrapping_add(K2), 3);
+	round!(g, d, a, b, c, input[3].wrapping_add(K2), 5);
+	round!(g, c, d, a, b, input[5].wrapping_add(K2), 9);
+	round!(g, b, c, d, a, input[7].wrapping_add(K2), 13);
+	round!(g, a, b, c, d, input[0].wrapping_add(K2), 3);
+	round!(g, d, a, b, c, input[2].wrapping_add(K2), 5);
+	round!(g, c, d, a, b, input[4].wrapping_add(K2), 9);
+	round!(g, b, c, d, a, input[6].wrapping_add(K2), 13);
+	// Round 3
+	round!(h, a, b, c, d, input[3].wrapping_add(K3), 3);
+	round!(h, d, a, b, c, input[7].wrapping_add(K3), 9);
+	round!(h, c, d, a, b, input[2].wrapping_add(K3), 11);
+	round!(h, b, c, d, a, input[6].wrapping_add(K3), 15);
+	round!(h, a, b, c, d, input[1].wrapping_add(K3), 3);
+	round!(h, d, a, b, c, input[5].wrapping_add(K3), 9);
+	round!(h, c, d, a, b, input[0].wrapping_add(K3), 11);
+	round!(h, b, c, d, a, input[4].wrapping_add(K3), 15);
+	for (buf, v) in buf.iter_mut().zip([a, b, c, d]) {
+		*buf = buf.wrapping_add(v);
+	}
+}
+
+/// The transformation of the TEA hash function.
+fn tea_transform(buf: &mut [u32; 4], input: &[u32; 4]) {
+	const DELTA: u32 = 0x9e3779b9;
+	let [a, b, c, d] = *input;
+	let mut sum: u32 = 0;
+	let mut b0 = buf[0];
+	let mut b1 = buf[1];
+	for _ in 0..16 {
+		sum = sum.wrapping_add(DELTA);
+		b0 = b0.wrapping_add(
+			(b1 << 4).wrapping_add(a) ^ b1.wrapping_add(sum) ^ (b1 >> 5).wrapping_add(b),
+		);
+		b1 = b1.wrapping_add(
+			(b0 << 4).wrapping_add(c) ^ b0.wrapping_add(sum) ^ (b0 >> 5).wrapping_add(d),
+		);
+	}
+	buf[0] = buf[0].wrapping_add(b0);
+	buf[1] = buf[1].wrapping_add(b1);
+}
+
+/// Computes the hash of the entry name `name` with the given hash `version` and `seed`.
+///
+/// If the hash version is not supported, the function returns `None`.
+fn dirhash(name: &[u8], version: u8, seed: &[u32; 4]) -> Option<u32> {
+	let mut buf = if seed.iter().any(|w| *w != 0) {
+		*seed
+	} else {
+		[0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476]
+	};
+	let hash = match version {
+		DX_HASH_LEGACY | DX_HASH_LEGACY_UNSIGNED => legacy_hash(name, version == DX_HASH_LEGACY),
+		DX_HASH_HALF_MD4 | DX_HASH_HALF_MD4_UNSIGNED => {
+			let mut input = [0; 8];
+			for chunk in (0..name.len()).step_by(32) {
+				str_to_hash_buf(&name[chunk..], version == DX_HASH_HALF_MD4, &mut input);
+				half_md4_transform(&mut buf, &input);
+			}
+			buf[1]
+		}
+		DX_HASH_TEA | DX_HASH_TEA_UNSIGNED => {
+			let mut input = [0; 4];
+			for chunk in (0..name.len()).step_by(16) {
+				str_to_hash_buf(&name[chunk..], version == DX_HASH_TEA, &mut input);
+				tea_transform(&mut buf, &input);
+			}
+			buf[0]
+		}
+		_ => return None,
+	};
+	// The lowest bit is reserved to mark collisions
+	let hash = hash & !1;
+	if hash == HTREE_EOF << 1 {
+		Some((HTREE_EOF - 1) << 1)
+	} else {
+		Some(hash)
+	}
+}
+
+/// A node of the index, being traversed.
+struct Frame {
+	/// The block containing the node.
+	blk: RcPage,
+	/// The offset of the entries in the block.
+	off: usize,
+	/// The number of entries.
+	count: usize,
+	/// The index of the current entry.
+	at: usize,
+}
+
+impl Frame {
+	/// Reads the node at offset `off` in `blk`, positioned on its first entry.
+	///
+	/// If the node is invalid, the function returns [`errno::EUCLEAN`].
+	fn new(blk: RcPage, off: usize, blk_size: usize) -> EResult<Self> {
+		let slice = &blk.slice::<u8>()[..blk_size];
+		if unlikely(off + ENTRY_SIZE > blk_size) {
+			return Err(errno!(EUCLEAN));
+		}
+		// The first entry holds the limit and count instead of a hash
+		let limit = read_u16(slice, off) as usize;
+		let count = read_u16(slice, off + 2) as usize;
+		if unlikely(count == 0 || count > limit || limit > (blk_size - off) / ENTRY_SIZE) {
+			return Err(errno!(EUCLEAN));
+		}
+		Ok(Self {
+			blk,
+			off,
+			count,
+			at: 0,
+		})
+	}
+
+	/// Positions the node on the entry covering `hash`.
+	fn seek(&mut self, hash: u32) {
+		// Find the last entry whose hash is lower or equal to `hash`. The first entry has no
+		// hash and covers everything below the second
+		let mut begin = 1;
+		let mut end = self.count;
+		while begin < end {
+			let mid = begin + (end - begin) / 2;
+			if self.hash(mid) <= hash {
+				begin = mid + 1;
+			} else {
+				end = mid;
+			}
+		}
+		self.at = begin - 1;
+	}
+
+	/// Returns the hash of the entry at index `i`.
+	fn hash(&self, i: usize) -> u32 {
+		read_u32(self.blk.slice(), self.off + i * ENTRY_SIZE)
+	}
+
+	/// Returns the directory block the current entry points to.
+	fn block(&self) -> u32 {
+		read_u32(self.blk.slice(), self.off + self.at * ENTRY_SIZE + 4) & 0x0fffffff
+	}
+}
+
+/// Reads the block at offset `off` in the directory `inode`.
+///
+/// If the block does not exist, the function returns [`errno::EUCLEAN`].
+fn read_block(inode: &Ext2INode, off: u32, fs: &Ext2Fs) -> EResult<RcPage> {
+	let blk = inode
+		.translate_blk_off(off, fs)?
+		.ok_or_else(|| errno!(EUCLEAN))?;
+	fs.dev.ops.read_page(&fs.dev, blk.get() as _)
+}
+
+/// Looks for the entry with the name `name` in the directory block at offset `blk`.
+///
+/// The function returns the inode and the offset of the entry.
+fn search_leaf(
+	inode: &Ext2INode,
+	blk: u32,
+	name: &[u8],
+	fs: &Ext2Fs,
+) -> EResult<Option<(u32, u64)>> {
+	let blk_size = fs.sp.get_block_size() as u64;
+	let start = blk as u64 * blk_size;
+	let mut page = None;
+	for ent in DirentIterator::new(fs, inode, &mut page, start)? {
+		let (off, ent) = ent?;
+		if off >= start + blk_size {
+			break;
+		}
+		if !ent.is_free() && ent.get_name(&fs.sp) == name {
+			return Ok(Some((ent.inode, off)));
+		}
+	}
+	Ok(None)
+}
+
+/// Moves `frames` to the next leaf block, if it may contain entries with the hash `hash`.
+///
+/// Entries with the same hash may span several blocks, in which case the hash of the
+/// following blocks has its lowest bit set.
+///
+/// If there is no such block, the function returns `false`.
+fn next_leaf(
+	inode: &Ext2INode,
+	frames: &mut [Frame],
+	hash: u32,
+	blk_size: usize,
+	fs: &Ext2Fs,
+) -> EResult<bool> {
+	// Find the deepest node that has a next entry
+	let Some(level) = frames.iter().rposition(|f| f.at + 1 < f.count) else {
+		return Ok(false);
+	};
+	let frame = &mut frames[level];
+	if frame.hash(frame.at + 1) & !1 != hash {
+		return Ok(false);
+	}
+	frame.at += 1;
+	// Descend to the first entry of the following nodes
+	for level in level + 1..frames.len() {
+		let blk = read_block(inode, frames[level - 1].block(), fs)?;
+		frames[level] = Frame::new(blk, NODE_ENTRIES_OFF, blk_size)?;
+	}
+	Ok(true)
+}
+
+/// Looks for the entry with the name `name` in the hash-indexed directory `inode`.
+///
+/// The function returns the inode and the offset of the entry. If the entry does not exist,
+/// the function returns `None`.
+///
+/// If the index is invalid, the function returns [`errno::EUCLEAN`], in which case the
+/// directory can still be searched linearly.
+pub fn lookup(inode: &Ext2INode, name: &[u8], fs: &Ext2Fs) -> EResult<Option<(u32, u64)>> {
+	let blk_size = fs.sp.get_block_size() as usize;
+	let root = read_block(inode, 0, fs)?;
+	let slice = root.slice::<u8>();
+	// Read the root's information
+	let reserved = read_u32(slice, ROOT_INFO_OFF);
+	let mut version = slice[ROOT_INFO_OFF + 4];
+	let info_len = slice[ROOT_INFO_OFF + 5] as usize;
+	let depth = slice[ROOT_INFO_OFF + 6] as usize + 1;
+	if unlikely(reserved != 0 || info_len < 8 || depth > MAX_DEPTH) {
+		return Err(errno!(EUCLEAN));
+	}
+	if version <= DX_HASH_TEA && fs.sp.s_flags & FLAG_UNSIGNED_HASH != 0 {
+		version += DX_HASH_LEGACY_UNSIGNED;
+	}
+	let hash = dirhash(name, version, &fs.sp.s_hash_seed).ok_or_else(|| errno!(EUCLEAN))?;
+	// Walk down to the leaf
+	let mut frames = Vec::with_capacity(depth)?;
+	let mut frame = Frame::new(root, ROOT_INFO_OFF + info_len, blk_size)?;
+	for _ in 1..depth {
+		frame.seek(hash);
+		let blk = read_block(inode, frame.block(), fs)?;
+		frames.push(frame)?;
+		frame = Frame::new(blk, NODE_ENTRIES_OFF, blk_size)?;
+	}
+	frame.seek(hash);
+	frames.push(frame)?;
+	loop {
+		let leaf = frames[depth - 1].block();
+		if let Some(ent) = search_leaf(inode, leaf, name, fs)? {
+			return Ok(Some(ent));
+		}
+		if !next_leaf(inode, &mut frames, hash, blk_size, fs)? {
+			return Ok(None);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn htree_hash_default_seed() {
+		let name = b"hello_world_this_is_a_long_filename_x";
+		assert_eq!(dirhash(name, DX_HASH_LEGACY, &[0; 4]), Some(0x671bed72));
+		assert_eq!(dirhash(name, DX_HASH_HALF_MD4, &[0; 4]), Some(0x035b8d58));
+		assert_eq!(dirhash(name, DX_HASH_TEA, &[0; 4]), Some(0x055ae826));
+		assert_eq!(dirhash(name, 42, &[0; 4]), None);
+	}
+
+	#[test_case]
+	fn htree_hash_seed() {
+		let seed = [0xcdab2301, 0x11110000, 0x33332222, 0x55554444];
+		let name = "café".as_bytes();
+		assert_eq!(dirhash(name, DX_HASH_LEGACY, &seed), Some(0x96ca5a2c));
+		assert_eq!(dirhash(name, DX_HASH_HALF_MD4, &seed), Some(0xd56c0448));
+		assert_eq!(dirhash(name, DX_HASH_TEA, &seed), Some(0x8d27d674));
+		// Only characters above 0x7f differ with unsigned variants
+		assert_eq!(
+			dirhash(b"file", DX_HASH_TEA, &seed),
+			dirhash(b"file", DX_HASH_TEA_UNSIGNED, &seed)
+		);
+		assert_ne!(
+			dirhash(name, DX_HASH_TEA, &seed),
+			dirhash(name, DX_HASH_TEA_UNSIGNED, &seed)
+		);
+	}
+}
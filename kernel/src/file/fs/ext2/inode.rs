@@ -18,21 +18,33 @@
 
 //! An inode represents a file in the filesystem.
 
-use super::{Ext2Fs, Superblock, bgd::BlockGroupDescriptor, dirent, dirent::Dirent, zero_block};
+use super::{
+	Ext2Fs, Superblock, bgd::BlockGroupDescriptor, dirent, dirent::Dirent, htree, zero_block,
+};
 use crate::{
-	file::{FileType, INode, Mode, Stat, fs::ext2::dirent::DirentIterator, vfs::node::Node},
+	file::{
+		FileType, INode, Mode, Stat,
+		fs::{downcast_fs, ext2::dirent::DirentIterator},
+		vfs::node::Node,
+	},
 	memory::cache::{RcBlockVal, RcPage},
 	sync::mutex::MutexGuard,
 };
 use core::{
+	cmp::min,
 	hint::unlikely,
 	mem,
 	num::NonZeroU32,
 	ops::{Deref, DerefMut},
-	sync::atomic::{AtomicU32, Ordering::Relaxed},
+	sync::atomic::{AtomicU8, AtomicU32, Ordering::Relaxed},
 };
 use macros::AnyRepr;
-use utils::{errno, errno::EResult, limits::NAME_MAX, math};
+use utils::{
+	errno,
+	errno::{EResult, EUCLEAN},
+	limits::NAME_MAX,
+	math,
+};
 
 /// The maximum number of direct blocks for each inodes.
 pub const DIRECT_BLOCKS_COUNT: usize = 12;
@@ -69,12 +81,16 @@ const INODE_FLAG_NODUMP: u32 = 0x00040;
 /// `s_flags`: Last accessed time should not be updated
 const INODE_FLAG_ATIME_NOUPDATE: u32 = 0x00080;
 /// `s_flags`: Hash indexed directory
-const INODE_FLAG_HASH_INDEXED: u32 = 0x10000;
+pub const INODE_FLAG_HASH_INDEXED: u32 = 0x01000;
 /// `s_flags`: AFS directory
 const INODE_FLAG_AFS_DIRECTORY: u32 = 0x20000;
 /// `s_flags`: Journal file data
 const INODE_FLAG_JOURNAL_FILE: u32 = 0x40000;
 
+/// The inode flags reported as file attributes. Their values match the `STATX_ATTR_*` flags.
+pub const ATTRIBUTE_FLAGS: u32 =
+	INODE_FLAG_COMPRESSION | INODE_FLAG_IMMUTABLE | INODE_FLAG_APPEND_ONLY | INODE_FLAG_NODUMP;
+
 /// The size of a sector in bytes.
 const SECTOR_SIZE: u32 = 512;
 
@@ -87,15 +103,21 @@ pub const ROOT_DIRECTORY_INODE: u32 = 2;
 
 /// Container for an inode, locking its associated spinlock to avoid concurrency issues
 pub(super) struct INodeWrap<'n> {
+	node: &'n Node,
 	_guard: MutexGuard<'n, (), false>,
 	inode: RcBlockVal<Ext2INode>,
 }
 
 impl INodeWrap<'_> {
 	/// Marks the associated page as dirty.
+	///
+	/// Since blocks may have been allocated or freed, the number of blocks in the node's status
+	/// is updated as well.
 	#[inline]
-	pub fn mark_dirty(&self) {
-		self.inode.mark_dirty()
+	pub fn mark_dirty(&self) -> EResult<()> {
+		self.node.stat.lock().blocks = self.i_blocks as _;
+		let fs = downcast_fs::<Ext2Fs>(&*self.node.fs.ops);
+		fs.mark_dirty(self.inode.page())
 	}
 }
 
@@ -150,28 +172,45 @@ fn indirections_offsets(
 	if off < ent_per_blk * ent_per_blk * ent_per_blk {
 		offsets[0] = DIRECT_BLOCKS_COUNT + 2;
 		offsets[1] = (off >> (ent_per_blk_log * 2)) as _;
-		offsets[2] = ((off >> ent_per_blk_log) & (ent_per_blk_log - 1)) as _;
+		offsets[2] = ((off >> ent_per_blk_log) & (ent_per_blk - 1)) as _;
 		offsets[3] = (off & (ent_per_blk - 1)) as _;
 		return Ok(4);
 	}
 	Err(errno!(EOVERFLOW))
 }
 
+/// Returns the maximum size of a regular file on the filesystem, in bytes.
+pub fn max_file_size(sp: &Superblock) -> u64 {
+	let blk_size = sp.get_block_size() as u64;
+	let ent_per_blk = blk_size / 4;
+	let blocks =
+		DIRECT_BLOCKS_COUNT as u64 + ent_per_blk + ent_per_blk.pow(2) + ent_per_blk.pow(3);
+	// The number of sectors must also fit in `i_blocks`
+	let max = min(blocks * blk_size, u32::MAX as u64 * SECTOR_SIZE as u64);
+	if sp.has_large_files() {
+		max
+	} else {
+		min(max, u32::MAX as _)
+	}
+}
+
 /// Checks for an invalid block number.
 ///
 /// If the block number is zero, the function returns `None`.
-pub fn check_blk_off(blk: u32, sp: &Superblock) -> EResult<Option<NonZeroU32>> {
-	if unlikely(blk >= sp.s_blocks_count) {
-		return Err(errno!(EUCLEAN));
+///
+/// If the block number is out of bounds, the function returns [`errno::EUCLEAN`].
+pub fn check_blk_off(blk: u32, fs: &Ext2Fs) -> EResult<Option<NonZeroU32>> {
+	if unlikely(blk >= fs.sp.s_blocks_count) {
+		return Err(fs.corrupted());
 	}
 	Ok(NonZeroU32::new(blk))
 }
 
 /// Tells whether the block contains only free directory entries.
-fn is_block_empty(blk: &mut [u8], sp: &Superblock) -> EResult<bool> {
+fn is_block_empty(blk: &mut [u8], fs: &Ext2Fs) -> EResult<bool> {
 	let mut off = 0;
 	while off < blk.len() {
-		let ent = Dirent::from_slice(&mut blk[off..], sp)?;
+		let ent = Dirent::from_slice(&mut blk[off..], fs)?;
 		if !ent.is_free() {
 			return Ok(false);
 		}
@@ -257,9 +296,19 @@ pub struct Ext2INode {
 }
 
 impl Ext2INode {
-	/// Returns the `i`th inode on the filesystem.
+	/// Returns the inode of the given node on the filesystem, locking the node.
 	pub fn get<'n>(node: &'n Node, fs: &Ext2Fs) -> EResult<INodeWrap<'n>> {
 		let i: u32 = node.inode.try_into().map_err(|_| errno!(EOVERFLOW))?;
+		let inode = Self::read(i, fs)?;
+		Ok(INodeWrap {
+			node,
+			_guard: node.lock.lock(),
+			inode,
+		})
+	}
+
+	/// Returns the `i`th inode on the filesystem, without locking it.
+	pub fn read(i: u32, fs: &Ext2Fs) -> EResult<RcBlockVal<Self>> {
 		// Check the index is correct
 		let Some(i) = i.checked_sub(1) else {
 			return Err(errno!(EINVAL));
@@ -278,10 +327,7 @@ impl Ext2INode {
 		let off = i as u64 % (blk_size / inode_size);
 		// Adapt to the size of an inode
 		let off = off * (inode_size / 128);
-		Ok(INodeWrap {
-			_guard: node.lock.lock(),
-			inode: RcBlockVal::new(blk, off as _),
-		})
+		Ok(RcBlockVal::new(blk, off as _))
 	}
 
 	/// Returns the file's status.
@@ -296,9 +342,13 @@ impl Ext2INode {
 			blocks: self.i_blocks as _,
 			dev_major: dev_major as _,
 			dev_minor: dev_minor as _,
-			ctime: self.i_ctime as _,
-			mtime: self.i_mtime as _,
-			atime: self.i_atime as _,
+			ctime: self.i_ctime as u64 * 1_000_000_000,
+			mtime: self.i_mtime as u64 * 1_000_000_000,
+			atime: self.i_atime as u64 * 1_000_000_000,
+			// ext2 does not record the creation time
+			btime: None,
+
+			attributes: (self.i_flags & ATTRIBUTE_FLAGS) as _,
 		}
 	}
 
@@ -326,9 +376,7 @@ impl Ext2INode {
 	///
 	/// `superblock` is the filesystem's superblock.
 	pub fn get_size(&self, sp: &Superblock) -> u64 {
-		let has_version = sp.s_rev_level >= 1;
-		let has_feature = sp.s_feature_ro_compat & super::WRITE_REQUIRED_64_BITS != 0;
-		if has_version && has_feature {
+		if sp.has_large_files() {
 			((self.i_dir_acl as u64) << 32) | (self.i_size as u64)
 		} else {
 			self.i_size as u64
@@ -340,27 +388,21 @@ impl Ext2INode {
 	/// Arguments:
 	/// - `superblock` is the filesystem's superblock
 	/// - `size` is the file's size
-	/// - `inline` is `true` if the inode is a symlink storing the target inline
-	pub fn set_size(&mut self, sp: &Superblock, size: u64, inline: bool) {
-		let has_version = sp.s_rev_level >= 1;
-		let has_feature = sp.s_feature_ro_compat & super::WRITE_REQUIRED_64_BITS != 0;
-		if has_version && has_feature {
+	pub fn set_size(&mut self, sp: &Superblock, size: u64) {
+		if sp.has_large_files() {
 			self.i_dir_acl = (size >> 32) as u32;
 		}
 		self.i_size = size as u32;
-		if !inline {
-			let blk_size = sp.get_block_size();
-			let sector_per_blk = blk_size / SECTOR_SIZE;
-			self.i_blocks = size.div_ceil(blk_size as _) as u32 * sector_per_blk;
-		} else {
-			self.i_blocks = 0;
-		}
 	}
 
-	/// Returns the number of content blocks.
+	/// Returns the number of content blocks covered by the file's size, including holes.
 	pub fn get_blocks(&self, sp: &Superblock) -> u32 {
-		let sector_per_blk = sp.get_block_size() / SECTOR_SIZE;
-		self.i_blocks.div_ceil(sector_per_blk)
+		self.get_size(sp).div_ceil(sp.get_block_size() as _) as _
+	}
+
+	/// Returns the disk space used by the inode, in bytes.
+	pub fn get_space(&self) -> u64 {
+		self.i_blocks as u64 * SECTOR_SIZE as u64
 	}
 
 	/// Translates the given file block offset `off` to disk block offset.
@@ -369,13 +411,13 @@ impl Ext2INode {
 	pub fn translate_blk_off(&self, off: u32, fs: &Ext2Fs) -> EResult<Option<NonZeroU32>> {
 		let mut offsets: [usize; 4] = [0; 4];
 		let depth = indirections_offsets(off, fs.sp.get_entries_per_block_log(), &mut offsets)?;
-		let Some(mut blk_off) = check_blk_off(self.i_block[offsets[0]], &fs.sp)? else {
+		let Some(mut blk_off) = check_blk_off(self.i_block[offsets[0]], fs)? else {
 			return Ok(None);
 		};
 		// Perform indirections
 		for off in &offsets[1..depth] {
 			let blk = fs.dev.ops.read_page(&fs.dev, blk_off.get() as _)?;
-			let Some(b) = check_blk_off(blk.slice()[*off], &fs.sp)? else {
+			let Some(b) = check_blk_off(blk.slice()[*off], fs)? else {
 				return Ok(None);
 			};
 			blk_off = b;
@@ -383,6 +425,21 @@ impl Ext2INode {
 		Ok(Some(blk_off))
 	}
 
+	/// Allocates a block for the inode, charging it to the disk quotas of its owner.
+	fn alloc_block(&self, fs: &Ext2Fs) -> EResult<u32> {
+		let blk_size = fs.sp.get_block_size() as u64;
+		fs.quota.charge(self.i_uid, self.i_gid, blk_size, 0)?;
+		fs.alloc_block()
+			.inspect_err(|_| fs.quota.release(self.i_uid, self.i_gid, blk_size, 0))
+	}
+
+	/// Tells whether a block of the inode holds file data, as opposed to metadata.
+	///
+	/// `content` tells whether the block is a content block, as opposed to an indirection block.
+	fn is_data(&self, content: bool) -> bool {
+		content && self.get_type() == FileType::Regular
+	}
+
 	/// Allocates a block for the node's content block at the given file block offset `off`.
 	///
 	/// The content of the allocated block is **not** initialized.
@@ -395,25 +452,28 @@ impl Ext2INode {
 	pub fn alloc_content_blk(&mut self, off: u32, fs: &Ext2Fs) -> EResult<u32> {
 		let mut offsets: [usize; 4] = [0; 4];
 		let depth = indirections_offsets(off, fs.sp.get_entries_per_block_log(), &mut offsets)?;
+		let sector_per_blk = fs.sp.get_block_size() / SECTOR_SIZE;
 		// Allocate the first level if needed
-		let blk_off = &mut self.i_block[offsets[0]];
-		if *blk_off == 0 {
-			*blk_off = fs.alloc_block()?;
-			zero_block(fs, *blk_off as _)?;
+		if self.i_block[offsets[0]] == 0 {
+			let blk = self.alloc_block(fs)?;
+			self.i_block[offsets[0]] = blk;
+			zero_block(fs, blk as _, self.is_data(depth == 1))?;
+			self.i_blocks += sector_per_blk;
 		}
 		// Perform indirections
-		let mut blk_off = *blk_off;
-		for off in &offsets[1..depth] {
+		let mut blk_off = self.i_block[offsets[0]];
+		for (level, off) in offsets[1..depth].iter().enumerate() {
 			let blk = fs.dev.ops.read_page(&fs.dev, blk_off as _)?;
 			let ent = &blk.slice::<AtomicU32>()[*off];
 			// Allocate block if needed (two atomic operations are fine here since the node is
 			// locked)
 			let mut b = ent.load(Relaxed);
 			if b == 0 {
-				let new = fs.alloc_block()?;
-				zero_block(fs, new as _)?;
+				let new = self.alloc_block(fs)?;
+				zero_block(fs, new as _, self.is_data(level + 2 == depth))?;
 				ent.store(new, Relaxed);
-				blk.mark_dirty();
+				fs.mark_dirty(&blk)?;
+				self.i_blocks += sector_per_blk;
 				b = new;
 			}
 			blk_off = b;
@@ -421,20 +481,35 @@ impl Ext2INode {
 		Ok(blk_off)
 	}
 
-	fn free_content_blk_impl(blk: u32, offsets: &[usize], fs: &Ext2Fs) -> EResult<bool> {
+	/// Frees the block at `offsets` under the indirection block `blk`, incrementing `freed` for
+	/// each block freed.
+	///
+	/// The function returns `true` if `blk` is left empty.
+	fn free_content_blk_impl(
+		blk: u32,
+		offsets: &[usize],
+		fs: &Ext2Fs,
+		freed: &mut u32,
+	) -> EResult<bool> {
 		let Some(off) = offsets.first() else {
 			return Ok(true);
 		};
 		let blk = fs.dev.ops.read_page(&fs.dev, blk as _)?;
 		let ents = blk.slice::<AtomicU32>();
 		let ent = &ents[*off];
+		let child = ent.load(Relaxed);
+		// If the entry is a hole, there is nothing to free
+		if check_blk_off(child, fs)?.is_none() {
+			return Ok(false);
+		}
 		// Handle child block and determine whether the entry in the current block should be freed
-		let free = Self::free_content_blk_impl(ent.load(Relaxed), &offsets[1..], fs)?;
+		let free = Self::free_content_blk_impl(child, &offsets[1..], fs, freed)?;
 		if free {
 			let b = ent.swap(0, Relaxed);
-			blk.mark_dirty();
+			fs.mark_dirty(&blk)?;
 			let empty = ents.iter().all(|b| b.load(Relaxed) == 0);
 			fs.free_block(b)?;
+			*freed += 1;
 			Ok(empty)
 		} else {
 			Ok(false)
@@ -448,12 +523,130 @@ impl Ext2INode {
 		let mut offsets: [usize; 4] = [0; 4];
 		let depth = indirections_offsets(off, fs.sp.get_entries_per_block_log(), &mut offsets)?;
 		let blk = &mut self.i_block[offsets[0]];
-		if check_blk_off(*blk, &fs.sp)?.is_none() {
+		if check_blk_off(*blk, fs)?.is_none() {
 			return Ok(());
 		}
-		if Self::free_content_blk_impl(*blk, &offsets[1..depth], fs)? {
-			let blk = mem::take(blk);
-			fs.free_block(blk)?;
+		let mut freed = 0;
+		let res = Self::free_content_blk_impl(*blk, &offsets[1..depth], fs, &mut freed).and_then(
+			|empty| {
+				if empty {
+					fs.free_block(mem::take(blk))?;
+					freed += 1;
+				}
+				Ok(())
+			},
+		);
+		let sector_per_blk = fs.sp.get_block_size() / SECTOR_SIZE;
+		self.i_blocks = self.i_blocks.saturating_sub(freed * sector_per_blk);
+		let space = freed as u64 * fs.sp.get_block_size() as u64;
+		fs.quota.release(self.i_uid, self.i_gid, space, 0);
+		res
+	}
+
+	/// Frees the blocks under the indirection block `blk` that are mapped at file block offsets
+	/// `start` and above, incrementing `freed` for each block freed.
+	///
+	/// Arguments:
+	/// - `level` is the number of indirections below `blk`
+	/// - `base` is the file block offset of the first block mapped under `blk`
+	///
+	/// The function returns `true` if `blk` is left empty.
+	fn indirect_free_from(
+		blk: u32,
+		level: u32,
+		base: u64,
+		start: u64,
+		fs: &Ext2Fs,
+		freed: &mut u32,
+	) -> EResult<bool> {
+		let span = 1u64 << (fs.sp.get_entries_per_block_log() * level);
+		let blk = fs.dev.ops.read_page(&fs.dev, blk as _)?;
+		let ents = blk.slice::<AtomicU32>();
+		for (i, ent) in ents.iter().enumerate() {
+			let child_base = base + i as u64 * span;
+			if child_base + span <= start {
+				continue;
+			}
+			let Some(child) = check_blk_off(ent.load(Relaxed), fs)? else {
+				continue;
+			};
+			let empty = match level.checked_sub(1) {
+				Some(level) => {
+					Self::indirect_free_from(child.get(), level, child_base, start, fs, freed)?
+				}
+				None => true,
+			};
+			if empty {
+				ent.store(0, Relaxed);
+				fs.mark_dirty(&blk)?;
+				fs.free_block(child.get())?;
+				*freed += 1;
+			}
+		}
+		Ok(ents.iter().all(|b| b.load(Relaxed) == 0))
+	}
+
+	/// Frees the content blocks at file block offsets `start` and above.
+	fn free_content_from(&mut self, start: u32, fs: &Ext2Fs) -> EResult<()> {
+		let start = start as u64;
+		let ent_per_blk_log = fs.sp.get_entries_per_block_log();
+		let mut freed = 0;
+		let mut free = || {
+			let mut base = 0;
+			for (off, blk) in self.i_block.iter_mut().enumerate() {
+				// The number of indirections and of blocks mapped under the entry
+				let level = off.saturating_sub(DIRECT_BLOCKS_COUNT) as u32;
+				let span = if off < DIRECT_BLOCKS_COUNT {
+					1
+				} else {
+					1u64 << (ent_per_blk_log * (level + 1))
+				};
+				let blk_base = base;
+				base += span;
+				if blk_base + span <= start {
+					continue;
+				}
+				let Some(b) = check_blk_off(*blk, fs)? else {
+					continue;
+				};
+				let empty = if off < DIRECT_BLOCKS_COUNT {
+					true
+				} else {
+					Self::indirect_free_from(b.get(), level, blk_base, start, fs, &mut freed)?
+				};
+				if empty {
+					*blk = 0;
+					fs.free_block(b.get())?;
+					freed += 1;
+				}
+			}
+			Ok(())
+		};
+		let res = free();
+		let sector_per_blk = fs.sp.get_block_size() / SECTOR_SIZE;
+		self.i_blocks = self.i_blocks.saturating_sub(freed * sector_per_blk);
+		let space = freed as u64 * fs.sp.get_block_size() as u64;
+		fs.quota.release(self.i_uid, self.i_gid, space, 0);
+		res
+	}
+
+	/// Frees the content located after `size` bytes.
+	///
+	/// The end of the last block is zeroed so that it reads as zeros if the file grows again.
+	///
+	/// The file's size is not updated.
+	pub fn truncate_content(&mut self, size: u64, fs: &Ext2Fs) -> EResult<()> {
+		let blk_size = fs.sp.get_block_size() as u64;
+		self.free_content_from(size.div_ceil(blk_size) as _, fs)?;
+		let inner_off = (size % blk_size) as usize;
+		if inner_off > 0
+			&& let Some(blk_off) = self.translate_blk_off((size / blk_size) as _, fs)?
+		{
+			let blk = fs.dev.ops.read_page(&fs.dev, blk_off.get() as _)?;
+			for b in &blk.slice::<AtomicU8>()[inner_off..] {
+				b.store(0, Relaxed);
+			}
+			fs.mark_data_dirty(&blk)?;
 		}
 		Ok(())
 	}
@@ -464,7 +657,7 @@ impl Ext2INode {
 	fn indirect_free_all(blk_off: u32, level: usize, fs: &Ext2Fs) -> EResult<()> {
 		let blk = fs.dev.ops.read_page(&fs.dev, blk_off as _)?;
 		for blk in blk.slice() {
-			let Some(blk) = check_blk_off(*blk, &fs.sp)? else {
+			let Some(blk) = check_blk_off(*blk, fs)? else {
 				continue;
 			};
 			if let Some(next_level) = level.checked_sub(1) {
@@ -483,10 +676,13 @@ impl Ext2INode {
 		{
 			return Ok(());
 		}
-		self.set_size(&fs.sp, 0, false);
+		self.set_size(&fs.sp, 0);
+		fs.quota
+			.release(self.i_uid, self.i_gid, self.get_space(), 0);
+		self.i_blocks = 0;
 		// Free blocks
 		for (off, blk) in self.i_block.iter().enumerate() {
-			let Some(blk) = check_blk_off(*blk, &fs.sp)? else {
+			let Some(blk) = check_blk_off(*blk, fs)? else {
 				continue;
 			};
 			let depth = off.saturating_sub(DIRECT_BLOCKS_COUNT);
@@ -513,7 +709,19 @@ impl Ext2INode {
 		if self.get_type() != FileType::Directory {
 			return Ok(None);
 		}
-		// TODO If the hash index is enabled, use it
+		// The `.` and `..` entries are not indexed
+		let indexed = self.i_flags & INODE_FLAG_HASH_INDEXED != 0
+			&& fs.sp.s_feature_compat & super::OPTIONAL_FEATURE_HASH_INDEX != 0
+			&& name != b"."
+			&& name != b"..";
+		if indexed {
+			match htree::lookup(self, name, fs) {
+				Ok(res) => return Ok(res),
+				// If the index is invalid, fallback to a linear lookup
+				Err(e) if e.as_int() == EUCLEAN => {}
+				Err(e) => return Err(e),
+			}
+		}
 		// Linear lookup
 		let mut blk = None;
 		for ent in DirentIterator::new(fs, self, &mut blk, 0)? {
@@ -612,6 +820,8 @@ impl Ext2INode {
 		if unlikely(rec_len as u32 > blk_size) {
 			return Err(errno!(ENAMETOOLONG));
 		}
+		// The index is not maintained: the directory becomes linear
+		self.i_flags &= !INODE_FLAG_HASH_INDEXED;
 		if let Some((blk, off, len)) = self.find_suitable_slot(fs, rec_len)? {
 			// Safe since the inode is locked
 			let buf = unsafe { blk.slice_mut() };
@@ -635,7 +845,7 @@ impl Ext2INode {
 				&mut buf[(inner_off + rec_len as usize)..(inner_off + len)],
 				&fs.sp,
 			)?;
-			blk.mark_dirty();
+			fs.mark_dirty(&blk)?;
 		} else {
 			// No suitable free entry: Fill a new block
 			let blocks = self.get_blocks(&fs.sp);
@@ -648,8 +858,8 @@ impl Ext2INode {
 			Dirent::write_new(buf, &fs.sp, entry_inode, rec_len, Some(file_type), name)?;
 			// Create free entries to cover remaining free space
 			fill_free_entries(&mut buf[rec_len as usize..], &fs.sp)?;
-			self.set_size(&fs.sp, (blocks as u64 + 1) * blk_size as u64, false);
-			blk.mark_dirty();
+			self.set_size(&fs.sp, (blocks as u64 + 1) * blk_size as u64);
+			fs.mark_dirty(&blk)?;
 		}
 		Ok(())
 	}
@@ -676,14 +886,18 @@ impl Ext2INode {
 		let blk = fs.dev.ops.read_page(&fs.dev, disk_blk_off.get() as _)?;
 		// Read and free entry
 		let slice = unsafe { blk.slice_mut() };
-		let ent = Dirent::from_slice(&mut slice[inner_off..], &fs.sp)?;
+		let ent = Dirent::from_slice(&mut slice[inner_off..], fs)?;
 		ent.inode = inode as _;
-		blk.mark_dirty();
+		fs.mark_dirty(&blk)?;
+		if inode == 0 {
+			// The index is not maintained: the directory becomes linear
+			self.i_flags &= !INODE_FLAG_HASH_INDEXED;
+		}
 		// If the block is now empty, free it
-		if inode == 0 && is_block_empty(slice, &fs.sp)? {
+		if inode == 0 && is_block_empty(slice, fs)? {
 			// If this is the last block, update the file's size
 			if file_blk_off as u32 + 1 >= self.get_blocks(&fs.sp) {
-				self.set_size(&fs.sp, file_blk_off * blk_size as u64, false);
+				self.set_size(&fs.sp, file_blk_off * blk_size as u64);
 			}
 			self.free_content_blk(file_blk_off as _, fs)?;
 		}
@@ -0,0 +1,661 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The journal makes updates of the filesystem's metadata atomic, so that the filesystem is left
+//! consistent after a crash. It uses the format of JBD2, the journaling layer of ext3 and ext4.
+//!
+//! Updates of metadata blocks (bitmaps, inodes, directory entries, ...) are grouped into a
+//! transaction. Operations on the filesystem hold a [`Handle`] on the running transaction, which
+//! prevents it from being committed while an operation is half done.
+//!
+//! Transactions are committed in *ordered* mode:
+//! - data blocks allocated during the transaction are written back first, so that metadata never
+//!   points to stale data
+//! - copies of the metadata blocks are written to the journal, followed by a commit block
+//! - the journal's superblock is updated to point to the transaction, which is replayed at the
+//!   next mount if a crash happens before the end of the commit
+//! - metadata blocks are written back to their location on the filesystem (checkpoint), after
+//!   which the journal is marked empty
+//!
+//! Since a transaction is checkpointed before the next one begins, the journal holds at most one
+//! transaction at a time.
+//!
+//! The running transaction is committed when the filesystem is synchronized, when it grows too
+//! large, and periodically by a kernel task.
+
+use super::{Ext2Fs, inode::Ext2INode};
+use crate::{
+	device::BlkDev,
+	memory::cache::RcPage,
+	println,
+	process::scheduler::schedule,
+	sync::{mutex::Mutex, spin::Spin},
+	time::{clock::Clock, sleep_for},
+};
+use core::{
+	hint::unlikely,
+	mem, ptr,
+	sync::atomic::{
+		AtomicUsize,
+		Ordering::{Acquire, Relaxed, Release},
+	},
+};
+use utils::{
+	collections::{btreemap::BTreeMap, vec::Vec},
+	errno,
+	errno::{AllocResult, EResult},
+	limits::PAGE_SIZE,
+	ptr::arc::Arc,
+};
+
+/// The magic number at the beginning of the journal's metadata blocks.
+const JOURNAL_MAGIC: u32 = 0xc03b3998;
+
+/// Block type: descriptor, listing the blocks of a transaction
+const BLOCKTYPE_DESCRIPTOR: u32 = 1;
+/// Block type: commit, ending a transaction
+const BLOCKTYPE_COMMIT: u32 = 2;
+/// Block type: superblock, version 1
+const BLOCKTYPE_SUPERBLOCK_V1: u32 = 3;
+/// Block type: superblock, version 2
+const BLOCKTYPE_SUPERBLOCK_V2: u32 = 4;
+/// Block type: revocation records
+const BLOCKTYPE_REVOKE: u32 = 5;
+
+/// Incompatible feature: the journal contains revocation records
+const INCOMPAT_REVOKE: u32 = 0x1;
+/// Incompatible feature: block numbers are 64 bits wide
+const INCOMPAT_64BIT: u32 = 0x2;
+/// Incompatible feature: commit blocks are written without waiting for the other blocks
+const INCOMPAT_ASYNC_COMMIT: u32 = 0x4;
+
+/// Tag flag: the first bytes of the block have been zeroed because they matched
+/// [`JOURNAL_MAGIC`]
+const TAG_FLAG_ESCAPE: u16 = 0x1;
+/// Tag flag: the tag is not followed by a UUID, since it is the same as the previous tag's
+const TAG_FLAG_SAME_UUID: u16 = 0x2;
+/// Tag flag: last tag of the descriptor block
+const TAG_FLAG_LAST_TAG: u16 = 0x8;
+
+/// Offset of the size of a block in the journal's superblock.
+const SB_BLOCKSIZE: usize = 12;
+/// Offset of the number of blocks in the journal's superblock.
+const SB_MAXLEN: usize = 16;
+/// Offset of the first block of the log in the journal's superblock.
+const SB_FIRST: usize = 20;
+/// Offset of the sequence number of the first transaction in the journal's superblock.
+const SB_SEQUENCE: usize = 24;
+/// Offset of the block of the first transaction in the journal's superblock. If zero, the
+/// journal is empty.
+const SB_START: usize = 28;
+/// Offset of the incompatible features in the journal's superblock.
+const SB_FEATURE_INCOMPAT: usize = 40;
+/// Offset of the UUID in the journal's superblock.
+const SB_UUID: usize = 48;
+
+/// The size of the header of the journal's metadata blocks.
+const HEADER_SIZE: usize = 12;
+/// The size of a UUID.
+const UUID_SIZE: usize = 16;
+
+/// The number of data pages after which the running transaction is committed.
+const MAX_DATA_PAGES: usize = 4096;
+/// The interval between periodic commits, in milliseconds.
+const COMMIT_INTERVAL: u64 = 5000;
+
+/// Reads a big-endian `u16` at offset `off` in `buf`.
+fn read_u16(buf: &[u8], off: usize) -> u16 {
+	u16::from_be_bytes([buf[off], buf[off + 1]])
+}
+
+/// Reads a big-endian `u32` at offset `off` in `buf`.
+fn read_u32(buf: &[u8], off: usize) -> u32 {
+	u32::from_be_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+/// Writes a big-endian `u16` at offset `off` in `buf`.
+fn write_u16(buf: &mut [u8], off: usize, val: u16) {
+	buf[off..(off + 2)].copy_from_slice(&val.to_be_bytes());
+}
+
+/// Writes a big-endian `u32` at offset `off` in `buf`.
+fn write_u32(buf: &mut [u8], off: usize, val: u32) {
+	buf[off..(off + 4)].copy_from_slice(&val.to_be_bytes());
+}
+
+/// Writes the header of a journal metadata block.
+fn write_header(buf: &mut [u8], blocktype: u32, sequence: u32) {
+	write_u32(buf, 0, JOURNAL_MAGIC);
+	write_u32(buf, 4, blocktype);
+	write_u32(buf, 8, sequence);
+}
+
+/// Tells whether the transaction sequence number `a` comes after `b`, taking wrapping into
+/// account.
+fn seq_after(a: u32, b: u32) -> bool {
+	(a.wrapping_sub(b) as i32) > 0
+}
+
+/// A pass of the journal recovery.
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Pass {
+	/// Find the end of the log
+	Scan,
+	/// Collect revocation records
+	Revoke,
+	/// Write blocks back to the filesystem
+	Replay,
+}
+
+/// A set of updates to be committed atomically.
+#[derive(Debug, Default)]
+struct Transaction {
+	/// Metadata pages, by offset on the underlying device
+	metadata: BTreeMap<u64, RcPage>,
+	/// Data pages to be written back before the metadata, by offset on the underlying device
+	data: BTreeMap<u64, RcPage>,
+}
+
+/// The journal of a filesystem.
+#[derive(Debug)]
+pub struct Journal {
+	/// The device on which the filesystem is located
+	dev: Arc<BlkDev>,
+	/// The offset of the filesystem on the underlying device, in pages
+	///
+	/// Cached pages are identified by their offset on the underlying device, which is different
+	/// when the filesystem is located on a partition.
+	base: u64,
+	/// The location of each block of the journal on the filesystem
+	blocks: Vec<u32>,
+	/// The journal's superblock
+	sb: RcPage,
+	/// The first block of the log
+	first: u32,
+	/// The journal's UUID
+	uuid: [u8; UUID_SIZE],
+	/// Tells whether block numbers are 64 bits wide
+	is_64bit: bool,
+
+	/// The sequence number of the next transaction, locked during commits
+	sequence: Mutex<u32, false>,
+	/// The number of operations holding a handle on the running transaction
+	handles: AtomicUsize,
+	/// The running transaction
+	running: Spin<Transaction>,
+}
+
+impl Journal {
+	/// Loads the journal of the filesystem, replaying the transactions it contains, if any.
+	pub fn load(fs: &Ext2Fs) -> EResult<Self> {
+		if unlikely(fs.sp.s_journal_inum == 0) {
+			return Err(errno!(EINVAL));
+		}
+		// Locate the journal's blocks
+		let inode = Ext2INode::read(fs.sp.s_journal_inum, fs)?;
+		let count = inode.get_size(&fs.sp) / PAGE_SIZE as u64;
+		let count: u32 = count.try_into().map_err(|_| fs.corrupted())?;
+		let mut blocks = Vec::with_capacity(count as _)?;
+		for off in 0..count {
+			let blk = inode
+				.translate_blk_off(off, fs)?
+				.ok_or_else(|| fs.corrupted())?;
+			blocks.push(blk.get())?;
+		}
+		// Read the superblock
+		let sb_blk = *blocks.first().ok_or_else(|| fs.corrupted())?;
+		let sb = fs.dev.ops.read_page(&fs.dev, sb_blk as _)?;
+		let buf = sb.slice::<u8>();
+		let blocktype = read_u32(buf, 4);
+		let maxlen = read_u32(buf, SB_MAXLEN);
+		let first = read_u32(buf, SB_FIRST);
+		let start = read_u32(buf, SB_START);
+		if unlikely(
+			read_u32(buf, 0) != JOURNAL_MAGIC
+				|| !matches!(blocktype, BLOCKTYPE_SUPERBLOCK_V1 | BLOCKTYPE_SUPERBLOCK_V2)
+				|| maxlen > count
+				|| first == 0
+				|| first >= maxlen
+				|| (start != 0 && (start < first || start >= maxlen)),
+		) {
+			return Err(fs.corrupted());
+		}
+		// A valid journal, but not supported
+		if unlikely(read_u32(buf, SB_BLOCKSIZE) as usize != PAGE_SIZE) {
+			return Err(errno!(EINVAL));
+		}
+		let features = if blocktype == BLOCKTYPE_SUPERBLOCK_V2 {
+			read_u32(buf, SB_FEATURE_INCOMPAT)
+		} else {
+			0
+		};
+		if unlikely(features & !(INCOMPAT_REVOKE | INCOMPAT_64BIT | INCOMPAT_ASYNC_COMMIT) != 0) {
+			return Err(errno!(EINVAL));
+		}
+		blocks.truncate(maxlen as _);
+		let mut uuid = [0; UUID_SIZE];
+		uuid.copy_from_slice(&buf[SB_UUID..(SB_UUID + UUID_SIZE)]);
+		let sequence = read_u32(buf, SB_SEQUENCE);
+		let base = fs
+			.dev
+			.ops
+			.partition()
+			.map(|p| p.pages(fs.dev.blk_size.get()).start)
+			.unwrap_or(0);
+		let journal = Self {
+			dev: fs.dev.clone(),
+			base,
+			blocks,
+			sb,
+			first,
+			uuid,
+			is_64bit: features & INCOMPAT_64BIT != 0,
+
+			sequence: Mutex::new(sequence),
+			handles: AtomicUsize::new(0),
+			running: Default::default(),
+		};
+		if start != 0 {
+			// Replaying the journal writes to the device
+			if unlikely(fs.readonly.load(Relaxed)) {
+				println!("ext2: journal recovery required, cannot mount read-only");
+				return Err(errno!(EROFS));
+			}
+			let sequence = journal.recover(start, sequence, fs)?;
+			*journal.sequence.lock() = sequence;
+		}
+		Ok(journal)
+	}
+
+	/// Returns the size of a tag in descriptor blocks.
+	fn tag_size(&self) -> usize {
+		if self.is_64bit { 12 } else { 8 }
+	}
+
+	/// Returns the number of tags fitting in a descriptor block.
+	fn tags_per_desc(&self) -> usize {
+		(PAGE_SIZE - HEADER_SIZE - UUID_SIZE) / self.tag_size()
+	}
+
+	/// Returns the number of journal blocks required to commit a transaction of `count` blocks.
+	fn blocks_needed(&self, count: usize) -> usize {
+		count + count.div_ceil(self.tags_per_desc()) + 1
+	}
+
+	/// Returns the number of blocks available in the log.
+	fn capacity(&self) -> usize {
+		self.blocks.len() - self.first as usize
+	}
+
+	/// Returns the block following `blk` in the log.
+	fn next_block(&self, blk: u32) -> u32 {
+		let next = blk + 1;
+		if next as usize >= self.blocks.len() {
+			self.first
+		} else {
+			next
+		}
+	}
+
+	/// Reads the journal block `blk`.
+	fn read_block(&self, blk: u32) -> EResult<RcPage> {
+		let off = self.blocks[blk as usize];
+		self.dev.ops.read_page(&self.dev, off as _)
+	}
+
+	/// Writes the journal block `blk`, whose content is filled by `f`.
+	///
+	/// The function returns the value returned by `f`.
+	fn write_block<F: FnOnce(&mut [u8]) -> R, R>(&self, blk: u32, f: F) -> EResult<R> {
+		let page = self.read_block(blk)?;
+		// No one else accesses the log while it is written
+		let res = f(unsafe { page.slice_mut() });
+		page.mark_dirty();
+		page.writeback(None, false)?;
+		Ok(res)
+	}
+
+	/// Updates the start of the log and the sequence number of its first transaction in the
+	/// journal's superblock, then writes it to the disk.
+	fn write_sb(&self, start: u32, sequence: u32) -> EResult<()> {
+		// The superblock is only written during mount and commits, which are exclusive
+		let buf = unsafe { self.sb.slice_mut::<u8>() };
+		write_u32(buf, SB_START, start);
+		write_u32(buf, SB_SEQUENCE, sequence);
+		self.sb.mark_dirty();
+		self.sb.writeback(None, false)
+	}
+
+	/// Reads the tag at offset `off` in the descriptor block `buf`.
+	///
+	/// The function returns the filesystem block the tag refers to, along with the tag's flags.
+	fn read_tag(&self, buf: &[u8], off: usize) -> (u64, u16) {
+		let mut blk = read_u32(buf, off) as u64;
+		if self.is_64bit {
+			blk |= (read_u32(buf, off + 8) as u64) << 32;
+		}
+		(blk, read_u16(buf, off + 6))
+	}
+
+	/// Records the revocation records of the revoke block `buf`, belonging to the transaction
+	/// `sequence`.
+	fn read_revoke(
+		&self,
+		buf: &[u8],
+		sequence: u32,
+		revoked: &mut BTreeMap<u64, u32>,
+	) -> EResult<()> {
+		// The number of bytes used in the block, including the header
+		let end = (read_u32(buf, HEADER_SIZE) as usize).min(buf.len());
+		let rec_size = if self.is_64bit { 8 } else { 4 };
+		let mut off = HEADER_SIZE + 4;
+		while off + rec_size <= end {
+			let mut blk = read_u32(buf, off) as u64;
+			if self.is_64bit {
+				blk = (blk << 32) | read_u32(buf, off + 4) as u64;
+			}
+			match revoked.get_mut(&blk) {
+				Some(seq) if seq_after(sequence, *seq) => *seq = sequence,
+				Some(_) => {}
+				None => {
+					revoked.insert(blk, sequence)?;
+				}
+			}
+			off += rec_size;
+		}
+		Ok(())
+	}
+
+	/// Writes the journal block `blk`, which is a copy of the filesystem block `home`, back to
+	/// the filesystem.
+	fn replay_block(&self, blk: u32, home: u64, flags: u16, fs: &Ext2Fs) -> EResult<()> {
+		if unlikely(home >= fs.sp.s_blocks_count as u64) {
+			return Err(fs.corrupted());
+		}
+		let src = self.read_block(blk)?;
+		let dst = self.dev.ops.read_page(&self.dev, home)?;
+		// No one else accesses the filesystem while it is mounted
+		let buf = unsafe { dst.slice_mut::<u8>() };
+		buf.copy_from_slice(src.slice());
+		if flags & TAG_FLAG_ESCAPE != 0 {
+			write_u32(buf, 0, JOURNAL_MAGIC);
+		}
+		dst.mark_dirty();
+		dst.writeback(None, false)
+	}
+
+	/// Performs a pass on the log, starting at block `start` with the transaction `sequence`.
+	///
+	/// If `end` is specified, the pass stops at the transaction with this sequence number.
+	///
+	/// The function returns the sequence number of the first transaction that has not been
+	/// processed.
+	fn recovery_pass(
+		&self,
+		pass: Pass,
+		start: u32,
+		mut sequence: u32,
+		end: Option<u32>,
+		revoked: &mut BTreeMap<u64, u32>,
+		fs: &Ext2Fs,
+	) -> EResult<u32> {
+		let mut blk = start;
+		// The number of blocks read, to detect a log looping on itself
+		let mut count = 0;
+		while end != Some(sequence) {
+			count += 1;
+			if unlikely(count > self.blocks.len()) {
+				return Err(fs.corrupted());
+			}
+			let page = self.read_block(blk)?;
+			let buf = page.slice::<u8>();
+			if read_u32(buf, 0) != JOURNAL_MAGIC || read_u32(buf, 8) != sequence {
+				break;
+			}
+			blk = self.next_block(blk);
+			match read_u32(buf, 4) {
+				BLOCKTYPE_DESCRIPTOR => {
+					let mut off = HEADER_SIZE;
+					while off + self.tag_size() <= buf.len() {
+						let (home, flags) = self.read_tag(buf, off);
+						off += self.tag_size();
+						if flags & TAG_FLAG_SAME_UUID == 0 {
+							off += UUID_SIZE;
+						}
+						let is_revoked = revoked
+							.get(&home)
+							.is_some_and(|seq| !seq_after(sequence, *seq));
+						if pass == Pass::Replay && !is_revoked {
+							self.replay_block(blk, home, flags, fs)?;
+						}
+						blk = self.next_block(blk);
+						count += 1;
+						if flags & TAG_FLAG_LAST_TAG != 0 {
+							break;
+						}
+					}
+				}
+				BLOCKTYPE_COMMIT => sequence = sequence.wrapping_add(1),
+				BLOCKTYPE_REVOKE => {
+					if pass == Pass::Revoke {
+						self.read_revoke(buf, sequence, revoked)?;
+					}
+				}
+				_ => break,
+			}
+		}
+		Ok(sequence)
+	}
+
+	/// Replays the committed transactions of the log, starting at block `start` with the
+	/// transaction `sequence`.
+	///
+	/// The function returns the sequence number of the next transaction.
+	fn recover(&self, start: u32, sequence: u32, fs: &Ext2Fs) -> EResult<u32> {
+		let mut revoked = BTreeMap::new();
+		// Transactions without a commit block are incomplete and are discarded
+		let end = self.recovery_pass(Pass::Scan, start, sequence, None, &mut revoked, fs)?;
+		self.recovery_pass(Pass::Revoke, start, sequence, Some(end), &mut revoked, fs)?;
+		self.recovery_pass(Pass::Replay, start, sequence, Some(end), &mut revoked, fs)?;
+		let count = end.wrapping_sub(sequence);
+		println!("ext2: replayed {count} transaction(s) from the journal");
+		let next = end.wrapping_add(1);
+		self.write_sb(0, next)?;
+		Ok(next)
+	}
+
+	/// Starts an operation, returning a handle on the running transaction.
+	///
+	/// If the running transaction is too large, it is committed first.
+	pub fn start(&self) -> EResult<Handle<'_>> {
+		let full = {
+			let running = self.running.lock();
+			self.blocks_needed(running.metadata.len()) > self.capacity() / 2
+				|| running.data.len() >= MAX_DATA_PAGES
+		};
+		if full {
+			self.commit()?;
+		}
+		// Wait for the commit in progress, if any
+		let _sequence = self.sequence.lock();
+		self.handles.fetch_add(1, Acquire);
+		Ok(Handle(self))
+	}
+
+	/// Adds the metadata page `page` to the running transaction.
+	pub fn add_metadata(&self, page: &RcPage) -> EResult<()> {
+		let _prev = self
+			.running
+			.lock()
+			.metadata
+			.insert(page.dev_offset(), page.clone())?;
+		Ok(())
+	}
+
+	/// Marks the data page `page` as dirty, making sure it is written back before the running
+	/// transaction is committed.
+	pub fn add_data(&self, page: &RcPage) -> EResult<()> {
+		page.mark_dirty();
+		let _prev = self
+			.running
+			.lock()
+			.data
+			.insert(page.dev_offset(), page.clone())?;
+		Ok(())
+	}
+
+	/// Writes the metadata pages of `transaction` to the log, then back to their location on the
+	/// filesystem.
+	fn commit_transaction(&self, transaction: &Transaction, sequence: u32) -> EResult<()> {
+		let count = transaction.metadata.len();
+		if unlikely(self.blocks_needed(count) > self.capacity()) {
+			println!("ext2: transaction too large for the journal, writing it in place");
+			return checkpoint(transaction);
+		}
+		let mut pages = transaction.metadata.iter();
+		let mut blk = self.first;
+		let mut remaining = count;
+		while remaining > 0 {
+			let n = remaining.min(self.tags_per_desc());
+			remaining -= n;
+			let desc = self.read_block(blk)?;
+			// No one else accesses the log while it is written
+			let desc_buf = unsafe { desc.slice_mut::<u8>() };
+			desc_buf.fill(0);
+			write_header(desc_buf, BLOCKTYPE_DESCRIPTOR, sequence);
+			let mut off = HEADER_SIZE;
+			for (i, (dev_off, page)) in pages.by_ref().take(n).enumerate() {
+				blk += 1;
+				// Write a copy of the page, escaping the magic number
+				let mut flags = self.write_block(blk, |buf| {
+					buf.copy_from_slice(page.slice());
+					if read_u32(buf, 0) == JOURNAL_MAGIC {
+						write_u32(buf, 0, 0);
+						TAG_FLAG_ESCAPE
+					} else {
+						0
+					}
+				})?;
+				if i > 0 {
+					flags |= TAG_FLAG_SAME_UUID;
+				}
+				if i + 1 == n {
+					flags |= TAG_FLAG_LAST_TAG;
+				}
+				let home = dev_off - self.base;
+				write_u32(desc_buf, off, home as u32);
+				write_u16(desc_buf, off + 6, flags);
+				if self.is_64bit {
+					write_u32(desc_buf, off + 8, (home >> 32) as u32);
+				}
+				off += self.tag_size();
+				if i == 0 {
+					desc_buf[off..(off + UUID_SIZE)].copy_from_slice(&self.uuid);
+					off += UUID_SIZE;
+				}
+			}
+			desc.mark_dirty();
+			desc.writeback(None, false)?;
+			blk += 1;
+		}
+		self.write_block(blk, |buf| {
+			buf.fill(0);
+			write_header(buf, BLOCKTYPE_COMMIT, sequence);
+		})?;
+		// From here, the transaction is replayed if the checkpoint is interrupted
+		self.write_sb(self.first, sequence)?;
+		checkpoint(transaction)
+	}
+
+	/// Commits the running transaction.
+	pub fn commit(&self) -> EResult<()> {
+		let mut sequence = self.sequence.lock();
+		// Wait for the operations in progress. New ones wait for the lock to be released
+		while self.handles.load(Acquire) > 0 {
+			schedule();
+		}
+		let transaction = mem::take(&mut *self.running.lock());
+		// Write data first, so that committed metadata does not point to stale data
+		for (_, page) in transaction.data.iter() {
+			page.writeback(None, false)?;
+		}
+		if transaction.metadata.is_empty() {
+			return Ok(());
+		}
+		if let Err(errno) = self.commit_transaction(&transaction, *sequence) {
+			// Do not lose the updates, even if they cannot be written atomically anymore
+			for (_, page) in transaction.metadata.iter() {
+				page.mark_dirty();
+			}
+			return Err(errno);
+		}
+		*sequence = sequence.wrapping_add(1);
+		self.write_sb(0, *sequence)
+	}
+}
+
+/// Writes the metadata pages of `transaction` back to their location on the filesystem.
+fn checkpoint(transaction: &Transaction) -> EResult<()> {
+	for (_, page) in transaction.metadata.iter() {
+		page.mark_dirty();
+		page.writeback(None, false)?;
+	}
+	Ok(())
+}
+
+/// A handle on the running transaction of a [`Journal`], preventing it from being committed
+/// until dropped.
+pub struct Handle<'j>(&'j Journal);
+
+impl Drop for Handle<'_> {
+	fn drop(&mut self) {
+		self.0.handles.fetch_sub(1, Release);
+	}
+}
+
+/// The journals of the mounted filesystems, committed periodically.
+static JOURNALS: Spin<Vec<Arc<Journal>>> = Spin::new(Vec::new());
+
+/// Registers `journal` to be committed periodically.
+pub fn register(journal: Arc<Journal>) -> AllocResult<()> {
+	JOURNALS.lock().push(journal)
+}
+
+/// Unregisters `journal`.
+pub fn unregister(journal: &Journal) {
+	JOURNALS
+		.lock()
+		.retain(|j| !ptr::eq(Arc::as_ptr(j), journal));
+}
+
+/// The entry point of the kernel task committing journals periodically.
+pub(crate) fn commit_task() -> ! {
+	loop {
+		let mut i = 0;
+		loop {
+			let Some(journal) = JOURNALS.lock().get(i).cloned() else {
+				break;
+			};
+			if let Err(errno) = journal.commit() {
+				println!("ext2: journal commit failure: {errno}");
+			}
+			i += 1;
+		}
+		let mut remain = 0;
+		let _ = sleep_for(Clock::Monotonic, COMMIT_INTERVAL * 1_000_000, &mut remain);
+	}
+}
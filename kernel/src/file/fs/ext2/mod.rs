@@ -48,7 +48,9 @@
 
 mod bgd;
 mod dirent;
+mod htree;
 mod inode;
+mod journal;
 
 use crate::{
 	device::BlkDev,
@@ -56,9 +58,14 @@ use crate::{
 		DirContext, DirEntry, File, FileType, INode, Stat,
 		fs::{
 			FileOps, Filesystem, FilesystemOps, FilesystemType, NodeOps, Statfs, downcast_fs,
-			ext2::{dirent::DirentIterator, inode::ROOT_DIRECTORY_INODE},
+			ext2::{
+				dirent::DirentIterator,
+				inode::{ATTRIBUTE_FLAGS, ROOT_DIRECTORY_INODE, max_file_size},
+			},
 			generic_file_read, generic_file_write,
+			options::MountOptions,
 		},
+		quota::Quota,
 		vfs,
 		vfs::node::Node,
 	},
@@ -66,6 +73,7 @@ use crate::{
 		cache::{RcBlockVal, RcPage},
 		user::UserSlice,
 	},
+	println,
 	sync::spin::Spin,
 	time::clock::{Clock, current_time_sec},
 };
@@ -73,19 +81,22 @@ use bgd::BlockGroupDescriptor;
 use core::{
 	cmp::max,
 	hint::unlikely,
+	mem,
 	sync::atomic::{
-		AtomicU8, AtomicU16, AtomicU32, AtomicUsize,
+		AtomicBool, AtomicU8, AtomicU16, AtomicU32, AtomicUsize,
 		Ordering::{Acquire, Relaxed, Release},
 	},
 };
 use inode::Ext2INode;
+pub(crate) use journal::commit_task;
+use journal::{Handle, Journal};
 use macros::AnyRepr;
 use utils::{
 	boxed::Box,
 	bytes,
 	collections::path::PathBuf,
 	errno,
-	errno::EResult,
+	errno::{EResult, Errno},
 	limits::{NAME_MAX, PAGE_SIZE, SYMLINK_MAX},
 	math,
 	ptr::arc::Arc,
@@ -137,13 +148,18 @@ const WRITE_REQUIRED_64_BITS: u32 = 0x2;
 const WRITE_REQUIRED_DIRECTORY_BINARY_TREE: u32 = 0x4;
 
 /// Zeros the page at the given offset on the disk.
-fn zero_block(fs: &Ext2Fs, off: u64) -> EResult<()> {
+///
+/// `data` tells whether the page holds file data, as opposed to metadata.
+fn zero_block(fs: &Ext2Fs, off: u64, data: bool) -> EResult<()> {
 	let blk = fs.dev.ops.read_page(&fs.dev, off)?;
 	for b in blk.slice::<AtomicUsize>() {
 		b.store(0, Relaxed);
 	}
-	blk.mark_dirty();
-	Ok(())
+	if data {
+		fs.mark_data_dirty(&blk)
+	} else {
+		fs.mark_dirty(&blk)
+	}
 }
 
 /// Finds a `0` bit in the given block, sets it atomically, then returns its offset.
@@ -167,7 +183,6 @@ fn bitmap_alloc_impl(blk: &RcPage) -> Option<u32> {
 			}
 		});
 		if res.is_ok() {
-			blk.mark_dirty();
 			let unit_off = unit_off * size_of::<usize>() * 8;
 			return Some(unit_off as u32 + off);
 		}
@@ -230,9 +245,10 @@ impl NodeOps for Ext2NodeOps {
 
 	fn link(&self, parent: Arc<Node>, ent: &vfs::Entry) -> EResult<()> {
 		let fs = downcast_fs::<Ext2Fs>(&*parent.fs.ops);
-		if unlikely(fs.readonly) {
+		if unlikely(fs.is_readonly()) {
 			return Err(errno!(EROFS));
 		}
+		let _handle = fs.start()?;
 		// Check the parent file is a directory
 		if parent.get_type() != Some(FileType::Directory) {
 			return Err(errno!(ENOTDIR));
@@ -261,16 +277,17 @@ impl NodeOps for Ext2NodeOps {
 		parent_inode.add_dirent(fs, target.inode as _, &ent.name, target_inode.get_type())?;
 		target_inode.i_links_count += 1;
 		target.stat.lock().nlink = target_inode.i_links_count;
-		parent_inode.mark_dirty();
-		target_inode.mark_dirty();
+		parent_inode.mark_dirty()?;
+		target_inode.mark_dirty()?;
 		Ok(())
 	}
 
 	fn unlink(&self, parent: &Node, ent: &vfs::Entry) -> EResult<()> {
 		let fs = downcast_fs::<Ext2Fs>(&*parent.fs.ops);
-		if unlikely(fs.readonly) {
+		if unlikely(fs.is_readonly()) {
 			return Err(errno!(EROFS));
 		}
+		let _handle = fs.start()?;
 		if ent.name == "." || ent.name == ".." {
 			return Err(errno!(EINVAL));
 		}
@@ -301,8 +318,8 @@ impl NodeOps for Ext2NodeOps {
 				parent.stat.lock().nlink = parent_.i_links_count;
 			}
 		}
-		parent_.mark_dirty();
-		target.mark_dirty();
+		parent_.mark_dirty()?;
+		target.mark_dirty()?;
 		Ok(())
 	}
 
@@ -314,7 +331,7 @@ impl NodeOps for Ext2NodeOps {
 		}
 		let size = inode_.get_size(&fs.sp);
 		if unlikely(size > SYMLINK_MAX as u64) {
-			return Err(errno!(EUCLEAN));
+			return Err(fs.corrupted());
 		}
 		if size <= inode::SYMLINK_INLINE_LIMIT {
 			// The target is stored inline in the inode
@@ -324,7 +341,7 @@ impl NodeOps for Ext2NodeOps {
 		} else {
 			// The target is stored like in regular files
 			let blk =
-				inode::check_blk_off(inode_.i_block[0], &fs.sp)?.ok_or_else(|| errno!(EUCLEAN))?;
+				inode::check_blk_off(inode_.i_block[0], fs)?.ok_or_else(|| fs.corrupted())?;
 			let blk = fs.dev.ops.read_page(&fs.dev, blk.get() as _)?;
 			let len = buf.copy_to_user(0, &blk.slice()[..size as usize])?;
 			Ok(len)
@@ -336,10 +353,13 @@ impl NodeOps for Ext2NodeOps {
 			return Err(errno!(ENAMETOOLONG));
 		}
 		let fs = downcast_fs::<Ext2Fs>(&*node.fs.ops);
+		let _handle = fs.start()?;
 		let mut inode_ = Ext2INode::get(node, fs)?;
 		if inode_.get_type() != FileType::Link {
 			return Err(errno!(EINVAL));
 		}
+		// Free the previous target, if stored in a block
+		inode_.free_content(fs)?;
 		// Get storage slice
 		let inline = buf.len() <= inode::SYMLINK_INLINE_LIMIT as usize;
 		if inline {
@@ -357,20 +377,22 @@ impl NodeOps for Ext2NodeOps {
 			// Copy
 			dst[..buf.len()].copy_from_slice(buf);
 			dst[buf.len()..].fill(0);
+			fs.mark_dirty(&blk)?;
 		}
 		// Update size
-		inode_.set_size(&fs.sp, buf.len() as _, inline);
+		inode_.set_size(&fs.sp, buf.len() as _);
 		node.stat.lock().size = buf.len() as _;
-		inode_.mark_dirty();
+		inode_.mark_dirty()?;
 		Ok(())
 	}
 
 	fn rename(&self, entry: &vfs::Entry, new_parent: &vfs::Entry, new_name: &[u8]) -> EResult<()> {
 		let entry_node = entry.node();
 		let fs = downcast_fs::<Ext2Fs>(&*entry_node.fs.ops);
-		if unlikely(fs.readonly) {
+		if unlikely(fs.is_readonly()) {
 			return Err(errno!(EROFS));
 		}
+		let _handle = fs.start()?;
 		// Create new entry
 		let dir = {
 			let new_parent_node = new_parent.node();
@@ -386,17 +408,15 @@ impl NodeOps for Ext2NodeOps {
 				if unlikely(new_parent_inode.i_links_count == u16::MAX) {
 					return Err(errno!(EMFILE));
 				}
-				let (_, off) = inode
-					.get_dirent(b"..", fs)?
-					.ok_or_else(|| errno!(EUCLEAN))?;
+				let (_, off) = inode.get_dirent(b"..", fs)?.ok_or_else(|| fs.corrupted())?;
 				inode.set_dirent_inode(off, new_parent_node.inode, fs)?;
 				// Update links count
 				new_parent_inode.i_links_count += 1;
 				new_parent.node().stat.lock().nlink = new_parent_inode.i_links_count;
 			}
 			new_parent_inode.add_dirent(fs, entry_node.inode as _, new_name, inode.get_type())?;
-			new_parent_inode.mark_dirty();
-			inode.mark_dirty();
+			new_parent_inode.mark_dirty()?;
+			inode.mark_dirty()?;
 			dir
 		};
 		// Remove old entry
@@ -412,7 +432,7 @@ impl NodeOps for Ext2NodeOps {
 			old_parent_inode.i_links_count = old_parent_inode.i_links_count.saturating_sub(1);
 			old_parent_node.stat.lock().nlink = old_parent_inode.i_links_count;
 		}
-		old_parent_inode.mark_dirty();
+		old_parent_inode.mark_dirty()?;
 		Ok(())
 	}
 
@@ -421,23 +441,46 @@ impl NodeOps for Ext2NodeOps {
 			let fs = downcast_fs::<Ext2Fs>(&*node.fs.ops);
 			let inode = Ext2INode::get(node, fs)?;
 			let off: u32 = off.try_into().map_err(|_| errno!(EOVERFLOW))?;
-			let blk_off = inode
-				.translate_blk_off(off, fs)?
-				.ok_or_else(|| errno!(EOVERFLOW))?;
+			let Some(blk_off) = inode.translate_blk_off(off, fs)? else {
+				// Hole in a sparse file. The block is allocated when written
+				return Ok(RcPage::new_zeroed()?);
+			};
 			fs.dev.ops.read_page(&fs.dev, blk_off.get() as _)
 		})
 	}
 
+	fn bmap(&self, node: &Node, blk: u64) -> EResult<Option<u64>> {
+		let fs = downcast_fs::<Ext2Fs>(&*node.fs.ops);
+		let inode = Ext2INode::get(node, fs)?;
+		// Fast symbolic links store their target in place of block numbers
+		if inode.get_type() == FileType::Link {
+			return Err(errno!(EINVAL));
+		}
+		if blk >= inode.get_blocks(&fs.sp) as u64 {
+			return Ok(None);
+		}
+		let blk = inode.translate_blk_off(blk as _, fs)?;
+		Ok(blk.map(|blk| blk.get() as _))
+	}
+
 	fn set_stat(&self, node: &Node, stat: &Stat) -> EResult<()> {
 		let fs = downcast_fs::<Ext2Fs>(&*node.fs.ops);
+		if unlikely(fs.is_readonly()) {
+			return Err(errno!(EROFS));
+		}
+		let _handle = fs.start()?;
 		let mut inode_ = Ext2INode::get(node, fs)?;
+		// Move the usage of the inode to its new owner
+		let space = inode_.get_space();
+		fs.quota
+			.transfer((inode_.i_uid, inode_.i_gid), (stat.uid, stat.gid), space)?;
 		inode_.set_permissions(stat.mode);
 		inode_.i_uid = stat.uid;
 		inode_.i_gid = stat.gid;
-		inode_.i_ctime = stat.ctime as _;
-		inode_.i_mtime = stat.mtime as _;
-		inode_.i_atime = stat.atime as _;
-		inode_.mark_dirty();
+		inode_.i_ctime = (stat.ctime / 1_000_000_000) as _;
+		inode_.i_mtime = (stat.mtime / 1_000_000_000) as _;
+		inode_.i_atime = (stat.atime / 1_000_000_000) as _;
+		inode_.mark_dirty()?;
 		Ok(())
 	}
 }
@@ -464,15 +507,31 @@ impl FileOps for Ext2FileOps {
 	fn write(&self, file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
 		let node = file.node();
 		let fs = downcast_fs::<Ext2Fs>(&*node.fs.ops);
-		if unlikely(fs.readonly) {
+		if unlikely(fs.is_readonly()) {
 			return Err(errno!(EROFS));
 		}
+		let end = off.saturating_add(buf.len() as u64);
+		if unlikely(end > max_file_size(&fs.sp)) {
+			return Err(errno!(EFBIG));
+		}
 		// TODO replace by filetype-specific FileOps
 		{
-			let inode_ = Ext2INode::get(node, fs)?;
+			let _handle = fs.start()?;
+			let mut inode_ = Ext2INode::get(node, fs)?;
 			if inode_.get_type() != FileType::Regular {
 				return Err(errno!(EINVAL));
 			}
+			// Allocate the blocks falling in holes, dropping the zeroed pages cached for them
+			let blk_size = fs.sp.get_block_size() as u64;
+			let start = (off / blk_size) as u32;
+			let end = end.div_ceil(blk_size) as u32;
+			for blk_off in start..end {
+				if inode_.translate_blk_off(blk_off, fs)?.is_none() {
+					inode_.alloc_content_blk(blk_off, fs)?;
+					node.mapped.invalidate(blk_off as _, blk_off as u64 + 1);
+				}
+			}
+			inode_.mark_dirty()?;
 		}
 		// TODO O_DIRECT
 		generic_file_write(file, off, buf)
@@ -481,37 +540,31 @@ impl FileOps for Ext2FileOps {
 	fn truncate(&self, file: &File, size: u64) -> EResult<()> {
 		let node = file.node();
 		let fs = downcast_fs::<Ext2Fs>(&*node.fs.ops);
-		if unlikely(fs.readonly) {
+		if unlikely(fs.is_readonly()) {
 			return Err(errno!(EROFS));
 		}
+		let _handle = fs.start()?;
 		let mut inode_ = Ext2INode::get(node, fs)?;
 		// TODO replace by filetype-specific FileOps
 		if inode_.get_type() != FileType::Regular {
 			return Err(errno!(EINVAL));
 		}
+		if unlikely(size > max_file_size(&fs.sp)) {
+			return Err(errno!(EFBIG));
+		}
 		// The size of a block
-		let blk_size = fs.sp.get_block_size();
+		let blk_size = fs.sp.get_block_size() as u64;
 		let old_size = inode_.get_size(&fs.sp);
+		// When expanding, the file is left sparse: blocks are allocated when written
 		if size < old_size {
 			// Shrink the file
-			let start = size.div_ceil(blk_size as _) as u32;
-			let end = old_size.div_ceil(blk_size as _) as u32;
-			for off in start..end {
-				inode_.free_content_blk(off, fs)?;
-			}
+			inode_.truncate_content(size, fs)?;
 			// Clear cache
-			node.mapped.truncate(start as _);
-		} else {
-			// Expand the file
-			let start = old_size.div_ceil(blk_size as _) as u32;
-			let end = size.div_ceil(blk_size as _) as u32;
-			for off in start..end {
-				inode_.alloc_content_blk(off, fs)?;
-			}
+			node.mapped.truncate(size.div_ceil(blk_size));
 		}
 		// Update size
-		inode_.set_size(&fs.sp, size, false);
-		inode_.mark_dirty();
+		inode_.set_size(&fs.sp, size);
+		inode_.mark_dirty()?;
 		node.stat.lock().size = size;
 		Ok(())
 	}
@@ -554,7 +607,7 @@ pub struct Superblock {
 	/// The ext2 signature.
 	s_magic: u16,
 	/// The filesystem's state.
-	s_state: u16,
+	s_state: AtomicU16,
 	/// The action to perform when an error is detected.
 	s_errors: u16,
 	/// The minor version.
@@ -582,7 +635,7 @@ pub struct Superblock {
 	/// Optional features for the implementation to support.
 	s_feature_compat: u32,
 	/// Required features for the implementation to support.
-	s_feature_incompat: u32,
+	s_feature_incompat: AtomicU32,
 	/// Required features for the implementation to support for writing.
 	s_feature_ro_compat: u32,
 	/// The filesystem id.
@@ -606,9 +659,27 @@ pub struct Superblock {
 	/// The journal device.
 	s_journal_dev: u32,
 	/// The head of orphan inodes list.
-	s_last_orphan: u32,
+	s_last_orphan: AtomicU32,
+	/// The seed of the hash function of directory indexes.
+	s_hash_seed: [u32; 4],
+	/// The default hash function of directory indexes.
+	s_def_hash_version: u8,
+	/// Unused.
+	_reserved: [u8; 99],
+	/// Miscellaneous flags.
+	s_flags: u32,
+	/// Unused.
+	_reserved2: [u8; 48],
+	/// The number of errors detected on the filesystem.
+	s_error_count: AtomicU32,
+	/// Timestamp of the first detected error.
+	s_first_error_time: AtomicU32,
+	/// Unused.
+	_reserved3: [u8; 48],
+	/// Timestamp of the last detected error.
+	s_last_error_time: AtomicU32,
 
-	_padding: [u8; 788],
+	_padding: [u8; 560],
 }
 
 impl Superblock {
@@ -628,6 +699,11 @@ impl Superblock {
 		math::pow2(self.s_log_block_size + 10) as _
 	}
 
+	/// Tells whether the filesystem supports files larger than 4 GiB.
+	pub fn has_large_files(&self) -> bool {
+		self.s_rev_level >= 1 && self.s_feature_ro_compat & WRITE_REQUIRED_64_BITS != 0
+	}
+
 	/// Returns the log2 of the number of block entries in each block.
 	pub fn get_entries_per_block_log(&self) -> u32 {
 		// An entry is 4 bytes long (`log2(4) = 2`)
@@ -658,6 +734,28 @@ impl Superblock {
 	}
 }
 
+/// The behaviour of the filesystem when an inconsistency is detected.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ErrorsBehaviour {
+	/// Ignore the error
+	Continue,
+	/// Remount the filesystem as read-only
+	RemountRo,
+	/// Panic the kernel
+	Panic,
+}
+
+impl ErrorsBehaviour {
+	/// Returns the behaviour for the given `s_errors` value of the superblock.
+	fn from_superblock(s_errors: u16) -> Self {
+		match s_errors {
+			ERR_ACTION_READ_ONLY => Self::RemountRo,
+			ERR_ACTION_KERNEL_PANIC => Self::Panic,
+			_ => Self::Continue,
+		}
+	}
+}
+
 /// An instance of the ext2 filesystem.
 #[derive(Debug)]
 struct Ext2Fs {
@@ -666,10 +764,187 @@ struct Ext2Fs {
 	/// The filesystem's superblock
 	sp: RcBlockVal<Superblock>,
 	/// Tells whether the filesystem is mounted as read-only
-	readonly: bool,
+	readonly: AtomicBool,
+	/// The behaviour when an inconsistency is detected
+	errors: ErrorsBehaviour,
+	/// Disk quotas
+	quota: Quota,
+	/// The journal, if the filesystem has one
+	journal: Option<Arc<Journal>>,
 }
 
 impl Ext2Fs {
+	/// Tells whether the filesystem is read-only.
+	#[inline]
+	fn is_readonly(&self) -> bool {
+		self.readonly.load(Relaxed)
+	}
+
+	/// Handles an inconsistency detected on the filesystem, according to the `errors` mount
+	/// option.
+	///
+	/// The error is recorded in the superblock, so that the filesystem gets checked by `fsck`.
+	///
+	/// The function returns [`errno::EUCLEAN`], to be returned to the caller.
+	fn corrupted(&self) -> Errno {
+		self.record_error();
+		match self.errors {
+			ErrorsBehaviour::Continue => {}
+			ErrorsBehaviour::RemountRo => {
+				if !self.readonly.swap(true, Relaxed) {
+					println!("ext2: filesystem error detected, remounting read-only");
+				}
+			}
+			ErrorsBehaviour::Panic => panic!("ext2: filesystem error detected"),
+		}
+		errno!(EUCLEAN)
+	}
+
+	/// Marks the filesystem as having errors in the superblock.
+	///
+	/// The superblock is written directly, bypassing the journal, so that the error is
+	/// recorded even if the current transaction never gets committed.
+	fn record_error(&self) {
+		let ts = current_time_sec(Clock::Realtime) as u32;
+		self.sp.s_state.fetch_or(FS_STATE_ERROR, Relaxed);
+		self.sp.s_error_count.fetch_add(1, Relaxed);
+		let _ = self
+			.sp
+			.s_first_error_time
+			.compare_exchange(0, ts, Relaxed, Relaxed);
+		self.sp.s_last_error_time.store(ts, Relaxed);
+		if self.is_readonly() {
+			return;
+		}
+		let page = self.sp.page();
+		page.mark_dirty();
+		if let Err(e) = page.writeback(None, false) {
+			println!("ext2: cannot record filesystem error: {e}");
+		}
+	}
+
+	/// Starts an operation modifying the filesystem.
+	///
+	/// If the filesystem has a journal, the returned handle must be kept until the end of the
+	/// operation so that its updates are committed atomically.
+	fn start(&self) -> EResult<Option<Handle<'_>>> {
+		self.journal.as_ref().map(|j| j.start()).transpose()
+	}
+
+	/// Marks the metadata page `page` as dirty.
+	///
+	/// If the filesystem has a journal, the page is added to the running transaction instead. It
+	/// is then written back when the transaction is committed.
+	fn mark_dirty(&self, page: &RcPage) -> EResult<()> {
+		match &self.journal {
+			Some(journal) => journal.add_metadata(page),
+			None => {
+				page.mark_dirty();
+				Ok(())
+			}
+		}
+	}
+
+	/// Marks the data page `page` as dirty.
+	///
+	/// If the filesystem has a journal, the page is written back before the running transaction
+	/// is committed.
+	fn mark_data_dirty(&self, page: &RcPage) -> EResult<()> {
+		match &self.journal {
+			Some(journal) => journal.add_data(page),
+			None => {
+				page.mark_dirty();
+				Ok(())
+			}
+		}
+	}
+
+	/// Sets or clears the flag telling the journal has to be replayed, then writes the superblock
+	/// to the disk.
+	///
+	/// The flag is set while the filesystem is mounted as read-write, so that other
+	/// implementations know the journal has to be checked.
+	fn set_needs_recovery(&self, set: bool) -> EResult<()> {
+		if self.journal.is_none() {
+			return Ok(());
+		}
+		let incompat = &self.sp.s_feature_incompat;
+		let prev = if set {
+			incompat.fetch_or(REQUIRED_FEATURE_JOURNAL_REPLAY, Relaxed)
+		} else {
+			incompat.fetch_and(!REQUIRED_FEATURE_JOURNAL_REPLAY, Relaxed)
+		};
+		if (prev & REQUIRED_FEATURE_JOURNAL_REPLAY != 0) == set {
+			return Ok(());
+		}
+		let page = self.sp.page();
+		page.mark_dirty();
+		page.writeback(None, false)
+	}
+
+	/// Processes the list of orphan inodes, which are inodes whose deletion or truncation has
+	/// been interrupted, for example by a crash.
+	///
+	/// Inodes without links left are removed. Others have the content located after their size
+	/// freed.
+	fn process_orphans(&self, fs: &Arc<Filesystem>) -> EResult<()> {
+		let mut count = 0;
+		loop {
+			let inode = self.sp.s_last_orphan.load(Relaxed);
+			if inode == 0 {
+				break;
+			}
+			// Check the inode is valid, and that the list does not loop
+			if unlikely(
+				inode < self.sp.get_first_available_inode()
+					|| inode > self.sp.s_inodes_count
+					|| count >= self.sp.s_inodes_count,
+			) {
+				return Err(self.corrupted());
+			}
+			count += 1;
+			let node = Node::new(
+				inode as _,
+				fs.clone(),
+				Default::default(),
+				Box::new(Ext2NodeOps)?,
+				Box::new(Ext2FileOps)?,
+			);
+			let _handle = self.start()?;
+			let mut inode_ = Ext2INode::get(&node, self)?;
+			// The link to the next orphan is stored in place of the deletion time
+			let next = mem::take(&mut inode_.i_dtime);
+			if inode_.i_links_count == 0 {
+				drop(inode_);
+				self.remove_inode(&node)?;
+			} else {
+				let size = inode_.get_size(&self.sp);
+				inode_.truncate_content(size, self)?;
+				inode_.mark_dirty()?;
+			}
+			self.sp.s_last_orphan.store(next, Relaxed);
+			self.mark_dirty(self.sp.page())?;
+		}
+		if count > 0 {
+			println!("ext2: processed {count} orphan inode(s)");
+		}
+		Ok(())
+	}
+
+	/// Removes the inode of the given node, freeing its content.
+	fn remove_inode(&self, node: &Node) -> EResult<()> {
+		let mut inode = Ext2INode::get(node, self)?;
+		inode.i_links_count = 0;
+		let ts = current_time_sec(Clock::Monotonic);
+		inode.i_dtime = ts as _;
+		inode.free_content(self)?;
+		inode.mark_dirty()?;
+		// Free inode
+		self.free_inode(node.inode, inode.get_type() == FileType::Directory)?;
+		self.quota.release(inode.i_uid, inode.i_gid, 0, 1);
+		Ok(())
+	}
+
 	/// Finds a free element in the given bitmap, allocates it, and returns its index.
 	///
 	/// Arguments:
@@ -682,6 +957,7 @@ impl Ext2Fs {
 		for blk_off in start_blk..end_blk {
 			let blk = self.dev.ops.read_page(&self.dev, blk_off as _)?;
 			if let Some(off) = bitmap_alloc_impl(&blk) {
+				self.mark_dirty(&blk)?;
 				let blk_off = blk_off - start_blk;
 				return Ok(Some(blk_off * blk_size * 8 + off));
 			}
@@ -703,7 +979,7 @@ impl Ext2Fs {
 		let bitmap_bit_index = index % 8;
 		// Atomic write and mark as dirty
 		let prev = byte.fetch_and(!(1 << bitmap_bit_index), Release);
-		blk.mark_dirty();
+		self.mark_dirty(&blk)?;
 		Ok(prev & (1 << bitmap_bit_index) != 0)
 	}
 
@@ -727,8 +1003,8 @@ impl Ext2Fs {
 				if directory {
 					bgd.bg_used_dirs_count.fetch_add(1, Release);
 				}
-				self.sp.mark_dirty();
-				bgd.mark_dirty();
+				self.mark_dirty(self.sp.page())?;
+				self.mark_dirty(bgd.page())?;
 				return Ok(group * self.sp.s_inodes_per_group + j + 1);
 			}
 		}
@@ -761,8 +1037,8 @@ impl Ext2Fs {
 			if directory {
 				bgd.bg_used_dirs_count.fetch_sub(1, Release);
 			}
-			self.sp.mark_dirty();
-			bgd.mark_dirty();
+			self.mark_dirty(self.sp.page())?;
+			self.mark_dirty(bgd.page())?;
 		}
 		Ok(())
 	}
@@ -783,12 +1059,12 @@ impl Ext2Fs {
 			};
 			let blk_index = i * self.sp.s_blocks_per_group + j;
 			if unlikely(blk_index <= 2 || blk_index >= self.sp.s_blocks_count) {
-				return Err(errno!(EUCLEAN));
+				return Err(self.corrupted());
 			}
 			self.sp.s_free_blocks_count.fetch_sub(1, Release);
 			bgd.bg_free_blocks_count.fetch_sub(1, Release);
-			self.sp.mark_dirty();
-			bgd.mark_dirty();
+			self.mark_dirty(self.sp.page())?;
+			self.mark_dirty(bgd.page())?;
 			return Ok(blk_index);
 		}
 		Err(errno!(ENOSPC))
@@ -800,7 +1076,7 @@ impl Ext2Fs {
 	pub fn free_block(&self, blk: u32) -> EResult<()> {
 		// Validation
 		if unlikely(blk <= 2 || blk >= self.sp.s_blocks_count) {
-			return Err(errno!(EUCLEAN));
+			return Err(self.corrupted());
 		}
 		// Get block group
 		let group = blk / self.sp.s_blocks_per_group;
@@ -812,8 +1088,8 @@ impl Ext2Fs {
 		if prev {
 			self.sp.s_free_blocks_count.fetch_add(1, Release);
 			bgd.bg_free_blocks_count.fetch_add(1, Release);
-			self.sp.mark_dirty();
-			bgd.mark_dirty();
+			self.mark_dirty(self.sp.page())?;
+			self.mark_dirty(bgd.page())?;
 		}
 		Ok(())
 	}
@@ -831,22 +1107,26 @@ impl FilesystemOps for Ext2Fs {
 	}
 
 	fn get_stat(&self) -> EResult<Statfs> {
+		let free_blocks = self.sp.s_free_blocks_count.load(Relaxed);
 		Ok(Statfs {
 			f_type: EXT2_MAGIC as _,
 			f_bsize: self.sp.get_block_size(),
 			f_blocks: self.sp.s_blocks_count as _,
-			f_bfree: self.sp.s_free_blocks_count.load(Relaxed) as _,
-			// TODO Subtract blocks for superuser
-			f_bavail: self.sp.s_free_blocks_count.load(Relaxed) as _,
+			f_bfree: free_blocks as _,
+			f_bavail: free_blocks.saturating_sub(self.sp.s_r_blocks_count) as _,
 			f_files: self.sp.s_inodes_count as _,
 			f_ffree: self.sp.s_free_inodes_count.load(Relaxed) as _,
 			f_fsid: Default::default(),
 			f_namelen: NAME_MAX as _,
 			f_frsize: math::pow2(self.sp.s_log_frag_size + 10),
-			f_flags: 0, // TODO
+			f_flags: 0,
 		})
 	}
 
+	fn supported_attributes(&self) -> u64 {
+		ATTRIBUTE_FLAGS as _
+	}
+
 	fn root(&self, fs: &Arc<Filesystem>) -> EResult<Arc<Node>> {
 		fs.node_get_or_insert(ROOT_DIRECTORY_INODE as _, || {
 			let mut node = Node::new(
@@ -863,12 +1143,16 @@ impl FilesystemOps for Ext2Fs {
 	}
 
 	fn create_node(&self, fs: &Arc<Filesystem>, stat: Stat) -> EResult<Arc<Node>> {
-		if unlikely(self.readonly) {
+		if unlikely(self.is_readonly()) {
 			return Err(errno!(EROFS));
 		}
+		let _handle = self.start()?;
 		let file_type = stat.get_type().ok_or_else(|| errno!(EINVAL))?;
 		// Allocate an inode
-		let inode_index = self.alloc_inode(file_type == FileType::Directory)?;
+		self.quota.charge(stat.uid, stat.gid, 0, 1)?;
+		let inode_index = self
+			.alloc_inode(file_type == FileType::Directory)
+			.inspect_err(|_| self.quota.release(stat.uid, stat.gid, 0, 1))?;
 		// Create inode
 		let mut node = Node::new(
 			inode_index as _,
@@ -882,9 +1166,9 @@ impl FilesystemOps for Ext2Fs {
 			i_mode: stat.mode as _,
 			i_uid: stat.uid,
 			i_size: 0,
-			i_ctime: stat.ctime as _,
-			i_mtime: stat.mtime as _,
-			i_atime: stat.atime as _,
+			i_ctime: (stat.ctime / 1_000_000_000) as _,
+			i_mtime: (stat.mtime / 1_000_000_000) as _,
+			i_atime: (stat.atime / 1_000_000_000) as _,
 			i_dtime: 0,
 			i_gid: stat.gid,
 			i_links_count: 0,
@@ -910,7 +1194,7 @@ impl FilesystemOps for Ext2Fs {
 			}
 			_ => {}
 		}
-		inode.mark_dirty();
+		inode.mark_dirty()?;
 		// Update stat on `node` and return it
 		let stat = inode.stat(&self.sp);
 		drop(inode);
@@ -922,24 +1206,76 @@ impl FilesystemOps for Ext2Fs {
 	}
 
 	fn destroy_node(&self, node: &Node) -> EResult<()> {
-		if unlikely(self.readonly) {
+		if unlikely(self.is_readonly()) {
 			return Err(errno!(EROFS));
 		}
-		let mut inode = Ext2INode::get(node, self)?;
-		// Remove the inode
-		inode.i_links_count = 0;
-		let ts = current_time_sec(Clock::Monotonic);
-		inode.i_dtime = ts as _;
-		inode.free_content(self)?;
-		inode.mark_dirty();
-		// Free inode
-		self.free_inode(node.inode, inode.get_type() == FileType::Directory)?;
-		Ok(())
+		let _handle = self.start()?;
+		self.remove_inode(node)
 	}
 
 	fn sync_fs(&self) -> EResult<()> {
+		self.quota.sync()?;
+		if let Some(journal) = &self.journal {
+			journal.commit()?;
+		}
 		self.dev.mapped.sync()
 	}
+
+	fn quota(&self) -> Option<&Quota> {
+		Some(&self.quota)
+	}
+
+	fn freeze(&self) -> EResult<()> {
+		// The journal is empty once synchronized, so that the frozen image does not need
+		// recovery
+		if !self.is_readonly() {
+			self.set_needs_recovery(false)?;
+		}
+		Ok(())
+	}
+
+	fn thaw(&self) -> EResult<()> {
+		if !self.is_readonly() {
+			self.set_needs_recovery(true)?;
+		}
+		Ok(())
+	}
+
+	fn remount(&self, readonly: bool) -> EResult<()> {
+		if readonly {
+			// Flush pending writes before refusing new ones
+			self.sync_fs()?;
+			if !self.is_readonly() {
+				self.set_needs_recovery(false)?;
+			}
+		} else {
+			if unlikely(self.dev.ops.is_read_only()) {
+				return Err(errno!(EACCES));
+			}
+			if unlikely(
+				self.sp.s_rev_level >= 1
+					&& self.sp.s_feature_ro_compat & WRITE_REQUIRED_DIRECTORY_BINARY_TREE != 0,
+			) {
+				return Err(errno!(EROFS));
+			}
+			if self.is_readonly() {
+				self.set_needs_recovery(true)?;
+			}
+		}
+		self.readonly.store(readonly, Relaxed);
+		Ok(())
+	}
+}
+
+impl Drop for Ext2Fs {
+	fn drop(&mut self) {
+		if let Some(journal) = &self.journal {
+			journal::unregister(journal);
+			if !self.is_readonly() {
+				let _ = self.set_needs_recovery(false);
+			}
+		}
+	}
 }
 
 /// The ext2 filesystem type.
@@ -959,12 +1295,30 @@ impl FilesystemType for Ext2FsType {
 		dev: Option<Arc<BlkDev>>,
 		_mountpath: PathBuf,
 		readonly: bool,
+		data: &[u8],
 	) -> EResult<Arc<Filesystem>> {
 		let dev = dev.ok_or_else(|| errno!(ENODEV))?;
 		let sp = Superblock::read(&dev)?;
 		if unlikely(!sp.is_valid()) {
 			return Err(errno!(EINVAL));
 		}
+		let mut errors = ErrorsBehaviour::from_superblock(sp.s_errors);
+		for opt in MountOptions::new(data) {
+			let opt = opt?;
+			match opt.key {
+				b"errors" => {
+					errors = match opt.value()? {
+						b"continue" => ErrorsBehaviour::Continue,
+						b"remount-ro" => ErrorsBehaviour::RemountRo,
+						b"panic" => ErrorsBehaviour::Panic,
+						_ => return Err(errno!(EINVAL)),
+					}
+				}
+				// Access times are never updated on reads, so there is nothing to do
+				b"noatime" => opt.flag()?,
+				_ => return Err(errno!(EINVAL)),
+			}
+		}
 		/*if unlikely(sp.s_log_block_size < 2) {
 			return Err(errno!(EINVAL));
 		}*/
@@ -980,19 +1334,32 @@ impl FilesystemType for Ext2FsType {
 			) {
 				return Err(errno!(EINVAL));
 			}
-			let unsupported_required_features = REQUIRED_FEATURE_COMPRESSION
-				| REQUIRED_FEATURE_JOURNAL_REPLAY
-				| REQUIRED_FEATURE_JOURNAL_DEVIXE;
-			if sp.s_feature_incompat & unsupported_required_features != 0 {
+			let unsupported_required_features =
+				REQUIRED_FEATURE_COMPRESSION | REQUIRED_FEATURE_JOURNAL_DEVIXE;
+			let incompat = sp.s_feature_incompat.load(Relaxed);
+			if incompat & unsupported_required_features != 0 {
 				// TODO Log?
 				return Err(errno!(EINVAL));
 			}
+			// The journal cannot be replayed without one
+			if unlikely(
+				incompat & REQUIRED_FEATURE_JOURNAL_REPLAY != 0
+					&& sp.s_feature_compat & OPTIONAL_FEATURE_JOURNAL == 0,
+			) {
+				return Err(errno!(EINVAL));
+			}
 			let unsupported_write_features = WRITE_REQUIRED_DIRECTORY_BINARY_TREE;
 			if !readonly && sp.s_feature_ro_compat & unsupported_write_features != 0 {
 				// TODO Log?
 				return Err(errno!(EROFS));
 			}
 		}
+		let state = sp.s_state.load(Relaxed);
+		if state & FS_STATE_CLEAN == 0 {
+			println!("ext2: mounting unchecked filesystem, running fsck is recommended");
+		} else if state & FS_STATE_ERROR != 0 {
+			println!("ext2: mounting filesystem with errors, running fsck is recommended");
+		}
 		let ts = current_time_sec(Clock::Monotonic);
 		if unlikely(sp.s_mnt_count.load(Relaxed) >= sp.s_max_mnt_count) {
 			return Err(errno!(EINVAL));
@@ -1010,13 +1377,25 @@ impl FilesystemType for Ext2FsType {
 		sp.s_mtime.store(ts as _, Relaxed);
 		sp.s_mnt_count.fetch_add(1, Relaxed);
 		sp.mark_dirty();
-		Ok(Filesystem::new(
-			dev.id.get_device_number(),
-			Box::new(Ext2Fs {
-				dev,
-				sp,
-				readonly,
-			})?,
-		)?)
+		let mut ext2 = Ext2Fs {
+			dev,
+			sp,
+			readonly: AtomicBool::new(readonly),
+			errors,
+			quota: Quota::default(),
+			journal: None,
+		};
+		if ext2.sp.s_rev_level >= 1 && ext2.sp.s_feature_compat & OPTIONAL_FEATURE_JOURNAL != 0 {
+			let journal = Arc::new(Journal::load(&ext2)?)?;
+			journal::register(journal.clone())?;
+			ext2.journal = Some(journal);
+			ext2.set_needs_recovery(!readonly)?;
+		}
+		let fs = Filesystem::new(ext2.dev.id.get_device_number(), Box::new(ext2)?)?;
+		// On a read-only mount, orphans are kept for the next read-write mount
+		if !readonly {
+			downcast_fs::<Ext2Fs>(&*fs.ops).process_orphans(&fs)?;
+		}
+		Ok(fs)
 	}
 }
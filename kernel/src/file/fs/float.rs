@@ -28,7 +28,14 @@ use crate::{
 	},
 	sync::once::OnceInit,
 };
-use utils::{boxed::Box, collections::string::String, errno, errno::EResult, ptr::arc::Arc};
+use utils::{
+	boxed::Box,
+	collections::string::String,
+	errno,
+	errno::EResult,
+	limits::{NAME_MAX, PAGE_SIZE},
+	ptr::arc::Arc,
+};
 
 /// Float filesystem
 #[derive(Debug)]
@@ -48,14 +55,14 @@ impl FilesystemOps for FloatFs {
 	fn get_stat(&self) -> EResult<Statfs> {
 		Ok(Statfs {
 			f_type: 0,
-			f_bsize: 0,
+			f_bsize: PAGE_SIZE as _,
 			f_blocks: 0,
 			f_bfree: 0,
 			f_bavail: 0,
 			f_files: 0,
 			f_ffree: 0,
 			f_fsid: Default::default(),
-			f_namelen: 0,
+			f_namelen: NAME_MAX as _,
 			f_frsize: 0,
 			f_flags: 0,
 		})
@@ -79,15 +86,12 @@ static FLOAT_FS: OnceInit<Arc<Filesystem>> = unsafe { OnceInit::new() };
 
 /// Initializes the floating filesystem
 pub(crate) fn init() -> EResult<()> {
-	let fs = Arc::new(Filesystem {
-		dev: 0,
-		ops: Box::new(FloatFs {
+	let fs = Filesystem::new(
+		0,
+		Box::new(FloatFs {
 			_private: (),
 		})?,
-
-		nodes: Default::default(),
-		buffers: Default::default(),
-	})?;
+	)?;
 	unsafe {
 		OnceInit::init(&FLOAT_FS, fs);
 	}
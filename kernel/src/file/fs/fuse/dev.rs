@@ -0,0 +1,439 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `/dev/fuse` device, through which the daemon receives requests and sends replies.
+//!
+//! Each open file description of the device is a separate connection, which is attached to a
+//! filesystem when mounted with the `fd` option.
+
+use super::proto::{
+	INIT, InHeader, InitIn, InitOut, KERNEL_MINOR_VERSION, KERNEL_VERSION, MIN_READ_BUFFER,
+	OutHeader,
+};
+use crate::{
+	file::{File, O_NONBLOCK, fs::FileOps},
+	memory::user::UserSlice,
+	process::Process,
+	sync::{spin::Spin, wait_queue::WaitQueue},
+	syscall::select::{POLLERR, POLLIN, POLLOUT, POLLRDNORM, POLLWRNORM},
+};
+use core::{cmp::max, hint::unlikely, mem::size_of};
+use utils::{
+	bytes,
+	bytes::AnyRepr,
+	collections::{btreemap::BTreeMap, hashmap::HashMap, vec::Vec},
+	errno,
+	errno::{EResult, Errno},
+	limits::PAGE_SIZE,
+	ptr::arc::Arc,
+};
+
+/// The state of a request awaiting a reply.
+#[derive(Debug)]
+enum Pending {
+	/// The caller is waiting for the reply.
+	Waiting,
+	/// The reply has been received, with the data following the header.
+	Replied(EResult<Vec<u8>>),
+	/// The reply is ignored.
+	Background,
+	/// The request is [`INIT`].
+	Init,
+}
+
+/// The state of a connection.
+#[derive(Debug)]
+struct ConnectionInner {
+	/// Requests waiting to be read by the daemon, by unique ID.
+	queue: BTreeMap<u64, Vec<u8>>,
+	/// Requests awaiting a reply, by unique ID.
+	pending: HashMap<u64, Pending>,
+	/// The unique ID of the next request.
+	next_unique: u64,
+	/// Tells whether a filesystem is mounted on the connection.
+	mounted: bool,
+	/// Tells whether the reply to [`INIT`] has been received.
+	initialized: bool,
+	/// Tells whether the connection has been aborted, in which case no request can be sent
+	/// anymore.
+	aborted: bool,
+	/// The maximum size of the data of a write request.
+	max_write: u32,
+}
+
+/// A connection between the kernel and a FUSE daemon.
+#[derive(Debug)]
+pub struct Connection {
+	/// The state of the connection.
+	inner: Spin<ConnectionInner>,
+	/// The queue of daemon processes waiting for requests.
+	rd_queue: WaitQueue,
+	/// The queue of processes waiting for replies.
+	reply_queue: WaitQueue,
+}
+
+impl Connection {
+	/// Creates a new connection.
+	fn new() -> Self {
+		Self {
+			inner: Spin::new(ConnectionInner {
+				queue: BTreeMap::new(),
+				pending: HashMap::new(),
+				next_unique: 1,
+				mounted: false,
+				initialized: false,
+				aborted: false,
+				max_write: PAGE_SIZE as _,
+			}),
+			rd_queue: WaitQueue::new(),
+			reply_queue: WaitQueue::new(),
+		}
+	}
+
+	/// Returns the maximum size of the data of a read or write request.
+	pub fn max_io(&self) -> usize {
+		self.inner.lock().max_write as _
+	}
+
+	/// Marks the connection as mounted and sends the [`INIT`] request.
+	///
+	/// The reply is not waited for, since the daemon is usually the process mounting the
+	/// filesystem. Instead, other requests wait for it.
+	///
+	/// If the connection is already mounted or aborted, the function returns
+	/// [`errno::EINVAL`].
+	pub fn mount(&self) -> EResult<()> {
+		let mut inner = self.inner.lock();
+		if unlikely(inner.mounted || inner.aborted) {
+			return Err(errno!(EINVAL));
+		}
+		let init = InitIn {
+			major: KERNEL_VERSION,
+			minor: KERNEL_MINOR_VERSION,
+			max_readahead: 0,
+			flags: 0,
+		};
+		self.push(
+			&mut inner,
+			INIT,
+			0,
+			&[bytes::as_bytes(&init)],
+			Some(Pending::Init),
+		)?;
+		inner.mounted = true;
+		Ok(())
+	}
+
+	/// Aborts the connection, failing all requests that have not been replied to.
+	pub fn abort(&self) {
+		let mut inner = self.inner.lock();
+		inner.aborted = true;
+		inner.queue.clear();
+		drop(inner);
+		self.rd_queue.wake_all();
+		self.reply_queue.wake_all();
+	}
+
+	/// Builds a request and adds it to the queue.
+	///
+	/// Arguments:
+	/// - `inner` is the locked state of the connection
+	/// - `opcode` is the operation to perform
+	/// - `nodeid` is the ID of the node the operation applies to
+	/// - `args` is the list of arguments, which are concatenated after the header
+	/// - `pending` is the state of the request while waiting for its reply. If `None`, the daemon
+	///   does not reply
+	///
+	/// On success, the function returns the unique ID of the request.
+	fn push(
+		&self,
+		inner: &mut ConnectionInner,
+		opcode: u32,
+		nodeid: u64,
+		args: &[&[u8]],
+		pending: Option<Pending>,
+	) -> EResult<u64> {
+		let unique = inner.next_unique;
+		let len = size_of::<InHeader>() + args.iter().map(|a| a.len()).sum::<usize>();
+		// Requests not sent by a process are attributed to the kernel
+		let (uid, gid, pid) = match pending {
+			Some(Pending::Waiting) => {
+				let proc = Process::current();
+				let ap = proc.cred().ap;
+				(ap.fsuid as _, ap.fsgid as _, proc.get_pid() as _)
+			}
+			_ => (0, 0, 0),
+		};
+		let hdr = InHeader {
+			len: len as _,
+			opcode,
+			unique,
+			nodeid,
+			uid,
+			gid,
+			pid,
+			padding: 0,
+		};
+		let mut msg = Vec::with_capacity(len)?;
+		msg.extend_from_slice(bytes::as_bytes(&hdr))?;
+		for arg in args {
+			msg.extend_from_slice(arg)?;
+		}
+		if let Some(pending) = pending {
+			inner.pending.insert(unique, pending)?;
+		}
+		if let Err(e) = inner.queue.insert(unique, msg) {
+			inner.pending.remove(&unique);
+			return Err(e.into());
+		}
+		inner.next_unique += 1;
+		self.rd_queue.wake_next();
+		Ok(unique)
+	}
+
+	/// Sends a request and waits for its reply.
+	///
+	/// Arguments:
+	/// - `opcode` is the operation to perform
+	/// - `nodeid` is the ID of the node the operation applies to
+	/// - `args` is the list of arguments, which are concatenated after the header
+	///
+	/// On success, the function returns the data of the reply, without the header.
+	///
+	/// If the daemon replied with an error, the function returns it. If the connection is
+	/// aborted, the function returns [`errno::ENOTCONN`].
+	pub fn request(&self, opcode: u32, nodeid: u64, args: &[&[u8]]) -> EResult<Vec<u8>> {
+		// Requests can be handled only after the connection is initialized
+		let unique = self.reply_queue.wait_until(|| {
+			let mut inner = self.inner.lock();
+			if inner.aborted {
+				return Some(Err(errno!(ENOTCONN)));
+			}
+			if !inner.initialized {
+				return None;
+			}
+			Some(self.push(&mut inner, opcode, nodeid, args, Some(Pending::Waiting)))
+		})??;
+		let res = self.reply_queue.wait_until(|| {
+			let mut inner = self.inner.lock();
+			if let Some(Pending::Replied(_)) = inner.pending.get(&unique) {
+				let Some(Pending::Replied(res)) = inner.pending.remove(&unique) else {
+					unreachable!();
+				};
+				return Some(res);
+			}
+			inner.aborted.then(|| Err(errno!(ENOTCONN)))
+		});
+		match res {
+			Ok(res) => res,
+			// Interrupted: the reply, if any, is discarded
+			Err(e) => {
+				let mut inner = self.inner.lock();
+				// If the daemon has not read the request yet, it is cancelled
+				let queued = inner.queue.remove(&unique).is_some();
+				match inner.pending.get_mut(&unique) {
+					Some(p @ Pending::Waiting) if !queued => *p = Pending::Background,
+					Some(_) => {
+						inner.pending.remove(&unique);
+					}
+					None => {}
+				}
+				Err(e)
+			}
+		}
+	}
+
+	/// Sends a request without waiting for its reply.
+	///
+	/// If `reply` is `false`, the daemon does not reply to the request.
+	///
+	/// If the connection is aborted, the request is dropped.
+	pub fn send(&self, opcode: u32, nodeid: u64, args: &[&[u8]], reply: bool) -> EResult<()> {
+		let mut inner = self.inner.lock();
+		if inner.aborted {
+			return Ok(());
+		}
+		let pending = reply.then_some(Pending::Background);
+		self.push(&mut inner, opcode, nodeid, args, pending)?;
+		Ok(())
+	}
+
+	/// Handles the reply to the [`INIT`] request.
+	///
+	/// `res` is the result of the request. On failure, the connection is aborted.
+	fn init(&self, inner: &mut ConnectionInner, res: EResult<Vec<u8>>) -> EResult<()> {
+		let out = match res.and_then(|data| read_arg::<InitOut>(&data)) {
+			Ok(out) if out.major == KERNEL_VERSION => out,
+			_ => {
+				inner.aborted = true;
+				inner.queue.clear();
+				return Ok(());
+			}
+		};
+		if out.minor >= 5 {
+			inner.max_write = max(out.max_write, PAGE_SIZE as _);
+		}
+		inner.initialized = true;
+		Ok(())
+	}
+
+	/// Reads the next request into `buf`.
+	fn read(&self, file: &File, buf: UserSlice<u8>) -> EResult<usize> {
+		if unlikely(buf.len() < MIN_READ_BUFFER) {
+			return Err(errno!(EINVAL));
+		}
+		let (unique, msg) = self.rd_queue.wait_until(|| {
+			let mut inner = self.inner.lock();
+			if inner.aborted {
+				return Some(Err(errno!(ENODEV)));
+			}
+			if let Some(req) = inner.queue.pop_first() {
+				return Some(Ok(req));
+			}
+			if file.get_flags() & O_NONBLOCK != 0 {
+				Some(Err(errno!(EAGAIN)))
+			} else {
+				None
+			}
+		})??;
+		let res = if msg.len() <= buf.len() {
+			buf.copy_to_user(0, &msg)
+		} else {
+			Err(errno!(EINVAL))
+		};
+		// The request cannot be handled, fail it
+		if res.is_err() {
+			let mut inner = self.inner.lock();
+			if let Some(p @ Pending::Waiting) = inner.pending.get_mut(&unique) {
+				*p = Pending::Replied(Err(errno!(EIO)));
+				drop(inner);
+				self.reply_queue.wake_all();
+			}
+		}
+		res
+	}
+
+	/// Handles the reply in `buf`.
+	fn write(&self, buf: UserSlice<u8>) -> EResult<usize> {
+		let msg = buf.copy_from_user_vec(0)?.ok_or_else(|| errno!(EFAULT))?;
+		let hdr: OutHeader = read_arg(&msg)?;
+		if unlikely(hdr.len as usize != msg.len()) {
+			return Err(errno!(EINVAL));
+		}
+		// Notifications are not supported
+		if unlikely(hdr.unique == 0) {
+			return Err(errno!(EINVAL));
+		}
+		if unlikely(hdr.error > 0 || hdr.error <= -4096) {
+			return Err(errno!(EINVAL));
+		}
+		let res = if hdr.error == 0 {
+			let mut data = Vec::new();
+			data.extend_from_slice(&msg[size_of::<OutHeader>()..])?;
+			Ok(data)
+		} else {
+			Err(Errno::from_int(-hdr.error))
+		};
+		let mut inner = self.inner.lock();
+		let pending = inner
+			.pending
+			.get_mut(&hdr.unique)
+			.ok_or_else(|| errno!(ENOENT))?;
+		match pending {
+			Pending::Waiting => *pending = Pending::Replied(res),
+			// Replied twice
+			Pending::Replied(_) => return Err(errno!(EINVAL)),
+			Pending::Background => {
+				inner.pending.remove(&hdr.unique);
+			}
+			Pending::Init => {
+				inner.pending.remove(&hdr.unique);
+				self.init(&mut inner, res)?;
+			}
+		}
+		drop(inner);
+		self.reply_queue.wake_all();
+		Ok(msg.len())
+	}
+}
+
+/// Reads an argument of type `T` at the beginning of `data`.
+///
+/// If `data` is too small, the function returns [`errno::EIO`].
+pub fn read_arg<T: AnyRepr>(data: &[u8]) -> EResult<T> {
+	let data = data.get(..size_of::<T>()).ok_or_else(|| errno!(EIO))?;
+	// The type can be represented by any bit pattern
+	let mut val: T = unsafe { core::mem::zeroed() };
+	bytes::as_bytes_mut(&mut val).copy_from_slice(data);
+	Ok(val)
+}
+
+/// The `/dev/fuse` device.
+#[derive(Debug, Default)]
+pub struct FuseDevice {
+	/// The connection of each open file, by file identifier.
+	conns: Spin<HashMap<usize, Arc<Connection>>>,
+}
+
+impl FuseDevice {
+	/// Returns the connection associated with `file`, creating it if necessary.
+	pub fn connection(&self, file: &File) -> EResult<Arc<Connection>> {
+		let mut conns = self.conns.lock();
+		if let Some(conn) = conns.get(&file.id()) {
+			return Ok(conn.clone());
+		}
+		let conn = Arc::new(Connection::new())?;
+		conns.insert(file.id(), conn.clone())?;
+		Ok(conn)
+	}
+}
+
+impl FileOps for FuseDevice {
+	fn acquire(&self, file: &File) {
+		// On failure, the connection is created when the file is first used
+		let _ = self.connection(file);
+	}
+
+	fn release(&self, file: &File) {
+		// Closing the device aborts the filesystem
+		if let Some(conn) = self.conns.lock().remove(&file.id()) {
+			conn.abort();
+		}
+	}
+
+	fn poll(&self, file: &File, mask: u32) -> EResult<u32> {
+		let conn = self.connection(file)?;
+		let inner = (*conn).inner.lock();
+		if inner.aborted {
+			return Ok(POLLERR & mask);
+		}
+		let mut events = POLLOUT | POLLWRNORM;
+		if !inner.queue.is_empty() {
+			events |= POLLIN | POLLRDNORM;
+		}
+		Ok(events & mask)
+	}
+
+	fn read(&self, file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		self.connection(file)?.read(file, buf)
+	}
+
+	fn write(&self, file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		self.connection(file)?.write(buf)
+	}
+}
@@ -0,0 +1,784 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! FUSE (Filesystem in USErspace) allows a userspace process, the *daemon*, to implement a
+//! filesystem.
+//!
+//! The daemon opens `/dev/fuse`, then mounts the filesystem with the file descriptor in the `fd`
+//! option. Operations on the filesystem are then turned into requests that the daemon reads from
+//! the device, and the daemon writes the replies back to it.
+//!
+//! Inode numbers are allocated by the kernel, each one being associated with a node ID given by
+//! the daemon. Since files are created by the VFS before being linked, a new node gets its node
+//! ID only once its first link is created.
+//!
+//! Files are accessed directly, without going through the page cache. As a consequence, they
+//! cannot be mapped in memory.
+
+mod dev;
+mod proto;
+
+use crate::{
+	device::{BlkDev, CharDev, DeviceID, register_char},
+	file::{
+		DirContext, DirEntry, File, FileType, INode, Mode, O_CREAT, O_EXCL, O_NOCTTY, O_TRUNC,
+		Stat,
+		fd::fd_to_file,
+		fs::{
+			FileOps, Filesystem, FilesystemOps, FilesystemType, NodeOps, Statfs, downcast_fs,
+			options::MountOptions,
+		},
+		vfs,
+		vfs::node::Node,
+	},
+	memory::user::UserSlice,
+	sync::{atomic::AtomicU64, spin::Spin},
+	time::unit::Timestamp,
+};
+use core::{
+	any::Any,
+	cmp::{max, min},
+	hint::unlikely,
+	sync::atomic::{
+		AtomicBool,
+		Ordering::{Acquire, Relaxed, Release},
+	},
+};
+use dev::{Connection, FuseDevice, read_arg};
+use proto::*;
+use utils::{
+	TryClone,
+	boxed::Box,
+	bytes::as_bytes,
+	collections::{hashmap::HashMap, path::PathBuf, vec::Vec},
+	errno,
+	errno::EResult,
+	limits::PAGE_SIZE,
+	ptr::arc::Arc,
+};
+
+/// The magic number of FUSE filesystems.
+const FUSE_SUPER_MAGIC: u32 = 0x65735546;
+/// Major number of miscellaneous character devices.
+const MISC_MAJOR: u32 = 10;
+/// Minor number of `/dev/fuse`.
+const FUSE_MINOR: u32 = 229;
+/// The inode number of the root directory.
+const ROOT_INODE: INode = 1;
+
+/// Converts a timestamp given by the daemon into nanoseconds.
+fn to_timestamp(sec: u64, nsec: u32) -> Timestamp {
+	sec.saturating_mul(1_000_000_000).saturating_add(nsec as _)
+}
+
+/// Converts the attributes given by the daemon into a [`Stat`].
+fn attr_to_stat(attr: &Attr) -> Stat {
+	Stat {
+		mode: attr.mode,
+		nlink: min(attr.nlink, u16::MAX as _) as _,
+		uid: attr.uid as _,
+		gid: attr.gid as _,
+		size: attr.size,
+		blocks: attr.blocks,
+		// Device numbers are encoded like Linux's `new_encode_dev`
+		dev_major: (attr.rdev >> 8) & 0xfff,
+		dev_minor: (attr.rdev & 0xff) | ((attr.rdev >> 12) & 0xfff00),
+		ctime: to_timestamp(attr.ctime, attr.ctimensec),
+		mtime: to_timestamp(attr.mtime, attr.mtimensec),
+		atime: to_timestamp(attr.atime, attr.atimensec),
+		btime: None,
+
+		attributes: 0,
+	}
+}
+
+/// Node operations.
+#[derive(Debug)]
+struct FuseNode {
+	/// The connection to the daemon.
+	conn: Arc<Connection>,
+	/// The node ID, or `0` if the node has not been created on the daemon's side yet.
+	nodeid: AtomicU64,
+	/// The number of times the daemon returned the node, to be given back when forgetting it.
+	nlookup: AtomicU64,
+	/// The status of the node as last known by the daemon, to tell which attributes are
+	/// modified.
+	attr: Spin<Stat>,
+	/// The target of a symbolic link that has not been created yet.
+	target: Spin<Vec<u8>>,
+	/// The file handle of each open file description, by file identifier.
+	///
+	/// Since opening a file cannot fail once the open file description exists, an error
+	/// returned by the daemon is kept to be returned by the following operations.
+	handles: Spin<HashMap<usize, EResult<u64>>>,
+}
+
+impl FuseNode {
+	/// Creates an instance.
+	fn new(conn: Arc<Connection>, nodeid: u64, stat: Stat) -> Self {
+		Self {
+			conn,
+			nodeid: AtomicU64::new(nodeid),
+			nlookup: AtomicU64::new(0),
+			attr: Spin::new(stat),
+			target: Default::default(),
+			handles: Default::default(),
+		}
+	}
+
+	/// Returns the operations of `node`.
+	fn from_node(node: &Node) -> &Self {
+		(&*node.node_ops as &dyn Any).downcast_ref().unwrap()
+	}
+
+	/// Returns the node ID.
+	fn id(&self) -> u64 {
+		self.nodeid.load(Acquire)
+	}
+
+	/// Updates the status of `node` with the attributes given by the daemon.
+	fn set_attr(&self, node: &Node, attr: &Attr) {
+		let stat = attr_to_stat(attr);
+		*node.stat.lock() = stat.clone();
+		*self.attr.lock() = stat;
+	}
+
+	/// Returns the file handle of `file`.
+	fn handle(&self, file: &File) -> EResult<u64> {
+		self.handles
+			.lock()
+			.get(&file.id())
+			.cloned()
+			.unwrap_or_else(|| Err(errno!(EBADF)))
+	}
+}
+
+/// Returns the node described by `out`, creating it if necessary.
+fn get_node(fs: &Arc<Filesystem>, out: &EntryOut) -> EResult<Arc<Node>> {
+	let fuse = downcast_fs::<FuseFs>(&*fs.ops);
+	let inode = fuse.inode(out.nodeid)?;
+	let node = fs.node_get_or_insert(inode, || {
+		let stat = attr_to_stat(&out.attr);
+		let ops = FuseNode::new(fuse.conn.clone(), out.nodeid, stat.clone());
+		Ok(Arc::new(Node::new(
+			inode,
+			fs.clone(),
+			stat,
+			Box::new(ops)?,
+			Box::new(FuseFile)?,
+		))?)
+	})?;
+	let ops = FuseNode::from_node(&node);
+	ops.nlookup.fetch_add(1, Relaxed);
+	ops.set_attr(&node, &out.attr);
+	Ok(node)
+}
+
+/// Returns an error if the filesystem of `node` is mounted read-only.
+fn check_writable(node: &Node) -> EResult<()> {
+	let fs = downcast_fs::<FuseFs>(&*node.fs.ops);
+	if unlikely(fs.readonly.load(Relaxed)) {
+		return Err(errno!(EROFS));
+	}
+	Ok(())
+}
+
+impl NodeOps for FuseNode {
+	fn lookup_entry(&self, dir: &Node, ent: &mut vfs::Entry) -> EResult<()> {
+		let res = self
+			.conn
+			.request(LOOKUP, self.id(), &[ent.name.as_bytes(), b"\0"]);
+		ent.node = match res {
+			Ok(data) => {
+				let out: EntryOut = read_arg(&data)?;
+				// A node ID of zero is a negative entry
+				if out.nodeid != 0 {
+					Some(get_node(&dir.fs, &out)?)
+				} else {
+					None
+				}
+			}
+			Err(e) if e.as_int() == errno::ENOENT => None,
+			Err(e) => return Err(e),
+		};
+		Ok(())
+	}
+
+	fn iter_entries(&self, _dir: &Node, ctx: &mut DirContext) -> EResult<()> {
+		let id = self.id();
+		let open = OpenIn {
+			flags: 0,
+			unused: 0,
+		};
+		let data = self.conn.request(OPENDIR, id, &[as_bytes(&open)])?;
+		let fh = read_arg::<OpenOut>(&data)?.fh;
+		let res = (|| loop {
+			let read = ReadIn {
+				fh,
+				offset: ctx.off,
+				size: PAGE_SIZE as _,
+				read_flags: 0,
+				lock_owner: 0,
+				flags: 0,
+				padding: 0,
+			};
+			let data = self.conn.request(READDIR, id, &[as_bytes(&read)])?;
+			if data.is_empty() {
+				break Ok(());
+			}
+			let mut buf = data.as_slice();
+			while !buf.is_empty() {
+				let dirent: Dirent = read_arg(buf)?;
+				let name_end = size_of::<Dirent>() + dirent.namelen as usize;
+				let name = buf
+					.get(size_of::<Dirent>()..name_end)
+					.ok_or_else(|| errno!(EIO))?;
+				// `DT_*` values are the file type bits of the mode
+				let entry_type = FileType::from_mode((dirent.typ & 0xf) << 12);
+				let e = DirEntry {
+					inode: dirent.ino,
+					entry_type,
+					name,
+				};
+				if !(ctx.write)(&e)? {
+					return Ok(());
+				}
+				ctx.off = dirent.off;
+				buf = buf.get(name_end.next_multiple_of(8)..).unwrap_or_default();
+			}
+		})();
+		let release = ReleaseIn {
+			fh,
+			flags: 0,
+			release_flags: 0,
+			lock_owner: 0,
+		};
+		self.conn
+			.send(RELEASEDIR, id, &[as_bytes(&release)], true)?;
+		res
+	}
+
+	fn link(&self, parent: Arc<Node>, ent: &vfs::Entry) -> EResult<()> {
+		check_writable(&parent)?;
+		let node = ent.node();
+		let ops = Self::from_node(node);
+		let name = ent.name.as_bytes();
+		let nodeid = ops.id();
+		let stat = node.stat();
+		let dir = stat.get_type() == Some(FileType::Directory);
+		let data = if nodeid != 0 {
+			let link = LinkIn {
+				oldnodeid: nodeid,
+			};
+			self.conn
+				.request(LINK, self.id(), &[as_bytes(&link), name, b"\0"])?
+		} else {
+			// The node does not exist yet on the daemon's side
+			match stat.get_type() {
+				Some(FileType::Directory) => {
+					let mkdir = MkdirIn {
+						mode: stat.mode & 0o7777,
+						umask: 0,
+					};
+					self.conn
+						.request(MKDIR, self.id(), &[as_bytes(&mkdir), name, b"\0"])?
+				}
+				Some(FileType::Link) => {
+					let target = ops.target.lock().try_clone()?;
+					self.conn
+						.request(SYMLINK, self.id(), &[name, b"\0", &target, b"\0"])?
+				}
+				_ => {
+					let (major, minor) = (stat.dev_major, stat.dev_minor);
+					let mknod = MknodIn {
+						mode: stat.mode,
+						rdev: (minor & 0xff) | (major << 8) | ((minor & !0xff) << 12),
+						umask: 0,
+						padding: 0,
+					};
+					self.conn
+						.request(MKNOD, self.id(), &[as_bytes(&mknod), name, b"\0"])?
+				}
+			}
+		};
+		let out: EntryOut = read_arg(&data)?;
+		if nodeid == 0 {
+			let fs = downcast_fs::<FuseFs>(&*parent.fs.ops);
+			fs.inodes.lock().insert(out.nodeid, node.inode)?;
+			ops.nodeid.store(out.nodeid, Release);
+			parent.fs.node_insert(node.clone())?;
+			ops.target.lock().clear();
+			if dir {
+				parent.stat.lock().nlink += 1;
+			}
+		} else if out.nodeid != nodeid {
+			// The daemon gave a different node ID to the new link, which is not used
+			let forget = ForgetIn {
+				nlookup: 1,
+			};
+			self.conn
+				.send(FORGET, out.nodeid, &[as_bytes(&forget)], false)?;
+			ops.set_attr(node, &out.attr);
+			return Ok(());
+		}
+		ops.nlookup.fetch_add(1, Relaxed);
+		ops.set_attr(node, &out.attr);
+		Ok(())
+	}
+
+	fn unlink(&self, parent: &Node, ent: &vfs::Entry) -> EResult<()> {
+		check_writable(parent)?;
+		let node = ent.node();
+		let dir = node.get_type() == Some(FileType::Directory);
+		let opcode = if dir { RMDIR } else { UNLINK };
+		self.conn
+			.request(opcode, self.id(), &[ent.name.as_bytes(), b"\0"])?;
+		// Update links count
+		if dir {
+			node.stat.lock().nlink = 0;
+			let mut parent_stat = parent.stat.lock();
+			parent_stat.nlink = parent_stat.nlink.saturating_sub(1);
+		} else {
+			let mut stat = node.stat.lock();
+			stat.nlink = stat.nlink.saturating_sub(1);
+		}
+		Ok(())
+	}
+
+	fn readlink(&self, _node: &Node, buf: UserSlice<u8>) -> EResult<usize> {
+		let target = self.conn.request(READLINK, self.id(), &[])?;
+		buf.copy_to_user(0, &target)
+	}
+
+	fn writelink(&self, _node: &Node, buf: &[u8]) -> EResult<()> {
+		// The target is sent when the link is created
+		if unlikely(self.id() != 0) {
+			return Err(errno!(EINVAL));
+		}
+		let mut target = self.target.lock();
+		target.clear();
+		target.extend_from_slice(buf)?;
+		Ok(())
+	}
+
+	fn rename(
+		&self,
+		old_entry: &vfs::Entry,
+		new_parent: &vfs::Entry,
+		new_name: &[u8],
+	) -> EResult<()> {
+		let old_parent = old_entry.parent.as_ref().ok_or_else(|| errno!(EINVAL))?;
+		check_writable(old_parent.node())?;
+		let rename = RenameIn {
+			newdir: Self::from_node(new_parent.node()).id(),
+		};
+		let args: [&[u8]; 5] = [
+			as_bytes(&rename),
+			old_entry.name.as_bytes(),
+			b"\0",
+			new_name,
+			b"\0",
+		];
+		self.conn
+			.request(RENAME, Self::from_node(old_parent.node()).id(), &args)?;
+		Ok(())
+	}
+
+	fn set_stat(&self, node: &Node, stat: &Stat) -> EResult<()> {
+		check_writable(node)?;
+		// The status is sent when the node is created
+		let id = self.id();
+		if id == 0 {
+			return Ok(());
+		}
+		let old = self.attr.lock().clone();
+		let mut set = SetattrIn::default();
+		if stat.mode != old.mode {
+			set.valid |= FATTR_MODE;
+			set.mode = stat.mode;
+		}
+		if stat.uid != old.uid {
+			set.valid |= FATTR_UID;
+			set.uid = stat.uid as _;
+		}
+		if stat.gid != old.gid {
+			set.valid |= FATTR_GID;
+			set.gid = stat.gid as _;
+		}
+		if stat.atime != old.atime {
+			set.valid |= FATTR_ATIME;
+			set.atime = stat.atime / 1_000_000_000;
+			set.atimensec = (stat.atime % 1_000_000_000) as _;
+		}
+		if stat.mtime != old.mtime {
+			set.valid |= FATTR_MTIME;
+			set.mtime = stat.mtime / 1_000_000_000;
+			set.mtimensec = (stat.mtime % 1_000_000_000) as _;
+		}
+		if set.valid == 0 {
+			return Ok(());
+		}
+		let data = self.conn.request(SETATTR, id, &[as_bytes(&set)])?;
+		let out: AttrOut = read_arg(&data)?;
+		// The node's status may be locked by the caller, which updates it
+		*self.attr.lock() = attr_to_stat(&out.attr);
+		Ok(())
+	}
+}
+
+impl Drop for FuseNode {
+	fn drop(&mut self) {
+		let id = self.id();
+		let nlookup = self.nlookup.load(Relaxed);
+		if id != 0 && id != ROOT_ID && nlookup > 0 {
+			let forget = ForgetIn {
+				nlookup,
+			};
+			let _ = self.conn.send(FORGET, id, &[as_bytes(&forget)], false);
+		}
+	}
+}
+
+/// Open file operations.
+#[derive(Debug)]
+struct FuseFile;
+
+impl FileOps for FuseFile {
+	fn acquire(&self, file: &File) {
+		let node = file.node();
+		if node.get_type() != Some(FileType::Regular) {
+			return;
+		}
+		let ops = FuseNode::from_node(node);
+		// Flags that are handled by the kernel are not passed to the daemon
+		let flags = file.get_flags() & !(O_CREAT | O_EXCL | O_NOCTTY | O_TRUNC);
+		let open = OpenIn {
+			flags: flags as _,
+			unused: 0,
+		};
+		let res = ops
+			.conn
+			.request(OPEN, ops.id(), &[as_bytes(&open)])
+			.and_then(|data| read_arg::<OpenOut>(&data))
+			.map(|out| out.fh);
+		// On failure, operations on the file fail since there is no handle
+		let _ = ops.handles.lock().insert(file.id(), res);
+	}
+
+	fn release(&self, file: &File) {
+		let ops = FuseNode::from_node(file.node());
+		let Some(Ok(fh)) = ops.handles.lock().remove(&file.id()) else {
+			return;
+		};
+		let release = ReleaseIn {
+			fh,
+			flags: file.get_flags() as _,
+			release_flags: 0,
+			lock_owner: 0,
+		};
+		let _ = ops
+			.conn
+			.send(RELEASE, ops.id(), &[as_bytes(&release)], true);
+	}
+
+	fn read(&self, file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let node = file.node();
+		if node.get_type() != Some(FileType::Regular) {
+			return Err(errno!(EINVAL));
+		}
+		let ops = FuseNode::from_node(node);
+		let fh = ops.handle(file)?;
+		let max_len = downcast_fs::<FuseFs>(&*node.fs.ops)
+			.max_read
+			.min(ops.conn.max_io());
+		let mut total = 0;
+		while total < buf.len() {
+			let len = min(buf.len() - total, max_len);
+			let read = ReadIn {
+				fh,
+				offset: off + total as u64,
+				size: len as _,
+				read_flags: 0,
+				lock_owner: 0,
+				flags: file.get_flags() as _,
+				padding: 0,
+			};
+			let data = ops.conn.request(READ, ops.id(), &[as_bytes(&read)])?;
+			let n = min(data.len(), len);
+			buf.copy_to_user(total, &data[..n])?;
+			total += n;
+			// End of file
+			if n < len {
+				break;
+			}
+		}
+		Ok(total)
+	}
+
+	fn write(&self, file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let node = file.node();
+		if node.get_type() != Some(FileType::Regular) {
+			return Err(errno!(EINVAL));
+		}
+		check_writable(node)?;
+		let ops = FuseNode::from_node(node);
+		let fh = ops.handle(file)?;
+		let max_len = ops.conn.max_io();
+		let mut chunk = Vec::new();
+		let mut total = 0;
+		while total < buf.len() {
+			let len = min(buf.len() - total, max_len);
+			chunk.resize(len, 0)?;
+			buf.copy_from_user(total, &mut chunk)?;
+			let write = WriteIn {
+				fh,
+				offset: off + total as u64,
+				size: len as _,
+				write_flags: 0,
+				lock_owner: 0,
+				flags: file.get_flags() as _,
+				padding: 0,
+			};
+			let data = ops
+				.conn
+				.request(WRITE, ops.id(), &[as_bytes(&write), &chunk])?;
+			let n = min(read_arg::<WriteOut>(&data)?.size as usize, len);
+			total += n;
+			if n < len {
+				break;
+			}
+		}
+		// Update the size of the file
+		let mut stat = node.stat.lock();
+		stat.size = max(stat.size, off + total as u64);
+		Ok(total)
+	}
+
+	fn truncate(&self, file: &File, size: u64) -> EResult<()> {
+		let node = file.node();
+		check_writable(node)?;
+		let ops = FuseNode::from_node(node);
+		let mut set = SetattrIn {
+			valid: FATTR_SIZE,
+			size,
+			..Default::default()
+		};
+		if let Ok(fh) = ops.handle(file) {
+			set.valid |= FATTR_FH;
+			set.fh = fh;
+		}
+		let data = ops.conn.request(SETATTR, ops.id(), &[as_bytes(&set)])?;
+		let out: AttrOut = read_arg(&data)?;
+		ops.set_attr(node, &out.attr);
+		Ok(())
+	}
+}
+
+/// An instance of FUSE filesystem.
+#[derive(Debug)]
+struct FuseFs {
+	/// The connection to the daemon.
+	conn: Arc<Connection>,
+	/// Tells whether the filesystem is mounted read-only.
+	readonly: AtomicBool,
+	/// The maximum size of a read request.
+	max_read: usize,
+	/// The status of the root directory.
+	root_stat: Stat,
+	/// The inode number associated with each node ID.
+	inodes: Spin<HashMap<u64, INode>>,
+	/// The next inode number to allocate.
+	next_inode: AtomicU64,
+}
+
+impl FuseFs {
+	/// Returns the inode number associated with `nodeid`, allocating one if necessary.
+	fn inode(&self, nodeid: u64) -> EResult<INode> {
+		let mut inodes = self.inodes.lock();
+		if let Some(inode) = inodes.get(&nodeid) {
+			return Ok(*inode);
+		}
+		let inode = self.next_inode.fetch_add(1, Relaxed);
+		inodes.insert(nodeid, inode)?;
+		Ok(inode)
+	}
+}
+
+impl FilesystemOps for FuseFs {
+	fn get_name(&self) -> &[u8] {
+		b"fuse"
+	}
+
+	fn cache_entries(&self) -> bool {
+		// The daemon may change the filesystem at any time
+		false
+	}
+
+	fn get_stat(&self) -> EResult<Statfs> {
+		let data = self.conn.request(STATFS, ROOT_ID, &[])?;
+		let out: StatfsOut = read_arg(&data)?;
+		Ok(Statfs {
+			f_type: FUSE_SUPER_MAGIC,
+			f_bsize: out.bsize,
+			f_blocks: out.blocks as _,
+			f_bfree: out.bfree as _,
+			f_bavail: out.bavail as _,
+			f_files: out.files as _,
+			f_ffree: out.ffree as _,
+			f_fsid: Default::default(),
+			f_namelen: out.namelen,
+			f_frsize: out.frsize,
+			f_flags: 0,
+		})
+	}
+
+	fn root(&self, fs: &Arc<Filesystem>) -> EResult<Arc<Node>> {
+		// The daemon cannot be queried at mount time since it is usually the process mounting the
+		// filesystem, so the status comes from mount options
+		fs.node_get_or_insert(ROOT_INODE, || {
+			let ops = FuseNode::new(self.conn.clone(), ROOT_ID, self.root_stat.clone());
+			Ok(Arc::new(Node::new(
+				ROOT_INODE,
+				fs.clone(),
+				self.root_stat.clone(),
+				Box::new(ops)?,
+				Box::new(FuseFile)?,
+			))?)
+		})
+	}
+
+	fn create_node(&self, fs: &Arc<Filesystem>, stat: Stat) -> EResult<Arc<Node>> {
+		if unlikely(self.readonly.load(Relaxed)) {
+			return Err(errno!(EROFS));
+		}
+		// The node is created on the daemon's side when linked
+		let inode = self.next_inode.fetch_add(1, Relaxed);
+		let ops = FuseNode::new(self.conn.clone(), 0, stat.clone());
+		Ok(Arc::new(Node::new(
+			inode,
+			fs.clone(),
+			stat,
+			Box::new(ops)?,
+			Box::new(FuseFile)?,
+		))?)
+	}
+
+	fn destroy_node(&self, _node: &Node) -> EResult<()> {
+		// The daemon removes the node once it has no link left and it is forgotten
+		Ok(())
+	}
+
+	fn remount(&self, readonly: bool) -> EResult<()> {
+		self.readonly.store(readonly, Relaxed);
+		Ok(())
+	}
+}
+
+impl Drop for FuseFs {
+	fn drop(&mut self) {
+		// Tell the daemon the filesystem is unmounted
+		self.conn.abort();
+	}
+}
+
+/// The FUSE filesystem type.
+pub struct FuseFsType;
+
+impl FilesystemType for FuseFsType {
+	fn get_name(&self) -> &'static [u8] {
+		b"fuse"
+	}
+
+	fn detect(&self, _dev: &Arc<BlkDev>) -> EResult<bool> {
+		Ok(false)
+	}
+
+	fn load_filesystem(
+		&self,
+		_dev: Option<Arc<BlkDev>>,
+		_mountpath: PathBuf,
+		readonly: bool,
+		data: &[u8],
+	) -> EResult<Arc<Filesystem>> {
+		let mut fd = None;
+		let mut root_mode = None;
+		let mut uid = None;
+		let mut gid = None;
+		let mut max_read = usize::MAX;
+		for opt in MountOptions::new(data) {
+			let opt = opt?;
+			match opt.key {
+				b"fd" => fd = Some(opt.value_u64(10)?),
+				b"rootmode" => root_mode = Some(opt.value_u64(8)?),
+				b"user_id" => uid = Some(opt.value_u64(10)?),
+				b"group_id" => gid = Some(opt.value_u64(10)?),
+				b"max_read" => max_read = max(opt.value_u64(10)? as usize, PAGE_SIZE),
+				// Permissions are always checked by the kernel
+				b"default_permissions" | b"allow_other" => opt.flag()?,
+				_ => return Err(errno!(EINVAL)),
+			}
+		}
+		let (Some(fd), Some(root_mode), Some(uid), Some(gid)) = (fd, root_mode, uid, gid) else {
+			return Err(errno!(EINVAL));
+		};
+		let root_mode = Mode::try_from(root_mode).map_err(|_| errno!(EINVAL))?;
+		if unlikely(FileType::from_mode(root_mode) != Some(FileType::Directory)) {
+			return Err(errno!(EINVAL));
+		}
+		let file = fd_to_file(fd.try_into().map_err(|_| errno!(EBADF))?)?;
+		let conn = file
+			.get_buffer::<FuseDevice>()
+			.ok_or_else(|| errno!(EINVAL))?
+			.connection(&file)?;
+		let root_stat = Stat {
+			mode: root_mode,
+			nlink: 2,
+			uid: uid.try_into().map_err(|_| errno!(EINVAL))?,
+			gid: gid.try_into().map_err(|_| errno!(EINVAL))?,
+			..Default::default()
+		};
+		let mut inodes = HashMap::new();
+		inodes.insert(ROOT_ID, ROOT_INODE)?;
+		conn.mount()?;
+		Ok(Filesystem::new(
+			0,
+			Box::new(FuseFs {
+				conn,
+				readonly: AtomicBool::new(readonly),
+				max_read,
+				root_stat,
+				inodes: Spin::new(inodes),
+				next_inode: AtomicU64::new(ROOT_INODE + 1),
+			})?,
+		)?)
+	}
+}
+
+/// Registers the `/dev/fuse` device.
+pub(super) fn register_device() -> EResult<()> {
+	register_char(CharDev::new(
+		DeviceID {
+			major: MISC_MAJOR,
+			minor: FUSE_MINOR,
+		},
+		PathBuf::try_from(b"/dev/fuse")?,
+		0o666,
+		FuseDevice::default(),
+	)?)?;
+	Ok(())
+}
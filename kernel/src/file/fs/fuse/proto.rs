@@ -0,0 +1,444 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Structures and constants of the FUSE protocol.
+//!
+//! Each message begins with a header, followed by arguments specific to the operation. Strings
+//! are terminated with a nul byte.
+
+use macros::AnyRepr;
+
+/// The major version of the protocol.
+pub const KERNEL_VERSION: u32 = 7;
+/// The minor version of the protocol implemented by the kernel.
+pub const KERNEL_MINOR_VERSION: u32 = 26;
+
+/// The node ID of the root directory.
+pub const ROOT_ID: u64 = 1;
+
+/// The minimum size of the buffer the daemon must use to read requests.
+pub const MIN_READ_BUFFER: usize = 8192;
+
+/// Opcode: looks up a directory entry by name.
+pub const LOOKUP: u32 = 1;
+/// Opcode: the kernel forgets about a node.
+pub const FORGET: u32 = 2;
+/// Opcode: changes the attributes of a node.
+pub const SETATTR: u32 = 4;
+/// Opcode: reads the target of a symbolic link.
+pub const READLINK: u32 = 5;
+/// Opcode: creates a symbolic link.
+pub const SYMLINK: u32 = 6;
+/// Opcode: creates a file.
+pub const MKNOD: u32 = 8;
+/// Opcode: creates a directory.
+pub const MKDIR: u32 = 9;
+/// Opcode: removes a file.
+pub const UNLINK: u32 = 10;
+/// Opcode: removes a directory.
+pub const RMDIR: u32 = 11;
+/// Opcode: renames a file.
+pub const RENAME: u32 = 12;
+/// Opcode: creates a hard link.
+pub const LINK: u32 = 13;
+/// Opcode: opens a file.
+pub const OPEN: u32 = 14;
+/// Opcode: reads from an open file.
+pub const READ: u32 = 15;
+/// Opcode: writes to an open file.
+pub const WRITE: u32 = 16;
+/// Opcode: returns statistics about the filesystem.
+pub const STATFS: u32 = 17;
+/// Opcode: closes an open file.
+pub const RELEASE: u32 = 18;
+/// Opcode: initializes the connection.
+pub const INIT: u32 = 26;
+/// Opcode: opens a directory.
+pub const OPENDIR: u32 = 27;
+/// Opcode: reads entries from an open directory.
+pub const READDIR: u32 = 28;
+/// Opcode: closes an open directory.
+pub const RELEASEDIR: u32 = 29;
+
+/// [`SetattrIn`] flag: set the mode.
+pub const FATTR_MODE: u32 = 1 << 0;
+/// [`SetattrIn`] flag: set the owner's user ID.
+pub const FATTR_UID: u32 = 1 << 1;
+/// [`SetattrIn`] flag: set the owner's group ID.
+pub const FATTR_GID: u32 = 1 << 2;
+/// [`SetattrIn`] flag: set the size.
+pub const FATTR_SIZE: u32 = 1 << 3;
+/// [`SetattrIn`] flag: set the timestamp of the last access.
+pub const FATTR_ATIME: u32 = 1 << 4;
+/// [`SetattrIn`] flag: set the timestamp of the last modification.
+pub const FATTR_MTIME: u32 = 1 << 5;
+/// [`SetattrIn`] flag: the file handle is valid.
+pub const FATTR_FH: u32 = 1 << 6;
+
+/// Header of a request.
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct InHeader {
+	/// The size of the request, including the header.
+	pub len: u32,
+	/// The operation to perform.
+	pub opcode: u32,
+	/// The unique ID of the request, to be given back in the reply.
+	pub unique: u64,
+	/// The ID of the node the operation applies to.
+	pub nodeid: u64,
+	/// The filesystem user ID of the calling process.
+	pub uid: u32,
+	/// The filesystem group ID of the calling process.
+	pub gid: u32,
+	/// The PID of the calling process.
+	pub pid: u32,
+	/// Padding.
+	pub padding: u32,
+}
+
+/// Header of a reply.
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct OutHeader {
+	/// The size of the reply, including the header.
+	pub len: u32,
+	/// If non-zero, the negated errno of the error that occurred.
+	pub error: i32,
+	/// The unique ID of the request.
+	pub unique: u64,
+}
+
+/// Arguments of [`INIT`].
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct InitIn {
+	/// The major version of the protocol.
+	pub major: u32,
+	/// The minor version of the protocol.
+	pub minor: u32,
+	/// The maximum readahead size.
+	pub max_readahead: u32,
+	/// Capability flags.
+	pub flags: u32,
+}
+
+/// Reply to [`INIT`], truncated to the fields used by the kernel.
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct InitOut {
+	/// The major version of the protocol.
+	pub major: u32,
+	/// The minor version of the protocol.
+	pub minor: u32,
+	/// The maximum readahead size.
+	pub max_readahead: u32,
+	/// Capability flags.
+	pub flags: u32,
+	/// The maximum number of background requests.
+	pub max_background: u16,
+	/// The number of background requests at which the connection is considered congested.
+	pub congestion_threshold: u16,
+	/// The maximum size of the data of a write request.
+	pub max_write: u32,
+}
+
+/// Attributes of a node.
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct Attr {
+	/// The inode number.
+	pub ino: u64,
+	/// The size of the file, in bytes.
+	pub size: u64,
+	/// The number of 512-byte blocks allocated to the file.
+	pub blocks: u64,
+	/// Timestamp of the last access, in seconds.
+	pub atime: u64,
+	/// Timestamp of the last modification of the content, in seconds.
+	pub mtime: u64,
+	/// Timestamp of the last modification of the status, in seconds.
+	pub ctime: u64,
+	/// Nanoseconds part of `atime`.
+	pub atimensec: u32,
+	/// Nanoseconds part of `mtime`.
+	pub mtimensec: u32,
+	/// Nanoseconds part of `ctime`.
+	pub ctimensec: u32,
+	/// The file's type and permissions.
+	pub mode: u32,
+	/// The number of links to the file.
+	pub nlink: u32,
+	/// The file owner's user ID.
+	pub uid: u32,
+	/// The file owner's group ID.
+	pub gid: u32,
+	/// The device number, if the file is a device file.
+	pub rdev: u32,
+	/// The optimal size for I/O.
+	pub blksize: u32,
+	/// Attribute flags.
+	pub flags: u32,
+}
+
+/// Reply to operations returning a node.
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct EntryOut {
+	/// The ID of the node.
+	pub nodeid: u64,
+	/// The generation of the node ID.
+	pub generation: u64,
+	/// Validity duration of the entry, in seconds.
+	pub entry_valid: u64,
+	/// Validity duration of the attributes, in seconds.
+	pub attr_valid: u64,
+	/// Nanoseconds part of `entry_valid`.
+	pub entry_valid_nsec: u32,
+	/// Nanoseconds part of `attr_valid`.
+	pub attr_valid_nsec: u32,
+	/// The attributes of the node.
+	pub attr: Attr,
+}
+
+/// Reply to operations returning attributes.
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct AttrOut {
+	/// Validity duration of the attributes, in seconds.
+	pub attr_valid: u64,
+	/// Nanoseconds part of `attr_valid`.
+	pub attr_valid_nsec: u32,
+	/// Padding.
+	pub dummy: u32,
+	/// The attributes.
+	pub attr: Attr,
+}
+
+/// Arguments of [`FORGET`].
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct ForgetIn {
+	/// The number of lookups to forget.
+	pub nlookup: u64,
+}
+
+/// Arguments of [`SETATTR`].
+#[repr(C)]
+#[derive(AnyRepr, Debug, Default)]
+pub struct SetattrIn {
+	/// Flags telling which fields are valid.
+	pub valid: u32,
+	/// Padding.
+	pub padding: u32,
+	/// The file handle.
+	pub fh: u64,
+	/// The size.
+	pub size: u64,
+	/// Unused.
+	pub lock_owner: u64,
+	/// Timestamp of the last access, in seconds.
+	pub atime: u64,
+	/// Timestamp of the last modification of the content, in seconds.
+	pub mtime: u64,
+	/// Timestamp of the last modification of the status, in seconds.
+	pub ctime: u64,
+	/// Nanoseconds part of `atime`.
+	pub atimensec: u32,
+	/// Nanoseconds part of `mtime`.
+	pub mtimensec: u32,
+	/// Nanoseconds part of `ctime`.
+	pub ctimensec: u32,
+	/// The mode.
+	pub mode: u32,
+	/// Unused.
+	pub unused4: u32,
+	/// The owner's user ID.
+	pub uid: u32,
+	/// The owner's group ID.
+	pub gid: u32,
+	/// Unused.
+	pub unused5: u32,
+}
+
+/// Arguments of [`MKNOD`], followed by the name of the file.
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct MknodIn {
+	/// The file's type and permissions.
+	pub mode: u32,
+	/// The device number, if the file is a device file.
+	pub rdev: u32,
+	/// The umask of the calling process.
+	pub umask: u32,
+	/// Padding.
+	pub padding: u32,
+}
+
+/// Arguments of [`MKDIR`], followed by the name of the directory.
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct MkdirIn {
+	/// The directory's permissions.
+	pub mode: u32,
+	/// The umask of the calling process.
+	pub umask: u32,
+}
+
+/// Arguments of [`RENAME`], followed by the old and new names.
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct RenameIn {
+	/// The node ID of the new parent directory.
+	pub newdir: u64,
+}
+
+/// Arguments of [`LINK`], followed by the name of the new link.
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct LinkIn {
+	/// The node ID of the target.
+	pub oldnodeid: u64,
+}
+
+/// Arguments of [`OPEN`] and [`OPENDIR`].
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct OpenIn {
+	/// The open flags.
+	pub flags: u32,
+	/// Unused.
+	pub unused: u32,
+}
+
+/// Reply to [`OPEN`] and [`OPENDIR`].
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct OpenOut {
+	/// The file handle.
+	pub fh: u64,
+	/// Open flags.
+	pub open_flags: u32,
+	/// Padding.
+	pub padding: u32,
+}
+
+/// Arguments of [`READ`] and [`READDIR`].
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct ReadIn {
+	/// The file handle.
+	pub fh: u64,
+	/// The offset to read from.
+	pub offset: u64,
+	/// The number of bytes to read.
+	pub size: u32,
+	/// Read flags.
+	pub read_flags: u32,
+	/// Unused.
+	pub lock_owner: u64,
+	/// The open flags.
+	pub flags: u32,
+	/// Padding.
+	pub padding: u32,
+}
+
+/// Arguments of [`WRITE`], followed by the data.
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct WriteIn {
+	/// The file handle.
+	pub fh: u64,
+	/// The offset to write at.
+	pub offset: u64,
+	/// The number of bytes to write.
+	pub size: u32,
+	/// Write flags.
+	pub write_flags: u32,
+	/// Unused.
+	pub lock_owner: u64,
+	/// The open flags.
+	pub flags: u32,
+	/// Padding.
+	pub padding: u32,
+}
+
+/// Reply to [`WRITE`].
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct WriteOut {
+	/// The number of bytes written.
+	pub size: u32,
+	/// Padding.
+	pub padding: u32,
+}
+
+/// Arguments of [`RELEASE`] and [`RELEASEDIR`].
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct ReleaseIn {
+	/// The file handle.
+	pub fh: u64,
+	/// The open flags.
+	pub flags: u32,
+	/// Release flags.
+	pub release_flags: u32,
+	/// Unused.
+	pub lock_owner: u64,
+}
+
+/// Reply to [`STATFS`].
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct StatfsOut {
+	/// Total data blocks in the filesystem.
+	pub blocks: u64,
+	/// Free blocks in the filesystem.
+	pub bfree: u64,
+	/// Free blocks available to unprivileged users.
+	pub bavail: u64,
+	/// Total inodes in the filesystem.
+	pub files: u64,
+	/// Free inodes in the filesystem.
+	pub ffree: u64,
+	/// Block size.
+	pub bsize: u32,
+	/// Maximum length of file names.
+	pub namelen: u32,
+	/// Fragment size.
+	pub frsize: u32,
+	/// Padding.
+	pub padding: u32,
+	/// Unused.
+	pub spare: [u32; 6],
+}
+
+/// Header of a directory entry in the reply to [`READDIR`], followed by the name, padded to a
+/// multiple of 8 bytes.
+#[repr(C)]
+#[derive(AnyRepr, Debug)]
+pub struct Dirent {
+	/// The inode number.
+	pub ino: u64,
+	/// The offset of the next entry.
+	pub off: u64,
+	/// The length of the name.
+	pub namelen: u32,
+	/// The type of the entry, as a `DT_*` value.
+	pub typ: u32,
+}
@@ -18,6 +18,8 @@
 
 //! The initramfs is a tmpfs stored under the form of an archive. It is used as an initialization
 //! environment which doesn't require disk accesses.
+//!
+//! The archive is a binary CPIO archive, which may be compressed with gzip.
 
 use crate::{
 	device, file,
@@ -27,7 +29,13 @@ use crate::{
 	},
 	memory::user::UserSlice,
 };
-use utils::{collections::path::Path, cpio::CPIOParser, errno, errno::EResult, ptr::arc::Arc};
+use utils::{
+	collections::path::Path, compress::gzip, cpio::CPIOParser, errno, errno::EResult,
+	ptr::arc::Arc,
+};
+
+/// The path to the init program provided by an initramfs.
+pub const INIT_PATH: &[u8] = b"/init";
 
 /// Updates the current parent used for the unpacking operation.
 ///
@@ -72,8 +80,25 @@ fn update_parent<'p>(
 
 /// Loads the initramsfs at the root of the VFS.
 ///
-/// `data` is the slice of data representing the initramfs image.
+/// `data` is the slice of data representing the initramfs image. If it is compressed with gzip,
+/// it is decompressed first.
 pub fn load(data: &[u8]) -> EResult<()> {
+	if data.starts_with(&gzip::MAGIC) {
+		let data = gzip::decompress(data)?;
+		return unpack(&data);
+	}
+	unpack(data)
+}
+
+/// Tells whether the initramfs provides an init program at [`INIT_PATH`].
+pub fn has_init() -> bool {
+	Path::new(INIT_PATH)
+		.and_then(|path| vfs::get_file_from_path_opt(path, true))
+		.is_ok_and(|ent| ent.is_some())
+}
+
+/// Unpacks the CPIO archive `data` at the root of the VFS.
+fn unpack(data: &[u8]) -> EResult<()> {
 	// The stored parent directory
 	let mut cur_parent: (&Path, Arc<vfs::Entry>) = (Path::root(), vfs::ROOT.clone());
 	let cpio_parser = CPIOParser::new(data);
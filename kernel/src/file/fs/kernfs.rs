@@ -40,7 +40,7 @@ use core::{
 use utils::{
 	DisplayableStr,
 	boxed::Box,
-	collections::vec::Vec,
+	collections::{string::String, vec::Vec},
 	errno,
 	errno::{AllocResult, EResult},
 	ptr::arc::Arc,
@@ -110,6 +110,11 @@ impl NodeStorage {
 		Ok((inode, slot))
 	}
 
+	/// Returns the number of nodes in the storage.
+	pub fn count(&self) -> usize {
+		self.0.iter().filter(|n| n.is_some()).count()
+	}
+
 	/// Removes the node with inode `inode`.
 	///
 	/// If the node is a non-empty directory, its content is **NOT** removed. It is the caller's
@@ -264,6 +269,25 @@ pub fn static_dir_stat() -> Stat {
 	}
 }
 
+/// Writes the entries `names` of a directory to `ctx`, starting at its offset.
+///
+/// The names are meant to be collected beforehand, so that no lock on the underlying list is
+/// held while writing entries.
+pub fn iter_names(names: &[String], entry_type: FileType, ctx: &mut DirContext) -> EResult<()> {
+	for name in names.iter().skip(ctx.off as usize) {
+		let ent = DirEntry {
+			inode: 0,
+			entry_type: Some(entry_type),
+			name,
+		};
+		if !(ctx.write)(&ent)? {
+			break;
+		}
+		ctx.off += 1;
+	}
+	Ok(())
+}
+
 impl<T: 'static + Clone + Debug> NodeOps for StaticDir<T> {
 	fn lookup_entry(&self, dir: &Node, ent: &mut vfs::Entry) -> EResult<()> {
 		ent.node = self
@@ -19,24 +19,41 @@
 //! A filesystem is the representation of the file hierarchy on a storage
 //! device.
 
+pub mod debug;
 pub mod ext2;
 pub mod float;
+pub mod fuse;
 pub mod initramfs;
 pub mod kernfs;
+pub mod options;
 pub mod proc;
+pub mod squashfs;
+pub mod sys;
 pub mod tmp;
 
 use super::{
 	DirContext, File, INode, Mode, Stat,
 	perm::{Gid, Uid},
+	quota::Quota,
 	vfs,
 };
 use crate::{
 	device::BlkDev,
-	file::vfs::node::Node,
-	memory::{cache::RcPage, user::UserSlice},
-	sync::{mutex::Mutex, spin::Spin},
-	syscall::ioctl,
+	file::vfs::{
+		mountpoint::{
+			FLAG_MANDLOCK, FLAG_NOATIME, FLAG_NODEV, FLAG_NODIRATIME, FLAG_NOEXEC, FLAG_NOSUID,
+			FLAG_RDONLY, FLAG_RELATIME, FLAG_SYNCHRONOUS,
+		},
+		node::Node,
+	},
+	memory::{PhysAddr, cache::RcPage, user::UserSlice},
+	module::kmod,
+	process::scheduler::schedule,
+	sync::{mutex::Mutex, spin::Spin, wait_queue::WaitQueue},
+	syscall::{
+		ioctl,
+		select::{POLLIN, POLLOUT, POLLRDNORM, POLLWRNORM},
+	},
 	time::unit::Timestamp,
 };
 use core::{
@@ -48,12 +65,18 @@ use core::{
 	fmt::{Debug, Formatter},
 	hash::{Hash, Hasher},
 	hint::unlikely,
+	sync::atomic::{
+		AtomicBool, AtomicUsize,
+		Ordering::{Release, SeqCst},
+	},
 };
 use utils::{
+	DisplayableStr,
 	boxed::Box,
-	collections::{hashmap::HashMap, hashset::HashSet, path::PathBuf, string::String},
+	collections::{hashmap::HashMap, hashset::HashSet, path::PathBuf, string::String, vec::Vec},
 	errno,
-	errno::{AllocResult, EResult},
+	errno::{AllocResult, CollectResult, EResult},
+	format,
 	limits::PAGE_SIZE,
 	ptr::arc::Arc,
 };
@@ -96,6 +119,41 @@ pub struct Statfs {
 	f_flags: u32,
 }
 
+/// [`Statfs`] flag: the `f_flags` field is valid.
+const ST_VALID: u32 = 0x20;
+/// [`Statfs`] flag: access times are updated relative to modification or change times.
+const ST_RELATIME: u32 = 0x1000;
+
+impl Statfs {
+	/// Returns the block size of the filesystem.
+	pub fn block_size(&self) -> u32 {
+		self.f_bsize
+	}
+
+	/// Fills `f_flags` from the flags `flags` of the mountpoint through which the filesystem is
+	/// accessed.
+	///
+	/// If the filesystem does not specify a fragment size, the block size is used.
+	pub fn set_mount_flags(&mut self, flags: u32) {
+		// These flags have the same value as their `ST_*` counterpart
+		let same = FLAG_RDONLY
+			| FLAG_NOSUID
+			| FLAG_NODEV
+			| FLAG_NOEXEC
+			| FLAG_SYNCHRONOUS
+			| FLAG_MANDLOCK
+			| FLAG_NOATIME
+			| FLAG_NODIRATIME;
+		self.f_flags = flags & same | ST_VALID;
+		if flags & FLAG_RELATIME != 0 {
+			self.f_flags |= ST_RELATIME;
+		}
+		if self.f_frsize == 0 {
+			self.f_frsize = self.f_bsize;
+		}
+	}
+}
+
 /// A set of attributes to modify on a file's status.
 #[derive(Default)]
 pub struct StatSet {
@@ -105,11 +163,11 @@ pub struct StatSet {
 	pub uid: Option<Uid>,
 	/// Set the owner's group ID.
 	pub gid: Option<Gid>,
-	/// Set the timestamp of the last modification of the metadata.
+	/// Set the timestamp of the last modification of the metadata, in nanoseconds.
 	pub ctime: Option<Timestamp>,
-	/// Set the timestamp of the last modification of the file's content.
+	/// Set the timestamp of the last modification of the file's content, in nanoseconds.
 	pub mtime: Option<Timestamp>,
-	/// Set the timestamp of the last access to the file.
+	/// Set the timestamp of the last access to the file, in nanoseconds.
 	pub atime: Option<Timestamp>,
 }
 
@@ -240,6 +298,17 @@ pub trait NodeOps: Any + Debug {
 		let _ = (node, stat);
 		Ok(())
 	}
+
+	/// Translates the block offset `blk` in `node` to the offset of the block on the device of
+	/// the filesystem. Offsets are in units of the filesystem's block size.
+	///
+	/// If the block is not mapped, the function returns `None`.
+	///
+	/// The default implementation of this function returns an error.
+	fn bmap(&self, node: &Node, blk: u64) -> EResult<Option<u64>> {
+		let _ = (node, blk);
+		Err(errno!(EINVAL))
+	}
 }
 
 /// Open file operations.
@@ -265,9 +334,11 @@ pub trait FileOps: Any + Debug {
 	/// - `mask` is the mask of events to wait for
 	///
 	/// On success, the function returns the mask events that occurred.
+	///
+	/// By default, the file is always ready for reading and writing.
 	fn poll(&self, file: &File, mask: u32) -> EResult<u32> {
-		let _ = (file, mask);
-		Err(errno!(EINVAL))
+		let _ = file;
+		Ok((POLLIN | POLLRDNORM | POLLOUT | POLLWRNORM) & mask)
 	}
 
 	/// Performs an ioctl operation on the device file.
@@ -311,6 +382,22 @@ pub trait FileOps: Any + Debug {
 		Err(errno!(EINVAL))
 	}
 
+	/// Computes the new offset of `file` for `lseek`.
+	///
+	/// Arguments:
+	/// - `file` is the file to perform the operation onto
+	/// - `off` is the offset given to `lseek`
+	/// - `whence` tells how `off` is interpreted
+	///
+	/// If the file has a special seeking behaviour, the function returns the new offset.
+	/// Else, it returns `None` and the generic behaviour is used.
+	///
+	/// The default implementation of this function returns `None`.
+	fn seek(&self, file: &File, off: i64, whence: u32) -> EResult<Option<u64>> {
+		let _ = (file, off, whence);
+		Ok(None)
+	}
+
 	/// Changes the size of the file, truncating its content if necessary.
 	///
 	/// If `size` is greater than or equals to the current size of the file, the function does
@@ -321,6 +408,19 @@ pub trait FileOps: Any + Debug {
 		let _ = (file, size);
 		Err(errno!(EINVAL))
 	}
+
+	/// Returns the physical address of the page of device memory to be mapped at the offset
+	/// `off` (in pages) when `file` is mapped in memory.
+	///
+	/// Device memory is mapped directly, bypassing the page cache.
+	///
+	/// If the file does not support this kind of mapping, the function returns `None`.
+	///
+	/// The default implementation of this function returns `None`.
+	fn map_page(&self, file: &File, off: u64) -> EResult<Option<PhysAddr>> {
+		let _ = (file, off);
+		Ok(None)
+	}
 }
 
 /// Generic implementation for [`FileOps::read`] on regular files.
@@ -335,6 +435,7 @@ pub fn generic_file_read(file: &File, mut off: u64, buf: UserSlice<u8>) -> EResu
 	let buf_len = min(buf.len() as u64, size - off);
 	let start = off / PAGE_SIZE as u64;
 	let end = off.saturating_add(buf_len).div_ceil(PAGE_SIZE as u64);
+	file.readahead.lock().on_read(node, start, end, size);
 	let mut buf_off = 0;
 	for page_off in start..end {
 		let page = node.node_ops.read_page(node, page_off)?;
@@ -415,6 +516,44 @@ pub trait FilesystemOps: Any + Debug {
 	fn sync_fs(&self) -> EResult<()> {
 		Ok(())
 	}
+
+	/// Changes the read-only state of the filesystem, when it is remounted.
+	///
+	/// If the filesystem cannot be made writable, the function returns [`errno::EROFS`].
+	///
+	/// The default implementation of this function does nothing.
+	fn remount(&self, _readonly: bool) -> EResult<()> {
+		Ok(())
+	}
+
+	/// Returns the disk quotas of the filesystem, if it supports them.
+	///
+	/// The default implementation of this function returns `None`.
+	fn quota(&self) -> Option<&Quota> {
+		None
+	}
+
+	/// Returns the set of `STATX_ATTR_*` attributes the filesystem supports on its files.
+	///
+	/// The default implementation of this function returns `0`.
+	fn supported_attributes(&self) -> u64 {
+		0
+	}
+
+	/// Brings the filesystem to a consistent state on its backing storage, once it has been
+	/// synchronized and before it gets frozen.
+	///
+	/// The default implementation of this function does nothing.
+	fn freeze(&self) -> EResult<()> {
+		Ok(())
+	}
+
+	/// Undoes the operations of [`Self::freeze`], when the filesystem is thawed.
+	///
+	/// The default implementation of this function does nothing.
+	fn thaw(&self) -> EResult<()> {
+		Ok(())
+	}
 }
 
 /// Downcasts the given `fs` into `F`.
@@ -464,6 +603,24 @@ pub struct Filesystem {
 	nodes: Mutex<HashSet<NodeWrapper>, false>,
 	/// Active buffers on the filesystem
 	buffers: Mutex<HashMap<INode, Arc<dyn FileOps>>, false>,
+
+	/// Serializes freezing and thawing the filesystem
+	freeze_lock: Mutex<(), false>,
+	/// Tells whether the filesystem is frozen, in which case modifications are blocked
+	frozen: AtomicBool,
+	/// The number of modifications of the filesystem in progress
+	writers: AtomicUsize,
+	/// Queue of processes waiting for the filesystem to be thawed
+	thaw_queue: WaitQueue,
+}
+
+/// Guard of a modification of a [`Filesystem`], preventing it from being frozen until dropped.
+pub struct WriteGuard<'f>(&'f Filesystem);
+
+impl Drop for WriteGuard<'_> {
+	fn drop(&mut self) {
+		self.0.writers.fetch_sub(1, Release);
+	}
 }
 
 impl Filesystem {
@@ -479,6 +636,11 @@ impl Filesystem {
 
 			nodes: Default::default(),
 			buffers: Default::default(),
+
+			freeze_lock: Mutex::new(()),
+			frozen: AtomicBool::new(false),
+			writers: AtomicUsize::new(0),
+			thaw_queue: WaitQueue::new(),
 		})
 	}
 
@@ -539,6 +701,58 @@ impl Filesystem {
 		// Synchronize filesystem structures
 		self.ops.sync_fs()
 	}
+
+	/// Starts a modification of the filesystem, waiting for it to be thawed if it is frozen.
+	///
+	/// The filesystem cannot be frozen until the returned guard is dropped.
+	///
+	/// If waiting is interrupted by a signal handler, the function returns [`errno::EINTR`].
+	pub fn start_write(&self) -> EResult<WriteGuard<'_>> {
+		loop {
+			self.thaw_queue
+				.wait_until(|| (!self.frozen.load(SeqCst)).then_some(()))?;
+			self.writers.fetch_add(1, SeqCst);
+			let guard = WriteGuard(self);
+			// The filesystem may have been frozen in the meantime
+			if !self.frozen.load(SeqCst) {
+				break Ok(guard);
+			}
+		}
+	}
+
+	/// Freezes the filesystem: modifications in progress are waited for, new ones are blocked,
+	/// then the filesystem is synchronized to its backing storage.
+	///
+	/// If the filesystem is already frozen, the function returns [`errno::EBUSY`].
+	pub fn freeze(&self) -> EResult<()> {
+		let _lock = self.freeze_lock.lock();
+		if unlikely(self.frozen.swap(true, SeqCst)) {
+			return Err(errno!(EBUSY));
+		}
+		while self.writers.load(SeqCst) > 0 {
+			schedule();
+		}
+		let res = self.sync().and_then(|_| self.ops.freeze());
+		if res.is_err() {
+			self.frozen.store(false, SeqCst);
+			self.thaw_queue.wake_all();
+		}
+		res
+	}
+
+	/// Thaws the filesystem, allowing modifications again.
+	///
+	/// If the filesystem is not frozen, the function returns [`errno::EINVAL`].
+	pub fn thaw(&self) -> EResult<()> {
+		let _lock = self.freeze_lock.lock();
+		if unlikely(!self.frozen.load(SeqCst)) {
+			return Err(errno!(EINVAL));
+		}
+		self.ops.thaw()?;
+		self.frozen.store(false, SeqCst);
+		self.thaw_queue.wake_all();
+		Ok(())
+	}
 }
 
 impl Drop for Filesystem {
@@ -558,17 +772,28 @@ pub trait FilesystemType {
 	/// `dev` is the device containing the potential filesystem
 	fn detect(&self, dev: &Arc<BlkDev>) -> EResult<bool>;
 
+	/// Returns the priority of the filesystem type when detecting the filesystem on a device.
+	///
+	/// Types with a higher priority are probed first, so that a filesystem with a weak signature
+	/// is not detected on a device containing another one.
+	fn detect_priority(&self) -> i32 {
+		0
+	}
+
 	/// Creates a new instance of the filesystem to mount it.
 	///
 	/// Arguments:
 	/// - `dev` is the mounted device
 	/// - `mountpath` is the path on which the filesystem is mounted
 	/// - `readonly` tells whether the filesystem is mounted in read-only
+	/// - `data` is the filesystem-specific data passed to `mount`, usually a list of options to be
+	///   parsed with [`options::MountOptions`]
 	fn load_filesystem(
 		&self,
 		dev: Option<Arc<BlkDev>>,
 		mountpath: PathBuf,
 		readonly: bool,
+		data: &[u8],
 	) -> EResult<Arc<Filesystem>>;
 }
 
@@ -594,12 +819,40 @@ pub fn get_type(name: &[u8]) -> Option<Arc<dyn FilesystemType>> {
 	FS_TYPES.lock().get(name).cloned()
 }
 
-/// Detects the filesystem type on device
+/// Returns the filesystem type with name `name`.
+///
+/// If the filesystem type is not registered, the function requests the module providing it, then
+/// tries again.
+pub fn request_type(name: &[u8]) -> Option<Arc<dyn FilesystemType>> {
+	get_type(name).or_else(|| {
+		let alias = format!("fs-{}", DisplayableStr(name)).ok()?;
+		kmod::request_module(alias).ok()?;
+		get_type(name)
+	})
+}
+
+/// Detects the filesystem type on device `dev`.
+///
+/// Registered types are probed by decreasing priority (see
+/// [`FilesystemType::detect_priority`]), then by name.
+///
+/// If no type matches, the function returns [`errno::ENODEV`].
 pub fn detect(dev: &Arc<BlkDev>) -> EResult<Arc<dyn FilesystemType>> {
-	let fs_types = FS_TYPES.lock();
-	for (_, fs_type) in fs_types.iter() {
+	// Do not hold the lock while reading the device
+	let mut fs_types = FS_TYPES
+		.lock()
+		.iter()
+		.map(|(_, fs_type)| fs_type.clone())
+		.collect::<CollectResult<Vec<_>>>()
+		.0?;
+	fs_types.sort_unstable_by(|a, b| {
+		b.detect_priority()
+			.cmp(&a.detect_priority())
+			.then_with(|| a.get_name().cmp(b.get_name()))
+	});
+	for fs_type in fs_types {
 		if fs_type.detect(dev)? {
-			return Ok(fs_type.clone());
+			return Ok(fs_type);
 		}
 	}
 	Err(errno!(ENODEV))
@@ -611,7 +864,11 @@ pub fn detect(dev: &Arc<BlkDev>) -> EResult<Arc<dyn FilesystemType>> {
 pub(crate) fn register_defaults() -> EResult<()> {
 	register(ext2::Ext2FsType)?;
 	register(tmp::TmpFsType)?;
+	register(squashfs::SquashFsType)?;
+	register(fuse::FuseFsType)?;
+	fuse::register_device()?;
 	register(proc::ProcFsType)?;
-	// TODO sysfs
+	register(sys::SysFsType)?;
+	register(debug::DebugFsType)?;
 	Ok(())
 }
@@ -0,0 +1,236 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Filesystem-independent parsing of mount options.
+//!
+//! The `data` argument of the `mount` system call is an opaque buffer of up to one page, which
+//! most filesystems interpret as a comma-separated list of `key[=value]` options. A value may be
+//! enclosed in double quotes so that it can contain commas.
+//!
+//! Filesystems taking binary data use the buffer directly instead.
+
+use crate::file::Mode;
+use core::{hint::unlikely, str};
+use utils::{errno, errno::EResult};
+
+/// A mount option.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MountOption<'d> {
+	/// The name of the option.
+	pub key: &'d [u8],
+	/// The value of the option, if any, without its enclosing quotes.
+	pub value: Option<&'d [u8]>,
+}
+
+impl<'d> MountOption<'d> {
+	/// Checks the option has no value, as expected for a flag.
+	///
+	/// If a value is present, the function returns [`errno::EINVAL`].
+	pub fn flag(&self) -> EResult<()> {
+		if unlikely(self.value.is_some()) {
+			return Err(errno!(EINVAL));
+		}
+		Ok(())
+	}
+
+	/// Returns the value of the option.
+	///
+	/// If the option has no value, the function returns [`errno::EINVAL`].
+	pub fn value(&self) -> EResult<&'d [u8]> {
+		self.value.ok_or_else(|| errno!(EINVAL))
+	}
+
+	/// Returns the value of the option as a string.
+	///
+	/// If the option has no value or if it is not valid UTF-8, the function returns
+	/// [`errno::EINVAL`].
+	pub fn value_str(&self) -> EResult<&'d str> {
+		str::from_utf8(self.value()?).map_err(|_| errno!(EINVAL))
+	}
+
+	/// Parses the value of the option as an integer in the given `radix`.
+	pub fn value_u64(&self, radix: u32) -> EResult<u64> {
+		u64::from_str_radix(self.value_str()?, radix).map_err(|_| errno!(EINVAL))
+	}
+
+	/// Parses the value of the option as file permissions, in octal.
+	pub fn value_mode(&self) -> EResult<Mode> {
+		let mode = self.value_u64(8)?;
+		if unlikely(mode > 0o7777) {
+			return Err(errno!(EINVAL));
+		}
+		Ok(mode as _)
+	}
+
+	/// Parses the value of the option as a size in bytes, with an optional binary suffix (`k`,
+	/// `m`, `g` or `t`, case-insensitive).
+	pub fn value_size(&self) -> EResult<u64> {
+		let value = self.value_str()?;
+		let (num, shift) = match value.as_bytes().last() {
+			Some(b'k' | b'K') => (&value[..value.len() - 1], 10),
+			Some(b'm' | b'M') => (&value[..value.len() - 1], 20),
+			Some(b'g' | b'G') => (&value[..value.len() - 1], 30),
+			Some(b't' | b'T') => (&value[..value.len() - 1], 40),
+			_ => (value, 0),
+		};
+		let num: u64 = num.parse().map_err(|_| errno!(EINVAL))?;
+		num.checked_mul(1 << shift).ok_or_else(|| errno!(EINVAL))
+	}
+}
+
+/// Iterator over the options of a mount data buffer.
+///
+/// If an option is malformed, the iterator returns [`errno::EINVAL`], then stops.
+#[derive(Clone, Debug)]
+pub struct MountOptions<'d> {
+	/// The remaining data to parse.
+	data: &'d [u8],
+}
+
+impl<'d> MountOptions<'d> {
+	/// Creates an iterator over the options in `data`.
+	///
+	/// The data is interpreted up to its first nul byte, if any.
+	pub fn new(data: &'d [u8]) -> Self {
+		let len = data.iter().position(|b| *b == b'\0').unwrap_or(data.len());
+		Self {
+			data: &data[..len],
+		}
+	}
+
+	/// Parses the option in `opt`.
+	fn parse(opt: &'d [u8]) -> EResult<MountOption<'d>> {
+		let (key, value) = match opt.iter().position(|b| *b == b'=') {
+			Some(i) => (&opt[..i], Some(&opt[(i + 1)..])),
+			None => (opt, None),
+		};
+		if unlikely(key.is_empty() || key.contains(&b'"')) {
+			return Err(errno!(EINVAL));
+		}
+		let value = match value {
+			// Quoted value
+			Some([b'"', inner @ .., b'"']) if !inner.contains(&b'"') => Some(inner),
+			Some(value) if value.contains(&b'"') => return Err(errno!(EINVAL)),
+			value => value,
+		};
+		Ok(MountOption {
+			key,
+			value,
+		})
+	}
+}
+
+impl<'d> Iterator for MountOptions<'d> {
+	type Item = EResult<MountOption<'d>>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		loop {
+			if self.data.is_empty() {
+				return None;
+			}
+			// Find the end of the option, ignoring commas between quotes
+			let mut quoted = false;
+			let end = self.data.iter().position(|b| {
+				if *b == b'"' {
+					quoted = !quoted;
+				}
+				*b == b',' && !quoted
+			});
+			if unlikely(quoted) {
+				self.data = &[];
+				return Some(Err(errno!(EINVAL)));
+			}
+			let (opt, rest) = match end {
+				Some(end) => (&self.data[..end], &self.data[(end + 1)..]),
+				None => (self.data, &[][..]),
+			};
+			self.data = rest;
+			// Skip empty options
+			if opt.is_empty() {
+				continue;
+			}
+			let res = Self::parse(opt);
+			if res.is_err() {
+				self.data = &[];
+			}
+			return Some(res);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn opt<'d>(key: &'d [u8], value: Option<&'d [u8]>) -> MountOption<'d> {
+		MountOption {
+			key,
+			value,
+		}
+	}
+
+	#[test_case]
+	fn mount_options_empty() {
+		assert_eq!(MountOptions::new(b"").next(), None);
+		assert_eq!(MountOptions::new(b",,").next(), None);
+		assert_eq!(MountOptions::new(b"\0size=1").next(), None);
+	}
+
+	#[test_case]
+	fn mount_options_list() {
+		let mut iter = MountOptions::new(b"noatime,size=10k,,mode=755\0garbage");
+		assert_eq!(iter.next(), Some(Ok(opt(b"noatime", None))));
+		assert_eq!(iter.next(), Some(Ok(opt(b"size", Some(b"10k")))));
+		assert_eq!(iter.next(), Some(Ok(opt(b"mode", Some(b"755")))));
+		assert_eq!(iter.next(), None);
+	}
+
+	#[test_case]
+	fn mount_options_quoted() {
+		let mut iter = MountOptions::new(b"dir=\"/a,b\",x=");
+		assert_eq!(iter.next(), Some(Ok(opt(b"dir", Some(b"/a,b")))));
+		assert_eq!(iter.next(), Some(Ok(opt(b"x", Some(b"")))));
+		assert_eq!(iter.next(), None);
+	}
+
+	#[test_case]
+	fn mount_options_invalid() {
+		let mut iter = MountOptions::new(b"dir=\"/a,b,c=d");
+		assert!(matches!(iter.next(), Some(Err(_))));
+		assert_eq!(iter.next(), None);
+		let mut iter = MountOptions::new(b"=1,a");
+		assert!(matches!(iter.next(), Some(Err(_))));
+		assert_eq!(iter.next(), None);
+		assert!(matches!(
+			MountOptions::new(b"a=b\"c\"").next(),
+			Some(Err(_))
+		));
+	}
+
+	#[test_case]
+	fn mount_options_values() {
+		assert_eq!(opt(b"size", Some(b"2M")).value_size(), Ok(2 << 20));
+		assert_eq!(opt(b"size", Some(b"4096")).value_size(), Ok(4096));
+		assert!(opt(b"size", Some(b"k")).value_size().is_err());
+		assert_eq!(opt(b"mode", Some(b"1777")).value_mode(), Ok(0o1777));
+		assert!(opt(b"mode", Some(b"17777")).value_mode().is_err());
+		assert!(opt(b"mode", None).value_mode().is_err());
+		assert!(opt(b"noatime", Some(b"1")).flag().is_err());
+		assert_eq!(opt(b"noatime", None).flag(), Ok(()));
+	}
+}
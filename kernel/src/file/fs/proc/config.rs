@@ -0,0 +1,43 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `config.gz` file returns the configuration the kernel has been built with, compressed
+//! with gzip.
+
+use crate::{
+	file::{File, fs::FileOps},
+	memory::user::UserSlice,
+};
+use utils::errno::EResult;
+
+/// The build configuration file, compressed.
+pub static CONFIG_GZ: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/config.gz"));
+
+/// The `config.gz` file.
+#[derive(Debug, Default)]
+pub struct ConfigGz;
+
+impl FileOps for ConfigGz {
+	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let off = usize::try_from(off).unwrap_or(usize::MAX);
+		let Some(data) = CONFIG_GZ.get(off..) else {
+			return Ok(0);
+		};
+		buf.copy_to_user(0, data)
+	}
+}
@@ -0,0 +1,134 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `cpuinfo` file returns information about the CPUs of the system.
+
+use crate::{
+	arch::x86::cpuid::{brand, cpuid},
+	file::{File, fs::FileOps},
+	format_content,
+	memory::user::UserSlice,
+	process::scheduler::cpu::CPU,
+};
+use core::{fmt, sync::atomic::Ordering::Acquire};
+use utils::{DisplayableStr, errno::EResult};
+
+/// Names of the features reported by CPUID leaf `0x1` in `edx`, by bit index.
+const EDX_FLAGS: [&str; 32] = [
+	"fpu", "vme", "de", "pse", "tsc", "msr", "pae", "mce", "cx8", "apic", "", "sep", "mtrr",
+	"pge", "mca", "cmov", "pat", "pse36", "pn", "clflush", "", "dts", "acpi", "mmx", "fxsr",
+	"sse", "sse2", "ss", "ht", "tm", "ia64", "pbe",
+];
+/// Names of the features reported by CPUID leaf `0x1` in `ecx`, by bit index.
+const ECX_FLAGS: [&str; 32] = [
+	"pni",
+	"pclmulqdq",
+	"dtes64",
+	"monitor",
+	"ds_cpl",
+	"vmx",
+	"smx",
+	"est",
+	"tm2",
+	"ssse3",
+	"cid",
+	"sdbg",
+	"fma",
+	"cx16",
+	"xtpr",
+	"pdcm",
+	"",
+	"pcid",
+	"dca",
+	"sse4_1",
+	"sse4_2",
+	"x2apic",
+	"movbe",
+	"popcnt",
+	"tsc_deadline_timer",
+	"aes",
+	"xsave",
+	"osxsave",
+	"avx",
+	"f16c",
+	"rdrand",
+	"hypervisor",
+];
+
+/// Writes the names of the features whose bit is set in `reg`.
+fn write_flags(f: &mut fmt::Formatter<'_>, names: &[&str; 32], reg: u32) -> fmt::Result {
+	names
+		.iter()
+		.enumerate()
+		.filter(|(i, name)| reg & (1 << i) != 0 && !name.is_empty())
+		.try_for_each(|(_, name)| write!(f, " {name}"))
+}
+
+/// The `cpuinfo` file.
+#[derive(Debug, Default)]
+pub struct CpuInfo;
+
+impl FileOps for CpuInfo {
+	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let (eax, _, ecx, edx) = cpuid(1, 0);
+		let stepping = eax & 0xf;
+		let mut model = (eax >> 4) & 0xf;
+		let mut family = (eax >> 8) & 0xf;
+		if family == 0xf {
+			family += (eax >> 20) & 0xff;
+		}
+		if family == 0x6 || family >= 0xf {
+			model |= ((eax >> 16) & 0xf) << 4;
+		}
+		let brand = brand();
+		let name = brand
+			.as_ref()
+			.map(|b| {
+				let len = b.iter().position(|c| *c == 0).unwrap_or(b.len());
+				b[..len].trim_ascii()
+			})
+			.unwrap_or(b"unknown");
+		let disp = fmt::from_fn(|f| {
+			let iter = CPU
+				.iter()
+				.enumerate()
+				.filter(|(_, cpu)| cpu.online.load(Acquire));
+			for (i, cpu) in iter {
+				write!(
+					f,
+					"processor\t: {i}
+vendor_id\t: {vendor}
+cpu family\t: {family}
+model\t\t: {model}
+model name\t: {name}
+stepping\t: {stepping}
+apicid\t\t: {apic_id}
+flags\t\t:",
+					vendor = DisplayableStr(&*cpu.vendor),
+					name = DisplayableStr(name),
+					apic_id = cpu.apic_id,
+				)?;
+				write_flags(f, &EDX_FLAGS, edx)?;
+				write_flags(f, &ECX_FLAGS, ecx)?;
+				f.write_str("\n\n")?;
+			}
+			Ok(())
+		});
+		format_content!(off, buf, "{disp}")
+	}
+}
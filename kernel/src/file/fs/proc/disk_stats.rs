@@ -0,0 +1,74 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `diskstats` file returns I/O statistics of each block device.
+
+use crate::{
+	device::BLK_DEVICES,
+	file::{File, fs::FileOps},
+	format_content,
+	memory::user::UserSlice,
+};
+use core::fmt;
+use utils::{
+	DisplayableStr,
+	collections::vec::Vec,
+	errno::{CollectResult, EResult},
+};
+
+/// The `diskstats` file.
+#[derive(Debug, Default)]
+pub struct DiskStats;
+
+impl FileOps for DiskStats {
+	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let mut devs: Vec<_> = BLK_DEVICES
+			.lock()
+			.iter()
+			.map(|(_, dev)| dev.clone())
+			.collect::<CollectResult<_>>()
+			.0?;
+		devs.sort_unstable_by_key(|dev| (dev.id.major, dev.id.minor));
+		let disp = fmt::from_fn(|f| {
+			for dev in &devs {
+				let name = dev.path.file_name().unwrap_or_default();
+				let stats = dev.stats.get();
+				// Merges are not supported, and durations are in milliseconds
+				writeln!(
+					f,
+					"{major:4} {minor:7} {name} {read_ios} 0 {read_sectors} {read_ticks} \
+{write_ios} 0 {write_sectors} {write_ticks} {in_flight} {io_ticks} {time_in_queue}",
+					major = dev.id.major,
+					minor = dev.id.minor,
+					name = DisplayableStr(name),
+					read_ios = stats.read_ios,
+					read_sectors = stats.read_sectors,
+					read_ticks = stats.read_ticks / 1_000_000,
+					write_ios = stats.write_ios,
+					write_sectors = stats.write_sectors,
+					write_ticks = stats.write_ticks / 1_000_000,
+					in_flight = stats.in_flight,
+					io_ticks = stats.io_ticks / 1_000_000,
+					time_in_queue = stats.time_in_queue / 1_000_000,
+				)?;
+			}
+			Ok(())
+		});
+		format_content!(off, buf, "{disp}")
+	}
+}
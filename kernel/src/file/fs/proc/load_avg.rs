@@ -0,0 +1,65 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `loadavg` file returns the system's load average and information about processes.
+
+use crate::{
+	file::{File, fs::FileOps},
+	format_content,
+	memory::user::UserSlice,
+	process::{
+		PROCESSES, State,
+		scheduler::{
+			loadavg,
+			loadavg::{FIXED_1, FSHIFT},
+		},
+	},
+};
+use core::{cmp::max, fmt};
+use utils::errno::EResult;
+
+/// Displays a load average with two decimals.
+struct DisplayLoad(u64);
+
+impl fmt::Display for DisplayLoad {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let int = self.0 >> FSHIFT;
+		let frac = ((self.0 & (FIXED_1 - 1)) * 100) >> FSHIFT;
+		write!(f, "{int}.{frac:02}")
+	}
+}
+
+/// The `loadavg` file.
+#[derive(Debug, Default)]
+pub struct LoadAvg;
+
+impl FileOps for LoadAvg {
+	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let (running, total, last_pid) = {
+			let processes = PROCESSES.read();
+			processes
+				.iter()
+				.fold((0, 0, 0), |(running, total, last), (pid, proc)| {
+					let running = running + (proc.get_state() == State::Running) as usize;
+					(running, total + 1, max(last, *pid))
+				})
+		};
+		let [l1, l5, l15] = loadavg::get().map(DisplayLoad);
+		format_content!(off, buf, "{l1} {l5} {l15} {running}/{total} {last_pid}\n")
+	}
+}
@@ -19,14 +19,22 @@
 //! The `procfs` is a virtual filesystem which provides information about
 //! processes.
 
+mod config;
+mod cpu_info;
+mod disk_stats;
+mod kmsg;
+mod load_avg;
 mod mem_info;
+mod modules;
+mod pci_devices;
 mod proc_dir;
 mod self_link;
 mod sys_dir;
+mod unimplemented;
 mod uptime;
 mod version;
 
-use super::{DummyOps, Filesystem, FilesystemOps, FilesystemType, NodeOps};
+use super::{DummyOps, Filesystem, FilesystemOps, FilesystemType, NodeOps, downcast_fs};
 use crate::{
 	device::BlkDev,
 	file::{
@@ -36,24 +44,44 @@ use crate::{
 			kernfs::{
 				EitherOps, StaticDir, StaticEntry, StaticLink, box_file, box_node, static_dir_stat,
 			},
+			options::MountOptions,
 			proc::proc_dir::{environ::Environ, maps::Maps},
 		},
+		perm,
 		perm::{Gid, Uid},
 		vfs,
 		vfs::node::Node,
 	},
 	process::{PROCESSES, Process, pid::Pid},
 };
+use config::{CONFIG_GZ, ConfigGz};
+use cpu_info::CpuInfo;
+use disk_stats::DiskStats;
+use kmsg::KMsg;
+use load_avg::LoadAvg;
 use mem_info::MemInfo;
+use modules::Modules;
+use pci_devices::PciDevices;
 use proc_dir::{
-	cmdline::Cmdline, cwd::Cwd, exe::Exe, mounts::Mounts, stat::StatNode, status::Status,
+	cmdline::Cmdline, cwd::Cwd, exe::Exe, fd::FdDir, mounts::Mounts, stat::StatNode,
+	status::Status,
 };
 use self_link::SelfNode;
-use sys_dir::OsRelease;
+use sys_dir::SysDir;
+use unimplemented::Unimplemented;
 use uptime::Uptime;
 use utils::{
-	boxed::Box, collections::path::PathBuf, errno, errno::EResult, format, ptr::arc::Arc,
+	boxed::Box,
+	collections::path::PathBuf,
+	errno,
+	errno::EResult,
+	format,
+	limits::{NAME_MAX, PAGE_SIZE},
+	ptr::arc::Arc,
 };
+
+/// The magic number of the procfs.
+const PROC_SUPER_MAGIC: u32 = 0x9fa0;
 use version::Version;
 
 /// Returns the user ID and group ID of the process with the given PID.
@@ -63,8 +91,8 @@ fn get_proc_owner(pid: Pid) -> (Uid, Gid) {
 	Process::get_by_pid(pid)
 		.as_ref()
 		.map(|proc| {
-			let fs = proc.fs.lock();
-			(fs.ap.euid, fs.ap.egid)
+			let ap = proc.cred().ap;
+			(ap.euid, ap.egid)
 		})
 		.unwrap_or((0, 0))
 }
@@ -80,6 +108,39 @@ fn proc_file_stat(pid: Pid, mode: Mode) -> Stat {
 	}
 }
 
+/// The directory of a process.
+///
+/// Access to the directory's content is restricted according to the `hidepid` mount option.
+#[derive(Debug)]
+struct ProcDir(StaticDir<Pid>);
+
+impl ProcDir {
+	/// Checks whether the current process can access the directory's content.
+	fn check_access(&self, dir: &Node) -> EResult<()> {
+		let fs = downcast_fs::<ProcFS>(&*dir.fs.ops);
+		let proc = Process::get_by_pid(self.0.data).ok_or_else(|| errno!(ENOENT))?;
+		if fs.can_access(&proc) {
+			return Ok(());
+		}
+		match fs.hidepid {
+			HidePid::Invisible => Err(errno!(ENOENT)),
+			_ => Err(errno!(EPERM)),
+		}
+	}
+}
+
+impl NodeOps for ProcDir {
+	fn lookup_entry(&self, dir: &Node, ent: &mut vfs::Entry) -> EResult<()> {
+		self.check_access(dir)?;
+		self.0.lookup_entry(dir, ent)
+	}
+
+	fn iter_entries(&self, dir: &Node, ctx: &mut DirContext) -> EResult<()> {
+		self.check_access(dir)?;
+		self.0.iter_entries(dir, ctx)
+	}
+}
+
 /// The root directory of the proc.
 #[derive(Clone, Debug)]
 struct RootDir;
@@ -91,6 +152,73 @@ impl RootDir {
 	/// processes.
 	const STATIC: StaticDir = StaticDir {
 		entries: &[
+			StaticEntry {
+				name: b"bus",
+				stat: |_| static_dir_stat(),
+				init: EitherOps::Node(|_| {
+					box_node(StaticDir {
+						entries: &[StaticEntry {
+							name: b"pci",
+							stat: |_| static_dir_stat(),
+							init: EitherOps::Node(|_| {
+								box_node(StaticDir {
+									entries: &[StaticEntry {
+										name: b"devices",
+										stat: |_| Stat {
+											mode: FileType::Regular.to_mode() | 0o444,
+											..Default::default()
+										},
+										init: EitherOps::File(|_| box_file(PciDevices)),
+									}],
+									data: (),
+								})
+							}),
+						}],
+						data: (),
+					})
+				}),
+			},
+			StaticEntry {
+				name: b"config.gz",
+				stat: |_| Stat {
+					mode: FileType::Regular.to_mode() | 0o444,
+					size: CONFIG_GZ.len() as _,
+					..Default::default()
+				},
+				init: EitherOps::File(|_| box_file(ConfigGz)),
+			},
+			StaticEntry {
+				name: b"cpuinfo",
+				stat: |_| Stat {
+					mode: FileType::Regular.to_mode() | 0o444,
+					..Default::default()
+				},
+				init: EitherOps::File(|_| box_file(CpuInfo)),
+			},
+			StaticEntry {
+				name: b"diskstats",
+				stat: |_| Stat {
+					mode: FileType::Regular.to_mode() | 0o444,
+					..Default::default()
+				},
+				init: EitherOps::File(|_| box_file(DiskStats)),
+			},
+			StaticEntry {
+				name: b"kmsg",
+				stat: |_| Stat {
+					mode: FileType::Regular.to_mode() | 0o400,
+					..Default::default()
+				},
+				init: EitherOps::File(|_| box_file(KMsg)),
+			},
+			StaticEntry {
+				name: b"loadavg",
+				stat: |_| Stat {
+					mode: FileType::Regular.to_mode() | 0o444,
+					..Default::default()
+				},
+				init: EitherOps::File(|_| box_file(LoadAvg)),
+			},
 			StaticEntry {
 				name: b"meminfo",
 				stat: |_| Stat {
@@ -99,6 +227,14 @@ impl RootDir {
 				},
 				init: EitherOps::File(|_| box_file(MemInfo)),
 			},
+			StaticEntry {
+				name: b"modules",
+				stat: |_| Stat {
+					mode: FileType::Regular.to_mode() | 0o444,
+					..Default::default()
+				},
+				init: EitherOps::File(|_| box_file(Modules)),
+			},
 			StaticEntry {
 				name: b"mounts",
 				stat: |_| Stat {
@@ -118,25 +254,15 @@ impl RootDir {
 			StaticEntry {
 				name: b"sys",
 				stat: |_| static_dir_stat(),
-				init: EitherOps::Node(|_| {
-					box_node(StaticDir {
-						entries: &[(StaticEntry {
-							name: b"kernel",
-							stat: |_| static_dir_stat(),
-							init: EitherOps::Node(|_| {
-								box_node(StaticDir {
-									entries: &[StaticEntry {
-										name: b"osrelease",
-										stat: |_| static_dir_stat(),
-										init: EitherOps::File(|_| box_file(OsRelease)),
-									}],
-									data: (),
-								})
-							}),
-						})],
-						data: (),
-					})
-				}),
+				init: EitherOps::Node(|_| box_node(SysDir::default())),
+			},
+			StaticEntry {
+				name: b"unimplemented",
+				stat: |_| Stat {
+					mode: FileType::Regular.to_mode() | 0o444,
+					..Default::default()
+				},
+				init: EitherOps::File(|_| box_file(Unimplemented)),
 			},
 			StaticEntry {
 				name: b"uptime",
@@ -176,13 +302,16 @@ impl NodeOps for RootDir {
 		let Some(pid) = pid else {
 			return Self::STATIC.lookup_entry(dir, ent);
 		};
+		let fs = downcast_fs::<ProcFS>(&*dir.fs.ops);
 		ent.node = Process::get_by_pid(pid)
+			// With `hidepid=2`, inaccessible processes do not appear at all
+			.filter(|proc| fs.hidepid != HidePid::Invisible || fs.can_access(proc))
 			.map(|_| {
 				Arc::new(Node::new(
 					0,
 					dir.fs.clone(),
-					static_dir_stat(),
-					Box::new(StaticDir {
+					proc_file_stat(pid, FileType::Directory.to_mode() | 0o555),
+					Box::new(ProcDir(StaticDir {
 						entries: &[
 							StaticEntry {
 								name: b"cmdline",
@@ -208,6 +337,13 @@ impl NodeOps for RootDir {
 								stat: |pid| proc_file_stat(pid, FileType::Link.to_mode() | 0o444),
 								init: EitherOps::Node(|pid| box_node(Exe(pid))),
 							},
+							StaticEntry {
+								name: b"fd",
+								stat: |pid| {
+									proc_file_stat(pid, FileType::Directory.to_mode() | 0o500)
+								},
+								init: EitherOps::Node(|pid| box_node(FdDir(pid))),
+							},
 							StaticEntry {
 								name: b"maps",
 								stat: |pid| {
@@ -238,7 +374,7 @@ impl NodeOps for RootDir {
 							},
 						],
 						data: pid,
-					})?,
+					}))?,
 					Box::new(DummyOps)?,
 				))
 			})
@@ -246,7 +382,7 @@ impl NodeOps for RootDir {
 		Ok(())
 	}
 
-	fn iter_entries(&self, _dir: &Node, ctx: &mut DirContext) -> EResult<()> {
+	fn iter_entries(&self, dir: &Node, ctx: &mut DirContext) -> EResult<()> {
 		let off: usize = ctx.off.try_into().map_err(|_| errno!(EINVAL))?;
 		// Iterate on static entries
 		let static_iter = Self::STATIC.entries.iter().skip(off);
@@ -264,8 +400,14 @@ impl NodeOps for RootDir {
 		}
 		// Iterate on processes
 		let off = ctx.off as usize - Self::STATIC.entries.len();
+		let fs = downcast_fs::<ProcFS>(&*dir.fs.ops);
 		let processes = PROCESSES.read();
-		for (pid, _) in processes.iter().skip(off) {
+		for (pid, proc) in processes.iter().skip(off) {
+			// With `hidepid=2`, inaccessible processes do not appear at all
+			if fs.hidepid == HidePid::Invisible && !fs.can_access(proc) {
+				ctx.off += 1;
+				continue;
+			}
 			let name = format!("{pid}")?;
 			let ent = DirEntry {
 				inode: 0,
@@ -281,9 +423,40 @@ impl NodeOps for RootDir {
 	}
 }
 
+/// Restriction on the access to processes' directories, set with the `hidepid` mount option.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum HidePid {
+	/// Everybody can access all processes' directories (`hidepid=0`).
+	Off,
+	/// Users cannot access the content of directories of processes they cannot inspect
+	/// (`hidepid=1`).
+	NoAccess,
+	/// Same as [`Self::NoAccess`], except directories are also hidden (`hidepid=2`).
+	Invisible,
+}
+
 /// A proc.
 #[derive(Debug)]
-pub struct ProcFS;
+pub struct ProcFS {
+	/// Restriction on the access to processes' directories
+	hidepid: HidePid,
+	/// Group whose members are exempted from the `hidepid` restriction
+	gid: Option<Gid>,
+}
+
+impl ProcFS {
+	/// Tells whether the current process can access the directory of `proc`.
+	fn can_access(&self, proc: &Process) -> bool {
+		if self.hidepid == HidePid::Off {
+			return true;
+		}
+		let in_group = self.gid.is_some_and(|gid| {
+			let cred = Process::current().cred();
+			cred.ap.egid == gid || cred.groups.contains(&gid)
+		});
+		in_group || perm::can_inspect(proc)
+	}
+}
 
 impl FilesystemOps for ProcFS {
 	fn get_name(&self) -> &[u8] {
@@ -296,15 +469,15 @@ impl FilesystemOps for ProcFS {
 
 	fn get_stat(&self) -> EResult<Statfs> {
 		Ok(Statfs {
-			f_type: 0,
-			f_bsize: 0,
+			f_type: PROC_SUPER_MAGIC,
+			f_bsize: PAGE_SIZE as _,
 			f_blocks: 0,
 			f_bfree: 0,
 			f_bavail: 0,
 			f_files: 0,
 			f_ffree: 0,
 			f_fsid: Default::default(),
-			f_namelen: 0,
+			f_namelen: NAME_MAX as _,
 			f_frsize: 0,
 			f_flags: 0,
 		})
@@ -346,7 +519,34 @@ impl FilesystemType for ProcFsType {
 		_dev: Option<Arc<BlkDev>>,
 		_mountpath: PathBuf,
 		_readonly: bool,
+		data: &[u8],
 	) -> EResult<Arc<Filesystem>> {
-		Ok(Filesystem::new(0, Box::new(ProcFS)?)?)
+		let mut hidepid = HidePid::Off;
+		let mut gid = None;
+		for opt in MountOptions::new(data) {
+			let opt = opt?;
+			match opt.key {
+				b"hidepid" => {
+					hidepid = match opt.value()? {
+						b"0" | b"off" => HidePid::Off,
+						b"1" | b"noaccess" => HidePid::NoAccess,
+						b"2" | b"invisible" => HidePid::Invisible,
+						_ => return Err(errno!(EINVAL)),
+					}
+				}
+				b"gid" => {
+					let val = opt.value_u64(10)?;
+					gid = Some(val.try_into().map_err(|_| errno!(EINVAL))?);
+				}
+				_ => return Err(errno!(EINVAL)),
+			}
+		}
+		Ok(Filesystem::new(
+			0,
+			Box::new(ProcFS {
+				hidepid,
+				gid,
+			})?,
+		)?)
 	}
 }
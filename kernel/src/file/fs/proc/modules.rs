@@ -0,0 +1,67 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `modules` file lists the loaded kernel modules.
+
+use crate::{
+	file::{File, fs::FileOps, perm::is_privileged},
+	format_content,
+	memory::user::UserSlice,
+	module,
+};
+use utils::{DisplayableStr, collections::string::String, errno::EResult, try_write, try_writeln};
+
+/// The `modules` file.
+///
+/// Each line describes a module, with the following space-separated fields:
+/// - name
+/// - size of the module's memory, in bytes
+/// - number of modules depending on it
+/// - comma-separated list of the modules depending on it, or `-` if none
+/// - state, always `Live`
+/// - address of the module's memory, zero for unprivileged users
+#[derive(Debug)]
+pub struct Modules;
+
+impl FileOps for Modules {
+	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let privileged = is_privileged();
+		let mut content = String::new();
+		module::for_each(|m, holders| {
+			let (addr, size) = m.get_mem();
+			let addr = if privileged { addr as usize } else { 0 };
+			try_write!(
+				content,
+				"{name} {size} {count} ",
+				name = DisplayableStr(m.get_name()),
+				count = m.get_holders()
+			)?;
+			let mut empty = true;
+			for holder in holders {
+				try_write!(content, "{},", DisplayableStr(holder.get_name()))?;
+				empty = false;
+			}
+			if empty {
+				try_write!(content, "-")?;
+			}
+			try_writeln!(content, " Live {addr:#x}")?;
+			Ok(())
+		})?;
+		format_content!(off, buf, "{content}")
+	}
+}
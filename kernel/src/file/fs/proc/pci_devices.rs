@@ -0,0 +1,72 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `bus/pci/devices` file lists the devices attached to the PCI bus.
+
+use crate::{
+	device::{bus::pci, manager::PhysicalDevice},
+	file::{File, fs::FileOps},
+	format_content,
+	memory::user::UserSlice,
+};
+use utils::{collections::string::String, errno::EResult, try_write, try_writeln};
+
+/// The `bus/pci/devices` file.
+///
+/// Each line describes a device, with the following tab-separated fields:
+/// - bus number and device/function number
+/// - vendor ID and device ID
+/// - interrupt line
+/// - the value of each BAR register, then of the expansion ROM register
+/// - the size of each BAR, then of the expansion ROM
+/// - the name of the driver bound to the device, if any
+#[derive(Debug)]
+pub struct PciDevices;
+
+impl FileOps for PciDevices {
+	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let content = pci::with_devices(|devices| -> EResult<String> {
+			let mut content = String::new();
+			for dev in devices {
+				let devfn = (dev.get_device() << 3) | dev.get_function();
+				try_write!(
+					content,
+					"{bus:02x}{devfn:02x}\t{vendor:04x}{device:04x}\t{irq:x}",
+					bus = dev.get_bus(),
+					vendor = dev.get_vendor_id(),
+					device = dev.get_device_id(),
+					irq = dev.get_interrupt_line().unwrap_or(0)
+				)?;
+				let bars = || (0..6).map(|n| dev.get_bar_raw(n).unwrap_or((0, 0)));
+				// The expansion ROM is not supported
+				for (addr, _) in bars().chain([(0, 0)]) {
+					try_write!(content, "\t{addr:16x}")?;
+				}
+				for (_, size) in bars().chain([(0, 0)]) {
+					try_write!(content, "\t{size:16x}")?;
+				}
+				if let Some(name) = dev.driver_name() {
+					try_write!(content, "\t{name}")?;
+				}
+				try_writeln!(content)?;
+			}
+			Ok(content)
+		})?;
+		format_content!(off, buf, "{content}")
+	}
+}
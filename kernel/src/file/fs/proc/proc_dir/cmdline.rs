@@ -26,6 +26,7 @@ use crate::{
 	memory::user::UserSlice,
 	process::{Process, pid::Pid},
 };
+use core::cmp::max;
 use utils::{DisplayableStr, errno, errno::EResult};
 
 /// The cmdline node of the proc.
@@ -38,11 +39,22 @@ impl FileOps for Cmdline {
 		let Some(mem_space) = proc.mem_space_opt() else {
 			return Ok(0);
 		};
-		let cmdline = read_memory(
-			mem_space,
-			mem_space.exe_info.argv_begin,
-			mem_space.exe_info.argv_end,
-		)?;
+		let args = *mem_space.exe_info.args.lock();
+		let mut cmdline = read_memory(mem_space, args.argv_begin, args.argv_end)?;
+		// If the program has overwritten the terminating nul byte (such as with `setproctitle`),
+		// the command line is a single string which may continue into the environment
+		if cmdline.last().is_some_and(|b| *b != b'\0') {
+			cmdline = read_memory(
+				mem_space,
+				args.argv_begin,
+				max(args.argv_end, args.envp_end),
+			)?;
+			let len = cmdline
+				.iter()
+				.position(|b| *b == b'\0')
+				.unwrap_or(cmdline.len());
+			cmdline.truncate(len);
+		}
 		format_content!(off, buf, "{}", DisplayableStr(&cmdline))
 	}
 }
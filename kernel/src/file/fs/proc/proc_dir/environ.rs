@@ -21,13 +21,16 @@
 use crate::{
 	file::{
 		File,
-		fs::{FileOps, proc::proc_dir::read_memory},
+		fs::{
+			FileOps,
+			proc::proc_dir::{get_inspectable, read_memory},
+		},
 	},
 	format_content,
 	memory::user::UserSlice,
-	process::{Process, pid::Pid},
+	process::pid::Pid,
 };
-use utils::{DisplayableStr, errno, errno::EResult};
+use utils::{DisplayableStr, errno::EResult};
 
 /// The `environ` node of the proc.
 #[derive(Clone, Debug)]
@@ -35,15 +38,12 @@ pub struct Environ(pub Pid);
 
 impl FileOps for Environ {
 	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
-		let proc = Process::get_by_pid(self.0).ok_or_else(|| errno!(ENOENT))?;
+		let proc = get_inspectable(self.0)?;
 		let Some(mem_space) = proc.mem_space_opt() else {
 			return Ok(0);
 		};
-		let environ = read_memory(
-			mem_space,
-			mem_space.exe_info.envp_begin,
-			mem_space.exe_info.envp_end,
-		)?;
+		let args = *mem_space.exe_info.args.lock();
+		let environ = read_memory(mem_space, args.envp_begin, args.envp_end)?;
 		format_content!(off, buf, "{}", DisplayableStr(&environ))
 	}
 }
@@ -20,12 +20,16 @@
 //! file of the process.
 
 use crate::{
-	file::{fs::NodeOps, vfs, vfs::node::Node},
+	file::{
+		fs::{NodeOps, proc::proc_dir::get_inspectable},
+		vfs,
+		vfs::node::Node,
+	},
 	format_content,
 	memory::user::UserSlice,
-	process::{Process, pid::Pid},
+	process::pid::Pid,
 };
-use utils::{errno, errno::EResult};
+use utils::errno::EResult;
 
 /// The `exe` node.
 #[derive(Debug)]
@@ -33,7 +37,7 @@ pub struct Exe(pub Pid);
 
 impl NodeOps for Exe {
 	fn readlink(&self, _node: &Node, buf: UserSlice<u8>) -> EResult<usize> {
-		let proc = Process::get_by_pid(self.0).ok_or_else(|| errno!(ENOENT))?;
+		let proc = get_inspectable(self.0)?;
 		let path = proc
 			.mem_space_opt()
 			.as_ref()
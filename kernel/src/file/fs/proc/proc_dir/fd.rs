@@ -0,0 +1,115 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Implementation of the `fd` directory, which contains a symbolic link for each open file
+//! descriptor of the process, pointing to the associated file.
+
+use crate::{
+	file::{
+		DirContext, DirEntry, FileType,
+		fs::{DummyOps, NodeOps, kernfs::box_node, proc::proc_file_stat},
+		vfs,
+		vfs::node::Node,
+	},
+	format_content,
+	memory::user::UserSlice,
+	process::{Process, pid::Pid},
+};
+use core::ffi::c_int;
+use utils::{boxed::Box, errno, errno::EResult, format, ptr::arc::Arc};
+
+/// The `fd` directory.
+#[derive(Debug)]
+pub struct FdDir(pub Pid);
+
+impl NodeOps for FdDir {
+	fn lookup_entry(&self, dir: &Node, ent: &mut vfs::Entry) -> EResult<()> {
+		let fd: Option<c_int> = core::str::from_utf8(&ent.name)
+			.ok()
+			.and_then(|s| s.parse().ok());
+		let Some(fd) = fd else {
+			return Ok(());
+		};
+		let proc = Process::get_by_pid(self.0).ok_or_else(|| errno!(ENOENT))?;
+		let exists = proc
+			.file_descriptors_opt()
+			.map(|fds| fds.get_fd(fd).is_ok())
+			.unwrap_or(false);
+		ent.node = exists
+			.then(|| {
+				Arc::new(Node::new(
+					0,
+					dir.fs.clone(),
+					proc_file_stat(self.0, FileType::Link.to_mode() | 0o700),
+					box_node(FdLink {
+						pid: self.0,
+						fd,
+					})?,
+					Box::new(DummyOps)?,
+				))
+			})
+			.transpose()?;
+		Ok(())
+	}
+
+	fn iter_entries(&self, _dir: &Node, ctx: &mut DirContext) -> EResult<()> {
+		let proc = Process::get_by_pid(self.0).ok_or_else(|| errno!(ENOENT))?;
+		let Some(fds) = proc.file_descriptors_opt() else {
+			return Ok(());
+		};
+		// The offset is the ID of the next file descriptor to be returned
+		let off = ctx.off;
+		let iter = fds.iter().skip_while(|(id, _)| (*id as u64) < off);
+		for (id, _) in iter {
+			let name = format!("{id}")?;
+			let ent = DirEntry {
+				inode: 0,
+				entry_type: Some(FileType::Link),
+				name: &name,
+			};
+			if !(ctx.write)(&ent)? {
+				break;
+			}
+			ctx.off = id as u64 + 1;
+		}
+		Ok(())
+	}
+}
+
+/// A symbolic link to the file pointed to by a file descriptor.
+#[derive(Debug)]
+struct FdLink {
+	/// The PID of the process owning the file descriptor.
+	pid: Pid,
+	/// The ID of the file descriptor.
+	fd: c_int,
+}
+
+impl NodeOps for FdLink {
+	fn readlink(&self, _node: &Node, buf: UserSlice<u8>) -> EResult<usize> {
+		let proc = Process::get_by_pid(self.pid).ok_or_else(|| errno!(ENOENT))?;
+		let fds = proc.file_descriptors_opt().ok_or_else(|| errno!(ENOENT))?;
+		let file = fds
+			.get_fd(self.fd)
+			.map_err(|_| errno!(ENOENT))?
+			.get_file()
+			.clone();
+		let path = vfs::Entry::get_path(&file.vfs_entry)?;
+		format_content!(0, buf, "{path}")
+	}
+}
@@ -19,17 +19,25 @@
 //! Implementation of the `maps` node which returns the list of memory mappings.
 
 use crate::{
-	file::{File, fs::FileOps, vfs},
+	file::{
+		File,
+		fs::{FileOps, proc::proc_dir::get_inspectable},
+		vfs,
+	},
 	format_content,
 	memory::user::UserSlice,
 	process::{
-		Process,
-		mem_space::{MAP_SHARED, MemSpace, PROT_EXEC, PROT_READ, PROT_WRITE, mapping::MemMapping},
+		mem_space::{MAP_SHARED, PROT_EXEC, PROT_READ, PROT_WRITE, mapping::MemMapping},
 		pid::Pid,
 	},
 };
 use core::{fmt, fmt::Formatter};
-use utils::{collections::path::PathBuf, errno, errno::EResult, limits::PAGE_SIZE};
+use utils::{
+	collections::{path::PathBuf, string::String},
+	errno::EResult,
+	limits::PAGE_SIZE,
+	try_writeln,
+};
 
 /// The `maps` node.
 #[derive(Debug)]
@@ -37,19 +45,12 @@ pub struct Maps(pub Pid);
 
 impl FileOps for Maps {
 	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
-		let proc = Process::get_by_pid(self.0).ok_or_else(|| errno!(ENOENT))?;
+		let proc = get_inspectable(self.0)?;
 		let Some(mem_space) = proc.mem_space_opt() else {
 			return Ok(0);
 		};
-		format_content!(off, buf, "{}", MapsDisplay(mem_space))
-	}
-}
-
-struct MapsDisplay<'m>(&'m MemSpace);
-
-impl fmt::Display for MapsDisplay<'_> {
-	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-		self.0.mappings(|mappings| {
+		let content = mem_space.mappings(|mappings| -> EResult<String> {
+			let mut content = String::new();
 			for (begin, mapping) in mappings.iter() {
 				let end = *begin + mapping.size.get() * PAGE_SIZE;
 				let perms = Perms(mapping);
@@ -57,23 +58,22 @@ impl fmt::Display for MapsDisplay<'_> {
 					Some(file) => {
 						let node = file.vfs_entry.node();
 						let stat = node.stat();
-						// TODO figure how to handle memory allocation failures
-						let path =
-							vfs::Entry::get_path(&file.vfs_entry).unwrap_or(PathBuf::empty());
+						let path = vfs::Entry::get_path(&file.vfs_entry)?;
 						(stat.dev_major, stat.dev_minor, node.inode, path)
 					}
 					None => (0, 0, 0, PathBuf::empty()),
 				};
-				writeln!(
-					f,
+				try_writeln!(
+					content,
 					"{begin:x}-{end:x} {perms} {off} {major}:{minor} {inode:<25} {pathname}",
 					begin = begin.0,
 					end = end.0,
 					off = mapping.off
 				)?;
 			}
-			Ok(())
-		})
+			Ok(content)
+		})?;
+		format_content!(off, buf, "{content}")
 	}
 }
 
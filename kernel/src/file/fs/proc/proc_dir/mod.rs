@@ -19,20 +19,41 @@
 //! Implementation of the directory of a process in the proc.
 
 use crate::{
+	file::perm,
 	memory::{VirtAddr, user::UserSlice},
-	process::mem_space::MemSpace,
+	process::{Process, mem_space::MemSpace, pid::Pid},
+};
+use utils::{
+	collections::vec::Vec,
+	errno,
+	errno::{AllocResult, EResult},
+	ptr::arc::Arc,
+	vec,
 };
-use utils::{collections::vec::Vec, errno::AllocResult, ptr::arc::Arc, vec};
 
 pub mod cmdline;
 pub mod cwd;
 pub mod environ;
 pub mod exe;
+pub mod fd;
 pub mod maps;
 pub mod mounts;
 pub mod stat;
 pub mod status;
 
+/// Returns the process with the given PID, if the current process is allowed to inspect its
+/// private information.
+///
+/// If the process does not exist, the function returns [`errno::ENOENT`]. If it cannot be
+/// inspected, the function returns [`errno::EACCES`].
+pub fn get_inspectable(pid: Pid) -> EResult<Arc<Process>> {
+	let proc = Process::get_by_pid(pid).ok_or_else(|| errno!(ENOENT))?;
+	if !perm::can_inspect(&proc) {
+		return Err(errno!(EACCES));
+	}
+	Ok(proc)
+}
+
 /// Reads a range of memory from `mem_space` and writes it to `f`.
 ///
 /// `begin` and `end` represent the range of memory to read.
@@ -19,13 +19,35 @@
 //! Implementation of the `mounts` node which allows to get the list of mountpoint.
 
 use crate::{
-	file::{File, fs::FileOps, vfs, vfs::mountpoint},
+	file::{
+		File,
+		fs::FileOps,
+		vfs,
+		vfs::{
+			mountpoint,
+			mountpoint::{
+				FLAG_MANDLOCK, FLAG_NOATIME, FLAG_NODEV, FLAG_NODIRATIME, FLAG_NOEXEC,
+				FLAG_NOSUID, FLAG_RDONLY, FLAG_RELATIME, FLAG_SYNCHRONOUS,
+			},
+		},
+	},
 	format_content,
 	memory::user::UserSlice,
 	process::pid::Pid,
 };
-use core::{fmt, fmt::Formatter};
-use utils::{DisplayableStr, errno::EResult};
+use utils::{DisplayableStr, collections::string::String, errno::EResult, try_write, try_writeln};
+
+/// Mount flags and their names, as displayed in the options column.
+const FLAG_NAMES: &[(u32, &str)] = &[
+	(FLAG_NOSUID, "nosuid"),
+	(FLAG_NODEV, "nodev"),
+	(FLAG_NOEXEC, "noexec"),
+	(FLAG_SYNCHRONOUS, "sync"),
+	(FLAG_MANDLOCK, "mand"),
+	(FLAG_NOATIME, "noatime"),
+	(FLAG_NODIRATIME, "nodiratime"),
+	(FLAG_RELATIME, "relatime"),
+];
 
 /// The `mounts` node.
 #[derive(Debug)]
@@ -33,27 +55,28 @@ pub struct Mounts(pub Pid);
 
 impl FileOps for Mounts {
 	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
-		format_content!(off, buf, "{self}")
-	}
-}
-
-impl fmt::Display for Mounts {
-	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let mut content = String::new();
 		let mps = mountpoint::MOUNT_POINTS.lock();
 		for (_, mp) in mps.iter() {
-			let Ok(target) = vfs::Entry::get_path(&mp.root_entry) else {
-				continue;
-			};
+			let target = vfs::Entry::get_path(&mp.root_entry)?;
 			let fs_type = mp.fs.ops.get_name();
-			let flags = "TODO"; // TODO
-			writeln!(
-				f,
-				"{source} {target} {fs_type} {flags} 0 0",
+			let flags = mp.flags();
+			let mode = if flags & FLAG_RDONLY != 0 { "ro" } else { "rw" };
+			try_write!(
+				content,
+				"{source} {target} {fs_type} {mode}",
 				source = mp.source,
 				target = target,
 				fs_type = DisplayableStr(fs_type)
 			)?;
+			for (flag, name) in FLAG_NAMES {
+				if flags & flag != 0 {
+					try_write!(content, ",{name}")?;
+				}
+			}
+			try_writeln!(content, " 0 0")?;
 		}
-		Ok(())
+		drop(mps);
+		format_content!(off, buf, "{content}")
 	}
 }
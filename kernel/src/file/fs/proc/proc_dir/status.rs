@@ -25,7 +25,7 @@ use crate::{
 	memory::user::UserSlice,
 	process::{Process, pid::Pid},
 };
-use core::{fmt, sync::atomic::Ordering::Acquire};
+use core::fmt;
 use utils::{DisplayableStr, errno, errno::EResult};
 
 /// The `status` node of the proc.
@@ -41,9 +41,10 @@ impl FileOps for Status {
 				.as_ref()
 				.map(|m| m.exe_info.exe.name.as_bytes())
 				.unwrap_or_default();
-			let umask = proc.umask.load(Acquire);
+			let cred = proc.cred();
+			let umask = proc.umask();
 			let state = proc.get_state();
-			let ap = proc.fs.lock().ap;
+			let ap = cred.ap;
 			// TODO Fill every fields with process's data
 			writeln!(
 				f,
@@ -55,8 +56,8 @@ Ngid: 0
 Pid: {pid}
 PPid: {ppid}
 TracerPid: 0
-Uid: {uid} {euid} {suid} {ruid}
-Gid: {gid} {egid} {sgid} {rgid}
+Uid: {uid} {euid} {suid} {fsuid}
+Gid: {gid} {egid} {sgid} {fsgid}
 FDSize: TODO
 Groups: TODO
 NStgid: TODO
@@ -112,11 +113,11 @@ nonvoluntary_ctxt_switches: 0",
 				uid = ap.uid,
 				euid = ap.euid,
 				suid = ap.suid,
-				ruid = ap.uid,
+				fsuid = ap.fsuid,
 				gid = ap.gid,
 				egid = ap.egid,
 				sgid = ap.sgid,
-				rgid = ap.gid,
+				fsgid = ap.fsgid,
 			)
 		});
 		format_content!(off, buf, "{disp}")
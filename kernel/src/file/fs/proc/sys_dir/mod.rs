@@ -16,21 +16,125 @@
  * Maestro. If not, see <https://www.gnu.org/licenses/>.
  */
 
-//! TODO doc
+//! The `sys` directory exposes kernel tunables registered in [`crate::sysctl`].
 
 use crate::{
-	file::{File, fs::FileOps},
+	file::{
+		DirContext, DirEntry, File, FileType, Stat,
+		fs::{DummyOps, FileOps, NodeOps, kernfs::static_dir_stat},
+		vfs,
+		vfs::node::Node,
+	},
 	format_content,
 	memory::user::UserSlice,
+	sysctl,
+};
+use utils::{
+	boxed::Box,
+	collections::{string::String, vec::Vec},
+	errno,
+	errno::EResult,
+	ptr::arc::Arc,
 };
-use utils::errno::EResult;
 
-/// The `osrelease` file.
+/// A directory of the sysctl tree.
+///
+/// The inner value is the dot-separated path of the directory. It is empty for the root.
 #[derive(Debug, Default)]
-pub struct OsRelease;
+pub struct SysDir(pub String);
+
+impl NodeOps for SysDir {
+	fn lookup_entry(&self, dir: &Node, ent: &mut vfs::Entry) -> EResult<()> {
+		// Names containing dots would allow to escape the directory's hierarchy
+		if ent.name.contains(&b'.') {
+			return Ok(());
+		}
+		let mut path = String::try_from(self.0.as_bytes())?;
+		if !path.is_empty() {
+			path.push(b'.')?;
+		}
+		path.push_str(&ent.name)?;
+		let (stat, node_ops, file_ops): (_, Box<dyn NodeOps>, Box<dyn FileOps>) =
+			if let Some(sysctl) = sysctl::get(&path) {
+				let stat = Stat {
+					mode: FileType::Regular.to_mode() | sysctl.mode,
+					..Default::default()
+				};
+				(stat, Box::new(DummyOps)?, Box::new(SysctlFile(path))?)
+			} else if sysctl::is_dir(&path) {
+				(
+					static_dir_stat(),
+					Box::new(SysDir(path))?,
+					Box::new(DummyOps)?,
+				)
+			} else {
+				return Ok(());
+			};
+		ent.node = Some(Arc::new(Node::new(
+			0,
+			dir.fs.clone(),
+			stat,
+			node_ops,
+			file_ops,
+		))?);
+		Ok(())
+	}
+
+	fn iter_entries(&self, _dir: &Node, ctx: &mut DirContext) -> EResult<()> {
+		let off = ctx.off.try_into().map_err(|_| errno!(EINVAL))?;
+		sysctl::iter_children(&self.0, off, |name, dir| {
+			let ent = DirEntry {
+				inode: 0,
+				entry_type: Some(if dir {
+					FileType::Directory
+				} else {
+					FileType::Regular
+				}),
+				name,
+			};
+			let cont = (ctx.write)(&ent)?;
+			if cont {
+				ctx.off += 1;
+			}
+			Ok(cont)
+		})
+	}
+}
+
+/// A file representing a tunable.
+///
+/// The inner value is the dot-separated path of the tunable.
+#[derive(Debug)]
+pub struct SysctlFile(String);
 
-impl FileOps for OsRelease {
+impl SysctlFile {
+	/// Returns the associated tunable.
+	fn get(&self) -> EResult<sysctl::Sysctl> {
+		sysctl::get(&self.0).ok_or_else(|| errno!(ENOENT))
+	}
+}
+
+impl FileOps for SysctlFile {
 	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
-		format_content!(off, buf, "{}\n", crate::VERSION)
+		let mut content = String::new();
+		self.get()?.tunable.read(&mut content)?;
+		format_content!(off, buf, "{content}")
+	}
+
+	fn write(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		// Partial writes are not supported
+		if off != 0 {
+			return Err(errno!(EINVAL));
+		}
+		let mut val = Vec::new();
+		val.resize(buf.len(), 0)?;
+		let len = buf.copy_from_user(0, &mut val)?;
+		self.get()?.tunable.write(&val[..len])?;
+		Ok(len)
+	}
+
+	fn truncate(&self, _file: &File, _size: u64) -> EResult<()> {
+		// Allow to open with `O_TRUNC`, as done by shells when redirecting output
+		Ok(())
 	}
 }
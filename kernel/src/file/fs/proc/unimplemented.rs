@@ -0,0 +1,62 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `unimplemented` file lists the unimplemented system calls that have been called, most
+//! frequently called first.
+
+use crate::{
+	file::{File, fs::FileOps},
+	format_content,
+	memory::user::UserSlice,
+	syscall::unimplemented::UNIMPLEMENTED,
+};
+use core::cmp::Reverse;
+use utils::{
+	DisplayableStr,
+	collections::{string::String, vec::Vec},
+	errno::EResult,
+	try_writeln,
+};
+
+/// The `unimplemented` file.
+#[derive(Debug, Default)]
+pub struct Unimplemented;
+
+impl FileOps for Unimplemented {
+	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let mut content = String::new();
+		{
+			let unimplemented = UNIMPLEMENTED.lock();
+			let mut entries = Vec::with_capacity(unimplemented.len())?;
+			for (key, ent) in unimplemented.iter() {
+				entries.push((key, ent))?;
+			}
+			entries.sort_unstable_by_key(|(_, ent)| Reverse(ent.count));
+			for ((compat, id), ent) in entries {
+				let abi = if *compat { "compat" } else { "native" };
+				try_writeln!(
+					content,
+					"0x{id:03x} {abi} {count} {comm}",
+					count = ent.count,
+					comm = DisplayableStr(&ent.comm)
+				)?;
+			}
+		}
+		format_content!(off, buf, "{content}")
+	}
+}
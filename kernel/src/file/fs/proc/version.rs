@@ -31,6 +31,13 @@ pub struct Version;
 
 impl FileOps for Version {
 	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
-		format_content!(off, buf, "{} version {}\n", crate::NAME, crate::VERSION)
+		format_content!(
+			off,
+			buf,
+			"{} version {} {}\n",
+			crate::NAME,
+			crate::RELEASE,
+			crate::BUILD_VERSION
+		)
 	}
 }
@@ -0,0 +1,171 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Squashfs directory listings.
+//!
+//! A listing is a sequence of headers, each followed by up to 256 entries whose inodes are
+//! located in the same metadata block.
+
+use super::{MetadataReader, SquashFs, inode, inode::DirContent};
+use crate::file::FileType;
+use core::hint::unlikely;
+use macros::AnyRepr;
+use utils::{bytes, bytes::AnyRepr, errno, errno::EResult};
+
+/// The maximum number of entries following a header.
+const MAX_ENTRIES: u32 = 256;
+/// The maximum length of an entry's name.
+const MAX_NAME_LEN: usize = 256;
+
+/// A header in a directory listing.
+#[repr(C)]
+#[derive(AnyRepr)]
+struct DirHeader {
+	/// The number of entries following the header, minus one.
+	count: u32,
+	/// The offset of the metadata block containing the entries' inodes, in the inode table.
+	start_block: u32,
+	/// The base inode number for the entries.
+	inode_number: u32,
+}
+
+/// An entry in a directory listing, followed by its name.
+#[repr(C)]
+#[derive(AnyRepr)]
+struct RawDirEntry {
+	/// The offset of the inode in the metadata block.
+	offset: u16,
+	/// The difference between the inode number and the header's base inode number.
+	inode_number: i16,
+	/// The basic type of the inode.
+	inode_type: u16,
+	/// The length of the name, minus one.
+	size: u16,
+}
+
+/// A directory entry.
+pub struct Entry<'i> {
+	/// A reference to the entry's inode.
+	pub inode_ref: u64,
+	/// The entry's inode number.
+	pub inode: u32,
+	/// The entry's type.
+	pub file_type: FileType,
+	/// The entry's name.
+	pub name: &'i [u8],
+}
+
+/// Reader on the content of a directory listing.
+struct Listing<'f> {
+	/// The reader on metadata blocks. If `None`, the listing is empty.
+	reader: Option<MetadataReader<'f>>,
+	/// The number of bytes remaining in the listing.
+	remain: usize,
+}
+
+impl Listing<'_> {
+	/// Reads `buf.len()` bytes from the listing.
+	///
+	/// If the end of the listing is reached, the function returns [`errno::EUCLEAN`].
+	fn read(&mut self, buf: &mut [u8]) -> EResult<()> {
+		let (Some(reader), Some(remain)) = (&mut self.reader, self.remain.checked_sub(buf.len()))
+		else {
+			return Err(errno!(EUCLEAN));
+		};
+		self.remain = remain;
+		reader.read(buf)
+	}
+
+	/// Reads a value of type `T` from the listing.
+	fn read_val<T: AnyRepr>(&mut self) -> EResult<T> {
+		// The type can be represented by any bit pattern
+		let mut val: T = unsafe { core::mem::zeroed() };
+		self.read(bytes::as_bytes_mut(&mut val))?;
+		Ok(val)
+	}
+}
+
+/// Iterator over the entries of a directory listing.
+pub struct DirIterator<'f> {
+	/// The listing.
+	listing: Listing<'f>,
+
+	/// The number of entries remaining for the current header.
+	count: u32,
+	/// The offset of the metadata block containing the inodes for the current header.
+	start_block: u32,
+	/// The base inode number for the current header.
+	base_inode: u32,
+
+	/// Buffer storing the name of the current entry.
+	name: [u8; MAX_NAME_LEN],
+}
+
+impl<'f> DirIterator<'f> {
+	/// Creates an iterator over the listing located by `content`.
+	pub fn new(fs: &'f SquashFs, content: &DirContent) -> EResult<Self> {
+		let reader = (content.size > 0)
+			.then(|| {
+				let start = fs.sp.directory_table_start + content.start as u64;
+				MetadataReader::new(fs, start, content.offset as _)
+			})
+			.transpose()?;
+		Ok(Self {
+			listing: Listing {
+				reader,
+				remain: content.size as _,
+			},
+
+			count: 0,
+			start_block: 0,
+			base_inode: 0,
+
+			name: [0; MAX_NAME_LEN],
+		})
+	}
+
+	/// Returns the next entry.
+	pub fn next(&mut self) -> EResult<Option<Entry<'_>>> {
+		if self.count == 0 {
+			if self.listing.remain == 0 {
+				return Ok(None);
+			}
+			let hdr: DirHeader = self.listing.read_val()?;
+			if unlikely(hdr.count >= MAX_ENTRIES) {
+				return Err(errno!(EUCLEAN));
+			}
+			self.count = hdr.count + 1;
+			self.start_block = hdr.start_block;
+			self.base_inode = hdr.inode_number;
+		}
+		let ent: RawDirEntry = self.listing.read_val()?;
+		let name = self
+			.name
+			.get_mut(..(ent.size as usize + 1))
+			.ok_or_else(|| errno!(EUCLEAN))?;
+		self.listing.read(name)?;
+		self.count -= 1;
+		let file_type = inode::file_type(ent.inode_type).ok_or_else(|| errno!(EUCLEAN))?;
+		Ok(Some(Entry {
+			inode_ref: ((self.start_block as u64) << 16) | ent.offset as u64,
+			inode: self.base_inode.wrapping_add_signed(ent.inode_number as i32),
+			file_type,
+			name,
+		}))
+	}
+}
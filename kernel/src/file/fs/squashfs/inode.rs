@@ -0,0 +1,439 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Squashfs inodes.
+//!
+//! Each inode type has a *basic* and an *extended* variant, the latter allowing larger values and
+//! extended attributes.
+
+use super::{BLOCK_UNCOMPRESSED, METADATA_SIZE, MetadataReader, SquashFs};
+use crate::{
+	device::id,
+	file::{FileType, Mode, Stat},
+};
+use core::{cmp::min, hint::unlikely};
+use macros::AnyRepr;
+use utils::{collections::vec::Vec, errno, errno::EResult, limits::SYMLINK_MAX};
+
+/// Inode type: directory.
+const TYPE_DIR: u16 = 1;
+/// Inode type: regular file.
+const TYPE_REG: u16 = 2;
+/// Inode type: symbolic link.
+const TYPE_SYMLINK: u16 = 3;
+/// Inode type: block device.
+const TYPE_BLKDEV: u16 = 4;
+/// Inode type: character device.
+const TYPE_CHRDEV: u16 = 5;
+/// Inode type: FIFO.
+const TYPE_FIFO: u16 = 6;
+/// Inode type: socket.
+const TYPE_SOCKET: u16 = 7;
+/// The offset between the basic and extended variants of an inode type.
+const TYPE_EXTENDED: u16 = 7;
+
+/// Fragment index telling the file has no fragment.
+const INVALID_FRAG: u32 = 0xffffffff;
+
+/// Returns the file type for the given inode type.
+///
+/// If the type is invalid, the function returns `None`.
+pub fn file_type(inode_type: u16) -> Option<FileType> {
+	let inode_type = match inode_type {
+		1..=TYPE_EXTENDED => inode_type,
+		_ => inode_type.checked_sub(TYPE_EXTENDED)?,
+	};
+	match inode_type {
+		TYPE_DIR => Some(FileType::Directory),
+		TYPE_REG => Some(FileType::Regular),
+		TYPE_SYMLINK => Some(FileType::Link),
+		TYPE_BLKDEV => Some(FileType::BlockDevice),
+		TYPE_CHRDEV => Some(FileType::CharDevice),
+		TYPE_FIFO => Some(FileType::Fifo),
+		TYPE_SOCKET => Some(FileType::Socket),
+		_ => None,
+	}
+}
+
+/// The header common to all inodes.
+#[repr(C)]
+#[derive(AnyRepr)]
+struct BaseInode {
+	/// The inode type.
+	inode_type: u16,
+	/// The file's permissions.
+	mode: u16,
+	/// The index of the owner's user ID in the ID table.
+	uid: u16,
+	/// The index of the owner's group ID in the ID table.
+	gid: u16,
+	/// Timestamp of the last modification.
+	mtime: u32,
+	/// The inode number.
+	inode_number: u32,
+}
+
+/// Basic directory inode.
+#[repr(C)]
+#[derive(AnyRepr)]
+struct DirInode {
+	/// The offset of the metadata block containing the listing, in the directory table.
+	start_block: u32,
+	/// The number of links to the directory.
+	nlink: u32,
+	/// The size of the listing, plus `3`.
+	file_size: u16,
+	/// The offset of the listing in the metadata block.
+	offset: u16,
+	/// The inode number of the parent directory.
+	parent_inode: u32,
+}
+
+/// Extended directory inode.
+#[repr(C)]
+#[derive(AnyRepr)]
+struct LDirInode {
+	/// The number of links to the directory.
+	nlink: u32,
+	/// The size of the listing, plus `3`.
+	file_size: u32,
+	/// The offset of the metadata block containing the listing, in the directory table.
+	start_block: u32,
+	/// The inode number of the parent directory.
+	parent_inode: u32,
+	/// The number of entries in the directory index following the inode.
+	i_count: u16,
+	/// The offset of the listing in the metadata block.
+	offset: u16,
+	/// The index of the extended attributes.
+	xattr: u32,
+}
+
+/// Basic regular file inode.
+#[repr(C)]
+#[derive(AnyRepr)]
+struct RegInode {
+	/// The offset of the first data block.
+	start_block: u32,
+	/// The index of the fragment containing the tail of the file.
+	fragment: u32,
+	/// The offset of the tail in the fragment.
+	offset: u32,
+	/// The size of the file, in bytes.
+	file_size: u32,
+}
+
+/// Extended regular file inode.
+#[repr(C)]
+#[derive(AnyRepr)]
+struct LRegInode {
+	/// The offset of the first data block.
+	start_block: u64,
+	/// The size of the file, in bytes.
+	file_size: u64,
+	/// The number of bytes saved by skipping zero blocks.
+	sparse: u64,
+	/// The number of links to the file.
+	nlink: u32,
+	/// The index of the fragment containing the tail of the file.
+	fragment: u32,
+	/// The offset of the tail in the fragment.
+	offset: u32,
+	/// The index of the extended attributes.
+	xattr: u32,
+}
+
+/// Symbolic link inode, followed by the target.
+#[repr(C)]
+#[derive(AnyRepr)]
+struct SymlinkInode {
+	/// The number of links to the file.
+	nlink: u32,
+	/// The size of the target, in bytes.
+	symlink_size: u32,
+}
+
+/// Device inode.
+#[repr(C)]
+#[derive(AnyRepr)]
+struct DevInode {
+	/// The number of links to the file.
+	nlink: u32,
+	/// The device number.
+	rdev: u32,
+}
+
+/// IPC (FIFO or socket) inode.
+#[repr(C)]
+#[derive(AnyRepr)]
+struct IpcInode {
+	/// The number of links to the file.
+	nlink: u32,
+}
+
+/// An entry of the fragment table.
+#[repr(C)]
+#[derive(AnyRepr)]
+struct FragmentEntry {
+	/// The offset of the fragment block.
+	start_block: u64,
+	/// The size of the fragment block, encoded like data block sizes.
+	size: u32,
+	/// Unused.
+	unused: u32,
+}
+
+/// The location of a directory's listing.
+#[derive(Debug)]
+pub struct DirContent {
+	/// The offset of the metadata block containing the listing, in the directory table.
+	pub start: u32,
+	/// The offset of the listing in the metadata block.
+	pub offset: u16,
+	/// The size of the listing, in bytes.
+	pub size: u32,
+	/// The inode number of the parent directory.
+	pub parent: u32,
+}
+
+/// The location of a regular file's content.
+#[derive(Debug)]
+pub struct FileContent {
+	/// The offset of the first data block.
+	blocks_start: u64,
+	/// The size of the file, in bytes.
+	size: u64,
+	/// The index of the fragment containing the tail of the file and the offset of the tail in
+	/// it, if any.
+	fragment: Option<(u32, u32)>,
+	/// The size of each data block, encoded with [`BLOCK_UNCOMPRESSED`].
+	block_sizes: Vec<u32>,
+}
+
+impl FileContent {
+	/// Reads the content location of a file, whose list of block sizes is the next data in
+	/// `reader`.
+	fn read(
+		reader: &mut MetadataReader,
+		blocks_start: u64,
+		size: u64,
+		fragment: u32,
+		offset: u32,
+	) -> EResult<Self> {
+		let fs = reader.fs;
+		let blk_size = fs.sp.block_size as u64;
+		let (count, fragment) = match fragment {
+			INVALID_FRAG => (size.div_ceil(blk_size), None),
+			_ => (size / blk_size, Some((fragment, offset))),
+		};
+		if unlikely(count > fs.sp.bytes_used / size_of::<u32>() as u64) {
+			return Err(errno!(EUCLEAN));
+		}
+		let mut block_sizes = Vec::with_capacity(count as _)?;
+		for _ in 0..count {
+			block_sizes.push(reader.read_val::<u32>()?)?;
+		}
+		Ok(Self {
+			blocks_start,
+			size,
+			fragment,
+			block_sizes,
+		})
+	}
+
+	/// Reads the data block at index `blk` into `buf`, which must be at least the size of a
+	/// block.
+	///
+	/// On success, the function returns the size of the data in the block, which is less than
+	/// the size of a block only for the last one. Past the end of the file, it returns `0`.
+	pub fn read_block(&self, fs: &SquashFs, blk: u64, buf: &mut [u8]) -> EResult<usize> {
+		let blk_size = fs.sp.block_size as u64;
+		let start = blk.saturating_mul(blk_size);
+		if start >= self.size {
+			return Ok(0);
+		}
+		let len = min(blk_size, self.size - start) as usize;
+		let buf = &mut buf[..len];
+		if let Some(disk_size) = self.block_sizes.get(blk as usize) {
+			// Zero block of a sparse file
+			if *disk_size & !BLOCK_UNCOMPRESSED == 0 {
+				buf.fill(0);
+				return Ok(len);
+			}
+			let off = self.block_sizes[..blk as usize]
+				.iter()
+				.map(|size| (*size & !BLOCK_UNCOMPRESSED) as u64)
+				.sum::<u64>();
+			let n = fs.read_block(self.blocks_start + off, *disk_size, buf)?;
+			if unlikely(n != len) {
+				return Err(errno!(EUCLEAN));
+			}
+		} else {
+			// The tail of the file is in a fragment
+			let (index, off) = self.fragment.ok_or_else(|| errno!(EUCLEAN))?;
+			let frag = read_fragment(fs, index)?;
+			let mut frag_buf = Vec::new();
+			frag_buf.resize(blk_size as _, 0)?;
+			let n = fs.read_block(frag.start_block, frag.size, &mut frag_buf)?;
+			let src = frag_buf[..n]
+				.get((off as usize)..(off as usize + len))
+				.ok_or_else(|| errno!(EUCLEAN))?;
+			buf.copy_from_slice(src);
+		}
+		Ok(len)
+	}
+}
+
+/// Reads the entry at index `index` in the fragment table.
+fn read_fragment(fs: &SquashFs, index: u32) -> EResult<FragmentEntry> {
+	if unlikely(index >= fs.sp.fragments) {
+		return Err(errno!(EUCLEAN));
+	}
+	// The table is stored in metadata blocks, located by an array of pointers
+	let per_block = (METADATA_SIZE / size_of::<FragmentEntry>()) as u32;
+	let ptr_off = (index / per_block) as usize * size_of::<u64>();
+	let ptr = fs.read_u64(fs.sp.fragment_table_start + ptr_off as u64)?;
+	let off = (index % per_block) as usize * size_of::<FragmentEntry>();
+	MetadataReader::new(fs, ptr, off)?.read_val()
+}
+
+/// The content of an inode.
+#[derive(Debug)]
+pub enum Content {
+	/// The location of a directory's listing.
+	Directory(DirContent),
+	/// The location of a regular file's content.
+	Regular(FileContent),
+	/// The target of a symbolic link.
+	Link(Vec<u8>),
+	/// No content.
+	None,
+}
+
+/// An inode, read from the inode table.
+pub struct Inode {
+	/// The inode number.
+	pub number: u32,
+	/// The status of the file.
+	pub stat: Stat,
+	/// The content of the inode.
+	pub content: Content,
+}
+
+impl Inode {
+	/// Reads the inode referred to by `inode_ref`.
+	///
+	/// An inode reference is made of the offset of the metadata block containing the inode in
+	/// the inode table, shifted left by `16`, and the offset of the inode in the metadata block.
+	pub fn read(fs: &SquashFs, inode_ref: u64) -> EResult<Self> {
+		let start = fs.sp.inode_table_start + (inode_ref >> 16);
+		let mut reader = MetadataReader::new(fs, start, (inode_ref & 0xffff) as _)?;
+		let base: BaseInode = reader.read_val()?;
+		let file_type = file_type(base.inode_type).ok_or_else(|| errno!(EUCLEAN))?;
+		let extended = base.inode_type > TYPE_EXTENDED;
+		// The number of links, the size, the device number and the content
+		let (nlink, size, rdev, content) = match (file_type, extended) {
+			(FileType::Directory, false) => {
+				let inode: DirInode = reader.read_val()?;
+				let content = DirContent {
+					start: inode.start_block,
+					offset: inode.offset,
+					size: (inode.file_size as u32).saturating_sub(3),
+					parent: inode.parent_inode,
+				};
+				let size = inode.file_size as u64;
+				(inode.nlink, size, 0, Content::Directory(content))
+			}
+			(FileType::Directory, true) => {
+				let inode: LDirInode = reader.read_val()?;
+				let content = DirContent {
+					start: inode.start_block,
+					offset: inode.offset,
+					size: inode.file_size.saturating_sub(3),
+					parent: inode.parent_inode,
+				};
+				let size = inode.file_size as u64;
+				(inode.nlink, size, 0, Content::Directory(content))
+			}
+			(FileType::Regular, false) => {
+				let inode: RegInode = reader.read_val()?;
+				let size = inode.file_size as u64;
+				let content = FileContent::read(
+					&mut reader,
+					inode.start_block as _,
+					size,
+					inode.fragment,
+					inode.offset,
+				)?;
+				(1, size, 0, Content::Regular(content))
+			}
+			(FileType::Regular, true) => {
+				let inode: LRegInode = reader.read_val()?;
+				let content = FileContent::read(
+					&mut reader,
+					inode.start_block,
+					inode.file_size,
+					inode.fragment,
+					inode.offset,
+				)?;
+				(inode.nlink, inode.file_size, 0, Content::Regular(content))
+			}
+			(FileType::Link, _) => {
+				let inode: SymlinkInode = reader.read_val()?;
+				let size = inode.symlink_size as usize;
+				if unlikely(size > SYMLINK_MAX) {
+					return Err(errno!(EUCLEAN));
+				}
+				let mut target = Vec::new();
+				target.resize(size, 0)?;
+				reader.read(&mut target)?;
+				(inode.nlink, size as _, 0, Content::Link(target))
+			}
+			(FileType::BlockDevice | FileType::CharDevice, _) => {
+				let inode: DevInode = reader.read_val()?;
+				(inode.nlink, 0, inode.rdev, Content::None)
+			}
+			(FileType::Fifo | FileType::Socket, _) => {
+				let inode: IpcInode = reader.read_val()?;
+				(inode.nlink, 0, 0, Content::None)
+			}
+		};
+		let stat = Stat {
+			mode: file_type.to_mode() | (base.mode as Mode & 0o7777),
+			nlink: min(nlink, u16::MAX as _) as _,
+			uid: fs.get_id(base.uid)? as _,
+			gid: fs.get_id(base.gid)? as _,
+			size,
+			blocks: size.div_ceil(512),
+			dev_major: id::major(rdev as _),
+			dev_minor: id::minor(rdev as _),
+			ctime: base.mtime as u64 * 1_000_000_000,
+			mtime: base.mtime as u64 * 1_000_000_000,
+			atime: base.mtime as u64 * 1_000_000_000,
+			// squashfs does not record the creation time
+			btime: None,
+
+			attributes: 0,
+		};
+		Ok(Self {
+			number: base.inode_number,
+			stat,
+			content,
+		})
+	}
+}
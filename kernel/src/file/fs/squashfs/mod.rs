@@ -0,0 +1,603 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Squashfs is a compressed read-only filesystem, commonly used for the root of live systems.
+//!
+//! The filesystem is made of several tables, each located with the superblock:
+//! - Inode table: stores the inodes
+//! - Directory table: stores the listings of directories
+//! - Fragment table: locates fragment blocks, which pack the tails of several files together
+//! - ID table: stores the user and group IDs, referred to by index in inodes
+//!
+//! Inodes and directories are stored in *metadata blocks*, each holding up to 8 KiB of
+//! (possibly compressed) data. Files' content is stored in data blocks, of the size given by the
+//! superblock.
+//!
+//! Only zlib compression is supported.
+//!
+//! For more information, see the
+//! [specifications](https://dr-emann.github.io/squashfs/squashfs.html).
+
+mod dir;
+mod inode;
+
+use crate::{
+	device::BlkDev,
+	file::{
+		DirContext, DirEntry, File, FileType, Stat,
+		fs::{
+			FileOps, Filesystem, FilesystemOps, FilesystemType, NodeOps, Statfs, downcast_fs,
+			generic_file_read, options::MountOptions,
+		},
+		vfs,
+		vfs::node::Node,
+	},
+	memory::{cache::RcPage, user::UserSlice},
+};
+use core::{cmp::min, hint::unlikely};
+use dir::DirIterator;
+use inode::{Content, FileContent, Inode};
+use macros::AnyRepr;
+use utils::{
+	boxed::Box,
+	bytes,
+	bytes::AnyRepr,
+	collections::{path::PathBuf, vec::Vec},
+	compress::{Decompress, zlib},
+	errno,
+	errno::EResult,
+	limits::{NAME_MAX, PAGE_SIZE},
+	ptr::arc::Arc,
+};
+
+/// The filesystem's magic number.
+const SQUASHFS_MAGIC: u32 = 0x73717368;
+/// The supported major version.
+const VERSION_MAJOR: u16 = 4;
+
+/// Compression algorithm: zlib.
+const COMPRESSION_ZLIB: u16 = 1;
+
+/// The minimum size of a data block, in bytes.
+const MIN_BLOCK_SIZE: u32 = 4096;
+/// The maximum size of a data block, in bytes.
+const MAX_BLOCK_SIZE: u32 = 1024 * 1024;
+
+/// The maximum size of the data in a metadata block, in bytes.
+const METADATA_SIZE: usize = 8192;
+/// Metadata block header flag: the block is not compressed.
+const METADATA_UNCOMPRESSED: u16 = 1 << 15;
+/// Data block size flag: the block is not compressed.
+const BLOCK_UNCOMPRESSED: u32 = 1 << 24;
+
+/// The squashfs superblock structure.
+#[repr(C)]
+#[derive(AnyRepr, Clone, Copy, Debug)]
+struct Superblock {
+	/// The magic number.
+	s_magic: u32,
+	/// The number of inodes.
+	inodes: u32,
+	/// The timestamp of the creation of the filesystem.
+	mkfs_time: u32,
+	/// The size of a data block, in bytes.
+	block_size: u32,
+	/// The number of entries in the fragment table.
+	fragments: u32,
+	/// The compression algorithm.
+	compression: u16,
+	/// The base-2 logarithm of `block_size`.
+	block_log: u16,
+	/// Superblock flags.
+	flags: u16,
+	/// The number of entries in the ID table.
+	no_ids: u16,
+	/// The major version.
+	s_major: u16,
+	/// The minor version.
+	s_minor: u16,
+	/// A reference to the root directory's inode.
+	root_inode: u64,
+	/// The number of bytes used by the filesystem.
+	bytes_used: u64,
+	/// The offset of the ID table.
+	id_table_start: u64,
+	/// The offset of the extended attributes table.
+	xattr_id_table_start: u64,
+	/// The offset of the inode table.
+	inode_table_start: u64,
+	/// The offset of the directory table.
+	directory_table_start: u64,
+	/// The offset of the fragment table.
+	fragment_table_start: u64,
+	/// The offset of the export table.
+	lookup_table_start: u64,
+}
+
+impl Superblock {
+	/// Reads the superblock from the given device.
+	fn read(dev: &Arc<BlkDev>) -> EResult<Self> {
+		let page = dev.ops.read_page(dev, 0)?;
+		let sp = bytes::from_bytes::<Self>(page.slice()).ok_or_else(|| errno!(EINVAL))?;
+		Ok(*sp)
+	}
+
+	/// Tells whether the superblock is valid.
+	fn is_valid(&self) -> bool {
+		self.s_magic == SQUASHFS_MAGIC && self.s_major == VERSION_MAJOR
+	}
+}
+
+/// Decompresses `src` into `dst`.
+///
+/// On success, the function returns the size of the decompressed data.
+///
+/// If the decompressed data does not fit in `dst`, the function returns [`errno::EUCLEAN`].
+fn decompress(src: &[u8], dst: &mut [u8]) -> EResult<usize> {
+	let mut reader = zlib::Reader::new(src)?;
+	let mut len = 0;
+	while len < dst.len() {
+		let n = reader.read(&mut dst[len..])?;
+		if n == 0 {
+			return Ok(len);
+		}
+		len += n;
+	}
+	// Make sure the stream ends here
+	if reader.read(&mut [0])? != 0 {
+		return Err(errno!(EUCLEAN));
+	}
+	Ok(len)
+}
+
+/// Reader for data stored in consecutive metadata blocks.
+struct MetadataReader<'f> {
+	/// The filesystem.
+	fs: &'f SquashFs,
+	/// The offset of the next metadata block on the device.
+	next: u64,
+	/// The decompressed data of the current metadata block.
+	buf: Vec<u8>,
+	/// The offset in `buf`.
+	off: usize,
+}
+
+impl<'f> MetadataReader<'f> {
+	/// Creates a reader starting at the offset `off` of the metadata block located at `start` on
+	/// the device.
+	fn new(fs: &'f SquashFs, start: u64, off: usize) -> EResult<Self> {
+		let mut reader = Self {
+			fs,
+			next: start,
+			buf: Vec::new(),
+			off: 0,
+		};
+		reader.next_block()?;
+		if unlikely(off > reader.buf.len()) {
+			return Err(errno!(EUCLEAN));
+		}
+		reader.off = off;
+		Ok(reader)
+	}
+
+	/// Reads the next metadata block.
+	fn next_block(&mut self) -> EResult<()> {
+		let mut hdr = [0; 2];
+		self.fs.read(self.next, &mut hdr)?;
+		let hdr = u16::from_le_bytes(hdr);
+		let size = (hdr & !METADATA_UNCOMPRESSED) as usize;
+		if unlikely(size == 0 || size > METADATA_SIZE) {
+			return Err(errno!(EUCLEAN));
+		}
+		let mut src = Vec::new();
+		src.resize(size, 0)?;
+		self.fs.read(self.next + 2, &mut src)?;
+		if hdr & METADATA_UNCOMPRESSED != 0 {
+			self.buf = src;
+		} else {
+			self.buf.resize(METADATA_SIZE, 0)?;
+			let len = decompress(&src, &mut self.buf)?;
+			self.buf.truncate(len);
+		}
+		self.next += 2 + size as u64;
+		self.off = 0;
+		Ok(())
+	}
+
+	/// Reads data into `buf`, continuing on the next metadata blocks if necessary.
+	fn read(&mut self, buf: &mut [u8]) -> EResult<()> {
+		let mut buf_off = 0;
+		while buf_off < buf.len() {
+			if self.off >= self.buf.len() {
+				self.next_block()?;
+			}
+			let len = min(self.buf.len() - self.off, buf.len() - buf_off);
+			buf[buf_off..(buf_off + len)].copy_from_slice(&self.buf[self.off..(self.off + len)]);
+			self.off += len;
+			buf_off += len;
+		}
+		Ok(())
+	}
+
+	/// Reads a value of type `T`.
+	fn read_val<T: AnyRepr>(&mut self) -> EResult<T> {
+		// The type can be represented by any bit pattern
+		let mut val: T = unsafe { core::mem::zeroed() };
+		self.read(bytes::as_bytes_mut(&mut val))?;
+		Ok(val)
+	}
+}
+
+/// An instance of the squashfs filesystem.
+#[derive(Debug)]
+struct SquashFs {
+	/// The device on which the filesystem is located
+	dev: Arc<BlkDev>,
+	/// The filesystem's superblock
+	sp: Superblock,
+	/// The content of the ID table
+	ids: Vec<u32>,
+}
+
+impl SquashFs {
+	/// Reads `buf.len()` bytes at the offset `off` on the device.
+	///
+	/// If the range is outside of the filesystem, the function returns [`errno::EUCLEAN`].
+	fn read(&self, off: u64, buf: &mut [u8]) -> EResult<()> {
+		let end = off.checked_add(buf.len() as u64);
+		if unlikely(end.is_none_or(|end| end > self.sp.bytes_used)) {
+			return Err(errno!(EUCLEAN));
+		}
+		let mut buf_off = 0;
+		while buf_off < buf.len() {
+			let cur = off + buf_off as u64;
+			let page = self.dev.ops.read_page(&self.dev, cur / PAGE_SIZE as u64)?;
+			let inner_off = (cur % PAGE_SIZE as u64) as usize;
+			let len = min(PAGE_SIZE - inner_off, buf.len() - buf_off);
+			buf[buf_off..(buf_off + len)]
+				.copy_from_slice(&page.slice()[inner_off..(inner_off + len)]);
+			buf_off += len;
+		}
+		Ok(())
+	}
+
+	/// Reads an on-disk `u64` at the offset `off` on the device.
+	fn read_u64(&self, off: u64) -> EResult<u64> {
+		let mut buf = [0; 8];
+		self.read(off, &mut buf)?;
+		Ok(u64::from_le_bytes(buf))
+	}
+
+	/// Reads a data block located at `off` into `buf`.
+	///
+	/// `size` is the size of the block as stored in inodes and in the fragment table.
+	///
+	/// On success, the function returns the size of the decompressed data.
+	fn read_block(&self, off: u64, size: u32, buf: &mut [u8]) -> EResult<usize> {
+		let disk_size = (size & !BLOCK_UNCOMPRESSED) as usize;
+		if unlikely(disk_size > self.sp.block_size as usize) {
+			return Err(errno!(EUCLEAN));
+		}
+		if size & BLOCK_UNCOMPRESSED != 0 {
+			let len = min(disk_size, buf.len());
+			self.read(off, &mut buf[..len])?;
+			Ok(len)
+		} else {
+			let mut src = Vec::new();
+			src.resize(disk_size, 0)?;
+			self.read(off, &mut src)?;
+			decompress(&src, buf)
+		}
+	}
+
+	/// Reads the ID table.
+	fn read_ids(&self) -> EResult<Vec<u32>> {
+		let count = self.sp.no_ids as usize;
+		let mut ids = Vec::with_capacity(count)?;
+		// The table is stored in metadata blocks, located by an array of pointers
+		let per_block = METADATA_SIZE / size_of::<u32>();
+		for blk in 0..count.div_ceil(per_block) {
+			let ptr = self.read_u64(self.sp.id_table_start + (blk * size_of::<u64>()) as u64)?;
+			let mut reader = MetadataReader::new(self, ptr, 0)?;
+			for _ in 0..min(count - ids.len(), per_block) {
+				ids.push(reader.read_val::<u32>()?)?;
+			}
+		}
+		Ok(ids)
+	}
+
+	/// Returns the ID at index `index` in the ID table.
+	fn get_id(&self, index: u16) -> EResult<u32> {
+		self.ids
+			.get(index as usize)
+			.cloned()
+			.ok_or_else(|| errno!(EUCLEAN))
+	}
+
+	/// Returns the node for the inode referred to by `inode_ref`, with the inode number `inode`.
+	fn get_node(&self, fs: &Arc<Filesystem>, inode: u32, inode_ref: u64) -> EResult<Arc<Node>> {
+		fs.node_get_or_insert(inode as _, || new_node(fs, Inode::read(self, inode_ref)?))
+	}
+}
+
+/// Creates a node for `inode`.
+fn new_node(fs: &Arc<Filesystem>, inode: Inode) -> EResult<Arc<Node>> {
+	Ok(Arc::new(Node::new(
+		inode.number as _,
+		fs.clone(),
+		inode.stat,
+		Box::new(SquashNodeOps(inode.content))?,
+		Box::new(SquashFileOps)?,
+	))?)
+}
+
+impl FilesystemOps for SquashFs {
+	fn get_name(&self) -> &[u8] {
+		b"squashfs"
+	}
+
+	fn cache_entries(&self) -> bool {
+		true
+	}
+
+	fn get_stat(&self) -> EResult<Statfs> {
+		Ok(Statfs {
+			f_type: SQUASHFS_MAGIC,
+			f_bsize: self.sp.block_size,
+			f_blocks: self.sp.bytes_used.div_ceil(self.sp.block_size as u64) as _,
+			f_bfree: 0,
+			f_bavail: 0,
+			f_files: self.sp.inodes as _,
+			f_ffree: 0,
+			f_fsid: Default::default(),
+			f_namelen: NAME_MAX as _,
+			f_frsize: self.sp.block_size,
+			f_flags: 0,
+		})
+	}
+
+	fn root(&self, fs: &Arc<Filesystem>) -> EResult<Arc<Node>> {
+		let inode = Inode::read(self, self.sp.root_inode)?;
+		fs.node_get_or_insert(inode.number as _, || new_node(fs, inode))
+	}
+
+	fn create_node(&self, _fs: &Arc<Filesystem>, _stat: Stat) -> EResult<Arc<Node>> {
+		Err(errno!(EROFS))
+	}
+
+	fn destroy_node(&self, _node: &Node) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+}
+
+/// Node operations.
+///
+/// The structure holds the location of the node's content.
+#[derive(Debug)]
+struct SquashNodeOps(Content);
+
+impl NodeOps for SquashNodeOps {
+	fn lookup_entry(&self, dir: &Node, ent: &mut vfs::Entry) -> EResult<()> {
+		let Content::Directory(content) = &self.0 else {
+			return Err(errno!(ENOTDIR));
+		};
+		let fs = downcast_fs::<SquashFs>(&*dir.fs.ops);
+		let mut iter = DirIterator::new(fs, content)?;
+		ent.node = None;
+		while let Some(e) = iter.next()? {
+			if e.name == ent.name.as_bytes() {
+				ent.node = Some(fs.get_node(&dir.fs, e.inode, e.inode_ref)?);
+				break;
+			}
+		}
+		Ok(())
+	}
+
+	fn iter_entries(&self, dir: &Node, ctx: &mut DirContext) -> EResult<()> {
+		let Content::Directory(content) = &self.0 else {
+			return Err(errno!(ENOTDIR));
+		};
+		// `.` and `..` are not stored on the filesystem
+		let dots: [(&[u8], u32); 2] = [(b".", dir.inode as _), (b"..", content.parent)];
+		for (name, inode) in dots.iter().skip(ctx.off as _) {
+			let e = DirEntry {
+				inode: *inode as _,
+				entry_type: Some(FileType::Directory),
+				name,
+			};
+			if !(ctx.write)(&e)? {
+				return Ok(());
+			}
+			ctx.off += 1;
+		}
+		let fs = downcast_fs::<SquashFs>(&*dir.fs.ops);
+		let mut iter = DirIterator::new(fs, content)?;
+		let mut off = dots.len() as u64;
+		while let Some(e) = iter.next()? {
+			if off >= ctx.off {
+				let e = DirEntry {
+					inode: e.inode as _,
+					entry_type: Some(e.file_type),
+					name: e.name,
+				};
+				if !(ctx.write)(&e)? {
+					break;
+				}
+				ctx.off += 1;
+			}
+			off += 1;
+		}
+		Ok(())
+	}
+
+	fn link(&self, _parent: Arc<Node>, _ent: &vfs::Entry) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+
+	fn unlink(&self, _parent: &Node, _ent: &vfs::Entry) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+
+	fn readlink(&self, _node: &Node, buf: UserSlice<u8>) -> EResult<usize> {
+		let Content::Link(target) = &self.0 else {
+			return Err(errno!(EINVAL));
+		};
+		buf.copy_to_user(0, target)
+	}
+
+	fn writelink(&self, _node: &Node, _buf: &[u8]) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+
+	fn rename(
+		&self,
+		_old_entry: &vfs::Entry,
+		_new_parent: &vfs::Entry,
+		_new_name: &[u8],
+	) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+
+	fn read_page(&self, node: &Arc<Node>, off: u64) -> EResult<RcPage> {
+		node.mapped.get_or_insert_page(off, || {
+			let Content::Regular(content) = &self.0 else {
+				return Err(errno!(EINVAL));
+			};
+			let fs = downcast_fs::<SquashFs>(&*node.fs.ops);
+			read_file_page(fs, node, content, off)
+		})
+	}
+
+	fn set_stat(&self, _node: &Node, _stat: &Stat) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+}
+
+/// Reads the page at offset `off` of a regular file.
+///
+/// Since a data block may span several pages, the other pages of the block are inserted in the
+/// node's cache so that the block does not have to be decompressed again.
+fn read_file_page(fs: &SquashFs, node: &Node, content: &FileContent, off: u64) -> EResult<RcPage> {
+	let blk_size = fs.sp.block_size as u64;
+	let pages_per_blk = blk_size / PAGE_SIZE as u64;
+	let blk = off / pages_per_blk;
+	let first_page = blk * pages_per_blk;
+	let mut buf = Vec::new();
+	buf.resize(blk_size as _, 0)?;
+	let len = content.read_block(fs, blk, &mut buf)?;
+	let mut page = None;
+	for (i, chunk) in buf[..len].chunks(PAGE_SIZE).enumerate() {
+		let p = RcPage::new_zeroed()?;
+		// No one else can access the page since we just allocated it
+		unsafe {
+			p.slice_mut()[..chunk.len()].copy_from_slice(chunk);
+		}
+		let page_off = first_page + i as u64;
+		if page_off == off {
+			page = Some(p);
+		} else {
+			node.mapped.get_or_insert_page(page_off, || Ok(p))?;
+		}
+	}
+	// The page may be past the end of the file
+	match page {
+		Some(page) => Ok(page),
+		None => Ok(RcPage::new_zeroed()?),
+	}
+}
+
+/// Open file operations.
+#[derive(Debug)]
+struct SquashFileOps;
+
+impl FileOps for SquashFileOps {
+	fn read(&self, file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		if file.node().get_type() != Some(FileType::Regular) {
+			return Err(errno!(EINVAL));
+		}
+		generic_file_read(file, off, buf)
+	}
+
+	fn write(&self, _file: &File, _off: u64, _buf: UserSlice<u8>) -> EResult<usize> {
+		Err(errno!(EROFS))
+	}
+
+	fn truncate(&self, _file: &File, _size: u64) -> EResult<()> {
+		Err(errno!(EROFS))
+	}
+}
+
+/// The squashfs filesystem type.
+pub struct SquashFsType;
+
+impl FilesystemType for SquashFsType {
+	fn get_name(&self) -> &'static [u8] {
+		b"squashfs"
+	}
+
+	fn detect(&self, dev: &Arc<BlkDev>) -> EResult<bool> {
+		Superblock::read(dev).map(|sp| sp.is_valid())
+	}
+
+	fn detect_priority(&self) -> i32 {
+		// The 32-bit magic number is more reliable than ext2's 16-bit one
+		1
+	}
+
+	fn load_filesystem(
+		&self,
+		dev: Option<Arc<BlkDev>>,
+		_mountpath: PathBuf,
+		_readonly: bool,
+		data: &[u8],
+	) -> EResult<Arc<Filesystem>> {
+		let dev = dev.ok_or_else(|| errno!(ENODEV))?;
+		for opt in MountOptions::new(data) {
+			let opt = opt?;
+			match opt.key {
+				// Access times are never updated, so there is nothing to do
+				b"noatime" => opt.flag()?,
+				_ => return Err(errno!(EINVAL)),
+			}
+		}
+		let sp = Superblock::read(&dev)?;
+		if unlikely(!sp.is_valid()) {
+			return Err(errno!(EINVAL));
+		}
+		if unlikely(sp.compression != COMPRESSION_ZLIB) {
+			return Err(errno!(EINVAL));
+		}
+		let valid_blk_size = sp.block_size.is_power_of_two()
+			&& (MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&sp.block_size)
+			&& sp.block_size.trailing_zeros() == sp.block_log as u32;
+		if unlikely(!valid_blk_size) {
+			return Err(errno!(EINVAL));
+		}
+		let mut fs = SquashFs {
+			dev,
+			sp,
+			ids: Vec::new(),
+		};
+		fs.ids = fs.read_ids()?;
+		// The filesystem is always read-only
+		Ok(Filesystem::new(
+			fs.dev.id.get_device_number(),
+			Box::new(fs)?,
+		)?)
+	}
+}
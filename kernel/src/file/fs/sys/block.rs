@@ -0,0 +1,229 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `block` directory contains a directory for each storage device, named after its device
+//! file.
+//!
+//! The directory of a device contains a subdirectory for each of its partitions. Sizes and offsets
+//! are given in units of 512 bytes, regardless of the sector size of the device.
+
+use crate::{
+	device::{BLK_DEVICES, BlkDev},
+	file::{
+		DirContext, DirEntry, File, FileType, Stat,
+		fs::{
+			DummyOps, FileOps, NodeOps,
+			kernfs::{box_file, box_node, iter_names, static_dir_stat},
+		},
+		vfs,
+		vfs::node::Node,
+	},
+	format_content,
+	memory::user::UserSlice,
+};
+use utils::{
+	boxed::Box,
+	collections::{string::String, vec::Vec},
+	errno,
+	errno::{AllocResult, EResult},
+	ptr::arc::Arc,
+};
+
+/// The unit of sizes and offsets, in bytes.
+const SECTOR_SIZE: u64 = 512;
+
+/// Returns the name of the device `dev`.
+fn dev_name(dev: &BlkDev) -> &[u8] {
+	dev.path.file_name().unwrap_or_default()
+}
+
+/// Returns the directory node of the device `dev`.
+fn dev_dir(dir: &Node, dev: Arc<BlkDev>) -> AllocResult<Arc<Node>> {
+	Arc::new(Node::new(
+		0,
+		dir.fs.clone(),
+		static_dir_stat(),
+		box_node(DevDir(dev))?,
+		Box::new(DummyOps)?,
+	))
+}
+
+/// The `block` directory.
+#[derive(Debug)]
+pub struct BlockDir;
+
+impl NodeOps for BlockDir {
+	fn lookup_entry(&self, dir: &Node, ent: &mut vfs::Entry) -> EResult<()> {
+		let dev = BLK_DEVICES
+			.lock()
+			.iter()
+			.find(|(_, dev)| !dev.is_partition && dev_name(dev) == &*ent.name)
+			.map(|(_, dev)| dev.clone());
+		ent.node = dev.map(|dev| dev_dir(dir, dev)).transpose()?;
+		Ok(())
+	}
+
+	fn iter_entries(&self, _dir: &Node, ctx: &mut DirContext) -> EResult<()> {
+		let mut names = Vec::new();
+		for (_, dev) in BLK_DEVICES.lock().iter() {
+			if !dev.is_partition {
+				names.push(String::try_from(dev_name(dev))?)?;
+			}
+		}
+		// Keep a stable order across calls
+		names.sort_unstable_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+		iter_names(&names, FileType::Directory, ctx)
+	}
+}
+
+/// An attribute of a block device, exposed as a file.
+#[derive(Clone, Copy, Debug)]
+enum Attr {
+	/// The major and minor numbers of the device
+	Dev,
+	/// The number of the partition
+	Partition,
+	/// Whether the device is read-only
+	Ro,
+	/// The size of the device
+	Size,
+	/// The offset of the partition on the device containing it
+	Start,
+}
+
+impl Attr {
+	/// Returns the name of the attribute's file.
+	fn name(self) -> &'static [u8] {
+		match self {
+			Self::Dev => b"dev",
+			Self::Partition => b"partition",
+			Self::Ro => b"ro",
+			Self::Size => b"size",
+			Self::Start => b"start",
+		}
+	}
+}
+
+/// Attributes of a whole device.
+const DISK_ATTRS: &[Attr] = &[Attr::Dev, Attr::Ro, Attr::Size];
+/// Attributes of a partition.
+const PART_ATTRS: &[Attr] = &[
+	Attr::Dev,
+	Attr::Partition,
+	Attr::Ro,
+	Attr::Size,
+	Attr::Start,
+];
+
+/// The directory of a block device.
+#[derive(Debug)]
+struct DevDir(Arc<BlkDev>);
+
+impl DevDir {
+	/// Returns the attributes of the device.
+	fn attrs(&self) -> &'static [Attr] {
+		if self.0.is_partition {
+			PART_ATTRS
+		} else {
+			DISK_ATTRS
+		}
+	}
+}
+
+impl NodeOps for DevDir {
+	fn lookup_entry(&self, dir: &Node, ent: &mut vfs::Entry) -> EResult<()> {
+		if let Some(attr) = self.attrs().iter().find(|a| a.name() == &*ent.name) {
+			ent.node = Some(Arc::new(Node::new(
+				0,
+				dir.fs.clone(),
+				Stat {
+					mode: FileType::Regular.to_mode() | 0o444,
+					..Default::default()
+				},
+				Box::new(DummyOps)?,
+				box_file(AttrFile {
+					dev: self.0.clone(),
+					attr: *attr,
+				})?,
+			))?);
+			return Ok(());
+		}
+		let part = self
+			.0
+			.partitions
+			.lock()
+			.iter()
+			.find(|part| dev_name(part) == &*ent.name)
+			.cloned();
+		ent.node = part.map(|part| dev_dir(dir, part)).transpose()?;
+		Ok(())
+	}
+
+	fn iter_entries(&self, _dir: &Node, ctx: &mut DirContext) -> EResult<()> {
+		let mut entries = Vec::new();
+		for attr in self.attrs() {
+			entries.push((String::try_from(attr.name())?, FileType::Regular))?;
+		}
+		for part in self.0.partitions.lock().iter() {
+			entries.push((String::try_from(dev_name(part))?, FileType::Directory))?;
+		}
+		for (name, entry_type) in entries.iter().skip(ctx.off as usize) {
+			let ent = DirEntry {
+				inode: 0,
+				entry_type: Some(*entry_type),
+				name,
+			};
+			if !(ctx.write)(&ent)? {
+				break;
+			}
+			ctx.off += 1;
+		}
+		Ok(())
+	}
+}
+
+/// A file containing an attribute of a block device.
+#[derive(Debug)]
+struct AttrFile {
+	/// The device
+	dev: Arc<BlkDev>,
+	/// The attribute
+	attr: Attr,
+}
+
+impl FileOps for AttrFile {
+	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let dev = &self.dev;
+		let blk_size = dev.blk_size.get();
+		match self.attr {
+			Attr::Dev => format_content!(off, buf, "{}:{}\n", dev.id.major, dev.id.minor),
+			Attr::Partition => {
+				let part = dev.ops.partition().ok_or_else(|| errno!(EINVAL))?;
+				format_content!(off, buf, "{}\n", part.number)
+			}
+			Attr::Ro => format_content!(off, buf, "{}\n", dev.ops.is_read_only() as u8),
+			Attr::Size => {
+				format_content!(off, buf, "{}\n", dev.blk_count * blk_size / SECTOR_SIZE)
+			}
+			Attr::Start => {
+				let part = dev.ops.partition().ok_or_else(|| errno!(EINVAL))?;
+				format_content!(off, buf, "{}\n", part.offset * blk_size / SECTOR_SIZE)
+			}
+		}
+	}
+}
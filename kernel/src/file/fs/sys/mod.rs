@@ -0,0 +1,163 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `sysfs` is a virtual filesystem which exposes kernel objects to userspace.
+//!
+//! Storage devices are exposed under the `block` directory, and kernel modules under the `module`
+//! directory. The `kernel/debug` directory is the mountpoint of the [debugfs](super::debug).
+
+mod block;
+mod module;
+
+use super::{DummyOps, Filesystem, FilesystemOps, FilesystemType};
+use crate::{
+	device::BlkDev,
+	file::{
+		Stat,
+		fs::{
+			Statfs,
+			kernfs::{EitherOps, StaticDir, StaticEntry, box_node, static_dir_stat},
+			options::MountOptions,
+		},
+		vfs::node::Node,
+	},
+};
+use block::BlockDir;
+use module::ModuleDir;
+use utils::{
+	boxed::Box,
+	collections::path::PathBuf,
+	errno,
+	errno::EResult,
+	limits::{NAME_MAX, PAGE_SIZE},
+	ptr::arc::Arc,
+};
+
+/// The magic number of the sysfs.
+const SYSFS_MAGIC: u32 = 0x62656572;
+
+/// The root directory of the sysfs.
+const ROOT: StaticDir = StaticDir {
+	entries: &[
+		StaticEntry {
+			name: b"block",
+			stat: |_| static_dir_stat(),
+			init: EitherOps::Node(|_| box_node(BlockDir)),
+		},
+		StaticEntry {
+			name: b"kernel",
+			stat: |_| static_dir_stat(),
+			init: EitherOps::Node(|_| {
+				box_node(StaticDir {
+					entries: &[StaticEntry {
+						name: b"debug",
+						stat: |_| static_dir_stat(),
+						init: EitherOps::Node(|_| {
+							box_node(StaticDir {
+								entries: &[],
+								data: (),
+							})
+						}),
+					}],
+					data: (),
+				})
+			}),
+		},
+		StaticEntry {
+			name: b"module",
+			stat: |_| static_dir_stat(),
+			init: EitherOps::Node(|_| box_node(ModuleDir)),
+		},
+	],
+	data: (),
+};
+
+/// A sysfs.
+#[derive(Debug)]
+pub struct SysFS;
+
+impl FilesystemOps for SysFS {
+	fn get_name(&self) -> &[u8] {
+		b"sysfs"
+	}
+
+	fn cache_entries(&self) -> bool {
+		false
+	}
+
+	fn get_stat(&self) -> EResult<Statfs> {
+		Ok(Statfs {
+			f_type: SYSFS_MAGIC,
+			f_bsize: PAGE_SIZE as _,
+			f_blocks: 0,
+			f_bfree: 0,
+			f_bavail: 0,
+			f_files: 0,
+			f_ffree: 0,
+			f_fsid: Default::default(),
+			f_namelen: NAME_MAX as _,
+			f_frsize: 0,
+			f_flags: 0,
+		})
+	}
+
+	fn root(&self, fs: &Arc<Filesystem>) -> EResult<Arc<Node>> {
+		Ok(Arc::new(Node::new(
+			0,
+			fs.clone(),
+			static_dir_stat(),
+			Box::new(ROOT)?,
+			Box::new(DummyOps)?,
+		))?)
+	}
+
+	fn create_node(&self, _fs: &Arc<Filesystem>, _stat: Stat) -> EResult<Arc<Node>> {
+		Err(errno!(EINVAL))
+	}
+
+	fn destroy_node(&self, _node: &Node) -> EResult<()> {
+		Ok(())
+	}
+}
+
+/// The sysfs filesystem type.
+pub struct SysFsType;
+
+impl FilesystemType for SysFsType {
+	fn get_name(&self) -> &'static [u8] {
+		b"sysfs"
+	}
+
+	fn detect(&self, _dev: &Arc<BlkDev>) -> EResult<bool> {
+		Ok(false)
+	}
+
+	fn load_filesystem(
+		&self,
+		_dev: Option<Arc<BlkDev>>,
+		_mountpath: PathBuf,
+		_readonly: bool,
+		data: &[u8],
+	) -> EResult<Arc<Filesystem>> {
+		// The sysfs takes no option
+		if MountOptions::new(data).next().is_some() {
+			return Err(errno!(EINVAL));
+		}
+		Ok(Filesystem::new(0, Box::new(SysFS)?)?)
+	}
+}
@@ -0,0 +1,155 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `module` directory contains a directory for each loaded kernel module.
+//!
+//! The `parameters` directory of a kernel module contains a file for each of its parameters,
+//! holding its current value.
+
+use crate::{
+	file::{
+		DirContext, File, FileType, Stat,
+		fs::{
+			DummyOps, FileOps, NodeOps,
+			kernfs::{
+				EitherOps, StaticDir, StaticEntry, box_file, box_node, iter_names, static_dir_stat,
+			},
+		},
+		vfs,
+		vfs::node::Node,
+	},
+	format_content,
+	memory::user::UserSlice,
+	module,
+};
+use utils::{
+	boxed::Box,
+	collections::{string::String, vec::Vec},
+	errno,
+	errno::EResult,
+	format,
+	ptr::arc::Arc,
+};
+
+/// The `module` directory.
+#[derive(Debug)]
+pub struct ModuleDir;
+
+impl NodeOps for ModuleDir {
+	fn lookup_entry(&self, dir: &Node, ent: &mut vfs::Entry) -> EResult<()> {
+		let exists = module::with(&ent.name, |_| Ok(())).is_ok();
+		ent.node = exists
+			.then(|| {
+				let name = Arc::new(String::try_from(&*ent.name)?)?;
+				Arc::new(Node::new(
+					0,
+					dir.fs.clone(),
+					static_dir_stat(),
+					box_node(StaticDir {
+						entries: &[StaticEntry {
+							name: b"parameters",
+							stat: |_| static_dir_stat(),
+							init: EitherOps::Node(|name| box_node(ParamsDir(name))),
+						}],
+						data: name,
+					})?,
+					Box::new(DummyOps)?,
+				))
+			})
+			.transpose()?;
+		Ok(())
+	}
+
+	fn iter_entries(&self, _dir: &Node, ctx: &mut DirContext) -> EResult<()> {
+		let mut names = Vec::new();
+		module::for_each(|m, _| {
+			names.push(String::try_from(m.get_name())?)?;
+			Ok(())
+		})?;
+		iter_names(&names, FileType::Directory, ctx)
+	}
+}
+
+/// The `parameters` directory of a kernel module.
+///
+/// The inner value is the name of the module.
+#[derive(Debug)]
+struct ParamsDir(Arc<String>);
+
+impl NodeOps for ParamsDir {
+	fn lookup_entry(&self, dir: &Node, ent: &mut vfs::Entry) -> EResult<()> {
+		let exists = module::with(&self.0, |m| {
+			Ok(m.get_params()
+				.iter()
+				.any(|p| p.name.as_bytes() == &*ent.name))
+		})?;
+		ent.node = exists
+			.then(|| {
+				Arc::new(Node::new(
+					0,
+					dir.fs.clone(),
+					Stat {
+						mode: FileType::Regular.to_mode() | 0o444,
+						..Default::default()
+					},
+					Box::new(DummyOps)?,
+					box_file(ParamFile {
+						module: self.0.clone(),
+						name: String::try_from(&*ent.name)?,
+					})?,
+				))
+			})
+			.transpose()?;
+		Ok(())
+	}
+
+	fn iter_entries(&self, _dir: &Node, ctx: &mut DirContext) -> EResult<()> {
+		let names = module::with(&self.0, |m| {
+			let mut names = Vec::new();
+			for p in m.get_params() {
+				names.push(String::try_from(p.name)?)?;
+			}
+			Ok(names)
+		})?;
+		iter_names(&names, FileType::Regular, ctx)
+	}
+}
+
+/// A file containing the current value of a kernel module parameter.
+#[derive(Debug)]
+struct ParamFile {
+	/// The name of the module.
+	module: Arc<String>,
+	/// The name of the parameter.
+	name: String,
+}
+
+impl FileOps for ParamFile {
+	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		// Format the value first, so that no lock is held while copying to userspace
+		let content = module::with(&self.module, |m| {
+			let param = m
+				.get_params()
+				.iter()
+				.find(|p| p.name.as_bytes() == self.name.as_bytes())
+				.ok_or_else(|| errno!(ENOENT))?;
+			Ok(format!("{param}\n")?)
+		})?;
+		format_content!(off, buf, "{content}")
+	}
+}
@@ -21,24 +21,28 @@
 //! The files are stored on the kernel's memory and thus are removed when the
 //! filesystem is unmounted.
 
-// TODO count memory usage to enforce quota
-
 use crate::{
 	device::BlkDev,
 	file::{
-		DirContext, DirEntry, File, FileType, Stat,
+		DirContext, DirEntry, File, FileType, Mode, Stat,
 		fs::{
 			FileOps, Filesystem, FilesystemOps, FilesystemType, NodeOps, Statfs, downcast_fs,
 			generic_file_read, generic_file_write, kernfs, kernfs::NodeStorage,
+			options::MountOptions,
 		},
 		perm::{ROOT_GID, ROOT_UID},
 		vfs,
 		vfs::node::Node,
 	},
-	memory::{cache::RcPage, user::UserSlice},
+	memory::{cache::RcPage, stats::MEM_INFO, user::UserSlice},
 	sync::{mutex::Mutex, spin::Spin},
 };
-use core::{any::Any, hint::unlikely};
+use core::{
+	any::Any,
+	hint::unlikely,
+	str,
+	sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed},
+};
 use utils::{
 	TryClone, TryToOwned,
 	boxed::Box,
@@ -49,6 +53,9 @@ use utils::{
 	ptr::{arc::Arc, cow::Cow},
 };
 
+/// The magic number of the tmpfs.
+const TMPFS_MAGIC: u32 = 0x01021994;
+
 #[derive(Debug)]
 struct TmpfsDirEntry {
 	name: Cow<'static, [u8]>,
@@ -177,7 +184,7 @@ impl NodeOps for NodeContent {
 
 	fn link(&self, parent: Arc<Node>, ent: &vfs::Entry) -> EResult<()> {
 		let fs = downcast_fs::<TmpFS>(&*parent.fs.ops);
-		if unlikely(fs.readonly) {
+		if unlikely(fs.readonly.load(Relaxed)) {
 			return Err(errno!(EROFS));
 		}
 		// Check if an entry already exists
@@ -215,7 +222,7 @@ impl NodeOps for NodeContent {
 
 	fn unlink(&self, parent: &Node, ent: &vfs::Entry) -> EResult<()> {
 		let fs = downcast_fs::<TmpFS>(&*parent.fs.ops);
-		if unlikely(fs.readonly) {
+		if unlikely(fs.readonly.load(Relaxed)) {
 			return Err(errno!(EROFS));
 		}
 		// Find entry
@@ -332,7 +339,7 @@ impl FileOps for TmpFSFile {
 	fn write(&self, file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
 		let node = file.node();
 		let fs = downcast_fs::<TmpFS>(&*node.fs.ops);
-		if unlikely(fs.readonly) {
+		if unlikely(fs.readonly.load(Relaxed)) {
 			return Err(errno!(EROFS));
 		}
 		generic_file_write(file, off, buf)
@@ -340,6 +347,7 @@ impl FileOps for TmpFSFile {
 
 	fn truncate(&self, file: &File, size: u64) -> EResult<()> {
 		let node = file.node();
+		let fs = downcast_fs::<TmpFS>(&*node.fs.ops);
 		let pages = NodeContent::from_ops(&*node.node_ops);
 		let NodeContent::Regular(pages) = pages else {
 			return Err(errno!(EINVAL));
@@ -350,13 +358,24 @@ impl FileOps for TmpFSFile {
 		let mut pages = pages.lock();
 		// Allocate or free pages
 		if let Some(count) = new_pages_count.checked_sub(pages.len()) {
-			pages.reserve(count)?;
-			for _ in 0..count {
-				// The offset is not necessary since `writeback` is a no-op
-				let frame = RcPage::new_zeroed()?;
-				pages.push(frame)?;
+			fs.reserve_pages(count)?;
+			let old_len = pages.len();
+			let res = (|| -> AllocResult<()> {
+				pages.reserve(count)?;
+				for _ in 0..count {
+					// The offset is not necessary since `writeback` is a no-op
+					let frame = RcPage::new_zeroed()?;
+					pages.push(frame)?;
+				}
+				Ok(())
+			})();
+			if let Err(e) = res {
+				pages.truncate(old_len);
+				fs.release_pages(count);
+				return Err(e.into());
 			}
 		} else {
+			fs.release_pages(pages.len() - new_pages_count);
 			pages.truncate(new_pages_count);
 			// Zero the last page
 			if let Some(page) = pages.last() {
@@ -368,7 +387,9 @@ impl FileOps for TmpFSFile {
 			node.mapped.truncate(new_pages_count as _);
 		}
 		// Update status
-		node.stat.lock().size = size as _;
+		let mut stat = node.stat.lock();
+		stat.size = size as _;
+		stat.blocks = (new_pages_count * (PAGE_SIZE / 512)) as _;
 		Ok(())
 	}
 }
@@ -379,11 +400,35 @@ impl FileOps for TmpFSFile {
 #[derive(Debug)]
 pub struct TmpFS {
 	/// Tells whether the filesystem is readonly.
-	readonly: bool,
+	readonly: AtomicBool,
+	/// The maximum number of pages used by files content. If `None`, there is no limit.
+	max_pages: Option<usize>,
+	/// The number of pages used by files content.
+	used_pages: AtomicUsize,
 	/// The inner kernfs.
 	nodes: Mutex<NodeStorage, false>,
 }
 
+impl TmpFS {
+	/// Reserves `count` pages for files content.
+	///
+	/// If the filesystem does not have enough space, the function returns [`errno::ENOSPC`].
+	fn reserve_pages(&self, count: usize) -> EResult<()> {
+		let max = self.max_pages.unwrap_or(usize::MAX);
+		self.used_pages
+			.fetch_update(Relaxed, Relaxed, |used| {
+				used.checked_add(count).filter(|used| *used <= max)
+			})
+			.map_err(|_| errno!(ENOSPC))?;
+		Ok(())
+	}
+
+	/// Releases `count` pages previously reserved with [`Self::reserve_pages`].
+	fn release_pages(&self, count: usize) {
+		self.used_pages.fetch_sub(count, Relaxed);
+	}
+}
+
 impl FilesystemOps for TmpFS {
 	fn get_name(&self) -> &[u8] {
 		b"tmpfs"
@@ -394,14 +439,21 @@ impl FilesystemOps for TmpFS {
 	}
 
 	fn get_stat(&self) -> EResult<Statfs> {
+		// Without a size limit, the filesystem can grow up to the total amount of memory
+		let blocks = self
+			.max_pages
+			.unwrap_or_else(|| MEM_INFO.lock().mem_total * 1024 / PAGE_SIZE);
+		let free = blocks.saturating_sub(self.used_pages.load(Relaxed));
+		// The number of nodes is limited only by memory, so each free page counts as a free node
+		let files = self.nodes.lock().count();
 		Ok(Statfs {
-			f_type: 0,
+			f_type: TMPFS_MAGIC,
 			f_bsize: PAGE_SIZE as _,
-			f_blocks: 0,
-			f_bfree: 0,
-			f_bavail: 0,
-			f_files: 0,
-			f_ffree: 0,
+			f_blocks: blocks as _,
+			f_bfree: free as _,
+			f_bavail: free as _,
+			f_files: (files + free) as _,
+			f_ffree: free as _,
 			f_fsid: Default::default(),
 			f_namelen: NAME_MAX as _,
 			f_frsize: 0,
@@ -414,7 +466,7 @@ impl FilesystemOps for TmpFS {
 	}
 
 	fn create_node(&self, fs: &Arc<Filesystem>, stat: Stat) -> EResult<Arc<Node>> {
-		if unlikely(self.readonly) {
+		if unlikely(self.readonly.load(Relaxed)) {
 			return Err(errno!(EROFS));
 		}
 		// Prepare content
@@ -431,7 +483,10 @@ impl FilesystemOps for TmpFS {
 		let node = Arc::new(Node::new(
 			inode,
 			fs.clone(),
-			stat,
+			Stat {
+				btime: Some(stat.ctime),
+				..stat
+			},
 			Box::new(content)?,
 			Box::new(TmpFSFile)?,
 		))?;
@@ -440,12 +495,20 @@ impl FilesystemOps for TmpFS {
 	}
 
 	fn destroy_node(&self, node: &Node) -> EResult<()> {
-		if unlikely(self.readonly) {
+		if unlikely(self.readonly.load(Relaxed)) {
 			return Err(errno!(EROFS));
 		}
+		if let NodeContent::Regular(pages) = NodeContent::from_ops(&*node.node_ops) {
+			self.release_pages(pages.lock().len());
+		}
 		self.nodes.lock().remove_node(node.inode);
 		Ok(())
 	}
+
+	fn remount(&self, readonly: bool) -> EResult<()> {
+		self.readonly.store(readonly, Relaxed);
+		Ok(())
+	}
 }
 
 /// The tmpfs filesystem type.
@@ -465,11 +528,40 @@ impl FilesystemType for TmpFsType {
 		_dev: Option<Arc<BlkDev>>,
 		_mountpath: PathBuf,
 		readonly: bool,
+		data: &[u8],
 	) -> EResult<Arc<Filesystem>> {
+		let mut max_pages = None;
+		let mut mode: Mode = 0o1777;
+		for opt in MountOptions::new(data) {
+			let opt = opt?;
+			match opt.key {
+				b"size" => {
+					let value = opt.value()?;
+					let size = match value.strip_suffix(b"%") {
+						// Percentage of the total amount of memory
+						Some(percent) => {
+							let percent: u64 = str::from_utf8(percent)
+								.ok()
+								.and_then(|p| p.parse().ok())
+								.ok_or_else(|| errno!(EINVAL))?;
+							let total = MEM_INFO.lock().mem_total as u64 * 1024;
+							total.saturating_mul(percent) / 100
+						}
+						None => opt.value_size()?,
+					};
+					// A size of zero means no limit
+					max_pages = (size > 0).then(|| size.div_ceil(PAGE_SIZE as u64) as usize);
+				}
+				b"mode" => mode = opt.value_mode()?,
+				_ => return Err(errno!(EINVAL)),
+			}
+		}
 		let fs = Filesystem::new(
 			0,
 			Box::new(TmpFS {
-				readonly,
+				readonly: AtomicBool::new(readonly),
+				max_pages,
+				used_pages: AtomicUsize::new(0),
 				nodes: Mutex::new(NodeStorage::new()?),
 			})?,
 		)?;
@@ -477,7 +569,7 @@ impl FilesystemType for TmpFsType {
 			0,
 			fs.clone(),
 			Stat {
-				mode: FileType::Directory.to_mode() | 0o1777,
+				mode: FileType::Directory.to_mode() | mode,
 				nlink: 2, // `.` and `..`
 				uid: ROOT_UID,
 				gid: ROOT_GID,
@@ -488,6 +580,9 @@ impl FilesystemType for TmpFsType {
 				ctime: 0,
 				mtime: 0,
 				atime: 0,
+				btime: Some(0),
+
+				attributes: 0,
 			},
 			Box::new(NodeContent::Directory(Default::default()))?,
 			Box::new(TmpFSFile)?,
@@ -17,16 +17,28 @@
  */
 
 //! Advisory file locking
+//!
+//! Two kinds of locks are supported:
+//! - BSD-style locks ([`Flock`]), taken on a whole file by an open file description
+//! - POSIX record locks ([`RecordLocks`]), taken on a range of bytes by a process
 
-use crate::sync::wait_queue::WaitQueue;
+use crate::{
+	process::pid::Pid,
+	sync::{spin::Spin, wait_queue::WaitQueue},
+};
 use core::{
+	cmp::{max, min},
 	hint::unlikely,
 	sync::atomic::{
 		AtomicUsize,
 		Ordering::{Acquire, Relaxed, Release},
 	},
 };
-use utils::{errno, errno::EResult};
+use utils::{
+	collections::{btreemap::BTreeMap, vec::Vec},
+	errno,
+	errno::{AllocResult, EResult},
+};
 
 const EXCLUSIVE_LOCKED: usize = !0;
 
@@ -114,3 +126,272 @@ impl Flock {
 		self.wait_queue.wake_all();
 	}
 }
+
+/// The maximum length of a chain of processes waiting on each other's record locks checked for
+/// deadlocks.
+const MAX_DEADLOCK_DEPTH: usize = 10;
+
+/// Processes waiting for a record lock, associated with the owner of the lock they wait for.
+static BLOCKED: Spin<BTreeMap<Pid, Pid>> = Spin::new(BTreeMap::new());
+
+/// The type of a record lock.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RecordLockType {
+	/// Shared lock, for reading
+	Read,
+	/// Exclusive lock, for writing
+	Write,
+}
+
+/// A POSIX record lock on a range of bytes of a file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RecordLock {
+	/// The process owning the lock.
+	pub owner: Pid,
+	/// The offset of the first locked byte.
+	pub start: u64,
+	/// The offset of the last locked byte. If [`u64::MAX`], the lock extends to the end of the
+	/// file, however large it grows.
+	pub end: u64,
+	/// The type of lock.
+	pub ty: RecordLockType,
+}
+
+impl RecordLock {
+	/// Tells whether the lock overlaps the range `start..=end`.
+	fn overlaps(&self, start: u64, end: u64) -> bool {
+		self.start <= end && start <= self.end
+	}
+
+	/// Tells whether the lock prevents `other` from being taken.
+	fn conflicts(&self, other: &Self) -> bool {
+		self.owner != other.owner
+			&& self.overlaps(other.start, other.end)
+			&& (self.ty == RecordLockType::Write || other.ty == RecordLockType::Write)
+	}
+}
+
+/// Tells whether `owner` waiting for a lock held by `holder` would cause a deadlock.
+fn would_deadlock(blocked: &BTreeMap<Pid, Pid>, owner: Pid, mut holder: Pid) -> bool {
+	for _ in 0..MAX_DEADLOCK_DEPTH {
+		if holder == owner {
+			return true;
+		}
+		let Some(next) = blocked.get(&holder) else {
+			return false;
+		};
+		holder = *next;
+	}
+	false
+}
+
+/// Sets the range `start..=end` of `owner` to the lock type `ty` in `locks`, or unlocks it if
+/// `ty` is `None`.
+///
+/// Locks of `owner` overlapping the range are split, and locks of the same type touching it are
+/// merged with it.
+fn apply(
+	locks: &mut Vec<RecordLock>,
+	owner: Pid,
+	mut start: u64,
+	mut end: u64,
+	ty: Option<RecordLockType>,
+) -> AllocResult<()> {
+	// Merge locks of the same type. Such locks of an owner never touch each other, so a single
+	// pass is enough
+	for l in locks.iter() {
+		let touches = l.start <= end.saturating_add(1) && start <= l.end.saturating_add(1);
+		if l.owner == owner && Some(l.ty) == ty && touches {
+			start = min(start, l.start);
+			end = max(end, l.end);
+		}
+	}
+	// Build the new list, so that it is left untouched on allocation failure
+	let mut new = Vec::with_capacity(locks.len() + 2)?;
+	for l in locks.iter() {
+		if l.owner != owner || !l.overlaps(start, end) {
+			new.push(*l)?;
+			continue;
+		}
+		if l.start < start {
+			new.push(RecordLock {
+				end: start - 1,
+				..*l
+			})?;
+		}
+		if l.end > end {
+			new.push(RecordLock {
+				start: end + 1,
+				..*l
+			})?;
+		}
+	}
+	if let Some(ty) = ty {
+		new.push(RecordLock {
+			owner,
+			start,
+			end,
+			ty,
+		})?;
+	}
+	*locks = new;
+	Ok(())
+}
+
+/// POSIX record locks, attached to an inode.
+#[derive(Debug, Default)]
+pub struct RecordLocks {
+	/// The locks currently held.
+	locks: Spin<Vec<RecordLock>>,
+	/// Processes waiting on a lock.
+	wait_queue: WaitQueue,
+}
+
+impl RecordLocks {
+	/// Tells whether no lock is held.
+	pub fn is_empty(&self) -> bool {
+		self.locks.lock().is_empty()
+	}
+
+	/// Returns a lock preventing `lock` from being taken, if any.
+	pub fn get_conflict(&self, lock: &RecordLock) -> Option<RecordLock> {
+		self.locks
+			.lock()
+			.iter()
+			.find(|l| l.conflicts(lock))
+			.copied()
+	}
+
+	/// Takes `lock`, replacing the locks its owner holds on the same range.
+	///
+	/// If the lock conflicts with a lock held by another process:
+	/// - if `wait` is `false`, the function returns [`errno::EAGAIN`]
+	/// - else, the function waits until the lock can be taken. If waiting would cause a deadlock,
+	///   the function returns [`errno::EDEADLK`]
+	pub fn lock(&self, lock: RecordLock, wait: bool) -> EResult<()> {
+		let try_lock = || -> Result<EResult<()>, Pid> {
+			let mut locks = self.locks.lock();
+			if let Some(l) = locks.iter().find(|l| l.conflicts(&lock)) {
+				return Err(l.owner);
+			}
+			let res = apply(&mut locks, lock.owner, lock.start, lock.end, Some(lock.ty));
+			drop(locks);
+			// Waiters may be able to take a lock the owner released by changing its type
+			self.wait_queue.wake_all();
+			Ok(res.map_err(Into::into))
+		};
+		if !wait {
+			return try_lock().unwrap_or_else(|_| Err(errno!(EAGAIN)));
+		}
+		let res = self.wait_queue.wait_until(|| {
+			let holder = match try_lock() {
+				Ok(res) => return Some(res),
+				Err(holder) => holder,
+			};
+			let mut blocked = BLOCKED.lock();
+			if would_deadlock(&blocked, lock.owner, holder) {
+				return Some(Err(errno!(EDEADLK)));
+			}
+			if let Err(e) = blocked.insert(lock.owner, holder) {
+				return Some(Err(e.into()));
+			}
+			None
+		});
+		BLOCKED.lock().remove(&lock.owner);
+		res?
+	}
+
+	/// Releases the locks of `owner` on the range `start..=end`.
+	pub fn unlock(&self, owner: Pid, start: u64, end: u64) -> AllocResult<()> {
+		apply(&mut self.locks.lock(), owner, start, end, None)?;
+		self.wait_queue.wake_all();
+		Ok(())
+	}
+
+	/// Releases all the locks of `owner`.
+	pub fn release_all(&self, owner: Pid) {
+		let mut locks = self.locks.lock();
+		let len = locks.len();
+		locks.retain(|l| l.owner != owner);
+		let released = locks.len() != len;
+		drop(locks);
+		if released {
+			self.wait_queue.wake_all();
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn lock(owner: Pid, start: u64, end: u64, ty: RecordLockType) -> RecordLock {
+		RecordLock {
+			owner,
+			start,
+			end,
+			ty,
+		}
+	}
+
+	#[test_case]
+	fn record_lock_apply() {
+		use RecordLockType::{Read, Write};
+		let mut locks = Vec::new();
+		apply(&mut locks, 1, 0, 99, Some(Read)).unwrap();
+		// Split
+		apply(&mut locks, 1, 10, 19, Some(Write)).unwrap();
+		assert_eq!(
+			locks.as_slice(),
+			&[
+				lock(1, 0, 9, Read),
+				lock(1, 20, 99, Read),
+				lock(1, 10, 19, Write)
+			]
+		);
+		// Merge
+		apply(&mut locks, 1, 10, 19, Some(Read)).unwrap();
+		assert_eq!(locks.as_slice(), &[lock(1, 0, 99, Read)]);
+		// Locks of other owners are untouched
+		apply(&mut locks, 2, 50, u64::MAX, Some(Read)).unwrap();
+		apply(&mut locks, 1, 0, u64::MAX, None).unwrap();
+		assert_eq!(locks.as_slice(), &[lock(2, 50, u64::MAX, Read)]);
+	}
+
+	#[test_case]
+	fn record_lock_conflict() {
+		use RecordLockType::{Read, Write};
+		let locks = RecordLocks::default();
+		locks.lock(lock(1, 0, 9, Read), false).unwrap();
+		locks.lock(lock(2, 5, 14, Read), false).unwrap();
+		assert_eq!(
+			locks
+				.lock(lock(3, 9, 9, Write), false)
+				.unwrap_err()
+				.as_int(),
+			errno::EAGAIN
+		);
+		assert_eq!(
+			locks.get_conflict(&lock(3, 10, 20, Write)),
+			Some(lock(2, 5, 14, Read))
+		);
+		assert_eq!(locks.get_conflict(&lock(3, 0, 20, Read)), None);
+		// The owner's own locks never conflict
+		locks.lock(lock(1, 0, 4, Write), false).unwrap();
+		locks.release_all(2);
+		locks.lock(lock(3, 10, 20, Write), false).unwrap();
+		locks.unlock(1, 0, u64::MAX).unwrap();
+		locks.release_all(3);
+		assert!(locks.is_empty());
+	}
+
+	#[test_case]
+	fn record_lock_deadlock() {
+		let mut blocked = BTreeMap::new();
+		blocked.insert(2, 3).unwrap();
+		blocked.insert(3, 1).unwrap();
+		assert!(would_deadlock(&blocked, 1, 2));
+		assert!(!would_deadlock(&blocked, 4, 2));
+		assert!(would_deadlock(&blocked, 1, 1));
+	}
+}
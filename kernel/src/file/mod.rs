@@ -24,22 +24,30 @@
 //! The root filesystem is passed to the kernel as an argument on boot.
 //! Other filesystems are mounted into subdirectories.
 
+pub mod aio;
+pub mod fanotify;
 pub mod fd;
+pub mod fiemap;
 pub mod fs;
 pub mod lock;
 pub mod perm;
 pub mod pipe;
+pub mod quota;
+pub mod readahead;
+pub mod relay;
 pub mod socket;
 pub mod util;
 pub mod vfs;
 
 use crate::{
-	device::{BLK_DEVICES, BlkDev, BlkDevFileOps, CHAR_DEVICES, DeviceID, DeviceType},
+	device,
+	device::{BLK_DEVICES, BlkDev, BlkDevFileOps, DeviceID, DeviceType},
 	file::{
-		fs::FileOps,
+		fs::{FileOps, WriteGuard},
 		lock::FlockMode,
 		perm::{Gid, Uid},
 		pipe::PipeBuffer,
+		readahead::ReadaheadState,
 		socket::Socket,
 		vfs::node::Node,
 	},
@@ -47,19 +55,34 @@ use crate::{
 	net::{SocketDesc, SocketDomain, SocketType},
 	println,
 	sync::{atomic::AtomicU64, mutex::Mutex, once::OnceInit, spin::Spin},
+	sysctl,
+	sysctl::{IntTunable, Tunable},
 	time::{
-		clock::{Clock, current_time_sec},
+		clock::{Clock, current_time_ns},
 		unit::Timestamp,
 	},
 };
-use core::{any::Any, fmt::Debug, ops::Deref, ptr::NonNull, sync::atomic::Ordering::Acquire};
+use core::{
+	any::Any,
+	fmt::Debug,
+	ops::Deref,
+	ptr::NonNull,
+	sync::atomic::{
+		AtomicUsize,
+		Ordering::{Acquire, Relaxed},
+	},
+};
 use utils::{
 	collections::{string::String, vec::Vec},
 	errno,
-	errno::EResult,
+	errno::{AllocResult, EResult},
 	ptr::arc::Arc,
+	try_writeln,
+};
+use vfs::{
+	mountpoint,
+	mountpoint::{MountSource, WriteAccess},
 };
-use vfs::{mountpoint, mountpoint::MountSource};
 
 /// A filesystem node ID.
 ///
@@ -105,6 +128,17 @@ pub const DT_SOCK: u8 = 12;
 /// Directory entry type: Unknown
 pub const DT_UNKNOWN: u8 = 0;
 
+/// `lseek` whence: Sets the offset from the given value.
+pub const SEEK_SET: u32 = 0;
+/// `lseek` whence: Sets the offset relative to the current offset.
+pub const SEEK_CUR: u32 = 1;
+/// `lseek` whence: Sets the offset relative to the end of the file.
+pub const SEEK_END: u32 = 2;
+/// `lseek` whence: Sets the offset to the next location containing data.
+pub const SEEK_DATA: u32 = 3;
+/// `lseek` whence: Sets the offset to the next hole.
+pub const SEEK_HOLE: u32 = 4;
+
 /// Read only.
 pub const O_RDONLY: i32 = 0b00000000000000000000000000000000;
 /// Write only.
@@ -141,6 +175,17 @@ pub const O_NONBLOCK: i32 = 0b00000000000000000000100000000000;
 pub const O_SYNC: i32 = 0b00000000000100000001000000000000;
 /// If the file already exists, truncate it to length zero.
 pub const O_TRUNC: i32 = 0b00000000000000000000001000000000;
+/// Internal flag: accesses through the open file description do not generate fanotify events.
+pub const FMODE_NONOTIFY: i32 = 0b00000100000000000000000000000000;
+
+/// File attribute: The file is compressed by the filesystem.
+pub const STATX_ATTR_COMPRESSED: u64 = 0x4;
+/// File attribute: The file cannot be modified, removed or linked to.
+pub const STATX_ATTR_IMMUTABLE: u64 = 0x10;
+/// File attribute: The file can only be opened for writing in append mode.
+pub const STATX_ATTR_APPEND: u64 = 0x20;
+/// File attribute: The file is not a candidate for backup.
+pub const STATX_ATTR_NODUMP: u64 = 0x40;
 
 /// Enumeration representing the different file types.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -251,7 +296,7 @@ pub struct Stat {
 
 	/// The size of the file in bytes.
 	pub size: u64,
-	/// The number of blocks occupied by the file.
+	/// The number of 512-byte blocks allocated to the file.
 	pub blocks: u64,
 
 	/// If the file is a device file, this is the major number.
@@ -259,12 +304,17 @@ pub struct Stat {
 	/// If the file is a device file, this is the minor number.
 	pub dev_minor: u32,
 
-	/// Timestamp of the last modification of the metadata.
+	/// Timestamp of the last modification of the metadata, in nanoseconds.
 	pub ctime: Timestamp,
-	/// Timestamp of the last modification of the file's content.
+	/// Timestamp of the last modification of the file's content, in nanoseconds.
 	pub mtime: Timestamp,
-	/// Timestamp of the last access to the file.
+	/// Timestamp of the last access to the file, in nanoseconds.
 	pub atime: Timestamp,
+	/// Timestamp of the creation of the file, in nanoseconds, if known.
+	pub btime: Option<Timestamp>,
+
+	/// The set of `STATX_ATTR_*` attributes of the file.
+	pub attributes: u64,
 }
 
 impl Default for Stat {
@@ -286,6 +336,9 @@ impl Default for Stat {
 			ctime: 0,
 			mtime: 0,
 			atime: 0,
+			btime: None,
+
+			attributes: 0,
 		}
 	}
 }
@@ -301,14 +354,14 @@ impl Stat {
 	/// Sets the owner user ID, updating `ctime` with the current timestamp.
 	pub fn set_uid(&mut self, uid: Uid) {
 		self.uid = uid;
-		let timestamp = current_time_sec(Clock::Monotonic);
+		let timestamp = current_time_ns(Clock::Realtime);
 		self.ctime = timestamp;
 	}
 
 	/// Sets the owner group ID, updating `ctime` with the current timestamp.
 	pub fn set_gid(&mut self, gid: Gid) {
 		self.gid = gid;
-		let timestamp = current_time_sec(Clock::Monotonic);
+		let timestamp = current_time_ns(Clock::Realtime);
 		self.ctime = timestamp;
 	}
 }
@@ -338,9 +391,54 @@ impl Deref for FileOpsWrapper {
 	}
 }
 
+/// The maximum number of open file descriptions on the system (`fs.file-max`).
+pub static FILE_MAX: IntTunable = IntTunable::new(65536, 1, usize::MAX);
+/// The number of open file descriptions on the system.
+static FILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// The identifier of the next open file description.
+static NEXT_FILE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Accounts for an open file description in [`FILE_COUNT`] for as long as it is alive.
+#[derive(Debug)]
+struct FileCountGuard;
+
+impl FileCountGuard {
+	/// Accounts for a new open file description.
+	///
+	/// If the maximum number of open file descriptions is reached, the function returns
+	/// [`errno::ENFILE`].
+	fn new() -> EResult<Self> {
+		let max = FILE_MAX.get();
+		FILE_COUNT
+			.fetch_update(Relaxed, Relaxed, |n| (n < max).then_some(n + 1))
+			.map_err(|_| errno!(ENFILE))?;
+		Ok(Self)
+	}
+}
+
+impl Drop for FileCountGuard {
+	fn drop(&mut self) {
+		FILE_COUNT.fetch_sub(1, Relaxed);
+	}
+}
+
+/// The `fs.file-nr` tunable.
+#[derive(Debug)]
+struct FileNr;
+
+impl Tunable for FileNr {
+	fn read(&self, buf: &mut String) -> AllocResult<()> {
+		let count = FILE_COUNT.load(Relaxed);
+		let max = FILE_MAX.get();
+		try_writeln!(buf, "{count}\t0\t{max}")
+	}
+}
+
 /// An open file description.
 #[derive(Debug)]
 pub struct File {
+	/// The unique identifier of the open file description
+	id: usize,
 	/// The VFS entry of the file
 	pub vfs_entry: Arc<vfs::Entry>,
 	/// Handle for file operations
@@ -352,6 +450,13 @@ pub struct File {
 
 	/// `flock` mode currently held by this open file description.
 	pub flock_mode: Mutex<FlockMode, false>,
+	/// Readahead state of the open file description.
+	pub readahead: Spin<ReadaheadState>,
+
+	/// Accounting in the system-wide number of open files
+	_count: FileCountGuard,
+	/// Write access to the mountpoint, held if the file is open for writing
+	_write_access: Option<WriteAccess>,
 }
 
 impl File {
@@ -363,7 +468,28 @@ impl File {
 	///
 	/// If the entry is negative, the function returns [`errno::ENOENT`].
 	pub fn open(vfs_entry: Arc<vfs::Entry>, flags: i32) -> EResult<Arc<Self>> {
+		Self::open_impl(vfs_entry, flags, true)
+	}
+
+	/// Opens a file from a [`vfs::Entry`] for internal use by the kernel.
+	///
+	/// Unlike [`Self::open`], the file does not hold write access to its mountpoint, which can
+	/// then be made read-only while the file is open.
+	pub fn open_internal(vfs_entry: Arc<vfs::Entry>, flags: i32) -> EResult<Arc<Self>> {
+		Self::open_impl(vfs_entry, flags, false)
+	}
+
+	/// Implementation of [`Self::open`] and [`Self::open_internal`].
+	///
+	/// `write_access` tells whether the file holds write access to its mountpoint when open for
+	/// writing.
+	fn open_impl(
+		vfs_entry: Arc<vfs::Entry>,
+		flags: i32,
+		write_access: bool,
+	) -> EResult<Arc<Self>> {
 		let node = vfs_entry.node.as_ref().ok_or_else(|| errno!(ENOENT))?;
+		let count = FileCountGuard::new()?;
 		let stat = node.stat();
 		// Get or create ops
 		let ops = match stat.get_type() {
@@ -379,26 +505,43 @@ impl File {
 					})
 				})?)
 			}
-			Some(FileType::BlockDevice) => FileOpsWrapper::Owned(Arc::new(BlkDevFileOps)?),
+			Some(FileType::BlockDevice) => {
+				device::request_blk(&DeviceID {
+					major: stat.dev_major,
+					minor: stat.dev_minor,
+				})
+				.ok_or_else(|| errno!(ENODEV))?;
+				FileOpsWrapper::Owned(Arc::new(BlkDevFileOps)?)
+			}
 			Some(FileType::CharDevice) => {
-				let devices = CHAR_DEVICES.lock();
-				let dev = devices
-					.get(&DeviceID {
-						major: stat.dev_major,
-						minor: stat.dev_minor,
-					})
-					.ok_or_else(|| errno!(ENODEV))?;
+				let id = device::tty::resolve(DeviceID {
+					major: stat.dev_major,
+					minor: stat.dev_minor,
+				})?;
+				let dev = device::request_char(&id).ok_or_else(|| errno!(ENODEV))?;
 				FileOpsWrapper::Borrowed(NonNull::from(dev.ops.as_ref()))
 			}
 			_ => FileOpsWrapper::Borrowed(NonNull::from(node.file_ops.as_ref())),
 		};
+		// Writing to special files does not modify the filesystem
+		let write = write_access && matches!(flags & 0b11, O_WRONLY | O_RDWR);
+		let write_access = if write && stat.get_type() == Some(FileType::Regular) {
+			mountpoint::get_write_access(&vfs_entry)?
+		} else {
+			None
+		};
 		let file = Self {
+			id: NEXT_FILE_ID.fetch_add(1, Relaxed),
 			vfs_entry,
 			ops,
 			flags: Spin::new(flags),
 			off: Default::default(),
 
 			flock_mode: Default::default(),
+			readahead: Default::default(),
+
+			_count: count,
+			_write_access: write_access,
 		};
 		file.ops.acquire(&file);
 		Ok(Arc::new(file)?)
@@ -407,14 +550,20 @@ impl File {
 	/// Open a floating file (for use with the floatfs)
 	pub fn open_floating(vfs_entry: Arc<vfs::Entry>, flags: i32) -> EResult<Arc<Self>> {
 		let node = vfs_entry.node.as_ref().ok_or_else(|| errno!(ENOENT))?;
+		let count = FileCountGuard::new()?;
 		let ops = FileOpsWrapper::Borrowed(NonNull::from(node.file_ops.as_ref()));
 		let file = Self {
+			id: NEXT_FILE_ID.fetch_add(1, Relaxed),
 			vfs_entry,
 			ops,
 			flags: Spin::new(flags),
 			off: Default::default(),
 
 			flock_mode: Default::default(),
+			readahead: Default::default(),
+
+			_count: count,
+			_write_access: None,
 		};
 		file.ops.acquire(&file);
 		Ok(Arc::new(file)?)
@@ -425,6 +574,17 @@ impl File {
 		self.vfs_entry.node()
 	}
 
+	/// Starts a write to the content of the file, waiting while its filesystem is frozen.
+	///
+	/// Writes to files other than regular files are not blocked, in which case the function
+	/// returns `None`.
+	pub fn start_write(&self) -> EResult<Option<WriteGuard<'_>>> {
+		if self.stat().get_type() != Some(FileType::Regular) {
+			return Ok(None);
+		}
+		self.node().fs.start_write().map(Some)
+	}
+
 	/// Returns the underlying buffer, if any.
 	pub fn get_buffer<B: FileOps>(&self) -> Option<&B> {
 		(self.ops.deref() as &dyn Any).downcast_ref::<B>()
@@ -445,6 +605,15 @@ impl File {
 			.cloned()
 	}
 
+	/// Returns the unique identifier of the open file description.
+	///
+	/// Unlike the address of the structure, it remains the same for the whole life of the open
+	/// file description, allowing file operations to keep a state for each open file.
+	#[inline]
+	pub fn id(&self) -> usize {
+		self.id
+	}
+
 	/// Returns the open file description's flags.
 	pub fn get_flags(&self) -> i32 {
 		*self.flags.lock()
@@ -536,6 +705,12 @@ impl File {
 	/// Closes the file, removing the underlying node if no link remain and this was the last
 	/// use of it.
 	pub fn close(self) -> EResult<()> {
+		let event = if self.can_write() {
+			fanotify::FAN_CLOSE_WRITE
+		} else {
+			fanotify::FAN_CLOSE_NOWRITE
+		};
+		fanotify::notify_file(&self, event);
 		// Release any flock lease held
 		let mode = *self.flock_mode.lock();
 		if mode != FlockMode::None
@@ -552,6 +727,11 @@ impl File {
 ///
 /// `root` is the set of major and minor numbers of the root device. If `None`, a tmpfs is used.
 pub(crate) fn init(root: Option<(u32, u32)>) -> EResult<()> {
+	sysctl::register(b"fs.file-max", sysctl::MODE_RW, &FILE_MAX)?;
+	sysctl::register(b"fs.file-nr", sysctl::MODE_RO, &FileNr)?;
+	sysctl::register(b"fs.default_umask", sysctl::MODE_RW, &perm::DefaultUmask)?;
+	sysctl::register(b"fs.dentry-max", sysctl::MODE_RW, &vfs::DENTRY_MAX)?;
+	sysctl::register(b"fs.dentry-state", sysctl::MODE_RO, &vfs::DentryState)?;
 	fs::register_defaults()?;
 	// Create the root mountpoint
 	let source = match root {
@@ -562,7 +742,7 @@ pub(crate) fn init(root: Option<(u32, u32)>) -> EResult<()> {
 		None => MountSource::NoDev(String::try_from(b"tmpfs")?),
 	};
 	println!("Mount root filesystem from `{source}`");
-	let root = mountpoint::create(source, None, 0, None)?;
+	let root = mountpoint::create(source, None, 0, &[], None)?;
 	// Init the VFS's root entry.
 	unsafe {
 		OnceInit::init(&vfs::ROOT, root);
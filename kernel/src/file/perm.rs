@@ -21,13 +21,15 @@
 //! This module implements management of such permissions.
 
 use super::{FileType, Mode, Stat, vfs};
-use crate::process::Process;
+use crate::{process::Process, sysctl::Tunable};
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
 use utils::{
 	TryClone,
 	collections::{string::String, vec::Vec},
 	errno,
 	errno::{AllocResult, EResult},
 	ptr::arc::Arc,
+	try_writeln,
 };
 
 /// Type representing a user ID.
@@ -40,6 +42,30 @@ pub const ROOT_UID: Uid = 0;
 /// The root group ID.
 pub const ROOT_GID: Gid = 0;
 
+/// The file mode creation mask of processes started by the kernel, such as `init` or usermode
+/// helpers.
+static DEFAULT_UMASK: AtomicU32 = AtomicU32::new(0o022);
+
+/// The `fs.default_umask` tunable, represented in octal.
+#[derive(Debug)]
+pub struct DefaultUmask;
+
+impl Tunable for DefaultUmask {
+	fn read(&self, buf: &mut String) -> AllocResult<()> {
+		try_writeln!(buf, "{:04o}", DEFAULT_UMASK.load(Relaxed))
+	}
+
+	fn write(&self, val: &[u8]) -> EResult<()> {
+		let val = core::str::from_utf8(val)
+			.ok()
+			.and_then(|s| Mode::from_str_radix(s.trim(), 8).ok())
+			.filter(|val| *val <= 0o777)
+			.ok_or_else(|| errno!(EINVAL))?;
+		DEFAULT_UMASK.store(val, Relaxed);
+		Ok(())
+	}
+}
+
 /// User: Read, Write and Execute.
 pub const S_IRWXU: Mode = 0o0700;
 /// User: Read.
@@ -92,6 +118,17 @@ pub struct AccessProfile {
 	pub suid: Uid,
 	/// The saved group ID
 	pub sgid: Gid,
+
+	/// The user ID used for filesystem permission checks
+	pub fsuid: Uid,
+	/// The group ID used for filesystem permission checks
+	pub fsgid: Gid,
+}
+
+impl Default for AccessProfile {
+	fn default() -> Self {
+		Self::root()
+	}
 }
 
 impl AccessProfile {
@@ -106,12 +143,21 @@ impl AccessProfile {
 
 			suid: ROOT_UID,
 			sgid: ROOT_GID,
+
+			fsuid: ROOT_UID,
+			fsgid: ROOT_GID,
 		}
 	}
 
 	/// Returns a copy of the current process's instance.
 	pub fn current() -> Self {
-		Process::current().fs.lock().ap
+		Process::current().cred().ap
+	}
+
+	/// Tells whether a program executed with this profile has been granted privileges through the
+	/// setuid or setgid bits, in which case it runs in secure mode.
+	pub fn is_secure(&self) -> bool {
+		self.uid != self.euid || self.gid != self.egid
 	}
 
 	/// Sets the user ID in the same way the `setgid` system call does.
@@ -173,15 +219,208 @@ impl AccessProfile {
 			Err(errno!(EPERM))
 		}
 	}
+
+	/// Sets the filesystem user ID in the way the `setfsuid` system call does.
+	///
+	/// If the agent is not privileged enough to make the change, the ID is left unchanged.
+	pub fn set_fsuid(&mut self, uid: Uid) {
+		if self.euid == ROOT_UID || [self.uid, self.euid, self.suid, self.fsuid].contains(&uid) {
+			self.fsuid = uid;
+		}
+	}
+
+	/// Sets the filesystem group ID in the way the `setfsgid` system call does.
+	///
+	/// If the agent is not privileged enough to make the change, the ID is left unchanged.
+	pub fn set_fsgid(&mut self, gid: Gid) {
+		if self.euid == ROOT_UID || [self.gid, self.egid, self.sgid, self.fsgid].contains(&gid) {
+			self.fsgid = gid;
+		}
+	}
 }
 
-/// A process's filesystem access information.
-pub struct ProcessFs {
-	/// The process's access profile, containing user and group IDs.
+/// A process's credentials, determining its access to resources.
+///
+/// Once shared, an instance is never modified. Instead, a modified copy replaces it on the process
+/// (see [`Process::update_cred`]), so that a permission check reading a snapshot always sees a
+/// consistent set of IDs, even if another thread changes them concurrently.
+#[derive(Debug, Default)]
+pub struct Credentials {
+	/// The access profile, containing user and group IDs.
 	pub ap: AccessProfile,
 	/// Supplementary group IDs
 	pub groups: Vec<Gid>,
+}
+
+impl Credentials {
+	/// Tells whether the credentials are privileged (root).
+	pub fn is_privileged(&self) -> bool {
+		self.ap.euid == ROOT_UID || self.ap.egid == ROOT_GID
+	}
+
+	/// Tells whether the credentials are privileged (root) for filesystem accesses.
+	pub fn is_fs_privileged(&self) -> bool {
+		self.ap.fsuid == ROOT_UID || self.ap.fsgid == ROOT_GID
+	}
+
+	/// Tells whether the owner and group of the file with the given status match the credentials.
+	///
+	/// `effective` tells whether to use filesystem IDs. If not, real IDs are used.
+	#[inline]
+	fn match_ids(&self, stat: &Stat, effective: bool) -> (bool, bool) {
+		let (uid, gid) = if effective {
+			(self.ap.fsuid, self.ap.fsgid)
+		} else {
+			(self.ap.uid, self.ap.gid)
+		};
+		(
+			stat.uid == uid,
+			stat.gid == gid || self.groups.contains(&stat.gid),
+		)
+	}
+
+	/// Tells whether a file with the given status can be read.
+	///
+	/// `effective` tells whether to use filesystem IDs. If not, real IDs are used.
+	pub fn can_read_file(&self, stat: &Stat, effective: bool) -> bool {
+		if self.is_fs_privileged() {
+			return true;
+		}
+		let (uid, gid) = self.match_ids(stat, effective);
+		if stat.mode & S_IRUSR != 0 && uid {
+			return true;
+		}
+		if stat.mode & S_IRGRP != 0 && gid {
+			return true;
+		}
+		stat.mode & S_IROTH != 0
+	}
+
+	/// Tells whether files of a directory with the given status can be listed, **not** including
+	/// access to files' contents and metadata.
+	#[inline]
+	pub fn can_list_directory(&self, stat: &Stat) -> bool {
+		self.can_read_file(stat, true)
+	}
+
+	/// Tells whether a file with the given status can be written.
+	///
+	/// `effective` tells whether to use filesystem IDs. If not, real IDs are used.
+	pub fn can_write_file(&self, stat: &Stat, effective: bool) -> bool {
+		if self.is_fs_privileged() {
+			return true;
+		}
+		let (uid, gid) = self.match_ids(stat, effective);
+		if stat.mode & S_IWUSR != 0 && uid {
+			return true;
+		}
+		if stat.mode & S_IWGRP != 0 && gid {
+			return true;
+		}
+		stat.mode & S_IWOTH != 0
+	}
+
+	/// Tells whether entries in a directory with the given status can be modified, including
+	/// creating files, deleting files, and renaming files.
+	#[inline]
+	pub fn can_write_directory(&self, stat: &Stat) -> bool {
+		self.can_write_file(stat, true) && self.can_execute_file(stat, true)
+	}
+
+	/// Tells whether a file with the given status can be executed.
+	///
+	/// `effective` tells whether to use filesystem IDs. If not, real IDs are used.
+	pub fn can_execute_file(&self, stat: &Stat, effective: bool) -> bool {
+		// If root, bypass checks (unless the file is a regular file)
+		if stat.get_type() != Some(FileType::Regular) && self.is_fs_privileged() {
+			return true;
+		}
+		let (uid, gid) = self.match_ids(stat, effective);
+		if stat.mode & S_IXUSR != 0 && uid {
+			return true;
+		}
+		if stat.mode & S_IXGRP != 0 && gid {
+			return true;
+		}
+		stat.mode & S_IXOTH != 0
+	}
+
+	/// Tells whether files of a directory with the given status can be accessed, *if the name of
+	/// the file is known*.
+	#[inline]
+	pub fn can_search_directory(&self, stat: &Stat) -> bool {
+		self.can_execute_file(stat, true)
+	}
+
+	/// Tells whether permissions can be set for a file with the given status.
+	pub fn can_set_file_permissions(&self, stat: &Stat) -> bool {
+		self.ap.fsuid == ROOT_UID || self.ap.fsuid == stat.uid
+	}
+
+	/// Tells whether the owner of a file with the given status can be set to `uid` and its group
+	/// to `gid`.
+	///
+	/// A value of `None` leaves the corresponding ID unchanged.
+	///
+	/// An unprivileged process can only change the group of a file it owns, to one of its groups.
+	pub fn can_chown(&self, stat: &Stat, uid: Option<Uid>, gid: Option<Gid>) -> bool {
+		if self.is_fs_privileged() {
+			return true;
+		}
+		if self.ap.fsuid != stat.uid {
+			return false;
+		}
+		let uid_ok = uid.is_none_or(|uid| uid == stat.uid);
+		let gid_ok = gid.is_none_or(|gid| gid == self.ap.fsgid || self.groups.contains(&gid));
+		uid_ok && gid_ok
+	}
+
+	/// Tells whether a signal can be sent to a process with the credentials `other`.
+	pub fn can_kill(&self, other: &Self) -> bool {
+		if self.is_privileged() {
+			return true;
+		}
+		// if sender's `uid` or `euid` equals receiver's `uid` or `suid`
+		self.ap.uid == other.ap.uid
+			|| self.ap.uid == other.ap.suid
+			|| self.ap.euid == other.ap.uid
+			|| self.ap.euid == other.ap.suid
+	}
 
+	/// Tells whether the private information (memory, environment, open files, ...) of a
+	/// process with the credentials `other` can be inspected.
+	///
+	/// This is the case if all the user and group IDs of `other` match the effective IDs of
+	/// `self`.
+	pub fn can_inspect(&self, other: &Self) -> bool {
+		if self.is_privileged() {
+			return true;
+		}
+		let ap = &other.ap;
+		[ap.uid, ap.euid, ap.suid]
+			.into_iter()
+			.all(|uid| uid == self.ap.euid)
+			&& [ap.gid, ap.egid, ap.sgid]
+				.into_iter()
+				.all(|gid| gid == self.ap.egid)
+	}
+}
+
+impl TryClone for Credentials {
+	fn try_clone(&self) -> Result<Self, Self::Error> {
+		Ok(Self {
+			ap: self.ap,
+			groups: self.groups.try_clone()?,
+		})
+	}
+}
+
+/// A process's filesystem access information.
+///
+/// It is shared between the processes created with `CLONE_FS`, which includes the threads of a
+/// process.
+#[derive(Clone)]
+pub struct ProcessFs {
 	/// Current working directory
 	///
 	/// If `None`, using the root directory of the VFS.
@@ -190,15 +429,16 @@ pub struct ProcessFs {
 	///
 	/// If `None`, using the root directory of the VFS.
 	pub chroot: Arc<vfs::Entry>,
+	/// The file mode creation mask.
+	pub umask: Mode,
 }
 
 impl Default for ProcessFs {
 	fn default() -> Self {
 		Self {
-			ap: AccessProfile::root(),
-			groups: Vec::new(),
 			cwd: vfs::ROOT.clone(),
 			chroot: vfs::ROOT.clone(),
+			umask: DEFAULT_UMASK.load(Relaxed),
 		}
 	}
 }
@@ -208,62 +448,32 @@ impl ProcessFs {
 	pub fn dummy() -> AllocResult<Self> {
 		let root = Arc::new(vfs::Entry::new(String::new(), None, None))?;
 		Ok(Self {
-			ap: AccessProfile::root(),
-			groups: Vec::new(),
 			cwd: root.clone(),
 			chroot: root,
+			umask: DEFAULT_UMASK.load(Relaxed),
 		})
 	}
 }
 
-impl TryClone for ProcessFs {
-	fn try_clone(&self) -> Result<Self, Self::Error> {
-		Ok(Self {
-			ap: self.ap,
-			groups: self.groups.try_clone()?,
-
-			cwd: self.cwd.clone(),
-			chroot: self.chroot.clone(),
-		})
-	}
+/// Returns the permissions of a file to be created by the current process in the directory with
+/// status `parent`.
+///
+/// `mode` is the mode requested by the process, from which its file mode creation mask is cleared.
+pub fn creation_mode(_parent: &Stat, mode: Mode) -> Mode {
+	// TODO: once ACLs are supported, if `parent` has a default ACL, use it instead of the umask
+	mode & !Process::current().umask()
 }
 
 /// Tells whether the current process is privileged (root).
 pub fn is_privileged() -> bool {
-	let ap = AccessProfile::current();
-	ap.euid == ROOT_UID || ap.egid == ROOT_GID
-}
-
-#[inline]
-fn match_ids(stat: &Stat, effective: bool) -> (bool, bool) {
-	let proc = Process::current();
-	let fs = proc.fs.lock();
-	let (uid, gid) = if effective {
-		(fs.ap.euid, fs.ap.egid)
-	} else {
-		(fs.ap.uid, fs.ap.gid)
-	};
-	(
-		stat.uid == uid,
-		stat.gid == gid || fs.groups.contains(&stat.gid),
-	)
+	Process::current().cred().is_privileged()
 }
 
 /// Tells whether the current process can read a file with the given status.
 ///
-/// `effective` tells whether to use effective IDs. If not, real IDs are used.
+/// `effective` tells whether to use filesystem IDs. If not, real IDs are used.
 pub fn can_read_file(stat: &Stat, effective: bool) -> bool {
-	if is_privileged() {
-		return true;
-	}
-	let (uid, gid) = match_ids(stat, effective);
-	if stat.mode & S_IRUSR != 0 && uid {
-		return true;
-	}
-	if stat.mode & S_IRGRP != 0 && gid {
-		return true;
-	}
-	stat.mode & S_IROTH != 0
+	Process::current().cred().can_read_file(stat, effective)
 }
 
 /// Tells whether the agent can list files of a directory with the given status, **not**
@@ -275,44 +485,23 @@ pub fn can_list_directory(stat: &Stat) -> bool {
 
 /// Tells whether the agent can write a file with the given status.
 ///
-/// `effective` tells whether to use effective IDs. If not, real IDs are used.
+/// `effective` tells whether to use filesystem IDs. If not, real IDs are used.
 pub fn can_write_file(stat: &Stat, effective: bool) -> bool {
-	if is_privileged() {
-		return true;
-	}
-	let (uid, gid) = match_ids(stat, effective);
-	if stat.mode & S_IWUSR != 0 && uid {
-		return true;
-	}
-	if stat.mode & S_IWGRP != 0 && gid {
-		return true;
-	}
-	stat.mode & S_IWOTH != 0
+	Process::current().cred().can_write_file(stat, effective)
 }
 
 /// Tells whether the agent can modify entries in a directory with the given status, including
 /// creating files, deleting files, and renaming files.
 #[inline]
 pub fn can_write_directory(stat: &Stat) -> bool {
-	can_write_file(stat, true) && can_execute_file(stat, true)
+	Process::current().cred().can_write_directory(stat)
 }
 
 /// Tells whether the agent can execute a file with the given status.
 ///
-/// `effective` tells whether to use effective IDs. If not, real IDs are used.
+/// `effective` tells whether to use filesystem IDs. If not, real IDs are used.
 pub fn can_execute_file(stat: &Stat, effective: bool) -> bool {
-	// If root, bypass checks (unless the file is a regular file)
-	if stat.get_type() != Some(FileType::Regular) && is_privileged() {
-		return true;
-	}
-	let (uid, gid) = match_ids(stat, effective);
-	if stat.mode & S_IXUSR != 0 && uid {
-		return true;
-	}
-	if stat.mode & S_IXGRP != 0 && gid {
-		return true;
-	}
-	stat.mode & S_IXOTH != 0
+	Process::current().cred().can_execute_file(stat, effective)
 }
 
 /// Tells whether the current process can access files of a directory with the given status, *if
@@ -324,20 +513,24 @@ pub fn can_search_directory(stat: &Stat) -> bool {
 
 /// Tells whether the current process can set permissions for a file with the given status.
 pub fn can_set_file_permissions(stat: &Stat) -> bool {
-	let ap = AccessProfile::current();
-	ap.uid == ROOT_UID || ap.uid == stat.uid
+	Process::current().cred().can_set_file_permissions(stat)
+}
+
+/// Tells whether the current process can set the owner of a file with the given status to `uid`
+/// and its group to `gid`.
+pub fn can_chown(stat: &Stat, uid: Option<Uid>, gid: Option<Gid>) -> bool {
+	Process::current().cred().can_chown(stat, uid, gid)
 }
 
 /// Tells whether the current process can kill `proc`.
 pub fn can_kill(proc: &Process) -> bool {
-	if is_privileged() {
-		return true;
-	}
-	let ap = AccessProfile::current();
-	let other_ap = proc.fs.lock().ap;
-	// if sender's `uid` or `euid` equals receiver's `uid` or `suid`
-	ap.uid == other_ap.uid
-		|| ap.uid == other_ap.suid
-		|| ap.euid == other_ap.uid
-		|| ap.euid == other_ap.suid
+	Process::current().cred().can_kill(&proc.cred())
+}
+
+/// Tells whether the current process can inspect the private information of the given process.
+#[inline]
+pub fn can_inspect(proc: &Process) -> bool {
+	let cur = Process::current();
+	// A process can always inspect itself
+	cur.get_pid() == proc.get_pid() || cur.cred().can_inspect(&proc.cred())
 }
@@ -27,7 +27,10 @@ use crate::{
 	},
 	process::{Process, signal::Signal},
 	sync::{spin::Spin, wait_queue::WaitQueue},
-	syscall::{FromSyscallArg, ioctl},
+	syscall::{
+		FromSyscallArg, ioctl,
+		select::{POLLERR, POLLHUP, POLLIN, POLLOUT, POLLRDNORM, POLLWRNORM},
+	},
 };
 use core::{
 	ffi::{c_int, c_void},
@@ -110,8 +113,28 @@ impl FileOps for PipeBuffer {
 		}
 	}
 
-	fn poll(&self, _file: &File, _mask: u32) -> EResult<u32> {
-		todo!()
+	fn poll(&self, file: &File, mask: u32) -> EResult<u32> {
+		let inner = self.inner.lock();
+		let mut events = 0;
+		if file.can_read() {
+			if !inner.buffer.is_empty() {
+				events |= POLLIN | POLLRDNORM;
+			}
+			// All writers are gone
+			if inner.writers == 0 {
+				events |= POLLHUP;
+			}
+		}
+		if file.can_write() {
+			if !inner.buffer.is_full() {
+				events |= POLLOUT | POLLWRNORM;
+			}
+			// All readers are gone
+			if inner.readers == 0 {
+				events |= POLLERR;
+			}
+		}
+		Ok(events & mask)
 	}
 
 	fn ioctl(&self, _file: &File, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
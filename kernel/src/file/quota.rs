@@ -0,0 +1,720 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Disk quotas limit the disk space and the number of inodes each user or group can use on a
+//! filesystem.
+//!
+//! Limits and usage are stored in a quota file on the filesystem, usually `aquota.user` or
+//! `aquota.group` at its root, in the `vfsv0` or `vfsv1` format. They are loaded in memory when
+//! quotas are enabled, and written back to the file when synchronized or disabled.
+//!
+//! # File format
+//!
+//! The file is divided in blocks of [`BLOCK_SIZE`] bytes. The first block holds the header and
+//! the settings of the quota type. The second block is the root of a radix tree of depth
+//! [`TREE_DEPTH`], indexed by the bytes of the user or group ID, from the most significant. Each
+//! tree block is an array of block numbers, zero standing for an absent child. At the last
+//! level, block numbers refer to data blocks, which contain the entries of the IDs.
+
+use crate::{
+	file::{
+		File,
+		perm::{Gid, Uid, is_privileged},
+	},
+	memory::user::UserSlice,
+	sync::spin::Spin,
+	time::clock::{Clock, current_time_sec},
+};
+use core::hint::unlikely;
+use utils::{
+	collections::{hashmap::HashMap, hashset::HashSet, vec::Vec},
+	errno,
+	errno::{CollectResult, EResult},
+	ptr::arc::Arc,
+};
+
+/// Quota format: `vfsv0`, with 32 bits limits.
+pub const QFMT_VFS_V0: u32 = 2;
+/// Quota format: `vfsv1`, with 64 bits limits.
+pub const QFMT_VFS_V1: u32 = 4;
+
+/// The size of the unit in which space limits are expressed, in bytes.
+pub const QUOTA_BLOCK_SIZE: u64 = 1024;
+
+/// The size of a block of a quota file, in bytes.
+const BLOCK_SIZE: usize = 1024;
+/// The depth of the tree of a quota file.
+const TREE_DEPTH: usize = 4;
+/// The block number of the root of the tree.
+const TREE_ROOT: usize = 1;
+/// The size of the header of a data block, in bytes.
+const DATA_HEADER_SIZE: usize = 16;
+/// The magic number of quota files, for each quota type.
+const MAGICS: [u32; 2] = [0xd9c01f11, 0xd9c01927];
+/// The default grace period, in seconds.
+const DEFAULT_GRACE: u64 = 7 * 24 * 3600;
+
+/// Reads a little-endian `u16` at offset `off` in `buf`.
+fn read_u16(buf: &[u8], off: usize) -> u16 {
+	u16::from_le_bytes(buf[off..off + 2].try_into().unwrap())
+}
+
+/// Reads a little-endian `u32` at offset `off` in `buf`.
+fn read_u32(buf: &[u8], off: usize) -> u32 {
+	u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+/// Reads a little-endian `u64` at offset `off` in `buf`.
+fn read_u64(buf: &[u8], off: usize) -> u64 {
+	u64::from_le_bytes(buf[off..off + 8].try_into().unwrap())
+}
+
+/// Writes `val` in little-endian at offset `off` in `buf`.
+fn write_u16(buf: &mut [u8], off: usize, val: u16) {
+	buf[off..off + 2].copy_from_slice(&val.to_le_bytes());
+}
+
+/// Writes `val` in little-endian at offset `off` in `buf`.
+fn write_u32(buf: &mut [u8], off: usize, val: u32) {
+	buf[off..off + 4].copy_from_slice(&val.to_le_bytes());
+}
+
+/// Writes `val` in little-endian at offset `off` in `buf`.
+fn write_u64(buf: &mut [u8], off: usize, val: u64) {
+	buf[off..off + 8].copy_from_slice(&val.to_le_bytes());
+}
+
+/// A type of quota.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum QuotaType {
+	/// Quotas applied to users.
+	User = 0,
+	/// Quotas applied to groups.
+	Group = 1,
+}
+
+impl QuotaType {
+	/// Returns the type with the identifier `id`, as given to `quotactl`.
+	pub fn from_id(id: u32) -> Option<Self> {
+		match id {
+			0 => Some(Self::User),
+			1 => Some(Self::Group),
+			_ => None,
+		}
+	}
+}
+
+/// The limits and usage of a user or group.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Dquot {
+	/// Hard limit of space, in units of [`QUOTA_BLOCK_SIZE`]. `0` means no limit.
+	pub bhardlimit: u64,
+	/// Soft limit of space, in units of [`QUOTA_BLOCK_SIZE`]. `0` means no limit.
+	pub bsoftlimit: u64,
+	/// Used space, in bytes.
+	pub curspace: u64,
+	/// Hard limit of inodes. `0` means no limit.
+	pub ihardlimit: u64,
+	/// Soft limit of inodes. `0` means no limit.
+	pub isoftlimit: u64,
+	/// Number of used inodes.
+	pub curinodes: u64,
+	/// Timestamp at which the soft limit of space becomes enforced, or `0` if it is not exceeded.
+	pub btime: u64,
+	/// Timestamp at which the soft limit of inodes becomes enforced, or `0` if it is not
+	/// exceeded.
+	pub itime: u64,
+}
+
+impl Dquot {
+	/// Updates the grace timestamps according to the current usage.
+	///
+	/// When a soft limit is exceeded, the grace period starts, unless it is already running.
+	fn update_times(&mut self, info: &QuotaInfo, now: u64) {
+		let space = self.curspace.div_ceil(QUOTA_BLOCK_SIZE);
+		for (usage, soft, time, grace) in [
+			(space, self.bsoftlimit, &mut self.btime, info.bgrace),
+			(
+				self.curinodes,
+				self.isoftlimit,
+				&mut self.itime,
+				info.igrace,
+			),
+		] {
+			if soft == 0 || usage <= soft {
+				*time = 0;
+			} else if *time == 0 {
+				*time = now + grace;
+			}
+		}
+	}
+
+	/// Checks the usage against the limits.
+	///
+	/// `space` and `inodes` tell which resources are being allocated. Only those are checked.
+	///
+	/// If a limit is exceeded, the function returns [`errno::EDQUOT`].
+	fn check(&self, now: u64, space: bool, inodes: bool) -> EResult<()> {
+		let exceeded = |usage: u64, hard: u64, soft: u64, time: u64| {
+			(hard != 0 && usage > hard) || (soft != 0 && usage > soft && time != 0 && now >= time)
+		};
+		let space_usage = self.curspace.div_ceil(QUOTA_BLOCK_SIZE);
+		if unlikely(
+			space && exceeded(space_usage, self.bhardlimit, self.bsoftlimit, self.btime)
+				|| inodes
+					&& exceeded(self.curinodes, self.ihardlimit, self.isoftlimit, self.itime),
+		) {
+			return Err(errno!(EDQUOT));
+		}
+		Ok(())
+	}
+}
+
+/// The settings of a quota type.
+#[derive(Clone, Copy, Debug)]
+pub struct QuotaInfo {
+	/// Grace period for space, in seconds.
+	pub bgrace: u64,
+	/// Grace period for inodes, in seconds.
+	pub igrace: u64,
+	/// Format-specific flags.
+	pub flags: u32,
+}
+
+/// A format of quota file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Format {
+	/// `vfsv0`.
+	V0,
+	/// `vfsv1`.
+	V1,
+}
+
+impl Format {
+	/// Returns the format with the identifier `id`.
+	fn from_id(id: u32) -> Option<Self> {
+		match id {
+			QFMT_VFS_V0 => Some(Self::V0),
+			QFMT_VFS_V1 => Some(Self::V1),
+			_ => None,
+		}
+	}
+
+	/// Returns the identifier of the format.
+	fn id(self) -> u32 {
+		match self {
+			Self::V0 => QFMT_VFS_V0,
+			Self::V1 => QFMT_VFS_V1,
+		}
+	}
+
+	/// Returns the version stored in the header of the file.
+	fn version(self) -> u32 {
+		match self {
+			Self::V0 => 0,
+			Self::V1 => 1,
+		}
+	}
+
+	/// Returns the size of an entry, in bytes.
+	fn entry_size(self) -> usize {
+		match self {
+			Self::V0 => 48,
+			Self::V1 => 72,
+		}
+	}
+
+	/// Returns the maximum value of a limit.
+	fn max_limit(self) -> u64 {
+		match self {
+			Self::V0 => u32::MAX as _,
+			Self::V1 => u64::MAX,
+		}
+	}
+
+	/// Decodes the entry `buf`, returning the ID and its quotas.
+	fn decode(self, buf: &[u8]) -> (u32, Dquot) {
+		let id = read_u32(buf, 0);
+		let mut dquot = match self {
+			Self::V0 => Dquot {
+				ihardlimit: read_u32(buf, 4) as _,
+				isoftlimit: read_u32(buf, 8) as _,
+				curinodes: read_u32(buf, 12) as _,
+				bhardlimit: read_u32(buf, 16) as _,
+				bsoftlimit: read_u32(buf, 20) as _,
+				curspace: read_u64(buf, 24),
+				btime: read_u64(buf, 32),
+				itime: read_u64(buf, 40),
+			},
+			Self::V1 => Dquot {
+				ihardlimit: read_u64(buf, 8),
+				isoftlimit: read_u64(buf, 16),
+				curinodes: read_u64(buf, 24),
+				bhardlimit: read_u64(buf, 32),
+				bsoftlimit: read_u64(buf, 40),
+				curspace: read_u64(buf, 48),
+				btime: read_u64(buf, 56),
+				itime: read_u64(buf, 64),
+			},
+		};
+		// An entry with only zeros is stored with `itime` set to `1`, to be told apart from a
+		// free entry
+		if dquot
+			== (Dquot {
+				itime: 1,
+				..Default::default()
+			}) {
+			dquot.itime = 0;
+		}
+		(id, dquot)
+	}
+
+	/// Encodes the entry of `id` with the quotas `dquot` into `buf`.
+	fn encode(self, id: u32, dquot: &Dquot, buf: &mut [u8]) {
+		let mut dquot = *dquot;
+		if dquot == Dquot::default() {
+			dquot.itime = 1;
+		}
+		write_u32(buf, 0, id);
+		match self {
+			Self::V0 => {
+				write_u32(buf, 4, dquot.ihardlimit as _);
+				write_u32(buf, 8, dquot.isoftlimit as _);
+				write_u32(buf, 12, dquot.curinodes as _);
+				write_u32(buf, 16, dquot.bhardlimit as _);
+				write_u32(buf, 20, dquot.bsoftlimit as _);
+				write_u64(buf, 24, dquot.curspace);
+				write_u64(buf, 32, dquot.btime);
+				write_u64(buf, 40, dquot.itime);
+			}
+			Self::V1 => {
+				write_u32(buf, 4, 0);
+				write_u64(buf, 8, dquot.ihardlimit);
+				write_u64(buf, 16, dquot.isoftlimit);
+				write_u64(buf, 24, dquot.curinodes);
+				write_u64(buf, 32, dquot.bhardlimit);
+				write_u64(buf, 40, dquot.bsoftlimit);
+				write_u64(buf, 48, dquot.curspace);
+				write_u64(buf, 56, dquot.btime);
+				write_u64(buf, 64, dquot.itime);
+			}
+		}
+	}
+}
+
+/// Returns the block `blk` of the quota file `data`.
+fn get_block(data: &[u8], blk: usize) -> EResult<&[u8]> {
+	data.get(blk * BLOCK_SIZE..(blk + 1) * BLOCK_SIZE)
+		.ok_or_else(|| errno!(EINVAL))
+}
+
+/// Parses the tree block `blk` of the quota file `data`, at depth `depth`, inserting the entries
+/// found beneath it in `dquots`.
+///
+/// `visited` is the set of blocks already parsed, which are ignored.
+fn parse_tree(
+	data: &[u8],
+	format: Format,
+	blk: usize,
+	depth: usize,
+	visited: &mut HashSet<usize>,
+	dquots: &mut HashMap<u32, Dquot>,
+) -> EResult<()> {
+	let buf = get_block(data, blk)?;
+	for i in 0..BLOCK_SIZE / 4 {
+		let child = read_u32(buf, i * 4) as usize;
+		if child == 0 || visited.contains(&child) {
+			continue;
+		}
+		visited.insert(child)?;
+		if depth + 1 < TREE_DEPTH {
+			parse_tree(data, format, child, depth + 1, visited, dquots)?;
+			continue;
+		}
+		// Data block
+		let buf = get_block(data, child)?;
+		for ent in buf[DATA_HEADER_SIZE..].chunks_exact(format.entry_size()) {
+			if ent.iter().all(|b| *b == 0) {
+				continue;
+			}
+			let (id, dquot) = format.decode(ent);
+			dquots.insert(id, dquot)?;
+		}
+	}
+	Ok(())
+}
+
+/// Parses the content `data` of a quota file of type `ty` and format `format`.
+///
+/// If the file is invalid, the function returns [`errno::EINVAL`].
+fn parse(data: &[u8], ty: QuotaType, format: Format) -> EResult<(QuotaInfo, HashMap<u32, Dquot>)> {
+	let header = get_block(data, 0)?;
+	if unlikely(
+		read_u32(header, 0) != MAGICS[ty as usize] || read_u32(header, 4) != format.version(),
+	) {
+		return Err(errno!(EINVAL));
+	}
+	let info = QuotaInfo {
+		bgrace: read_u32(header, 8) as _,
+		igrace: read_u32(header, 12) as _,
+		flags: read_u32(header, 16),
+	};
+	let mut visited = HashSet::new();
+	visited.insert(TREE_ROOT)?;
+	let mut dquots = HashMap::new();
+	parse_tree(data, format, TREE_ROOT, 0, &mut visited, &mut dquots)?;
+	Ok((info, dquots))
+}
+
+/// Appends a zeroed block to the quota file `data` and returns its number.
+fn alloc_block(data: &mut Vec<u8>) -> EResult<usize> {
+	let blk = data.len() / BLOCK_SIZE;
+	data.resize(data.len() + BLOCK_SIZE, 0)?;
+	Ok(blk)
+}
+
+/// Returns the content of a quota file of type `ty` and format `format`, holding the settings
+/// `info` and the quotas `dquots`.
+///
+/// Entries with no usage and no limit are omitted.
+fn serialize(
+	ty: QuotaType,
+	format: Format,
+	info: &QuotaInfo,
+	dquots: &HashMap<u32, Dquot>,
+) -> EResult<Vec<u8>> {
+	let mut entries = dquots
+		.iter()
+		.filter(|(_, dquot)| **dquot != Dquot::default())
+		.map(|(id, dquot)| (*id, *dquot))
+		.collect::<CollectResult<Vec<_>>>()
+		.0?;
+	entries.sort_unstable_by_key(|(id, _)| *id);
+	let mut data = Vec::new();
+	data.resize((TREE_ROOT + 1) * BLOCK_SIZE, 0)?;
+	// Entries are packed in data blocks by increasing ID
+	let size = format.entry_size();
+	let per_block = (BLOCK_SIZE - DATA_HEADER_SIZE) / size;
+	let mut data_blk = 0;
+	let mut count = per_block;
+	for (id, dquot) in entries {
+		if count == per_block {
+			data_blk = alloc_block(&mut data)?;
+			count = 0;
+		}
+		let off = data_blk * BLOCK_SIZE + DATA_HEADER_SIZE + count * size;
+		format.encode(id, &dquot, &mut data[off..off + size]);
+		count += 1;
+		write_u16(&mut data, data_blk * BLOCK_SIZE + 8, count as _);
+		// Insert in the tree, allocating the missing tree blocks
+		let mut blk = TREE_ROOT;
+		for depth in 0..TREE_DEPTH {
+			let index = (id >> ((TREE_DEPTH - depth - 1) * 8)) as usize & 0xff;
+			let ref_off = blk * BLOCK_SIZE + index * 4;
+			if depth + 1 == TREE_DEPTH {
+				write_u32(&mut data, ref_off, data_blk as _);
+				break;
+			}
+			blk = match read_u32(&data, ref_off) as usize {
+				0 => {
+					let child = alloc_block(&mut data)?;
+					write_u32(&mut data, ref_off, child as _);
+					child
+				}
+				child => child,
+			};
+		}
+	}
+	// Header
+	write_u32(&mut data, 0, MAGICS[ty as usize]);
+	write_u32(&mut data, 4, format.version());
+	// Settings. The last data block is the only one that may have free entries
+	let free_entry = if count < per_block { data_blk } else { 0 };
+	write_u32(&mut data, 8, info.bgrace as _);
+	write_u32(&mut data, 12, info.igrace as _);
+	write_u32(&mut data, 16, info.flags);
+	let blocks = data.len() / BLOCK_SIZE;
+	write_u32(&mut data, 20, blocks as _);
+	write_u32(&mut data, 24, 0);
+	write_u32(&mut data, 28, free_entry as _);
+	Ok(data)
+}
+
+/// Reads the whole content of `file`.
+fn read_file(file: &File) -> EResult<Vec<u8>> {
+	let mut data = Vec::new();
+	data.resize(file.stat().size as _, 0)?;
+	let mut off = 0;
+	while off < data.len() {
+		let len = file
+			.ops
+			.read(file, off as _, UserSlice::from_slice_mut(&mut data[off..]))?;
+		if len == 0 {
+			break;
+		}
+		off += len;
+	}
+	data.truncate(off);
+	Ok(data)
+}
+
+/// Replaces the content of `file` with `data`.
+fn write_file(file: &File, data: &[u8]) -> EResult<()> {
+	let mut off = 0;
+	while off < data.len() {
+		let slice = unsafe { UserSlice::from_slice(&data[off..]) };
+		let len = file.ops.write(file, off as _, slice)?;
+		if unlikely(len == 0) {
+			return Err(errno!(EIO));
+		}
+		off += len;
+	}
+	file.ops.truncate(file, data.len() as _)
+}
+
+/// A quota type enabled on a filesystem.
+#[derive(Debug)]
+struct Enabled {
+	/// The quota file.
+	file: Arc<File>,
+	/// The format of the quota file.
+	format: Format,
+	/// The settings of the quota type.
+	info: QuotaInfo,
+	/// The quotas of each ID.
+	dquots: HashMap<u32, Dquot>,
+}
+
+/// The disk quotas of a filesystem, for each [`QuotaType`].
+#[derive(Debug, Default)]
+pub struct Quota(Spin<[Option<Enabled>; 2]>);
+
+impl Quota {
+	/// Enables quotas of type `ty`, stored in `file` with the format `format`.
+	///
+	/// The usage stored in the file is assumed to be up to date.
+	///
+	/// If quotas of this type are already enabled, the function returns [`errno::EBUSY`]. If the
+	/// format is not supported or does not match the file, the function returns
+	/// [`errno::EINVAL`].
+	pub fn enable(&self, ty: QuotaType, file: Arc<File>, format: u32) -> EResult<()> {
+		let format = Format::from_id(format).ok_or_else(|| errno!(EINVAL))?;
+		if unlikely(self.0.lock()[ty as usize].is_some()) {
+			return Err(errno!(EBUSY));
+		}
+		let data = read_file(&file)?;
+		let (mut info, dquots) = parse(&data, ty, format)?;
+		if info.bgrace == 0 {
+			info.bgrace = DEFAULT_GRACE;
+		}
+		if info.igrace == 0 {
+			info.igrace = DEFAULT_GRACE;
+		}
+		let mut types = self.0.lock();
+		let slot = &mut types[ty as usize];
+		if unlikely(slot.is_some()) {
+			return Err(errno!(EBUSY));
+		}
+		*slot = Some(Enabled {
+			file,
+			format,
+			info,
+			dquots,
+		});
+		Ok(())
+	}
+
+	/// Disables quotas of type `ty`, writing them back to their file.
+	///
+	/// If quotas of this type are not enabled, the function does nothing.
+	pub fn disable(&self, ty: QuotaType) -> EResult<()> {
+		let Some(enabled) = self.0.lock()[ty as usize].take() else {
+			return Ok(());
+		};
+		let data = serialize(ty, enabled.format, &enabled.info, &enabled.dquots)?;
+		write_file(&enabled.file, &data)
+	}
+
+	/// Disables all quota types, writing them back to their files.
+	pub fn disable_all(&self) -> EResult<()> {
+		self.disable(QuotaType::User)?;
+		self.disable(QuotaType::Group)
+	}
+
+	/// Writes the quotas of all enabled types back to their files.
+	pub fn sync(&self) -> EResult<()> {
+		for ty in [QuotaType::User, QuotaType::Group] {
+			// Writing to the file may charge its owner, so the lock must be released first
+			let (file, data) = {
+				let types = self.0.lock();
+				let Some(enabled) = &types[ty as usize] else {
+					continue;
+				};
+				let data = serialize(ty, enabled.format, &enabled.info, &enabled.dquots)?;
+				(enabled.file.clone(), data)
+			};
+			write_file(&file, &data)?;
+		}
+		Ok(())
+	}
+
+	/// Returns the identifier of the format of quotas of type `ty`.
+	///
+	/// If quotas of this type are not enabled, the function returns [`errno::ESRCH`].
+	pub fn format(&self, ty: QuotaType) -> EResult<u32> {
+		let types = self.0.lock();
+		let enabled = types[ty as usize].as_ref().ok_or_else(|| errno!(ESRCH))?;
+		Ok(enabled.format.id())
+	}
+
+	/// Returns the settings of quotas of type `ty`.
+	///
+	/// If quotas of this type are not enabled, the function returns [`errno::ESRCH`].
+	pub fn get_info(&self, ty: QuotaType) -> EResult<QuotaInfo> {
+		let types = self.0.lock();
+		let enabled = types[ty as usize].as_ref().ok_or_else(|| errno!(ESRCH))?;
+		Ok(enabled.info)
+	}
+
+	/// Changes the settings of quotas of type `ty` with `f`.
+	///
+	/// If quotas of this type are not enabled, the function returns [`errno::ESRCH`].
+	pub fn set_info<F: FnOnce(&mut QuotaInfo)>(&self, ty: QuotaType, f: F) -> EResult<()> {
+		let mut types = self.0.lock();
+		let enabled = types[ty as usize].as_mut().ok_or_else(|| errno!(ESRCH))?;
+		f(&mut enabled.info);
+		Ok(())
+	}
+
+	/// Returns the quotas of `id` for the type `ty`.
+	///
+	/// If quotas of this type are not enabled, the function returns [`errno::ESRCH`].
+	pub fn get(&self, ty: QuotaType, id: u32) -> EResult<Dquot> {
+		let types = self.0.lock();
+		let enabled = types[ty as usize].as_ref().ok_or_else(|| errno!(ESRCH))?;
+		Ok(enabled.dquots.get(&id).copied().unwrap_or_default())
+	}
+
+	/// Changes the quotas of `id` for the type `ty` with `f`.
+	///
+	/// Grace periods are started or stopped according to the new limits.
+	///
+	/// If quotas of this type are not enabled, the function returns [`errno::ESRCH`]. If a limit
+	/// is too large for the format, the function returns [`errno::ERANGE`].
+	pub fn set<F: FnOnce(&mut Dquot)>(&self, ty: QuotaType, id: u32, f: F) -> EResult<()> {
+		let mut types = self.0.lock();
+		let enabled = types[ty as usize].as_mut().ok_or_else(|| errno!(ESRCH))?;
+		let mut dquot = enabled.dquots.get(&id).copied().unwrap_or_default();
+		f(&mut dquot);
+		let max = enabled.format.max_limit();
+		if unlikely(
+			[
+				dquot.bhardlimit,
+				dquot.bsoftlimit,
+				dquot.ihardlimit,
+				dquot.isoftlimit,
+			]
+			.into_iter()
+			.any(|limit| limit > max),
+		) {
+			return Err(errno!(ERANGE));
+		}
+		dquot.update_times(&enabled.info, current_time_sec(Clock::Realtime));
+		enabled.dquots.insert(id, dquot)?;
+		Ok(())
+	}
+
+	/// Charges `space` bytes and `inodes` inodes to the IDs `ids`, for each quota type.
+	///
+	/// If a limit would be exceeded, the function returns [`errno::EDQUOT`] and nothing is
+	/// charged. Limits are not enforced for privileged processes.
+	fn charge_ids(&self, ids: [Option<u32>; 2], space: u64, inodes: u64) -> EResult<()> {
+		let mut types = self.0.lock();
+		if types.iter().all(Option::is_none) {
+			return Ok(());
+		}
+		let enforce = !is_privileged();
+		let now = current_time_sec(Clock::Realtime);
+		// Compute the new usage of each ID first, so that nothing is charged on failure
+		let mut new = [None; 2];
+		for ((enabled, id), new) in types.iter_mut().zip(ids).zip(&mut new) {
+			let (Some(enabled), Some(id)) = (enabled, id) else {
+				continue;
+			};
+			let mut dquot = *enabled.dquots.entry(id).or_insert(Dquot::default())?;
+			dquot.curspace = dquot.curspace.saturating_add(space);
+			dquot.curinodes = dquot.curinodes.saturating_add(inodes);
+			if enforce {
+				dquot.check(now, space > 0, inodes > 0)?;
+			}
+			dquot.update_times(&enabled.info, now);
+			*new = Some((id, dquot));
+		}
+		for (enabled, new) in types.iter_mut().zip(new) {
+			if let (Some(enabled), Some((id, dquot))) = (enabled, new)
+				&& let Some(d) = enabled.dquots.get_mut(&id)
+			{
+				*d = dquot;
+			}
+		}
+		Ok(())
+	}
+
+	/// Releases `space` bytes and `inodes` inodes from the IDs `ids`, for each quota type.
+	fn release_ids(&self, ids: [Option<u32>; 2], space: u64, inodes: u64) {
+		let mut types = self.0.lock();
+		let now = current_time_sec(Clock::Realtime);
+		for (enabled, id) in types.iter_mut().zip(ids) {
+			let (Some(enabled), Some(id)) = (enabled, id) else {
+				continue;
+			};
+			if let Some(dquot) = enabled.dquots.get_mut(&id) {
+				dquot.curspace = dquot.curspace.saturating_sub(space);
+				dquot.curinodes = dquot.curinodes.saturating_sub(inodes);
+				dquot.update_times(&enabled.info, now);
+			}
+		}
+	}
+
+	/// Charges `space` bytes and `inodes` inodes to the user `uid` and the group `gid`.
+	///
+	/// If a limit would be exceeded, the function returns [`errno::EDQUOT`] and nothing is
+	/// charged. Limits are not enforced for privileged processes.
+	pub fn charge(&self, uid: Uid, gid: Gid, space: u64, inodes: u64) -> EResult<()> {
+		self.charge_ids([Some(uid as _), Some(gid as _)], space, inodes)
+	}
+
+	/// Releases `space` bytes and `inodes` inodes from the user `uid` and the group `gid`.
+	pub fn release(&self, uid: Uid, gid: Gid, space: u64, inodes: u64) {
+		self.release_ids([Some(uid as _), Some(gid as _)], space, inodes);
+	}
+
+	/// Transfers the usage of an inode using `space` bytes from the owner `from` to the owner
+	/// `to`, each given as a user and group pair.
+	///
+	/// If a limit of the new owner would be exceeded, the function returns [`errno::EDQUOT`] and
+	/// nothing is transferred.
+	pub fn transfer(&self, from: (Uid, Gid), to: (Uid, Gid), space: u64) -> EResult<()> {
+		let changed = [from.0 != to.0, from.1 != to.1];
+		let from = [from.0 as u32, from.1 as u32];
+		let to = [to.0 as u32, to.1 as u32];
+		self.charge_ids([0, 1].map(|i| changed[i].then_some(to[i])), space, 1)?;
+		self.release_ids([0, 1].map(|i| changed[i].then_some(from[i])), space, 1);
+		Ok(())
+	}
+}
@@ -0,0 +1,150 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Readahead loads the pages of a file into the page cache before they are accessed, so that
+//! sequential reads do not have to wait on disk I/O.
+//!
+//! Each open file description has a readahead window, which userspace can tune with
+//! `posix_fadvise`. Asynchronous readahead requests are processed by a kernel task.
+
+use crate::{
+	file::vfs::node::Node,
+	sync::{spin::Spin, wait_queue::WaitQueue},
+};
+use core::cmp::min;
+use utils::{
+	collections::vec::Vec,
+	errno::{AllocResult, EResult},
+	limits::PAGE_SIZE,
+	ptr::arc::Arc,
+};
+
+/// The default size of the readahead window, in pages.
+pub const DEFAULT_WINDOW: u64 = 32;
+/// The size of the readahead window for files accessed sequentially, in pages.
+pub const SEQUENTIAL_WINDOW: u64 = DEFAULT_WINDOW * 2;
+
+/// The maximum number of pending asynchronous readahead requests.
+const MAX_PENDING: usize = 64;
+
+/// An asynchronous readahead request.
+struct Request {
+	/// The node to read from
+	node: Arc<Node>,
+	/// The first page to read
+	start: u64,
+	/// The end of the range of pages to read (exclusive)
+	end: u64,
+}
+
+/// The queue of pending asynchronous readahead requests.
+static QUEUE: Spin<Vec<Request>> = Spin::new(Vec::new());
+/// The queue on which the readahead task waits for requests.
+static WAIT_QUEUE: WaitQueue = WaitQueue::new();
+
+/// Readahead state of an open file description.
+#[derive(Debug)]
+pub struct ReadaheadState {
+	/// The size of the readahead window, in pages. If zero, readahead is disabled
+	pub window: u64,
+	/// The page following the last read, used to detect sequential accesses
+	next: u64,
+	/// The end of the range of pages already submitted for readahead (exclusive)
+	ahead: u64,
+}
+
+impl Default for ReadaheadState {
+	fn default() -> Self {
+		Self {
+			window: DEFAULT_WINDOW,
+			next: 0,
+			ahead: 0,
+		}
+	}
+}
+
+impl ReadaheadState {
+	/// Updates the state after a read of the pages in range `start..end` on `node`, whose size is
+	/// `size` in bytes.
+	///
+	/// If the access is sequential and the pages read so far approach the end of the readahead
+	/// window, the next window is submitted for asynchronous readahead.
+	pub fn on_read(&mut self, node: &Arc<Node>, start: u64, end: u64, size: u64) {
+		let sequential = start == 0 || start == self.next;
+		self.next = end;
+		if self.window == 0 || !sequential {
+			self.ahead = end;
+			return;
+		}
+		// Wait for the reader to consume half of the window before submitting the next one
+		if self.ahead >= end + self.window / 2 {
+			return;
+		}
+		let start = self.ahead.max(end);
+		let end = min(end + self.window, size.div_ceil(PAGE_SIZE as u64));
+		if start < end && submit(node.clone(), start, end).is_ok() {
+			self.ahead = end;
+		}
+	}
+}
+
+/// Submits the pages in range `start..end` of `node` for asynchronous readahead.
+///
+/// Since readahead is only a hint, the request is dropped if too many requests are pending.
+pub fn submit(node: Arc<Node>, start: u64, end: u64) -> AllocResult<()> {
+	{
+		let mut queue = QUEUE.lock();
+		if queue.len() >= MAX_PENDING {
+			return Ok(());
+		}
+		queue.push(Request {
+			node,
+			start,
+			end,
+		})?;
+	}
+	WAIT_QUEUE.wake_next();
+	Ok(())
+}
+
+/// Synchronously reads the pages in range `start..end` of `node` into the page cache.
+///
+/// Pages that are already cached are skipped.
+pub fn read_pages(node: &Arc<Node>, start: u64, end: u64) -> EResult<()> {
+	for off in start..end {
+		if node.mapped.get(off).is_none() {
+			node.node_ops.read_page(node, off)?;
+		}
+	}
+	Ok(())
+}
+
+/// The entry point of the kernel task processing asynchronous readahead requests.
+pub(crate) fn readahead_task() -> ! {
+	loop {
+		let res = WAIT_QUEUE.wait_until(|| {
+			let mut queue = QUEUE.lock();
+			(!queue.is_empty()).then(|| queue.remove(0))
+		});
+		let Ok(req) = res else {
+			continue;
+		};
+		// Errors are ignored: the pages will be read again on access
+		let _ = read_pages(&req.node, req.start, req.end);
+	}
+}
@@ -0,0 +1,355 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Relay channels move high-volume data from the kernel to userspace.
+//!
+//! A channel is a named set of ring buffers, one per CPU. A kernel subsystem writes records on
+//! the buffer of the current CPU, without blocking, while userspace consumes them through the
+//! files of the channel in the `debugfs`.
+//!
+//! # Buffer layout
+//!
+//! The file of a buffer can be either read, or mapped in memory to avoid a system call per
+//! record. Its first page starts with a [`Header`], followed by the data area, which starts at
+//! the second page.
+//!
+//! Data between the `tail` and `head` positions, modulo the size of the data area, is available
+//! to the consumer. After consuming it, a consumer which mapped the buffer advances `tail`. When
+//! there is not enough space for a record, it is dropped and `lost` is incremented.
+
+use crate::{
+	file::{
+		File, FileType, Stat,
+		fs::{FileOps, NodeOps},
+		vfs::node::Node,
+	},
+	memory::{cache::RcPage, user::UserSlice},
+	process::scheduler::cpu::{CPU, per_cpu},
+	sync::{
+		atomic::AtomicU64,
+		mutex::Mutex,
+		spin::{IntSpin, Spin},
+	},
+	syscall::select::{POLLIN, POLLRDNORM},
+};
+use core::{
+	cmp::min,
+	hint::unlikely,
+	ptr,
+	sync::atomic::Ordering::{Acquire, Relaxed, Release},
+};
+use utils::{
+	collections::{hashmap::HashMap, string::String, vec::Vec},
+	errno,
+	errno::{AllocResult, EResult},
+	limits::PAGE_SIZE,
+	ptr::arc::Arc,
+};
+
+/// The header of a relay buffer, shared with userspace.
+#[repr(C)]
+#[derive(Debug)]
+pub struct Header {
+	/// The number of bytes written to the buffer since its creation. Updated by the kernel.
+	pub head: AtomicU64,
+	/// The number of bytes consumed since the creation of the buffer. Updated by the consumer.
+	pub tail: AtomicU64,
+	/// The number of records dropped because the buffer was full.
+	pub lost: AtomicU64,
+	/// The size of the data area, in bytes.
+	pub size: u64,
+}
+
+/// The ring buffer of a relay channel, for a single CPU.
+#[derive(Debug)]
+pub struct Buffer {
+	/// The pages of the buffer: the header, then the data area.
+	pages: Vec<RcPage>,
+	/// Serializes writers, which may run in interrupt context.
+	write_lock: IntSpin<()>,
+	/// Serializes readers.
+	read_lock: Mutex<(), false>,
+}
+
+impl Buffer {
+	/// Creates a buffer with a data area of `pages` pages.
+	fn new(pages: usize) -> AllocResult<Self> {
+		let mut pages_vec = Vec::with_capacity(1 + pages)?;
+		for _ in 0..=pages {
+			pages_vec.push(RcPage::new_zeroed()?)?;
+		}
+		let buf = Self {
+			pages: pages_vec,
+			write_lock: IntSpin::new(()),
+			read_lock: Mutex::new(()),
+		};
+		let header = buf.pages[0].virt_addr().as_ptr::<Header>();
+		unsafe {
+			(&raw mut (*header).size).write(buf.size() as u64);
+		}
+		Ok(buf)
+	}
+
+	/// Returns the header of the buffer.
+	#[inline]
+	pub fn header(&self) -> &Header {
+		pages_header(&self.pages)
+	}
+
+	/// Returns the size of the data area, in bytes.
+	#[inline]
+	pub fn size(&self) -> usize {
+		(self.pages.len() - 1) * PAGE_SIZE
+	}
+
+	/// Returns the number of bytes available to the consumer, given the `head` and `tail`
+	/// positions.
+	///
+	/// A `tail` set beyond `head` by the consumer is treated as if the buffer was empty.
+	fn available(&self, head: u64, tail: u64) -> usize {
+		let len = head.wrapping_sub(tail);
+		if len > self.size() as u64 {
+			return 0;
+		}
+		len as usize
+	}
+
+	/// Returns a pointer to the byte at position `pos` in the data area, along with the number of
+	/// contiguous bytes from there.
+	fn data_at(&self, pos: u64) -> (*mut u8, usize) {
+		let off = (pos % self.size() as u64) as usize;
+		let page = &self.pages[1 + off / PAGE_SIZE];
+		let inner = off % PAGE_SIZE;
+		let ptr = unsafe { page.virt_addr().as_ptr::<u8>().add(inner) };
+		(ptr, PAGE_SIZE - inner)
+	}
+
+	/// Writes `data` as a single record.
+	///
+	/// If there is not enough space for the record, it is dropped and the function returns
+	/// `false`.
+	pub fn write(&self, data: &[u8]) -> bool {
+		let _guard = self.write_lock.lock();
+		let header = self.header();
+		let head = header.head.load(Relaxed);
+		let tail = header.tail.load(Acquire);
+		let free = self.size() - self.available(head, tail);
+		if unlikely(data.len() > free) {
+			header.lost.fetch_add(1, Relaxed);
+			return false;
+		}
+		let mut off = 0;
+		while off < data.len() {
+			let (ptr, len) = self.data_at(head + off as u64);
+			let len = min(len, data.len() - off);
+			unsafe {
+				ptr::copy_nonoverlapping(data[off..].as_ptr(), ptr, len);
+			}
+			off += len;
+		}
+		header.head.store(head + data.len() as u64, Release);
+		true
+	}
+
+	/// Consumes available data into `buf`, returning the number of bytes read.
+	fn read(&self, buf: UserSlice<u8>) -> EResult<usize> {
+		let _guard = self.read_lock.lock();
+		let header = self.header();
+		let head = header.head.load(Acquire);
+		let tail = header.tail.load(Relaxed);
+		let len = min(self.available(head, tail), buf.len());
+		let mut off = 0;
+		while off < len {
+			let (ptr, chunk) = self.data_at(tail + off as u64);
+			let chunk = min(chunk, len - off);
+			off += unsafe { buf.copy_to_user_raw(off, ptr, chunk)? };
+		}
+		header.tail.store(tail + len as u64, Release);
+		Ok(len)
+	}
+}
+
+/// Returns the header at the beginning of the first page of `pages`.
+fn pages_header(pages: &[RcPage]) -> &Header {
+	unsafe { &*pages[0].virt_addr().as_ptr::<Header>() }
+}
+
+/// A relay channel.
+#[derive(Debug)]
+pub struct Channel {
+	/// The name of the channel.
+	name: String,
+	/// The buffers, one per CPU.
+	bufs: Vec<Buffer>,
+}
+
+impl Channel {
+	/// Creates and registers a channel named `name`, with a data area of `pages` pages for each
+	/// CPU.
+	///
+	/// Errors:
+	/// - [`errno::EINVAL`]: `pages` is zero
+	/// - [`errno::EEXIST`]: a channel with the same name already exists
+	pub fn new(name: &[u8], pages: usize) -> EResult<Arc<Self>> {
+		if unlikely(pages == 0) {
+			return Err(errno!(EINVAL));
+		}
+		let mut bufs = Vec::with_capacity(CPU.len())?;
+		for _ in 0..CPU.len() {
+			bufs.push(Buffer::new(pages)?)?;
+		}
+		let chan = Arc::new(Self {
+			name: String::try_from(name)?,
+			bufs,
+		})?;
+		let mut channels = CHANNELS.lock();
+		if unlikely(channels.contains_key(name)) {
+			return Err(errno!(EEXIST));
+		}
+		channels.insert(String::try_from(name)?, chan.clone())?;
+		Ok(chan)
+	}
+
+	/// Returns the name of the channel.
+	#[inline]
+	pub fn name(&self) -> &[u8] {
+		&self.name
+	}
+
+	/// Returns the buffers of the channel, indexed by CPU.
+	#[inline]
+	pub fn buffers(&self) -> &[Buffer] {
+		&self.bufs
+	}
+
+	/// Writes `data` as a single record on the buffer of the current CPU.
+	///
+	/// If there is not enough space for the record, it is dropped and the function returns
+	/// `false`.
+	pub fn write(&self, data: &[u8]) -> bool {
+		let cpu = per_cpu().cpu_id as usize;
+		self.bufs[cpu].write(data)
+	}
+
+	/// Unregisters the channel.
+	///
+	/// Files that are open or mapped remain usable until they are closed.
+	pub fn close(&self) {
+		CHANNELS.lock().remove(self.name.as_bytes());
+	}
+}
+
+/// The registered channels, by name.
+static CHANNELS: Spin<HashMap<String, Arc<Channel>>> = Spin::new(HashMap::new());
+
+/// Returns the channel named `name`, if registered.
+pub fn get(name: &[u8]) -> Option<Arc<Channel>> {
+	CHANNELS.lock().get(name).cloned()
+}
+
+/// Returns the names of the registered channels.
+pub fn names() -> AllocResult<Vec<String>> {
+	let channels = CHANNELS.lock();
+	let mut names = Vec::with_capacity(channels.len())?;
+	for (name, _) in channels.iter() {
+		names.push(String::try_from(name.as_bytes())?)?;
+	}
+	Ok(names)
+}
+
+/// The file of a relay buffer.
+#[derive(Debug)]
+pub struct BufferFile {
+	/// The channel.
+	pub chan: Arc<Channel>,
+	/// The CPU of the buffer.
+	pub cpu: usize,
+}
+
+impl BufferFile {
+	/// Returns the buffer.
+	#[inline]
+	fn buf(&self) -> &Buffer {
+		&self.chan.bufs[self.cpu]
+	}
+
+	/// Returns the status of the file.
+	pub fn stat(&self) -> Stat {
+		Stat {
+			mode: FileType::Regular.to_mode() | 0o600,
+			size: self.buf().pages.len() as u64 * PAGE_SIZE as u64,
+			..Default::default()
+		}
+	}
+}
+
+impl NodeOps for BufferFile {
+	fn read_page(&self, _node: &Arc<Node>, off: u64) -> EResult<RcPage> {
+		let off: usize = off.try_into().map_err(|_| errno!(EOVERFLOW))?;
+		self.buf()
+			.pages
+			.get(off)
+			.cloned()
+			.ok_or_else(|| errno!(EINVAL))
+	}
+}
+
+impl FileOps for BufferFile {
+	fn poll(&self, _file: &File, mask: u32) -> EResult<u32> {
+		let header = self.buf().header();
+		let head = header.head.load(Acquire);
+		let tail = header.tail.load(Relaxed);
+		let events = if self.buf().available(head, tail) > 0 {
+			POLLIN | POLLRDNORM
+		} else {
+			0
+		};
+		Ok(events & mask)
+	}
+
+	fn read(&self, _file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		self.buf().read(buf)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn relay_wrap() {
+		let buf = Buffer::new(1).unwrap();
+		assert_eq!(buf.header().size, PAGE_SIZE as u64);
+		let record = [0xaa; 1000];
+		for _ in 0..4 {
+			assert!(buf.write(&record));
+		}
+		// Not enough space left
+		assert!(!buf.write(&record));
+		assert_eq!(buf.header().lost.load(Relaxed), 1);
+		let mut out = [0; 1500];
+		assert_eq!(buf.read(UserSlice::from_slice_mut(&mut out)), Ok(1500));
+		// The record wraps around the end of the data area
+		assert!(buf.write(&[0x55; 1000]));
+		let mut out = [0; 4096];
+		assert_eq!(buf.read(UserSlice::from_slice_mut(&mut out)), Ok(3500));
+		assert!(out[..2500].iter().all(|b| *b == 0xaa));
+		assert!(out[2500..3500].iter().all(|b| *b == 0x55));
+		assert_eq!(buf.read(UserSlice::from_slice_mut(&mut out)), Ok(0));
+	}
+}
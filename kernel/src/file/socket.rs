@@ -19,28 +19,532 @@
 //! This file implements sockets.
 
 use crate::{
-	file::{File, fs::FileOps},
-	memory::{ring_buffer::RingBuffer, user::UserSlice},
-	net::{SocketDesc, osi},
+	file::{
+		File, O_NONBLOCK,
+		fs::FileOps,
+		perm::{Uid, is_privileged},
+	},
+	memory::user::{IOVec, UserSlice},
+	net,
+	net::{Address, SocketDesc, SocketDomain, SocketType, netlink, osi, sockaddr::SockAddr, udp},
+	process::{Process, rlimit::RLIMIT_NOFILE, signal::Signal},
 	sync::{spin::Spin, wait_queue::WaitQueue},
-	syscall::ioctl,
+	syscall::{
+		ioctl,
+		select::{POLLERR, POLLHUP, POLLIN, POLLOUT, POLLPRI, POLLRDHUP, POLLRDNORM, POLLWRNORM},
+	},
 };
 use core::{
+	cmp::min,
 	ffi::{c_int, c_void},
-	num::NonZeroUsize,
-	sync::{atomic, atomic::AtomicUsize},
+	hint::unlikely,
+	mem,
+	sync::atomic::{
+		AtomicBool, AtomicUsize,
+		Ordering::{Acquire, Relaxed, Release},
+	},
 };
 use utils::{
-	collections::vec::Vec,
+	TryClone,
+	bytes::as_bytes,
+	collections::{hashmap::HashMap, string::String, vec::Vec},
 	errno,
-	errno::{AllocResult, EResult},
+	errno::{AllocResult, CollectResult, EResult, Errno},
+	ptr::arc::Arc,
 };
 
-/// The maximum size of a socket's buffers.
+/// The default size of a socket's buffers.
 const BUFFER_SIZE: usize = 65536;
+/// The minimum size of a socket's buffers.
+const BUFFER_SIZE_MIN: usize = 4608;
+/// The maximum size of a socket's buffers that can be requested by userspace.
+const BUFFER_SIZE_MAX: usize = 212992;
+/// The maximum number of pending connections on a listening socket.
+const SOMAXCONN: c_int = 4096;
+/// The maximum length of the path of a Unix socket address.
+const UNIX_PATH_MAX: usize = 108;
 
 /// Socket option level: Socket
-const SOL_SOCKET: c_int = 1;
+pub const SOL_SOCKET: c_int = 1;
+/// Socket option: allow reusing local addresses.
+const SO_REUSEADDR: c_int = 2;
+/// Socket option: the type of the socket.
+const SO_TYPE: c_int = 3;
+/// Socket option: the pending error, which is cleared when read.
+const SO_ERROR: c_int = 4;
+/// Socket option: allow sending datagrams to broadcast addresses.
+const SO_BROADCAST: c_int = 6;
+/// Socket option: the size of the send buffer.
+const SO_SNDBUF: c_int = 7;
+/// Socket option: the size of the receive buffer.
+const SO_RCVBUF: c_int = 8;
+/// Socket option: receive the credentials of the sender along with messages.
+const SO_PASSCRED: c_int = 16;
+/// Socket option: the credentials of the peer at the time of connection.
+const SO_PEERCRED: c_int = 17;
+
+/// A socket option.
+struct SockOpt {
+	/// The level (protocol) at which the option is located
+	level: c_int,
+	/// The name of the option
+	name: c_int,
+	/// Reads the value of the option
+	get: fn(&Socket) -> EResult<Vec<u8>>,
+	/// Writes the value of the option
+	set: fn(&Socket, &[u8]) -> EResult<()>,
+}
+
+/// Setter for read-only socket options.
+fn read_only(_sock: &Socket, _val: &[u8]) -> EResult<()> {
+	Err(errno!(ENOPROTOOPT))
+}
+
+/// Returns the value of an integer socket option.
+fn int_opt(val: c_int) -> EResult<Vec<u8>> {
+	Ok(Vec::try_from(&val.to_ne_bytes()[..])?)
+}
+
+/// Parses the value of an integer socket option.
+///
+/// If the value is too small, the function returns [`errno::EINVAL`].
+fn parse_int_opt(val: &[u8]) -> EResult<c_int> {
+	let val = val.first_chunk().ok_or_else(|| errno!(EINVAL))?;
+	Ok(c_int::from_ne_bytes(*val))
+}
+
+/// Returns the size of a buffer requested by userspace with the value `val`.
+///
+/// As on Linux, the requested size is doubled to account for bookkeeping overhead.
+fn parse_buffer_size(val: &[u8]) -> EResult<usize> {
+	let val = parse_int_opt(val)?.max(0) as usize;
+	Ok((min(val, BUFFER_SIZE_MAX) * 2).max(BUFFER_SIZE_MIN))
+}
+
+/// The supported socket options.
+static SOCK_OPTS: &[SockOpt] = &[
+	SockOpt {
+		level: SOL_SOCKET,
+		name: SO_REUSEADDR,
+		get: |sock| int_opt(sock.reuseaddr.load(Acquire) as _),
+		set: |sock, val| {
+			sock.reuseaddr.store(parse_int_opt(val)? != 0, Release);
+			Ok(())
+		},
+	},
+	SockOpt {
+		level: SOL_SOCKET,
+		name: SO_TYPE,
+		get: |sock| int_opt(sock.desc.type_.get_id() as _),
+		set: read_only,
+	},
+	SockOpt {
+		level: SOL_SOCKET,
+		name: SO_ERROR,
+		get: |sock| int_opt(sock.error.lock().take().map(|e| e.as_int()).unwrap_or(0)),
+		set: read_only,
+	},
+	SockOpt {
+		level: SOL_SOCKET,
+		name: SO_BROADCAST,
+		get: |sock| int_opt(sock.broadcast.load(Acquire) as _),
+		set: |sock, val| {
+			sock.broadcast.store(parse_int_opt(val)? != 0, Release);
+			Ok(())
+		},
+	},
+	SockOpt {
+		level: SOL_SOCKET,
+		name: SO_SNDBUF,
+		get: |sock| int_opt(sock.sndbuf.load(Relaxed) as _),
+		set: |sock, val| {
+			sock.sndbuf.store(parse_buffer_size(val)?, Relaxed);
+			Ok(())
+		},
+	},
+	SockOpt {
+		level: SOL_SOCKET,
+		name: SO_RCVBUF,
+		get: |sock| int_opt(sock.rx.capacity.load(Relaxed) as _),
+		set: |sock, val| {
+			sock.rx.capacity.store(parse_buffer_size(val)?, Relaxed);
+			// The peer may be able to send more data
+			sock.rx.wr_queue.wake_all();
+			Ok(())
+		},
+	},
+	SockOpt {
+		level: SOL_SOCKET,
+		name: SO_PASSCRED,
+		get: |sock| int_opt(sock.passcred.load(Acquire) as _),
+		set: |sock, val| {
+			sock.passcred.store(parse_int_opt(val)? != 0, Release);
+			Ok(())
+		},
+	},
+	SockOpt {
+		level: SOL_SOCKET,
+		name: SO_PEERCRED,
+		get: |sock| {
+			// Without a peer, the credentials are invalid
+			let cred = sock.peercred.lock().unwrap_or(UCred {
+				pid: 0,
+				uid: u32::MAX,
+				gid: u32::MAX,
+			});
+			let mut val = Vec::with_capacity(size_of::<UCred>())?;
+			val.extend_from_slice(&cred.pid.to_ne_bytes())?;
+			val.extend_from_slice(&cred.uid.to_ne_bytes())?;
+			val.extend_from_slice(&cred.gid.to_ne_bytes())?;
+			Ok(val)
+		},
+		set: read_only,
+	},
+	// TODO TCP_NODELAY, once TCP sockets are supported
+];
+
+/// Message flag: receive data without removing it from the queue.
+pub const MSG_PEEK: c_int = 0x2;
+/// Message flag: control data has been truncated.
+pub const MSG_CTRUNC: c_int = 0x8;
+/// Message flag: the datagram has been truncated.
+pub const MSG_TRUNC: c_int = 0x20;
+/// Message flag: do not block.
+pub const MSG_DONTWAIT: c_int = 0x40;
+/// Message flag: do not send `SIGPIPE` when the peer is gone.
+pub const MSG_NOSIGNAL: c_int = 0x4000;
+/// Message flag: set the close-on-exec flag on file descriptors received with `SCM_RIGHTS`.
+pub const MSG_CMSG_CLOEXEC: c_int = 0x40000000;
+
+/// Unix sockets bound to an address, by name, with their type and receiving side.
+static BOUND: Spin<HashMap<String, (SocketType, Arc<Receiver>)>> = Spin::new(HashMap::new());
+/// The number of files passed with `SCM_RIGHTS` and not received yet, per user who sent them.
+static IN_FLIGHT: Spin<HashMap<Uid, usize>> = Spin::new(HashMap::new());
+
+/// A UDP socket bound to a local address.
+#[derive(Debug)]
+struct UdpBinding {
+	/// The local address, which may be unspecified to receive on every address
+	addr: Address,
+	/// The local port
+	port: u16,
+	/// Tells whether the socket allows reusing the address
+	reuse: bool,
+	/// The receiving side of the socket
+	rx: Arc<Receiver>,
+}
+
+/// UDP sockets bound to a local address.
+static UDP_BOUND: Spin<Vec<UdpBinding>> = Spin::new(Vec::new());
+
+/// Returns the name in the Unix socket address `sockaddr`, which is either a path or an abstract
+/// name starting with a null byte.
+///
+/// If the address is invalid, the function returns [`errno::EINVAL`].
+fn unix_name(sockaddr: &[u8]) -> EResult<&[u8]> {
+	let Some((family, path)) = sockaddr.split_first_chunk::<2>() else {
+		return Err(errno!(EINVAL));
+	};
+	if u16::from_ne_bytes(*family) as u32 != SocketDomain::AfUnix.get_id() {
+		return Err(errno!(EINVAL));
+	}
+	let path = match path {
+		[0, ..] => path,
+		_ => path.split(|b| *b == 0).next().unwrap_or_default(),
+	};
+	// TODO autobind on empty names
+	if unlikely(path.is_empty() || path.len() > UNIX_PATH_MAX) {
+		return Err(errno!(EINVAL));
+	}
+	Ok(path)
+}
+
+/// Closes `file` if this is the last reference to it.
+fn close_file(file: Arc<File>) {
+	if let Some(file) = Arc::into_inner(file) {
+		let _ = file.close();
+	}
+}
+
+/// Credentials of the process which sent a message, as passed with `SCM_CREDENTIALS`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct UCred {
+	/// The process ID of the sender
+	pub pid: i32,
+	/// The user ID of the sender
+	pub uid: u32,
+	/// The group ID of the sender
+	pub gid: u32,
+}
+
+impl UCred {
+	/// Returns the credentials of the current process.
+	pub fn current() -> Self {
+		let proc = Process::current();
+		let cred = proc.cred();
+		Self {
+			pid: proc.get_pid() as _,
+			uid: cred.ap.euid as _,
+			gid: cred.ap.egid as _,
+		}
+	}
+}
+
+/// Ancillary data sent along with a message.
+#[derive(Debug, Default)]
+pub struct Ancillary {
+	/// Open file descriptions passed with `SCM_RIGHTS`
+	pub rights: Vec<Arc<File>>,
+	/// The credentials of the sender
+	pub cred: Option<UCred>,
+	/// The user charged for `rights` while they are in flight, if any
+	charged: Option<Uid>,
+}
+
+impl Ancillary {
+	/// Charges the files in `rights` to the current user while they are in flight.
+	///
+	/// Unless privileged, a user cannot have more files in flight than its `RLIMIT_NOFILE`
+	/// limit. Otherwise, the function returns [`errno::ETOOMANYREFS`].
+	fn charge(&mut self) -> EResult<()> {
+		if self.rights.is_empty() {
+			return Ok(());
+		}
+		let proc = Process::current();
+		let cred = proc.cred();
+		let limit = proc.rlimits.lock().0[RLIMIT_NOFILE].rlim_cur;
+		let uid = cred.ap.uid;
+		let mut in_flight = IN_FLIGHT.lock();
+		let count = in_flight.get(&uid).copied().unwrap_or(0) + self.rights.len();
+		if unlikely(count as u64 > limit && !cred.is_privileged()) {
+			return Err(errno!(ETOOMANYREFS));
+		}
+		in_flight.insert(uid, count)?;
+		self.charged = Some(uid);
+		Ok(())
+	}
+
+	/// Releases the charge of the files in `rights`, which are not in flight anymore.
+	fn uncharge(&mut self) {
+		let Some(uid) = self.charged.take() else {
+			return;
+		};
+		let mut in_flight = IN_FLIGHT.lock();
+		if let Some(count) = in_flight.get_mut(&uid) {
+			*count -= self.rights.len();
+			if *count == 0 {
+				in_flight.remove(&uid);
+			}
+		}
+	}
+}
+
+impl Drop for Ancillary {
+	fn drop(&mut self) {
+		self.uncharge();
+		// Files that have not been received are closed
+		for file in mem::take(&mut self.rights) {
+			close_file(file);
+		}
+	}
+}
+
+/// A message queued on a socket.
+#[derive(Debug)]
+struct Message {
+	/// The data of the message
+	data: Vec<u8>,
+	/// The offset of the next byte to be read in `data`
+	off: usize,
+	/// The address of the sender, for messages received on sockets that are not connected
+	name: Vec<u8>,
+	/// The ancillary data sent along with the message
+	anc: Ancillary,
+	/// The memory charged on the queue for the message, in addition to its data
+	overhead: usize,
+}
+
+impl Message {
+	/// Creates a new instance.
+	fn new(data: Vec<u8>, name: Vec<u8>, anc: Ancillary) -> Self {
+		let overhead = message_overhead(&anc);
+		Self {
+			data,
+			off: 0,
+			name,
+			anc,
+			overhead,
+		}
+	}
+}
+
+/// Returns the memory charged on a receive queue for a message carrying `anc`, in addition to
+/// its data.
+///
+/// Charging messages for more than their data bounds the number of queued messages, even empty
+/// ones.
+fn message_overhead(anc: &Ancillary) -> usize {
+	size_of::<Message>() + anc.rights.len() * size_of::<Arc<File>>()
+}
+
+/// The result of a reception on a socket.
+#[derive(Debug, Default)]
+pub struct Received {
+	/// The number of bytes received
+	pub len: usize,
+	/// The address of the sender, if any
+	pub name: Vec<u8>,
+	/// The ancillary data received along with the data
+	pub anc: Ancillary,
+	/// Flags to report in the message header
+	pub flags: c_int,
+}
+
+/// Queue of messages received by a socket.
+#[derive(Debug, Default)]
+struct RxQueue {
+	/// The queued messages
+	messages: Vec<Message>,
+	/// The total number of bytes queued, including the overhead of messages
+	len: usize,
+	/// Tells whether reception has been shut down
+	shutdown: bool,
+	/// Tells whether the peer will not send any more data
+	eof: bool,
+
+	/// If the socket is listening, the maximum number of pending connections
+	backlog: Option<usize>,
+	/// The credentials of the process which started listening
+	listen_cred: Option<UCred>,
+	/// Connections waiting to be accepted
+	pending: Vec<Socket>,
+}
+
+impl RxQueue {
+	/// Queues `msg`, charging it on the queue.
+	fn push(&mut self, msg: Message) -> AllocResult<()> {
+		let len = msg.data.len() + msg.overhead;
+		self.messages.push(msg)?;
+		self.len += len;
+		Ok(())
+	}
+
+	/// Tells whether files passed with `SCM_RIGHTS` are waiting to be received on the queue, or
+	/// on the queue of a pending connection.
+	fn has_rights(&self) -> bool {
+		self.messages.iter().any(|msg| !msg.anc.rights.is_empty())
+			|| self.pending.iter().any(Socket::has_rights)
+	}
+}
+
+/// The receiving side of a socket, written to by its peer.
+#[derive(Debug)]
+struct Receiver {
+	/// The queue of received messages
+	queue: Spin<RxQueue>,
+	/// The maximum number of bytes in the queue
+	capacity: AtomicUsize,
+	/// Processes waiting for data to be received
+	rd_queue: WaitQueue,
+	/// Processes waiting for space in the queue
+	wr_queue: WaitQueue,
+}
+
+impl Receiver {
+	/// Creates a new instance.
+	fn new() -> AllocResult<Arc<Self>> {
+		Arc::new(Self {
+			queue: Default::default(),
+			capacity: AtomicUsize::new(BUFFER_SIZE),
+			rd_queue: WaitQueue::new(),
+			wr_queue: WaitQueue::new(),
+		})
+	}
+
+	/// Shuts down reception, dropping queued messages and refusing pending connections.
+	fn shutdown(&self) {
+		let (messages, pending) = {
+			let mut queue = self.queue.lock();
+			queue.shutdown = true;
+			queue.len = 0;
+			queue.backlog = None;
+			(
+				mem::take(&mut queue.messages),
+				mem::take(&mut queue.pending),
+			)
+		};
+		// Files passed along with messages may be closed
+		drop(messages);
+		for sock in pending {
+			sock.shutdown_reception();
+			sock.shutdown_transmit();
+		}
+		self.rd_queue.wake_all();
+		self.wr_queue.wake_all();
+	}
+
+	/// Queues the datagram `data`, sent from the address `name`.
+	///
+	/// Datagrams that do not fit in the queue are dropped.
+	fn push_datagram(&self, data: Vec<u8>, name: Vec<u8>) -> AllocResult<()> {
+		{
+			let mut queue = self.queue.lock();
+			let msg = Message::new(data, name, Ancillary::default());
+			if queue.shutdown
+				|| queue.len + msg.data.len() + msg.overhead > self.capacity.load(Relaxed)
+			{
+				return Ok(());
+			}
+			queue.push(msg)?;
+		}
+		self.rd_queue.wake_next();
+		Ok(())
+	}
+
+	/// Notifies the receiver that the peer will not send any more data.
+	fn hangup(&self) {
+		self.queue.lock().eof = true;
+		self.rd_queue.wake_all();
+		self.wr_queue.wake_all();
+	}
+}
+
+/// Copies at most `len` bytes from the IO vector `iov` to a new buffer.
+fn gather(iov: &[IOVec], len: usize) -> EResult<Vec<u8>> {
+	let mut data = Vec::new();
+	data.resize(len, 0)?;
+	let mut off = 0;
+	for i in iov {
+		if off >= len {
+			break;
+		}
+		let n = min(i.iov_len, len - off);
+		let buf = UserSlice::from_user(i.iov_base, n)?;
+		buf.copy_from_user(0, &mut data[off..(off + n)])?;
+		off += n;
+	}
+	Ok(data)
+}
+
+/// Copies `data` to the IO vector `iov`, starting at the offset `off` in the vector.
+fn scatter(iov: &[IOVec], mut off: usize, mut data: &[u8]) -> EResult<()> {
+	for i in iov {
+		if data.is_empty() {
+			break;
+		}
+		if off >= i.iov_len {
+			off -= i.iov_len;
+			continue;
+		}
+		let n = min(i.iov_len - off, data.len());
+		let buf = UserSlice::from_user(i.iov_base, i.iov_len)?;
+		buf.copy_to_user(off, &data[..n])?;
+		data = &data[n..];
+		off = 0;
+	}
+	Ok(())
+}
 
 /// A UNIX socket.
 #[derive(Debug)]
@@ -55,37 +559,74 @@ pub struct Socket {
 
 	/// The address the socket is bound to.
 	sockname: Spin<Vec<u8>>,
+	/// The address of the peer the socket is connected to.
+	peername: Spin<Vec<u8>>,
 
-	/// The buffer containing received data. If `None`, reception has been shutdown.
-	rx_buff: Spin<Option<RingBuffer>>,
-	/// The buffer containing data to be transmitted. If `None`, transmission has been shutdown.
-	tx_buff: Spin<Option<RingBuffer>>,
-
-	/// Receive wait queue.
-	rx_queue: WaitQueue,
-	/// Transmit wait queue.
-	tx_queue: WaitQueue,
+	/// The receiving side of the socket.
+	rx: Arc<Receiver>,
+	/// The receiving side of the peer the socket is connected to, if any.
+	peer: Spin<Option<Arc<Receiver>>>,
+	/// Tells whether transmission has been shut down.
+	tx_shutdown: AtomicBool,
+	/// The credentials of the peer at the time of connection.
+	peercred: Spin<Option<UCred>>,
+	/// The maximum size of a datagram sent on the socket.
+	sndbuf: AtomicUsize,
+	/// Tells whether local addresses can be reused.
+	reuseaddr: AtomicBool,
+	/// Tells whether datagrams can be sent to broadcast addresses.
+	broadcast: AtomicBool,
+	/// Tells whether the credentials of the sender are received along with messages.
+	passcred: AtomicBool,
+	/// The pending out-of-band byte, if any.
+	oob: Spin<Option<u8>>,
+	/// The pending error, if any.
+	error: Spin<Option<Errno>>,
 }
 
 impl Socket {
 	/// Creates a new instance.
 	pub fn new(desc: SocketDesc) -> AllocResult<Self> {
+		Self::with_peer(desc, Receiver::new()?, None)
+	}
+
+	/// Creates a pair of sockets connected to each other.
+	pub fn new_pair(desc: SocketDesc) -> AllocResult<(Self, Self)> {
+		let rx0 = Receiver::new()?;
+		let rx1 = Receiver::new()?;
+		let sock0 = Self::with_peer(desc.clone(), rx0.clone(), Some(rx1.clone()))?;
+		let sock1 = Self::with_peer(desc, rx1, Some(rx0))?;
+		let cred = UCred::current();
+		*sock0.peercred.lock() = Some(cred);
+		*sock1.peercred.lock() = Some(cred);
+		Ok((sock0, sock1))
+	}
+
+	/// Creates a new instance with the receiving side `rx`, connected to the receiving side
+	/// `peer`.
+	fn with_peer(
+		desc: SocketDesc,
+		rx: Arc<Receiver>,
+		peer: Option<Arc<Receiver>>,
+	) -> AllocResult<Self> {
 		Ok(Self {
 			desc,
 			stack: None,
 			open_count: AtomicUsize::new(0),
 
 			sockname: Default::default(),
+			peername: Default::default(),
 
-			rx_buff: Spin::new(Some(RingBuffer::new(
-				NonZeroUsize::new(BUFFER_SIZE).unwrap(),
-			)?)),
-			tx_buff: Spin::new(Some(RingBuffer::new(
-				NonZeroUsize::new(BUFFER_SIZE).unwrap(),
-			)?)),
-
-			rx_queue: WaitQueue::new(),
-			tx_queue: WaitQueue::new(),
+			rx,
+			peer: Spin::new(peer),
+			tx_shutdown: AtomicBool::new(false),
+			peercred: Spin::new(None),
+			sndbuf: AtomicUsize::new(BUFFER_SIZE),
+			reuseaddr: AtomicBool::new(false),
+			broadcast: AtomicBool::new(false),
+			passcred: AtomicBool::new(false),
+			oob: Spin::new(None),
+			error: Spin::new(None),
 		})
 	}
 
@@ -101,14 +642,29 @@ impl Socket {
 		self.stack.as_ref()
 	}
 
+	/// Tells whether the socket is a UDP socket.
+	#[inline]
+	fn is_udp(&self) -> bool {
+		self.desc.domain == SocketDomain::AfInet && self.desc.type_ == SocketType::SockDgram
+	}
+
+	/// Returns the option with the given `level` and `name`.
+	///
+	/// If the option does not exist, the function returns [`errno::ENOPROTOOPT`].
+	fn find_opt(level: c_int, name: c_int) -> EResult<&'static SockOpt> {
+		SOCK_OPTS
+			.iter()
+			.find(|opt| opt.level == level && opt.name == name)
+			.ok_or_else(|| errno!(ENOPROTOOPT))
+	}
+
 	/// Reads the given socket option.
 	///
 	/// Arguments:
 	/// - `level` is the level (protocol) at which the option is located.
 	/// - `optname` is the name of the option.
-	pub fn get_opt(&self, _level: c_int, _optname: c_int) -> EResult<&[u8]> {
-		// TODO
-		todo!()
+	pub fn get_opt(&self, level: c_int, optname: c_int) -> EResult<Vec<u8>> {
+		(Self::find_opt(level, optname)?.get)(self)
 	}
 
 	/// Writes the given socket option.
@@ -117,11 +673,8 @@ impl Socket {
 	/// - `level` is the level (protocol) at which the option is located.
 	/// - `optname` is the name of the option.
 	/// - `optval` is the value of the option.
-	///
-	/// The function returns a value to be returned by the syscall on success.
-	pub fn set_opt(&self, _level: c_int, _optname: c_int, _optval: &[u8]) -> EResult<c_int> {
-		// TODO
-		Ok(0)
+	pub fn set_opt(&self, level: c_int, optname: c_int, optval: &[u8]) -> EResult<()> {
+		(Self::find_opt(level, optname)?.set)(self, optval)
 	}
 
 	/// Returns the name of the socket.
@@ -140,57 +693,638 @@ impl Socket {
 		if !sockname.is_empty() {
 			return Err(errno!(EINVAL));
 		}
-		// TODO check if address is already in used (EADDRINUSE)
+		let mut new_name = Vec::try_from(sockaddr)?;
+		match self.desc.domain {
+			SocketDomain::AfUnix => {
+				let name = unix_name(sockaddr)?;
+				let mut bound = BOUND.lock();
+				if bound.contains_key(name) {
+					return Err(errno!(EADDRINUSE));
+				}
+				bound.insert(String::try_from(name)?, (self.desc.type_, self.rx.clone()))?;
+			}
+			SocketDomain::AfNetlink => {
+				let mut addr = netlink::parse_sockaddr(sockaddr)?;
+				// TODO support multicast groups
+				// The port ID is chosen by the kernel if not specified
+				if addr.nl_pid == 0 {
+					addr.nl_pid = Process::current().get_pid() as _;
+				}
+				new_name = Vec::try_from(as_bytes(&addr))?;
+			}
+			SocketDomain::AfInet if self.is_udp() => {
+				new_name = self.bind_udp(SockAddr::parse_in(sockaddr)?)?;
+			}
+			_ => {}
+		}
 		// TODO check the requested network interface exists (EADDRNOTAVAIL)
 		// TODO check address against stack's domain
 
-		*sockname = Vec::try_from(sockaddr)?;
+		*sockname = new_name;
+		Ok(())
+	}
+
+	/// Binds the UDP socket to the local address `addr`, returning the resulting socket name.
+	///
+	/// If the port is zero, a free ephemeral port is chosen.
+	fn bind_udp(&self, addr: SockAddr) -> EResult<Vec<u8>> {
+		if !addr.addr.is_unspecified()
+			&& !net::is_local(&addr.addr)
+			&& !net::is_broadcast(&addr.addr)
+		{
+			return Err(errno!(EADDRNOTAVAIL));
+		}
+		if addr.port != 0 && addr.port < udp::PROT_SOCK && !is_privileged() {
+			return Err(errno!(EACCES));
+		}
+		let reuse = self.reuseaddr.load(Acquire);
+		let mut bound = UDP_BOUND.lock();
+		let in_use = |port: u16| {
+			bound.iter().any(|b| {
+				b.port == port
+					&& (b.addr == addr.addr
+						|| b.addr.is_unspecified()
+						|| addr.addr.is_unspecified())
+					&& !(reuse && b.reuse)
+			})
+		};
+		let port = if addr.port == 0 {
+			let mut ports = udp::EPHEMERAL_PORTS;
+			ports
+				.find(|port| !in_use(*port))
+				.ok_or_else(|| errno!(EADDRINUSE))?
+		} else if in_use(addr.port) {
+			return Err(errno!(EADDRINUSE));
+		} else {
+			addr.port
+		};
+		bound.push(UdpBinding {
+			addr: addr.addr,
+			port,
+			reuse,
+			rx: self.rx.clone(),
+		})?;
+		Ok(SockAddr {
+			port,
+			addr: addr.addr,
+		}
+		.to_bytes()?)
+	}
+
+	/// Removes the socket's address from the addresses in use, if it is bound.
+	fn unbind(&self) {
+		if self.is_udp() {
+			UDP_BOUND
+				.lock()
+				.retain(|b| Arc::as_ptr(&b.rx) != Arc::as_ptr(&self.rx));
+			return;
+		}
+		if self.desc.domain != SocketDomain::AfUnix {
+			return;
+		}
+		let sockname = self.sockname.lock();
+		let Ok(name) = unix_name(&sockname) else {
+			return;
+		};
+		let mut bound = BOUND.lock();
+		if bound
+			.get(name)
+			.is_some_and(|(_, rx)| Arc::as_ptr(rx) == Arc::as_ptr(&self.rx))
+		{
+			bound.remove(name);
+		}
+	}
+
+	/// Connects the socket to the socket bound to the address `sockaddr`.
+	///
+	/// A stream socket is connected once the connection has been queued on the listening socket.
+	/// If its backlog is full, the function waits for space, unless `nonblock` is set, in which
+	/// case it returns [`errno::EAGAIN`].
+	///
+	/// A datagram socket is only set to send to the given address by default.
+	pub fn connect(&self, sockaddr: &[u8], nonblock: bool) -> EResult<()> {
+		// TODO support other domains once the network stack is complete
+		if self.desc.domain != SocketDomain::AfUnix {
+			return Err(errno!(EAFNOSUPPORT));
+		}
+		let name = unix_name(sockaddr)?;
+		let (type_, peer_rx) = BOUND
+			.lock()
+			.get(name)
+			.map(|(type_, rx)| (*type_, rx.clone()))
+			.ok_or_else(|| errno!(ECONNREFUSED))?;
+		if type_ != self.desc.type_ {
+			return Err(errno!(EPROTOTYPE));
+		}
+		if !self.desc.type_.is_stream() {
+			*self.peer.lock() = Some(peer_rx);
+			*self.peername.lock() = Vec::try_from(sockaddr)?;
+			return Ok(());
+		}
+		if self.is_connected() {
+			return Err(errno!(EISCONN));
+		}
+		if self.rx.queue.lock().backlog.is_some() {
+			return Err(errno!(EINVAL));
+		}
+		// The socket of the server's side
+		let rx = Receiver::new()?;
+		let server = Self::with_peer(self.desc.clone(), rx.clone(), Some(self.rx.clone()))?;
+		*server.sockname.lock() = Vec::try_from(sockaddr)?;
+		*server.peername.lock() = self.sockname.lock().try_clone()?;
+		*server.peercred.lock() = Some(UCred::current());
+		let peername = Vec::try_from(sockaddr)?;
+		let mut server = Some(server);
+		peer_rx.wr_queue.wait_until(|| {
+			let mut queue = peer_rx.queue.lock();
+			let Some(backlog) = queue.backlog else {
+				return Some(Err(errno!(ECONNREFUSED)));
+			};
+			if queue.pending.len() > backlog {
+				return nonblock.then(|| Err(errno!(EAGAIN)));
+			}
+			*self.peercred.lock() = queue.listen_cred;
+			let res = server
+				.take()
+				.map(|server| queue.pending.push(server))
+				.transpose();
+			Some(res.map_err(Into::into))
+		})??;
+		*self.peer.lock() = Some(rx);
+		*self.peername.lock() = peername;
+		peer_rx.rd_queue.wake_next();
+		Ok(())
+	}
+
+	/// Marks the socket as accepting connections, with at most `backlog` pending connections.
+	pub fn listen(&self, backlog: c_int) -> EResult<()> {
+		if !self.desc.type_.is_stream() {
+			return Err(errno!(EOPNOTSUPP));
+		}
+		if self.is_connected() {
+			return Err(errno!(EINVAL));
+		}
+		{
+			let mut queue = self.rx.queue.lock();
+			queue.backlog = Some(backlog.clamp(0, SOMAXCONN) as _);
+			queue.listen_cred = Some(UCred::current());
+		}
+		// The backlog may have grown
+		self.rx.wr_queue.wake_all();
 		Ok(())
 	}
 
+	/// Accepts a pending connection and returns the socket for it.
+	///
+	/// If no connection is pending, the function waits for one, unless `nonblock` is set, in
+	/// which case it returns [`errno::EAGAIN`].
+	pub fn accept(&self, nonblock: bool) -> EResult<Socket> {
+		let sock = self.rx.rd_queue.wait_until(|| {
+			let mut queue = self.rx.queue.lock();
+			if queue.backlog.is_none() {
+				return Some(Err(errno!(EINVAL)));
+			}
+			if !queue.pending.is_empty() {
+				return Some(Ok(queue.pending.remove(0)));
+			}
+			nonblock.then(|| Err(errno!(EAGAIN)))
+		})??;
+		// Make room for connecting sockets
+		self.rx.wr_queue.wake_next();
+		Ok(sock)
+	}
+
+	/// Returns the address of the peer the socket is connected to.
+	pub fn get_peername(&self) -> &Spin<Vec<u8>> {
+		&self.peername
+	}
+
+	/// Tells whether the socket is connected to a peer.
+	#[inline]
+	pub fn is_connected(&self) -> bool {
+		self.peer.lock().is_some()
+	}
+
+	/// Tells whether files passed with `SCM_RIGHTS` are waiting to be received on the socket.
+	fn has_rights(&self) -> bool {
+		self.rx.queue.lock().has_rights()
+	}
+
 	/// Shuts down the reception side of the socket.
 	pub fn shutdown_reception(&self) {
-		*self.rx_buff.lock() = None;
+		self.rx.shutdown();
 	}
 
 	/// Shuts down the transmit side of the socket.
 	pub fn shutdown_transmit(&self) {
-		*self.tx_buff.lock() = None;
+		self.tx_shutdown.store(true, Release);
+		if let Some(peer) = &*self.peer.lock() {
+			peer.hangup();
+		}
+	}
+
+	/// Sets the out-of-band byte `b`, received from the peer as urgent data.
+	///
+	/// If a byte was already pending, it is replaced.
+	pub fn receive_oob(&self, b: u8) {
+		*self.oob.lock() = Some(b);
+		self.rx.rd_queue.wake_all();
+	}
+
+	/// Handles a reset of the connection by the peer.
+	///
+	/// Both sides of the socket are shut down, and the error is kept pending until it is reported.
+	pub fn reset(&self) {
+		*self.error.lock() = Some(errno!(ECONNRESET));
+		self.shutdown_reception();
+		self.shutdown_transmit();
+	}
+
+	/// Sends the data in `iov` to the peer, along with the ancillary data `anc`.
+	///
+	/// Arguments:
+	/// - `dest` is the address to send the data to. If `None`, the data is sent to the peer
+	/// - `flags` is the set of `MSG_*` flags
+	/// - `nonblock` tells whether the operation must fail instead of blocking
+	///
+	/// On a stream socket, data may be sent partially. On success, the function returns the
+	/// number of bytes sent.
+	pub fn send(
+		&self,
+		iov: &[IOVec],
+		dest: Option<&[u8]>,
+		mut anc: Ancillary,
+		flags: c_int,
+		nonblock: bool,
+	) -> EResult<usize> {
+		if let Some(err) = self.error.lock().take() {
+			return Err(err);
+		}
+		// Netlink messages are always sent to the kernel
+		if self.desc.domain == SocketDomain::AfNetlink {
+			return self.send_netlink(iov);
+		}
+		if self.is_udp() {
+			return self.send_udp(iov, dest);
+		}
+		if dest.is_some() {
+			// TODO support sending to a specific address
+			return Err(if self.is_connected() {
+				errno!(EISCONN)
+			} else {
+				errno!(EOPNOTSUPP)
+			});
+		}
+		let Some(peer) = self.peer.lock().clone() else {
+			return Err(if self.desc.type_.is_stream() {
+				errno!(ENOTCONN)
+			} else {
+				errno!(EDESTADDRREQ)
+			});
+		};
+		let stream = self.desc.type_ == SocketType::SockStream;
+		let total = iov.iter().map(|i| i.iov_len).sum();
+		if stream && total == 0 {
+			return Ok(0);
+		}
+		if !stream && total > min(self.sndbuf.load(Relaxed), peer.capacity.load(Relaxed)) {
+			return Err(errno!(EMSGSIZE));
+		}
+		if !anc.rights.is_empty() {
+			// Files in flight must never hold references to each other in a cycle, since they
+			// would never be released. Thus, a socket cannot be passed to itself, nor while it
+			// has files in flight itself
+			let cycle = anc
+				.rights
+				.iter()
+				.filter_map(|file| file.get_buffer::<Socket>())
+				.any(|sock| Arc::as_ptr(&sock.rx) == Arc::as_ptr(&peer) || sock.has_rights());
+			if unlikely(cycle) {
+				return Err(errno!(ETOOMANYREFS));
+			}
+			anc.charge()?;
+		}
+		anc.cred.get_or_insert_with(UCred::current);
+		let overhead = message_overhead(&anc);
+		let mut anc = Some(anc);
+		peer.wr_queue.wait_until(|| {
+			let mut queue = peer.queue.lock();
+			if self.tx_shutdown.load(Acquire) || queue.shutdown {
+				if flags & MSG_NOSIGNAL == 0 {
+					Process::kill(&Process::current(), Signal::SIGPIPE);
+				}
+				return Some(Err(errno!(EPIPE)));
+			}
+			// Datagrams are never split. An empty queue accepts a message of its full capacity
+			let capacity = peer.capacity.load(Relaxed);
+			let space = if queue.messages.is_empty() {
+				capacity
+			} else {
+				capacity.saturating_sub(queue.len + overhead)
+			};
+			let len = if stream { min(total, space) } else { total };
+			if space == 0 || (!stream && len < total) {
+				return if nonblock {
+					Some(Err(errno!(EAGAIN)))
+				} else {
+					None
+				};
+			}
+			let res = gather(iov, len).and_then(|data| {
+				let msg = Message::new(data, Vec::new(), anc.take().unwrap_or_default());
+				queue.push(msg)?;
+				Ok(())
+			});
+			if let Err(e) = res {
+				return Some(Err(e));
+			}
+			peer.rd_queue.wake_next();
+			Some(Ok(len))
+		})?
+	}
+
+	/// Handles the netlink request in `iov`, queueing the replies on the socket.
+	///
+	/// On success, the function returns the size of the request.
+	fn send_netlink(&self, iov: &[IOVec]) -> EResult<usize> {
+		let len = iov.iter().map(|i| i.iov_len).sum();
+		let req = gather(iov, len)?;
+		// The port ID is the PID if the socket is not bound
+		let port = netlink::parse_sockaddr(&self.sockname.lock())
+			.map(|addr| addr.nl_pid)
+			.unwrap_or_else(|_| Process::current().get_pid() as _);
+		let replies = netlink::handle(&req, port)?;
+		{
+			let mut queue = self.rx.queue.lock();
+			if queue.shutdown {
+				return Err(errno!(EPIPE));
+			}
+			for data in replies {
+				queue.push(Message::new(data, Vec::new(), Ancillary::default()))?;
+			}
+		}
+		self.rx.rd_queue.wake_all();
+		Ok(len)
+	}
+
+	/// Sends the datagram in `iov` from the UDP socket to the address `dest`.
+	///
+	/// If the socket is not bound, it is bound to an ephemeral port first.
+	///
+	/// On success, the function returns the size of the datagram.
+	fn send_udp(&self, iov: &[IOVec], dest: Option<&[u8]>) -> EResult<usize> {
+		// TODO support connected UDP sockets
+		let dest = SockAddr::parse_in(dest.ok_or_else(|| errno!(EDESTADDRREQ))?)?;
+		if dest.port == 0 {
+			return Err(errno!(EINVAL));
+		}
+		let len = iov.iter().map(|i| i.iov_len).sum();
+		if len > min(udp::MAX_PAYLOAD, self.sndbuf.load(Relaxed)) {
+			return Err(errno!(EMSGSIZE));
+		}
+		let broadcast = net::is_broadcast(&dest.addr);
+		if broadcast && !self.broadcast.load(Acquire) {
+			return Err(errno!(EACCES));
+		}
+		if !broadcast && !net::is_local(&dest.addr) {
+			// TODO transmit through the interface of the route once the IP layer is implemented
+			return Err(errno!(ENETUNREACH));
+		}
+		let mut src = {
+			let mut sockname = self.sockname.lock();
+			if sockname.is_empty() {
+				*sockname = self.bind_udp(SockAddr {
+					port: 0,
+					addr: Address::IPv4([0; 4]),
+				})?;
+			}
+			SockAddr::parse_in(&sockname)?
+		};
+		// A socket bound to any address sends from the destination when it is local
+		if src.addr.is_unspecified() && !broadcast {
+			src.addr = dest.addr;
+		}
+		let data = gather(iov, len)?;
+		let name = src.to_bytes()?;
+		let receivers = {
+			let bound = UDP_BOUND.lock();
+			let mut receivers = bound.iter().filter(|b| {
+				b.port == dest.port && (b.addr == dest.addr || b.addr.is_unspecified())
+			});
+			let receivers: CollectResult<Vec<_>> = if broadcast {
+				receivers.map(|b| b.rx.clone()).collect()
+			} else {
+				// A unicast datagram is received by a single socket, preferably bound to the
+				// exact address
+				let exact = receivers.clone().find(|b| b.addr == dest.addr);
+				exact
+					.or_else(|| receivers.next())
+					.map(|b| b.rx.clone())
+					.into_iter()
+					.collect()
+			};
+			receivers.0?
+		};
+		// If no socket is bound to the destination, the datagram is lost
+		for rx in receivers {
+			rx.push_datagram(data.try_clone()?, name.try_clone()?)?;
+		}
+		Ok(len)
+	}
+
+	/// Receives data from the socket into `iov`.
+	///
+	/// Arguments:
+	/// - `flags` is the set of `MSG_*` flags
+	/// - `nonblock` tells whether the operation must fail instead of blocking
+	///
+	/// On a stream socket, data from several messages may be received at once, but never from
+	/// two messages carrying file descriptors.
+	pub fn recv(&self, iov: &[IOVec], flags: c_int, nonblock: bool) -> EResult<Received> {
+		if let Some(err) = self.error.lock().take() {
+			return Err(err);
+		}
+		let cap = iov.iter().map(|i| i.iov_len).sum();
+		let mut received = self.rx.rd_queue.wait_until(|| {
+			let mut queue = self.rx.queue.lock();
+			if queue.messages.is_empty() {
+				// End of file
+				if queue.shutdown || queue.eof {
+					return Some(Ok(Received::default()));
+				}
+				if !self.is_connected() && self.desc.type_.is_stream() {
+					return Some(Err(errno!(ENOTCONN)));
+				}
+				return if nonblock {
+					Some(Err(errno!(EAGAIN)))
+				} else {
+					None
+				};
+			}
+			let res = self.recv_queued(&mut queue, iov, cap, flags);
+			if matches!(
+				res,
+				Ok(Received {
+					len: 1..,
+					..
+				})
+			) && flags & MSG_PEEK == 0
+			{
+				self.rx.wr_queue.wake_next();
+			}
+			Some(res)
+		})??;
+		if !self.passcred.load(Acquire) {
+			received.anc.cred = None;
+		}
+		Ok(received)
+	}
+
+	/// Receives queued messages from `queue` into `iov`, which can hold at most `cap` bytes.
+	///
+	/// `queue` must not be empty.
+	fn recv_queued(
+		&self,
+		queue: &mut RxQueue,
+		iov: &[IOVec],
+		cap: usize,
+		flags: c_int,
+	) -> EResult<Received> {
+		let peek = flags & MSG_PEEK != 0;
+		let stream = self.desc.type_ == SocketType::SockStream;
+		let mut received = Received::default();
+		let mut i = 0;
+		let mut first = true;
+		let mut msg_len = 0;
+		while let Some(msg) = queue.messages.get_mut(i) {
+			let rights = !msg.anc.rights.is_empty();
+			if !first && (!stream || rights || received.len >= cap) {
+				break;
+			}
+			// Ancillary data is received along with the first byte of the message
+			if first && msg.off == 0 {
+				received.name = if peek {
+					msg.name.try_clone()?
+				} else {
+					mem::take(&mut msg.name)
+				};
+				received.anc.cred = msg.anc.cred;
+				received.anc.rights = if peek {
+					msg.anc.rights.try_clone()?
+				} else {
+					msg.anc.uncharge();
+					mem::take(&mut msg.anc.rights)
+				};
+			}
+			let remain = &msg.data[msg.off..];
+			msg_len = remain.len();
+			let n = min(remain.len(), cap - received.len);
+			scatter(iov, received.len, &remain[..n])?;
+			received.len += n;
+			if !stream && n < remain.len() {
+				received.flags |= MSG_TRUNC;
+			}
+			if peek {
+				i += 1;
+			} else if stream && n < remain.len() {
+				msg.off += n;
+			} else {
+				// The rest of a truncated datagram is discarded
+				queue.len -= remain.len() - n + msg.overhead;
+				queue.messages.remove(i);
+			}
+			if rights {
+				break;
+			}
+			first = false;
+		}
+		if !peek {
+			queue.len -= received.len;
+		}
+		// With `MSG_TRUNC`, the real length of a datagram is returned
+		if !stream && flags & MSG_TRUNC != 0 {
+			received.len = msg_len;
+		}
+		Ok(received)
 	}
 }
 
 impl FileOps for Socket {
 	fn acquire(&self, _file: &File) {
-		self.open_count.fetch_add(1, atomic::Ordering::Acquire);
+		self.open_count.fetch_add(1, Relaxed);
 	}
 
 	fn release(&self, _file: &File) {
-		let cnt = self.open_count.fetch_sub(1, atomic::Ordering::Release);
-		if cnt == 0 {
-			// TODO close the socket
+		let cnt = self.open_count.fetch_sub(1, Release);
+		if cnt == 1 {
+			self.unbind();
+			self.shutdown_reception();
+			self.shutdown_transmit();
 		}
 	}
 
-	fn poll(&self, _file: &File, _mask: u32) -> EResult<u32> {
-		todo!()
+	fn poll(&self, _file: &File, mask: u32) -> EResult<u32> {
+		let mut events = 0;
+		let rx_shutdown = {
+			let queue = self.rx.queue.lock();
+			// A listening socket is readable when a connection is pending
+			if queue.backlog.is_some() {
+				if !queue.pending.is_empty() {
+					events |= POLLIN | POLLRDNORM;
+				}
+				return Ok(events & mask);
+			}
+			if !queue.messages.is_empty() {
+				events |= POLLIN | POLLRDNORM;
+			}
+			// Reading returns end-of-file
+			let shutdown = queue.shutdown || queue.eof;
+			if shutdown {
+				events |= POLLIN | POLLRDNORM | POLLRDHUP;
+			}
+			shutdown
+		};
+		let tx_shutdown = self.tx_shutdown.load(Acquire);
+		let tx_full = self
+			.peer
+			.lock()
+			.as_ref()
+			.is_some_and(|peer| peer.queue.lock().len >= peer.capacity.load(Relaxed));
+		// Writing does not block if transmission is shut down, it fails
+		if tx_shutdown || !tx_full {
+			events |= POLLOUT | POLLWRNORM;
+		}
+		if rx_shutdown && tx_shutdown {
+			events |= POLLHUP;
+		}
+		if self.oob.lock().is_some() {
+			events |= POLLPRI;
+		}
+		if self.error.lock().is_some() {
+			events |= POLLERR;
+		}
+		Ok(events & mask)
 	}
 
 	fn ioctl(&self, _file: &File, _request: ioctl::Request, _argp: *const c_void) -> EResult<u32> {
 		todo!()
 	}
 
-	fn read(&self, _file: &File, _off: u64, _buf: UserSlice<u8>) -> EResult<usize> {
-		if !self.desc.type_.is_stream() {
-			// TODO error
-		}
-		todo!()
+	fn read(&self, file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let iov = [IOVec {
+			iov_base: buf.as_ptr(),
+			iov_len: buf.len(),
+		}];
+		let nonblock = file.get_flags() & O_NONBLOCK != 0;
+		Ok(self.recv(&iov, 0, nonblock)?.len)
 	}
 
-	fn write(&self, _file: &File, _off: u64, _buf: UserSlice<u8>) -> EResult<usize> {
-		// A destination address is required
-		let Some(_stack) = self.stack.as_ref() else {
-			return Err(errno!(EDESTADDRREQ));
-		};
-		todo!()
+	fn write(&self, file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let iov = [IOVec {
+			iov_base: buf.as_ptr(),
+			iov_len: buf.len(),
+		}];
+		let nonblock = file.get_flags() & O_NONBLOCK != 0;
+		self.send(&iov, None, Ancillary::default(), 0, nonblock)
 	}
 }
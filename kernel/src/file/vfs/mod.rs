@@ -33,22 +33,25 @@
 pub mod mountpoint;
 pub mod node;
 
-use super::{
-	FileType, Stat, perm,
-	perm::{AccessProfile, S_ISVTX},
-};
+use super::{FileType, Stat, perm, perm::S_ISVTX};
 use crate::{
 	file::{
 		fs::StatSet,
-		perm::{can_search_directory, can_set_file_permissions, can_write_directory},
+		perm::{
+			S_ISGID, S_ISUID, S_IXGRP, can_chown, can_search_directory, can_set_file_permissions,
+			can_write_directory,
+		},
 	},
 	process::Process,
-	sync::{mutex::Mutex, once::OnceInit, spin::Spin},
+	sync::{mutex::Mutex, once::OnceInit, rwlock::RwLock, spin::Spin},
+	sysctl::{IntTunable, Tunable},
+	time::clock::{Clock, current_time_ns},
 };
 use core::{
 	borrow::Borrow,
 	hash::{Hash, Hasher},
 	hint::unlikely,
+	sync::atomic::{AtomicBool, Ordering::Relaxed},
 };
 use node::Node;
 use utils::{
@@ -64,6 +67,7 @@ use utils::{
 	limits::{LINK_MAX, PATH_MAX, SYMLOOP_MAX},
 	list, list_type,
 	ptr::arc::Arc,
+	try_writeln,
 };
 
 /// A child of a VFS entry.
@@ -106,7 +110,13 @@ pub struct Entry {
 	/// The list of cached file entries.
 	///
 	/// This is not an exhaustive list of the file's entries. Only those that are loaded.
-	children: Mutex<HashSet<EntryChild>, false>,
+	///
+	/// The lock is held only while accessing the set, so that lookups hitting the cache can run
+	/// concurrently.
+	children: RwLock<HashSet<EntryChild>>,
+	/// Lock serializing lookups missing the cache with the operations invalidating entries in the
+	/// directory, so that no stale entry gets inserted in `children`.
+	lookup_lock: Mutex<(), false>,
 	/// The node associated with the entry.
 	///
 	/// If `None`, the entry is negative.
@@ -114,6 +124,8 @@ pub struct Entry {
 
 	/// Node for the LRU
 	lru: ListNode,
+	/// Tells whether the entry has been accessed since the last scan of the LRU.
+	referenced: AtomicBool,
 }
 
 impl Entry {
@@ -123,9 +135,11 @@ impl Entry {
 			name,
 			parent,
 			children: Default::default(),
+			lookup_lock: Default::default(),
 			node,
 
 			lru: Default::default(),
+			referenced: AtomicBool::new(false),
 		}
 	}
 
@@ -181,15 +195,30 @@ impl Entry {
 		Ok(PathBuf::new_unchecked(String::from(buf)))
 	}
 
+	/// Returns the cached child entry with the given `name`, if any.
+	fn cached_child(&self, name: &[u8]) -> Option<Arc<Entry>> {
+		let ent = self.children.read().get(name)?.0.clone();
+		ent.referenced.store(true, Relaxed);
+		Some(ent)
+	}
+
+	/// Removes the child entry with the given `name` from the cache, waiting for lookups in
+	/// progress in the directory.
+	fn invalidate_child(&self, name: &[u8]) {
+		let _lookup = self.lookup_lock.lock();
+		self.children.write().remove(name);
+	}
+
 	/// Makes `self` a child of its parent, if any. The entry is also inserted in the LRU.
 	///
 	/// The function returns `self` wrapped into an [`Arc`].
 	pub fn link_parent(self) -> AllocResult<Arc<Self>> {
 		let entry = Arc::new(self)?;
 		if let Some(parent) = &entry.parent {
-			parent.children.lock().insert(EntryChild(entry.clone()))?;
+			parent.children.write().insert(EntryChild(entry.clone()))?;
 		}
-		LRU.lock().insert_front(entry.clone());
+		LRU.lock().insert(entry.clone());
+		shrink_to_limit();
 		Ok(entry)
 	}
 
@@ -225,40 +254,141 @@ impl Entry {
 	}
 }
 
+/// The maximum number of cached directory entries (`fs.dentry-max`).
+///
+/// Once reached, the least recently used entries are evicted to make room for new ones.
+pub static DENTRY_MAX: IntTunable = IntTunable::new(65536, 64, usize::MAX);
+
+/// Directory entries LRU, along with counters on its content.
+struct Lru {
+	/// The entries, from the most recently inserted to the least.
+	///
+	/// Accesses do not reorder the list, to avoid locking it on every lookup. Instead, they set
+	/// the entries' `referenced` flag.
+	list: list_type!(Entry, lru),
+	/// The number of entries.
+	len: usize,
+	/// The number of negative entries.
+	negative: usize,
+}
+
+impl Lru {
+	/// Inserts `entry` as the most recently used entry.
+	fn insert(&mut self, entry: Arc<Entry>) {
+		self.len += 1;
+		if entry.is_negative() {
+			self.negative += 1;
+		}
+		self.list.insert_front(entry);
+	}
+
+	/// Removes `entry`, if inserted.
+	///
+	/// # Safety
+	///
+	/// `entry` must not be inserted in another list.
+	unsafe fn remove(&mut self, entry: &Arc<Entry>) {
+		if !entry.lru.is_linked() {
+			return;
+		}
+		unsafe {
+			self.list.remove(entry);
+		}
+		self.len -= 1;
+		if entry.is_negative() {
+			self.negative -= 1;
+		}
+	}
+}
+
 /// Directory entries LRU.
-static LRU: Spin<list_type!(Entry, lru)> = Spin::new(list!(Entry, lru));
+static LRU: Spin<Lru> = Spin::new(Lru {
+	list: list!(Entry, lru),
+	len: 0,
+	negative: 0,
+});
 
 /// Attempts to shrink the directory entries cache.
 ///
 /// If the cache cannot shrink, the function returns `false`.
 pub fn shrink_entries() -> bool {
 	let mut lru = LRU.lock();
-	for cursor in lru.iter().rev() {
-		let entry = cursor.arc();
-		// The following is the same as the implementation of `Entry::release`. We don't call
-		// directly to reuse the lock on `LRU`
-		let Some(parent) = entry.parent.clone() else {
-			continue;
-		};
-		let mut parent_children = parent.children.lock();
-		if Arc::strong_count(&entry) > 3 {
-			continue;
-		}
-		parent_children.remove(&*entry.name);
-		cursor.remove();
-		let Some(entry) = Arc::into_inner(entry) else {
-			continue;
-		};
-		drop(parent_children);
-		if let Some(node) = entry.node {
-			// TODO log I/O errors?
-			let _ = Node::release(node);
+	// Entries accessed since the last scan are given a second chance: the first pass clears their
+	// flag so that the second pass can evict them
+	for _ in 0..2 {
+		let Lru {
+			list,
+			len,
+			negative,
+		} = &mut *lru;
+		for cursor in list.iter().rev() {
+			let entry = cursor.arc();
+			if entry.referenced.swap(false, Relaxed) {
+				continue;
+			}
+			// The following is the same as the implementation of `Entry::release`. We don't call
+			// directly to reuse the lock on `LRU`
+			let Some(parent) = entry.parent.clone() else {
+				continue;
+			};
+			let mut parent_children = parent.children.write();
+			// The entry may have been replaced in, or removed from, the cache of its parent, in
+			// which case the parent does not hold a reference to it
+			let cached = parent_children
+				.get(&*entry.name)
+				.is_some_and(|child| Arc::as_ptr(&child.0) == Arc::as_ptr(&entry));
+			if Arc::strong_count(&entry) > 2 + cached as usize {
+				continue;
+			}
+			if cached {
+				parent_children.remove(&*entry.name);
+			}
+			drop(parent_children);
+			cursor.remove();
+			*len -= 1;
+			if entry.is_negative() {
+				*negative -= 1;
+			}
+			let Some(entry) = Arc::into_inner(entry) else {
+				continue;
+			};
+			if let Some(node) = entry.node {
+				// TODO log I/O errors?
+				let _ = Node::release(node);
+			}
+			return true;
 		}
-		return true;
 	}
 	false
 }
 
+/// Evicts the least recently used entries until the cache does not exceed [`DENTRY_MAX`].
+///
+/// Entries in use cannot be evicted, so the cache may remain larger.
+fn shrink_to_limit() {
+	while LRU.lock().len > DENTRY_MAX.get() {
+		if !shrink_entries() {
+			break;
+		}
+	}
+}
+
+/// The `fs.dentry-state` tunable.
+#[derive(Debug)]
+pub struct DentryState;
+
+impl Tunable for DentryState {
+	fn read(&self, buf: &mut String) -> AllocResult<()> {
+		let (len, negative) = {
+			let lru = LRU.lock();
+			(lru.len, lru.negative)
+		};
+		// Same layout as Linux: `nr_dentry nr_unused age_limit want_pages nr_negative dummy`.
+		// Unused entries are not tracked
+		try_writeln!(buf, "{len}\t0\t0\t0\t{negative}\t0")
+	}
+}
+
 /// The root entry of the VFS
 pub static ROOT: OnceInit<Arc<Entry>> = unsafe { OnceInit::new() };
 
@@ -323,18 +453,16 @@ pub enum Resolved<'s> {
 /// If the entry does not exist in cache or on the filesystem, the function returns a negative
 /// entry.
 fn resolve_entry(lookup_dir: &Arc<Entry>, name: &[u8]) -> EResult<Arc<Entry>> {
-	let mut children = lookup_dir.children.lock();
 	// Try to get from cache first
-	if let Some(ent) = children.get(name) {
-		let ent = ent.0.clone();
-		drop(children);
-		// Promote the entry in the LRU
-		unsafe {
-			LRU.lock().lru_promote(&ent);
-		}
+	if let Some(ent) = lookup_dir.cached_child(name) {
 		return Ok(ent);
 	}
 	// Not in cache. Try to get from the filesystem
+	let lookup = lookup_dir.lookup_lock.lock();
+	// Another process may have looked the entry up in the meantime
+	if let Some(ent) = lookup_dir.cached_child(name) {
+		return Ok(ent);
+	}
 	let mut entry = Entry::new(String::try_from(name)?, Some(lookup_dir.clone()), None);
 	let lookup_dir_node = lookup_dir.node();
 	lookup_dir_node
@@ -342,10 +470,17 @@ fn resolve_entry(lookup_dir: &Arc<Entry>, name: &[u8]) -> EResult<Arc<Entry>> {
 		.lookup_entry(lookup_dir_node, &mut entry)?;
 	let entry = Arc::new(entry)?;
 	if lookup_dir_node.fs.ops.cache_entries() {
-		// Insert in cache. Do not use `link_parent` to keep `children` locked
+		let mut children = lookup_dir.children.write();
+		// A file may have been created with this name in the meantime, in which case its entry
+		// is more recent than the result of the lookup
+		if let Some(ent) = children.get(name) {
+			return Ok(ent.0.clone());
+		}
 		children.insert(EntryChild(entry.clone()))?;
 		drop(children);
-		LRU.lock().insert_front(entry.clone());
+		drop(lookup);
+		LRU.lock().insert(entry.clone());
+		shrink_to_limit();
 	}
 	Ok(entry)
 }
@@ -522,11 +657,33 @@ pub fn get_file_from_path(path: &Path, follow_link: bool) -> EResult<Arc<Entry>>
 	get_file_from_path_opt(path, follow_link)?.ok_or_else(|| errno!(ENOENT))
 }
 
+/// Tells whether the mountpoint containing `ent` has the mount flag `flag` set.
+///
+/// `flag` is one of the `FLAG_*` constants of [`mountpoint`].
+pub fn has_mount_flag(ent: &Arc<Entry>, flag: u32) -> bool {
+	mountpoint::get_flags(ent) & flag != 0
+}
+
+/// Checks the file `ent` can be modified.
+///
+/// If `ent` is located on a read-only mountpoint, the function returns [`errno::EROFS`].
+pub fn check_writable(ent: &Arc<Entry>) -> EResult<()> {
+	if unlikely(has_mount_flag(ent, mountpoint::FLAG_RDONLY)) {
+		return Err(errno!(EROFS));
+	}
+	Ok(())
+}
+
 /// Updates status of a node.
 pub fn set_stat(node: &Node, set: &StatSet) -> EResult<()> {
+	let _write = node.fs.start_write()?;
 	let mut stat = node.stat.lock();
+	let chown = set.uid.is_some() || set.gid.is_some();
 	// Check permissions
-	if !can_set_file_permissions(&stat) {
+	if (set.mode.is_some() || !chown) && !can_set_file_permissions(&stat) {
+		return Err(errno!(EPERM));
+	}
+	if chown && !can_chown(&stat, set.uid, set.gid) {
 		return Err(errno!(EPERM));
 	}
 	// Update stat
@@ -539,9 +696,18 @@ pub fn set_stat(node: &Node, set: &StatSet) -> EResult<()> {
 	if let Some(gid) = set.gid {
 		stat.gid = gid;
 	}
-	if let Some(ctime) = set.ctime {
-		stat.ctime = ctime;
+	// Changing the ownership of a non-directory file drops its privileges. The setgid bit
+	// without group execution marks mandatory locking instead, and is kept
+	if chown && stat.get_type() != Some(FileType::Directory) {
+		stat.mode &= !S_ISUID;
+		if stat.mode & S_IXGRP != 0 {
+			stat.mode &= !S_ISGID;
+		}
 	}
+	// Any change to the status updates `ctime`
+	stat.ctime = set
+		.ctime
+		.unwrap_or_else(|| current_time_ns(Clock::Realtime));
 	if let Some(mtime) = set.mtime {
 		stat.mtime = mtime;
 	}
@@ -552,6 +718,39 @@ pub fn set_stat(node: &Node, set: &StatSet) -> EResult<()> {
 	Ok(())
 }
 
+/// For [`update_times`]: update the timestamp of the last access.
+pub const UPDATE_ATIME: u8 = 0b001;
+/// For [`update_times`]: update the timestamp of the last modification of the content.
+pub const UPDATE_MTIME: u8 = 0b010;
+/// For [`update_times`]: update the timestamp of the last modification of the status.
+pub const UPDATE_CTIME: u8 = 0b100;
+
+/// Sets the timestamps of `node` selected by `flags` to the current time.
+///
+/// This function is meant to be called after an operation modifying the node has succeeded. No
+/// permission check is performed, and errors are ignored since the operation has already been
+/// performed.
+pub fn update_times(node: &Node, flags: u8) {
+	let ts = current_time_ns(Clock::Realtime);
+	let set = |stat: &mut Stat| {
+		if flags & UPDATE_ATIME != 0 {
+			stat.atime = ts;
+		}
+		if flags & UPDATE_MTIME != 0 {
+			stat.mtime = ts;
+		}
+		if flags & UPDATE_CTIME != 0 {
+			stat.ctime = ts;
+		}
+	};
+	// Do not hold the lock while the filesystem is accessed
+	let mut stat = node.stat();
+	set(&mut stat);
+	if node.node_ops.set_stat(node, &stat).is_ok() {
+		set(&mut node.stat.lock());
+	}
+}
+
 /// Creates a file, adds it to the VFS, then returns it.
 ///
 /// Arguments:
@@ -573,25 +772,42 @@ pub fn create_file(parent: Arc<Entry>, name: &[u8], mut stat: Stat) -> EResult<A
 	if parent_stat.get_type() != Some(FileType::Directory) {
 		return Err(errno!(ENOTDIR));
 	}
-	if !can_write_directory(&parent_stat) {
+	check_writable(&parent)?;
+	let cred = Process::current().cred();
+	if !cred.can_write_directory(&parent_stat) {
 		return Err(errno!(EACCES));
 	}
-	let ap = AccessProfile::current();
+	let _write = parent.node().fs.start_write()?;
+	let ap = cred.ap;
 	stat.nlink = 0;
-	stat.uid = ap.euid;
+	stat.uid = ap.fsuid;
 	stat.gid = if parent_stat.mode & perm::S_ISGID != 0 {
 		// If SGID is set, the newly created file shall inherit the group ID of the
-		// parent directory
+		// parent directory. A directory also inherits the flag, to propagate it to its own content
+		if stat.get_type() == Some(FileType::Directory) {
+			stat.mode |= perm::S_ISGID;
+		}
 		parent_stat.gid
 	} else {
-		ap.egid
+		ap.fsgid
 	};
+	// An inherited group does not grant its privileges to an unprivileged creator outside of it
+	let sgid = perm::S_ISGID | perm::S_IXGRP;
+	if stat.get_type() != Some(FileType::Directory)
+		&& stat.mode & sgid == sgid
+		&& stat.gid != ap.fsgid
+		&& !cred.groups.contains(&stat.gid)
+		&& !cred.is_fs_privileged()
+	{
+		stat.mode &= !perm::S_ISGID;
+	}
 	// Add file to filesystem
 	let parent_node = parent.node();
 	let node = parent_node.fs.ops.create_node(&parent_node.fs, stat)?;
 	// Add link to filesystem
 	let ent = Entry::new(String::try_from(name)?, Some(parent.clone()), Some(node));
 	parent_node.node_ops.link(parent_node.clone(), &ent)?;
+	update_times(parent_node, UPDATE_MTIME | UPDATE_CTIME);
 	Ok(ent.link_parent()?)
 }
 
@@ -623,15 +839,19 @@ pub fn link(parent: &Arc<Entry>, name: String, target: Arc<Node>) -> EResult<()>
 	if target_stat.nlink >= LINK_MAX as u16 {
 		return Err(errno!(EMLINK));
 	}
+	check_writable(parent)?;
 	if !can_write_directory(&parent_stat) {
 		return Err(errno!(EACCES));
 	}
 	if !parent.node().is_same_fs(&target) {
 		return Err(errno!(EXDEV));
 	}
+	let _write = parent.node().fs.start_write()?;
 	// Add link to the filesystem
 	let ent = Entry::new(name, Some(parent.clone()), Some(target));
 	parent.node().node_ops.link(parent.node().clone(), &ent)?;
+	update_times(parent.node(), UPDATE_MTIME | UPDATE_CTIME);
+	update_times(ent.node(), UPDATE_CTIME);
 	ent.link_parent()?;
 	Ok(())
 }
@@ -657,28 +877,37 @@ pub fn unlink(entry: Arc<Entry>) -> EResult<()> {
 	if parent_stat.get_type() != Some(FileType::Directory) {
 		return Err(errno!(ENOTDIR));
 	}
-	if !can_write_directory(&parent_stat) {
+	check_writable(parent)?;
+	let cred = Process::current().cred();
+	if !cred.can_write_directory(&parent_stat) {
 		return Err(errno!(EACCES));
 	}
 	let stat = entry.stat();
 	let has_sticky_bit = parent_stat.mode & S_ISVTX != 0;
-	let ap = AccessProfile::current();
-	if has_sticky_bit && ap.euid != stat.uid && ap.euid != parent_stat.uid {
+	let ap = cred.ap;
+	if has_sticky_bit && ap.fsuid != stat.uid && ap.fsuid != parent_stat.uid {
 		return Err(errno!(EACCES));
 	}
 	// If the file to remove is a mountpoint, error
 	if mountpoint::from_entry(&entry).is_some() {
 		return Err(errno!(EBUSY));
 	}
-	// Lock now to avoid race conditions
-	let mut children = parent.children.lock();
+	// The filesystem is kept to cover the removal of the node
+	let fs = parent.node().fs.clone();
+	let _write = fs.start_write()?;
+	// Lock now to avoid race conditions with lookups
+	let lookup = parent.lookup_lock.lock();
 	// Remove link from filesystem
 	let dir_node = parent.node();
 	dir_node.node_ops.unlink(dir_node, &entry)?;
+	update_times(dir_node, UPDATE_MTIME | UPDATE_CTIME);
+	// If the file still has links, its status has changed
+	if entry.stat().nlink > 0 {
+		update_times(entry.node(), UPDATE_CTIME);
+	}
 	// Remove link from cache
-	children.remove(entry.name.as_bytes());
-	// Drop to avoid deadlock
-	drop(children);
+	parent.children.write().remove(entry.name.as_bytes());
+	drop(lookup);
 	// Remove the underlying node if this was the last reference to it
 	Entry::release(entry)?;
 	Ok(())
@@ -702,19 +931,22 @@ pub fn symlink(parent: &Arc<Entry>, name: &[u8], target: &[u8], mut stat: Stat)
 	if parent_stat.get_type() != Some(FileType::Directory) {
 		return Err(errno!(ENOTDIR));
 	}
-	if !can_write_directory(&parent_stat) {
+	check_writable(parent)?;
+	let cred = Process::current().cred();
+	if !cred.can_write_directory(&parent_stat) {
 		return Err(errno!(EACCES));
 	}
-	let ap = AccessProfile::current();
+	let _write = parent.node().fs.start_write()?;
+	let ap = cred.ap;
 	stat.mode = FileType::Link.to_mode() | 0o777;
 	stat.nlink = 0;
-	stat.uid = ap.euid;
+	stat.uid = ap.fsuid;
 	stat.gid = if parent_stat.mode & perm::S_ISGID != 0 {
 		// If SGID is set, the newly created file shall inherit the group ID of the
 		// parent directory
 		parent_stat.gid
 	} else {
-		ap.egid
+		ap.fsgid
 	};
 	// Create node
 	let parent_node = parent.node();
@@ -723,6 +955,7 @@ pub fn symlink(parent: &Arc<Entry>, name: &[u8], target: &[u8], mut stat: Stat)
 	// Add link to the filesystem
 	let ent = Entry::new(String::try_from(name)?, Some(parent.clone()), Some(node));
 	parent_node.node_ops.link(parent_node.clone(), &ent)?;
+	update_times(parent_node, UPDATE_MTIME | UPDATE_CTIME);
 	ent.link_parent()?;
 	Ok(())
 }
@@ -749,19 +982,23 @@ pub fn rename(old: Arc<Entry>, new_parent: Arc<Entry>, new_name: &[u8]) -> EResu
 	if mountpoint::from_entry(&old).is_some() {
 		return Err(errno!(EBUSY));
 	}
+	check_writable(old_parent)?;
+	check_writable(&new_parent)?;
 	// Check permissions on `old`
 	let old_parent_stat = old_parent.stat();
-	if !can_write_directory(&old_parent_stat) {
+	let cred = Process::current().cred();
+	if !cred.can_write_directory(&old_parent_stat) {
 		return Err(errno!(EACCES));
 	}
 	let old_stat = old.stat();
-	let ap = AccessProfile::current();
-	if old_stat.mode & S_ISVTX != 0 && ap.euid != old_stat.uid && ap.euid != old_parent_stat.uid {
+	let ap = cred.ap;
+	if old_stat.mode & S_ISVTX != 0 && ap.fsuid != old_stat.uid && ap.fsuid != old_parent_stat.uid
+	{
 		return Err(errno!(EACCES));
 	}
 	// Check permissions on `new`
 	let new_parent_stat = new_parent.stat();
-	if !can_write_directory(&new_parent_stat) {
+	if !cred.can_write_directory(&new_parent_stat) {
 		return Err(errno!(EACCES));
 	}
 	let new = resolve_entry(&new_parent, new_name)?;
@@ -772,16 +1009,20 @@ pub fn rename(old: Arc<Entry>, new_parent: Arc<Entry>, new_name: &[u8]) -> EResu
 		}
 		let new_stat = new.stat();
 		if new_stat.mode & S_ISVTX != 0
-			&& ap.euid != new_stat.uid
-			&& ap.euid != new_parent_stat.uid
+			&& ap.fsuid != new_stat.uid
+			&& ap.fsuid != new_parent_stat.uid
 		{
 			return Err(errno!(EACCES));
 		}
 	}
 	// Perform rename
+	let _write = old.node().fs.start_write()?;
 	old.node().node_ops.rename(&old, &new_parent, new_name)?;
+	update_times(old_parent.node(), UPDATE_MTIME | UPDATE_CTIME);
+	update_times(new_parent.node(), UPDATE_MTIME | UPDATE_CTIME);
+	update_times(old.node(), UPDATE_CTIME);
 	// Invalidate cache
-	old_parent.children.lock().remove(&*old.name);
-	new_parent.children.lock().remove(new_name);
+	old_parent.invalidate_child(&old.name);
+	new_parent.invalidate_child(new_name);
 	Ok(())
 }
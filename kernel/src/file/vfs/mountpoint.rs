@@ -24,48 +24,69 @@ use crate::{
 		FileType, fs,
 		fs::{Filesystem, FilesystemType},
 		vfs,
-		vfs::EntryChild,
+		vfs::{EntryChild, node::Node},
 	},
 	sync::spin::Spin,
 };
-use core::fmt;
+use core::{
+	fmt,
+	hint::unlikely,
+	sync::atomic::{
+		AtomicU32, AtomicUsize,
+		Ordering::{AcqRel, Acquire, Relaxed, Release},
+	},
+};
 use utils::{
 	TryClone,
 	collections::{
 		hashmap::HashMap,
 		path::{Path, PathBuf},
 		string::String,
+		vec::Vec,
 	},
 	errno,
-	errno::{AllocResult, EResult},
+	errno::{AllocResult, CollectResult, EResult},
 	ptr::arc::Arc,
 };
 
+// Mount flags, with the same values as the ones passed to the `mount` system call
+
+/// Mounts the filesystem in read-only.
+pub const FLAG_RDONLY: u32 = 0x1;
+/// Ignore setuid and setgid flags on the filesystem.
+pub const FLAG_NOSUID: u32 = 0x2;
+/// Do not allow access to device files on the filesystem.
+pub const FLAG_NODEV: u32 = 0x4;
+/// Do not allow files on the filesystem to be executed.
+pub const FLAG_NOEXEC: u32 = 0x8;
+/// Makes writes on this filesystem synchronous.
+pub const FLAG_SYNCHRONOUS: u32 = 0x10;
 /// Permits mandatory locking on files.
-pub const FLAG_MANDLOCK: u32 = 0b000000000001;
+pub const FLAG_MANDLOCK: u32 = 0x40;
 /// Do not update file (all kinds) access timestamps on the filesystem.
-pub const FLAG_NOATIME: u32 = 0b000000000010;
-/// Do not allow access to device files on the filesystem.
-pub const FLAG_NODEV: u32 = 0b000000000100;
+pub const FLAG_NOATIME: u32 = 0x400;
 /// Do not update directory access timestamps on the filesystem.
-pub const FLAG_NODIRATIME: u32 = 0b000000001000;
-/// Do not allow files on the filesystem to be executed.
-pub const FLAG_NOEXEC: u32 = 0b000000010000;
-/// Ignore setuid and setgid flags on the filesystem.
-pub const FLAG_NOSUID: u32 = 0b000000100000;
-/// Mounts the filesystem in read-only.
-pub const FLAG_RDONLY: u32 = 0b000001000000;
-/// TODO doc
-pub const FLAG_REC: u32 = 0b000010000000;
-/// Update atime only if less than or equal to mtime or ctime.
-pub const FLAG_RELATIME: u32 = 0b000100000000;
+pub const FLAG_NODIRATIME: u32 = 0x800;
 /// Suppresses certain warning messages in the kernel logs.
-pub const FLAG_SILENT: u32 = 0b001000000000;
+pub const FLAG_SILENT: u32 = 0x8000;
+/// Update atime only if less than or equal to mtime or ctime.
+pub const FLAG_RELATIME: u32 = 0x200000;
 /// Always update the last access time when files on this filesystem are
 /// accessed. Overrides NOATIME and RELATIME.
-pub const FLAG_STRICTATIME: u32 = 0b010000000000;
-/// Makes writes on this filesystem synchronous.
-pub const FLAG_SYNCHRONOUS: u32 = 0b100000000000;
+pub const FLAG_STRICTATIME: u32 = 0x1000000;
+
+/// The mask of flags stored in a mountpoint.
+pub const FLAGS_MASK: u32 = FLAG_RDONLY
+	| FLAG_NOSUID
+	| FLAG_NODEV
+	| FLAG_NOEXEC
+	| FLAG_SYNCHRONOUS
+	| FLAG_MANDLOCK
+	| FLAG_NOATIME
+	| FLAG_NODIRATIME
+	| FLAG_SILENT
+	| FLAG_RELATIME
+	| FLAG_STRICTATIME;
 
 /// Value specifying the device from which a filesystem is mounted.
 #[derive(Debug, Eq, Hash, PartialEq)]
@@ -137,11 +158,13 @@ pub static FILESYSTEMS: Spin<HashMap<DeviceID, Arc<Filesystem>>> = Spin::new(Has
 /// - `fs_type` is the filesystem type. If `None`, the function tries to detect it automatically.
 /// - `target_path` is the path at which the filesystem is to be mounted.
 /// - `readonly` tells whether the filesystem is mount in readonly.
+/// - `data` is the filesystem-specific data. It is ignored if the filesystem is already loaded.
 fn get_fs(
 	source: &MountSource,
 	fs_type: Option<Arc<dyn FilesystemType>>,
 	target_path: PathBuf,
 	readonly: bool,
+	data: &[u8],
 ) -> EResult<Arc<Filesystem>> {
 	match source {
 		MountSource::Device(dev_id) => {
@@ -156,11 +179,14 @@ fn get_fs(
 				.get(dev_id)
 				.ok_or_else(|| errno!(ENODEV))?
 				.clone();
+			if !readonly && dev.ops.is_read_only() {
+				return Err(errno!(EACCES));
+			}
 			let fs_type = match fs_type {
 				Some(f) => f,
 				None => fs::detect(&dev)?,
 			};
-			let fs = fs_type.load_filesystem(Some(dev), target_path, readonly)?;
+			let fs = fs_type.load_filesystem(Some(dev), target_path, readonly, data)?;
 			filesystems.insert(*dev_id, fs.clone())?;
 			Ok(fs)
 		}
@@ -169,22 +195,95 @@ fn get_fs(
 				Some(f) => f,
 				None => fs::get_type(name).ok_or_else(|| errno!(ENODEV))?,
 			};
-			fs_type.load_filesystem(None, target_path, readonly)
+			fs_type.load_filesystem(None, target_path, readonly, data)
 		}
 	}
 }
 
+/// Propagation settings of a mountpoint, telling how mount and unmount events beneath it are
+/// propagated to other mountpoints.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Propagation {
+	/// The ID of the peer group of the mountpoint, if shared.
+	///
+	/// Events beneath a shared mountpoint are propagated to its peers and to their slaves.
+	pub peer_group: Option<u32>,
+	/// The ID of the peer group from which the mountpoint receives events, if it is a slave.
+	pub master: Option<u32>,
+	/// If `true`, the mountpoint cannot be the source of a bind mount.
+	pub unbindable: bool,
+}
+
+impl Propagation {
+	/// Returns the propagation settings of a copy of a mountpoint having the settings `self`.
+	///
+	/// The copy is a peer of the original if it is shared, and a slave of the same peer group if
+	/// it is a slave.
+	fn copy(&self) -> Self {
+		Self {
+			peer_group: self.peer_group,
+			master: self.master,
+			unbindable: false,
+		}
+	}
+}
+
+/// Propagation type to set on a mountpoint with [`set_propagation`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PropagationType {
+	/// Events are neither sent nor received.
+	Private,
+	/// Events are sent to, and received from, the peers of the mountpoint.
+	Shared,
+	/// Events are received from the former peers of the mountpoint, but not sent to them.
+	Slave,
+	/// Same as [`Self::Private`], and the mountpoint cannot be bind mounted.
+	Unbindable,
+}
+
+/// The ID of the next peer group to be allocated.
+static NEXT_PEER_GROUP: AtomicU32 = AtomicU32::new(1);
+
 /// A mount point, allowing to attach a filesystem to a directory on the VFS.
 #[derive(Debug)]
 pub struct MountPoint {
 	/// Mount flags.
-	pub flags: u32,
+	flags: AtomicU32,
 	/// The source of the mountpoint.
 	pub source: MountSource,
 	/// The filesystem associated with the mountpoint.
 	pub fs: Arc<Filesystem>,
 	/// The root entry of the mountpoint.
 	pub root_entry: Arc<vfs::Entry>,
+	/// The number of files open for writing through the mountpoint.
+	writers: AtomicUsize,
+	/// Propagation settings.
+	pub propagation: Spin<Propagation>,
+}
+
+impl MountPoint {
+	/// Returns the mount flags.
+	#[inline]
+	pub fn flags(&self) -> u32 {
+		self.flags.load(Acquire)
+	}
+
+	/// Tells whether files are open for writing through the mountpoint.
+	fn has_writers(&self) -> bool {
+		self.writers.load(Acquire) != 0
+	}
+}
+
+/// Write access to a mountpoint, held by a file open for writing.
+///
+/// While write access is held, the mountpoint cannot be made read-only.
+#[derive(Debug)]
+pub struct WriteAccess(Arc<MountPoint>);
+
+impl Drop for WriteAccess {
+	fn drop(&mut self) {
+		self.0.writers.fetch_sub(1, Release);
+	}
 }
 
 impl Drop for MountPoint {
@@ -212,14 +311,204 @@ impl Drop for MountPoint {
 pub static MOUNT_POINTS: Spin<HashMap<*const vfs::Entry, Arc<MountPoint>>> =
 	Spin::new(HashMap::new());
 
+/// Inserts `ent` and its ancestors in the children of their respective parents.
+///
+/// Filesystems that do not cache entries would otherwise lose the path to a mountpoint located
+/// on them, since it is looked up again on each resolution.
+fn pin_ancestors(ent: &Arc<vfs::Entry>) -> AllocResult<()> {
+	let mut cur = ent.clone();
+	while let Some(parent) = cur.parent.clone() {
+		let mut children = parent.children.write();
+		if children.get(cur.name.as_bytes()).is_some() {
+			break;
+		}
+		children.insert(EntryChild(cur))?;
+		drop(children);
+		cur = parent;
+	}
+	Ok(())
+}
+
+/// Returns the mountpoint containing `ent`, which is either the mountpoint of which `ent` is the
+/// root, or the one of the closest ancestor of `ent` that is the root of a mountpoint.
+fn containing_mountpoint(
+	mps: &HashMap<*const vfs::Entry, Arc<MountPoint>>,
+	ent: &Arc<vfs::Entry>,
+) -> Option<Arc<MountPoint>> {
+	let mut cur = Some(ent);
+	while let Some(ent) = cur {
+		if let Some(mp) = mps.get(&Arc::as_ptr(ent)) {
+			return Some(mp.clone());
+		}
+		cur = ent.parent.as_ref();
+	}
+	None
+}
+
+/// Returns the names of the components of the path from `ancestor` to `ent`.
+///
+/// If `ent` is `ancestor`, the list is empty. If `ancestor` is not an ancestor of `ent`, the
+/// function returns `None`.
+fn relative_path(
+	ent: &Arc<vfs::Entry>,
+	ancestor: &Arc<vfs::Entry>,
+) -> AllocResult<Option<Vec<String>>> {
+	let mut names = Vec::new();
+	let mut cur = ent;
+	while Arc::as_ptr(cur) != Arc::as_ptr(ancestor) {
+		let Some(parent) = &cur.parent else {
+			return Ok(None);
+		};
+		names.push(cur.name.try_clone()?)?;
+		cur = parent;
+	}
+	names.reverse();
+	Ok(Some(names))
+}
+
+/// Resolves the path made of the components `names`, starting from `root`.
+///
+/// Symbolic links are not followed. If a file does not exist, the function returns
+/// [`errno::ENOENT`].
+fn resolve_relative(root: &Arc<vfs::Entry>, names: &[String]) -> EResult<Arc<vfs::Entry>> {
+	let mut ent = root.clone();
+	for name in names {
+		ent = vfs::resolve_entry(&ent, name)?;
+		if ent.is_negative() {
+			return Err(errno!(ENOENT));
+		}
+	}
+	Ok(ent)
+}
+
+/// Attaches a mountpoint over `target`.
+///
+/// Arguments:
+/// - `source` is the source of the mountpoint
+/// - `fs` is the mounted filesystem
+/// - `root` is the node at the root of the mountpoint
+/// - `flags` are the mount flags
+/// - `propagation` is the propagation settings of the mountpoint
+/// - `target` is the entry to cover. If `None`, the mountpoint is root
+fn attach(
+	source: MountSource,
+	fs: Arc<Filesystem>,
+	root: Arc<Node>,
+	flags: u32,
+	propagation: Propagation,
+	target: Option<&Arc<vfs::Entry>>,
+) -> EResult<Arc<MountPoint>> {
+	let (name, parent) = match target {
+		Some(target) => (target.name.try_clone()?, target.parent.clone()),
+		None => (String::new(), None),
+	};
+	let mut mps = MOUNT_POINTS.lock();
+	// Create an entry for the root of the mountpoint
+	let root_entry = Arc::new(vfs::Entry::new(name, parent.clone(), Some(root)))?;
+	// Create mountpoint
+	let mountpoint = Arc::new(MountPoint {
+		flags: AtomicU32::new(flags),
+		source,
+		fs,
+		root_entry: root_entry.clone(),
+		writers: AtomicUsize::new(0),
+		propagation: Spin::new(propagation),
+	})?;
+	// If the next insertion fails, this will be undone by the implementation of `Drop`
+	mps.insert(Arc::as_ptr(&root_entry), mountpoint.clone())?;
+	// Replace `target` with the mountpoint's root in the tree
+	if let Some(target_parent) = &parent {
+		pin_ancestors(target_parent)?;
+		target_parent
+			.children
+			.write()
+			.insert(EntryChild(root_entry))?;
+	}
+	Ok(mountpoint)
+}
+
+/// Returns the mountpoints receiving the events happening on `target`, along with the location
+/// of `target` relative to the root of the mountpoint containing it.
+///
+/// Each mountpoint comes with a boolean telling whether it is a peer, or else a slave.
+///
+/// If the mountpoint containing `target` is not shared, the function returns no mountpoint.
+#[allow(clippy::type_complexity)]
+fn receivers(target: &Arc<vfs::Entry>) -> EResult<(Vec<(Arc<MountPoint>, bool)>, Vec<String>)> {
+	let mps = MOUNT_POINTS.lock();
+	let Some(parent_mp) = containing_mountpoint(&mps, target) else {
+		return Ok((Vec::new(), Vec::new()));
+	};
+	let Some(group) = parent_mp.propagation.lock().peer_group else {
+		return Ok((Vec::new(), Vec::new()));
+	};
+	let receivers = mps
+		.iter()
+		.filter(|(_, mp)| Arc::as_ptr(mp) != Arc::as_ptr(&parent_mp))
+		.filter_map(|(_, mp)| {
+			let propagation = mp.propagation.lock();
+			let peer = propagation.peer_group == Some(group);
+			(peer || propagation.master == Some(group)).then(|| (mp.clone(), peer))
+		})
+		.collect::<CollectResult<Vec<_>>>()
+		.0?;
+	let names = relative_path(target, &parent_mp.root_entry)?.unwrap_or_default();
+	Ok((receivers, names))
+}
+
+/// Propagates the mountpoint `mp`, which has just been attached over `target`, to the receivers
+/// of the mountpoint containing `target`.
+///
+/// `mp` is made shared with its copies. Targets that do not exist in a receiver are ignored.
+fn propagate_mount(mp: &Arc<MountPoint>, target: &Arc<vfs::Entry>) -> EResult<()> {
+	let (receivers, names) = receivers(target)?;
+	if receivers.is_empty() {
+		return Ok(());
+	}
+	let group = {
+		let mut propagation = mp.propagation.lock();
+		*propagation
+			.peer_group
+			.get_or_insert_with(|| NEXT_PEER_GROUP.fetch_add(1, Relaxed))
+	};
+	for (receiver, peer) in receivers {
+		// A bind mount may be a peer of the mountpoint containing it
+		if Arc::as_ptr(&receiver) == Arc::as_ptr(mp) {
+			continue;
+		}
+		let Ok(target) = resolve_relative(&receiver.root_entry, &names) else {
+			continue;
+		};
+		// The copy is a peer if the receiver is a peer, else a slave
+		let propagation = Propagation {
+			peer_group: peer.then_some(group),
+			master: (!peer).then_some(group),
+			unbindable: false,
+		};
+		attach(
+			mp.source.try_clone()?,
+			mp.fs.clone(),
+			mp.root_entry.node().clone(),
+			mp.flags(),
+			propagation,
+			Some(&target),
+		)?;
+	}
+	Ok(())
+}
+
 /// Creates a new mountpoint.
 ///
 /// If a mountpoint is already present at the same path, the function fails with [`errno::EINVAL`].
 ///
+/// If the mountpoint containing `target` is shared, the new mountpoint is propagated to its
+/// receivers.
+///
 /// Arguments:
 /// - `source` is the source of the mountpoint
 /// - `fs_type` is the filesystem type. If `None`, the function tries to detect it automatically
-/// - `flags` are the mount flags
+/// - `flags` are the mount flags. Flags outside of [`FLAGS_MASK`] are ignored
+/// - `data` is the filesystem-specific data
 /// - `target` is the target directory. If `None`, the mountpoint is root
 ///
 /// The function returns the root VFS entry of the mountpoint.
@@ -227,64 +516,277 @@ pub fn create(
 	source: MountSource,
 	fs_type: Option<Arc<dyn FilesystemType>>,
 	flags: u32,
+	data: &[u8],
 	target: Option<Arc<vfs::Entry>>,
 ) -> EResult<Arc<vfs::Entry>> {
+	let flags = flags & FLAGS_MASK;
 	// Get filesystem
-	let (target_path, name, parent) = match target {
-		Some(target) => (
-			vfs::Entry::get_path(&target)?,
-			target.name.try_clone()?,
-			target.parent.clone(),
-		),
-		None => (PathBuf::root()?, String::new(), None),
+	let target_path = match &target {
+		Some(target) => vfs::Entry::get_path(target)?,
+		None => PathBuf::root()?,
 	};
-	let fs = get_fs(&source, fs_type, target_path, flags & FLAG_RDONLY != 0)?;
-	let mut mps = MOUNT_POINTS.lock();
+	let fs = get_fs(
+		&source,
+		fs_type,
+		target_path,
+		flags & FLAG_RDONLY != 0,
+		data,
+	)?;
 	// TODO get root node from cache if present instead
 	// Get filesystem root node
 	let root = fs.ops.root(&fs)?;
-	// Create an entry for the root of the mountpoint
-	let root_entry = Arc::new(vfs::Entry::new(name, parent.clone(), Some(root)))?;
-	// Create mountpoint
-	let mountpoint = Arc::new(MountPoint {
-		flags,
+	let mp = attach(
 		source,
 		fs,
-		root_entry: root_entry.clone(),
-	})?;
-	// If the next insertion fails, this will be undone by the implementation of `Drop`
-	mps.insert(Arc::as_ptr(&root_entry), mountpoint)?;
-	// Replace `target` with the mountpoint's root in the tree
-	if let Some(target_parent) = &parent {
-		target_parent
-			.children
-			.lock()
-			.insert(EntryChild(root_entry.clone()))?;
+		root,
+		flags,
+		Propagation::default(),
+		target.as_ref(),
+	)?;
+	if let Some(target) = &target {
+		propagate_mount(&mp, target)?;
+	}
+	Ok(mp.root_entry.clone())
+}
+
+/// Creates a bind mount, making the file `source` accessible at `target`.
+///
+/// If `rec` is `true`, the mountpoints located beneath `source` are bound too, at the same
+/// location relative to `target`. Unbindable mountpoints are ignored.
+///
+/// If `source` is a directory and `target` is not, or conversely, the function returns
+/// [`errno::ENOTDIR`]. If the mountpoint containing `source` is unbindable, the function returns
+/// [`errno::EINVAL`].
+pub fn bind(source: &Arc<vfs::Entry>, target: &Arc<vfs::Entry>, rec: bool) -> EResult<()> {
+	let dir = source.get_type()? == FileType::Directory;
+	if unlikely(dir != (target.get_type()? == FileType::Directory)) {
+		return Err(errno!(ENOTDIR));
+	}
+	let mps = MOUNT_POINTS.lock();
+	let source_mp = containing_mountpoint(&mps, source).ok_or_else(|| errno!(EINVAL))?;
+	if unlikely(source_mp.propagation.lock().unbindable) {
+		return Err(errno!(EINVAL));
+	}
+	// Collect the mountpoints to bind beneath the source
+	let mut submounts = Vec::new();
+	if rec {
+		for (_, mp) in mps.iter() {
+			if Arc::as_ptr(mp) == Arc::as_ptr(&source_mp) || mp.propagation.lock().unbindable {
+				continue;
+			}
+			let Some(names) = relative_path(&mp.root_entry, source)? else {
+				continue;
+			};
+			submounts.push((mp.clone(), names))?;
+		}
 	}
-	Ok(root_entry)
+	drop(mps);
+	// Parents are bound before their children
+	submounts.sort_unstable_by_key(|(_, names)| names.len());
+	let propagation = source_mp.propagation.lock().copy();
+	let mp = attach(
+		source_mp.source.try_clone()?,
+		source_mp.fs.clone(),
+		source.node().clone(),
+		source_mp.flags(),
+		propagation,
+		Some(target),
+	)?;
+	for (sub, names) in submounts {
+		let Ok(sub_target) = resolve_relative(&mp.root_entry, &names) else {
+			continue;
+		};
+		let propagation = sub.propagation.lock().copy();
+		attach(
+			sub.source.try_clone()?,
+			sub.fs.clone(),
+			sub.root_entry.node().clone(),
+			sub.flags(),
+			propagation,
+			Some(&sub_target),
+		)?;
+	}
+	propagate_mount(&mp, target)
+}
+
+/// Sets the propagation type of the mountpoint `mp` to `ty`.
+///
+/// If `rec` is `true`, the propagation type of the mountpoints located beneath `mp` is changed
+/// too.
+pub fn set_propagation(mp: &Arc<MountPoint>, ty: PropagationType, rec: bool) -> EResult<()> {
+	let mps = MOUNT_POINTS.lock();
+	for (_, cur) in mps.iter() {
+		let beneath = rec && relative_path(&cur.root_entry, &mp.root_entry)?.is_some();
+		if Arc::as_ptr(cur) != Arc::as_ptr(mp) && !beneath {
+			continue;
+		}
+		let mut propagation = cur.propagation.lock();
+		match ty {
+			PropagationType::Private => *propagation = Propagation::default(),
+			PropagationType::Shared => {
+				propagation
+					.peer_group
+					.get_or_insert_with(|| NEXT_PEER_GROUP.fetch_add(1, Relaxed));
+				propagation.unbindable = false;
+			}
+			PropagationType::Slave => {
+				// A mountpoint without peers becomes private
+				if let Some(group) = propagation.peer_group.take() {
+					propagation.master = Some(group);
+				}
+				propagation.unbindable = false;
+			}
+			PropagationType::Unbindable => {
+				*propagation = Propagation {
+					unbindable: true,
+					..Default::default()
+				}
+			}
+		}
+	}
+	Ok(())
+}
+
+/// Changes the flags of the mountpoint `mp` to `flags`.
+///
+/// If `bind` is `false`, the read-only state of the mounted filesystem is changed too, which
+/// affects all the mountpoints of the filesystem. Else, only `mp` is affected.
+///
+/// Flags are checked on each access to a file, so the change takes effect immediately.
+pub fn remount(mp: &MountPoint, flags: u32, bind: bool) -> EResult<()> {
+	let flags = flags & FLAGS_MASK;
+	let prev = {
+		let mps = MOUNT_POINTS.lock();
+		// Files open for writing prevent making the mountpoint read-only. Without `bind`, this
+		// applies to all the mountpoints of the filesystem
+		if flags & FLAG_RDONLY != 0 {
+			let busy = if bind {
+				mp.has_writers()
+			} else {
+				mps.iter()
+					.any(|(_, m)| Arc::as_ptr(&m.fs) == Arc::as_ptr(&mp.fs) && m.has_writers())
+			};
+			if unlikely(busy) {
+				return Err(errno!(EBUSY));
+			}
+		}
+		// Setting the flags while the lock is held prevents new writers in between
+		mp.flags.swap(flags, AcqRel)
+	};
+	if !bind && let Err(e) = mp.fs.ops.remount(flags & FLAG_RDONLY != 0) {
+		mp.flags.store(prev, Release);
+		return Err(e);
+	}
+	Ok(())
+}
+
+/// Sets the flags `set` and clears the flags `clear` on the mountpoint `mp`.
+///
+/// If `rec` is `true`, the mountpoints located beneath `mp` are changed too. The change is
+/// atomic with respect to other mountpoint operations.
+pub fn set_attr(mp: &Arc<MountPoint>, set: u32, clear: u32, rec: bool) -> EResult<()> {
+	let mps = MOUNT_POINTS.lock();
+	// Select the mountpoints first so that a failure leaves all of them unchanged
+	let mut targets = Vec::new();
+	for (_, cur) in mps.iter() {
+		let beneath = rec && relative_path(&cur.root_entry, &mp.root_entry)?.is_some();
+		if Arc::as_ptr(cur) == Arc::as_ptr(mp) || beneath {
+			// Files open for writing prevent making the mountpoint read-only
+			if unlikely(set & FLAG_RDONLY != 0 && cur.has_writers()) {
+				return Err(errno!(EBUSY));
+			}
+			targets.push(cur)?;
+		}
+	}
+	for cur in targets {
+		let flags = (cur.flags() & !clear | set) & FLAGS_MASK;
+		cur.flags.store(flags, Release);
+	}
+	Ok(())
+}
+
+/// Detaches the mountpoint `mp` from the VFS.
+fn detach(mp: &MountPoint) {
+	let root = &mp.root_entry;
+	if let Some(parent) = &root.parent {
+		let mut children = parent.children.write();
+		// Another mountpoint may have been stacked on top of this one
+		if children
+			.get(root.name.as_bytes())
+			.is_some_and(|ent| Arc::as_ptr(&ent.0) == Arc::as_ptr(root))
+		{
+			children.remove(root.name.as_bytes());
+		}
+	}
+	// TODO release node and children
+	MOUNT_POINTS.lock().remove(&Arc::as_ptr(root));
 }
 
 /// Removes the mountpoint at the given `target` entry.
 ///
 /// Data is synchronized to the associated storage device, if any, before removing the mountpoint.
 ///
+/// If the mountpoint containing `target` is shared, the copies of the mountpoint at the same
+/// location in its receivers are removed too.
+///
 /// If `target` is not a mountpoint, the function returns [`errno::EINVAL`].
 ///
 /// If the mountpoint is busy, the function returns [`errno::EBUSY`].
 pub fn remove(target: Arc<vfs::Entry>) -> EResult<()> {
 	// TODO Check if another mount point is present in a subdirectory (EBUSY)
 	// TODO Check if busy (EBUSY)
-	// Detach entry from parent
 	let Some(parent) = &target.parent else {
 		// Cannot unmount root filesystem
 		return Err(errno!(EINVAL));
 	};
-	parent.children.lock().remove(target.name.as_bytes());
-	// TODO release node and children
-	MOUNT_POINTS.lock().remove(&Arc::as_ptr(&target));
+	let mp = from_entry(&target).ok_or_else(|| errno!(EINVAL))?;
+	// Remove copies from receivers
+	let (receivers, mut names) = receivers(parent)?;
+	names.push(target.name.try_clone()?)?;
+	for (receiver, _) in receivers {
+		let Ok(copy) = resolve_relative(&receiver.root_entry, &names) else {
+			continue;
+		};
+		if let Some(copy) =
+			from_entry(&copy).filter(|copy| Arc::as_ptr(&copy.fs) == Arc::as_ptr(&mp.fs))
+		{
+			detach(&copy);
+		}
+	}
+	detach(&mp);
+	// Quota files hold a reference to the filesystem. Release them when it is no longer mounted
+	let mounted = MOUNT_POINTS
+		.lock()
+		.iter()
+		.any(|(_, m)| Arc::as_ptr(&m.fs) == Arc::as_ptr(&mp.fs));
+	if !mounted && let Some(quota) = mp.fs.ops.quota() {
+		quota.disable_all()?;
+	}
 	Ok(())
 }
 
+/// Takes write access to the mountpoint containing `ent`, for a file open for writing.
+///
+/// If `ent` is not on a mountpoint, the function returns `None`. If the mountpoint is read-only,
+/// the function returns [`errno::EROFS`].
+pub fn get_write_access(ent: &Arc<vfs::Entry>) -> EResult<Option<WriteAccess>> {
+	let mps = MOUNT_POINTS.lock();
+	let Some(mp) = containing_mountpoint(&mps, ent) else {
+		return Ok(None);
+	};
+	if unlikely(mp.flags() & FLAG_RDONLY != 0) {
+		return Err(errno!(EROFS));
+	}
+	mp.writers.fetch_add(1, Acquire);
+	Ok(Some(WriteAccess(mp)))
+}
+
+/// Returns the flags of the mountpoint containing `ent`.
+pub fn get_flags(ent: &Arc<vfs::Entry>) -> u32 {
+	let mps = MOUNT_POINTS.lock();
+	containing_mountpoint(&mps, ent).map_or(0, |mp| mp.flags())
+}
+
 /// Returns the mountpoint for the root entry `ent`.
 ///
 /// If `ent` is not associated to a mountpoint, the function returns `None`.
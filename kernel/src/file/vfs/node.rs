@@ -22,7 +22,7 @@ use crate::{
 	file::{
 		FileType, INode, Stat,
 		fs::{FileOps, Filesystem, NodeOps},
-		lock::Flock,
+		lock::{Flock, RecordLocks},
 	},
 	memory::{cache::MappedNode, user::UserSlice},
 	sync::{mutex::Mutex, spin::Spin},
@@ -62,6 +62,8 @@ pub struct Node {
 
 	/// BSD flavour advisory lock state
 	pub flock: Flock,
+	/// POSIX record locks
+	pub record_locks: RecordLocks,
 
 	/// LRU node
 	lru: ListNode,
@@ -96,6 +98,7 @@ impl Node {
 			mapped: Default::default(),
 
 			flock: Default::default(),
+			record_locks: Default::default(),
 
 			lru: Default::default(),
 		}
@@ -59,6 +59,7 @@ mod boot;
 pub mod cmdline;
 #[macro_use]
 pub mod config;
+pub mod crash;
 pub mod debug;
 pub mod device;
 pub mod elf;
@@ -79,6 +80,7 @@ pub mod rand;
 pub mod selftest;
 pub mod sync;
 pub mod syscall;
+pub mod sysctl;
 pub mod time;
 #[cfg(config_tty_enabled)]
 pub mod tty;
@@ -86,8 +88,8 @@ pub mod tty;
 use crate::{
 	arch::x86::{idt::IntFrame, smp},
 	file::{
-		fs::{float, initramfs},
-		vfs,
+		fs::{ext2, float, initramfs},
+		readahead, vfs,
 	},
 	memory::{cache, vmem},
 	process::{
@@ -96,11 +98,13 @@ use crate::{
 		scheduler,
 		scheduler::{cpu::CPU, switch, switch::idle_task},
 	},
-	sync::spin::Spin,
+	sync::{rcu, spin::Spin},
 };
 use core::{ffi::c_void, sync::atomic::Ordering::Release};
+pub use macros::module_params;
 pub use utils;
 use utils::{
+	TryClone,
 	collections::{path::Path, string::String, vec::Vec},
 	errno::EResult,
 	vec,
@@ -110,6 +114,11 @@ use utils::{
 pub const NAME: &str = env!("CARGO_PKG_NAME");
 /// Current kernel version.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// The kernel's release, which is [`VERSION`] followed by the git commit the kernel has been built
+/// from.
+pub const RELEASE: &str = env!("KERNEL_RELEASE");
+/// Information about the kernel build, including the build time.
+pub const BUILD_VERSION: &str = env!("KERNEL_BUILD_VERSION");
 
 /// The path to the init process binary.
 const INIT_PATH: &[u8] = b"/sbin/init";
@@ -127,9 +136,10 @@ fn init(init_path: String) -> EResult<IntFrame> {
 	{
 		let path = Path::new(&init_path)?;
 		let ent = vfs::get_file_from_path(path, true)?;
+		let (ent, argv) = exec::script::resolve(ent, init_path.try_clone()?, vec![init_path]?)?;
 		let program_image = exec::elf::exec(
 			ent,
-			vec![init_path]?,
+			argv,
 			vec![
 				b"PATH=/bin:/sbin:/usr/bin:/usr/sbin:/usr/local/bin:/usr/local/sbin".try_into()?,
 				b"TERM=maestro".try_into()?,
@@ -156,10 +166,12 @@ fn kernel_main_inner(magic: u32, multiboot_ptr: *const c_void) {
 	// initialized
 
 	let fb = tty::show(boot_info).expect("TTY initialization failed");
-	println!("Boot {NAME} version {VERSION}");
+	println!("Boot {NAME} version {RELEASE} {BUILD_VERSION}");
 
 	// Init kernel symbols map
 	elf::kernel::init().expect("cannot initialize kernel symbols map");
+	sysctl::init().expect("sysctl initialization failed");
+	file::fs::debug::init().expect("debugfs initialization failed");
 
 	// Necessary for selftesting
 	float::init().expect("floatfs initialization failed");
@@ -171,6 +183,12 @@ fn kernel_main_inner(magic: u32, multiboot_ptr: *const c_void) {
 	let cmdline = boot_info.cmdline.unwrap_or_default();
 	let args_parser = cmdline::ArgsParser::parse(cmdline).expect("could not parse command line");
 	logger::SILENT.store(args_parser.is_silent(), Release);
+	if args_parser.is_init_on_alloc() {
+		memory::INIT_ON_ALLOC.store(true, Release);
+	}
+	if args_parser.is_init_on_free() {
+		memory::INIT_ON_FREE.store(true, Release);
+	}
 
 	println!("Find ACPI structures");
 	acpi::init().expect("ACPI initialization failed");
@@ -188,23 +206,35 @@ fn kernel_main_inner(magic: u32, multiboot_ptr: *const c_void) {
 
 	println!("Setup devices management");
 	device::init().expect("devices management initialization failed");
-	net::osi::init().expect("network initialization failed");
+	if let Some(size) = args_parser.get_ramdisk_size() {
+		device::storage::ramdisk::create(size).expect("RAM disk creation failed");
+	}
+	net::init().expect("network initialization failed");
 	rand::init().expect("entropy pool initialization failed");
 
+	if let Some(target) = args_parser.get_crashdump() {
+		crash::init(target, args_parser.is_crashdump_mem()).expect("crash dump setup failed");
+	}
+
 	let root = args_parser.get_root_dev();
 	println!("Setup files management");
 	file::init(root).expect("files management initialization failed");
+	let mut init_path = args_parser.get_init_path();
 	if let Some(initramfs) = boot_info.initramfs {
 		println!("Load initramfs");
 		initramfs::load(initramfs).expect("initramfs loading failed");
+		// Like on Linux, the initramfs may provide its own init program
+		if init_path.is_none() && initramfs::has_init() {
+			init_path = Some(initramfs::INIT_PATH);
+		}
 	}
 
 	process::init2().expect("process initialization stage 2 failed");
 	device::stage2(fb).expect("device files creation failure");
 	process::init3().expect("process initialization stage 3 failed");
+	module::kmod::init().expect("module requests initialization failed");
 
-	let init_path = args_parser.get_init_path().unwrap_or(INIT_PATH);
-	let init_path = String::try_from(init_path).unwrap();
+	let init_path = String::try_from(init_path.unwrap_or(INIT_PATH)).unwrap();
 	println!("Execute init process ({init_path})");
 	let init_frame = init(init_path).expect("init process execution failed");
 
@@ -214,6 +244,11 @@ fn kernel_main_inner(magic: u32, multiboot_ptr: *const c_void) {
 			.expect("rebalance task launch failed");
 	}
 	Process::new_kthread(None, cache::flush_task, true).expect("cache flush task launch failed");
+	Process::new_kthread(None, readahead::readahead_task, true)
+		.expect("readahead task launch failed");
+	Process::new_kthread(None, ext2::commit_task, true)
+		.expect("journal commit task launch failed");
+	Process::new_kthread(None, rcu::reclaim_task, true).expect("RCU reclaim task launch failed");
 
 	unsafe {
 		switch::init_ctx(&init_frame);
@@ -18,35 +18,155 @@
 
 //! Kernel logging
 //!
+//! Logs are stored in a ring buffer, each line being prefixed with its priority and timestamp in
+//! the format `<priority>[seconds.microseconds] `, so that userspace can retrieve them with the
+//! `syslog` system call.
+//!
+//! Each line is a record identified by a sequence number. `/dev/kmsg` readers walk through
+//! records using a [`RecordCursor`], independently of each other.
+//!
 //! If the logger is set as silent, logs will not show up on screen, but will be kept in memory
 //! anyway.
 
-use crate::{device::serial, sync::spin::IntSpin, tty::TTY};
+use crate::{
+	device::serial,
+	memory::user::UserSlice,
+	sync::spin::IntSpin,
+	time::{
+		clock::{Clock, current_time_ns},
+		sleep_for,
+	},
+	tty,
+};
 use core::{
-	cmp::{Ordering, min},
+	cmp::min,
 	fmt,
 	fmt::Write,
-	sync::atomic::{AtomicBool, Ordering::Relaxed},
+	sync::atomic::{AtomicBool, AtomicU8, Ordering::Relaxed},
 };
+use utils::{collections::vec::Vec, errno, errno::EResult};
 
 /// The size of the kernel logs buffer in bytes.
-const LOGS_SIZE: usize = 1048576;
+pub const LOGS_SIZE: usize = 1048576;
+
+/// The level of a log message, from the most to the least important.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[repr(u8)]
+pub enum Level {
+	/// The system is unusable.
+	Emerg = 0,
+	/// Action must be taken immediately.
+	Alert,
+	/// Critical conditions.
+	Crit,
+	/// Error conditions.
+	Err,
+	/// Warning conditions.
+	Warning,
+	/// Normal but significant condition.
+	Notice,
+	/// Informational.
+	Info,
+	/// Debug-level messages.
+	Debug,
+}
+
+impl TryFrom<u8> for Level {
+	type Error = ();
+
+	fn try_from(n: u8) -> Result<Self, Self::Error> {
+		let level = match n {
+			0 => Self::Emerg,
+			1 => Self::Alert,
+			2 => Self::Crit,
+			3 => Self::Err,
+			4 => Self::Warning,
+			5 => Self::Notice,
+			6 => Self::Info,
+			7 => Self::Debug,
+			_ => return Err(()),
+		};
+		Ok(level)
+	}
+}
+
+/// Facility of messages emitted by the kernel.
+pub const LOG_KERN: u8 = 0;
+/// Facility of messages emitted by userspace.
+pub const LOG_USER: u8 = 1;
+
+/// The level of messages printed without an explicit level.
+pub const DEFAULT_LEVEL: Level = Level::Info;
+/// The default console log level, allowing every message to be printed.
+pub const DEFAULT_CONSOLE_LEVEL: u8 = Level::Debug as u8 + 1;
+
+/// The interval at which logs are polled by [`consume`], in nanoseconds.
+const CONSUME_POLL_INTERVAL: u64 = 100_000_000;
+/// The maximum length of a record returned by [`read_record`], in bytes.
+///
+/// Longer records are truncated.
+pub const RECORD_MAX_LEN: usize = 8192;
 
 /// Tells whether the logger is silent.
 pub static SILENT: AtomicBool = AtomicBool::new(false);
+/// Only messages with a level strictly lower than this value are printed on the console.
+pub static CONSOLE_LEVEL: AtomicU8 = AtomicU8::new(DEFAULT_CONSOLE_LEVEL);
 /// The kernel's logger.
 pub static BUF: IntSpin<LoggerBuffer> = IntSpin::new(LoggerBuffer::new());
 
+/// Writer formatting into a fixed-size buffer, failing if it is too small.
+struct SliceWriter<'b> {
+	buf: &'b mut [u8],
+	len: usize,
+}
+
+impl Write for SliceWriter<'_> {
+	fn write_str(&mut self, s: &str) -> fmt::Result {
+		let end = self.len + s.len();
+		if end > self.buf.len() {
+			return Err(fmt::Error);
+		}
+		self.buf[self.len..end].copy_from_slice(s.as_bytes());
+		self.len = end;
+		Ok(())
+	}
+}
+
+/// The position of a reader in the kernel logs, pointing to a record.
+#[derive(Clone, Copy, Debug)]
+pub struct RecordCursor {
+	/// The sequence number of the record.
+	seq: u64,
+	/// The offset of the record since boot.
+	off: u64,
+}
+
 /// Kernel logger, used to print/store kernel logs.
 ///
 /// Internally, the logger uses a ring buffer for storage.
 pub struct LoggerBuffer {
 	/// The buffer storing the kernel logs.
 	buf: [u8; LOGS_SIZE],
-	/// The buffer's reading head.
-	read_head: usize,
-	/// The buffer's writing head.
-	write_head: usize,
+	/// The offset of the oldest byte in the buffer, since boot.
+	start: u64,
+	/// The offset of the end of the data in the buffer, since boot.
+	end: u64,
+	/// The offset up to which logs have been consumed by `syslog`, since boot.
+	consumed: u64,
+
+	/// The sequence number of the oldest record in the buffer.
+	first_seq: u64,
+	/// The sequence number of the record being written.
+	next_seq: u64,
+	/// The offset of the record being written, since boot.
+	line_off: u64,
+
+	/// The facility of the message being written.
+	facility: u8,
+	/// The level of the message being written.
+	level: Level,
+	/// Tells whether the next byte to be written is at the beginning of a line.
+	line_start: bool,
 }
 
 impl LoggerBuffer {
@@ -55,75 +175,343 @@ impl LoggerBuffer {
 	pub const fn new() -> Self {
 		Self {
 			buf: [0; LOGS_SIZE],
-			read_head: 0,
-			write_head: 0,
+			start: 0,
+			end: 0,
+			consumed: 0,
+
+			first_seq: 0,
+			next_seq: 0,
+			line_off: 0,
+
+			facility: LOG_KERN,
+			level: DEFAULT_LEVEL,
+			line_start: true,
 		}
 	}
 
-	/// Returns the number of available bytes in the buffer.
-	fn available_space(&self) -> usize {
-		match self.write_head.cmp(&self.read_head) {
-			Ordering::Equal => self.buf.len(),
-			Ordering::Greater => self.buf.len() - (self.write_head - self.read_head),
-			Ordering::Less => self.read_head - self.write_head - 1,
-		}
+	/// Returns the number of bytes of logs stored in the buffer.
+	pub fn len(&self) -> usize {
+		(self.end - self.start) as _
 	}
 
-	// FIXME: this function is useless because the actual buffer may be split in half (ring buffer)
-	/// Returns a reference to a slice containing the logs stored into the
-	/// logger's buffer.
-	pub fn get_content(&self) -> &[u8] {
-		&self.buf
+	/// Tells whether the buffer is empty.
+	pub fn is_empty(&self) -> bool {
+		self.end == self.start
 	}
 
-	/// Pushes the given string onto the kernel logs buffer.
-	pub fn push(&mut self, s: &[u8]) {
-		if self.available_space() < s.len() {
-			self.pop(s.len() - self.available_space());
+	/// Returns the number of bytes which have not been consumed by [`Self::consume`] yet.
+	pub fn unread_len(&self) -> usize {
+		(self.end - self.consumed.max(self.start)) as _
+	}
+
+	/// Copies logs to `buf`, starting at offset `off` in the stored logs.
+	///
+	/// The function returns the number of bytes copied.
+	pub fn read(&self, off: usize, buf: &mut [u8]) -> usize {
+		self.read_abs(self.start + off as u64, buf)
+	}
+
+	/// Copies the last logs to `buf`, filling it as much as possible.
+	///
+	/// The function returns the number of bytes copied.
+	pub fn read_last(&self, buf: &mut [u8]) -> usize {
+		let off = self.len().saturating_sub(buf.len());
+		self.read(off, buf)
+	}
+
+	/// Copies logs that have not been consumed yet to `buf`, then marks them as consumed.
+	///
+	/// The function returns the number of bytes copied.
+	pub fn consume(&mut self, buf: &mut [u8]) -> usize {
+		let off = self.consumed.max(self.start);
+		let len = self.read_abs(off, buf);
+		self.consumed = off + len as u64;
+		len
+	}
+
+	/// Removes all the logs from the buffer.
+	///
+	/// If a line is being written, its beginning is kept.
+	pub fn clear(&mut self) {
+		self.start = self.line_off;
+		self.first_seq = self.next_seq;
+	}
+
+	/// Returns a cursor to the oldest record in the buffer.
+	pub fn first_record(&self) -> RecordCursor {
+		RecordCursor {
+			seq: self.first_seq,
+			off: self.start,
 		}
+	}
 
-		let len = min(self.available_space(), s.len());
-		let end = (self.write_head + len) % self.buf.len();
-		if end < self.write_head {
-			self.buf[self.write_head..].copy_from_slice(&s[0..(len - end)]);
-			self.buf[0..end].copy_from_slice(&s[(len - end)..]);
-		} else {
-			self.buf[self.write_head..end].copy_from_slice(&s[0..len]);
+	/// Returns a cursor to the next record to be written.
+	pub fn end_record(&self) -> RecordCursor {
+		RecordCursor {
+			seq: self.next_seq,
+			off: self.line_off,
 		}
-		self.write_head = end;
 	}
 
-	/// Pops at least `n` characters from the buffer. If the popping `n`
-	/// characters result in cutting a line, the function shall pop the full
-	/// line.
-	fn pop(&mut self, n: usize) {
-		let read_new = (self.read_head + n) % self.buf.len();
-		if read_new >= self.write_head && read_new < self.read_head {
-			self.read_head = self.write_head;
-			return;
+	/// Returns the byte at the offset since boot `off`.
+	fn byte_at(&self, off: u64) -> u8 {
+		self.buf[(off % LOGS_SIZE as u64) as usize]
+	}
+
+	/// Parses the prefix of the record at the offset since boot `off`.
+	///
+	/// On success, the function returns the priority of the record, its timestamp in microseconds
+	/// and the offset of its text. If the prefix is invalid, the function returns `None`.
+	fn parse_prefix(&self, mut off: u64) -> Option<(u8, u64, u64)> {
+		let expect = |c: u8, off: &mut u64| {
+			let valid = *off < self.end && self.byte_at(*off) == c;
+			*off += valid as u64;
+			valid
+		};
+		let number = |off: &mut u64| {
+			let mut n: u64 = 0;
+			while *off < self.end && self.byte_at(*off).is_ascii_digit() {
+				n = n
+					.checked_mul(10)?
+					.checked_add((self.byte_at(*off) - b'0') as u64)?;
+				*off += 1;
+			}
+			Some(n)
+		};
+		if !expect(b'<', &mut off) {
+			return None;
+		}
+		let prio = number(&mut off)?.try_into().ok()?;
+		if !expect(b'>', &mut off) || !expect(b'[', &mut off) {
+			return None;
+		}
+		while expect(b' ', &mut off) {}
+		let sec = number(&mut off)?;
+		if !expect(b'.', &mut off) {
+			return None;
 		}
+		let usec = number(&mut off)?;
+		if !expect(b']', &mut off) || !expect(b' ', &mut off) {
+			return None;
+		}
+		Some((prio, sec.checked_mul(1_000_000)?.checked_add(usec)?, off))
+	}
 
-		let mut i = 0;
-		while i < self.buf.len() {
-			let off = (read_new + i) % self.buf.len();
-			if off >= self.write_head || self.buf[off] == b'\n' {
+	/// Formats the record pointed to by `cursor` into `buf`, then moves the cursor to the next
+	/// record.
+	///
+	/// The record is formatted as `priority,sequence,timestamp,-;text\n`, the timestamp being in
+	/// microseconds and non-printable characters of the text being escaped as `\xNN`.
+	///
+	/// If no record is available yet, the function returns `None`.
+	///
+	/// Errors:
+	/// - [`errno::EPIPE`]: the record has been overwritten. The cursor is moved to the oldest
+	///   record
+	/// - [`errno::EINVAL`]: `buf` is too small to fit the record
+	pub fn read_record(
+		&self,
+		cursor: &mut RecordCursor,
+		buf: &mut [u8],
+	) -> EResult<Option<usize>> {
+		if cursor.seq < self.first_seq || cursor.off < self.start {
+			*cursor = self.first_record();
+			return Err(errno!(EPIPE));
+		}
+		if cursor.seq >= self.next_seq {
+			return Ok(None);
+		}
+		// If the prefix is invalid, output the whole line
+		let (prio, ts, mut off) =
+			self.parse_prefix(cursor.off)
+				.unwrap_or((DEFAULT_LEVEL as u8, 0, cursor.off));
+		let mut w = SliceWriter {
+			buf,
+			len: 0,
+		};
+		write!(w, "{prio},{},{ts},-;", cursor.seq).map_err(|_| errno!(EINVAL))?;
+		// Copy text, escaping non-printable characters
+		let text_max = w.buf.len().min(RECORD_MAX_LEN).saturating_sub(1);
+		while off < self.end {
+			let c = self.byte_at(off);
+			off += 1;
+			if c == b'\n' {
+				break;
+			}
+			let res = if !(b' '..0x7f).contains(&c) || c == b'\\' {
+				write!(w, "\\x{c:02x}")
+			} else {
+				w.write_char(c as char)
+			};
+			if res.is_err() || w.len > text_max {
+				// Truncate the record only if the buffer is large enough to fit a record
+				if w.buf.len() < RECORD_MAX_LEN {
+					return Err(errno!(EINVAL));
+				}
+				w.len = w.len.min(text_max);
+				// Skip the rest of the line
+				while off < self.end && self.byte_at(off) != b'\n' {
+					off += 1;
+				}
+				off += 1;
 				break;
 			}
-			i += 1;
 		}
+		w.write_char('\n').map_err(|_| errno!(EINVAL))?;
+		cursor.seq += 1;
+		cursor.off = off;
+		Ok(Some(w.len))
+	}
+
+	/// Copies logs to `buf`, starting at the offset since boot `off`.
+	fn read_abs(&self, off: u64, buf: &mut [u8]) -> usize {
+		if off >= self.end {
+			return 0;
+		}
+		let len = min((self.end - off) as usize, buf.len());
+		let begin = (off % LOGS_SIZE as u64) as usize;
+		let first = min(len, LOGS_SIZE - begin);
+		buf[..first].copy_from_slice(&self.buf[begin..(begin + first)]);
+		buf[first..len].copy_from_slice(&self.buf[..(len - first)]);
+		len
+	}
+
+	/// Pushes the given string onto the kernel logs buffer.
+	///
+	/// If the buffer is full, the oldest lines are discarded.
+	fn push(&mut self, s: &[u8]) {
+		// Only the end of the data fits in the buffer
+		let s = &s[s.len().saturating_sub(LOGS_SIZE)..];
+		let new_end = self.end + s.len() as u64;
+		if new_end - self.start > LOGS_SIZE as u64 {
+			self.discard(new_end - LOGS_SIZE as u64);
+		}
+		let begin = (self.end % LOGS_SIZE as u64) as usize;
+		let first = min(s.len(), LOGS_SIZE - begin);
+		self.buf[begin..(begin + first)].copy_from_slice(&s[..first]);
+		self.buf[..(s.len() - first)].copy_from_slice(&s[first..]);
+		self.end = new_end;
+	}
+
+	/// Discards the oldest logs up to the offset since boot `off`, without cutting a line if
+	/// possible.
+	fn discard(&mut self, off: u64) {
+		let mut start = self.start;
+		while start < off {
+			let newline = (start..self.end).find(|i| self.byte_at(*i) == b'\n');
+			match newline {
+				Some(newline) => {
+					start = newline + 1;
+					self.first_seq += 1;
+				}
+				// The line being written is too long to fit entirely
+				None => {
+					start = off;
+					self.first_seq = self.next_seq;
+					break;
+				}
+			}
+		}
+		self.start = start;
+		self.line_off = self.line_off.max(start);
+	}
 
-		self.read_head = (read_new + i) % self.buf.len();
+	/// Writes a message with level `level`.
+	pub fn write_level(&mut self, level: Level, args: fmt::Arguments) {
+		self.write_record(LOG_KERN, level, args);
+	}
+
+	/// Writes a message with facility `facility` and level `level`.
+	pub fn write_record(&mut self, facility: u8, level: Level, args: fmt::Arguments) {
+		self.facility = facility;
+		self.level = level;
+		fmt::write(self, args).ok();
+		self.facility = LOG_KERN;
+		self.level = DEFAULT_LEVEL;
 	}
 }
 
 impl Write for LoggerBuffer {
 	fn write_str(&mut self, s: &str) -> fmt::Result {
-		self.push(s.as_bytes());
-		if !SILENT.load(Relaxed) {
+		for line in s.split_inclusive('\n') {
+			if self.line_start {
+				let ts = current_time_ns(Clock::Boottime) / 1000;
+				let prio = (self.facility << 3) | self.level as u8;
+				let mut prefix = [0; 40];
+				let mut w = SliceWriter {
+					buf: &mut prefix,
+					len: 0,
+				};
+				let (sec, usec) = (ts / 1_000_000, ts % 1_000_000);
+				write!(w, "<{prio}>[{sec:5}.{usec:06}] ")?;
+				let len = w.len;
+				self.push(&prefix[..len]);
+			}
+			self.push(line.as_bytes());
+			self.line_start = line.ends_with('\n');
+			if self.line_start {
+				self.next_seq += 1;
+				self.line_off = self.end;
+			}
+		}
+		let console = !SILENT.load(Relaxed) && (self.level as u8) < CONSOLE_LEVEL.load(Relaxed);
+		if console {
 			// TODO Add a compilation and/or runtime option for this
 			serial::PORTS[0].lock().write(s.as_bytes());
-			TTY.write(s.as_bytes());
+			tty::console().write(s.as_bytes());
 		}
 		Ok(())
 	}
 }
+
+/// Reads logs with `f` into an intermediate buffer, then copies it to `buf`.
+///
+/// This allows not to hold the logger's lock while accessing userspace.
+pub fn copy_to_user<F: FnOnce(&mut LoggerBuffer, &mut [u8]) -> usize>(
+	buf: UserSlice<u8>,
+	f: F,
+) -> EResult<usize> {
+	let mut tmp = Vec::new();
+	tmp.resize(min(buf.len(), LOGS_SIZE), 0)?;
+	let len = f(&mut BUF.lock(), &mut tmp);
+	buf.copy_to_user(0, &tmp[..len])
+}
+
+/// Formats the record pointed to by `cursor` into `buf`, then moves the cursor to the next record.
+///
+/// If no record is available, the function waits until one is, unless `nonblock` is set, in
+/// which case it returns [`errno::EAGAIN`].
+///
+/// For more details, see [`LoggerBuffer::read_record`].
+pub fn read_record(
+	cursor: &mut RecordCursor,
+	buf: UserSlice<u8>,
+	nonblock: bool,
+) -> EResult<usize> {
+	let mut tmp = Vec::new();
+	tmp.resize(min(buf.len(), RECORD_MAX_LEN), 0)?;
+	loop {
+		if let Some(len) = BUF.lock().read_record(cursor, &mut tmp)? {
+			return buf.copy_to_user(0, &tmp[..len]);
+		}
+		if nonblock {
+			return Err(errno!(EAGAIN));
+		}
+		// TODO wake up readers when new logs are written instead of polling
+		sleep_for(Clock::Monotonic, CONSUME_POLL_INTERVAL, &mut 0)?;
+	}
+}
+
+/// Copies logs that have not been consumed yet to `buf`, then marks them as consumed.
+///
+/// If no log is available, the function waits until some are.
+pub fn consume(buf: UserSlice<u8>) -> EResult<usize> {
+	if buf.is_empty() {
+		return Ok(0);
+	}
+	// TODO wake up readers when new logs are written instead of polling
+	while BUF.lock().unread_len() == 0 {
+		sleep_for(Clock::Monotonic, CONSUME_POLL_INTERVAL, &mut 0)?;
+	}
+	copy_to_user(buf, |logs, buf| logs.consume(buf))
+}
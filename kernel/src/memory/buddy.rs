@@ -24,8 +24,11 @@
 //! The order of a frame is the `n` in the expression `pow(2, n)` that represents the
 //! size of a frame in pages.
 
-use super::{PhysAddr, VirtAddr, oom, stats};
-use crate::sync::{atomic::AtomicU64, spin::IntSpin};
+use super::{INIT_ON_ALLOC, INIT_ON_FREE, PhysAddr, VirtAddr, oom, stats, vmem};
+use crate::{
+	file::fs::debug::ShowFile,
+	sync::{atomic::AtomicU64, spin::IntSpin},
+};
 use core::{
 	alloc::AllocError,
 	hint::unlikely,
@@ -36,7 +39,7 @@ use core::{
 	slice,
 	sync::atomic::{AtomicBool, Ordering::Relaxed},
 };
-use utils::{errno::AllocResult, limits::PAGE_SIZE, math};
+use utils::{errno::AllocResult, limits::PAGE_SIZE, math, try_write, try_writeln};
 
 /// The order of a memory frame.
 pub type FrameOrder = u8;
@@ -310,6 +313,8 @@ pub struct Page {
 
 	/// Tells whether the page has been written to
 	pub dirty: AtomicBool,
+	/// Tells whether the page is currently being written back to disk
+	pub writeback: AtomicBool,
 	/// Timestamp of the last write to disk, in milliseconds
 	pub last_write: AtomicU64,
 }
@@ -321,6 +326,7 @@ impl Page {
 	pub fn init(&self, off: u64) {
 		self.off.store(off, Relaxed);
 		self.dirty.store(false, Relaxed);
+		self.writeback.store(false, Relaxed);
 		self.last_write.store(0, Relaxed);
 	}
 }
@@ -404,6 +410,16 @@ fn get_zone_for_addr(zones: &mut [Zone; ZONES_COUNT], phys_addr: PhysAddr) -> Op
 	})
 }
 
+/// Fills the frame at `addr` with order `order` with zeros.
+///
+/// Since pages outside the kernel's direct mapping are zeroed through a temporary mapping,
+/// [`ZONES`] must not be locked when calling this function.
+fn zero_frame(addr: PhysAddr, order: FrameOrder) {
+	for i in 0..math::pow2(order as usize) {
+		vmem::zero_page(addr + i * PAGE_SIZE);
+	}
+}
+
 /// Allocates a frame of memory using the buddy allocator.
 ///
 /// Arguments:
@@ -449,6 +465,10 @@ pub fn alloc(order: FrameOrder, flags: Flags) -> AllocResult<PhysAddr> {
 	let pages_count = math::pow2(order as usize);
 	zone.allocated_pages += pages_count;
 	stats::MEM_INFO.lock().mem_free -= pages_count * 4;
+	drop(guard);
+	if INIT_ON_ALLOC.load(Relaxed) {
+		zero_frame(addr, order);
+	}
 	#[cfg(feature = "memtrace")]
 	super::trace::sample("buddy", super::trace::SampleOp::Alloc, addr.0, pages_count);
 	Ok(addr)
@@ -497,6 +517,9 @@ pub fn get_page(addr: PhysAddr) -> &'static Page {
 pub unsafe fn free(addr: PhysAddr, order: FrameOrder) {
 	debug_assert!(addr.is_aligned_to(PAGE_SIZE));
 	debug_assert!(order <= MAX_ORDER);
+	if INIT_ON_FREE.load(Relaxed) {
+		zero_frame(addr, order);
+	}
 	// Get zone
 	let mut zones = ZONES.lock();
 	let zone = get_zone_for_addr(&mut zones, addr).unwrap();
@@ -536,6 +559,31 @@ pub fn allocated_pages_count() -> usize {
 	zones.iter().map(|z| z.allocated_pages).sum()
 }
 
+/// Debugfs file giving, for each zone, the number of free frames of each order.
+pub(crate) static FREE_LISTS: ShowFile = ShowFile(|buf| {
+	// Count first, so that no allocation happens while the zones are locked
+	let mut counts = [[0usize; (MAX_ORDER + 1) as usize]; ZONES_COUNT];
+	{
+		let zones = ZONES.lock();
+		for (zone, counts) in zones.iter().zip(counts.iter_mut()) {
+			for (mut frame, count) in zone.free_list.iter().copied().zip(counts.iter_mut()) {
+				while let Some(f) = frame {
+					*count += 1;
+					frame = unsafe { f.as_ref() }.next;
+				}
+			}
+		}
+	}
+	for (name, counts) in ["user", "mmio", "kernel"].iter().zip(counts.iter()) {
+		try_write!(buf, "{name:<6}")?;
+		for count in counts {
+			try_write!(buf, " {count:>6}")?;
+		}
+		try_writeln!(buf)?;
+	}
+	Ok(())
+});
+
 #[cfg(test)]
 mod test {
 	use super::*;
@@ -27,13 +27,15 @@
 
 use crate::{
 	device::BlkDev,
+	file::fs::debug::ShowFile,
 	memory::{
 		PhysAddr, VirtAddr, buddy,
 		buddy::{Flags, Page, ZONE_KERNEL},
 		stats::MEM_INFO,
 	},
 	println,
-	sync::{mutex::Mutex, spin::IntSpin},
+	process::scheduler::schedule,
+	sync::{atomic::AtomicU64, mutex::Mutex, spin::IntSpin},
 	time::{
 		clock::{Clock, current_time_ms},
 		sleep_for,
@@ -45,10 +47,10 @@ use core::{
 	fmt::Formatter,
 	marker::PhantomData,
 	ops::Deref,
-	slice,
+	ptr, slice,
 	sync::atomic::{
 		AtomicUsize,
-		Ordering::{Acquire, Release},
+		Ordering::{Acquire, Relaxed, Release},
 	},
 };
 use utils::{
@@ -58,6 +60,7 @@ use utils::{
 	limits::PAGE_SIZE,
 	list, list_type,
 	ptr::arc::Arc,
+	try_writeln,
 };
 
 /// The timeout, in milliseconds, after which a dirty page may be written back to disk.
@@ -209,7 +212,10 @@ impl RcPage {
 			return Ok(());
 		}
 		// Write page
-		dev.ops.writeback(dev, self.dev_offset(), self)?;
+		page.writeback.store(true, Release);
+		let res = dev.ops.writeback(dev, self.dev_offset(), self);
+		page.writeback.store(false, Release);
+		res?;
 		// Update write timestamp
 		if let Some(ts) = ts {
 			page.last_write.store(ts, Release);
@@ -217,6 +223,13 @@ impl RcPage {
 		Ok(())
 	}
 
+	/// Waits until the page is no longer being written back to disk.
+	pub fn wait_writeback(&self) {
+		while self.get_page().writeback.load(Acquire) {
+			schedule();
+		}
+	}
+
 	/// Returns a reference to the map counter.
 	#[inline]
 	pub fn map_counter(&self) -> &AtomicUsize {
@@ -274,6 +287,12 @@ impl<T: AnyRepr> RcBlockVal<T> {
 		&mut self.page.slice_mut()[self.off]
 	}
 
+	/// Returns the page the value is located on.
+	#[inline]
+	pub fn page(&self) -> &RcPage {
+		&self.page
+	}
+
 	/// Marks the pages storing the inner value as dirty.
 	#[inline]
 	pub fn mark_dirty(&self) {
@@ -323,8 +342,10 @@ impl MappedNode {
 		let pages = self.cache.lock();
 		if let Some(page) = pages.get(&off) {
 			// Cache hit
+			HITS.fetch_add(1, Relaxed);
 			return Ok(page.clone());
 		}
+		MISSES.fetch_add(1, Relaxed);
 		// Getting the page from disk might require sleeping. Do not hold a spinlock while sleeping
 		drop(pages);
 		// Cache miss: read and insert
@@ -348,12 +369,82 @@ impl MappedNode {
 		Ok(())
 	}
 
+	/// Returns the first page in the range `start..end` (in pages), along with its offset.
+	fn next_in_range(&self, start: u64, end: u64) -> Option<(u64, RcPage)> {
+		self.cache
+			.lock()
+			.range(start..end)
+			.next()
+			.map(|(off, page)| (*off, page.clone()))
+	}
+
+	/// Waits for the completion of the writeback of all pages in the range `start..end` (in
+	/// pages).
+	pub fn wait_range(&self, start: u64, end: u64) {
+		let mut off = start;
+		while let Some((page_off, page)) = self.next_in_range(off, end) {
+			page.wait_writeback();
+			off = page_off + 1;
+		}
+	}
+
+	/// Writes the dirty pages in the range `start..end` (in pages) back to disk.
+	///
+	/// Contrary to [`Self::sync`], the cache is not locked during I/O.
+	pub fn sync_range(&self, start: u64, end: u64) -> EResult<()> {
+		let ts = current_time_ms(Clock::Boottime);
+		let mut off = start;
+		while let Some((page_off, page)) = self.next_in_range(off, end) {
+			page.writeback(Some(ts), false)?;
+			off = page_off + 1;
+		}
+		Ok(())
+	}
+
+	/// Drops the clean and unmapped pages in the range `start..end` (in pages) from the cache.
+	///
+	/// Dirty pages are kept, since they must be written back first.
+	pub fn invalidate(&self, start: u64, end: u64) {
+		self.cache.lock().retain(|off, page| {
+			if !(start..end).contains(off) {
+				return true;
+			}
+			let busy = page.get_page().dirty.load(Acquire) || page.0.map_count.load(Acquire) > 0;
+			if busy {
+				return true;
+			}
+			// If the page is also cached by its device, drop it there too, unless it is used
+			// somewhere else
+			if let Some(dev) = &page.0.dev
+				&& !ptr::eq(&dev.mapped, self)
+			{
+				let mut dev_cache = dev.mapped.cache.lock();
+				// Node cache, device cache and LRU
+				if Arc::strong_count(&page.0) == 3 {
+					dev_cache.remove(&page.0.dev_off);
+				}
+			}
+			false
+		});
+	}
+
 	/// Removes, without flushing, all the pages after the offset `off` (included).
 	pub fn truncate(&self, off: u64) {
 		self.cache.lock().retain(|o, _| *o < off);
 	}
 }
 
+/// The number of lookups that found the page in cache.
+static HITS: AtomicU64 = AtomicU64::new(0);
+/// The number of lookups that had to read the page.
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// Debugfs file giving the number of cache hits and misses.
+pub(crate) static STATS: ShowFile = ShowFile(|buf| {
+	try_writeln!(buf, "hits {}", HITS.load(Relaxed))?;
+	try_writeln!(buf, "misses {}", MISSES.load(Relaxed))
+});
+
 /// Global cache for all pages
 static LRU: Mutex<list_type!(RcPageInner, lru), false> = Mutex::new(list!(RcPageInner, lru));
 
@@ -23,7 +23,7 @@ mod chunk;
 
 use crate::{
 	memory,
-	memory::{buddy, malloc::ptr::NonNull},
+	memory::{INIT_ON_ALLOC, INIT_ON_FREE, buddy, malloc::ptr::NonNull},
 	sync::spin::IntSpin,
 };
 use block::Block;
@@ -35,6 +35,7 @@ use core::{
 	num::NonZeroUsize,
 	ptr,
 	ptr::drop_in_place,
+	sync::atomic::Ordering::Relaxed,
 };
 use utils::{errno::AllocResult, limits::PAGE_SIZE};
 
@@ -54,6 +55,9 @@ unsafe fn alloc_impl(n: NonZeroUsize) -> AllocResult<NonNull<u8>> {
 	let ptr = chunk.get_ptr_mut();
 	debug_assert!(ptr.is_aligned_to(chunk::ALIGNMENT));
 	debug_assert!(ptr as usize >= memory::PROCESS_END.0);
+	if INIT_ON_ALLOC.load(Relaxed) {
+		ptr::write_bytes(ptr, 0, n.get());
+	}
 	NonNull::new(ptr).ok_or(AllocError)
 }
 
@@ -80,18 +84,24 @@ unsafe fn realloc(ptr: NonNull<u8>, n: NonZeroUsize) -> AllocResult<NonNull<u8>>
 	let chunk_size = chunk.get_size();
 	let new_ptr = match n.get().cmp(&chunk_size) {
 		Ordering::Less => {
+			if INIT_ON_FREE.load(Relaxed) {
+				ptr::write_bytes(ptr.as_ptr().add(n.get()), 0, chunk_size - n.get());
+			}
 			chunk.shrink(chunk_size - n.get());
 			ptr
 		}
 		Ordering::Greater => {
-			if !chunk.grow(n.get() - chunk_size) {
+			if chunk.grow(n.get() - chunk_size) {
+				if INIT_ON_ALLOC.load(Relaxed) {
+					ptr::write_bytes(ptr.as_ptr().add(chunk_size), 0, n.get() - chunk_size);
+				}
+				ptr
+			} else {
 				// Allocate new chunk and copy to it
 				let mut new_ptr = alloc_impl(n)?;
 				ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr.as_mut(), chunk_size);
 				free_impl(ptr);
 				new_ptr
-			} else {
-				ptr
 			}
 		}
 		Ordering::Equal => ptr,
@@ -112,6 +122,9 @@ unsafe fn free_impl(mut ptr: NonNull<u8>) {
 	assert!(chunk.used);
 	#[cfg(config_debug_malloc_check)]
 	chunk.check();
+	if INIT_ON_FREE.load(Relaxed) {
+		ptr::write_bytes(ptr.as_ptr(), 0, chunk.get_size());
+	}
 	// Mark as free
 	chunk.used = false;
 	let free_chunk = chunk.as_free_chunk().unwrap();
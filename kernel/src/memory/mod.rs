@@ -33,6 +33,7 @@ use core::{
 	ops::{Add, Deref, DerefMut, Sub},
 	ptr,
 	ptr::NonNull,
+	sync::atomic::AtomicBool,
 };
 
 pub mod alloc;
@@ -68,6 +69,16 @@ pub const KERNEL_BEGIN: VirtAddr = VirtAddr(0xffff800000000000);
 /// The size of the kernelspace virtual memory in bytes.
 pub const KERNELSPACE_SIZE: usize = usize::MAX - KERNEL_BEGIN.0 + 1;
 
+/// If set, kernel memory is filled with zeros when allocated.
+///
+/// This prevents stale data from leaking through uninitialized memory.
+pub static INIT_ON_ALLOC: AtomicBool = AtomicBool::new(build_cfg!(config_memory_init_on_alloc));
+/// If set, kernel memory is filled with zeros when freed.
+///
+/// This prevents stale data from persisting in memory, and makes use-after-free bugs more
+/// deterministic.
+pub static INIT_ON_FREE: AtomicBool = AtomicBool::new(build_cfg!(config_memory_init_on_free));
+
 /// An address on physical memory.
 #[repr(transparent)]
 #[derive(Clone, Copy, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -24,8 +24,35 @@
 //!
 //! This is an emergency procedure which is not supposed to be used under normal conditions.
 
-use crate::{file::vfs, memory::cache};
-use utils::errno::AllocResult;
+use crate::{
+	file::vfs,
+	memory::{cache, stats},
+	sysctl::IntTunable,
+};
+use utils::{errno::AllocResult, limits::PAGE_SIZE};
+
+/// Overcommit policy: refuse mappings that obviously cannot be backed by memory.
+pub const OVERCOMMIT_GUESS: usize = 0;
+/// Overcommit policy: always allow mappings.
+pub const OVERCOMMIT_ALWAYS: usize = 1;
+/// Overcommit policy: refuse mappings that cannot be backed by currently available memory.
+pub const OVERCOMMIT_NEVER: usize = 2;
+
+/// The overcommit policy (`vm.overcommit_memory`).
+pub static OVERCOMMIT_MEMORY: IntTunable =
+	IntTunable::new(OVERCOMMIT_GUESS, OVERCOMMIT_GUESS, OVERCOMMIT_NEVER);
+
+/// Tells whether, according to the overcommit policy, a private writable mapping of `pages` pages
+/// may be created.
+pub fn can_commit(pages: usize) -> bool {
+	let size = pages.saturating_mul(PAGE_SIZE / 1024);
+	let info = stats::MEM_INFO.lock();
+	match OVERCOMMIT_MEMORY.get() {
+		OVERCOMMIT_ALWAYS => true,
+		OVERCOMMIT_NEVER => size <= info.mem_free + info.inactive,
+		_ => size <= info.mem_total,
+	}
+}
 
 /// Attempts to reclaim memory from different places, or panics on failure.
 pub fn reclaim() {
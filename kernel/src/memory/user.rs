@@ -21,6 +21,7 @@
 use crate::{memory::vmem, process::mem_space::bound_check, syscall::FromSyscallArg};
 use core::{
 	cmp::min,
+	ffi::c_int,
 	fmt,
 	hint::{likely, unlikely},
 	marker::PhantomData,
@@ -32,7 +33,7 @@ use utils::{
 	collections::{path::PathBuf, string::String, vec::Vec},
 	errno,
 	errno::EResult,
-	limits::PAGE_SIZE,
+	limits::{IOV_MAX, PAGE_SIZE},
 };
 
 unsafe extern "C" {
@@ -576,55 +577,52 @@ impl fmt::Debug for UserIOVec {
 }
 
 impl UserIOVec {
-	/// Returns an iterator over the iovec.
-	///
-	/// `count` is the number of elements in the vector.
-	pub fn iter(&self, count: usize) -> IOVecIter {
-		IOVecIter {
-			vec: self,
-			cursor: 0,
-			count,
+	/// Copies the IO vector, with `count` elements, from userspace.
+	///
+	/// The whole vector is validated before any transfer takes place, so that an invalid vector
+	/// does not result in a partial transfer.
+	///
+	/// If `count` is negative or exceeds [`IOV_MAX`], or if the length of an element is negative
+	/// when interpreted as a signed value, the function returns [`errno::EINVAL`].
+	///
+	/// Lengths are truncated so that their total does not exceed `i32::MAX`.
+	pub fn import(&self, count: c_int) -> EResult<Vec<IOVec>> {
+		let count: usize = count.try_into().map_err(|_| errno!(EINVAL))?;
+		if unlikely(count > IOV_MAX) {
+			return Err(errno!(EINVAL));
 		}
-	}
-}
-
-/// Iterator over [`IOVec`]s.
-pub struct IOVecIter<'a> {
-	/// The iovec pointer.
-	vec: &'a UserIOVec,
-	/// Cursor
-	cursor: usize,
-	/// The number of elements.
-	count: usize,
-}
-
-impl Iterator for IOVecIter<'_> {
-	type Item = EResult<IOVec>;
-
-	fn next(&mut self) -> Option<Self::Item> {
-		let stride = if self.vec.compat {
-			size_of::<IOVecCompat>()
+		let (stride, max_len) = if self.compat {
+			(size_of::<IOVecCompat>(), i32::MAX as usize)
 		} else {
-			size_of::<IOVec>()
+			(size_of::<IOVec>(), isize::MAX as usize)
 		};
-		// Bound check
-		if unlikely(self.cursor >= self.count * stride) {
-			return None;
-		}
-		let iov = unsafe {
-			let ptr = self.vec.ptr?.byte_add(self.cursor);
-			if self.vec.compat {
-				let ptr = UserPtr::<IOVecCompat>(Some(ptr.cast()));
-				ptr.copy_from_user().transpose()?.map(|iov| IOVec {
+		let mut vec = Vec::with_capacity(count)?;
+		let mut total = 0;
+		for i in 0..count {
+			let ptr = unsafe { self.ptr.ok_or_else(|| errno!(EFAULT))?.byte_add(i * stride) };
+			let iov = if self.compat {
+				let iov = UserPtr::<IOVecCompat>(Some(ptr.cast()))
+					.copy_from_user()?
+					.ok_or_else(|| errno!(EFAULT))?;
+				IOVec {
 					iov_base: ptr::with_exposed_provenance_mut(iov.iov_base as _),
 					iov_len: iov.iov_len as _,
-				})
+				}
 			} else {
-				let ptr = UserPtr::<IOVec>(Some(ptr.cast()));
-				ptr.copy_from_user().transpose()?
+				UserPtr::<IOVec>(Some(ptr.cast()))
+					.copy_from_user()?
+					.ok_or_else(|| errno!(EFAULT))?
+			};
+			if unlikely(iov.iov_len > max_len) {
+				return Err(errno!(EINVAL));
 			}
-		};
-		self.cursor += stride;
-		Some(iov)
+			let iov_len = min(iov.iov_len, i32::MAX as usize - total);
+			total += iov_len;
+			vec.push(IOVec {
+				iov_base: iov.iov_base,
+				iov_len,
+			})?;
+		}
+		Ok(vec)
 	}
 }
@@ -35,7 +35,7 @@ use crate::{
 	process::scheduler::defer,
 	sync::{once::OnceInit, spin::IntSpin},
 };
-use core::{ptr::NonNull, sync::atomic::Ordering::Release};
+use core::{ptr, ptr::NonNull, sync::atomic::Ordering::Release};
 use utils::limits::PAGE_SIZE;
 
 // TODO should be configurable
@@ -161,7 +161,10 @@ impl VMem {
 
 	/// Polls the dirty flags on the range of `pages` pages starting at `addr`, clearing them
 	/// atomically, and setting them to the associated [`buddy::Page`] structure.
-	pub fn poll_dirty(&self, addr: VirtAddr, pages: usize) {
+	///
+	/// The function returns `true` if at least one page was dirty.
+	pub fn poll_dirty(&self, addr: VirtAddr, pages: usize) -> bool {
+		let mut dirty = false;
 		for n in 0..pages {
 			// TODO polling pages one by one is inefficient
 			let addr = addr + n * PAGE_SIZE;
@@ -170,7 +173,9 @@ impl VMem {
 			};
 			let page = buddy::get_page(physaddr);
 			page.dirty.store(true, Release);
+			dirty = true;
 		}
+		dirty
 	}
 
 	/// Binds the virtual memory context to the current CPU.
@@ -286,6 +291,30 @@ pub unsafe fn smap_disable<F: FnOnce() -> T, T>(f: F) -> T {
 
 /// The kernel's virtual memory context.
 pub static KERNEL_VMEM: OnceInit<VMem> = unsafe { OnceInit::new() };
+/// The page of kernelspace through which physical pages outside the direct mapping are accessed.
+///
+/// The frame initially backing this page is only allocated to reserve its virtual address.
+static WINDOW: IntSpin<VirtAddr> = IntSpin::new(VirtAddr(0));
+
+/// Fills the physical page at `addr` with zeros.
+///
+/// If the page is outside the kernel's direct mapping, it is temporarily mapped for the
+/// operation.
+pub(crate) fn zero_page(addr: PhysAddr) {
+	if let Some(ptr) = addr.kernel_to_virtual() {
+		unsafe {
+			ptr::write_bytes(ptr.as_ptr::<u8>(), 0, PAGE_SIZE);
+		}
+		return;
+	}
+	let window = WINDOW.lock();
+	// The page has been mapped at initialization, so this does not allocate
+	KERNEL_VMEM.map(addr, *window, FLAG_WRITE | FLAG_GLOBAL, 0);
+	invalidate_page(*window);
+	unsafe {
+		ptr::write_bytes(window.as_ptr::<u8>(), 0, PAGE_SIZE);
+	}
+}
 
 /// Initializes virtual memory management.
 pub(crate) fn init() {
@@ -364,6 +393,16 @@ pub(crate) fn init() {
 			FLAG_GLOBAL,
 		);
 	}
+	// Reserve the window, mapping it with its own page table entry so that remapping it later
+	// does not require allocating a table
+	let window = VirtAddr::from(buddy::alloc_kernel(0, 0).unwrap().as_ptr());
+	kernel_vmem.map(
+		window.kernel_to_physical().unwrap(),
+		window,
+		FLAG_WRITE | FLAG_GLOBAL,
+		0,
+	);
+	*WINDOW.lock() = window;
 	kernel_vmem.bind();
 	unsafe {
 		OnceInit::init(&KERNEL_VMEM, kernel_vmem);
@@ -0,0 +1,200 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Automatic loading of kernel modules on demand.
+//!
+//! When the kernel needs a facility which is not available, it calls [`request_module`] with an
+//! alias describing it. The following aliases are used:
+//! - `fs-<name>`: the filesystem type `name`
+//! - `char-major-<major>-<minor>` and `char-major-<major>`: a character device
+//! - `block-major-<major>-<minor>` and `block-major-<major>`: a block device
+//!
+//! The kernel then runs a userspace helper (the program set in the `kernel.modprobe` tunable,
+//! `/sbin/modprobe` by default) with the alias as argument, which is responsible for finding and
+//! loading the module providing it. The helper runs as root, with the root of the VFS as working
+//! directory, no open file and a fixed environment.
+
+use crate::{
+	arch::x86::idt::IntFrame,
+	file::vfs,
+	println, process,
+	process::{
+		Process, State, exec,
+		pid::Pid,
+		scheduler::{schedule, switch::init_ctx},
+	},
+	sync::spin::Spin,
+	sysctl,
+	sysctl::Tunable,
+	time,
+	time::clock::Clock,
+};
+use core::{
+	hint::unlikely,
+	sync::atomic::{
+		AtomicBool, AtomicUsize,
+		Ordering::{Acquire, Release},
+	},
+};
+use utils::{
+	DisplayableStr, TryClone,
+	collections::{btreemap::BTreeMap, path::Path, string::String, vec::Vec},
+	errno,
+	errno::{AllocResult, EResult},
+	try_writeln, vec,
+};
+
+/// The default path to the helper program.
+const DEFAULT_MODPROBE: &[u8] = b"/sbin/modprobe";
+/// The maximum number of helpers running concurrently.
+///
+/// Reaching this limit usually means a module request is looping.
+const MAX_HELPERS: usize = 50;
+/// The interval at which the completion of a helper is checked, in nanoseconds.
+const POLL_INTERVAL: u64 = 10_000_000;
+
+/// Tells whether helpers can be run.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// The number of helpers currently running.
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+/// The path to the helper program. If empty, modules are not requested.
+static MODPROBE: Spin<Vec<u8>> = Spin::new(Vec::new());
+/// The modules requested by helpers which have not started yet, by PID.
+static REQUESTS: Spin<BTreeMap<Pid, String>> = Spin::new(BTreeMap::new());
+
+/// The `kernel.modprobe` tunable.
+#[derive(Debug)]
+struct Modprobe;
+
+impl Tunable for Modprobe {
+	fn read(&self, buf: &mut String) -> AllocResult<()> {
+		try_writeln!(buf, "{}", DisplayableStr(&MODPROBE.lock()))
+	}
+
+	fn write(&self, val: &[u8]) -> EResult<()> {
+		let val = val.strip_suffix(b"\n").unwrap_or(val);
+		if unlikely(val.contains(&b'\0') || (!val.is_empty() && !val.starts_with(b"/"))) {
+			return Err(errno!(EINVAL));
+		}
+		*MODPROBE.lock() = Vec::try_from(val)?;
+		Ok(())
+	}
+}
+
+/// Executes the helper program for the module `name` on the current process.
+///
+/// On success, the function returns the register state to start the program with.
+fn exec_helper(name: String) -> EResult<IntFrame> {
+	let path = String::try_from(MODPROBE.lock().as_slice())?;
+	let ent = vfs::get_file_from_path(Path::new(&path)?, true)?;
+	let argv = vec![
+		path.try_clone()?,
+		b"-q".try_into()?,
+		b"--".try_into()?,
+		name,
+	]?;
+	let (ent, argv) = exec::script::resolve(ent, path, argv)?;
+	let image = exec::elf::exec(
+		ent,
+		argv,
+		vec![
+			b"HOME=/".try_into()?,
+			b"TERM=linux".try_into()?,
+			b"PATH=/sbin:/usr/sbin:/bin:/usr/bin".try_into()?,
+		]?,
+	)?;
+	let mut frame = IntFrame::default();
+	exec::exec(&mut frame, image)?;
+	Ok(frame)
+}
+
+/// The entry point of helpers.
+fn helper_entry() -> ! {
+	let pid = Process::current().get_pid();
+	let name = REQUESTS.lock().remove(&pid);
+	// Everything must be dropped before calling `init_ctx`, since it does not return
+	let res = name.ok_or_else(|| errno!(EINVAL)).and_then(exec_helper);
+	match res {
+		Ok(frame) => unsafe { init_ctx(&frame) },
+		Err(e) => {
+			// A missing helper program is not an error
+			if e.as_int() != errno::ENOENT {
+				println!("Cannot run module helper: {e}");
+			}
+			process::exit(1);
+			loop {
+				schedule();
+			}
+		}
+	}
+}
+
+/// Decrements the number of running helpers when dropped.
+struct RunningGuard;
+
+impl Drop for RunningGuard {
+	fn drop(&mut self) {
+		RUNNING.fetch_sub(1, Release);
+	}
+}
+
+/// Requests the module providing the facility `name` (see the module's documentation for the
+/// list of aliases) by running the helper program, then waits for it to complete.
+///
+/// The function does not tell whether the module has been loaded: the caller has to check
+/// whether the facility is available afterward.
+///
+/// The caller must not hold any spinlock, since the function sleeps.
+///
+/// Errors:
+/// - [`errno::ENOENT`]: no helper program is set, or helpers cannot be run yet
+/// - [`errno::EAGAIN`]: too many helpers are running
+/// - [`errno::EINTR`]: the current process has been interrupted while waiting
+pub fn request_module(name: String) -> EResult<()> {
+	if !ENABLED.load(Acquire) || MODPROBE.lock().is_empty() {
+		return Err(errno!(ENOENT));
+	}
+	if RUNNING.fetch_add(1, Acquire) >= MAX_HELPERS {
+		RUNNING.fetch_sub(1, Release);
+		println!(
+			"request_module: too many requests, possible loop ({})",
+			DisplayableStr(&name)
+		);
+		return Err(errno!(EAGAIN));
+	}
+	let _guard = RunningGuard;
+	let helper = Process::new_helper(helper_entry, |pid| {
+		REQUESTS.lock().insert(pid, name)?;
+		Ok(())
+	})?;
+	// Wait for the helper to exit
+	while helper.get_state() != State::Zombie {
+		time::sleep_for(Clock::Monotonic, POLL_INTERVAL, &mut 0)?;
+	}
+	Ok(())
+}
+
+/// Initializes module requests.
+///
+/// This function must be called once the VFS is initialized, before running the init process.
+pub(crate) fn init() -> AllocResult<()> {
+	*MODPROBE.lock() = Vec::try_from(DEFAULT_MODPROBE)?;
+	sysctl::register(b"kernel.modprobe", sysctl::MODE_RW, &Modprobe)?;
+	ENABLED.store(true, Release);
+	Ok(())
+}
@@ -27,16 +27,32 @@
 //! - **Kernel Module**: A piece of software to be loaded at runtime in kernelspace.
 //!
 //! Thus, **Kernel Modules** contain **Modules**.
+//!
+//! # Symbols
+//!
+//! A kernel module exports its globally visible symbols. Each kernel module has its own
+//! namespace: when relocating a kernel module, undefined symbols are looked up in the kernel,
+//! then only in the kernel modules it declares as dependencies.
+//!
+//! # Dependencies
+//!
+//! The dependencies of a kernel module which are not loaded yet are requested with
+//! [`kmod::request_module`] before relocating it. A kernel module then holds a reference on each
+//! of its dependencies, which cannot be unloaded until every kernel module depending on them is.
+//!
+//! # Parameters
+//!
+//! A kernel module may declare parameters (see [`param`]), which are set from the arguments
+//! passed when loading it.
 
+pub mod kmod;
+pub mod param;
 pub(crate) mod relocation;
 pub mod version;
 
 use crate::{
 	elf,
-	elf::{
-		kernel::KernSym,
-		parser::{ELFParser, Rel, Rela},
-	},
+	elf::parser::{ELFParser, Rel, Rela},
 	module::relocation::RelocationError,
 	println,
 	sync::spin::Spin,
@@ -48,12 +64,14 @@ use core::{
 	hint::unlikely,
 	mem::{size_of, transmute},
 	slice,
+	sync::atomic::{AtomicUsize, Ordering::Relaxed},
 };
+use param::ParamDesc;
 use utils::{
 	DisplayableStr,
-	collections::{hashset::HashSet, string::String, vec::Vec},
+	collections::{hashmap::HashMap, hashset::HashSet, string::String, vec::Vec},
 	errno,
-	errno::EResult,
+	errno::{AllocResult, EResult},
 	vec,
 };
 use version::{Dependency, Version};
@@ -130,16 +148,20 @@ impl Hash for NameHash {
 	}
 }
 
-/// Resolves an external symbol from the kernel or another module.
+/// Resolves an external symbol from the kernel or from one of the modules `deps`.
 ///
 /// `name` is the name of the symbol to look for.
 ///
 /// If the symbol doesn't exist, the function returns `None`.
-fn resolve_symbol(name: &[u8]) -> Option<&'static KernSym> {
+fn resolve_symbol(name: &[u8], deps: &[Dependency]) -> Option<usize> {
 	// The symbol on the kernel side
-	let kernel_sym = elf::kernel::get_symbol_by_name(name)?;
-	// TODO check symbols from other loaded modules
-	Some(kernel_sym)
+	if let Some(sym) = elf::kernel::get_symbol_by_name(name) {
+		return Some(sym.st_value as _);
+	}
+	let modules = MODULES.lock();
+	deps.iter()
+		.filter_map(|dep| modules.get(dep.name.as_bytes()))
+		.find_map(|module| module.0.exports.get(name).copied())
 }
 
 /// Returns the value of a symbol from the kernel or another module, in order to relocate a module.
@@ -149,13 +171,17 @@ fn resolve_symbol(name: &[u8]) -> Option<&'static KernSym> {
 /// - `load_base` is the pointer at which the module is being loaded
 /// - `sym_section` is the section containing symbols
 /// - `sym` is the index of the symbol in `sym_section`
+/// - `deps` is the list of modules in which external symbols are looked up
+/// - `report` tells whether a symbol that does not exist has to be reported
 ///
-/// If the symbol does not exist, the function prints an error and returns `None`.
+/// If the symbol does not exist, the function returns `None`.
 fn get_symbol_value(
 	parser: &ELFParser,
 	load_base: *const u8,
 	sym_section: u32,
 	sym: usize,
+	deps: &[Dependency],
+	report: bool,
 ) -> Option<usize> {
 	let section = parser.get_section_by_index(sym_section as _)?;
 	let sym = parser.get_symbol_by_index(&section, sym as _)?;
@@ -165,7 +191,10 @@ fn get_symbol_value(
 	let strtab = parser.get_section_by_index(section.sh_link as _)?;
 	let name = parser.get_symbol_name(&strtab, &sym)?;
 	// Look inside the kernel image or other modules
-	let Some(other_sym) = resolve_symbol(name) else {
+	let Some(value) = resolve_symbol(name, deps) else {
+		if !report {
+			return None;
+		}
 		if let Ok(name_str) = str::from_utf8(name) {
 			println!(
 				"Symbol `{}` not found in kernel or other loaded modules",
@@ -179,7 +208,93 @@ fn get_symbol_value(
 		}
 		return None;
 	};
-	Some(other_sym.st_value as usize)
+	Some(value)
+}
+
+/// Copies the module's `image` to `mem`, then performs relocations.
+///
+/// Arguments:
+/// - `parser` is the parser for `image`
+/// - `deps` is the list of modules in which external symbols are looked up
+/// - `report` tells whether symbols that do not exist have to be reported
+///
+/// If a relocation fails, the function returns `false`.
+fn relocate(
+	image: &[u8],
+	parser: &ELFParser,
+	mem: &mut [u8],
+	deps: &[Dependency],
+	report: bool,
+) -> bool {
+	// Copy the module's image
+	parser
+		.segments()
+		.iter()
+		.filter(|seg| seg.p_type != elf::PT_NULL)
+		.for_each(|seg| {
+			let len = min(seg.p_memsz, seg.p_filesz) as usize;
+			let mem_begin = seg.p_vaddr as usize;
+			let image_begin = seg.p_offset as usize;
+			mem[mem_begin..(mem_begin + len)]
+				.copy_from_slice(&image[image_begin..(image_begin + len)]);
+		});
+	// The base virtual address at which the module is loaded
+	let load_base = mem.as_mut_ptr();
+	// Closure returning a symbol
+	let get_sym = |sym_section: u32, sym: usize| {
+		get_symbol_value(parser, load_base, sym_section, sym, deps, report).ok_or(RelocationError)
+	};
+	let mut success = true;
+	for section in parser.sections() {
+		for rel in parser.iter_rel::<Rel>(section) {
+			let res = unsafe { relocation::perform(&rel, load_base, section, get_sym) };
+			if res.is_err() {
+				success = false;
+			}
+		}
+		for rela in parser.iter_rel::<Rela>(section) {
+			let res = unsafe { relocation::perform(&rela, load_base, section, get_sym) };
+			if res.is_err() {
+				success = false;
+			}
+		}
+	}
+	success
+}
+
+/// Returns the symbols exported by a module, along with their addresses.
+///
+/// Arguments:
+/// - `parser` is the module's parser
+/// - `load_base` is the pointer at which the module is loaded
+fn exported_symbols(
+	parser: &ELFParser,
+	load_base: *const u8,
+) -> AllocResult<HashMap<String, usize>> {
+	let mut exports = HashMap::new();
+	let symtabs = parser
+		.sections()
+		.iter()
+		.filter(|section| section.sh_type == elf::SHT_DYNSYM);
+	for symtab in symtabs {
+		let Some(strtab) = parser.get_section_by_index(symtab.sh_link as _) else {
+			continue;
+		};
+		for sym in parser.iter_symbols(symtab) {
+			let exported = sym.is_defined()
+				&& matches!(sym.bind(), elf::STB_GLOBAL | elf::STB_WEAK)
+				&& matches!(sym.visibility(), elf::STV_DEFAULT | elf::STV_PROTECTED);
+			if !exported {
+				continue;
+			}
+			let Some(name) = parser.get_symbol_name(&strtab, &sym) else {
+				continue;
+			};
+			let value = load_base as usize + sym.st_value as usize;
+			exports.insert(String::try_from(name)?, value)?;
+		}
+	}
+	Ok(exports)
 }
 
 /// Returns the value of the given attribute of a module.
@@ -227,6 +342,50 @@ fn get_array_attribute<'mem, T>(
 	Some(slice)
 }
 
+/// References held by a module on its dependencies, released when dropped.
+struct DepsRef(Vec<Dependency>);
+
+impl DepsRef {
+	/// Takes a reference on each module in `deps`.
+	///
+	/// If a dependency is not loaded, the function returns [`errno::ENOENT`]. If the version of a
+	/// dependency does not satisfy its constraint, the function returns [`errno::EINVAL`].
+	fn new(deps: Vec<Dependency>) -> EResult<Self> {
+		let modules = MODULES.lock();
+		for dep in &deps {
+			let Some(module) = modules.get(dep.name.as_bytes()) else {
+				println!("Missing dependency `{}`", dep.name);
+				return Err(errno!(ENOENT));
+			};
+			if !dep.is_satisfied_by(&module.0.version) {
+				println!(
+					"Dependency `{}` has version `{}`, which does not match `{}`",
+					dep.name, module.0.version, dep.version
+				);
+				return Err(errno!(EINVAL));
+			}
+		}
+		deps.iter()
+			.filter_map(|dep| modules.get(dep.name.as_bytes()))
+			.for_each(|module| {
+				module.0.holders.fetch_add(1, Relaxed);
+			});
+		Ok(Self(deps))
+	}
+}
+
+impl Drop for DepsRef {
+	fn drop(&mut self) {
+		let modules = MODULES.lock();
+		self.0
+			.iter()
+			.filter_map(|dep| modules.get(dep.name.as_bytes()))
+			.for_each(|module| {
+				module.0.holders.fetch_sub(1, Relaxed);
+			});
+	}
+}
+
 // TODO keep offsets of name, version and dependencies instead of allocating
 /// A loaded kernel module.
 pub struct Module {
@@ -235,8 +394,17 @@ pub struct Module {
 	/// The module's version.
 	version: Version,
 
-	/// The list of dependencies associated with the module.
-	deps: Vec<Dependency>,
+	/// The references on the dependencies of the module.
+	deps: DepsRef,
+	/// The symbols exported by the module, along with their addresses.
+	exports: HashMap<String, usize>,
+	/// The number of loaded modules depending on this module.
+	///
+	/// Modified only while [`MODULES`] is locked.
+	holders: AtomicUsize,
+
+	/// The offset of the module's parameters in its memory, along with their number.
+	params: (usize, usize),
 
 	/// The module's memory.
 	mem: Vec<u8>,
@@ -249,49 +417,18 @@ pub struct Module {
 
 impl Module {
 	/// Loads a kernel module from the given image.
-	pub fn load(image: &[u8]) -> EResult<Self> {
+	///
+	/// `args` is the list of the module's parameters, as a space-separated list of `name=value`.
+	pub fn load(image: &[u8], args: &[u8]) -> EResult<Self> {
 		let parser = ELFParser::from_slice(image).inspect_err(|_| {
 			println!("Invalid ELF file as loaded module");
 		})?;
 		// Allocate memory for the module
 		let mem_size = parser.get_load_size();
 		let mut mem = vec![0; mem_size]?; // FIXME: memory alignment
-		// The base virtual address at which the module is loaded
-		let load_base = mem.as_mut_ptr();
-		// Copy the module's image
-		parser
-			.segments()
-			.iter()
-			.filter(|seg| seg.p_type != elf::PT_NULL)
-			.for_each(|seg| {
-				let len = min(seg.p_memsz, seg.p_filesz) as usize;
-				let mem_begin = seg.p_vaddr as usize;
-				let image_begin = seg.p_offset as usize;
-				mem[mem_begin..(mem_begin + len)]
-					.copy_from_slice(&image[image_begin..(image_begin + len)]);
-			});
-		// Closure returning a symbol
-		let get_sym = |sym_section: u32, sym: usize| {
-			get_symbol_value(&parser, load_base, sym_section, sym).ok_or(RelocationError)
-		};
-		let mut relocation_failure = false;
-		for section in parser.sections() {
-			for rel in parser.iter_rel::<Rel>(section) {
-				let res = unsafe { relocation::perform(&rel, load_base, section, get_sym) };
-				if res.is_err() {
-					relocation_failure = true;
-				}
-			}
-			for rela in parser.iter_rel::<Rela>(section) {
-				let res = unsafe { relocation::perform(&rela, load_base, section, get_sym) };
-				if res.is_err() {
-					relocation_failure = true;
-				}
-			}
-		}
-		if unlikely(relocation_failure) {
-			return Err(errno!(EINVAL));
-		}
+		// Relocate against the kernel only, which is enough to read the module's attributes.
+		// Symbols from dependencies are resolved afterward, once they are loaded
+		let relocated = relocate(image, &parser, &mut mem, &[], false);
 		// Check the magic number
 		let magic = get_attribute::<u64>(&mem, &parser, b"MOD_MAGIC").ok_or_else(|| {
 			println!("Missing `MOD_MAGIC` symbol in module image");
@@ -320,8 +457,29 @@ impl Module {
 				errno!(EINVAL)
 			})?;
 		let deps = Vec::try_from(deps)?;
+		let version = *version;
 		println!("Load module `{name}` version `{version}`");
-		// TODO Check that all dependencies are loaded
+		// Load missing dependencies
+		for dep in &deps {
+			if !MODULES.lock().contains(dep.name.as_bytes()) {
+				// If the dependency cannot be loaded, the error is reported when taking references
+				let _ = kmod::request_module(String::try_from(dep.name)?);
+			}
+		}
+		let deps = DepsRef::new(deps)?;
+		if !relocated && unlikely(!relocate(image, &parser, &mut mem, &deps.0, true)) {
+			return Err(errno!(EINVAL));
+		}
+		let exports = exported_symbols(&parser, mem.as_ptr())?;
+		// Set parameters
+		let params =
+			get_array_attribute::<ParamDesc>(&mem, &parser, b"MOD_PARAMS").unwrap_or_default();
+		param::apply(params, args)?;
+		// If there is no parameter, the offset is not used
+		let params = (
+			params.as_ptr().addr().wrapping_sub(mem.as_ptr().addr()),
+			params.len(),
+		);
 		// Initialize module
 		let init = parser.get_symbol_by_name(b"init").ok_or_else(|| {
 			println!("Missing `init` symbol in module image");
@@ -344,9 +502,13 @@ impl Module {
 		});
 		Ok(Self {
 			name,
-			version: *version,
+			version,
 
 			deps,
+			exports,
+			holders: AtomicUsize::new(0),
+
+			params,
 
 			mem: mem as _,
 			mem_size,
@@ -364,6 +526,30 @@ impl Module {
 	pub fn get_version(&self) -> &Version {
 		&self.version
 	}
+
+	/// Returns the number of loaded modules depending on this module.
+	pub fn get_holders(&self) -> usize {
+		self.holders.load(Relaxed)
+	}
+
+	/// Returns the modules this module depends on.
+	pub fn get_deps(&self) -> &[Dependency] {
+		&self.deps.0
+	}
+
+	/// Returns the parameters of the module.
+	pub fn get_params(&self) -> &[ParamDesc] {
+		let (off, len) = self.params;
+		if len == 0 {
+			return &[];
+		}
+		unsafe { slice::from_raw_parts(self.mem.as_ptr().add(off) as *const ParamDesc, len) }
+	}
+
+	/// Returns the address and size of the memory on which the module is loaded.
+	pub fn get_mem(&self) -> (*const u8, usize) {
+		(self.mem.as_ptr(), self.mem_size)
+	}
 }
 
 impl Drop for Module {
@@ -384,22 +570,65 @@ static MODULES: Spin<HashSet<NameHash>> = Spin::new(HashSet::new());
 /// If a module with the same name is already loaded, the function returns [`errno::EEXIST`].
 pub fn add(module: Module) -> EResult<()> {
 	let module = NameHash(module);
+	// On error, the module is dropped after the lock is released since it releases its
+	// dependencies
 	let mut modules = MODULES.lock();
-	if !modules.contains(&module) {
-		modules.insert(module)?;
-		Ok(())
-	} else {
-		Err(errno!(EEXIST))
+	if unlikely(modules.contains(&module)) {
+		return Err(errno!(EEXIST));
 	}
+	modules.reserve(1)?;
+	modules.insert(module)?;
+	Ok(())
 }
 
 /// Removes the module with name `name`.
 ///
-/// If no module with this name is loaded, the function returns [`errno::ENOENT`].
+/// Errors:
+/// - [`errno::ENOENT`]: no module with this name is loaded
+/// - [`errno::EWOULDBLOCK`]: other loaded modules depend on the module
 pub fn remove(name: &[u8]) -> EResult<()> {
-	MODULES
-		.lock()
-		.remove(name)
-		.map(drop)
-		.ok_or_else(|| errno!(ENOENT))
+	let module = {
+		let mut modules = MODULES.lock();
+		let module = modules.get(name).ok_or_else(|| errno!(ENOENT))?;
+		if unlikely(module.0.get_holders() > 0) {
+			return Err(errno!(EWOULDBLOCK));
+		}
+		modules.remove(name)
+	};
+	// Unload outside the critical section since the module releases its dependencies
+	drop(module);
+	Ok(())
+}
+
+/// Executes `f` with the loaded module named `name`.
+///
+/// If no module with this name is loaded, the function returns [`errno::ENOENT`].
+pub fn with<F, R>(name: &[u8], f: F) -> EResult<R>
+where
+	F: FnOnce(&Module) -> EResult<R>,
+{
+	let modules = MODULES.lock();
+	let module = modules.get(name).ok_or_else(|| errno!(ENOENT))?;
+	f(&module.0)
+}
+
+/// Executes `f` with each loaded module, along with an iterator over the modules depending on
+/// it.
+///
+/// If `f` returns an error, the iteration stops and the function returns it.
+pub fn for_each<F>(mut f: F) -> EResult<()>
+where
+	F: FnMut(&Module, &mut dyn Iterator<Item = &Module>) -> EResult<()>,
+{
+	let modules = MODULES.lock();
+	modules.iter().try_for_each(|module| {
+		let name = module.0.get_name();
+		let mut holders = modules.iter().map(|holder| &holder.0).filter(|holder| {
+			holder
+				.get_deps()
+				.iter()
+				.any(|dep| dep.name.as_bytes() == name)
+		});
+		f(&module.0, &mut holders)
+	})
 }
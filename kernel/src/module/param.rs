@@ -0,0 +1,209 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Kernel module parameters.
+//!
+//! A kernel module declares its parameters with the [`module_params`](crate::module_params)
+//! macro. Their values are set from the space-separated `name=value` list passed when loading the
+//! module, before its `init` function is called.
+//!
+//! Dashes and underscores are equivalent in parameter names. A boolean parameter given without a
+//! value is set to `true`.
+
+use crate::{println, sync::spin::Spin};
+use core::{fmt, hint::unlikely, str};
+use utils::{DisplayableStr, errno, errno::EResult};
+
+/// A type which can be used as the value of a kernel module parameter.
+pub trait ParamType: Copy + fmt::Display {
+	/// Parses the value `val`, or returns the value to use when the parameter is given without
+	/// one if `None`.
+	///
+	/// If the value is invalid, the function returns `None`.
+	fn parse(val: Option<&[u8]>) -> Option<Self>;
+
+	/// Writes the value to `f`, in the format accepted by [`Self::parse`].
+	fn fmt_value(self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Display::fmt(&self, f)
+	}
+}
+
+impl ParamType for bool {
+	fn parse(val: Option<&[u8]>) -> Option<Self> {
+		match val {
+			None | Some(b"1" | b"y" | b"Y" | b"on" | b"true") => Some(true),
+			Some(b"0" | b"n" | b"N" | b"off" | b"false") => Some(false),
+			_ => None,
+		}
+	}
+
+	fn fmt_value(self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(if self { "Y" } else { "N" })
+	}
+}
+
+macro_rules! int_param {
+	($($ty:ty),*) => {
+		$(impl ParamType for $ty {
+			fn parse(val: Option<&[u8]>) -> Option<Self> {
+				let val = str::from_utf8(val?).ok()?;
+				match val.strip_prefix("0x") {
+					Some(hex) => <$ty>::from_str_radix(hex, 16).ok(),
+					None => val.parse().ok(),
+				}
+			}
+		})*
+	};
+}
+
+int_param!(i8, u8, i16, u16, i32, u32, i64, u64, isize, usize);
+
+/// Operations on a kernel module parameter, independent of the type of its value.
+pub trait ParamValue: Sync {
+	/// Parses `val` and sets it as the parameter's value.
+	///
+	/// `None` means the parameter is given without a value.
+	///
+	/// If the value is invalid, the function returns [`errno::EINVAL`].
+	fn set(&self, val: Option<&[u8]>) -> EResult<()>;
+
+	/// Writes the parameter's current value to `f`.
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result;
+}
+
+/// The storage of a kernel module parameter.
+pub struct Param<T: ParamType>(Spin<T>);
+
+impl<T: ParamType> Param<T> {
+	/// Creates a parameter with the default value `val`.
+	pub const fn new(val: T) -> Self {
+		Self(Spin::new(val))
+	}
+
+	/// Returns the current value of the parameter.
+	pub fn get(&self) -> T {
+		*self.0.lock()
+	}
+}
+
+impl<T: ParamType> ParamValue for Param<T> {
+	fn set(&self, val: Option<&[u8]>) -> EResult<()> {
+		*self.0.lock() = T::parse(val).ok_or_else(|| errno!(EINVAL))?;
+		Ok(())
+	}
+
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.get().fmt_value(f)
+	}
+}
+
+/// The description of a kernel module parameter, as exported by a kernel module.
+pub struct ParamDesc {
+	/// The name of the parameter.
+	pub name: &'static str,
+	/// A description of the parameter.
+	pub desc: &'static str,
+	/// The parameter's value.
+	pub value: &'static dyn ParamValue,
+}
+
+impl fmt::Display for ParamDesc {
+	/// Writes the parameter's current value.
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.value.fmt(f)
+	}
+}
+
+/// Tells whether the parameter names `a` and `b` are equal, dashes and underscores being
+/// equivalent.
+pub fn name_eq(a: &[u8], b: &[u8]) -> bool {
+	let normalize = |c: &u8| if *c == b'-' { b'_' } else { *c };
+	a.len() == b.len() && a.iter().map(normalize).eq(b.iter().map(normalize))
+}
+
+/// Sets the values of the parameters `params` from the argument string `args`.
+///
+/// Unknown parameters are ignored. If a value is invalid, the function returns
+/// [`errno::EINVAL`].
+pub(crate) fn apply(params: &[ParamDesc], args: &[u8]) -> EResult<()> {
+	let args = args
+		.split(|b| b.is_ascii_whitespace())
+		.filter(|arg| !arg.is_empty());
+	for arg in args {
+		let (name, val) = match arg.iter().position(|b| *b == b'=') {
+			Some(i) => (&arg[..i], Some(&arg[(i + 1)..])),
+			None => (arg, None),
+		};
+		let Some(param) = params.iter().find(|p| name_eq(p.name.as_bytes(), name)) else {
+			println!(
+				"Unknown module parameter `{}` ignored",
+				DisplayableStr(name)
+			);
+			continue;
+		};
+		if unlikely(param.value.set(val).is_err()) {
+			println!(
+				"Invalid value for module parameter `{}`: `{}`",
+				param.name,
+				DisplayableStr(val.unwrap_or_default())
+			);
+			return Err(errno!(EINVAL));
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn param_parse() {
+		assert_eq!(bool::parse(None), Some(true));
+		assert_eq!(bool::parse(Some(b"N")), Some(false));
+		assert_eq!(bool::parse(Some(b"2")), None);
+		assert_eq!(u32::parse(Some(b"42")), Some(42));
+		assert_eq!(u32::parse(Some(b"0x2a")), Some(42));
+		assert_eq!(i32::parse(Some(b"-1")), Some(-1));
+		assert_eq!(u8::parse(Some(b"256")), None);
+		assert_eq!(u32::parse(None), None);
+	}
+
+	#[test_case]
+	fn param_apply() {
+		static VERBOSE: Param<bool> = Param::new(false);
+		static RX_RING: Param<u32> = Param::new(64);
+		let params = [
+			ParamDesc {
+				name: "verbose",
+				desc: "",
+				value: &VERBOSE,
+			},
+			ParamDesc {
+				name: "rx_ring",
+				desc: "",
+				value: &RX_RING,
+			},
+		];
+		apply(&params, b" verbose  rx-ring=256 unknown=1\n").unwrap();
+		assert!(VERBOSE.get());
+		assert_eq!(RX_RING.get(), 256);
+		assert!(apply(&params, b"rx_ring=abc").is_err());
+		assert_eq!(RX_RING.get(), 256);
+	}
+}
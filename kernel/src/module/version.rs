@@ -128,10 +128,21 @@ pub struct Dependency {
 	pub name: &'static str,
 	/// The version.
 	pub version: Version,
-	/// The constraint on the version.
+	/// The constraint on the version:
+	/// - [`Ordering::Equal`]: the version of the dependency must be exactly `version`
+	/// - [`Ordering::Greater`]: the version of the dependency must be at least `version`
+	/// - [`Ordering::Less`]: the version of the dependency must be at most `version`
 	pub constraint: Ordering,
 }
 
+impl Dependency {
+	/// Tells whether the given version of the dependency satisfies the constraint.
+	pub fn is_satisfied_by(&self, version: &Version) -> bool {
+		let ord = version.cmp(&self.version);
+		ord == Ordering::Equal || ord == self.constraint
+	}
+}
+
 #[cfg(test)]
 mod test {
 	use super::*;
@@ -190,4 +201,24 @@ mod test {
 			})
 		);
 	}
+
+	#[test_case]
+	fn dependency_constraint() {
+		let dep = |constraint| Dependency {
+			name: "dep",
+			version: Version::new(1, 2, 0),
+			constraint,
+		};
+		let eq = dep(Ordering::Equal);
+		assert!(eq.is_satisfied_by(&Version::new(1, 2, 0)));
+		assert!(!eq.is_satisfied_by(&Version::new(1, 2, 1)));
+		let ge = dep(Ordering::Greater);
+		assert!(ge.is_satisfied_by(&Version::new(1, 2, 0)));
+		assert!(ge.is_satisfied_by(&Version::new(2, 0, 0)));
+		assert!(!ge.is_satisfied_by(&Version::new(1, 1, 9)));
+		let le = dep(Ordering::Less);
+		assert!(le.is_satisfied_by(&Version::new(1, 2, 0)));
+		assert!(le.is_satisfied_by(&Version::new(0, 9, 0)));
+		assert!(!le.is_satisfied_by(&Version::new(1, 3, 0)));
+	}
 }
@@ -42,6 +42,10 @@ pub const TAG_TYPE_MMAP: u32 = 6;
 pub const TAG_TYPE_FRAMEBUFFER: u32 = 8;
 /// Multiboot tag type: kernel's ELF sections
 pub const TAG_TYPE_ELF_SECTIONS: u32 = 9;
+/// Multiboot tag type: copy of the ACPI 1.0 RSDP
+pub const TAG_TYPE_ACPI_OLD: u32 = 14;
+/// Multiboot tag type: copy of the ACPI 2.0 RSDP
+pub const TAG_TYPE_ACPI_NEW: u32 = 15;
 
 /// Memory region: available
 pub const MEMORY_AVAILABLE: u32 = 1;
@@ -88,6 +92,13 @@ struct TagModule {
 	cmdline: [u8; 0],
 }
 
+#[repr(C)]
+struct TagAcpi {
+	type_: u32,
+	size: u32,
+	rsdp: [u8; 0],
+}
+
 #[repr(C)]
 struct TagBasicMeminfo {
 	type_: u32,
@@ -192,6 +203,9 @@ pub struct BootInfo {
 	///
 	/// If `None`, no initramfs is loaded.
 	pub initramfs: Option<&'static [u8]>,
+
+	/// The physical address of the copy of the ACPI RSDP given by the bootloader, if any.
+	pub rsdp: Option<PhysAddr>,
 }
 
 /// Initial framebuffer information
@@ -298,6 +312,13 @@ fn handle_tag(boot_info: &mut BootInfo, tag: &Tag) {
 			boot_info.elf_shndx = t.shndx;
 			boot_info.elf_sections = PhysAddr(t.sections.as_ptr() as usize);
 		}
+		TAG_TYPE_ACPI_OLD | TAG_TYPE_ACPI_NEW => {
+			let t: &TagAcpi = unsafe { reinterpret_tag(tag) };
+			// Prefer the newest version of the RSDP
+			if boot_info.rsdp.is_none() || t.type_ == TAG_TYPE_ACPI_NEW {
+				boot_info.rsdp = Some(PhysAddr(t.rsdp.as_ptr() as usize));
+			}
+		}
 		_ => {}
 	}
 }
@@ -18,8 +18,8 @@
 
 //! This module implements the local loopback.
 
-use super::{Address, BindAddress, Interface, MAC, buf::BufList};
-use utils::errno::EResult;
+use super::{Address, BindAddress, Interface, MAC, add_address, buf::BufList, register_iface};
+use utils::{collections::string::String, errno::EResult};
 
 /// Local loopback interfaces allows the system to write data to itself.
 pub struct LocalLoopback {}
@@ -37,20 +37,8 @@ impl Interface for LocalLoopback {
 		&[0x00; 6]
 	}
 
-	fn get_addresses(&self) -> &[BindAddress] {
-		&[
-			BindAddress {
-				addr: Address::IPv4([127, 0, 0, 1]),
-				subnet_mask: 8,
-			},
-			BindAddress {
-				addr: Address::IPv6([
-					0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-					0x00, 0x00, 0x01,
-				]),
-				subnet_mask: 128,
-			},
-		]
+	fn is_loopback(&self) -> bool {
+		true
 	}
 
 	fn read(&mut self, _buff: &mut [u8]) -> EResult<u64> {
@@ -63,3 +51,25 @@ impl Interface for LocalLoopback {
 		todo!();
 	}
 }
+
+/// Registers the local loopback interface, with its default addresses.
+pub(crate) fn init() -> EResult<()> {
+	let index = register_iface(String::try_from(b"lo")?, LocalLoopback {})?;
+	add_address(
+		index,
+		BindAddress {
+			addr: Address::IPv4([127, 0, 0, 1]),
+			subnet_mask: 8,
+		},
+	)?;
+	add_address(
+		index,
+		BindAddress {
+			addr: Address::IPv6([
+				0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+				0x00, 0x00, 0x01,
+			]),
+			subnet_mask: 128,
+		},
+	)
+}
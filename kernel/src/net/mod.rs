@@ -22,9 +22,11 @@ pub mod buf;
 pub mod icmp;
 pub mod ip;
 pub mod lo;
+pub mod netlink;
 pub mod osi;
 pub mod sockaddr;
 pub mod tcp;
+pub mod udp;
 
 use crate::{
 	file::perm::is_privileged,
@@ -32,7 +34,11 @@ use crate::{
 	sync::spin::Spin,
 };
 use buf::BufList;
-use core::{cmp::Ordering, mem::size_of};
+use core::{
+	cmp::Ordering,
+	mem::size_of,
+	sync::atomic::{AtomicU32, Ordering::Relaxed},
+};
 use utils::{
 	collections::{hashmap::HashMap, string::String, vec::Vec},
 	errno,
@@ -46,7 +52,7 @@ pub type MAC = [u8; 6];
 // TODO allow implementation of custom protocols
 
 /// An enumeration of network address types.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Address {
 	/// Internet Protocol version 4.
 	IPv4([u8; 4]),
@@ -54,8 +60,26 @@ pub enum Address {
 	IPv6([u8; 16]),
 }
 
+impl Address {
+	/// Tells whether the address is unspecified (`0.0.0.0` or `::`).
+	pub fn is_unspecified(&self) -> bool {
+		match self {
+			Self::IPv4(addr) => *addr == [0; 4],
+			Self::IPv6(addr) => *addr == [0; 16],
+		}
+	}
+
+	/// Tells whether the address is a loopback address (`127.0.0.0/8` or `::1`).
+	pub fn is_loopback(&self) -> bool {
+		match self {
+			Self::IPv4(addr) => addr[0] == 127,
+			Self::IPv6(addr) => u128::from_be_bytes(*addr) == 1,
+		}
+	}
+}
+
 /// An address/subnet mask pair to be bound to an interface.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct BindAddress {
 	/// The bound address.
 	pub addr: Address,
@@ -68,18 +92,12 @@ impl BindAddress {
 	/// address.
 	pub fn is_matching(&self, addr: &Address) -> bool {
 		fn check<const N: usize>(a: &[u8; N], b: &[u8; N], mask: usize) -> bool {
-			a.array_chunks::<4>()
-				.zip(b.array_chunks::<4>())
-				.enumerate()
-				.all(|(i, (a, b))| {
-					let a = u32::from_ne_bytes(*a);
-					let b = u32::from_ne_bytes(*b);
-
-					let order = 32 - mask.saturating_sub(i * 32);
-					let mask = !((1 << order) - 1);
-
-					(a & mask) == (b & mask)
-				})
+			a.iter().zip(b).enumerate().all(|(i, (a, b))| {
+				// Addresses are in network byte order, so the prefix starts with the first byte
+				let bits = mask.saturating_sub(i * 8).min(8) as u32;
+				let mask = !0xffu8.checked_shr(bits).unwrap_or(0);
+				(a & mask) == (b & mask)
+			})
 		}
 
 		match (&self.addr, addr) {
@@ -88,6 +106,20 @@ impl BindAddress {
 			_ => false,
 		}
 	}
+
+	/// Returns the broadcast address of the network, if any.
+	pub fn broadcast(&self) -> Option<Address> {
+		match self.addr {
+			// Point-to-point networks have no broadcast address
+			Address::IPv4(addr) if self.subnet_mask < 31 => {
+				let host = u32::MAX >> self.subnet_mask;
+				Some(Address::IPv4(
+					(u32::from_be_bytes(addr) | host).to_be_bytes(),
+				))
+			}
+			_ => None,
+		}
+	}
 }
 
 /// Trait representing a network interface.
@@ -101,8 +133,10 @@ pub trait Interface {
 	/// Returns the mac address of the interface.
 	fn get_mac(&self) -> &MAC;
 
-	/// Returns the list of addresses bound to the interface.
-	fn get_addresses(&self) -> &[BindAddress];
+	/// Tells whether the interface loops back to the local system.
+	fn is_loopback(&self) -> bool {
+		false
+	}
 
 	/// Reads data from the network interface and writes it into `buff`.
 	///
@@ -122,8 +156,8 @@ pub struct Route {
 
 	/// The name of the network interface.
 	iface: String,
-	/// The gateway's address.
-	gateway: Address,
+	/// The gateway's address. If `None`, the destination is directly reachable.
+	gateway: Option<Address>,
 
 	/// The route's metric. The route with the lowest metric has priority.
 	metric: u32,
@@ -133,7 +167,7 @@ impl Route {
 	/// Tells whether the route matches the given address.
 	pub fn is_matching(&self, addr: &Address) -> bool {
 		// Check gateway
-		if &self.gateway == addr {
+		if self.gateway.as_ref() == Some(addr) {
 			return true;
 		}
 
@@ -151,8 +185,8 @@ impl Route {
 	/// Ordering is done so that the best route is the greatest.
 	pub fn cmp_for(&self, other: &Self, addr: &Address) -> Ordering {
 		// Check gateway
-		let self_match = addr == &self.gateway;
-		let other_match = addr == &other.gateway;
+		let self_match = self.gateway.as_ref() == Some(addr);
+		let other_match = other.gateway.as_ref() == Some(addr);
 
 		self_match
 			.cmp(&other_match)
@@ -182,23 +216,46 @@ impl Route {
 	}
 }
 
+/// A registered network interface.
+pub struct InterfaceEntry {
+	/// The index of the interface, unique for the lifetime of the system.
+	pub index: u32,
+	/// The interface.
+	pub iface: Arc<Spin<dyn Interface>>,
+	/// The list of addresses bound to the interface.
+	pub addresses: Spin<Vec<BindAddress>>,
+}
+
 /// The list of network interfaces.
-pub static INTERFACES: Spin<HashMap<String, Arc<Spin<dyn Interface>>>> = Spin::new(HashMap::new());
+pub static INTERFACES: Spin<HashMap<String, InterfaceEntry>> = Spin::new(HashMap::new());
 /// The routing table.
 pub static ROUTING_TABLE: Spin<Vec<Route>> = Spin::new(Vec::new());
 
+/// The index of the next registered network interface.
+static NEXT_IFINDEX: AtomicU32 = AtomicU32::new(1);
+
 /// Registers the given network interface.
 ///
 /// Arguments:
 /// - `name` is the name of the interface.
 /// - `iface` is the interface to register.
-pub fn register_iface<I: 'static + Interface>(name: String, iface: I) -> EResult<()> {
+///
+/// The function returns the index of the interface.
+pub fn register_iface<I: 'static + Interface>(name: String, iface: I) -> EResult<u32> {
 	let mut interfaces = INTERFACES.lock();
 
+	let index = NEXT_IFINDEX.fetch_add(1, Relaxed);
 	let i = Arc::new(Spin::new(iface))?;
-	interfaces.insert(name, i)?;
-
-	Ok(())
+	interfaces.insert(
+		name,
+		InterfaceEntry {
+			index,
+			iface: i,
+			addresses: Spin::new(Vec::new()),
+		},
+	)?;
+
+	Ok(index)
 }
 
 /// Unregisters the network interface with the given name.
@@ -211,7 +268,70 @@ pub fn unregister_iface(name: &[u8]) {
 ///
 /// If the interface doesn't exist, thhe function returns `None`.
 pub fn get_iface(name: &[u8]) -> Option<Arc<Spin<dyn Interface>>> {
-	INTERFACES.lock().get(name).cloned()
+	INTERFACES.lock().get(name).map(|entry| entry.iface.clone())
+}
+
+/// Binds the address `addr` to the network interface with the given `index`.
+///
+/// If the interface does not exist, the function returns [`errno::ENODEV`]. If the address is
+/// already bound to it, the function returns [`errno::EEXIST`].
+pub fn add_address(index: u32, addr: BindAddress) -> EResult<()> {
+	let interfaces = INTERFACES.lock();
+	let (_, entry) = interfaces
+		.iter()
+		.find(|(_, entry)| entry.index == index)
+		.ok_or_else(|| errno!(ENODEV))?;
+	let mut addresses = entry.addresses.lock();
+	if addresses.contains(&addr) {
+		return Err(errno!(EEXIST));
+	}
+	addresses.push(addr)?;
+	Ok(())
+}
+
+/// Unbinds the address `addr` from the network interface with the given `index`.
+///
+/// If the interface does not exist, the function returns [`errno::ENODEV`]. If the address is
+/// not bound to it, the function returns [`errno::EADDRNOTAVAIL`].
+pub fn remove_address(index: u32, addr: &BindAddress) -> EResult<()> {
+	let interfaces = INTERFACES.lock();
+	let (_, entry) = interfaces
+		.iter()
+		.find(|(_, entry)| entry.index == index)
+		.ok_or_else(|| errno!(ENODEV))?;
+	let mut addresses = entry.addresses.lock();
+	let i = addresses
+		.iter()
+		.position(|a| a == addr)
+		.ok_or_else(|| errno!(EADDRNOTAVAIL))?;
+	addresses.remove(i);
+	Ok(())
+}
+
+/// Tells whether the address `addr` designates the local system.
+pub fn is_local(addr: &Address) -> bool {
+	if addr.is_unspecified() || addr.is_loopback() {
+		return true;
+	}
+	INTERFACES
+		.lock()
+		.iter()
+		.any(|(_, entry)| entry.addresses.lock().iter().any(|a| a.addr == *addr))
+}
+
+/// Tells whether `addr` is a broadcast address, either limited or directed to the network of an
+/// interface.
+pub fn is_broadcast(addr: &Address) -> bool {
+	if *addr == Address::IPv4([0xff; 4]) {
+		return true;
+	}
+	INTERFACES.lock().iter().any(|(_, entry)| {
+		entry
+			.addresses
+			.lock()
+			.iter()
+			.any(|a| a.broadcast() == Some(*addr))
+	})
 }
 
 /// Returns the network interface to be used to transmit a packet to the given destination address.
@@ -272,6 +392,7 @@ impl SocketDomain {
 		match self {
 			Self::AfInet => size_of::<SockAddrIn>(),
 			Self::AfInet6 => size_of::<SockAddrIn6>(),
+			Self::AfNetlink => size_of::<netlink::SockAddrNl>(),
 			// TODO add others
 			_ => 0,
 		}
@@ -341,7 +462,7 @@ impl SocketType {
 }
 
 /// Socket network stack descriptor.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct SocketDesc {
 	/// The socket's domain.
 	pub domain: SocketDomain,
@@ -350,3 +471,69 @@ pub struct SocketDesc {
 	/// The socket's protocol. `0` means using the default protocol for the domain/type pair.
 	pub protocol: i32,
 }
+
+/// Initializes the network stack.
+pub(crate) fn init() -> EResult<()> {
+	osi::init()?;
+	lo::init()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn bind_address_match_ipv4() {
+		let addr = BindAddress {
+			addr: Address::IPv4([10, 1, 2, 3]),
+			subnet_mask: 24,
+		};
+		assert!(addr.is_matching(&Address::IPv4([10, 1, 2, 254])));
+		assert!(!addr.is_matching(&Address::IPv4([10, 1, 3, 3])));
+		assert!(!addr.is_matching(&Address::IPv4([11, 1, 2, 3])));
+		let addr = BindAddress {
+			addr: Address::IPv4([172, 16, 0, 1]),
+			subnet_mask: 12,
+		};
+		assert!(addr.is_matching(&Address::IPv4([172, 31, 255, 255])));
+		assert!(!addr.is_matching(&Address::IPv4([172, 32, 0, 0])));
+	}
+
+	#[test_case]
+	fn bind_address_match_bounds() {
+		let any = BindAddress {
+			addr: Address::IPv4([0; 4]),
+			subnet_mask: 0,
+		};
+		assert!(any.is_matching(&Address::IPv4([192, 168, 1, 1])));
+		let mut addr = [0; 16];
+		addr[15] = 1;
+		let host = BindAddress {
+			addr: Address::IPv6(addr),
+			subnet_mask: 128,
+		};
+		assert!(host.is_matching(&Address::IPv6(addr)));
+		addr[15] = 2;
+		assert!(!host.is_matching(&Address::IPv6(addr)));
+		assert!(!host.is_matching(&Address::IPv4([0, 0, 0, 1])));
+	}
+
+	#[test_case]
+	fn bind_address_broadcast() {
+		let addr = BindAddress {
+			addr: Address::IPv4([192, 168, 1, 10]),
+			subnet_mask: 23,
+		};
+		assert_eq!(addr.broadcast(), Some(Address::IPv4([192, 168, 1, 255])));
+		let addr = BindAddress {
+			addr: Address::IPv4([10, 0, 0, 1]),
+			subnet_mask: 0,
+		};
+		assert_eq!(addr.broadcast(), Some(Address::IPv4([255; 4])));
+		let addr = BindAddress {
+			addr: Address::IPv4([10, 0, 0, 1]),
+			subnet_mask: 31,
+		};
+		assert_eq!(addr.broadcast(), None);
+	}
+}
@@ -0,0 +1,662 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Netlink is an interface allowing userspace to communicate with the kernel through sockets.
+//!
+//! Only the `NETLINK_ROUTE` protocol is supported, to list network interfaces and to configure
+//! their addresses and the routing table.
+//!
+//! Requests are handled synchronously when they are sent, and the replies are queued on the
+//! socket which sent them.
+
+use super::{
+	Address, BindAddress, INTERFACES, ROUTING_TABLE, Route, SocketDomain, SocketType, add_address,
+	remove_address,
+};
+use crate::file::perm::is_privileged;
+use core::{cmp::min, hint::unlikely, mem::size_of, ptr};
+use macros::AnyRepr;
+use utils::{
+	TryClone,
+	bytes::{AnyRepr, as_bytes},
+	collections::vec::Vec,
+	errno,
+	errno::{AllocResult, EResult, Errno},
+};
+
+/// Netlink protocol: routing and link configuration.
+pub const NETLINK_ROUTE: i32 = 0;
+
+/// Message type: error or acknowledgement.
+const NLMSG_ERROR: u16 = 2;
+/// Message type: end of a multipart message.
+const NLMSG_DONE: u16 = 3;
+
+/// Message flag: part of a multipart message.
+const NLM_F_MULTI: u16 = 0x2;
+/// Message flag: an acknowledgement is requested.
+const NLM_F_ACK: u16 = 0x4;
+/// Message flag: return all the matching entries.
+const NLM_F_DUMP: u16 = 0x300;
+
+/// Message type: a network interface.
+const RTM_NEWLINK: u16 = 16;
+/// Message type: list network interfaces.
+const RTM_GETLINK: u16 = 18;
+/// Message type: add an address to an interface.
+const RTM_NEWADDR: u16 = 20;
+/// Message type: remove an address from an interface.
+const RTM_DELADDR: u16 = 21;
+/// Message type: list addresses.
+const RTM_GETADDR: u16 = 22;
+/// Message type: add a route.
+const RTM_NEWROUTE: u16 = 24;
+/// Message type: remove a route.
+const RTM_DELROUTE: u16 = 25;
+/// Message type: list routes.
+const RTM_GETROUTE: u16 = 26;
+
+/// Link attribute: hardware address.
+const IFLA_ADDRESS: u16 = 1;
+/// Link attribute: interface name.
+const IFLA_IFNAME: u16 = 3;
+
+/// Address attribute: the address.
+const IFA_ADDRESS: u16 = 1;
+/// Address attribute: the local address.
+const IFA_LOCAL: u16 = 2;
+/// Address attribute: the name of the interface.
+const IFA_LABEL: u16 = 3;
+
+/// Route attribute: destination address.
+const RTA_DST: u16 = 1;
+/// Route attribute: index of the output interface.
+const RTA_OIF: u16 = 4;
+/// Route attribute: gateway address.
+const RTA_GATEWAY: u16 = 5;
+/// Route attribute: metric.
+const RTA_PRIORITY: u16 = 6;
+
+/// Interface flag: the interface is up.
+const IFF_UP: u32 = 0x1;
+/// Interface flag: the interface is a loopback.
+const IFF_LOOPBACK: u32 = 0x8;
+/// Interface flag: the interface is operational.
+const IFF_RUNNING: u32 = 0x40;
+
+/// Hardware type: Ethernet.
+const ARPHRD_ETHER: u16 = 1;
+/// Hardware type: loopback.
+const ARPHRD_LOOPBACK: u16 = 772;
+
+/// Address flag: the address does not expire.
+const IFA_F_PERMANENT: u8 = 0x80;
+
+/// Routing table: main table.
+const RT_TABLE_MAIN: u8 = 254;
+/// Route origin: installed at boot or by an administrator.
+const RTPROT_BOOT: u8 = 3;
+/// Route type: gateway or direct route.
+const RTN_UNICAST: u8 = 1;
+
+/// Scope: global.
+const RT_SCOPE_UNIVERSE: u8 = 0;
+/// Scope: directly attached link.
+const RT_SCOPE_LINK: u8 = 253;
+/// Scope: local host.
+const RT_SCOPE_HOST: u8 = 254;
+
+/// Netlink socket address.
+#[repr(C)]
+#[derive(AnyRepr, Clone, Copy, Debug)]
+pub struct SockAddrNl {
+	/// The address family (`AF_NETLINK`).
+	pub nl_family: u16,
+	/// Padding.
+	pub nl_pad: u16,
+	/// The port ID of the socket.
+	pub nl_pid: u32,
+	/// The mask of multicast groups.
+	pub nl_groups: u32,
+}
+
+/// Header of a netlink message.
+#[repr(C)]
+#[derive(AnyRepr, Clone, Copy, Debug)]
+struct NlMsgHdr {
+	/// The length of the message, including the header.
+	nlmsg_len: u32,
+	/// The type of the message.
+	nlmsg_type: u16,
+	/// Flags.
+	nlmsg_flags: u16,
+	/// Sequence number.
+	nlmsg_seq: u32,
+	/// The port ID of the sender.
+	nlmsg_pid: u32,
+}
+
+/// Description of a network interface, for `RTM_*LINK` messages.
+#[repr(C)]
+#[derive(AnyRepr, Clone, Copy, Debug)]
+struct IfInfoMsg {
+	/// The address family.
+	ifi_family: u8,
+	/// Padding.
+	__ifi_pad: u8,
+	/// The hardware type.
+	ifi_type: u16,
+	/// The index of the interface.
+	ifi_index: i32,
+	/// Interface flags.
+	ifi_flags: u32,
+	/// The mask of changed flags.
+	ifi_change: u32,
+}
+
+/// Description of an address, for `RTM_*ADDR` messages.
+#[repr(C)]
+#[derive(AnyRepr, Clone, Copy, Debug)]
+struct IfAddrMsg {
+	/// The address family.
+	ifa_family: u8,
+	/// The length of the prefix.
+	ifa_prefixlen: u8,
+	/// Address flags.
+	ifa_flags: u8,
+	/// The scope of the address.
+	ifa_scope: u8,
+	/// The index of the interface.
+	ifa_index: u32,
+}
+
+/// Description of a route, for `RTM_*ROUTE` messages.
+#[repr(C)]
+#[derive(AnyRepr, Clone, Copy, Debug)]
+struct RtMsg {
+	/// The address family.
+	rtm_family: u8,
+	/// The length of the destination prefix.
+	rtm_dst_len: u8,
+	/// The length of the source prefix.
+	rtm_src_len: u8,
+	/// Type of service.
+	rtm_tos: u8,
+	/// The routing table.
+	rtm_table: u8,
+	/// The origin of the route.
+	rtm_protocol: u8,
+	/// The scope of the destination.
+	rtm_scope: u8,
+	/// The type of the route.
+	rtm_type: u8,
+	/// Route flags.
+	rtm_flags: u32,
+}
+
+/// Header of an attribute.
+#[repr(C)]
+#[derive(AnyRepr, Clone, Copy, Debug)]
+struct RtAttr {
+	/// The length of the attribute, including the header.
+	rta_len: u16,
+	/// The type of the attribute.
+	rta_type: u16,
+}
+
+/// Aligns `len` to the alignment of netlink messages and attributes.
+fn align(len: usize) -> usize {
+	len.next_multiple_of(4)
+}
+
+/// Reads a value of type `T` at offset `off` in `buf`.
+///
+/// If `buf` is too small, the function returns `None`.
+fn read<T: AnyRepr>(buf: &[u8], off: usize) -> Option<T> {
+	let end = off.checked_add(size_of::<T>())?;
+	let bytes = buf.get(off..end)?;
+	// Messages from userspace are not necessarily aligned
+	Some(unsafe { ptr::read_unaligned(bytes.as_ptr() as *const T) })
+}
+
+/// Returns an iterator over the attributes in `buf`, as type/payload pairs.
+fn attrs(buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+	let mut off = 0;
+	core::iter::from_fn(move || {
+		let attr: RtAttr = read(buf, off)?;
+		let len = attr.rta_len as usize;
+		if len < size_of::<RtAttr>() || len > buf.len() - off {
+			return None;
+		}
+		let data = &buf[(off + size_of::<RtAttr>())..(off + len)];
+		off = min(off + align(len), buf.len());
+		Some((attr.rta_type, data))
+	})
+}
+
+/// Returns the address of family `family` in `data`.
+///
+/// If the family or the size of the address is invalid, the function returns
+/// [`errno::EINVAL`].
+fn parse_addr(family: u8, data: &[u8]) -> EResult<Address> {
+	match (SocketDomain::try_from(family as u32), data.len()) {
+		(Ok(SocketDomain::AfInet), 4) => Ok(Address::IPv4(data.try_into().unwrap())),
+		(Ok(SocketDomain::AfInet6), 16) => Ok(Address::IPv6(data.try_into().unwrap())),
+		_ => Err(errno!(EINVAL)),
+	}
+}
+
+/// Returns the address family and the bytes of `addr`.
+fn addr_bytes(addr: &Address) -> (u8, &[u8]) {
+	match addr {
+		Address::IPv4(addr) => (SocketDomain::AfInet.get_id() as _, addr),
+		Address::IPv6(addr) => (SocketDomain::AfInet6.get_id() as _, addr),
+	}
+}
+
+/// Returns the index of the network interface with the given name.
+fn iface_index(name: &[u8]) -> Option<u32> {
+	INTERFACES.lock().get(name).map(|entry| entry.index)
+}
+
+/// A netlink message being built.
+struct MessageBuilder(Vec<u8>);
+
+impl MessageBuilder {
+	/// Creates a new message of type `type` with flags `flags`, replying to the request `req`
+	/// sent by the socket with the port ID `port`.
+	fn new(req: &NlMsgHdr, port: u32, r#type: u16, flags: u16) -> AllocResult<Self> {
+		let mut msg = Self(Vec::new());
+		msg.push(&NlMsgHdr {
+			nlmsg_len: 0,
+			nlmsg_type: r#type,
+			nlmsg_flags: flags,
+			nlmsg_seq: req.nlmsg_seq,
+			nlmsg_pid: port,
+		})?;
+		Ok(msg)
+	}
+
+	/// Appends `data` to the message, followed by padding.
+	fn push_bytes(&mut self, data: &[u8]) -> AllocResult<()> {
+		self.0.extend_from_slice(data)?;
+		self.0.resize(align(self.0.len()), 0)
+	}
+
+	/// Appends `val` to the message, followed by padding.
+	fn push<T: AnyRepr>(&mut self, val: &T) -> AllocResult<()> {
+		self.push_bytes(as_bytes(val))
+	}
+
+	/// Appends the attribute of type `type` with payload `data`.
+	fn attr(&mut self, r#type: u16, data: &[u8]) -> AllocResult<()> {
+		self.push(&RtAttr {
+			rta_len: (size_of::<RtAttr>() + data.len()) as _,
+			rta_type: r#type,
+		})?;
+		self.push_bytes(data)
+	}
+
+	/// Returns the data of the message.
+	fn finish(mut self) -> Vec<u8> {
+		let len = (self.0.len() as u32).to_ne_bytes();
+		self.0[..4].copy_from_slice(&len);
+		self.0
+	}
+}
+
+/// Parses the netlink socket address `sockaddr`.
+///
+/// If the address is invalid, the function returns [`errno::EINVAL`].
+pub fn parse_sockaddr(sockaddr: &[u8]) -> EResult<SockAddrNl> {
+	let addr: SockAddrNl = read(sockaddr, 0).ok_or_else(|| errno!(EINVAL))?;
+	if unlikely(addr.nl_family as u32 != SocketDomain::AfNetlink.get_id()) {
+		return Err(errno!(EINVAL));
+	}
+	Ok(addr)
+}
+
+/// Checks that a netlink socket can be created with type `type` and protocol `protocol`.
+pub fn check_desc(r#type: SocketType, protocol: i32) -> EResult<()> {
+	if !matches!(r#type, SocketType::SockRaw | SocketType::SockDgram) {
+		return Err(errno!(ESOCKTNOSUPPORT));
+	}
+	if protocol != NETLINK_ROUTE {
+		return Err(errno!(EPROTONOSUPPORT));
+	}
+	Ok(())
+}
+
+/// Lists the network interfaces, or the one whose index is given in `payload`.
+fn get_link(req: &NlMsgHdr, port: u32, payload: &[u8], replies: &mut Vec<Vec<u8>>) -> EResult<()> {
+	let dump = req.nlmsg_flags & NLM_F_DUMP == NLM_F_DUMP;
+	let index = read::<IfInfoMsg>(payload, 0).map(|msg| msg.ifi_index as u32);
+	if unlikely(!dump && index.is_none()) {
+		return Err(errno!(EINVAL));
+	}
+	let flags = if dump { NLM_F_MULTI } else { 0 };
+	let interfaces = INTERFACES.lock();
+	let mut found = false;
+	for (name, entry) in interfaces.iter() {
+		if !dump && Some(entry.index) != index {
+			continue;
+		}
+		found = true;
+		let iface = entry.iface.lock();
+		let mut ifi_flags = 0;
+		if iface.is_up() {
+			ifi_flags |= IFF_UP | IFF_RUNNING;
+		}
+		let ifi_type = if iface.is_loopback() {
+			ifi_flags |= IFF_LOOPBACK;
+			ARPHRD_LOOPBACK
+		} else {
+			ARPHRD_ETHER
+		};
+		let mut msg = MessageBuilder::new(req, port, RTM_NEWLINK, flags)?;
+		msg.push(&IfInfoMsg {
+			ifi_family: 0,
+			__ifi_pad: 0,
+			ifi_type,
+			ifi_index: entry.index as _,
+			ifi_flags,
+			ifi_change: 0,
+		})?;
+		let mut ifname = name.try_clone()?;
+		ifname.push(b'\0')?;
+		msg.attr(IFLA_IFNAME, ifname.as_bytes())?;
+		msg.attr(IFLA_ADDRESS, iface.get_mac())?;
+		replies.push(msg.finish())?;
+	}
+	if !found {
+		return Err(errno!(ENODEV));
+	}
+	Ok(())
+}
+
+/// Lists the addresses of network interfaces with the family given in `payload`.
+fn get_addr(req: &NlMsgHdr, port: u32, payload: &[u8], replies: &mut Vec<Vec<u8>>) -> EResult<()> {
+	if unlikely(req.nlmsg_flags & NLM_F_DUMP != NLM_F_DUMP) {
+		return Err(errno!(EOPNOTSUPP));
+	}
+	// Requests may be shorter than `IfAddrMsg`, but always start with the family
+	let family = payload.first().copied().unwrap_or(0);
+	let interfaces = INTERFACES.lock();
+	for (name, entry) in interfaces.iter() {
+		let loopback = entry.iface.lock().is_loopback();
+		for addr in entry.addresses.lock().iter() {
+			let (addr_family, bytes) = addr_bytes(&addr.addr);
+			if family != 0 && family != addr_family {
+				continue;
+			}
+			let mut msg = MessageBuilder::new(req, port, RTM_NEWADDR, NLM_F_MULTI)?;
+			msg.push(&IfAddrMsg {
+				ifa_family: addr_family,
+				ifa_prefixlen: addr.subnet_mask,
+				ifa_flags: IFA_F_PERMANENT,
+				ifa_scope: if loopback {
+					RT_SCOPE_HOST
+				} else {
+					RT_SCOPE_UNIVERSE
+				},
+				ifa_index: entry.index,
+			})?;
+			msg.attr(IFA_ADDRESS, bytes)?;
+			msg.attr(IFA_LOCAL, bytes)?;
+			if matches!(addr.addr, Address::IPv4(_)) {
+				let mut label = name.try_clone()?;
+				label.push(b'\0')?;
+				msg.attr(IFA_LABEL, label.as_bytes())?;
+			}
+			replies.push(msg.finish())?;
+		}
+	}
+	Ok(())
+}
+
+/// Adds or removes the address described in `payload`, depending on `add`.
+fn set_addr(payload: &[u8], add: bool) -> EResult<()> {
+	let msg: IfAddrMsg = read(payload, 0).ok_or_else(|| errno!(EINVAL))?;
+	let max_prefix = match SocketDomain::try_from(msg.ifa_family as u32) {
+		Ok(SocketDomain::AfInet) => 32,
+		Ok(SocketDomain::AfInet6) => 128,
+		_ => return Err(errno!(EAFNOSUPPORT)),
+	};
+	if unlikely(msg.ifa_prefixlen > max_prefix) {
+		return Err(errno!(EINVAL));
+	}
+	// The local address takes precedence, as it is the address of the interface
+	let mut addr = None;
+	for (r#type, data) in attrs(&payload[align(size_of::<IfAddrMsg>())..]) {
+		match r#type {
+			IFA_LOCAL => addr = Some(parse_addr(msg.ifa_family, data)?),
+			IFA_ADDRESS if addr.is_none() => addr = Some(parse_addr(msg.ifa_family, data)?),
+			_ => {}
+		}
+	}
+	let addr = BindAddress {
+		addr: addr.ok_or_else(|| errno!(EINVAL))?,
+		subnet_mask: msg.ifa_prefixlen,
+	};
+	if add {
+		add_address(msg.ifa_index, addr)
+	} else {
+		remove_address(msg.ifa_index, &addr)
+	}
+}
+
+/// Lists the routes with the family given in `payload`.
+fn get_route(
+	req: &NlMsgHdr,
+	port: u32,
+	payload: &[u8],
+	replies: &mut Vec<Vec<u8>>,
+) -> EResult<()> {
+	// TODO support looking up the route to an address
+	if unlikely(req.nlmsg_flags & NLM_F_DUMP != NLM_F_DUMP) {
+		return Err(errno!(EOPNOTSUPP));
+	}
+	let family = payload.first().copied().unwrap_or(0);
+	let routes = ROUTING_TABLE.lock();
+	for route in routes.iter() {
+		let Some(route_family) = route
+			.dst
+			.as_ref()
+			.map(|dst| &dst.addr)
+			.or(route.gateway.as_ref())
+			.map(|addr| addr_bytes(addr).0)
+		else {
+			continue;
+		};
+		if family != 0 && family != route_family {
+			continue;
+		}
+		let mut msg = MessageBuilder::new(req, port, RTM_NEWROUTE, NLM_F_MULTI)?;
+		msg.push(&RtMsg {
+			rtm_family: route_family,
+			rtm_dst_len: route.dst.as_ref().map(|dst| dst.subnet_mask).unwrap_or(0),
+			rtm_src_len: 0,
+			rtm_tos: 0,
+			rtm_table: RT_TABLE_MAIN,
+			rtm_protocol: RTPROT_BOOT,
+			rtm_scope: if route.gateway.is_some() {
+				RT_SCOPE_UNIVERSE
+			} else {
+				RT_SCOPE_LINK
+			},
+			rtm_type: RTN_UNICAST,
+			rtm_flags: 0,
+		})?;
+		if let Some(dst) = &route.dst {
+			msg.attr(RTA_DST, addr_bytes(&dst.addr).1)?;
+		}
+		if let Some(gateway) = &route.gateway {
+			msg.attr(RTA_GATEWAY, addr_bytes(gateway).1)?;
+		}
+		if let Some(index) = iface_index(&route.iface) {
+			msg.attr(RTA_OIF, &index.to_ne_bytes())?;
+		}
+		msg.attr(RTA_PRIORITY, &route.metric.to_ne_bytes())?;
+		replies.push(msg.finish())?;
+	}
+	Ok(())
+}
+
+/// Adds or removes the route described in `payload`, depending on `add`.
+fn set_route(payload: &[u8], add: bool) -> EResult<()> {
+	let msg: RtMsg = read(payload, 0).ok_or_else(|| errno!(EINVAL))?;
+	let mut dst = None;
+	let mut gateway = None;
+	let mut oif = None;
+	let mut metric = 0;
+	for (r#type, data) in attrs(&payload[align(size_of::<RtMsg>())..]) {
+		match r#type {
+			RTA_DST => dst = Some(parse_addr(msg.rtm_family, data)?),
+			RTA_GATEWAY => gateway = Some(parse_addr(msg.rtm_family, data)?),
+			RTA_OIF => {
+				let index = data.try_into().map_err(|_| errno!(EINVAL))?;
+				oif = Some(u32::from_ne_bytes(index));
+			}
+			RTA_PRIORITY => {
+				let val = data.try_into().map_err(|_| errno!(EINVAL))?;
+				metric = u32::from_ne_bytes(val);
+			}
+			_ => {}
+		}
+	}
+	let dst = dst.map(|addr| BindAddress {
+		addr,
+		subnet_mask: msg.rtm_dst_len,
+	});
+	// Find the output interface, directly or from the network of the gateway
+	let iface = {
+		let interfaces = INTERFACES.lock();
+		interfaces
+			.iter()
+			.find(|(_, entry)| match (oif, &gateway) {
+				(Some(oif), _) => entry.index == oif,
+				(None, Some(gateway)) => entry
+					.addresses
+					.lock()
+					.iter()
+					.any(|addr| addr.is_matching(gateway)),
+				(None, None) => !add,
+			})
+			.map(|(name, _)| name.try_clone())
+			.transpose()?
+	};
+	let mut routes = ROUTING_TABLE.lock();
+	let matching = |route: &Route| {
+		route.dst == dst
+			&& (gateway.is_none() || route.gateway == gateway)
+			&& (oif.is_none() || Some(&route.iface) == iface.as_ref())
+	};
+	if add {
+		if routes.iter().any(matching) {
+			return Err(errno!(EEXIST));
+		}
+		let iface = iface.ok_or_else(|| {
+			if oif.is_some() {
+				errno!(ENODEV)
+			} else {
+				errno!(ENETUNREACH)
+			}
+		})?;
+		routes.push(Route {
+			dst,
+			iface,
+			gateway,
+			metric,
+		})?;
+	} else {
+		let i = routes
+			.iter()
+			.position(matching)
+			.ok_or_else(|| errno!(ESRCH))?;
+		routes.remove(i);
+	}
+	Ok(())
+}
+
+/// Handles the message with header `hdr` and payload `payload`, sent by the socket with the port
+/// ID `port`, pushing replies to `replies`.
+fn handle_msg(
+	hdr: &NlMsgHdr,
+	port: u32,
+	payload: &[u8],
+	replies: &mut Vec<Vec<u8>>,
+) -> EResult<()> {
+	let modify = matches!(
+		hdr.nlmsg_type,
+		RTM_NEWADDR | RTM_DELADDR | RTM_NEWROUTE | RTM_DELROUTE
+	);
+	if unlikely(modify && !is_privileged()) {
+		return Err(errno!(EPERM));
+	}
+	match hdr.nlmsg_type {
+		RTM_GETLINK => get_link(hdr, port, payload, replies)?,
+		RTM_GETADDR => get_addr(hdr, port, payload, replies)?,
+		RTM_NEWADDR => set_addr(payload, true)?,
+		RTM_DELADDR => set_addr(payload, false)?,
+		RTM_GETROUTE => get_route(hdr, port, payload, replies)?,
+		RTM_NEWROUTE => set_route(payload, true)?,
+		RTM_DELROUTE => set_route(payload, false)?,
+		_ => return Err(errno!(EOPNOTSUPP)),
+	}
+	// Terminate dumps
+	if hdr.nlmsg_flags & NLM_F_DUMP == NLM_F_DUMP {
+		let mut msg = MessageBuilder::new(hdr, port, NLMSG_DONE, NLM_F_MULTI)?;
+		msg.push(&0i32)?;
+		replies.push(msg.finish())?;
+	}
+	Ok(())
+}
+
+/// Returns an error message reporting `err` (or an acknowledgement if `None`) for the request
+/// with header `req`, sent by the socket with the port ID `port`.
+fn error_msg(req: &NlMsgHdr, port: u32, err: Option<Errno>) -> AllocResult<Vec<u8>> {
+	let mut msg = MessageBuilder::new(req, port, NLMSG_ERROR, 0)?;
+	msg.push(&-err.map(|e| e.as_int()).unwrap_or(0))?;
+	// The header of the request is echoed back
+	msg.push(req)?;
+	Ok(msg.finish())
+}
+
+/// Handles the netlink messages in `req`, sent by the socket with the port ID `port`.
+///
+/// The function returns the replies, each of which is a datagram to be received by the socket.
+pub fn handle(req: &[u8], port: u32) -> AllocResult<Vec<Vec<u8>>> {
+	let mut replies = Vec::new();
+	let mut off = 0;
+	while let Some(hdr) = read::<NlMsgHdr>(req, off) {
+		let len = hdr.nlmsg_len as usize;
+		if len < size_of::<NlMsgHdr>() || len > req.len() - off {
+			break;
+		}
+		let payload = &req[(off + size_of::<NlMsgHdr>())..(off + len)];
+		match handle_msg(&hdr, port, payload, &mut replies) {
+			Ok(()) if hdr.nlmsg_flags & NLM_F_ACK != 0 => {
+				replies.push(error_msg(&hdr, port, None)?)?
+			}
+			Ok(()) => {}
+			Err(e) => replies.push(error_msg(&hdr, port, Some(e))?)?,
+		}
+		off = min(off + align(len), req.len());
+	}
+	Ok(replies)
+}
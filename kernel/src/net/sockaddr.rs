@@ -19,8 +19,13 @@
 //! This module defines sockaddr structures used by system calls to define connection informations
 //! on sockets.
 
-use super::Address;
-use core::ffi::c_short;
+use super::{Address, SocketDomain};
+use core::{ffi::c_short, hint::unlikely, mem::size_of};
+use utils::{
+	collections::vec::Vec,
+	errno,
+	errno::{AllocResult, EResult},
+};
 
 /// Structure providing connection informations for sockets with IPv4.
 #[repr(C)]
@@ -62,7 +67,7 @@ pub struct SockAddrIn6 {
 }
 
 /// A unified structure which contains data passed from userspace.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct SockAddr {
 	/// The port used by the socket.
 	pub port: u16,
@@ -96,3 +101,41 @@ impl From<SockAddrIn6> for SockAddr {
 		}
 	}
 }
+
+impl SockAddr {
+	/// Parses the IPv4 socket address `sockaddr`, as passed by userspace.
+	///
+	/// If the address is too short, the function returns [`errno::EINVAL`]. If it is not an IPv4
+	/// address, the function returns [`errno::EAFNOSUPPORT`].
+	pub fn parse_in(sockaddr: &[u8]) -> EResult<Self> {
+		if unlikely(sockaddr.len() < size_of::<SockAddrIn>()) {
+			return Err(errno!(EINVAL));
+		}
+		let family = u16::from_ne_bytes([sockaddr[0], sockaddr[1]]);
+		if unlikely(family as u32 != SocketDomain::AfInet.get_id()) {
+			return Err(errno!(EAFNOSUPPORT));
+		}
+		Ok(Self {
+			port: u16::from_be_bytes([sockaddr[2], sockaddr[3]]),
+			addr: Address::IPv4(sockaddr[4..8].try_into().unwrap()),
+		})
+	}
+
+	/// Returns the socket address structure for the address, as passed to userspace.
+	pub fn to_bytes(&self) -> AllocResult<Vec<u8>> {
+		let (family, len) = match self.addr {
+			Address::IPv4(_) => (SocketDomain::AfInet, size_of::<SockAddrIn>()),
+			Address::IPv6(_) => (SocketDomain::AfInet6, size_of::<SockAddrIn6>()),
+		};
+		let mut buf = Vec::new();
+		buf.resize(len, 0)?;
+		buf[..2].copy_from_slice(&(family.get_id() as u16).to_ne_bytes());
+		buf[2..4].copy_from_slice(&self.port.to_be_bytes());
+		match self.addr {
+			Address::IPv4(addr) => buf[4..8].copy_from_slice(&addr),
+			// Skip the flow information
+			Address::IPv6(addr) => buf[8..24].copy_from_slice(&addr),
+		}
+		Ok(buf)
+	}
+}
@@ -0,0 +1,40 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The User Datagram Protocol (UDP) is a protocol transmitting unreliable, connectionless
+//! datagrams.
+
+use core::{ffi::c_int, ops::RangeInclusive};
+use utils::{errno, errno::EResult};
+
+/// The IP protocol number of UDP.
+pub const IPPROTO_UDP: c_int = 17;
+/// The maximum size of the payload of a datagram over IPv4.
+pub const MAX_PAYLOAD: usize = 65507;
+/// Ports below this value can only be bound by privileged processes.
+pub const PROT_SOCK: u16 = 1024;
+/// The range of ports assigned to sockets which are not bound to a specific port.
+pub const EPHEMERAL_PORTS: RangeInclusive<u16> = 32768..=60999;
+
+/// Checks that a UDP socket can be created with protocol `protocol`.
+pub fn check_desc(protocol: c_int) -> EResult<()> {
+	if protocol != 0 && protocol != IPPROTO_UDP {
+		return Err(errno!(EPROTONOSUPPORT));
+	}
+	Ok(())
+}
@@ -29,7 +29,7 @@ use crate::{
 		core_id,
 		x86::{cli, idt::IntFrame},
 	},
-	logger,
+	crash, logger,
 	memory::VirtAddr,
 	power, println, register_get,
 };
@@ -42,6 +42,7 @@ use core::{
 fn panic_impl(msg: impl fmt::Display, loc: Option<&Location>, frame: Option<&IntFrame>) -> ! {
 	cli();
 	logger::SILENT.store(false, Release);
+	logger::CONSOLE_LEVEL.store(logger::DEFAULT_CONSOLE_LEVEL, Release);
 	// Print panic
 	println!("-- KERNEL PANIC! --");
 	let cpu = core_id();
@@ -60,6 +61,9 @@ fn panic_impl(msg: impl fmt::Display, loc: Option<&Location>, frame: Option<&Int
 		}
 	}
 	// Print callstack
+	const CALLSTACK_DEPTH: usize = build_cfg!(config_panic_callstack_depth);
+	#[allow(unused_mut)]
+	let mut callstack: [VirtAddr; CALLSTACK_DEPTH] = [VirtAddr::default(); CALLSTACK_DEPTH];
 	#[cfg(debug_assertions)]
 	{
 		use crate::debug;
@@ -71,14 +75,13 @@ fn panic_impl(msg: impl fmt::Display, loc: Option<&Location>, frame: Option<&Int
 		#[cfg(target_arch = "x86_64")]
 		let frame = register_get!("rbp");
 		let frame = ptr::with_exposed_provenance(frame);
-		const CALLSTACK_DEPTH: usize = build_cfg!(config_panic_callstack_depth);
-		let mut callstack: [VirtAddr; CALLSTACK_DEPTH] = [VirtAddr::default(); CALLSTACK_DEPTH];
 		unsafe {
 			debug::get_callstack(frame, &mut callstack);
 		}
 		debug::print_callstack(&callstack);
 	}
 	println!("-- end trace --");
+	crash::dump(format_args!("{msg}"), loc, frame, &callstack);
 	#[cfg(config_debug_qemu)]
 	qemu::exit(qemu::FAILURE);
 	power::halt();
@@ -19,6 +19,7 @@
 //! This module handles system power.
 
 use crate::{
+	acpi,
 	arch::x86::{
 		apic,
 		apic::{IpiDeliveryMode, lapic_id},
@@ -71,21 +72,30 @@ fn notify_halt(log: &str) {
 	per_cpu().online.store(false, Release);
 }
 
-/// Halts the kernel until reboot.
-pub fn halt() -> ! {
-	cli();
-	notify_halt("Halting...");
+/// Halts the current core forever.
+fn halt_loop() -> ! {
 	loop {
 		cli();
 		hlt();
 	}
 }
 
+/// Halts the kernel until reboot.
+pub fn halt() -> ! {
+	cli();
+	notify_halt("Halting...");
+	halt_loop()
+}
+
 /// Powers the system down.
+///
+/// If the system cannot be powered down, it is halted instead.
 pub fn shutdown() -> ! {
 	cli();
 	notify_halt("Power down...");
-	todo!() // use ACPI to power off the system
+	acpi::power_info().shutdown();
+	println!("Power down failed, halting");
+	halt_loop()
 }
 
 /// Reboots the system.
@@ -93,7 +103,7 @@ pub fn reboot() -> ! {
 	cli();
 	notify_halt("Rebooting...");
 	// First try: ACPI
-	// TODO Use ACPI reset
+	acpi::power_info().reset();
 	// Second try: PS/2
 	loop {
 		let tmp = unsafe { inb(0x64) };
@@ -24,7 +24,7 @@
 //! Printing can be silenced at boot using the `-silent` command line argument, but logs remain in
 //! memory.
 
-use crate::logger;
+use crate::{logger, logger::Level};
 use core::fmt;
 
 /// Prints/logs the given message.
@@ -35,6 +35,14 @@ pub fn _print(args: fmt::Arguments) {
 	fmt::write(&mut *logger::BUF.lock(), args).ok();
 }
 
+/// Prints/logs the given message with the given level.
+///
+/// This function is meant to be used through the [`log!`] macro only.
+#[doc(hidden)]
+pub fn _log(level: Level, args: fmt::Arguments) {
+	logger::BUF.lock().write_level(level, args);
+}
+
 /// Prints the given formatted string with the given values.
 #[allow_internal_unstable(print_internals)]
 #[macro_export]
@@ -53,3 +61,18 @@ macro_rules! println {
 		$crate::print::_print(format_args_nl!($($arg)*));
 	}};
 }
+
+/// Logs the given formatted string with the given level, followed by a newline.
+///
+/// The level is the name of a variant of [`crate::logger::Level`]. Example:
+///
+/// ```ignore
+/// log!(Warning, "something unexpected happened: {err}");
+/// ```
+#[allow_internal_unstable(print_internals, format_args_nl)]
+#[macro_export]
+macro_rules! log {
+	($level:ident, $($arg:tt)*) => {{
+		$crate::print::_log($crate::logger::Level::$level, format_args_nl!($($arg)*));
+	}};
+}
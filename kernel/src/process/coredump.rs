@@ -0,0 +1,684 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Core dumps of processes terminated by a signal.
+//!
+//! A core dump is an ELF file of type [`ET_CORE`] describing the state of the process at the time
+//! it was killed, allowing to debug it post-mortem. It contains:
+//! - a `PT_NOTE` segment, holding the registers (`NT_PRSTATUS`) and information about the process
+//!   (`NT_PRPSINFO`)
+//! - a `PT_LOAD` segment for each memory mapping of the process
+//!
+//! The content of read-only file mappings is not dumped since it can be retrieved from the file
+//! itself.
+//!
+//! The dump is written to the `core` file, in the working directory of the process. Its size is
+//! bounded by the [`RLIMIT_CORE`] resource limit.
+
+#[cfg(target_pointer_width = "64")]
+use crate::process::scheduler::switch::save_segments;
+use crate::{
+	arch::x86::idt::IntFrame,
+	elf::{
+		EI_CLASS, EI_DATA, EI_NIDENT, EI_VERSION, ELF32ELFHeader, ELF32ProgramHeader, ELFCLASS32,
+		ELFDATA2LSB, ELFNoteHeader, EM_386, ET_CORE, EV_CURRENT, NT_PRPSINFO, NT_PRSTATUS, PF_R,
+		PF_W, PF_X, PT_LOAD, PT_NOTE,
+	},
+	file::{
+		File, FileType, O_WRONLY, Stat, vfs,
+		vfs::{ResolutionSettings, Resolved},
+	},
+	memory::{VirtAddr, cache::RcPage, user::UserSlice},
+	process::{
+		PROCESS_FLAG_NOT_DUMPABLE, Process,
+		mem_space::{MemSpace, PROT_EXEC, PROT_READ, PROT_WRITE},
+		rlimit::RLIMIT_CORE,
+		signal::Signal,
+	},
+	time::{
+		clock::{Clock, current_time_ns},
+		unit::{TimeUnit, Timeval},
+	},
+};
+use core::{
+	cmp::min,
+	hint::unlikely,
+	sync::atomic::Ordering::{Acquire, Relaxed},
+};
+use utils::{
+	bytes::as_bytes,
+	collections::{path::Path, vec::Vec},
+	errno,
+	errno::{AllocResult, EResult},
+	limits::PAGE_SIZE,
+	ptr::arc::Arc,
+	vec,
+};
+
+/// The name of the core file.
+const CORE_NAME: &[u8] = b"core";
+/// The name of notes written in core files.
+const NOTE_NAME: &[u8] = b"CORE\0";
+
+/// Process status note for 32 bit processes.
+#[repr(C)]
+struct PrStatus32 {
+	si_signo: i32,
+	si_code: i32,
+	si_errno: i32,
+	pr_cursig: i16,
+	_pad: u16,
+	pr_sigpend: u32,
+	pr_sighold: u32,
+	pr_pid: i32,
+	pr_ppid: i32,
+	pr_pgrp: i32,
+	pr_sid: i32,
+	pr_utime: [i32; 2],
+	pr_stime: [i32; 2],
+	pr_cutime: [i32; 2],
+	pr_cstime: [i32; 2],
+	/// Registers, in the order of the `user_regs_struct` structure
+	pr_reg: [u32; 17],
+	pr_fpvalid: i32,
+}
+
+/// Process information note for 32 bit processes.
+#[repr(C)]
+struct PrPsInfo32 {
+	pr_state: i8,
+	pr_sname: u8,
+	pr_zomb: i8,
+	pr_nice: i8,
+	pr_flag: u32,
+	pr_uid: u16,
+	pr_gid: u16,
+	pr_pid: i32,
+	pr_ppid: i32,
+	pr_pgrp: i32,
+	pr_sid: i32,
+	pr_fname: [u8; 16],
+	pr_psargs: [u8; 80],
+}
+
+/// Process status note for 64 bit processes.
+#[cfg(target_pointer_width = "64")]
+#[repr(C)]
+struct PrStatus64 {
+	si_signo: i32,
+	si_code: i32,
+	si_errno: i32,
+	pr_cursig: i16,
+	_pad0: u16,
+	pr_sigpend: u64,
+	pr_sighold: u64,
+	pr_pid: i32,
+	pr_ppid: i32,
+	pr_pgrp: i32,
+	pr_sid: i32,
+	pr_utime: [i64; 2],
+	pr_stime: [i64; 2],
+	pr_cutime: [i64; 2],
+	pr_cstime: [i64; 2],
+	/// Registers, in the order of the `user_regs_struct` structure
+	pr_reg: [u64; 27],
+	pr_fpvalid: i32,
+	_pad1: u32,
+}
+
+/// Process information note for 64 bit processes.
+#[cfg(target_pointer_width = "64")]
+#[repr(C)]
+struct PrPsInfo64 {
+	pr_state: i8,
+	pr_sname: u8,
+	pr_zomb: i8,
+	pr_nice: i8,
+	_pad: u32,
+	pr_flag: u64,
+	pr_uid: u32,
+	pr_gid: u32,
+	pr_pid: i32,
+	pr_ppid: i32,
+	pr_pgrp: i32,
+	pr_sid: i32,
+	pr_fname: [u8; 16],
+	pr_psargs: [u8; 80],
+}
+
+/// Information about the process, common to all classes of core files.
+struct ProcInfo {
+	sig: i32,
+	pid: i32,
+	ppid: i32,
+	pgrp: i32,
+	sigpend: u64,
+	sighold: u64,
+	utime: (i64, i64),
+	stime: (i64, i64),
+	nice: i8,
+	uid: u16,
+	gid: u16,
+	fname: [u8; 16],
+	psargs: [u8; 80],
+}
+
+impl ProcInfo {
+	/// Gathers information about the current process `proc`, killed by `sig`.
+	fn new(proc: &Process, sig: Signal, mem_space: &MemSpace) -> Self {
+		let (sigpend, sighold) = {
+			let signal = proc.signal.lock();
+			(signal.pending().0, signal.sigmask.0)
+		};
+		let (utime, stime) = {
+			let (utime, stime) = proc.cputime.get();
+			let (utime, stime) = (Timeval::from_nano(utime), Timeval::from_nano(stime));
+			(
+				(utime.tv_sec as _, utime.tv_usec as _),
+				(stime.tv_sec as _, stime.tv_usec as _),
+			)
+		};
+		let ap = proc.cred().ap;
+		let mut fname = [0; 16];
+		let name = mem_space.exe_info.exe.name.as_bytes();
+		let len = min(name.len(), fname.len() - 1);
+		fname[..len].copy_from_slice(&name[..len]);
+		// Arguments are separated by spaces
+		let mut psargs = [0; 80];
+		let args = *mem_space.exe_info.args.lock();
+		let len = min(
+			args.argv_end.0.saturating_sub(args.argv_begin.0),
+			psargs.len() - 1,
+		);
+		if let Ok(slice) = UserSlice::from_user(args.argv_begin.as_ptr(), len) {
+			let len = slice.copy_from_user(0, &mut psargs[..len]).unwrap_or(0);
+			psargs[..len]
+				.iter_mut()
+				.filter(|b| **b == b'\0')
+				.for_each(|b| *b = b' ');
+			if let Some(b) = psargs[..len].last_mut().filter(|b| **b == b' ') {
+				*b = b'\0';
+			}
+		}
+		Self {
+			sig: sig.0,
+			pid: proc.get_pid() as _,
+			ppid: proc.get_parent_pid() as _,
+			pgrp: proc.get_pgid() as _,
+			sigpend,
+			sighold,
+			utime,
+			stime,
+			nice: proc.nice.load(Relaxed),
+			uid: ap.uid,
+			gid: ap.gid,
+			fname,
+			psargs,
+		}
+	}
+}
+
+/// Appends a note of type `ty` with the descriptor `desc` to `buf`.
+fn push_note(buf: &mut Vec<u8>, ty: u32, desc: &[u8]) -> AllocResult<()> {
+	let hdr = ELFNoteHeader {
+		n_namesz: NOTE_NAME.len() as _,
+		n_descsz: desc.len() as _,
+		n_type: ty,
+	};
+	buf.extend_from_slice(as_bytes(&hdr))?;
+	for data in [NOTE_NAME, desc] {
+		buf.extend_from_slice(data)?;
+		buf.resize(buf.len().next_multiple_of(4), 0)?;
+	}
+	Ok(())
+}
+
+/// Returns the notes of a core file for a 32 bit process.
+fn notes32(info: &ProcInfo, frame: &IntFrame) -> AllocResult<Vec<u8>> {
+	let status = PrStatus32 {
+		si_signo: info.sig,
+		si_code: 0,
+		si_errno: 0,
+		pr_cursig: info.sig as _,
+		_pad: 0,
+		pr_sigpend: info.sigpend as _,
+		pr_sighold: info.sighold as _,
+		pr_pid: info.pid,
+		pr_ppid: info.ppid,
+		pr_pgrp: info.pgrp,
+		pr_sid: 0,
+		pr_utime: [info.utime.0 as _, info.utime.1 as _],
+		pr_stime: [info.stime.0 as _, info.stime.1 as _],
+		pr_cutime: [0; 2],
+		pr_cstime: [0; 2],
+		pr_reg: [
+			frame.rbx as _,
+			frame.rcx as _,
+			frame.rdx as _,
+			frame.rsi as _,
+			frame.rdi as _,
+			frame.rbp as _,
+			frame.rax as _,
+			frame.ss as _,
+			frame.ss as _,
+			frame.fs as _,
+			frame.gs as _,
+			// Not interrupted in a system call
+			u32::MAX,
+			frame.rip as _,
+			frame.cs as _,
+			frame.rflags as _,
+			frame.rsp as _,
+			frame.ss as _,
+		],
+		pr_fpvalid: 0,
+	};
+	let info = PrPsInfo32 {
+		pr_state: 0,
+		pr_sname: b'R',
+		pr_zomb: 0,
+		pr_nice: info.nice,
+		pr_flag: 0,
+		pr_uid: info.uid,
+		pr_gid: info.gid,
+		pr_pid: info.pid,
+		pr_ppid: info.ppid,
+		pr_pgrp: info.pgrp,
+		pr_sid: 0,
+		pr_fname: info.fname,
+		pr_psargs: info.psargs,
+	};
+	let mut buf = Vec::new();
+	push_note(&mut buf, NT_PRSTATUS, as_bytes(&status))?;
+	push_note(&mut buf, NT_PRPSINFO, as_bytes(&info))?;
+	Ok(buf)
+}
+
+/// Returns the notes of a core file for a 64 bit process.
+#[cfg(target_pointer_width = "64")]
+fn notes64(proc: &Process, info: &ProcInfo, frame: &IntFrame) -> AllocResult<Vec<u8>> {
+	save_segments(proc);
+	let status = PrStatus64 {
+		si_signo: info.sig,
+		si_code: 0,
+		si_errno: 0,
+		pr_cursig: info.sig as _,
+		_pad0: 0,
+		pr_sigpend: info.sigpend,
+		pr_sighold: info.sighold,
+		pr_pid: info.pid,
+		pr_ppid: info.ppid,
+		pr_pgrp: info.pgrp,
+		pr_sid: 0,
+		pr_utime: [info.utime.0, info.utime.1],
+		pr_stime: [info.stime.0, info.stime.1],
+		pr_cutime: [0; 2],
+		pr_cstime: [0; 2],
+		pr_reg: [
+			frame.r15,
+			frame.r14,
+			frame.r13,
+			frame.r12,
+			frame.rbp,
+			frame.rbx,
+			frame.r11,
+			frame.r10,
+			frame.r9,
+			frame.r8,
+			frame.rax,
+			frame.rcx,
+			frame.rdx,
+			frame.rsi,
+			frame.rdi,
+			// Not interrupted in a system call
+			u64::MAX,
+			frame.rip,
+			frame.cs,
+			frame.rflags,
+			frame.rsp,
+			frame.ss,
+			proc.fs_base.load(Relaxed),
+			proc.gs_base.load(Relaxed),
+			0,
+			0,
+			frame.fs,
+			frame.gs,
+		],
+		pr_fpvalid: 0,
+		_pad1: 0,
+	};
+	let info = PrPsInfo64 {
+		pr_state: 0,
+		pr_sname: b'R',
+		pr_zomb: 0,
+		pr_nice: info.nice,
+		_pad: 0,
+		pr_flag: 0,
+		pr_uid: info.uid as _,
+		pr_gid: info.gid as _,
+		pr_pid: info.pid,
+		pr_ppid: info.ppid,
+		pr_pgrp: info.pgrp,
+		pr_sid: 0,
+		pr_fname: info.fname,
+		pr_psargs: info.psargs,
+	};
+	let mut buf = Vec::new();
+	push_note(&mut buf, NT_PRSTATUS, as_bytes(&status))?;
+	push_note(&mut buf, NT_PRPSINFO, as_bytes(&info))?;
+	Ok(buf)
+}
+
+/// A memory mapping to be dumped in a `PT_LOAD` segment.
+struct Segment {
+	/// The address of the beginning of the mapping
+	addr: VirtAddr,
+	/// The size of the mapping in pages
+	size: usize,
+	/// The segment's flags
+	flags: u32,
+	/// The pages to dump. A page that is not present is dumped as zeros.
+	///
+	/// If empty, the content of the mapping is not dumped.
+	pages: Vec<Option<RcPage>>,
+}
+
+/// Returns the list of segments to dump for `mem_space`.
+///
+/// The function takes a reference to the pages of the mappings so that they remain valid while
+/// being written.
+fn segments(mem_space: &MemSpace) -> AllocResult<Vec<Segment>> {
+	mem_space.mappings(|mappings| {
+		let mut segments = Vec::new();
+		for (_, mapping) in mappings.iter() {
+			let mut flags = 0;
+			if mapping.prot & PROT_READ != 0 {
+				flags |= PF_R;
+			}
+			if mapping.prot & PROT_WRITE != 0 {
+				flags |= PF_W;
+			}
+			if mapping.prot & PROT_EXEC != 0 {
+				flags |= PF_X;
+			}
+			let dump = mapping.prot & PROT_READ != 0
+				&& (mapping.file.is_none() || mapping.prot & PROT_WRITE != 0);
+			let mut pages = Vec::new();
+			if dump {
+				for i in 0..mapping.size.get() {
+					pages.push(mapping.get_page(i))?;
+				}
+			}
+			segments.push(Segment {
+				addr: mapping.addr,
+				size: mapping.size.get(),
+				flags,
+				pages,
+			})?;
+		}
+		Ok(segments)
+	})
+}
+
+/// Writer of a core file, truncating the content to the size limit.
+struct CoreWriter {
+	/// The core file
+	file: Arc<File>,
+	/// The current offset in the file
+	off: u64,
+	/// The maximum size of the file
+	limit: u64,
+}
+
+impl CoreWriter {
+	/// Writes `buf` at the current offset.
+	///
+	/// If the limit is reached, the function writes as much as possible, then returns
+	/// [`errno::EFBIG`].
+	fn write(&mut self, buf: &[u8]) -> EResult<()> {
+		let len = min(buf.len() as u64, self.limit.saturating_sub(self.off)) as usize;
+		let mut i = 0;
+		while i < len {
+			let slice = unsafe { UserSlice::from_slice(&buf[i..len]) };
+			let l = self.file.ops.write(&self.file, self.off, slice)?;
+			if unlikely(l == 0) {
+				return Err(errno!(EIO));
+			}
+			i += l;
+			self.off += l as u64;
+		}
+		if unlikely(len < buf.len()) {
+			return Err(errno!(EFBIG));
+		}
+		Ok(())
+	}
+}
+
+/// Opens the core file in the working directory of the current process, truncating it.
+///
+/// If the file already exists, it must be a regular file with a single link, owned by the current
+/// process's effective user.
+fn open_core() -> EResult<Arc<File>> {
+	let rs = ResolutionSettings::cur_task(true, false);
+	let ent = match vfs::resolve_path(Path::new(CORE_NAME)?, &rs)? {
+		Resolved::Found(ent) => {
+			let stat = ent.stat();
+			let cred = Process::current().cred();
+			if unlikely(
+				stat.get_type() != Some(FileType::Regular)
+					|| stat.nlink > 1
+					|| stat.uid != cred.ap.fsuid
+					|| !cred.can_write_file(&stat, true),
+			) {
+				return Err(errno!(EPERM));
+			}
+			ent
+		}
+		Resolved::Creatable {
+			parent,
+			name,
+		} => {
+			let ts = current_time_ns(Clock::Realtime);
+			vfs::create_file(
+				parent,
+				name,
+				Stat {
+					mode: FileType::Regular.to_mode() | 0o600,
+					ctime: ts,
+					mtime: ts,
+					atime: ts,
+					..Default::default()
+				},
+			)?
+		}
+	};
+	let file = File::open(ent, O_WRONLY)?;
+	file.ops.truncate(&file, 0)?;
+	Ok(file)
+}
+
+/// Writes the core file for the current process `proc`, killed by `sig`.
+fn write_core(proc: &Process, sig: Signal, frame: &IntFrame, limit: u64) -> EResult<()> {
+	let Some(mem_space) = proc.mem_space_opt() else {
+		return Err(errno!(EINVAL));
+	};
+	let info = ProcInfo::new(proc, sig, mem_space);
+	let segments = segments(mem_space)?;
+	let phnum = segments.len() + 1;
+	// Build headers and notes
+	#[cfg(target_pointer_width = "64")]
+	let compat = frame.is_compat();
+	#[cfg(target_pointer_width = "32")]
+	let compat = true;
+	let (notes, ehsize, phentsize) = if compat {
+		(
+			notes32(&info, frame)?,
+			size_of::<ELF32ELFHeader>(),
+			size_of::<ELF32ProgramHeader>(),
+		)
+	} else {
+		#[cfg(target_pointer_width = "32")]
+		unreachable!();
+		#[cfg(target_pointer_width = "64")]
+		(
+			notes64(proc, &info, frame)?,
+			size_of::<crate::elf::ELF64ELFHeader>(),
+			size_of::<crate::elf::ELF64ProgramHeader>(),
+		)
+	};
+	let notes_off = ehsize + phnum * phentsize;
+	let data_off = (notes_off + notes.len()).next_multiple_of(PAGE_SIZE);
+	let mut hdr = Vec::with_capacity(notes_off)?;
+	let mut ident = [0; EI_NIDENT];
+	ident[..4].copy_from_slice(b"\x7fELF");
+	ident[EI_DATA] = ELFDATA2LSB;
+	ident[EI_VERSION] = EV_CURRENT;
+	if compat {
+		ident[EI_CLASS] = ELFCLASS32;
+		hdr.extend_from_slice(as_bytes(&ELF32ELFHeader {
+			e_ident: ident,
+			e_type: ET_CORE,
+			e_machine: EM_386,
+			e_version: EV_CURRENT as _,
+			e_entry: 0,
+			e_phoff: ehsize as _,
+			e_shoff: 0,
+			e_flags: 0,
+			e_ehsize: ehsize as _,
+			e_phentsize: phentsize as _,
+			e_phnum: phnum as _,
+			e_shentsize: 0,
+			e_shnum: 0,
+			e_shstrndx: 0,
+		}))?;
+		hdr.extend_from_slice(as_bytes(&ELF32ProgramHeader {
+			p_type: PT_NOTE,
+			p_offset: notes_off as _,
+			p_vaddr: 0,
+			p_paddr: 0,
+			p_filesz: notes.len() as _,
+			p_memsz: 0,
+			p_flags: 0,
+			p_align: 0,
+		}))?;
+		let mut off = data_off;
+		for seg in &segments {
+			let filesz = seg.pages.len() * PAGE_SIZE;
+			hdr.extend_from_slice(as_bytes(&ELF32ProgramHeader {
+				p_type: PT_LOAD,
+				p_offset: off as _,
+				p_vaddr: seg.addr.0 as _,
+				p_paddr: 0,
+				p_filesz: filesz as _,
+				p_memsz: (seg.size * PAGE_SIZE) as _,
+				p_flags: seg.flags,
+				p_align: PAGE_SIZE as _,
+			}))?;
+			off += filesz;
+		}
+	} else {
+		#[cfg(target_pointer_width = "64")]
+		{
+			use crate::elf::{ELF64ELFHeader, ELF64ProgramHeader, ELFCLASS64, EM_X86_64};
+
+			ident[EI_CLASS] = ELFCLASS64;
+			hdr.extend_from_slice(as_bytes(&ELF64ELFHeader {
+				e_ident: ident,
+				e_type: ET_CORE,
+				e_machine: EM_X86_64,
+				e_version: EV_CURRENT as _,
+				e_entry: 0,
+				e_phoff: ehsize as _,
+				e_shoff: 0,
+				e_flags: 0,
+				e_ehsize: ehsize as _,
+				e_phentsize: phentsize as _,
+				e_phnum: phnum as _,
+				e_shentsize: 0,
+				e_shnum: 0,
+				e_shstrndx: 0,
+			}))?;
+			hdr.extend_from_slice(as_bytes(&ELF64ProgramHeader {
+				p_type: PT_NOTE,
+				p_flags: 0,
+				p_offset: notes_off as _,
+				p_vaddr: 0,
+				p_paddr: 0,
+				p_filesz: notes.len() as _,
+				p_memsz: 0,
+				p_align: 0,
+			}))?;
+			let mut off = data_off;
+			for seg in &segments {
+				let filesz = seg.pages.len() * PAGE_SIZE;
+				hdr.extend_from_slice(as_bytes(&ELF64ProgramHeader {
+					p_type: PT_LOAD,
+					p_flags: seg.flags,
+					p_offset: off as _,
+					p_vaddr: seg.addr.0 as _,
+					p_paddr: 0,
+					p_filesz: filesz as _,
+					p_memsz: (seg.size * PAGE_SIZE) as _,
+					p_align: PAGE_SIZE as _,
+				}))?;
+				off += filesz;
+			}
+		}
+	}
+	// Write
+	let zero = vec![0u8; PAGE_SIZE]?;
+	let mut writer = CoreWriter {
+		file: open_core()?,
+		off: 0,
+		limit,
+	};
+	writer.write(&hdr)?;
+	writer.write(&notes)?;
+	writer.write(&zero[..(data_off - notes_off - notes.len())])?;
+	for page in segments.iter().flat_map(|seg| seg.pages.iter()) {
+		match page {
+			Some(page) => writer.write(page.slice())?,
+			None => writer.write(&zero)?,
+		}
+	}
+	Ok(())
+}
+
+/// Dumps the core of the current process `proc`, killed by `sig`.
+///
+/// `frame` is the state of the registers of the process at the moment it was killed.
+///
+/// If the [`RLIMIT_CORE`] limit of the process is zero, or if the process is not dumpable (see
+/// [`PROCESS_FLAG_NOT_DUMPABLE`]), the function does nothing.
+///
+/// The function returns `true` if a core file has been written, even partially.
+pub fn dump(proc: &Process, sig: Signal, frame: &IntFrame) -> bool {
+	if proc.flags.load(Acquire) & PROCESS_FLAG_NOT_DUMPABLE != 0 {
+		return false;
+	}
+	let limit = proc.rlimits.lock().0[RLIMIT_CORE].rlim_cur;
+	if limit == 0 {
+		return false;
+	}
+	match write_core(proc, sig, frame, limit) {
+		Ok(()) => true,
+		// The size limit has been reached
+		Err(e) if e.as_int() == errno::EFBIG => true,
+		Err(_) => false,
+	}
+}
@@ -22,26 +22,31 @@ use super::vdso;
 use crate::{
 	arch::x86,
 	elf::{
-		ET_DYN, ET_EXEC, PF_X, PT_GNU_STACK, PT_LOAD,
+		ET_DYN, ET_EXEC, PF_X, PT_GNU_STACK, PT_LOAD, PT_PHDR,
 		parser::{Class, ELFParser, ProgramHeader},
 	},
 	file::{
-		File, FileType, O_RDONLY,
-		perm::{AccessProfile, can_execute_file},
+		File, FileType, O_RDONLY, Stat,
+		perm::{AccessProfile, Credentials, S_ISGID, S_ISUID, can_execute_file},
 		vfs,
+		vfs::mountpoint::{FLAG_NOEXEC, FLAG_NOSUID},
 	},
 	memory::{COMPAT_PROCESS_END, PROCESS_END, VirtAddr, user::UserSlice, vmem},
 	process::{
-		USER_STACK_SIZE,
+		Process, USER_STACK_SIZE,
 		exec::{ProgramImage, vdso::MappedVDSO},
 		mem_space,
 		mem_space::{
-			MAP_ANONYMOUS, MAP_FIXED, MAP_PRIVATE, MemSpace, PROT_EXEC, PROT_READ, PROT_WRITE,
+			ExeArgs, MAP_ANONYMOUS, MAP_FIXED, MAP_PRIVATE, MemSpace, PROT_EXEC, PROT_READ,
+			PROT_WRITE,
 		},
 	},
+	rand,
+	time::CLK_TCK,
 };
 use core::{cmp::max, hint::unlikely, num::NonZeroUsize, ops::Add, ptr};
 use utils::{
+	TryClone,
 	collections::{path::Path, string::String, vec::Vec},
 	errno,
 	errno::{AllocResult, EResult},
@@ -57,7 +62,7 @@ const AT_IGNORE: i32 = 1;
 /// Entry containing a file descriptor to the application object file in case
 /// the program is run using an interpreter.
 const AT_EXECFD: i32 = 2;
-/// Entry containing a pointer to the program header table for the interpreter.
+/// Entry containing a pointer to the program header table of the program.
 const AT_PHDR: i32 = 3;
 /// The size in bytes of one entry in the program header table to which AT_PHDR
 /// points.
@@ -141,13 +146,19 @@ struct AuxEntryDesc<'s> {
 }
 
 /// Builds an auxiliary vector.
+///
+/// `compat` indicates whether userspace runs in compatibility mode.
 fn build_auxiliary<'s>(
 	exec_path: &'s Path,
 	interp_load_base: VirtAddr,
 	load_info: &ELFLoadInfo,
 	vdso: &MappedVDSO,
+	ap: &AccessProfile,
+	random: &'s [u8; 16],
+	compat: bool,
 ) -> AllocResult<Vec<AuxEntryDesc<'s>>> {
-	let ap = AccessProfile::current();
+	let secure = ap.is_secure();
+	let platform: &[u8] = if compat { b"i686" } else { b"x86_64" };
 	let mut vec = vec![
 		AuxEntryDesc {
 			a_type: AT_PHDR,
@@ -169,6 +180,10 @@ fn build_auxiliary<'s>(
 			a_type: AT_BASE,
 			a_val: AuxEntryDescValue::Number(interp_load_base.0),
 		},
+		AuxEntryDesc {
+			a_type: AT_FLAGS,
+			a_val: AuxEntryDescValue::Number(0),
+		},
 		AuxEntryDesc {
 			a_type: AT_ENTRY,
 			a_val: AuxEntryDescValue::Number(load_info.entry_point.0),
@@ -195,23 +210,27 @@ fn build_auxiliary<'s>(
 		},
 		AuxEntryDesc {
 			a_type: AT_PLATFORM,
-			a_val: AuxEntryDescValue::String(crate::NAME.as_bytes()),
+			a_val: AuxEntryDescValue::String(platform),
 		},
 		AuxEntryDesc {
 			a_type: AT_HWCAP,
 			a_val: AuxEntryDescValue::Number(x86::get_hwcap() as _),
 		},
 		AuxEntryDesc {
-			a_type: AT_SECURE,
-			a_val: AuxEntryDescValue::Number(0), // TODO
+			a_type: AT_HWCAP2,
+			a_val: AuxEntryDescValue::Number(0),
+		},
+		AuxEntryDesc {
+			a_type: AT_CLKTCK,
+			a_val: AuxEntryDescValue::Number(CLK_TCK as _),
 		},
 		AuxEntryDesc {
-			a_type: AT_BASE_PLATFORM,
-			a_val: AuxEntryDescValue::String(crate::NAME.as_bytes()),
+			a_type: AT_SECURE,
+			a_val: AuxEntryDescValue::Number(secure as _),
 		},
 		AuxEntryDesc {
 			a_type: AT_RANDOM,
-			a_val: AuxEntryDescValue::String(&[0; 16]), // TODO
+			a_val: AuxEntryDescValue::String(random),
 		},
 		AuxEntryDesc {
 			a_type: AT_EXECFN,
@@ -334,11 +353,14 @@ fn load_elf(
 					let seg_end = map_segment(file.clone(), mem_space, load_base, seg)?;
 					load_end = max(seg_end, load_end);
 					// If the segment contains the phdr, keep its address
-					if (seg.p_offset..seg.p_offset + seg.p_filesz).contains(&ehdr.e_phoff) {
+					if phdr_addr.is_null()
+						&& (seg.p_offset..seg.p_offset + seg.p_filesz).contains(&ehdr.e_phoff)
+					{
 						phdr_addr =
 							load_base + (ehdr.e_phoff - seg.p_offset + seg.p_vaddr) as usize;
 					}
 				}
+				PT_PHDR => phdr_addr = load_base + seg.p_vaddr as usize,
 				PT_GNU_STACK => exec_stack = seg.p_flags & PF_X != 0,
 				_ => {}
 			}
@@ -370,8 +392,8 @@ fn get_init_stack_size(
 	compat: bool,
 ) -> (usize, usize) {
 	let size = if compat { 4 } else { 8 };
-	// The size of the block storing the arguments and environment
-	let info_block_size = aux
+	// The size of the strings in the block storing the arguments and environment
+	let strings_size = aux
 		.iter()
 		.filter_map(|a| {
 			if let AuxEntryDescValue::String(slice) = a.a_val {
@@ -382,18 +404,19 @@ fn get_init_stack_size(
 		})
 		.chain(envp.iter().map(|e| e.len() + 1))
 		.chain(argv.iter().map(|a| a.len() + 1))
-		.sum::<usize>()
-		// Add padding before the information block allowing to preserve stack alignment
-		.next_multiple_of(size);
+		.sum::<usize>();
 	// The size of the auxiliary vector
 	let aux_size = aux.len() * (size * 2);
 	// The size of the environment pointers + null
 	let envp_size = (envp.len() + 1) * size;
 	// The size of the argument pointers + null + argc
 	let argv_size = (argv.len() + 2) * size;
-	// The total size of the stack data in bytes
-	let total_size = info_block_size + aux_size + envp_size + argv_size;
-	(info_block_size, total_size)
+	let vectors_size = aux_size + envp_size + argv_size;
+	// The total size of the stack data in bytes. The ABI requires the stack pointer to be aligned
+	// on 16 bytes at the entry point
+	let total_size = (strings_size + vectors_size).next_multiple_of(16);
+	// Padding is placed before the information block
+	(total_size - vectors_size, total_size)
 }
 
 /// Writes `val` on `stack`.
@@ -425,14 +448,14 @@ unsafe fn copy_string(stack: &mut *mut u8, str: &[u8]) {
 
 /// Initializes the stack data of the process according to the System V ABI.
 ///
-/// The start/end of `argv` and `envp` in userspace are also updated into `exe_info`.
+/// The start/end of `argv` and `envp` in userspace are also updated into `exe_args`.
 ///
 /// Arguments:
 /// - `user_stack` the pointer to the user stack
 /// - `argv` is the list of arguments
 /// - `envp` is the environment
 /// - `aux` is the auxiliary vector
-/// - `exe_info` is the execution information stored the memory space's structure
+/// - `exe_args` is the location of arguments stored in the memory space's structure
 /// - `compat` indicates whether userspace runs in compatibility mode
 ///
 /// # Safety
@@ -443,7 +466,7 @@ unsafe fn init_stack(
 	argv: &[String],
 	envp: &[String],
 	aux: &[AuxEntryDesc],
-	exe_info: &mut mem_space::ExeInfo,
+	exe_args: &mut mem_space::ExeArgs,
 	compat: bool,
 ) {
 	let (info_size, total_size) = get_init_stack_size(argv, envp, aux, compat);
@@ -452,23 +475,23 @@ unsafe fn init_stack(
 	// Push argc
 	write_val(&mut args_ptr, argv.len(), compat);
 	// Set argv
-	exe_info.argv_begin = VirtAddr::from(info_ptr);
+	exe_args.argv_begin = VirtAddr::from(info_ptr);
 	for arg in argv {
 		write_val(&mut args_ptr, info_ptr as _, compat);
 		copy_string(&mut info_ptr, arg);
 	}
 	// Set the nul byte to end argv
 	write_val(&mut args_ptr, 0, compat);
-	exe_info.argv_end = VirtAddr::from(info_ptr);
+	exe_args.argv_end = VirtAddr::from(info_ptr);
 	// Set environment
-	exe_info.envp_begin = exe_info.argv_end;
+	exe_args.envp_begin = exe_args.argv_end;
 	for var in envp {
 		write_val(&mut args_ptr, info_ptr as _, compat);
 		copy_string(&mut info_ptr, var);
 	}
 	// Set the nul bytes to end envp
 	write_val(&mut args_ptr, 0, compat);
-	exe_info.envp_end = VirtAddr::from(info_ptr);
+	exe_args.envp_end = VirtAddr::from(info_ptr);
 	// Set auxiliary vector
 	for a in aux {
 		let val = match a.a_val {
@@ -484,7 +507,28 @@ unsafe fn init_stack(
 	}
 }
 
-// TODO Handle suid and sgid
+/// Returns the credentials of the current process after executing a file with the given status.
+///
+/// If the file has the setuid (respectively setgid) bit, the effective user (respectively group)
+/// ID is set to the file's owner (respectively group). The saved and filesystem IDs are set to the
+/// effective IDs.
+///
+/// If `nosuid` is `true`, the setuid and setgid bits are ignored.
+fn exec_cred(stat: &Stat, nosuid: bool) -> AllocResult<Arc<Credentials>> {
+	let mut cred = Credentials::try_clone(&Process::current().cred())?;
+	if stat.mode & S_ISUID != 0 && !nosuid {
+		cred.ap.euid = stat.uid;
+	}
+	if stat.mode & S_ISGID != 0 && !nosuid {
+		cred.ap.egid = stat.gid;
+	}
+	cred.ap.suid = cred.ap.euid;
+	cred.ap.sgid = cred.ap.egid;
+	cred.ap.fsuid = cred.ap.euid;
+	cred.ap.fsgid = cred.ap.egid;
+	Arc::new(cred)
+}
+
 /// Builds a program image from the given executable file.
 ///
 /// Arguments:
@@ -498,9 +542,10 @@ pub fn exec(ent: Arc<vfs::Entry>, argv: Vec<String>, envp: Vec<String>) -> EResu
 	if unlikely(stat.get_type() != Some(FileType::Regular)) {
 		return Err(errno!(EACCES));
 	}
-	if unlikely(!can_execute_file(&stat, true)) {
+	if unlikely(!can_execute_file(&stat, true) || vfs::has_mount_flag(&ent, FLAG_NOEXEC)) {
 		return Err(errno!(EACCES));
 	}
+	let cred = exec_cred(&stat, vfs::has_mount_flag(&ent, FLAG_NOSUID))?;
 	// Read and parse file
 	let file = File::open(ent.clone(), O_RDONLY)?;
 	let parser = ELFParser::from_file(&file)?;
@@ -516,7 +561,7 @@ pub fn exec(ent: Arc<vfs::Entry>, argv: Vec<String>, envp: Vec<String>) -> EResu
 	// Initialize memory space
 	let load_end = load_base + parser.get_load_size();
 	let compat = parser.class() == Class::Bit32;
-	let mut mem_space = MemSpace::new(ent, load_end, compat)?;
+	let mem_space = MemSpace::new(ent, load_end, compat)?;
 	// Load program
 	let load_info = load_elf(&file, &parser, &mem_space, load_base)?;
 	let mut entry_point = load_info.entry_point;
@@ -536,15 +581,18 @@ pub fn exec(ent: Arc<vfs::Entry>, argv: Vec<String>, envp: Vec<String>) -> EResu
 		if unlikely(stat.get_type() != Some(FileType::Regular)) {
 			return Err(errno!(EACCES));
 		}
-		if unlikely(!can_execute_file(&stat, true)) {
+		if unlikely(
+			!can_execute_file(&stat, true) || vfs::has_mount_flag(&interp_ent, FLAG_NOEXEC),
+		) {
 			return Err(errno!(EACCES));
 		}
 		// Read and parse file
 		let file = File::open(interp_ent, O_RDONLY)?;
 		let parser = ELFParser::from_file(&file)?;
 		// Cannot load the interpreter at the beginning since it might be used by the program
-		// itself
-		if unlikely(parser.hdr().e_type != ET_DYN) {
+		// itself. It must also run in the same mode as the program
+		let interp_compat = parser.class() == Class::Bit32;
+		if unlikely(parser.hdr().e_type != ET_DYN || interp_compat != compat) {
 			return Err(errno!(ENOEXEC));
 		}
 		// Subtract one page to leave a space in between the stack and the interpreter
@@ -571,9 +619,20 @@ pub fn exec(ent: Arc<vfs::Entry>, argv: Vec<String>, envp: Vec<String>) -> EResu
 	let vdso = vdso::map(&mem_space, compat)?;
 	// Initialize the userspace stack
 	let exec_path = vfs::Entry::get_path(&mem_space.exe_info.exe)?;
-	let aux = build_auxiliary(&exec_path, interp_load_base, &load_info, &vdso)?;
+	// Random bytes used by the program (e.g. for stack protectors and pointer mangling)
+	let mut random = [0u8; 16];
+	rand::getrandom(UserSlice::from_slice_mut(&mut random), 0)?;
+	let aux = build_auxiliary(
+		&exec_path,
+		interp_load_base,
+		&load_info,
+		&vdso,
+		&cred.ap,
+		&random,
+		compat,
+	)?;
 	let (_, init_stack_size) = get_init_stack_size(&argv, &envp, &aux, compat);
-	let mut exe_info = mem_space.exe_info.clone();
+	let mut exe_args = ExeArgs::default();
 	MemSpace::switch(&mem_space, |_| unsafe {
 		vmem::smap_disable(|| {
 			init_stack(
@@ -581,17 +640,16 @@ pub fn exec(ent: Arc<vfs::Entry>, argv: Vec<String>, envp: Vec<String>) -> EResu
 				&argv,
 				&envp,
 				&aux,
-				&mut exe_info,
+				&mut exe_args,
 				compat,
 			);
 		});
 	});
-	// Set immutable fields
-	let m = Arc::as_mut(&mut mem_space).unwrap(); // Cannot fail since no one else hold a reference
-	m.exe_info = exe_info;
+	*mem_space.exe_info.args.lock() = exe_args;
 	Ok(ProgramImage {
 		mem_space,
 		compat,
+		cred,
 
 		entry_point,
 		user_stack: user_stack - init_stack_size,
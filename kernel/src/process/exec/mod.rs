@@ -19,21 +19,23 @@
 //! Program execution routines.
 //!
 //! Program execution is done in several stages:
-//! - Read the program
+//! - Read the program, following the interpreter lines of scripts
 //! - Parse the program
 //! - Build the memory image according to the program
 //! - Replace the process's memory with the newly created image to run it
 
 pub mod elf;
+pub mod script;
 pub mod vdso;
 
 use crate::{
 	arch::x86::idt::IntFrame,
+	file::perm::Credentials,
 	memory::VirtAddr,
-	process::{Process, mem_space::MemSpace, scheduler::cpu::per_cpu},
+	process::{PROCESS_FLAG_NOT_DUMPABLE, Process, mem_space::MemSpace, scheduler::cpu::per_cpu},
 	sync::spin::Spin,
 };
-use core::array;
+use core::{array, sync::atomic::Ordering::Release};
 use utils::{errno::EResult, ptr::arc::Arc};
 
 /// A built program image.
@@ -42,6 +44,8 @@ pub struct ProgramImage {
 	mem_space: Arc<MemSpace>,
 	/// Tells whether the program runs in compatibility mode.
 	compat: bool,
+	/// The credentials the process runs the program with.
+	cred: Arc<Credentials>,
 
 	/// A pointer to the entry point of the program.
 	entry_point: VirtAddr,
@@ -59,14 +63,13 @@ pub fn exec(frame: &mut IntFrame, image: ProgramImage) -> EResult<()> {
 	let fds = proc
 		.fd_table
 		.as_ref()
-		.map(|fds_mutex| -> EResult<_> {
-			let fds = fds_mutex.lock();
-			let new_fds = fds.duplicate(true)?;
-			Ok(Arc::new(Spin::new(new_fds))?)
-		})
+		.map(|fds| -> EResult<_> { Ok(Arc::new(fds.duplicate(true)?)?) })
 		.transpose()?;
 	let signal_handlers = Arc::new(Spin::new(array::from_fn(|_| Default::default())))?;
 	// All fallible operations succeeded, flush to process
+	if let Some(fds) = proc.fd_table.as_ref() {
+		fds.release_record_locks(proc.get_pid(), true);
+	}
 	MemSpace::bind(&image.mem_space);
 	// Safe because no other thread can execute this function at the same time for the same process
 	unsafe {
@@ -75,8 +78,15 @@ pub fn exec(frame: &mut IntFrame, image: ProgramImage) -> EResult<()> {
 		*proc.sig_handlers.get_mut() = signal_handlers;
 	}
 	*proc.active_mem_space.lock() = Some(image.mem_space);
+	// A program running in secure mode must not leak its memory in a core dump
+	if image.cred.ap.is_secure() {
+		proc.flags.fetch_or(PROCESS_FLAG_NOT_DUMPABLE, Release);
+	} else {
+		proc.flags.fetch_and(!PROCESS_FLAG_NOT_DUMPABLE, Release);
+	}
+	proc.set_cred(image.cred);
 	// Reset signals
-	proc.signal.lock().sigpending = Default::default();
+	proc.signal.lock().clear_pending();
 	proc.vfork_wake();
 	*proc.tls.lock() = Default::default();
 	// Set TSS here for the first process to be executed
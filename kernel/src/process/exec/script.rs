@@ -0,0 +1,199 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Execution of interpreted scripts.
+//!
+//! A script is a file starting with a line of the form `#!interpreter [optional-arg]`. Executing
+//! it runs `interpreter` instead, with the arguments `interpreter [optional-arg] path args...`,
+//! where `path` is the path to the script and `args` are the arguments passed to it, except the
+//! first one.
+//!
+//! The interpreter may itself be a script, up to [`INTERP_MAX`] levels.
+
+use crate::{
+	file::{File, FileType, O_RDONLY, perm::can_execute_file, vfs, vfs::mountpoint::FLAG_NOEXEC},
+	memory::user::UserSlice,
+};
+use core::hint::unlikely;
+use utils::{
+	collections::{path::Path, string::String, vec::Vec},
+	errno,
+	errno::EResult,
+	ptr::arc::Arc,
+};
+
+/// The maximum length of the interpreter line, including the `#!` prefix.
+const SHEBANG_MAX: usize = 256;
+/// The maximum number of interpreters that can be used recursively for an execution.
+const INTERP_MAX: usize = 4;
+
+/// An interpreter line.
+#[derive(Debug, Eq, PartialEq)]
+struct Shebang<'b> {
+	/// The path to the interpreter.
+	interp_path: &'b [u8],
+	/// The optional argument, passed to the interpreter before the path to the script.
+	optional_arg: Option<&'b [u8]>,
+}
+
+impl Shebang<'_> {
+	/// Pushes the arguments to `args`, in reverse order.
+	fn push_args(&self, args: &mut Vec<String>) -> EResult<()> {
+		if let Some(arg) = self.optional_arg {
+			args.push(arg.try_into()?)?;
+		}
+		args.push(self.interp_path.try_into()?)?;
+		Ok(())
+	}
+}
+
+/// Parses the interpreter line at the beginning of `buf`.
+///
+/// `truncated` tells whether the line may continue past the end of `buf`.
+///
+/// If the file is not a script, the function returns `None`. If the line is invalid, the function
+/// returns [`errno::ENOEXEC`].
+fn parse_shebang(buf: &[u8], truncated: bool) -> EResult<Option<Shebang<'_>>> {
+	let Some(line) = buf.strip_prefix(b"#!") else {
+		return Ok(None);
+	};
+	let (line, truncated) = match line.iter().position(|b| *b == b'\n') {
+		Some(end) => (&line[..end], false),
+		None => (line, truncated),
+	};
+	let line = line.trim_ascii_start();
+	let end = line
+		.iter()
+		.position(|b| matches!(*b, b' ' | b'\t'))
+		.unwrap_or(line.len());
+	let (interp_path, arg) = line.split_at(end);
+	// A truncated path would designate another file
+	if unlikely(interp_path.is_empty() || (truncated && arg.is_empty())) {
+		return Err(errno!(ENOEXEC));
+	}
+	// The remaining of the line is passed as a single argument
+	let arg = arg.trim_ascii();
+	Ok(Some(Shebang {
+		interp_path,
+		optional_arg: (!arg.is_empty()).then_some(arg),
+	}))
+}
+
+/// Reads the interpreter line of the file `ent` into `buf` and parses it.
+///
+/// If the file is not a script, the function returns `None`.
+fn read_shebang(
+	buf: &mut [u8; SHEBANG_MAX],
+	ent: Arc<vfs::Entry>,
+) -> EResult<Option<Shebang<'_>>> {
+	// Check permission. Reading the file is not required, as for programs
+	let stat = ent.stat();
+	if unlikely(stat.get_type() != Some(FileType::Regular)) {
+		return Err(errno!(EACCES));
+	}
+	if unlikely(!can_execute_file(&stat, true) || vfs::has_mount_flag(&ent, FLAG_NOEXEC)) {
+		return Err(errno!(EACCES));
+	}
+	let file = File::open(ent, O_RDONLY)?;
+	let len = file.ops.read(&file, 0, UserSlice::from_slice_mut(buf))?;
+	parse_shebang(&buf[..len], len == SHEBANG_MAX)
+}
+
+/// Resolves the program to run to execute the file `ent`, following interpreter lines.
+///
+/// Arguments:
+/// - `path` is the path to `ent`, which replaces the first argument if `ent` is a script
+/// - `argv` is the list of arguments passed to the program
+///
+/// The function returns the program to run along with its arguments.
+///
+/// If too many interpreters are nested, the function returns [`errno::ELOOP`].
+pub fn resolve(
+	mut ent: Arc<vfs::Entry>,
+	path: String,
+	mut argv: Vec<String>,
+) -> EResult<(Arc<vfs::Entry>, Vec<String>)> {
+	let mut buf = [0; SHEBANG_MAX];
+	let Some(shebang) = read_shebang(&mut buf, ent.clone())? else {
+		// Not a script, stop here
+		return Ok((ent, argv));
+	};
+	// Reverse the list, to avoid shifting everything at each push
+	argv.reverse();
+	// Swap `argv[0]` for `path`
+	if let Some(a) = argv.last_mut() {
+		*a = path;
+	} else {
+		argv.push(path)?;
+	}
+	shebang.push_args(&mut argv)?;
+	ent = vfs::get_file_from_path(Path::new(shebang.interp_path)?, true)?;
+	// Handle nested interpreters
+	let mut depth = 1;
+	while let Some(shebang) = read_shebang(&mut buf, ent.clone())? {
+		depth += 1;
+		if unlikely(depth > INTERP_MAX) {
+			return Err(errno!(ELOOP));
+		}
+		shebang.push_args(&mut argv)?;
+		ent = vfs::get_file_from_path(Path::new(shebang.interp_path)?, true)?;
+	}
+	// Put back in the original order
+	argv.reverse();
+	Ok((ent, argv))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn shebang_parse() {
+		assert_eq!(parse_shebang(b"\x7fELF", false).unwrap(), None);
+		assert_eq!(parse_shebang(b"", false).unwrap(), None);
+		assert_eq!(
+			parse_shebang(b"#!/bin/sh\necho", false).unwrap(),
+			Some(Shebang {
+				interp_path: b"/bin/sh",
+				optional_arg: None,
+			})
+		);
+		assert_eq!(
+			parse_shebang(b"#! /usr/bin/env  perl -w \n", false).unwrap(),
+			Some(Shebang {
+				interp_path: b"/usr/bin/env",
+				optional_arg: Some(b"perl -w"),
+			})
+		);
+		assert_eq!(
+			parse_shebang(b"#!/bin/sh -e", true).unwrap(),
+			Some(Shebang {
+				interp_path: b"/bin/sh",
+				optional_arg: Some(b"-e"),
+			})
+		);
+		assert_eq!(
+			parse_shebang(b"#!\n/bin/sh", false).unwrap_err().as_int(),
+			errno::ENOEXEC
+		);
+		assert_eq!(
+			parse_shebang(b"#!/bin/s", true).unwrap_err().as_int(),
+			errno::ENOEXEC
+		);
+	}
+}
@@ -24,7 +24,7 @@
 use super::gap::MemGap;
 use crate::{
 	arch::x86::paging,
-	file::File,
+	file::{File, vfs},
 	memory::{
 		PhysAddr, VirtAddr,
 		buddy::ZONE_USER,
@@ -209,6 +209,14 @@ impl MemMapping {
 		})
 	}
 
+	/// Returns the physical page present at the offset `offset` of the mapping, if any.
+	///
+	/// Pages that have not been accessed yet are not present.
+	pub fn get_page(&self, offset: usize) -> Option<RcPage> {
+		let pages = self.pages.lock();
+		pages.get(offset)?.as_deref().cloned()
+	}
+
 	/// Maps the page at the offset `offset` of the mapping, onto `mem_space`.
 	///
 	/// `write` tells whether the page has to be mapped for writing.
@@ -261,9 +269,18 @@ impl MemMapping {
 			}
 			// Mapped file
 			Some(file) => {
+				let file_off = self.off / PAGE_SIZE as u64 + offset as u64;
+				// Device memory is shared and mapped directly
+				if let Some(phys_addr) = file.ops.map_page(file, file_off)? {
+					let flags = vmem_flags(self.prot, false)
+						| paging::FLAG_CACHE_DISABLE
+						| paging::FLAG_WRITE_THROUGH;
+					mem_space.vmem.map(phys_addr, virtaddr, flags, 0);
+					shootdown_page(virtaddr, mem_space.bound_cpus());
+					return Ok(());
+				}
 				// Get page from file
 				let node = file.node();
-				let file_off = self.off / PAGE_SIZE as u64 + offset as u64;
 				let mut page = node.node_ops.read_page(node, file_off)?;
 				// If the mapping is private, we need our own copy
 				if self.flags & MAP_PRIVATE != 0 {
@@ -358,13 +375,16 @@ impl MemMapping {
 			return Ok(());
 		}
 		// TODO if locked, EBUSY
-		if self.file.is_none() {
+		let Some(file) = &self.file else {
 			return Ok(());
+		};
+		// Writes through the mapping are accounted as modifications of the file
+		if vmem.poll_dirty(self.addr, self.size.get()) {
+			vfs::update_times(file.node(), vfs::UPDATE_MTIME | vfs::UPDATE_CTIME);
 		}
 		let ts = current_time_ms(Clock::Boottime);
 		let pages = self.pages.lock();
 		for frame in pages.iter().flatten() {
-			vmem.poll_dirty(self.addr, self.size.get());
 			if sync {
 				// TODO warn on error?
 				let _ = frame.writeback(Some(ts), false);
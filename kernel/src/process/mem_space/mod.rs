@@ -32,7 +32,7 @@ use crate::{
 		core_id, x86,
 		x86::paging::{PAGE_FAULT_INSTRUCTION, PAGE_FAULT_WRITE},
 	},
-	file::{File, perm::can_write_file, vfs},
+	file::{File, FileType, aio::AioContext, perm::can_write_file, vfs},
 	memory::{
 		COMPAT_PROCESS_END, PROCESS_END, VirtAddr,
 		cache::RcPage,
@@ -44,7 +44,7 @@ use crate::{
 		mem_space::mapping::MappedPage,
 		scheduler::{cpu, cpu::per_cpu, critical},
 	},
-	sync::rwlock::IntRwLock,
+	sync::{rwlock::IntRwLock, spin::Spin},
 };
 use core::{alloc::AllocError, cmp::min, fmt, hint::unlikely, mem, num::NonZeroUsize, ptr};
 use gap::MemGap;
@@ -52,7 +52,7 @@ use mapping::MemMapping;
 use transaction::MemSpaceTransaction;
 use utils::{
 	TryClone,
-	collections::{btreemap::BTreeMap, vec::Vec},
+	collections::{btreemap::BTreeMap, hashmap::HashMap, vec::Vec},
 	errno,
 	errno::{AllocResult, CollectResult, EResult},
 	limits::PAGE_SIZE,
@@ -90,10 +90,18 @@ pub fn bound_check(addr: usize, n: usize) -> bool {
 }
 
 fn check_write_perm(file: Option<&Arc<File>>, prot: u8) -> EResult<()> {
-	if prot & PROT_WRITE != 0
-		&& let Some(file) = file
-		&& unlikely(!can_write_file(&file.stat(), true))
-	{
+	if prot & PROT_WRITE == 0 {
+		return Ok(());
+	}
+	let Some(file) = file else {
+		return Ok(());
+	};
+	let stat = file.stat();
+	if unlikely(!can_write_file(&stat, true)) {
+		return Err(errno!(EACCES));
+	}
+	// Device memory is mapped directly, even for private mappings
+	if unlikely(stat.get_type() == Some(FileType::CharDevice) && !file.can_write()) {
 		return Err(errno!(EACCES));
 	}
 	Ok(())
@@ -207,12 +215,9 @@ impl MemSpaceState {
 	}
 }
 
-/// Executable program information.
-#[derive(Clone)]
-pub struct ExeInfo {
-	/// The VFS entry of the program loaded on this memory space.
-	pub exe: Arc<vfs::Entry>,
-
+/// Location of the program's arguments and environment in userspace.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ExeArgs {
 	/// Address to the beginning of program argument.
 	pub argv_begin: VirtAddr,
 	/// Address to the end of program argument.
@@ -223,6 +228,23 @@ pub struct ExeInfo {
 	pub envp_end: VirtAddr,
 }
 
+/// Executable program information.
+pub struct ExeInfo {
+	/// The VFS entry of the program loaded on this memory space.
+	pub exe: Arc<vfs::Entry>,
+	/// The location of arguments and environment, which the program may change with `prctl`.
+	pub args: Spin<ExeArgs>,
+}
+
+impl Clone for ExeInfo {
+	fn clone(&self) -> Self {
+		Self {
+			exe: self.exe.clone(),
+			args: Spin::new(*self.args.lock()),
+		}
+	}
+}
+
 /// A virtual memory space.
 pub struct MemSpace {
 	/// The memory space's structure, used as a model for `vmem`
@@ -235,6 +257,10 @@ pub struct MemSpace {
 
 	/// Executable program information
 	pub exe_info: ExeInfo,
+	/// Asynchronous I/O contexts, by address of their ring
+	///
+	/// Contexts are not inherited on fork.
+	pub aio: Spin<HashMap<VirtAddr, Arc<AioContext>>>,
 
 	/// Bitmap of CPUs currently binding the memory space
 	bound_cpus: cpu::Bitmap,
@@ -258,12 +284,9 @@ impl MemSpace {
 
 			exe_info: ExeInfo {
 				exe,
-
-				argv_begin: Default::default(),
-				argv_end: Default::default(),
-				envp_begin: Default::default(),
-				envp_end: Default::default(),
+				args: Default::default(),
 			},
+			aio: Default::default(),
 
 			bound_cpus: cpu::Bitmap::new(false)?,
 		};
@@ -648,6 +671,7 @@ impl MemSpace {
 			vmem: unsafe { VMem::new() },
 
 			exe_info: self.exe_info.clone(),
+			aio: Default::default(),
 
 			bound_cpus,
 		})
@@ -22,9 +22,11 @@
 //! several processes to run at the same time by sharing the CPU resources using
 //! a scheduler.
 
+pub mod coredump;
 pub mod exec;
 pub mod mem_space;
 pub mod pid;
+pub mod rlimit;
 pub mod rusage;
 pub mod scheduler;
 pub mod signal;
@@ -36,25 +38,42 @@ use crate::{
 	file::{
 		File, O_RDWR,
 		fd::{FileDescriptorTable, NewFDConstraint},
-		perm::ProcessFs,
+		fs::debug,
+		perm::{Credentials, ProcessFs},
 		vfs,
 	},
 	int,
 	memory::{VirtAddr, buddy, buddy::FrameOrder, oom, user, user::UserPtr},
 	panic,
 	process::{
-		pid::{IDLE_PID, INIT_PID, PidHandle},
-		rusage::Rusage,
+		pid::{IDLE_PID, INIT_PID, PidHandle, PidMap},
+		rlimit::{RLIMIT_SIGPENDING, RLimits},
+		rusage::{CpuTime, Rusage},
 		scheduler::{
 			cpu, critical, dequeue, enqueue, preempt, switch,
 			switch::{KThreadEntry, idle_task, save_segments},
 		},
-		signal::{AltStack, SIGNALS_COUNT, SigSet, SignalAction},
+		signal::{
+			AltStack, CLD_CONTINUED, CLD_DUMPED, CLD_EXITED, CLD_KILLED, CLD_STOPPED, SI_TIMER,
+			SIGNALS_COUNT, SigInfo, SigSet, SignalAction,
+		},
 	},
 	register_get,
-	sync::{atomic::AtomicU64, rwlock::IntRwLock, spin::Spin},
-	syscall::{FromSyscallArg, wait::WEXITED},
-	time::timer::TimerManager,
+	sync::{
+		atomic::AtomicU64,
+		rcu::RcuArc,
+		rwlock::IntRwLock,
+		spin::{IntSpin, Spin},
+	},
+	syscall::{
+		FromSyscallArg,
+		wait::{WEXITED, WUNTRACED},
+	},
+	sysctl,
+	time::{
+		timer::{ITimers, TimerManager},
+		to_clock_ticks,
+	},
 };
 use core::{
 	array,
@@ -68,7 +87,7 @@ use core::{
 	ops::Deref,
 	ptr::NonNull,
 	sync::atomic::{
-		AtomicBool, AtomicI8, AtomicPtr, AtomicU8, AtomicU16, AtomicU32,
+		AtomicBool, AtomicI8, AtomicPtr, AtomicU8, AtomicU16, AtomicUsize,
 		Ordering::{Acquire, Relaxed, Release},
 	},
 };
@@ -92,10 +111,7 @@ const STATE_LOCK: u8 = 1 << 7;
 const HLT_INSTRUCTION: u8 = 0xf4;
 
 /// The path to the TTY device file.
-const TTY_DEVICE_PATH: &str = "/dev/tty";
-
-/// The default file creation mask.
-const DEFAULT_UMASK: file::Mode = 0o022;
+const TTY_DEVICE_PATH: &str = "/dev/tty1";
 
 /// The size of the userspace stack of a process in number of pages.
 const USER_STACK_SIZE: usize = 2048;
@@ -123,6 +139,9 @@ const REDZONE_SIZE: usize = 128;
 
 /// Process flag: if set, the kernel pretends to be Linux for this process
 pub const PROCESS_FLAG_LINUX: u8 = 0b1;
+/// Process flag: if set, the process does not produce core dumps since its memory may hold data
+/// its owner is not allowed to access
+pub const PROCESS_FLAG_NOT_DUMPABLE: u8 = 0b10;
 
 /// An enumeration containing possible states for a process.
 #[repr(u8)]
@@ -191,6 +210,11 @@ pub struct ForkOptions {
 	/// If `true`, the parent and child processes both share the same signal
 	/// handlers table.
 	pub share_sighand: bool,
+	/// If `true`, the parent and child processes both share the same filesystem access
+	/// information, including the file mode creation mask.
+	pub share_fs: bool,
+	/// If `true`, the parent is suspended until the child executes a program or exits.
+	pub vfork: bool,
 }
 
 /// Wrapper for the kernel stack, allowing to free it on drop.
@@ -243,13 +267,22 @@ pub struct ProcessSignal {
 	pub altstack: AltStack,
 	/// A bitfield storing the set of blocked signals
 	pub sigmask: SigSet,
+	/// The signal mask to restore once the current system call returns, if it temporarily
+	/// replaced the mask
+	saved_sigmask: Option<SigSet>,
 	/// A bitfield storing the set of pending signals
 	sigpending: SigSet,
+	/// The information of pending signals, in the order they have been sent
+	///
+	/// A pending signal may have no entry if allocating it failed.
+	sigqueue: Vec<SigInfo>,
 
 	/// The exit status of the process after exiting
 	pub exit_status: ExitStatus,
 	/// The terminating signal
 	pub termsig: u8,
+	/// Tells whether a core dump has been written when the process was terminated
+	pub coredump: bool,
 }
 
 impl ProcessSignal {
@@ -258,10 +291,13 @@ impl ProcessSignal {
 		Ok(ProcessSignal {
 			altstack: AltStack::default(),
 			sigmask: Default::default(),
+			saved_sigmask: None,
 			sigpending: Default::default(),
+			sigqueue: Vec::new(),
 
 			exit_status: 0,
 			termsig: 0,
+			coredump: false,
 		})
 	}
 
@@ -270,28 +306,105 @@ impl ProcessSignal {
 		self.sigmask.is_set(sig.0 as usize)
 	}
 
+	/// Replaces the signal mask with `mask` until the current system call returns.
+	///
+	/// The previous mask is restored by [`Self::restore_sigmask`], or by the return from the
+	/// handler of a signal delivered in the meantime.
+	pub fn set_temporary_sigmask(&mut self, mask: SigSet) {
+		let prev = mem::replace(&mut self.sigmask, mask);
+		self.saved_sigmask.get_or_insert(prev);
+	}
+
+	/// Restores the signal mask replaced by [`Self::set_temporary_sigmask`], if any.
+	pub fn restore_sigmask(&mut self) {
+		if let Some(mask) = self.saved_sigmask.take() {
+			self.sigmask = mask;
+		}
+	}
+
+	/// Returns the signal mask to be restored when returning from a signal handler, consuming
+	/// the saved mask if any.
+	pub fn take_handler_sigmask(&mut self) -> SigSet {
+		self.saved_sigmask.take().unwrap_or(self.sigmask)
+	}
+
 	/// Returns the set of signals currently pending on the process.
 	#[inline]
 	pub fn pending(&self) -> SigSet {
 		self.sigpending
 	}
 
+	/// Makes the signal described by `info` pending.
+	///
+	/// A standard signal is pending at most once: if it is already pending, the new instance is
+	/// discarded. Realtime signals are queued, in order.
+	///
+	/// Realtime signals and signals with a negative code (sent by `sigqueue`, timers, ...) are
+	/// queued only if less than `limit` signals are already queued. If a realtime signal with a
+	/// negative code cannot be queued, the function returns [`errno::EAGAIN`].
+	fn enqueue(&mut self, info: SigInfo, limit: usize) -> EResult<()> {
+		let sig = Signal(info.si_signo);
+		// A timer has at most one pending signal, which counts the overruns
+		if info.si_code == SI_TIMER
+			&& let Some(pending) = self.sigqueue.iter_mut().find(|i| {
+				i.si_signo == info.si_signo && i.si_code == SI_TIMER && i.si_pid == info.si_pid
+			}) {
+			pending.si_uid = pending.si_uid.saturating_add(1);
+			return Ok(());
+		}
+		if !sig.is_realtime() && self.sigpending.is_set(sig.0 as usize) {
+			return Ok(());
+		}
+		let overflow = (sig.is_realtime() || info.si_code < 0) && self.sigqueue.len() >= limit;
+		if overflow || self.sigqueue.push(info).is_err() {
+			// The value sent along with the signal must not be silently lost
+			if sig.is_realtime() && info.si_code < 0 {
+				return Err(errno!(EAGAIN));
+			}
+			// Else, the signal remains pending without its information
+		}
+		self.sigpending.set(sig.0 as usize);
+		Ok(())
+	}
+
+	/// Removes the oldest instance of `sig` from the pending signals and returns its
+	/// information.
+	fn take(&mut self, sig: Signal) -> SigInfo {
+		let info = self
+			.sigqueue
+			.iter()
+			.position(|info| info.si_signo == sig.0)
+			.map(|i| self.sigqueue.remove(i))
+			.unwrap_or_else(|| SigInfo::kernel(sig));
+		// Realtime signals may have more instances queued
+		if !self.sigqueue.iter().any(|info| info.si_signo == sig.0) {
+			self.sigpending.clear(sig.0 as usize);
+		}
+		info
+	}
+
+	/// Discards all pending signals.
+	pub fn clear_pending(&mut self) {
+		self.sigpending = Default::default();
+		self.sigqueue.clear();
+	}
+
 	/// Atomically dequeues the lowest-numbered pending signal that's in `set` and returns
-	/// it, or `None` if none is pending.
-	pub fn dequeue_from(&mut self, set: SigSet) -> Option<Signal> {
+	/// its information, or `None` if none is pending.
+	pub fn dequeue_from(&mut self, set: SigSet) -> Option<SigInfo> {
 		let masked = self.sigpending.0 & set.0;
 		if masked == 0 {
 			return None;
 		}
-		let bit = masked.trailing_zeros() as i32;
-		self.sigpending.clear(bit as usize);
-		Some(Signal(bit))
+		let sig = Signal(masked.trailing_zeros() as i32 + 1);
+		Some(self.take(sig))
 	}
 
-	/// Returns the ID of the next signal to be handled, clearing it from the pending signals mask.
+	/// Returns the information of the next signal to be handled, removing it from the pending
+	/// signals.
 	///
 	/// If no signal is pending, the function returns `None`.
-	pub fn next_signal(&mut self) -> Option<Signal> {
+	pub fn next_signal(&mut self) -> Option<SigInfo> {
 		if self.sigpending.is_empty() {
 			return None;
 		}
@@ -301,14 +414,12 @@ impl ProcessSignal {
 			.enumerate()
 			.filter(|(_, b)| *b)
 			.filter_map(|(i, _)| {
-				let s = Signal::try_from(i as c_int).ok()?;
-				(!s.can_catch() || !self.sigmask.is_set(i)).then_some(s)
+				let n = i + 1;
+				let s = Signal::try_from(n as c_int).ok()?;
+				(!s.can_catch() || !self.sigmask.is_set(n)).then_some(s)
 			})
 			.next();
-		if let Some(id) = sig {
-			self.sigpending.clear(id.0 as usize);
-		}
-		sig
+		sig.map(|id| self.take(id))
 	}
 }
 
@@ -325,6 +436,8 @@ pub struct Process {
 	/// [`STATE_LOCK`] write-locks the state, while allowing it to be read
 	state: AtomicU8,
 	/// If `true`, the parent can resume after a `vfork`.
+	///
+	/// This is always `true` for processes that have not been created by a `vfork`.
 	pub vfork_done: AtomicBool,
 	/// Links to other processes.
 	pub links: Spin<ProcessLinks>,
@@ -335,6 +448,10 @@ pub struct Process {
 	pub affinity: cpu::Bitmap,
 	/// Process's niceness (`-20..=19`). Defines its scheduling priority (lower = higher priority)
 	pub nice: AtomicI8,
+	/// The process's I/O priority, as set by `ioprio_set`
+	pub ioprio: AtomicU16,
+	/// The number of the process's controlling virtual terminal. If zero, the process has none
+	pub ctty: AtomicUsize,
 	/// A queue the process is inserted in when waiting on a resource
 	pub(crate) wait_queue: ListNode,
 
@@ -364,13 +481,17 @@ pub struct Process {
 	active_mem_space: Spin<Option<Arc<MemSpace>>, false>,
 
 	/// Filesystem access information
-	pub fs: Spin<ProcessFs>,
-	/// The process's current umask.
-	pub umask: AtomicU32,
+	pub fs: Arc<Spin<ProcessFs>>,
+	/// The process's credentials, read without locking.
+	cred: RcuArc<Credentials>,
+	/// Serializes updates of the credentials.
+	cred_lock: Spin<()>,
 	/// The list of open file descriptors with their respective ID.
-	fd_table: UnsafeMut<Option<Arc<Spin<FileDescriptorTable>>>>,
+	fd_table: UnsafeMut<Option<Arc<FileDescriptorTable>>>,
 	/// Process's timers, shared between all threads of the same process.
 	pub timer_manager: Arc<Spin<TimerManager>>,
+	/// The process's interval timers (`setitimer`).
+	pub itimers: IntSpin<ITimers>,
 	/// The list of signal handlers
 	pub sig_handlers: UnsafeMut<Arc<Spin<[SignalHandler; SIGNALS_COUNT]>>>,
 	/// The process's signal management structure.
@@ -380,10 +501,31 @@ pub struct Process {
 
 	/// The process's resources usage.
 	pub rusage: Spin<Rusage>,
+	/// The process's CPU time accounting.
+	pub cputime: CpuTime,
+	/// The process's resource limits.
+	pub rlimits: Spin<RLimits>,
 }
 
 /// The list of all processes on the system.
+///
+/// To get a process from its PID, use [`Process::get_by_pid`] instead, which does not take the
+/// lock.
 pub static PROCESSES: IntRwLock<BTreeMap<Pid, Arc<Process>>> = IntRwLock::new(BTreeMap::new());
+/// Processes indexed by PID, for lookups under RCU. Modified along with [`PROCESSES`], under its
+/// lock.
+static PIDS: PidMap<Process> = PidMap::new();
+
+/// Inserts `proc` in the list of processes.
+fn register(proc: &Arc<Process>) -> AllocResult<()> {
+	let mut processes = PROCESSES.write();
+	processes.insert(*proc.pid, proc.clone())?;
+	if let Err(e) = unsafe { PIDS.insert(*proc.pid, proc.clone()) } {
+		processes.remove(&*proc.pid);
+		return Err(e);
+	}
+	Ok(())
+}
 
 /// Registers process callbacks on the current CPU
 pub(crate) fn register_callbacks() -> AllocResult<()> {
@@ -458,14 +600,27 @@ pub(crate) fn register_callbacks() -> AllocResult<()> {
 		int::register_callback(0x11, callback)?;
 		int::register_callback(0x13, callback)?;
 		int::register_callback(0x0e, page_fault_callback)?;
-		int::register_callback(0x20, |_, _, _, _| preempt())?;
+		int::register_callback(0x20, |_, _, _, ring| {
+			let proc = Process::current();
+			proc.cputime.account(ring == 3);
+			proc.itimers.lock().tick(&proc);
+			scheduler::loadavg::tick();
+			preempt();
+		})?;
 	}
 	// Re-enable timer since it has been disabled by delay functions
-	timer::apic::periodic(100_000_000);
+	timer::apic::periodic(scheduler::timeslice());
 	Ok(())
 }
 
 pub(crate) fn init() -> EResult<()> {
+	sysctl::register(b"kernel.pid_max", sysctl::MODE_RW, &pid::PID_MAX)?;
+	sysctl::register(
+		b"kernel.sched_rr_timeslice_ms",
+		sysctl::MODE_RW,
+		&scheduler::Timeslice,
+	)?;
+	debug::create_file(b"sched/run_queues", debug::MODE_RO, &scheduler::RUN_QUEUES)?;
 	// Create init process
 	let proc = Process::init()?;
 	per_cpu().sched.swap_current_process(proc);
@@ -485,8 +640,7 @@ pub(crate) fn init3() -> EResult<()> {
 	let tty_file = File::open(tty_ent, O_RDWR)?;
 	let proc = Process::get_by_pid(INIT_PID).unwrap();
 	let fd_table = proc.file_descriptors();
-	let mut fd_table = fd_table.lock();
-	let (stdin_fd_id, _) = fd_table.create_fd(0, tty_file)?;
+	let stdin_fd_id = fd_table.create_fd(0, tty_file)?;
 	assert_eq!(stdin_fd_id, STDIN_FILENO);
 	fd_table.duplicate_fd(
 		STDIN_FILENO as _,
@@ -506,7 +660,7 @@ impl Process {
 	///
 	/// If the process doesn't exist, the function returns `None`.
 	pub fn get_by_pid(pid: Pid) -> Option<Arc<Self>> {
-		PROCESSES.read().get(&pid).cloned()
+		PIDS.get(pid)
 	}
 
 	/// Returns the process with TID `tid`.
@@ -551,12 +705,14 @@ impl Process {
 			tid,
 
 			state: AtomicU8::new(State::Running as _),
-			vfork_done: AtomicBool::new(false),
+			vfork_done: AtomicBool::new(true),
 			links: Default::default(),
 
 			sched_node: ListNode::default(),
 			affinity: cpu::Bitmap::new(true)?,
 			nice: AtomicI8::new(nice),
+			ioprio: AtomicU16::new(0),
+			ctty: AtomicUsize::new(0),
 			wait_queue: ListNode::default(),
 
 			kernel_stack,
@@ -574,10 +730,12 @@ impl Process {
 			mem_space: Default::default(),
 			active_mem_space: Default::default(),
 
-			fs: Spin::new(ProcessFs::dummy()?),
-			umask: Default::default(),
+			fs: Arc::new(Spin::new(ProcessFs::dummy()?))?,
+			cred: RcuArc::new(Arc::new(Credentials::default())?),
+			cred_lock: Spin::new(()),
 			fd_table: Default::default(),
 			timer_manager: Arc::new(Spin::new(TimerManager::new()?))?,
+			itimers: Default::default(),
 			sig_handlers: UnsafeMut::new(Arc::new(Spin::new(array::from_fn(|_| {
 				Default::default()
 			})))?),
@@ -585,14 +743,43 @@ impl Process {
 			parent_event: Default::default(),
 
 			rusage: Default::default(),
+			cputime: CpuTime::new(),
+			rlimits: Default::default(),
 		})?;
 		if queue {
-			PROCESSES.write().insert(*thread.pid, thread.clone())?;
+			register(&thread)?;
 			enqueue(&thread);
 		}
 		Ok(thread)
 	}
 
+	/// Creates and starts a kernel thread which is meant to turn into a userspace process by
+	/// executing a program, such as a usermode helper.
+	///
+	/// The thread is a child of the init process, which reaps it once it exits. Its working
+	/// directory is the root of the VFS.
+	///
+	/// `setup` is called with the PID of the thread before it starts, allowing to pass data to it.
+	pub fn new_helper<F: FnOnce(Pid) -> AllocResult<()>>(
+		entry: KThreadEntry,
+		setup: F,
+	) -> AllocResult<Arc<Self>> {
+		let thread = Self::new_kthread(None, entry, false)?;
+		let pid = thread.get_pid();
+		thread.nice.store(0, Relaxed);
+		*thread.fs.lock() = ProcessFs::default();
+		setup(pid)?;
+		let init_proc = Process::get_by_pid(INIT_PID).unwrap();
+		init_proc.add_child(pid)?;
+		if let Err(e) = register(&thread) {
+			init_proc.links.lock().children.retain(|p| *p != pid);
+			return Err(e);
+		}
+		thread.links.lock().parent = Some(init_proc);
+		enqueue(&thread);
+		Ok(thread)
+	}
+
 	/// Creates an idle task.
 	///
 	/// The idle task is a special process, running in kernelspace, used by the scheduler when no
@@ -611,12 +798,14 @@ impl Process {
 			tid: INIT_PID,
 
 			state: AtomicU8::new(State::Running as _),
-			vfork_done: AtomicBool::new(false),
+			vfork_done: AtomicBool::new(true),
 			links: Spin::new(ProcessLinks::default()),
 
 			sched_node: ListNode::default(),
 			affinity: cpu::Bitmap::new(true)?,
 			nice: AtomicI8::new(0),
+			ioprio: AtomicU16::new(0),
+			ctty: AtomicUsize::new(0),
 			wait_queue: ListNode::default(),
 
 			kernel_stack: KernelStack::new()?,
@@ -633,26 +822,33 @@ impl Process {
 			mem_space: UnsafeMut::new(None),
 			active_mem_space: Spin::new(None),
 
-			fs: Spin::new(ProcessFs::dummy()?),
-			umask: AtomicU32::new(DEFAULT_UMASK),
+			fs: Arc::new(Spin::new(ProcessFs::dummy()?))?,
+			cred: RcuArc::new(Arc::new(Credentials::default())?),
+			cred_lock: Spin::new(()),
 			fd_table: UnsafeMut::new(Some(Arc::new(Default::default())?)),
 			timer_manager: Arc::new(Spin::new(TimerManager::new()?))?,
+			itimers: Default::default(),
 			sig_handlers: UnsafeMut::new(Arc::new(Spin::new(array::from_fn(|_| {
 				Default::default()
 			})))?),
 			signal: Spin::new(ProcessSignal {
 				altstack: Default::default(),
 				sigmask: Default::default(),
+				saved_sigmask: None,
 				sigpending: Default::default(),
+				sigqueue: Vec::new(),
 
 				exit_status: 0,
 				termsig: 0,
+				coredump: false,
 			}),
 			parent_event: Default::default(),
 
 			rusage: Default::default(),
+			cputime: CpuTime::new(),
+			rlimits: Default::default(),
 		})?;
-		PROCESSES.write().insert(INIT_PID, proc.clone())?;
+		register(&proc)?;
 		enqueue(&proc);
 		Ok(proc)
 	}
@@ -794,13 +990,44 @@ impl Process {
 		let parent = self.links.lock().parent.clone();
 		if let Some(parent) = parent {
 			self.parent_event.fetch_or(event, Release);
-			Process::kill(&parent, Signal::SIGCHLD);
+			Process::kill_info(&parent, self.child_info(event));
+		}
+	}
+
+	/// Returns the `SIGCHLD` information describing the wait `event` that occurred on the
+	/// process.
+	pub fn child_info(&self, event: u8) -> SigInfo {
+		let (code, status) = {
+			let signal = self.signal.lock();
+			match event as i32 {
+				WEXITED if signal.termsig == 0 => (CLD_EXITED, signal.exit_status as u64),
+				WEXITED if signal.coredump => (CLD_DUMPED, signal.termsig as u64),
+				WEXITED => (CLD_KILLED, signal.termsig as u64),
+				WUNTRACED => (CLD_STOPPED, signal.termsig as u64),
+				_ => (CLD_CONTINUED, Signal::SIGCONT.0 as u64),
+			}
+		};
+		let (utime, stime) = self.cputime.total();
+		SigInfo {
+			si_signo: Signal::SIGCHLD.0,
+			si_code: code,
+			si_pid: self.get_pid() as _,
+			si_uid: self.cred().ap.uid as _,
+			si_value: status,
+			si_utime: to_clock_ticks(utime),
+			si_stime: to_clock_ticks(stime),
+			..Default::default()
 		}
 	}
 
 	/// Signals the parent that the `vfork` operation has completed.
+	///
+	/// If the process has not been created by a `vfork`, or if the parent has already been
+	/// signaled, the function does nothing.
 	pub fn vfork_wake(&self) {
-		self.vfork_done.store(true, Release);
+		if self.vfork_done.swap(true, Release) {
+			return;
+		}
 		let links = self.links.lock();
 		if let Some(parent) = &links.parent {
 			Process::wake_from(parent, State::Sleeping as u8);
@@ -810,7 +1037,7 @@ impl Process {
 	/// Tells whether the vfork operation has completed.
 	#[inline]
 	pub fn is_vfork_done(&self) -> bool {
-		self.vfork_done.load(Relaxed)
+		self.vfork_done.load(Acquire)
 	}
 
 	/// Reads the last known userspace registers state.
@@ -860,12 +1087,17 @@ impl Process {
 				.fd_table
 				.as_ref()
 				.map(|fds| -> EResult<_> {
-					let fds = fds.lock();
 					let new_fds = fds.duplicate(false)?;
-					Ok(Arc::new(Spin::new(new_fds))?)
+					Ok(Arc::new(new_fds)?)
 				})
 				.transpose()?
 		};
+		// Clone filesystem access information
+		let fs = if fork_options.share_fs {
+			parent.fs.clone()
+		} else {
+			Arc::new(Spin::new(parent.fs.lock().clone()))?
+		};
 		// Clone signal handlers
 		let sig_handlers = if fork_options.share_sighand {
 			parent.sig_handlers.get().clone()
@@ -892,7 +1124,7 @@ impl Process {
 			tid: pid_int,
 
 			state: AtomicU8::new(State::Running as _),
-			vfork_done: AtomicBool::new(false),
+			vfork_done: AtomicBool::new(!fork_options.vfork),
 			links: Spin::new(ProcessLinks {
 				parent: Some(parent.clone()),
 				group_leader: Some(group_leader.clone()),
@@ -902,6 +1134,8 @@ impl Process {
 			sched_node: ListNode::default(),
 			affinity: parent.affinity.try_clone()?,
 			nice: AtomicI8::new(0),
+			ioprio: AtomicU16::new(parent.ioprio.load(Acquire)),
+			ctty: AtomicUsize::new(parent.ctty.load(Acquire)),
 			wait_queue: ListNode::default(),
 
 			kernel_stack,
@@ -918,23 +1152,30 @@ impl Process {
 			mem_space: UnsafeMut::new(Some(mem_space.clone())),
 			active_mem_space: Spin::new(Some(mem_space)),
 
-			fs: Spin::new(parent.fs.lock().try_clone()?),
-			umask: AtomicU32::new(parent.umask.load(Relaxed)),
+			fs,
+			cred: RcuArc::new(parent.cred()),
+			cred_lock: Spin::new(()),
 			fd_table: UnsafeMut::new(fd_table),
 			// TODO if creating a thread: timer_manager: parent.timer_manager.clone(),
 			timer_manager: Arc::new(Spin::new(TimerManager::new()?))?,
+			itimers: Default::default(),
 			sig_handlers: UnsafeMut::new(sig_handlers),
 			signal: Spin::new(ProcessSignal {
 				altstack: Default::default(),
 				sigmask: parent.signal.lock().sigmask,
+				saved_sigmask: None,
 				sigpending: Default::default(),
+				sigqueue: Vec::new(),
 
 				exit_status: 0,
 				termsig: 0,
+				coredump: false,
 			}),
 			parent_event: Default::default(),
 
 			rusage: Default::default(),
+			cputime: CpuTime::new(),
+			rlimits: Spin::new(parent.rlimits.lock().clone()),
 		})?;
 		// Set FS and GS
 		save_segments(&proc);
@@ -946,7 +1187,7 @@ impl Process {
 				links.process_group.insert(i, pid_int)?;
 			}
 		}
-		PROCESSES.write().insert(*proc.pid, proc.clone())?;
+		register(&proc)?;
 		enqueue(&proc);
 		Ok(proc)
 	}
@@ -968,21 +1209,89 @@ impl Process {
 		self.mem_space.deref()
 	}
 
-	/// Returns the umask
+	/// Returns a snapshot of the process's credentials.
+	#[inline]
+	pub fn cred(&self) -> Arc<Credentials> {
+		self.cred.get()
+	}
+
+	/// Replaces the process's credentials with `cred`.
+	pub fn set_cred(&self, cred: Arc<Credentials>) {
+		let _guard = self.cred_lock.lock();
+		self.cred.swap(cred);
+	}
+
+	/// Updates the process's credentials.
+	///
+	/// `f` is called on a copy of the current credentials. If it succeeds, the copy atomically
+	/// replaces them. Concurrent updates are serialized, so that `f` can check permissions on the
+	/// credentials it modifies.
+	///
+	/// If `f` changes an effective ID, the corresponding filesystem ID follows it.
+	pub fn update_cred<F: FnOnce(&mut Credentials) -> EResult<()>>(&self, f: F) -> EResult<()> {
+		let _guard = self.cred_lock.lock();
+		let cur = self.cred.get();
+		let mut cred = Credentials::try_clone(&cur)?;
+		f(&mut cred)?;
+		if cred.ap.euid != cur.ap.euid {
+			cred.ap.fsuid = cred.ap.euid;
+		}
+		if cred.ap.egid != cur.ap.egid {
+			cred.ap.fsgid = cred.ap.egid;
+		}
+		let (old, new) = (&cur.ap, &cred.ap);
+		if (old.euid, old.egid, old.fsuid, old.fsgid) != (new.euid, new.egid, new.fsuid, new.fsgid)
+		{
+			self.flags.fetch_or(PROCESS_FLAG_NOT_DUMPABLE, Release);
+		}
+		self.cred.swap(Arc::new(cred)?);
+		Ok(())
+	}
+
+	/// Returns the file mode creation mask.
 	#[inline]
-	pub fn umask(&self) -> u32 {
-		self.umask.load(Acquire)
+	pub fn umask(&self) -> file::Mode {
+		self.fs.lock().umask
 	}
 
 	/// Returns a reference to the file descriptors table
 	#[inline]
-	pub fn file_descriptors(&self) -> Arc<Spin<FileDescriptorTable>> {
+	pub fn file_descriptors(&self) -> Arc<FileDescriptorTable> {
 		self.fd_table
 			.get()
 			.clone()
 			.expect("kernel threads don't have a file descriptor table")
 	}
 
+	/// Returns a reference to the file descriptors table, if any.
+	#[inline]
+	pub fn file_descriptors_opt(&self) -> Option<Arc<FileDescriptorTable>> {
+		self.fd_table.get().clone()
+	}
+
+	/// Gives the current process its own copy of its file descriptors table, if shared with other
+	/// processes, then returns it.
+	pub fn unshare_file_descriptors(&self) -> EResult<Arc<FileDescriptorTable>> {
+		let fds = self.file_descriptors();
+		// The table is referenced only by the process and by `fds`
+		if Arc::strong_count(&fds) <= 2 {
+			return Ok(fds);
+		}
+		let new_fds = Arc::new(fds.duplicate(false)?)?;
+		// Safe because only the process itself replaces its table
+		unsafe {
+			*self.fd_table.get_mut() = Some(new_fds.clone());
+		}
+		Ok(new_fds)
+	}
+
+	/// Releases the POSIX record locks held by the process, which is exiting.
+	pub fn release_record_locks(&self) {
+		if let Some(fds) = self.fd_table.as_ref() {
+			fds.release_record_locks(self.get_pid(), false);
+		}
+	}
+
 	/// Tells whether there is a pending signal on the process.
 	pub fn has_pending_signal(&self) -> bool {
 		let signal = self.signal.lock();
@@ -994,7 +1303,35 @@ impl Process {
 	/// If the process doesn't have a signal handler, the default action for the signal is
 	/// executed.
 	pub fn kill(this: &Arc<Self>, sig: Signal) {
+		Self::kill_info(this, SigInfo::kernel(sig));
+	}
+
+	/// Kills the process with the signal described by `info`.
+	///
+	/// If the process doesn't have a signal handler, the default action for the signal is
+	/// executed.
+	///
+	/// This function is reserved to signals generated by the kernel, which are not subject to
+	/// the queue limit. Signals sent by userspace must go through [`Self::queue_signal`].
+	pub fn kill_info(this: &Arc<Self>, info: SigInfo) {
+		let _ = Self::send_signal(this, info, usize::MAX);
+	}
+
+	/// Queues the signal described by `info` on the process, within the limit set by
+	/// `RLIMIT_SIGPENDING`.
+	///
+	/// If the signal cannot be queued, the function returns [`errno::EAGAIN`].
+	pub fn queue_signal(this: &Arc<Self>, info: SigInfo) -> EResult<()> {
+		let limit = this.rlimits.lock().0[RLIMIT_SIGPENDING].rlim_cur;
+		Self::send_signal(this, info, limit.try_into().unwrap_or(usize::MAX))
+	}
+
+	/// Makes the signal described by `info` pending on the process and wakes it up to handle
+	/// it, queueing it only if less than `limit` signals are queued.
+	fn send_signal(this: &Arc<Self>, info: SigInfo, limit: usize) -> EResult<()> {
+		let sig = Signal(info.si_signo);
 		let mut s = this.signal.lock();
+		s.enqueue(info, limit)?;
 		// Statistics
 		this.rusage.lock().ru_nsignals += 1;
 		#[cfg(feature = "strace")]
@@ -1003,13 +1340,13 @@ impl Process {
 			pid = this.get_pid(),
 			sig = sig.0
 		);
-		s.sigpending.set(sig.0 as usize);
 		// Change state so that the process can handle the signal
 		let mut mask = State::IntSleeping as u8;
 		if sig.get_default_action() == SignalAction::Continue {
 			mask |= State::Stopped as u8;
 		}
 		Self::wake_from(this, mask);
+		Ok(())
 	}
 
 	/// Kills every process in the process group.
@@ -1058,7 +1395,11 @@ impl Process {
 				links.process_group.remove(i);
 			}
 		}
-		PROCESSES.write().remove(&*this.pid);
+		let mut processes = PROCESSES.write();
+		processes.remove(&*this.pid);
+		unsafe {
+			PIDS.remove(*this.pid);
+		}
 	}
 }
 
@@ -1168,7 +1509,56 @@ pub fn exit(status: u32) {
 		"[strace {pid}] exited with status `{status}`",
 		pid = *proc.pid
 	);
-	proc.signal.lock().exit_status = status as ExitStatus;
+	{
+		let mut signal = proc.signal.lock();
+		signal.exit_status = status as ExitStatus;
+		// The process may have been stopped before
+		signal.termsig = 0;
+	}
+	proc.release_record_locks();
+	// Release timers, since they hold a reference to the process
+	proc.itimers.lock().clear();
 	set_state(State::Zombie);
 	proc.notify_parent(WEXITED as u8);
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::process::signal::SI_QUEUE;
+
+	fn queued(sig: Signal, value: u64) -> SigInfo {
+		SigInfo {
+			si_signo: sig.0,
+			si_code: SI_QUEUE,
+			si_value: value,
+			..Default::default()
+		}
+	}
+
+	#[test_case]
+	fn sigqueue_standard() {
+		let mut s = ProcessSignal::new().unwrap();
+		s.enqueue(queued(Signal::SIGUSR1, 1), usize::MAX).unwrap();
+		s.enqueue(queued(Signal::SIGUSR1, 2), usize::MAX).unwrap();
+		let info = s.dequeue_from(SigSet(!0)).unwrap();
+		assert_eq!(info.si_value, 1);
+		assert!(s.dequeue_from(SigSet(!0)).is_none());
+	}
+
+	#[test_case]
+	fn sigqueue_realtime() {
+		let mut s = ProcessSignal::new().unwrap();
+		for value in 0..3 {
+			s.enqueue(queued(Signal::SIGRTMIN, value), 3).unwrap();
+		}
+		let res = s.enqueue(queued(Signal::SIGRTMIN, 3), 3);
+		assert!(matches!(res, Err(e) if e.as_int() == errno::EAGAIN));
+		for value in 0..3 {
+			assert!(s.pending().is_set(Signal::SIGRTMIN.0 as usize));
+			let info = s.dequeue_from(SigSet(!0)).unwrap();
+			assert_eq!(info.si_value, value);
+		}
+		assert!(s.pending().is_empty());
+	}
+}
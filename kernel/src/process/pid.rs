@@ -21,9 +21,23 @@
 //! Each process must have a unique PID, thus they have to be allocated.
 //! A bitfield is used to store the used PIDs.
 
-use crate::sync::spin::Spin;
-use core::{alloc::AllocError, ops::Deref};
-use utils::{collections::id_allocator::IDAllocator, errno::AllocResult};
+use crate::{
+	sync::{rcu::RcuOptionArc, spin::Spin},
+	sysctl::IntTunable,
+};
+use core::{
+	alloc::AllocError,
+	mem::MaybeUninit,
+	ops::Deref,
+	ptr::null_mut,
+	sync::atomic::{
+		AtomicPtr,
+		Ordering::{Acquire, Release},
+	},
+};
+use utils::{
+	boxed::Box, collections::id_allocator::IDAllocator, errno::AllocResult, ptr::arc::Arc,
+};
 
 /// Type representing a Process ID. This ID is unique for every running
 /// processes.
@@ -31,6 +45,9 @@ pub type Pid = u16;
 
 /// The maximum possible PID.
 const MAX_PID: Pid = 32768;
+/// The maximum PID that can be allocated (`kernel.pid_max`).
+pub static PID_MAX: IntTunable =
+	IntTunable::new(MAX_PID as _, INIT_PID as usize + 1, MAX_PID as _);
 /// Special PID for the idle task.
 pub const IDLE_PID: Pid = 0;
 /// PID of the init process.
@@ -74,7 +91,16 @@ impl PidHandle {
 
 	/// Returns an unused PID and marks it as used.
 	pub fn unique() -> AllocResult<PidHandle> {
-		allocator_do(|allocator| allocator.alloc(None)).map(|i| PidHandle((i + 1) as _))
+		let pid = allocator_do(|allocator| {
+			let i = allocator.alloc(None)?;
+			// IDs are allocated in increasing order, so none is available below the limit
+			if i as usize >= PID_MAX.get() {
+				allocator.free(i);
+				return Err(AllocError);
+			}
+			Ok(i + 1)
+		})?;
+		Ok(PidHandle(pid as _))
 	}
 }
 
@@ -99,3 +125,119 @@ impl Drop for PidHandle {
 		});
 	}
 }
+
+/// The number of PIDs per chunk of a [`PidMap`].
+const CHUNK_SIZE: usize = 1024;
+/// The number of chunks in a [`PidMap`].
+const CHUNKS_COUNT: usize = MAX_PID as usize / CHUNK_SIZE + 1;
+
+/// A chunk of a [`PidMap`].
+type Chunk<T> = [RcuOptionArc<T>; CHUNK_SIZE];
+
+/// Map associating PIDs with values, whose lookups do not take any lock.
+///
+/// Lookups are protected by RCU, while modifications have to be serialized by the caller.
+///
+/// Chunks of the map are allocated on insertion and are never freed, so they can be accessed
+/// without synchronization.
+pub struct PidMap<T>([AtomicPtr<Chunk<T>>; CHUNKS_COUNT]);
+
+impl<T: 'static> PidMap<T> {
+	/// Creates a new empty instance.
+	pub const fn new() -> Self {
+		Self([const { AtomicPtr::new(null_mut()) }; CHUNKS_COUNT])
+	}
+
+	/// Returns the value associated with `pid`, if any.
+	///
+	/// PIDs out of the range of the map are never associated with a value.
+	pub fn get(&self, pid: Pid) -> Option<Arc<T>> {
+		let chunk = self.0.get(pid as usize / CHUNK_SIZE)?.load(Acquire);
+		let chunk = unsafe { chunk.as_ref()? };
+		chunk[pid as usize % CHUNK_SIZE].get()
+	}
+
+	/// Associates `val` with `pid`, returning the previous value.
+	///
+	/// If `pid` is out of the range of the map, the function returns an error.
+	///
+	/// # Safety
+	///
+	/// Modifications of the map must be serialized.
+	pub unsafe fn insert(&self, pid: Pid, val: Arc<T>) -> AllocResult<Option<Arc<T>>> {
+		let slot = self.0.get(pid as usize / CHUNK_SIZE).ok_or(AllocError)?;
+		let mut chunk = slot.load(Acquire);
+		if chunk.is_null() {
+			let mut new = Box::<MaybeUninit<Chunk<T>>>::new_uninit()?;
+			let ptr = new.as_mut_ptr() as *mut RcuOptionArc<T>;
+			for i in 0..CHUNK_SIZE {
+				unsafe {
+					ptr.add(i).write(RcuOptionArc::new(None));
+				}
+			}
+			chunk = Box::into_raw(unsafe { new.assume_init() });
+			slot.store(chunk, Release);
+		}
+		let chunk = unsafe { &*chunk };
+		Ok(chunk[pid as usize % CHUNK_SIZE].swap(Some(val)))
+	}
+
+	/// Removes the value associated with `pid`, and returns it.
+	///
+	/// # Safety
+	///
+	/// Modifications of the map must be serialized.
+	pub unsafe fn remove(&self, pid: Pid) -> Option<Arc<T>> {
+		let chunk = self.0.get(pid as usize / CHUNK_SIZE)?.load(Acquire);
+		let chunk = unsafe { chunk.as_ref()? };
+		chunk[pid as usize % CHUNK_SIZE].swap(None)
+	}
+}
+
+impl<T: 'static> Default for PidMap<T> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<T> Drop for PidMap<T> {
+	fn drop(&mut self) {
+		for chunk in &self.0 {
+			let chunk = chunk.load(Acquire);
+			if !chunk.is_null() {
+				drop(unsafe { Box::from_raw(chunk) });
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn pid_map() {
+		let map = PidMap::new();
+		assert!(map.get(42).is_none());
+		let val = Arc::new(42u32).unwrap();
+		unsafe {
+			assert!(map.insert(42, val.clone()).unwrap().is_none());
+		}
+		assert_eq!(map.get(42).as_deref(), Some(&42));
+		assert!(map.get(42 + CHUNK_SIZE as Pid).is_none());
+		let old = unsafe { map.remove(42) }.unwrap();
+		assert_eq!(Arc::as_ptr(&old), Arc::as_ptr(&val));
+		assert!(map.get(42).is_none());
+		assert!(unsafe { map.remove(MAX_PID) }.is_none());
+	}
+
+	#[test_case]
+	fn pid_map_out_of_range() {
+		let map = PidMap::new();
+		assert!(map.get(Pid::MAX).is_none());
+		assert!(unsafe { map.remove(Pid::MAX) }.is_none());
+		let val = Arc::new(42u32).unwrap();
+		assert!(unsafe { map.insert(Pid::MAX, val) }.is_err());
+		assert!(map.get(Pid::MAX).is_none());
+	}
+}
@@ -0,0 +1,107 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Resource limits of processes.
+
+use utils::limits::{OPEN_MAX, SIGQUEUE_MAX};
+
+/// The amount of seconds of CPU time the process can consume.
+pub const RLIMIT_CPU: usize = 0;
+/// The maximum size of a file the process may create, in bytes.
+pub const RLIMIT_FSIZE: usize = 1;
+/// The maximum size of the process's data segment in bytes, rounded down to the
+/// page size.
+pub const RLIMIT_DATA: usize = 2;
+/// The maximum size of the process stack, in bytes.
+pub const RLIMIT_STACK: usize = 3;
+/// The maximum size of a core file the process may dump in bytes.
+pub const RLIMIT_CORE: usize = 4;
+/// A limit on the process's resident set (the number of virtual pages resident in RAM).
+pub const RLIMIT_RSS: usize = 5;
+/// The limit on the number of threads for the real user ID of the calling process.
+pub const RLIMIT_NPROC: usize = 6;
+/// A value one greater than the maximum number of file descriptors that can be
+/// open by the process.
+pub const RLIMIT_NOFILE: usize = 7;
+/// The maximum number of butes of memory that may be locked into RAM.
+pub const RLIMIT_MEMLOCK: usize = 8;
+/// The maximum size of the memory space in bytes, rounded down to the page
+/// size.
+pub const RLIMIT_AS: usize = 9;
+/// The limit on the combined number of flock(2) locks and fcntl(2) leases the
+/// process may establish.
+pub const RLIMIT_LOCKS: usize = 10;
+/// The limit on the number of signals that may be queued for the real user ID of the calling
+/// process.
+pub const RLIMIT_SIGPENDING: usize = 11;
+/// The limit on the number of bytes that can be allocated for POSIX message queues for the real
+/// user IF of the calling process.
+pub const RLIMIT_MSGQUEUE: usize = 12;
+/// The ceiling to which the process's nice value can be raised.
+pub const RLIMIT_NICE: usize = 13;
+/// The ceiling on the real-time priority that may be set for this process.
+pub const RLIMIT_RTPRIO: usize = 14;
+/// The limit (in microseconds) on the amount of CPU that a process scheduled under a real-time
+/// scheduling policy may consume without masking a blocking system call.
+pub const RLIMIT_RTTIME: usize = 15;
+/// The number of resource limits.
+pub const RLIMIT_NLIMITS: usize = 16;
+
+/// Value of a limit representing the absence of limit.
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// A resource limit.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RLimit {
+	/// Soft limit
+	pub rlim_cur: u64,
+	/// Hard limit (ceiling for [`Self::rlim_cur`])
+	pub rlim_max: u64,
+}
+
+impl RLimit {
+	/// A limit with both values set to [`RLIM_INFINITY`].
+	pub const INFINITY: Self = Self {
+		rlim_cur: RLIM_INFINITY,
+		rlim_max: RLIM_INFINITY,
+	};
+}
+
+/// The set of resource limits of a process, indexed by resource.
+///
+/// Resource limits are inherited across `fork` and preserved across `execve`.
+#[derive(Clone, Debug)]
+pub struct RLimits(pub [RLimit; RLIMIT_NLIMITS]);
+
+impl Default for RLimits {
+	fn default() -> Self {
+		let mut limits = [RLimit::INFINITY; RLIMIT_NLIMITS];
+		// Core dumps are disabled unless enabled explicitly
+		limits[RLIMIT_CORE].rlim_cur = 0;
+		limits[RLIMIT_NOFILE] = RLimit {
+			rlim_cur: OPEN_MAX as _,
+			rlim_max: OPEN_MAX as _,
+		};
+		limits[RLIMIT_SIGPENDING] = RLimit {
+			rlim_cur: SIGQUEUE_MAX as _,
+			rlim_max: SIGQUEUE_MAX as _,
+		};
+		Self(limits)
+	}
+}
@@ -18,7 +18,14 @@
 
 //! Monitoring of the resource usage of processes.
 
-use crate::time::unit::Timeval;
+use crate::{
+	sync::atomic::AtomicU64,
+	time::{
+		clock::{Clock, current_time_ns},
+		unit::{TimeUnit, Timeval},
+	},
+};
+use core::sync::atomic::Ordering::Relaxed;
 
 // TODO Place calls in kernel's code to update usage
 
@@ -58,3 +65,95 @@ pub struct Rusage {
 	/// Involuntary context switches.
 	pub ru_nivcsw: i64,
 }
+
+impl Rusage {
+	/// Sets the CPU times of the structure, in nanoseconds.
+	pub fn with_cputime(mut self, utime: u64, stime: u64) -> Self {
+		self.ru_utime = Timeval::from_nano(utime);
+		self.ru_stime = Timeval::from_nano(stime);
+		self
+	}
+}
+
+/// CPU time accounting of a process, split between userspace and kernelspace.
+///
+/// The time elapsed since the last accounting boundary (system call entry or exit, timer
+/// interrupt, context switch) is charged to either side when the next boundary is reached.
+#[derive(Debug)]
+pub struct CpuTime {
+	/// The time spent in userspace, in nanoseconds.
+	utime: AtomicU64,
+	/// The time spent in kernelspace, in nanoseconds.
+	stime: AtomicU64,
+	/// The timestamp of the last accounting boundary, in nanoseconds.
+	mark: AtomicU64,
+
+	/// The time spent in userspace by terminated and waited-for children, in nanoseconds.
+	cutime: AtomicU64,
+	/// The time spent in kernelspace by terminated and waited-for children, in nanoseconds.
+	cstime: AtomicU64,
+}
+
+impl Default for CpuTime {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl CpuTime {
+	/// Creates a new instance, starting accounting at the current time.
+	pub fn new() -> Self {
+		Self {
+			utime: AtomicU64::new(0),
+			stime: AtomicU64::new(0),
+			mark: AtomicU64::new(current_time_ns(Clock::Monotonic)),
+
+			cutime: AtomicU64::new(0),
+			cstime: AtomicU64::new(0),
+		}
+	}
+
+	/// Charges the time elapsed since the last boundary to userspace if `user` is set, else to
+	/// kernelspace.
+	pub fn account(&self, user: bool) {
+		let now = current_time_ns(Clock::Monotonic);
+		let delta = now.saturating_sub(self.mark.swap(now, Relaxed));
+		let counter = if user { &self.utime } else { &self.stime };
+		counter.fetch_add(delta, Relaxed);
+	}
+
+	/// Starts a new accounting period without charging the elapsed time.
+	///
+	/// This is used when the process is scheduled back in, so that the time it did not run is
+	/// not accounted.
+	pub fn resume(&self) {
+		self.mark.store(current_time_ns(Clock::Monotonic), Relaxed);
+	}
+
+	/// Adds the CPU times of the waited-for child `child`, including its own children's, to the
+	/// children's times.
+	pub fn reap(&self, child: &Self) {
+		let (utime, stime) = child.total();
+		self.cutime.fetch_add(utime, Relaxed);
+		self.cstime.fetch_add(stime, Relaxed);
+	}
+
+	/// Returns the user and system times of the process, in nanoseconds.
+	pub fn get(&self) -> (u64, u64) {
+		(self.utime.load(Relaxed), self.stime.load(Relaxed))
+	}
+
+	/// Returns the user and system times of the terminated and waited-for children, in
+	/// nanoseconds.
+	pub fn children(&self) -> (u64, u64) {
+		(self.cutime.load(Relaxed), self.cstime.load(Relaxed))
+	}
+
+	/// Returns the user and system times of the process and its waited-for children, in
+	/// nanoseconds.
+	pub fn total(&self) -> (u64, u64) {
+		let (utime, stime) = self.get();
+		let (cutime, cstime) = self.children();
+		(utime + cutime, stime + cstime)
+	}
+}
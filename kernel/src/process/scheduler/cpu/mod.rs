@@ -96,6 +96,9 @@ pub struct PerCpu {
 
 	/// Queue of deferred calls to be executed on this core
 	pub(super) deferred_calls: DeferredCallQueue,
+
+	/// The RCU grace period sequence number at the last quiescent state of the core
+	pub(crate) rcu_seq: AtomicU64,
 }
 
 impl PerCpu {
@@ -135,6 +138,8 @@ impl PerCpu {
 			mem_space: AtomicOptionalArc::new(),
 
 			deferred_calls: DeferredCallQueue::new(),
+
+			rcu_seq: AtomicU64::new(0),
 		})
 	}
 
@@ -0,0 +1,103 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The load average is an exponentially-decayed moving average of the number of runnable
+//! processes, over 1, 5 and 15 minutes.
+//!
+//! The number of runnable processes is sampled on the timer tick, every [`LOAD_FREQ`]
+//! nanoseconds. Averages are stored in fixed point, with [`FSHIFT`] bits of fractional part.
+
+use crate::{
+	process::scheduler::cpu::CPU,
+	sync::atomic::AtomicU64,
+	time::clock::{Clock, current_time_ns},
+};
+use core::sync::atomic::Ordering::Relaxed;
+
+/// The number of bits of fractional part of load averages.
+pub const FSHIFT: u32 = 11;
+/// `1.0` in fixed point.
+pub const FIXED_1: u64 = 1 << FSHIFT;
+
+/// The interval between two samples, in nanoseconds.
+const LOAD_FREQ: u64 = 5_000_000_000;
+/// Decay factors of the 1, 5 and 15 minutes averages, in fixed point.
+///
+/// Each factor is `FIXED_1 / exp(LOAD_FREQ / period)`.
+const EXP: [u64; 3] = [1884, 2014, 2037];
+
+/// The 1, 5 and 15 minutes load averages.
+static LOADS: [AtomicU64; 3] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+/// The timestamp of the next sample, in nanoseconds.
+static NEXT_SAMPLE: AtomicU64 = AtomicU64::new(0);
+
+/// Computes the next value of the average `load` with the decay factor `exp`, for `active`
+/// runnable processes (in fixed point).
+fn calc_load(load: u64, exp: u64, active: u64) -> u64 {
+	let mut new = load * exp + active * (FIXED_1 - exp);
+	// Round up when the load increases, so that it eventually reaches `active`
+	if active >= load {
+		new += FIXED_1 - 1;
+	}
+	new / FIXED_1
+}
+
+/// Updates the load averages if the sampling interval has elapsed.
+///
+/// This function is called on the timer tick of every core, but only one of them samples each
+/// interval.
+pub fn tick() {
+	let now = current_time_ns(Clock::Monotonic);
+	let next = NEXT_SAMPLE.load(Relaxed);
+	if now < next {
+		return;
+	}
+	if NEXT_SAMPLE
+		.compare_exchange(next, now + LOAD_FREQ, Relaxed, Relaxed)
+		.is_err()
+	{
+		return;
+	}
+	let active = CPU.iter().map(|cpu| cpu.sched.queue_len()).sum::<usize>() as u64 * FIXED_1;
+	for (load, exp) in LOADS.iter().zip(EXP) {
+		load.store(calc_load(load.load(Relaxed), exp, active), Relaxed);
+	}
+}
+
+/// Returns the 1, 5 and 15 minutes load averages, in fixed point.
+pub fn get() -> [u64; 3] {
+	LOADS.each_ref().map(|load| load.load(Relaxed))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn loadavg_converge() {
+		let mut load = 0;
+		for _ in 0..1000 {
+			load = calc_load(load, EXP[0], 2 * FIXED_1);
+		}
+		assert_eq!(load, 2 * FIXED_1);
+		for _ in 0..1000 {
+			load = calc_load(load, EXP[0], 0);
+		}
+		assert_eq!(load, 0);
+	}
+}
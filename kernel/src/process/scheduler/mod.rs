@@ -24,40 +24,105 @@
 
 pub mod cpu;
 pub mod defer;
+pub mod loadavg;
 pub mod switch;
 
 use crate::{
 	arch::{
 		core_id,
-		x86::{cli, idt::IntFrame},
+		x86::{cli, idt::IntFrame, timer},
 	},
+	file::fs::debug::ShowFile,
 	process::{
 		Process, State,
-		scheduler::{cpu::per_cpu, switch::switch},
+		scheduler::{
+			cpu::{iter_online, per_cpu},
+			switch::switch,
+		},
 	},
-	sync::spin::IntSpin,
+	sync::{rcu, spin::IntSpin},
+	sysctl::{IntTunable, Tunable},
 	time::{clock::Clock, sleep_for},
 };
 use core::{
-	cmp::Ordering,
+	cmp::{Ordering, min},
 	hint::unlikely,
 	mem::swap,
 	ptr,
-	sync::atomic::Ordering::{Relaxed, Release},
+	sync::atomic::Ordering::{Acquire, Relaxed, Release},
 };
 use cpu::{CPU, IDLE_CPUS, PerCpu};
 use utils::{
+	collections::string::String,
+	errno::{AllocResult, EResult},
 	list_type,
 	ptr::arc::{Arc, AtomicArc},
+	try_write, try_writeln,
 };
 
 /// Flag in the preempt counter, telling whether preemption has been requested
 const PREEMPT_FLAG: u32 = 1 << 31;
 
+/// The time slice given to a process before preemption, in milliseconds
+static SCHED_RR_TIMESLICE_MS: IntTunable = IntTunable::new(100, 1, 1000);
+
+/// The `kernel.sched_rr_timeslice_ms` tunable.
+///
+/// Writing it re-arms the timer of every online CPU, so that the new time slice applies
+/// immediately.
+#[derive(Debug)]
+pub struct Timeslice;
+
+impl Tunable for Timeslice {
+	fn read(&self, buf: &mut String) -> AllocResult<()> {
+		SCHED_RR_TIMESLICE_MS.read(buf)
+	}
+
+	fn write(&self, val: &[u8]) -> EResult<()> {
+		SCHED_RR_TIMESLICE_MS.write(val)?;
+		defer::synchronous_multiple(iter_online(), || timer::apic::periodic(timeslice()));
+		Ok(())
+	}
+}
+
+/// Debugfs file listing, for each CPU, the running process and the processes in the run queue.
+pub(crate) static RUN_QUEUES: ShowFile = ShowFile(|buf| {
+	// The maximum number of processes listed per run queue
+	const MAX: usize = 32;
+	for cpu in CPU.iter() {
+		let cur = cpu.sched.get_current_process().get_pid();
+		// Collect first, so that no allocation happens while the run queue is locked
+		let mut pids = [0; MAX];
+		let count = {
+			let mut queue = cpu.sched.run_queue.lock();
+			for (pid, cursor) in pids.iter_mut().zip(queue.queue.iter()) {
+				*pid = cursor.value().get_pid();
+			}
+			queue.len
+		};
+		try_write!(buf, "cpu{} current {cur} queued {count}:", cpu.cpu_id)?;
+		for pid in &pids[..min(count, MAX)] {
+			try_write!(buf, " {pid}")?;
+		}
+		if count > MAX {
+			try_write!(buf, " ...")?;
+		}
+		try_writeln!(buf)?;
+	}
+	Ok(())
+});
+
 // TODO must be configurable
 /// The timeout, in milliseconds, after which processes are rebalanced
 const REBALANCE_TIMEOUT: u64 = 100;
 
+/// Returns the scheduler's time slice, in nanoseconds.
+#[inline]
+pub fn timeslice() -> u32 {
+	// Cannot overflow thanks to the tunable's bounds
+	(SCHED_RR_TIMESLICE_MS.get() * 1_000_000) as _
+}
+
 /// Queue of processes to run
 struct RunQueue {
 	/// Queue of processes to run
@@ -157,12 +222,14 @@ pub(crate) fn enqueue(proc: &Arc<Process>) {
 			// Select the scheduler with the least running processes among those able to run the
 			// process immediately
 			CPU.iter()
-				.filter(|cpu| cpu.sched.can_immediately_run(proc))
+				.filter(|cpu| cpu.online.load(Acquire) && cpu.sched.can_immediately_run(proc))
 				.min_by(cpu_cmp)
 		})
 		.or_else(|| {
 			// Select the scheduler with the least running processes
-			CPU.iter().min_by(cpu_cmp)
+			CPU.iter()
+				.filter(|cpu| cpu.online.load(Acquire))
+				.min_by(cpu_cmp)
 		})
 		// There is at least one CPU on the system
 		.unwrap();
@@ -204,7 +271,7 @@ pub(crate) fn dequeue(proc: &Arc<Process>) {
 
 /// Attempts to return the CPU cores with the least and most processes queued, without locking
 fn min_max() -> (&'static PerCpu, &'static PerCpu) {
-	let mut iter = CPU.iter();
+	let mut iter = CPU.iter().filter(|cpu| cpu.online.load(Acquire));
 	let mut min = iter.next().unwrap(); // The system has at least one core
 	let mut max = min;
 	let mut min_cnt = min.sched.queue_len();
@@ -313,6 +380,7 @@ pub fn schedule() {
 	let old_preempt_counter = per_cpu().preempt_counter.fetch_or(PREEMPT_FLAG, Relaxed);
 	// Ensure we are not in a critical section
 	debug_assert_eq!(old_preempt_counter & !PREEMPT_FLAG, 0);
+	rcu::quiescent_state();
 	// Make deferred calls
 	defer::consume();
 	let sched = &per_cpu().sched;
@@ -326,6 +394,10 @@ pub fn schedule() {
 		if ptr::eq(next.as_ref(), prev.as_ref()) {
 			return;
 		}
+		// Charge the outgoing process for its time in kernelspace, and do not charge the incoming
+		// one for the time it did not run
+		prev.cputime.account(false);
+		next.cputime.resume();
 		// Update the idle bitmap if necessary
 		if prev.is_idle_task() {
 			IDLE_CPUS.clear_bit(core_id() as _);
@@ -369,6 +441,18 @@ pub unsafe fn preempt_enable() {
 	}
 }
 
+/// Exit a critical section without rescheduling, even if preemption has been requested.
+///
+/// The preemption then happens at the next call to [`preempt_check_resched`].
+///
+/// # Safety
+///
+/// Calling this function outside a critical section is undefined.
+#[inline]
+pub unsafe fn preempt_enable_no_resched() {
+	per_cpu().preempt_counter.fetch_sub(1, Relaxed);
+}
+
 /// Reschedules, if requested by the timer, and we are not in a critical section.
 ///
 /// This function may never return in case the process has been turned to a zombie after switching
@@ -404,11 +488,17 @@ fn alter_flow_impl(frame: &mut IntFrame) -> bool {
 		return true;
 	}
 	// Get signal handler to execute, if any
-	let Some(sig) = proc.signal.lock().next_signal() else {
+	let info = proc.signal.lock().next_signal();
+	let Some(info) = info else {
+		// Restore the mask a system call may have temporarily replaced
+		proc.signal.lock().restore_sigmask();
 		return false;
 	};
-	// Prepare for execution of signal handler
-	proc.sig_handlers.lock()[sig.0 as usize].exec(sig, frame);
+	// Prepare for execution of signal handler. The handler is copied since executing it may sleep
+	let handler = proc.sig_handlers.lock()[info.si_signo as usize].clone();
+	handler.exec(info, frame);
+	// If the signal has not been caught, no handler took over the temporary mask
+	proc.signal.lock().restore_sigmask();
 	// If the process is still running, continue execution
 	proc.get_state() != State::Running
 }
@@ -22,27 +22,28 @@ pub mod ucontext;
 
 use super::{Process, REDZONE_SIZE, State};
 use crate::{
-	arch::x86::idt::IntFrame,
-	file::perm::Uid,
+	arch::x86::{cli, idt::IntFrame, sti},
 	memory::{VirtAddr, user::UserPtr},
 	process,
-	process::pid::Pid,
+	process::{coredump, pid::Pid},
 	syscall::{
 		FromSyscallArg,
 		wait::{WCONTINUED, WEXITED, WUNTRACED},
 	},
-	time::unit::ClockIdT,
 };
 use core::{
 	ffi::{c_int, c_void},
-	hint::{likely, unlikely},
+	hint::unlikely,
 	mem::size_of,
 	ptr::NonNull,
 };
 use ucontext::UContext32;
 #[cfg(target_pointer_width = "64")]
 use ucontext::UContext64;
-use utils::{errno, errno::Errno};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+};
 
 /// sigaltstack flag: Currently executing on the alternate signal stack
 pub const SS_ONSTACK: i32 = 1;
@@ -50,6 +51,8 @@ pub const SS_ONSTACK: i32 = 1;
 pub const SS_DISABLE: i32 = 2;
 /// sigaltstack flag: Autodisarm on signal handler entry
 pub const SS_AUTODISARM: i32 = 1 << 31;
+/// The minimum size of an alternate signal stack.
+pub const MINSIGSTKSZ: usize = 2048;
 
 /// Signal handler value: Ignoring the signal.
 pub const SIG_IGN: usize = 0x0;
@@ -153,6 +156,29 @@ pub type AltStack = Stack32;
 /// Kernelspace alternative stack structure
 pub type AltStack = Stack64;
 
+impl AltStack {
+	/// Tells whether the alternate stack is enabled.
+	pub fn is_enabled(&self) -> bool {
+		self.ss_flags & SS_DISABLE == 0 && self.ss_size != 0
+	}
+
+	/// Tells whether the stack pointer `sp` is located on the alternate stack.
+	pub fn is_on(&self, sp: usize) -> bool {
+		let start = self.ss_sp as usize;
+		self.is_enabled() && sp > start && sp - start <= self.ss_size as _
+	}
+
+	/// Returns the alternate stack as reported to userspace for the stack pointer `sp`, with
+	/// [`SS_ONSTACK`] set if `sp` is located on it.
+	pub fn report(&self, sp: usize) -> Self {
+		let mut ss = self.clone();
+		if self.is_on(sp) {
+			ss.ss_flags |= SS_ONSTACK;
+		}
+		ss
+	}
+}
+
 /// Enumeration representing the action to perform for a signal.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum SignalAction {
@@ -170,12 +196,25 @@ pub enum SignalAction {
 
 impl SignalAction {
 	/// Executes the signal action for the current process.
-	pub fn exec(self, sig: Signal) {
+	///
+	/// `frame` is the state of the registers of the process when the signal is handled.
+	pub fn exec(self, sig: Signal, frame: &IntFrame) {
 		let proc = Process::current();
 		match self {
-			// TODO when `Abort`ing, dump core
 			SignalAction::Terminate | SignalAction::Abort => {
-				proc.signal.lock().termsig = sig.0 as u8;
+				let coredump = self == SignalAction::Abort && {
+					// Writing the core file may sleep
+					sti();
+					let coredump = coredump::dump(&proc, sig, frame);
+					cli();
+					coredump
+				};
+				{
+					let mut signal = proc.signal.lock();
+					signal.termsig = sig.0 as u8;
+					signal.coredump = coredump;
+				}
+				proc.release_record_locks();
 				process::set_state(State::Zombie);
 				proc.notify_parent(WEXITED as u8);
 			}
@@ -193,62 +232,178 @@ impl SignalAction {
 /// A signal handler value.
 pub type SigVal = usize;
 
-// FIXME: fields are incorrect (check musl source)
-/// Signal information.
-#[repr(C)]
-#[derive(Debug)]
+/// `si_code` value: the signal has been sent by `kill`.
+pub const SI_USER: i32 = 0;
+/// `si_code` value: the signal has been sent by the kernel.
+pub const SI_KERNEL: i32 = 0x80;
+/// `si_code` value: the signal has been sent by `sigqueue`.
+pub const SI_QUEUE: i32 = -1;
+/// `si_code` value: the signal has been sent by the expiration of a timer.
+pub const SI_TIMER: i32 = -2;
+/// `si_code` value: the signal has been sent by `tkill` or `tgkill`.
+pub const SI_TKILL: i32 = -6;
+
+/// `si_code` value for `SIGCHLD`: the child has exited.
+pub const CLD_EXITED: i32 = 1;
+/// `si_code` value for `SIGCHLD`: the child has been killed by a signal.
+pub const CLD_KILLED: i32 = 2;
+/// `si_code` value for `SIGCHLD`: the child has been killed by a signal and dumped its core.
+pub const CLD_DUMPED: i32 = 3;
+/// `si_code` value for `SIGCHLD`: the child has been stopped by a signal.
+pub const CLD_STOPPED: i32 = 5;
+/// `si_code` value for `SIGCHLD`: the stopped child has been resumed by `SIGCONT`.
+pub const CLD_CONTINUED: i32 = 6;
+
+/// The size of the userspace `siginfo_t` structure, for all ABIs.
+const SI_MAX_SIZE: usize = 128;
+
+/// Information about a pending signal.
+///
+/// Only the fields shared by signals sent by processes, timers and children state changes are
+/// represented.
+#[derive(Clone, Copy, Debug, Default)]
 pub struct SigInfo {
-	/// Signal number.
+	/// Signal number
 	pub si_signo: i32,
-	/// An errno value.
+	/// An errno value
+	pub si_errno: i32,
+	/// Signal code, telling where the signal comes from
+	pub si_code: i32,
+	/// The PID of the sending process, or the ID of the timer for [`SI_TIMER`]
+	pub si_pid: u32,
+	/// The real user ID of the sending process, or the overrun count for [`SI_TIMER`]
+	pub si_uid: u32,
+	/// The value sent along with the signal, or the exit status or signal of the child for
+	/// `SIGCHLD`
+	pub si_value: u64,
+	/// For `SIGCHLD`, the user CPU time consumed by the child, in clock ticks
+	pub si_utime: u64,
+	/// For `SIGCHLD`, the system CPU time consumed by the child, in clock ticks
+	pub si_stime: u64,
+}
+
+impl SigInfo {
+	/// Returns the information of a signal `sig` generated by the kernel.
+	pub fn kernel(sig: Signal) -> Self {
+		Self {
+			si_signo: sig.0,
+			si_code: SI_KERNEL,
+			..Default::default()
+		}
+	}
+
+	/// Returns the information of a signal `sig` sent by the current process, with the given
+	/// `code`.
+	pub fn user(sig: Signal, code: i32) -> Self {
+		let proc = Process::current();
+		Self {
+			si_signo: sig.0,
+			si_code: code,
+			si_pid: proc.get_pid() as _,
+			si_uid: proc.cred().ap.uid as _,
+			..Default::default()
+		}
+	}
+}
+
+/// 32-bit version of `siginfo_t`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SigInfo32 {
+	si_signo: i32,
+	si_errno: i32,
+	si_code: i32,
+	si_pid: u32,
+	si_uid: u32,
+	si_value: u32,
+	si_utime: u32,
+	si_stime: u32,
+	__pad: [u8; SI_MAX_SIZE - 32],
+}
+
+impl From<SigInfo> for SigInfo32 {
+	fn from(info: SigInfo) -> Self {
+		Self {
+			si_signo: info.si_signo,
+			si_errno: info.si_errno,
+			si_code: info.si_code,
+			si_pid: info.si_pid,
+			si_uid: info.si_uid,
+			si_value: info.si_value as _,
+			si_utime: info.si_utime as _,
+			si_stime: info.si_stime as _,
+			__pad: [0; SI_MAX_SIZE - 32],
+		}
+	}
+}
+
+impl From<SigInfo32> for SigInfo {
+	fn from(info: SigInfo32) -> Self {
+		Self {
+			si_signo: info.si_signo,
+			si_errno: info.si_errno,
+			si_code: info.si_code,
+			si_pid: info.si_pid,
+			si_uid: info.si_uid,
+			si_value: info.si_value as _,
+			si_utime: info.si_utime as _,
+			si_stime: info.si_stime as _,
+		}
+	}
+}
+
+/// 64-bit version of `siginfo_t`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SigInfo64 {
+	si_signo: i32,
 	si_errno: i32,
-	/// Signal code.
 	si_code: i32,
-	/// Trap number that caused hardware-generated signal.
-	si_trapno: i32,
-	/// Sending process ID.
-	si_pid: Pid,
-	/// Real user ID of sending process.
-	si_uid: Uid,
-	/// Exit value or signal.
-	si_status: i32,
-	/// User time consumed.
-	si_utime: ClockIdT,
-	/// System time consumed.
-	si_stime: ClockIdT,
-	/// Signal value
-	si_value: SigVal,
-	/// POSIX.1b signal.
-	si_int: i32,
-	/// POSIX.1b signal.
-	si_ptr: *mut c_void,
-	/// Timer overrun count.
-	si_overrun: i32,
-	/// Timer ID.
-	si_timerid: i32,
-	/// Memory location which caused fault.
-	si_addr: *mut c_void,
-	/// Band event.
-	si_band: i32, // FIXME long (64bits?)
-	/// File descriptor.
-	si_fd: i32,
-	/// Least significant bit of address.
-	si_addr_lsb: i16,
-	/// Lower bound when address violation.
-	si_lower: *mut c_void,
-	/// Upper bound when address violation.
-	si_upper: *mut c_void,
-	/// Protection key on PTE that caused fault.
-	si_pkey: i32,
-	/// Address of system call instruction.
-	si_call_addr: *mut c_void,
-	/// Number of attempted system call.
-	si_syscall: i32,
-	/// Architecture of attempted system call.
-	si_arch: u32,
+	// The union of signal-specific fields is 8-byte aligned
+	__pad0: u32,
+	si_pid: u32,
+	si_uid: u32,
+	si_value: u64,
+	si_utime: u64,
+	si_stime: u64,
+	__pad: [u8; SI_MAX_SIZE - 48],
+}
+
+impl From<SigInfo> for SigInfo64 {
+	fn from(info: SigInfo) -> Self {
+		Self {
+			si_signo: info.si_signo,
+			si_errno: info.si_errno,
+			si_code: info.si_code,
+			__pad0: 0,
+			si_pid: info.si_pid,
+			si_uid: info.si_uid,
+			si_value: info.si_value,
+			si_utime: info.si_utime,
+			si_stime: info.si_stime,
+			__pad: [0; SI_MAX_SIZE - 48],
+		}
+	}
+}
+
+impl From<SigInfo64> for SigInfo {
+	fn from(info: SigInfo64) -> Self {
+		Self {
+			si_signo: info.si_signo,
+			si_errno: info.si_errno,
+			si_code: info.si_code,
+			si_pid: info.si_pid,
+			si_uid: info.si_uid,
+			si_value: info.si_value,
+			si_utime: info.si_utime,
+			si_stime: info.si_stime,
+		}
+	}
 }
 
 /// Kernelspace signal mask.
+///
+/// As in userspace, signal `n` is represented by bit `n - 1`.
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub struct SigSet(pub u64);
 
@@ -259,28 +414,28 @@ impl SigSet {
 		self.0 == 0
 	}
 
-	/// Tells whether the `n`th bit is set.
+	/// Tells whether signal `n` is in the set.
 	#[inline]
 	pub fn is_set(&self, n: usize) -> bool {
-		self.0 & (1u64 << n) != 0
+		self.0 & (1u64 << (n - 1)) != 0
 	}
 
-	/// Sets the `n`th bit.
+	/// Adds signal `n` to the set.
 	#[inline]
 	pub fn set(&mut self, n: usize) {
-		self.0 |= 1u64 << n;
+		self.0 |= 1u64 << (n - 1);
 	}
 
-	/// Sets the `n`th bit.
+	/// Removes signal `n` from the set.
 	#[inline]
 	pub fn clear(&mut self, n: usize) {
-		self.0 &= !(1u64 << n);
+		self.0 &= !(1u64 << (n - 1));
 	}
 
-	/// Returns an iterator over the bitset's values
+	/// Returns an iterator over the bitset's values, starting from signal `1`.
 	#[inline]
 	pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
-		(0..64).map(|n| self.is_set(n))
+		(1..=64).map(|n| self.is_set(n))
 	}
 }
 
@@ -435,9 +590,10 @@ impl SignalHandler {
 		}
 	}
 
-	/// Executes the action for `signal` on the current process.
-	pub fn exec(&self, signal: Signal, frame: &mut IntFrame) {
+	/// Executes the action for the signal described by `info` on the current process.
+	pub fn exec(&self, info: SigInfo, frame: &mut IntFrame) {
 		let proc = Process::current();
+		let signal = Signal(info.si_signo);
 		let action = match self {
 			Self::Handler(action) if signal.can_catch() => action,
 			Self::Ignore => return,
@@ -446,76 +602,103 @@ impl SignalHandler {
 				// Signals on the init process can be executed only if the process has set a
 				// signal handler
 				if !proc.is_init() || !signal.can_catch() {
-					signal.get_default_action().exec(signal);
+					signal.get_default_action().exec(signal, frame);
 				}
 				return;
 			}
 		};
-		// TODO handle SA_SIGINFO
+		let siginfo = action.sa_flags & SA_SIGINFO != 0;
 		// Prepare the signal handler stack
 		let (stack_addr, altstack, sigmask) = {
 			let mut sig = proc.signal.lock();
-			let altstack = sig.altstack.clone();
+			let sp = frame.get_stack_address();
+			// The context saves the alternate stack as it was before the handler
+			let altstack = sig.altstack.report(sp);
+			// Switch to the alternate stack, unless a handler is already running on it
 			let stack_addr = if action.sa_flags & SA_ONSTACK != 0
-				&& sig.altstack.ss_flags & SS_DISABLE == 0
-				&& likely(sig.altstack.ss_sp != 0)
+				&& sig.altstack.is_enabled()
+				&& !sig.altstack.is_on(sp)
 			{
-				sig.altstack.ss_flags |= SS_ONSTACK;
 				if sig.altstack.ss_flags & SS_AUTODISARM != 0 {
 					sig.altstack = Default::default();
 				}
-				VirtAddr(altstack.ss_sp as _)
+				VirtAddr((altstack.ss_sp as usize).saturating_add(altstack.ss_size as _))
 			} else {
-				VirtAddr(frame.get_stack_address().saturating_sub(REDZONE_SIZE))
+				VirtAddr(sp.saturating_sub(REDZONE_SIZE))
 			};
-			(stack_addr, altstack, sig.sigmask)
+			// The handler returns to the mask in place before any temporary one
+			(stack_addr, altstack, sig.take_handler_sigmask())
 		};
-		// Size of the `ucontext_t` struct and arguments *on the stack*
-		let (ctx_size, ctx_align) = if frame.is_compat() {
-			(size_of::<UContext32>(), align_of::<UContext32>())
+		// Size of the `siginfo_t` and `ucontext_t` structs and of the arguments *on the stack*
+		let (info_size, ctx_size, ctx_align, args_size) = if frame.is_compat() {
+			// With `SA_SIGINFO`, pointers to the `siginfo_t` and `ucontext_t` structs are passed
+			// too
+			let args_size = if siginfo { 4 } else { 2 } * size_of::<u32>();
+			(
+				size_of::<SigInfo32>(),
+				size_of::<UContext32>(),
+				align_of::<UContext32>(),
+				args_size,
+			)
 		} else {
 			#[cfg(target_pointer_width = "32")]
 			unreachable!();
 			#[cfg(target_pointer_width = "64")]
-			(size_of::<UContext64>(), align_of::<UContext64>())
+			(
+				size_of::<SigInfo64>(),
+				size_of::<UContext64>(),
+				align_of::<UContext64>(),
+				size_of::<u64>(),
+			)
 		};
-		let ctx_addr = VirtAddr(stack_addr.saturating_sub(ctx_size)).down_align_to(ctx_align);
-		let signal_sp = VirtAddr(ctx_addr.saturating_sub(size_of::<u64>()));
+		let info_addr = VirtAddr(stack_addr.saturating_sub(info_size)).down_align_to(ctx_align);
+		let ctx_addr = VirtAddr(info_addr.saturating_sub(ctx_size)).down_align_to(ctx_align);
+		let signal_sp = VirtAddr(ctx_addr.saturating_sub(args_size));
 		// Write data on stack
-		if frame.is_compat() {
-			let ctx = UContext32::new(altstack.into(), sigmask, frame);
-			let res = UserPtr::<UContext32>::from_ptr(ctx_addr.0).copy_to_user(&ctx);
-			if unlikely(res.is_err()) {
-				Signal::SIGSEGV.get_default_action().exec(Signal::SIGSEGV);
-				return;
-			}
-			let res = UserPtr::<[u32; 2]>::from_ptr(signal_sp.0).copy_to_user(&[
-				// Return pointer
-				action.sa_restorer as _,
-				// Argument
-				signal.0 as _,
-			]);
-			if unlikely(res.is_err()) {
-				Signal::SIGSEGV.get_default_action().exec(Signal::SIGSEGV);
-				return;
-			}
-		} else {
-			#[cfg(target_pointer_width = "64")]
-			{
-				let ctx = UContext64::new(altstack, sigmask, frame);
-				let res = UserPtr::<UContext64>::from_ptr(ctx_addr.0).copy_to_user(&ctx);
-				if unlikely(res.is_err()) {
-					Signal::SIGSEGV.get_default_action().exec(Signal::SIGSEGV);
-					return;
+		let res = (|| -> EResult<()> {
+			if frame.is_compat() {
+				// `AltStack` is `Stack32` on 32-bit architectures
+				#[allow(clippy::useless_conversion)]
+				let ctx = UContext32::new(altstack.into(), sigmask, frame);
+				UserPtr::<UContext32>::from_ptr(ctx_addr.0).copy_to_user(&ctx)?;
+				if siginfo {
+					UserPtr::<SigInfo32>::from_ptr(info_addr.0).copy_to_user(&info.into())?;
+					UserPtr::<[u32; 4]>::from_ptr(signal_sp.0).copy_to_user(&[
+						// Return pointer
+						action.sa_restorer as _,
+						// Arguments
+						signal.0 as _,
+						info_addr.0 as _,
+						ctx_addr.0 as _,
+					])?;
+				} else {
+					UserPtr::<[u32; 2]>::from_ptr(signal_sp.0).copy_to_user(&[
+						// Return pointer
+						action.sa_restorer as _,
+						// Argument
+						signal.0 as _,
+					])?;
 				}
-				// Return pointer
-				let res =
-					UserPtr::<u64>::from_ptr(signal_sp.0).copy_to_user(&(action.sa_restorer as _));
-				if unlikely(res.is_err()) {
-					Signal::SIGSEGV.get_default_action().exec(Signal::SIGSEGV);
-					return;
+			} else {
+				#[cfg(target_pointer_width = "64")]
+				{
+					let ctx = UContext64::new(altstack, sigmask, frame);
+					UserPtr::<UContext64>::from_ptr(ctx_addr.0).copy_to_user(&ctx)?;
+					if siginfo {
+						UserPtr::<SigInfo64>::from_ptr(info_addr.0).copy_to_user(&info.into())?;
+					}
+					// Return pointer
+					UserPtr::<u64>::from_ptr(signal_sp.0)
+						.copy_to_user(&(action.sa_restorer as _))?;
 				}
 			}
+			Ok(())
+		})();
+		if unlikely(res.is_err()) {
+			Signal::SIGSEGV
+				.get_default_action()
+				.exec(Signal::SIGSEGV, frame);
+			return;
 		}
 		// Block signal from `sa_mask`
 		{
@@ -532,8 +715,12 @@ impl SignalHandler {
 		#[cfg(target_pointer_width = "64")]
 		if !frame.is_compat() {
 			frame.rcx = frame.rip;
-			// Argument
+			// Arguments
 			frame.rdi = signal.0 as _;
+			if siginfo {
+				frame.rsi = info_addr.0 as _;
+				frame.rdx = ctx_addr.0 as _;
+			}
 		}
 	}
 }
@@ -643,6 +830,12 @@ impl Signal {
 	pub fn can_catch(self) -> bool {
 		!matches!(self.0, 9 | 11 | 19 | 31) // SIGKILL, SIGSEGV, SIGSTOP, SIGSYS
 	}
+
+	/// Tells whether the signal is a realtime signal, in which case multiple instances of it may
+	/// be pending at once.
+	pub fn is_realtime(self) -> bool {
+		self.0 >= Self::SIGRTMIN.0
+	}
 }
 
 impl TryFrom<i32> for Signal {
@@ -657,3 +850,32 @@ impl TryFrom<i32> for Signal {
 		}
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use core::mem::offset_of;
+
+	#[test_case]
+	fn sigset_bits() {
+		let mut set = SigSet::default();
+		set.set(Signal::SIGHUP.0 as usize);
+		set.set(Signal::SIGRTMAX.0 as usize);
+		assert_eq!(set.0, 1 | (1 << 63));
+		assert!(set.is_set(Signal::SIGRTMAX.0 as usize));
+		set.clear(Signal::SIGHUP.0 as usize);
+		assert!(!set.is_set(Signal::SIGHUP.0 as usize));
+	}
+
+	#[test_case]
+	fn siginfo_layout() {
+		assert_eq!(size_of::<SigInfo32>(), SI_MAX_SIZE);
+		assert_eq!(offset_of!(SigInfo32, si_pid), 12);
+		assert_eq!(offset_of!(SigInfo32, si_value), 20);
+		assert_eq!(offset_of!(SigInfo32, si_stime), 28);
+		assert_eq!(size_of::<SigInfo64>(), SI_MAX_SIZE);
+		assert_eq!(offset_of!(SigInfo64, si_pid), 16);
+		assert_eq!(offset_of!(SigInfo64, si_value), 24);
+		assert_eq!(offset_of!(SigInfo64, si_stime), 40);
+	}
+}
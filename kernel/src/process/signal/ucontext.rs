@@ -24,7 +24,7 @@ use crate::{
 	arch::x86::{gdt, idt::IntFrame},
 	process::{
 		Process,
-		signal::{SigSet, Stack32},
+		signal::{AltStack, SS_ONSTACK, SigSet, Stack32},
 	},
 };
 
@@ -125,8 +125,11 @@ impl UContext32 {
 
 	/// Restores the context.
 	pub fn restore(self, proc: &Process, frame: &mut IntFrame) {
-		// Restore alternative stack setting
-		proc.signal.lock().altstack = self.uc_stack.into();
+		// Restore alternative stack setting. `AltStack` is `Stack32` on 32-bit architectures
+		#[allow(clippy::useless_conversion)]
+		let mut altstack: AltStack = self.uc_stack.into();
+		altstack.ss_flags &= !SS_ONSTACK;
+		proc.signal.lock().altstack = altstack;
 		// Restore general registers
 		frame.gs = self.uc_mcontext.gregs[GReg32::Gs as usize] as _;
 		frame.fs = self.uc_mcontext.gregs[GReg32::Fs as usize] as _;
@@ -189,7 +192,7 @@ mod long {
 		process::{
 			Process,
 			mem_space::bound_check,
-			signal::{SigSet, Stack64},
+			signal::{SS_ONSTACK, SigSet, Stack64},
 		},
 	};
 	use core::hint::unlikely;
@@ -300,7 +303,9 @@ mod long {
 		/// Restores the context.
 		pub fn restore(self, proc: &Process, frame: &mut IntFrame) -> EResult<()> {
 			// Restore alternative stack setting
-			proc.signal.lock().altstack = self.uc_stack;
+			let mut altstack = self.uc_stack;
+			altstack.ss_flags &= !SS_ONSTACK;
+			proc.signal.lock().altstack = altstack;
 			// Restore general registers
 			frame.rax = self.uc_mcontext.gregs[GReg64::Rax as usize] as _;
 			frame.rbx = self.uc_mcontext.gregs[GReg64::Rbx as usize] as _;
@@ -19,6 +19,7 @@
 //! Randomness engines.
 
 use crate::{
+	arch::x86,
 	memory::{ring_buffer::RingBuffer, user::UserSlice},
 	sync::spin::IntSpin,
 };
@@ -38,20 +39,25 @@ pub const GRND_RANDOM: u32 = 2;
 /// returns [`utils::errno::EAGAIN`].
 pub const GRND_NONBLOCK: u32 = 1;
 
-// TODO Implement entropy extraction (Fast Key Erasure?)
+/// The ChaCha20 constant, placed at the beginning of the state.
+const CHACHA20_CONST: &[u8; 16] = b"expand 32-byte k";
 
 /// An entropy pool.
+///
+/// Entropy is accumulated in a buffer, then absorbed into the key of a ChaCha20-based generator.
+/// The generator uses Fast Key Erasure: each block it produces replaces the key with its first
+/// half, so that previously returned bytes cannot be recovered from the state.
 pub struct EntropyPool {
 	/// Available, non-encoded entropy
 	pending: RingBuffer,
-	/// Unused remains of the last encoding round
+	/// Unused remains of the last generated block
 	remain: RingBuffer,
 
 	/// The ChaCha20 counter.
 	counter: Wrapping<u64>,
 
-	/// The seed to be used for pseudo-random generation (urandom).
-	pseudo_seed: u64,
+	/// The key of the generator.
+	key: [u8; 32],
 }
 
 impl EntropyPool {
@@ -63,37 +69,54 @@ impl EntropyPool {
 
 			counter: Wrapping::default(),
 
-			pseudo_seed: 0,
+			key: [0; 32],
 		})
 	}
 
-	/// Reads data from the pending entropy buffer, encodes it and writes it in `dst`.
+	/// Reads data from the pending entropy buffer, encodes it and mixes it into the generator's
+	/// key.
 	///
 	/// If not enough entropy is available, the function returns `false`
-	fn encode(&mut self, dst: &mut [u8; 64]) -> EResult<bool> {
+	fn reseed(&mut self) -> EResult<bool> {
 		// Read data from the pending entropy buffer
 		let mut src = [0u8; 56];
 		if self.pending.get_data_len() < src.len() {
 			return Ok(false);
 		}
 		self.pending.read(UserSlice::from_slice_mut(&mut src))?;
+		let mut block = [0u8; 64];
 		// Add data
-		dst[0..48].copy_from_slice(&src[..48]);
+		block[0..48].copy_from_slice(&src[..48]);
 		// Add counter to buffer
-		dst[48..56].copy_from_slice(&self.counter.0.to_ne_bytes());
+		block[48..56].copy_from_slice(&self.counter.0.to_ne_bytes());
 		// Add nonce
-		dst[56..].copy_from_slice(&src[48..]);
+		block[56..].copy_from_slice(&src[48..]);
 		// Encode with ChaCha20
-		chacha20::block(dst);
-		// Update pseudo seed
-		let mut seed: [u8; 8] = [0; 8];
-		seed.copy_from_slice(&dst[..8]);
-		self.pseudo_seed = u64::from_ne_bytes(seed);
+		chacha20::block(&mut block);
+		// Mix into the key
+		for (k, b) in self.key.iter_mut().zip(&block) {
+			*k ^= *b;
+		}
 		// Update counter
 		self.counter += 1;
 		Ok(true)
 	}
 
+	/// Generates a block of random bytes from the generator's key, then replaces the key.
+	fn generate(&mut self) -> [u8; 32] {
+		let mut block = [0u8; 64];
+		block[..16].copy_from_slice(CHACHA20_CONST);
+		block[16..48].copy_from_slice(&self.key);
+		block[48..56].copy_from_slice(&self.counter.0.to_ne_bytes());
+		chacha20::block(&mut block);
+		self.counter += 1;
+		// Fast Key Erasure
+		self.key.copy_from_slice(&block[..32]);
+		let mut out = [0u8; 32];
+		out.copy_from_slice(&block[32..]);
+		out
+	}
+
 	/// Reads entropy from the pool.
 	///
 	/// Arguments:
@@ -109,34 +132,22 @@ impl EntropyPool {
 		random: bool,
 		_nonblocking: bool,
 	) -> EResult<usize> {
-		// First, use remaining used entropy
+		// First, use remaining bytes from the last block
 		let mut off = self.remain.read(buf)?;
-		// If we need more entropy, iterate
-		let mut encode_buf = [0u8; 64];
 		while off < buf.len() {
-			let res = self.encode(&mut encode_buf)?;
-			// If not enough entropy is available
-			if !res {
-				// TODO if blocking, block until enough entropy is available
-				if !random {
-					// urandom is allowed: use a PRNG
-					let mut seed = self.pseudo_seed;
-					for b in encode_buf.iter_mut() {
-						seed = 6364136223846793005u64.wrapping_mul(seed).wrapping_add(1);
-						*b = (seed & 0xff) as _;
-					}
-					self.pseudo_seed = seed;
-				} else {
-					// urandom is not allowed, stop
-					break;
-				}
+			let reseeded = self.reseed()?;
+			// If not enough entropy is available and urandom is not allowed, stop
+			// TODO if blocking, block until enough entropy is available
+			if !reseeded && random {
+				break;
 			}
+			let mut block = self.generate();
 			// Copy to user
-			let l = min(buf.len() - off, encode_buf.len());
-			buf.copy_to_user(off, &encode_buf[..l])?;
+			let l = min(buf.len() - off, block.len());
+			buf.copy_to_user(off, &block[..l])?;
 			// Keep remaining bytes
 			self.remain
-				.write(UserSlice::from_slice_mut(&mut encode_buf[l..]))?;
+				.write(UserSlice::from_slice_mut(&mut block[l..]))?;
 			off += l;
 		}
 		Ok(off)
@@ -166,6 +177,13 @@ pub fn getrandom(buf: UserSlice<u8>, flags: c_uint) -> EResult<usize> {
 
 /// Initializes randomness sources.
 pub(crate) fn init() -> AllocResult<()> {
-	*ENTROPY_POOL.lock() = Some(EntropyPool::new()?);
+	let mut pool = EntropyPool::new()?;
+	// Seed the generator with the CPU's random number generator if available, and the timestamp
+	// counter
+	for chunk in pool.key.chunks_exact_mut(size_of::<u64>()) {
+		let val = x86::rdrand().unwrap_or(0) as u64 ^ x86::rdtsc();
+		chunk.copy_from_slice(&val.to_ne_bytes());
+	}
+	*ENTROPY_POOL.lock() = Some(pool);
 	Ok(())
 }
@@ -86,6 +86,47 @@ impl AtomicU64 {
 			prev
 		}
 	}
+
+	/// Stores a value into the atomic integer, returning the previous value.
+	#[allow(unused_variables)]
+	pub fn swap(&self, val: u64, order: atomic::Ordering) -> u64 {
+		#[cfg(target_has_atomic = "64")]
+		{
+			self.0.swap(val, order)
+		}
+		#[cfg(not(target_has_atomic = "64"))]
+		{
+			core::mem::replace(&mut *self.0.lock(), val)
+		}
+	}
+
+	/// Stores `new` into the atomic integer if its current value is `current`.
+	///
+	/// On success, the function returns the previous value. Else, it returns the current value.
+	#[allow(unused_variables)]
+	pub fn compare_exchange(
+		&self,
+		current: u64,
+		new: u64,
+		success: atomic::Ordering,
+		failure: atomic::Ordering,
+	) -> Result<u64, u64> {
+		#[cfg(target_has_atomic = "64")]
+		{
+			self.0.compare_exchange(current, new, success, failure)
+		}
+		#[cfg(not(target_has_atomic = "64"))]
+		{
+			let mut guard = self.0.lock();
+			let prev = *guard;
+			if prev == current {
+				*guard = new;
+				Ok(prev)
+			} else {
+				Err(prev)
+			}
+		}
+	}
 }
 
 impl fmt::Debug for AtomicU64 {
@@ -18,16 +18,138 @@
 
 //! Read-Copy-Update allows several threads to read and update data structures concurrently without
 //! using locks.
+//!
+//! Readers access the data inside a **read-side critical section**, entered with [`read_lock`],
+//! during which preemption is disabled. Thus, a core calling the scheduler cannot be inside such a
+//! section: it is in a **quiescent state**.
+//!
+//! A **grace period** ends once every core has been through a quiescent state. After that, no
+//! reader can hold a reference to data that was unpublished before the grace period started, so
+//! the data can be reclaimed. [`synchronize`] waits for the end of a grace period, while
+//! [`defer_drop`] hands the reclamation over to a kernel task.
 
+use crate::{
+	process::scheduler::{
+		cpu::{CPU, per_cpu},
+		preempt_disable, preempt_enable_no_resched, schedule,
+	},
+	sync::{atomic::AtomicU64, spin::Spin, wait_queue::WaitQueue},
+};
 use core::{
+	marker::PhantomData,
 	mem,
+	mem::MaybeUninit,
 	ptr::NonNull,
 	sync::atomic::{
 		AtomicPtr,
-		Ordering::{Acquire, Relaxed, SeqCst},
+		Ordering::{Acquire, Relaxed, Release, SeqCst},
+		fence,
 	},
 };
-use utils::ptr::arc::{Arc, ArcInner};
+use utils::{
+	boxed::Box,
+	collections::vec::Vec,
+	ptr::arc::{Arc, ArcInner},
+};
+
+/// Sequence number of the latest grace period.
+static GP_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Guard of a read-side critical section. The section ends when the guard is dropped.
+pub struct ReadGuard(PhantomData<*const ()>);
+
+impl Drop for ReadGuard {
+	fn drop(&mut self) {
+		// Read-side critical sections may be entered from interrupt handlers, which cannot
+		// reschedule
+		unsafe {
+			preempt_enable_no_resched();
+		}
+	}
+}
+
+/// Enters a read-side critical section.
+///
+/// Data accessed in the section is not reclaimed before the returned guard is dropped.
+///
+/// Since preemption is disabled, the current process must not sleep inside the section.
+#[inline]
+pub fn read_lock() -> ReadGuard {
+	preempt_disable();
+	ReadGuard(PhantomData)
+}
+
+/// Reports a quiescent state for the current core.
+///
+/// This function is called by the scheduler, outside any critical section.
+pub(crate) fn quiescent_state() {
+	// Accesses from previous read-side critical sections must not be reordered after the report
+	fence(SeqCst);
+	per_cpu().rcu_seq.store(GP_SEQ.load(SeqCst), Release);
+}
+
+/// Waits for the end of a grace period.
+///
+/// **Note**: calling this function inside a critical section is invalid.
+pub fn synchronize() {
+	let seq = GP_SEQ.fetch_add(1, SeqCst) + 1;
+	// The current core reports a quiescent state each time it calls the scheduler
+	while !CPU
+		.iter()
+		.filter(|cpu| cpu.online.load(Acquire))
+		.all(|cpu| cpu.rcu_seq.load(Acquire) >= seq)
+	{
+		schedule();
+	}
+}
+
+/// An object whose reclamation is deferred. Dropping it reclaims the object.
+trait Deferred {}
+
+impl<T> Deferred for T {}
+
+/// Objects waiting for the end of a grace period to be dropped.
+static DEFERRED: Spin<Vec<Box<dyn Deferred>>> = Spin::new(Vec::new());
+/// Queue on which the reclamation task waits for objects to drop.
+static RECLAIM_QUEUE: WaitQueue = WaitQueue::new();
+
+/// Drops `val` once the current grace period has ended, from the reclamation task.
+///
+/// If memory cannot be allocated to defer the operation, the function waits for the end of the
+/// grace period then drops `val` itself. As such, it must not be called inside a critical section.
+pub fn defer_drop<T: 'static>(val: T) {
+	let mut deferred = DEFERRED.lock();
+	let boxed = deferred
+		.reserve(1)
+		.and_then(|_| Box::<MaybeUninit<T>>::new_uninit());
+	let Ok(mut boxed) = boxed else {
+		drop(deferred);
+		synchronize();
+		drop(val);
+		return;
+	};
+	boxed.write(val);
+	// Cannot fail since memory has been reserved
+	let _ = deferred.push(unsafe { boxed.assume_init() });
+	drop(deferred);
+	RECLAIM_QUEUE.wake_next();
+}
+
+/// The entry point of the kernel task dropping objects once their grace period has ended.
+pub(crate) fn reclaim_task() -> ! {
+	loop {
+		let batch = RECLAIM_QUEUE.wait_until(|| {
+			let mut deferred = DEFERRED.lock();
+			(!deferred.is_empty()).then(|| mem::take(&mut *deferred))
+		});
+		// Kernel tasks do not receive signals
+		let Ok(batch) = batch else {
+			continue;
+		};
+		synchronize();
+		drop(batch);
+	}
+}
 
 /// An [`Arc`], behind a RCU.
 pub struct RcuArc<T>(RcuOptionArc<T>);
@@ -47,8 +169,13 @@ impl<T> RcuArc<T> {
 	}
 
 	/// Atomically swap the inner [`Arc`] for the given `other`.
+	///
+	/// See [`RcuOptionArc::swap`].
 	#[inline]
-	pub fn swap(&self, other: Arc<T>) -> Arc<T> {
+	pub fn swap(&self, other: Arc<T>) -> Arc<T>
+	where
+		T: 'static,
+	{
 		let arc = self.0.swap(Some(other));
 		unsafe { arc.unwrap_unchecked() }
 	}
@@ -77,7 +204,7 @@ impl<T> RcuOptionArc<T> {
 
 	/// Returns a reference to the inner [`Arc`].
 	pub fn get(&self) -> Option<Arc<T>> {
-		// TODO enter RCU read critical section
+		let _guard = read_lock();
 		let inner = self.inner.load(Acquire);
 		NonNull::new(inner).map(|inner| {
 			let inner_ref = unsafe { inner.as_ref() };
@@ -86,11 +213,16 @@ impl<T> RcuOptionArc<T> {
 				inner,
 			}
 		})
-		// TODO exit RCU read critical section before returning
 	}
 
 	/// Atomically swap the inner [`Arc`] for the given `other`.
-	pub fn swap(&self, other: Option<Arc<T>>) -> Option<Arc<T>> {
+	///
+	/// Readers may still be accessing the previous value, which thus remains allocated until the
+	/// end of the current grace period (see [`defer_drop`]).
+	pub fn swap(&self, other: Option<Arc<T>>) -> Option<Arc<T>>
+	where
+		T: 'static,
+	{
 		let new = other
 			.as_ref()
 			.map(|arc| arc.inner.as_ptr())
@@ -98,12 +230,13 @@ impl<T> RcuOptionArc<T> {
 		// avoid decrementing reference counter
 		mem::forget(other);
 		let old = self.inner.swap(new, SeqCst);
-		NonNull::new(old).map(|inner| {
-			// TODO RCU sync
-			Arc {
-				inner,
-			}
-		})
+		let old = NonNull::new(old).map(|inner| Arc {
+			inner,
+		});
+		if let Some(old) = &old {
+			defer_drop(old.clone());
+		}
+		old
 	}
 }
 
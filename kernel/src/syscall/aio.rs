@@ -0,0 +1,343 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Legacy asynchronous I/O system calls.
+//!
+//! Requests are performed synchronously by `io_submit`, so `io_cancel` never finds a request to
+//! cancel.
+
+use crate::{
+	file::{
+		aio::{
+			AIO_MAX_NR, AioContext, IOCB_CMD_FDSYNC, IOCB_CMD_FSYNC, IOCB_CMD_PREAD,
+			IOCB_CMD_PREADV, IOCB_CMD_PWRITE, IOCB_CMD_PWRITEV, IoCb, IoEvent, check_iocb,
+		},
+		fd::fd_to_file,
+	},
+	memory::{
+		VirtAddr,
+		cache::RcPage,
+		user::{UserIOVec, UserPtr, UserSlice},
+	},
+	process::{
+		Process,
+		mem_space::{MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ},
+	},
+	syscall::{
+		FromSyscallArg,
+		fd::{pread64, preadv2, pwrite64, pwritev2},
+		select::{PSelectSigmask, with_sigmask},
+		sync::{fdatasync, fsync},
+	},
+	time::{
+		clock::Clock,
+		timeout::Timeout,
+		unit::{Timespec, Timespec32},
+	},
+};
+use core::{
+	cmp::min,
+	ffi::{c_int, c_long},
+	hint::unlikely,
+	num::NonZeroUsize,
+	ptr,
+	ptr::NonNull,
+};
+use utils::{errno, errno::EResult, ptr::arc::Arc, vec};
+
+/// Returns the context with ID `ctx_id` in the current memory space.
+///
+/// If the context does not exist, the function returns [`errno::EINVAL`].
+fn get_context(ctx_id: usize) -> EResult<Arc<AioContext>> {
+	Process::current()
+		.mem_space()
+		.aio
+		.lock()
+		.get(&VirtAddr(ctx_id))
+		.cloned()
+		.ok_or_else(|| errno!(EINVAL))
+}
+
+/// Creates a context able to hold `nr_events` events and returns its ID.
+///
+/// The ID is the address of a read-only page mapped in userspace, which stands for the ring of
+/// events that Linux exposes there. The page is zeroed, so that the ring's magic number does not
+/// match and libraries always reap events with the system call.
+fn do_io_setup(nr_events: usize) -> EResult<VirtAddr> {
+	if unlikely(nr_events == 0) {
+		return Err(errno!(EINVAL));
+	}
+	if unlikely(nr_events > AIO_MAX_NR) {
+		return Err(errno!(EAGAIN));
+	}
+	let mem_space = Process::current().mem_space().clone();
+	let page = RcPage::new_zeroed()?;
+	let addr = mem_space.map_special(PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, &[page])?;
+	let res = (|| {
+		let ctx = Arc::new(AioContext::new(addr, nr_events)?)?;
+		mem_space.aio.lock().insert(addr, ctx)?;
+		Ok(())
+	})();
+	if let Err(e) = res {
+		let _ = mem_space.unmap(addr, NonZeroUsize::MIN);
+		return Err(e);
+	}
+	Ok(addr)
+}
+
+pub fn io_setup(nr_events: u32, ctxp: UserPtr<usize>) -> EResult<usize> {
+	let ctx_id = ctxp.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if unlikely(ctx_id != 0) {
+		return Err(errno!(EINVAL));
+	}
+	let addr = do_io_setup(nr_events as _)?;
+	if let Err(e) = ctxp.copy_to_user(&addr.0) {
+		io_destroy(addr.0)?;
+		return Err(e);
+	}
+	Ok(0)
+}
+
+pub fn compat_io_setup(nr_events: u32, ctxp: UserPtr<u32>) -> EResult<usize> {
+	let ctx_id = ctxp.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if unlikely(ctx_id != 0) {
+		return Err(errno!(EINVAL));
+	}
+	let addr = do_io_setup(nr_events as _)?;
+	if let Err(e) = ctxp.copy_to_user(&(addr.0 as u32)) {
+		io_destroy(addr.0)?;
+		return Err(e);
+	}
+	Ok(0)
+}
+
+pub fn io_destroy(ctx_id: usize) -> EResult<usize> {
+	let mem_space = Process::current().mem_space().clone();
+	let ctx = mem_space
+		.aio
+		.lock()
+		.remove(&VirtAddr(ctx_id))
+		.ok_or_else(|| errno!(EINVAL))?;
+	ctx.kill();
+	// The ring may have been unmapped by userspace already
+	let _ = mem_space.unmap(ctx.addr(), NonZeroUsize::MIN);
+	Ok(0)
+}
+
+/// Performs the request at address `iocb_addr` and queues its completion event on `ctx`.
+///
+/// `compat` tells whether the userspace is in compatibility mode.
+///
+/// If the request cannot be submitted, the function returns an error. Errors of the operation
+/// itself are reported in the completion event instead.
+fn submit(ctx: &AioContext, iocb_addr: usize, compat: bool) -> EResult<()> {
+	let iocb: UserPtr<IoCb> = UserPtr(NonNull::new(ptr::with_exposed_provenance_mut(iocb_addr)));
+	let iocb = iocb.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	check_iocb(&iocb)?;
+	let fd = iocb.aio_fildes as c_int;
+	let file = fd_to_file(fd)?;
+	let allowed = match iocb.aio_lio_opcode {
+		IOCB_CMD_PREAD | IOCB_CMD_PREADV => file.can_read(),
+		IOCB_CMD_PWRITE | IOCB_CMD_PWRITEV => file.can_write(),
+		_ => true,
+	};
+	if unlikely(!allowed) {
+		return Err(errno!(EBADF));
+	}
+	ctx.reserve()?;
+	let buf = ptr::with_exposed_provenance_mut(iocb.aio_buf as usize);
+	let len = iocb.aio_nbytes as usize;
+	let iovcnt = min(iocb.aio_nbytes, c_int::MAX as u64) as c_int;
+	let off = iocb.aio_offset;
+	let res = match iocb.aio_lio_opcode {
+		IOCB_CMD_PREAD => pread64(fd, buf, len, off as _),
+		IOCB_CMD_PWRITE => pwrite64(fd, buf, len, off as _),
+		IOCB_CMD_FSYNC => fsync(fd),
+		IOCB_CMD_FDSYNC => fdatasync(fd),
+		IOCB_CMD_PREADV => {
+			let iov = UserIOVec::from_syscall_arg(buf as usize, compat);
+			preadv2(fd, iov, iovcnt, off as _, 0)
+		}
+		IOCB_CMD_PWRITEV => {
+			let iov = UserIOVec::from_syscall_arg(buf as usize, compat);
+			pwritev2(fd, iov, iovcnt, off as _, 0)
+		}
+		_ => Err(errno!(EINVAL)),
+	};
+	ctx.complete(IoEvent {
+		data: iocb.aio_data,
+		obj: iocb_addr as _,
+		res: match res {
+			Ok(n) => n as _,
+			Err(e) => -(e.as_int() as i64),
+		},
+		res2: 0,
+	});
+	Ok(())
+}
+
+/// Submits the requests whose addresses are returned by `iocb`, given their index.
+///
+/// `nr` is the number of requests and `compat` tells whether the userspace is in compatibility
+/// mode.
+///
+/// The function returns the number of submitted requests. If the first request cannot be
+/// submitted, the function returns the corresponding error.
+fn do_io_submit<F: Fn(usize) -> EResult<usize>>(
+	ctx_id: usize,
+	nr: usize,
+	iocb: F,
+	compat: bool,
+) -> EResult<usize> {
+	let ctx = get_context(ctx_id)?;
+	for i in 0..nr {
+		let res = iocb(i).and_then(|iocb_addr| submit(&ctx, iocb_addr, compat));
+		match res {
+			Ok(()) => {}
+			Err(e) if i == 0 => return Err(e),
+			Err(_) => return Ok(i),
+		}
+	}
+	Ok(nr)
+}
+
+pub fn io_submit(ctx_id: usize, nr: c_long, iocbpp: *mut usize) -> EResult<usize> {
+	if unlikely(nr < 0) {
+		return Err(errno!(EINVAL));
+	}
+	let iocbpp = UserSlice::from_user(iocbpp, nr as _)?;
+	do_io_submit(
+		ctx_id,
+		nr as _,
+		|i| {
+			let mut addr = [0];
+			if iocbpp.copy_from_user(i, &mut addr)? == 0 {
+				return Err(errno!(EFAULT));
+			}
+			Ok(addr[0])
+		},
+		false,
+	)
+}
+
+pub fn compat_io_submit(ctx_id: usize, nr: c_int, iocbpp: *mut u32) -> EResult<usize> {
+	if unlikely(nr < 0) {
+		return Err(errno!(EINVAL));
+	}
+	let iocbpp = UserSlice::from_user(iocbpp, nr as _)?;
+	do_io_submit(
+		ctx_id,
+		nr as _,
+		|i| {
+			let mut addr = [0];
+			if iocbpp.copy_from_user(i, &mut addr)? == 0 {
+				return Err(errno!(EFAULT));
+			}
+			Ok(addr[0] as _)
+		},
+		true,
+	)
+}
+
+/// Reaps between `min_nr` and `nr` events from the context `ctx_id` into `events`.
+///
+/// `timeout` is the maximum time to wait.
+fn do_io_getevents(
+	ctx_id: usize,
+	min_nr: i64,
+	nr: i64,
+	events: *mut IoEvent,
+	timeout: Timeout,
+) -> EResult<usize> {
+	if unlikely(min_nr < 0 || nr < 0 || min_nr > nr) {
+		return Err(errno!(EINVAL));
+	}
+	let ctx = get_context(ctx_id)?;
+	let nr = min(nr as usize, ctx.size());
+	if unlikely(events.is_null() && nr > 0) {
+		return Err(errno!(EFAULT));
+	}
+	let events = UserSlice::from_user(events, nr)?;
+	// Set up a timer to be woken up when the timeout expires. Dropping the timer at the end of
+	// the function removes it from the timer queue
+	let _timer = timeout.timer()?;
+	let mut buf = vec![IoEvent::default(); nr]?;
+	let count = ctx.get_events(min_nr as _, &mut buf, || timeout.has_expired())?;
+	events.copy_to_user(0, &buf[..count])?;
+	Ok(count)
+}
+
+pub fn io_getevents(
+	ctx_id: usize,
+	min_nr: c_int,
+	nr: c_int,
+	events: *mut IoEvent,
+	timeout: UserPtr<Timespec32>,
+) -> EResult<usize> {
+	let timeout = Timeout::from_user(timeout, Clock::Monotonic, false)?;
+	do_io_getevents(ctx_id, min_nr as _, nr as _, events, timeout)
+}
+
+pub fn io_getevents64(
+	ctx_id: usize,
+	min_nr: c_long,
+	nr: c_long,
+	events: *mut IoEvent,
+	timeout: UserPtr<Timespec>,
+) -> EResult<usize> {
+	let timeout = Timeout::from_user(timeout, Clock::Monotonic, false)?;
+	do_io_getevents(ctx_id, min_nr as _, nr as _, events, timeout)
+}
+
+/// 32-bit ABI: the timeout uses 32-bit `time_t` (`Timespec32`).
+pub fn io_pgetevents(
+	ctx_id: usize,
+	min_nr: c_int,
+	nr: c_int,
+	events: *mut IoEvent,
+	timeout: UserPtr<Timespec32>,
+	usig: PSelectSigmask,
+) -> EResult<usize> {
+	let timeout = Timeout::from_user(timeout, Clock::Monotonic, false)?;
+	let (sigmask, sigsetsize) = usig.copy_from_user()?;
+	with_sigmask(sigmask, sigsetsize, || {
+		do_io_getevents(ctx_id, min_nr as _, nr as _, events, timeout)
+	})
+}
+
+/// 64-bit ABI: the timeout uses 64-bit `time_t` (`Timespec`).
+pub fn io_pgetevents_time64(
+	ctx_id: usize,
+	min_nr: c_long,
+	nr: c_long,
+	events: *mut IoEvent,
+	timeout: UserPtr<Timespec>,
+	usig: PSelectSigmask,
+) -> EResult<usize> {
+	let timeout = Timeout::from_user(timeout, Clock::Monotonic, false)?;
+	let (sigmask, sigsetsize) = usig.copy_from_user()?;
+	with_sigmask(sigmask, sigsetsize, || {
+		do_io_getevents(ctx_id, min_nr as _, nr as _, events, timeout)
+	})
+}
+
+pub fn io_cancel(ctx_id: usize, _iocb: *mut IoCb, _result: *mut IoEvent) -> EResult<usize> {
+	get_context(ctx_id)?;
+	// Requests complete on submission, so there is no pending request to cancel
+	Err(errno!(EINVAL))
+}
@@ -25,6 +25,7 @@ use crate::{
 };
 use core::{
 	ffi::{c_int, c_uint},
+	hint::unlikely,
 	mem::{offset_of, size_of},
 	sync::atomic,
 };
@@ -85,9 +86,12 @@ pub fn getdents(fd: c_int, dirp: *mut u8, count: c_uint) -> EResult<usize> {
 	let dirp = UserSlice::from_user(dirp, count)?;
 	let mut buf_off = 0;
 	do_getdents(fd, |entry| {
-		// Skip entries whose inode cannot fit in the structure
-		if entry.inode > u32::MAX as _ {
-			return Ok(true);
+		// If the inode cannot fit in the structure, fail or stop at this entry
+		if unlikely(entry.inode > u32::MAX as _) {
+			if buf_off == 0 {
+				return Err(errno!(EOVERFLOW));
+			}
+			return Ok(false);
 		}
 		let reclen = (size_of::<LinuxDirent>() + entry.name.len() + 2)
 			// Padding for alignment
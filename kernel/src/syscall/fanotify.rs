@@ -0,0 +1,220 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `fanotify_init` and `fanotify_mark` system calls allow to watch accesses to files.
+
+use crate::{
+	file::{
+		File, FileType, O_APPEND, O_CLOEXEC, O_LARGEFILE, O_NOATIME, O_NONBLOCK, O_RDWR, O_SYNC,
+		fanotify::{
+			FAN_ALL_EVENTS, FAN_EVENT_ON_CHILD, FAN_ONDIR, FAN_PERM_EVENTS, Group, GroupFile,
+			MarkObject,
+		},
+		fd::{FD_CLOEXEC, fd_to_file},
+		fs::float,
+		perm::{can_read_file, is_privileged},
+		vfs::Resolved,
+	},
+	memory::user::UserString,
+	process::Process,
+	syscall::util::{at, at::AT_EMPTY_PATH},
+};
+use core::{
+	ffi::{c_int, c_uint},
+	hint::unlikely,
+};
+use utils::{collections::path::PathBuf, errno, errno::EResult};
+
+/// `fanotify_init`: Set the close-on-exec flag on the file descriptor.
+const FAN_CLOEXEC: c_uint = 0x1;
+/// `fanotify_init`: Non-blocking reads.
+const FAN_NONBLOCK: c_uint = 0x2;
+/// `fanotify_init`: The listener only receives notifications.
+const FAN_CLASS_NOTIF: c_uint = 0x0;
+/// `fanotify_init`: The listener may decide on accesses, once the content of files is final.
+const FAN_CLASS_CONTENT: c_uint = 0x4;
+/// `fanotify_init`: The listener may decide on accesses, before the content of files is final.
+const FAN_CLASS_PRE_CONTENT: c_uint = 0x8;
+/// `fanotify_init`: Do not limit the number of queued events.
+const FAN_UNLIMITED_QUEUE: c_uint = 0x10;
+/// `fanotify_init`: Do not limit the number of marks.
+const FAN_UNLIMITED_MARKS: c_uint = 0x20;
+
+/// `fanotify_mark`: Add events to a mark.
+const FAN_MARK_ADD: c_uint = 0x1;
+/// `fanotify_mark`: Remove events from a mark.
+const FAN_MARK_REMOVE: c_uint = 0x2;
+/// `fanotify_mark`: Do not follow symbolic links.
+const FAN_MARK_DONT_FOLLOW: c_uint = 0x4;
+/// `fanotify_mark`: Fail if the marked file is not a directory.
+const FAN_MARK_ONLYDIR: c_uint = 0x8;
+/// `fanotify_mark`: Mark the mount containing the file.
+const FAN_MARK_MOUNT: c_uint = 0x10;
+/// `fanotify_mark`: Update the ignored mask instead of the mask.
+const FAN_MARK_IGNORED_MASK: c_uint = 0x20;
+/// `fanotify_mark`: Keep the ignored mask when the file is modified.
+const FAN_MARK_IGNORED_SURV_MODIFY: c_uint = 0x40;
+/// `fanotify_mark`: Remove all marks of the given kind.
+const FAN_MARK_FLUSH: c_uint = 0x80;
+/// `fanotify_mark`: Mark the filesystem containing the file.
+const FAN_MARK_FILESYSTEM: c_uint = 0x100;
+
+pub fn fanotify_init(flags: c_uint, event_f_flags: c_uint) -> EResult<usize> {
+	if unlikely(!is_privileged()) {
+		return Err(errno!(EPERM));
+	}
+	// Validation
+	const FLAGS: c_uint = FAN_CLOEXEC
+		| FAN_NONBLOCK
+		| FAN_CLASS_CONTENT
+		| FAN_CLASS_PRE_CONTENT
+		| FAN_UNLIMITED_QUEUE
+		| FAN_UNLIMITED_MARKS;
+	if unlikely(flags & !FLAGS != 0) {
+		return Err(errno!(EINVAL));
+	}
+	let permissions = match flags & (FAN_CLASS_CONTENT | FAN_CLASS_PRE_CONTENT) {
+		FAN_CLASS_NOTIF => false,
+		FAN_CLASS_CONTENT | FAN_CLASS_PRE_CONTENT => true,
+		_ => return Err(errno!(EINVAL)),
+	};
+	let event_f_flags = event_f_flags as c_int;
+	const EVENT_F_FLAGS: c_int =
+		0b11 | O_APPEND | O_CLOEXEC | O_LARGEFILE | O_NOATIME | O_NONBLOCK | O_SYNC;
+	if unlikely(event_f_flags & !EVENT_F_FLAGS != 0 || event_f_flags & 0b11 > O_RDWR) {
+		return Err(errno!(EINVAL));
+	}
+	let group = Group::new(
+		permissions,
+		flags & FAN_UNLIMITED_QUEUE != 0,
+		flags & FAN_UNLIMITED_MARKS != 0,
+		event_f_flags,
+	)?;
+	let ent = float::get_entry(GroupFile(group), FileType::Regular)?;
+	let file_flags = if flags & FAN_NONBLOCK != 0 {
+		O_RDWR | O_NONBLOCK
+	} else {
+		O_RDWR
+	};
+	let file = File::open_floating(ent, file_flags)?;
+	let fd_flags = if flags & FAN_CLOEXEC != 0 {
+		FD_CLOEXEC
+	} else {
+		0
+	};
+	let fd = Process::current()
+		.file_descriptors()
+		.create_fd(fd_flags, file)?;
+	Ok(fd as _)
+}
+
+pub fn fanotify_mark(
+	fanotify_fd: c_int,
+	flags: c_uint,
+	mask: u64,
+	dirfd: c_int,
+	pathname: UserString,
+) -> EResult<usize> {
+	// Validation
+	const FLAGS: c_uint = FAN_MARK_ADD
+		| FAN_MARK_REMOVE
+		| FAN_MARK_DONT_FOLLOW
+		| FAN_MARK_ONLYDIR
+		| FAN_MARK_MOUNT
+		| FAN_MARK_IGNORED_MASK
+		| FAN_MARK_IGNORED_SURV_MODIFY
+		| FAN_MARK_FLUSH
+		| FAN_MARK_FILESYSTEM;
+	if unlikely(flags & !FLAGS != 0) {
+		return Err(errno!(EINVAL));
+	}
+	if unlikely(mask & !(FAN_ALL_EVENTS | FAN_EVENT_ON_CHILD | FAN_ONDIR) != 0) {
+		return Err(errno!(EINVAL));
+	}
+	// TODO support mount marks
+	if unlikely(flags & FAN_MARK_MOUNT != 0) {
+		return Err(errno!(EINVAL));
+	}
+	let file = fd_to_file(fanotify_fd)?;
+	let group = &file
+		.get_buffer::<GroupFile>()
+		.ok_or_else(|| errno!(EINVAL))?
+		.0;
+	let filesystem = flags & FAN_MARK_FILESYSTEM != 0;
+	match flags & (FAN_MARK_ADD | FAN_MARK_REMOVE | FAN_MARK_FLUSH) {
+		FAN_MARK_FLUSH => {
+			group.flush_marks(|o| matches!(o, MarkObject::Filesystem(_)) == filesystem);
+			return Ok(0);
+		}
+		FAN_MARK_ADD | FAN_MARK_REMOVE => {}
+		_ => return Err(errno!(EINVAL)),
+	}
+	if unlikely(mask == 0) {
+		return Err(errno!(EINVAL));
+	}
+	if unlikely(mask & FAN_PERM_EVENTS != 0 && !group.permissions) {
+		return Err(errno!(EINVAL));
+	}
+	// Get the marked file
+	let (pathname, at_flags) = match pathname.copy_path_opt_from_user()? {
+		Some(pathname) => (pathname, 0),
+		None => (PathBuf::empty(), AT_EMPTY_PATH),
+	};
+	let Resolved::Found(ent) = at::get_file(
+		dirfd,
+		&pathname,
+		at_flags,
+		false,
+		flags & FAN_MARK_DONT_FOLLOW == 0,
+	)?
+	else {
+		unreachable!();
+	};
+	let stat = ent.stat();
+	if unlikely(flags & FAN_MARK_ONLYDIR != 0 && stat.get_type() != Some(FileType::Directory)) {
+		return Err(errno!(ENOTDIR));
+	}
+	if unlikely(!can_read_file(&stat, true)) {
+		return Err(errno!(EACCES));
+	}
+	let node = ent.node();
+	let object = if filesystem {
+		MarkObject::Filesystem(node.fs.dev)
+	} else {
+		MarkObject::inode(node)
+	};
+	let ignored = flags & FAN_MARK_IGNORED_MASK != 0;
+	if flags & FAN_MARK_ADD != 0 {
+		group.add_mark(object, mask, ignored)?;
+	} else {
+		group.remove_mark(object, mask, ignored)?;
+	}
+	Ok(0)
+}
+
+pub fn compat_fanotify_mark(
+	fanotify_fd: c_int,
+	flags: c_uint,
+	mask_lo: u32,
+	mask_hi: u32,
+	dirfd: c_int,
+	pathname: UserString,
+) -> EResult<usize> {
+	let mask = ((mask_hi as u64) << 32) | mask_lo as u64;
+	fanotify_mark(fanotify_fd, flags, mask, dirfd, pathname)
+}
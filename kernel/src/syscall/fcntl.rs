@@ -19,10 +19,22 @@
 //! The `fcntl` syscall call allows to manipulate a file descriptor.
 
 use crate::{
-	file::{fd::NewFDConstraint, pipe::PipeBuffer},
+	arch::x86::idt::IntFrame,
+	file::{
+		FMODE_NONOTIFY, File, SEEK_CUR, SEEK_END, SEEK_SET,
+		fd::{FD_CLOEXEC, NewFDConstraint, fd_to_file},
+		lock::{RecordLock, RecordLockType},
+		pipe::PipeBuffer,
+	},
+	memory::user::UserPtr,
 	process::Process,
+	syscall::FromSyscallArg,
+};
+use core::{
+	ffi::{c_int, c_long, c_short, c_void},
+	hint::unlikely,
+	sync::atomic::Ordering::Acquire,
 };
-use core::ffi::{c_int, c_void};
 use utils::{errno, errno::EResult};
 
 /// Duplicate the file descriptor using the lowest numbered available file descriptor greater than
@@ -36,11 +48,11 @@ const F_SETFD: c_int = 2;
 const F_GETFL: c_int = 3;
 /// Set the file status flag.
 const F_SETFL: c_int = 4;
-/// TODO doc
+/// Return a record lock preventing the given one from being taken, if any.
 const F_GETLK: c_int = 5;
-/// TODO doc
+/// Take or release a record lock, failing if it conflicts with another process's lock.
 const F_SETLK: c_int = 6;
-/// TODO doc
+/// Like `F_SETLK`, but wait for conflicting locks to be released.
 const F_SETLKW: c_int = 7;
 /// Set the process ID or process group ID that will receive `SIGIO` and `SIGURG` signals for
 /// events on the file descriptor.
@@ -52,11 +64,11 @@ const F_GETOWN: c_int = 9;
 const F_SETSIG: c_int = 10;
 /// Return the signal sent when input or output becomes possible.
 const F_GETSIG: c_int = 11;
-/// TODO doc
+/// Like `F_GETLK`, with 64-bit offsets for 32-bit userspace.
 const F_GETLK64: c_int = 12;
-/// TODO doc
+/// Like `F_SETLK`, with 64-bit offsets for 32-bit userspace.
 const F_SETLK64: c_int = 13;
-/// TODO doc
+/// Like `F_SETLKW`, with 64-bit offsets for 32-bit userspace.
 const F_SETLKW64: c_int = 14;
 /// Similar to `F_SETOWN`, except it allows to specifiy a thread ID using the `f_owner_ex`
 /// structure.
@@ -104,11 +116,11 @@ const F_SET_FILE_RW_HINT: c_int = 1038;
 /// TODO doc
 const F_SEAL_FUTURE_WRITE: c_int = 16;
 
-/// Take out a read lease.
+/// Take out a read lease, or a shared record lock.
 const F_RDLCK: c_int = 0;
-/// Take out a write lease.
+/// Take out a write lease, or an exclusive record lock.
 const F_WRLCK: c_int = 1;
-/// Remove our lease from the file.
+/// Remove our lease or record lock from the file.
 const F_UNLCK: c_int = 2;
 
 /// Send the signal to the process group whose ID is specified.
@@ -127,41 +139,261 @@ const F_SEAL_SHRINK: c_int = 2;
 /// If this seal is set, you cannot modify the contents of the file.
 const F_SEAL_WRITE: c_int = 8;
 
+/// Description of a record lock, with offsets of the size of `off_t`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Flock {
+	l_type: c_short,
+	l_whence: c_short,
+	l_start: c_long,
+	l_len: c_long,
+	l_pid: c_int,
+}
+
+/// Description of a record lock, for compatibility mode.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct CompatFlock {
+	l_type: c_short,
+	l_whence: c_short,
+	l_start: i32,
+	l_len: i32,
+	l_pid: c_int,
+}
+
+/// Description of a record lock, with 64-bit offsets for 32-bit userspace.
+#[repr(C, packed(4))]
+#[derive(Clone, Copy, Debug)]
+struct Flock64 {
+	l_type: c_short,
+	l_whence: c_short,
+	l_start: i64,
+	l_len: i64,
+	l_pid: c_int,
+}
+
+/// Layout of the structure describing a record lock in userspace.
+#[derive(Clone, Copy)]
+enum FlockLayout {
+	/// [`Flock`]
+	Native,
+	/// [`CompatFlock`]
+	Compat,
+	/// [`Flock64`]
+	Large,
+}
+
+/// Reads the description of a record lock at `arg`, with the layout `layout`.
+///
+/// The offsets of the returned structure are always 64 bits wide.
+fn read_flock(arg: *mut c_void, layout: FlockLayout) -> EResult<Flock64> {
+	let flock = match layout {
+		FlockLayout::Native => {
+			let f = UserPtr::<Flock>::from_ptr(arg as usize)
+				.copy_from_user()?
+				.ok_or_else(|| errno!(EFAULT))?;
+			Flock64 {
+				l_type: f.l_type,
+				l_whence: f.l_whence,
+				l_start: f.l_start as _,
+				l_len: f.l_len as _,
+				l_pid: f.l_pid,
+			}
+		}
+		FlockLayout::Compat => {
+			let f = UserPtr::<CompatFlock>::from_ptr(arg as usize)
+				.copy_from_user()?
+				.ok_or_else(|| errno!(EFAULT))?;
+			Flock64 {
+				l_type: f.l_type,
+				l_whence: f.l_whence,
+				l_start: f.l_start as _,
+				l_len: f.l_len as _,
+				l_pid: f.l_pid,
+			}
+		}
+		FlockLayout::Large => UserPtr::<Flock64>::from_ptr(arg as usize)
+			.copy_from_user()?
+			.ok_or_else(|| errno!(EFAULT))?,
+	};
+	Ok(flock)
+}
+
+/// Writes the description of a record lock `flock` at `arg`, with the layout `layout`.
+///
+/// If an offset cannot be represented with the layout, the function returns
+/// [`errno::EOVERFLOW`].
+// `c_long` is 64 bits wide on 64-bit architectures
+#[allow(clippy::useless_conversion)]
+fn write_flock(arg: *mut c_void, layout: FlockLayout, flock: Flock64) -> EResult<()> {
+	let (start, len) = (flock.l_start, flock.l_len);
+	match layout {
+		FlockLayout::Native => UserPtr::from_ptr(arg as usize).copy_to_user(&Flock {
+			l_type: flock.l_type,
+			l_whence: flock.l_whence,
+			l_start: start.try_into().map_err(|_| errno!(EOVERFLOW))?,
+			l_len: len.try_into().map_err(|_| errno!(EOVERFLOW))?,
+			l_pid: flock.l_pid,
+		}),
+		FlockLayout::Compat => UserPtr::from_ptr(arg as usize).copy_to_user(&CompatFlock {
+			l_type: flock.l_type,
+			l_whence: flock.l_whence,
+			l_start: start.try_into().map_err(|_| errno!(EOVERFLOW))?,
+			l_len: len.try_into().map_err(|_| errno!(EOVERFLOW))?,
+			l_pid: flock.l_pid,
+		}),
+		FlockLayout::Large => UserPtr::from_ptr(arg as usize).copy_to_user(&flock),
+	}
+}
+
+/// Returns the range of bytes `start..=end` described by `flock` on `file`.
+fn lock_range(file: &File, flock: &Flock64) -> EResult<(u64, u64)> {
+	let base = match flock.l_whence as u32 {
+		SEEK_SET => 0,
+		SEEK_CUR => file.off.load(Acquire),
+		SEEK_END => file.stat().size,
+		_ => return Err(errno!(EINVAL)),
+	};
+	let (l_start, l_len) = (flock.l_start, flock.l_len);
+	let start = (base as i64)
+		.checked_add(l_start)
+		.ok_or_else(|| errno!(EOVERFLOW))?;
+	let (start, end) = match l_len {
+		// Up to the end of the file, however large it grows
+		0 => (start, i64::MAX),
+		1.. => {
+			let end = start
+				.checked_add(l_len - 1)
+				.ok_or_else(|| errno!(EOVERFLOW))?;
+			(start, end)
+		}
+		// The range ends before `start`
+		_ => {
+			let begin = start.checked_add(l_len).ok_or_else(|| errno!(EINVAL))?;
+			(begin, start - 1)
+		}
+	};
+	if unlikely(start < 0) {
+		return Err(errno!(EINVAL));
+	}
+	let end = if end == i64::MAX {
+		u64::MAX
+	} else {
+		end as u64
+	};
+	Ok((start as u64, end))
+}
+
+/// Performs the `F_GETLK`, `F_SETLK` and `F_SETLKW` commands, or their 64-bit variants.
+fn record_lock(file: &File, cmd: c_int, arg: *mut c_void, layout: FlockLayout) -> EResult<usize> {
+	let node = file.vfs_entry.node.as_ref().ok_or_else(|| errno!(EBADF))?;
+	let mut flock = read_flock(arg, layout)?;
+	let (start, end) = lock_range(file, &flock)?;
+	let owner = Process::current().get_pid();
+	let ty = match flock.l_type as c_int {
+		F_RDLCK => RecordLockType::Read,
+		F_WRLCK => RecordLockType::Write,
+		F_UNLCK if !matches!(cmd, F_GETLK | F_GETLK64) => {
+			node.record_locks.unlock(owner, start, end)?;
+			return Ok(0);
+		}
+		_ => return Err(errno!(EINVAL)),
+	};
+	let lock = RecordLock {
+		owner,
+		start,
+		end,
+		ty,
+	};
+	match cmd {
+		F_GETLK | F_GETLK64 => {
+			match node.record_locks.get_conflict(&lock) {
+				Some(l) => {
+					flock.l_type = match l.ty {
+						RecordLockType::Read => F_RDLCK as _,
+						RecordLockType::Write => F_WRLCK as _,
+					};
+					flock.l_whence = SEEK_SET as _;
+					flock.l_start = l.start as _;
+					flock.l_len = if l.end == u64::MAX {
+						0
+					} else {
+						(l.end - l.start + 1) as _
+					};
+					flock.l_pid = l.owner as _;
+				}
+				None => flock.l_type = F_UNLCK as _,
+			}
+			write_flock(arg, layout, flock)?;
+		}
+		_ => {
+			// The file must be open with the corresponding access
+			let allowed = match ty {
+				RecordLockType::Read => file.can_read(),
+				RecordLockType::Write => file.can_write(),
+			};
+			if unlikely(!allowed) {
+				return Err(errno!(EBADF));
+			}
+			let wait = matches!(cmd, F_SETLKW | F_SETLKW64);
+			node.record_locks.lock(lock, wait)?;
+		}
+	}
+	Ok(0)
+}
+
 /// Performs the fcntl system call.
 ///
 /// `fcntl64` tells whether this is the `fcntl64` system call.
-pub fn do_fcntl(fd: c_int, cmd: c_int, arg: *mut c_void, _fcntl64: bool) -> EResult<usize> {
-	let fds_mutex = Process::current().file_descriptors();
-	let mut fds = fds_mutex.lock();
+pub fn do_fcntl(
+	fd: c_int,
+	cmd: c_int,
+	arg: *mut c_void,
+	fcntl64: bool,
+	frame: &IntFrame,
+) -> EResult<usize> {
+	// 64-bit offsets are the default for 64-bit userspace
+	let compat = frame.is_compat() || cfg!(target_arch = "x86");
+	let fds = Process::current().file_descriptors();
 	match cmd {
-		F_DUPFD => {
-			let (id, _) = fds.duplicate_fd(fd as _, NewFDConstraint::Min(arg as _), false)?;
+		F_DUPFD | F_DUPFD_CLOEXEC => {
+			// The argument is the minimum ID, as an `int`
+			let min = u32::try_from(arg as usize as c_int).map_err(|_| errno!(EINVAL))?;
+			let cloexec = cmd == F_DUPFD_CLOEXEC;
+			let id = fds.duplicate_fd(fd, NewFDConstraint::Min(min), cloexec)?;
 			Ok(id as _)
 		}
-		F_GETFD => {
-			let fd = fds.get_fd(fd)?;
-			Ok(fd.flags as _)
-		}
+		F_GETFD => Ok(fds.get_fd(fd)?.flags as _),
 		F_SETFD => {
-			let fd = fds.get_fd_mut(fd)?;
-			fd.flags = arg as _;
+			fds.set_flags(fd, arg as c_int & FD_CLOEXEC)?;
 			Ok(0)
 		}
-		F_GETFL => Ok(fds.get_fd(fd)?.get_file().get_flags() as _),
+		F_GETFL => {
+			let flags = fds.get_fd(fd)?.get_file().get_flags();
+			Ok((flags & !FMODE_NONOTIFY) as _)
+		}
 		F_SETFL => {
-			fds.get_fd(fd)?.get_file().set_flags(arg as _, true);
+			let file = fd_to_file(fd)?;
+			file.set_flags(arg as _, true);
 			Ok(0)
 		}
-		F_GETLK => todo!(),
-		F_SETLK => todo!(),
-		F_SETLKW => todo!(),
+		F_GETLK | F_SETLK | F_SETLKW => {
+			let file = fd_to_file(fd)?;
+			let layout = if frame.is_compat() {
+				FlockLayout::Compat
+			} else {
+				FlockLayout::Native
+			};
+			record_lock(&file, cmd, arg, layout)
+		}
 		F_SETOWN => todo!(),
 		F_GETOWN => todo!(),
 		F_SETSIG => todo!(),
 		F_GETSIG => todo!(),
-		F_GETLK64 => todo!(),
-		F_SETLK64 => todo!(),
-		F_SETLKW64 => todo!(),
+		F_GETLK64 | F_SETLK64 | F_SETLKW64 if compat && fcntl64 => {
+			let file = fd_to_file(fd)?;
+			record_lock(&file, cmd, arg, FlockLayout::Large)
+		}
 		F_SETOWN_EX => todo!(),
 		F_GETOWN_EX => todo!(),
 		F_OFD_GETLK => todo!(),
@@ -170,13 +402,9 @@ pub fn do_fcntl(fd: c_int, cmd: c_int, arg: *mut c_void, _fcntl64: bool) -> ERes
 		F_SETLEASE => todo!(),
 		F_GETLEASE => todo!(),
 		F_NOTIFY => todo!(),
-		F_DUPFD_CLOEXEC => {
-			let (id, _) = fds.duplicate_fd(fd, NewFDConstraint::Min(arg as _), true)?;
-			Ok(id as _)
-		}
 		F_SETPIPE_SZ => todo!(),
 		F_GETPIPE_SZ => {
-			let file = fds.get_fd(fd)?.get_file();
+			let file = fd_to_file(fd)?;
 			match file.get_buffer::<PipeBuffer>() {
 				Some(fifo) => Ok(fifo.get_capacity() as _),
 				_ => Ok(0),
@@ -192,10 +420,10 @@ pub fn do_fcntl(fd: c_int, cmd: c_int, arg: *mut c_void, _fcntl64: bool) -> ERes
 	}
 }
 
-pub fn fcntl(fd: c_int, cmd: c_int, arg: *mut c_void) -> EResult<usize> {
-	do_fcntl(fd, cmd, arg, false)
+pub fn fcntl(fd: c_int, cmd: c_int, arg: *mut c_void, frame: &mut IntFrame) -> EResult<usize> {
+	do_fcntl(fd, cmd, arg, false, frame)
 }
 
-pub fn fcntl64(fd: c_int, cmd: c_int, arg: *mut c_void) -> EResult<usize> {
-	do_fcntl(fd, cmd, arg, true)
+pub fn fcntl64(fd: c_int, cmd: c_int, arg: *mut c_void, frame: &mut IntFrame) -> EResult<usize> {
+	do_fcntl(fd, cmd, arg, true, frame)
 }
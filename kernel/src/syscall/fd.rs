@@ -20,8 +20,11 @@
 
 use crate::{
 	file::{
+		File, FileType, O_CLOEXEC, SEEK_CUR, SEEK_DATA, SEEK_END, SEEK_HOLE, SEEK_SET, fanotify,
+		fanotify::{FAN_ACCESS, FAN_ACCESS_PERM, FAN_MODIFY},
 		fd::{NewFDConstraint, fd_to_file},
 		lock::FlockMode,
+		vfs,
 	},
 	memory::user::{UserIOVec, UserPtr, UserSlice},
 	process::Process,
@@ -32,14 +35,7 @@ use core::{
 	hint::unlikely,
 	sync::atomic::Ordering::{Acquire, Release},
 };
-use utils::{errno, errno::EResult, limits::IOV_MAX};
-
-/// Sets the offset from the given value.
-const SEEK_SET: u32 = 0;
-/// Sets the offset relative to the current offset.
-const SEEK_CUR: u32 = 1;
-/// Sets the offset relative to the end of the file.
-const SEEK_END: u32 = 2;
+use utils::{errno, errno::EResult};
 
 /// `flock`: Shared lock
 const LOCK_SH: c_int = 1;
@@ -50,6 +46,11 @@ const LOCK_NB: c_int = 4;
 /// `flock`: Unlock
 const LOCK_UN: c_int = 8;
 
+/// `close_range`: Give the process its own copy of the file descriptors table before closing
+const CLOSE_RANGE_UNSHARE: c_uint = 2;
+/// `close_range`: Set the close-on-exec flag instead of closing
+const CLOSE_RANGE_CLOEXEC: c_uint = 4;
+
 pub fn read(fd: c_int, buf: *mut u8, count: usize) -> EResult<usize> {
 	let buf = UserSlice::from_user(buf, count)?;
 	// Validation
@@ -58,24 +59,43 @@ pub fn read(fd: c_int, buf: *mut u8, count: usize) -> EResult<usize> {
 		return Ok(0);
 	}
 	let file = fd_to_file(fd)?;
+	fanotify::permission_file(&file, FAN_ACCESS_PERM)?;
 	// Read
 	let off = file.off.load(Acquire);
 	let len = file.ops.read(&file, off, buf)?;
 	// Update offset
 	let new_off = off.saturating_add(len as u64);
 	file.off.store(new_off, Release);
+	file_read(&file, len);
 	Ok(len as _)
 }
 
+/// Checks that `file` supports positional I/O at `offset`.
+fn check_positional(file: &File, offset: u64) -> EResult<()> {
+	if matches!(
+		file.stat().get_type(),
+		Some(FileType::Fifo | FileType::Socket)
+	) {
+		return Err(errno!(ESPIPE));
+	}
+	if unlikely(offset > i64::MAX as u64) {
+		return Err(errno!(EINVAL));
+	}
+	Ok(())
+}
+
 pub fn pread64(fd: c_int, buf: *mut u8, count: usize, offset: u64) -> EResult<usize> {
-	let buf = UserSlice::from_user(buf, count)?;
 	// Validation
 	let len = min(count, i32::MAX as usize);
+	let buf = UserSlice::from_user(buf, len)?;
+	let file = fd_to_file(fd)?;
+	check_positional(&file, offset)?;
 	if len == 0 {
 		return Ok(0);
 	}
-	let file = fd_to_file(fd)?;
+	fanotify::permission_file(&file, FAN_ACCESS_PERM)?;
 	let len = file.ops.read(&file, offset, buf)?;
+	file_read(&file, len);
 	Ok(len as _)
 }
 
@@ -106,10 +126,6 @@ fn do_readv(
 	offset: Option<isize>,
 	_flags: Option<i32>,
 ) -> EResult<usize> {
-	// Validation
-	if unlikely(iovcnt < 0 || iovcnt as usize > IOV_MAX) {
-		return Err(errno!(EINVAL));
-	}
 	let offset = match offset {
 		Some(o @ 0..) => Some(o as u64),
 		None | Some(-1) => None,
@@ -117,30 +133,39 @@ fn do_readv(
 	};
 	// TODO Handle flags
 	let file = fd_to_file(fd)?;
+	let iov = iov.import(iovcnt)?;
+	if let Some(offset) = offset {
+		check_positional(&file, offset)?;
+	}
+	fanotify::permission_file(&file, FAN_ACCESS_PERM)?;
 	// Read
 	let mut off = 0;
-	for i in iov.iter(iovcnt as _) {
-		let i = i?;
-		// The size to read. This is limited to avoid an overflow on the total length
-		let max_len = min(i.iov_len, i32::MAX as usize - off);
-		let buf = UserSlice::<u8>::from_user(i.iov_base, max_len)?;
-		// Read
-		let len = if let Some(offset) = offset {
-			let file_off = offset + off as u64;
-			file.ops.read(&file, file_off, buf)?
-		} else {
-			let off = file.off.load(Acquire);
-			let len = file.ops.read(&file, off, buf)?;
-			// Update offset
-			let new_off = off.saturating_add(len as u64);
-			file.off.store(new_off, Release);
-			len
+	for i in &iov {
+		let res = UserSlice::<u8>::from_user(i.iov_base, i.iov_len).and_then(|buf| {
+			if let Some(offset) = offset {
+				let file_off = offset + off as u64;
+				file.ops.read(&file, file_off, buf)
+			} else {
+				let off = file.off.load(Acquire);
+				let len = file.ops.read(&file, off, buf)?;
+				// Update offset
+				let new_off = off.saturating_add(len as u64);
+				file.off.store(new_off, Release);
+				Ok(len)
+			}
+		});
+		let len = match res {
+			Ok(len) => len,
+			// Report the data already transferred
+			Err(_) if off > 0 => break,
+			Err(e) => return Err(e),
 		};
 		off += len;
-		if unlikely(len < max_len) {
+		if unlikely(len < i.iov_len) {
 			break;
 		}
 	}
+	file_read(&file, off);
 	Ok(off)
 }
 
@@ -170,6 +195,21 @@ pub fn preadv2(
 	do_readv(fd, iov, iovcnt, Some(offset), Some(flags))
 }
 
+/// Reports the access to `file` after `len` bytes have been read from it.
+fn file_read(file: &File, len: usize) {
+	if len > 0 {
+		fanotify::notify_file(file, FAN_ACCESS);
+	}
+}
+
+/// Updates the timestamps of `file` after `len` bytes have been written to it.
+fn file_written(file: &File, len: usize) {
+	if len > 0 {
+		vfs::update_times(file.node(), vfs::UPDATE_MTIME | vfs::UPDATE_CTIME);
+		fanotify::notify_file(file, FAN_MODIFY);
+	}
+}
+
 pub fn write(fd: c_int, buf: *mut u8, count: usize) -> EResult<usize> {
 	let buf = UserSlice::from_user(buf, count)?;
 	// Validation
@@ -178,22 +218,27 @@ pub fn write(fd: c_int, buf: *mut u8, count: usize) -> EResult<usize> {
 		return Ok(0);
 	}
 	let file = fd_to_file(fd)?;
+	let _write = file.start_write()?;
 	let off = file.get_offset();
 	let len = file.ops.write(&file, off, buf)?;
 	let new_off = off.saturating_add(len as u64);
 	file.off.store(new_off, Release);
+	file_written(&file, len);
 	Ok(len)
 }
 
 pub fn pwrite64(fd: c_int, buf: *mut u8, count: usize, offset: u64) -> EResult<usize> {
-	let buf = UserSlice::from_user(buf, count)?;
 	// Validation
 	let len = min(count, i32::MAX as usize);
+	let buf = UserSlice::from_user(buf, len)?;
+	let file = fd_to_file(fd)?;
+	check_positional(&file, offset)?;
 	if len == 0 {
 		return Ok(0);
 	}
-	let file = fd_to_file(fd)?;
+	let _write = file.start_write()?;
 	let len = file.ops.write(&file, offset, buf)?;
+	file_written(&file, len);
 	Ok(len)
 }
 
@@ -224,10 +269,6 @@ fn do_writev(
 	offset: Option<isize>,
 	_flags: Option<i32>,
 ) -> EResult<usize> {
-	// Validation
-	if iovcnt < 0 || iovcnt as usize > IOV_MAX {
-		return Err(errno!(EINVAL));
-	}
 	let offset = match offset {
 		Some(o @ 0..) => Some(o as u64),
 		None | Some(-1) => None,
@@ -235,26 +276,40 @@ fn do_writev(
 	};
 	// Get file
 	let file = fd_to_file(fd)?;
+	let iov = iov.import(iovcnt)?;
+	if let Some(offset) = offset {
+		check_positional(&file, offset)?;
+	}
 	// Write
+	let _write = file.start_write()?;
 	let mut off = 0;
-	for i in iov.iter(iovcnt as _) {
-		let i = i?;
-		// The size to write. This is limited to avoid an overflow on the total length
-		let len = min(i.iov_len, i32::MAX as usize - off);
-		let buf = UserSlice::<u8>::from_user(i.iov_base, len)?;
-		let len = if let Some(offset) = offset {
-			let file_off = offset + off as u64;
-			file.ops.write(&file, file_off, buf)?
-		} else {
-			let off = file.get_offset();
-			let len = file.ops.write(&file, off, buf)?;
-			// Update offset
-			let new_off = off.saturating_add(len as u64);
-			file.off.store(new_off, Release);
-			len
+	for i in &iov {
+		let res = UserSlice::<u8>::from_user(i.iov_base, i.iov_len).and_then(|buf| {
+			if let Some(offset) = offset {
+				let file_off = offset + off as u64;
+				file.ops.write(&file, file_off, buf)
+			} else {
+				let off = file.get_offset();
+				let len = file.ops.write(&file, off, buf)?;
+				// Update offset
+				let new_off = off.saturating_add(len as u64);
+				file.off.store(new_off, Release);
+				Ok(len)
+			}
+		});
+		let len = match res {
+			Ok(len) => len,
+			// Report the data already transferred
+			Err(_) if off > 0 => break,
+			Err(e) => return Err(e),
 		};
 		off += len;
+		// A short write ends the transfer, so that no gap is left in the data
+		if unlikely(len < i.iov_len) {
+			break;
+		}
 	}
+	file_written(&file, off);
 	Ok(off)
 }
 
@@ -291,23 +346,45 @@ fn do_lseek(
 	whence: c_uint,
 ) -> EResult<usize> {
 	let file = fd_to_file(fd as _)?;
-	// Compute the offset
-	let base = match whence {
-		SEEK_SET => 0,
-		SEEK_CUR => file.off.load(Acquire),
-		SEEK_END => file.stat().size,
-		_ => return Err(errno!(EINVAL)),
-	};
-	let offset = match offset {
-		// Positive offset
-		0.. => base
-			.checked_add(offset as _)
-			.ok_or_else(|| errno!(EOVERFLOW))?,
-		// Negative offset
-		..0 => {
-			let offset = offset.checked_abs().ok_or_else(|| errno!(EOVERFLOW))?;
-			base.checked_sub(offset as _)
-				.ok_or_else(|| errno!(EOVERFLOW))?
+	// Files with a special seeking behaviour
+	if let Some(offset) = file.ops.seek(&file, offset, whence)? {
+		if let Some(result) = result {
+			result.copy_to_user(&offset)?;
+		}
+		file.off.store(offset, Release);
+		return Ok(offset as _);
+	}
+	let offset = match whence {
+		SEEK_DATA | SEEK_HOLE => {
+			// Files have no holes: data spans the whole file, followed by an implicit hole at the
+			// end
+			let size = file.stat().size;
+			let offset: u64 = offset.try_into().map_err(|_| errno!(ENXIO))?;
+			if unlikely(offset >= size) {
+				return Err(errno!(ENXIO));
+			}
+			if whence == SEEK_DATA { offset } else { size }
+		}
+		_ => {
+			// Compute the offset
+			let base = match whence {
+				SEEK_SET => 0,
+				SEEK_CUR => file.off.load(Acquire),
+				SEEK_END => file.stat().size,
+				_ => return Err(errno!(EINVAL)),
+			};
+			match offset {
+				// Positive offset
+				0.. => base
+					.checked_add(offset as _)
+					.ok_or_else(|| errno!(EOVERFLOW))?,
+				// Negative offset
+				..0 => {
+					let offset = offset.checked_abs().ok_or_else(|| errno!(EOVERFLOW))?;
+					base.checked_sub(offset as _)
+						.ok_or_else(|| errno!(EOVERFLOW))?
+				}
+			}
 		}
 	};
 	if let Some(result) = result {
@@ -326,7 +403,7 @@ pub fn _llseek(
 	result: UserPtr<u64>,
 	whence: c_uint,
 ) -> EResult<usize> {
-	let offset = ((offset_high as i64) << 32) | (offset_low as i64);
+	let offset = ((offset_high as i64) << 32) | (offset_low as u32 as i64);
 	do_lseek(fd, offset, Some(result), whence)?;
 	Ok(0)
 }
@@ -335,8 +412,17 @@ pub fn lseek(fd: c_uint, offset: i64, whence: c_uint) -> EResult<usize> {
 	do_lseek(fd, offset, None, whence)
 }
 
+pub fn compat_lseek(fd: c_uint, offset: i32, whence: c_uint) -> EResult<usize> {
+	let offset = do_lseek(fd, offset as _, None, whence)?;
+	// The resulting offset must be representable in a 32-bit `off_t`
+	if unlikely(offset > i32::MAX as usize) {
+		return Err(errno!(EOVERFLOW));
+	}
+	Ok(offset)
+}
+
 pub fn dup(oldfd: c_int) -> EResult<usize> {
-	let (newfd_id, _) = Process::current().file_descriptors().lock().duplicate_fd(
+	let newfd_id = Process::current().file_descriptors().duplicate_fd(
 		oldfd as _,
 		NewFDConstraint::None,
 		false,
@@ -345,10 +431,24 @@ pub fn dup(oldfd: c_int) -> EResult<usize> {
 }
 
 pub fn dup2(oldfd: c_int, newfd: c_int) -> EResult<usize> {
-	let (newfd_id, _) = Process::current().file_descriptors().lock().duplicate_fd(
+	let fds = Process::current().file_descriptors();
+	// Duplicating a file descriptor onto itself leaves it untouched
+	if oldfd == newfd {
+		fds.get_fd(oldfd)?;
+		return Ok(newfd as _);
+	}
+	let newfd_id = fds.duplicate_fd(oldfd as _, NewFDConstraint::Fixed(newfd as _), false)?;
+	Ok(newfd_id as _)
+}
+
+pub fn dup3(oldfd: c_int, newfd: c_int, flags: c_int) -> EResult<usize> {
+	if unlikely(flags & !O_CLOEXEC != 0 || oldfd == newfd) {
+		return Err(errno!(EINVAL));
+	}
+	let newfd_id = Process::current().file_descriptors().duplicate_fd(
 		oldfd as _,
 		NewFDConstraint::Fixed(newfd as _),
-		false,
+		flags & O_CLOEXEC != 0,
 	)?;
 	Ok(newfd_id as _)
 }
@@ -361,13 +461,7 @@ pub fn flock(fd: c_int, op: c_int) -> EResult<usize> {
 		LOCK_UN => FlockMode::None,
 		_ => return Err(errno!(EINVAL)),
 	};
-	// Resolve the OFD, then drop the fd-table lock before potentially blocking
-	let open_file = Process::current()
-		.file_descriptors()
-		.lock()
-		.get_fd(fd)?
-		.get_file()
-		.clone();
+	let open_file = fd_to_file(fd)?;
 	let Some(node) = open_file.vfs_entry.node.as_ref() else {
 		return Err(errno!(EBADF));
 	};
@@ -398,9 +492,20 @@ pub fn flock(fd: c_int, op: c_int) -> EResult<usize> {
 }
 
 pub fn close(fd: c_int) -> EResult<usize> {
-	Process::current()
-		.file_descriptors()
-		.lock()
-		.close_fd(fd as _)?;
+	Process::current().file_descriptors().close_fd(fd as _)?;
+	Ok(0)
+}
+
+pub fn close_range(first: c_uint, last: c_uint, flags: c_uint) -> EResult<usize> {
+	if unlikely(flags & !(CLOSE_RANGE_UNSHARE | CLOSE_RANGE_CLOEXEC) != 0 || first > last) {
+		return Err(errno!(EINVAL));
+	}
+	let proc = Process::current();
+	let fds = if flags & CLOSE_RANGE_UNSHARE != 0 {
+		proc.unshare_file_descriptors()?
+	} else {
+		proc.file_descriptors()
+	};
+	fds.close_range(first, last, flags & CLOSE_RANGE_CLOEXEC != 0);
 	Ok(0)
 }
@@ -19,32 +19,39 @@
 //! Files handling system calls.
 
 use crate::{
+	arch::x86::idt::IntFrame,
 	device::id,
 	file,
 	file::{
-		File, FileType, O_CLOEXEC, O_CREAT, O_DIRECTORY, O_EXCL, O_NOCTTY, O_NOFOLLOW, O_RDONLY,
-		O_RDWR, O_TRUNC, O_WRONLY, Stat,
+		File, FileType, O_CLOEXEC, O_CREAT, O_DIRECTORY, O_EXCL, O_LARGEFILE, O_NOCTTY,
+		O_NOFOLLOW, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, Stat, fanotify,
+		fanotify::{FAN_OPEN, FAN_OPEN_PERM},
 		fd::{FD_CLOEXEC, fd_to_file},
 		fs::StatSet,
 		perm::{
-			can_execute_file, can_list_directory, can_read_file, can_write_file, is_privileged,
+			can_execute_file, can_list_directory, can_read_file, can_write_file, creation_mode,
+			is_privileged,
 		},
-		vfs,
-		vfs::{ResolutionSettings, Resolved},
+		readahead, vfs,
+		vfs::{ResolutionSettings, Resolved, mountpoint::FLAG_NODEV},
 	},
 	memory::user::{UserPtr, UserSlice, UserString},
 	process::Process,
 	syscall::util::{
 		at,
-		at::{AT_EACCESS, AT_EMPTY_PATH, AT_FDCWD, AT_SYMLINK_NOFOLLOW},
+		at::{AT_EACCESS, AT_EMPTY_PATH, AT_FDCWD, AT_SYMLINK_FOLLOW, AT_SYMLINK_NOFOLLOW},
 	},
 	time::{
-		clock::{Clock, current_time_ns, current_time_sec},
-		unit::{TimeUnit, Timespec, Timeval, UTimBuf},
+		clock::{Clock, current_time_ns},
+		unit::{TimeUnit, Timespec, Timespec32, Timestamp, Timeval, Timeval32, UTimBuf},
 	},
 };
-use core::{ffi::c_int, hint::unlikely, sync::atomic::Ordering::Release};
-use utils::{errno, errno::EResult, limits::SYMLINK_MAX};
+use core::{cmp::min, ffi::c_int, hint::unlikely, mem};
+use utils::{
+	errno,
+	errno::EResult,
+	limits::{PAGE_SIZE, SYMLINK_MAX},
+};
 
 /// `access` flag: Checks for existence of the file.
 const F_OK: i32 = 0;
@@ -55,13 +62,43 @@ const W_OK: i32 = 2;
 /// `access` flag: Checks the file can be executed.
 const X_OK: i32 = 1;
 
+/// `fadvise` advice: No special treatment.
+const POSIX_FADV_NORMAL: c_int = 0;
+/// `fadvise` advice: Expect page references in random order.
+const POSIX_FADV_RANDOM: c_int = 1;
+/// `fadvise` advice: Expect page references in sequential order.
+const POSIX_FADV_SEQUENTIAL: c_int = 2;
+/// `fadvise` advice: Expect access in the near future.
+const POSIX_FADV_WILLNEED: c_int = 3;
+/// `fadvise` advice: Do not expect access in the near future.
+const POSIX_FADV_DONTNEED: c_int = 4;
+/// `fadvise` advice: Data will be accessed only once.
+const POSIX_FADV_NOREUSE: c_int = 5;
+
 /// `rename` flag: Don't replace new path if it exists. Return an error instead.
 const RENAME_NOREPLACE: c_int = 1;
 /// `rename` flag: Exchanges old and new paths atomically.
 const RENAME_EXCHANGE: c_int = 2;
 
-pub fn creat(pathname: UserString, mode: c_int) -> EResult<usize> {
-	do_openat(AT_FDCWD, pathname, O_CREAT | O_WRONLY | O_TRUNC, mode as _)
+/// `utimensat`: set the timestamp to the current time.
+const UTIME_NOW: i64 = (1 << 30) - 1;
+/// `utimensat`: leave the timestamp unchanged.
+const UTIME_OMIT: i64 = (1 << 30) - 2;
+
+/// Returns the open flags to use for a system call made with the given `frame`.
+///
+/// 64-bit system calls always use 64-bit offsets, so `O_LARGEFILE` is implied for them.
+fn open_flags(flags: c_int, frame: &IntFrame) -> c_int {
+	if frame.is_compat() {
+		flags
+	} else {
+		flags | O_LARGEFILE
+	}
+}
+
+pub fn creat(pathname: UserString, mode: c_int, frame: &mut IntFrame) -> EResult<usize> {
+	let flags = open_flags(O_CREAT | O_WRONLY | O_TRUNC, frame);
+	do_openat(AT_FDCWD, pathname, flags, mode as _)
 }
 
 pub fn mkdir(pathname: UserString, mode: file::Mode) -> EResult<usize> {
@@ -77,8 +114,8 @@ pub fn mkdirat(dirfd: c_int, path: UserString, mode: file::Mode) -> EResult<usiz
 	else {
 		return Err(errno!(EEXIST));
 	};
-	let mode = mode & !Process::current().umask();
-	let ts = current_time_sec(Clock::Realtime);
+	let mode = creation_mode(&parent.stat(), mode & 0o1777);
+	let ts = current_time_ns(Clock::Realtime);
 	vfs::create_file(
 		parent,
 		name,
@@ -106,8 +143,14 @@ pub fn mknodat(dirfd: c_int, path: UserString, mode: file::Mode, dev: u64) -> ER
 	else {
 		return Err(errno!(EEXIST));
 	};
+	// A zero file type designates a regular file
+	let mode = if FileType::from_mode(mode).is_none() && mode & !0o7777 == 0 {
+		FileType::Regular.to_mode() | mode
+	} else {
+		mode
+	};
 	// Check file type and permissions
-	let mode = mode & !Process::current().umask();
+	let mode = creation_mode(&parent.stat(), mode);
 	let file_type = FileType::from_mode(mode).ok_or(errno!(EPERM))?;
 	match (file_type, is_privileged()) {
 		(FileType::Regular | FileType::Fifo | FileType::Socket, _) => {}
@@ -115,7 +158,7 @@ pub fn mknodat(dirfd: c_int, path: UserString, mode: file::Mode, dev: u64) -> ER
 		(_, false) => return Err(errno!(EPERM)),
 		(_, true) => return Err(errno!(EINVAL)),
 	}
-	let ts = current_time_sec(Clock::Realtime);
+	let ts = current_time_ns(Clock::Realtime);
 	vfs::create_file(
 		parent,
 		name,
@@ -143,6 +186,9 @@ pub fn linkat(
 	newpath: UserString,
 	flags: c_int,
 ) -> EResult<usize> {
+	if unlikely(flags & !(AT_SYMLINK_FOLLOW | AT_EMPTY_PATH) != 0) {
+		return Err(errno!(EINVAL));
+	}
 	let oldpath = oldpath.copy_path_from_user()?;
 	let newpath = newpath.copy_path_from_user()?;
 	// Get old file
@@ -183,7 +229,7 @@ pub fn symlinkat(target: UserString, newdirfd: c_int, linkpath: UserString) -> E
 	else {
 		return Err(errno!(EEXIST));
 	};
-	let ts = current_time_sec(Clock::Realtime);
+	let ts = current_time_ns(Clock::Realtime);
 	vfs::symlink(
 		&parent,
 		name,
@@ -216,8 +262,13 @@ pub fn readlinkat(dirfd: c_int, path: UserString, buf: *mut u8, bufsiz: usize) -
 	Ok(len)
 }
 
-pub fn open(pathname: UserString, flags: c_int, mode: file::Mode) -> EResult<usize> {
-	do_openat(AT_FDCWD, pathname, flags, mode)
+pub fn open(
+	pathname: UserString,
+	flags: c_int,
+	mode: file::Mode,
+	frame: &mut IntFrame,
+) -> EResult<usize> {
+	do_openat(AT_FDCWD, pathname, open_flags(flags, frame), mode)
 }
 
 /// Perform the `openat` system call.
@@ -229,7 +280,6 @@ pub fn do_openat(
 ) -> EResult<usize> {
 	let proc = Process::current();
 	let pathname = pathname.copy_path_from_user()?;
-	let mode = mode & !proc.umask();
 	// Get file
 	let resolved = at::get_file(
 		dirfd,
@@ -238,13 +288,42 @@ pub fn do_openat(
 		flags & O_CREAT != 0,
 		flags & O_NOFOLLOW == 0,
 	)?;
+	let (read, write) = match flags & 0b11 {
+		O_RDONLY => (true, false),
+		O_WRONLY => (false, true),
+		O_RDWR => (true, true),
+		_ => return Err(errno!(EINVAL)),
+	};
 	let file = match resolved {
-		Resolved::Found(file) => file,
+		Resolved::Found(file) => {
+			// Check permissions
+			let stat = file.stat();
+			if read && !can_read_file(&stat, true) {
+				return Err(errno!(EACCES));
+			}
+			if write && !can_write_file(&stat, true) {
+				return Err(errno!(EACCES));
+			}
+			match stat.get_type() {
+				// Device files and IPC do not modify the filesystem
+				Some(
+					FileType::CharDevice
+					| FileType::BlockDevice
+					| FileType::Fifo
+					| FileType::Socket,
+				) => {}
+				_ if write || flags & O_TRUNC != 0 => vfs::check_writable(&file)?,
+				_ => {}
+			}
+			file
+		}
+		// The creator of a file may open it with any access, whatever its mode
 		Resolved::Creatable {
 			parent,
 			name,
 		} => {
-			let ts = current_time_sec(Clock::Realtime);
+			let mode = creation_mode(&parent.stat(), mode & 0o7777);
+			let ts = current_time_ns(Clock::Realtime);
 			vfs::create_file(
 				parent,
 				name,
@@ -258,39 +337,45 @@ pub fn do_openat(
 			)?
 		}
 	};
-	// Check permissions
-	let (read, write) = match flags & 0b11 {
-		O_RDONLY => (true, false),
-		O_WRONLY => (false, true),
-		O_RDWR => (true, true),
-		_ => return Err(errno!(EINVAL)),
-	};
 	let stat = file.stat();
-	if read && !can_read_file(&stat, true) {
-		return Err(errno!(EACCES));
-	}
-	if write && !can_write_file(&stat, true) {
-		return Err(errno!(EACCES));
-	}
 	let file_type = stat.get_type();
+	// Without `O_LARGEFILE`, the size of the file must be representable in a 32-bit `off_t`
+	if flags & O_LARGEFILE == 0
+		&& file_type == Some(FileType::Regular)
+		&& stat.size > i32::MAX as u64
+	{
+		return Err(errno!(EOVERFLOW));
+	}
 	// If `O_DIRECTORY` is set and the file is not a directory, return an error
 	if flags & O_DIRECTORY != 0 && file_type != Some(FileType::Directory) {
 		return Err(errno!(ENOTDIR));
 	}
+	// Device files cannot be opened on a `nodev` mountpoint
+	if matches!(
+		file_type,
+		Some(FileType::CharDevice | FileType::BlockDevice)
+	) && vfs::has_mount_flag(&file, FLAG_NODEV)
+	{
+		return Err(errno!(EACCES));
+	}
+	fanotify::permission(&file, FAN_OPEN_PERM)?;
 	// Open file
 	const FLAGS_MASK: i32 =
 		!(O_CLOEXEC | O_CREAT | O_DIRECTORY | O_EXCL | O_NOCTTY | O_NOFOLLOW | O_TRUNC);
 	let file = File::open(file, flags & FLAGS_MASK)?;
 	// Truncate if necessary
 	if flags & O_TRUNC != 0 && file_type == Some(FileType::Regular) {
+		let _write = file.start_write()?;
 		file.ops.truncate(&file, 0)?;
+		vfs::update_times(file.node(), vfs::UPDATE_MTIME | vfs::UPDATE_CTIME);
 	}
 	// Create FD
 	let mut fd_flags = 0;
 	if flags & O_CLOEXEC != 0 {
 		fd_flags |= FD_CLOEXEC;
 	}
-	let (fd_id, _) = proc.file_descriptors().lock().create_fd(fd_flags, file)?;
+	fanotify::notify(&file.vfs_entry, FAN_OPEN);
+	let fd_id = proc.file_descriptors().create_fd(fd_flags, file)?;
 	Ok(fd_id as _)
 }
 
@@ -299,8 +384,9 @@ pub fn openat(
 	pathname: UserString,
 	flags: c_int,
 	mode: file::Mode,
+	frame: &mut IntFrame,
 ) -> EResult<usize> {
-	do_openat(dirfd, pathname, flags, mode)
+	do_openat(dirfd, pathname, open_flags(flags, frame), mode)
 }
 
 /// Performs the access operation.
@@ -355,8 +441,89 @@ pub fn faccessat2(
 	do_access(Some(dir_fd), pathname, mode, flags)
 }
 
-pub fn fadvise64_64(_fd: c_int, _offset: u64, _len: u64, _advice: c_int) -> EResult<usize> {
-	// TODO
+pub fn fadvise64(fd: c_int, offset: u64, len: usize, advice: c_int) -> EResult<usize> {
+	fadvise64_64(fd, offset, len as _, advice)
+}
+
+pub fn compat_fadvise64(
+	fd: c_int,
+	offset_low: u32,
+	offset_high: u32,
+	len: usize,
+	advice: c_int,
+) -> EResult<usize> {
+	let offset = ((offset_high as u64) << 32) | (offset_low as u64);
+	fadvise64_64(fd, offset, len as _, advice)
+}
+
+pub fn compat_fadvise64_64(
+	fd: c_int,
+	offset_low: u32,
+	offset_high: u32,
+	len_low: u32,
+	len_high: u32,
+	advice: c_int,
+) -> EResult<usize> {
+	let offset = ((offset_high as u64) << 32) | (offset_low as u64);
+	let len = ((len_high as u64) << 32) | (len_low as u64);
+	fadvise64_64(fd, offset, len, advice)
+}
+
+pub fn fadvise64_64(fd: c_int, offset: u64, len: u64, advice: c_int) -> EResult<usize> {
+	let file = fd_to_file(fd)?;
+	let stat = file.stat();
+	let file_type = stat.get_type();
+	if matches!(file_type, Some(FileType::Fifo | FileType::Socket)) {
+		return Err(errno!(ESPIPE));
+	}
+	if unlikely(offset > i64::MAX as u64 || len > i64::MAX as u64) {
+		return Err(errno!(EINVAL));
+	}
+	// A length of zero means until the end of the file
+	let end = if len == 0 {
+		u64::MAX
+	} else {
+		offset.saturating_add(len)
+	};
+	match advice {
+		POSIX_FADV_NORMAL => file.readahead.lock().window = readahead::DEFAULT_WINDOW,
+		POSIX_FADV_RANDOM => file.readahead.lock().window = 0,
+		POSIX_FADV_SEQUENTIAL => file.readahead.lock().window = readahead::SEQUENTIAL_WINDOW,
+		POSIX_FADV_WILLNEED if file_type == Some(FileType::Regular) => {
+			let start = offset / PAGE_SIZE as u64;
+			let end = min(end, stat.size).div_ceil(PAGE_SIZE as u64);
+			if start < end {
+				readahead::submit(file.node().clone(), start, end)?;
+			}
+		}
+		POSIX_FADV_DONTNEED if file_type == Some(FileType::Regular) => {
+			// Only drop pages that are entirely inside of the range
+			let start = offset.div_ceil(PAGE_SIZE as u64);
+			let end = if end == u64::MAX {
+				u64::MAX
+			} else {
+				end / PAGE_SIZE as u64
+			};
+			file.node().mapped.invalidate(start, end);
+		}
+		POSIX_FADV_WILLNEED | POSIX_FADV_DONTNEED | POSIX_FADV_NOREUSE => {}
+		_ => return Err(errno!(EINVAL)),
+	}
+	Ok(0)
+}
+
+pub fn readahead(fd: c_int, offset: u64, count: usize) -> EResult<usize> {
+	let file = fd_to_file(fd)?;
+	if !file.can_read() {
+		return Err(errno!(EBADF));
+	}
+	let stat = file.stat();
+	if stat.get_type() != Some(FileType::Regular) {
+		return Err(errno!(EINVAL));
+	}
+	let start = offset / PAGE_SIZE as u64;
+	let end = min(offset.saturating_add(count as u64), stat.size).div_ceil(PAGE_SIZE as u64);
+	readahead::read_pages(file.node(), start, end)?;
 	Ok(0)
 }
 
@@ -366,6 +533,7 @@ pub fn chmod(pathname: UserString, mode: file::Mode) -> EResult<usize> {
 
 pub fn fchmod(fd: c_int, mode: file::Mode) -> EResult<usize> {
 	let file = fd_to_file(fd)?;
+	vfs::check_writable(&file.vfs_entry)?;
 	vfs::set_stat(
 		file.vfs_entry.node(),
 		&StatSet {
@@ -387,6 +555,7 @@ pub fn fchmodat(
 	let Resolved::Found(file) = at::get_file(dirfd, &pathname, flags, false, true)? else {
 		unreachable!();
 	};
+	vfs::check_writable(&file)?;
 	vfs::set_stat(
 		file.node(),
 		&StatSet {
@@ -405,6 +574,9 @@ fn do_fchownat(
 	flags: c_int,
 ) -> EResult<usize> {
 	// Validation
+	if unlikely(flags & !(AT_SYMLINK_NOFOLLOW | AT_EMPTY_PATH) != 0) {
+		return Err(errno!(EINVAL));
+	}
 	if !(-1..=u16::MAX as c_int).contains(&user) || !(-1..=u16::MAX as c_int).contains(&group) {
 		return Err(errno!(EINVAL));
 	}
@@ -416,10 +588,7 @@ fn do_fchownat(
 	let Resolved::Found(ent) = at::get_file(dirfd, &path, flags, false, true)? else {
 		unreachable!();
 	};
-	// TODO allow changing group to any group whose owner is member
-	if unlikely(!is_privileged()) {
-		return Err(errno!(EPERM));
-	}
+	vfs::check_writable(&ent)?;
 	vfs::set_stat(
 		ent.node(),
 		&StatSet {
@@ -517,59 +686,117 @@ pub fn fchdir(fd: c_int) -> EResult<usize> {
 }
 
 pub fn umask(mask: file::Mode) -> EResult<usize> {
-	let prev = Process::current().umask.swap(mask & 0o777, Release);
+	let prev = mem::replace(&mut Process::current().fs.lock().umask, mask & 0o777);
 	Ok(prev as _)
 }
 
-fn do_utimensat<T: TimeUnit>(
+/// Performs the `utimensat` system call.
+///
+/// `times` are the new access and modification timestamps, in nanoseconds. `None` leaves the
+/// timestamp unchanged.
+fn do_utimensat(
 	dirfd: c_int,
 	pathname: UserString,
-	times: [T; 2],
+	times: [Option<Timestamp>; 2],
 	flags: c_int,
 ) -> EResult<usize> {
 	let pathname = pathname.copy_path_from_user()?;
 	let Resolved::Found(file) = at::get_file(dirfd, &pathname, flags, false, true)? else {
 		unreachable!();
 	};
+	// Nothing to change
+	if times == [None, None] {
+		return Ok(0);
+	}
+	vfs::check_writable(&file)?;
 	vfs::set_stat(
 		file.node(),
 		&StatSet {
-			atime: Some(times[0].to_nano() / 1_000_000_000),
-			mtime: Some(times[1].to_nano() / 1_000_000_000),
+			atime: times[0],
+			mtime: times[1],
 			..Default::default()
 		},
 	)?;
 	Ok(0)
 }
 
+/// Returns the timestamps to be set by the legacy `utime*` system calls.
+///
+/// If `times` is `None`, both timestamps are set to the current time.
+fn utime_values<T: TimeUnit>(times: Option<[T; 2]>) -> EResult<[Option<Timestamp>; 2]> {
+	let Some(times) = times else {
+		let now = current_time_ns(Clock::Realtime);
+		return Ok([Some(now); 2]);
+	};
+	if unlikely(times.iter().any(|t| !t.is_valid())) {
+		return Err(errno!(EINVAL));
+	}
+	Ok(times.map(|t| Some(t.to_nano())))
+}
+
 pub fn utime(path: UserString, times: UserPtr<UTimBuf>) -> EResult<usize> {
-	let times = times.copy_from_user()?.ok_or(errno!(EFAULT))?;
-	let mut t: [Timespec; 2] = Default::default();
-	t[0].tv_sec = times.actime as _;
-	t[1].tv_sec = times.modtime as _;
-	do_utimensat(AT_FDCWD, path, t, 0)
+	let times = times.copy_from_user()?.map(|times| {
+		[
+			Timeval32 {
+				tv_sec: times.actime,
+				tv_usec: 0,
+			},
+			Timeval32 {
+				tv_sec: times.modtime,
+				tv_usec: 0,
+			},
+		]
+	});
+	do_utimensat(AT_FDCWD, path, utime_values(times)?, 0)
 }
 
 pub fn utimes(path: UserString, times: UserPtr<[Timeval; 2]>) -> EResult<usize> {
-	let times = times.copy_from_user()?.ok_or(errno!(EFAULT))?;
+	let times = utime_values(times.copy_from_user()?)?;
 	do_utimensat(AT_FDCWD, path, times, 0)
 }
 
 pub fn futimesat(dirfd: c_int, path: UserString, times: UserPtr<[Timeval; 2]>) -> EResult<usize> {
-	let times = times.copy_from_user()?.ok_or(errno!(EFAULT))?;
+	let times = utime_values(times.copy_from_user()?)?;
 	do_utimensat(dirfd, path, times, 0)
 }
 
+/// Decodes the timestamps passed to `utimensat`, handling `UTIME_NOW` and `UTIME_OMIT`.
+///
+/// If `times` is `None`, both timestamps are set to the current time.
+fn utimensat_values(times: Option<[Timespec; 2]>) -> EResult<[Option<Timestamp>; 2]> {
+	let now = current_time_ns(Clock::Realtime);
+	let value = |ts: &Timespec| match ts.tv_nsec {
+		UTIME_NOW => Ok(Some(now)),
+		UTIME_OMIT => Ok(None),
+		_ if ts.is_valid() => Ok(Some(ts.to_nano())),
+		_ => Err(errno!(EINVAL)),
+	};
+	match times {
+		Some(times) => Ok([value(&times[0])?, value(&times[1])?]),
+		None => Ok([Some(now); 2]),
+	}
+}
+
+/// 32-bit ABI: the timestamps use 32-bit `time_t` (`Timespec32`).
+pub fn compat_utimensat(
+	dirfd: c_int,
+	pathname: UserString,
+	times: UserPtr<[Timespec32; 2]>,
+	flags: c_int,
+) -> EResult<usize> {
+	let times = times.copy_from_user()?.map(|t| t.map(Timespec::from));
+	let times = utimensat_values(times)?;
+	do_utimensat(dirfd, pathname, times, flags)
+}
+
+/// 64-bit ABI: the timestamps use 64-bit `time_t` (`Timespec`).
 pub fn utimensat(
 	dirfd: c_int,
 	pathname: UserString,
 	times: UserPtr<[Timespec; 2]>,
 	flags: c_int,
 ) -> EResult<usize> {
-	let times = times.copy_from_user()?.unwrap_or_else(|| {
-		let ts = Timespec::from_nano(current_time_ns(Clock::Monotonic));
-		[ts, ts]
-	});
+	let times = utimensat_values(times.copy_from_user()?)?;
 	do_utimensat(dirfd, pathname, times, flags)
 }
 
@@ -626,32 +853,67 @@ pub fn renameat2(
 	do_renameat2(olddirfd, oldpath, newdirfd, newpath, flags)
 }
 
-pub fn truncate(path: UserString, length: usize) -> EResult<usize> {
+/// Performs the `truncate` system call.
+fn do_truncate(path: UserString, length: i64) -> EResult<usize> {
+	let length: u64 = length.try_into().map_err(|_| errno!(EINVAL))?;
 	let path = path.copy_path_from_user()?;
 	let ent = vfs::get_file_from_path(&path, true)?;
 	// Permission check
+	vfs::check_writable(&ent)?;
 	if !can_write_file(&ent.stat(), true) {
 		return Err(errno!(EACCES));
 	}
 	// Truncate
 	let file = File::open(ent, O_WRONLY)?;
-	file.ops.truncate(&file, length as _)?;
+	let _write = file.start_write()?;
+	file.ops.truncate(&file, length)?;
+	vfs::update_times(file.node(), vfs::UPDATE_MTIME | vfs::UPDATE_CTIME);
 	Ok(0)
 }
 
-pub fn ftruncate(fd: c_int, length: usize) -> EResult<usize> {
+/// Performs the `ftruncate` system call.
+fn do_ftruncate(fd: c_int, length: i64) -> EResult<usize> {
 	if unlikely(fd < 0) {
 		return Err(errno!(EBADF));
 	}
+	let length: u64 = length.try_into().map_err(|_| errno!(EINVAL))?;
 	let file = fd_to_file(fd)?;
 	// Permission check
 	if unlikely(!file.can_write()) {
 		return Err(errno!(EINVAL));
 	}
-	file.ops.truncate(&file, length as _)?;
+	let _write = file.start_write()?;
+	file.ops.truncate(&file, length)?;
+	vfs::update_times(file.node(), vfs::UPDATE_MTIME | vfs::UPDATE_CTIME);
 	Ok(0)
 }
 
+pub fn truncate(path: UserString, length: i64) -> EResult<usize> {
+	do_truncate(path, length)
+}
+
+pub fn compat_truncate(path: UserString, length: i32) -> EResult<usize> {
+	do_truncate(path, length as _)
+}
+
+pub fn truncate64(path: UserString, length_low: u32, length_high: u32) -> EResult<usize> {
+	let length = ((length_high as i64) << 32) | (length_low as i64);
+	do_truncate(path, length)
+}
+
+pub fn ftruncate(fd: c_int, length: i64) -> EResult<usize> {
+	do_ftruncate(fd, length)
+}
+
+pub fn compat_ftruncate(fd: c_int, length: i32) -> EResult<usize> {
+	do_ftruncate(fd, length as _)
+}
+
+pub fn ftruncate64(fd: c_int, length_low: u32, length_high: u32) -> EResult<usize> {
+	let length = ((length_high as i64) << 32) | (length_low as i64);
+	do_ftruncate(fd, length)
+}
+
 pub fn unlink(pathname: UserString) -> EResult<usize> {
 	do_unlinkat(AT_FDCWD, pathname, 0)
 }
@@ -20,12 +20,12 @@
 
 use crate::{
 	memory::user::UserPtr,
-	process::{Process, State},
+	process::Process,
 	sync::{spin::Spin, wait_queue::WaitQueue},
 	time::{
-		clock::{Clock, current_time_ns},
-		timer::Timer,
-		unit::{TimeUnit, Timespec, Timespec32, Timestamp},
+		clock::Clock,
+		timeout::Timeout,
+		unit::{TimeUnit, Timespec, Timespec32},
 	},
 };
 use core::{ffi::c_int, hint::unlikely, ptr::NonNull};
@@ -106,30 +106,13 @@ fn user_word(uaddr: *mut u32) -> EResult<UserPtr<u32>> {
 	Ok(UserPtr(NonNull::new(uaddr)))
 }
 
-/// Performs `FUTEX_WAIT` / `FUTEX_WAIT_BITSET`.
-///
-/// `delay` is the relative timeout, in nanoseconds. `0` means "no timeout".
-fn do_wait(uaddr: *mut u32, val: u32, clock: Clock, delay: Timestamp) -> EResult<()> {
+/// Performs `FUTEX_WAIT` / `FUTEX_WAIT_BITSET`, until woken up or until `timeout` expires.
+fn do_wait(uaddr: *mut u32, val: u32, timeout: Timeout) -> EResult<()> {
 	let user = user_word(uaddr)?;
 	let key = make_key(uaddr as usize);
 	let queue = lookup_or_create(key)?;
-	// Set up a timer if a timeout was given. Dropping the timer at the end of the function
-	// removes it from the timer queue.
-	let _timer = if delay > 0 {
-		let proc = Process::current();
-		let mut t = Timer::new(clock, move || {
-			Process::wake_from(&proc, State::IntSleeping as u8);
-		})?;
-		t.set_time(0, delay)?;
-		Some(t)
-	} else {
-		None
-	};
-	let deadline = if delay > 0 {
-		Some(current_time_ns(clock).saturating_add(delay))
-	} else {
-		None
-	};
+	// Dropping the timer at the end of the function removes it from the timer queue
+	let _timer = timeout.timer()?;
 	let res = queue.wait_check(|| {
 		let cur = user.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
 		if cur != val {
@@ -139,10 +122,7 @@ fn do_wait(uaddr: *mut u32, val: u32, clock: Clock, delay: Timestamp) -> EResult
 	});
 	cleanup_if_unused(&key, &queue);
 	// Convert a normal wake into `ETIMEDOUT` if the timer has expired
-	if res.is_ok()
-		&& let Some(deadline) = deadline
-		&& current_time_ns(clock) >= deadline
-	{
+	if res.is_ok() && timeout.has_expired() {
 		return Err(errno!(ETIMEDOUT));
 	}
 	res
@@ -162,12 +142,12 @@ fn do_wake(uaddr: *mut u32, val: u32) -> EResult<usize> {
 
 /// Common dispatch for `futex`, parameterized on the timespec ABI.
 ///
-/// `timeout_ns` returns the timespec at the given userspace pointer in nanoseconds.
-fn do_futex(
+/// `timeout` is read only by the operations that take a timeout. If null, there is no timeout.
+fn do_futex<T: TimeUnit>(
 	uaddr: *mut u32,
 	op: c_int,
 	val: u32,
-	timeout_ns: impl FnOnce() -> EResult<Timestamp>,
+	timeout: UserPtr<T>,
 ) -> EResult<usize> {
 	let cmd = op & FUTEX_CMD_MASK;
 	let clock = if op & FUTEX_CLOCK_REALTIME != 0 {
@@ -177,22 +157,13 @@ fn do_futex(
 	};
 	match cmd {
 		FUTEX_WAIT => {
-			let delay = timeout_ns()?;
-			do_wait(uaddr, val, Clock::Monotonic, delay)?;
+			let timeout = Timeout::from_user(timeout, Clock::Monotonic, false)?;
+			do_wait(uaddr, val, timeout)?;
 			Ok(0)
 		}
 		FUTEX_WAIT_BITSET => {
-			let delay = match timeout_ns()? {
-				0 => 0,
-				ts => {
-					let now = current_time_ns(clock);
-					if ts <= now {
-						return Err(errno!(ETIMEDOUT));
-					}
-					ts - now
-				}
-			};
-			do_wait(uaddr, val, clock, delay)?;
+			let timeout = Timeout::from_user(timeout, clock, true)?;
+			do_wait(uaddr, val, timeout)?;
 			Ok(0)
 		}
 		FUTEX_WAKE | FUTEX_WAKE_BITSET => do_wake(uaddr, val),
@@ -209,12 +180,7 @@ pub fn futex(
 	_uaddr2: *mut u32,
 	_val3: u32,
 ) -> EResult<usize> {
-	do_futex(uaddr, op, val, || {
-		Ok(timeout
-			.copy_from_user()?
-			.map(|ts| ts.to_nano())
-			.unwrap_or(0))
-	})
+	do_futex(uaddr, op, val, timeout)
 }
 
 /// 64-bit ABI: `timeout` points to a [`Timespec`].
@@ -226,10 +192,5 @@ pub fn futex_time64(
 	_uaddr2: *mut u32,
 	_val3: u32,
 ) -> EResult<usize> {
-	do_futex(uaddr, op, val, || {
-		Ok(timeout
-			.copy_from_user()?
-			.map(|ts| ts.to_nano())
-			.unwrap_or(0))
-	})
+	do_futex(uaddr, op, val, timeout)
 }
@@ -19,7 +19,7 @@
 //! Host management system calls.
 
 use crate::{
-	NAME, VERSION,
+	BUILD_VERSION, NAME, RELEASE,
 	arch::ARCH,
 	file::perm::is_privileged,
 	memory::{
@@ -27,7 +27,10 @@ use crate::{
 		user::{UserPtr, UserSlice},
 	},
 	power,
-	process::{PROCESS_FLAG_LINUX, PROCESSES, Process},
+	process::{
+		PROCESS_FLAG_LINUX, PROCESSES, Process,
+		scheduler::{loadavg, loadavg::FSHIFT},
+	},
 	time::clock::{Clock, current_time_sec},
 };
 use core::{
@@ -82,13 +85,16 @@ pub fn uname(buf: UserPtr<Utsname>) -> EResult<usize> {
 	};
 	slice_copy(sysname, &mut utsname.sysname);
 	slice_copy(&crate::HOSTNAME.lock(), &mut utsname.nodename);
-	slice_copy(VERSION.as_bytes(), &mut utsname.release);
-	slice_copy(&[], &mut utsname.version);
+	slice_copy(RELEASE.as_bytes(), &mut utsname.release);
+	slice_copy(BUILD_VERSION.as_bytes(), &mut utsname.version);
 	slice_copy(ARCH.as_bytes(), &mut utsname.machine);
 	buf.copy_to_user(&utsname)?;
 	Ok(0)
 }
 
+/// The number of bits of fractional part of the load averages returned by `sysinfo`.
+const SI_LOAD_SHIFT: u32 = 16;
+
 /// Userspace structure storing some system usage statistics.
 #[derive(Debug)]
 #[repr(C)]
@@ -126,7 +132,7 @@ pub fn sysinfo(info: UserPtr<Sysinfo>) -> EResult<usize> {
 	let mem_info = MEM_INFO.lock().clone();
 	info.copy_to_user(&Sysinfo {
 		uptime: current_time_sec(Clock::Boottime) as _,
-		loads: [0; 3], // TODO
+		loads: loadavg::get().map(|load| (load << (SI_LOAD_SHIFT - FSHIFT)) as _),
 		totalram: mem_info.mem_total as _,
 		freeram: mem_info.mem_free as _,
 		sharedram: 0, // TODO
@@ -19,14 +19,23 @@
 //! The `ioctl` syscall allows to control a device represented by a file
 //! descriptor.
 
-use crate::process::Process;
-use core::ffi::{c_int, c_ulong, c_void};
-use utils::errno::EResult;
+use crate::file::{FileType, fd::fd_to_file, fiemap, perm::is_privileged};
+use core::{
+	ffi::{c_int, c_ulong, c_void},
+	hint::unlikely,
+};
+use utils::{errno, errno::EResult};
 
 // ioctl requests: hard drive
 
 /// ioctl request: get device geometry.
 pub const HDIO_GETGEO: c_ulong = 0x00000301;
+/// ioctl request: get the data returned by the IDENTIFY DEVICE command.
+pub const HDIO_GET_IDENTITY: c_ulong = 0x0000030d;
+/// ioctl request: execute an ATA command, transferring no data.
+pub const HDIO_DRIVE_TASK: c_ulong = 0x0000031e;
+/// ioctl request: execute an ATA command, possibly reading data.
+pub const HDIO_DRIVE_CMD: c_ulong = 0x0000031f;
 
 // ioctl requests: storage
 
@@ -37,6 +46,37 @@ pub const BLKSSZGET: c_ulong = 0x00001268;
 /// ioctl request: get storage size in bytes.
 pub const BLKGETSIZE64: c_ulong = 0x00001272;
 
+// ioctl requests: loop devices
+
+/// ioctl request: attach the loop device to a file.
+pub const LOOP_SET_FD: c_ulong = 0x00004c00;
+/// ioctl request: detach the loop device from its file.
+pub const LOOP_CLR_FD: c_ulong = 0x00004c01;
+/// ioctl request: set the status of the loop device.
+pub const LOOP_SET_STATUS64: c_ulong = 0x00004c04;
+/// ioctl request: get the status of the loop device.
+pub const LOOP_GET_STATUS64: c_ulong = 0x00004c05;
+/// ioctl request: create a new loop device.
+pub const LOOP_CTL_ADD: c_ulong = 0x00004c80;
+/// ioctl request: remove a loop device.
+pub const LOOP_CTL_REMOVE: c_ulong = 0x00004c81;
+/// ioctl request: get the index of a free loop device, creating one if necessary.
+pub const LOOP_CTL_GET_FREE: c_ulong = 0x00004c82;
+
+// ioctl requests: filesystems
+
+/// ioctl request: get the location of a block of a file on the device.
+pub const FIBMAP: c_ulong = 0x00000001;
+/// ioctl request: get the block size of the filesystem.
+pub const FIGETBSZ: c_ulong = 0x00000002;
+/// ioctl request: get the location of the extents of a file on the device.
+pub const FS_IOC_FIEMAP: c_ulong = 0x0000660b;
+
+/// ioctl request: freeze the filesystem.
+pub const FIFREEZE: c_ulong = 0x00005877;
+/// ioctl request: thaw the filesystem.
+pub const FITHAW: c_ulong = 0x00005878;
+
 // ioctl requests: TTY
 
 /// ioctl request: Returns the current serial port settings.
@@ -44,12 +84,17 @@ pub const TCGETS: c_ulong = 0x00005401;
 /// ioctl request: Sets the serial port settings. Making the change immediately.
 pub const TCSETS: c_ulong = 0x00005402;
 /// ioctl request: Sets the serial port settings. Making the change only when
-/// all currently written data has been transmitted. At this points, any
-/// received data is discarded.
+/// all currently written data has been transmitted.
 pub const TCSETSW: c_ulong = 0x00005403;
 /// ioctl request: Sets the serial port settings. Making the change only when
-/// all currently written data has been transmitted.
+/// all currently written data has been transmitted. At this points, any
+/// received data is discarded.
 pub const TCSETSF: c_ulong = 0x00005404;
+/// ioctl request: Waits until all written data has been transmitted, then sends a break if the
+/// argument is zero.
+pub const TCSBRK: c_ulong = 0x00005409;
+/// ioctl request: Discards the data received, written, or both, depending on the argument.
+pub const TCFLSH: c_ulong = 0x0000540b;
 /// ioctl request: Get the foreground process group ID on the terminal.
 pub const TIOCGPGRP: c_ulong = 0x0000540f;
 /// ioctl request: Set the foreground process group ID on the terminal.
@@ -60,6 +105,17 @@ pub const TIOCGWINSZ: c_ulong = 0x00005413;
 pub const TIOCSWINSZ: c_ulong = 0x00005414;
 /// ioctl request: Returns the number of bytes available on the file descriptor.
 pub const FIONREAD: c_ulong = 0x0000541b;
+/// ioctl request: Hangs up the terminal.
+pub const TIOCVHANGUP: c_ulong = 0x00005437;
+
+// ioctl requests: virtual terminals
+
+/// ioctl request: Returns the state of virtual terminals.
+pub const VT_GETSTATE: c_ulong = 0x00005603;
+/// ioctl request: Makes the given virtual terminal the active one.
+pub const VT_ACTIVATE: c_ulong = 0x00005606;
+/// ioctl request: Waits until the given virtual terminal is the active one.
+pub const VT_WAITACTIVE: c_ulong = 0x00005607;
 
 /// IO directions for ioctl requests.
 #[derive(Eq, PartialEq)]
@@ -70,6 +126,8 @@ pub enum Direction {
 	Read,
 	/// The userspace transmits information.
 	Write,
+	/// The userspace transmits information and requires information back.
+	ReadWrite,
 }
 
 impl TryFrom<c_ulong> for Direction {
@@ -80,6 +138,7 @@ impl TryFrom<c_ulong> for Direction {
 			0 => Ok(Self::None),
 			2 => Ok(Self::Read),
 			1 => Ok(Self::Write),
+			3 => Ok(Self::ReadWrite),
 			_ => Err(()),
 		}
 	}
@@ -104,7 +163,7 @@ impl From<c_ulong> for Request {
 			major: ((req >> 8) & 0xff) as u8,
 			minor: (req & 0xff) as u8,
 
-			size: ((req >> 16) & 0x3f) as usize,
+			size: ((req >> 16) & 0x3fff) as usize,
 			direction: ((req >> 30) & 0x03).try_into().unwrap(),
 		}
 	}
@@ -119,11 +178,33 @@ impl Request {
 
 pub(super) fn ioctl(fd: c_int, request: c_ulong, argp: *const c_void) -> EResult<usize> {
 	let request = Request::from(request);
-	let file = Process::current()
-		.file_descriptors()
-		.lock()
-		.get_fd(fd)?
-		.get_file()
-		.clone();
-	file.ops.ioctl(&file, request, argp).map(|v| v as _)
+	let file = fd_to_file(fd)?;
+	match request.get_old_format() {
+		// Requests on the filesystem the file is located on
+		FIFREEZE | FITHAW if unlikely(!is_privileged()) => Err(errno!(EPERM)),
+		FIFREEZE => {
+			file.node().fs.freeze()?;
+			Ok(0)
+		}
+		FITHAW => {
+			file.node().fs.thaw()?;
+			Ok(0)
+		}
+		// Requests on the blocks of files, other files forward them to their driver
+		FIBMAP | FIGETBSZ | FS_IOC_FIEMAP
+			if matches!(
+				file.stat().get_type(),
+				Some(FileType::Regular | FileType::Directory)
+			) =>
+		{
+			match request.get_old_format() {
+				FIBMAP if unlikely(!is_privileged()) => Err(errno!(EPERM)),
+				FIBMAP => fiemap::fibmap(&file, argp),
+				FIGETBSZ => fiemap::getbsz(&file, argp),
+				_ => fiemap::fiemap(&file, argp),
+			}
+			.map(|v| v as _)
+		}
+		_ => file.ops.ioctl(&file, request, argp).map(|v| v as _),
+	}
 }
@@ -19,9 +19,9 @@
 //! Memory management system calls.
 
 use crate::{
-	file::{FileType, fd::fd_to_file},
+	file::{FileType, fd::fd_to_file, vfs, vfs::mountpoint::FLAG_NOEXEC},
 	memory,
-	memory::{VirtAddr, user::UserSlice},
+	memory::{VirtAddr, oom, user::UserSlice},
 	process::{
 		Process,
 		mem_space::{MAP_ANONYMOUS, MAP_SHARED, PROT_EXEC, PROT_READ, PROT_WRITE},
@@ -60,16 +60,37 @@ pub fn do_mmap(
 		// Get file
 		let file = fd_to_file(fd)?;
 		// Check permissions
-		if unlikely(file.stat().get_type() != Some(FileType::Regular)) {
-			return Err(errno!(EACCES));
+		match file.stat().get_type() {
+			Some(FileType::Regular) => {}
+			// Only devices exposing their memory can be mapped
+			Some(FileType::CharDevice) => {
+				if file
+					.ops
+					.map_page(&file, offset / PAGE_SIZE as u64)?
+					.is_none()
+				{
+					return Err(errno!(ENODEV));
+				}
+			}
+			_ => return Err(errno!(EACCES)),
 		}
-		if unlikely(flags & MAP_SHARED != 0 && prot & PROT_WRITE != 0 && !file.can_write()) {
+		// Device memory is mapped directly, so private mappings write to the device too
+		let shared =
+			flags & MAP_SHARED != 0 || file.stat().get_type() == Some(FileType::CharDevice);
+		if unlikely(shared && prot & PROT_WRITE != 0 && !file.can_write()) {
 			return Err(errno!(EACCES));
 		}
+		if unlikely(prot & PROT_EXEC != 0 && vfs::has_mount_flag(&file.vfs_entry, FLAG_NOEXEC)) {
+			return Err(errno!(EPERM));
+		}
 		Some(file)
 	} else {
 		None
 	};
+	// Private writable mappings may require memory to be allocated
+	if prot & PROT_WRITE != 0 && flags & MAP_SHARED == 0 && !oom::can_commit(pages.get()) {
+		return Err(errno!(ENOMEM));
+	}
 	let addr = Process::current()
 		.mem_space()
 		.map(addr, pages, prot, flags, file, offset)?;
@@ -21,8 +21,10 @@
 //! Documentation for each system call can be retrieved from the man. Type the
 //! command: `man 2 <syscall>`
 
+mod aio;
 mod dirent;
 mod execve;
+mod fanotify;
 mod fcntl;
 mod fd;
 mod fs;
@@ -35,12 +37,15 @@ mod module;
 mod mount;
 mod pipe;
 mod process;
+mod quota;
 pub mod select;
 mod signal;
 mod socket;
 mod stat;
 mod sync;
+mod syslog;
 mod time;
+pub mod unimplemented;
 mod user;
 mod util;
 pub mod wait;
@@ -56,58 +61,77 @@ use crate::{
 		signal::Signal,
 	},
 	syscall::{
+		aio::{
+			compat_io_setup, compat_io_submit, io_cancel, io_destroy, io_getevents,
+			io_getevents64, io_pgetevents, io_pgetevents_time64, io_setup, io_submit,
+		},
 		dirent::{getdents, getdents64},
 		execve::execve,
 		execve::execveat,
+		fanotify::{compat_fanotify_mark, fanotify_init, fanotify_mark},
 		fcntl::{fcntl, fcntl64},
 		fd::{
-			_llseek, close, dup, dup2, flock, lseek, pread64, preadv, preadv2, pwrite64, pwritev,
-			pwritev2, read, readv, write, writev,
+			_llseek, close, close_range, compat_lseek, compat_pread64, compat_pwrite64, dup, dup2,
+			dup3, flock, lseek, pread64, preadv, preadv2, pwrite64, pwritev, pwritev2, read,
+			readv, write, writev,
 		},
 		fs::{
-			access, chdir, chmod, chown, chroot, creat, faccessat, faccessat2, fadvise64_64,
-			fchdir, fchmod, fchmodat, fchown, fchownat, ftruncate, getcwd, lchown, link, linkat,
-			mkdir, mknod, open, openat, readlink, rename, renameat2, rmdir, symlink, symlinkat,
-			truncate, umask, unlink, unlinkat, utimensat,
+			access, chdir, chmod, chown, chroot, compat_fadvise64, compat_fadvise64_64,
+			compat_ftruncate, compat_truncate, compat_utimensat, creat, faccessat, faccessat2,
+			fadvise64, fadvise64_64, fchdir, fchmod, fchmodat, fchown, fchownat, ftruncate,
+			ftruncate64, getcwd, lchown, link, linkat, mkdir, mknod, open, openat, readlink,
+			rename, renameat2, rmdir, symlink, symlinkat, truncate, truncate64, umask, unlink,
+			unlinkat, utimensat,
 		},
-		fs::{futimesat, mkdirat, mknodat, readlinkat, renameat, utime, utimes},
+		fs::{futimesat, mkdirat, mknodat, readahead, readlinkat, renameat, utime, utimes},
 		futex::{futex, futex_time64},
 		getrandom::getrandom,
 		host::{reboot, sethostname, sysinfo, uname},
 		ioctl::ioctl,
 		mem::{brk, madvise, mincore, mmap, mmap2, mprotect, munmap},
 		module::{delete_module, finit_module, init_module},
-		mount::{mount, umount, umount2},
+		mount::{mount, mount_setattr, umount, umount2},
 		pipe::{pipe, pipe2},
 		process::{
 			_exit, arch_prctl, clone, compat_clone, exit_group, fork, getpgid, getpid, getppid,
-			getpriority, getrusage, gettid, membarrier, nice, prctl, prlimit64, sched_getaffinity,
+			getpriority, getrusage, gettid, ioprio_get, ioprio_set, membarrier, nice, prctl,
+			prlimit64, sched_getaffinity, sched_rr_get_interval, sched_rr_get_interval_time64,
 			sched_setaffinity, sched_yield, set_thread_area, set_tid_address, setpgid,
-			setpriority, vfork,
+			setpriority, vfork, vhangup,
 		},
-		select::{_newselect, poll, pselect6, select},
+		quota::quotactl,
+		select::{_newselect, poll, ppoll, ppoll_time64, pselect6, pselect6_time64, select},
 		signal::{
-			compat_rt_sigaction, compat_sigaltstack, kill, rt_sigaction, rt_sigpending,
-			rt_sigprocmask, rt_sigreturn, rt_sigtimedwait, sigaltstack, signal, sigreturn, tkill,
+			compat_rt_sigaction, compat_rt_sigqueueinfo, compat_sigaltstack, kill, pause,
+			rt_sigaction, rt_sigpending, rt_sigprocmask, rt_sigqueueinfo, rt_sigreturn,
+			rt_sigsuspend, rt_sigtimedwait, rt_sigtimedwait_time64, sigaltstack, signal,
+			sigpending, sigreturn, sigsuspend, tkill,
 		},
 		socket::{
-			bind, connect, getsockname, getsockopt, sendto, setsockopt, shutdown, socket,
+			accept, accept4, bind, connect, getpeername, getsockname, getsockopt, listen,
+			recvfrom, recvmsg, sendmsg, sendto, setsockopt, shutdown, socket, socketcall,
 			socketpair,
 		},
 		stat::{
-			fstat, fstat64, fstatat64, fstatfs, fstatfs64, lstat, lstat64, newfstatat, oldfstat,
-			oldlstat, oldstat, stat, stat64, statfs, statfs64, statx,
+			compat_fstat64, compat_fstatat64, compat_lstat64, compat_stat64, fstat, fstat64,
+			fstatfs, fstatfs64, lstat, lstat64, newfstatat, oldfstat, oldlstat, oldstat, stat,
+			stat64, statfs, statfs64, statx,
 		},
-		sync::{fdatasync, fsync, msync, sync, syncfs},
+		sync::{compat_sync_file_range, fdatasync, fsync, msync, sync, sync_file_range, syncfs},
+		syslog::syslog,
 		time::{
-			clock_gettime, clock_gettime64, nanosleep32, nanosleep64, time32, time64,
-			timer_create, timer_delete, timer_settime, timer_settime64,
+			alarm, clock_getres, clock_getres64, clock_gettime, clock_gettime64, clock_nanosleep,
+			clock_nanosleep64, clock_settime, clock_settime64, compat_times, getitimer,
+			getitimer64, gettimeofday, gettimeofday64, nanosleep32, nanosleep64, setitimer,
+			setitimer64, settimeofday, settimeofday64, time32, time64, timer_create, timer_delete,
+			timer_gettime, timer_gettime64, timer_settime, timer_settime64, times,
 		},
 		user::{
 			getegid, geteuid, getgid, getgroups, getgroups32, getresgid, getresuid, getuid,
-			setgid, setgroups, setgroups32, setregid, setresgid, setresuid, setreuid, setuid,
+			setfsgid, setfsuid, setgid, setgroups, setgroups32, setregid, setresgid, setresuid,
+			setreuid, setuid,
 		},
-		wait::{wait4, waitpid},
+		wait::{wait4, waitid, waitpid},
 	},
 };
 use core::{fmt, hint::unlikely, ptr};
@@ -277,7 +301,7 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x010 => syscall!(lchown, frame),
 		// 0x011: unimplemented (break)
 		0x012 => syscall!(oldstat, frame),
-		// TODO 0x013 => syscall!(lseek, frame),
+		0x013 => syscall!(compat_lseek, frame),
 		0x014 => syscall!(getpid, frame),
 		0x015 => syscall!(mount, frame),
 		0x016 => syscall!(umount, frame),
@@ -285,9 +309,9 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x018 => syscall!(getuid, frame),
 		// TODO 0x019 => syscall!(stime, frame),
 		// TODO 0x01a => syscall!(ptrace, frame),
-		// TODO 0x01b => syscall!(alarm, frame),
+		0x01b => syscall!(alarm, frame),
 		0x01c => syscall!(oldfstat, frame),
-		// TODO 0x01d => syscall!(pause, frame),
+		0x01d => syscall!(pause, frame),
 		0x01e => syscall!(utime, frame),
 		// 0x01f: unimplemented (stty),
 		// 0x020: unimplemented_syscall (gtty)
@@ -301,7 +325,7 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x028 => syscall!(rmdir, frame),
 		0x029 => syscall!(dup, frame),
 		0x02a => syscall!(pipe, frame),
-		// TODO 0x02b => syscall!(times, frame),
+		0x02b => syscall!(compat_times, frame),
 		// 0x02c: unimplemented (prof),
 		0x02d => syscall!(brk, frame),
 		0x02e => syscall!(setgid, frame),
@@ -330,14 +354,14 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x045 => syscall!(ssetmask, frame),
 		0x046 => syscall!(setreuid, frame),
 		0x047 => syscall!(setregid, frame),
-		// TODO 0x048 => syscall!(sigsuspend, frame),
-		// TODO 0x049 => syscall!(sigpending, frame),
+		0x048 => syscall!(sigsuspend, frame),
+		0x049 => syscall!(sigpending, frame),
 		0x04a => syscall!(sethostname, frame),
 		// TODO 0x04b => syscall!(setrlimit, frame),
 		// TODO 0x04c => syscall!(getrlimit, frame),
 		0x04d => syscall!(getrusage, frame),
-		// TODO 0x04e => syscall!(gettimeofday, frame),
-		// TODO 0x04f => syscall!(settimeofday, frame),
+		0x04e => syscall!(gettimeofday, frame),
+		0x04f => syscall!(settimeofday, frame),
 		0x050 => syscall!(getgroups, frame),
 		0x051 => syscall!(setgroups, frame),
 		0x052 => syscall!(select, frame),
@@ -350,8 +374,8 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x059 => syscall!(readdir, frame),
 		0x05a => syscall!(mmap, frame),
 		0x05b => syscall!(munmap, frame),
-		0x05c => syscall!(truncate, frame),
-		0x05d => syscall!(ftruncate, frame),
+		0x05c => syscall!(compat_truncate, frame),
+		0x05d => syscall!(compat_ftruncate, frame),
 		0x05e => syscall!(fchmod, frame),
 		0x05f => syscall!(fchown, frame),
 		0x060 => syscall!(getpriority, frame),
@@ -360,16 +384,16 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x063 => syscall!(statfs, frame),
 		0x064 => syscall!(fstatfs, frame),
 		// TODO 0x065 => syscall!(ioperm, frame),
-		// TODO 0x066 => syscall!(socketcall, frame),
-		// TODO 0x067 => syscall!(syslog, frame),
-		// TODO 0x068 => syscall!(setitimer, frame),
-		// TODO 0x069 => syscall!(getitimer, frame),
+		0x066 => syscall!(socketcall, frame),
+		0x067 => syscall!(syslog, frame),
+		0x068 => syscall!(setitimer, frame),
+		0x069 => syscall!(getitimer, frame),
 		0x06a => syscall!(stat, frame),
 		0x06b => syscall!(lstat, frame),
 		0x06c => syscall!(fstat, frame),
 		// TODO 0x06d => syscall!(olduname, frame),
 		// TODO 0x06e => syscall!(iopl, frame),
-		// TODO 0x06f => syscall!(vhangup, frame),
+		0x06f => syscall!(vhangup, frame),
 		// TODO 0x070 => syscall!(idle, frame),
 		// TODO 0x071 => syscall!(vm86old, frame),
 		0x072 => syscall!(wait4, frame),
@@ -387,15 +411,15 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x07f => syscall!(create_module, frame),
 		0x080 => syscall!(init_module, frame),
 		0x081 => syscall!(delete_module, frame),
-		// TODO 0x083 => syscall!(quotactl, frame),
+		0x083 => syscall!(quotactl, frame),
 		0x084 => syscall!(getpgid, frame),
 		0x085 => syscall!(fchdir, frame),
 		// TODO 0x086 => syscall!(bdflush, frame),
 		// TODO 0x087 => syscall!(sysfs, frame),
 		// TODO 0x088 => syscall!(personality, frame),
 		// 0x089: unimplemented (afs_syscall),
-		// TODO 0x08a => syscall!(setfsuid, frame),
-		// TODO 0x08b => syscall!(setfsgid, frame),
+		0x08a => syscall!(setfsuid, frame),
+		0x08b => syscall!(setfsgid, frame),
 		0x08c => syscall!(_llseek, frame),
 		0x08d => syscall!(getdents, frame),
 		0x08e => syscall!(_newselect, frame),
@@ -417,7 +441,7 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x09e => syscall!(sched_yield, frame),
 		// TODO 0x09f => syscall!(sched_get_priority_max, frame),
 		// TODO 0x0a0 => syscall!(sched_get_priority_min, frame),
-		// TODO 0x0a1 => syscall!(sched_rr_get_interval, frame),
+		0x0a1 => syscall!(sched_rr_get_interval, frame),
 		0x0a2 => syscall!(nanosleep32, frame),
 		// TODO 0x0a3 => syscall!(mremap, frame),
 		0x0a4 => syscall!(setresuid, frame),
@@ -429,15 +453,15 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x0aa => syscall!(setresgid, frame),
 		0x0ab => syscall!(getresgid, frame),
 		0x0ac => syscall!(prctl, frame),
-		0x0ad => syscall!(sigreturn, frame),
+		0x0ad => syscall!(rt_sigreturn, frame),
 		0x0ae => syscall!(compat_rt_sigaction, frame),
 		0x0af => syscall!(rt_sigprocmask, frame),
 		0x0b0 => syscall!(rt_sigpending, frame),
 		0x0b1 => syscall!(rt_sigtimedwait, frame),
-		// TODO 0x0b2 => syscall!(rt_sigqueueinfo, frame),
-		// TODO 0x0b3 => syscall!(rt_sigsuspend, frame),
-		0x0b4 => syscall!(pread64, frame),
-		0x0b5 => syscall!(pwrite64, frame),
+		0x0b2 => syscall!(compat_rt_sigqueueinfo, frame),
+		0x0b3 => syscall!(rt_sigsuspend, frame),
+		0x0b4 => syscall!(compat_pread64, frame),
+		0x0b5 => syscall!(compat_pwrite64, frame),
 		0x0b6 => syscall!(chown, frame),
 		0x0b7 => syscall!(getcwd, frame),
 		// TODO 0x0b8 => syscall!(capget, frame),
@@ -449,12 +473,12 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x0be => syscall!(vfork, frame),
 		// TODO 0x0bf => syscall!(ugetrlimit, frame),
 		0x0c0 => syscall!(mmap2, frame),
-		// TODO 0x0c1 => syscall!(truncate64, frame),
-		// TODO 0x0c2 => syscall!(ftruncate64, frame),
-		0x0c3 => syscall!(stat64, frame),
-		0x0c4 => syscall!(lstat64, frame),
-		0x0c5 => syscall!(fstat64, frame),
-		// TODO 0x0c6 => syscall!(lchown32, frame),
+		0x0c1 => syscall!(truncate64, frame),
+		0x0c2 => syscall!(ftruncate64, frame),
+		0x0c3 => syscall!(compat_stat64, frame),
+		0x0c4 => syscall!(compat_lstat64, frame),
+		0x0c5 => syscall!(compat_fstat64, frame),
+		0x0c6 => syscall!(lchown, frame),   // lchown32
 		0x0c7 => syscall!(getuid, frame),   // getuid32
 		0x0c8 => syscall!(getgid, frame),   // getgid32
 		0x0c9 => syscall!(geteuid, frame),  // geteuid32
@@ -463,7 +487,7 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x0cc => syscall!(setregid, frame), // setregid32
 		0x0cd => syscall!(getgroups32, frame),
 		0x0ce => syscall!(setgroups32, frame),
-		// TODO 0x0cf => syscall!(fchown32, frame),
+		0x0cf => syscall!(fchown, frame),    // fchown32
 		0x0d0 => syscall!(setresuid, frame), // setresuid32
 		0x0d1 => syscall!(getresuid, frame), // getresuid32
 		0x0d2 => syscall!(setresgid, frame), // setresgid32
@@ -471,15 +495,15 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x0d4 => syscall!(chown, frame),     // chown32
 		0x0d5 => syscall!(setuid, frame),    // setuid32
 		0x0d6 => syscall!(setgid, frame),    // setgid32
-		// TODO 0x0d7 => syscall!(setfsuid32, frame),
-		// TODO 0x0d8 => syscall!(setfsgid32, frame),
+		0x0d7 => syscall!(setfsuid, frame),  // setfsuid32
+		0x0d8 => syscall!(setfsgid, frame),  // setfsgid32
 		// TODO 0x0d9 => syscall!(pivot_root, frame),
 		0x0da => syscall!(mincore, frame),
 		0x0db => syscall!(madvise, frame),
 		0x0dc => syscall!(getdents64, frame),
 		0x0dd => syscall!(fcntl64, frame),
 		0x0e0 => syscall!(gettid, frame),
-		// TODO 0x0e1 => syscall!(readahead, frame),
+		0x0e1 => syscall!(readahead, frame),
 		// TODO 0x0e2 => syscall!(setxattr, frame),
 		// TODO 0x0e3 => syscall!(lsetxattr, frame),
 		// TODO 0x0e4 => syscall!(fsetxattr, frame),
@@ -499,12 +523,12 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x0f2 => syscall!(sched_getaffinity, frame),
 		0x0f3 => syscall!(set_thread_area, frame),
 		// TODO 0x0f4 => syscall!(get_thread_area, frame),
-		// TODO 0x0f5 => syscall!(io_setup, frame),
-		// TODO 0x0f6 => syscall!(io_destroy, frame),
-		// TODO 0x0f7 => syscall!(io_getevents, frame),
-		// TODO 0x0f8 => syscall!(io_submit, frame),
-		// TODO 0x0f9 => syscall!(io_cancel, frame),
-		// TODO 0x0fa => syscall!(fadvise64, frame),
+		0x0f5 => syscall!(compat_io_setup, frame),
+		0x0f6 => syscall!(io_destroy, frame),
+		0x0f7 => syscall!(io_getevents, frame),
+		0x0f8 => syscall!(compat_io_submit, frame),
+		0x0f9 => syscall!(io_cancel, frame),
+		0x0fa => syscall!(compat_fadvise64, frame),
 		0x0fc => syscall!(exit_group, frame),
 		// TODO 0x0fd => syscall!(lookup_dcookie, frame),
 		// TODO 0x0fe => syscall!(epoll_create, frame),
@@ -514,18 +538,18 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x102 => syscall!(set_tid_address, frame),
 		0x103 => syscall!(timer_create, frame),
 		0x104 => syscall!(timer_settime, frame),
-		// TODO 0x105 => syscall!(timer_gettime, frame),
+		0x105 => syscall!(timer_gettime, frame),
 		// TODO 0x106 => syscall!(timer_getoverrun, frame),
 		0x107 => syscall!(timer_delete, frame),
-		// TODO 0x108 => syscall!(clock_settime, frame),
+		0x108 => syscall!(clock_settime, frame),
 		0x109 => syscall!(clock_gettime, frame),
-		// TODO 0x10a => syscall!(clock_getres, frame),
-		// TODO 0x10b => syscall!(clock_nanosleep, frame),
+		0x10a => syscall!(clock_getres, frame),
+		0x10b => syscall!(clock_nanosleep, frame),
 		0x10c => syscall!(statfs64, frame),
 		0x10d => syscall!(fstatfs64, frame),
 		// TODO 0x10e => syscall!(tgkill, frame),
 		0x10f => syscall!(utimes, frame),
-		0x110 => syscall!(fadvise64_64, frame),
+		0x110 => syscall!(compat_fadvise64_64, frame),
 		// 0x111: unimplemented (vserver),
 		// TODO 0x112 => syscall!(mbind, frame),
 		// TODO 0x113 => syscall!(get_mempolicy, frame),
@@ -537,12 +561,12 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x119 => syscall!(mq_notify, frame),
 		// TODO 0x11a => syscall!(mq_getsetattr, frame),
 		// TODO 0x11b => syscall!(kexec_load, frame),
-		// TODO 0x11c => syscall!(waitid, frame),
+		0x11c => syscall!(waitid, frame),
 		// TODO 0x11e => syscall!(add_key, frame),
 		// TODO 0x11f => syscall!(request_key, frame),
 		// TODO 0x120 => syscall!(keyctl, frame),
-		// TODO 0x121 => syscall!(ioprio_set, frame),
-		// TODO 0x122 => syscall!(ioprio_get, frame),
+		0x121 => syscall!(ioprio_set, frame),
+		0x122 => syscall!(ioprio_get, frame),
 		// TODO 0x123 => syscall!(inotify_init, frame),
 		// TODO 0x124 => syscall!(inotify_add_watch, frame),
 		// TODO 0x125 => syscall!(inotify_rm_watch, frame),
@@ -552,7 +576,7 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x129 => syscall!(mknodat, frame),
 		0x12a => syscall!(fchownat, frame),
 		0x12b => syscall!(futimesat, frame),
-		0x12c => syscall!(fstatat64, frame),
+		0x12c => syscall!(compat_fstatat64, frame),
 		0x12d => syscall!(unlinkat, frame),
 		0x12e => syscall!(renameat, frame),
 		0x12f => syscall!(linkat, frame),
@@ -561,18 +585,18 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x132 => syscall!(fchmodat, frame),
 		0x133 => syscall!(faccessat, frame),
 		0x134 => syscall!(pselect6, frame),
-		// TODO 0x135 => syscall!(ppoll, frame),
+		0x135 => syscall!(ppoll, frame),
 		// TODO 0x136 => syscall!(unshare, frame),
 		// TODO 0x137 => syscall!(set_robust_list, frame),
 		// TODO 0x138 => syscall!(get_robust_list, frame),
 		// TODO 0x139 => syscall!(splice, frame),
-		// TODO 0x13a => syscall!(sync_file_range, frame),
+		0x13a => syscall!(compat_sync_file_range, frame),
 		// TODO 0x13b => syscall!(tee, frame),
 		// TODO 0x13c => syscall!(vmsplice, frame),
 		// TODO 0x13d => syscall!(move_pages, frame),
 		// TODO 0x13e => syscall!(getcpu, frame),
 		// TODO 0x13f => syscall!(epoll_pwait, frame),
-		0x140 => syscall!(utimensat, frame),
+		0x140 => syscall!(compat_utimensat, frame),
 		// TODO 0x141 => syscall!(signalfd, frame),
 		// TODO 0x142 => syscall!(timerfd_create, frame),
 		// TODO 0x143 => syscall!(eventfd, frame),
@@ -582,7 +606,7 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x147 => syscall!(signalfd4, frame),
 		// TODO 0x148 => syscall!(eventfd2, frame),
 		// TODO 0x149 => syscall!(epoll_create1, frame),
-		// TODO 0x14a => syscall!(dup3, frame),
+		0x14a => syscall!(dup3, frame),
 		0x14b => syscall!(pipe2, frame),
 		// TODO 0x14c => syscall!(inotify_init1, frame),
 		0x14d => syscall!(preadv, frame),
@@ -590,8 +614,8 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x14f => syscall!(rt_tgsigqueueinfo, frame),
 		// TODO 0x150 => syscall!(perf_event_open, frame),
 		// TODO 0x151 => syscall!(recvmmsg, frame),
-		// TODO 0x152 => syscall!(fanotify_init, frame),
-		// TODO 0x153 => syscall!(fanotify_mark, frame),
+		0x152 => syscall!(fanotify_init, frame),
+		0x153 => syscall!(compat_fanotify_mark, frame),
 		0x154 => syscall!(prlimit64, frame),
 		// TODO 0x155 => syscall!(name_to_handle_at, frame),
 		// TODO 0x156 => syscall!(open_by_handle_at, frame),
@@ -615,16 +639,16 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x168 => syscall!(socketpair, frame),
 		0x169 => syscall!(bind, frame),
 		0x16a => syscall!(connect, frame),
-		// TODO 0x16b => syscall!(listen, frame),
-		// TODO 0x16c => syscall!(accept4, frame),
+		0x16b => syscall!(listen, frame),
+		0x16c => syscall!(accept4, frame),
 		0x16d => syscall!(getsockopt, frame),
 		0x16e => syscall!(setsockopt, frame),
 		0x16f => syscall!(getsockname, frame),
-		// TODO 0x170 => syscall!(getpeername, frame),
+		0x170 => syscall!(getpeername, frame),
 		0x171 => syscall!(sendto, frame),
-		// TODO 0x172 => syscall!(sendmsg, frame),
-		// TODO 0x173 => syscall!(recvfrom, frame),
-		// TODO 0x174 => syscall!(recvmsg, frame),
+		0x172 => syscall!(sendmsg, frame),
+		0x173 => syscall!(recvfrom, frame),
+		0x174 => syscall!(recvmsg, frame),
 		0x175 => syscall!(shutdown, frame),
 		// TODO 0x176 => syscall!(userfaultfd, frame),
 		0x177 => syscall!(membarrier, frame),
@@ -637,7 +661,7 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x17e => syscall!(pkey_free, frame),
 		0x17f => syscall!(statx, frame),
 		0x180 => syscall!(arch_prctl, frame),
-		// TODO 0x181 => syscall!(io_pgetevents, frame),
+		0x181 => syscall!(io_pgetevents, frame),
 		// TODO 0x182 => syscall!(rseq, frame),
 		// TODO 0x189 => syscall!(semget, frame),
 		// TODO 0x18a => syscall!(semctl, frame),
@@ -650,25 +674,25 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x191 => syscall!(msgrcv, frame),
 		// TODO 0x192 => syscall!(msgctl, frame),
 		0x193 => syscall!(clock_gettime64, frame),
-		// TODO 0x194 => syscall!(clock_settime64, frame),
+		0x194 => syscall!(clock_settime64, frame),
 		// TODO 0x195 => syscall!(clock_adjtime64, frame),
-		// TODO 0x196 => syscall!(clock_getres_time64, frame),
-		// TODO 0x197 => syscall!(clock_nanosleep_time64, frame),
-		// TODO 0x198 => syscall!(timer_gettime64, frame),
+		0x196 => syscall!(clock_getres64, frame),
+		0x197 => syscall!(clock_nanosleep64, frame),
+		0x198 => syscall!(timer_gettime64, frame),
 		0x199 => syscall!(timer_settime64, frame),
 		// TODO 0x19a => syscall!(timerfd_gettime64, frame),
 		// TODO 0x19b => syscall!(timerfd_settime64, frame),
-		// TODO 0x19c => syscall!(utimensat_time64, frame),
-		// TODO 0x19d => syscall!(pselect6_time64, frame),
-		// TODO 0x19e => syscall!(ppoll_time64, frame),
-		// TODO 0x1a0 => syscall!(io_pgetevents_time64, frame),
+		0x19c => syscall!(utimensat, frame),
+		0x19d => syscall!(pselect6_time64, frame),
+		0x19e => syscall!(ppoll_time64, frame),
+		0x1a0 => syscall!(io_pgetevents_time64, frame),
 		// TODO 0x1a1 => syscall!(recvmmsg_time64, frame),
 		// TODO 0x1a2 => syscall!(mq_timedsend_time64, frame),
 		// TODO 0x1a3 => syscall!(mq_timedreceive_time64, frame),
 		// TODO 0x1a4 => syscall!(semtimedop_time64, frame),
-		// TODO 0x1a5 => syscall!(rt_sigtimedwait_time64, frame),
+		0x1a5 => syscall!(rt_sigtimedwait_time64, frame),
 		0x1a6 => syscall!(futex_time64, frame),
-		// TODO 0x1a7 => syscall!(sched_rr_get_interval_time64, frame),
+		0x1a7 => syscall!(sched_rr_get_interval_time64, frame),
 		// TODO 0x1a8 => syscall!(pidfd_send_signal, frame),
 		// TODO 0x1a9 => syscall!(io_uring_setup, frame),
 		// TODO 0x1aa => syscall!(io_uring_enter, frame),
@@ -681,13 +705,13 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x1b1 => syscall!(fspick, frame),
 		// TODO 0x1b2 => syscall!(pidfd_open, frame),
 		// TODO 0x1b3 => syscall!(clone3, frame),
-		// TODO 0x1b4 => syscall!(close_range, frame),
+		0x1b4 => syscall!(close_range, frame),
 		// TODO 0x1b5 => syscall!(openat2, frame),
 		// TODO 0x1b6 => syscall!(pidfd_getfd, frame),
 		0x1b7 => syscall!(faccessat2, frame),
 		// TODO 0x1b8 => syscall!(process_madvise, frame),
 		// TODO 0x1b9 => syscall!(epoll_pwait2, frame),
-		// TODO 0x1ba => syscall!(mount_setattr, frame),
+		0x1ba => syscall!(mount_setattr, frame),
 		// TODO 0x1bb => syscall!(quotactl_fd, frame),
 		// TODO 0x1bc => syscall!(landlock_create_ruleset, frame),
 		// TODO 0x1bd => syscall!(landlock_add_rule, frame),
@@ -738,25 +762,25 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x01f => syscall!(shmctl, frame),
 		0x020 => syscall!(dup, frame),
 		0x021 => syscall!(dup2, frame),
-		// TODO 0x022 => syscall!(pause, frame),
+		0x022 => syscall!(pause, frame),
 		0x023 => syscall!(nanosleep64, frame),
-		// TODO 0x024 => syscall!(getitimer, frame),
-		// TODO 0x025 => syscall!(alarm, frame),
-		// TODO 0x026 => syscall!(setitimer, frame),
+		0x024 => syscall!(getitimer64, frame),
+		0x025 => syscall!(alarm, frame),
+		0x026 => syscall!(setitimer64, frame),
 		0x027 => syscall!(getpid, frame),
 		// TODO 0x028 => syscall!(sendfile, frame),
 		0x029 => syscall!(socket, frame),
 		0x02a => syscall!(connect, frame),
-		// TODO 0x02b => syscall!(accept, frame),
+		0x02b => syscall!(accept, frame),
 		0x02c => syscall!(sendto, frame),
-		// TODO 0x02d => syscall!(recvfrom, frame),
-		// TODO 0x02e => syscall!(sendmsg, frame),
-		// TODO 0x02f => syscall!(recvmsg, frame),
+		0x02d => syscall!(recvfrom, frame),
+		0x02e => syscall!(sendmsg, frame),
+		0x02f => syscall!(recvmsg, frame),
 		0x030 => syscall!(shutdown, frame),
 		0x031 => syscall!(bind, frame),
-		// TODO 0x032 => syscall!(listen, frame),
+		0x032 => syscall!(listen, frame),
 		0x033 => syscall!(getsockname, frame),
-		// TODO 0x034 => syscall!(getpeername, frame),
+		0x034 => syscall!(getpeername, frame),
 		0x035 => syscall!(socketpair, frame),
 		0x036 => syscall!(setsockopt, frame),
 		0x037 => syscall!(getsockopt, frame),
@@ -800,14 +824,14 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x05d => syscall!(fchown, frame),
 		0x05e => syscall!(lchown, frame),
 		0x05f => syscall!(umask, frame),
-		// TODO 0x060 => syscall!(gettimeofday, frame),
+		0x060 => syscall!(gettimeofday64, frame),
 		// TODO 0x061 => syscall!(getrlimit, frame),
 		0x062 => syscall!(getrusage, frame),
 		0x063 => syscall!(sysinfo, frame),
-		// TODO 0x064 => syscall!(times, frame),
+		0x064 => syscall!(times, frame),
 		// TODO 0x065 => syscall!(ptrace, frame),
 		0x066 => syscall!(getuid, frame),
-		// TODO 0x067 => syscall!(syslog, frame),
+		0x067 => syscall!(syslog, frame),
 		0x068 => syscall!(getgid, frame),
 		0x069 => syscall!(setuid, frame),
 		0x06a => syscall!(setgid, frame),
@@ -826,15 +850,15 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x077 => syscall!(setresgid, frame),
 		0x078 => syscall!(getresgid, frame),
 		0x079 => syscall!(getpgid, frame),
-		// TODO 0x07a => syscall!(setfsuid, frame),
-		// TODO 0x07b => syscall!(setfsgid, frame),
+		0x07a => syscall!(setfsuid, frame),
+		0x07b => syscall!(setfsgid, frame),
 		// TODO 0x07c => syscall!(getsid, frame),
 		// TODO 0x07d => syscall!(capget, frame),
 		// TODO 0x07e => syscall!(capset, frame),
 		0x07f => syscall!(rt_sigpending, frame),
-		0x080 => syscall!(rt_sigtimedwait, frame),
-		// TODO 0x081 => syscall!(rt_sigqueueinfo, frame),
-		// TODO 0x082 => syscall!(rt_sigsuspend, frame),
+		0x080 => syscall!(rt_sigtimedwait_time64, frame),
+		0x081 => syscall!(rt_sigqueueinfo, frame),
+		0x082 => syscall!(rt_sigsuspend, frame),
 		0x083 => syscall!(sigaltstack, frame),
 		0x084 => syscall!(utime, frame),
 		0x085 => syscall!(mknod, frame),
@@ -852,12 +876,12 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x091 => syscall!(sched_getscheduler, frame),
 		// TODO 0x092 => syscall!(sched_get_priority_max, frame),
 		// TODO 0x093 => syscall!(sched_get_priority_min, frame),
-		// TODO 0x094 => syscall!(sched_rr_get_interval, frame),
+		0x094 => syscall!(sched_rr_get_interval_time64, frame),
 		// TODO 0x095 => syscall!(mlock, frame),
 		// TODO 0x096 => syscall!(munlock, frame),
 		// TODO 0x097 => syscall!(mlockall, frame),
 		// TODO 0x098 => syscall!(munlockall, frame),
-		// TODO 0x099 => syscall!(vhangup, frame),
+		0x099 => syscall!(vhangup, frame),
 		// TODO 0x09a => syscall!(modify_ldt, frame),
 		// TODO 0x09b => syscall!(pivot_root, frame),
 		// TODO 0x09c => syscall!(_sysctl, frame),
@@ -868,7 +892,7 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x0a1 => syscall!(chroot, frame),
 		0x0a2 => syscall!(sync, frame),
 		// TODO 0x0a3 => syscall!(acct, frame),
-		// TODO 0x0a4 => syscall!(settimeofday, frame),
+		0x0a4 => syscall!(settimeofday64, frame),
 		0x0a5 => syscall!(mount, frame),
 		0x0a6 => syscall!(umount2, frame),
 		// TODO 0x0a7 => syscall!(swapon, frame),
@@ -883,7 +907,7 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x0b0 => syscall!(delete_module, frame),
 		// TODO 0x0b1 => syscall!(get_kernel_sym, frame),
 		// TODO 0x0b2 => syscall!(query_modul, frame),
-		// TODO 0x0b3 => syscall!(quotactl, frame),
+		0x0b3 => syscall!(quotactl, frame),
 		// TODO 0x0b4 => syscall!(nfsservct, frame),
 		// TODO 0x0b5 => syscall!(getpms, frame),
 		// TODO 0x0b6 => syscall!(putpms, frame),
@@ -891,7 +915,7 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x0b8 => syscall!(tuxcal, frame),
 		// TODO 0x0b9 => syscall!(securit, frame),
 		0x0ba => syscall!(gettid, frame),
-		// TODO 0x0bb => syscall!(readahead, frame),
+		0x0bb => syscall!(readahead, frame),
 		// TODO 0x0bc => syscall!(setxattr, frame),
 		// TODO 0x0bd => syscall!(lsetxattr, frame),
 		// TODO 0x0be => syscall!(fsetxattr, frame),
@@ -910,11 +934,11 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x0cb => syscall!(sched_setaffinity, frame),
 		0x0cc => syscall!(sched_getaffinity, frame),
 		0x0cd => syscall!(set_thread_area, frame),
-		// TODO 0x0ce => syscall!(io_setup, frame),
-		// TODO 0x0cf => syscall!(io_destroy, frame),
-		// TODO 0x0d0 => syscall!(io_getevents, frame),
-		// TODO 0x0d1 => syscall!(io_submit, frame),
-		// TODO 0x0d2 => syscall!(io_cancel, frame),
+		0x0ce => syscall!(io_setup, frame),
+		0x0cf => syscall!(io_destroy, frame),
+		0x0d0 => syscall!(io_getevents64, frame),
+		0x0d1 => syscall!(io_submit, frame),
+		0x0d2 => syscall!(io_cancel, frame),
 		// TODO 0x0d3 => syscall!(get_thread_are, frame),
 		// TODO 0x0d4 => syscall!(lookup_dcooki, frame),
 		// TODO 0x0d5 => syscall!(epoll_create, frame),
@@ -925,16 +949,16 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x0da => syscall!(set_tid_address, frame),
 		// TODO 0x0db => syscall!(restart_syscall, frame),
 		// TODO 0x0dc => syscall!(semtimedop, frame),
-		// TODO 0x0dd => syscall!(fadvise64, frame),
+		0x0dd => syscall!(fadvise64, frame),
 		0x0de => syscall!(timer_create, frame),
 		0x0df => syscall!(timer_settime64, frame),
-		// TODO 0x0e0 => syscall!(timer_gettime, frame),
+		0x0e0 => syscall!(timer_gettime64, frame),
 		// TODO 0x0e1 => syscall!(timer_getoverrun, frame),
 		0x0e2 => syscall!(timer_delete, frame),
-		// TODO 0x0e3 => syscall!(clock_settime, frame),
-		0x0e4 => syscall!(clock_gettime, frame),
-		// TODO 0x0e5 => syscall!(clock_getres, frame),
-		// TODO 0x0e6 => syscall!(clock_nanosleep, frame),
+		0x0e3 => syscall!(clock_settime64, frame),
+		0x0e4 => syscall!(clock_gettime64, frame),
+		0x0e5 => syscall!(clock_getres64, frame),
+		0x0e6 => syscall!(clock_nanosleep64, frame),
 		0x0e7 => syscall!(exit_group, frame),
 		// TODO 0x0e8 => syscall!(epoll_wait, frame),
 		// TODO 0x0e9 => syscall!(epoll_ctl, frame),
@@ -951,12 +975,12 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x0f4 => syscall!(mq_notify, frame),
 		// TODO 0x0f5 => syscall!(mq_getsetattr, frame),
 		// TODO 0x0f6 => syscall!(kexec_load, frame),
-		// TODO 0x0f7 => syscall!(waitid, frame),
+		0x0f7 => syscall!(waitid, frame),
 		// TODO 0x0f8 => syscall!(add_key, frame),
 		// TODO 0x0f9 => syscall!(request_key, frame),
 		// TODO 0x0fa => syscall!(keyctl, frame),
-		// TODO 0x0fb => syscall!(ioprio_set, frame),
-		// TODO 0x0fc => syscall!(ioprio_get, frame),
+		0x0fb => syscall!(ioprio_set, frame),
+		0x0fc => syscall!(ioprio_get, frame),
 		// TODO 0x0fd => syscall!(inotify_init, frame),
 		// TODO 0x0fe => syscall!(inotify_add_watch, frame),
 		// TODO 0x0ff => syscall!(inotify_rm_watch, frame),
@@ -974,14 +998,14 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x10b => syscall!(readlinkat, frame),
 		0x10c => syscall!(fchmodat, frame),
 		0x10d => syscall!(faccessat, frame),
-		0x10e => syscall!(pselect6, frame),
-		// TODO 0x10f => syscall!(ppoll, frame),
+		0x10e => syscall!(pselect6_time64, frame),
+		0x10f => syscall!(ppoll_time64, frame),
 		// TODO 0x110 => syscall!(unshare, frame),
 		// TODO 0x111 => syscall!(set_robust_list, frame),
 		// TODO 0x112 => syscall!(get_robust_list, frame),
 		// TODO 0x113 => syscall!(splice, frame),
 		// TODO 0x114 => syscall!(tee, frame),
-		// TODO 0x115 => syscall!(sync_file_range, frame),
+		0x115 => syscall!(sync_file_range, frame),
 		// TODO 0x116 => syscall!(vmsplice, frame),
 		// TODO 0x117 => syscall!(move_pages, frame),
 		0x118 => syscall!(utimensat, frame),
@@ -992,11 +1016,11 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x11d => syscall!(fallocate, frame),
 		// TODO 0x11e => syscall!(timerfd_settime, frame),
 		// TODO 0x11f => syscall!(timerfd_gettime, frame),
-		// TODO 0x120 => syscall!(accept4, frame),
+		0x120 => syscall!(accept4, frame),
 		// TODO 0x121 => syscall!(signalfd4, frame),
 		// TODO 0x122 => syscall!(eventfd2, frame),
 		// TODO 0x123 => syscall!(epoll_create1, frame),
-		// TODO 0x124 => syscall!(dup3, frame),
+		0x124 => syscall!(dup3, frame),
 		0x125 => syscall!(pipe2, frame),
 		// TODO 0x126 => syscall!(inotify_init1, frame),
 		0x127 => syscall!(preadv, frame),
@@ -1004,8 +1028,8 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x129 => syscall!(rt_tgsigqueueinfo, frame),
 		// TODO 0x12a => syscall!(perf_event_open, frame),
 		// TODO 0x12b => syscall!(recvmmsg, frame),
-		// TODO 0x12c => syscall!(fanotify_init, frame),
-		// TODO 0x12d => syscall!(fanotify_mark, frame),
+		0x12c => syscall!(fanotify_init, frame),
+		0x12d => syscall!(fanotify_mark, frame),
 		0x12e => syscall!(prlimit64, frame),
 		// TODO 0x12f => syscall!(name_to_handle_at, frame),
 		// TODO 0x130 => syscall!(open_by_handle_at, frame),
@@ -1037,7 +1061,7 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x14a => syscall!(pkey_alloc, frame),
 		// TODO 0x14b => syscall!(pkey_free, frame),
 		0x14c => syscall!(statx, frame),
-		// TODO 0x14d => syscall!(io_pgetevents, frame),
+		0x14d => syscall!(io_pgetevents_time64, frame),
 		// TODO 0x14e => syscall!(rseq, frame),
 		// TODO 0x1a8 => syscall!(pidfd_send_signal, frame),
 		// TODO 0x1a9 => syscall!(io_uring_setup, frame),
@@ -1051,13 +1075,13 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x1b1 => syscall!(fspick, frame),
 		// TODO 0x1b2 => syscall!(pidfd_open, frame),
 		// TODO 0x1b3 => syscall!(clone3, frame),
-		// TODO 0x1b4 => syscall!(close_range, frame),
+		0x1b4 => syscall!(close_range, frame),
 		// TODO 0x1b5 => syscall!(openat2, frame),
 		// TODO 0x1b6 => syscall!(pidfd_getfd, frame),
 		0x1b7 => syscall!(faccessat2, frame),
 		// TODO 0x1b8 => syscall!(process_madvise, frame),
 		// TODO 0x1b9 => syscall!(epoll_pwait2, frame),
-		// TODO 0x1ba => syscall!(mount_setattr, frame),
+		0x1ba => syscall!(mount_setattr, frame),
 		// TODO 0x1bb => syscall!(quotactl_fd, frame),
 		// TODO 0x1bc => syscall!(landlock_create_ruleset, frame),
 		// TODO 0x1bd => syscall!(landlock_add_rule, frame),
@@ -1079,6 +1103,8 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 /// Called whenever a system call is triggered.
 #[unsafe(no_mangle)]
 pub extern "C" fn syscall_handler(frame: &mut IntFrame) {
+	// The time elapsed since the last boundary has been spent in userspace
+	Process::current().cputime.account(true);
 	let id = frame.get_syscall_id();
 	#[cfg(target_arch = "x86")]
 	let res = do_syscall32(id, frame);
@@ -1089,19 +1115,14 @@ pub extern "C" fn syscall_handler(frame: &mut IntFrame) {
 		do_syscall64(id, frame)
 	};
 	frame.set_syscall_return(res);
-	// If the system call does not exist, kill the process with SIGSYS
+	// Userspace is expected to fall back on another system call, so only keep track of it
 	if unlikely(matches!(res, Err(e) if e.as_int() == ENOSYS)) {
-		let proc = Process::current();
-		#[cfg(feature = "strace")]
-		crate::println!(
-			"[strace {pid}] invalid syscall (ID: 0x{id:x})",
-			pid = proc.get_pid()
-		);
-		Process::kill(&proc, Signal::SIGSYS);
+		unimplemented::record(&Process::current(), frame.is_compat(), id);
 	}
 	// If the process has been killed, handle it
 	alter_flow(3, frame);
 	preempt_check_resched();
+	Process::current().cputime.account(false);
 }
 
 unsafe extern "C" {
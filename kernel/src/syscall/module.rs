@@ -28,12 +28,19 @@ use core::{
 	ffi::{c_int, c_uint, c_ulong},
 	hint::unlikely,
 };
-use utils::{errno, errno::EResult};
+use utils::{compress::gzip, errno, errno::EResult};
+
+/// `finit_module` flag: ignore symbol version hashes.
+const MODULE_INIT_IGNORE_MODVERSIONS: c_int = 1;
+/// `finit_module` flag: ignore the kernel version magic.
+const MODULE_INIT_IGNORE_VERMAGIC: c_int = 2;
+/// `finit_module` flag: the file is compressed.
+const MODULE_INIT_COMPRESSED_FILE: c_int = 4;
 
 pub fn init_module(
 	module_image: *mut u8,
 	len: c_ulong,
-	_param_values: UserString,
+	param_values: UserString,
 ) -> EResult<usize> {
 	let module_image = UserSlice::from_user(module_image, len as _)?;
 	if unlikely(!is_privileged()) {
@@ -42,19 +49,34 @@ pub fn init_module(
 	let image = module_image
 		.copy_from_user_vec(0)?
 		.ok_or_else(|| errno!(EFAULT))?;
-	let module = Module::load(&image)?;
+	let args = param_values
+		.copy_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
+	let module = Module::load(&image, &args)?;
 	module::add(module)?;
 	Ok(0)
 }
 
-pub fn finit_module(fd: c_int, _param_values: UserString, _flags: c_int) -> EResult<usize> {
+pub fn finit_module(fd: c_int, param_values: UserString, flags: c_int) -> EResult<usize> {
 	if unlikely(!is_privileged()) {
 		return Err(errno!(EPERM));
 	}
+	let valid_flags =
+		MODULE_INIT_IGNORE_MODVERSIONS | MODULE_INIT_IGNORE_VERMAGIC | MODULE_INIT_COMPRESSED_FILE;
+	if unlikely(flags & !valid_flags != 0) {
+		return Err(errno!(EINVAL));
+	}
 	// Read file
 	let file = fd_to_file(fd)?;
-	let image = file.read_all()?;
-	let module = Module::load(&image)?;
+	let mut image = file.read_all()?;
+	// Only gzip is supported
+	if flags & MODULE_INIT_COMPRESSED_FILE != 0 {
+		image = gzip::decompress(&image)?;
+	}
+	let args = param_values
+		.copy_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
+	let module = Module::load(&image, &args)?;
 	module::add(module)?;
 	Ok(0)
 }
@@ -65,7 +87,6 @@ pub fn delete_module(name: UserString, _flags: c_uint) -> EResult<usize> {
 		return Err(errno!(EPERM));
 	}
 	let name = name.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
-	// TODO handle dependency (don't unload a module that is required by another)
 	module::remove(&name)?;
 	Ok(0)
 }
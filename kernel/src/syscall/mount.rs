@@ -21,43 +21,217 @@
 use crate::{
 	file::{
 		FileType, fs,
+		fs::FilesystemType,
 		perm::is_privileged,
 		vfs,
-		vfs::{mountpoint, mountpoint::MountSource},
+		vfs::{
+			Resolved, mountpoint,
+			mountpoint::{MountSource, PropagationType},
+		},
+	},
+	memory::user::{UserPtr, UserSlice, UserString},
+	syscall::util::{
+		at,
+		at::{AT_EMPTY_PATH, AT_NO_AUTOMOUNT, AT_RECURSIVE, AT_SYMLINK_NOFOLLOW},
 	},
-	memory::user::{UserPtr, UserString},
 };
 use core::{
-	ffi::{c_int, c_ulong, c_void},
+	ffi::{c_int, c_uint, c_ulong},
 	hint::unlikely,
 };
-use utils::{errno, errno::EResult};
+use utils::{
+	TryClone, collections::vec::Vec, errno, errno::EResult, limits::PAGE_SIZE, ptr::arc::Arc,
+};
+
+/// Mount flag: changes the flags of an existing mountpoint.
+const MS_REMOUNT: c_ulong = 0x20;
+/// Mount flag: creates a bind mount.
+const MS_BIND: c_ulong = 0x1000;
+/// Mount flag: applies the operation to the mountpoints beneath the target too.
+const MS_REC: c_ulong = 0x4000;
+/// Mount flag: makes the mountpoint unbindable.
+const MS_UNBINDABLE: c_ulong = 1 << 17;
+/// Mount flag: makes the mountpoint private.
+const MS_PRIVATE: c_ulong = 1 << 18;
+/// Mount flag: makes the mountpoint a slave.
+const MS_SLAVE: c_ulong = 1 << 19;
+/// Mount flag: makes the mountpoint shared.
+const MS_SHARED: c_ulong = 1 << 20;
+
+/// Mount attribute: the mountpoint is read-only.
+const MOUNT_ATTR_RDONLY: u64 = 0x1;
+/// Mount attribute: the setuid and setgid bits are ignored.
+const MOUNT_ATTR_NOSUID: u64 = 0x2;
+/// Mount attribute: device files cannot be accessed.
+const MOUNT_ATTR_NODEV: u64 = 0x4;
+/// Mount attribute: files cannot be executed.
+const MOUNT_ATTR_NOEXEC: u64 = 0x8;
+/// Mask of the mount attributes setting the access time update behaviour.
+const MOUNT_ATTR__ATIME: u64 = 0x70;
+/// Mount attribute: access times are updated relative to the modification time.
+const MOUNT_ATTR_RELATIME: u64 = 0x0;
+/// Mount attribute: access times are not updated.
+const MOUNT_ATTR_NOATIME: u64 = 0x10;
+/// Mount attribute: access times are always updated.
+const MOUNT_ATTR_STRICTATIME: u64 = 0x20;
+/// Mount attribute: access times of directories are not updated.
+const MOUNT_ATTR_NODIRATIME: u64 = 0x80;
+
+/// The attributes to change on a mountpoint with `mount_setattr`.
+#[derive(Debug)]
+#[repr(C)]
+pub struct MountAttr {
+	/// The attributes to set.
+	attr_set: u64,
+	/// The attributes to clear.
+	attr_clr: u64,
+	/// The propagation type to set, if not zero.
+	propagation: u64,
+	/// The user namespace for ID-mapped mounts.
+	userns_fd: u64,
+}
+
+/// Returns the propagation type corresponding to the given `MS_*` flag.
+///
+/// If `flag` is not exactly one propagation flag, the function returns [`errno::EINVAL`].
+fn propagation_type(flag: c_ulong) -> EResult<PropagationType> {
+	match flag {
+		MS_UNBINDABLE => Ok(PropagationType::Unbindable),
+		MS_PRIVATE => Ok(PropagationType::Private),
+		MS_SLAVE => Ok(PropagationType::Slave),
+		MS_SHARED => Ok(PropagationType::Shared),
+		_ => Err(errno!(EINVAL)),
+	}
+}
+
+/// Converts the `MOUNT_ATTR_*` attributes `attr`, except for access time ones, into mount
+/// flags.
+fn attr_to_flags(attr: u64) -> u32 {
+	[
+		(MOUNT_ATTR_RDONLY, mountpoint::FLAG_RDONLY),
+		(MOUNT_ATTR_NOSUID, mountpoint::FLAG_NOSUID),
+		(MOUNT_ATTR_NODEV, mountpoint::FLAG_NODEV),
+		(MOUNT_ATTR_NOEXEC, mountpoint::FLAG_NOEXEC),
+		(MOUNT_ATTR_NODIRATIME, mountpoint::FLAG_NODIRATIME),
+	]
+	.into_iter()
+	.filter(|(a, _)| attr & a != 0)
+	.fold(0, |flags, (_, f)| flags | f)
+}
+
+/// Copies the `data` argument of `mount` from userspace.
+///
+/// Since the data may be binary, its size is unknown: up to a page is copied. Copying stops
+/// without error at the end of the mapping containing `data`.
+fn copy_mount_data(data: *mut u8) -> EResult<Vec<u8>> {
+	let mut buf = Vec::new();
+	if data.is_null() {
+		return Ok(buf);
+	}
+	buf.resize(PAGE_SIZE, 0)?;
+	// The part on the same page as the start of `data` must be accessible
+	let first = PAGE_SIZE - data as usize % PAGE_SIZE;
+	UserSlice::from_user(data, first)?.copy_from_user(0, &mut buf[..first])?;
+	let rest = UserSlice::from_user(data.wrapping_add(first), PAGE_SIZE - first)
+		.and_then(|rest| rest.copy_from_user(0, &mut buf[first..]));
+	if rest.is_err() {
+		buf.truncate(first);
+	}
+	Ok(buf)
+}
+
+/// Returns the list of filesystem types to try in order, from the `filesystemtype` argument of
+/// `mount`.
+///
+/// `name` may be `None` or `auto` to detect the type automatically, or a comma-separated list of
+/// types, in which `auto` can also appear. In the returned list, `None` stands for automatic
+/// detection.
+///
+/// Types that are not registered are skipped. If no type is left, the function returns
+/// [`errno::ENODEV`].
+fn get_fs_types(name: Option<&[u8]>) -> EResult<Vec<Option<Arc<dyn FilesystemType>>>> {
+	let mut fs_types = Vec::new();
+	let Some(name) = name else {
+		fs_types.push(None)?;
+		return Ok(fs_types);
+	};
+	for name in name.split(|c| *c == b',').filter(|name| !name.is_empty()) {
+		let fs_type = match name {
+			b"auto" => None,
+			_ => match fs::request_type(name) {
+				Some(fs_type) => Some(fs_type),
+				None => continue,
+			},
+		};
+		fs_types.push(fs_type)?;
+	}
+	if unlikely(fs_types.is_empty()) {
+		return Err(errno!(ENODEV));
+	}
+	Ok(fs_types)
+}
 
 pub fn mount(
 	source: UserString,
 	target: UserString,
 	filesystemtype: UserString,
 	mountflags: c_ulong,
-	_data: UserPtr<c_void>,
+	data: *mut u8,
 ) -> EResult<usize> {
 	if unlikely(!is_privileged()) {
 		return Err(errno!(EPERM));
 	}
+	let target = target.copy_path_from_user()?;
+	let target = vfs::get_file_from_path(&target, true)?;
+	let rec = mountflags & MS_REC != 0;
+	// Change the flags of an existing mountpoint. With `MS_BIND`, the filesystem is left untouched
+	if mountflags & MS_REMOUNT != 0 {
+		let mp = mountpoint::from_entry(&target).ok_or_else(|| errno!(EINVAL))?;
+		mountpoint::remount(&mp, mountflags as _, mountflags & MS_BIND != 0)?;
+		return Ok(0);
+	}
+	// Change the propagation type. Other arguments are ignored
+	let propagation = mountflags & (MS_UNBINDABLE | MS_PRIVATE | MS_SLAVE | MS_SHARED);
+	if propagation != 0 {
+		let ty = propagation_type(propagation)?;
+		let mp = mountpoint::from_entry(&target).ok_or_else(|| errno!(EINVAL))?;
+		mountpoint::set_propagation(&mp, ty, rec)?;
+		return Ok(0);
+	}
+	// Bind mount. The filesystem type and data are ignored
+	if mountflags & MS_BIND != 0 {
+		let source = source.copy_path_from_user()?;
+		let source = vfs::get_file_from_path(&source, true)?;
+		mountpoint::bind(&source, &target, rec)?;
+		return Ok(0);
+	}
 	// Read arguments
 	let source_slice = source.copy_from_user()?.ok_or(errno!(EFAULT))?;
 	let mount_source = MountSource::new(&source_slice)?;
-	let target = target.copy_path_from_user()?;
-	let filesystemtype_slice = filesystemtype.copy_from_user()?.ok_or(errno!(EFAULT))?;
-	let fs_type = fs::get_type(&filesystemtype_slice).ok_or(errno!(ENODEV))?;
-	// Get target file
-	let target = vfs::get_file_from_path(&target, true)?;
+	let filesystemtype = filesystemtype.copy_from_user()?;
+	let fs_types = get_fs_types(filesystemtype.as_deref())?;
 	// Check the target is a directory
 	if target.get_type()? != FileType::Directory {
 		return Err(errno!(ENOTDIR));
 	}
-	// TODO Use `data`
-	// Create mountpoint
-	mountpoint::create(mount_source, Some(fs_type), mountflags as _, Some(target))?;
+	let data = copy_mount_data(data)?;
+	// Create mountpoint, trying each type in order
+	let mut res = Err(errno!(ENODEV));
+	for fs_type in fs_types {
+		res = mountpoint::create(
+			mount_source.try_clone()?,
+			fs_type,
+			mountflags as _,
+			&data,
+			Some(target.clone()),
+		);
+		// Other errors are not caused by a wrong filesystem type
+		match &res {
+			Err(e) if matches!(e.as_int(), errno::EINVAL | errno::ENODEV) => {}
+			_ => break,
+		}
+	}
+	res?;
 	Ok(0)
 }
 
@@ -78,3 +252,67 @@ pub fn umount2(target: UserString, _flags: c_int) -> EResult<usize> {
 	mountpoint::remove(target)?;
 	Ok(0)
 }
+
+pub fn mount_setattr(
+	dirfd: c_int,
+	pathname: UserString,
+	flags: c_uint,
+	uattr: UserPtr<MountAttr>,
+	size: usize,
+) -> EResult<usize> {
+	if unlikely(!is_privileged()) {
+		return Err(errno!(EPERM));
+	}
+	let flags = flags as c_int;
+	if unlikely(
+		flags & !(AT_EMPTY_PATH | AT_RECURSIVE | AT_SYMLINK_NOFOLLOW | AT_NO_AUTOMOUNT) != 0,
+	) {
+		return Err(errno!(EINVAL));
+	}
+	if unlikely(size < size_of::<MountAttr>()) {
+		return Err(errno!(EINVAL));
+	}
+	let attr = uattr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	// ID-mapped mounts are not supported
+	let supported = MOUNT_ATTR_RDONLY
+		| MOUNT_ATTR_NOSUID
+		| MOUNT_ATTR_NODEV
+		| MOUNT_ATTR_NOEXEC
+		| MOUNT_ATTR__ATIME
+		| MOUNT_ATTR_NODIRATIME;
+	if unlikely((attr.attr_set | attr.attr_clr) & !supported != 0 || attr.userns_fd != 0) {
+		return Err(errno!(EINVAL));
+	}
+	let mut set = attr_to_flags(attr.attr_set);
+	let mut clear = attr_to_flags(attr.attr_clr);
+	// The access time behaviour can be changed only if the previous one is cleared
+	let atime = attr.attr_set & MOUNT_ATTR__ATIME;
+	if attr.attr_clr & MOUNT_ATTR__ATIME == MOUNT_ATTR__ATIME {
+		clear |=
+			mountpoint::FLAG_RELATIME | mountpoint::FLAG_NOATIME | mountpoint::FLAG_STRICTATIME;
+		set |= match atime {
+			MOUNT_ATTR_RELATIME => mountpoint::FLAG_RELATIME,
+			MOUNT_ATTR_NOATIME => mountpoint::FLAG_NOATIME,
+			MOUNT_ATTR_STRICTATIME => mountpoint::FLAG_STRICTATIME,
+			_ => return Err(errno!(EINVAL)),
+		};
+	} else if unlikely(atime != 0 || attr.attr_clr & MOUNT_ATTR__ATIME != 0) {
+		return Err(errno!(EINVAL));
+	}
+	let propagation = match attr.propagation {
+		0 => None,
+		p => Some(propagation_type(p as _)?),
+	};
+	// Get the mountpoint
+	let pathname = pathname.copy_path_from_user()?;
+	let Resolved::Found(target) = at::get_file(dirfd, &pathname, flags, false, true)? else {
+		unreachable!();
+	};
+	let mp = mountpoint::from_entry(&target).ok_or_else(|| errno!(EINVAL))?;
+	let rec = flags & AT_RECURSIVE != 0;
+	mountpoint::set_attr(&mp, set, clear, rec)?;
+	if let Some(ty) = propagation {
+		mountpoint::set_propagation(&mp, ty, rec)?;
+	}
+	Ok(0)
+}
@@ -20,8 +20,8 @@
 
 use crate::{
 	file::{
-		File, FileType, O_CLOEXEC, O_DIRECT, O_NONBLOCK, O_RDONLY, O_WRONLY, fs::float,
-		pipe::PipeBuffer,
+		File, FileType, O_CLOEXEC, O_DIRECT, O_NONBLOCK, O_RDONLY, O_WRONLY, fd::FD_CLOEXEC,
+		fs::float, pipe::PipeBuffer,
 	},
 	memory::user::UserPtr,
 	process::Process,
@@ -35,8 +35,7 @@ pub fn pipe(pipefd: UserPtr<[c_int; 2]>) -> EResult<usize> {
 	let file1 = File::open_floating(pipe, O_WRONLY)?;
 	let (fd0_id, fd1_id) = Process::current()
 		.file_descriptors()
-		.lock()
-		.create_fd_pair(file0, file1)?;
+		.create_fd_pair(0, file0, file1)?;
 	pipefd.copy_to_user(&[fd0_id as _, fd1_id as _])?;
 	Ok(0)
 }
@@ -47,12 +46,17 @@ pub fn pipe2(pipefd: UserPtr<[c_int; 2]>, flags: c_int) -> EResult<usize> {
 		return Err(errno!(EINVAL));
 	}
 	let pipe = float::get_entry(PipeBuffer::new()?, FileType::Fifo)?;
-	let file0 = File::open_floating(pipe.clone(), flags | O_RDONLY)?;
-	let file1 = File::open_floating(pipe, flags | O_WRONLY)?;
+	let file_flags = flags & !O_CLOEXEC;
+	let file0 = File::open_floating(pipe.clone(), file_flags | O_RDONLY)?;
+	let file1 = File::open_floating(pipe, file_flags | O_WRONLY)?;
+	let fd_flags = if flags & O_CLOEXEC != 0 {
+		FD_CLOEXEC
+	} else {
+		0
+	};
 	let (fd0_id, fd1_id) = Process::current()
 		.file_descriptors()
-		.lock()
-		.create_fd_pair(file0, file1)?;
+		.create_fd_pair(fd_flags, file0, file1)?;
 	pipefd.copy_to_user(&[fd0_id as _, fd1_id as _])?;
 	Ok(0)
 }
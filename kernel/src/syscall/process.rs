@@ -22,19 +22,30 @@
 use crate::{arch::x86, syscall::FromSyscallArg};
 use crate::{
 	arch::x86::{cli, gdt, idt::IntFrame},
-	file::perm::{can_kill, is_privileged},
-	memory::user::{UserPtr, UserSlice},
+	device::io_queue::{
+		IOPRIO_CLASS_BE, IOPRIO_CLASS_IDLE, IOPRIO_CLASS_NONE, IOPRIO_CLASS_RT, IOPRIO_NR_LEVELS,
+		effective_ioprio, ioprio_class, ioprio_data,
+	},
+	file::perm::{Uid, can_inspect, can_kill, is_privileged},
+	memory::{
+		VirtAddr,
+		user::{UserPtr, UserSlice},
+	},
 	process,
 	process::{
-		ForkOptions, PROCESS_FLAG_LINUX, Process, State,
+		ForkOptions, PROCESS_FLAG_LINUX, PROCESS_FLAG_NOT_DUMPABLE, PROCESSES, Process, State,
+		mem_space::bound_check,
 		pid::Pid,
+		rlimit::{RLIMIT_NLIMITS, RLimit},
 		rusage::Rusage,
 		scheduler::{
 			cpu::{CPU, iter_online},
-			defer, schedule,
+			defer, schedule, timeslice,
 		},
 		user_desc::UserDesc,
 	},
+	time::unit::{TimeUnit, Timespec, Timespec32},
+	tty,
 };
 use core::{
 	ffi::{c_int, c_ulong, c_void},
@@ -46,7 +57,13 @@ use core::{
 		fence,
 	},
 };
-use utils::{errno, errno::EResult};
+use utils::{
+	collections::vec::Vec,
+	errno,
+	errno::{CollectResult, EResult},
+	ptr::arc::Arc,
+	vec,
+};
 
 /// TODO doc
 pub const CLONE_IO: c_ulong = -0x80000000 as _;
@@ -115,6 +132,21 @@ const ARCH_GET_CPUID: c_int = 0x1011;
 /// Enable or disable cpuid instruction.
 const ARCH_SET_CPUID: c_int = 0x1012;
 
+/// `prctl` command: tell whether the process produces core dumps
+const PR_GET_DUMPABLE: c_int = 3;
+/// `prctl` command: set whether the process produces core dumps
+const PR_SET_DUMPABLE: c_int = 4;
+/// `prctl` command: modify the description of the process's memory map
+const PR_SET_MM: c_int = 35;
+/// [`PR_SET_MM`] subcommand: set the address of the beginning of the command line
+const PR_SET_MM_ARG_START: c_int = 8;
+/// [`PR_SET_MM`] subcommand: set the address of the end of the command line
+const PR_SET_MM_ARG_END: c_int = 9;
+/// [`PR_SET_MM`] subcommand: set the address of the beginning of the environment
+const PR_SET_MM_ENV_START: c_int = 10;
+/// [`PR_SET_MM`] subcommand: set the address of the end of the environment
+const PR_SET_MM_ENV_END: c_int = 11;
+
 // `prctl` command: Maestro-specific subcommands
 const PR_MAESTRO: c_int = 0x4d535452;
 // [`PR_MAESTRO`] subcommand: pretend to be Linux
@@ -125,48 +157,6 @@ const RUSAGE_SELF: i32 = 0;
 /// Returns the resource usage of the process's children.
 const RUSAGE_CHILDREN: i32 = -1;
 
-/// The amount of seconds of CPU time the process can consume.
-const RLIMIT_CPU: i32 = 0;
-/// The maximum size of a file the process may create, in bytes.
-const RLIMIT_FSIZE: i32 = 1;
-/// The maximum size of the process's data segment in bytes, rounded down to the
-/// page size.
-const RLIMIT_DATA: i32 = 2;
-/// The maximum size of the process stack, in bytes.
-const RLIMIT_STACK: i32 = 3;
-/// The maximum size of a kernel file the process may dump in bytes.
-const RLIMIT_CORE: i32 = 4;
-/// A limit on the process's resident set (the number of virtual pages resident in RAM).
-const RLIMIT_RSS: i32 = 5;
-/// The limit on the number of threads for the real user ID of the calling process.
-const RLIMIT_NPROC: i32 = 6;
-/// A value one greater than the maximum number of file descriptors that can be
-/// open by the process.
-const RLIMIT_NOFILE: i32 = 7;
-/// The maximum number of butes of memory that may be locked into RAM.
-const RLIMIT_MEMLOCK: i32 = 8;
-/// The maximum size of the memory space in bytes, rounded down to the page
-/// size.
-const RLIMIT_AS: i32 = 9;
-/// The limit on the combined number of flock(2) locks and fcntl(2) leases the
-/// process may establish.
-const RLIMIT_LOCKS: i32 = 10;
-/// The limit on the number of signals that may be queued for the real user ID of the calling
-/// process.
-const RLIMIT_SIGPENDING: i32 = 11;
-/// The limit on the number of bytes that can be allocated for POSIX message queues for the real
-/// user IF of the calling process.
-const RLIMIT_MSGQUEUE: i32 = 12;
-/// The ceiling to which the process's nice value can be raised.
-const RLIMIT_NICE: i32 = 13;
-/// The ceiling on the real-time priority that may be set for this process.
-const RLIMIT_RTPRIO: i32 = 14;
-/// The limit (in microseconds) on the amount of CPU that a process scheduled under a real-time
-/// scheduling policy may consume without masking a blocking system call.
-const RLIMIT_RTTIME: i32 = 15;
-/// TODO doc
-const RLIMIT_NLIMITS: i32 = 16;
-
 /// Process priority type: Process
 const PRIO_PROCESS: c_int = 0;
 /// Process priority type: Process group
@@ -174,6 +164,13 @@ const PRIO_PGRP: c_int = 1;
 /// Process priority type: User
 const PRIO_USER: c_int = 2;
 
+/// I/O priority target type: Process
+const IOPRIO_WHO_PROCESS: c_int = 1;
+/// I/O priority target type: Process group
+const IOPRIO_WHO_PGRP: c_int = 2;
+/// I/O priority target type: User
+const IOPRIO_WHO_USER: c_int = 3;
+
 /// `membarrier`: Query the supported commands. Returns a bitmask of supported commands.
 const MEMBARRIER_CMD_QUERY: c_int = 0;
 /// `membarrier`: Issue a memory barrier on all running threads in the system.
@@ -235,6 +232,17 @@ pub fn setpgid(mut pid: Pid, mut pgid: Pid) -> EResult<usize> {
 	Ok(0)
 }
 
+pub fn vhangup() -> EResult<usize> {
+	if !is_privileged() {
+		return Err(errno!(EPERM));
+	}
+	let vt = Process::current().ctty.load(Acquire);
+	if let Some(tty) = tty::get(vt) {
+		tty.hangup();
+	}
+	Ok(0)
+}
+
 pub fn gettid() -> EResult<usize> {
 	Ok(Process::current().tid as _)
 }
@@ -260,11 +268,19 @@ pub fn compat_clone(
 			share_memory: flags & CLONE_VM != 0,
 			share_fd: flags & CLONE_FILES != 0,
 			share_sighand: flags & CLONE_SIGHAND != 0,
+			share_fs: flags & CLONE_FS != 0,
+			vfork: flags & CLONE_VFORK != 0,
 		},
 	)?;
 	if flags & CLONE_VFORK != 0 {
-		while !child.is_vfork_done() {
+		// Wait until the child executes a program or exits. The state is changed before checking
+		// so that a wakeup happening in between is not lost
+		loop {
 			process::set_state(State::Sleeping);
+			if child.is_vfork_done() {
+				process::cancel_sleep();
+				break;
+			}
 			schedule();
 		}
 	}
@@ -387,6 +403,51 @@ pub fn prctl(op: c_int, arg0: usize, arg1: usize, arg2: usize, arg3: usize) -> E
 			}
 			Ok(0)
 		}
+		PR_GET_DUMPABLE => {
+			let dumpable = proc.flags.load(Acquire) & PROCESS_FLAG_NOT_DUMPABLE == 0;
+			Ok(dumpable as _)
+		}
+		PR_SET_DUMPABLE => match arg0 {
+			0 => {
+				proc.flags.fetch_or(PROCESS_FLAG_NOT_DUMPABLE, Release);
+				Ok(0)
+			}
+			1 => {
+				proc.flags.fetch_and(!PROCESS_FLAG_NOT_DUMPABLE, Release);
+				Ok(0)
+			}
+			_ => Err(errno!(EINVAL)),
+		},
+		PR_SET_MM => {
+			if unlikely(arg2 != 0 || arg3 != 0) {
+				return Err(errno!(EINVAL));
+			}
+			if unlikely(!is_privileged()) {
+				return Err(errno!(EPERM));
+			}
+			let addr = VirtAddr(arg1);
+			if unlikely(!bound_check(addr.0, 0)) {
+				return Err(errno!(EINVAL));
+			}
+			let mem_space = proc
+				.mem_space_opt()
+				.as_ref()
+				.ok_or_else(|| errno!(EINVAL))?;
+			let mut args = mem_space.exe_info.args.lock();
+			let mut new = *args;
+			match arg0 as c_int {
+				PR_SET_MM_ARG_START => new.argv_begin = addr,
+				PR_SET_MM_ARG_END => new.argv_end = addr,
+				PR_SET_MM_ENV_START => new.envp_begin = addr,
+				PR_SET_MM_ENV_END => new.envp_end = addr,
+				_ => return Err(errno!(EINVAL)),
+			}
+			if unlikely(new.argv_begin > new.argv_end || new.envp_begin > new.envp_end) {
+				return Err(errno!(EINVAL));
+			}
+			*args = new;
+			Ok(0)
+		}
 		_ => Err(errno!(EINVAL)),
 	}
 }
@@ -394,10 +455,14 @@ pub fn prctl(op: c_int, arg0: usize, arg1: usize, arg2: usize, arg3: usize) -> E
 pub fn getrusage(who: c_int, usage: UserPtr<Rusage>) -> EResult<usize> {
 	let proc = Process::current();
 	let rusage = match who {
-		RUSAGE_SELF => proc.rusage.lock().clone(),
+		RUSAGE_SELF => {
+			let (utime, stime) = proc.cputime.get();
+			proc.rusage.lock().clone().with_cputime(utime, stime)
+		}
 		RUSAGE_CHILDREN => {
-			// TODO Return resources of terminated children
-			Rusage::default()
+			// TODO Return other resources of terminated children
+			let (utime, stime) = proc.cputime.children();
+			Rusage::default().with_cputime(utime, stime)
 		}
 		_ => return Err(errno!(EINVAL)),
 	};
@@ -405,50 +470,44 @@ pub fn getrusage(who: c_int, usage: UserPtr<Rusage>) -> EResult<usize> {
 	Ok(0)
 }
 
-/// A resource limit.
-#[repr(C)]
-#[derive(Debug)]
-pub struct RLimit {
-	/// Soft limit
-	rlim_cur: u64,
-	/// Hard limit (ceiling for [`rlim_cur`])
-	rlim_max: u64,
-}
-
 pub fn prlimit64(
 	pid: Pid,
 	resource: c_int,
-	_new_limit: UserPtr<RLimit>,
-	_old_limit: UserPtr<RLimit>,
+	new_limit: UserPtr<RLimit>,
+	old_limit: UserPtr<RLimit>,
 ) -> EResult<usize> {
-	// The target process. If None, the current process is the target
-	let _target_proc = if pid != 0 {
-		// TODO Check permission
-		Some(Process::get_by_pid(pid).ok_or_else(|| errno!(ESRCH))?)
+	let resource = usize::try_from(resource)
+		.ok()
+		.filter(|r| *r < RLIMIT_NLIMITS)
+		.ok_or_else(|| errno!(EINVAL))?;
+	let new_limit = new_limit.copy_from_user()?;
+	if let Some(new) = &new_limit
+		&& unlikely(new.rlim_cur > new.rlim_max)
+	{
+		return Err(errno!(EINVAL));
+	}
+	let target = if pid != 0 {
+		let proc = Process::get_by_pid(pid).ok_or_else(|| errno!(ESRCH))?;
+		if unlikely(!can_inspect(&proc)) {
+			return Err(errno!(EPERM));
+		}
+		proc
 	} else {
-		None
+		Process::current()
 	};
-	// TODO Implement all
-	match resource {
-		RLIMIT_CPU => {}
-		RLIMIT_FSIZE => {}
-		RLIMIT_DATA => {}
-		RLIMIT_STACK => {}
-		RLIMIT_CORE => {}
-		RLIMIT_RSS => {}
-		RLIMIT_NPROC => {}
-		RLIMIT_NOFILE => {}
-		RLIMIT_MEMLOCK => {}
-		RLIMIT_AS => {}
-		RLIMIT_LOCKS => {}
-		RLIMIT_SIGPENDING => {}
-		RLIMIT_MSGQUEUE => {}
-		RLIMIT_NICE => {}
-		RLIMIT_RTPRIO => {}
-		RLIMIT_RTTIME => {}
-		RLIMIT_NLIMITS => {}
-		_ => return Err(errno!(EINVAL)),
-	}
+	let old = {
+		let mut limits = target.rlimits.lock();
+		let old = limits.0[resource];
+		if let Some(new) = new_limit {
+			// Only a privileged process may raise the hard limit
+			if unlikely(new.rlim_max > old.rlim_max && !is_privileged()) {
+				return Err(errno!(EPERM));
+			}
+			limits.0[resource] = new;
+		}
+		old
+	};
+	old_limit.copy_to_user(&old)?;
 	Ok(0)
 }
 
@@ -508,6 +567,79 @@ pub fn setpriority(which: c_int, who: Pid, prio: c_int) -> EResult<usize> {
 	}
 }
 
+/// Returns the processes targeted by an `ioprio_get` or `ioprio_set` call.
+fn ioprio_targets(which: c_int, who: c_int) -> EResult<Vec<Arc<Process>>> {
+	let cur = Process::current();
+	let mut procs = match which {
+		IOPRIO_WHO_PROCESS => {
+			let proc = if who != 0 {
+				Process::get_by_pid(who as _).ok_or_else(|| errno!(ESRCH))?
+			} else {
+				cur
+			};
+			return Ok(vec![proc]?);
+		}
+		IOPRIO_WHO_PGRP | IOPRIO_WHO_USER => {
+			PROCESSES
+				.read()
+				.iter()
+				.map(|(_, proc)| proc.clone())
+				.collect::<CollectResult<Vec<_>>>()
+				.0?
+		}
+		_ => return Err(errno!(EINVAL)),
+	};
+	if which == IOPRIO_WHO_PGRP {
+		let pgid = if who != 0 { who as Pid } else { cur.get_pgid() };
+		procs.retain(|proc| proc.get_pgid() == pgid);
+	} else {
+		let uid = if who != 0 {
+			who as Uid
+		} else {
+			cur.cred().ap.uid
+		};
+		procs.retain(|proc| proc.cred().ap.uid == uid);
+	}
+	if unlikely(procs.is_empty()) {
+		return Err(errno!(ESRCH));
+	}
+	Ok(procs)
+}
+
+pub fn ioprio_get(which: c_int, who: c_int) -> EResult<usize> {
+	let procs = ioprio_targets(which, who)?;
+	// Return the highest priority among targets. Lower values have higher priority
+	let prio = procs
+		.iter()
+		.map(|proc| effective_ioprio(proc.ioprio.load(Acquire), proc.nice.load(Acquire)))
+		.min()
+		.unwrap_or_default();
+	Ok(prio as _)
+}
+
+pub fn ioprio_set(which: c_int, who: c_int, ioprio: c_int) -> EResult<usize> {
+	let ioprio: u16 = ioprio.try_into().map_err(|_| errno!(EINVAL))?;
+	let data = ioprio_data(ioprio);
+	match ioprio_class(ioprio) {
+		IOPRIO_CLASS_NONE if data != 0 => return Err(errno!(EINVAL)),
+		IOPRIO_CLASS_RT | IOPRIO_CLASS_BE if data >= IOPRIO_NR_LEVELS => {
+			return Err(errno!(EINVAL));
+		}
+		IOPRIO_CLASS_RT if !is_privileged() => return Err(errno!(EPERM)),
+		IOPRIO_CLASS_NONE..=IOPRIO_CLASS_IDLE => {}
+		_ => return Err(errno!(EINVAL)),
+	}
+	let procs = ioprio_targets(which, who)?;
+	// Check permission
+	if unlikely(!procs.iter().all(|proc| can_kill(proc))) {
+		return Err(errno!(EPERM));
+	}
+	for proc in procs {
+		proc.ioprio.store(ioprio, Release);
+	}
+	Ok(0)
+}
+
 pub fn sched_getaffinity(pid: Pid, cpusetsize: usize, mask: *mut AtomicUsize) -> EResult<usize> {
 	// Get process
 	let proc = if pid == 0 {
@@ -542,16 +674,17 @@ fn is_valid_affinity_mask(mask: &[usize]) -> bool {
 
 pub fn sched_setaffinity(pid: Pid, cpusetsize: usize, mask: *mut usize) -> EResult<usize> {
 	// Get process
-	let src_euid = Process::current().fs.lock().ap.euid;
+	let src_cred = Process::current().cred();
 	let dst = if pid == 0 {
 		Process::current()
 	} else {
 		Process::get_by_pid(pid).ok_or_else(|| errno!(ESRCH))?
 	};
 	// Check permission
-	if !is_privileged() {
-		let fs = dst.fs.lock();
-		if unlikely(src_euid != fs.ap.uid && src_euid != fs.ap.euid) {
+	if !src_cred.is_privileged() {
+		let dst_ap = dst.cred().ap;
+		let src_euid = src_cred.ap.euid;
+		if unlikely(src_euid != dst_ap.uid && src_euid != dst_ap.euid) {
 			return Err(errno!(EPERM));
 		}
 	}
@@ -575,6 +708,25 @@ pub fn sched_yield() -> EResult<usize> {
 	Ok(0)
 }
 
+/// Common implementation of `sched_rr_get_interval`, parameterized over the timespec ABI.
+fn do_sched_rr_get_interval<T: TimeUnit>(pid: Pid, tp: UserPtr<T>) -> EResult<usize> {
+	if pid != 0 && Process::get_by_pid(pid).is_none() {
+		return Err(errno!(ESRCH));
+	}
+	tp.copy_to_user(&T::from_nano(timeslice() as _))?;
+	Ok(0)
+}
+
+/// 32-bit ABI: the interval uses 32-bit `time_t` (`Timespec32`).
+pub fn sched_rr_get_interval(pid: Pid, tp: UserPtr<Timespec32>) -> EResult<usize> {
+	do_sched_rr_get_interval(pid, tp)
+}
+
+/// 64-bit ABI: the interval uses 64-bit `time_t` (`Timespec`).
+pub fn sched_rr_get_interval_time64(pid: Pid, tp: UserPtr<Timespec>) -> EResult<usize> {
+	do_sched_rr_get_interval(pid, tp)
+}
+
 /// Exits the current process.
 ///
 /// Arguments:
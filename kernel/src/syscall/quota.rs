@@ -0,0 +1,277 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Disk quotas management system call.
+
+use crate::{
+	device::DeviceID,
+	file::{
+		File, FileType, O_RDWR,
+		fs::Filesystem,
+		perm::is_privileged,
+		quota::{Dquot, Quota, QuotaType},
+		vfs,
+		vfs::mountpoint::FILESYSTEMS,
+	},
+	memory::user::{UserPtr, UserString},
+	process::Process,
+};
+use core::{
+	ffi::{c_int, c_void},
+	hint::unlikely,
+	ptr::NonNull,
+};
+use utils::{errno, errno::EResult, ptr::arc::Arc};
+
+/// The shift of the subcommand in the command.
+const SUBCMDSHIFT: u32 = 8;
+/// The mask of the quota type in the command.
+const SUBCMDMASK: u32 = 0xff;
+
+/// Subcommand: writes quotas back to the disk.
+const Q_SYNC: u32 = 0x800001;
+/// Subcommand: enables quotas.
+const Q_QUOTAON: u32 = 0x800002;
+/// Subcommand: disables quotas.
+const Q_QUOTAOFF: u32 = 0x800003;
+/// Subcommand: returns the format of quota files.
+const Q_GETFMT: u32 = 0x800004;
+/// Subcommand: returns the settings of a quota type.
+const Q_GETINFO: u32 = 0x800005;
+/// Subcommand: changes the settings of a quota type.
+const Q_SETINFO: u32 = 0x800006;
+/// Subcommand: returns the quotas of an ID.
+const Q_GETQUOTA: u32 = 0x800007;
+/// Subcommand: changes the quotas of an ID.
+const Q_SETQUOTA: u32 = 0x800008;
+
+/// [`IfDqblk`] field: space limits.
+const QIF_BLIMITS: u32 = 1;
+/// [`IfDqblk`] field: used space.
+const QIF_SPACE: u32 = 2;
+/// [`IfDqblk`] field: inodes limits.
+const QIF_ILIMITS: u32 = 4;
+/// [`IfDqblk`] field: used inodes.
+const QIF_INODES: u32 = 8;
+/// [`IfDqblk`] field: space grace time.
+const QIF_BTIME: u32 = 16;
+/// [`IfDqblk`] field: inodes grace time.
+const QIF_ITIME: u32 = 32;
+/// [`IfDqblk`]: all fields.
+const QIF_ALL: u32 = QIF_BLIMITS | QIF_SPACE | QIF_ILIMITS | QIF_INODES | QIF_BTIME | QIF_ITIME;
+
+/// [`IfDqinfo`] field: space grace period.
+const IIF_BGRACE: u32 = 1;
+/// [`IfDqinfo`] field: inodes grace period.
+const IIF_IGRACE: u32 = 2;
+/// [`IfDqinfo`] field: flags.
+const IIF_FLAGS: u32 = 4;
+
+/// The quotas of an ID, as exchanged with userspace.
+///
+/// The structure is packed so that it has the same layout on 32 and 64 bits.
+#[derive(Debug)]
+#[repr(C, packed(4))]
+pub struct IfDqblk {
+	/// Hard limit of space, in kilobytes.
+	dqb_bhardlimit: u64,
+	/// Soft limit of space, in kilobytes.
+	dqb_bsoftlimit: u64,
+	/// Used space, in bytes.
+	dqb_curspace: u64,
+	/// Hard limit of inodes.
+	dqb_ihardlimit: u64,
+	/// Soft limit of inodes.
+	dqb_isoftlimit: u64,
+	/// Used inodes.
+	dqb_curinodes: u64,
+	/// Time at which the space soft limit becomes enforced.
+	dqb_btime: u64,
+	/// Time at which the inodes soft limit becomes enforced.
+	dqb_itime: u64,
+	/// The set of `QIF_*` flags telling which fields are valid.
+	dqb_valid: u32,
+}
+
+/// The settings of a quota type, as exchanged with userspace.
+#[derive(Debug)]
+#[repr(C)]
+pub struct IfDqinfo {
+	/// Space grace period, in seconds.
+	dqi_bgrace: u64,
+	/// Inodes grace period, in seconds.
+	dqi_igrace: u64,
+	/// Format-specific flags.
+	dqi_flags: u32,
+	/// The set of `IIF_*` flags telling which fields are valid.
+	dqi_valid: u32,
+}
+
+/// Returns the mounted filesystem located on the block device at `path`.
+fn get_fs(path: UserString) -> EResult<Arc<Filesystem>> {
+	let path = path.copy_path_from_user()?;
+	let stat = vfs::get_file_from_path(&path, true)?.stat();
+	if unlikely(stat.get_type() != Some(FileType::BlockDevice)) {
+		return Err(errno!(ENOTBLK));
+	}
+	let id = DeviceID {
+		major: stat.dev_major,
+		minor: stat.dev_minor,
+	};
+	FILESYSTEMS
+		.lock()
+		.get(&id)
+		.cloned()
+		.ok_or_else(|| errno!(ENODEV))
+}
+
+/// Enables quotas of type `ty` on `fs`, stored in the file at `path` with the format `format`.
+fn quota_on(fs: &Filesystem, ty: QuotaType, format: u32, path: UserString) -> EResult<()> {
+	let quota = fs.ops.quota().ok_or_else(|| errno!(ENOSYS))?;
+	let path = path.copy_path_from_user()?;
+	let ent = vfs::get_file_from_path(&path, true)?;
+	// The quota file must be on the filesystem itself
+	if unlikely(!core::ptr::eq(&*ent.node().fs, fs)) {
+		return Err(errno!(EXDEV));
+	}
+	if unlikely(ent.get_type()? != FileType::Regular) {
+		return Err(errno!(EACCES));
+	}
+	// The quota file must not prevent remounting the filesystem read-only
+	let file = File::open_internal(ent, O_RDWR)?;
+	quota.enable(ty, file, format)
+}
+
+/// Returns the quotas of `fs`.
+///
+/// If the filesystem does not support quotas, the function returns [`errno::ENOSYS`].
+fn get_quota(fs: &Filesystem) -> EResult<&Quota> {
+	fs.ops.quota().ok_or_else(|| errno!(ENOSYS))
+}
+
+pub fn quotactl(cmd: c_int, special: UserString, id: c_int, addr: *mut c_void) -> EResult<usize> {
+	let cmd = cmd as u32;
+	let subcmd = cmd >> SUBCMDSHIFT;
+	let ty = QuotaType::from_id(cmd & SUBCMDMASK).ok_or_else(|| errno!(EINVAL))?;
+	// Check permissions. Anyone can read their own quotas
+	let own = {
+		let cred = Process::current().cred();
+		match ty {
+			QuotaType::User => cred.ap.euid as c_int == id,
+			QuotaType::Group => cred.ap.egid as c_int == id,
+		}
+	};
+	let allowed = match subcmd {
+		Q_SYNC | Q_GETFMT | Q_GETINFO => true,
+		Q_GETQUOTA => own,
+		_ => false,
+	};
+	if unlikely(!allowed && !is_privileged()) {
+		return Err(errno!(EPERM));
+	}
+	// Without a device, all filesystems are synchronized
+	if subcmd == Q_SYNC && special.0.is_none() {
+		let filesystems = FILESYSTEMS.lock();
+		for (_, fs) in filesystems.iter() {
+			if let Some(quota) = fs.ops.quota() {
+				quota.sync()?;
+			}
+		}
+		return Ok(0);
+	}
+	let fs = get_fs(special)?;
+	let id = id as u32;
+	match subcmd {
+		Q_SYNC => get_quota(&fs)?.sync()?,
+		Q_QUOTAON => quota_on(&fs, ty, id, UserString(NonNull::new(addr.cast())))?,
+		Q_QUOTAOFF => get_quota(&fs)?.disable(ty)?,
+		Q_GETFMT => {
+			let format = get_quota(&fs)?.format(ty)?;
+			UserPtr::<u32>(NonNull::new(addr.cast())).copy_to_user(&format)?;
+		}
+		Q_GETINFO => {
+			let info = get_quota(&fs)?.get_info(ty)?;
+			UserPtr(NonNull::new(addr.cast())).copy_to_user(&IfDqinfo {
+				dqi_bgrace: info.bgrace,
+				dqi_igrace: info.igrace,
+				dqi_flags: info.flags,
+				dqi_valid: IIF_BGRACE | IIF_IGRACE | IIF_FLAGS,
+			})?;
+		}
+		Q_SETINFO => {
+			let new = UserPtr::<IfDqinfo>(NonNull::new(addr.cast()))
+				.copy_from_user()?
+				.ok_or_else(|| errno!(EFAULT))?;
+			get_quota(&fs)?.set_info(ty, |info| {
+				if new.dqi_valid & IIF_BGRACE != 0 {
+					info.bgrace = new.dqi_bgrace;
+				}
+				if new.dqi_valid & IIF_IGRACE != 0 {
+					info.igrace = new.dqi_igrace;
+				}
+				if new.dqi_valid & IIF_FLAGS != 0 {
+					info.flags = new.dqi_flags;
+				}
+			})?;
+		}
+		Q_GETQUOTA => {
+			let dquot = get_quota(&fs)?.get(ty, id)?;
+			UserPtr(NonNull::new(addr.cast())).copy_to_user(&IfDqblk {
+				dqb_bhardlimit: dquot.bhardlimit,
+				dqb_bsoftlimit: dquot.bsoftlimit,
+				dqb_curspace: dquot.curspace,
+				dqb_ihardlimit: dquot.ihardlimit,
+				dqb_isoftlimit: dquot.isoftlimit,
+				dqb_curinodes: dquot.curinodes,
+				dqb_btime: dquot.btime,
+				dqb_itime: dquot.itime,
+				dqb_valid: QIF_ALL,
+			})?;
+		}
+		Q_SETQUOTA => {
+			let new = UserPtr::<IfDqblk>(NonNull::new(addr.cast()))
+				.copy_from_user()?
+				.ok_or_else(|| errno!(EFAULT))?;
+			get_quota(&fs)?.set(ty, id, |dquot: &mut Dquot| {
+				let valid = new.dqb_valid;
+				if valid & QIF_BLIMITS != 0 {
+					dquot.bhardlimit = new.dqb_bhardlimit;
+					dquot.bsoftlimit = new.dqb_bsoftlimit;
+				}
+				if valid & QIF_SPACE != 0 {
+					dquot.curspace = new.dqb_curspace;
+				}
+				if valid & QIF_ILIMITS != 0 {
+					dquot.ihardlimit = new.dqb_ihardlimit;
+					dquot.isoftlimit = new.dqb_isoftlimit;
+				}
+				if valid & QIF_INODES != 0 {
+					dquot.curinodes = new.dqb_curinodes;
+				}
+				if valid & QIF_BTIME != 0 {
+					dquot.btime = new.dqb_btime;
+				}
+				if valid & QIF_ITIME != 0 {
+					dquot.itime = new.dqb_itime;
+				}
+			})?;
+		}
+		_ => return Err(errno!(EINVAL)),
+	}
+	Ok(0)
+}
@@ -21,17 +21,22 @@
 
 use crate::{
 	memory::user::{UserPtr, UserSlice},
-	process::{Process, scheduler::schedule},
+	process,
+	process::{Process, State, scheduler::schedule, signal::SigSet},
+	syscall::FromSyscallArg,
 	time::{
-		clock::{Clock, current_time_ms, current_time_ns},
-		unit::{TimeUnit, Timespec, Timestamp, Timeval},
+		clock::Clock,
+		timeout::Timeout,
+		unit::{TimeUnit, Timespec, Timespec32, Timeval, Timeval32},
 	},
 };
 use core::{
 	cmp::min,
 	ffi::{c_int, c_long},
+	fmt,
+	hint::unlikely,
 };
-use utils::{errno, errno::EResult};
+use utils::{collections::vec::Vec, errno, errno::EResult};
 
 /// The number of file descriptors in FDSet.
 pub const FD_SETSIZE: usize = 1024;
@@ -50,9 +55,8 @@ impl FDSet {
 		if fd as usize >= FD_SETSIZE {
 			return false;
 		}
-		// TODO Check correctness
 		let i = (fd as usize) / c_long::BITS as usize;
-		self.fds_bits[i] >> (fd % c_long::BITS) != 0
+		(self.fds_bits[i] >> (fd % c_long::BITS)) & 1 != 0
 	}
 
 	/// Sets or clears the bit for file descriptor `fd`.
@@ -74,27 +78,18 @@ impl FDSet {
 /// - `readfds` is the bitfield of fds to check for read operations.
 /// - `writefds` is the bitfield of fds to check for write operations.
 /// - `exceptfds` is the bitfield of fds to check for exceptional conditions.
-/// - `timeout` is the timeout after which the syscall returns.
-/// - `sigmask` TODO
+/// - `timeout` is the timeout after which the syscall returns. The remaining time is written back
+///   to it.
 pub fn do_select<T: TimeUnit>(
 	nfds: u32,
 	readfds: UserPtr<FDSet>,
 	writefds: UserPtr<FDSet>,
 	exceptfds: UserPtr<FDSet>,
 	timeout: UserPtr<T>,
-	_sigmask: Option<*mut u8>,
 ) -> EResult<usize> {
 	let proc = Process::current();
-	// Get timeout
-	let end_ts = timeout
-		.copy_from_user()?
-		.map(|t| {
-			let ts = current_time_ns(Clock::Monotonic);
-			ts.checked_add(t.to_nano()).ok_or_else(|| errno!(EINVAL))
-		})
-		.transpose()?;
-	// Tells whether the syscall immediately returns
-	let polling = end_ts.map(|ts| ts == 0).unwrap_or(false);
+	let to = Timeout::from_user(timeout, Clock::Monotonic, false)?;
+	let _timer = to.timer()?;
 	// Read
 	let mut readfds_set = readfds.copy_from_user()?;
 	let mut writefds_set = writefds.copy_from_user()?;
@@ -119,34 +114,35 @@ pub fn do_select<T: TimeUnit>(
 			// Build event mask
 			let mut mask = 0;
 			if read {
-				mask |= POLLIN;
+				mask |= POLLIN_SET;
 			}
 			if write {
-				mask |= POLLOUT;
+				mask |= POLLOUT_SET;
 			}
 			if except {
-				mask |= POLLPRI;
+				mask |= POLLEX_SET;
 			}
 			if mask != 0 {
 				all_zeros = false;
 			}
 			// Poll file
 			let result = {
-				let fds_mutex = proc.file_descriptors();
-				let fds = fds_mutex.lock();
-				let Ok(fd) = fds.get_fd(fd_id as _) else {
+				let file = proc
+					.file_descriptors()
+					.get_fd(fd_id as _)
+					.map(|fd| fd.get_file().clone());
+				let Ok(file) = file else {
 					if mask != 0 {
 						return Err(errno!(EBADF));
 					}
 					continue;
 				};
-				let file = fd.get_file();
-				file.ops.poll(file, mask)?
+				file.ops.poll(&file, mask)?
 			};
 			// Set results
-			let read = read && result & POLLIN != 0;
-			let write = write && result & POLLOUT != 0;
-			let except = except && result & POLLPRI != 0;
+			let read = read && result & POLLIN_SET != 0;
+			let write = write && result & POLLOUT_SET != 0;
+			let except = except && result & POLLEX_SET != 0;
 			if let Some(fds) = &mut readfds_set {
 				fds.set(fd_id, read);
 			}
@@ -159,18 +155,24 @@ pub fn do_select<T: TimeUnit>(
 			events_count += read as usize + write as usize + except as usize;
 		}
 		// If one or more events occurred, return
-		if all_zeros || polling || events_count > 0 {
-			break events_count;
+		if events_count > 0 {
+			break Ok(events_count);
 		}
-		if let Some(end_ts) = end_ts {
-			// On timeout, return 0
-			if current_time_ns(Clock::Monotonic) >= end_ts {
-				break 0;
-			}
+		if to.has_expired() {
+			break Ok(0);
+		}
+		if unlikely(proc.has_pending_signal()) {
+			break Err(errno!(EINTR));
+		}
+		// Without any file descriptor, only the timer or a signal can wake the process up
+		if all_zeros {
+			process::set_state(State::IntSleeping);
 		}
-		// TODO Make the process sleep
+		// TODO Make the process sleep until an event occurs on a file descriptor
 		schedule();
 	};
+	to.write_remaining(timeout)?;
+	let res = res?;
 	// Write back
 	if let Some(val) = readfds_set {
 		readfds.copy_to_user(&val)?;
@@ -192,7 +194,7 @@ pub(super) fn select(
 	exceptfds: UserPtr<FDSet>,
 	timeout: UserPtr<Timeval>,
 ) -> EResult<usize> {
-	do_select(nfds as _, readfds, writefds, exceptfds, timeout, None)
+	do_select(nfds as _, readfds, writefds, exceptfds, timeout)
 }
 
 #[allow(clippy::type_complexity)]
@@ -201,28 +203,109 @@ pub(super) fn _newselect(
 	readfds: UserPtr<FDSet>,
 	writefds: UserPtr<FDSet>,
 	exceptfds: UserPtr<FDSet>,
-	timeout: UserPtr<Timeval>,
+	timeout: UserPtr<Timeval32>,
+) -> EResult<usize> {
+	do_select(nfds as _, readfds, writefds, exceptfds, timeout)
+}
+
+/// Executes `f` with the signal mask at `sigmask` in place of the current process's.
+///
+/// If `sigmask` is null, the mask is left unchanged. `sigsetsize` is the size of the mask.
+///
+/// If `f` is interrupted by a signal, the temporary mask remains in place until the signal is
+/// handled, so that a signal it unblocks is delivered.
+pub(super) fn with_sigmask<F: FnOnce() -> EResult<usize>>(
+	sigmask: UserPtr<SigSet>,
+	sigsetsize: usize,
+	f: F,
 ) -> EResult<usize> {
-	do_select(nfds as _, readfds, writefds, exceptfds, timeout, None)
+	if !sigmask.is_null() {
+		if unlikely(sigsetsize != size_of::<SigSet>()) {
+			return Err(errno!(EINVAL));
+		}
+		if let Some(mask) = sigmask.copy_from_user()? {
+			Process::current().signal.lock().set_temporary_sigmask(mask);
+		}
+	}
+	let res = f();
+	if !matches!(&res, Err(e) if e.as_int() == errno::EINTR) {
+		Process::current().signal.lock().restore_sigmask();
+	}
+	res
+}
+
+/// The last argument of `pselect6`, pointing to the signal mask and its size.
+///
+/// `io_pgetevents` uses the same layout (`struct __aio_sigset`).
+pub struct PSelectSigmask {
+	/// The pointer to the structure.
+	ptr: usize,
+	/// Tells whether the userspace is in compatibility mode.
+	compat: bool,
+}
+
+impl FromSyscallArg for PSelectSigmask {
+	fn from_syscall_arg(ptr: usize, compat: bool) -> Self {
+		Self {
+			ptr,
+			compat,
+		}
+	}
+}
+
+impl fmt::Debug for PSelectSigmask {
+	fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(fmt, "{:#x}", self.ptr)
+	}
+}
+
+impl PSelectSigmask {
+	/// Copies the pointer to the signal mask and its size from userspace.
+	///
+	/// If the structure's pointer is null, the function returns a null mask.
+	pub(super) fn copy_from_user(&self) -> EResult<(UserPtr<SigSet>, usize)> {
+		let (ptr, size) = if self.compat {
+			UserPtr::<[u32; 2]>::from_ptr(self.ptr)
+				.copy_from_user()?
+				.map(|[ptr, size]| (ptr as usize, size as usize))
+		} else {
+			UserPtr::<[usize; 2]>::from_ptr(self.ptr)
+				.copy_from_user()?
+				.map(|[ptr, size]| (ptr, size))
+		}
+		.unwrap_or_default();
+		Ok((UserPtr::from_ptr(ptr), size))
+	}
 }
 
 #[allow(clippy::type_complexity)]
 pub(super) fn pselect6(
+	nfds: c_int,
+	readfds: UserPtr<FDSet>,
+	writefds: UserPtr<FDSet>,
+	exceptfds: UserPtr<FDSet>,
+	timeout: UserPtr<Timespec32>,
+	sigmask: PSelectSigmask,
+) -> EResult<usize> {
+	let (sigmask, sigsetsize) = sigmask.copy_from_user()?;
+	with_sigmask(sigmask, sigsetsize, || {
+		do_select(nfds as _, readfds, writefds, exceptfds, timeout)
+	})
+}
+
+#[allow(clippy::type_complexity)]
+pub(super) fn pselect6_time64(
 	nfds: c_int,
 	readfds: UserPtr<FDSet>,
 	writefds: UserPtr<FDSet>,
 	exceptfds: UserPtr<FDSet>,
 	timeout: UserPtr<Timespec>,
-	sigmask: *mut u8,
+	sigmask: PSelectSigmask,
 ) -> EResult<usize> {
-	do_select(
-		nfds as _,
-		readfds,
-		writefds,
-		exceptfds,
-		timeout,
-		Some(sigmask),
-	)
+	let (sigmask, sigsetsize) = sigmask.copy_from_user()?;
+	with_sigmask(sigmask, sigsetsize, || {
+		do_select(nfds as _, readfds, writefds, exceptfds, timeout)
+	})
 }
 
 /// Poll event: There is data to read.
@@ -249,6 +332,13 @@ pub const POLLWRBAND: u32 = 0x200;
 /// of connection.
 pub const POLLRDHUP: u32 = 0x2000;
 
+/// Events making a file descriptor ready for reading, for `select`.
+const POLLIN_SET: u32 = POLLIN | POLLRDNORM | POLLRDBAND | POLLHUP | POLLERR;
+/// Events making a file descriptor ready for writing, for `select`.
+const POLLOUT_SET: u32 = POLLOUT | POLLWRNORM | POLLWRBAND | POLLERR;
+/// Events signaling an exceptional condition, for `select`.
+const POLLEX_SET: u32 = POLLPRI;
+
 /// A file descriptor passed to the `poll` system call.
 #[repr(C)]
 #[derive(Debug)]
@@ -261,56 +351,99 @@ pub struct PollFD {
 	revents: i16,
 }
 
-pub(super) fn poll(fds: *mut PollFD, nfds: usize, timeout: c_int) -> EResult<usize> {
+/// Polls the file descriptor `fd` and returns the events that occurred.
+///
+/// [`POLLERR`] and [`POLLHUP`] are always reported, even if not requested. If the file descriptor
+/// is invalid, the function returns [`POLLNVAL`].
+fn poll_fd(proc: &Process, fd: &PollFD) -> EResult<u32> {
+	// Negative file descriptors are ignored
+	if fd.fd < 0 {
+		return Ok(0);
+	}
+	let file = proc
+		.file_descriptors()
+		.get_fd(fd.fd)
+		.map(|fd| fd.get_file().clone());
+	let Ok(file) = file else {
+		return Ok(POLLNVAL);
+	};
+	let mask = fd.events as u16 as u32 | POLLERR | POLLHUP;
+	Ok(file.ops.poll(&file, mask)? & mask)
+}
+
+/// Performs the poll operation on the `nfds` file descriptors at `fds`, until an event occurs or
+/// `to` expires.
+fn do_poll(fds: *mut PollFD, nfds: usize, to: Timeout) -> EResult<usize> {
 	let fds = UserSlice::from_user(fds, nfds)?;
-	// The timeout. `None` means no timeout
-	let to = (timeout >= 0).then_some(timeout as Timestamp);
-	let start_ts = current_time_ms(Clock::Monotonic);
+	let proc = Process::current();
+	let mut fds_arr = match fds.copy_from_user_vec(0)? {
+		Some(fds_arr) => fds_arr,
+		// Without any file descriptor, the array may be null
+		None if nfds == 0 => Vec::new(),
+		None => return Err(errno!(EFAULT)),
+	};
+	let _timer = to.timer()?;
 	loop {
-		// Check whether the system call timed out
-		if let Some(timeout) = to {
-			let now = current_time_ms(Clock::Monotonic);
-			if now >= start_ts + timeout {
-				return Ok(0);
+		// The number of file descriptors with at least one event
+		let mut fd_event_count = 0;
+		for fd in fds_arr.iter_mut() {
+			fd.revents = poll_fd(&proc, fd)? as _;
+			if fd.revents != 0 {
+				fd_event_count += 1;
 			}
 		}
-		{
-			let fds_arr = fds.copy_from_user_vec(0)?.ok_or_else(|| errno!(EFAULT))?;
-			// Check the file descriptors list
-			for fd in &fds_arr {
-				if fd.events as u32 & POLLIN != 0 {
-					todo!();
-				}
-				if fd.events as u32 & POLLPRI != 0 {
-					todo!();
-				}
-				if fd.events as u32 & POLLOUT != 0 {
-					todo!();
-				}
-				if fd.events as u32 & POLLRDNORM != 0 {
-					todo!();
-				}
-				if fd.events as u32 & POLLRDBAND != 0 {
-					todo!();
-				}
-				if fd.events as u32 & POLLWRNORM != 0 {
-					todo!();
-				}
-				if fd.events as u32 & POLLWRBAND != 0 {
-					todo!();
-				}
-			}
-			// The number of file descriptor with at least one event
-			let fd_event_count = fds_arr.iter().filter(|fd| fd.revents != 0).count();
-			// If at least on event happened, return the number of file descriptors
-			// concerned
-			if fd_event_count > 0 {
-				fds.copy_to_user(0, &fds_arr)?;
-				return Ok(fd_event_count as _);
-			}
+		// Return if at least one event happened, or on timeout
+		if fd_event_count > 0 || to.has_expired() {
+			fds.copy_to_user(0, &fds_arr)?;
+			return Ok(fd_event_count);
+		}
+		if unlikely(proc.has_pending_signal()) {
+			return Err(errno!(EINTR));
+		}
+		// Without any file descriptor, only the timer or a signal can wake the process up
+		if fds_arr.is_empty() {
+			process::set_state(State::IntSleeping);
 		}
 		// TODO Make process sleep until an event occurs on a file descriptor in
 		// `fds`
 		schedule();
 	}
 }
+
+pub(super) fn poll(fds: *mut PollFD, nfds: usize, timeout: c_int) -> EResult<usize> {
+	do_poll(fds, nfds, Timeout::from_ms(timeout))
+}
+
+/// Performs the `ppoll` system call, writing the remaining time back to `timeout`.
+fn do_ppoll<T: TimeUnit>(
+	fds: *mut PollFD,
+	nfds: usize,
+	timeout: UserPtr<T>,
+	sigmask: UserPtr<SigSet>,
+	sigsetsize: usize,
+) -> EResult<usize> {
+	let to = Timeout::from_user(timeout, Clock::Monotonic, false)?;
+	let res = with_sigmask(sigmask, sigsetsize, || do_poll(fds, nfds, to));
+	to.write_remaining(timeout)?;
+	res
+}
+
+pub(super) fn ppoll(
+	fds: *mut PollFD,
+	nfds: usize,
+	timeout: UserPtr<Timespec32>,
+	sigmask: UserPtr<SigSet>,
+	sigsetsize: usize,
+) -> EResult<usize> {
+	do_ppoll(fds, nfds, timeout, sigmask, sigsetsize)
+}
+
+pub(super) fn ppoll_time64(
+	fds: *mut PollFD,
+	nfds: usize,
+	timeout: UserPtr<Timespec>,
+	sigmask: UserPtr<SigSet>,
+	sigsetsize: usize,
+) -> EResult<usize> {
+	do_ppoll(fds, nfds, timeout, sigmask, sigsetsize)
+}
@@ -28,15 +28,16 @@ use crate::{
 		pid::{INIT_PID, Pid},
 		scheduler::schedule,
 		signal::{
-			AltStack, CompatSigAction, SS_AUTODISARM, SS_DISABLE, SigAction, SigInfo, SigSet,
-			Signal, SignalHandler, Stack32, Stack64, ucontext,
+			AltStack, CompatSigAction, MINSIGSTKSZ, SI_TKILL, SI_USER, SS_AUTODISARM, SS_DISABLE,
+			SS_ONSTACK, SigAction, SigInfo, SigInfo32, SigInfo64, SigSet, Signal, SignalHandler,
+			Stack32, Stack64, ucontext,
 		},
 	},
 	syscall::FromSyscallArg,
 	time::{
-		clock::{Clock, current_time_ns},
-		timer::Timer,
-		unit::{TimeUnit, Timespec},
+		clock::Clock,
+		timeout::Timeout,
+		unit::{TimeUnit, Timespec, Timespec32},
 	},
 };
 use core::{
@@ -57,30 +58,52 @@ const SIG_SETMASK: i32 = 2;
 fn do_sigaltstack<S: fmt::Debug + From<AltStack> + Into<AltStack>>(
 	ss: UserPtr<S>,
 	old_ss: UserPtr<S>,
+	frame: &IntFrame,
 ) -> EResult<usize> {
+	let sp = frame.get_stack_address();
+	// Read new before writing old, since both may point to the same structure
+	let ss = ss.copy_from_user()?;
 	let proc = Process::current();
 	let mut sig = proc.signal.lock();
-	// Write old
-	let old: S = sig.altstack.clone().into();
-	old_ss.copy_to_user(&old)?;
-	// Read new
-	if let Some(ss) = ss.copy_from_user()? {
-		let ss: AltStack = ss.into();
-		// Validate flags
-		if unlikely(ss.ss_flags & !(SS_DISABLE | SS_AUTODISARM) != 0) {
-			return Err(errno!(EINVAL));
+	let old: S = sig.altstack.report(sp).into();
+	if let Some(ss) = ss {
+		let mut ss: AltStack = ss.into();
+		// The stack cannot be changed while a handler is running on it
+		if unlikely(sig.altstack.is_on(sp)) {
+			return Err(errno!(EPERM));
+		}
+		// Validate flags. `SS_ONSTACK` is accepted for compatibility and ignored
+		match ss.ss_flags & !SS_AUTODISARM {
+			0 | SS_ONSTACK => {
+				if unlikely((ss.ss_size as usize) < MINSIGSTKSZ) {
+					return Err(errno!(ENOMEM));
+				}
+				ss.ss_flags &= SS_AUTODISARM;
+			}
+			SS_DISABLE => ss = AltStack::default(),
+			_ => return Err(errno!(EINVAL)),
 		}
 		sig.altstack = ss;
 	}
+	drop(sig);
+	old_ss.copy_to_user(&old)?;
 	Ok(0)
 }
 
-pub fn compat_sigaltstack(ss: UserPtr<Stack32>, old_ss: UserPtr<Stack32>) -> EResult<usize> {
-	do_sigaltstack(ss, old_ss)
+pub fn compat_sigaltstack(
+	ss: UserPtr<Stack32>,
+	old_ss: UserPtr<Stack32>,
+	frame: &mut IntFrame,
+) -> EResult<usize> {
+	do_sigaltstack(ss, old_ss, frame)
 }
 
-pub fn sigaltstack(ss: UserPtr<Stack64>, old_ss: UserPtr<Stack64>) -> EResult<usize> {
-	do_sigaltstack(ss, old_ss)
+pub fn sigaltstack(
+	ss: UserPtr<Stack64>,
+	old_ss: UserPtr<Stack64>,
+	frame: &mut IntFrame,
+) -> EResult<usize> {
+	do_sigaltstack(ss, old_ss, frame)
 }
 
 pub fn signal(signum: c_int, handler: *const c_void) -> EResult<usize> {
@@ -154,19 +177,19 @@ pub fn rt_sigprocmask(
 	Ok(0)
 }
 
-pub fn sigreturn(frame: &mut IntFrame) -> EResult<usize> {
+/// Restores the state saved in the `ucontext_t` structure at `ctx_ptr` on the handler's stack.
+fn restore_context(frame: &mut IntFrame, ctx_ptr: usize) -> EResult<usize> {
 	let proc = Process::current();
 	// Retrieve and restore previous state
-	let stack_ptr = frame.get_stack_address();
 	if frame.is_compat() {
-		let ctx = UserPtr::<ucontext::UContext32>::from_ptr(stack_ptr)
+		let ctx = UserPtr::<ucontext::UContext32>::from_ptr(ctx_ptr)
 			.copy_from_user()?
 			.ok_or_else(|| errno!(EFAULT))?;
 		ctx.restore(&proc, frame);
 	} else {
 		#[cfg(target_arch = "x86_64")]
 		{
-			let ctx = UserPtr::<ucontext::UContext64>::from_ptr(stack_ptr)
+			let ctx = UserPtr::<ucontext::UContext64>::from_ptr(ctx_ptr)
 				.copy_from_user()?
 				.ok_or_else(|| errno!(EFAULT))?;
 			let res = ctx.restore(&proc, frame);
@@ -179,15 +202,28 @@ pub fn sigreturn(frame: &mut IntFrame) -> EResult<usize> {
 	Ok(frame.get_syscall_id())
 }
 
+pub fn sigreturn(frame: &mut IntFrame) -> EResult<usize> {
+	// The trampoline popped the signal number, leaving the context on top of the stack
+	restore_context(frame, frame.get_stack_address())
+}
+
 pub fn rt_sigreturn(frame: &mut IntFrame) -> EResult<usize> {
-	sigreturn(frame)
+	let stack_ptr = frame.get_stack_address();
+	// In 32 bit, the signal number and pointers to the `siginfo_t` and `ucontext_t` structs are
+	// still on the stack
+	let ctx_ptr = if frame.is_compat() {
+		stack_ptr.saturating_add(3 * size_of::<u32>())
+	} else {
+		stack_ptr
+	};
+	restore_context(frame, ctx_ptr)
 }
 
 /// Tries to kill the process with PID `pid` with the signal `sig`.
 ///
-/// If `sig` is `None`, the function doesn't send a signal, but still checks if
+/// If `info` is `None`, the function doesn't send a signal, but still checks if
 /// there is a process that could be killed.
-fn try_kill(pid: Pid, sig: Option<Signal>) -> EResult<()> {
+fn try_kill(pid: Pid, info: Option<SigInfo>) -> EResult<()> {
 	let target = Process::get_by_pid(pid).ok_or_else(|| errno!(ESRCH))?;
 	if matches!(target.get_state(), State::Zombie) {
 		return Ok(());
@@ -195,8 +231,8 @@ fn try_kill(pid: Pid, sig: Option<Signal>) -> EResult<()> {
 	if !can_kill(&target) {
 		return Err(errno!(EPERM));
 	}
-	if let Some(sig) = sig {
-		Process::kill(&target, sig);
+	if let Some(info) = info {
+		Process::queue_signal(&target, info)?;
 	}
 	Ok(())
 }
@@ -205,11 +241,11 @@ fn try_kill(pid: Pid, sig: Option<Signal>) -> EResult<()> {
 ///
 /// Arguments:
 /// - `pid` is the value that determine which process(es) to kill.
-/// - `sig` is the signal to send.
+/// - `info` is the signal to send.
 ///
-/// If `sig` is `None`, the function doesn't send a signal, but still checks if
+/// If `info` is `None`, the function doesn't send a signal, but still checks if
 /// there is a process that could be killed.
-fn try_kill_group(pid: i32, sig: Option<Signal>) -> EResult<()> {
+fn try_kill_group(pid: i32, info: Option<SigInfo>) -> EResult<()> {
 	let pgid = match pid {
 		0 => Process::current().get_pgid(),
 		i if i < 0 => -pid as Pid,
@@ -222,20 +258,21 @@ fn try_kill_group(pid: i32, sig: Option<Signal>) -> EResult<()> {
 		.lock()
 		.process_group
 		.iter()
-		.try_for_each(|pid| try_kill(*pid, sig))?;
-	if let Some(sig) = sig {
-		Process::kill(&leader, sig);
+		.try_for_each(|pid| try_kill(*pid, info))?;
+	if let Some(info) = info {
+		Process::queue_signal(&leader, info)?;
 	}
 	Ok(())
 }
 
 pub fn kill(pid: c_int, sig: c_int) -> EResult<usize> {
 	let sig = (sig != 0).then(|| Signal::try_from(sig)).transpose()?;
+	let info = sig.map(|sig| SigInfo::user(sig, SI_USER));
 	match pid {
 		// Kill the process with the given PID
-		1.. => try_kill(pid as _, sig)?,
+		1.. => try_kill(pid as _, info)?,
 		// Kill all processes in the current process group
-		0 => try_kill_group(0, sig)?,
+		0 => try_kill_group(0, info)?,
 		// Kill all processes for which the current process has the permission
 		-1 => {
 			let processes = PROCESSES.read();
@@ -244,11 +281,11 @@ pub fn kill(pid: c_int, sig: c_int) -> EResult<usize> {
 					continue;
 				}
 				// TODO Check permission
-				try_kill(*pid, sig)?;
+				try_kill(*pid, info)?;
 			}
 		}
 		// Kill the given process group
-		..-1 => try_kill_group(-pid as _, sig)?,
+		..-1 => try_kill_group(-pid as _, info)?,
 	}
 	Ok(0)
 }
@@ -259,10 +296,49 @@ pub fn tkill(tid: Pid, sig: c_int) -> EResult<usize> {
 	if unlikely(!can_kill(&thread)) {
 		return Err(errno!(EPERM));
 	}
-	Process::kill(&thread, sig);
+	Process::queue_signal(&thread, SigInfo::user(sig, SI_TKILL))?;
+	Ok(0)
+}
+
+/// Common implementation of `rt_sigqueueinfo`, parameterized over the `siginfo_t` ABI.
+fn do_rt_sigqueueinfo<S: fmt::Debug + Into<SigInfo>>(
+	pid: Pid,
+	sig: c_int,
+	uinfo: UserPtr<S>,
+) -> EResult<usize> {
+	let sig = (sig != 0).then(|| Signal::try_from(sig)).transpose()?;
+	let mut info: SigInfo = uinfo
+		.copy_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?
+		.into();
+	// Do not allow impersonating `kill` or the kernel when targeting another process
+	if unlikely(
+		(info.si_code >= 0 || info.si_code == SI_TKILL) && pid != Process::current().get_pid(),
+	) {
+		return Err(errno!(EPERM));
+	}
+	let target = Process::get_by_pid(pid).ok_or_else(|| errno!(ESRCH))?;
+	if matches!(target.get_state(), State::Zombie) {
+		return Ok(0);
+	}
+	if unlikely(!can_kill(&target)) {
+		return Err(errno!(EPERM));
+	}
+	if let Some(sig) = sig {
+		info.si_signo = sig.0;
+		Process::queue_signal(&target, info)?;
+	}
 	Ok(0)
 }
 
+pub fn compat_rt_sigqueueinfo(pid: Pid, sig: c_int, uinfo: UserPtr<SigInfo32>) -> EResult<usize> {
+	do_rt_sigqueueinfo(pid, sig, uinfo)
+}
+
+pub fn rt_sigqueueinfo(pid: Pid, sig: c_int, uinfo: UserPtr<SigInfo64>) -> EResult<usize> {
+	do_rt_sigqueueinfo(pid, sig, uinfo)
+}
+
 pub fn rt_sigpending(set: UserPtr<SigSet>, sigsetsize: usize) -> EResult<usize> {
 	if unlikely(sigsetsize != size_of::<SigSet>()) {
 		return Err(errno!(EINVAL));
@@ -272,56 +348,100 @@ pub fn rt_sigpending(set: UserPtr<SigSet>, sigsetsize: usize) -> EResult<usize>
 	Ok(0)
 }
 
-/// Writes signal information for `sig` to `info` (no-op if `info` is null).
-fn write_siginfo(info: UserPtr<SigInfo>, sig: Signal) -> EResult<()> {
-	let mut value: SigInfo = unsafe { mem::zeroed() };
-	value.si_signo = sig.0;
-	info.copy_to_user(&value)
+pub fn sigpending(set: UserPtr<u32>) -> EResult<usize> {
+	let pending = Process::current().signal.lock().pending();
+	set.copy_to_user(&(pending.0 as u32))?;
+	Ok(0)
 }
 
-pub fn rt_sigtimedwait(
+/// Puts the current process to sleep until a signal that is not blocked becomes pending.
+///
+/// The pending check is performed after entering the sleeping state, so that a signal sent in
+/// between cannot be missed.
+///
+/// The function always returns [`errno::EINTR`].
+fn sleep_until_signal() -> EResult<usize> {
+	let proc = Process::current();
+	loop {
+		process::set_state(State::IntSleeping);
+		if proc.has_pending_signal() {
+			process::cancel_sleep();
+			break;
+		}
+		schedule();
+	}
+	Err(errno!(EINTR))
+}
+
+pub fn pause() -> EResult<usize> {
+	sleep_until_signal()
+}
+
+/// Replaces the signal mask with `mask` and waits for a signal.
+///
+/// The previous mask is restored once the signal is handled.
+fn do_sigsuspend(mut mask: SigSet) -> EResult<usize> {
+	// These signals cannot be blocked
+	mask.clear(Signal::SIGKILL.0 as usize);
+	mask.clear(Signal::SIGSTOP.0 as usize);
+	Process::current().signal.lock().set_temporary_sigmask(mask);
+	sleep_until_signal()
+}
+
+pub fn sigsuspend(_unused0: c_int, _unused1: c_int, mask: u32) -> EResult<usize> {
+	do_sigsuspend(SigSet(mask as u64))
+}
+
+pub fn rt_sigsuspend(set: UserPtr<SigSet>, sigsetsize: usize) -> EResult<usize> {
+	if unlikely(sigsetsize != size_of::<SigSet>()) {
+		return Err(errno!(EINVAL));
+	}
+	let mask = set.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	do_sigsuspend(mask)
+}
+
+/// Common implementation of `rt_sigtimedwait`, parameterized over the timespec and `siginfo_t`
+/// ABIs.
+fn do_rt_sigtimedwait<T: TimeUnit, S: fmt::Debug + From<SigInfo>>(
 	set: UserPtr<SigSet>,
-	info: UserPtr<SigInfo>,
-	timeout: UserPtr<Timespec>,
+	info: UserPtr<S>,
+	timeout: UserPtr<T>,
 	sigsetsize: usize,
 ) -> EResult<usize> {
 	if unlikely(sigsetsize != size_of::<SigSet>()) {
 		return Err(errno!(EINVAL));
 	}
 	let set = set.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
-	let timeout_ns = timeout.copy_from_user()?.map(|ts| ts.to_nano());
+	let timeout = Timeout::from_user(timeout, Clock::Monotonic, false)?;
 	let proc = Process::current();
+	let dequeued = |proc: &Process| -> EResult<Option<usize>> {
+		let Some(val) = proc.signal.lock().dequeue_from(set) else {
+			return Ok(None);
+		};
+		info.copy_to_user(&val.into())?;
+		Ok(Some(val.si_signo as usize))
+	};
 	// Fast path: a wanted signal is already pending
-	if let Some(sig) = proc.signal.lock().dequeue_from(set) {
-		write_siginfo(info, sig)?;
-		return Ok(sig.0 as usize);
+	if let Some(sig) = dequeued(&proc)? {
+		return Ok(sig);
 	}
 	// Poll mode: zero timeout and no signal pending
-	if timeout_ns == Some(0) {
+	if timeout.has_expired() {
 		return Err(errno!(EAGAIN));
 	}
-	// Set up the timeout timer if any
-	let _timer = if let Some(delay) = timeout_ns {
-		let proc_clone = proc.clone();
-		let mut t = Timer::new(Clock::Monotonic, move || {
-			Process::wake_from(&proc_clone, State::IntSleeping as u8);
-		})?;
-		t.set_time(0, delay)?;
-		Some(t)
-	} else {
-		None
-	};
-	let deadline = timeout_ns.map(|d| current_time_ns(Clock::Monotonic).saturating_add(d));
+	let _timer = timeout.timer()?;
 	loop {
 		process::set_state(State::IntSleeping);
-		schedule();
-		if let Some(sig) = proc.signal.lock().dequeue_from(set) {
-			write_siginfo(info, sig)?;
-			return Ok(sig.0 as usize);
+		// Check after entering the sleeping state, so that a signal sent in between is not missed
+		if proc.signal.lock().pending().0 & set.0 != 0 || proc.has_pending_signal() {
+			process::cancel_sleep();
+		} else {
+			schedule();
 		}
-		if let Some(deadline) = deadline
-			&& current_time_ns(Clock::Monotonic) >= deadline
-		{
+		if let Some(sig) = dequeued(&proc)? {
+			return Ok(sig);
+		}
+		if timeout.has_expired() {
 			return Err(errno!(EAGAIN));
 		}
 		if proc.has_pending_signal() {
@@ -329,3 +449,27 @@ pub fn rt_sigtimedwait(
 		}
 	}
 }
+
+pub fn rt_sigtimedwait(
+	set: UserPtr<SigSet>,
+	info: UserPtr<SigInfo32>,
+	timeout: UserPtr<Timespec32>,
+	sigsetsize: usize,
+) -> EResult<usize> {
+	do_rt_sigtimedwait(set, info, timeout, sigsetsize)
+}
+
+pub fn rt_sigtimedwait_time64(
+	set: UserPtr<SigSet>,
+	info: *mut c_void,
+	timeout: UserPtr<Timespec>,
+	sigsetsize: usize,
+	frame: &mut IntFrame,
+) -> EResult<usize> {
+	let info = info as usize;
+	if frame.is_compat() {
+		do_rt_sigtimedwait::<_, SigInfo32>(set, UserPtr::from_ptr(info), timeout, sigsetsize)
+	} else {
+		do_rt_sigtimedwait::<_, SigInfo64>(set, UserPtr::from_ptr(info), timeout, sigsetsize)
+	}
+}
@@ -19,13 +19,26 @@
 //! Socket interface system calls.
 
 use crate::{
-	file::{File, FileType, O_RDWR, fd::fd_to_file, fs::float, socket::Socket},
-	memory::user::{UserPtr, UserSlice},
-	net::{SocketDesc, SocketDomain, SocketType},
+	file::{
+		File, FileType, O_CLOEXEC, O_NONBLOCK, O_RDWR,
+		fd::{FD_CLOEXEC, fd_to_file},
+		fs::float,
+		socket::{
+			Ancillary, MSG_CMSG_CLOEXEC, MSG_CTRUNC, MSG_DONTWAIT, SOL_SOCKET, Socket, UCred,
+		},
+	},
+	memory::user::{IOVec, UserIOVec, UserPtr, UserSlice},
+	net::{SocketDesc, SocketDomain, SocketType, netlink, udp},
 	process::Process,
+	syscall::FromSyscallArg,
+};
+use core::{cmp::min, ffi::c_int, hint::unlikely, ptr, ptr::null_mut};
+use utils::{
+	TryClone,
+	collections::vec::Vec,
+	errno,
+	errno::{AllocResult, EResult},
 };
-use core::{cmp::min, ffi::c_int, hint::unlikely};
-use utils::{errno, errno::EResult};
 
 /// Shutdown receive side of the connection.
 const SHUT_RD: c_int = 0;
@@ -34,11 +47,89 @@ const SHUT_WR: c_int = 1;
 /// Both sides are shutdown.
 const SHUT_RDWR: c_int = 2;
 
+/// `socketcall` call: `socket`.
+const SYS_SOCKET: c_int = 1;
+/// `socketcall` call: `bind`.
+const SYS_BIND: c_int = 2;
+/// `socketcall` call: `connect`.
+const SYS_CONNECT: c_int = 3;
+/// `socketcall` call: `listen`.
+const SYS_LISTEN: c_int = 4;
+/// `socketcall` call: `accept`.
+const SYS_ACCEPT: c_int = 5;
+/// `socketcall` call: `getsockname`.
+const SYS_GETSOCKNAME: c_int = 6;
+/// `socketcall` call: `getpeername`.
+const SYS_GETPEERNAME: c_int = 7;
+/// `socketcall` call: `socketpair`.
+const SYS_SOCKETPAIR: c_int = 8;
+/// `socketcall` call: `send`.
+const SYS_SEND: c_int = 9;
+/// `socketcall` call: `recv`.
+const SYS_RECV: c_int = 10;
+/// `socketcall` call: `sendto`.
+const SYS_SENDTO: c_int = 11;
+/// `socketcall` call: `recvfrom`.
+const SYS_RECVFROM: c_int = 12;
+/// `socketcall` call: `shutdown`.
+const SYS_SHUTDOWN: c_int = 13;
+/// `socketcall` call: `setsockopt`.
+const SYS_SETSOCKOPT: c_int = 14;
+/// `socketcall` call: `getsockopt`.
+const SYS_GETSOCKOPT: c_int = 15;
+/// `socketcall` call: `sendmsg`.
+const SYS_SENDMSG: c_int = 16;
+/// `socketcall` call: `recvmsg`.
+const SYS_RECVMSG: c_int = 17;
+/// `socketcall` call: `accept4`.
+const SYS_ACCEPT4: c_int = 18;
+/// The number of arguments taken by each `socketcall` call, indexed by call number.
+const SOCKETCALL_NARGS: [usize; 21] = [
+	0, 3, 3, 3, 2, 3, 3, 3, 4, 4, 4, 6, 6, 2, 5, 5, 3, 3, 4, 5, 4,
+];
+
+/// Control message type: file descriptors.
+const SCM_RIGHTS: c_int = 1;
+/// Control message type: credentials of the sender.
+const SCM_CREDENTIALS: c_int = 2;
+/// The maximum number of file descriptors passed in a single control message.
+const SCM_MAX_FD: usize = 253;
+/// The maximum size of the control data of a message.
+const OPTMEM_MAX: usize = 20480;
+
+/// Socket type flag: Open the socket in non-blocking mode.
+const SOCK_NONBLOCK: c_int = O_NONBLOCK;
+/// Socket type flag: Set the close-on-exec flag on the new file descriptors.
+const SOCK_CLOEXEC: c_int = O_CLOEXEC;
+
+/// Converts `SOCK_*` flags into the flags of the open file and the flags of the file descriptor.
+fn split_flags(flags: c_int) -> (c_int, c_int) {
+	let file_flags = O_RDWR | (flags & SOCK_NONBLOCK);
+	let fd_flags = if flags & SOCK_CLOEXEC != 0 {
+		FD_CLOEXEC
+	} else {
+		0
+	};
+	(file_flags, fd_flags)
+}
+
+/// Splits the `type` argument of socket creation system calls into the socket type, the flags of
+/// the open file and the flags of the file descriptors.
+fn split_type(r#type: c_int) -> EResult<(SocketType, c_int, c_int)> {
+	let sock_type = SocketType::try_from((r#type & !(SOCK_NONBLOCK | SOCK_CLOEXEC)) as u32)?;
+	let (file_flags, fd_flags) = split_flags(r#type);
+	Ok((sock_type, file_flags, fd_flags))
+}
+
 pub fn socket(domain: c_int, r#type: c_int, protocol: c_int) -> EResult<usize> {
 	let sock_domain = SocketDomain::try_from(domain as u32)?;
-	let sock_type = SocketType::try_from(r#type as u32)?;
+	let (sock_type, file_flags, fd_flags) = split_type(r#type)?;
 	// Check permissions
-	if unlikely(!sock_domain.can_use() || !sock_type.can_use()) {
+	if sock_domain == SocketDomain::AfNetlink {
+		netlink::check_desc(sock_type, protocol)?;
+	} else if sock_domain == SocketDomain::AfInet && sock_type == SocketType::SockDgram {
+		udp::check_desc(protocol)?;
+	} else if unlikely(!sock_domain.can_use() || !sock_type.can_use()) {
 		return Err(errno!(EACCES));
 	}
 	let desc = SocketDesc {
@@ -48,11 +139,10 @@ pub fn socket(domain: c_int, r#type: c_int, protocol: c_int) -> EResult<usize> {
 	};
 	// Create socket
 	let sock = float::get_entry(Socket::new(desc)?, FileType::Socket)?;
-	let file = File::open_floating(sock, O_RDWR)?;
-	let (sock_fd_id, _) = Process::current()
+	let file = File::open_floating(sock, file_flags)?;
+	let sock_fd_id = Process::current()
 		.file_descriptors()
-		.lock()
-		.create_fd(0, file)?;
+		.create_fd(fd_flags, file)?;
 	Ok(sock_fd_id as _)
 }
 
@@ -63,7 +153,7 @@ pub fn socketpair(
 	sv: UserPtr<[c_int; 2]>,
 ) -> EResult<usize> {
 	let sock_domain = SocketDomain::try_from(domain as u32)?;
-	let sock_type = SocketType::try_from(r#type as u32)?;
+	let (sock_type, file_flags, fd_flags) = split_type(r#type)?;
 	// Check permissions
 	if unlikely(!sock_domain.can_use() || !sock_type.can_use()) {
 		return Err(errno!(EACCES));
@@ -73,33 +163,36 @@ pub fn socketpair(
 		type_: sock_type,
 		protocol,
 	};
-	// Create socket
-	let sock = float::get_entry(Socket::new(desc)?, FileType::Socket)?;
-	let file0 = File::open_floating(sock.clone(), O_RDWR)?;
-	let file1 = File::open_floating(sock, O_RDWR)?;
+	// Create sockets
+	let (sock0, sock1) = Socket::new_pair(desc)?;
+	let sock0 = float::get_entry(sock0, FileType::Socket)?;
+	let sock1 = float::get_entry(sock1, FileType::Socket)?;
+	let file0 = File::open_floating(sock0, file_flags)?;
+	let file1 = File::open_floating(sock1, file_flags)?;
 	// Create file descriptors
 	let (fd0_id, fd1_id) = Process::current()
 		.file_descriptors()
-		.lock()
-		.create_fd_pair(file0, file1)?;
+		.create_fd_pair(fd_flags, file0, file1)?;
 	sv.copy_to_user(&[fd0_id as _, fd1_id as _])?;
 	Ok(0)
 }
 
-pub fn getsockname(sockfd: c_int, addr: *mut u8, addrlen: UserPtr<isize>) -> EResult<usize> {
-	// Get socket
+pub fn getsockname(sockfd: c_int, addr: *mut u8, addrlen: UserPtr<u32>) -> EResult<usize> {
 	let file = fd_to_file(sockfd)?;
 	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
-	// Read and check buffer length
-	let addrlen_val = addrlen.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
-	if addrlen_val < 0 {
-		return Err(errno!(EINVAL));
+	let name = sock.get_sockname().lock().try_clone()?;
+	copy_sockaddr_to_user(sock, &name, addr, addrlen)?;
+	Ok(0)
+}
+
+pub fn getpeername(sockfd: c_int, addr: *mut u8, addrlen: UserPtr<u32>) -> EResult<usize> {
+	let file = fd_to_file(sockfd)?;
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	if !sock.is_connected() {
+		return Err(errno!(ENOTCONN));
 	}
-	let name = sock.get_sockname().lock();
-	let len = min(name.len(), addrlen_val as _);
-	let addr = UserSlice::from_user(addr, len)?;
-	addr.copy_to_user(0, &name[..len])?;
-	addrlen.copy_to_user(&(len as _))?;
+	let name = sock.get_peername().lock().try_clone()?;
+	copy_sockaddr_to_user(sock, &name, addr, addrlen)?;
 	Ok(0)
 }
 
@@ -108,17 +201,21 @@ pub fn getsockopt(
 	level: c_int,
 	optname: c_int,
 	optval: *mut u8,
-	optlen: usize,
+	optlen: UserPtr<u32>,
 ) -> EResult<usize> {
+	let optlen_val = optlen.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if unlikely(optlen_val > i32::MAX as u32) {
+		return Err(errno!(EINVAL));
+	}
 	// Get socket
 	let file = fd_to_file(sockfd)?;
 	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
 	let val = sock.get_opt(level, optname)?;
 	// Write
-	let len = min(val.len(), optlen);
-	let optval = UserSlice::from_user(optval, optlen)?;
-	optval.copy_to_user(0, &val[..len])?;
-	Ok(len as _)
+	let len = min(val.len(), optlen_val as _);
+	UserSlice::from_user(optval, len)?.copy_to_user(0, &val[..len])?;
+	optlen.copy_to_user(&(len as _))?;
+	Ok(0)
 }
 
 pub fn setsockopt(
@@ -134,7 +231,8 @@ pub fn setsockopt(
 	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
 	// Set opt
 	let optval = optval.copy_from_user_vec(0)?.ok_or(errno!(EFAULT))?;
-	sock.set_opt(level, optname, &optval).map(|opt| opt as _)
+	sock.set_opt(level, optname, &optval)?;
+	Ok(0)
 }
 
 pub fn connect(sockfd: c_int, addr: *mut u8, addrlen: isize) -> EResult<usize> {
@@ -144,11 +242,72 @@ pub fn connect(sockfd: c_int, addr: *mut u8, addrlen: isize) -> EResult<usize> {
 	}
 	// Get socket
 	let file = fd_to_file(sockfd)?;
-	let _sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
 	let addr = UserSlice::from_user(addr, addrlen as _)?;
-	let _addr = addr.copy_from_user_vec(0)?.ok_or_else(|| errno!(EFAULT))?;
-	// TODO connect socket
-	todo!()
+	let addr = addr.copy_from_user_vec(0)?.ok_or_else(|| errno!(EFAULT))?;
+	sock.connect(&addr, file.get_flags() & O_NONBLOCK != 0)?;
+	Ok(0)
+}
+
+pub fn listen(sockfd: c_int, backlog: c_int) -> EResult<usize> {
+	let file = fd_to_file(sockfd)?;
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	sock.listen(backlog)?;
+	Ok(0)
+}
+
+/// Copies the address `name` of the socket `sock` to the userspace buffer `addr`, whose size is
+/// pointed to by `addrlen`.
+///
+/// An empty address is reported as the address family alone.
+///
+/// The address is truncated if the buffer is too small, and `addrlen` is updated with the
+/// actual size of the address.
+fn copy_sockaddr_to_user(
+	sock: &Socket,
+	name: &[u8],
+	addr: *mut u8,
+	addrlen: UserPtr<u32>,
+) -> EResult<()> {
+	let family = (sock.desc().domain.get_id() as u16).to_ne_bytes();
+	let name = if name.is_empty() { &family[..] } else { name };
+	let addrlen_val = addrlen.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if unlikely(addrlen_val > i32::MAX as u32) {
+		return Err(errno!(EINVAL));
+	}
+	let len = min(name.len(), addrlen_val as _);
+	UserSlice::from_user(addr, len)?.copy_to_user(0, &name[..len])?;
+	addrlen.copy_to_user(&(name.len() as _))?;
+	Ok(())
+}
+
+pub fn accept(sockfd: c_int, addr: *mut u8, addrlen: UserPtr<u32>) -> EResult<usize> {
+	accept4(sockfd, addr, addrlen, 0)
+}
+
+pub fn accept4(
+	sockfd: c_int,
+	addr: *mut u8,
+	addrlen: UserPtr<u32>,
+	flags: c_int,
+) -> EResult<usize> {
+	if unlikely(flags & !(SOCK_NONBLOCK | SOCK_CLOEXEC) != 0) {
+		return Err(errno!(EINVAL));
+	}
+	let file = fd_to_file(sockfd)?;
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	let new_sock = sock.accept(file.get_flags() & O_NONBLOCK != 0)?;
+	let peername = new_sock.get_peername().lock().try_clone()?;
+	let (file_flags, fd_flags) = split_flags(flags);
+	let new_sock = float::get_entry(new_sock, FileType::Socket)?;
+	let new_file = File::open_floating(new_sock, file_flags)?;
+	if !addrlen.is_null() {
+		copy_sockaddr_to_user(sock, &peername, addr, addrlen)?;
+	}
+	let fd = Process::current()
+		.file_descriptors()
+		.create_fd(fd_flags, new_file)?;
+	Ok(fd as _)
 }
 
 pub fn bind(sockfd: c_int, addr: *mut u8, addrlen: isize) -> EResult<usize> {
@@ -165,28 +324,69 @@ pub fn bind(sockfd: c_int, addr: *mut u8, addrlen: isize) -> EResult<usize> {
 	Ok(0)
 }
 
-// TODO implement flags
+/// Checks that a destination address can be given when sending on `sock`.
+/// Copies the destination address `addr` of size `addrlen` from userspace.
+///
+/// If no address is given, the function returns `None`.
+fn copy_dest_addr(addr: *mut u8, addrlen: usize) -> EResult<Option<Vec<u8>>> {
+	if addr.is_null() || addrlen == 0 {
+		return Ok(None);
+	}
+	let addr = UserSlice::from_user(addr, addrlen)?;
+	Ok(Some(
+		addr.copy_from_user_vec(0)?.ok_or_else(|| errno!(EFAULT))?,
+	))
+}
+
 pub fn sendto(
 	sockfd: c_int,
 	buf: *mut u8,
 	len: usize,
-	_flags: c_int,
+	flags: c_int,
 	dest_addr: *mut u8,
 	addrlen: isize,
 ) -> EResult<usize> {
-	let buf = UserSlice::from_user(buf, len)?;
-	let dest_addr = UserSlice::from_user(dest_addr, addrlen as _)?;
 	// Validation
 	if unlikely(addrlen < 0) {
 		return Err(errno!(EINVAL));
 	}
 	// Get socket
 	let file = fd_to_file(sockfd)?;
-	let _sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
-	// Get slices
-	let _buf_slice = buf.copy_from_user_vec(0)?.ok_or(errno!(EFAULT))?;
-	let _dest_addr_slice = dest_addr.copy_from_user_vec(0)?.ok_or(errno!(EFAULT))?;
-	todo!()
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	let dest = copy_dest_addr(dest_addr, addrlen as _)?;
+	let iov = [IOVec {
+		iov_base: buf,
+		iov_len: len,
+	}];
+	let nonblock = file.get_flags() & O_NONBLOCK != 0 || flags & MSG_DONTWAIT != 0;
+	sock.send(&iov, dest.as_deref(), Ancillary::default(), flags, nonblock)
+}
+
+pub fn recvfrom(
+	sockfd: c_int,
+	buf: *mut u8,
+	len: usize,
+	flags: c_int,
+	src_addr: *mut u8,
+	addrlen: UserPtr<u32>,
+) -> EResult<usize> {
+	let file = fd_to_file(sockfd)?;
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	let iov = [IOVec {
+		iov_base: buf,
+		iov_len: len,
+	}];
+	let nonblock = file.get_flags() & O_NONBLOCK != 0 || flags & MSG_DONTWAIT != 0;
+	let received = sock.recv(&iov, flags, nonblock)?;
+	if !src_addr.is_null() {
+		if received.name.is_empty() {
+			// Connected sockets have no address to report
+			addrlen.copy_to_user(&0)?;
+		} else {
+			copy_sockaddr_to_user(sock, &received.name, src_addr, addrlen)?;
+		}
+	}
+	Ok(received.len)
 }
 
 pub fn shutdown(sockfd: c_int, how: c_int) -> EResult<usize> {
@@ -205,3 +405,385 @@ pub fn shutdown(sockfd: c_int, how: c_int) -> EResult<usize> {
 	}
 	Ok(0)
 }
+
+/// An argument of a system call multiplexed by `socketcall`.
+struct SocketcallArg(u32);
+
+impl SocketcallArg {
+	/// Converts the argument to the type expected by the underlying system call.
+	fn get<T: FromSyscallArg>(self) -> T {
+		// Arguments are passed by 32-bit userspace
+		T::from_syscall_arg(self.0 as _, true)
+	}
+}
+
+/// Multiplexer for socket system calls, used by old 32-bit userspace.
+///
+/// `args` points to an array of arguments for the call, whose length depends on `call`.
+pub fn socketcall(call: c_int, args: *mut u32) -> EResult<usize> {
+	let nargs = usize::try_from(call)
+		.ok()
+		.and_then(|call| SOCKETCALL_NARGS.get(call))
+		.copied()
+		.filter(|nargs| *nargs > 0)
+		.ok_or_else(|| errno!(EINVAL))?;
+	let mut a = [0u32; 6];
+	let len = UserSlice::from_user(args, nargs)?.copy_from_user(0, &mut a[..nargs])?;
+	if unlikely(len < nargs) {
+		return Err(errno!(EFAULT));
+	}
+	let arg = |i: usize| SocketcallArg(a[i]);
+	match call {
+		SYS_SOCKET => socket(arg(0).get(), arg(1).get(), arg(2).get()),
+		SYS_BIND => bind(arg(0).get(), arg(1).get(), arg(2).get()),
+		SYS_CONNECT => connect(arg(0).get(), arg(1).get(), arg(2).get()),
+		SYS_LISTEN => listen(arg(0).get(), arg(1).get()),
+		SYS_ACCEPT => accept(arg(0).get(), arg(1).get(), arg(2).get()),
+		SYS_GETSOCKNAME => getsockname(arg(0).get(), arg(1).get(), arg(2).get()),
+		SYS_GETPEERNAME => getpeername(arg(0).get(), arg(1).get(), arg(2).get()),
+		SYS_SOCKETPAIR => socketpair(arg(0).get(), arg(1).get(), arg(2).get(), arg(3).get()),
+		SYS_SEND => sendto(
+			arg(0).get(),
+			arg(1).get(),
+			arg(2).get(),
+			arg(3).get(),
+			null_mut(),
+			0,
+		),
+		SYS_RECV => recvfrom(
+			arg(0).get(),
+			arg(1).get(),
+			arg(2).get(),
+			arg(3).get(),
+			null_mut(),
+			UserPtr::from_ptr(0),
+		),
+		SYS_SENDTO => sendto(
+			arg(0).get(),
+			arg(1).get(),
+			arg(2).get(),
+			arg(3).get(),
+			arg(4).get(),
+			arg(5).get(),
+		),
+		SYS_RECVFROM => recvfrom(
+			arg(0).get(),
+			arg(1).get(),
+			arg(2).get(),
+			arg(3).get(),
+			arg(4).get(),
+			arg(5).get(),
+		),
+		SYS_SHUTDOWN => shutdown(arg(0).get(), arg(1).get()),
+		SYS_SETSOCKOPT => setsockopt(
+			arg(0).get(),
+			arg(1).get(),
+			arg(2).get(),
+			arg(3).get(),
+			arg(4).get(),
+		),
+		SYS_GETSOCKOPT => getsockopt(
+			arg(0).get(),
+			arg(1).get(),
+			arg(2).get(),
+			arg(3).get(),
+			arg(4).get(),
+		),
+		SYS_SENDMSG => sendmsg(arg(0).get(), arg(1).get(), arg(2).get()),
+		SYS_RECVMSG => recvmsg(arg(0).get(), arg(1).get(), arg(2).get()),
+		SYS_ACCEPT4 => accept4(arg(0).get(), arg(1).get(), arg(2).get(), arg(3).get()),
+		// TODO implement the remaining calls along with their system calls
+		_ => Err(errno!(ENOSYS)),
+	}
+}
+
+/// Message header, as passed to `sendmsg` and `recvmsg`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct MsgHdr {
+	/// Pointer to the address of the peer.
+	msg_name: usize,
+	/// The size of the address of the peer.
+	msg_namelen: u32,
+	/// Pointer to the IO vector.
+	msg_iov: usize,
+	/// The number of elements in the IO vector.
+	msg_iovlen: usize,
+	/// Pointer to the control data.
+	msg_control: usize,
+	/// The size of the control data.
+	msg_controllen: usize,
+	/// Flags of the received message.
+	msg_flags: c_int,
+}
+
+/// A [`MsgHdr`] for compatibility mode.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct MsgHdrCompat {
+	/// Pointer to the address of the peer.
+	msg_name: u32,
+	/// The size of the address of the peer.
+	msg_namelen: u32,
+	/// Pointer to the IO vector.
+	msg_iov: u32,
+	/// The number of elements in the IO vector.
+	msg_iovlen: u32,
+	/// Pointer to the control data.
+	msg_control: u32,
+	/// The size of the control data.
+	msg_controllen: u32,
+	/// Flags of the received message.
+	msg_flags: c_int,
+}
+
+/// A [`MsgHdr`] as a system call argument.
+#[derive(Debug)]
+pub struct UserMsgHdr {
+	/// The address of the header.
+	ptr: usize,
+	/// Tells whether the userspace is in compatibility mode.
+	compat: bool,
+}
+
+impl FromSyscallArg for UserMsgHdr {
+	fn from_syscall_arg(ptr: usize, compat: bool) -> Self {
+		Self {
+			ptr,
+			compat,
+		}
+	}
+}
+
+impl UserMsgHdr {
+	/// Copies the header from userspace.
+	fn copy_from_user(&self) -> EResult<MsgHdr> {
+		if !self.compat {
+			return UserPtr::<MsgHdr>::from_ptr(self.ptr)
+				.copy_from_user()?
+				.ok_or_else(|| errno!(EFAULT));
+		}
+		let hdr = UserPtr::<MsgHdrCompat>::from_ptr(self.ptr)
+			.copy_from_user()?
+			.ok_or_else(|| errno!(EFAULT))?;
+		Ok(MsgHdr {
+			msg_name: hdr.msg_name as _,
+			msg_namelen: hdr.msg_namelen,
+			msg_iov: hdr.msg_iov as _,
+			msg_iovlen: hdr.msg_iovlen as _,
+			msg_control: hdr.msg_control as _,
+			msg_controllen: hdr.msg_controllen as _,
+			msg_flags: hdr.msg_flags,
+		})
+	}
+
+	/// Copies the header `hdr` to userspace.
+	fn copy_to_user(&self, hdr: &MsgHdr) -> EResult<()> {
+		if !self.compat {
+			return UserPtr::<MsgHdr>::from_ptr(self.ptr).copy_to_user(hdr);
+		}
+		UserPtr::<MsgHdrCompat>::from_ptr(self.ptr).copy_to_user(&MsgHdrCompat {
+			msg_name: hdr.msg_name as _,
+			msg_namelen: hdr.msg_namelen,
+			msg_iov: hdr.msg_iov as _,
+			msg_iovlen: hdr.msg_iovlen as _,
+			msg_control: hdr.msg_control as _,
+			msg_controllen: hdr.msg_controllen as _,
+			msg_flags: hdr.msg_flags,
+		})
+	}
+
+	/// Returns the size of a control message header and the alignment of control messages.
+	fn cmsg_layout(&self) -> (usize, usize) {
+		if self.compat { (12, 4) } else { (16, 8) }
+	}
+
+	/// Imports the IO vector of the header `hdr`.
+	fn import_iov(&self, hdr: &MsgHdr) -> EResult<Vec<IOVec>> {
+		let count = hdr.msg_iovlen.try_into().map_err(|_| errno!(EMSGSIZE))?;
+		UserIOVec::from_syscall_arg(hdr.msg_iov, self.compat).import(count)
+	}
+
+	/// Parses the control messages of the header `hdr` to be sent.
+	fn parse_control(&self, hdr: &MsgHdr) -> EResult<Ancillary> {
+		let mut anc = Ancillary::default();
+		if hdr.msg_controllen == 0 {
+			return Ok(anc);
+		}
+		if unlikely(hdr.msg_controllen > OPTMEM_MAX) {
+			return Err(errno!(ENOBUFS));
+		}
+		let buf = UserSlice::<u8>::from_user(
+			ptr::with_exposed_provenance_mut(hdr.msg_control),
+			hdr.msg_controllen,
+		)?
+		.copy_from_user_vec(0)?
+		.ok_or_else(|| errno!(EFAULT))?;
+		let (hdr_size, align) = self.cmsg_layout();
+		let int = |off: usize| c_int::from_ne_bytes(buf[off..(off + 4)].try_into().unwrap());
+		let mut off = 0;
+		while off + hdr_size <= buf.len() {
+			let len = if self.compat {
+				int(off) as u32 as usize
+			} else {
+				u64::from_ne_bytes(buf[off..(off + 8)].try_into().unwrap()) as usize
+			};
+			if unlikely(len < hdr_size || len > buf.len() - off) {
+				return Err(errno!(EINVAL));
+			}
+			let level = int(off + hdr_size - 8);
+			let r#type = int(off + hdr_size - 4);
+			let data = (off + hdr_size)..(off + len);
+			match (level, r#type) {
+				(SOL_SOCKET, SCM_RIGHTS) => {
+					let count = data.len() / 4;
+					if unlikely(data.len() % 4 != 0 || anc.rights.len() + count > SCM_MAX_FD) {
+						return Err(errno!(EINVAL));
+					}
+					for fd in data.step_by(4) {
+						anc.rights.push(fd_to_file(int(fd))?)?;
+					}
+				}
+				(SOL_SOCKET, SCM_CREDENTIALS) => {
+					if unlikely(data.len() != size_of::<UCred>()) {
+						return Err(errno!(EINVAL));
+					}
+					let cred = UCred {
+						pid: int(data.start),
+						uid: int(data.start + 4) as _,
+						gid: int(data.start + 8) as _,
+					};
+					check_cred(&cred)?;
+					anc.cred = Some(cred);
+				}
+				_ => return Err(errno!(EINVAL)),
+			}
+			off += len.next_multiple_of(align);
+		}
+		Ok(anc)
+	}
+
+	/// Writes the control messages for the received ancillary data `anc` to the control buffer
+	/// of `hdr`, updating it.
+	///
+	/// File descriptors passed along with the message are installed in the current process.
+	/// Those which do not fit in the control buffer are closed.
+	fn write_control(&self, hdr: &mut MsgHdr, anc: Ancillary, flags: c_int) -> EResult<()> {
+		let cap = hdr.msg_controllen;
+		let (hdr_size, align) = self.cmsg_layout();
+		let mut out = Vec::new();
+		let put = |out: &mut Vec<u8>, r#type: c_int, data: &[u8]| -> AllocResult<bool> {
+			let len = hdr_size + data.len();
+			if out.len() + len > cap {
+				return Ok(false);
+			}
+			if self.compat {
+				out.extend_from_slice(&(len as u32).to_ne_bytes())?;
+			} else {
+				out.extend_from_slice(&(len as u64).to_ne_bytes())?;
+			}
+			out.extend_from_slice(&SOL_SOCKET.to_ne_bytes())?;
+			out.extend_from_slice(&r#type.to_ne_bytes())?;
+			out.extend_from_slice(data)?;
+			// Padding is not required after the last message
+			out.resize(min(out.len().next_multiple_of(align), cap), 0)?;
+			Ok(true)
+		};
+		let mut truncated = false;
+		if let Some(cred) = anc.cred {
+			let mut data = [0u8; size_of::<UCred>()];
+			data[..4].copy_from_slice(&cred.pid.to_ne_bytes());
+			data[4..8].copy_from_slice(&cred.uid.to_ne_bytes());
+			data[8..].copy_from_slice(&cred.gid.to_ne_bytes());
+			truncated |= !put(&mut out, SCM_CREDENTIALS, &data)?;
+		}
+		if !anc.rights.is_empty() {
+			let count = min(
+				anc.rights.len(),
+				cap.saturating_sub(out.len() + hdr_size) / size_of::<c_int>(),
+			);
+			truncated |= count < anc.rights.len();
+			let fd_flags = if flags & MSG_CMSG_CLOEXEC != 0 {
+				FD_CLOEXEC
+			} else {
+				0
+			};
+			let mut data = Vec::new();
+			{
+				let fds = Process::current().file_descriptors();
+				for file in &anc.rights[..count] {
+					let Ok(fd) = fds.create_fd(fd_flags, file.clone()) else {
+						truncated = true;
+						break;
+					};
+					data.extend_from_slice(&(fd as c_int).to_ne_bytes())?;
+				}
+			}
+			if !data.is_empty() {
+				put(&mut out, SCM_RIGHTS, &data)?;
+			}
+		}
+		// Files that have not been installed are closed when `anc` is dropped
+		UserSlice::<u8>::from_user(ptr::with_exposed_provenance_mut(hdr.msg_control), cap)?
+			.copy_to_user(0, &out)?;
+		hdr.msg_controllen = out.len();
+		if truncated {
+			hdr.msg_flags |= MSG_CTRUNC;
+		}
+		Ok(())
+	}
+}
+
+/// Checks the current process is allowed to send the credentials `cred`.
+fn check_cred(cred: &UCred) -> EResult<()> {
+	let proc = Process::current();
+	let creds = proc.cred();
+	if creds.is_privileged() {
+		return Ok(());
+	}
+	let ap = &creds.ap;
+	let uid_ok = [ap.uid, ap.euid, ap.suid]
+		.iter()
+		.any(|uid| *uid as u32 == cred.uid);
+	let gid_ok = [ap.gid, ap.egid, ap.sgid]
+		.iter()
+		.any(|gid| *gid as u32 == cred.gid);
+	if unlikely(cred.pid != proc.get_pid() as i32 || !uid_ok || !gid_ok) {
+		return Err(errno!(EPERM));
+	}
+	Ok(())
+}
+
+pub fn sendmsg(sockfd: c_int, msg: UserMsgHdr, flags: c_int) -> EResult<usize> {
+	let file = fd_to_file(sockfd)?;
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	let hdr = msg.copy_from_user()?;
+	let dest = copy_dest_addr(
+		ptr::with_exposed_provenance_mut(hdr.msg_name),
+		hdr.msg_namelen as _,
+	)?;
+	let iov = msg.import_iov(&hdr)?;
+	let anc = msg.parse_control(&hdr)?;
+	let nonblock = file.get_flags() & O_NONBLOCK != 0 || flags & MSG_DONTWAIT != 0;
+	sock.send(&iov, dest.as_deref(), anc, flags, nonblock)
+}
+
+pub fn recvmsg(sockfd: c_int, msg: UserMsgHdr, flags: c_int) -> EResult<usize> {
+	let file = fd_to_file(sockfd)?;
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	let mut hdr = msg.copy_from_user()?;
+	let iov = msg.import_iov(&hdr)?;
+	let nonblock = file.get_flags() & O_NONBLOCK != 0 || flags & MSG_DONTWAIT != 0;
+	let received = sock.recv(&iov, flags, nonblock)?;
+	// Connected sockets have no address to report
+	let namelen = min(hdr.msg_namelen as usize, received.name.len());
+	if !received.name.is_empty() && hdr.msg_name != 0 {
+		UserSlice::from_user(ptr::with_exposed_provenance_mut(hdr.msg_name), namelen)?
+			.copy_to_user(0, &received.name[..namelen])?;
+	}
+	hdr.msg_namelen = received.name.len() as _;
+	hdr.msg_flags = received.flags;
+	msg.write_control(&mut hdr, received.anc, flags)?;
+	msg.copy_to_user(&hdr)?;
+	Ok(received.len)
+}
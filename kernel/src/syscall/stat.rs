@@ -20,15 +20,28 @@
 
 use crate::{
 	device::id::{major, makedev, minor},
-	file::{INode, Stat, fd::fd_to_file, fs::Statfs, vfs, vfs::Resolved},
+	file::{
+		INode, Stat,
+		fd::fd_to_file,
+		fs::Statfs,
+		vfs,
+		vfs::{Resolved, mountpoint},
+	},
 	memory::user::{UserPtr, UserString},
-	syscall::util::at,
+	syscall::util::{
+		at,
+		at::{
+			AT_EMPTY_PATH, AT_NO_AUTOMOUNT, AT_STATX_DONT_SYNC, AT_STATX_FORCE_SYNC,
+			AT_STATX_SYNC_TYPE, AT_SYMLINK_NOFOLLOW,
+		},
+	},
+	time::unit::{TimeUnit, Timespec, Timestamp},
 };
 use core::{
 	ffi::{c_int, c_uint},
 	hint::unlikely,
 };
-use utils::{errno, errno::EResult};
+use utils::{errno, errno::EResult, ptr::arc::Arc};
 
 /// Old structure representing the status of a file.
 #[derive(Debug)]
@@ -127,14 +140,78 @@ pub struct Stat64 {
 	st_ctime_nsec: u64,
 }
 
+/// Status of a file, 64 bit version for the 32-bit ABI.
+///
+/// Unlike [`Stat64`], this structure is packed and keeps a truncated copy of the inode number for
+/// backward compatibility.
+#[derive(Debug)]
+#[repr(C, packed)]
+pub struct CompatStat64 {
+	/// ID of the device containing the file
+	st_dev: u64,
+	/// Padding
+	pad0: [u8; 4],
+	/// The inode number, truncated to 32 bits
+	__st_ino: u32,
+	/// File mode
+	st_mode: u32,
+	/// Number of hard links to the file
+	st_nlink: u32,
+	/// User ID of the file's owner
+	st_uid: u32,
+	/// Group ID of the file's group
+	st_gid: u32,
+	/// Device ID (if device file)
+	st_rdev: u64,
+	/// Padding
+	pad3: [u8; 4],
+	/// Size of file, in bytes
+	st_size: i64,
+	/// Optimal block size for I/O
+	st_blksize: u32,
+	/// Number of 512-byte block allocated
+	st_blocks: u64,
+	/// Timestamp of last access (seconds)
+	st_atime: u32,
+	/// Timestamp of last access (nanoseconds)
+	st_atime_nsec: u32,
+	/// Timestamp of last modification of the content (seconds)
+	st_mtime: u32,
+	/// Timestamp of last modification of the content (nanoseconds)
+	st_mtime_nsec: u32,
+	/// Timestamp of last modification of the metadata (seconds)
+	st_ctime: u32,
+	/// Timestamp of last modification of the metadata (nanoseconds)
+	st_ctime_nsec: u32,
+	/// The inode number
+	st_ino: u64,
+}
+
 /// Extract device number and inode from [`vfs::Entry`].
 fn entry_info(entry: &vfs::Entry) -> (u64, INode) {
 	let node = entry.node();
 	(node.fs.dev, node.inode)
 }
 
+/// Returns the block size of the filesystem `entry` is located on, which is the optimal size for
+/// I/O.
+fn block_size(entry: &vfs::Entry) -> EResult<u32> {
+	Ok(entry.node().fs.ops.get_stat()?.block_size())
+}
+
+/// Checks that the inode number and size of a file fit in a structure with 32-bit fields.
+///
+/// If not, the function returns [`EOVERFLOW`](utils::errno::EOVERFLOW).
+fn check_stat32(stat: &Stat, st_ino: INode, ino_max: u64) -> EResult<()> {
+	if unlikely(st_ino > ino_max || stat.size > i32::MAX as u64) {
+		return Err(errno!(EOVERFLOW));
+	}
+	Ok(())
+}
+
 fn do_oldstat(stat: Stat, entry: &vfs::Entry, statbuf: UserPtr<OldStat>) -> EResult<()> {
 	let (st_dev, st_ino) = entry_info(entry);
+	check_stat32(&stat, st_ino, u16::MAX as _)?;
 	statbuf.copy_to_user(&OldStat {
 		st_dev: st_dev as _,
 		st_ino: st_ino as _,
@@ -144,14 +221,18 @@ fn do_oldstat(stat: Stat, entry: &vfs::Entry, statbuf: UserPtr<OldStat>) -> ERes
 		st_gid: stat.gid as _,
 		st_rdev: makedev(stat.dev_major, stat.dev_minor) as _,
 		st_size: stat.size as _,
-		st_atime: stat.atime as _,
-		st_mtime: stat.mtime as _,
-		st_ctime: stat.ctime as _,
+		st_atime: (stat.atime / 1_000_000_000) as _,
+		st_mtime: (stat.mtime / 1_000_000_000) as _,
+		st_ctime: (stat.ctime / 1_000_000_000) as _,
 	})
 }
 
 fn do_stat32(stat: Stat, entry: &vfs::Entry, statbuf: UserPtr<Stat32>) -> EResult<()> {
 	let (st_dev, st_ino) = entry_info(entry);
+	let atime = Timespec::from_nano(stat.atime);
+	let mtime = Timespec::from_nano(stat.mtime);
+	let ctime = Timespec::from_nano(stat.ctime);
+	check_stat32(&stat, st_ino, u32::MAX as _)?;
 	statbuf.copy_to_user(&Stat32 {
 		st_dev: st_dev as _,
 		st_ino: st_ino as _,
@@ -161,20 +242,23 @@ fn do_stat32(stat: Stat, entry: &vfs::Entry, statbuf: UserPtr<Stat32>) -> EResul
 		st_gid: stat.gid as _,
 		st_rdev: makedev(stat.dev_major, stat.dev_minor) as _,
 		st_size: stat.size as _,
-		st_blksize: 512, // TODO
+		st_blksize: block_size(entry)? as _,
 		st_blocks: stat.blocks as _,
-		st_atime: stat.atime as _,
-		st_atime_nsec: 0, // TODO
-		st_mtime: stat.mtime as _,
-		st_mtime_nsec: 0, // TODO
-		st_ctime: stat.ctime as _,
-		st_ctime_nsec: 0, // TODO
+		st_atime: atime.tv_sec as _,
+		st_atime_nsec: atime.tv_nsec as _,
+		st_mtime: mtime.tv_sec as _,
+		st_mtime_nsec: mtime.tv_nsec as _,
+		st_ctime: ctime.tv_sec as _,
+		st_ctime_nsec: ctime.tv_nsec as _,
 		padding: 0,
 	})
 }
 
 fn do_stat64(stat: Stat, entry: &vfs::Entry, statbuf: UserPtr<Stat64>) -> EResult<()> {
 	let (st_dev, st_ino) = entry_info(entry);
+	let atime = Timespec::from_nano(stat.atime);
+	let mtime = Timespec::from_nano(stat.mtime);
+	let ctime = Timespec::from_nano(stat.ctime);
 	statbuf.copy_to_user(&Stat64 {
 		st_dev,
 		st_ino,
@@ -185,14 +269,46 @@ fn do_stat64(stat: Stat, entry: &vfs::Entry, statbuf: UserPtr<Stat64>) -> EResul
 		pad0: 0,
 		st_rdev: makedev(stat.dev_major, stat.dev_minor),
 		st_size: stat.size as _,
-		st_blksize: 512, // TODO
+		st_blksize: block_size(entry)? as _,
 		st_blocks: stat.blocks as _,
-		st_atime: stat.atime,
-		st_atime_nsec: 0, // TODO
-		st_mtime: stat.mtime,
-		st_mtime_nsec: 0, // TODO
-		st_ctime: stat.ctime,
-		st_ctime_nsec: 0, // TODO
+		st_atime: atime.tv_sec as _,
+		st_atime_nsec: atime.tv_nsec as _,
+		st_mtime: mtime.tv_sec as _,
+		st_mtime_nsec: mtime.tv_nsec as _,
+		st_ctime: ctime.tv_sec as _,
+		st_ctime_nsec: ctime.tv_nsec as _,
+	})
+}
+
+fn do_compat_stat64(
+	stat: Stat,
+	entry: &vfs::Entry,
+	statbuf: UserPtr<CompatStat64>,
+) -> EResult<()> {
+	let (st_dev, st_ino) = entry_info(entry);
+	let atime = Timespec::from_nano(stat.atime);
+	let mtime = Timespec::from_nano(stat.mtime);
+	let ctime = Timespec::from_nano(stat.ctime);
+	statbuf.copy_to_user(&CompatStat64 {
+		st_dev,
+		pad0: [0; 4],
+		__st_ino: st_ino as _,
+		st_mode: stat.mode as _,
+		st_nlink: stat.nlink as _,
+		st_uid: stat.uid as _,
+		st_gid: stat.gid as _,
+		st_rdev: makedev(stat.dev_major, stat.dev_minor),
+		pad3: [0; 4],
+		st_size: stat.size as _,
+		st_blksize: block_size(entry)? as _,
+		st_blocks: stat.blocks,
+		st_atime: atime.tv_sec as _,
+		st_atime_nsec: atime.tv_nsec as _,
+		st_mtime: mtime.tv_sec as _,
+		st_mtime_nsec: mtime.tv_nsec as _,
+		st_ctime: ctime.tv_sec as _,
+		st_ctime_nsec: ctime.tv_nsec as _,
+		st_ino,
 	})
 }
 
@@ -230,6 +346,13 @@ pub fn stat64(pathname: UserString, statbuf: UserPtr<Stat64>) -> EResult<usize>
 	Ok(0)
 }
 
+pub fn compat_stat64(pathname: UserString, statbuf: UserPtr<CompatStat64>) -> EResult<usize> {
+	let pathname = pathname.copy_path_from_user()?;
+	let ent = vfs::get_file_from_path(&pathname, true)?;
+	do_compat_stat64(ent.stat(), &ent, statbuf)?;
+	Ok(0)
+}
+
 pub fn fstat(fd: c_int, statbuf: UserPtr<Stat32>) -> EResult<usize> {
 	let file = fd_to_file(fd)?;
 	do_stat32(file.stat(), &file.vfs_entry, statbuf)?;
@@ -242,6 +365,12 @@ pub fn fstat64(fd: c_int, statbuf: UserPtr<Stat64>) -> EResult<usize> {
 	Ok(0)
 }
 
+pub fn compat_fstat64(fd: c_int, statbuf: UserPtr<CompatStat64>) -> EResult<usize> {
+	let file = fd_to_file(fd)?;
+	do_compat_stat64(file.stat(), &file.vfs_entry, statbuf)?;
+	Ok(0)
+}
+
 pub fn lstat(pathname: UserString, statbuf: UserPtr<Stat32>) -> EResult<usize> {
 	let pathname = pathname.copy_path_from_user()?;
 	let ent = vfs::get_file_from_path(&pathname, false)?;
@@ -256,20 +385,48 @@ pub fn lstat64(pathname: UserString, statbuf: UserPtr<Stat64>) -> EResult<usize>
 	Ok(0)
 }
 
+pub fn compat_lstat64(pathname: UserString, statbuf: UserPtr<CompatStat64>) -> EResult<usize> {
+	let pathname = pathname.copy_path_from_user()?;
+	let ent = vfs::get_file_from_path(&pathname, false)?;
+	do_compat_stat64(ent.stat(), &ent, statbuf)?;
+	Ok(0)
+}
+
+/// Returns the file targeted by a `fstatat`-like system call.
+fn fstatat_entry(dirfd: c_int, path: UserString, flags: c_int) -> EResult<Arc<vfs::Entry>> {
+	const FLAGS: c_int = AT_SYMLINK_NOFOLLOW | AT_EMPTY_PATH | AT_NO_AUTOMOUNT;
+	if unlikely(flags & !FLAGS != 0) {
+		return Err(errno!(EINVAL));
+	}
+	let path = path.copy_path_from_user()?;
+	let Resolved::Found(ent) = at::get_file(dirfd, &path, flags, false, true)? else {
+		unreachable!();
+	};
+	Ok(ent)
+}
+
 pub fn fstatat64(
 	dirfd: c_int,
 	path: UserString,
 	statbuf: UserPtr<Stat64>,
 	flags: c_int,
 ) -> EResult<usize> {
-	let path = path.copy_path_from_user()?;
-	let Resolved::Found(ent) = at::get_file(dirfd, &path, flags, false, true)? else {
-		unreachable!();
-	};
+	let ent = fstatat_entry(dirfd, path, flags)?;
 	do_stat64(ent.stat(), &ent, statbuf)?;
 	Ok(0)
 }
 
+pub fn compat_fstatat64(
+	dirfd: c_int,
+	path: UserString,
+	statbuf: UserPtr<CompatStat64>,
+	flags: c_int,
+) -> EResult<usize> {
+	let ent = fstatat_entry(dirfd, path, flags)?;
+	do_compat_stat64(ent.stat(), &ent, statbuf)?;
+	Ok(0)
+}
+
 pub fn newfstatat(
 	dirfd: c_int,
 	path: UserString,
@@ -279,6 +436,13 @@ pub fn newfstatat(
 	fstatat64(dirfd, path, statbuf, flags)
 }
 
+/// `statx` mask: The fields also returned by `stat`.
+const STATX_BASIC_STATS: c_uint = 0x7ff;
+/// `statx` mask: The creation timestamp.
+const STATX_BTIME: c_uint = 0x800;
+/// `statx` mask: Reserved for future extensions.
+const STATX__RESERVED: c_uint = 0x80000000;
+
 /// A timestamp for the [`statx`] syscall.
 #[derive(Debug)]
 #[repr(C)]
@@ -291,6 +455,17 @@ struct StatxTimestamp {
 	__reserved: i32,
 }
 
+impl From<Timestamp> for StatxTimestamp {
+	fn from(ts: Timestamp) -> Self {
+		let ts = Timespec::from_nano(ts);
+		Self {
+			tv_sec: ts.tv_sec as _,
+			tv_nsec: ts.tv_nsec as _,
+			__reserved: 0,
+		}
+	}
+}
+
 /// Status of a file, extended.
 #[derive(Debug)]
 #[repr(C)]
@@ -357,31 +532,51 @@ pub fn statx(
 	dirfd: c_int,
 	pathname: UserString,
 	flags: c_int,
-	_mask: c_uint,
+	mask: c_uint,
 	statxbuff: UserPtr<Statx>,
 ) -> EResult<usize> {
 	// Validation
 	if unlikely(pathname.0.is_none() || statxbuff.0.is_none()) {
 		return Err(errno!(EINVAL));
 	}
-	// TODO Implement all flags
+	const FLAGS: c_int = AT_SYMLINK_NOFOLLOW
+		| AT_EMPTY_PATH
+		| AT_NO_AUTOMOUNT
+		| AT_STATX_FORCE_SYNC
+		| AT_STATX_DONT_SYNC;
+	if unlikely(flags & !FLAGS != 0 || mask & STATX__RESERVED != 0) {
+		return Err(errno!(EINVAL));
+	}
+	if unlikely(flags & AT_STATX_SYNC_TYPE == AT_STATX_SYNC_TYPE) {
+		return Err(errno!(EINVAL));
+	}
 	// Get the file
 	let pathname = pathname.copy_path_from_user()?;
 	let Resolved::Found(file) = at::get_file(dirfd, &pathname, flags, false, true)? else {
 		unreachable!();
 	};
-	// Get file's stat
+	let node = file.node();
+	// The status is always kept up to date in memory. Forcing synchronization writes back cached
+	// data, so that the status also matches the storage
+	if flags & AT_STATX_FORCE_SYNC != 0 {
+		node.sync_data()?;
+	}
 	let stat = file.stat();
-	// TODO Use mask?
+	// Fields are filled whether requested or not
+	let mut stx_mask = STATX_BASIC_STATS;
+	if stat.btime.is_some() {
+		stx_mask |= STATX_BTIME;
+	}
+	let stx_attributes_mask = node.fs.ops.supported_attributes();
 	// Get the major and minor numbers of the device of the file's filesystem
 	let (stx_dev, stx_ino) = entry_info(&file);
 	let stx_dev_minor = minor(stx_dev);
 	let stx_dev_major = major(stx_dev);
 	// Write
 	statxbuff.copy_to_user(&Statx {
-		stx_mask: !0,      // TODO
-		stx_blksize: 512,  // TODO
-		stx_attributes: 0, // TODO
+		stx_mask,
+		stx_blksize: block_size(&file)?,
+		stx_attributes: stat.attributes & stx_attributes_mask,
 		stx_nlink: stat.nlink as _,
 		stx_uid: stat.uid as _,
 		stx_gid: stat.gid as _,
@@ -390,27 +585,11 @@ pub fn statx(
 		stx_ino,
 		stx_size: stat.size,
 		stx_blocks: stat.blocks,
-		stx_attributes_mask: 0, // TODO
-		stx_atime: StatxTimestamp {
-			tv_sec: stat.atime as _,
-			tv_nsec: 0, // TODO
-			__reserved: 0,
-		},
-		stx_btime: StatxTimestamp {
-			tv_sec: 0,  // TODO
-			tv_nsec: 0, // TODO
-			__reserved: 0,
-		},
-		stx_ctime: StatxTimestamp {
-			tv_sec: stat.ctime as _,
-			tv_nsec: 0, // TODO
-			__reserved: 0,
-		},
-		stx_mtime: StatxTimestamp {
-			tv_sec: stat.mtime as _,
-			tv_nsec: 0, // TODO
-			__reserved: 0,
-		},
+		stx_attributes_mask,
+		stx_atime: StatxTimestamp::from(stat.atime),
+		stx_btime: StatxTimestamp::from(stat.btime.unwrap_or(0)),
+		stx_ctime: StatxTimestamp::from(stat.ctime),
+		stx_mtime: StatxTimestamp::from(stat.mtime),
 		stx_rdev_major: stat.dev_major,
 		stx_rdev_minor: stat.dev_minor,
 		stx_dev_major,
@@ -428,13 +607,16 @@ pub fn statx(
 	Ok(0)
 }
 
+/// Returns the statistics of the filesystem on which `ent` is located.
+fn get_statfs(ent: &Arc<vfs::Entry>) -> EResult<Statfs> {
+	let mut stat = ent.node().fs.ops.get_stat()?;
+	stat.set_mount_flags(mountpoint::get_flags(ent));
+	Ok(stat)
+}
+
 pub(super) fn do_statfs(path: UserString, buf: UserPtr<Statfs>) -> EResult<usize> {
 	let path = path.copy_path_from_user()?;
-	let stat = vfs::get_file_from_path(&path, false)?
-		.node()
-		.fs
-		.ops
-		.get_stat()?;
+	let stat = get_statfs(&vfs::get_file_from_path(&path, false)?)?;
 	// Write structure to userspace
 	buf.copy_to_user(&stat)?;
 	Ok(0)
@@ -453,7 +635,7 @@ pub fn statfs64(path: UserString, _sz: usize, buf: UserPtr<Statfs>) -> EResult<u
 /// Performs the `fstatfs` system call.
 pub fn do_fstatfs(fd: c_int, _sz: usize, buf: UserPtr<Statfs>) -> EResult<usize> {
 	// TODO use `sz`
-	let stat = fd_to_file(fd)?.vfs_entry.node().fs.ops.get_stat()?;
+	let stat = get_statfs(&fd_to_file(fd)?.vfs_entry)?;
 	buf.copy_to_user(&stat)?;
 	Ok(0)
 }
@@ -19,11 +19,14 @@
 //! Filesystem synchronization system calls.
 
 use crate::{
-	file::{fd::fd_to_file, vfs::mountpoint::FILESYSTEMS},
+	file::{FileType, fd::fd_to_file, vfs::mountpoint::FILESYSTEMS},
 	memory::VirtAddr,
 	process::Process,
 };
-use core::{ffi::c_int, hint::unlikely};
+use core::{
+	ffi::{c_int, c_uint},
+	hint::unlikely,
+};
 use utils::{errno, errno::EResult, limits::PAGE_SIZE};
 
 /// Schedules a synchronization and returns directly
@@ -33,6 +36,13 @@ const MS_SYNC: i32 = 0b010;
 /// Invalidates other mappings of the same file, so they can be updated
 const MS_INVALIDATE: i32 = 0b100;
 
+/// Waits for the writeback of pages in the range before writing
+const SYNC_FILE_RANGE_WAIT_BEFORE: c_uint = 0b001;
+/// Starts the writeback of dirty pages in the range
+const SYNC_FILE_RANGE_WRITE: c_uint = 0b010;
+/// Waits for the writeback of pages in the range after writing
+const SYNC_FILE_RANGE_WAIT_AFTER: c_uint = 0b100;
+
 pub fn sync() -> EResult<usize> {
 	let fs = FILESYSTEMS.lock();
 	for (_, fs) in fs.iter() {
@@ -74,6 +84,62 @@ pub fn fdatasync(fd: c_int) -> EResult<usize> {
 	do_fsync(fd, false)
 }
 
+pub fn compat_sync_file_range(
+	fd: c_int,
+	offset_low: u32,
+	offset_high: u32,
+	nbytes_low: u32,
+	nbytes_high: u32,
+	flags: c_uint,
+) -> EResult<usize> {
+	let offset = ((offset_high as u64) << 32) | (offset_low as u64);
+	let nbytes = ((nbytes_high as u64) << 32) | (nbytes_low as u64);
+	sync_file_range(fd, offset as _, nbytes as _, flags)
+}
+
+pub fn sync_file_range(fd: c_int, offset: i64, nbytes: i64, flags: c_uint) -> EResult<usize> {
+	if unlikely(
+		flags
+			& !(SYNC_FILE_RANGE_WAIT_BEFORE | SYNC_FILE_RANGE_WRITE | SYNC_FILE_RANGE_WAIT_AFTER)
+			!= 0,
+	) {
+		return Err(errno!(EINVAL));
+	}
+	if unlikely(offset < 0 || nbytes < 0) {
+		return Err(errno!(EINVAL));
+	}
+	let end = offset.checked_add(nbytes).ok_or_else(|| errno!(EINVAL))?;
+	if unlikely(fd < 0) {
+		return Err(errno!(EBADF));
+	}
+	let file = fd_to_file(fd)?;
+	let file_type = file.stat().get_type();
+	if !matches!(
+		file_type,
+		Some(FileType::Regular | FileType::Directory | FileType::Link | FileType::BlockDevice)
+	) {
+		return Err(errno!(ESPIPE));
+	}
+	// Convert to a range of pages. A length of zero means until the end of the file
+	let start = offset as u64 / PAGE_SIZE as u64;
+	let end = if nbytes == 0 {
+		u64::MAX
+	} else {
+		(end as u64).div_ceil(PAGE_SIZE as u64)
+	};
+	let mapped = &file.node().mapped;
+	if flags & SYNC_FILE_RANGE_WAIT_BEFORE != 0 {
+		mapped.wait_range(start, end);
+	}
+	if flags & SYNC_FILE_RANGE_WRITE != 0 {
+		mapped.sync_range(start, end)?;
+	}
+	if flags & SYNC_FILE_RANGE_WAIT_AFTER != 0 {
+		mapped.wait_range(start, end);
+	}
+	Ok(0)
+}
+
 pub fn msync(addr: VirtAddr, length: usize, flags: c_int) -> EResult<usize> {
 	// Check address alignment
 	if !addr.is_aligned_to(PAGE_SIZE) {
@@ -0,0 +1,105 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `syslog` system call allows to read and control the kernel logs buffer.
+
+use crate::{
+	file::perm::is_privileged,
+	logger,
+	logger::{BUF, CONSOLE_LEVEL, DEFAULT_CONSOLE_LEVEL, LOGS_SIZE},
+	memory::user::UserSlice,
+};
+use core::{ffi::c_int, hint::unlikely, sync::atomic::Ordering::Relaxed};
+use utils::{errno, errno::EResult};
+
+/// Action: close the log. Does nothing.
+const SYSLOG_ACTION_CLOSE: c_int = 0;
+/// Action: open the log. Does nothing.
+const SYSLOG_ACTION_OPEN: c_int = 1;
+/// Action: read logs, waiting until some are available, and mark them as consumed.
+const SYSLOG_ACTION_READ: c_int = 2;
+/// Action: read the last logs.
+const SYSLOG_ACTION_READ_ALL: c_int = 3;
+/// Action: read the last logs, then clear the buffer.
+const SYSLOG_ACTION_READ_CLEAR: c_int = 4;
+/// Action: clear the buffer.
+const SYSLOG_ACTION_CLEAR: c_int = 5;
+/// Action: disable printing logs on the console.
+const SYSLOG_ACTION_CONSOLE_OFF: c_int = 6;
+/// Action: enable printing logs on the console.
+const SYSLOG_ACTION_CONSOLE_ON: c_int = 7;
+/// Action: set the console log level.
+const SYSLOG_ACTION_CONSOLE_LEVEL: c_int = 8;
+/// Action: return the number of bytes that have not been consumed by
+/// [`SYSLOG_ACTION_READ`].
+const SYSLOG_ACTION_SIZE_UNREAD: c_int = 9;
+/// Action: return the size of the buffer.
+const SYSLOG_ACTION_SIZE_BUFFER: c_int = 10;
+
+/// The console log level set by [`SYSLOG_ACTION_CONSOLE_OFF`]: only emergency messages are
+/// printed.
+const CONSOLE_LEVEL_OFF: u8 = 1;
+
+/// Returns the userspace buffer for a read action.
+fn read_buf(buf: *mut u8, len: c_int) -> EResult<UserSlice<'static, u8>> {
+	let len = len.try_into().map_err(|_| errno!(EINVAL))?;
+	UserSlice::from_user(buf, len)
+}
+
+pub fn syslog(type_: c_int, buf: *mut u8, len: c_int) -> EResult<usize> {
+	// Reading the whole buffer and its size is allowed to everyone
+	let unprivileged = matches!(type_, SYSLOG_ACTION_READ_ALL | SYSLOG_ACTION_SIZE_BUFFER);
+	if unlikely(!unprivileged && !is_privileged()) {
+		return Err(errno!(EPERM));
+	}
+	match type_ {
+		SYSLOG_ACTION_CLOSE | SYSLOG_ACTION_OPEN => Ok(0),
+		SYSLOG_ACTION_READ => logger::consume(read_buf(buf, len)?),
+		SYSLOG_ACTION_READ_ALL => {
+			logger::copy_to_user(read_buf(buf, len)?, |logs, buf| logs.read_last(buf))
+		}
+		SYSLOG_ACTION_READ_CLEAR => logger::copy_to_user(read_buf(buf, len)?, |logs, buf| {
+			let len = logs.read_last(buf);
+			logs.clear();
+			len
+		}),
+		SYSLOG_ACTION_CLEAR => {
+			BUF.lock().clear();
+			Ok(0)
+		}
+		SYSLOG_ACTION_CONSOLE_OFF => {
+			CONSOLE_LEVEL.store(CONSOLE_LEVEL_OFF, Relaxed);
+			Ok(0)
+		}
+		SYSLOG_ACTION_CONSOLE_ON => {
+			CONSOLE_LEVEL.store(DEFAULT_CONSOLE_LEVEL, Relaxed);
+			Ok(0)
+		}
+		SYSLOG_ACTION_CONSOLE_LEVEL => {
+			// The level is passed in `len`
+			if unlikely(!(1..=DEFAULT_CONSOLE_LEVEL as c_int).contains(&len)) {
+				return Err(errno!(EINVAL));
+			}
+			CONSOLE_LEVEL.store(len as _, Relaxed);
+			Ok(0)
+		}
+		SYSLOG_ACTION_SIZE_UNREAD => Ok(BUF.lock().unread_len()),
+		SYSLOG_ACTION_SIZE_BUFFER => Ok(LOGS_SIZE),
+		_ => Err(errno!(EINVAL)),
+	}
+}
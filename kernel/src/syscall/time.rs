@@ -16,23 +16,34 @@
  * Maestro. If not, see <https://www.gnu.org/licenses/>.
  */
 
-//! The `time` syscall allows to retrieve the number of seconds elapsed since
-//! the UNIX Epoch.
+//! Time-related system calls: clocks, sleeps and timers.
 
 use crate::{
+	file::perm::is_privileged,
 	memory::user::UserPtr,
 	process::{
 		Process,
 		signal::{SIGEV_SIGNAL, SigEvent, Signal},
 	},
+	sync::spin::IntSpin,
 	time::{
+		clock,
 		clock::{Clock, current_time_ns, current_time_sec},
-		sleep_for,
-		timer::TimerManager,
-		unit::{ClockIdT, ITimerspec, ITimerspec32, TimeUnit, TimerT, Timespec, Timespec32},
+		set_time,
+		timeout::Timeout,
+		timer::{ITIMER_REAL, TimerManager},
+		to_clock_ticks,
+		unit::{
+			ClockIdT, ITimerspec, ITimerspec32, ITimerval, ITimerval32, TimeUnit, TimerT,
+			Timespec, Timespec32, Timestamp, Timeval, Timeval32, Timezone, Tms, Tms32,
+		},
 	},
 };
-use core::ffi::c_int;
+use core::{
+	cmp::max,
+	ffi::{c_int, c_uint},
+	hint::unlikely,
+};
 use utils::{errno, errno::EResult};
 
 /// If set, the specified time is *not* relative to the timer's current counter.
@@ -51,10 +62,10 @@ pub fn time64(tloc: UserPtr<u64>) -> EResult<usize> {
 	Ok(time as _)
 }
 
-pub fn clock_gettime(clockid: ClockIdT, tp: UserPtr<Timespec>) -> EResult<usize> {
+pub fn clock_gettime(clockid: ClockIdT, tp: UserPtr<Timespec32>) -> EResult<usize> {
 	let clk = Clock::from_id(clockid).ok_or_else(|| errno!(EINVAL))?;
 	let ts = current_time_ns(clk);
-	tp.copy_to_user(&Timespec::from_nano(ts))?;
+	tp.copy_to_user(&Timespec32::from_nano(ts))?;
 	Ok(0)
 }
 
@@ -65,36 +76,187 @@ pub fn clock_gettime64(clockid: ClockIdT, tp: UserPtr<Timespec>) -> EResult<usiz
 	Ok(0)
 }
 
-pub fn nanosleep32(req: UserPtr<Timespec32>, rem: UserPtr<Timespec32>) -> EResult<usize> {
-	let delay = req
-		.copy_from_user()?
-		.ok_or_else(|| errno!(EFAULT))?
-		.to_nano();
-	let mut remain = 0;
-	let res = sleep_for(Clock::Monotonic, delay, &mut remain);
-	match res {
-		Ok(_) => Ok(0),
-		Err(e) => {
-			rem.copy_to_user(&Timespec32::from_nano(remain))?;
-			Err(e)
+/// Common implementation of `times`, returning the process's times in clock ticks.
+fn do_times() -> Tms {
+	let proc = Process::current();
+	let (utime, stime) = proc.cputime.get();
+	let (cutime, cstime) = proc.cputime.children();
+	Tms {
+		tms_utime: to_clock_ticks(utime),
+		tms_stime: to_clock_ticks(stime),
+		tms_cutime: to_clock_ticks(cutime),
+		tms_cstime: to_clock_ticks(cstime),
+	}
+}
+
+/// 32-bit ABI: `clock_t` is 32 bits wide.
+pub fn compat_times(buf: UserPtr<Tms32>) -> EResult<usize> {
+	let tms = do_times();
+	buf.copy_to_user(&Tms32 {
+		tms_utime: tms.tms_utime as _,
+		tms_stime: tms.tms_stime as _,
+		tms_cutime: tms.tms_cutime as _,
+		tms_cstime: tms.tms_cstime as _,
+	})?;
+	Ok(to_clock_ticks(current_time_ns(Clock::Monotonic)) as u32 as _)
+}
+
+/// 64-bit ABI: `clock_t` is 64 bits wide.
+pub fn times(buf: UserPtr<Tms>) -> EResult<usize> {
+	buf.copy_to_user(&do_times())?;
+	Ok(to_clock_ticks(current_time_ns(Clock::Monotonic)) as _)
+}
+
+/// The system's timezone, set by `settimeofday`.
+static TIMEZONE: IntSpin<Timezone> = IntSpin::new(Timezone {
+	tz_minuteswest: 0,
+	tz_dsttime: 0,
+});
+
+pub fn gettimeofday(tv: UserPtr<Timeval32>, tz: UserPtr<Timezone>) -> EResult<usize> {
+	tv.copy_to_user(&Timeval32::from_nano(current_time_ns(Clock::Realtime)))?;
+	let timezone = *TIMEZONE.lock();
+	tz.copy_to_user(&timezone)?;
+	Ok(0)
+}
+
+pub fn gettimeofday64(tv: UserPtr<Timeval>, tz: UserPtr<Timezone>) -> EResult<usize> {
+	tv.copy_to_user(&Timeval::from_nano(current_time_ns(Clock::Realtime)))?;
+	let timezone = *TIMEZONE.lock();
+	tz.copy_to_user(&timezone)?;
+	Ok(0)
+}
+
+/// Common implementation of `settimeofday` parameterized over the timeval ABI.
+///
+/// `ts` is the new time in nanoseconds, if any. `tz` is the new timezone, if any.
+fn do_settimeofday(ts: Option<Timestamp>, tz: Option<Timezone>) -> EResult<usize> {
+	if unlikely(!is_privileged()) {
+		return Err(errno!(EPERM));
+	}
+	if let Some(tz) = tz {
+		if unlikely(!(-15 * 60..=15 * 60).contains(&tz.tz_minuteswest)) {
+			return Err(errno!(EINVAL));
 		}
+		*TIMEZONE.lock() = tz;
 	}
+	if let Some(ts) = ts {
+		set_time(ts);
+	}
+	Ok(0)
+}
+
+/// 32-bit ABI: `timeval` uses 32-bit `time_t` (`Timeval32`).
+pub fn settimeofday(tv: UserPtr<Timeval32>, tz: UserPtr<Timezone>) -> EResult<usize> {
+	let tv = tv.copy_from_user()?;
+	if unlikely(tv.is_some_and(|tv| tv.tv_usec >= 1_000_000)) {
+		return Err(errno!(EINVAL));
+	}
+	do_settimeofday(tv.map(|tv| tv.to_nano()), tz.copy_from_user()?)
+}
+
+/// 64-bit ABI: `timeval` uses 64-bit `time_t` (`Timeval`).
+pub fn settimeofday64(tv: UserPtr<Timeval>, tz: UserPtr<Timezone>) -> EResult<usize> {
+	let tv = tv.copy_from_user()?;
+	if unlikely(tv.is_some_and(|tv| tv.tv_usec >= 1_000_000)) {
+		return Err(errno!(EINVAL));
+	}
+	do_settimeofday(tv.map(|tv| tv.to_nano()), tz.copy_from_user()?)
+}
+
+/// Common implementation of `clock_settime`. `ts` is the new time in nanoseconds.
+fn do_clock_settime(clockid: ClockIdT, ts: Timestamp) -> EResult<usize> {
+	let clock = Clock::from_id(clockid).ok_or_else(|| errno!(EINVAL))?;
+	// Only the real time clock can be set
+	if !matches!(clock, Clock::Realtime) {
+		return Err(errno!(EINVAL));
+	}
+	if unlikely(!is_privileged()) {
+		return Err(errno!(EPERM));
+	}
+	set_time(ts);
+	Ok(0)
+}
+
+pub fn clock_settime(clockid: ClockIdT, tp: UserPtr<Timespec32>) -> EResult<usize> {
+	let ts = tp.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if unlikely(ts.tv_nsec >= 1_000_000_000) {
+		return Err(errno!(EINVAL));
+	}
+	do_clock_settime(clockid, ts.to_nano())
+}
+
+pub fn clock_settime64(clockid: ClockIdT, tp: UserPtr<Timespec>) -> EResult<usize> {
+	let ts = tp.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if unlikely(!(0..1_000_000_000).contains(&ts.tv_nsec)) {
+		return Err(errno!(EINVAL));
+	}
+	do_clock_settime(clockid, ts.to_nano())
+}
+
+pub fn clock_getres(clockid: ClockIdT, res: UserPtr<Timespec32>) -> EResult<usize> {
+	Clock::from_id(clockid).ok_or_else(|| errno!(EINVAL))?;
+	res.copy_to_user(&Timespec32::from_nano(clock::resolution()))?;
+	Ok(0)
+}
+
+pub fn clock_getres64(clockid: ClockIdT, res: UserPtr<Timespec>) -> EResult<usize> {
+	Clock::from_id(clockid).ok_or_else(|| errno!(EINVAL))?;
+	res.copy_to_user(&Timespec::from_nano(clock::resolution()))?;
+	Ok(0)
+}
+
+pub fn nanosleep32(req: UserPtr<Timespec32>, rem: UserPtr<Timespec32>) -> EResult<usize> {
+	do_clock_nanosleep(Clock::Monotonic, 0, req, rem)
 }
 
 pub fn nanosleep64(req: UserPtr<Timespec>, rem: UserPtr<Timespec>) -> EResult<usize> {
-	let delay = req
-		.copy_from_user()?
-		.ok_or_else(|| errno!(EFAULT))?
-		.to_nano();
-	let mut remain = 0;
-	let res = sleep_for(Clock::Monotonic, delay, &mut remain);
-	match res {
-		Ok(_) => Ok(0),
-		Err(e) => {
-			rem.copy_to_user(&Timespec::from_nano(remain))?;
-			Err(e)
-		}
+	do_clock_nanosleep(Clock::Monotonic, 0, req, rem)
+}
+
+/// Common implementation of `nanosleep` and `clock_nanosleep`, parameterized over the timespec
+/// ABI.
+///
+/// On interruption, the remaining time is written to `rem`, unless the request is absolute.
+fn do_clock_nanosleep<T: TimeUnit>(
+	clock: Clock,
+	flags: c_int,
+	req: UserPtr<T>,
+	rem: UserPtr<T>,
+) -> EResult<usize> {
+	if matches!(clock, Clock::ProcessCputimeId | Clock::ThreadCputimeId) {
+		return Err(errno!(EINVAL));
 	}
+	let req = req.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	let abs = flags & TIMER_ABSTIME != 0;
+	let timeout = Timeout::from_value(&req, clock, abs)?;
+	let res = timeout.sleep();
+	if res.is_err() && !abs {
+		timeout.write_remaining(rem)?;
+	}
+	res.map(|_| 0)
+}
+
+/// 32-bit ABI: the requested time uses 32-bit `time_t` (`Timespec32`).
+pub fn clock_nanosleep(
+	clockid: ClockIdT,
+	flags: c_int,
+	req: UserPtr<Timespec32>,
+	rem: UserPtr<Timespec32>,
+) -> EResult<usize> {
+	let clock = Clock::from_id(clockid).ok_or_else(|| errno!(EINVAL))?;
+	do_clock_nanosleep(clock, flags, req, rem)
+}
+
+/// 64-bit ABI: the requested time uses 64-bit `time_t` (`Timespec`).
+pub fn clock_nanosleep64(
+	clockid: ClockIdT,
+	flags: c_int,
+	req: UserPtr<Timespec>,
+	rem: UserPtr<Timespec>,
+) -> EResult<usize> {
+	let clock = Clock::from_id(clockid).ok_or_else(|| errno!(EINVAL))?;
+	do_clock_nanosleep(clock, flags, req, rem)
 }
 
 pub fn timer_create(
@@ -122,34 +284,35 @@ pub fn timer_delete(timerid: TimerT) -> EResult<usize> {
 	Ok(0)
 }
 
-/// Common implementation of `timer_settime` parameterized over the timespec ABI.
+/// Common implementation of `timer_settime`.
 ///
-/// `interval_ns` and `value_ns` are the new interval and initial-value of the timer in
-/// nanoseconds (caller must have decoded them from the appropriate `itimerspec` flavor).
-fn do_timer_settime(
-	timerid: TimerT,
-	flags: c_int,
-	interval_ns: u64,
-	value_ns: u64,
-	on_old: impl FnOnce(u64, u64) -> EResult<()>,
-) -> EResult<usize> {
+/// `new` is the new state of the timer, decoded from the appropriate `itimerspec` flavor.
+///
+/// On success, the function returns the previous state of the timer.
+fn do_timer_settime(timerid: TimerT, flags: c_int, new: ITimerspec) -> EResult<ITimerspec> {
+	if unlikely(!new.it_interval.is_valid() || !new.it_value.is_valid()) {
+		return Err(errno!(EINVAL));
+	}
 	let proc = Process::current();
 	let mut manager = proc.timer_manager.lock();
 	let timer = manager
 		.get_timer_mut(timerid)
 		.ok_or_else(|| errno!(EINVAL))?;
-	let (old_interval, old_value_ns) = timer.get_time();
-	on_old(old_interval, old_value_ns)?;
+	let (old_interval, old_value) = timer.get_time();
 	// Convert absolute timeouts (TIMER_ABSTIME) to a relative delay; relative timeouts pass
 	// through unchanged.
+	let value = new.it_value.to_nano();
 	let value = if flags & TIMER_ABSTIME != 0 {
 		let now = current_time_ns(Clock::Monotonic);
-		value_ns.saturating_sub(now)
+		value.saturating_sub(now)
 	} else {
-		value_ns
+		value
 	};
-	timer.set_time(interval_ns, value)?;
-	Ok(0)
+	timer.set_time(new.it_interval.to_nano(), value)?;
+	Ok(ITimerspec {
+		it_interval: Timespec::from_nano(old_interval),
+		it_value: Timespec::from_nano(old_value),
+	})
 }
 
 /// 32-bit ABI: `itimerspec` uses 32-bit `time_t` (`Timespec32`).
@@ -160,18 +323,9 @@ pub fn timer_settime(
 	old_value: UserPtr<ITimerspec32>,
 ) -> EResult<usize> {
 	let new = new_value.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
-	do_timer_settime(
-		timerid,
-		flags,
-		new.it_interval.to_nano(),
-		new.it_value.to_nano(),
-		|old_interval, old_value_ns| {
-			old_value.copy_to_user(&ITimerspec32 {
-				it_interval: Timespec32::from_nano(old_interval),
-				it_value: Timespec32::from_nano(old_value_ns),
-			})
-		},
-	)
+	let old = do_timer_settime(timerid, flags, new.into())?;
+	old_value.copy_to_user(&old.into())?;
+	Ok(0)
 }
 
 /// 64-bit ABI: `itimerspec` uses 64-bit `time_t` (`Timespec`).
@@ -182,16 +336,119 @@ pub fn timer_settime64(
 	old_value: UserPtr<ITimerspec>,
 ) -> EResult<usize> {
 	let new = new_value.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
-	do_timer_settime(
-		timerid,
-		flags,
-		new.it_interval.to_nano(),
-		new.it_value.to_nano(),
-		|old_interval, old_value_ns| {
-			old_value.copy_to_user(&ITimerspec {
-				it_interval: Timespec::from_nano(old_interval),
-				it_value: Timespec::from_nano(old_value_ns),
-			})
-		},
-	)
+	let old = do_timer_settime(timerid, flags, new)?;
+	old_value.copy_to_user(&old)?;
+	Ok(0)
+}
+
+/// Common implementation of `timer_gettime`, returning the current state of the timer.
+fn do_timer_gettime(timerid: TimerT) -> EResult<ITimerspec> {
+	let proc = Process::current();
+	let manager = proc.timer_manager.lock();
+	let timer = manager.get_timer(timerid).ok_or_else(|| errno!(EINVAL))?;
+	let (interval, value) = timer.get_time();
+	Ok(ITimerspec {
+		it_interval: Timespec::from_nano(interval),
+		it_value: Timespec::from_nano(value),
+	})
+}
+
+/// 32-bit ABI: `itimerspec` uses 32-bit `time_t` (`Timespec32`).
+pub fn timer_gettime(timerid: TimerT, curr_value: UserPtr<ITimerspec32>) -> EResult<usize> {
+	let curr = do_timer_gettime(timerid)?;
+	curr_value.copy_to_user(&curr.into())?;
+	Ok(0)
+}
+
+/// 64-bit ABI: `itimerspec` uses 64-bit `time_t` (`Timespec`).
+pub fn timer_gettime64(timerid: TimerT, curr_value: UserPtr<ITimerspec>) -> EResult<usize> {
+	let curr = do_timer_gettime(timerid)?;
+	curr_value.copy_to_user(&curr)?;
+	Ok(0)
+}
+
+/// Common implementation of `setitimer`.
+///
+/// `interval` and `value` are in nanoseconds. On success, the function returns the previous state
+/// of the timer.
+fn do_setitimer(which: c_int, interval: u64, value: u64) -> EResult<(u64, u64)> {
+	let proc = Process::current();
+	proc.itimers.lock().set(&proc, which, interval, value)
+}
+
+/// Decodes the timer's state `new`, returning `(interval, value)` in nanoseconds.
+///
+/// A null state disarms the timer.
+fn itimerval_values<T: TimeUnit>(new: Option<(T, T)>) -> EResult<(u64, u64)> {
+	match new {
+		Some((interval, value)) if interval.is_valid() && value.is_valid() => {
+			Ok((interval.to_nano(), value.to_nano()))
+		}
+		Some(_) => Err(errno!(EINVAL)),
+		None => Ok((0, 0)),
+	}
+}
+
+/// 32-bit ABI: `itimerval` uses 32-bit `time_t` (`Timeval32`).
+pub fn getitimer(which: c_int, curr_value: UserPtr<ITimerval32>) -> EResult<usize> {
+	let proc = Process::current();
+	let (interval, value) = proc.itimers.lock().get(&proc, which)?;
+	curr_value.copy_to_user(&ITimerval32 {
+		it_interval: Timeval32::from_nano(interval),
+		it_value: Timeval32::from_nano(value),
+	})?;
+	Ok(0)
+}
+
+/// 64-bit ABI: `itimerval` uses 64-bit `time_t` (`Timeval`).
+pub fn getitimer64(which: c_int, curr_value: UserPtr<ITimerval>) -> EResult<usize> {
+	let proc = Process::current();
+	let (interval, value) = proc.itimers.lock().get(&proc, which)?;
+	curr_value.copy_to_user(&ITimerval {
+		it_interval: Timeval::from_nano(interval),
+		it_value: Timeval::from_nano(value),
+	})?;
+	Ok(0)
+}
+
+/// 32-bit ABI: `itimerval` uses 32-bit `time_t` (`Timeval32`).
+pub fn setitimer(
+	which: c_int,
+	new_value: UserPtr<ITimerval32>,
+	old_value: UserPtr<ITimerval32>,
+) -> EResult<usize> {
+	let new = new_value.copy_from_user()?;
+	let (interval, value) = itimerval_values(new.map(|new| (new.it_interval, new.it_value)))?;
+	let (old_interval, old_value_ns) = do_setitimer(which, interval, value)?;
+	old_value.copy_to_user(&ITimerval32 {
+		it_interval: Timeval32::from_nano(old_interval),
+		it_value: Timeval32::from_nano(old_value_ns),
+	})?;
+	Ok(0)
+}
+
+/// 64-bit ABI: `itimerval` uses 64-bit `time_t` (`Timeval`).
+pub fn setitimer64(
+	which: c_int,
+	new_value: UserPtr<ITimerval>,
+	old_value: UserPtr<ITimerval>,
+) -> EResult<usize> {
+	let new = new_value.copy_from_user()?;
+	let (interval, value) = itimerval_values(new.map(|new| (new.it_interval, new.it_value)))?;
+	let (old_interval, old_value_ns) = do_setitimer(which, interval, value)?;
+	old_value.copy_to_user(&ITimerval {
+		it_interval: Timeval::from_nano(old_interval),
+		it_value: Timeval::from_nano(old_value_ns),
+	})?;
+	Ok(0)
+}
+
+pub fn alarm(seconds: c_uint) -> EResult<usize> {
+	let (_, old) = do_setitimer(ITIMER_REAL, 0, seconds as u64 * 1_000_000_000)?;
+	// Round to the nearest second, without reporting an armed timer as expired
+	let old = match old {
+		0 => 0,
+		old => max(old.saturating_add(500_000_000) / 1_000_000_000, 1),
+	};
+	Ok(old as _)
 }
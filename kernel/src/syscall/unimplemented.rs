@@ -0,0 +1,93 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Telemetry on unimplemented system calls.
+//!
+//! Every `ENOSYS` returned to userspace is accounted for here, along with the name of
+//! the calling binary. The first occurrence of each system call number is logged.
+//!
+//! Statistics are exposed in `/proc/unimplemented`, giving a list of the missing system calls
+//! that workloads actually need.
+
+use crate::{process::Process, sync::spin::Spin};
+use utils::{
+	DisplayableStr,
+	collections::{btreemap::BTreeMap, string::String},
+};
+
+/// Statistics about an unimplemented system call.
+#[derive(Debug)]
+pub struct Entry {
+	/// The number of times the system call has been called.
+	pub count: u64,
+	/// The name of the last binary that called the system call.
+	pub comm: String,
+}
+
+/// The upper bound of tracked system call numbers.
+///
+/// Numbers above do not correspond to any system call. Not tracking them bounds the number of
+/// entries userspace can create.
+const MAX_ID: usize = 0x200;
+
+/// Unimplemented system calls, indexed by compatibility mode flag and system call number.
+pub static UNIMPLEMENTED: Spin<BTreeMap<(bool, usize), Entry>> = Spin::new(BTreeMap::new());
+
+/// Accounts for `ENOSYS` being returned to `proc` for the system call `id`.
+///
+/// `compat` tells whether the system call has been made in compatibility mode.
+pub fn record(proc: &Process, compat: bool, id: usize) {
+	if id >= MAX_ID {
+		return;
+	}
+	let name = proc
+		.mem_space_opt()
+		.as_ref()
+		.map(|m| m.exe_info.exe.name.as_bytes())
+		.unwrap_or_default();
+	{
+		let mut unimplemented = UNIMPLEMENTED.lock();
+		if let Some(ent) = unimplemented.get_mut(&(compat, id)) {
+			ent.count += 1;
+			if ent.comm.as_bytes() != name {
+				// On allocation failure, keep the previous name
+				if let Ok(name) = String::try_from(name) {
+					ent.comm = name;
+				}
+			}
+			return;
+		}
+		// Statistics are best-effort: ignore allocation failures
+		if let Ok(comm) = String::try_from(name) {
+			let _ = unimplemented.insert(
+				(compat, id),
+				Entry {
+					count: 1,
+					comm,
+				},
+			);
+		}
+	}
+	log!(
+		Warning,
+		"{comm}[{pid}]: unimplemented system call 0x{id:x}{compat}",
+		comm = DisplayableStr(name),
+		pid = proc.get_pid(),
+		compat = if compat { " (compat)" } else { "" }
+	);
+}
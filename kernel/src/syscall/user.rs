@@ -19,12 +19,12 @@
 //! Users and groups system calls.
 
 use crate::{
-	file::perm::{AccessProfile, Gid, Uid, is_privileged},
+	file::perm::{AccessProfile, Gid, Uid},
 	memory::user::{UserPtr, UserSlice},
 	process::Process,
 };
 use core::{ffi::c_int, hint::unlikely};
-use utils::{errno, errno::EResult};
+use utils::{collections::vec::Vec, errno, errno::EResult, limits::NGROUPS_MAX};
 
 pub fn getuid() -> EResult<usize> {
 	Ok(AccessProfile::current().uid as _)
@@ -59,7 +59,7 @@ pub fn getresgid(rgid: UserPtr<Gid>, egid: UserPtr<Gid>, sgid: UserPtr<Gid>) ->
 }
 
 pub fn setuid(uid: Uid) -> EResult<usize> {
-	Process::current().fs.lock().ap.set_uid(uid)?;
+	Process::current().update_cred(|cred| cred.ap.set_uid(uid))?;
 	Ok(0)
 }
 
@@ -68,28 +68,30 @@ pub fn setreuid(ruid: c_int, euid: c_int) -> EResult<usize> {
 	if ruid < -1 || euid < -1 {
 		return Err(errno!(EINVAL));
 	}
-	let ap = AccessProfile::current();
-	if unlikely(!is_privileged()) && ![-1, ap.uid as _, ap.euid as _].contains(&ruid)
-		|| ![-1, ap.uid as _, ap.euid as _, ap.suid as _].contains(&euid)
-	{
-		return Err(errno!(EPERM));
-	}
-	// Update
-	let new_ruid = match ruid {
-		-1 => ap.uid,
-		i => i as _,
-	};
-	let new_euid = match euid {
-		-1 => ap.euid,
-		i => i as _,
-	};
-	let proc = Process::current();
-	let mut fs = proc.fs.lock();
-	fs.ap.uid = new_ruid;
-	fs.ap.euid = new_euid;
-	if new_ruid != ap.uid || new_euid != ap.uid {
-		fs.ap.suid = new_euid;
-	}
+	Process::current().update_cred(|cred| {
+		let ap = cred.ap;
+		if unlikely(!cred.is_privileged())
+			&& (![-1, ap.uid as _, ap.euid as _].contains(&ruid)
+				|| ![-1, ap.uid as _, ap.euid as _, ap.suid as _].contains(&euid))
+		{
+			return Err(errno!(EPERM));
+		}
+		// Update
+		let new_ruid = match ruid {
+			-1 => ap.uid,
+			i => i as _,
+		};
+		let new_euid = match euid {
+			-1 => ap.euid,
+			i => i as _,
+		};
+		cred.ap.uid = new_ruid;
+		cred.ap.euid = new_euid;
+		if new_ruid != ap.uid || new_euid != ap.uid {
+			cred.ap.suid = new_euid;
+		}
+		Ok(())
+	})?;
 	Ok(0)
 }
 
@@ -98,33 +100,34 @@ pub fn setresuid(ruid: c_int, euid: c_int, suid: c_int) -> EResult<usize> {
 	if ruid < -1 || euid < -1 || suid < -1 {
 		return Err(errno!(EINVAL));
 	}
-	let ap = AccessProfile::current();
-	if !is_privileged() {
-		let allowed = [-1, ap.uid as _, ap.euid as _, ap.suid as _];
-		if !allowed.contains(&ruid) || !allowed.contains(&euid) || !allowed.contains(&suid) {
-			return Err(errno!(EPERM));
+	Process::current().update_cred(|cred| {
+		let ap = cred.ap;
+		if !cred.is_privileged() {
+			let allowed = [-1, ap.uid as _, ap.euid as _, ap.suid as _];
+			if !allowed.contains(&ruid) || !allowed.contains(&euid) || !allowed.contains(&suid) {
+				return Err(errno!(EPERM));
+			}
 		}
-	}
-	// Update
-	let proc = Process::current();
-	let mut fs = proc.fs.lock();
-	fs.ap.uid = match ruid {
-		-1 => ap.uid,
-		i => i as _,
-	};
-	fs.ap.euid = match euid {
-		-1 => ap.euid,
-		i => i as _,
-	};
-	fs.ap.suid = match suid {
-		-1 => ap.suid,
-		i => i as _,
-	};
+		// Update
+		cred.ap.uid = match ruid {
+			-1 => ap.uid,
+			i => i as _,
+		};
+		cred.ap.euid = match euid {
+			-1 => ap.euid,
+			i => i as _,
+		};
+		cred.ap.suid = match suid {
+			-1 => ap.suid,
+			i => i as _,
+		};
+		Ok(())
+	})?;
 	Ok(0)
 }
 
 pub fn setgid(gid: Gid) -> EResult<usize> {
-	Process::current().fs.lock().ap.set_gid(gid)?;
+	Process::current().update_cred(|cred| cred.ap.set_gid(gid))?;
 	Ok(0)
 }
 
@@ -133,29 +136,30 @@ pub fn setregid(rgid: c_int, egid: c_int) -> EResult<usize> {
 	if rgid < -1 || egid < -1 {
 		return Err(errno!(EINVAL));
 	}
-	let ap = AccessProfile::current();
-	if !is_privileged()
-		&& (![-1, ap.gid as _, ap.egid as _].contains(&rgid)
-			|| ![-1, ap.gid as _, ap.egid as _, ap.sgid as _].contains(&egid))
-	{
-		return Err(errno!(EPERM));
-	}
-	// Update
-	let new_rgid = match rgid {
-		-1 => ap.gid,
-		i => i as _,
-	};
-	let new_egid = match egid {
-		-1 => ap.egid,
-		i => i as _,
-	};
-	let proc = Process::current();
-	let mut fs = proc.fs.lock();
-	fs.ap.gid = new_rgid;
-	fs.ap.egid = new_egid;
-	if new_rgid != ap.gid || new_egid != ap.gid {
-		fs.ap.sgid = new_egid;
-	}
+	Process::current().update_cred(|cred| {
+		let ap = cred.ap;
+		if !cred.is_privileged()
+			&& (![-1, ap.gid as _, ap.egid as _].contains(&rgid)
+				|| ![-1, ap.gid as _, ap.egid as _, ap.sgid as _].contains(&egid))
+		{
+			return Err(errno!(EPERM));
+		}
+		// Update
+		let new_rgid = match rgid {
+			-1 => ap.gid,
+			i => i as _,
+		};
+		let new_egid = match egid {
+			-1 => ap.egid,
+			i => i as _,
+		};
+		cred.ap.gid = new_rgid;
+		cred.ap.egid = new_egid;
+		if new_rgid != ap.gid || new_egid != ap.gid {
+			cred.ap.sgid = new_egid;
+		}
+		Ok(())
+	})?;
 	Ok(0)
 }
 
@@ -164,42 +168,68 @@ pub fn setresgid(rgid: c_int, egid: c_int, sgid: c_int) -> EResult<usize> {
 	if rgid < -1 || egid < -1 || sgid < -1 {
 		return Err(errno!(EINVAL));
 	}
-	let ap = AccessProfile::current();
-	if !is_privileged() {
-		let allowed = [-1, ap.gid as _, ap.egid as _, ap.sgid as _];
-		if !allowed.contains(&rgid) || !allowed.contains(&egid) || !allowed.contains(&sgid) {
-			return Err(errno!(EPERM));
+	Process::current().update_cred(|cred| {
+		let ap = cred.ap;
+		if !cred.is_privileged() {
+			let allowed = [-1, ap.gid as _, ap.egid as _, ap.sgid as _];
+			if !allowed.contains(&rgid) || !allowed.contains(&egid) || !allowed.contains(&sgid) {
+				return Err(errno!(EPERM));
+			}
 		}
-	}
-	// Update
-	let proc = Process::current();
-	let mut fs = proc.fs.lock();
-	fs.ap.gid = match rgid {
-		-1 => ap.gid,
-		i => i as _,
-	};
-	fs.ap.egid = match egid {
-		-1 => ap.egid,
-		i => i as _,
-	};
-	fs.ap.sgid = match sgid {
-		-1 => ap.sgid,
-		i => i as _,
-	};
+		// Update
+		cred.ap.gid = match rgid {
+			-1 => ap.gid,
+			i => i as _,
+		};
+		cred.ap.egid = match egid {
+			-1 => ap.egid,
+			i => i as _,
+		};
+		cred.ap.sgid = match sgid {
+			-1 => ap.sgid,
+			i => i as _,
+		};
+		Ok(())
+	})?;
 	Ok(0)
 }
 
+pub fn setfsuid(fsuid: Uid) -> EResult<usize> {
+	let mut old = 0;
+	Process::current().update_cred(|cred| {
+		old = cred.ap.fsuid;
+		// `-1` is not a valid ID: only return the current one
+		if fsuid != Uid::MAX {
+			cred.ap.set_fsuid(fsuid);
+		}
+		Ok(())
+	})?;
+	Ok(old as _)
+}
+
+pub fn setfsgid(fsgid: Gid) -> EResult<usize> {
+	let mut old = 0;
+	Process::current().update_cred(|cred| {
+		old = cred.ap.fsgid;
+		// `-1` is not a valid ID: only return the current one
+		if fsgid != Gid::MAX {
+			cred.ap.set_fsgid(fsgid);
+		}
+		Ok(())
+	})?;
+	Ok(old as _)
+}
+
 pub fn getgroups(size: c_int, list: *mut Gid) -> EResult<usize> {
-	let proc = Process::current();
-	let fs = proc.fs.lock();
+	let cred = Process::current().cred();
 	if size > 0 {
-		if size as usize != fs.groups.len() {
+		if size as usize != cred.groups.len() {
 			return Err(errno!(EINVAL));
 		}
 		let list = UserSlice::from_user(list, size as _)?;
-		list.copy_to_user(0, &fs.groups)?;
+		list.copy_to_user(0, &cred.groups)?;
 	}
-	Ok(fs.groups.len())
+	Ok(cred.groups.len())
 }
 
 pub fn getgroups32(size: c_int, list: *mut Gid) -> EResult<usize> {
@@ -207,15 +237,27 @@ pub fn getgroups32(size: c_int, list: *mut Gid) -> EResult<usize> {
 }
 
 pub fn setgroups(size: usize, list: *mut Gid) -> EResult<usize> {
-	let proc = Process::current();
-	let mut fs = proc.fs.lock();
-	if unlikely(!is_privileged()) {
+	// Check before copying, to prevent unprivileged processes from making large allocations
+	if unlikely(!Process::current().cred().is_privileged()) {
 		return Err(errno!(EPERM));
 	}
+	if unlikely(size > NGROUPS_MAX) {
+		return Err(errno!(EINVAL));
+	}
 	let list = UserSlice::from_user(list, size)?;
-	// TODO no need to zero-init
-	fs.groups.resize(size, 0)?;
-	list.copy_from_user(0, &mut fs.groups)?;
+	// Copy the list before locking, since this may fault
+	let groups = match list.copy_from_user_vec(0)? {
+		Some(groups) => groups,
+		None if size == 0 => Vec::new(),
+		None => return Err(errno!(EFAULT)),
+	};
+	Process::current().update_cred(|cred| {
+		if unlikely(!cred.is_privileged()) {
+			return Err(errno!(EPERM));
+		}
+		cred.groups = groups;
+		Ok(())
+	})?;
 	Ok(0)
 }
 
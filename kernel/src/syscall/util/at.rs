@@ -43,12 +43,16 @@ pub const AT_SYMLINK_FOLLOW: c_int = 0x400;
 pub const AT_NO_AUTOMOUNT: c_int = 0x800;
 /// Flag: If `pathname` is an empty string, operate on the file referred to by `dirfd`.
 pub const AT_EMPTY_PATH: c_int = 0x1000;
+/// Flag: Apply the operation to the whole subtree.
+pub const AT_RECURSIVE: c_int = 0x8000;
 /// Flag: Do whatever `stat` does.
 pub const AT_STATX_SYNC_AS_STAT: c_int = 0x0000;
 /// Flag: Force the attributes to be synchronized with the server.
 pub const AT_STATX_FORCE_SYNC: c_int = 0x2000;
 /// Flag: Don't synchronize anything, but rather take cached information.
 pub const AT_STATX_DONT_SYNC: c_int = 0x4000;
+/// Mask of the flags selecting the synchronization behaviour of `statx`.
+pub const AT_STATX_SYNC_TYPE: c_int = 0x6000;
 
 /// Returns the file for the given path `path`.
 ///
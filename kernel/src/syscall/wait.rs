@@ -19,21 +19,30 @@
 //! Process management system calls.
 
 use crate::{
+	arch::x86::idt::IntFrame,
 	memory::user::UserPtr,
 	process,
-	process::{Process, State, pid::Pid, rusage::Rusage, scheduler::schedule},
+	process::{
+		Process, State,
+		rusage::Rusage,
+		scheduler::schedule,
+		signal::{CLD_DUMPED, CLD_EXITED, CLD_KILLED, CLD_STOPPED, SigInfo, SigInfo32, SigInfo64},
+	},
+	syscall::FromSyscallArg,
 };
 use core::{
-	ffi::c_int,
+	ffi::{c_int, c_void},
 	iter,
 	sync::atomic::Ordering::{Acquire, Release},
 };
-use utils::{errno, errno::EResult};
+use utils::{errno, errno::EResult, ptr::arc::Arc};
 
 /// Wait flag. Returns immediately if no child has exited.
 pub const WNOHANG: i32 = 1;
 /// Wait flag. Returns if a child has stopped.
 pub const WUNTRACED: i32 = 2;
+/// Wait flag. Alias of [`WUNTRACED`], used by `waitid`.
+pub const WSTOPPED: i32 = WUNTRACED;
 /// Wait flag. Returns if a child has terminated.
 pub const WEXITED: i32 = 4;
 /// Wait flag. Returns if a stopped child has been resumed by delivery of
@@ -43,92 +52,135 @@ pub const WCONTINUED: i32 = 8;
 /// child.
 pub const WNOWAIT: i32 = 0x1000000;
 
-/// Returns an iterator over the IDs of the processes to be watched according to the given
-/// constraint.
+/// `waitid` ID type: wait for any child.
+pub const P_ALL: c_int = 0;
+/// `waitid` ID type: wait for the child with the given PID.
+pub const P_PID: c_int = 1;
+/// `waitid` ID type: wait for any child in the given process group.
+pub const P_PGID: c_int = 2;
+
+/// The set of children a wait system call waits upon.
+#[derive(Clone, Copy)]
+enum WaitTarget {
+	/// Any child.
+	Any,
+	/// The child with the given PID.
+	Pid(i32),
+	/// Any child in the process group with the given ID.
+	Pgid(i32),
+}
+
+impl WaitTarget {
+	/// Returns the target corresponding to the `pid` argument of `wait4` and `waitpid`.
+	fn from_pid(pid: i32) -> Self {
+		match pid {
+			..-1 => Self::Pgid(-pid),
+			-1 => Self::Any,
+			0 => Self::Pgid(Process::current().get_pgid() as _),
+			_ => Self::Pid(pid),
+		}
+	}
+
+	/// Tells whether the child `proc` is part of the target.
+	fn matches(&self, proc: &Process) -> bool {
+		match *self {
+			Self::Any => true,
+			Self::Pid(pid) => proc.get_pid() as i32 == pid,
+			Self::Pgid(pgid) => proc.get_pgid() as i32 == pgid,
+		}
+	}
+}
+
+/// Looks for a child of the current process in `target` with a pending event in `options`.
+///
+/// On success, the function returns the child along with the event. Unless [`WNOWAIT`] is set
+/// in `options`, the event is consumed.
 ///
-/// `pid` is the constraint given to the system call.
-fn iter_targets(pid: i32) -> impl Iterator<Item = Pid> {
+/// If no child is part of `target`, the function returns [`errno::ECHILD`].
+fn find_waitable(target: WaitTarget, options: i32) -> EResult<Option<(Arc<Process>, u8)>> {
 	let proc = Process::current();
 	let mut i = 0;
-	iter::from_fn(move || {
-		// FIXME: select only process that are children of the current process
-		let links = proc.links.lock();
-		let res = match pid {
-			// FIXME: must wait for any child process whose pgid is equal to -pid
-			..-1 => links.process_group.get(i).cloned(),
-			-1 => links.children.get(i).cloned(),
-			0 => links.process_group.get(i).cloned(),
-			_ => (i == 0).then_some(pid as _),
-		};
+	let children = iter::from_fn(|| {
+		let pid = proc.links.lock().children.get(i).cloned();
 		i += 1;
-		res
-	})
-}
-
-/// Returns the wait status for the given process.
-fn get_wstatus(proc: &Process) -> i32 {
-	let (status, termsig) = {
-		let signal = proc.signal.lock();
-		(signal.exit_status, signal.termsig)
-	};
-	#[allow(clippy::let_and_return)]
-	let wstatus = match proc.get_state() {
-		State::Running | State::IntSleeping | State::Sleeping => 0xffff,
-		State::Stopped => ((termsig as i32 & 0xff) << 8) | 0x7f,
-		State::Zombie => ((status as i32 & 0xff) << 8) | (termsig as i32 & 0x7f),
-	};
-	// TODO
-	/*if coredump {
-		wstatus |= 0x80;
-	}*/
-	wstatus
+		pid
+	});
+	let mut empty = true;
+	for child in children
+		.filter_map(Process::get_by_pid)
+		.filter(|child| target.matches(child))
+	{
+		empty = false;
+		// Only the event matching the current state of the child can be reported
+		let event = match child.get_state() {
+			State::Zombie => WEXITED,
+			State::Stopped => WUNTRACED,
+			State::Running | State::IntSleeping | State::Sleeping => WCONTINUED,
+		} as u8;
+		if event & options as u8 == 0 {
+			continue;
+		}
+		let events = if options & WNOWAIT == 0 {
+			child.parent_event.fetch_and(!event, Release)
+		} else {
+			child.parent_event.load(Acquire)
+		};
+		if events & event != 0 {
+			return Ok(Some((child, event)));
+		}
+	}
+	if empty { Err(errno!(ECHILD)) } else { Ok(None) }
 }
 
-/// Waits upon a process and returns it. If no process can be waited upon, the function returns
-/// `None`.
+/// Waits for a child of the current process in `target` to have a pending event in `options`.
 ///
-/// Arguments:
-/// - `pid` is the constraint given to the system call.
-/// - `wstatus` is the pointer to the wait status.
-/// - `options` is a set of flags.
-/// - `rusage` is the pointer to the resource usage structure.
-fn get_waitable(
-	pid: i32,
-	wstatus: UserPtr<i32>,
+/// On success, the function returns the `SIGCHLD` information describing the event. If
+/// [`WNOHANG`] is set and no event is pending, the function returns `None`.
+///
+/// Unless [`WNOWAIT`] is set, the event is consumed and an exited child is reaped.
+fn wait_child(
+	target: WaitTarget,
 	options: i32,
 	rusage: UserPtr<Rusage>,
-) -> EResult<Option<Pid>> {
-	let mut empty = true;
-	// Find a waitable process
-	let proc = iter_targets(pid)
-		.inspect(|_| empty = false)
-		.filter_map(Process::get_by_pid)
-		// Select a waitable process
-		.find(|proc| {
-			let events = if options & WNOWAIT == 0 {
-				proc.parent_event.fetch_and(!(options as u8), Release)
-			} else {
-				proc.parent_event.load(Acquire)
-			};
-			events & options as u8 != 0
-		});
-	let Some(proc) = proc else {
-		return if empty {
-			// No target
-			Err(errno!(ECHILD))
+) -> EResult<Option<SigInfo>> {
+	let (child, event) = loop {
+		if let Some(res) = find_waitable(target, options)? {
+			break res;
+		}
+		// If the flag is set, do not wait
+		if options & WNOHANG != 0 {
+			return Ok(None);
+		}
+		// When a child process has its state changed, SIGCHLD is sent to the current process to
+		// wake it up. Check again once asleep so that the wakeup cannot be missed
+		process::set_state(State::IntSleeping);
+		if matches!(find_waitable(target, options | WNOWAIT), Ok(None)) {
+			schedule();
 		} else {
-			Ok(None)
-		};
+			process::cancel_sleep();
+		}
 	};
-	// Write values back
-	wstatus.copy_to_user(&get_wstatus(&proc))?;
-	rusage.copy_to_user(&proc.rusage.lock())?;
+	let info = child.child_info(event);
+	let (utime, stime) = child.cputime.total();
+	rusage.copy_to_user(&child.rusage.lock().clone().with_cputime(utime, stime))?;
 	// Remove zombie process if requested
-	let pid = proc.get_pid();
-	if options & WNOWAIT == 0 && proc.get_state() == State::Zombie {
-		Process::remove(proc);
+	if options & WNOWAIT == 0 && event == WEXITED as u8 {
+		Process::current().cputime.reap(&child.cputime);
+		Process::remove(child);
+	}
+	Ok(Some(info))
+}
+
+/// Returns the wait status corresponding to the `SIGCHLD` information `info`.
+fn get_wstatus(info: &SigInfo) -> i32 {
+	let status = info.si_value as i32;
+	match info.si_code {
+		CLD_EXITED => (status & 0xff) << 8,
+		CLD_KILLED => status & 0x7f,
+		CLD_DUMPED => (status & 0x7f) | 0x80,
+		CLD_STOPPED => ((status & 0xff) << 8) | 0x7f,
+		_ => 0xffff,
 	}
-	Ok(Some(pid))
 }
 
 /// Executes the `waitpid` system call.
@@ -138,21 +190,11 @@ pub fn do_waitpid(
 	options: i32,
 	rusage: UserPtr<Rusage>,
 ) -> EResult<usize> {
-	loop {
-		let result = get_waitable(pid, wstatus, options, rusage.clone())?;
-		// On success, return
-		if let Some(p) = result {
-			return Ok(p as _);
-		}
-		// If the flag is set, do not wait
-		if options & WNOHANG != 0 {
-			return Ok(0);
-		}
-		// When a child process has its state changed by a signal, SIGCHLD is sent to the
-		// current process to wake it up
-		process::set_state(State::IntSleeping);
-		schedule();
-	}
+	let Some(info) = wait_child(WaitTarget::from_pid(pid), options, rusage)? else {
+		return Ok(0);
+	};
+	wstatus.copy_to_user(&get_wstatus(&info))?;
+	Ok(info.si_pid as _)
 }
 
 #[allow(missing_docs)]
@@ -169,3 +211,34 @@ pub fn wait4(
 ) -> EResult<usize> {
 	do_waitpid(pid, wstatus, options | WEXITED, rusage)
 }
+
+#[allow(missing_docs)]
+pub fn waitid(
+	idtype: c_int,
+	id: c_int,
+	infop: *mut c_void,
+	options: c_int,
+	rusage: UserPtr<Rusage>,
+	frame: &mut IntFrame,
+) -> EResult<usize> {
+	let events = WEXITED | WSTOPPED | WCONTINUED;
+	if options & !(WNOHANG | WNOWAIT | events) != 0 || options & events == 0 {
+		return Err(errno!(EINVAL));
+	}
+	let target = match idtype {
+		P_ALL => WaitTarget::Any,
+		P_PID if id > 0 => WaitTarget::Pid(id),
+		P_PGID if id == 0 => WaitTarget::Pgid(Process::current().get_pgid() as _),
+		P_PGID if id > 0 => WaitTarget::Pgid(id),
+		_ => return Err(errno!(EINVAL)),
+	};
+	// If no child is waitable with `WNOHANG`, the structure is zeroed
+	let info = wait_child(target, options, rusage)?.unwrap_or_default();
+	let infop = infop as usize;
+	if frame.is_compat() {
+		UserPtr::<SigInfo32>::from_ptr(infop).copy_to_user(&info.into())?;
+	} else {
+		UserPtr::<SigInfo64>::from_ptr(infop).copy_to_user(&info.into())?;
+	}
+	Ok(0)
+}
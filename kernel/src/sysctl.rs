@@ -0,0 +1,295 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Kernel tunables, exposed to userspace in the `/proc/sys` directory.
+//!
+//! Each tunable is identified by a dot-separated path. For example, `kernel.hostname` is exposed
+//! as `/proc/sys/kernel/hostname`.
+//!
+//! Subsystems declare their tunables as statics, then make them visible with [`register`].
+
+use crate::{
+	BUILD_VERSION, HOSTNAME, RELEASE,
+	file::{
+		Mode,
+		perm::{S_IRGRP, S_IROTH, S_IRUSR, S_IWUSR},
+	},
+	memory::oom,
+	sync::spin::Spin,
+};
+use core::{
+	fmt::Debug,
+	sync::atomic::{AtomicUsize, Ordering::Relaxed},
+};
+use utils::{
+	collections::{btreemap::BTreeMap, string::String, vec::Vec},
+	errno,
+	errno::{AllocResult, EResult},
+	limits::HOST_NAME_MAX,
+	try_writeln,
+};
+
+/// Permissions for a read-only tunable.
+pub const MODE_RO: Mode = S_IRUSR | S_IRGRP | S_IROTH;
+/// Permissions for a tunable writable by the owner (root).
+pub const MODE_RW: Mode = MODE_RO | S_IWUSR;
+
+/// A kernel tunable.
+pub trait Tunable: Debug + Sync {
+	/// Appends the text representation of the current value to `buf`.
+	fn read(&self, buf: &mut String) -> AllocResult<()>;
+
+	/// Sets the value from its text representation `val`.
+	///
+	/// The default implementation returns [`errno::EPERM`].
+	fn write(&self, val: &[u8]) -> EResult<()> {
+		let _ = val;
+		Err(errno!(EPERM))
+	}
+}
+
+/// An integer tunable, bounded by an inclusive range.
+#[derive(Debug)]
+pub struct IntTunable {
+	/// The current value.
+	val: AtomicUsize,
+	/// The minimum value.
+	min: usize,
+	/// The maximum value.
+	max: usize,
+}
+
+impl IntTunable {
+	/// Creates a new instance with the default value `val` and the range `min..=max`.
+	pub const fn new(val: usize, min: usize, max: usize) -> Self {
+		Self {
+			val: AtomicUsize::new(val),
+			min,
+			max,
+		}
+	}
+
+	/// Returns the current value.
+	#[inline]
+	pub fn get(&self) -> usize {
+		self.val.load(Relaxed)
+	}
+}
+
+impl Tunable for IntTunable {
+	fn read(&self, buf: &mut String) -> AllocResult<()> {
+		try_writeln!(buf, "{}", self.get())
+	}
+
+	fn write(&self, val: &[u8]) -> EResult<()> {
+		let val: usize = core::str::from_utf8(val)
+			.ok()
+			.and_then(|s| s.trim().parse().ok())
+			.ok_or_else(|| errno!(EINVAL))?;
+		if !(self.min..=self.max).contains(&val) {
+			return Err(errno!(EINVAL));
+		}
+		self.val.store(val, Relaxed);
+		Ok(())
+	}
+}
+
+/// A registered tunable.
+#[derive(Clone, Copy, Debug)]
+pub struct Sysctl {
+	/// The file permissions of the tunable.
+	pub mode: Mode,
+	/// The tunable itself.
+	pub tunable: &'static dyn Tunable,
+}
+
+/// Registered tunables, by path.
+static SYSCTLS: Spin<BTreeMap<&'static [u8], Sysctl>> = Spin::new(BTreeMap::new());
+
+/// Registers the tunable `tunable` at the dot-separated path `path`, with the permissions
+/// `mode`.
+///
+/// If a tunable is already registered at the same path, it is replaced.
+pub fn register(
+	path: &'static [u8],
+	mode: Mode,
+	tunable: &'static dyn Tunable,
+) -> AllocResult<()> {
+	SYSCTLS.lock().insert(
+		path,
+		Sysctl {
+			mode,
+			tunable,
+		},
+	)?;
+	Ok(())
+}
+
+/// Unregisters the tunable at `path`.
+pub fn unregister(path: &[u8]) {
+	SYSCTLS.lock().remove(path);
+}
+
+/// Returns the tunable registered at `path`, if any.
+pub fn get(path: &[u8]) -> Option<Sysctl> {
+	SYSCTLS.lock().get(path).copied()
+}
+
+/// Returns the path of the given tunable relative to `prefix`, or `None` if not in `prefix`.
+///
+/// An empty `prefix` represents the root of the tree.
+fn strip_prefix<'p>(path: &'p [u8], prefix: &[u8]) -> Option<&'p [u8]> {
+	if prefix.is_empty() {
+		return Some(path);
+	}
+	path.strip_prefix(prefix)?.strip_prefix(b".")
+}
+
+/// Tells whether at least one tunable is located under `prefix`.
+pub fn is_dir(prefix: &[u8]) -> bool {
+	SYSCTLS
+		.lock()
+		.iter()
+		.any(|(path, _)| strip_prefix(path, prefix).is_some())
+}
+
+/// Calls `f` on each direct child of the directory `prefix`, skipping the first `off` ones.
+///
+/// Arguments passed to `f` are the name of the child and whether it is a directory. If `f`
+/// returns `false`, iteration stops.
+pub fn iter_children<F: FnMut(&[u8], bool) -> EResult<bool>>(
+	prefix: &[u8],
+	off: usize,
+	mut f: F,
+) -> EResult<()> {
+	let sysctls = SYSCTLS.lock();
+	let mut prev: Option<&[u8]> = None;
+	let mut i = 0;
+	for (path, _) in sysctls.iter() {
+		let Some(rel) = strip_prefix(path, prefix) else {
+			continue;
+		};
+		let (name, dir) = match rel.iter().position(|c| *c == b'.') {
+			Some(i) => (&rel[..i], true),
+			None => (rel, false),
+		};
+		// Entries are sorted, so entries sharing a subdirectory are next to each other
+		if prev == Some(name) {
+			continue;
+		}
+		prev = Some(name);
+		if i >= off && !f(name, dir)? {
+			break;
+		}
+		i += 1;
+	}
+	Ok(())
+}
+
+/// The `kernel.hostname` tunable.
+#[derive(Debug)]
+struct Hostname;
+
+impl Tunable for Hostname {
+	fn read(&self, buf: &mut String) -> AllocResult<()> {
+		buf.push_str(&*HOSTNAME.lock())?;
+		buf.push(b'\n')
+	}
+
+	fn write(&self, val: &[u8]) -> EResult<()> {
+		let val = val.strip_suffix(b"\n").unwrap_or(val);
+		if val.len() > HOST_NAME_MAX {
+			return Err(errno!(EINVAL));
+		}
+		*HOSTNAME.lock() = Vec::try_from(val)?;
+		Ok(())
+	}
+}
+
+/// The `kernel.osrelease` tunable.
+#[derive(Debug)]
+struct OsRelease;
+
+impl Tunable for OsRelease {
+	fn read(&self, buf: &mut String) -> AllocResult<()> {
+		try_writeln!(buf, "{RELEASE}")
+	}
+}
+
+/// The `kernel.version` tunable.
+#[derive(Debug)]
+struct OsVersion;
+
+impl Tunable for OsVersion {
+	fn read(&self, buf: &mut String) -> AllocResult<()> {
+		try_writeln!(buf, "{BUILD_VERSION}")
+	}
+}
+
+/// Registers the tunables of subsystems which do not have an initialization stage of their own.
+pub(crate) fn init() -> AllocResult<()> {
+	register(b"kernel.hostname", MODE_RW, &Hostname)?;
+	register(b"kernel.osrelease", MODE_RO, &OsRelease)?;
+	register(b"kernel.version", MODE_RO, &OsVersion)?;
+	register(b"vm.overcommit_memory", MODE_RW, &oom::OVERCOMMIT_MEMORY)?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn sysctl_int_tunable() {
+		let t = IntTunable::new(10, 1, 100);
+		t.write(b"42\n").unwrap();
+		assert_eq!(t.get(), 42);
+		assert!(t.write(b"0").is_err());
+		assert!(t.write(b"101").is_err());
+		assert!(t.write(b"abc").is_err());
+		assert_eq!(t.get(), 42);
+		let mut buf = String::new();
+		t.read(&mut buf).unwrap();
+		assert_eq!(buf.as_bytes(), b"42\n");
+	}
+
+	#[test_case]
+	fn sysctl_children() {
+		static T: IntTunable = IntTunable::new(0, 0, 0);
+		register(b"test.a.b", MODE_RO, &T).unwrap();
+		register(b"test.a.c", MODE_RO, &T).unwrap();
+		register(b"test.d", MODE_RO, &T).unwrap();
+		assert!(is_dir(b"test.a"));
+		assert!(!is_dir(b"test.d"));
+		let mut children = Vec::new();
+		iter_children(b"test", 0, |name, dir| {
+			children.push((String::try_from(name)?, dir))?;
+			Ok(true)
+		})
+		.unwrap();
+		assert_eq!(children.len(), 2);
+		assert_eq!(children[0].0.as_bytes(), b"a");
+		assert!(children[0].1);
+		assert_eq!(children[1].0.as_bytes(), b"d");
+		assert!(!children[1].1);
+		unregister(b"test.a.b");
+		unregister(b"test.a.c");
+		unregister(b"test.d");
+		assert!(get(b"test.d").is_none());
+	}
+}
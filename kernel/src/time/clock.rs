@@ -17,14 +17,21 @@
  */
 
 //! System clocks.
+//!
+//! Clocks are advanced by the periodic tick. If a [`ClockSource`] is available, the time elapsed
+//! since the clocksource has been selected is read from it instead, which gives clocks a
+//! resolution down to the nanosecond.
 
 use crate::{
-	sync::atomic::AtomicU64,
-	time::{Timestamp, unit::ClockIdT},
+	sync::{atomic::AtomicU64, once::OnceInit},
+	time::{TICK_FREQUENCY, Timestamp, unit::ClockIdT, vvar, vvar::VvarClocks},
 };
 use core::{
 	cmp::max,
-	sync::atomic::Ordering::{Acquire, Relaxed, Release},
+	sync::atomic::{
+		AtomicBool,
+		Ordering::{Acquire, Relaxed, Release},
+	},
 };
 
 /// Available clocks
@@ -68,6 +75,41 @@ impl Clock {
 	}
 }
 
+/// A free-running hardware counter, used to measure the passage of time.
+#[derive(Clone, Copy, Debug)]
+pub struct ClockSource {
+	/// The name of the clocksource
+	pub name: &'static str,
+	/// Returns the current value of the counter
+	pub read: fn() -> u64,
+	/// The frequency of the counter, in hertz
+	pub frequency: u64,
+	/// Tells whether the counter is the TSC, which the vDSO can read from userspace
+	pub tsc: bool,
+}
+
+/// Shift of the multiplier converting counter cycles into nanoseconds.
+const CYCLES_SHIFT: u32 = 32;
+
+/// The clocksource in use.
+struct Source {
+	/// Returns the current value of the counter
+	read: fn() -> u64,
+	/// The value of the counter at the moment the clocksource has been selected
+	base: u64,
+	/// Multiplier converting counter cycles into nanoseconds, shifted by [`CYCLES_SHIFT`]
+	mult: u64,
+	/// The resolution of the clocksource, in nanoseconds
+	resolution: Timestamp,
+	/// Tells whether the counter is the TSC
+	tsc: bool,
+}
+
+/// The clocksource in use, valid only if [`HAS_SOURCE`] is set.
+static SOURCE: OnceInit<Source> = unsafe { OnceInit::new() };
+/// Tells whether a clocksource is in use.
+static HAS_SOURCE: AtomicBool = AtomicBool::new(false);
+
 // TODO allow accessing clocks through an address shared with userspace (vDSO)
 
 /// The current timestamp of the real time clock, in nanoseconds.
@@ -83,13 +125,94 @@ pub(crate) fn init(ts: Timestamp) {
 	REALTIME.store(ts, Relaxed);
 	MONOTONIC.store(ts, Relaxed);
 	BOOTTIME.store(ts, Relaxed);
+	update_vvar();
+}
+
+/// Starts reading time from the clocksource `src`.
+///
+/// Clocks keep their current values, then advance according to the clocksource.
+///
+/// This function must be called only once, at boot, before the periodic tick is enabled.
+pub(crate) fn set_source(src: ClockSource) {
+	let mult = (1_000_000_000u64 << CYCLES_SHIFT) / src.frequency;
+	let source = Source {
+		read: src.read,
+		base: (src.read)(),
+		mult,
+		resolution: 1_000_000_000u64.div_ceil(src.frequency),
+		tsc: src.tsc,
+	};
+	unsafe {
+		OnceInit::init(&SOURCE, source);
+	}
+	HAS_SOURCE.store(true, Release);
+	update_vvar();
+}
+
+/// Exposes the current state of clocks to the vDSO.
+fn update_vvar() {
+	let (mode, base, mult) = if !HAS_SOURCE.load(Acquire) {
+		(vvar::MODE_TICK, 0, 0)
+	} else if SOURCE.tsc {
+		(vvar::MODE_TSC, SOURCE.base, SOURCE.mult)
+	} else {
+		(vvar::MODE_SYSCALL, 0, 0)
+	};
+	let realtime = REALTIME.load(Acquire);
+	let monotonic = MONOTONIC.load(Acquire);
+	vvar::update(VvarClocks {
+		mode,
+		base,
+		mult,
+		realtime,
+		monotonic: max(realtime, monotonic),
+		boottime: BOOTTIME.load(Acquire),
+	});
+}
+
+/// Sets the current value of the real time clock to `ts`, in nanoseconds.
+///
+/// The monotonic clock keeps its current value, even if the real time clock goes backwards.
+pub fn set_realtime(ts: Timestamp) {
+	let realtime = REALTIME.load(Acquire);
+	let monotonic = MONOTONIC.load(Acquire);
+	MONOTONIC.store(max(realtime, monotonic), Release);
+	REALTIME.store(ts.saturating_sub(source_elapsed()), Release);
+	update_vvar();
+}
+
+/// Returns the number of nanoseconds elapsed since the clocksource has been selected.
+///
+/// If no clocksource is in use, the function returns zero.
+#[inline]
+fn source_elapsed() -> Timestamp {
+	if !HAS_SOURCE.load(Acquire) {
+		return 0;
+	}
+	let cycles = (SOURCE.read)().wrapping_sub(SOURCE.base);
+	((cycles as u128 * SOURCE.mult as u128) >> CYCLES_SHIFT) as _
+}
+
+/// Returns the resolution of clocks, in nanoseconds.
+pub fn resolution() -> Timestamp {
+	if HAS_SOURCE.load(Acquire) {
+		SOURCE.resolution
+	} else {
+		1_000_000_000u64.div_ceil(TICK_FREQUENCY as _)
+	}
 }
 
 /// Updates clocks with the given delta value in nanoseconds.
+///
+/// If a clocksource is in use, clocks are read from it and the function does nothing.
 pub fn update(delta: Timestamp) {
+	if HAS_SOURCE.load(Acquire) {
+		return;
+	}
 	REALTIME.fetch_add(delta, Release);
 	MONOTONIC.fetch_add(delta, Release);
 	BOOTTIME.fetch_add(delta, Release);
+	update_vvar();
 }
 
 /// Returns the current timestamp in nanoseconds.
@@ -100,17 +223,18 @@ pub fn update(delta: Timestamp) {
 ///
 /// If the clock is invalid, the function returns an error.
 pub fn current_time_ns(clk: Clock) -> Timestamp {
-	match clk {
-		Clock::Realtime | Clock::RealtimeAlarm => REALTIME.load(Acquire),
-		Clock::Monotonic => {
+	let base = match clk {
+		Clock::Realtime | Clock::RealtimeAlarm | Clock::RealtimeCoarse => REALTIME.load(Acquire),
+		Clock::Monotonic | Clock::MonotonicRaw | Clock::MonotonicCoarse => {
 			let realtime = REALTIME.load(Acquire);
 			let monotonic = MONOTONIC.load(Acquire);
 			max(realtime, monotonic)
 		}
 		Clock::Boottime | Clock::BoottimeAlarm => BOOTTIME.load(Acquire),
 		// TODO implement all clocks
-		_ => 0,
-	}
+		_ => return 0,
+	};
+	base + source_elapsed()
 }
 
 /// Returns the current timestamp in milliseconds.
@@ -25,24 +25,42 @@
 //! - Software Clocks, which maintain a timestamp based on hardware clocks.
 
 pub mod clock;
+pub mod timeout;
 pub mod timer;
 pub mod unit;
+pub mod vvar;
 
 use crate::{
 	arch::{
 		core_id,
-		x86::{apic, timer::rtc},
-	},
-	int, process,
-	process::{Process, State, scheduler::schedule},
-	time::{
-		clock::{Clock, current_time_ns},
-		timer::Timer,
+		x86::{
+			apic,
+			timer::{clocksource, hpet, rtc},
+		},
 	},
+	int,
+	time::{clock::Clock, timeout::Timeout},
 };
-use core::hint::unlikely;
 use unit::Timestamp;
-use utils::{errno, errno::EResult};
+use utils::errno::EResult;
+
+/// The frequency of the periodic tick, in hertz.
+pub const TICK_FREQUENCY: u32 = 1024;
+/// The number of clock ticks per second reported to userspace (`USER_HZ`).
+pub const CLK_TCK: u64 = 100;
+
+/// Converts a duration in nanoseconds to clock ticks.
+#[inline]
+pub fn to_clock_ticks(ns: u64) -> u64 {
+	ns / (1_000_000_000 / CLK_TCK)
+}
+
+/// Sets the current wall-clock time to `ts`, in nanoseconds since the Unix epoch, and writes it
+/// back to the RTC so that it persists across reboots.
+pub fn set_time(ts: Timestamp) {
+	clock::set_realtime(ts);
+	rtc::write_time(ts);
+}
 
 /// Makes the current thread sleep for `delay`, in nanoseconds.
 ///
@@ -51,42 +69,39 @@ use utils::{errno, errno::EResult};
 /// If the current process is interrupted by a signal, the function returns [`errno::EINTR`] and
 /// sets the remaining time in `remain`.
 pub fn sleep_for(clock: Clock, delay: Timestamp, remain: &mut Timestamp) -> EResult<()> {
-	let proc = Process::current();
-	// FIXME: there can be allocation failures here
-	let mut timer = Timer::new(clock, move || {
-		Process::wake_from(&proc, State::IntSleeping as u8)
-	})?;
-	timer.set_time(0, delay)?;
-	// Loop until the timer expires
-	loop {
-		let cur_ts = current_time_ns(clock);
-		if unlikely(timer.has_expired(cur_ts)) {
-			break;
-		}
-		// The timer has not expired, we need to sleep
-		if unlikely(Process::current().has_pending_signal()) {
-			*remain = timer.get_time().1;
-			return Err(errno!(EINTR));
-		}
-		process::set_state(State::IntSleeping);
-		schedule();
+	let timeout = Timeout::relative(clock, delay);
+	let res = timeout.sleep();
+	if res.is_err() {
+		*remain = timeout.remaining().unwrap_or(0);
 	}
-	Ok(())
+	res
 }
 
 /// Initializes timekeeping
 pub(crate) fn init() -> EResult<()> {
+	vvar::init()?;
 	clock::init(rtc::read_time());
-	const FREQUENCY: u32 = 1024;
-	rtc::set_frequency(FREQUENCY);
+	if let Some(src) = clocksource() {
+		println!("Using clocksource {}", src.name);
+		clock::set_source(src);
+	}
+	// Fire timers at their exact expiration time, if possible
+	if apic::is_present() && hpet::init_event(core_id()) {
+		unsafe {
+			int::register_callback(hpet::INTERRUPT_VECTOR as _, |_, _, _, _| {
+				timer::tick();
+			})?;
+		}
+	}
+	rtc::set_frequency(TICK_FREQUENCY);
 	if apic::is_present() {
-		apic::redirect_int(0x8, core_id(), rtc::INTERRUPT_VECTOR);
+		apic::redirect_irq(0x8, core_id(), rtc::INTERRUPT_VECTOR);
 	}
 	unsafe {
 		int::register_callback(rtc::INTERRUPT_VECTOR as _, move |_, _, _, _| {
 			rtc::reset();
 			// FIXME: we are loosing precision here
-			clock::update((1_000_000_000 / FREQUENCY) as _);
+			clock::update((1_000_000_000 / TICK_FREQUENCY) as _);
 			timer::tick();
 		})?;
 	}
@@ -0,0 +1,217 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Timeouts of blocking system calls.
+//!
+//! A [`Timeout`] is built once when entering a system call, from either a relative delay or an
+//! absolute deadline, then used to arm a wakeup timer, check for expiry and report the remaining
+//! time back to userspace.
+
+use crate::{
+	memory::user::UserPtr,
+	process,
+	process::{Process, State, scheduler::schedule},
+	time::{
+		clock::{Clock, current_time_ns},
+		timer::Timer,
+		unit::{TimeUnit, Timestamp},
+	},
+};
+use core::{ffi::c_int, hint::unlikely};
+use utils::{
+	errno,
+	errno::{AllocResult, EResult},
+};
+
+/// The timeout of a blocking operation.
+#[derive(Clone, Copy, Debug)]
+pub enum Timeout {
+	/// The operation never times out.
+	Infinite,
+	/// The operation times out when `clock` reaches `deadline`.
+	Deadline {
+		/// The clock the deadline refers to
+		clock: Clock,
+		/// The expiry time, in nanoseconds
+		deadline: Timestamp,
+	},
+}
+
+impl Timeout {
+	/// Returns a timeout expiring after `delay` nanoseconds on `clock`.
+	///
+	/// If the deadline cannot be represented, the timeout is infinite.
+	pub fn relative(clock: Clock, delay: Timestamp) -> Self {
+		match current_time_ns(clock).checked_add(delay) {
+			Some(deadline) => Self::Deadline {
+				clock,
+				deadline,
+			},
+			None => Self::Infinite,
+		}
+	}
+
+	/// Returns a timeout expiring when `clock` reaches `deadline`, in nanoseconds.
+	pub const fn absolute(clock: Clock, deadline: Timestamp) -> Self {
+		Self::Deadline {
+			clock,
+			deadline,
+		}
+	}
+
+	/// Returns a timeout from a value in milliseconds, as used by `poll` and `epoll_wait`.
+	///
+	/// A negative value means the timeout is infinite.
+	pub fn from_ms(ms: c_int) -> Self {
+		if ms < 0 {
+			Self::Infinite
+		} else {
+			Self::relative(Clock::Monotonic, ms as Timestamp * 1_000_000)
+		}
+	}
+
+	/// Returns a timeout from the userspace value `val`.
+	///
+	/// If `abs` is set, the value is a deadline on `clock`. Else, it is a delay.
+	///
+	/// If the value is invalid, the function returns [`errno::EINVAL`].
+	pub fn from_value<T: TimeUnit>(val: &T, clock: Clock, abs: bool) -> EResult<Self> {
+		if unlikely(!val.is_valid()) {
+			return Err(errno!(EINVAL));
+		}
+		let val = val.to_nano();
+		if abs {
+			Ok(Self::absolute(clock, val))
+		} else {
+			Ok(Self::relative(clock, val))
+		}
+	}
+
+	/// Same as [`Self::from_value`], reading the value from userspace.
+	///
+	/// If `ptr` is null, the timeout is infinite.
+	pub fn from_user<T: TimeUnit>(ptr: UserPtr<T>, clock: Clock, abs: bool) -> EResult<Self> {
+		match ptr.copy_from_user()? {
+			Some(val) => Self::from_value(&val, clock, abs),
+			None => Ok(Self::Infinite),
+		}
+	}
+
+	/// Returns the time remaining before expiry, in nanoseconds.
+	///
+	/// If the timeout is infinite, the function returns `None`.
+	pub fn remaining(&self) -> Option<Timestamp> {
+		match self {
+			Self::Infinite => None,
+			Self::Deadline {
+				clock,
+				deadline,
+			} => Some(deadline.saturating_sub(current_time_ns(*clock))),
+		}
+	}
+
+	/// Tells whether the timeout has expired.
+	pub fn has_expired(&self) -> bool {
+		self.remaining() == Some(0)
+	}
+
+	/// Writes the remaining time to `ptr`.
+	///
+	/// If the timeout is infinite or `ptr` is null, nothing is written.
+	pub fn write_remaining<T: TimeUnit>(&self, ptr: UserPtr<T>) -> EResult<()> {
+		if let Some(remain) = self.remaining() {
+			ptr.copy_to_user(&T::from_nano(remain))?;
+		}
+		Ok(())
+	}
+
+	/// Returns a timer waking the current process up from [`State::IntSleeping`] when the timeout
+	/// expires.
+	///
+	/// The timer is disarmed when dropped. If the timeout is infinite, the function returns
+	/// `None`.
+	pub fn timer(&self) -> AllocResult<Option<Timer>> {
+		let Self::Deadline {
+			clock, ..
+		} = *self
+		else {
+			return Ok(None);
+		};
+		let proc = Process::current();
+		let mut timer = Timer::new(clock, move || {
+			Process::wake_from(&proc, State::IntSleeping as u8)
+		})?;
+		// A value of zero would disarm the timer
+		let remain = self.remaining().unwrap_or(0).max(1);
+		timer.set_time(0, remain)?;
+		Ok(Some(timer))
+	}
+
+	/// Makes the current process sleep until the timeout expires.
+	///
+	/// If the process is interrupted by a signal, the function returns [`errno::EINTR`].
+	pub fn sleep(&self) -> EResult<()> {
+		let _timer = self.timer()?;
+		loop {
+			if unlikely(self.has_expired()) {
+				break;
+			}
+			if unlikely(Process::current().has_pending_signal()) {
+				return Err(errno!(EINTR));
+			}
+			process::set_state(State::IntSleeping);
+			schedule();
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::time::unit::{Timespec, Timeval32};
+
+	#[test_case]
+	fn timeout_validation() {
+		let ts = Timespec {
+			tv_sec: 0,
+			tv_nsec: 1_000_000_000,
+		};
+		assert!(Timeout::from_value(&ts, Clock::Monotonic, false).is_err());
+		let ts = Timespec {
+			tv_sec: 0,
+			tv_nsec: -1,
+		};
+		assert!(Timeout::from_value(&ts, Clock::Monotonic, false).is_err());
+		let tv = Timeval32 {
+			tv_sec: u32::MAX,
+			tv_usec: 0,
+		};
+		assert!(Timeout::from_value(&tv, Clock::Monotonic, false).is_err());
+	}
+
+	#[test_case]
+	fn timeout_expiry() {
+		assert!(Timeout::relative(Clock::Monotonic, 0).has_expired());
+		assert!(Timeout::absolute(Clock::Monotonic, 0).has_expired());
+		assert!(!Timeout::Infinite.has_expired());
+		assert_eq!(Timeout::Infinite.remaining(), None);
+		let timeout = Timeout::relative(Clock::Monotonic, u64::MAX);
+		assert!(matches!(timeout, Timeout::Infinite));
+	}
+}
@@ -20,10 +20,13 @@
 
 use super::unit::TimerT;
 use crate::{
+	arch::x86::timer::hpet,
 	memory::oom,
 	process::{
 		Process,
-		signal::{SIGEV_SIGNAL, SIGEV_THREAD, SIGEV_THREAD_ID, SigEvent, Signal},
+		signal::{
+			SI_TIMER, SIGEV_SIGNAL, SIGEV_THREAD, SIGEV_THREAD_ID, SigEvent, SigInfo, Signal,
+		},
 	},
 	sync::spin::IntSpin,
 	time::{
@@ -31,13 +34,14 @@ use crate::{
 		unit::Timestamp,
 	},
 };
-use core::hint::unlikely;
+use core::{ffi::c_int, hint::unlikely};
 use utils::{
 	boxed::Box,
 	collections::{btreemap::BTreeMap, hashmap::HashMap, id_allocator::IDAllocator},
 	errno,
 	errno::{AllocResult, EResult},
 	limits::TIMER_MAX,
+	ptr::arc::Arc,
 };
 // TODO make sure a timer doesn't send a signal to a thread that do not belong to the manager's
 // process
@@ -158,10 +162,11 @@ impl Timer {
 		if value == 0 {
 			spec.next = None;
 		} else {
-			let next = current_time_ns(self.0.clock) + value;
+			let next = current_time_ns(self.0.clock).saturating_add(value);
 			spec.next = Some(next);
 			// Insert back in queue
 			queue.insert((next, self.0.as_ptr()), ())?;
+			arm_next(&queue);
 		}
 		Ok(())
 	}
@@ -201,6 +206,14 @@ impl TimerManager {
 		})
 	}
 
+	/// Returns a reference to the timer with the given ID.
+	///
+	/// If the timer doesn't exist, the function returns `None`.
+	#[inline]
+	pub fn get_timer(&self, id: TimerT) -> Option<&Timer> {
+		self.timers.get(&id)
+	}
+
 	/// Returns a mutable reference to the timer with the given ID.
 	///
 	/// If the timer doesn't exist, the function returns `None`.
@@ -222,23 +235,28 @@ impl TimerManager {
 		}
 		let sig = Signal::try_from(sevp.sigev_signo)?;
 		let proc = Process::current();
+		let mut this = proc.timer_manager.lock();
+		let id = this.id_allocator.alloc(None)?;
+		let info = SigInfo {
+			si_signo: sig.0,
+			si_code: SI_TIMER,
+			si_pid: id,
+			si_value: sevp.sigev_value as _,
+			..Default::default()
+		};
+		let target = proc.clone();
 		let f = move || {
 			match sevp.sigev_notify {
 				// TODO for SIGEV_THREAD_ID, target the thread identified by
 				// sevp.sigev_notify_thread_id
-				SIGEV_SIGNAL | SIGEV_THREAD_ID => {
-					// TODO on sigint_t, set si_code to SI_TIMER
-					Process::kill(&proc, sig);
-				}
+				SIGEV_SIGNAL | SIGEV_THREAD_ID => Process::kill_info(&target, info),
 				SIGEV_THREAD => todo!(),
 				_ => {}
 			}
 		};
-		let timer = Timer::new(clock, f)?;
-		let proc = Process::current();
-		let mut this = proc.timer_manager.lock();
-		let id = this.id_allocator.alloc(None)?;
-		if let Err(e) = this.timers.insert(id as _, timer) {
+		let res =
+			Timer::new(clock, f).and_then(|timer| this.timers.insert(id as _, timer).map(|_| ()));
+		if let Err(e) = res {
 			// Allocation error: rollback
 			this.id_allocator.free(id);
 			return Err(e.into());
@@ -260,6 +278,140 @@ impl TimerManager {
 	}
 }
 
+/// Interval timer counting real time, sending `SIGALRM` on expiration.
+pub const ITIMER_REAL: c_int = 0;
+/// Interval timer counting user CPU time, sending `SIGVTALRM` on expiration.
+pub const ITIMER_VIRTUAL: c_int = 1;
+/// Interval timer counting total CPU time, sending `SIGPROF` on expiration.
+pub const ITIMER_PROF: c_int = 2;
+
+/// An interval timer counting CPU time.
+///
+/// Since CPU time only advances while the process runs, the timer is checked on the timer tick
+/// instead of being placed on the timers queue.
+#[derive(Default)]
+struct CpuTimer {
+	/// The interval between two expirations, in nanoseconds
+	interval: Timestamp,
+	/// The value of the CPU time counter, in nanoseconds, at which the timer expires
+	///
+	/// If `None`, the timer is unarmed
+	deadline: Option<Timestamp>,
+}
+
+impl CpuTimer {
+	/// Returns the current state of the timer as `(interval, value)`, in nanoseconds.
+	///
+	/// `counter` is the current value of the CPU time counter.
+	fn get(&self, counter: Timestamp) -> (Timestamp, Timestamp) {
+		let value = self
+			.deadline
+			.map(|deadline| deadline.saturating_sub(counter).max(1))
+			.unwrap_or(0);
+		(self.interval, value)
+	}
+
+	/// Sets the timer's state.
+	///
+	/// `counter` is the current value of the CPU time counter.
+	fn set(&mut self, counter: Timestamp, interval: Timestamp, value: Timestamp) {
+		self.interval = interval;
+		self.deadline = (value != 0).then(|| counter.saturating_add(value));
+	}
+
+	/// Tells whether the timer has expired, rearming it if periodic.
+	///
+	/// `counter` is the current value of the CPU time counter.
+	fn check(&mut self, counter: Timestamp) -> bool {
+		let Some(deadline) = self.deadline else {
+			return false;
+		};
+		if counter < deadline {
+			return false;
+		}
+		self.deadline = (self.interval != 0).then(|| counter.saturating_add(self.interval));
+		true
+	}
+}
+
+/// A process's interval timers, as set by `setitimer`.
+#[derive(Default)]
+pub struct ITimers {
+	/// [`ITIMER_REAL`], created on first use
+	real: Option<Timer>,
+	/// [`ITIMER_VIRTUAL`]
+	virt: CpuTimer,
+	/// [`ITIMER_PROF`]
+	prof: CpuTimer,
+}
+
+impl ITimers {
+	/// Returns the state of the timer `which` of `proc` as `(interval, value)`, in nanoseconds.
+	///
+	/// If `which` is invalid, the function returns an error.
+	pub fn get(&self, proc: &Process, which: c_int) -> EResult<(Timestamp, Timestamp)> {
+		let (utime, stime) = proc.cputime.get();
+		match which {
+			ITIMER_REAL => Ok(self.real.as_ref().map(Timer::get_time).unwrap_or((0, 0))),
+			ITIMER_VIRTUAL => Ok(self.virt.get(utime)),
+			ITIMER_PROF => Ok(self.prof.get(utime + stime)),
+			_ => Err(errno!(EINVAL)),
+		}
+	}
+
+	/// Sets the state of the timer `which` of `proc`, in nanoseconds.
+	///
+	/// A `value` of zero disarms the timer.
+	///
+	/// On success, the function returns the previous state of the timer as `(interval, value)`.
+	pub fn set(
+		&mut self,
+		proc: &Arc<Process>,
+		which: c_int,
+		interval: Timestamp,
+		value: Timestamp,
+	) -> EResult<(Timestamp, Timestamp)> {
+		let old = self.get(proc, which)?;
+		let (utime, stime) = proc.cputime.get();
+		match which {
+			ITIMER_REAL => {
+				let timer = match &mut self.real {
+					Some(timer) => timer,
+					real @ None => {
+						let proc = proc.clone();
+						real.insert(Timer::new(Clock::Monotonic, move || {
+							Process::kill(&proc, Signal::SIGALRM);
+						})?)
+					}
+				};
+				timer.set_time(interval, value)?;
+			}
+			ITIMER_VIRTUAL => self.virt.set(utime, interval, value),
+			ITIMER_PROF => self.prof.set(utime + stime, interval, value),
+			_ => unreachable!(),
+		}
+		Ok(old)
+	}
+
+	/// Checks the CPU time timers of `proc`, sending a signal to it for each expired timer.
+	///
+	/// This function is called on the timer tick.
+	pub fn tick(&mut self, proc: &Arc<Process>) {
+		let (utime, stime) = proc.cputime.get();
+		if self.virt.check(utime) {
+			Process::kill(proc, Signal::SIGVTALRM);
+		}
+		if self.prof.check(utime + stime) {
+			Process::kill(proc, Signal::SIGPROF);
+		}
+	}
+
+	/// Disarms and releases all the timers.
+	pub fn clear(&mut self) {
+		*self = Self::default();
+	}
+}
+
 // TODO use intrusive binary trees in order to avoid memory allocations
 /// The queue of timers to be fired next.
 ///
@@ -269,6 +421,18 @@ impl TimerManager {
 static TIMERS_QUEUE: IntSpin<BTreeMap<(Timestamp, *const TimerInner), ()>> =
 	IntSpin::new(BTreeMap::new());
 
+/// Programs the event timer to fire when the first timer of `queue` expires.
+///
+/// If no event timer is available, timers are checked on each tick of the periodic timer instead.
+fn arm_next(queue: &BTreeMap<(Timestamp, *const TimerInner), ()>) {
+	let Some(((next, timer), _)) = queue.first_key_value() else {
+		return;
+	};
+	let clock = unsafe { (**timer).clock };
+	let delay = next.saturating_sub(current_time_ns(clock));
+	hpet::set_oneshot(delay);
+}
+
 /// Triggers all expired timers.
 pub(super) fn tick() {
 	let mut times: [Option<Timestamp>; 12] = Default::default();
@@ -292,4 +456,5 @@ pub(super) fn tick() {
 			oom::wrap(|| timer.reset(&mut queue, ts));
 		}
 	}
+	arm_next(&queue);
 }
@@ -20,7 +20,7 @@
 
 use core::{
 	cmp::Ordering,
-	ffi::{c_int, c_long},
+	ffi::c_int,
 	fmt::Debug,
 	ops::{Add, Sub},
 };
@@ -42,6 +42,9 @@ pub trait TimeUnit: Sized + Clone + Copy + Debug {
 	fn from_nano(timestamp: u64) -> Self;
 	/// Returns the equivalent timestamp in nanoseconds.
 	fn to_nano(&self) -> u64;
+	/// Tells whether the value is valid, that is if it is not negative and if the sub-second
+	/// part is in range.
+	fn is_valid(&self) -> bool;
 }
 
 /// POSIX structure representing a timestamp.
@@ -64,8 +67,12 @@ impl TimeUnit for Timeval {
 
 	fn to_nano(&self) -> u64 {
 		self.tv_sec
-			.wrapping_mul(1_000_000_000)
-			.wrapping_add(self.tv_usec.wrapping_mul(1000))
+			.saturating_mul(1_000_000_000)
+			.saturating_add(self.tv_usec.saturating_mul(1000))
+	}
+
+	fn is_valid(&self) -> bool {
+		(self.tv_sec as i64) >= 0 && self.tv_usec < 1_000_000
 	}
 }
 
@@ -107,6 +114,33 @@ impl PartialOrd for Timeval {
 	}
 }
 
+/// Same as [`Timeval`], but with 32 bits values.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct Timeval32 {
+	/// Seconds
+	pub tv_sec: u32,
+	/// Microseconds
+	pub tv_usec: u32,
+}
+
+impl TimeUnit for Timeval32 {
+	fn from_nano(timestamp: u64) -> Self {
+		Self {
+			tv_sec: (timestamp / 1_000_000_000) as _,
+			tv_usec: ((timestamp % 1_000_000_000) / 1000) as _,
+		}
+	}
+
+	fn to_nano(&self) -> u64 {
+		(self.tv_sec as u64) * 1_000_000_000 + (self.tv_usec as u64) * 1000
+	}
+
+	fn is_valid(&self) -> bool {
+		(self.tv_sec as i32) >= 0 && self.tv_usec < 1_000_000
+	}
+}
+
 /// Same as [`Timeval`], but with nanosecond precision.
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 #[repr(C)]
@@ -114,7 +148,7 @@ pub struct Timespec {
 	/// Seconds
 	pub tv_sec: Timestamp,
 	/// Nanoseconds
-	pub tv_nsec: c_long,
+	pub tv_nsec: i64,
 }
 
 impl TimeUnit for Timespec {
@@ -127,8 +161,12 @@ impl TimeUnit for Timespec {
 
 	fn to_nano(&self) -> u64 {
 		self.tv_sec
-			.wrapping_mul(1_000_000_000)
-			.wrapping_add(self.tv_nsec as u64)
+			.saturating_mul(1_000_000_000)
+			.saturating_add(self.tv_nsec as u64)
+	}
+
+	fn is_valid(&self) -> bool {
+		(self.tv_sec as i64) >= 0 && (0..1_000_000_000).contains(&self.tv_nsec)
 	}
 }
 
@@ -178,6 +216,16 @@ pub struct Timespec32 {
 	pub tv_nsec: u32,
 }
 
+impl From<Timespec32> for Timespec {
+	fn from(ts: Timespec32) -> Self {
+		Self {
+			// Sign-extend so that negative (invalid) values remain invalid
+			tv_sec: ts.tv_sec as i32 as _,
+			tv_nsec: ts.tv_nsec as _,
+		}
+	}
+}
+
 impl TimeUnit for Timespec32 {
 	fn from_nano(timestamp: u64) -> Self {
 		Self {
@@ -187,9 +235,11 @@ impl TimeUnit for Timespec32 {
 	}
 
 	fn to_nano(&self) -> u64 {
-		(self.tv_sec as u64)
-			.wrapping_mul(1_000_000_000)
-			.wrapping_add(self.tv_nsec as u64)
+		(self.tv_sec as u64) * 1_000_000_000 + self.tv_nsec as u64
+	}
+
+	fn is_valid(&self) -> bool {
+		(self.tv_sec as i32) >= 0 && self.tv_nsec < 1_000_000_000
 	}
 }
 
@@ -239,6 +289,24 @@ pub struct ITimerspec32 {
 	pub it_value: Timespec32,
 }
 
+impl From<ITimerspec32> for ITimerspec {
+	fn from(spec: ITimerspec32) -> Self {
+		Self {
+			it_interval: spec.it_interval.into(),
+			it_value: spec.it_value.into(),
+		}
+	}
+}
+
+impl From<ITimerspec> for ITimerspec32 {
+	fn from(spec: ITimerspec) -> Self {
+		Self {
+			it_interval: Timespec32::from_nano(spec.it_interval.to_nano()),
+			it_value: Timespec32::from_nano(spec.it_value.to_nano()),
+		}
+	}
+}
+
 /// A timer's state, 64-bit ABI.
 #[derive(Clone, Copy, Debug, Default)]
 #[repr(C)]
@@ -249,6 +317,36 @@ pub struct ITimerspec {
 	pub it_value: Timespec,
 }
 
+/// An interval timer's state, 32-bit ABI.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct ITimerval32 {
+	/// The interval between each expiration of the timer.
+	pub it_interval: Timeval32,
+	/// Time until the next expiration of the timer.
+	pub it_value: Timeval32,
+}
+
+/// An interval timer's state, 64-bit ABI.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct ITimerval {
+	/// The interval between each expiration of the timer.
+	pub it_interval: Timeval,
+	/// Time until the next expiration of the timer.
+	pub it_value: Timeval,
+}
+
+/// The system's timezone, as used by `gettimeofday` and `settimeofday`.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct Timezone {
+	/// Minutes west of Greenwich
+	pub tz_minuteswest: c_int,
+	/// Type of daylight saving time correction
+	pub tz_dsttime: c_int,
+}
+
 /// Legacy structure for `utime`
 #[derive(Clone, Copy, Debug, Default)]
 #[repr(C)]
@@ -258,3 +356,31 @@ pub struct UTimBuf {
 	/// Modification time
 	pub modtime: u32,
 }
+
+/// Process times, as returned by `times`, in clock ticks (see [`super::CLK_TCK`]).
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct Tms {
+	/// User CPU time
+	pub tms_utime: u64,
+	/// System CPU time
+	pub tms_stime: u64,
+	/// User CPU time of terminated children
+	pub tms_cutime: u64,
+	/// System CPU time of terminated children
+	pub tms_cstime: u64,
+}
+
+/// Same as [`Tms`], 32-bit ABI.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct Tms32 {
+	/// User CPU time
+	pub tms_utime: u32,
+	/// System CPU time
+	pub tms_stime: u32,
+	/// User CPU time of terminated children
+	pub tms_cutime: u32,
+	/// System CPU time of terminated children
+	pub tms_cstime: u32,
+}
@@ -0,0 +1,118 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The vvar page is a page of data shared read-only with userspace, next to the vDSO.
+//!
+//! It exposes the state of clocks so that the vDSO can implement `clock_gettime`,
+//! `gettimeofday` and `time` without performing a system call.
+//!
+//! Readers use the sequence counter to detect concurrent updates: the counter is odd while an
+//! update is in progress, and changes after each update.
+
+use crate::{
+	memory::cache::RcPage,
+	sync::{once::OnceInit, spin::IntSpin},
+};
+use core::sync::atomic::{
+	AtomicU32,
+	Ordering::{Relaxed, Release},
+	fence,
+};
+use utils::errno::AllocResult;
+
+/// Mode: clocks values are exact, the vDSO returns them directly.
+pub const MODE_TICK: u32 = 0;
+/// Mode: the vDSO extrapolates clocks values from the TSC.
+pub const MODE_TSC: u32 = 1;
+/// Mode: the vDSO cannot compute time and falls back to the system call.
+pub const MODE_SYSCALL: u32 = 2;
+
+/// The content of the vvar page.
+///
+/// The layout of this structure must match the offsets used by the vDSO.
+#[repr(C)]
+struct VvarData {
+	/// The sequence counter
+	seq: AtomicU32,
+	/// The mode, telling how the vDSO computes time
+	mode: u32,
+	/// For [`MODE_TSC`], the value of the counter from which time is extrapolated
+	base: u64,
+	/// For [`MODE_TSC`], the multiplier converting cycles into nanoseconds, shifted by 32
+	mult: u64,
+	/// The base value of the real time clock, in nanoseconds
+	realtime: u64,
+	/// The base value of the monotonic clock, in nanoseconds
+	monotonic: u64,
+	/// The base value of the boot time clock, in nanoseconds
+	boottime: u64,
+}
+
+/// The state of clocks to be exposed through the vvar page.
+#[derive(Clone, Copy, Debug)]
+pub struct VvarClocks {
+	/// The mode, telling how the vDSO computes time
+	pub mode: u32,
+	/// The value of the counter from which time is extrapolated
+	pub base: u64,
+	/// The multiplier converting cycles into nanoseconds, shifted by 32
+	pub mult: u64,
+	/// The base value of the real time clock, in nanoseconds
+	pub realtime: u64,
+	/// The base value of the monotonic clock, in nanoseconds
+	pub monotonic: u64,
+	/// The base value of the boot time clock, in nanoseconds
+	pub boottime: u64,
+}
+
+/// The vvar page.
+static PAGE: OnceInit<RcPage> = unsafe { OnceInit::new() };
+/// Lock serializing updates of the page.
+static LOCK: IntSpin<()> = IntSpin::new(());
+
+/// Returns the vvar page.
+pub fn page() -> &'static RcPage {
+	&PAGE
+}
+
+/// Updates the content of the vvar page with `clocks`.
+pub(crate) fn update(clocks: VvarClocks) {
+	let _guard = LOCK.lock();
+	let data = PAGE.virt_addr().as_ptr::<VvarData>();
+	unsafe {
+		let seq = (*data).seq.load(Relaxed);
+		(*data).seq.store(seq.wrapping_add(1), Relaxed);
+		fence(Release);
+		(*data).mode = clocks.mode;
+		(*data).base = clocks.base;
+		(*data).mult = clocks.mult;
+		(*data).realtime = clocks.realtime;
+		(*data).monotonic = clocks.monotonic;
+		(*data).boottime = clocks.boottime;
+		(*data).seq.store(seq.wrapping_add(2), Release);
+	}
+}
+
+/// Allocates the vvar page.
+pub(crate) fn init() -> AllocResult<()> {
+	let page = RcPage::new_zeroed()?;
+	unsafe {
+		OnceInit::init(&PAGE, page);
+	}
+	Ok(())
+}
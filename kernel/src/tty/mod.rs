@@ -21,8 +21,9 @@
 //!
 //! This module implements line discipline for TTYs.
 //!
-//! At startup, the kernel has one TTY: the init TTY, which is stored separately
-//! because at the time of creation, memory management isn't initialized yet.
+//! The kernel provides [`VT_COUNT`] virtual terminals sharing the screen. Only the active one is
+//! displayed, the others keep updating their history in the background. They are stored
+//! statically because at the time of creation, memory management isn't initialized yet.
 
 mod ansi;
 pub mod termios;
@@ -32,8 +33,13 @@ use crate::{
 	device::{fb, fb::Framebuffer},
 	memory::{user::UserSlice, vmem::KERNEL_VMEM},
 	multiboot::BootInfo,
-	process::{Process, pid::Pid, signal::Signal},
+	process::{Process, State, pid::Pid, signal::Signal},
 	sync::{spin::IntSpin, wait_queue::WaitQueue},
+	time::{
+		clock::{Clock, current_time_ns},
+		timer::Timer,
+		unit::Timestamp,
+	},
 	tty::{
 		ansi::{ANSIBuffer, ESCAPE},
 		termios::{Termios, consts::*},
@@ -43,6 +49,7 @@ use crate::{
 use core::{cmp::min, hint::unlikely, mem, ptr};
 use utils::{
 	collections::vec::Vec,
+	errno,
 	errno::{AllocResult, EResult},
 	ptr::arc::Arc,
 	vec,
@@ -51,6 +58,9 @@ use utils::{
 /// The number of history lines for one TTY.
 const HISTORY_LINES: usize = 128;
 
+/// The number of virtual terminals.
+pub const VT_COUNT: usize = 6;
+
 /// Color
 pub type Rgb = (u8, u8, u8);
 
@@ -170,12 +180,16 @@ pub struct Display {
 
 	/// The Y position of the screen in the history
 	screen_y: usize,
+	/// The number of lines the view is scrolled back in the history, relative to the screen
+	view_off: usize,
 	/// The content of the TTY's history
 	// TODO Vec stores capacity and length. We don't need those since we can determine them from
 	// the size of the history and the width of the screen
 	history: Vec<Char>,
 	/// The framebuffer. If `None`, we use text mode
 	framebuffer: Option<Arc<Framebuffer>>,
+	/// Tells whether the TTY is the one on screen. If not, nothing is drawn
+	active: bool,
 
 	/// Top row of the scrolling region (DECSTBM), in screen-relative coordinates.
 	scroll_top: usize,
@@ -198,17 +212,30 @@ impl Display {
 		y * self.width + x
 	}
 
+	/// Returns the Y position in the history of the first line on display, taking the scrollback
+	/// into account
+	fn view_y(&self) -> usize {
+		(self.screen_y + HISTORY_LINES - self.view_off) % HISTORY_LINES
+	}
+
 	fn display_char(&self, c: Char, x: usize, y: usize) {
+		// If the TTY is not displayed, or the view is scrolled back, do nothing
+		if !self.active || self.view_off != 0 {
+			return;
+		}
 		// If the character isn't on screen, do nothing
-		let history_y = y;
-		let y = if y >= self.screen_y {
-			y - self.screen_y
-		} else {
-			(HISTORY_LINES - self.screen_y) + y
-		};
-		if y >= self.height {
+		let row = relative_y_distance(self.screen_y, y);
+		if row >= self.height {
 			return;
 		}
+		let cursor = self.cursor_visible && x == self.cursor_x && y == self.cursor_y;
+		self.draw_char(c, x, row, cursor);
+	}
+
+	/// Draws the character `c` at the given position on screen.
+	///
+	/// `cursor` tells whether the cursor is on this cell.
+	fn draw_char(&self, c: Char, x: usize, y: usize, cursor: bool) {
 		const FONT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/font.bin"));
 		if let Some(fb) = &self.framebuffer {
 			let fb_ptr: *mut u8 = fb.addr().as_ptr();
@@ -220,12 +247,7 @@ impl Display {
 			let data = &FONT[data_off..data_off + 16];
 			let char_px_off = y * CHAR_HEIGHT * pitch + x * CHAR_WIDTH * bytes_per_pixel;
 			// Swap fg/bg if the cursor is on this cell
-			let (fg, bg) =
-				if self.cursor_visible && x == self.cursor_x && history_y == self.cursor_y {
-					(c.bg, c.fg)
-				} else {
-					(c.fg, c.bg)
-				};
+			let (fg, bg) = if cursor { (c.bg, c.fg) } else { (c.fg, c.bg) };
 			// Pack a Rgb triple into a pixel word using the framebuffer channel layout
 			let pack = |val: u8, pos: u8, size: u8| -> u32 { (val as u32 >> (8 - size)) << pos };
 			let to_pixel = |(r, g, b): Rgb| -> [u8; 4] {
@@ -271,15 +293,26 @@ impl Display {
 	}
 
 	fn update_display(&self) {
-		for l in 0..self.height {
-			let y = (self.screen_y + l) % HISTORY_LINES;
+		if !self.active {
+			return;
+		}
+		let view_y = self.view_y();
+		for row in 0..self.height {
+			let y = (view_y + row) % HISTORY_LINES;
 			for x in 0..self.width {
-				self.display_char(self.history[self.history_off(x, y)], x, y);
+				let cursor = self.view_off == 0
+					&& self.cursor_visible
+					&& x == self.cursor_x
+					&& y == self.cursor_y;
+				self.draw_char(self.history[self.history_off(x, y)], x, row, cursor);
 			}
 		}
 	}
 
 	fn scroll_display(&mut self, newlines: usize) {
+		if !self.active || self.view_off != 0 {
+			return;
+		}
 		if let Some(fb) = &self.framebuffer {
 			let fb_ptr: *mut u8 = fb.addr().as_ptr();
 			let pitch = fb.info().framebuffer_pitch as usize;
@@ -310,6 +343,9 @@ impl Display {
 	}
 
 	fn clear_display(&self) {
+		if !self.active {
+			return;
+		}
 		if let Some(fb) = &self.framebuffer {
 			let fb_ptr: *mut u8 = fb.addr().as_ptr();
 			unsafe {
@@ -331,6 +367,9 @@ impl Display {
 	/// `old_cursor` is the previous position of the cursor. If set, the function erases the cursor
 	/// at the previous position
 	fn update_cursor(&self, old_cursor: Option<(usize, usize)>) {
+		if !self.active || self.view_off != 0 {
+			return;
+		}
 		if self.framebuffer.is_some() {
 			if let Some((old_cursor_x, old_cursor_y)) = old_cursor {
 				// Erase old cursor
@@ -349,6 +388,9 @@ impl Display {
 	/// Hides or shows the cursor on screen.
 	fn set_cursor_visible(&mut self, visible: bool) {
 		self.cursor_visible = visible;
+		if !self.active {
+			return;
+		}
 		#[allow(clippy::collapsible_else_if)]
 		if self.framebuffer.is_some() {
 			let off = self.history_off(self.cursor_x, self.cursor_y);
@@ -405,6 +447,35 @@ impl Display {
 		self.update_cursor(None);
 	}
 
+	/// Redraws the whole screen from the TTY's state. This is used when the TTY becomes active.
+	fn redraw(&mut self) {
+		self.clear_display();
+		self.update_display();
+		// In text mode, the cursor is a hardware feature
+		if self.framebuffer.is_none() {
+			if self.cursor_visible && self.view_off == 0 {
+				vga::enable_cursor();
+			} else {
+				vga::disable_cursor();
+			}
+		}
+		self.update_cursor(None);
+	}
+
+	/// Scrolls the view `n` lines back in the history if `up` is `true`, or forward otherwise.
+	fn scroll_view(&mut self, n: usize, up: bool) {
+		let max = HISTORY_LINES - self.height;
+		let view_off = if up {
+			min(self.view_off + n, max)
+		} else {
+			self.view_off.saturating_sub(n)
+		};
+		if view_off != self.view_off {
+			self.view_off = view_off;
+			self.redraw();
+		}
+	}
+
 	/// Copies the history row at `src_row` to the row at `dst_row`.
 	fn copy_history_row(&mut self, src_row: usize, dst_row: usize) {
 		let src_start = src_row * self.width;
@@ -577,10 +648,88 @@ impl Display {
 struct Input {
 	/// The buffer containing characters from TTY input.
 	buf: [u8; INPUT_MAX],
+	/// For each byte in `buf`, tells whether it terminates a line in canonical mode.
+	delim: [bool; INPUT_MAX],
 	/// The current size of the input buffer.
 	input_size: usize,
 	/// The size of the data available to be read from the TTY.
+	///
+	/// In canonical mode, this is the end of the last complete line. The line being edited
+	/// follows.
 	available_size: usize,
+	/// If `true`, the next character is to be taken literally (`VLNEXT`).
+	lnext: bool,
+}
+
+impl Input {
+	/// Appends the byte `c` to the buffer.
+	///
+	/// Arguments:
+	/// - `delim` tells whether the byte terminates a line
+	/// - `canon` tells whether the terminal is in canonical mode
+	///
+	/// If the buffer is full, the function returns `false`.
+	fn push(&mut self, c: u8, delim: bool, canon: bool) -> bool {
+		// In canonical mode, keep room for the line delimiter
+		let max = if canon && !delim {
+			INPUT_MAX - 1
+		} else {
+			INPUT_MAX
+		};
+		if self.input_size >= max {
+			return false;
+		}
+		self.buf[self.input_size] = c;
+		self.delim[self.input_size] = delim;
+		self.input_size += 1;
+		if !canon || delim {
+			self.available_size = self.input_size;
+		}
+		true
+	}
+
+	/// Returns the last character of the line being edited, if any.
+	fn last(&self) -> Option<u8> {
+		(self.input_size > self.available_size).then(|| self.buf[self.input_size - 1])
+	}
+
+	/// Removes the first `len` bytes of the buffer.
+	fn consume(&mut self, len: usize) {
+		self.buf.copy_within(len..self.input_size, 0);
+		self.delim.copy_within(len..self.input_size, 0);
+		self.input_size -= len;
+		self.available_size -= len;
+	}
+
+	/// Discards all the data in the buffer.
+	fn flush(&mut self) {
+		self.input_size = 0;
+		self.available_size = 0;
+		self.lnext = false;
+	}
+}
+
+/// An erase operation in canonical mode.
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Erase {
+	/// Erase the last character (`VERASE`).
+	Char,
+	/// Erase the last word (`VWERASE`).
+	Word,
+	/// Erase the whole line (`VKILL`).
+	Line,
+}
+
+/// Tells whether the special character at index `i` in `termios` is `c`.
+///
+/// A special character with the value `0` is disabled.
+fn is_cc(termios: &Termios, i: usize, c: u8) -> bool {
+	termios.c_cc[i] != 0 && termios.c_cc[i] == c
+}
+
+/// Tells whether `c` is echoed as `^X` when `ECHOCTL` is set.
+fn is_echoctl(c: u8) -> bool {
+	(c < 0x20 && c != b'\t' && c != b'\n') || c == 0x7f
 }
 
 struct Settings {
@@ -590,6 +739,8 @@ struct Settings {
 	winsize: WinSize,
 	/// The current foreground Program Group ID.
 	pgrp: Pid,
+	/// The number of times the TTY has been hung up.
+	hangups: u32,
 }
 
 // TODO Use the values in winsize
@@ -606,46 +757,116 @@ pub struct TTY {
 	rd_queue: WaitQueue,
 }
 
-/// The TTY.
-pub static TTY: TTY = TTY {
-	display: IntSpin::new(Display {
-		width: vga::WIDTH as usize,
-		height: vga::HEIGHT as usize,
-
-		cursor_x: 0,
-		cursor_y: 0,
-
-		screen_y: 0,
-		history: Vec::new(),
-		framebuffer: None,
-
-		scroll_top: 0,
-		scroll_bottom: vga::HEIGHT as usize,
-
-		ansi_buffer: ANSIBuffer::new(),
-
-		cursor_visible: true,
-		fg_color: DEFAULT_FG_COLOR,
-		bg_color: DEFAULT_BG_COLOR,
-	}),
-	input: IntSpin::new(Input {
-		buf: [0; INPUT_MAX],
-		input_size: 0,
-		available_size: 0,
-	}),
-	settings: IntSpin::new(Settings {
-		pgrp: 0,
-		termios: Termios::new(),
-		winsize: WinSize {
-			ws_row: vga::HEIGHT as _,
-			ws_col: vga::WIDTH as _,
-			ws_xpixel: vga::PIXEL_WIDTH as _,
-			ws_ypixel: vga::PIXEL_HEIGHT as _,
-		},
-	}),
-
-	rd_queue: WaitQueue::new(),
-};
+/// The virtual terminals.
+static VTS: [TTY; VT_COUNT] = [const { TTY::new() }; VT_COUNT];
+/// The index of the active virtual terminal in [`VTS`].
+static ACTIVE_VT: IntSpin<usize> = IntSpin::new(0);
+/// The queue of processes waiting for a virtual terminal to become active.
+static VT_SWITCH_QUEUE: WaitQueue = WaitQueue::new();
+
+impl TTY {
+	/// Creates a new TTY, to be shown with [`Self::show`].
+	const fn new() -> Self {
+		Self {
+			display: IntSpin::new(Display {
+				width: vga::WIDTH as usize,
+				height: vga::HEIGHT as usize,
+
+				cursor_x: 0,
+				cursor_y: 0,
+
+				screen_y: 0,
+				view_off: 0,
+				history: Vec::new(),
+				framebuffer: None,
+				active: false,
+
+				scroll_top: 0,
+				scroll_bottom: vga::HEIGHT as usize,
+
+				ansi_buffer: ANSIBuffer::new(),
+
+				cursor_visible: true,
+				fg_color: DEFAULT_FG_COLOR,
+				bg_color: DEFAULT_BG_COLOR,
+			}),
+			input: IntSpin::new(Input {
+				buf: [0; INPUT_MAX],
+				delim: [false; INPUT_MAX],
+				input_size: 0,
+				available_size: 0,
+				lnext: false,
+			}),
+			settings: IntSpin::new(Settings {
+				pgrp: 0,
+				hangups: 0,
+				termios: Termios::new(),
+				winsize: WinSize {
+					ws_row: vga::HEIGHT as _,
+					ws_col: vga::WIDTH as _,
+					ws_xpixel: vga::PIXEL_WIDTH as _,
+					ws_ypixel: vga::PIXEL_HEIGHT as _,
+				},
+			}),
+
+			rd_queue: WaitQueue::new(),
+		}
+	}
+}
+
+/// Returns the virtual terminal with the given number, starting at `1`.
+pub fn get(n: usize) -> Option<&'static TTY> {
+	VTS.get(n.checked_sub(1)?)
+}
+
+/// Returns the number of the active virtual terminal, starting at `1`.
+pub fn active() -> usize {
+	*ACTIVE_VT.lock() + 1
+}
+
+/// Returns the active virtual terminal.
+pub fn current() -> &'static TTY {
+	&VTS[*ACTIVE_VT.lock()]
+}
+
+/// Returns the TTY used as the kernel console.
+pub fn console() -> &'static TTY {
+	&VTS[0]
+}
+
+/// Makes the virtual terminal with the given number, starting at `1`, the active one.
+///
+/// If the virtual terminal does not exist, the function returns [`errno::ENXIO`].
+pub fn switch(n: usize) -> EResult<()> {
+	let new = n
+		.checked_sub(1)
+		.filter(|i| *i < VT_COUNT)
+		.ok_or_else(|| errno!(ENXIO))?;
+	{
+		let mut active = ACTIVE_VT.lock();
+		if *active == new {
+			return Ok(());
+		}
+		VTS[*active].display.lock().active = false;
+		*active = new;
+		let mut disp = VTS[new].display.lock();
+		disp.active = true;
+		// If not init yet, there is nothing to draw
+		if !disp.history.is_empty() {
+			disp.redraw();
+		}
+	}
+	VT_SWITCH_QUEUE.wake_all();
+	Ok(())
+}
+
+/// Waits until the virtual terminal with the given number, starting at `1`, is the active one.
+///
+/// If the virtual terminal does not exist, the function returns [`errno::ENXIO`].
+pub fn wait_active(n: usize) -> EResult<()> {
+	get(n).ok_or_else(|| errno!(ENXIO))?;
+	VT_SWITCH_QUEUE.wait_until(|| (active() == n).then_some(()))
+}
 
 impl TTY {
 	/// Shows the TTY on screen.
@@ -655,6 +876,7 @@ impl TTY {
 		let (width, height, xpixel, ypixel) = {
 			let mut disp = self.display.lock();
 			disp.framebuffer = fb;
+			disp.active = ptr::eq(self, current());
 			if let Some(fb) = &disp.framebuffer {
 				let width = fb.info().framebuffer_width as usize / CHAR_WIDTH;
 				let height = fb.info().framebuffer_height as usize / CHAR_HEIGHT;
@@ -690,19 +912,27 @@ impl TTY {
 
 	/// Writes the character `c` to the TTY.
 	fn putchar(&self, disp: &mut Display, mut c: char) {
-		if self.get_termios().c_oflag & OLCUC != 0 {
-			c = c.to_ascii_lowercase();
+		let oflag = self.get_termios().c_oflag;
+		let opost = oflag & OPOST != 0;
+		if opost && oflag & OLCUC != 0 {
+			c = c.to_ascii_uppercase();
+		}
+		if opost && oflag & OCRNL != 0 && c == '\r' {
+			c = '\n';
 		}
-
-		// TODO Implement ONLCR (Map NL to CR-NL)
-		// TODO Implement ONOCR
-		// TODO Implement ONLRET
 
 		match c as u32 {
 			0x07 => ring_bell(),
 			// Tab (\t)
 			0x09 => disp.cursor_forward(get_tab_size(disp.cursor_x)),
-			// New Line (\n)
+			// New Line (\n). Without ONLCR, a line feed does not return the carriage
+			0x0a if !opost || oflag & (ONLCR | ONLRET) == 0 => {
+				let cursor_x = disp.cursor_x;
+				disp.newline(1);
+				let old_cursor = (disp.cursor_x, disp.cursor_y);
+				disp.cursor_x = cursor_x;
+				disp.update_cursor(Some(old_cursor));
+			}
 			0x0a => disp.newline(1),
 			// Form Feed (^L)
 			0x0c => {
@@ -734,6 +964,11 @@ impl TTY {
 		if unlikely(display.history.is_empty()) {
 			return;
 		}
+		// Output brings the view back to the bottom of the history
+		if display.view_off != 0 {
+			display.view_off = 0;
+			display.redraw();
+		}
 		let mut i = 0;
 		while i < buf.len() {
 			let c = buf[i];
@@ -752,17 +987,26 @@ impl TTY {
 		}
 	}
 
+	/// Scrolls the view `n` lines back in the TTY's history if `up` is `true`, or forward
+	/// otherwise.
+	pub fn scroll(&self, n: usize, up: bool) {
+		let mut display = self.display.lock();
+		if unlikely(display.history.is_empty()) {
+			return;
+		}
+		display.scroll_view(n, up);
+	}
+
 	/// Injects bytes directly into the input buffer, bypassing ECHO and canonical mode
 	/// processing.
 	pub(crate) fn inject_input(&self, buffer: &[u8]) {
 		{
 			let mut input = self.input.lock();
-			let off = input.input_size;
-			let avail = input.buf.len() - off;
-			let len = min(buffer.len(), avail);
-			input.buf[off..off + len].copy_from_slice(&buffer[..len]);
-			input.input_size += len;
-			input.available_size += len;
+			for c in buffer {
+				if !input.push(*c, false, false) {
+					break;
+				}
+			}
 		}
 		self.rd_queue.wake_next();
 	}
@@ -770,54 +1014,96 @@ impl TTY {
 	// TODO Implement IUTF8
 	/// Reads inputs from the TTY and writes it into the buffer `buf`.
 	///
+	/// In canonical mode, at most one line is read. Otherwise, the behaviour depends on the
+	/// `VMIN` and `VTIME` special characters.
+	///
+	/// If `nonblock` is set and no data is available, the function returns [`errno::EAGAIN`].
+	///
+	/// `hangups` is the hangup count of the TTY when the file was opened (see
+	/// [`Self::hangup_count`]). If the TTY is hung up in the meantime, the function returns
+	/// end-of-file.
+	///
 	/// The function returns the number of bytes read.
-	pub fn read(&self, buf: UserSlice<u8>) -> EResult<usize> {
+	pub fn read(&self, buf: UserSlice<u8>, nonblock: bool, hangups: u32) -> EResult<usize> {
+		if unlikely(buf.is_empty()) {
+			return Ok(0);
+		}
+		let termios = self.get_termios();
+		if termios.c_lflag & ICANON != 0 {
+			return self.rd_queue.wait_until(|| {
+				if self.hangup_count() != hangups {
+					return Some(Ok(0));
+				}
+				let mut input = self.input.lock();
+				if input.available_size == 0 {
+					return nonblock.then(|| Err(errno!(EAGAIN)));
+				}
+				// Read up to the end of the first line
+				let line = input.delim[..input.available_size].iter().position(|d| *d);
+				let (len, consumed) = match line {
+					// The end-of-file character is not returned
+					Some(i) if i < buf.len() && input.buf[i] == termios.c_cc[VEOF] => (i, i + 1),
+					Some(i) if i < buf.len() => (i + 1, i + 1),
+					_ => {
+						let len = min(buf.len(), input.available_size);
+						(len, len)
+					}
+				};
+				if let Err(e) = buf.copy_to_user(0, &input.buf[..len]) {
+					return Some(Err(e));
+				}
+				input.consume(consumed);
+				Some(Ok(len))
+			})?;
+		}
+		let min_chars = min(termios.c_cc[VMIN] as usize, buf.len());
+		let time = termios.c_cc[VTIME] as Timestamp * 100_000_000;
+		// If `VMIN` is zero, the timer starts immediately. Otherwise, it is restarted each time a
+		// byte is received
+		let mut timer = None;
+		if time > 0 {
+			let proc = Process::current();
+			let mut t = Timer::new(Clock::Monotonic, move || {
+				Process::wake_from(&proc, State::IntSleeping as u8)
+			})?;
+			if min_chars == 0 {
+				t.set_time(0, time)?;
+			}
+			timer = Some(t);
+		}
+		let mut armed = min_chars == 0;
+		let mut last_available = 0;
 		self.rd_queue.wait_until(|| {
+			if self.hangup_count() != hangups {
+				return Some(Ok(0));
+			}
 			let mut input = self.input.lock();
-			let termios = self.get_termios();
-			// Canonical mode
-			let canon = termios.c_lflag & ICANON != 0;
-			let min_chars = if canon {
-				1
-			} else {
-				termios.c_cc[VMIN] as usize
-			};
-			// If not enough data is available, wait
-			if input.available_size < min_chars {
-				return None;
+			let available = input.available_size;
+			let mut ready = available >= min_chars.max(1) || (min_chars == 0 && time == 0);
+			if !ready && let Some(timer) = &mut timer {
+				if min_chars > 0 && available > last_available {
+					// A byte has been received: restart the inter-byte timer
+					last_available = available;
+					armed = true;
+					if let Err(e) = timer.set_time(0, time) {
+						return Some(Err(e.into()));
+					}
+				}
+				ready = armed && timer.has_expired(current_time_ns(Clock::Monotonic));
 			}
-			let mut len = min(buf.len(), input.available_size);
-			if canon {
-				let eof = termios.c_cc[VEOF];
-				let eof_off = input.buf[..len].iter().position(|v| *v == eof);
-				if eof_off == Some(0) {
-					// Shift data
-					input.buf.rotate_left(1);
-					input.input_size -= 1;
-					input.available_size -= 1;
-					return Some(Ok(0));
+			if !ready {
+				if !nonblock {
+					return None;
 				}
-				if let Some(eof_off) = eof_off {
-					// Making the next call EOF
-					len = eof_off;
+				if available == 0 {
+					return Some(Err(errno!(EAGAIN)));
 				}
-			} else {
-				// Update available length
-				len = min(buf.len(), input.available_size);
 			}
-			// Copy data
-			let res = buf.copy_to_user(0, &input.buf[..len]);
-			if let Err(e) = res {
+			let len = min(buf.len(), available);
+			if let Err(e) = buf.copy_to_user(0, &input.buf[..len]) {
 				return Some(Err(e));
 			}
-			// Shift data
-			input.buf.rotate_left(len);
-			input.input_size -= len;
-			input.available_size -= len;
-			// Ring the bell if the buffer is full
-			if termios.c_iflag & IMAXBEL != 0 && input.input_size >= buf.len() {
-				ring_bell();
-			}
+			input.consume(len);
 			Some(Ok(len))
 		})?
 	}
@@ -825,138 +1111,217 @@ impl TTY {
 	/// Tells whether the TTY has any data available to be read.
 	pub fn has_input_available(&self) -> bool {
 		let termios = self.get_termios();
-		// Canonical mode
-		let canon = termios.c_lflag & ICANON != 0;
-		let min = if canon {
-			1
+		// In non-canonical mode without timeout, wait for `VMIN` characters
+		let min = if termios.c_lflag & ICANON == 0 && termios.c_cc[VTIME] == 0 {
+			(termios.c_cc[VMIN] as usize).max(1)
 		} else {
-			termios.c_cc[VMIN] as usize
+			1
 		};
-		self.input.lock().available_size >= min
+		self.available_len() >= min
 	}
 
-	// TODO Implement IUTF8
-	/// Takes the given string `buffer` as input, making it available from the
-	/// terminal input.
-	pub fn input(&self, buffer: &[u8]) {
-		let termios = self.get_termios().clone();
+	/// Returns the number of bytes available to be read from the TTY.
+	pub fn available_len(&self) -> usize {
+		self.input.lock().available_size
+	}
 
-		// Echo without holding the input lock to avoid display -> input vs input -> display lock
-		// inversion against `write` (which holds `display` and may call `inject_input`)
-		if termios.c_lflag & ECHO != 0 {
-			self.write(buffer);
-		}
-		// TODO If ECHO is disabled but ICANON and ECHONL are set, print newlines
+	/// Discards all the data in the input buffer.
+	pub fn flush_input(&self) {
+		self.input.lock().flush();
+	}
 
-		// TODO Implement IGNBRK and BRKINT
-		// TODO Implement parity checking
-		// TODO IXON / IXANY / IXOFF
+	/// Echoes the input character `c` on the TTY.
+	fn echo_char(&self, termios: &Termios, c: u8) {
+		if termios.c_lflag & ECHOCTL != 0 && is_echoctl(c) {
+			self.write(&[b'^', c ^ 0x40]);
+		} else {
+			self.write(&[c]);
+		}
+	}
 
-		// Mutate the input buffer under the input lock only.
-		let mut erase_count = 0usize;
-		let stored_len;
+	/// Performs the erase operation `kind`, triggered by the character `c`, on the line being
+	/// edited.
+	fn erase(&self, termios: &Termios, kind: Erase, c: u8) {
+		// The number of columns to erase on display
+		let mut cols = 0;
 		{
 			let mut input = self.input.lock();
-			let len = min(buffer.len(), input.buf.len() - input.input_size);
-			stored_len = len;
-			let buffer = &buffer[..len];
-			let input_size = input.input_size;
-			utils::slice_copy(buffer, &mut input.buf[input_size..]);
-			let new_bytes = &mut input.buf[input_size..(input_size + len)];
-			for b in new_bytes {
-				if termios.c_iflag & ISTRIP != 0 {
-					*b &= 1 << 7;
-				}
-				// TODO Implement IGNCR (ignore carriage return)
-				if termios.c_iflag & INLCR != 0 && *b == b'\n' {
-					*b = b'\r';
+			let mut in_word = false;
+			while let Some(last) = input.last() {
+				// Skip trailing spaces, then erase up to the beginning of the word
+				if kind == Erase::Word {
+					let space = last.is_ascii_whitespace();
+					if space && in_word {
+						break;
+					}
+					in_word |= !space;
 				}
-				if termios.c_iflag & ICRNL != 0 && *b == b'\r' {
-					*b = b'\n';
+				input.input_size -= 1;
+				// TODO Handle tab characters
+				cols += if termios.c_lflag & ECHOCTL != 0 && is_echoctl(last) {
+					2
+				} else {
+					1
+				};
+				if kind == Erase::Char {
+					break;
 				}
-				if termios.c_iflag & IUCLC != 0 && (*b as char).is_ascii_uppercase() {
-					*b = (*b as char).to_ascii_uppercase() as u8;
+			}
+		}
+		if termios.c_lflag & ECHO == 0 {
+			return;
+		}
+		match kind {
+			Erase::Line if termios.c_lflag & ECHOKE == 0 => {
+				self.echo_char(termios, c);
+				if termios.c_lflag & ECHOK != 0 {
+					self.write(b"\n");
 				}
 			}
-			input.input_size += len;
-			if termios.c_lflag & ICANON != 0 {
-				let mut i = input.input_size - len;
-				while i < input.input_size {
-					let b = input.buf[i];
-					if b == termios.c_cc[VEOF] || b == b'\n' {
-						input.available_size = i + 1;
-						i += 1;
-					} else if b == 0xf7 {
-						// Drop the 0xf7 byte from the input buffer
-						let end = input.input_size;
-						input.buf.copy_within((i + 1)..end, i);
-						input.input_size -= 1;
-						erase_count += 1;
-					} else {
-						i += 1;
-					}
+			Erase::Char | Erase::Word if termios.c_lflag & ECHOE == 0 => {
+				self.echo_char(termios, c);
+			}
+			_ => {
+				for _ in 0..cols {
+					self.write(b"\x08 \x08");
 				}
-			} else {
-				input.available_size = input.input_size;
 			}
 		}
+	}
 
-		// Now-lock-free post-processing: visual erase, ECHOCTL printing, signal delivery.
-		for _ in 0..erase_count {
-			self.erase();
+	/// Prints the line being edited again, after the character `c`.
+	fn reprint(&self, termios: &Termios, c: u8) {
+		if termios.c_lflag & ECHO == 0 {
+			return;
 		}
-		if termios.c_lflag & ISIG != 0 {
-			let pgrp = self.get_pgrp();
-			for b in &buffer[..stored_len] {
-				if termios.c_lflag & (ECHO | ECHOCTL) == ECHO | ECHOCTL && *b >= 1 && *b < 32 {
-					self.write(b"^A");
-				}
-				// TODO Handle every special characters
-				if *b == termios.c_cc[VINTR] {
-					send_signal(Signal::SIGINT, pgrp);
-				} else if *b == termios.c_cc[VQUIT] {
-					send_signal(Signal::SIGQUIT, pgrp);
-				} else if *b == termios.c_cc[VSUSP] {
-					send_signal(Signal::SIGTSTP, pgrp);
-				}
+		self.echo_char(termios, c);
+		self.write(b"\n");
+		// Copy by chunks to avoid holding the input lock while writing
+		let mut off = 0;
+		loop {
+			let mut chunk = [0u8; 64];
+			let len = {
+				let input = self.input.lock();
+				let line = &input.buf[input.available_size..input.input_size];
+				let rest = line.get(off..).unwrap_or_default();
+				let len = min(rest.len(), chunk.len());
+				chunk[..len].copy_from_slice(&rest[..len]);
+				len
+			};
+			if len == 0 {
+				break;
 			}
+			for c in &chunk[..len] {
+				self.echo_char(termios, *c);
+			}
+			off += len;
 		}
-
-		self.rd_queue.wake_next();
 	}
 
-	/// Erases `count` characters in TTY.
-	pub fn erase(&self) {
-		let termios = self.get_termios();
-		// Release the input lock before taking the display lock so we don't form a cycle
-		// against `write` -> `inject_input` (display -> input)
-		let visual_erase;
-		{
-			let mut input = self.input.lock();
-			if termios.c_lflag & ICANON != 0 {
-				if input.input_size == 0 {
+	/// Processes the input character `c` according to the terminal settings `termios`.
+	fn input_char(&self, termios: &Termios, mut c: u8) {
+		let iflag = termios.c_iflag;
+		let lflag = termios.c_lflag;
+		let canon = lflag & ICANON != 0;
+		let iexten = lflag & IEXTEN != 0;
+		let literal = mem::take(&mut self.input.lock().lnext);
+		if !literal {
+			// TODO Implement IGNBRK and BRKINT
+			// TODO Implement parity checking
+			// TODO IXON / IXANY / IXOFF
+			if iflag & ISTRIP != 0 {
+				c &= 0x7f;
+			}
+			match c {
+				b'\r' if iflag & IGNCR != 0 => return,
+				b'\r' if iflag & ICRNL != 0 => c = b'\n',
+				b'\n' if iflag & INLCR != 0 => c = b'\r',
+				_ => {}
+			}
+			if iflag & IUCLC != 0 && iexten {
+				c = c.to_ascii_lowercase();
+			}
+			if lflag & ISIG != 0 {
+				let sig = if is_cc(termios, VINTR, c) {
+					Some(Signal::SIGINT)
+				} else if is_cc(termios, VQUIT, c) {
+					Some(Signal::SIGQUIT)
+				} else if is_cc(termios, VSUSP, c) {
+					Some(Signal::SIGTSTP)
+				} else {
+					None
+				};
+				if let Some(sig) = sig {
+					if lflag & NOFLSH == 0 {
+						self.flush_input();
+					}
+					if lflag & ECHO != 0 {
+						self.echo_char(termios, c);
+					}
+					send_signal(sig, self.get_pgrp());
+					return;
+				}
+			}
+			if canon {
+				let erase = if is_cc(termios, VERASE, c) {
+					Some(Erase::Char)
+				} else if is_cc(termios, VKILL, c) {
+					Some(Erase::Line)
+				} else if iexten && is_cc(termios, VWERASE, c) {
+					Some(Erase::Word)
+				} else {
+					None
+				};
+				if let Some(kind) = erase {
+					self.erase(termios, kind, c);
+					return;
+				}
+				if iexten && is_cc(termios, VLNEXT, c) {
+					self.input.lock().lnext = true;
+					if lflag & (ECHO | ECHOCTL) == ECHO | ECHOCTL {
+						self.write(b"^\x08");
+					}
+					return;
+				}
+				if iexten && is_cc(termios, VREPRINT, c) {
+					self.reprint(termios, c);
+					return;
+				}
+				let eof = is_cc(termios, VEOF, c);
+				if eof
+					|| c == b'\n' || is_cc(termios, VEOL, c)
+					|| (iexten && is_cc(termios, VEOL2, c))
+				{
+					self.input.lock().push(c, true, true);
+					// The end-of-file character is not echoed
+					if !eof && (lflag & ECHO != 0 || (c == b'\n' && lflag & ECHONL != 0)) {
+						self.echo_char(termios, c);
+					}
 					return;
 				}
-				input.input_size -= 1;
-				visual_erase = termios.c_lflag & ECHOE != 0;
-			} else {
-				drop(input);
-				self.input(&[0x7f]);
-				return;
 			}
 		}
-		if visual_erase {
-			let mut disp = self.display.lock();
-			let old_cursor_x = disp.cursor_x;
-			let old_cursor_y = disp.cursor_y;
-			// TODO Handle tab characters
-			disp.cursor_backward(1);
-			let cursor_x = disp.cursor_x;
-			let cursor_y = disp.cursor_y;
-			let off = disp.history_off(cursor_x, cursor_y);
-			disp.history[off] = Char::empty();
-			disp.display_char(Char::empty(), cursor_x, cursor_y);
-			disp.update_cursor(Some((old_cursor_x, old_cursor_y)));
+		// Regular character
+		if !self.input.lock().push(c, false, canon) {
+			if iflag & IMAXBEL != 0 {
+				ring_bell();
+			}
+			return;
+		}
+		if lflag & ECHO != 0 {
+			self.echo_char(termios, c);
+		}
+	}
+
+	/// Takes the given string `buffer` as input, making it available from the
+	/// terminal input.
+	pub fn input(&self, buffer: &[u8]) {
+		let termios = self.get_termios();
+		// The input lock is never held while echoing to avoid display -> input vs input ->
+		// display lock inversion against `write` (which holds `display` and may call
+		// `inject_input`)
+		for c in buffer {
+			self.input_char(&termios, *c);
 		}
 		self.rd_queue.wake_next();
 	}
@@ -973,6 +1338,31 @@ impl TTY {
 		self.settings.lock().pgrp = pgrp;
 	}
 
+	/// Returns the number of times the TTY has been hung up.
+	///
+	/// Open file descriptions referring to the TTY become unusable when the TTY is hung up, so
+	/// they record this value when opened.
+	#[inline]
+	pub fn hangup_count(&self) -> u32 {
+		self.settings.lock().hangups
+	}
+
+	/// Hangs up the TTY.
+	///
+	/// Pending input is discarded, blocked readers are woken up, and the foreground process group
+	/// receives `SIGHUP` then `SIGCONT`.
+	pub fn hangup(&self) {
+		let pgrp = {
+			let mut settings = self.settings.lock();
+			settings.hangups = settings.hangups.wrapping_add(1);
+			mem::take(&mut settings.pgrp)
+		};
+		self.flush_input();
+		self.rd_queue.wake_all();
+		send_signal(Signal::SIGHUP, pgrp);
+		send_signal(Signal::SIGCONT, pgrp);
+	}
+
 	/// Returns the terminal IO settings.
 	pub fn get_termios(&self) -> Termios {
 		self.settings.lock().termios.clone()
@@ -980,7 +1370,23 @@ impl TTY {
 
 	/// Sets the terminal IO settings.
 	pub fn set_termios(&self, termios: Termios) {
+		{
+			let mut input = self.input.lock();
+			if termios.c_lflag & ICANON != 0 {
+				// Only complete lines can be read
+				input.available_size = input.delim[..input.input_size]
+					.iter()
+					.rposition(|d| *d)
+					.map(|i| i + 1)
+					.unwrap_or(0);
+			} else {
+				input.available_size = input.input_size;
+				input.lnext = false;
+			}
+		}
 		self.settings.lock().termios = termios;
+		// Data may have become available
+		self.rd_queue.wake_all();
 	}
 
 	/// Returns the window size of the TTY.
@@ -1019,7 +1425,7 @@ impl TTY {
 	}
 }
 
-/// Shows the initialization TTY on screen
+/// Initializes the virtual terminals and shows the active one on screen
 pub(crate) fn show(boot_info: &BootInfo) -> AllocResult<Option<Arc<Framebuffer>>> {
 	let mut warn = false;
 	let fb = if let Some(fb_info) = boot_info.fb_info.clone() {
@@ -1038,7 +1444,9 @@ pub(crate) fn show(boot_info: &BootInfo) -> AllocResult<Option<Arc<Framebuffer>>
 			fb::MAP_FLAGS,
 		);
 	}
-	TTY.show(fb.clone())?;
+	for tty in &VTS {
+		tty.show(fb.clone())?;
+	}
 	if warn {
 		// TODO panic?
 		println!("Warning: could not remap framebuffer, using text mode!");
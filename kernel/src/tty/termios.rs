@@ -212,10 +212,10 @@ impl Termios {
 	pub const fn new() -> Self {
 		use consts::*;
 		let mut t = Self {
-			c_iflag: ICRNL | IUCLC | IXANY | IMAXBEL,
+			c_iflag: ICRNL | IXANY | IMAXBEL,
 			c_oflag: OPOST | ONLCR,
-			c_cflag: CS8,
-			c_lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK,
+			c_cflag: CS8 | CREAD,
+			c_lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE | IEXTEN,
 			c_line: 0,
 			c_cc: [0; NCCS],
 			__c_ispeed: 0,
@@ -25,6 +25,21 @@
 .global __vdso_gettimeofday
 .global __vdso_time
 
+.hidden vvar_page
+
+# Offsets in the vvar page
+.set VVAR_SEQ, 0
+.set VVAR_MODE, 4
+.set VVAR_BASE, 8
+.set VVAR_MULT, 16
+.set VVAR_REALTIME, 24
+.set VVAR_MONOTONIC, 32
+.set VVAR_BOOTTIME, 40
+
+# Modes of the vvar page
+.set MODE_TSC, 1
+.set MODE_SYSCALL, 2
+
 __kernel_vsyscall:
 	int $0x80
 	ret
@@ -37,14 +52,164 @@ __kernel_sigreturn:
 	# TODO
 	ud2
 
+# Reads the clock whose offset in the vvar page is in ecx.
+#
+# On success, the carry flag is cleared and the value of the clock in nanoseconds is returned in
+# edx:eax. If the system call has to be used instead, the carry flag is set.
+#
+# Only eax, ecx and edx are clobbered.
+read_clock:
+	push %ebx
+	push %esi
+	push %edi
+	push %ebp
+	call 1f
+1:
+	pop %ebx
+	lea (vvar_page - 1b)(%ebx), %ebx
+2:
+	mov VVAR_SEQ(%ebx), %ebp
+	test $1, %ebp
+	jnz 4f
+	mov VVAR_MODE(%ebx), %eax
+	cmp $MODE_SYSCALL, %eax
+	je 5f
+	mov (%ebx, %ecx), %esi
+	mov 4(%ebx, %ecx), %edi
+	cmp $MODE_TSC, %eax
+	jne 3f
+	# Add the time elapsed since the base value of the counter: ((tsc - base) * mult) >> 32
+	lfence
+	rdtsc
+	sub VVAR_BASE(%ebx), %eax
+	sbb (VVAR_BASE + 4)(%ebx), %edx
+	push %edx
+	push %eax
+	mull VVAR_MULT(%ebx)
+	add %edx, %esi
+	adc $0, %edi
+	mov (%esp), %eax
+	mull (VVAR_MULT + 4)(%ebx)
+	add %eax, %esi
+	adc %edx, %edi
+	mov 4(%esp), %eax
+	mull VVAR_MULT(%ebx)
+	add %eax, %esi
+	adc %edx, %edi
+	mov 4(%esp), %eax
+	imul (VVAR_MULT + 4)(%ebx), %eax
+	add %eax, %edi
+	add $8, %esp
+3:
+	# Retry if the page has been updated in between
+	cmp VVAR_SEQ(%ebx), %ebp
+	jne 2b
+	mov %esi, %eax
+	mov %edi, %edx
+	clc
+	jmp 6f
+4:
+	pause
+	jmp 2b
+5:
+	stc
+6:
+	pop %ebp
+	pop %edi
+	pop %esi
+	pop %ebx
+	ret
+
+# The offset of each clock in the vvar page, indexed by ID. Zero if not supported
+clock_offsets:
+	.byte VVAR_REALTIME # CLOCK_REALTIME
+	.byte VVAR_MONOTONIC # CLOCK_MONOTONIC
+	.byte 0 # CLOCK_PROCESS_CPUTIME_ID
+	.byte 0 # CLOCK_THREAD_CPUTIME_ID
+	.byte VVAR_MONOTONIC # CLOCK_MONOTONIC_RAW
+	.byte VVAR_REALTIME # CLOCK_REALTIME_COARSE
+	.byte VVAR_MONOTONIC # CLOCK_MONOTONIC_COARSE
+	.byte VVAR_BOOTTIME # CLOCK_BOOTTIME
+.set CLOCKS_COUNT, . - clock_offsets
+
+# Arguments (stack): clockid, tp
 __vdso_clock_gettime:
-	# TODO
-	ud2
+	mov 4(%esp), %eax
+	cmp $CLOCKS_COUNT, %eax
+	jae 2f
+	call 1f
+1:
+	pop %ecx
+	movzbl (clock_offsets - 1b)(%ecx, %eax), %ecx
+	test %ecx, %ecx
+	jz 2f
+	call read_clock
+	jc 2f
+	mov $1000000000, %ecx
+	div %ecx
+	mov 8(%esp), %ecx
+	mov %eax, (%ecx)
+	mov %edx, 4(%ecx)
+	xor %eax, %eax
+	ret
+2:
+	push %ebx
+	mov $0x109, %eax
+	mov 8(%esp), %ebx
+	mov 12(%esp), %ecx
+	int $0x80
+	pop %ebx
+	ret
 
+# Arguments (stack): tv, tz
 __vdso_gettimeofday:
-	# TODO
-	ud2
+	cmpl $0, 8(%esp)
+	jne 2f
+	cmpl $0, 4(%esp)
+	je 1f
+	mov $VVAR_REALTIME, %ecx
+	call read_clock
+	jc 2f
+	mov $1000000000, %ecx
+	div %ecx
+	mov 4(%esp), %ecx
+	mov %eax, (%ecx)
+	mov %edx, %eax
+	xor %edx, %edx
+	push %ebx
+	mov $1000, %ebx
+	div %ebx
+	pop %ebx
+	mov %eax, 4(%ecx)
+1:
+	xor %eax, %eax
+	ret
+2:
+	push %ebx
+	mov $0x4e, %eax
+	mov 8(%esp), %ebx
+	mov 12(%esp), %ecx
+	int $0x80
+	pop %ebx
+	ret
 
+# Arguments (stack): tloc
 __vdso_time:
-	# TODO
-	ud2
+	mov $VVAR_REALTIME, %ecx
+	call read_clock
+	jc 2f
+	mov $1000000000, %ecx
+	div %ecx
+	mov 4(%esp), %ecx
+	test %ecx, %ecx
+	jz 1f
+	mov %eax, (%ecx)
+1:
+	ret
+2:
+	push %ebx
+	mov $0x0d, %eax
+	mov 8(%esp), %ebx
+	int $0x80
+	pop %ebx
+	ret
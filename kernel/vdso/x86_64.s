@@ -23,18 +23,140 @@
 .global __vdso_gettimeofday
 .global __vdso_time
 
+.hidden vvar_page
+
+# Offsets in the vvar page
+.set VVAR_SEQ, 0
+.set VVAR_MODE, 4
+.set VVAR_BASE, 8
+.set VVAR_MULT, 16
+.set VVAR_REALTIME, 24
+.set VVAR_MONOTONIC, 32
+.set VVAR_BOOTTIME, 40
+
+# Modes of the vvar page
+.set MODE_TSC, 1
+.set MODE_SYSCALL, 2
+
+# Reads the clock whose offset in the vvar page is in rcx.
+#
+# On success, the carry flag is cleared and the value of the clock in nanoseconds is returned in
+# rax. If the system call has to be used instead, the carry flag is set.
+#
+# Only rax, rcx, rdx, r8, r9, r10 and r11 are clobbered.
+read_clock:
+	lea vvar_page(%rip), %r8
+1:
+	mov VVAR_SEQ(%r8), %r9d
+	test $1, %r9d
+	jnz 3f
+	mov VVAR_MODE(%r8), %r10d
+	cmp $MODE_SYSCALL, %r10d
+	je 4f
+	mov (%r8, %rcx), %r11
+	cmp $MODE_TSC, %r10d
+	jne 2f
+	# Add the time elapsed since the base value of the counter
+	lfence
+	rdtsc
+	shl $32, %rdx
+	or %rdx, %rax
+	sub VVAR_BASE(%r8), %rax
+	mulq VVAR_MULT(%r8)
+	shrd $32, %rdx, %rax
+	add %rax, %r11
+2:
+	# Retry if the page has been updated in between
+	cmp VVAR_SEQ(%r8), %r9d
+	jne 1b
+	mov %r11, %rax
+	clc
+	ret
+3:
+	pause
+	jmp 1b
+4:
+	stc
+	ret
+
+# The offset of each clock in the vvar page, indexed by ID. Zero if not supported
+clock_offsets:
+	.byte VVAR_REALTIME # CLOCK_REALTIME
+	.byte VVAR_MONOTONIC # CLOCK_MONOTONIC
+	.byte 0 # CLOCK_PROCESS_CPUTIME_ID
+	.byte 0 # CLOCK_THREAD_CPUTIME_ID
+	.byte VVAR_MONOTONIC # CLOCK_MONOTONIC_RAW
+	.byte VVAR_REALTIME # CLOCK_REALTIME_COARSE
+	.byte VVAR_MONOTONIC # CLOCK_MONOTONIC_COARSE
+	.byte VVAR_BOOTTIME # CLOCK_BOOTTIME
+.set CLOCKS_COUNT, . - clock_offsets
+
+# Arguments: clockid (rdi), tp (rsi)
 __vdso_clock_gettime:
-	# TODO
-	ud2
+	cmp $CLOCKS_COUNT, %edi
+	jae 1f
+	lea clock_offsets(%rip), %rax
+	mov %edi, %edx
+	movzbl (%rax, %rdx), %ecx
+	test %ecx, %ecx
+	jz 1f
+	call read_clock
+	jc 1f
+	xor %edx, %edx
+	mov $1000000000, %ecx
+	div %rcx
+	mov %rax, (%rsi)
+	mov %rdx, 8(%rsi)
+	xor %eax, %eax
+	ret
+1:
+	mov $0xe4, %eax
+	syscall
+	ret
 
 __vdso_getcpu:
     # TODO
     ud2
 
+# Arguments: tv (rdi), tz (rsi)
 __vdso_gettimeofday:
-	# TODO
-	ud2
+	test %rsi, %rsi
+	jnz 2f
+	test %rdi, %rdi
+	jz 1f
+	mov $VVAR_REALTIME, %ecx
+	call read_clock
+	jc 2f
+	xor %edx, %edx
+	mov $1000, %ecx
+	div %rcx
+	xor %edx, %edx
+	mov $1000000, %ecx
+	div %rcx
+	mov %rax, (%rdi)
+	mov %rdx, 8(%rdi)
+1:
+	xor %eax, %eax
+	ret
+2:
+	mov $0x60, %eax
+	syscall
+	ret
 
+# Arguments: tloc (rdi)
 __vdso_time:
-	# TODO
-	ud2
+	mov $VVAR_REALTIME, %ecx
+	call read_clock
+	jc 2f
+	xor %edx, %edx
+	mov $1000000000, %ecx
+	div %rcx
+	test %rdi, %rdi
+	jz 1f
+	mov %rax, (%rdi)
+1:
+	ret
+2:
+	mov $0xc9, %eax
+	syscall
+	ret
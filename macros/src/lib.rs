@@ -23,6 +23,7 @@
 extern crate proc_macro;
 
 mod aml;
+mod module_params;
 mod util;
 
 use crate::util::has_repr_c;
@@ -52,3 +53,27 @@ pub fn any_repr(input: TokenStream) -> TokenStream {
 pub fn aml_parseable(input: TokenStream) -> TokenStream {
 	aml::derive_parseable(input)
 }
+
+/// Declares the parameters of a kernel module.
+///
+/// Each parameter is declared as a `static`, whose type is the type of the parameter's value and
+/// whose expression is its default value. The name of the parameter is the lowercase name of the
+/// `static` and its documentation is used as the parameter's description.
+///
+/// The value of a parameter is retrieved with `get`.
+///
+/// This macro must be used at most once, only inside a kernel module.
+///
+/// Example:
+/// ```text
+/// kernel::module_params! {
+///     /// Enables verbose output.
+///     static VERBOSE: bool = false;
+///     /// The number of receive descriptors.
+///     static RX_RING: u32 = 256;
+/// }
+/// ```
+#[proc_macro]
+pub fn module_params(input: TokenStream) -> TokenStream {
+	module_params::module_params(input)
+}
@@ -0,0 +1,110 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Implementation of the `module_params` macro.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{
+	Error, Expr, ExprLit, ItemStatic, Lit, LitStr, Meta, MetaNameValue, StaticMutability,
+	parse::{Parse, ParseStream},
+	parse_macro_input,
+};
+
+/// The list of parameters declared with the macro.
+struct Params(Vec<ItemStatic>);
+
+impl Parse for Params {
+	fn parse(input: ParseStream) -> syn::Result<Self> {
+		let mut params = Vec::new();
+		while !input.is_empty() {
+			params.push(input.parse()?);
+		}
+		Ok(Self(params))
+	}
+}
+
+/// Returns the content of the documentation comments in `param`, as a single line.
+fn get_doc(param: &ItemStatic) -> String {
+	param
+		.attrs
+		.iter()
+		.filter_map(|attr| {
+			let Meta::NameValue(MetaNameValue {
+				path,
+				value: Expr::Lit(ExprLit {
+					lit: Lit::Str(s), ..
+				}),
+				..
+			}) = &attr.meta
+			else {
+				return None;
+			};
+			path.is_ident("doc").then(|| s.value())
+		})
+		.map(|line| line.trim().to_owned())
+		.filter(|line| !line.is_empty())
+		.intersperse(" ".to_owned())
+		.collect()
+}
+
+pub fn module_params(input: TokenStream) -> TokenStream {
+	let Params(params) = parse_macro_input!(input as Params);
+	let mut statics = Vec::with_capacity(params.len());
+	let mut descs = Vec::with_capacity(params.len());
+	for param in params {
+		if let StaticMutability::Mut(token) = param.mutability {
+			return Error::new(token.span, "module parameters cannot be mutable")
+				.to_compile_error()
+				.into();
+		}
+		let ItemStatic {
+			attrs,
+			vis,
+			ident,
+			ty,
+			expr,
+			..
+		} = &param;
+		let name = LitStr::new(&ident.to_string().to_lowercase(), Span::call_site());
+		let desc = LitStr::new(&get_doc(&param), Span::call_site());
+		statics.push(quote! {
+			#(#attrs)*
+			#vis static #ident: ::kernel::module::param::Param<#ty> =
+				::kernel::module::param::Param::new(#expr);
+		});
+		descs.push(quote! {
+			::kernel::module::param::ParamDesc {
+				name: #name,
+				desc: #desc,
+				value: &super::#ident,
+			}
+		});
+	}
+	let count = descs.len();
+	let toks = quote! {
+		#(#statics)*
+
+		mod module_params {
+			#[unsafe(no_mangle)]
+			pub static MOD_PARAMS: [::kernel::module::param::ParamDesc; #count] = [#(#descs),*];
+		}
+	};
+	TokenStream::from(toks)
+}
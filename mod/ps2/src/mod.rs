@@ -25,9 +25,10 @@
 #[no_link]
 extern crate kernel;
 
+mod mouse;
 mod scancode;
 
-use crate::scancode::ScancodeSet;
+use crate::{mouse::PS2_MOUSE, scancode::ScancodeSet};
 use core::any::Any;
 use kernel::{
 	arch::x86::{
@@ -37,6 +38,8 @@ use kernel::{
 		io,
 	},
 	device::{
+		input,
+		input::{BUS_I8042, EV_KEY, InputDevice, InputId},
 		keyboard::{Keyboard, KeyboardAction, KeyboardKey, KeyboardLED, KeyboardManager},
 		manager,
 	},
@@ -44,12 +47,15 @@ use kernel::{
 	int::CallbackHandle,
 	println,
 	sync::spin::Spin,
+	utils::ptr::arc::Arc,
 };
 
 kernel::module!([]);
 
 /// The interrupt number for keyboard input events.
 const KBD_INT: u8 = 0x21;
+/// The interrupt number for mouse input events.
+const MOUSE_INT: u8 = 0x2c;
 
 /// Register: Data
 const DATA_REGISTER: u16 = 0x60;
@@ -60,6 +66,8 @@ const COMMAND_REGISTER: u16 = 0x64;
 
 /// The maximum number of attempts for sending a command to the PS/2 controller.
 const MAX_ATTEMPTS: usize = 3;
+/// The number of polls of the status register before giving up waiting for a response.
+const READ_TIMEOUT: usize = 100000;
 
 /// Command: Read configuration byte.
 const CTRL_CMD_READ_CONFIG: u8 = 0x20;
@@ -102,6 +110,11 @@ fn can_write() -> bool {
 	unsafe { io::inb(STATUS_REGISTER) & 0b10 == 0 }
 }
 
+/// Tells whether the data available for reading comes from the second port.
+fn is_aux_data() -> bool {
+	unsafe { io::inb(STATUS_REGISTER) & 0b100000 != 0 }
+}
+
 /// Waits until the registers are ready for reading.
 fn wait_read() {
 	while !can_read() {}
@@ -118,6 +131,18 @@ fn read_data() -> u8 {
 	unsafe { io::inb(DATA_REGISTER) }
 }
 
+/// Waits for the data register to be ready, then reads from it.
+///
+/// If no data is received in time, the function returns `None`.
+fn read_data_timeout() -> Option<u8> {
+	for _ in 0..READ_TIMEOUT {
+		if can_read() {
+			return Some(unsafe { io::inb(DATA_REGISTER) });
+		}
+	}
+	None
+}
+
 /// Clears the PS/2 controller's buffer.
 fn clear_buffer() {
 	while can_read() {
@@ -230,9 +255,23 @@ fn test_device() -> Result<(), ()> {
 /// Handles the given keyboard input.
 ///
 /// Arguments:
+/// - `input_dev` is the keyboard's input device.
 /// - `key` is the key that has been typed.
 /// - `action` is the action.
-fn handle_input(key: KeyboardKey, action: KeyboardAction) {
+fn handle_input(input_dev: &InputDevice, key: KeyboardKey, action: KeyboardAction) {
+	let code = key.code();
+	let value = match action {
+		// Typematic repetition
+		KeyboardAction::Pressed if input_dev.is_pressed(code) => 2,
+		KeyboardAction::Pressed => 1,
+		KeyboardAction::Released => 0,
+	};
+	input_dev.report(EV_KEY, code, value);
+	input_dev.sync();
+	// A grabbing process receives input exclusively
+	if input_dev.is_grabbed() {
+		return;
+	}
 	// TODO Do not retrieve at each keystroke
 	let Some(manager_mutex) = manager::get::<KeyboardManager>() else {
 		return;
@@ -244,9 +283,32 @@ fn handle_input(key: KeyboardKey, action: KeyboardAction) {
 	kbd_manager.input(key, action);
 }
 
+/// Handles the data available on the controller, for both the keyboard and the mouse.
+///
+/// Since both devices share the same data register, the same handler is used for both
+/// interrupts.
+fn handle_data() {
+	let kbd = PS2_KEYBOAD.lock();
+	let mut mouse = PS2_MOUSE.lock();
+	while can_read() {
+		if is_aux_data() {
+			mouse.handle_byte(read_data());
+			continue;
+		}
+		let Some(input_dev) = &kbd.input_dev else {
+			read_data();
+			continue;
+		};
+		if let Some((key, action)) = kbd.scancode_set.read_keystroke() {
+			handle_input(input_dev, key, action);
+		}
+	}
+}
+
 /// Global variable containing the module's instance.
 static PS2_KEYBOAD: Spin<PS2Keyboard> = Spin::new(PS2Keyboard {
 	keyboard_interrupt_callback_hook: None,
+	input_dev: None,
 
 	scancode_set: ScancodeSet::Set2,
 	leds_state: 0,
@@ -256,6 +318,8 @@ static PS2_KEYBOAD: Spin<PS2Keyboard> = Spin::new(PS2Keyboard {
 pub struct PS2Keyboard {
 	/// The callback hook for keyboard input interrupts.
 	keyboard_interrupt_callback_hook: Option<CallbackHandle>,
+	/// The keyboard's input device.
+	input_dev: Option<Arc<InputDevice>>,
 
 	/// The current scancode set being used by the keyboard.
 	scancode_set: ScancodeSet,
@@ -287,7 +351,7 @@ fn init_in() -> Result<(), ()> {
 
 	let mut kbd = PS2_KEYBOAD.lock();
 
-	disable_int(|| {
+	let mouse_wheel = disable_int(|| {
 		disable_devices();
 		clear_buffer();
 
@@ -300,30 +364,59 @@ fn init_in() -> Result<(), ()> {
 		test_device()?;
 		println!("Enable PS/2 keyboard...");
 		enable_keyboard(&mut kbd)?;
+		// The mouse is optional
+		let mouse_wheel = mouse::init_device().ok();
+		if mouse_wheel.is_none() {
+			println!("No PS/2 mouse");
+		}
 
 		// Enable first port and disable keycodes translation
-		set_config_byte((get_config_byte() | 0b1) & !(1 << 6));
+		let mut config = (get_config_byte() | 0b1) & !(1 << 6);
+		// Enable second port's interrupts and clock
+		if mouse_wheel.is_some() {
+			config = (config | 0b10) & !(1 << 5);
+		}
+		set_config_byte(config);
 
 		clear_buffer();
-		Ok(())
+		Ok(mouse_wheel)
 	})?;
 
-	let callback = |_id: u32, _code: u32, _regs: &mut IntFrame, _ring: u8| {
-		let kbd = PS2_KEYBOAD.lock();
-		while can_read() {
-			if let Some((key, action)) = kbd.scancode_set.read_keystroke() {
-				handle_input(key, action);
-			}
-		}
-	};
+	let mut input_dev = InputDevice::new(
+		b"AT Translated Set 2 keyboard",
+		InputId {
+			bustype: BUS_I8042,
+			vendor: 1,
+			product: 1,
+			version: 0xab41,
+		},
+	);
+	for key in kbd.scancode_set.keys() {
+		input_dev.set_capability(EV_KEY, key.code());
+	}
+	kbd.input_dev = Some(input::register(input_dev).map_err(|_| ())?);
 
+	let callback = |_id: u32, _code: u32, _regs: &mut IntFrame, _ring: u8| handle_data();
 	if apic::is_present() {
-		apic::redirect_int(0x1, lapic_id(), KBD_INT);
+		apic::redirect_irq(0x1, lapic_id(), KBD_INT);
 	}
 	unsafe {
 		let hook_result = int::register_callback(KBD_INT as _, callback);
 		kbd.keyboard_interrupt_callback_hook = hook_result.unwrap();
 	}
+	drop(kbd);
+
+	if let Some(wheel) = mouse_wheel {
+		let mut mouse = PS2_MOUSE.lock();
+		mouse::register(&mut mouse, wheel)?;
+		if apic::is_present() {
+			apic::redirect_irq(0xc, lapic_id(), MOUSE_INT);
+		}
+		unsafe {
+			let hook_result = int::register_callback(MOUSE_INT as _, callback);
+			mouse.interrupt_callback_hook = hook_result.unwrap();
+		}
+	}
 	Ok(())
 }
 
@@ -331,7 +424,7 @@ fn init_in() -> Result<(), ()> {
 pub extern "C" fn init() -> bool {
 	match init_in() {
 		Ok(_) => {
-			println!("PS/2 keyboard ready");
+			println!("PS/2 devices ready");
 			true
 		}
 		Err(_) => {
@@ -343,6 +436,17 @@ pub extern "C" fn init() -> bool {
 
 #[unsafe(no_mangle)]
 pub extern "C" fn fini() {
+	let mut mouse = PS2_MOUSE.lock();
+	mouse.interrupt_callback_hook = None;
+	if let Some(input_dev) = mouse.input_dev.take() {
+		input::unregister(&input_dev);
+	}
+	drop(mouse);
+	let mut kbd = PS2_KEYBOAD.lock();
 	// Destroy interrupt handler
-	PS2_KEYBOAD.lock().keyboard_interrupt_callback_hook = None;
+	kbd.keyboard_interrupt_callback_hook = None;
+	// Remove the input device
+	if let Some(input_dev) = kbd.input_dev.take() {
+		input::unregister(&input_dev);
+	}
 }
@@ -0,0 +1,220 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! PS/2 mouse support, on the controller's second port.
+//!
+//! Movements and buttons are reported as relative events through the input subsystem. The
+//! IntelliMouse extension is used when available, to report the scroll wheel.
+
+use crate::{read_data_timeout, write_cmd, write_data};
+use kernel::{
+	device::{
+		input,
+		input::{
+			BTN_LEFT, BTN_MIDDLE, BTN_RIGHT, BUS_I8042, EV_KEY, EV_REL, InputDevice, InputId,
+			REL_WHEEL, REL_X, REL_Y,
+		},
+	},
+	int::CallbackHandle,
+	sync::spin::Spin,
+	utils::ptr::arc::Arc,
+};
+
+/// Command: Enable second port.
+const CTRL_CMD_ENABLE_PORT2: u8 = 0xa8;
+/// Command: Test second port.
+const CTRL_CMD_TEST_PORT2: u8 = 0xa9;
+/// Command: Write the next byte to the second port.
+const CTRL_CMD_WRITE_PORT2: u8 = 0xd4;
+
+/// Command: Get the mouse's ID.
+const MOUSE_CMD_GET_ID: u8 = 0xf2;
+/// Command: Set the sample rate.
+const MOUSE_CMD_SET_SAMPLE_RATE: u8 = 0xf3;
+/// Command: Enable data reporting.
+const MOUSE_CMD_ENABLE: u8 = 0xf4;
+/// Command: Set default parameters.
+const MOUSE_CMD_SET_DEFAULTS: u8 = 0xf6;
+
+/// Command response: Port test passed.
+const RESP_TEST_PORT_PASS: u8 = 0x00;
+/// Command response: Mouse acknowledgement.
+const RESP_MOUSE_ACK: u8 = 0xfa;
+
+/// Mouse ID: IntelliMouse, with a scroll wheel.
+const ID_INTELLIMOUSE: u8 = 3;
+
+/// Packet flag: left button.
+const PACKET_LEFT: u8 = 1 << 0;
+/// Packet flag: right button.
+const PACKET_RIGHT: u8 = 1 << 1;
+/// Packet flag: middle button.
+const PACKET_MIDDLE: u8 = 1 << 2;
+/// Packet flag: always set, used to synchronize on the first byte of packets.
+const PACKET_SYNC: u8 = 1 << 3;
+/// Packet flag: horizontal or vertical overflow.
+const PACKET_OVERFLOW: u8 = 0b11 << 6;
+
+/// Global variable containing the mouse's state.
+pub static PS2_MOUSE: Spin<PS2Mouse> = Spin::new(PS2Mouse {
+	interrupt_callback_hook: None,
+	input_dev: None,
+
+	packet: [0; 4],
+	packet_len: 0,
+	packet_size: 3,
+});
+
+/// The PS/2 mouse structure.
+pub struct PS2Mouse {
+	/// The callback hook for mouse input interrupts.
+	pub interrupt_callback_hook: Option<CallbackHandle>,
+	/// The mouse's input device.
+	pub input_dev: Option<Arc<InputDevice>>,
+
+	/// The packet being received.
+	packet: [u8; 4],
+	/// The number of bytes of the packet received so far.
+	packet_len: usize,
+	/// The size of a packet in bytes, which depends on the protocol.
+	packet_size: usize,
+}
+
+impl PS2Mouse {
+	/// Handles a byte received from the mouse.
+	pub fn handle_byte(&mut self, b: u8) {
+		// Resynchronize if the first byte is invalid
+		if self.packet_len == 0 && b & PACKET_SYNC == 0 {
+			return;
+		}
+		self.packet[self.packet_len] = b;
+		self.packet_len += 1;
+		if self.packet_len >= self.packet_size {
+			self.packet_len = 0;
+			self.handle_packet();
+		}
+	}
+
+	/// Handles a complete packet.
+	fn handle_packet(&self) {
+		let Some(input_dev) = &self.input_dev else {
+			return;
+		};
+		let [flags, x, y, z] = self.packet;
+		input_dev.report(EV_KEY, BTN_LEFT, (flags & PACKET_LEFT != 0) as _);
+		input_dev.report(EV_KEY, BTN_RIGHT, (flags & PACKET_RIGHT != 0) as _);
+		input_dev.report(EV_KEY, BTN_MIDDLE, (flags & PACKET_MIDDLE != 0) as _);
+		// Movements are unreliable on overflow
+		if flags & PACKET_OVERFLOW == 0 {
+			// Sign bits are in the flags byte
+			let dx = x as i32 - (((flags as i32) << 4) & 0x100);
+			let dy = y as i32 - (((flags as i32) << 3) & 0x100);
+			if dx != 0 {
+				input_dev.report(EV_REL, REL_X, dx);
+			}
+			// The Y axis of the mouse points upwards, unlike the screen's
+			if dy != 0 {
+				input_dev.report(EV_REL, REL_Y, -dy);
+			}
+		}
+		if self.packet_size == 4 {
+			// Sign-extend the 4 bits of the wheel's movement
+			let dz = ((z << 4) as i8 >> 4) as i32;
+			if dz != 0 {
+				input_dev.report(EV_REL, REL_WHEEL, -dz);
+			}
+		}
+		input_dev.sync();
+	}
+}
+
+/// Sends the given command `cmd` to the mouse and waits for the acknowledgement.
+fn mouse_send(cmd: u8) -> Result<(), ()> {
+	write_cmd(CTRL_CMD_WRITE_PORT2);
+	write_data(cmd);
+	match read_data_timeout() {
+		Some(RESP_MOUSE_ACK) => Ok(()),
+		_ => Err(()),
+	}
+}
+
+/// Sets the mouse's sample rate.
+fn set_sample_rate(rate: u8) -> Result<(), ()> {
+	mouse_send(MOUSE_CMD_SET_SAMPLE_RATE)?;
+	mouse_send(rate)
+}
+
+/// Returns the mouse's ID.
+fn get_id() -> Result<u8, ()> {
+	mouse_send(MOUSE_CMD_GET_ID)?;
+	read_data_timeout().ok_or(())
+}
+
+/// Initializes the mouse, if present.
+///
+/// On success, the function returns `true` if the mouse has a scroll wheel.
+///
+/// This function must be called with interruptions disabled.
+pub fn init_device() -> Result<bool, ()> {
+	write_cmd(CTRL_CMD_TEST_PORT2);
+	if read_data_timeout() != Some(RESP_TEST_PORT_PASS) {
+		return Err(());
+	}
+	write_cmd(CTRL_CMD_ENABLE_PORT2);
+	mouse_send(MOUSE_CMD_SET_DEFAULTS)?;
+	// The magic sequence of sample rates enables the IntelliMouse extension, if supported
+	set_sample_rate(200)?;
+	set_sample_rate(100)?;
+	set_sample_rate(80)?;
+	let wheel = get_id()? == ID_INTELLIMOUSE;
+	set_sample_rate(100)?;
+	mouse_send(MOUSE_CMD_ENABLE)?;
+	Ok(wheel)
+}
+
+/// Registers the input device of the mouse.
+///
+/// `wheel` tells whether the mouse has a scroll wheel.
+pub fn register(mouse: &mut PS2Mouse, wheel: bool) -> Result<(), ()> {
+	let name: &[u8] = if wheel {
+		b"ImPS/2 Generic Wheel Mouse"
+	} else {
+		b"PS/2 Generic Mouse"
+	};
+	let mut input_dev = InputDevice::new(
+		name,
+		InputId {
+			bustype: BUS_I8042,
+			vendor: 2,
+			product: if wheel { 3 } else { 1 },
+			version: 0,
+		},
+	);
+	for btn in [BTN_LEFT, BTN_RIGHT, BTN_MIDDLE] {
+		input_dev.set_capability(EV_KEY, btn);
+	}
+	input_dev.set_capability(EV_REL, REL_X);
+	input_dev.set_capability(EV_REL, REL_Y);
+	if wheel {
+		input_dev.set_capability(EV_REL, REL_WHEEL);
+	}
+	mouse.packet_size = if wheel { 4 } else { 3 };
+	mouse.packet_len = 0;
+	mouse.input_dev = Some(input::register(input_dev).map_err(|_| ())?);
+	Ok(())
+}
@@ -355,6 +355,16 @@ impl ScancodeSet {
 		Err(())
 	}
 
+	/// Returns an iterator over the keys the set can produce.
+	pub fn keys(&self) -> impl Iterator<Item = KeyboardKey> {
+		let (base, special) = match self {
+			Self::Set1 => (&SET1_BASE_KEYS[..], &SET1_SPECIAL_KEYS[..]),
+			Self::Set2 => (&SET2_BASE_KEYS[..], &SET2_SPECIAL_KEYS[..]),
+			Self::Set3 => (&[][..], &[][..]),
+		};
+		base.iter().chain(special).map(|(_, key)| *key)
+	}
+
 	/// Reads a keystroke and returns the associated key and action.
 	pub fn read_keystroke(&self) -> Option<(KeyboardKey, KeyboardAction)> {
 		let mut keycode = read_data();
@@ -31,7 +31,6 @@ use core::{
 	fmt,
 	fmt::{Arguments, Debug, Write},
 	hash::{Hash, Hasher},
-	mem,
 	ops::{Add, Deref},
 	str,
 };
@@ -339,28 +338,72 @@ impl Debug for String {
 	}
 }
 
-/// Fallible writer for [`String`].
-pub struct StringWriter(pub AllocResult<String>);
+/// A buffer on which formatted text can be appended, handling memory allocation failures.
+///
+/// This is the fallible counterpart of [`fmt::Write`]. It is meant to be used through the
+/// [`crate::try_write`], [`crate::try_writeln`] and [`crate::try_format`] macros.
+pub trait TryFormat {
+	/// Appends the bytes `s` to the end of the buffer.
+	fn try_push_bytes(&mut self, s: &[u8]) -> AllocResult<()>;
 
-impl Write for StringWriter {
-	fn write_str(&mut self, s: &str) -> Result<(), fmt::Error> {
-		let buf = mem::replace(&mut self.0, Ok(String::default()));
-		self.0 = buf.and_then(|mut buf| {
-			buf.push_str(s)?;
-			Ok(buf)
-		});
+	/// Appends the formatted arguments `args` to the end of the buffer.
+	///
+	/// On memory allocation failure, the function returns an error and the buffer contains the
+	/// output written so far.
+	fn try_write_fmt(&mut self, args: Arguments<'_>) -> AllocResult<()> {
+		let mut w = TryFormatWriter {
+			buf: self,
+			res: Ok(()),
+		};
+		let fmt_res = fmt::write(&mut w, args);
+		w.res?;
+		fmt_res.expect("a formatting trait implementation returned an error");
 		Ok(())
 	}
 }
 
-/// Formats a string from the given arguments.
+impl TryFormat for String {
+	fn try_push_bytes(&mut self, s: &[u8]) -> AllocResult<()> {
+		self.push_str(s)
+	}
+}
+
+impl TryFormat for Vec<u8> {
+	fn try_push_bytes(&mut self, s: &[u8]) -> AllocResult<()> {
+		self.extend_from_slice(s)
+	}
+}
+
+/// Adapter from [`TryFormat`] to [`fmt::Write`], keeping track of allocation failures.
+struct TryFormatWriter<'b, B: ?Sized> {
+	buf: &'b mut B,
+	res: AllocResult<()>,
+}
+
+impl<B: TryFormat + ?Sized> Write for TryFormatWriter<'_, B> {
+	fn write_str(&mut self, s: &str) -> fmt::Result {
+		self.res = self.buf.try_push_bytes(s.as_bytes());
+		// Stop formatting at the first failure
+		self.res.map_err(|_| fmt::Error)
+	}
+}
+
+/// Formats the given arguments into a new buffer of type `B`.
+///
+/// On memory allocation failure, the function returns an error.
+pub fn try_format_impl<B: TryFormat + Default>(args: Arguments<'_>) -> AllocResult<B> {
+	let mut buf = B::default();
+	buf.try_write_fmt(args)?;
+	Ok(buf)
+}
+
+/// Formats a [`String`] from the given arguments.
 ///
 /// On memory allocation failure, the function returns an error.
 pub fn format_impl(args: Arguments<'_>) -> AllocResult<String> {
-	let buf = String::with_capacity(args.estimated_capacity());
-	let mut w = StringWriter(buf);
-	fmt::write(&mut w, args).expect("a formatting trait implementation returned an error");
-	w.0
+	let mut buf = String::with_capacity(args.estimated_capacity())?;
+	buf.try_write_fmt(args)?;
+	Ok(buf)
 }
 
 /// Formats a [`String`] from the given arguments.
@@ -373,6 +416,46 @@ macro_rules! format {
 	}};
 }
 
+/// Formats the given arguments into a new buffer implementing
+/// [`crate::collections::string::TryFormat`] (for example [`String`] or `Vec<u8>`). The type of
+/// the buffer is inferred from the context.
+///
+/// On memory allocation failure, the macro returns an error.
+#[macro_export]
+macro_rules! try_format {
+	($($arg:tt)*) => {{
+		$crate::collections::string::try_format_impl(format_args!($($arg)*))
+	}};
+}
+
+/// Same as [`write`], but on a buffer implementing
+/// [`crate::collections::string::TryFormat`].
+///
+/// On memory allocation failure, the macro returns an error.
+#[macro_export]
+macro_rules! try_write {
+	($dst:expr, $($arg:tt)*) => {{
+		use $crate::collections::string::TryFormat as _;
+		$dst.try_write_fmt(format_args!($($arg)*))
+	}};
+}
+
+/// Same as [`crate::try_write`], with a newline appended.
+///
+/// **Note**: the destination expression is evaluated twice.
+#[macro_export]
+macro_rules! try_writeln {
+	($dst:expr $(,)?) => {
+		$crate::try_write!($dst, "\n")
+	};
+	($dst:expr, $($arg:tt)*) => {
+		match $crate::try_write!($dst, $($arg)*) {
+			Ok(()) => $crate::try_write!($dst, "\n"),
+			Err(e) => Err(e),
+		}
+	};
+}
+
 #[cfg(test)]
 mod test {
 	use super::*;
@@ -398,4 +481,21 @@ mod test {
 		}
 		assert_eq!(s, "aaaaaaaaaa");
 	}
+
+	#[test]
+	fn string_try_write() {
+		let mut s = String::new();
+		crate::try_write!(s, "{} {}", 42, "abc").unwrap();
+		crate::try_writeln!(s, "-{:02x}", 10).unwrap();
+		crate::try_writeln!(&mut s).unwrap();
+		assert_eq!(s, "42 abc-0a\n\n");
+	}
+
+	#[test]
+	fn try_format() {
+		let s: String = crate::try_format!("{}:{}", 1, 2).unwrap();
+		assert_eq!(s, "1:2");
+		let v: Vec<u8> = crate::try_format!("{}", "abc").unwrap();
+		assert_eq!(v.as_slice(), b"abc");
+	}
 }
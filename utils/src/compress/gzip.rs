@@ -0,0 +1,198 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Decompression of gzip streams (RFC 1952), which wrap data compressed with the DEFLATE
+//! algorithm.
+
+use super::{Decompress, inflate::Inflate};
+use crate::{
+	collections::vec::Vec,
+	crypto::checksum::{crc32_lookuptable, crc32_update},
+	errno,
+	errno::EResult,
+};
+
+/// The magic number at the beginning of a gzip stream.
+pub const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Compression method: DEFLATE.
+const CM_DEFLATE: u8 = 8;
+
+/// Flag: a CRC16 of the header is present.
+const FHCRC: u8 = 1 << 1;
+/// Flag: extra fields are present.
+const FEXTRA: u8 = 1 << 2;
+/// Flag: the original filename is present.
+const FNAME: u8 = 1 << 3;
+/// Flag: a comment is present.
+const FCOMMENT: u8 = 1 << 4;
+/// Reserved flags, which must be cleared.
+const FRESERVED: u8 = 0xe0;
+
+/// The polynomial of the CRC32 at the end of a stream.
+const CRC32_POLYNOM: u32 = 0xedb88320;
+
+/// Returns the offset of the byte following the NUL-terminated string at the beginning of
+/// `data`.
+fn skip_str(data: &[u8]) -> EResult<usize> {
+	data.iter()
+		.position(|b| *b == 0)
+		.map(|i| i + 1)
+		.ok_or_else(|| errno!(EINVAL))
+}
+
+/// Returns the size of the header at the beginning of `data`.
+fn parse_header(data: &[u8]) -> EResult<usize> {
+	let Some(hdr) = data.get(..10) else {
+		return Err(errno!(EINVAL));
+	};
+	if hdr[..2] != MAGIC || hdr[2] != CM_DEFLATE {
+		return Err(errno!(EINVAL));
+	}
+	let flags = hdr[3];
+	if flags & FRESERVED != 0 {
+		return Err(errno!(EINVAL));
+	}
+	let mut off = hdr.len();
+	if flags & FEXTRA != 0 {
+		let xlen = data.get(off..(off + 2)).ok_or_else(|| errno!(EINVAL))?;
+		off += 2 + u16::from_le_bytes([xlen[0], xlen[1]]) as usize;
+	}
+	if flags & FNAME != 0 {
+		off += skip_str(data.get(off..).ok_or_else(|| errno!(EINVAL))?)?;
+	}
+	if flags & FCOMMENT != 0 {
+		off += skip_str(data.get(off..).ok_or_else(|| errno!(EINVAL))?)?;
+	}
+	if flags & FHCRC != 0 {
+		off += 2;
+	}
+	if off > data.len() {
+		return Err(errno!(EINVAL));
+	}
+	Ok(off)
+}
+
+/// A gzip stream decompressor.
+///
+/// Data following the first member of the stream is ignored.
+///
+/// The checksum is verified when reaching the end of the stream. If it does not match, reading
+/// fails with [`errno::EINVAL`].
+pub struct Reader<'a> {
+	/// The compressed stream.
+	data: &'a [u8],
+	/// The offset of the compressed data in `data`.
+	off: usize,
+	/// The decompressor.
+	inflate: Inflate<'a>,
+
+	/// The lookup table for the CRC32.
+	lookup_table: [u32; 256],
+	/// The CRC32 of the data decompressed so far.
+	crc: u32,
+	/// The size of the data decompressed so far, modulo `2^32`.
+	size: u32,
+}
+
+impl<'a> Reader<'a> {
+	/// Creates a decompressor for the stream `data`.
+	///
+	/// If the header of the stream is invalid, the function returns [`errno::EINVAL`].
+	pub fn new(data: &'a [u8]) -> EResult<Self> {
+		let off = parse_header(data)?;
+		let mut lookup_table = [0; 256];
+		crc32_lookuptable(&mut lookup_table, CRC32_POLYNOM);
+		Ok(Self {
+			data,
+			off,
+			inflate: Inflate::new(&data[off..])?,
+
+			lookup_table,
+			crc: 0,
+			size: 0,
+		})
+	}
+
+	/// Checks the trailer of the stream.
+	fn check_trailer(&self) -> EResult<()> {
+		let off = self.off + self.inflate.consumed();
+		let trailer = self
+			.data
+			.get(off..(off + 8))
+			.ok_or_else(|| errno!(EINVAL))?;
+		let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+		let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+		if self.crc != crc || self.size != size {
+			return Err(errno!(EINVAL));
+		}
+		Ok(())
+	}
+}
+
+impl Decompress for Reader<'_> {
+	fn read(&mut self, buf: &mut [u8]) -> EResult<usize> {
+		let n = self.inflate.read(buf)?;
+		self.crc = crc32_update(self.crc, &buf[..n], &self.lookup_table);
+		self.size = self.size.wrapping_add(n as u32);
+		if n == 0 && !buf.is_empty() {
+			self.check_trailer()?;
+		}
+		Ok(n)
+	}
+}
+
+/// Decompresses the gzip stream `data`.
+///
+/// Data following the first member of the stream is ignored.
+///
+/// If the stream is invalid or if its checksum does not match, the function returns
+/// [`errno::EINVAL`].
+pub fn decompress(data: &[u8]) -> EResult<Vec<u8>> {
+	let mut out = Vec::new();
+	Reader::new(data)?.read_to_end(&mut out)?;
+	Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// `hello hello hello world\n`, compressed with gzip.
+	const HELLO: &[u8] = &[
+		0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+		0x57, 0xc8, 0x40, 0x22, 0xcb, 0xf3, 0x8b, 0x72, 0x52, 0xb8, 0x00, 0x88, 0xd9, 0x5b, 0xe0,
+		0x18, 0x00, 0x00, 0x00,
+	];
+
+	#[test]
+	fn gzip_decompress() {
+		let out = decompress(HELLO).unwrap();
+		assert_eq!(out.as_slice(), b"hello hello hello world\n");
+	}
+
+	#[test]
+	fn gzip_invalid() {
+		let mut data = [0; HELLO.len()];
+		data.copy_from_slice(HELLO);
+		data[HELLO.len() - 8] ^= 1;
+		assert!(decompress(&data).is_err());
+		assert!(decompress(&HELLO[1..]).is_err());
+		assert!(decompress(&HELLO[..(HELLO.len() - 1)]).is_err());
+	}
+}
@@ -0,0 +1,467 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Decompression of data compressed with the DEFLATE algorithm (RFC 1951).
+
+use super::Decompress;
+use crate::{collections::vec::Vec, errno, errno::EResult};
+
+/// The size of the window in which back-references can point, in bytes.
+const WINDOW_SIZE: usize = 32768;
+/// The maximum length of a Huffman code, in bits.
+const MAX_BITS: usize = 15;
+/// The maximum number of literal/length codes.
+const MAX_LIT_CODES: usize = 288;
+/// The maximum number of distance codes.
+const MAX_DIST_CODES: usize = 30;
+
+/// Base lengths for length codes `257..=285`.
+const LEN_BASE: [u16; 29] = [
+	3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+	163, 195, 227, 258,
+];
+/// Number of extra bits for length codes `257..=285`.
+const LEN_EXTRA: [u8; 29] = [
+	0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distances for distance codes `0..=29`.
+const DIST_BASE: [u16; 30] = [
+	1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+	2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+/// Number of extra bits for distance codes `0..=29`.
+const DIST_EXTRA: [u8; 30] = [
+	0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+	13,
+];
+/// The order in which code lengths of the code lengths alphabet are stored.
+const CLEN_ORDER: [usize; 19] = [
+	16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Reads a stream of bits, least significant bit first.
+struct BitReader<'a> {
+	/// The data to read.
+	data: &'a [u8],
+	/// The offset of the next byte to read in `data`.
+	off: usize,
+	/// Bits that have been read from `data`, but not consumed yet.
+	buf: u32,
+	/// The number of bits in `buf`.
+	cnt: u32,
+}
+
+impl BitReader<'_> {
+	/// Consumes `n` bits, with `n <= 16`.
+	///
+	/// If the end of the data is reached, the function returns [`errno::EINVAL`].
+	fn bits(&mut self, n: u32) -> EResult<u32> {
+		while self.cnt < n {
+			let b = *self.data.get(self.off).ok_or_else(|| errno!(EINVAL))?;
+			self.off += 1;
+			self.buf |= (b as u32) << self.cnt;
+			self.cnt += 8;
+		}
+		let val = self.buf & ((1 << n) - 1);
+		self.buf >>= n;
+		self.cnt -= n;
+		Ok(val)
+	}
+
+	/// Discards the remaining bits of the current byte.
+	fn align(&mut self) {
+		self.buf = 0;
+		self.cnt = 0;
+	}
+
+	/// Consumes `n` bytes, which must be aligned.
+	fn bytes(&mut self, n: usize) -> EResult<&[u8]> {
+		let end = self.off.checked_add(n).ok_or_else(|| errno!(EINVAL))?;
+		let slice = self.data.get(self.off..end).ok_or_else(|| errno!(EINVAL))?;
+		self.off = end;
+		Ok(slice)
+	}
+}
+
+/// A canonical Huffman code.
+struct Huffman {
+	/// The number of symbols for each code length.
+	counts: [u16; MAX_BITS + 1],
+	/// Symbols, sorted by code.
+	symbols: [u16; MAX_LIT_CODES],
+}
+
+impl Huffman {
+	/// Builds the code from the code length of each symbol.
+	///
+	/// If the code is over-subscribed, the function returns [`errno::EINVAL`]. Incomplete codes
+	/// are accepted, decoding an unused code fails.
+	fn new(lengths: &[u8]) -> EResult<Self> {
+		let mut counts = [0u16; MAX_BITS + 1];
+		for l in lengths {
+			counts[*l as usize] += 1;
+		}
+		let mut left: i32 = 1;
+		for count in &counts[1..] {
+			left = (left << 1) - *count as i32;
+			if left < 0 {
+				return Err(errno!(EINVAL));
+			}
+		}
+		// Offset of the first symbol of each length in `symbols`
+		let mut offs = [0u16; MAX_BITS + 1];
+		for len in 1..MAX_BITS {
+			offs[len + 1] = offs[len] + counts[len];
+		}
+		let mut symbols = [0u16; MAX_LIT_CODES];
+		for (sym, l) in lengths.iter().enumerate() {
+			if *l != 0 {
+				symbols[offs[*l as usize] as usize] = sym as u16;
+				offs[*l as usize] += 1;
+			}
+		}
+		Ok(Self {
+			counts,
+			symbols,
+		})
+	}
+
+	/// Decodes the next symbol from `reader`.
+	fn decode(&self, reader: &mut BitReader) -> EResult<u16> {
+		// The first code of the current length
+		let mut first: u32 = 0;
+		// The index of the first symbol of the current length
+		let mut index: u32 = 0;
+		let mut code: u32 = 0;
+		for count in &self.counts[1..] {
+			let count = *count as u32;
+			code |= reader.bits(1)?;
+			if code < first + count {
+				return Ok(self.symbols[(index + code - first) as usize]);
+			}
+			index += count;
+			first = (first + count) << 1;
+			code <<= 1;
+		}
+		Err(errno!(EINVAL))
+	}
+}
+
+/// Reads the Huffman codes of a block compressed with dynamic codes.
+fn dynamic_codes(reader: &mut BitReader) -> EResult<(Huffman, Huffman)> {
+	let hlit = reader.bits(5)? as usize + 257;
+	let hdist = reader.bits(5)? as usize + 1;
+	let hclen = reader.bits(4)? as usize + 4;
+	if hlit > MAX_LIT_CODES || hdist > MAX_DIST_CODES {
+		return Err(errno!(EINVAL));
+	}
+	let mut lengths = [0u8; MAX_LIT_CODES + MAX_DIST_CODES];
+	for i in &CLEN_ORDER[..hclen] {
+		lengths[*i] = reader.bits(3)? as u8;
+	}
+	let clen = Huffman::new(&lengths[..CLEN_ORDER.len()])?;
+	let mut i = 0;
+	while i < hlit + hdist {
+		let sym = clen.decode(reader)?;
+		let (val, repeat) = match sym {
+			0..16 => (sym as u8, 1),
+			16 => {
+				let prev = *i
+					.checked_sub(1)
+					.and_then(|i| lengths.get(i))
+					.ok_or_else(|| errno!(EINVAL))?;
+				(prev, 3 + reader.bits(2)?)
+			}
+			17 => (0, 3 + reader.bits(3)?),
+			_ => (0, 11 + reader.bits(7)?),
+		};
+		let end = i + repeat as usize;
+		if end > hlit + hdist {
+			return Err(errno!(EINVAL));
+		}
+		lengths[i..end].fill(val);
+		i = end;
+	}
+	// The end-of-block code is required
+	if lengths[256] == 0 {
+		return Err(errno!(EINVAL));
+	}
+	let lit = Huffman::new(&lengths[..hlit])?;
+	let dist = Huffman::new(&lengths[hlit..(hlit + hdist)])?;
+	Ok((lit, dist))
+}
+
+/// Returns the fixed Huffman codes.
+fn fixed_codes() -> EResult<(Huffman, Huffman)> {
+	let mut lengths = [0u8; MAX_LIT_CODES];
+	lengths[..144].fill(8);
+	lengths[144..256].fill(9);
+	lengths[256..280].fill(7);
+	lengths[280..].fill(8);
+	let lit = Huffman::new(&lengths)?;
+	let dist = Huffman::new(&[5; MAX_DIST_CODES])?;
+	Ok((lit, dist))
+}
+
+/// The state of the decompressor.
+enum State {
+	/// Waiting for the header of the next block.
+	Header,
+	/// In a stored block, with the given number of bytes left.
+	Stored(usize),
+	/// In a block compressed with Huffman codes.
+	Codes,
+	/// The end of the stream has been reached.
+	Done,
+}
+
+/// A DEFLATE stream decompressor.
+pub struct Inflate<'a> {
+	/// The compressed stream.
+	reader: BitReader<'a>,
+	/// The current state.
+	state: State,
+	/// Tells whether the current block is the last of the stream.
+	last: bool,
+	/// The literal/length code of the current block.
+	lit: Huffman,
+	/// The distance code of the current block.
+	dist: Huffman,
+
+	/// The last [`WINDOW_SIZE`] bytes of output, used as a ring buffer.
+	window: Vec<u8>,
+	/// The total number of bytes of output.
+	total: usize,
+	/// The number of bytes left to copy from a back-reference.
+	copy_len: usize,
+	/// The distance of the back-reference being copied.
+	copy_dist: usize,
+}
+
+impl<'a> Inflate<'a> {
+	/// Creates a decompressor for the stream at the beginning of `data`.
+	pub fn new(data: &'a [u8]) -> EResult<Self> {
+		let mut window = Vec::new();
+		window.resize(WINDOW_SIZE, 0)?;
+		Ok(Self {
+			reader: BitReader {
+				data,
+				off: 0,
+				buf: 0,
+				cnt: 0,
+			},
+			state: State::Header,
+			last: false,
+			lit: Huffman::new(&[])?,
+			dist: Huffman::new(&[])?,
+
+			window,
+			total: 0,
+			copy_len: 0,
+			copy_dist: 0,
+		})
+	}
+
+	/// Returns the number of bytes of the input consumed so far.
+	///
+	/// At the end of the stream, this is the offset of the data following it.
+	pub fn consumed(&self) -> usize {
+		self.reader.off
+	}
+
+	/// Appends the byte `b` to the window.
+	fn push(&mut self, b: u8) {
+		self.window[self.total % WINDOW_SIZE] = b;
+		self.total += 1;
+	}
+
+	/// Reads the header of the next block.
+	fn block_header(&mut self) -> EResult<()> {
+		self.last = self.reader.bits(1)? != 0;
+		match self.reader.bits(2)? {
+			// Stored
+			0 => {
+				self.reader.align();
+				let hdr = self.reader.bytes(4)?;
+				let len = u16::from_le_bytes([hdr[0], hdr[1]]);
+				let nlen = u16::from_le_bytes([hdr[2], hdr[3]]);
+				if len != !nlen {
+					return Err(errno!(EINVAL));
+				}
+				self.state = State::Stored(len as _);
+			}
+			// Fixed Huffman codes
+			1 => {
+				(self.lit, self.dist) = fixed_codes()?;
+				self.state = State::Codes;
+			}
+			// Dynamic Huffman codes
+			2 => {
+				(self.lit, self.dist) = dynamic_codes(&mut self.reader)?;
+				self.state = State::Codes;
+			}
+			_ => return Err(errno!(EINVAL)),
+		}
+		Ok(())
+	}
+
+	/// Decodes the next symbol of a block compressed with Huffman codes.
+	///
+	/// If the symbol is a literal, the function returns it.
+	fn symbol(&mut self) -> EResult<Option<u8>> {
+		let sym = self.lit.decode(&mut self.reader)? as usize;
+		match sym {
+			0..256 => return Ok(Some(sym as u8)),
+			256 => self.state = State::Header,
+			_ => {
+				let sym = sym - 257;
+				if sym >= LEN_BASE.len() {
+					return Err(errno!(EINVAL));
+				}
+				let len = LEN_BASE[sym] as usize + self.reader.bits(LEN_EXTRA[sym] as _)? as usize;
+				let sym = self.dist.decode(&mut self.reader)? as usize;
+				if sym >= DIST_BASE.len() {
+					return Err(errno!(EINVAL));
+				}
+				let dist =
+					DIST_BASE[sym] as usize + self.reader.bits(DIST_EXTRA[sym] as _)? as usize;
+				if dist > self.total {
+					return Err(errno!(EINVAL));
+				}
+				self.copy_len = len;
+				self.copy_dist = dist;
+			}
+		}
+		Ok(None)
+	}
+}
+
+impl Decompress for Inflate<'_> {
+	fn read(&mut self, buf: &mut [u8]) -> EResult<usize> {
+		let mut n = 0;
+		while n < buf.len() {
+			// Copy from a back-reference. The source and destination may overlap, so copy byte by
+			// byte
+			if self.copy_len > 0 {
+				let b = self.window[(self.total - self.copy_dist) % WINDOW_SIZE];
+				self.push(b);
+				buf[n] = b;
+				n += 1;
+				self.copy_len -= 1;
+				continue;
+			}
+			match self.state {
+				State::Header if self.last => self.state = State::Done,
+				State::Header => self.block_header()?,
+				State::Stored(0) => self.state = State::Header,
+				State::Stored(remain) => {
+					let len = remain.min(buf.len() - n);
+					let src = self.reader.bytes(len)?;
+					buf[n..(n + len)].copy_from_slice(src);
+					for b in &buf[n..(n + len)] {
+						self.push(*b);
+					}
+					n += len;
+					self.state = State::Stored(remain - len);
+				}
+				State::Codes => {
+					if let Some(b) = self.symbol()? {
+						self.push(b);
+						buf[n] = b;
+						n += 1;
+					}
+				}
+				State::Done => break,
+			}
+		}
+		Ok(n)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// Decompresses `data` with chunks of size `chunk`.
+	fn inflate(data: &[u8], chunk: usize) -> EResult<(Vec<u8>, usize)> {
+		let mut inflate = Inflate::new(data)?;
+		let mut out = Vec::new();
+		let mut buf = [0; 16];
+		loop {
+			let n = inflate.read(&mut buf[..chunk])?;
+			if n == 0 {
+				break;
+			}
+			out.extend_from_slice(&buf[..n])?;
+		}
+		Ok((out, inflate.consumed()))
+	}
+
+	#[test]
+	fn inflate_stored() {
+		let data = [
+			0x01, 0x06, 0x00, 0xf9, 0xff, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64,
+		];
+		for chunk in [1, 4, 16] {
+			let (out, consumed) = inflate(&data, chunk).unwrap();
+			assert_eq!(out.as_slice(), b"stored");
+			assert_eq!(consumed, data.len());
+		}
+	}
+
+	#[test]
+	fn inflate_fixed() {
+		// `hello hello hello world\n`
+		let data = [
+			0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x22, 0xcb, 0xf3, 0x8b, 0x72, 0x52,
+			0xb8, 0x00,
+		];
+		for chunk in [1, 4, 16] {
+			let (out, consumed) = inflate(&data, chunk).unwrap();
+			assert_eq!(out.as_slice(), b"hello hello hello world\n");
+			assert_eq!(consumed, data.len());
+		}
+	}
+
+	#[test]
+	fn inflate_dynamic() {
+		let data = [
+			0x25, 0x8a, 0xc1, 0x0d, 0x00, 0x00, 0x10, 0xc1, 0x66, 0x2d, 0xf6, 0x9f, 0xe1, 0xc8,
+			0x79, 0x34, 0x52, 0xb0, 0xa4, 0xb0, 0x8c, 0x11, 0x02, 0xbf, 0xe8, 0xb4, 0x1e, 0xd7,
+			0xf5, 0x55, 0x1e,
+		];
+		for chunk in [1, 4, 16] {
+			let (out, consumed) = inflate(&data, chunk).unwrap();
+			assert_eq!(
+				out.as_slice(),
+				b"acbbbdaaaaadaaadbabaacdaaaaacbbaacddcababbbaba"
+			);
+			assert_eq!(consumed, data.len());
+		}
+	}
+
+	#[test]
+	fn inflate_invalid() {
+		// Truncated
+		assert!(inflate(&[0xcb, 0x48, 0xcd, 0xc9], 16).is_err());
+		// Reserved block type
+		assert!(inflate(&[0x07], 16).is_err());
+		// Back-reference before the beginning of the output
+		assert!(inflate(&[0x03, 0x02], 16).is_err());
+	}
+}
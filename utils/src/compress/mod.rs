@@ -0,0 +1,57 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Decompression algorithms.
+//!
+//! Decompressors read from a compressed stream held in memory, and produce data through the
+//! [`Decompress`] trait, allowing to process it by chunks without decompressing the whole stream
+//! at once.
+
+pub mod gzip;
+pub mod inflate;
+pub mod zlib;
+
+use crate::{collections::vec::Vec, errno::EResult};
+
+/// The size of the chunks by which [`Decompress::read_to_end`] grows its output.
+const CHUNK_SIZE: usize = 4096;
+
+/// A decompressor.
+pub trait Decompress {
+	/// Decompresses data into `buf`.
+	///
+	/// The function returns the number of bytes written to `buf`. If zero while `buf` is not
+	/// empty, the end of the stream has been reached.
+	///
+	/// If the stream is invalid or truncated, the function returns [`crate::errno::EINVAL`].
+	fn read(&mut self, buf: &mut [u8]) -> EResult<usize>;
+
+	/// Decompresses the rest of the stream, appending it to `out`.
+	fn read_to_end(&mut self, out: &mut Vec<u8>) -> EResult<()> {
+		loop {
+			let len = out.len();
+			out.resize(len + CHUNK_SIZE, 0)?;
+			let n = self.read(&mut out[len..])?;
+			out.truncate(len + n);
+			if n == 0 {
+				break;
+			}
+		}
+		Ok(())
+	}
+}
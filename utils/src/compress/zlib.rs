@@ -0,0 +1,126 @@
+/*
+ * Copyright 2026 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Decompression of zlib streams (RFC 1950), which wrap data compressed with the DEFLATE
+//! algorithm.
+
+use super::{Decompress, inflate::Inflate};
+use crate::{collections::vec::Vec, crypto::checksum::adler32_update, errno, errno::EResult};
+
+/// Compression method: DEFLATE.
+const CM_DEFLATE: u8 = 8;
+/// The maximum base-2 logarithm of the window size, minus `8`.
+const MAX_CINFO: u8 = 7;
+/// Flag: a preset dictionary is used.
+const FDICT: u8 = 1 << 5;
+
+/// A zlib stream decompressor.
+///
+/// Streams using a preset dictionary are not supported.
+///
+/// The checksum is verified when reaching the end of the stream. If it does not match, reading
+/// fails with [`errno::EINVAL`].
+pub struct Reader<'a> {
+	/// The compressed stream.
+	data: &'a [u8],
+	/// The decompressor.
+	inflate: Inflate<'a>,
+	/// The Adler-32 checksum of the data decompressed so far.
+	adler: u32,
+}
+
+impl<'a> Reader<'a> {
+	/// Creates a decompressor for the stream `data`.
+	///
+	/// If the header of the stream is invalid, the function returns [`errno::EINVAL`].
+	pub fn new(data: &'a [u8]) -> EResult<Self> {
+		let Some(&[cmf, flg]) = data.get(..2) else {
+			return Err(errno!(EINVAL));
+		};
+		let valid = cmf & 0xf == CM_DEFLATE
+			&& cmf >> 4 <= MAX_CINFO
+			&& flg & FDICT == 0
+			&& u16::from_be_bytes([cmf, flg]).is_multiple_of(31);
+		if !valid {
+			return Err(errno!(EINVAL));
+		}
+		Ok(Self {
+			data,
+			inflate: Inflate::new(&data[2..])?,
+			adler: 1,
+		})
+	}
+}
+
+impl Decompress for Reader<'_> {
+	fn read(&mut self, buf: &mut [u8]) -> EResult<usize> {
+		let n = self.inflate.read(buf)?;
+		self.adler = adler32_update(self.adler, &buf[..n]);
+		if n == 0 && !buf.is_empty() {
+			let off = 2 + self.inflate.consumed();
+			let trailer = self
+				.data
+				.get(off..(off + 4))
+				.ok_or_else(|| errno!(EINVAL))?;
+			let adler = u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+			if self.adler != adler {
+				return Err(errno!(EINVAL));
+			}
+		}
+		Ok(n)
+	}
+}
+
+/// Decompresses the zlib stream `data`.
+///
+/// If the stream is invalid or if its checksum does not match, the function returns
+/// [`errno::EINVAL`].
+pub fn decompress(data: &[u8]) -> EResult<Vec<u8>> {
+	let mut out = Vec::new();
+	Reader::new(data)?.read_to_end(&mut out)?;
+	Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// `hello hello hello world\n`, compressed with zlib.
+	const HELLO: &[u8] = &[
+		0x78, 0xda, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x22, 0xcb, 0xf3, 0x8b, 0x72,
+		0x52, 0xb8, 0x00, 0x71, 0x4c, 0x08, 0xcf,
+	];
+
+	#[test]
+	fn zlib_decompress() {
+		let out = decompress(HELLO).unwrap();
+		assert_eq!(out.as_slice(), b"hello hello hello world\n");
+	}
+
+	#[test]
+	fn zlib_invalid() {
+		let mut data = [0; HELLO.len()];
+		data.copy_from_slice(HELLO);
+		data[HELLO.len() - 1] ^= 1;
+		assert!(decompress(&data).is_err());
+		// Bad header check
+		data.copy_from_slice(HELLO);
+		data[1] ^= 1;
+		assert!(decompress(&data).is_err());
+	}
+}
@@ -52,11 +52,14 @@ macro_rules! quarter_round {
 }
 
 /// Computes a ChaCha20 block.
+///
+/// `inout` is the input state, which is replaced by the output.
 pub fn block(inout: &mut [u8; 64]) {
-	let mut buf: [u32; 16] = [0; 16];
+	let mut input: [u32; 16] = [0; 16];
 	unsafe {
-		ptr::copy_nonoverlapping(inout.as_ptr(), buf.as_mut_ptr() as *mut u8, 64);
+		ptr::copy_nonoverlapping(inout.as_ptr(), input.as_mut_ptr() as *mut u8, 64);
 	}
+	let mut buf = input;
 	for _ in (0..20).step_by(2) {
 		// Odd round
 		quarter_round!(buf[0], buf[4], buf[8], buf[12]);
@@ -69,9 +72,42 @@ pub fn block(inout: &mut [u8; 64]) {
 		quarter_round!(buf[2], buf[7], buf[8], buf[13]);
 		quarter_round!(buf[3], buf[4], buf[9], buf[14]);
 	}
+	// Add the input so that the output does not allow to retrieve it
+	for (b, i) in buf.iter_mut().zip(input) {
+		*b = b.wrapping_add(i);
+	}
 	unsafe {
 		ptr::copy_nonoverlapping(buf.as_ptr() as *mut u8, inout.as_mut_ptr(), 64);
 	}
 }
 
-// TODO unit tests
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// Converts the given words into a ChaCha20 state.
+	fn to_bytes(words: [u32; 16]) -> [u8; 64] {
+		let mut bytes = [0; 64];
+		for (b, w) in bytes.chunks_exact_mut(4).zip(words) {
+			b.copy_from_slice(&w.to_le_bytes());
+		}
+		bytes
+	}
+
+	/// Test vector from RFC 7539, section 2.3.2.
+	#[test]
+	fn chacha20_block() {
+		let mut state = to_bytes([
+			0x61707865, 0x3320646e, 0x79622d32, 0x6b206574, 0x03020100, 0x07060504, 0x0b0a0908,
+			0x0f0e0d0c, 0x13121110, 0x17161514, 0x1b1a1918, 0x1f1e1d1c, 0x00000001, 0x09000000,
+			0x4a000000, 0x00000000,
+		]);
+		block(&mut state);
+		let expected = to_bytes([
+			0xe4e7f110, 0x15593bd1, 0x1fdd0f50, 0xc47120a3, 0xc7f4d1c7, 0x0368c033, 0x9aaa2204,
+			0x4e6cd4c3, 0x466482d2, 0x09aa9f07, 0x05d7c214, 0xa2028bd9, 0xd19c12b5, 0xb94e16de,
+			0xe883d0cb, 0x4e3c50a2,
+		]);
+		assert_eq!(state, expected);
+	}
+}
@@ -64,11 +64,13 @@ pub fn crc32_lookuptable(table: &mut [u32; 256], polynomial: u32) {
 	}
 }
 
-/// Computes the CRC32 checksum on the given data `data` with the given `table`, built with
-/// [`crc32_lookuptable`].
-pub fn crc32(data: &[u8], table: &[u32; 256]) -> u32 {
+/// Updates the CRC32 checksum `crc` of previous data with the given data `data`, using the given
+/// `table`, built with [`crc32_lookuptable`].
+///
+/// The checksum of empty data is `0`.
+pub fn crc32_update(crc: u32, data: &[u8], table: &[u32; 256]) -> u32 {
 	// Sarwate algorithm
-	let mut crc = !0u32;
+	let mut crc = !crc;
 	for b in data {
 		let i = ((crc as usize) ^ (*b as usize)) & 0xff;
 		crc = table[i] ^ (crc >> 8);
@@ -76,6 +78,33 @@ pub fn crc32(data: &[u8], table: &[u32; 256]) -> u32 {
 	!crc
 }
 
+/// Computes the CRC32 checksum on the given data `data` with the given `table`, built with
+/// [`crc32_lookuptable`].
+pub fn crc32(data: &[u8], table: &[u32; 256]) -> u32 {
+	crc32_update(0, data, table)
+}
+
+/// Updates the Adler-32 checksum `adler` of previous data with the given data `data`, according
+/// to **RFC1950**.
+///
+/// The checksum of empty data is `1`.
+pub fn adler32_update(adler: u32, data: &[u8]) -> u32 {
+	const MOD: u32 = 65521;
+	// The largest number of bytes that can be summed before `b` overflows
+	const NMAX: usize = 5552;
+	let mut a = adler & 0xffff;
+	let mut b = adler >> 16;
+	for chunk in data.chunks(NMAX) {
+		for byte in chunk {
+			a += *byte as u32;
+			b += a;
+		}
+		a %= MOD;
+		b %= MOD;
+	}
+	(b << 16) | a
+}
+
 #[cfg(test)]
 mod test {
 	use super::*;
@@ -89,5 +118,33 @@ mod test {
 	}
 
 	// TODO More tests on RFC1071
-	// TODO Test CRC32
+
+	#[test]
+	fn crc32_0() {
+		let mut table = [0; 256];
+		crc32_lookuptable(&mut table, 0xedb88320);
+		assert_eq!(crc32(b"", &table), 0);
+		assert_eq!(crc32(b"123456789", &table), 0xcbf43926);
+		let crc = crc32_update(crc32(b"1234", &table), b"56789", &table);
+		assert_eq!(crc, 0xcbf43926);
+	}
+
+	#[test]
+	fn adler32_0() {
+		assert_eq!(adler32_update(1, b""), 1);
+		assert_eq!(adler32_update(1, b"Wikipedia"), 0x11e60398);
+		assert_eq!(
+			adler32_update(adler32_update(1, b"Wiki"), b"pedia"),
+			0x11e60398
+		);
+		let data = [0xff; 100000];
+		let expected = data.iter().fold((1u64, 0u64), |(a, b), c| {
+			let a = (a + *c as u64) % 65521;
+			(a, (b + a) % 65521)
+		});
+		assert_eq!(
+			adler32_update(1, &data),
+			((expected.1 << 16) | expected.0) as u32
+		);
+	}
 }
@@ -107,6 +107,26 @@ impl Errno {
 		}
 	}
 
+	/// Creates an instance from the integer representation of an errno, such as one received
+	/// from userspace.
+	#[track_caller]
+	pub fn from_int(errno: i32) -> Self {
+		#[cfg(debug_assertions)]
+		{
+			let location = core::panic::Location::caller();
+			Self::new(
+				errno,
+				ErrnoLocation {
+					file: location.file(),
+					line: location.line(),
+					column: location.column(),
+				},
+			)
+		}
+		#[cfg(not(debug_assertions))]
+		Self::new(errno)
+	}
+
 	/// Returns the integer representation of the errno.
 	pub fn as_int(&self) -> i32 {
 		self.errno
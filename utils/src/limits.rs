@@ -40,13 +40,15 @@ pub const DELAYTIMER_MAX: usize = 32;
 pub const HOST_NAME_MAX: usize = 255;
 /// Maximum number of iovec structures that one process has available for use
 /// with readv() or writev().
-pub const IOV_MAX: usize = 16;
+pub const IOV_MAX: usize = 1024;
 /// Maximum length of a login name.
 pub const LOGIN_NAME_MAX: usize = 255;
 /// The maximum number of open message queue descriptors a process may hold.
 pub const MQ_OPEN_MAX: usize = 8;
 /// The maximum number of message priorities supported by the implementation.
 pub const MQ_PRIO_MAX: usize = 32;
+/// Maximum number of simultaneous supplementary group IDs per process.
+pub const NGROUPS_MAX: usize = 65536;
 /// A value one greater than the maximum value that the system may assign to a
 /// newly-created file descriptor.
 pub const OPEN_MAX: u32 = 1024;